@@ -0,0 +1,42 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This example describes how to receive payloads as `futures::Stream`.
+
+use cameleon::u3v::enumerate_cameras;
+use futures::StreamExt;
+
+fn main() {
+    // Enumerates cameras connected to the host.
+    let mut cameras = enumerate_cameras().unwrap();
+
+    if cameras.is_empty() {
+        println!("no camera found!");
+        return;
+    }
+
+    let mut camera = cameras.pop().unwrap();
+
+    // Open the camera.
+    camera.open().unwrap();
+    // Load `GenApi` context.
+    camera.load_context().unwrap();
+
+    // Start streaming. Channel capacity is set to 3.
+    let payload_rx = camera.start_streaming(3).unwrap();
+
+    // Receive 100 payloads without dedicating a thread to polling.
+    async_std::task::block_on(payload_rx.take(100).for_each(|payload| async move {
+        match payload {
+            Ok(payload) => println!(
+                "payload received! block_id: {:?}, timestamp: {:?}",
+                payload.id(),
+                payload.timestamp()
+            ),
+            Err(err) => println!("failed to receive payload: {}", err),
+        }
+    }));
+
+    camera.close().ok();
+}
@@ -7,8 +7,8 @@
 //! In this example, we'll define a context in which the cache can be dynamically switched on and off.
 
 use cameleon::genapi::{
-    CacheStore, DefaultCacheStore, DefaultGenApiCtxt, DefaultNodeStore, DefaultValueStore,
-    GenApiCtxt, NodeId, ValueCtxt,
+    CacheStore, CachedData, DefaultCacheStore, DefaultGenApiCtxt, DefaultNodeStore,
+    DefaultValueStore, GenApiCtxt, NodeId, ValueCtxt,
 };
 use cameleon::{u3v, Camera};
 
@@ -23,7 +23,7 @@ impl CacheStore for MyCacheStore {
             self.store.cache(nid, address, length, data)
         }
     }
-    fn get_cache(&self, nid: NodeId, address: i64, length: i64) -> Option<&[u8]> {
+    fn get_cache(&self, nid: NodeId, address: i64, length: i64) -> Option<CachedData<'_>> {
         if self.use_cache {
             self.store.get_cache(nid, address, length)
         } else {
@@ -0,0 +1,109 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This example describes how to build a debugging tool on top of [`cameleon::u3v::diag`].
+//!
+//! ```text
+//! diag list
+//! diag abrm <SERIAL>
+//! diag xml <SERIAL> <PATH>
+//! diag read <SERIAL> <ADDRESS> <LEN>
+//! diag write <SERIAL> <ADDRESS> <HEX_BYTES> --allow-writes
+//! diag probe <SERIAL> <SECONDS> --allow-writes
+//! ```
+//!
+//! `--retry-count <N>` and `--timeout-ms <MS>` are applied to [`cameleon::u3v::OpenOptions`].
+
+use std::{env, process, time::Duration};
+
+use cameleon::u3v::{
+    diag::{self, DiagOptions},
+    OpenOptions,
+};
+
+const USAGE: &str = "usage: diag [--allow-writes] [--retry-count <N>] [--timeout-ms <MS>] \
+                     <list | abrm SERIAL | xml SERIAL PATH | read SERIAL ADDRESS LEN | \
+                     write SERIAL ADDRESS HEX_BYTES | probe SERIAL SECONDS>";
+
+fn main() {
+    let mut positional = vec![];
+    let mut open = OpenOptions::new();
+    let mut allow_writes = false;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--allow-writes" => allow_writes = true,
+            "--retry-count" => open = open.retry_count(parse_int(args.next()) as u16),
+            "--timeout-ms" => {
+                open = open.timeout_duration(Duration::from_millis(parse_int(args.next())));
+            }
+            "-h" | "--help" => exit(USAGE),
+            _ => positional.push(arg),
+        }
+    }
+    let options = DiagOptions::new().open(open).allow_writes(allow_writes);
+
+    let positional: Vec<&str> = positional.iter().map(String::as_str).collect();
+    let result = match positional.as_slice() {
+        ["list"] => diag::list_devices().map(|r| format!("{:#?}", r)),
+        ["abrm", serial] => diag::dump_abrm(serial, &options).map(|r| format!("{:#?}", r)),
+        ["xml", serial, path] => {
+            diag::download_xml(serial, path, &options).map(|r| format!("{:#?}", r))
+        }
+        ["read", serial, address, len] => diag::read_register(
+            serial,
+            parse_int(Some(address.to_string())),
+            parse_int(Some(len.to_string())) as usize,
+            &options,
+        )
+        .map(|r| format!("{:#x?}", r)),
+        ["write", serial, address, data] => diag::write_register(
+            serial,
+            parse_int(Some(address.to_string())),
+            &parse_hex(data),
+            &options,
+        )
+        .map(|r| format!("{:#x?}", r)),
+        ["probe", serial, seconds] => {
+            diag::stream_probe(serial, parse_int(Some(seconds.to_string())), &options)
+                .map(|r| format!("{:#?}", r))
+        }
+        _ => exit(USAGE),
+    };
+
+    match result {
+        Ok(report) => println!("{}", report),
+        Err(e) => exit(&e.to_string()),
+    }
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal integer.
+fn parse_int(arg: Option<String>) -> u64 {
+    let arg = arg.unwrap_or_else(|| exit(USAGE));
+    let parsed = match arg.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => arg.parse(),
+    };
+    parsed.unwrap_or_else(|_| exit(&format!("invalid integer: {}", arg)))
+}
+
+fn parse_hex(arg: &str) -> Vec<u8> {
+    let arg = arg.strip_prefix("0x").unwrap_or(arg);
+    arg.as_bytes()
+        .chunks(2)
+        .map(|byte| {
+            std::str::from_utf8(byte)
+                .ok()
+                .filter(|byte| byte.len() == 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .unwrap_or_else(|| exit(&format!("invalid hex bytes: {}", arg)))
+        })
+        .collect()
+}
+
+fn exit(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1)
+}
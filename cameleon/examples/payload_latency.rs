@@ -0,0 +1,137 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This example compares the per-frame latency of accessing a received payload in place with
+//! copying it into a buffer of the application.
+//!
+//! The streaming loop of a U3V camera receives payload data directly into the buffer held by
+//! `Payload`, so accessing the image doesn't copy it. The only exception is a transfer which ends
+//! with a short or zero-length packet, the bytes received after it are then moved towards the head
+//! of the same buffer.
+//!
+//! When built with the `emulator` feature and no camera is connected, the example measures an
+//! emulated camera instead. The emulated stream copies each frame out of the emulator, so the
+//! numbers only show the cost of accessing a payload on the host side.
+
+use std::time::{Duration, Instant};
+
+use cameleon::{
+    genapi::DefaultGenApiCtxt, u3v::enumerate_cameras, Camera, DeviceControl, PayloadStream,
+};
+
+/// The number of payloads measured for each method.
+const FRAMES: usize = 100;
+
+#[derive(Default)]
+struct Latency {
+    total: Duration,
+    max: Duration,
+    frames: u32,
+}
+
+impl Latency {
+    fn observe(&mut self, elapsed: Duration) {
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        self.frames += 1;
+    }
+
+    fn report(&self, method: &str) {
+        if self.frames == 0 {
+            println!("{}: no payload received", method);
+            return;
+        }
+        println!(
+            "{}: mean {:?}, max {:?} over {} frames",
+            method,
+            self.total / self.frames,
+            self.max,
+            self.frames
+        );
+    }
+}
+
+/// Returns a checksum of the payload so that reading the bytes isn't optimized away.
+fn checksum(data: &[u8]) -> u64 {
+    data.iter().map(|b| u64::from(*b)).sum()
+}
+
+fn main() {
+    // Enumerates cameras connected to the host.
+    let mut cameras = enumerate_cameras().unwrap();
+
+    if let Some(camera) = cameras.pop() {
+        measure(camera);
+        return;
+    }
+
+    #[cfg(feature = "emulator")]
+    {
+        use cameleon::emulator::{self, EmulatorBuilder};
+
+        println!("no camera found, measuring an emulated camera");
+        EmulatorBuilder::new()
+            .serial_number("LATENCY1")
+            .unwrap()
+            .build();
+        let camera = emulator::enumerate_cameras()
+            .unwrap()
+            .into_iter()
+            .find(|camera| camera.info().serial_number == "LATENCY1")
+            .unwrap();
+        measure(camera);
+    }
+
+    #[cfg(not(feature = "emulator"))]
+    println!("no camera found!");
+}
+
+fn measure<Ctrl, Strm>(mut camera: Camera<Ctrl, Strm, DefaultGenApiCtxt>)
+where
+    Ctrl: DeviceControl,
+    Strm: PayloadStream,
+{
+    // Open the camera.
+    camera.open().unwrap();
+    // Load `GenApi` context.
+    camera.load_context().unwrap();
+
+    // Start streaming. Channel capacity is set to 3.
+    let payload_rx = camera.start_streaming(3).unwrap();
+
+    let mut zero_copy = Latency::default();
+    let mut copy = Latency::default();
+    let mut dst = vec![];
+    let mut sum = 0;
+    for i in 0..FRAMES * 2 {
+        let payload = match async_std::task::block_on(payload_rx.recv()) {
+            Ok(payload) => payload,
+            Err(err) => {
+                println!("failed to receive payload: {}", err);
+                continue;
+            }
+        };
+
+        // Alternate the methods so that both see the same stream conditions.
+        let start = Instant::now();
+        if i % 2 == 0 {
+            sum += checksum(payload.payload());
+            zero_copy.observe(start.elapsed());
+        } else {
+            dst.resize(payload.payload().len(), 0);
+            dst.copy_from_slice(payload.payload());
+            sum += checksum(&dst);
+            copy.observe(start.elapsed());
+        }
+
+        // Send back payload to streaming loop to reuse the buffer.
+        payload_rx.send_back(payload);
+    }
+
+    zero_copy.report("zero-copy");
+    copy.report("copy");
+    println!("checksum: {}", sum);
+
+    camera.close().ok();
+}
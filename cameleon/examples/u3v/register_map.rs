@@ -25,31 +25,22 @@ fn main() {
     println!("\n### Technology Agnostic Boot Register Map ###\n");
     let abrm = ctrl.abrm().unwrap();
 
-    println!("gencp_version: {}", abrm.gencp_version(ctrl).unwrap());
-    println!(
-        "manufacturer_name: {}",
-        abrm.manufacturer_name(ctrl).unwrap()
-    );
-    println!("model_name: {}", abrm.model_name(ctrl).unwrap());
-    println!("family_name: {:?}", abrm.family_name(ctrl));
-    println!("device_version: {}", abrm.device_version(ctrl).unwrap());
-    println!(
-        "manufacturer_name: {}",
-        abrm.manufacturer_name(ctrl).unwrap()
-    );
-    println!("serial_number: {}", abrm.serial_number(ctrl).unwrap());
-    println!(
-        "manifest_table_address: {}",
-        abrm.manifest_table_address(ctrl).unwrap()
-    );
-    println!("sbrm_address: {}", abrm.sbrm_address(ctrl).unwrap());
+    println!("gencp_version: {}", abrm.gencp_version());
+    println!("manufacturer_name: {}", abrm.manufacturer_name());
+    println!("model_name: {}", abrm.model_name());
+    println!("family_name: {:?}", abrm.family_name());
+    println!("device_version: {}", abrm.device_version());
+    println!("manufacturer_info: {}", abrm.manufacturer_info());
+    println!("serial_number: {}", abrm.serial_number());
+    println!("manifest_table_address: {}", abrm.manifest_table_address());
+    println!("sbrm_address: {}", abrm.sbrm_address());
     println!(
         "device_software_interface_version: {:?}",
-        abrm.device_software_interface_version(ctrl)
+        abrm.device_software_interface_version()
     );
     println!(
         "maximum_device_response_time: {:?}",
-        abrm.maximum_device_response_time(ctrl)
+        abrm.maximum_device_response_time()
     );
 
     let device_capability = abrm.device_capability().unwrap();
@@ -57,10 +48,7 @@ fn main() {
         "is_user_defined_name_supported: {}",
         device_capability.is_user_defined_name_supported()
     );
-    println!(
-        "user_defined_name: {:?}",
-        abrm.user_defined_name(ctrl).unwrap()
-    );
+    println!("user_defined_name: {:?}", abrm.user_defined_name());
     println!(
         "is_multi_event_supported: {}",
         device_capability.is_multi_event_supported()
@@ -79,11 +67,8 @@ fn main() {
     // Write to registers.
     // NOTE. These oeprations will cause non-volatile changes to the register.
     //
-    // abrm.set_user_defined_name("Cameleon").unwrap();
-    // println!(
-    //     "changed user_defined_name: {:?}",
-    //     abrm.user_defined_name(ctrl).unwrap()
-    // );
+    // abrm.set_user_defined_name(ctrl, "Cameleon").unwrap();
+    // println!("changed user_defined_name: {:?}", abrm.user_defined_name());
 
     // if abrm.is_multi_event_supported() {
     //     abrm.enable_multi_event(ctlr).unwrap();
@@ -94,42 +79,36 @@ fn main() {
     println!("\n### Technology Specific Boot Register Map ###\n");
 
     let sbrm = ctrl.sbrm().unwrap();
-    println!("u3v_version: {}", sbrm.u3v_version(ctrl).unwrap());
+    println!("u3v_version: {}", sbrm.u3v_version());
     println!(
         "maximum_command_transfer_length: {}",
-        sbrm.maximum_command_transfer_length(ctrl).unwrap()
+        sbrm.maximum_command_transfer_length()
     );
     println!(
         "maximum_acknowledge_transfer_length: {}",
-        sbrm.maximum_acknowledge_trasfer_length(ctrl).unwrap()
+        sbrm.maximum_acknowledge_trasfer_length()
     );
     println!(
         "number_of_stream_channel: {}",
-        sbrm.number_of_stream_channel(ctrl).unwrap()
+        sbrm.number_of_stream_channel()
     );
-    println!("sirm_address: {:?}", sbrm.sirm_address(ctrl).unwrap());
-    println!("sirm_length: {:?}", sbrm.sirm_length(ctrl).unwrap());
+    println!("sirm_address: {:?}", sbrm.sirm_address());
+    println!("sirm_length: {:?}", sbrm.sirm_length());
 
-    println!("eirm_address: {:?}", sbrm.eirm_address(ctrl).unwrap());
-    println!("eirm_length: {:?}", sbrm.eirm_length(ctrl).unwrap());
-    println!("iidc2_address: {:?}", sbrm.iidc2_address(ctrl).unwrap());
+    println!("eirm_address: {:?}", sbrm.eirm_address());
+    println!("eirm_length: {:?}", sbrm.eirm_length());
+    println!("iidc2_address: {:?}", sbrm.iidc2_address());
     println!("current_speed: {:?}", sbrm.current_speed(ctrl).unwrap());
 
     // Read manifest entries.
-    let manifest_table = abrm.manifest_table(ctrl).unwrap();
-    for (i, entry) in manifest_table.entries(ctrl).unwrap().enumerate() {
+    let manifest_table = ctrl.manifest_table().unwrap();
+    for (i, entry) in manifest_table.entries().iter().enumerate() {
         println!("\n### Manifest Entry {} ###\n", i);
-        println!(
-            "GenICam file version: {}",
-            entry.genicam_file_version(ctrl).unwrap()
-        );
-        println!(
-            "GenICam file address: {}",
-            entry.file_address(ctrl).unwrap()
-        );
-        println!("GenICam file size: {}", entry.file_size(ctrl).unwrap());
+        println!("GenICam file version: {}", entry.genicam_file_version());
+        println!("GenICam file address: {}", entry.file_address());
+        println!("GenICam file size: {}", entry.file_size());
 
-        let file_info = entry.file_info(ctrl).unwrap();
+        let file_info = entry.file_info();
         println!(
             "GenICam file compression type: {:?}",
             file_info.compression_type().unwrap()
@@ -57,12 +57,21 @@
 //! camera.close().unwrap();
 //! ```
 
+use std::path::Path;
+
 use auto_impl::auto_impl;
 use tracing::info;
 
 use super::{
-    genapi::{DefaultGenApiCtxt, FromXml, GenApiCtxt, ParamsCtxt},
-    payload::{channel, PayloadReceiver, PayloadSender},
+    genapi::{
+        CompatibilityReport, DefaultGenApiCtxt, FeatureValue, FloatNode, FromXml, GenApiCtxt,
+        GenApiError, ParamsCtxt, ParserConfig, SfncFeature, StreamingWhitelist,
+    },
+    limits::Limits,
+    load_options::{self, GenApiFile, LoadOptions, LoadPhase, LoadResult},
+    patch::{PatchOptions, PatchReport, PatchResult, PatchScript},
+    payload::{channel_with_config, PayloadReceiver, PayloadSender, ReceiverConfig},
+    user_set::{self, UserSet, UserSetOptions, UserSetSnapshot},
     CameleonError, CameleonResult, ControlResult, StreamError, StreamResult,
 };
 
@@ -127,17 +136,20 @@ pub struct Camera<Ctrl, Strm, Ctxt = DefaultGenApiCtxt> {
     pub ctxt: Option<Ctxt>,
     /// Information of the camera.
     info: CameraInfo,
+    /// Features writable while streaming is active.
+    streaming_whitelist: StreamingWhitelist,
 }
 
 macro_rules! expect_node {
-    ($ctxt:expr, $name:expr, $as_type:ident) => {{
-        let err_msg = std::concat!("missing ", $name);
-        let err_msg2 = std::concat!($name, " has invalid interface");
+    ($ctxt:expr, $feature:expr, $as_type:ident) => {{
+        let feature: SfncFeature = $feature;
         $ctxt
-            .node($name)
-            .ok_or_else(|| CameleonError::InvalidGenApiXml(err_msg.into()))?
+            .get(feature)
+            .ok_or_else(|| CameleonError::InvalidGenApiXml(format!("missing {}", feature).into()))?
             .$as_type($ctxt)
-            .ok_or_else(|| CameleonError::InvalidGenApiXml(err_msg2.into()))?
+            .ok_or_else(|| {
+                CameleonError::InvalidGenApiXml(format!("{} has invalid interface", feature).into())
+            })?
     }};
 }
 
@@ -214,6 +226,26 @@ impl<Ctrl, Strm, Ctxt> Camera<Ctrl, Strm, Ctxt> {
         Ok(())
     }
 
+    /// Returns [`Limits`] on the values claimed by the device, see [`DeviceControl::limits`].
+    pub fn limits(&self) -> Limits
+    where
+        Ctrl: DeviceControl,
+    {
+        self.ctrl.limits()
+    }
+
+    /// Sets [`Limits`] on the values claimed by the device.
+    ///
+    /// The limits are enforced by the control handle, e.g. on the transfer lengths read when the
+    /// camera is opened and the payload size read when streaming is started, and by
+    /// [`Self::load_context`] on the size of `GenApi` xml.
+    pub fn set_limits(&mut self, limits: Limits)
+    where
+        Ctrl: DeviceControl,
+    {
+        self.ctrl.set_limits(limits);
+    }
+
     /// Loads `GenApi` xml from the device and builds the context, then returns the `GenApi` xml
     /// string.  
     ///
@@ -239,16 +271,120 @@ impl<Ctrl, Strm, Ctxt> Camera<Ctrl, Strm, Ctxt> {
     /// camera.close().unwrap();
     /// ```
     pub fn load_context(&mut self) -> CameleonResult<String>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt + FromXml,
+    {
+        self.load_context_with(&ParserConfig::default())
+    }
+
+    /// Same as [`Self::load_context`], but parses the `GenApi` xml with `config`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use cameleon::u3v;
+    /// use cameleon::genapi::{ParserConfig, SchemaPolicy};
+    ///
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    ///
+    /// // Refuses the xml if it conforms to a newer schema than the supported one.
+    /// let config = ParserConfig::new().schema_policy(SchemaPolicy::Reject);
+    /// camera.load_context_with(&config).unwrap();
+    ///
+    /// camera.close().unwrap();
+    /// ```
+    pub fn load_context_with(&mut self, config: &ParserConfig) -> CameleonResult<String>
     where
         Ctrl: DeviceControl,
         Strm: PayloadStream,
         Ctxt: GenApiCtxt + FromXml,
     {
         let xml = self.ctrl.genapi()?;
-        self.ctxt = Some(Ctxt::from_xml(&xml)?);
+        self.ctrl.limits().check_xml_size(xml.len())?;
+        self.ctxt = Some(Ctxt::from_xml_with(&xml, config)?);
+        self.streaming_whitelist.invalidate();
+        Ok(xml)
+    }
+
+    /// Same as [`Self::load_context`], but retrieves the `GenApi` xml according to `options`.
+    ///
+    /// The retrieval can be cancelled, bounded with a deadline, retried and resumed from the disk
+    /// cache. See [`load_options`](crate::load_options) for details.
+    ///
+    /// The camera is left opened on any error, and the context is kept unchanged.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use cameleon::u3v;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// use cameleon::load_options::LoadOptions;
+    ///
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    ///
+    /// let options = LoadOptions::new()
+    ///     .deadline(Instant::now() + Duration::from_secs(10))
+    ///     .retries(3);
+    /// camera.load_context_with_options(&options).unwrap();
+    ///
+    /// camera.close().unwrap();
+    /// ```
+    pub fn load_context_with_options(&mut self, options: &LoadOptions) -> LoadResult<String>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt + FromXml,
+    {
+        let xml = load_options::load_xml(&mut self.ctrl, options)?;
+        options.check(LoadPhase::Parse, xml.len())?;
+        self.ctxt = Some(Ctxt::from_xml_with(&xml, &options.parser_config)?);
+        self.streaming_whitelist.invalidate();
         Ok(xml)
     }
 
+    /// Returns [`CompatibilityReport`] of the `GenApi` xml the context is built from.
+    ///
+    /// Returns `None` if the context is not loaded or the context doesn't keep the report.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// if let Some(report) = camera.compatibility_report() {
+    ///     if report.is_degraded() {
+    ///         for warning in report.warnings() {
+    ///             println!("{}", warning);
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// camera.close().unwrap();
+    /// ```
+    pub fn compatibility_report(&self) -> Option<&CompatibilityReport>
+    where
+        Ctxt: GenApiCtxt,
+    {
+        self.ctxt.as_ref()?.compatibility_report()
+    }
+
     /// Starts streaming and returns the receiver for the `Payload`.
     ///
     /// Make sure to load `GenApi` context before calling this method.
@@ -282,15 +418,33 @@ impl<Ctrl, Strm, Ctxt> Camera<Ctrl, Strm, Ctxt> {
     ///
     /// # Arguments
     /// * `cap` - A capacity of the paylaod receiver, the sender will stop to send a payload when it
-    /// gets full.
+    ///   gets full.
     ///
     ///
     /// # Panics
     /// If `cap` is zero, this method will panic.
+    pub fn start_streaming(&mut self, cap: usize) -> CameleonResult<PayloadReceiver>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        self.start_streaming_with_config(cap, ReceiverConfig::default())
+    }
+
+    /// Starts streaming as [`Self::start_streaming`], and configures the returned receiver with
+    /// `config`, e.g. to drop incomplete payloads.
+    ///
+    /// # Panics
+    /// If `cap` is zero, this method will panic.
     #[tracing::instrument(skip(self),
                           level = "info",
                           fields(camera = ?self.info()))]
-    pub fn start_streaming(&mut self, cap: usize) -> CameleonResult<PayloadReceiver>
+    pub fn start_streaming_with_config(
+        &mut self,
+        cap: usize,
+        config: ReceiverConfig,
+    ) -> CameleonResult<PayloadReceiver>
     where
         Ctrl: DeviceControl,
         Strm: PayloadStream,
@@ -306,11 +460,11 @@ impl<Ctrl, Strm, Ctxt> Camera<Ctrl, Strm, Ctxt> {
         // Enable streaimng.
         self.ctrl.enable_streaming()?;
         let mut ctxt = self.params_ctxt()?;
-        expect_node!(&ctxt, "TLParamsLocked", as_integer).set_value(&mut ctxt, 1)?;
-        expect_node!(&ctxt, "AcquisitionStart", as_command).execute(&mut ctxt)?;
+        expect_node!(&ctxt, SfncFeature::TlParamsLocked, as_integer).set_value(&mut ctxt, 1)?;
+        expect_node!(&ctxt, SfncFeature::AcquisitionStart, as_command).execute(&mut ctxt)?;
 
         // Start streaming loop.
-        let (sender, receiver) = channel(cap, DEFAULT_BUFFER_CAP);
+        let (sender, receiver) = channel_with_config(cap, DEFAULT_BUFFER_CAP, config);
         self.strm.start_streaming_loop(sender, &mut self.ctrl)?;
 
         info!("start streaming successfully");
@@ -356,23 +510,30 @@ impl<Ctrl, Strm, Ctxt> Camera<Ctrl, Strm, Ctxt> {
             return Ok(());
         }
 
-        // Stop streaming loop.
+        // Stop streaming loop before touching the control handle, so that the loop is joined
+        // without holding the lock of the handle.
         self.strm.stop_streaming_loop()?;
 
         // Disable streaming.
         let mut ctxt = self.params_ctxt()?;
-        expect_node!(&ctxt, "AcquisitionStop", as_command).execute(&mut ctxt)?;
-        expect_node!(&ctxt, "TLParamsLocked", as_integer).set_value(&mut ctxt, 0)?;
+        expect_node!(&ctxt, SfncFeature::AcquisitionStop, as_command).execute(&mut ctxt)?;
+        expect_node!(&ctxt, SfncFeature::TlParamsLocked, as_integer).set_value(&mut ctxt, 0)?;
         self.ctrl.disable_streaming()?;
 
         info!("stop streaming successfully");
         Ok(())
     }
 
-    /// Returns the context of the camera params.
+    /// Pauses the streaming without tearing it down.
     ///
-    /// Make sure to load `GenApi` context before calling this method.
-    /// See [`load_context`](Self::load_context) and [`set_context`](Self::set_context) how to configure `GenApi` context.
+    /// Acquisition is stopped with `AcquisitionStop`, or by disabling the stream interface if the
+    /// context lacks the node. Then the streaming loop stops submitting transfers once the frame
+    /// in flight is finished or discarded. The buffers and the transfer parameters negotiated
+    /// with the device are kept, so [`Self::resume_streaming`] delivers frames without
+    /// renegotiation.
+    ///
+    /// The receiver returned from [`Self::start_streaming`] stays valid. Does nothing if the
+    /// streaming is not started or already paused.
     ///
     /// # Examples
     /// ```
@@ -384,48 +545,100 @@ impl<Ctrl, Strm, Ctxt> Camera<Ctrl, Strm, Ctxt> {
     /// # let mut camera = cameras.pop().unwrap();
     /// camera.open().unwrap();
     /// camera.load_context().unwrap();
+    /// let payload_rx = camera.start_streaming(3).unwrap();
     ///
-    /// // Get params context.
-    /// let mut params_ctxt = camera.params_ctxt().unwrap();
-    ///
-    /// // Get `Gain` node of `GenApi`.
-    /// // `GenApi SFNC` defines that `Gain` node should have `IFloat` interface,
-    /// // so this conversion would be success if the camera follows that.
-    /// // Some vendors may define `Gain` node as `IInteger`, in that case, use
-    /// // `as_integer(&params_ctxt)` instead of `as_float(&params_ctxt).
-    /// let gain_node = params_ctxt.node("Gain").unwrap().as_float(&params_ctxt).unwrap();
-    ///
-    /// // Get the current value of `Gain`.
-    /// if gain_node.is_readable(&mut params_ctxt).unwrap() {
-    ///     let value = gain_node.value(&mut params_ctxt).unwrap();
-    ///     println!("{}", value);
-    /// }
+    /// camera.pause_streaming().unwrap();
+    /// // No payload is sent while the streaming is paused.
+    /// camera.resume_streaming().unwrap();
     ///
-    /// // Set `0.1` to `Gain`.
-    /// if gain_node.is_writable(&mut params_ctxt).unwrap() {
-    ///     gain_node.set_value(&mut params_ctxt, 0.1).unwrap();
-    /// }
-    /// # camera.close();
+    /// camera.stop_streaming().unwrap();
     /// ```
-    pub fn params_ctxt(&mut self) -> CameleonResult<ParamsCtxt<&mut Ctrl, &mut Ctxt>>
+    #[tracing::instrument(skip(self),
+                          level = "info",
+                          fields(camera = ?self.info()))]
+    pub fn pause_streaming(&mut self) -> CameleonResult<()>
     where
         Ctrl: DeviceControl,
         Strm: PayloadStream,
         Ctxt: GenApiCtxt,
     {
-        if let Some(ctxt) = self.ctxt.as_mut() {
-            Ok(ParamsCtxt {
-                ctrl: &mut self.ctrl,
-                ctxt,
-            })
-        } else {
-            Err(CameleonError::GenApiContextMissing)
+        info!("try pausing streaming");
+        if !self.strm.is_loop_running() || self.strm.is_loop_paused() {
+            return Ok(());
+        }
+
+        // Stop acquisition first so that the device finishes the frame in flight.
+        if !self.execute_if_present(SfncFeature::AcquisitionStop)? {
+            self.ctrl.disable_streaming()?;
         }
+        self.strm.pause_streaming_loop()?;
+
+        info!("pause streaming successfully");
+        Ok(())
     }
 
-    /// Returns basic information of the camera.
+    /// Resumes the streaming paused by [`Self::pause_streaming`].
+    ///
+    /// The block ids skipped while the streaming is paused are reported as an intentional gap in
+    /// the stream statistics instead of lost payloads. Does nothing if the streaming is not
+    /// paused.
+    #[tracing::instrument(skip(self),
+                          level = "info",
+                          fields(camera = ?self.info()))]
+    pub fn resume_streaming(&mut self) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        info!("try resuming streaming");
+        if !self.strm.is_loop_paused() {
+            return Ok(());
+        }
+
+        // Resume the loop first so that it's ready to receive the first frame.
+        self.strm.resume_streaming_loop()?;
+        if !self.execute_if_present(SfncFeature::AcquisitionStart)? {
+            self.ctrl.reenable_streaming()?;
+        }
+
+        info!("resume streaming successfully");
+        Ok(())
+    }
+
+    /// Executes the command node `feature`, returns `false` if the context or the node is
+    /// missing.
+    fn execute_if_present(&mut self, feature: SfncFeature) -> CameleonResult<bool>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        let mut ctxt = match self.params_ctxt() {
+            Ok(ctxt) => ctxt,
+            Err(CameleonError::GenApiContextMissing) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        match ctxt.get(feature).and_then(|node| node.as_command(&ctxt)) {
+            Some(command) => {
+                command.execute(&mut ctxt)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Sets the acquisition frame rate to `target_fps` as close as possible, then returns the
+    /// resulting frame rate.
+    ///
+    /// `AcquisitionFrameRateEnable` is turned on if the camera has the node, and `target_fps` is
+    /// clamped to the current range of `AcquisitionFrameRate`. The resulting frame rate is read
+    /// from `ResultingFrameRate` if the camera has the node, otherwise the written value is
+    /// returned.
+    ///
+    /// Returns [`CameleonError::MissingCapability`] if the camera doesn't have the nodes to
+    /// control the frame rate.
     ///
-    /// This information can be obtained without calling [`Self::open`].
     /// # Examples
     /// ```
     /// # use cameleon::u3v;
@@ -434,83 +647,568 @@ impl<Ctrl, Strm, Ctxt> Camera<Ctrl, Strm, Ctxt> {
     /// #     return;
     /// # }
     /// # let mut camera = cameras.pop().unwrap();
-    /// let info = camera.info();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// let fps = camera.set_frame_rate(30.0).unwrap();
+    /// println!("running at {} fps", fps);
+    /// # camera.close();
     /// ```
-    pub fn info(&self) -> &CameraInfo {
-        &self.info
-    }
+    pub fn set_frame_rate(&mut self, target_fps: f64) -> CameleonResult<f64>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        let mut ctxt = self.params_ctxt()?;
+        let rate = frame_rate_node(&ctxt)?;
 
-    /// Constructs a camera.
-    pub fn new(ctrl: Ctrl, strm: Strm, ctxt: Option<Ctxt>, info: CameraInfo) -> Self {
-        Self {
-            ctrl,
-            strm,
-            ctxt,
-            info,
+        if let Some(enable) = ctxt
+            .get(SfncFeature::AcquisitionFrameRateEnable)
+            .and_then(|node| node.as_boolean(&ctxt))
+        {
+            if !enable.value(&mut ctxt)? {
+                enable.set_value(&mut ctxt, true)?;
+            }
+        } else if !rate.is_writable(&mut ctxt)? {
+            return Err(CameleonError::MissingCapability {
+                missing: vec![SfncFeature::AcquisitionFrameRateEnable.as_str()],
+            });
+        }
+
+        let min = rate.min(&mut ctxt)?;
+        let max = rate.max(&mut ctxt)?;
+        let fps = target_fps.max(min).min(max);
+        rate.set_value(&mut ctxt, fps)?;
+
+        match resulting_frame_rate(&mut ctxt)? {
+            Some(resulting) => Ok(resulting),
+            None => Ok(fps),
         }
     }
 
-    /// Converts internal types.
+    /// Returns the current frame rate.
     ///
-    /// This method works same as `std::convert::From`, just hack to avoid
-    /// `E0119`.
-    pub fn convert_from<Ctrl2, Strm2, Ctxt2>(from: Camera<Ctrl2, Strm2, Ctxt2>) -> Self
+    /// The value is read from `ResultingFrameRate` if the camera has the node, otherwise from
+    /// `AcquisitionFrameRate`.
+    pub fn frame_rate(&mut self) -> CameleonResult<f64>
     where
-        Ctrl: From<Ctrl2>,
-        Strm: From<Strm2>,
-        Ctxt: From<Ctxt2>,
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
     {
-        Camera::new(
-            from.ctrl.into(),
-            from.strm.into(),
-            from.ctxt.map(|ctxt| ctxt.into()),
-            from.info,
-        )
+        let mut ctxt = self.params_ctxt()?;
+        let rate = frame_rate_node(&ctxt)?;
+        match resulting_frame_rate(&mut ctxt)? {
+            Some(resulting) => Ok(resulting),
+            None => Ok(rate.value(&mut ctxt)?),
+        }
     }
 
-    /// Converts internal types. This method work same as `std::convert::Into`, just hack to avoid
-    /// `E0119`.
+    /// Returns the maximum frame rate under the current settings, e.g. exposure time and
+    /// bandwidth limit.
+    pub fn max_frame_rate(&mut self) -> CameleonResult<f64>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        let mut ctxt = self.params_ctxt()?;
+        let rate = frame_rate_node(&ctxt)?;
+        Ok(rate.max(&mut ctxt)?)
+    }
+
+    /// Saves the current settings to the user set `slot`, and waits for the camera to complete
+    /// saving.
+    ///
+    /// Returns [`CameleonError::UserSetUnavailable`] if the camera doesn't have `slot`. The
+    /// factory settings [`UserSet::Default`] is usually read-only.
     ///
     /// # Examples
-    /// ```rust
+    /// ```
     /// # use cameleon::u3v;
     /// # let mut cameras = u3v::enumerate_cameras().unwrap();
     /// # if cameras.is_empty() {
     /// #     return;
     /// # }
-    /// # let camera = cameras.pop().unwrap();
-    /// use cameleon::{DeviceControl, PayloadStream, Camera};
-    /// use cameleon::genapi::NoCacheGenApiCtxt;
+    /// # let mut camera = cameras.pop().unwrap();
+    /// use cameleon::user_set::UserSet;
     ///
-    /// // Convert into `Camera<Box<dyn DeviceControl>, Box<dyn PayloadStream>, NoCacheGenApiCtxt>`.
-    /// let dyn_camera: Camera<Box<dyn DeviceControl>, Box<dyn PayloadStream>, NoCacheGenApiCtxt> =
-    ///     camera.convert_into();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// camera.save_user_set(UserSet::User(1)).unwrap();
+    /// camera.set_default_user_set(UserSet::User(1)).unwrap();
+    /// # camera.close();
     /// ```
-    pub fn convert_into<Ctrl2, Strm2, Ctxt2>(self) -> Camera<Ctrl2, Strm2, Ctxt2>
+    pub fn save_user_set(&mut self, slot: UserSet) -> CameleonResult<()>
     where
-        Ctrl: Into<Ctrl2>,
-        Strm: Into<Strm2>,
-        Ctxt: Into<Ctxt2>,
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
     {
-        Camera::new(
-            self.ctrl.into(),
-            self.strm.into(),
-            self.ctxt.map(|ctxt| ctxt.into()),
-            self.info,
-        )
-    }
-
-    /// Set a context to the camera. It's recommended to use [`Self::load_context`] instead if `Self::Ctxt`
-    /// implements [`FromXml`] trait.
-    pub fn set_context<Ctxt2>(self, ctxt: Ctxt2) -> Camera<Ctrl, Strm, Ctxt2> {
-        Camera {
-            ctrl: self.ctrl,
-            strm: self.strm,
-            ctxt: Some(ctxt),
-            info: self.info,
-        }
+        self.save_user_set_with(slot, &UserSetOptions::default())
+            .map(drop)
     }
-}
+
+    /// Saves the current settings to the user set `slot` with `options`, then returns the values
+    /// of [`UserSetOptions::verified_features`] at the time of saving.
+    ///
+    /// The returned snapshot is passed to [`Self::load_user_set_verified`] to verify that the
+    /// user set is loaded as saved.
+    pub fn save_user_set_with(
+        &mut self,
+        slot: UserSet,
+        options: &UserSetOptions,
+    ) -> CameleonResult<UserSetSnapshot>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        let mut ctxt = self.params_ctxt()?;
+        user_set::save(&mut ctxt, slot, options)
+    }
+
+    /// Loads the user set `slot`, and waits for the camera to complete loading.
+    ///
+    /// Returns [`CameleonError::UserSetUnavailable`] if the camera doesn't have `slot`.
+    pub fn load_user_set(&mut self, slot: UserSet) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        let mut ctxt = self.params_ctxt()?;
+        user_set::load(&mut ctxt, slot, None, &UserSetOptions::default())
+    }
+
+    /// Loads the user set of `snapshot`, then verifies that the features in `snapshot` have the
+    /// values at the time of saving.
+    ///
+    /// Returns [`CameleonError::UserSetMismatch`] listing the features whose values differ.
+    ///
+    /// # Examples
+    /// ```
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// use cameleon::user_set::{UserSet, UserSetOptions};
+    ///
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// let options = UserSetOptions::default()
+    ///     .verify("ExposureTime")
+    ///     .verify("Gain");
+    /// let snapshot = camera.save_user_set_with(UserSet::User(1), &options).unwrap();
+    /// // ...
+    /// camera.load_user_set_verified(&snapshot, &options).unwrap();
+    /// # camera.close();
+    /// ```
+    pub fn load_user_set_verified(
+        &mut self,
+        snapshot: &UserSetSnapshot,
+        options: &UserSetOptions,
+    ) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        let mut ctxt = self.params_ctxt()?;
+        user_set::load(&mut ctxt, snapshot.slot(), Some(snapshot), options)
+    }
+
+    /// Sets the user set `slot` to be loaded when the camera is powered up.
+    ///
+    /// Returns [`CameleonError::UserSetUnavailable`] if the camera doesn't have `slot`.
+    pub fn set_default_user_set(&mut self, slot: UserSet) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        let mut ctxt = self.params_ctxt()?;
+        user_set::set_default(&mut ctxt, slot)
+    }
+
+    /// Returns the features which are allowed to be written while streaming is active.
+    ///
+    /// The whitelist is detected from the `GenApi` context when it's first accessed: a feature is
+    /// whitelisted if it stays writable while `TLParamsLocked` is set. The device is not accessed
+    /// for the detection. The returned whitelist can be overridden, and the overrides are kept
+    /// when the context is reloaded.
+    ///
+    /// The whitelist is enforced by the typed setters, e.g. [`Self::set_float`]. Writes through
+    /// [`Self::params_ctxt`] are not checked.
+    ///
+    /// # Examples
+    /// ```
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// for feature in camera.streaming_whitelist().unwrap().features() {
+    ///     println!("{} is writable while streaming", feature);
+    /// }
+    /// # camera.close();
+    /// ```
+    pub fn streaming_whitelist(&mut self) -> CameleonResult<&mut StreamingWhitelist>
+    where
+        Ctxt: GenApiCtxt,
+    {
+        let ctxt = self
+            .ctxt
+            .as_ref()
+            .ok_or(CameleonError::GenApiContextMissing)?;
+        if !self.streaming_whitelist.is_detected() {
+            self.streaming_whitelist.detect(ctxt.node_store());
+        }
+        Ok(&mut self.streaming_whitelist)
+    }
+
+    /// Writes `value` to the `IInteger` or `IEnumeration` feature `name`.
+    ///
+    /// Returns [`CameleonError::LockedDuringAcquisition`] if streaming is active and `name` is
+    /// not in [`Self::streaming_whitelist`].
+    ///
+    /// # Examples
+    /// ```
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// use cameleon::CameleonError;
+    ///
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    /// let payload_rx = camera.start_streaming(3).unwrap();
+    ///
+    /// match camera.set_integer("Width", 640) {
+    ///     Err(CameleonError::LockedDuringAcquisition { .. }) => {
+    ///         camera.stop_streaming().unwrap();
+    ///         camera.set_integer("Width", 640).unwrap();
+    ///         let payload_rx = camera.start_streaming(3).unwrap();
+    ///     }
+    ///     res => res.unwrap(),
+    /// }
+    /// # camera.close();
+    /// ```
+    pub fn set_integer(&mut self, name: &str, value: i64) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        self.set_feature(name, value.into())
+    }
+
+    /// Writes `value` to the `IFloat` feature `name`, see [`Self::set_integer`].
+    pub fn set_float(&mut self, name: &str, value: f64) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        self.set_feature(name, value.into())
+    }
+
+    /// Writes `value` to the `IBoolean` feature `name`, see [`Self::set_integer`].
+    pub fn set_boolean(&mut self, name: &str, value: bool) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        self.set_feature(name, value.into())
+    }
+
+    /// Sets the entry named `entry` to the `IEnumeration` feature `name`, see
+    /// [`Self::set_integer`].
+    pub fn set_enum_entry(&mut self, name: &str, entry: &str) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        self.set_feature(name, entry.to_string().into())
+    }
+
+    fn set_feature(&mut self, name: &str, value: FeatureValue) -> CameleonResult<()>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        if self.strm.is_loop_running() && !self.streaming_whitelist()?.contains(name) {
+            return Err(CameleonError::LockedDuringAcquisition {
+                feature: name.to_string(),
+            });
+        }
+
+        let mut ctxt = self.params_ctxt()?;
+        let node = ctxt.node(name).ok_or_else(|| {
+            GenApiError::InvalidNode(format!("no node named `{}` exists", name).into())
+        })?;
+        Ok(ctxt.write_feature(node, value)?)
+    }
+
+    /// Loads the patch script at `path` and applies it to the camera, see [`patch`](crate::patch)
+    /// for the format.
+    ///
+    /// Failed steps are reported in the returned [`PatchReport`]. Scripts which write to
+    /// bootstrap registers are refused, use [`Self::apply_patch_with`] to change the behavior.
+    ///
+    /// # Examples
+    /// ```
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    ///
+    /// let report = camera.apply_patch("fix_black_level.patch").unwrap();
+    /// if !report.is_success() {
+    ///     println!("failed to apply patch:\n{}", report);
+    /// }
+    /// # camera.close();
+    /// ```
+    pub fn apply_patch(&mut self, path: impl AsRef<Path>) -> PatchResult<PatchReport>
+    where
+        Ctrl: DeviceControl,
+    {
+        let script = PatchScript::load(path)?;
+        self.apply_patch_with(&script, &PatchOptions::default())
+    }
+
+    /// Applies `script` to the camera with `options`.
+    ///
+    /// # Examples
+    /// ```
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// use cameleon::patch::{PatchOptions, PatchScript};
+    ///
+    /// camera.open().unwrap();
+    ///
+    /// let script = PatchScript::load("fix_black_level.patch").unwrap();
+    /// // Validates the script and prints the plan without writing to the camera.
+    /// let plan = camera
+    ///     .apply_patch_with(&script, &PatchOptions::new().dry_run(true))
+    ///     .unwrap();
+    /// println!("{}", plan);
+    /// # camera.close();
+    /// ```
+    pub fn apply_patch_with(
+        &mut self,
+        script: &PatchScript,
+        options: &PatchOptions,
+    ) -> PatchResult<PatchReport>
+    where
+        Ctrl: DeviceControl,
+    {
+        let report = script.apply(&mut self.ctrl, options)?;
+        info!(
+            "applied patch script: dry_run {}, aborted {}",
+            report.dry_run, report.aborted
+        );
+        Ok(report)
+    }
+
+    /// Returns the context of the camera params.
+    ///
+    /// Make sure to load `GenApi` context before calling this method.
+    /// See [`load_context`](Self::load_context) and [`set_context`](Self::set_context) how to configure `GenApi` context.
+    ///
+    /// # Examples
+    /// ```
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    /// camera.load_context().unwrap();
+    ///
+    /// // Get params context.
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    ///
+    /// // Get `Gain` node of `GenApi`.
+    /// // `GenApi SFNC` defines that `Gain` node should have `IFloat` interface,
+    /// // so this conversion would be success if the camera follows that.
+    /// // Some vendors may define `Gain` node as `IInteger`, in that case, use
+    /// // `as_integer(&params_ctxt)` instead of `as_float(&params_ctxt).
+    /// let gain_node = params_ctxt.node("Gain").unwrap().as_float(&params_ctxt).unwrap();
+    ///
+    /// // Get the current value of `Gain`.
+    /// if gain_node.is_readable(&mut params_ctxt).unwrap() {
+    ///     let value = gain_node.value(&mut params_ctxt).unwrap();
+    ///     println!("{}", value);
+    /// }
+    ///
+    /// // Set `0.1` to `Gain`.
+    /// if gain_node.is_writable(&mut params_ctxt).unwrap() {
+    ///     gain_node.set_value(&mut params_ctxt, 0.1).unwrap();
+    /// }
+    /// # camera.close();
+    /// ```
+    pub fn params_ctxt(&mut self) -> CameleonResult<ParamsCtxt<&mut Ctrl, &mut Ctxt>>
+    where
+        Ctrl: DeviceControl,
+        Strm: PayloadStream,
+        Ctxt: GenApiCtxt,
+    {
+        if let Some(ctxt) = self.ctxt.as_mut() {
+            Ok(ParamsCtxt {
+                ctrl: &mut self.ctrl,
+                ctxt,
+            })
+        } else {
+            Err(CameleonError::GenApiContextMissing)
+        }
+    }
+
+    /// Returns basic information of the camera.
+    ///
+    /// This information can be obtained without calling [`Self::open`].
+    /// # Examples
+    /// ```
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// let info = camera.info();
+    /// ```
+    pub fn info(&self) -> &CameraInfo {
+        &self.info
+    }
+
+    /// Constructs a camera.
+    pub fn new(ctrl: Ctrl, strm: Strm, ctxt: Option<Ctxt>, info: CameraInfo) -> Self {
+        Self {
+            ctrl,
+            strm,
+            ctxt,
+            info,
+            streaming_whitelist: StreamingWhitelist::default(),
+        }
+    }
+
+    /// Converts internal types.
+    ///
+    /// This method works same as `std::convert::From`, just hack to avoid
+    /// `E0119`.
+    pub fn convert_from<Ctrl2, Strm2, Ctxt2>(from: Camera<Ctrl2, Strm2, Ctxt2>) -> Self
+    where
+        Ctrl: From<Ctrl2>,
+        Strm: From<Strm2>,
+        Ctxt: From<Ctxt2>,
+    {
+        let mut camera = Camera::new(
+            from.ctrl.into(),
+            from.strm.into(),
+            from.ctxt.map(|ctxt| ctxt.into()),
+            from.info,
+        );
+        camera.streaming_whitelist = from.streaming_whitelist;
+        camera
+    }
+
+    /// Converts internal types. This method work same as `std::convert::Into`, just hack to avoid
+    /// `E0119`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let camera = cameras.pop().unwrap();
+    /// use cameleon::{DeviceControl, PayloadStream, Camera};
+    /// use cameleon::genapi::NoCacheGenApiCtxt;
+    ///
+    /// // Convert into `Camera<Box<dyn DeviceControl>, Box<dyn PayloadStream>, NoCacheGenApiCtxt>`.
+    /// let dyn_camera: Camera<Box<dyn DeviceControl>, Box<dyn PayloadStream>, NoCacheGenApiCtxt> =
+    ///     camera.convert_into();
+    /// ```
+    pub fn convert_into<Ctrl2, Strm2, Ctxt2>(self) -> Camera<Ctrl2, Strm2, Ctxt2>
+    where
+        Ctrl: Into<Ctrl2>,
+        Strm: Into<Strm2>,
+        Ctxt: Into<Ctxt2>,
+    {
+        let mut camera = Camera::new(
+            self.ctrl.into(),
+            self.strm.into(),
+            self.ctxt.map(|ctxt| ctxt.into()),
+            self.info,
+        );
+        camera.streaming_whitelist = self.streaming_whitelist;
+        camera
+    }
+
+    /// Set a context to the camera. It's recommended to use [`Self::load_context`] instead if `Self::Ctxt`
+    /// implements [`FromXml`] trait.
+    pub fn set_context<Ctxt2>(mut self, ctxt: Ctxt2) -> Camera<Ctrl, Strm, Ctxt2> {
+        self.streaming_whitelist.invalidate();
+        Camera {
+            ctrl: self.ctrl,
+            strm: self.strm,
+            ctxt: Some(ctxt),
+            info: self.info,
+            streaming_whitelist: self.streaming_whitelist,
+        }
+    }
+}
+
+fn frame_rate_node<Ctrl, Ctxt>(ctxt: &ParamsCtxt<Ctrl, Ctxt>) -> CameleonResult<FloatNode>
+where
+    Ctxt: GenApiCtxt,
+{
+    ctxt.get(SfncFeature::AcquisitionFrameRate)
+        .and_then(|node| node.as_float(ctxt))
+        .ok_or(CameleonError::MissingCapability {
+            missing: vec![SfncFeature::AcquisitionFrameRate.as_str()],
+        })
+}
+
+/// Returns `None` if `ResultingFrameRate` is not available.
+fn resulting_frame_rate<Ctrl, Ctxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+) -> CameleonResult<Option<f64>>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    match ctxt
+        .get(SfncFeature::ResultingFrameRate)
+        .and_then(|node| node.as_float(ctxt))
+    {
+        Some(node) if node.is_readable(ctxt)? => Ok(Some(node.value(ctxt)?)),
+        _ => Ok(None),
+    }
+}
 
 /// Information of the camera.
 #[derive(Clone, Debug, PartialEq, Hash)]
@@ -546,11 +1244,52 @@ pub trait DeviceControl {
     /// Returns `GenICam` xml string.
     fn genapi(&mut self) -> ControlResult<String>;
 
+    /// Returns the location of `GenICam` xml file in the device's memory.
+    ///
+    /// Returns `None` if the file can't be read directly with [`DeviceControl::read`], in which
+    /// case [`Camera::load_context_with_options`] falls back to [`DeviceControl::genapi`].
+    fn genapi_file(&mut self) -> ControlResult<Option<GenApiFile>> {
+        Ok(None)
+    }
+
     /// Enables streaming.
     fn enable_streaming(&mut self) -> ControlResult<()>;
 
     /// Disables streaming.
     fn disable_streaming(&mut self) -> ControlResult<()>;
+
+    /// Enables streaming disabled by [`DeviceControl::disable_streaming`] again without
+    /// renegotiating the transfer parameters.
+    ///
+    /// Falls back to [`DeviceControl::enable_streaming`] by default.
+    fn reenable_streaming(&mut self) -> ControlResult<()> {
+        self.enable_streaming()
+    }
+
+    /// Returns the data of the chunk whose id is `chunk_id`, which is read by chunk features.
+    ///
+    /// Returns `None` by default, the data is served by [`ChunkControl`] instead.
+    ///
+    /// [`ChunkControl`]: crate::payload::ChunkControl
+    fn chunk_data(&self, chunk_id: u64) -> Option<&[u8]> {
+        let _ = chunk_id;
+        None
+    }
+
+    /// Returns [`Limits`] on the values claimed by the device.
+    ///
+    /// Returns the default limits by default.
+    fn limits(&self) -> Limits {
+        Limits::default()
+    }
+
+    /// Sets [`Limits`] on the values claimed by the device.
+    ///
+    /// Does nothing by default, which is only correct for handles which never allocate or wait
+    /// according to the values claimed by the device.
+    fn set_limits(&mut self, limits: Limits) {
+        let _ = limits;
+    }
 }
 
 /// This trait provides streaming capability.
@@ -574,4 +1313,467 @@ pub trait PayloadStream {
 
     /// Returns `true` if streaming loop is running.
     fn is_loop_running(&self) -> bool;
+
+    /// Pauses the streaming loop while keeping its buffers and transfer parameters.
+    ///
+    /// Returns [`StreamError::NotSupported`] if the handle doesn't support pausing.
+    fn pause_streaming_loop(&mut self) -> StreamResult<()> {
+        Err(StreamError::NotSupported("pausing streaming loop".into()))
+    }
+
+    /// Resumes the streaming loop paused by [`PayloadStream::pause_streaming_loop`].
+    ///
+    /// Returns [`StreamError::NotSupported`] if the handle doesn't support pausing.
+    fn resume_streaming_loop(&mut self) -> StreamResult<()> {
+        Err(StreamError::NotSupported("resuming streaming loop".into()))
+    }
+
+    /// Returns `true` if streaming loop is paused.
+    fn is_loop_paused(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genapi::DefaultGenApiCtxt;
+
+    const XML_HEADER: &str = r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="2"
+          SubMinorVersion="3"
+          ToolTip="ToolTiptest"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210"
+          xmlns="http://www.genicam.org/GenApi/Version_1_0"
+          xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+          xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_0 GenApiSchema.xsd">
+        "#;
+
+    /// Frame rate is limited by `ExposureTime` as well as `AcquisitionFrameRate`.
+    const FRAME_RATE_NODES: &str = r#"
+            <Boolean Name="AcquisitionFrameRateEnable">
+                <pValue>AcquisitionFrameRateEnableReg</pValue>
+                <OnValue>1</OnValue>
+                <OffValue>0</OffValue>
+            </Boolean>
+
+            <IntReg Name="AcquisitionFrameRateEnableReg">
+              <Address>0x0</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Float Name="AcquisitionFrameRate">
+                <pValue>AcquisitionFrameRateReg</pValue>
+                <Min>1.0</Min>
+                <Max>60.0</Max>
+            </Float>
+
+            <FloatReg Name="AcquisitionFrameRateReg">
+              <Address>0x8</Address>
+              <Length>8</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </FloatReg>
+
+            <FloatReg Name="ExposureTime">
+              <Address>0x10</Address>
+              <Length>8</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </FloatReg>
+
+            <SwissKnife Name="ResultingFrameRate">
+                <pVariable Name="FPS">AcquisitionFrameRate</pVariable>
+                <pVariable Name="EXPOSURE">ExposureTime</pVariable>
+                <Formula>(FPS &lt; 1000000 / EXPOSURE) ? FPS : 1000000 / EXPOSURE</Formula>
+            </SwissKnife>
+        "#;
+
+    const XML_FOOTER: &str = r#"
+            <Port Name="Device">
+            </Port>
+
+        </RegisterDescription>
+        "#;
+
+    /// Device memory which records the streaming operations.
+    struct Memory(Vec<u8>, Vec<&'static str>);
+
+    impl DeviceControl for Memory {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            let address = address as usize;
+            buf.copy_from_slice(&self.0[address..address + buf.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+            let address = address as usize;
+            self.0[address..address + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            unreachable!()
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            self.1.push("enable_streaming");
+            Ok(())
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            self.1.push("disable_streaming");
+            Ok(())
+        }
+
+        fn reenable_streaming(&mut self) -> ControlResult<()> {
+            self.1.push("reenable_streaming");
+            Ok(())
+        }
+    }
+
+    struct NoStream;
+
+    impl PayloadStream for NoStream {
+        fn open(&mut self) -> StreamResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> StreamResult<()> {
+            Ok(())
+        }
+
+        fn start_streaming_loop(
+            &mut self,
+            _sender: PayloadSender,
+            _ctrl: &mut dyn DeviceControl,
+        ) -> StreamResult<()> {
+            Ok(())
+        }
+
+        fn stop_streaming_loop(&mut self) -> StreamResult<()> {
+            Ok(())
+        }
+
+        fn is_loop_running(&self) -> bool {
+            false
+        }
+    }
+
+    /// A stream whose loop is always running.
+    #[derive(Default)]
+    struct PausableStream {
+        paused: bool,
+    }
+
+    impl PayloadStream for PausableStream {
+        fn open(&mut self) -> StreamResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> StreamResult<()> {
+            Ok(())
+        }
+
+        fn start_streaming_loop(
+            &mut self,
+            _sender: PayloadSender,
+            _ctrl: &mut dyn DeviceControl,
+        ) -> StreamResult<()> {
+            Ok(())
+        }
+
+        fn stop_streaming_loop(&mut self) -> StreamResult<()> {
+            Ok(())
+        }
+
+        fn is_loop_running(&self) -> bool {
+            true
+        }
+
+        fn pause_streaming_loop(&mut self) -> StreamResult<()> {
+            self.paused = true;
+            Ok(())
+        }
+
+        fn resume_streaming_loop(&mut self) -> StreamResult<()> {
+            self.paused = false;
+            Ok(())
+        }
+
+        fn is_loop_paused(&self) -> bool {
+            self.paused
+        }
+    }
+
+    /// A stream whose loop runs between `start_streaming_loop` and `stop_streaming_loop`.
+    #[derive(Default)]
+    struct LoopStream {
+        running: bool,
+    }
+
+    impl PayloadStream for LoopStream {
+        fn open(&mut self) -> StreamResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> StreamResult<()> {
+            Ok(())
+        }
+
+        fn start_streaming_loop(
+            &mut self,
+            _sender: PayloadSender,
+            _ctrl: &mut dyn DeviceControl,
+        ) -> StreamResult<()> {
+            self.running = true;
+            Ok(())
+        }
+
+        fn stop_streaming_loop(&mut self) -> StreamResult<()> {
+            self.running = false;
+            Ok(())
+        }
+
+        fn is_loop_running(&self) -> bool {
+            self.running
+        }
+    }
+
+    fn camera(nodes: &str, exposure_time: f64) -> Camera<Memory, NoStream> {
+        let xml = format!("{}{}{}", XML_HEADER, nodes, XML_FOOTER);
+        let mut memory = vec![0; 24];
+        memory[8..16].copy_from_slice(&30.0_f64.to_le_bytes());
+        memory[16..24].copy_from_slice(&exposure_time.to_le_bytes());
+
+        let info = CameraInfo {
+            vendor_name: "CameleonVendor".into(),
+            model_name: "CameleonModel".into(),
+            serial_number: "0".into(),
+        };
+        Camera::new(
+            Memory(memory, vec![]),
+            NoStream,
+            Some(DefaultGenApiCtxt::from_xml(&xml).unwrap()),
+            info,
+        )
+    }
+
+    #[test]
+    fn test_set_frame_rate() {
+        // Exposure time allows up to 100 fps.
+        let mut camera = camera(FRAME_RATE_NODES, 10000.0);
+        assert_eq!(camera.set_frame_rate(25.0).unwrap(), 25.0);
+        assert_eq!(camera.frame_rate().unwrap(), 25.0);
+        // `AcquisitionFrameRateEnable` is turned on.
+        assert_eq!(camera.ctrl.0[0], 1);
+
+        // Target is clamped to the maximum of `AcquisitionFrameRate`.
+        assert_eq!(camera.set_frame_rate(100.0).unwrap(), 60.0);
+        assert_eq!(camera.max_frame_rate().unwrap(), 60.0);
+        // Target is clamped to the minimum of `AcquisitionFrameRate`.
+        assert_eq!(camera.set_frame_rate(0.1).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_resulting_frame_rate() {
+        // Exposure time allows up to 20 fps.
+        let mut camera = camera(FRAME_RATE_NODES, 50000.0);
+        assert_eq!(camera.set_frame_rate(30.0).unwrap(), 20.0);
+        assert_eq!(camera.frame_rate().unwrap(), 20.0);
+    }
+
+    #[test]
+    fn test_pause_streaming() {
+        const ACQUISITION_NODES: &str = r#"
+            <Command Name="AcquisitionStart">
+                <pValue>AcquisitionStartReg</pValue>
+                <CommandValue>1</CommandValue>
+            </Command>
+
+            <IntReg Name="AcquisitionStartReg">
+              <Address>0x0</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Command Name="AcquisitionStop">
+                <pValue>AcquisitionStopReg</pValue>
+                <CommandValue>1</CommandValue>
+            </Command>
+
+            <IntReg Name="AcquisitionStopReg">
+              <Address>0x4</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+        "#;
+
+        let xml = format!("{}{}{}", XML_HEADER, ACQUISITION_NODES, XML_FOOTER);
+        let info = camera("", 0.0).info;
+        let mut camera = Camera::new(
+            Memory(vec![0; 8], vec![]),
+            PausableStream::default(),
+            Some(DefaultGenApiCtxt::from_xml(&xml).unwrap()),
+            info,
+        );
+
+        camera.pause_streaming().unwrap();
+        assert!(camera.strm.is_loop_paused());
+        assert_eq!(camera.ctrl.0[4], 1);
+        camera.resume_streaming().unwrap();
+        assert!(!camera.strm.is_loop_paused());
+        assert_eq!(camera.ctrl.0[0], 1);
+        // The stream interface is untouched.
+        assert!(camera.ctrl.1.is_empty());
+    }
+
+    #[test]
+    fn test_pause_streaming_without_acquisition_nodes() {
+        let mut camera = camera("", 0.0);
+        let mut camera = Camera::new(
+            Memory(vec![], vec![]),
+            PausableStream::default(),
+            camera.ctxt.take(),
+            camera.info,
+        );
+
+        camera.pause_streaming().unwrap();
+        // Pausing twice is no-op.
+        camera.pause_streaming().unwrap();
+        camera.resume_streaming().unwrap();
+        camera.resume_streaming().unwrap();
+        // The transfer parameters are not renegotiated.
+        assert_eq!(
+            camera.ctrl.1,
+            vec!["disable_streaming", "reenable_streaming"]
+        );
+    }
+
+    #[test]
+    fn test_missing_frame_rate_nodes() {
+        let mut camera = camera("", 10000.0);
+        match camera.set_frame_rate(30.0) {
+            Err(CameleonError::MissingCapability { missing }) => {
+                assert_eq!(missing, vec!["AcquisitionFrameRate"]);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert!(camera.frame_rate().is_err());
+    }
+
+    #[test]
+    fn test_streaming_whitelist() {
+        const LOCK_NODES: &str = r#"
+            <Integer Name="TLParamsLocked">
+                <pValue>TLParamsLockedReg</pValue>
+            </Integer>
+
+            <IntReg Name="TLParamsLockedReg">
+              <Address>0x0</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Command Name="AcquisitionStart">
+                <pValue>AcquisitionReg</pValue>
+                <CommandValue>1</CommandValue>
+            </Command>
+
+            <Command Name="AcquisitionStop">
+                <pValue>AcquisitionReg</pValue>
+                <CommandValue>0</CommandValue>
+            </Command>
+
+            <IntReg Name="AcquisitionReg">
+              <Address>0x4</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <FloatReg Name="ExposureTime">
+              <Address>0x8</Address>
+              <Length>8</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </FloatReg>
+
+            <Integer Name="Width">
+                <pIsLocked>TLParamsLocked</pIsLocked>
+                <pValue>WidthReg</pValue>
+            </Integer>
+
+            <IntReg Name="WidthReg">
+              <Address>0x10</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+        "#;
+
+        let xml = format!("{}{}{}", XML_HEADER, LOCK_NODES, XML_FOOTER);
+        let info = camera("", 0.0).info;
+        let mut camera = Camera::new(
+            Memory(vec![0; 20], vec![]),
+            LoopStream::default(),
+            Some(DefaultGenApiCtxt::from_xml(&xml).unwrap()),
+            info,
+        );
+
+        camera.set_integer("Width", 640).unwrap();
+        camera.start_streaming(1).unwrap();
+        assert_eq!(camera.ctrl.0[0], 1);
+
+        // `ExposureTime` stays writable while `TLParamsLocked` is set.
+        camera.set_float("ExposureTime", 100.0).unwrap();
+        assert_eq!(camera.ctrl.0[8..16], 100.0_f64.to_le_bytes());
+        match camera.set_integer("Width", 320) {
+            Err(CameleonError::LockedDuringAcquisition { feature }) => assert_eq!(feature, "Width"),
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        // Overrides take precedence over the detected whitelist.
+        let whitelist = camera.streaming_whitelist().unwrap();
+        whitelist.remove("ExposureTime");
+        whitelist.add("Width");
+        assert!(matches!(
+            camera.set_float("ExposureTime", 200.0),
+            Err(CameleonError::LockedDuringAcquisition { .. })
+        ));
+        camera.set_integer("Width", 320).unwrap();
+        assert_eq!(camera.ctrl.0[16..20], 320_u32.to_le_bytes());
+
+        // Every feature is writable after streaming stops.
+        camera.stop_streaming().unwrap();
+        camera.set_float("ExposureTime", 200.0).unwrap();
+        camera.set_integer("Width", 480).unwrap();
+        assert_eq!(camera.ctrl.0[16..20], 480_u32.to_le_bytes());
+    }
 }
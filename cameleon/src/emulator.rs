@@ -0,0 +1,1375 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides cameras over emulated U3V devices, which let applications be tested
+//! without cameras.
+//!
+//! Emulated devices are built by [`EmulatorBuilder`], possibly from a fixture, see
+//! [`cameleon_device::fixture`]. The emulator sends image frames whose size and pixel format are
+//! given by the fixture, see [`cameleon_device::fixture::StreamSettings`].
+//!
+//! Cameras over emulated devices implement [`SoakTarget`], so that a soak scenario can be run
+//! against the whole host stack.
+//!
+//! # Examples
+//!
+//! ```
+//! use cameleon::emulator::{self, EmulatorBuilder};
+//! use cameleon::DeviceControl;
+//!
+//! EmulatorBuilder::new().serial_number("EMU0001").unwrap().build();
+//! let mut camera = emulator::enumerate_cameras()
+//!     .unwrap()
+//!     .into_iter()
+//!     .find(|camera| camera.info().serial_number == "EMU0001")
+//!     .unwrap();
+//!
+//! camera.open().unwrap();
+//! let xml = camera.ctrl.genapi().unwrap();
+//! camera.close().unwrap();
+//! ```
+
+use std::{
+    convert::TryInto,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cameleon_device::{
+    emulator::{self, ControlChannel, ReceiveChannel},
+    fixture::FaultKind,
+    soak::{ControlOp, SoakSnapshot, SoakTarget},
+    u3v::{
+        protocol::{ack, cmd, stream as u3v_stream},
+        register_map::{abrm, manifest_entry, sbrm, sirm},
+        Error, LibUsbError,
+    },
+};
+use cameleon_impl::leak_check::{Resource, Tracked};
+
+use super::{
+    limits::Limits,
+    metrics::{self, MetricSink, MetricSource},
+    payload::{FrameId, ImageInfo, Payload, PayloadSender, PayloadStatus, PayloadType, PoolHandle},
+    CameleonResult, Camera, CameraInfo, ControlError, ControlResult, DeviceControl, PayloadStream,
+    StreamError, StreamResult,
+};
+
+pub use cameleon_device::emulator::{BuilderError, BuilderResult, EmulatorBuilder};
+
+/// Timeout of each transaction, an emulated device responds immediately unless a timeout fault
+/// is injected.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The maximum number of attempts of a transaction rejected with `GENCP_BUSY`.
+const BUSY_ATTEMPTS: u32 = 3;
+
+/// Maximum command and acknowledge packet length used until SBRM of the device is read.
+const INITIAL_MAXIMUM_PACKET_LENGTH: usize = 128;
+
+/// Length of prefix and CCD of a packet.
+const PACKET_HEADER_LENGTH: usize = 12;
+
+/// Length of the address field of `WriteMem` command.
+const WRITE_MEM_ADDRESS_LENGTH: usize = 8;
+
+/// Size of each payload transfer requested by [`enable_stream_channel`].
+const PAYLOAD_TRANSFER_SIZE: u32 = 64 * 1024;
+
+/// Timeout of receiving a leader, after which the streaming loop checks whether it's stopped.
+const LEADER_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Timeout of receiving the transfers following a leader.
+const TRANSFER_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Enumerates cameras over emulated devices.
+///
+/// # Examples
+///
+/// ```
+/// use cameleon::emulator;
+///
+/// let cameras = emulator::enumerate_cameras().unwrap();
+/// ```
+pub fn enumerate_cameras() -> CameleonResult<Vec<Camera<EmulatedControl, EmulatedStream>>> {
+    let devices = emulator::enumerate_devices().map_err(ControlError::from)?;
+
+    let mut cameras = Vec::with_capacity(devices.len());
+    for device in devices {
+        let info = CameraInfo {
+            vendor_name: device.device_info.vendor_name.clone(),
+            model_name: device.device_info.model_name.clone(),
+            serial_number: device.device_info.serial_number.clone(),
+        };
+        let strm = EmulatedStream::new(&device)?;
+        let ctrl = EmulatedControl::new(device)?;
+        cameras.push(Camera::new(ctrl, strm, None, info));
+    }
+
+    Ok(cameras)
+}
+
+/// Writes the transfer sizes of `SIRM` for the payload size required by the device, then enables
+/// the stream channel of the device.
+///
+/// The sizes required by the device are checked against [`Limits::max_allocation`] of `ctrl`.
+///
+/// [`EmulatedControl::enable_streaming`] calls this, and other [`DeviceControl`] of an emulated
+/// device can call this to let [`EmulatedStream`] receive payloads.
+pub fn enable_stream_channel<Ctrl: DeviceControl + ?Sized>(ctrl: &mut Ctrl) -> ControlResult<()> {
+    let sirm = sirm_address(ctrl)?;
+    // The exponent of the alignment is in the upper 8 bits of `SI Info`.
+    let alignment = 1_u32 << (read_u32(ctrl, sirm + sirm::SI_INFO.0)? >> 24);
+    let align = |size: u32| (size + alignment - 1) & !(alignment - 1);
+
+    let leader_size = read_u32(ctrl, sirm + sirm::REQUIRED_LEADER_SIZE.0)?;
+    let trailer_size = read_u32(ctrl, sirm + sirm::REQUIRED_TRAILER_SIZE.0)?;
+    let payload_size = read_u64(ctrl, sirm + sirm::REQUIRED_PAYLOAD_SIZE.0)?;
+    let limits = ctrl.limits();
+    limits.check_allocation(leader_size)?;
+    limits.check_allocation(trailer_size)?;
+    limits.check_allocation(payload_size)?;
+    let transfer_size = align(PAYLOAD_TRANSFER_SIZE);
+    let count: u32 = (payload_size / u64::from(transfer_size)).try_into()?;
+    let remainder = (payload_size % u64::from(transfer_size)) as u32;
+
+    write_u32(ctrl, sirm + sirm::MAXIMUM_LEADER_SIZE.0, align(leader_size))?;
+    write_u32(
+        ctrl,
+        sirm + sirm::MAXIMUM_TRAILER_SIZE.0,
+        align(trailer_size),
+    )?;
+    write_u32(ctrl, sirm + sirm::PAYLOAD_TRANSFER_SIZE.0, transfer_size)?;
+    write_u32(ctrl, sirm + sirm::PAYLOAD_TRANSFER_COUNT.0, count)?;
+    write_u32(
+        ctrl,
+        sirm + sirm::PAYLOAD_FINAL_TRANSFER1_SIZE.0,
+        align(remainder),
+    )?;
+    write_u32(ctrl, sirm + sirm::PAYLOAD_FINAL_TRANSFER2_SIZE.0, 0)?;
+    write_u32(ctrl, sirm + sirm::SI_CONTROL.0, 1)
+}
+
+/// Disables the stream channel of the device enabled by [`enable_stream_channel`].
+pub fn disable_stream_channel<Ctrl: DeviceControl + ?Sized>(ctrl: &mut Ctrl) -> ControlResult<()> {
+    let sirm = sirm_address(ctrl)?;
+    write_u32(ctrl, sirm + sirm::SI_CONTROL.0, 0)
+}
+
+fn sirm_address<Ctrl: DeviceControl + ?Sized>(ctrl: &mut Ctrl) -> ControlResult<u64> {
+    let sbrm = read_u64(ctrl, abrm::SBRM_ADDRESS.0)?;
+    read_u64(ctrl, sbrm + sbrm::SIRM_ADDRESS.0)
+}
+
+fn read_u32<Ctrl: DeviceControl + ?Sized>(ctrl: &mut Ctrl, address: u64) -> ControlResult<u32> {
+    let mut buf = [0; 4];
+    ctrl.read(address, &mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<Ctrl: DeviceControl + ?Sized>(ctrl: &mut Ctrl, address: u64) -> ControlResult<u64> {
+    let mut buf = [0; 8];
+    ctrl.read(address, &mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn write_u32<Ctrl: DeviceControl + ?Sized>(
+    ctrl: &mut Ctrl,
+    address: u64,
+    value: u32,
+) -> ControlResult<()> {
+    ctrl.write(address, &value.to_le_bytes())
+}
+
+/// Statistics of control transactions issued by [`EmulatedControl`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ControlStats {
+    /// The number of transactions completed successfully.
+    pub transactions: u64,
+    /// The number of transactions failed.
+    pub errors: u64,
+    /// The number of acknowledges whose request id doesn't match the command.
+    pub request_id_mismatches: u64,
+}
+
+/// Counters behind [`ControlStats`], which are also registered as metrics.
+#[derive(Default)]
+struct ControlCounters {
+    transactions: AtomicU64,
+    errors: AtomicU64,
+    request_id_mismatches: AtomicU64,
+}
+
+impl ControlCounters {
+    fn snapshot(&self) -> ControlStats {
+        ControlStats {
+            transactions: self.transactions.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            request_id_mismatches: self.request_id_mismatches.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl MetricSource for ControlCounters {
+    fn collect(&self, sink: &mut MetricSink<'_>) {
+        let stats = self.snapshot();
+        sink.counter("cameleon_control_transactions_total", stats.transactions);
+        sink.counter("cameleon_control_errors_total", stats.errors);
+        sink.counter(
+            "cameleon_control_request_id_mismatches_total",
+            stats.request_id_mismatches,
+        );
+    }
+}
+
+/// [`DeviceControl`] on the control channel of an emulated device.
+///
+/// The statistics of the control transactions are registered as metrics, see [`metrics`].
+pub struct EmulatedControl {
+    device: emulator::Device,
+    channel: ControlChannel,
+    request_ids: cmd::RequestIdGenerator,
+    /// Buffer for serializing/deserializing a packet.
+    buffer: Vec<u8>,
+    maximum_cmd_length: usize,
+    maximum_ack_length: usize,
+    stats: Arc<ControlCounters>,
+    /// Limits on the values claimed by the device.
+    limits: Limits,
+    /// Accounts the channel as opened.
+    tracked: Option<Tracked>,
+}
+
+impl EmulatedControl {
+    fn new(device: emulator::Device) -> ControlResult<Self> {
+        let channel = device.control_channel()?;
+        let stats = Arc::new(ControlCounters::default());
+        metrics::register(
+            &stats,
+            metrics::device_labels(&device.device_info.serial_number),
+        );
+        Ok(Self {
+            device,
+            channel,
+            request_ids: cmd::RequestIdGenerator::new(0),
+            buffer: Vec::new(),
+            maximum_cmd_length: INITIAL_MAXIMUM_PACKET_LENGTH,
+            maximum_ack_length: INITIAL_MAXIMUM_PACKET_LENGTH,
+            stats,
+            limits: Limits::default(),
+            tracked: None,
+        })
+    }
+
+    /// Makes the device inject `kind` to the next command, see
+    /// [`emulator::Device::inject_fault`].
+    pub fn inject_fault(&self, kind: FaultKind) -> ControlResult<()> {
+        Ok(self.device.inject_fault(kind)?)
+    }
+
+    /// Returns statistics of transactions issued so far.
+    #[must_use]
+    pub fn stats(&self) -> ControlStats {
+        self.stats.snapshot()
+    }
+
+    /// Reads `entries` in a single `ReadMemStacked` transaction.
+    pub fn read_stacked(&mut self, entries: &mut [(u64, &mut [u8])]) -> ControlResult<()> {
+        self.assert_open()?;
+
+        let scd = cmd::ReadMemStacked::new(
+            entries
+                .iter()
+                .map(|(address, buf)| Ok(cmd::ReadMem::new(*address, buf.len().try_into()?)))
+                .collect::<ControlResult<Vec<_>>>()?,
+        )?;
+        self.transact(scd, |ack| {
+            let mut data = ack.scd_as::<ack::ReadMemStacked>()?.data;
+            for (_, buf) in entries.iter_mut() {
+                if data.len() < buf.len() {
+                    return Err(ControlError::InvalidDevice(
+                        "`ReadMemStacked` acknowledge is shorter than the command".into(),
+                    ));
+                }
+                let (head, rest) = data.split_at(buf.len());
+                buf.copy_from_slice(head);
+                data = rest;
+            }
+            Ok(())
+        })
+    }
+
+    fn assert_open(&self) -> ControlResult<()> {
+        if self.is_opened() {
+            Ok(())
+        } else {
+            Err(ControlError::NotOpened)
+        }
+    }
+
+    /// Sends `scd` and receives its final ack, then returns the ack parsed by `f`, and counts the
+    /// transaction in the statistics.
+    fn transact<T, U>(
+        &mut self,
+        scd: T,
+        f: impl FnOnce(&ack::AckPacket) -> ControlResult<U>,
+    ) -> ControlResult<U>
+    where
+        T: cmd::CommandScd + Clone,
+    {
+        let result = self.transact_with_retry(scd, f);
+        match &result {
+            Ok(_) => count(&self.stats.transactions),
+            Err(ControlError::RequestIdMismatch { .. }) => {
+                count(&self.stats.request_id_mismatches);
+                count(&self.stats.errors);
+            }
+            Err(_) => count(&self.stats.errors),
+        }
+        result
+    }
+
+    fn transact_with_retry<T, U>(
+        &mut self,
+        scd: T,
+        f: impl FnOnce(&ack::AckPacket) -> ControlResult<U>,
+    ) -> ControlResult<U>
+    where
+        T: cmd::CommandScd + Clone,
+    {
+        for _ in 0..BUSY_ATTEMPTS {
+            if let Some(ack_len) = self.transact_once(scd.clone())? {
+                return f(&ack::AckPacket::parse(&self.buffer[..ack_len])?);
+            }
+        }
+        Err(ControlError::RetriesExhausted {
+            attempts: BUSY_ATTEMPTS,
+            source: Box::new(ControlError::Io(anyhow::Error::msg("device is busy"))),
+        })
+    }
+
+    /// Sends `scd` and receives its final ack into the buffer, then returns the length of the ack,
+    /// or `None` if the device is busy.
+    fn transact_once<T>(&mut self, scd: T) -> ControlResult<Option<usize>>
+    where
+        T: cmd::CommandScd,
+    {
+        let cmd = cmd::CommandPacket::new(scd, self.request_ids.next_id());
+        let len = std::cmp::max(cmd.cmd_len(), cmd.maximum_ack_len());
+        if self.buffer.len() < len {
+            self.buffer.resize(len, 0);
+        }
+        cmd.serialize(self.buffer.as_mut_slice())?;
+        let result = self
+            .channel
+            .send(&self.buffer[..cmd.cmd_len()], TRANSACTION_TIMEOUT);
+        result.map_err(|err| self.recover(err))?;
+
+        let mut timeout = TRANSACTION_TIMEOUT;
+        loop {
+            let result = self.channel.recv(&mut self.buffer, timeout);
+            let recv_len = result.map_err(|err| self.recover(err))?;
+            let ack = ack::AckPacket::parse(&self.buffer[..recv_len])?;
+            if ack.request_id() != cmd.request_id() {
+                return Err(ControlError::RequestIdMismatch {
+                    expected: cmd.request_id(),
+                    actual: ack.request_id(),
+                });
+            }
+            let status = *ack.status();
+            if status.kind() == &ack::StatusKind::GenCp(ack::GenCpStatus::Busy) {
+                return Ok(None);
+            }
+            if !status.is_success() {
+                return Err(ControlError::Io(anyhow::Error::msg(format!(
+                    "invalid status: {:?}",
+                    status.kind()
+                ))));
+            }
+
+            // Wait for the final ack with the same request id.
+            if ack.scd_kind() == ack::ScdKind::Pending {
+                let pending: ack::Pending = ack.scd_as()?;
+                timeout = pending.timeout;
+                continue;
+            }
+
+            return Ok(Some(recv_len));
+        }
+    }
+
+    /// Clears the halt of the control endpoint if `err` is caused by it, so that following
+    /// transactions succeed.
+    fn recover(&mut self, err: Error) -> ControlError {
+        if matches!(err, Error::LibUsb(LibUsbError::Pipe)) {
+            self.channel.clear_halt().ok();
+        }
+        err.into()
+    }
+}
+
+impl DeviceControl for EmulatedControl {
+    fn open(&mut self) -> ControlResult<()> {
+        if self.is_opened() {
+            return Ok(());
+        }
+
+        self.channel.open()?;
+        self.maximum_cmd_length = INITIAL_MAXIMUM_PACKET_LENGTH;
+        self.maximum_ack_length = INITIAL_MAXIMUM_PACKET_LENGTH;
+
+        let result = (|| {
+            let sbrm = read_u64(self, abrm::SBRM_ADDRESS.0)?;
+            let cmd_length = read_u32(self, sbrm + sbrm::MAXIMUM_COMMAND_TRANSFER_LENGTH.0)?;
+            let ack_length = read_u32(self, sbrm + sbrm::MAXIMUM_ACKNOWLEDGE_TRANSFER_LENGTH.0)?;
+            self.limits.check_allocation(cmd_length)?;
+            self.limits.check_allocation(ack_length)?;
+            Ok((cmd_length.try_into()?, ack_length.try_into()?))
+        })();
+        match result {
+            Ok((cmd_length, ack_length)) => {
+                self.maximum_cmd_length = cmd_length;
+                self.maximum_ack_length = ack_length;
+                self.tracked = Some(Tracked::new(Resource::Channel));
+                Ok(())
+            }
+            Err(err) => {
+                self.channel.close().ok();
+                Err(err)
+            }
+        }
+    }
+
+    fn close(&mut self) -> ControlResult<()> {
+        self.tracked = None;
+        Ok(self.channel.close()?)
+    }
+
+    fn is_opened(&self) -> bool {
+        self.channel.is_opened()
+    }
+
+    fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+        self.assert_open()?;
+
+        let maximum_read_len = cmd::ReadMem::maximum_read_length(self.maximum_ack_length) as usize;
+        for (i, chunk) in buf.chunks_mut(maximum_read_len).enumerate() {
+            let offset = i * maximum_read_len;
+            let scd = cmd::ReadMem::new(address + offset as u64, chunk.len().try_into()?);
+            let read = self.transact(scd, |ack| {
+                let data = ack.scd_as::<ack::ReadMem>()?.data;
+                if data.len() == chunk.len() {
+                    chunk.copy_from_slice(data);
+                }
+                Ok(data.len())
+            })?;
+            if read != chunk.len() {
+                return Err(ControlError::PartialChunkRead {
+                    offset,
+                    requested: chunk.len(),
+                    read,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+        self.assert_open()?;
+
+        let maximum_data_len = std::cmp::min(
+            self.maximum_cmd_length - PACKET_HEADER_LENGTH - WRITE_MEM_ADDRESS_LENGTH,
+            u16::MAX as usize - WRITE_MEM_ADDRESS_LENGTH,
+        );
+        for (i, chunk) in data.chunks(maximum_data_len).enumerate() {
+            let offset = i * maximum_data_len;
+            let scd = cmd::WriteMem::new(address + offset as u64, chunk)?;
+            let written =
+                self.transact(
+                    scd,
+                    |ack| Ok(ack.scd_as::<ack::WriteMem>()?.length as usize),
+                )?;
+            if written != chunk.len() {
+                return Err(ControlError::PartialChunkWrite {
+                    offset,
+                    requested: chunk.len(),
+                    written,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the uncompressed `GenApi` xml pointed by the first entry of the manifest table.
+    fn genapi(&mut self) -> ControlResult<String> {
+        let manifest_table = read_u64(self, abrm::MANIFEST_TABLE_ADDRESS.0)?;
+        // The first entry follows the entry count.
+        let entry = manifest_table + 8;
+        let address = read_u64(self, entry + manifest_entry::REGISTER_ADDRESS.0)?;
+        let size = read_u64(self, entry + manifest_entry::FILE_SIZE.0)?;
+        self.limits.check_xml_size(size)?;
+
+        let mut xml = vec![0; size.try_into()?];
+        self.read(address, &mut xml)?;
+        String::from_utf8(xml).map_err(ControlError::XmlNotUtf8)
+    }
+
+    fn enable_streaming(&mut self) -> ControlResult<()> {
+        enable_stream_channel(self)
+    }
+
+    fn disable_streaming(&mut self) -> ControlResult<()> {
+        disable_stream_channel(self)
+    }
+
+    fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+}
+
+/// Counters of the payloads received by the streaming loop, which are registered as metrics.
+#[derive(Debug, Default)]
+struct StreamCounters {
+    received_payloads: AtomicU64,
+    /// Payloads dropped because the receiver was full.
+    dropped_payloads: AtomicU64,
+}
+
+impl MetricSource for StreamCounters {
+    fn collect(&self, sink: &mut MetricSink<'_>) {
+        sink.counter(
+            "cameleon_stream_received_payloads_total",
+            self.received_payloads.load(Ordering::Relaxed),
+        );
+        sink.counter(
+            "cameleon_stream_dropped_payloads_total",
+            self.dropped_payloads.load(Ordering::Relaxed),
+        );
+    }
+}
+
+fn count(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// [`PayloadStream`] on the stream channel of an emulated device.
+///
+/// The streaming loop runs on its own thread and receives frames sent by the emulator while the
+/// stream channel is enabled, see [`enable_stream_channel`]. The emulator sends image payloads, or
+/// GenDC payloads if the fixture of the device says so. Unlike the USB transfers of a U3V camera,
+/// each transfer copies the bytes queued by the emulator into the buffer of the payload.
+///
+/// The thread of the streaming loop is named `cameleon-emulator-<serial number>`. The numbers of
+/// received and dropped payloads are registered as metrics, see [`metrics`].
+#[derive(Debug)]
+pub struct EmulatedStream {
+    channel: Arc<Mutex<ReceiveChannel>>,
+    device_id: u64,
+    /// Name of the thread running the streaming loop.
+    thread_name: String,
+    /// Incremented each time the streaming loop is started.
+    generation: u32,
+    counters: Arc<StreamCounters>,
+    streaming_loop: Option<LoopHandle>,
+    /// Accounts the channel as opened.
+    tracked: Option<Tracked>,
+}
+
+#[derive(Debug)]
+struct LoopHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl EmulatedStream {
+    /// Creates the stream on the stream channel of `device`.
+    pub fn new(device: &emulator::Device) -> StreamResult<Self> {
+        let channel = device.stream_channel()?.ok_or_else(|| {
+            StreamError::NotSupported("emulated device without stream channel".into())
+        })?;
+        let counters = Arc::new(StreamCounters::default());
+        metrics::register(
+            &counters,
+            metrics::stream_labels(&device.device_info.serial_number, 0),
+        );
+        Ok(Self {
+            channel: Arc::new(Mutex::new(channel)),
+            device_id: FrameId::device_id_from_guid(&device.device_info.guid.to_string()),
+            thread_name: format!("cameleon-emulator-{}", device.device_info.serial_number),
+            generation: 0,
+            counters,
+            streaming_loop: None,
+            tracked: None,
+        })
+    }
+
+    /// Returns the number of payloads dropped because the receiver was full.
+    #[must_use]
+    pub fn dropped_payloads(&self) -> u64 {
+        self.counters.dropped_payloads.load(Ordering::Relaxed)
+    }
+
+    fn lock_channel(&self) -> StreamResult<std::sync::MutexGuard<'_, ReceiveChannel>> {
+        self.channel
+            .lock()
+            .map_err(|_| StreamError::Poisoned("stream channel is poisoned".into()))
+    }
+}
+
+impl PayloadStream for EmulatedStream {
+    fn open(&mut self) -> StreamResult<()> {
+        self.lock_channel()?.open()?;
+        if self.tracked.is_none() {
+            self.tracked = Some(Tracked::new(Resource::Channel));
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> StreamResult<()> {
+        self.stop_streaming_loop()?;
+        self.tracked = None;
+        Ok(self.lock_channel()?.close()?)
+    }
+
+    fn start_streaming_loop(
+        &mut self,
+        sender: PayloadSender,
+        ctrl: &mut dyn DeviceControl,
+    ) -> StreamResult<()> {
+        if self.is_loop_running() {
+            return Err(StreamError::InStreaming);
+        }
+        if !self.lock_channel()?.is_opened() {
+            return Err(StreamError::Io(anyhow::Error::msg(
+                "stream channel is not opened",
+            )));
+        }
+
+        let transfers = TransferSizes::read(ctrl).map_err(|e| {
+            StreamError::Io(anyhow::Error::msg(format!(
+                "can't read SIRM of the device: {}",
+                e
+            )))
+        })?;
+        self.generation = self.generation.wrapping_add(1);
+        let stop = Arc::new(AtomicBool::new(false));
+        let streaming_loop = StreamingLoop {
+            channel: self.channel.clone(),
+            transfers,
+            sender,
+            device_id: self.device_id,
+            generation: self.generation,
+            stop: stop.clone(),
+            counters: self.counters.clone(),
+        };
+        let thread = thread::Builder::new()
+            .name(self.thread_name.clone())
+            .spawn(move || streaming_loop.run())
+            .map_err(|e| StreamError::Io(e.into()))?;
+        self.streaming_loop = Some(LoopHandle { stop, thread });
+        Ok(())
+    }
+
+    fn stop_streaming_loop(&mut self) -> StreamResult<()> {
+        if let Some(LoopHandle { stop, thread }) = self.streaming_loop.take() {
+            stop.store(true, Ordering::Relaxed);
+            thread
+                .join()
+                .map_err(|_| StreamError::Poisoned("streaming loop panicked".into()))?;
+        }
+        Ok(())
+    }
+
+    fn is_loop_running(&self) -> bool {
+        self.streaming_loop
+            .as_ref()
+            .is_some_and(|handle| !handle.thread.is_finished())
+    }
+}
+
+impl Drop for EmulatedStream {
+    fn drop(&mut self) {
+        self.stop_streaming_loop().ok();
+    }
+}
+
+/// Sizes of the transfers of a frame, which are written to `SIRM` before streaming.
+#[derive(Debug, Clone)]
+struct TransferSizes {
+    maximum_leader_size: usize,
+    maximum_trailer_size: usize,
+    payload_size: usize,
+    /// Sizes of the payload transfers in the order they are sent.
+    payload_transfers: Vec<usize>,
+}
+
+impl TransferSizes {
+    fn read(ctrl: &mut dyn DeviceControl) -> ControlResult<Self> {
+        let sirm = sirm_address(ctrl)?;
+        let mut read_size = |offset: u64| -> ControlResult<usize> {
+            Ok(read_u32(ctrl, sirm + offset)?.try_into()?)
+        };
+        let maximum_leader_size = read_size(sirm::MAXIMUM_LEADER_SIZE.0)?;
+        let maximum_trailer_size = read_size(sirm::MAXIMUM_TRAILER_SIZE.0)?;
+        let transfer_size = read_size(sirm::PAYLOAD_TRANSFER_SIZE.0)?;
+        let transfer_count = read_size(sirm::PAYLOAD_TRANSFER_COUNT.0)?;
+        let final_transfer1_size = read_size(sirm::PAYLOAD_FINAL_TRANSFER1_SIZE.0)?;
+        let final_transfer2_size = read_size(sirm::PAYLOAD_FINAL_TRANSFER2_SIZE.0)?;
+
+        let payload_transfers: Vec<usize> = std::iter::repeat_n(transfer_size, transfer_count)
+            .chain([final_transfer1_size, final_transfer2_size])
+            .filter(|size| *size != 0)
+            .collect();
+        Ok(Self {
+            maximum_leader_size,
+            maximum_trailer_size,
+            payload_size: payload_transfers.iter().sum(),
+            payload_transfers,
+        })
+    }
+}
+
+struct StreamingLoop {
+    channel: Arc<Mutex<ReceiveChannel>>,
+    transfers: TransferSizes,
+    sender: PayloadSender,
+    device_id: u64,
+    generation: u32,
+    stop: Arc<AtomicBool>,
+    counters: Arc<StreamCounters>,
+}
+
+impl StreamingLoop {
+    fn run(self) {
+        let channel = match self.channel.lock() {
+            Ok(channel) => channel,
+            Err(_) => return,
+        };
+        let mut leader = vec![0; self.transfers.maximum_leader_size];
+
+        while !self.stop.load(Ordering::Relaxed) && !self.sender.is_closed() {
+            let leader_len = match channel.recv(&mut leader, LEADER_TIMEOUT) {
+                Ok(len) => len,
+                Err(Error::LibUsb(LibUsbError::Timeout)) => continue,
+                Err(e) => {
+                    let err = StreamError::from(e);
+                    let is_disconnected = matches!(err, StreamError::Disconnected);
+                    self.sender.try_send(Err(err)).ok();
+                    if is_disconnected {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let payload = self.recv_payload(&channel, &leader[..leader_len]);
+            let is_payload = payload.is_ok();
+            if is_payload {
+                count(&self.counters.received_payloads);
+            }
+            if self.sender.try_send(payload).is_err() && is_payload {
+                count(&self.counters.dropped_payloads);
+            }
+        }
+    }
+
+    /// Receives the payload transfers and the trailer following `leader`.
+    fn recv_payload(&self, channel: &ReceiveChannel, leader: &[u8]) -> StreamResult<Payload> {
+        let leader = u3v_stream::Leader::parse(leader)?;
+        let (payload_type, image_leader, timestamp) = match leader.payload_type() {
+            u3v_stream::PayloadType::Image => {
+                let image_leader: u3v_stream::ImageLeader = leader.specific_leader_as()?;
+                let timestamp = image_leader.timestamp();
+                (PayloadType::Image, Some(image_leader), timestamp)
+            }
+            u3v_stream::PayloadType::GenDc => {
+                let gendc_leader: u3v_stream::GenDcLeader = leader.specific_leader_as()?;
+                (PayloadType::GenDc, None, gendc_leader.timestamp())
+            }
+            other => {
+                return Err(StreamError::InvalidPayload(
+                    format!("unexpected payload type: {:?}", other).into(),
+                ))
+            }
+        };
+
+        let mut payload = vec![0; self.transfers.payload_size];
+        let mut len = 0;
+        for size in &self.transfers.payload_transfers {
+            let end = (len + size).min(payload.len());
+            len += channel.recv(&mut payload[len..end], TRANSFER_TIMEOUT)?;
+        }
+
+        let mut trailer = vec![0; self.transfers.maximum_trailer_size];
+        let trailer_len = channel.recv(&mut trailer, TRANSFER_TIMEOUT)?;
+        let trailer = u3v_stream::Trailer::parse(&trailer[..trailer_len])?;
+        if trailer.block_id() != leader.block_id() {
+            return Err(StreamError::InvalidPayload(
+                format!(
+                    "block id of the trailer {} doesn't match the leader {}",
+                    trailer.block_id(),
+                    leader.block_id()
+                )
+                .into(),
+            ));
+        }
+        let valid_payload_size: usize = trailer
+            .valid_payload_size()
+            .try_into()
+            .unwrap_or(usize::MAX);
+        let valid_payload_size = valid_payload_size.min(len);
+        let status = match trailer.payload_status() {
+            u3v_stream::PayloadStatus::Success => PayloadStatus::Success,
+            u3v_stream::PayloadStatus::DataDiscarded => PayloadStatus::DataDiscarded,
+            u3v_stream::PayloadStatus::DataOverrun => PayloadStatus::DataOverrun,
+        };
+        let image_info = match image_leader {
+            Some(image_leader) => {
+                let image_trailer: u3v_stream::ImageTrailer = trailer.specific_trailer_as()?;
+                Some(ImageInfo {
+                    width: image_leader.width() as usize,
+                    height: image_trailer.actual_height() as usize,
+                    x_offset: image_leader.x_offset() as usize,
+                    y_offset: image_leader.y_offset() as usize,
+                    pixel_format: image_leader.pixel_format(),
+                    image_size: valid_payload_size,
+                    x_padding: image_leader.x_padding() as usize,
+                })
+            }
+            None => None,
+        };
+        let id = leader.block_id();
+        Ok(Payload {
+            id,
+            frame_id: FrameId::new(self.device_id, 0, self.generation, id),
+            payload_type,
+            chunk_layout_id: None,
+            image_info,
+            payload,
+            provided: None,
+            valid_payload_size,
+            timestamp,
+            incomplete_info: None,
+            status,
+            pool: PoolHandle::default(),
+            tracked: Tracked::new(Resource::PoolBuffer),
+            decoders: None,
+        })
+    }
+}
+
+/// Runs soak scenarios against the host stack over the emulated device.
+///
+/// Control transactions are issued on [`Camera::ctrl`], and faults are injected by the emulator
+/// with [`EmulatedControl::inject_fault`]. The emulator sends frames at its own rate rather than
+/// at the rate of the scenario, and doesn't send events yet, so scenarios should be run without
+/// `[stream]` and `[events]`.
+///
+/// The camera must be opened before the run.
+impl<Ctxt> SoakTarget for Camera<EmulatedControl, EmulatedStream, Ctxt> {
+    fn control(&mut self, op: ControlOp, fault: Option<FaultKind>) {
+        if let Some(kind) = fault {
+            if self.ctrl.inject_fault(kind).is_err() {
+                count(&self.ctrl.stats.errors);
+                return;
+            }
+        }
+
+        let (serial_number, serial_number_len) = abrm::SERIAL_NUMBER;
+        let (user_defined_name, user_defined_name_len) = abrm::USER_DEFINED_NAME;
+        // Errors are counted in the statistics.
+        match op {
+            ControlOp::Read => {
+                let mut buf = vec![0; serial_number_len.into()];
+                self.ctrl.read(serial_number, &mut buf).ok();
+            }
+            ControlOp::Write => {
+                self.ctrl.write(user_defined_name, b"soak\0").ok();
+            }
+            ControlOp::Stacked => {
+                let mut serial = vec![0; serial_number_len.into()];
+                let mut name = vec![0; user_defined_name_len.into()];
+                self.ctrl
+                    .read_stacked(&mut [
+                        (serial_number, &mut serial),
+                        (user_defined_name, &mut name),
+                    ])
+                    .ok();
+            }
+        }
+    }
+
+    fn frame(&mut self) {}
+
+    fn event(&mut self) {}
+
+    fn snapshot(&mut self) -> SoakSnapshot {
+        let stats = self.ctrl.stats();
+        SoakSnapshot {
+            control_transactions: stats.transactions,
+            control_errors: stats.errors,
+            request_id_mismatches: stats.request_id_mismatches,
+            ..SoakSnapshot::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cameleon_device::soak::{SoakAction, SoakRunner, SoakScenario};
+    use cameleon_impl::leak_check;
+
+    use super::*;
+    use crate::genapi::DefaultGenApiCtxt;
+
+    fn camera(serial_number: &str) -> Camera<EmulatedControl, EmulatedStream, DefaultGenApiCtxt> {
+        EmulatorBuilder::new()
+            .serial_number(serial_number)
+            .unwrap()
+            .build();
+        let mut camera = enumerate_cameras()
+            .unwrap()
+            .into_iter()
+            .find(|camera| camera.info().serial_number == serial_number)
+            .unwrap();
+        camera.open().unwrap();
+        camera
+    }
+
+    /// Panics if any resource created by the test or by the streaming loop of the device is still
+    /// alive.
+    #[track_caller]
+    fn assert_clean(serial_number: &str) {
+        leak_check::assert_clean_on(&[&format!("cameleon-emulator-{}", serial_number)]);
+    }
+
+    #[test]
+    fn test_control() {
+        let mut camera = camera("EMUCTRL1");
+        let (address, len) = abrm::USER_DEFINED_NAME;
+
+        camera.ctrl.write(address, b"camera\0").unwrap();
+        let mut buf = vec![0; len.into()];
+        camera.ctrl.read(address, &mut buf).unwrap();
+        assert!(buf.starts_with(b"camera\0"));
+
+        let xml = camera.ctrl.genapi().unwrap();
+        assert!(xml.contains("<RegisterDescription"));
+
+        // The transaction is retried.
+        camera.ctrl.inject_fault(FaultKind::Busy).unwrap();
+        camera.ctrl.read(address, &mut buf).unwrap();
+
+        // The halt is cleared by the failed transaction.
+        camera.ctrl.inject_fault(FaultKind::Stall).unwrap();
+        assert!(camera.ctrl.read(address, &mut buf).is_err());
+        camera.ctrl.read(address, &mut buf).unwrap();
+
+        camera.close().unwrap();
+        assert_clean("EMUCTRL1");
+    }
+
+    #[test]
+    fn test_streaming() {
+        let mut camera = camera("EMUSTRM1");
+        camera.load_context().unwrap();
+        let payload_rx = camera.start_streaming(4).unwrap();
+
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let payload = async_std::task::block_on(payload_rx.recv()).unwrap();
+            let image_info = payload.image_info().unwrap();
+            assert_eq!((image_info.width, image_info.height), (640, 480));
+            assert_eq!(image_info.pixel_format, crate::payload::PixelFormat::Mono8);
+            let image = payload.image().unwrap();
+            assert_eq!(image.len(), 640 * 480);
+            // The emulator fills the `i`th byte with `block_id + i`.
+            let first = payload.id() as u8;
+            assert!(image
+                .iter()
+                .enumerate()
+                .all(|(i, byte)| *byte == first.wrapping_add(i as u8)));
+            ids.push(payload.id());
+            payload_rx.send_back(payload);
+        }
+        assert!(ids.windows(2).all(|ids| ids[0] < ids[1]));
+
+        camera.stop_streaming().unwrap();
+        assert!(!camera.strm.is_loop_running());
+        camera.close().unwrap();
+        drop(payload_rx);
+        assert_clean("EMUSTRM1");
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async_streaming() {
+        use futures::StreamExt;
+
+        let mut camera = camera("EMUASYN1");
+        camera.load_context().unwrap();
+        let mut payload_rx = camera.start_streaming(4).unwrap();
+
+        let ids = async_std::task::block_on(async {
+            let mut ids = Vec::new();
+            while ids.len() < 3 {
+                let payload = payload_rx.next().await.unwrap().unwrap();
+                let image_info = payload.image_info().unwrap();
+                assert_eq!((image_info.width, image_info.height), (640, 480));
+                let first = payload.id() as u8;
+                assert!(payload
+                    .image()
+                    .unwrap()
+                    .iter()
+                    .enumerate()
+                    .all(|(i, byte)| *byte == first.wrapping_add(i as u8)));
+                ids.push(payload.id());
+                payload_rx.send_back(payload);
+            }
+            ids
+        });
+        assert!(ids.windows(2).all(|ids| ids[0] < ids[1]));
+
+        // The stream ends once the payloads sent before the stop are drained.
+        camera.stop_streaming().unwrap();
+        let rest = async_std::task::block_on(payload_rx.by_ref().collect::<Vec<_>>());
+        assert!(rest.len() <= 4);
+
+        camera.close().unwrap();
+        drop(payload_rx);
+        drop(rest);
+        assert_clean("EMUASYN1");
+    }
+
+    #[test]
+    fn test_metrics() {
+        use crate::metrics::{MetricValue, DEVICE_SERIAL_LABEL, STREAM_INDEX_LABEL};
+
+        fn counters(serial_number: &str) -> Vec<(String, Option<String>, u64)> {
+            crate::metrics::snapshot()
+                .into_iter()
+                .filter(|metric| metric.label(DEVICE_SERIAL_LABEL) == Some(serial_number))
+                .filter_map(|metric| match metric.value {
+                    MetricValue::Counter(value) => Some((
+                        metric.name.clone(),
+                        metric.label(STREAM_INDEX_LABEL).map(ToString::to_string),
+                        value,
+                    )),
+                    _ => None,
+                })
+                .collect()
+        }
+        fn counter(counters: &[(String, Option<String>, u64)], name: &str) -> u64 {
+            counters.iter().find(|(n, ..)| n == name).unwrap().2
+        }
+
+        let mut camera = camera("EMUMTRC1");
+        camera.load_context().unwrap();
+        let payload_rx = camera.start_streaming(4).unwrap();
+        for _ in 0..3 {
+            let payload = async_std::task::block_on(payload_rx.recv()).unwrap();
+            payload_rx.send_back(payload);
+        }
+        camera.stop_streaming().unwrap();
+
+        let metrics = counters("EMUMTRC1");
+        assert!(counter(&metrics, "cameleon_control_transactions_total") > 0);
+        assert_eq!(counter(&metrics, "cameleon_control_errors_total"), 0);
+        assert_eq!(
+            counter(&metrics, "cameleon_control_request_id_mismatches_total"),
+            0
+        );
+        assert!(counter(&metrics, "cameleon_stream_received_payloads_total") >= 3);
+        assert_eq!(
+            counter(&metrics, "cameleon_stream_dropped_payloads_total"),
+            camera.strm.dropped_payloads()
+        );
+        assert!(metrics
+            .iter()
+            .filter(|(name, ..)| name.starts_with("cameleon_stream_"))
+            .all(|(_, index, _)| index.as_deref() == Some("0")));
+
+        camera.close().unwrap();
+        drop(payload_rx);
+        drop(camera);
+        assert!(counters("EMUMTRC1").is_empty());
+    }
+
+    #[cfg(feature = "leak-check")]
+    #[test]
+    fn test_leak_check() {
+        let mut camera = camera("EMULEAK1");
+        camera.load_context().unwrap();
+        let payload_rx = camera.start_streaming(4).unwrap();
+
+        // The payload is intentionally kept alive after the camera is closed.
+        let payload = async_std::task::block_on(payload_rx.recv()).unwrap();
+        camera.close().unwrap();
+        drop(payload_rx);
+        let err = std::panic::catch_unwind(|| assert_clean("EMULEAK1")).unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.starts_with("1 resources are leaked:"), "{}", msg);
+        assert!(msg.contains(&format!("pool buffer created at {}", file!())));
+        assert!(msg.contains("on thread `cameleon-emulator-EMULEAK1`"));
+
+        drop(payload);
+        assert_clean("EMULEAK1");
+    }
+
+    #[test]
+    fn test_gendc_streaming() {
+        use cameleon_device::fixture::{Fixture, StreamPayload, StreamSettings};
+
+        use crate::payload::{GenDcPartKind, PixelFormat};
+
+        let fixture = Fixture {
+            stream: Some(StreamSettings {
+                payload: StreamPayload::GenDc,
+                ..StreamSettings::default()
+            }),
+            ..Fixture::default()
+        };
+        EmulatorBuilder::with_fixture(fixture)
+            .unwrap()
+            .serial_number("EMUGNDC1")
+            .unwrap()
+            .build();
+        let mut camera = enumerate_cameras()
+            .unwrap()
+            .into_iter()
+            .find(|camera| camera.info().serial_number == "EMUGNDC1")
+            .unwrap();
+        camera.open().unwrap();
+        camera.load_context().unwrap();
+        let payload_rx = camera.start_streaming(4).unwrap();
+
+        let payload = async_std::task::block_on(payload_rx.recv()).unwrap();
+        assert_eq!(payload.payload_type(), PayloadType::GenDc);
+        assert!(payload.image_info().is_none());
+
+        let container = payload.gendc().unwrap();
+        assert_eq!(container.id, payload.id());
+        let parts: Vec<_> = container.parts().map(|(_, part)| part).collect();
+        assert_eq!(parts.len(), 2);
+
+        let image = parts[0];
+        assert_eq!(
+            image.kind,
+            GenDcPartKind::Image {
+                width: 640,
+                height: 480,
+                x_padding: 0,
+                y_padding: 0
+            }
+        );
+        assert_eq!(image.pixel_format(), Some(PixelFormat::Mono8));
+        // The emulator fills the `i`th byte of the image with `block_id + i`.
+        let first = payload.id() as u8;
+        assert!(container
+            .part_data(image)
+            .unwrap()
+            .iter()
+            .enumerate()
+            .all(|(i, byte)| *byte == first.wrapping_add(i as u8)));
+
+        // The metadata holds the block ID and the timestamp.
+        let metadata = parts[1];
+        assert_eq!(metadata.kind, GenDcPartKind::Metadata);
+        let metadata = container.part_data(metadata).unwrap();
+        assert_eq!(metadata[..8], payload.id().to_le_bytes());
+        assert_eq!(metadata.len(), 16);
+        payload_rx.send_back(payload);
+
+        camera.stop_streaming().unwrap();
+        camera.close().unwrap();
+        drop(payload_rx);
+        assert_clean("EMUGNDC1");
+    }
+
+    #[test]
+    fn test_limits() {
+        use crate::{
+            limits::{Limit, Limits},
+            CameleonError,
+        };
+
+        fn assert_exceeded<T: std::fmt::Debug>(
+            result: CameleonResult<T>,
+            expected: Limit,
+            expected_claimed: u64,
+        ) {
+            match result {
+                Err(CameleonError::ControlError(ControlError::LimitExceeded {
+                    limit,
+                    claimed,
+                    ..
+                })) => {
+                    assert_eq!(limit, expected);
+                    assert_eq!(claimed, expected_claimed);
+                }
+                res => panic!("limit is not enforced: {:?}", res),
+            }
+        }
+
+        EmulatorBuilder::new()
+            .serial_number("EMULIMT1")
+            .unwrap()
+            .build();
+        let mut camera = enumerate_cameras()
+            .unwrap()
+            .into_iter()
+            .find(|camera| camera.info().serial_number == "EMULIMT1")
+            .unwrap();
+
+        // The maximum command transfer length of the device is 1024 bytes.
+        let limits = Limits {
+            max_allocation: 1023,
+            ..Limits::default()
+        };
+        camera.set_limits(limits);
+        assert_eq!(camera.limits(), limits);
+        assert_exceeded(camera.open(), Limit::Allocation, 1024);
+        assert!(!camera.ctrl.is_opened());
+
+        camera.set_limits(Limits::default());
+        camera.open().unwrap();
+        let xml_size = camera.load_context().unwrap().len();
+
+        // The xml is refused before it's read.
+        camera.set_limits(Limits {
+            max_xml_size: xml_size - 1,
+            ..Limits::default()
+        });
+        let transactions = camera.ctrl.stats().transactions;
+        assert_exceeded(camera.load_context(), Limit::XmlSize, xml_size as u64);
+        assert!(camera.ctrl.stats().transactions - transactions < 8);
+
+        // The payload size required by the device is checked before streaming is started.
+        let payload_size = 640 * 480;
+        camera.set_limits(Limits {
+            max_allocation: payload_size - 1,
+            ..Limits::default()
+        });
+        assert_exceeded(
+            camera.start_streaming(4),
+            Limit::Allocation,
+            payload_size as u64,
+        );
+        assert!(!camera.strm.is_loop_running());
+
+        camera.set_limits(Limits::default());
+        camera.close().unwrap();
+        assert_clean("EMULIMT1");
+    }
+
+    #[test]
+    fn test_transaction_rollback() {
+        use cameleon_genapi::GenApiError;
+
+        use crate::genapi::TransactionError;
+
+        let mut camera = camera("EMUTRAN1");
+        camera.load_context().unwrap();
+        let (address, len) = abrm::USER_DEFINED_NAME;
+        camera.ctrl.write(address, b"before\0").unwrap();
+
+        let mut params_ctxt = camera.params_ctxt().unwrap();
+        let acquisition_start = params_ctxt.node("AcquisitionStart").unwrap();
+        let res = params_ctxt.transaction(|tx| {
+            // `TLParamsLocked` is held only in the value store.
+            tx.set_by_name("TLParamsLocked", 1)?;
+            tx.params_ctxt()
+                .ctrl
+                .write(address, b"after\0")
+                .map_err(|e| GenApiError::Device(Box::new(e)))?;
+            // `AcquisitionStartReg` is write-only.
+            tx.execute(acquisition_start)?;
+            Err::<(), _>(GenApiError::InvalidData("abort".into()))
+        });
+
+        match res {
+            Err(TransactionError::RolledBack { unrestored, .. }) => {
+                assert_eq!(unrestored.len(), 1);
+            }
+            res => panic!("transaction must be rolled back: {:?}", res),
+        }
+        let locked = params_ctxt.node("TLParamsLocked").unwrap();
+        let locked = locked.as_integer(&params_ctxt).unwrap();
+        assert_eq!(locked.value(&mut params_ctxt).unwrap(), 0);
+        let mut buf = vec![0; len.into()];
+        camera.ctrl.read(address, &mut buf).unwrap();
+        assert!(buf.starts_with(b"before\0"));
+
+        camera.close().unwrap();
+        assert_clean("EMUTRAN1");
+    }
+
+    #[test]
+    fn test_soak() {
+        let src = r#"
+duration = 5.0
+seed = 1984
+
+[control]
+rate = 100.0
+mix = { read = 6, write = 3, stacked = 1 }
+
+[[faults]]
+kind = "busy"
+probability = 0.05
+
+[[faults]]
+kind = "stall"
+probability = 0.02
+
+[[faults]]
+kind = "timeout"
+probability = 0.02
+"#;
+        let scenario = SoakScenario::from_toml(src).unwrap();
+        let mut camera = camera("EMUSOAK1");
+        // Transactions issued by `open`.
+        let before = camera.ctrl.stats();
+
+        let report = SoakRunner::new(&scenario).run(&mut camera);
+        assert!(report.violations().is_empty(), "{:?}", report.violations());
+
+        let count = |f: fn(&SoakAction) -> bool| {
+            scenario.schedule().filter(|(_, action)| f(action)).count() as u64
+        };
+        let controls = count(|action| matches!(action, SoakAction::Control { .. }));
+        // Busy transactions succeed by retrying.
+        let failures = count(|action| {
+            matches!(
+                action,
+                SoakAction::Control {
+                    fault: Some(FaultKind::Stall | FaultKind::Timeout),
+                    ..
+                }
+            )
+        });
+        assert!(failures > 0);
+
+        let last = report.last().unwrap();
+        assert_eq!(last.control_errors - before.errors, failures);
+        assert_eq!(
+            last.control_transactions - before.transactions,
+            controls - failures
+        );
+        assert_eq!(last.request_id_mismatches, 0);
+
+        camera.close().unwrap();
+        assert_clean("EMUSOAK1");
+    }
+}
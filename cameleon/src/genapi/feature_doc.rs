@@ -0,0 +1,580 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains reference documentation of features extracted from `GenApi` context.
+//!
+//! Documentation is extracted without accessing the device. Limits of a feature are resolved only
+//! when they are static, i.e. written as literals in the XML or derived from the register
+//! definition, and otherwise marked as [`Limit::Dynamic`].
+//!
+//! # Examples
+//! ```rust
+//! # use cameleon::u3v;
+//! # let mut cameras = u3v::enumerate_cameras().unwrap();
+//! # if cameras.is_empty() {
+//! #     return;
+//! # }
+//! # let mut camera = cameras.pop().unwrap();
+//! use cameleon::genapi::{render_markdown, Visibility};
+//!
+//! camera.load_context().unwrap();
+//! let mut params_ctxt = camera.params_ctxt().unwrap();
+//!
+//! let docs = params_ctxt.document_features(Visibility::Expert);
+//! println!("{}", render_markdown(&docs));
+//! ```
+
+use std::{collections::HashSet, fmt, fmt::Write};
+
+use cameleon_genapi::{
+    elem_type::{FloatRepresentation, ImmOrPNode, IntegerRepresentation},
+    interface::{IFloatKind, IIntegerKind},
+    prelude::*,
+    store::NodeData,
+    Device, GenApiError, NodeBase,
+};
+
+use super::{AccessMode, CacheStore, NodeId, NodeStore, ValueCtxt, ValueStore, Visibility};
+
+/// Name of the root category of the feature tree.
+const ROOT_CATEGORY: &str = "Root";
+
+/// Reference documentation of a feature.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureDoc {
+    /// Name of the node.
+    pub name: String,
+    /// Display name of the node.
+    pub display_name: Option<String>,
+    /// Interface of the node.
+    pub interface: FeatureInterface,
+    /// Description of the node.
+    pub description: Option<String>,
+    /// Tool tip of the node.
+    pub tooltip: Option<String>,
+    /// Unit of the value, only for [`FeatureInterface::Float`] and [`FeatureInterface::Integer`].
+    pub unit: Option<String>,
+    /// Representation of the value, only for [`FeatureInterface::Float`] and
+    /// [`FeatureInterface::Integer`].
+    pub representation: Option<Representation>,
+    /// Minimum value, only for [`FeatureInterface::Float`] and [`FeatureInterface::Integer`].
+    pub min: Option<Limit>,
+    /// Maximum value, only for [`FeatureInterface::Float`] and [`FeatureInterface::Integer`].
+    pub max: Option<Limit>,
+    /// Increment of the value, `None` if the value has no increment.
+    pub inc: Option<Limit>,
+    /// Entries of the enumeration, only for [`FeatureInterface::Enumeration`].
+    pub enum_entries: Vec<EnumEntryDoc>,
+    /// Access mode written in the XML, the actual access mode may be restricted by the device.
+    pub access_mode: AccessMode,
+    /// Visibility of the node.
+    pub visibility: Visibility,
+    /// Names of categories from the root category to the category which owns the node.
+    pub category_path: Vec<String>,
+}
+
+/// Interface of a feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeatureInterface {
+    /// `IInteger`.
+    Integer,
+    /// `IFloat`.
+    Float,
+    /// `IString`.
+    String,
+    /// `IEnumeration`.
+    Enumeration,
+    /// `ICommand`.
+    Command,
+    /// `IBoolean`.
+    Boolean,
+    /// `IRegister`.
+    Register,
+    /// `IPort`.
+    Port,
+}
+
+impl fmt::Display for FeatureInterface {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Integer => "IInteger",
+            Self::Float => "IFloat",
+            Self::String => "IString",
+            Self::Enumeration => "IEnumeration",
+            Self::Command => "ICommand",
+            Self::Boolean => "IBoolean",
+            Self::Register => "IRegister",
+            Self::Port => "IPort",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Representation of a numeric feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Representation {
+    /// Representation of `IInteger`.
+    Integer(IntegerRepresentation),
+    /// Representation of `IFloat`.
+    Float(FloatRepresentation),
+}
+
+impl fmt::Display for Representation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(repr) => write!(f, "{:?}", repr),
+            Self::Float(repr) => write!(f, "{:?}", repr),
+        }
+    }
+}
+
+/// A limit of a numeric feature.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    /// Static integer limit.
+    Integer(i64),
+    /// Static float limit.
+    Float(f64),
+    /// The limit refers to other nodes, so it's resolved only when the device is accessed.
+    Dynamic,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::Float(v) => write!(f, "{}", v),
+            Self::Dynamic => f.write_str("dynamic"),
+        }
+    }
+}
+
+/// Reference documentation of an enumeration entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumEntryDoc {
+    /// Name of the entry.
+    pub name: String,
+    /// Display name of the entry.
+    pub display_name: Option<String>,
+    /// Value of the entry.
+    pub value: i64,
+    /// Description of the entry.
+    pub description: Option<String>,
+}
+
+/// Collects documentation of features reachable from the root category whose visibility is
+/// `visibility` or lower, in the order of the category tree.
+///
+/// Features of a category precede its sub categories. A feature in multiple categories is
+/// documented once, in the first category.
+pub(super) fn document_features<T: ValueStore, U: CacheStore>(
+    store: &impl NodeStore,
+    cx: &mut ValueCtxt<T, U>,
+    visibility: Visibility,
+) -> Vec<FeatureDoc> {
+    let mut walker = Walker {
+        store,
+        cx,
+        visibility,
+        visited: HashSet::new(),
+        path: vec![],
+        docs: vec![],
+    };
+    if let Some(root) = store.id_by_name(ROOT_CATEGORY) {
+        walker.visit_category(root);
+    }
+    walker.docs
+}
+
+struct Walker<'a, S, T, U> {
+    store: &'a S,
+    cx: &'a mut ValueCtxt<T, U>,
+    visibility: Visibility,
+    visited: HashSet<NodeId>,
+    path: Vec<String>,
+    docs: Vec<FeatureDoc>,
+}
+
+impl<'a, S, T, U> Walker<'a, S, T, U>
+where
+    S: NodeStore,
+    T: ValueStore,
+    U: CacheStore,
+{
+    fn visit_category(&mut self, nid: NodeId) {
+        let category = match self.store.node_opt(nid) {
+            Some(NodeData::Category(category)) => category,
+            _ => return,
+        };
+        if !self.visited.insert(nid) || !self.is_visible(category.node_base().visibility()) {
+            return;
+        }
+
+        self.path.push(nid.name(self.store).into());
+        let (categories, features): (Vec<&NodeId>, Vec<&NodeId>) = category
+            .p_features()
+            .iter()
+            .partition(|nid| matches!(self.store.node_opt(**nid), Some(NodeData::Category(_))));
+        for nid in features {
+            self.visit_feature(*nid);
+        }
+        for nid in categories {
+            self.visit_category(*nid);
+        }
+        self.path.pop();
+    }
+
+    fn visit_feature(&mut self, nid: NodeId) {
+        let node = match self.store.node_opt(nid) {
+            Some(node) => node,
+            None => return,
+        };
+        let interface = match interface_of(node) {
+            Some(interface) => interface,
+            None => return,
+        };
+        let base = node.node_base();
+        if !self.is_visible(base.visibility()) || !self.visited.insert(nid) {
+            return;
+        }
+
+        let mut doc = FeatureDoc {
+            name: nid.name(self.store).into(),
+            display_name: base.display_name().map(Into::into),
+            interface,
+            description: base.description().map(Into::into),
+            tooltip: base.tooltip().map(Into::into),
+            unit: None,
+            representation: None,
+            min: None,
+            max: None,
+            inc: None,
+            enum_entries: vec![],
+            access_mode: access_mode_of(node, &base),
+            visibility: base.visibility(),
+            category_path: self.path.clone(),
+        };
+
+        if let Some(kind) = nid.as_iinteger_kind(self.store) {
+            self.fill_integer(&mut doc, kind);
+        } else if let Some(kind) = nid.as_ifloat_kind(self.store) {
+            self.fill_float(&mut doc, kind);
+        } else if let Some(NodeData::Enumeration(node)) = self.store.node_opt(nid) {
+            doc.enum_entries = node
+                .entries_elem()
+                .iter()
+                .filter(|entry| self.is_visible(entry.visibility()))
+                .map(|entry| EnumEntryDoc {
+                    name: entry.name().into(),
+                    display_name: entry.display_name().map(Into::into),
+                    value: entry.value(),
+                    description: entry.description().map(Into::into),
+                })
+                .collect();
+        }
+
+        self.docs.push(doc);
+    }
+
+    fn fill_integer(&mut self, doc: &mut FeatureDoc, kind: IIntegerKind) {
+        let (store, cx) = (self.store, &mut *self.cx);
+        doc.unit = kind.unit(store).map(Into::into);
+        doc.representation = Some(Representation::Integer(kind.representation(store)));
+
+        // Limits of `Integer` are static only if they are literals, others are static if they are
+        // resolved without accessing the device.
+        let literal = match kind {
+            IIntegerKind::Integer(node) => Some((
+                is_literal(node.min_elem()),
+                is_literal(node.max_elem()),
+                is_literal(node.inc_elem()),
+            )),
+            _ => None,
+        };
+        let (min_literal, max_literal, inc_literal) = literal.unwrap_or((true, true, true));
+
+        doc.min = Some(limit(
+            min_literal,
+            kind.min(&mut NoDevice, store, cx),
+            Limit::Integer,
+        ));
+        doc.max = Some(limit(
+            max_literal,
+            kind.max(&mut NoDevice, store, cx),
+            Limit::Integer,
+        ));
+        doc.inc = match kind.inc(&mut NoDevice, store, cx) {
+            Ok(Some(inc)) if inc_literal => Some(Limit::Integer(inc)),
+            Ok(None) => None,
+            _ => Some(Limit::Dynamic),
+        };
+    }
+
+    fn fill_float(&mut self, doc: &mut FeatureDoc, kind: IFloatKind) {
+        let (store, cx) = (self.store, &mut *self.cx);
+        doc.unit = kind.unit(store).map(Into::into);
+        doc.representation = Some(Representation::Float(kind.representation(store)));
+
+        let literal = match kind {
+            IFloatKind::Float(node) => Some((
+                is_literal(node.min_elem()),
+                is_literal(node.max_elem()),
+                !matches!(node.inc_elem(), Some(inc) if !is_literal(*inc)),
+            )),
+            _ => None,
+        };
+        let (min_literal, max_literal, inc_literal) = literal.unwrap_or((true, true, true));
+
+        doc.min = Some(limit(
+            min_literal,
+            kind.min(&mut NoDevice, store, cx),
+            Limit::Float,
+        ));
+        doc.max = Some(limit(
+            max_literal,
+            kind.max(&mut NoDevice, store, cx),
+            Limit::Float,
+        ));
+        doc.inc = match kind.inc(&mut NoDevice, store, cx) {
+            // `NaN` means the increment is not specified.
+            Ok(Some(inc)) if inc.is_nan() => None,
+            Ok(Some(inc)) if inc_literal => Some(Limit::Float(inc)),
+            Ok(None) => None,
+            _ => Some(Limit::Dynamic),
+        };
+    }
+
+    fn is_visible(&self, visibility: Visibility) -> bool {
+        visibility_rank(visibility) <= visibility_rank(self.visibility)
+    }
+}
+
+fn interface_of(node: &NodeData) -> Option<FeatureInterface> {
+    let interface = match node {
+        NodeData::Integer(_)
+        | NodeData::IntReg(_)
+        | NodeData::MaskedIntReg(_)
+        | NodeData::IntConverter(_)
+        | NodeData::IntSwissKnife(_) => FeatureInterface::Integer,
+        NodeData::Float(_)
+        | NodeData::FloatReg(_)
+        | NodeData::Converter(_)
+        | NodeData::SwissKnife(_) => FeatureInterface::Float,
+        NodeData::String(_) | NodeData::StringReg(_) => FeatureInterface::String,
+        NodeData::Enumeration(_) => FeatureInterface::Enumeration,
+        NodeData::Command(_) => FeatureInterface::Command,
+        NodeData::Boolean(_) => FeatureInterface::Boolean,
+        NodeData::Register(_) => FeatureInterface::Register,
+        NodeData::Port(_) => FeatureInterface::Port,
+        _ => return None,
+    };
+    Some(interface)
+}
+
+fn access_mode_of(node: &NodeData, base: &NodeBase) -> AccessMode {
+    let register_mode = match node {
+        NodeData::IntReg(n) => n.register_base().access_mode(),
+        NodeData::MaskedIntReg(n) => n.register_base().access_mode(),
+        NodeData::FloatReg(n) => n.register_base().access_mode(),
+        NodeData::StringReg(n) => n.register_base().access_mode(),
+        NodeData::Register(n) => n.register_base().access_mode(),
+        _ => return base.imposed_access_mode(),
+    };
+    match (register_mode, base.imposed_access_mode()) {
+        (AccessMode::RW, imposed) => imposed,
+        (mode, _) => mode,
+    }
+}
+
+fn visibility_rank(visibility: Visibility) -> u8 {
+    match visibility {
+        Visibility::Beginner => 0,
+        Visibility::Expert => 1,
+        Visibility::Guru => 2,
+        Visibility::Invisible => 3,
+    }
+}
+
+fn is_literal<T>(elem: ImmOrPNode<T>) -> bool {
+    matches!(elem, ImmOrPNode::Imm(_))
+}
+
+fn limit<T>(
+    literal: bool,
+    value: cameleon_genapi::GenApiResult<T>,
+    f: impl FnOnce(T) -> Limit,
+) -> Limit {
+    match value {
+        Ok(value) if literal => f(value),
+        _ => Limit::Dynamic,
+    }
+}
+
+/// A device which rejects all access, used to resolve values without accessing the device.
+struct NoDevice;
+
+impl Device for NoDevice {
+    fn read_mem(&mut self, _: i64, _: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Err(GenApiError::InvalidData("device is not accessible".into()).into())
+    }
+
+    fn write_mem(&mut self, _: i64, _: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Err(GenApiError::InvalidData("device is not accessible".into()).into())
+    }
+}
+
+/// Renders documentation of features into Markdown.
+///
+/// Features are grouped by their category path, in the order of `docs`.
+#[must_use]
+pub fn render_markdown(docs: &[FeatureDoc]) -> String {
+    let mut out = String::from("# Features\n");
+    let mut current_path: Option<&[String]> = None;
+
+    for doc in docs {
+        if current_path != Some(&doc.category_path) {
+            let _ = write!(out, "\n## {}\n", doc.category_path.join(" / "));
+            current_path = Some(&doc.category_path);
+        }
+        // Writing to `String` never fails.
+        let _ = render_feature(&mut out, doc);
+    }
+
+    out
+}
+
+fn render_feature(out: &mut String, doc: &FeatureDoc) -> fmt::Result {
+    writeln!(out, "\n### {}\n", doc.name)?;
+    if let Some(description) = &doc.description {
+        writeln!(out, "{}\n", description.trim())?;
+    }
+
+    writeln!(out, "| Property | Value |")?;
+    writeln!(out, "|---|---|")?;
+    let mut row = |key: &str, value: &dyn fmt::Display| {
+        writeln!(out, "| {} | {} |", key, escape(&value.to_string()))
+    };
+    if let Some(display_name) = &doc.display_name {
+        row("Display name", display_name)?;
+    }
+    row("Interface", &doc.interface)?;
+    row("Access mode", &format_args!("{:?}", doc.access_mode))?;
+    row("Visibility", &format_args!("{:?}", doc.visibility))?;
+    if let Some(tooltip) = &doc.tooltip {
+        row("Tool tip", tooltip)?;
+    }
+    if let Some(unit) = &doc.unit {
+        row("Unit", unit)?;
+    }
+    if let Some(representation) = &doc.representation {
+        row("Representation", representation)?;
+    }
+    if let Some(min) = &doc.min {
+        row("Minimum", min)?;
+    }
+    if let Some(max) = &doc.max {
+        row("Maximum", max)?;
+    }
+    if let Some(inc) = &doc.inc {
+        row("Increment", inc)?;
+    }
+
+    if !doc.enum_entries.is_empty() {
+        writeln!(out, "\n| Entry | Value | Description |")?;
+        writeln!(out, "|---|---|---|")?;
+        for entry in &doc.enum_entries {
+            let name = match &entry.display_name {
+                Some(display_name) => format!("{} ({})", entry.name, display_name),
+                None => entry.name.clone(),
+            };
+            writeln!(
+                out,
+                "| {} | {} | {} |",
+                escape(&name),
+                entry.value,
+                escape(entry.description.as_deref().unwrap_or(""))
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Escapes a text so that it fits in a cell of Markdown table.
+fn escape(s: &str) -> String {
+    s.trim().replace('|', "\\|").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{DefaultGenApiCtxt, FromXml, GenApiCtxt},
+        *,
+    };
+
+    const XML: &str = include_str!("../../tests/fixtures/sample_device.xml");
+    const MARKDOWN: &str = include_str!("../../tests/fixtures/sample_device.md");
+
+    fn document(visibility: Visibility) -> Vec<FeatureDoc> {
+        let mut ctxt = DefaultGenApiCtxt::from_xml(&XML).unwrap();
+        ctxt.enter(|store, cx| document_features(store, cx, visibility))
+    }
+
+    fn find<'a>(docs: &'a [FeatureDoc], name: &str) -> &'a FeatureDoc {
+        docs.iter().find(|doc| doc.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_static_and_dynamic_limits() {
+        let docs = document(Visibility::Guru);
+
+        let width = find(&docs, "Width");
+        assert_eq!(width.min, Some(Limit::Integer(16)));
+        assert_eq!(width.max, Some(Limit::Dynamic));
+        assert_eq!(width.inc, Some(Limit::Integer(4)));
+        assert_eq!(width.unit.as_deref(), Some("px"));
+
+        let exposure = find(&docs, "ExposureTime");
+        assert_eq!(exposure.min, Some(Limit::Float(10.0)));
+        assert_eq!(exposure.max, Some(Limit::Float(1_000_000.0)));
+        assert_eq!(exposure.inc, None);
+
+        let gain = find(&docs, "Gain");
+        assert_eq!(gain.min, Some(Limit::Float(0.0)));
+        assert_eq!(gain.max, Some(Limit::Dynamic));
+
+        // Limits of a register are derived from its definition.
+        let offset = find(&docs, "OffsetX");
+        assert_eq!(offset.min, Some(Limit::Integer(0)));
+        assert_eq!(offset.access_mode, AccessMode::RW);
+
+        let pixel_format = find(&docs, "PixelFormat");
+        assert_eq!(pixel_format.enum_entries.len(), 2);
+        assert_eq!(pixel_format.enum_entries[1].value, 0x0110_0003);
+        assert_eq!(
+            pixel_format.category_path,
+            vec!["Root".to_string(), "ImageFormatControl".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_visibility() {
+        let beginner = document(Visibility::Beginner);
+        assert!(beginner
+            .iter()
+            .all(|doc| doc.visibility == Visibility::Beginner));
+        assert!(beginner.iter().all(|doc| doc.name != "TestPattern"));
+
+        let guru = document(Visibility::Guru);
+        assert!(guru.len() > beginner.len());
+        assert_eq!(find(&guru, "TestPattern").visibility, Visibility::Guru);
+        assert!(guru.iter().all(|doc| doc.name != "DebugCounter"));
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let markdown = render_markdown(&document(Visibility::Guru));
+        assert_eq!(markdown, MARKDOWN);
+    }
+}
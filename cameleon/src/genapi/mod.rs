@@ -34,12 +34,27 @@
 //!     gain_node.set_value(&mut params_ctxt, 0.1).unwrap();
 //! }
 //! ```
+pub mod sfnc;
+
+mod feature_doc;
 mod node_kind;
+mod streaming_whitelist;
+mod transaction;
+mod watcher;
 
+pub use feature_doc::{
+    render_markdown, EnumEntryDoc, FeatureDoc, FeatureInterface, Limit, Representation,
+};
 pub use node_kind::{
     BooleanNode, CategoryNode, CommandNode, EnumerationNode, FloatNode, IntegerNode, Node,
     PortNode, RegisterNode, StringNode,
 };
+pub use sfnc::SfncFeature;
+pub use streaming_whitelist::{StreamingWhitelist, LOCK_NODE};
+pub use transaction::{
+    JournaledCtrl, RollbackFailure, Transaction, TransactionError, TransactionResult,
+};
+pub use watcher::{FeatureChange, FeatureValue, FeatureWatcher, FeatureWatchers};
 
 use std::{
     convert::TryInto,
@@ -49,15 +64,20 @@ use std::{
 use auto_impl::auto_impl;
 use cameleon_genapi::{builder::GenApiBuilder, store};
 
-use super::{ControlError, ControlResult, DeviceControl};
+use super::{
+    payload::{ChunkControl, ChunkError, Payload},
+    ControlError, ControlResult, DeviceControl,
+};
 
 pub use cameleon_genapi::{
     elem_type::{AccessMode, NameSpace, Visibility},
+    parser::{CompatibilityReport, ParseWarning, ParserConfig, SchemaPolicy, SchemaVersion},
     store::{
-        CacheSink, CacheStore, DefaultCacheStore, DefaultNodeStore, DefaultValueStore, NodeId,
-        NodeStore, ValueStore,
+        CacheSink, CacheStore, CachedData, DefaultCacheStore, DefaultNodeStore, DefaultValueStore,
+        NodeId, NodeStore, ShardedCacheHandle, ShardedCacheStore, SharedValueStore, ValueStore,
+        ValueStoreSnapshot,
     },
-    GenApiError, RegisterDescription, ValueCtxt,
+    GenApiError, GenApiResult, RegisterDescription, ValueCtxt,
 };
 
 /// Manages context of parameters of the device.
@@ -110,10 +130,70 @@ where
         ns.id_by_name(name).map(Node)
     }
 
+    /// Returns the node of the `SFNC` feature, or `None` if there is no such node in the context.
+    ///
+    /// If the context follows an older `SFNC`, the node is looked up by
+    /// [`SfncFeature::legacy_name`]. Note that the interface of the legacy node may differ, e.g.
+    /// `GainRaw` is `IInteger` while `Gain` is `IFloat`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # if cameras.is_empty() {
+    /// #     return;
+    /// # }
+    /// # let mut camera = cameras.pop().unwrap();
+    /// use cameleon::genapi::SfncFeature;
+    ///
+    /// camera.load_context().unwrap();
+    /// let params_ctxt = camera.params_ctxt().unwrap();
+    /// let width = params_ctxt.get(SfncFeature::Width).unwrap();
+    /// ```
+    pub fn get(&self, feature: SfncFeature) -> Option<Node> {
+        self.node(feature.as_str()).or_else(|| {
+            feature
+                .legacy_name()
+                .and_then(|(legacy, _)| self.node(legacy))
+        })
+    }
+
     /// Returns [`NodeStore`] in the context.
     pub fn node_store(&self) -> &Ctxt::NS {
         self.ctxt.node_store()
     }
+
+    /// Subscribes to changes of the node.
+    ///
+    /// The watcher is notified when the node is written through the context, or when the node is
+    /// invalidated by [`ParamsCtxt::invalidate`]. The subscription never reads the device on its
+    /// own, so the new value is delivered only when it's known without reading the device.
+    ///
+    /// Returns `None` if the context doesn't support watching features.
+    pub fn subscribe(&self, node: Node) -> Option<FeatureWatcher> {
+        self.ctxt
+            .watchers()
+            .map(|watchers| watchers.subscribe(node, false))
+    }
+
+    /// Subscribes to changes of the node, and reads the new value when the node is invalidated.
+    ///
+    /// Returns `None` if the context doesn't support watching features.
+    pub fn subscribe_eager(&self, node: Node) -> Option<FeatureWatcher> {
+        self.ctxt
+            .watchers()
+            .map(|watchers| watchers.subscribe(node, true))
+    }
+
+    /// Collects reference documentation of features whose visibility is `visibility` or lower,
+    /// in the order of the category tree.
+    ///
+    /// The device is never accessed, so limits referring to other nodes are reported as
+    /// [`Limit::Dynamic`]. Use [`render_markdown`] to render the documentation.
+    pub fn document_features(&mut self, visibility: Visibility) -> Vec<FeatureDoc> {
+        self.ctxt
+            .enter(|store, cx| feature_doc::document_features(store, cx, visibility))
+    }
 }
 
 impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt>
@@ -138,6 +218,209 @@ where
             ctxt.enter(|node_store, value_ctxt| f(ctrl, node_store, value_ctxt))
         })
     }
+
+    /// Invalidates cached values of the node and nodes which depend on the node, then notifies
+    /// watchers of the affected nodes.
+    ///
+    /// This is the entry point for sources which change values behind the context, e.g. device
+    /// events, chunk data and polling.
+    pub fn invalidate(&mut self, node: Node) {
+        let changed = watcher::changed_by(self.ctxt.node_store(), node.0);
+        self.ctxt.enter(|_, value_ctxt| {
+            for nid in changed {
+                value_ctxt.invalidate_cache_of(nid);
+                value_ctxt.invalidate_cache_by(nid);
+            }
+        });
+        self.notify(node.0, None);
+    }
+
+    /// Invalidates the nodes carrying data of the event `event_id`, i.e. nodes whose `EventID` is
+    /// `event_id` and registers read through such ports, then notifies watchers of the affected
+    /// nodes.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// # camera.open().unwrap();
+    /// # camera.load_context().unwrap();
+    /// let serial = camera.info().serial_number.clone();
+    /// let mut events = u3v::EventReceiver::open_by_serial(&serial).unwrap().unwrap();
+    /// let event_rx = events.start(&mut camera.ctrl, 16).unwrap();
+    ///
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// let temperature = params_ctxt.node("DeviceTemperature").unwrap();
+    /// let watcher = params_ctxt.subscribe(temperature).unwrap();
+    /// while let Ok(event) = event_rx.try_recv() {
+    ///     params_ctxt.invalidate_event(event.id.into());
+    /// }
+    /// println!("{:?}", watcher.try_recv());
+    /// ```
+    pub fn invalidate_event(&mut self, event_id: u64) {
+        for nid in watcher::event_nodes(self.ctxt.node_store(), event_id) {
+            self.invalidate(Node(nid));
+        }
+    }
+
+    /// Returns a context which reads chunk features, e.g. `ChunkExposureTime`, from the chunks
+    /// of `payload`. Other features are read from the device as usual.
+    ///
+    /// The registers read through chunk ports are invalidated in the returned context, so the
+    /// chunk features reflect `payload` instead of the payload inspected before.
+    ///
+    /// # Errors
+    ///
+    /// [`ChunkError`] if `payload` doesn't contain chunks or a chunk is malformed, see
+    /// [`Payload::chunks`].
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// # camera.open().unwrap();
+    /// # camera.load_context().unwrap();
+    /// let payload_rx = camera.start_streaming(3).unwrap();
+    /// let payload = payload_rx.try_recv().unwrap();
+    ///
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// let mut chunk_ctxt = params_ctxt.with_chunks(&payload).unwrap();
+    /// let exposure_time = chunk_ctxt.node("ChunkExposureTime").unwrap();
+    /// let exposure_time = exposure_time.as_float(&chunk_ctxt).unwrap();
+    /// println!("{}", exposure_time.value(&mut chunk_ctxt).unwrap());
+    /// ```
+    pub fn with_chunks<'a>(
+        &'a mut self,
+        payload: &'a Payload,
+    ) -> Result<ParamsCtxt<ChunkControl<'a, Ctrl>, &'a mut Ctxt>, ChunkError> {
+        let mut chunk_ctxt = ParamsCtxt {
+            ctrl: ChunkControl::new(&mut self.ctrl, payload)?,
+            ctxt: &mut self.ctxt,
+        };
+        for nid in watcher::chunk_registers(chunk_ctxt.node_store()) {
+            chunk_ctxt.invalidate(Node(nid));
+        }
+        Ok(chunk_ctxt)
+    }
+
+    /// Writes features in a transaction. Writes are applied to the device in the order `f` issues
+    /// them, and if `f` returns an error, the registers written by `f` are restored to their
+    /// values before the transaction in the reverse order.
+    ///
+    /// The prior value of a register is read just before each write, so that writes to a
+    /// register multiplexed by a selector are restored along with the selector.
+    ///
+    /// NOTE: The transaction is NOT atomic on the device side. The device may observe the
+    /// intermediate states, and the rollback is best-effort. Failures of the rollback are
+    /// reported as [`TransactionError::RollbackFailed`]. Writes to registers which can't be read,
+    /// e.g. executing a command, can't be rolled back and are reported as `unrestored`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// # camera.open().unwrap();
+    /// # camera.load_context().unwrap();
+    /// let mut params_ctxt = camera.params_ctxt().unwrap();
+    /// params_ctxt
+    ///     .transaction(|tx| {
+    ///         tx.set_by_name("PixelFormat", "Mono8".to_string())?;
+    ///         tx.set_by_name("Width", 640)?;
+    ///         tx.set_by_name("Height", 480)
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn transaction<F, R>(&mut self, f: F) -> TransactionResult<R>
+    where
+        F: FnOnce(&mut Transaction<'_, Ctrl, Ctxt>) -> GenApiResult<R>,
+    {
+        transaction::run(self, f)
+    }
+
+    /// Notifies watchers affected by a change of `nid`. `value` is the new value of `nid` if it's
+    /// known.
+    fn notify(&mut self, nid: NodeId, value: Option<FeatureValue>) {
+        let targets = match self.ctxt.watchers() {
+            Some(watchers) => watchers.affected_by(self.ctxt.node_store(), nid),
+            None => return,
+        };
+
+        for target in targets {
+            let change = match &value {
+                Some(value) if target.nid == nid => Some(FeatureChange::Changed(value.clone())),
+                _ if target.eager => self
+                    .read_value(Node(target.nid))
+                    .map(FeatureChange::Changed),
+                _ => None,
+            };
+            target.notify(change.unwrap_or(FeatureChange::Invalidated(Node(nid))));
+        }
+    }
+
+    fn read_value(&mut self, node: Node) -> Option<FeatureValue> {
+        self.feature_value(node).ok().flatten()
+    }
+
+    /// Reads the value of `node`, returns `None` if the node doesn't have a value.
+    pub(crate) fn feature_value(&mut self, node: Node) -> GenApiResult<Option<FeatureValue>> {
+        let value = if let Some(node) = node.as_integer(self) {
+            FeatureValue::Integer(node.value(self)?)
+        } else if let Some(node) = node.as_float(self) {
+            FeatureValue::Float(node.value(self)?)
+        } else if let Some(node) = node.as_boolean(self) {
+            FeatureValue::Boolean(node.value(self)?)
+        } else if let Some(node) = node.as_string(self) {
+            FeatureValue::String(node.value(self)?)
+        } else if let Some(node) = node.as_enumeration(self) {
+            FeatureValue::Integer(node.current_entry(self)?.value())
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(value))
+    }
+
+    /// Writes `value` to `node`, see [`Transaction::set`] for the accepted interfaces.
+    pub(crate) fn write_feature(&mut self, node: Node, value: FeatureValue) -> GenApiResult<()> {
+        match value {
+            FeatureValue::Integer(value) => {
+                if let Some(node) = node.as_integer(self) {
+                    node.set_value(self, value)
+                } else if let Some(node) = node.as_enumeration(self) {
+                    node.set_entry_by_value(self, value)
+                } else if let Some(node) = node.as_float(self) {
+                    #[allow(clippy::cast_precision_loss)]
+                    node.set_value(self, value as f64)
+                } else {
+                    Err(self.mismatch(node, "an integer"))
+                }
+            }
+            FeatureValue::Float(value) => match node.as_float(self) {
+                Some(node) => node.set_value(self, value),
+                None => Err(self.mismatch(node, "a float")),
+            },
+            FeatureValue::Boolean(value) => match node.as_boolean(self) {
+                Some(node) => node.set_value(self, value),
+                None => Err(self.mismatch(node, "a boolean")),
+            },
+            FeatureValue::String(value) => {
+                if let Some(node) = node.as_enumeration(self) {
+                    node.set_entry_by_name(self, &value)
+                } else if let Some(node) = node.as_string(self) {
+                    node.set_value(self, value)
+                } else {
+                    Err(self.mismatch(node, "a string"))
+                }
+            }
+        }
+    }
+
+    fn mismatch(&self, node: Node, kind: &str) -> GenApiError {
+        let name = node.0.name(self.ctxt.node_store());
+        GenApiError::InvalidData(format!("`{}` doesn't accept {} value", name, kind).into())
+    }
 }
 
 impl<Ctrl, Ctxt> ParamsCtxt<Ctrl, Ctxt> {
@@ -190,12 +473,32 @@ pub trait GenApiCtxt {
     fn clear_cache(&mut self) {
         self.enter(|_, value_ctxt| value_ctxt.clear_cache())
     }
+
+    /// Returns [`FeatureWatchers`] of the context.
+    /// Returns `None` if the context doesn't support watching features.
+    fn watchers(&self) -> Option<&FeatureWatchers> {
+        None
+    }
+
+    /// Returns [`CompatibilityReport`] of the XML the context is built from.
+    /// Returns `None` if the context doesn't keep the report.
+    fn compatibility_report(&self) -> Option<&CompatibilityReport> {
+        None
+    }
 }
 
 /// A trait that provides directly conversion from `GenApi` string to a `GenApi` context.
 pub trait FromXml {
     /// Parse `GenApi` context and build `
     fn from_xml(xml: &impl AsRef<str>) -> ControlResult<Self>
+    where
+        Self: Sized + GenApiCtxt,
+    {
+        Self::from_xml_with(xml, &ParserConfig::default())
+    }
+
+    /// Parse `GenApi` context with `config` and build `
+    fn from_xml_with(xml: &impl AsRef<str>, config: &ParserConfig) -> ControlResult<Self>
     where
         Self: Sized + GenApiCtxt;
 }
@@ -212,6 +515,10 @@ pub struct DefaultGenApiCtxt {
     pub value_ctxt: ValueCtxt<store::DefaultValueStore, store::DefaultCacheStore>,
     /// Register description.
     pub reg_desc: RegisterDescription,
+    /// Watchers of features, see [`GenApiCtxt::watchers`].
+    watchers: FeatureWatchers,
+    /// Compatibility of the XML the context is built from.
+    pub compatibility: CompatibilityReport,
 }
 
 impl GenApiCtxt for DefaultGenApiCtxt {
@@ -229,60 +536,91 @@ impl GenApiCtxt for DefaultGenApiCtxt {
     fn node_store(&self) -> &Self::NS {
         &self.node_store
     }
+
+    fn watchers(&self) -> Option<&FeatureWatchers> {
+        Some(&self.watchers)
+    }
+
+    fn compatibility_report(&self) -> Option<&CompatibilityReport> {
+        Some(&self.compatibility)
+    }
 }
 
 impl FromXml for DefaultGenApiCtxt {
-    /// Parse `GenApi` context and build `
-    fn from_xml(xml: &impl AsRef<str>) -> ControlResult<Self>
+    /// Parse `GenApi` context with `config` and build `
+    fn from_xml_with(xml: &impl AsRef<str>, config: &ParserConfig) -> ControlResult<Self>
     where
         Self: Sized + GenApiCtxt,
     {
-        let (reg_desc, node_store, value_ctxt) = GenApiBuilder::default()
-            .build(xml)
+        let (reg_desc, node_store, value_ctxt, compatibility) = GenApiBuilder::default()
+            .with_parser_config(config.clone())
+            .build_with_report(xml)
             .map_err(|e| ControlError::InvalidData(e.into()))?;
         Ok(Self {
             node_store,
             value_ctxt,
             reg_desc,
+            watchers: FeatureWatchers::default(),
+            compatibility,
         })
     }
 }
 
 /// A sharable version of [`DefaultGenApiCtxt`].
+///
+/// Clones of the context can be used from multiple threads concurrently. Cache hits don't block
+/// each other, see [`ShardedCacheStore`] for consistency of the cache.
 #[derive(Clone, Debug)]
 pub struct SharedDefaultGenApiCtxt {
     /// Node store.
     pub node_store: Arc<store::DefaultNodeStore>,
-    /// Value context.
-    pub value_ctxt: Arc<Mutex<ValueCtxt<store::DefaultValueStore, store::DefaultCacheStore>>>,
+    /// Value store.
+    pub value_store: SharedValueStore,
+    /// Cache store.
+    pub cache_store: ShardedCacheStore,
     /// Register description.
     pub reg_desc: Arc<RegisterDescription>,
+    /// Watchers of features, see [`GenApiCtxt::watchers`].
+    watchers: FeatureWatchers,
+    /// Compatibility of the XML the context is built from.
+    pub compatibility: Arc<CompatibilityReport>,
 }
 
 impl GenApiCtxt for SharedDefaultGenApiCtxt {
     type NS = store::DefaultNodeStore;
-    type VS = store::DefaultValueStore;
-    type CS = store::DefaultCacheStore;
+    type VS = store::ValueStoreSnapshot;
+    type CS = store::ShardedCacheHandle;
 
     fn enter<F, R>(&mut self, f: F) -> R
     where
         F: FnOnce(&Self::NS, &mut ValueCtxt<Self::VS, Self::CS>) -> R,
     {
-        f(&self.node_store, &mut self.value_ctxt.lock().unwrap())
+        let mut value_ctxt = ValueCtxt::new(self.value_store.snapshot(), self.cache_store.handle());
+        let res = f(&self.node_store, &mut value_ctxt);
+        self.value_store.commit(value_ctxt.value_store);
+        res
     }
 
     fn node_store(&self) -> &Self::NS {
         &self.node_store
     }
+
+    fn watchers(&self) -> Option<&FeatureWatchers> {
+        Some(&self.watchers)
+    }
+
+    fn compatibility_report(&self) -> Option<&CompatibilityReport> {
+        Some(&self.compatibility)
+    }
 }
 
 impl FromXml for SharedDefaultGenApiCtxt {
-    /// Parse `GenApi` context and build `
-    fn from_xml(xml: &impl AsRef<str>) -> ControlResult<Self>
+    /// Parse `GenApi` context with `config` and build `
+    fn from_xml_with(xml: &impl AsRef<str>, config: &ParserConfig) -> ControlResult<Self>
     where
         Self: Sized + GenApiCtxt,
     {
-        Ok(DefaultGenApiCtxt::from_xml(xml)?.into())
+        Ok(DefaultGenApiCtxt::from_xml_with(xml, config)?.into())
     }
 }
 
@@ -290,8 +628,11 @@ impl From<DefaultGenApiCtxt> for SharedDefaultGenApiCtxt {
     fn from(ctxt: DefaultGenApiCtxt) -> Self {
         Self {
             node_store: Arc::new(ctxt.node_store),
-            value_ctxt: Arc::new(Mutex::new(ctxt.value_ctxt)),
+            value_store: ctxt.value_ctxt.value_store.into(),
+            cache_store: ctxt.value_ctxt.cache_store.into(),
             reg_desc: Arc::new(ctxt.reg_desc),
+            watchers: ctxt.watchers,
+            compatibility: Arc::new(ctxt.compatibility),
         }
     }
 }
@@ -306,6 +647,10 @@ pub struct NoCacheGenApiCtxt {
     pub value_ctxt: ValueCtxt<store::DefaultValueStore, store::CacheSink>,
     /// Register description.
     pub reg_desc: RegisterDescription,
+    /// Watchers of features, see [`GenApiCtxt::watchers`].
+    watchers: FeatureWatchers,
+    /// Compatibility of the XML the context is built from.
+    pub compatibility: CompatibilityReport,
 }
 
 impl GenApiCtxt for NoCacheGenApiCtxt {
@@ -323,22 +668,33 @@ impl GenApiCtxt for NoCacheGenApiCtxt {
     fn node_store(&self) -> &Self::NS {
         &self.node_store
     }
+
+    fn watchers(&self) -> Option<&FeatureWatchers> {
+        Some(&self.watchers)
+    }
+
+    fn compatibility_report(&self) -> Option<&CompatibilityReport> {
+        Some(&self.compatibility)
+    }
 }
 
 impl FromXml for NoCacheGenApiCtxt {
-    /// Parse `GenApi` context and build `
-    fn from_xml(xml: &impl AsRef<str>) -> ControlResult<Self>
+    /// Parse `GenApi` context with `config` and build `
+    fn from_xml_with(xml: &impl AsRef<str>, config: &ParserConfig) -> ControlResult<Self>
     where
         Self: Sized + GenApiCtxt,
     {
-        let (reg_desc, node_store, value_ctxt) = GenApiBuilder::default()
+        let (reg_desc, node_store, value_ctxt, compatibility) = GenApiBuilder::default()
             .no_cache()
-            .build(xml)
+            .with_parser_config(config.clone())
+            .build_with_report(xml)
             .map_err(|e| ControlError::InvalidData(e.into()))?;
         Ok(Self {
             node_store,
             value_ctxt,
             reg_desc,
+            watchers: FeatureWatchers::default(),
+            compatibility,
         })
     }
 }
@@ -349,6 +705,8 @@ impl From<DefaultGenApiCtxt> for NoCacheGenApiCtxt {
             node_store: from.node_store,
             value_ctxt: ValueCtxt::new(from.value_ctxt.value_store, store::CacheSink::default()),
             reg_desc: from.reg_desc,
+            watchers: from.watchers,
+            compatibility: from.compatibility,
         }
     }
 }
@@ -362,6 +720,10 @@ pub struct SharedNoCacheGenApiCtxt {
     pub value_ctxt: Arc<Mutex<ValueCtxt<store::DefaultValueStore, store::CacheSink>>>,
     /// Register description.
     pub reg_desc: Arc<RegisterDescription>,
+    /// Watchers of features, see [`GenApiCtxt::watchers`].
+    watchers: FeatureWatchers,
+    /// Compatibility of the XML the context is built from.
+    pub compatibility: Arc<CompatibilityReport>,
 }
 
 impl GenApiCtxt for SharedNoCacheGenApiCtxt {
@@ -379,14 +741,22 @@ impl GenApiCtxt for SharedNoCacheGenApiCtxt {
     fn node_store(&self) -> &Self::NS {
         &self.node_store
     }
+
+    fn watchers(&self) -> Option<&FeatureWatchers> {
+        Some(&self.watchers)
+    }
+
+    fn compatibility_report(&self) -> Option<&CompatibilityReport> {
+        Some(&self.compatibility)
+    }
 }
 
 impl FromXml for SharedNoCacheGenApiCtxt {
-    fn from_xml(xml: &impl AsRef<str>) -> ControlResult<Self>
+    fn from_xml_with(xml: &impl AsRef<str>, config: &ParserConfig) -> ControlResult<Self>
     where
         Self: Sized + GenApiCtxt,
     {
-        Ok(NoCacheGenApiCtxt::from_xml(xml)?.into())
+        Ok(NoCacheGenApiCtxt::from_xml_with(xml, config)?.into())
     }
 }
 
@@ -396,6 +766,8 @@ impl From<NoCacheGenApiCtxt> for SharedNoCacheGenApiCtxt {
             node_store: Arc::new(from.node_store),
             value_ctxt: Arc::new(Mutex::new(from.value_ctxt)),
             reg_desc: Arc::new(from.reg_desc),
+            watchers: from.watchers,
+            compatibility: Arc::new(from.compatibility),
         }
     }
 }
@@ -455,4 +827,8 @@ where
         })?;
         Ok(self.inner.write(address, data)?)
     }
+
+    fn chunk_data(&self, chunk_id: u64) -> Option<&[u8]> {
+        self.inner.chunk_data(chunk_id)
+    }
 }
@@ -12,7 +12,7 @@ use cameleon_genapi::{
     EnumEntryNode, GenApiError, GenApiResult, NodeId,
 };
 
-use super::{DeviceControl, GenApiCtxt, GenApiDevice, ParamsCtxt};
+use super::{DeviceControl, FeatureValue, GenApiCtxt, GenApiDevice, ParamsCtxt};
 
 /// A node that has `IInteger` interface
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -83,6 +83,57 @@ macro_rules! delegate {
         )*
     };
 
+    (
+        notify,
+        $expect_kind:ident,
+        $(
+            $(#[$meta:meta])*
+            $vis:vis fn $method:ident<$Ctrl:ident, $Ctxt:ident>($self:ident, ctxt: &mut ParamsCtxt<Ctrl, Ctxt> $(,$arg:ident: $arg_ty:ty)*) -> $ret_ty:ty,)*) => {
+        $(
+            $(#[$meta])*
+            $vis fn $method<$Ctrl, $Ctxt>($self, ctxt: &mut ParamsCtxt<$Ctrl, $Ctxt> $(,$arg: $arg_ty)*) -> $ret_ty
+            where $Ctrl: DeviceControl,
+                  $Ctxt: GenApiCtxt
+            {
+                ctxt.enter2(|ctrl, ns, vc| {
+                    let mut device = GenApiDevice::new(ctrl);
+                    $self.0
+                        .$expect_kind(ns)
+                        .unwrap()
+                        .$method($($arg,)* &mut device, ns, vc)
+                })?;
+                ctxt.notify($self.0, None);
+                Ok(())
+            }
+        )*
+    };
+
+    (
+        notify_value,
+        $expect_kind:ident,
+        $(
+            $(#[$meta:meta])*
+            $vis:vis fn $method:ident<$Ctrl:ident, $Ctxt:ident>($self:ident, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, $arg:ident: $arg_ty:ty) -> $ret_ty:ty,)*) => {
+        $(
+            $(#[$meta])*
+            $vis fn $method<$Ctrl, $Ctxt>($self, ctxt: &mut ParamsCtxt<$Ctrl, $Ctxt>, $arg: $arg_ty) -> $ret_ty
+            where $Ctrl: DeviceControl,
+                  $Ctxt: GenApiCtxt
+            {
+                let new_value = FeatureValue::from($arg.clone());
+                ctxt.enter2(|ctrl, ns, vc| {
+                    let mut device = GenApiDevice::new(ctrl);
+                    $self.0
+                        .$expect_kind(ns)
+                        .unwrap()
+                        .$method($arg, &mut device, ns, vc)
+                })?;
+                ctxt.notify($self.0, Some(new_value));
+                Ok(())
+            }
+        )*
+    };
+
     (
         no_vc,
         $expect_kind:ident,
@@ -107,16 +158,10 @@ impl IntegerNode {
         expect_iinteger_kind,
         /// Returns the value of the node.
         pub fn value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<i64>,
-        /// Sets the value of the node.
-        pub fn set_value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: i64) -> GenApiResult<()>,
         /// Returns the minimum value which the node can take.
         pub fn min<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<i64>,
-        /// Restricts minimum value of the node.
-        pub fn set_min<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: i64) -> GenApiResult<()>,
         /// Returns the maximum value which the node can take.
         pub fn max<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<i64>,
-        /// Restricts maximum value of the node.
-        pub fn set_max<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: i64) -> GenApiResult<()>,
         /// Returns the increment value if `inc_mode` returns IncrementMode::FixedIncrement. The value
         /// to set must be `min + i * Increment`.
         ///
@@ -128,6 +173,23 @@ impl IntegerNode {
         /// Returns `true` if the node is writable.
         pub fn is_writable<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>,
     }
+
+    delegate! {
+        notify_value,
+        expect_iinteger_kind,
+        /// Sets the value of the node.
+        pub fn set_value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: i64) -> GenApiResult<()>,
+    }
+
+    delegate! {
+        notify,
+        expect_iinteger_kind,
+        /// Restricts minimum value of the node.
+        pub fn set_min<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: i64) -> GenApiResult<()>,
+        /// Restricts maximum value of the node.
+        pub fn set_max<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: i64) -> GenApiResult<()>,
+    }
+
     delegate! {
        no_vc,
        expect_iinteger_kind,
@@ -161,8 +223,6 @@ impl FloatNode {
         expect_ifloat_kind,
         /// Returns the value of the node.
         pub fn value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<f64>,
-        /// Sets the value of the node.
-        pub fn set_value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: f64) -> GenApiResult<()>,
         /// Returns minimum value which the node can take.
         pub fn min<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<f64>,
         /// Returns maximum value which the node can take.
@@ -179,6 +239,13 @@ impl FloatNode {
         pub fn is_writable<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>,
     }
 
+    delegate! {
+        notify_value,
+        expect_ifloat_kind,
+        /// Sets the value of the node.
+        pub fn set_value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: f64) -> GenApiResult<()>,
+    }
+
     delegate! {
        no_vc,
        expect_ifloat_kind,
@@ -214,8 +281,6 @@ impl StringNode {
         expect_istring_kind,
         /// Returns the value of the node.
         pub fn value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<String>,
-        /// Sets the value of the node.
-        pub fn set_value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: String) -> GenApiResult<()>,
         /// Returns the maximum length of the string.
         pub fn max_length<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<i64>,
         /// Returns `true` if the node is readable.
@@ -224,6 +289,13 @@ impl StringNode {
         pub fn is_writable<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>,
     }
 
+    delegate! {
+        notify_value,
+        expect_istring_kind,
+        /// Sets the value of the node.
+        pub fn set_value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: String) -> GenApiResult<()>,
+    }
+
     /// Upcast to [`Node`].
     pub fn as_node(self) -> Node {
         Node(self.0)
@@ -232,16 +304,25 @@ impl StringNode {
 
 impl EnumerationNode {
     delegate! {
-    expect_ienumeration_kind,
-        /// Sets entry to the enumeration node by the entry name.
-        pub fn set_entry_by_name<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, name: &str) -> GenApiResult<()>,
-        /// Sets entry to the enumeration node by the entry value.
-        pub fn set_entry_by_value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: i64) -> GenApiResult<()>,
+        expect_ienumeration_kind,
         /// Returns `true` if the node is readable.
         pub fn is_readable<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>,
         /// Returns `true` if the node is writable.
         pub fn is_writable<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>,
+    }
 
+    delegate! {
+        notify,
+        expect_ienumeration_kind,
+        /// Sets entry to the enumeration node by the entry name.
+        pub fn set_entry_by_name<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, name: &str) -> GenApiResult<()>,
+    }
+
+    delegate! {
+        notify_value,
+        expect_ienumeration_kind,
+        /// Sets entry to the enumeration node by the entry value.
+        pub fn set_entry_by_value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: i64) -> GenApiResult<()>,
     }
 
     /// Returns entries of the node.
@@ -297,14 +378,19 @@ impl EnumerationNode {
 impl CommandNode {
     delegate! {
         expect_icommand_kind,
-        /// Executes the command.
-        pub fn execute<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<()>,
         /// Returns `true` if the previous command is executed on the device.
         pub fn is_done<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>,
         /// Returns `true` if the node is writable (executable).
         pub fn is_writable<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>,
     }
 
+    delegate! {
+        notify,
+        expect_icommand_kind,
+        /// Executes the command.
+        pub fn execute<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<()>,
+    }
+
     /// Upcast to [`Node`].
     pub fn as_node(self) -> Node {
         Node(self.0)
@@ -316,14 +402,19 @@ impl BooleanNode {
         expect_iboolean_kind,
         /// Returns the value of the node.
         pub fn value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>,
-        /// Sets the value of the node.
-        pub fn set_value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: bool) -> GenApiResult<()>,
         /// Returns `true` if the node is readable.
         pub fn is_readable<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>,
         /// Returns `true` if the node is writable.
         pub fn is_writable<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<bool>,
     }
 
+    delegate! {
+        notify_value,
+        expect_iboolean_kind,
+        /// Sets the value of the node.
+        pub fn set_value<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, value: bool) -> GenApiResult<()>,
+    }
+
     /// Upcast to [`Node`].
     pub fn as_node(self) -> Node {
         Node(self.0)
@@ -336,16 +427,21 @@ impl RegisterNode {
         /// Reads bytes from the register.
         /// `buf.len()` must be same as the register length returned from [`Self::length`].
         pub fn read<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, buf: &mut [u8]) -> GenApiResult<()>,
-        /// Writes bytes to the register.
-        ///
-        /// `data.len()` must be same as the register length returned from [`IRegister::length`].
-        pub fn write<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, data: &[u8]) -> GenApiResult<()>,
         /// Returns the address of the register that the node pointing to.
         pub fn address<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<i64>,
         /// Returns the length of the register that the node pointing to.
         pub fn length<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>) -> GenApiResult<i64>,
     }
 
+    delegate! {
+        notify,
+        expect_iregister_kind,
+        /// Writes bytes to the register.
+        ///
+        /// `data.len()` must be same as the register length returned from [`IRegister::length`].
+        pub fn write<Ctrl, Ctxt>(self, ctxt: &mut ParamsCtxt<Ctrl, Ctxt>, data: &[u8]) -> GenApiResult<()>,
+    }
+
     /// Upcast to [`Node`].
     pub fn as_node(self) -> Node {
         Node(self.0)
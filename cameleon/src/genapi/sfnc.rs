@@ -0,0 +1,396 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Names of features defined in `GenICam SFNC` (Standard Features Naming Convention).
+//!
+//! Each feature is available both as a string constant, e.g. [`EXPOSURE_TIME`], and as a variant
+//! of [`SfncFeature`], so that a typo in a feature name is caught at compile time.
+//! [`entry`] and [`category`] contain names of enumeration entries and categories.
+//!
+//! Some features were renamed by later `SFNC` releases, e.g. `ExposureTimeAbs` became
+//! `ExposureTime` in `SFNC 2.0`. The names in this module follow the latest release, and the
+//! former names are available by [`SfncFeature::legacy_name`].
+//!
+//! # Examples
+//! ```rust
+//! # use cameleon::u3v;
+//! # let mut cameras = u3v::enumerate_cameras().unwrap();
+//! # if cameras.is_empty() {
+//! #     return;
+//! # }
+//! # let mut camera = cameras.pop().unwrap();
+//! use cameleon::genapi::sfnc::SfncFeature;
+//!
+//! camera.load_context().unwrap();
+//! let mut params_ctxt = camera.params_ctxt().unwrap();
+//!
+//! // Falls back to `ExposureTimeAbs` if the camera follows an older `SFNC`.
+//! let exposure_time = params_ctxt.get(SfncFeature::ExposureTime).unwrap();
+//! if let Some(node) = exposure_time.as_float(&params_ctxt) {
+//!     println!("{}", node.value(&mut params_ctxt).unwrap());
+//! }
+//! ```
+
+use std::{fmt, str::FromStr};
+
+/// Version of `SFNC`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SfncVersion {
+    /// Major version.
+    pub major: u16,
+    /// Minor version.
+    pub minor: u16,
+}
+
+impl SfncVersion {
+    /// Constructs `SfncVersion`.
+    #[must_use]
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl fmt::Display for SfncVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+macro_rules! sfnc_features {
+    ($(
+        $(#[$meta:meta])*
+        $variant:ident => $const_name:ident = $name:literal
+            $(, renamed_from($legacy:literal, $major:literal, $minor:literal))?;
+    )*) => {
+        $(
+            $(#[$meta])*
+            pub const $const_name: &str = $name;
+        )*
+
+        /// Features defined in `SFNC`, see the [module level documentation](self).
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum SfncFeature {
+            $(
+                $(#[$meta])*
+                $variant,
+            )*
+        }
+
+        impl SfncFeature {
+            /// All features.
+            pub const ALL: &'static [SfncFeature] = &[$(Self::$variant),*];
+
+            /// Returns the name of the feature.
+            #[must_use]
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(Self::$variant => $const_name,)*
+                }
+            }
+
+            /// Returns the name used before the feature was renamed, and the `SFNC` version
+            /// which renamed it.
+            ///
+            /// Returns `None` if the feature has never been renamed.
+            #[must_use]
+            pub fn legacy_name(self) -> Option<(&'static str, SfncVersion)> {
+                match self {
+                    $($(Self::$variant => Some(($legacy, SfncVersion::new($major, $minor))),)?)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl FromStr for SfncFeature {
+            type Err = ParseSfncFeatureError;
+
+            /// Parses the name of the feature, the legacy names are accepted as well.
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                match s {
+                    $($name $(| $legacy)? => Ok(Self::$variant),)*
+                    _ => Err(ParseSfncFeatureError(s.to_string())),
+                }
+            }
+        }
+    };
+}
+
+sfnc_features! {
+    /// Name of the manufacturer of the device.
+    DeviceVendorName => DEVICE_VENDOR_NAME = "DeviceVendorName";
+    /// Model of the device.
+    DeviceModelName => DEVICE_MODEL_NAME = "DeviceModelName";
+    /// Serial number of the device.
+    DeviceSerialNumber => DEVICE_SERIAL_NUMBER = "DeviceSerialNumber";
+    /// User-programmable device identifier.
+    DeviceUserId => DEVICE_USER_ID = "DeviceUserID";
+    /// Temperature of the device in degrees Celsius.
+    DeviceTemperature => DEVICE_TEMPERATURE = "DeviceTemperature";
+
+    /// Effective width of the sensor in pixels.
+    SensorWidth => SENSOR_WIDTH = "SensorWidth";
+    /// Effective height of the sensor in pixels.
+    SensorHeight => SENSOR_HEIGHT = "SensorHeight";
+    /// Maximum width of the image in pixels.
+    WidthMax => WIDTH_MAX = "WidthMax";
+    /// Maximum height of the image in pixels.
+    HeightMax => HEIGHT_MAX = "HeightMax";
+    /// Width of the image provided by the device in pixels.
+    Width => WIDTH = "Width";
+    /// Height of the image provided by the device in pixels.
+    Height => HEIGHT = "Height";
+    /// Horizontal offset from the origin to the region of interest in pixels.
+    OffsetX => OFFSET_X = "OffsetX";
+    /// Vertical offset from the origin to the region of interest in pixels.
+    OffsetY => OFFSET_Y = "OffsetY";
+    /// Format of the pixels provided by the device.
+    PixelFormat => PIXEL_FORMAT = "PixelFormat";
+    /// Number of horizontal pixels to combine together.
+    BinningHorizontal => BINNING_HORIZONTAL = "BinningHorizontal";
+    /// Number of vertical pixels to combine together.
+    BinningVertical => BINNING_VERTICAL = "BinningVertical";
+
+    /// Acquisition mode of the device, e.g. [`entry::CONTINUOUS`].
+    AcquisitionMode => ACQUISITION_MODE = "AcquisitionMode";
+    /// Starts the acquisition of the device.
+    AcquisitionStart => ACQUISITION_START = "AcquisitionStart";
+    /// Stops the acquisition of the device at the end of the current frame.
+    AcquisitionStop => ACQUISITION_STOP = "AcquisitionStop";
+    /// Frame rate in Hz at which the frames are captured.
+    AcquisitionFrameRate => ACQUISITION_FRAME_RATE = "AcquisitionFrameRate",
+        renamed_from("AcquisitionFrameRateAbs", 2, 0);
+    /// Enables [`ACQUISITION_FRAME_RATE`] to control the frame rate.
+    AcquisitionFrameRateEnable => ACQUISITION_FRAME_RATE_ENABLE = "AcquisitionFrameRateEnable";
+    /// Frame rate in Hz resulting from the current settings.
+    ///
+    /// NOTE: This is not defined in `SFNC`, but widely provided by vendors.
+    ResultingFrameRate => RESULTING_FRAME_RATE = "ResultingFrameRate";
+    /// Selects the trigger to configure, e.g. [`entry::FRAME_START`].
+    TriggerSelector => TRIGGER_SELECTOR = "TriggerSelector";
+    /// Enables the selected trigger.
+    TriggerMode => TRIGGER_MODE = "TriggerMode";
+    /// Source of the selected trigger, e.g. [`entry::SOFTWARE`].
+    TriggerSource => TRIGGER_SOURCE = "TriggerSource";
+    /// Activation mode of the selected trigger, e.g. [`entry::RISING_EDGE`].
+    TriggerActivation => TRIGGER_ACTIVATION = "TriggerActivation";
+    /// Generates an internal trigger when [`TRIGGER_SOURCE`] is [`entry::SOFTWARE`].
+    TriggerSoftware => TRIGGER_SOFTWARE = "TriggerSoftware";
+    /// Delay in us to apply after the trigger reception.
+    TriggerDelay => TRIGGER_DELAY = "TriggerDelay",
+        renamed_from("TriggerDelayAbs", 2, 0);
+    /// Operation mode of the exposure, e.g. [`entry::TIMED`].
+    ExposureMode => EXPOSURE_MODE = "ExposureMode";
+    /// Exposure time in us.
+    ExposureTime => EXPOSURE_TIME = "ExposureTime",
+        renamed_from("ExposureTimeAbs", 2, 0);
+    /// Automatic exposure mode.
+    ExposureAuto => EXPOSURE_AUTO = "ExposureAuto";
+
+    /// Gain applied to the image.
+    Gain => GAIN = "Gain",
+        renamed_from("GainRaw", 2, 0);
+    /// Automatic gain mode.
+    GainAuto => GAIN_AUTO = "GainAuto";
+    /// Black level applied to the image.
+    BlackLevel => BLACK_LEVEL = "BlackLevel",
+        renamed_from("BlackLevelRaw", 2, 0);
+    /// Gamma correction of pixel intensity.
+    Gamma => GAMMA = "Gamma";
+
+    /// Selects the user set to load, save or configure.
+    UserSetSelector => USER_SET_SELECTOR = "UserSetSelector";
+    /// Loads the user set selected by [`USER_SET_SELECTOR`].
+    UserSetLoad => USER_SET_LOAD = "UserSetLoad";
+    /// Saves the current settings to the user set selected by [`USER_SET_SELECTOR`].
+    UserSetSave => USER_SET_SAVE = "UserSetSave";
+    /// The user set loaded at power-up.
+    UserSetDefault => USER_SET_DEFAULT = "UserSetDefault",
+        renamed_from("UserSetDefaultSelector", 2, 1);
+
+    /// Size of the payload in bytes.
+    PayloadSize => PAYLOAD_SIZE = "PayloadSize";
+    /// Locks the features which must not be changed during acquisition.
+    TlParamsLocked => TL_PARAMS_LOCKED = "TLParamsLocked";
+}
+
+impl fmt::Display for SfncFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl AsRef<str> for SfncFeature {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+/// An error returned when parsing [`SfncFeature`] from a string.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("unknown SFNC feature: `{0}`")]
+pub struct ParseSfncFeatureError(String);
+
+/// Names of enumeration entries defined in `SFNC`.
+pub mod entry {
+    /// [`AcquisitionMode`](super::ACQUISITION_MODE) which acquires frames until stopped.
+    pub const CONTINUOUS: &str = "Continuous";
+    /// [`AcquisitionMode`](super::ACQUISITION_MODE) which acquires one frame.
+    pub const SINGLE_FRAME: &str = "SingleFrame";
+    /// [`AcquisitionMode`](super::ACQUISITION_MODE) which acquires a number of frames.
+    pub const MULTI_FRAME: &str = "MultiFrame";
+    /// Enables a mode, e.g. [`TriggerMode`](super::TRIGGER_MODE).
+    pub const ON: &str = "On";
+    /// Disables a mode, e.g. [`TriggerMode`](super::TRIGGER_MODE).
+    pub const OFF: &str = "Off";
+    /// [`TriggerSelector`](super::TRIGGER_SELECTOR) which starts the capture of a frame.
+    pub const FRAME_START: &str = "FrameStart";
+    /// [`TriggerSource`](super::TRIGGER_SOURCE) of [`TriggerSoftware`](super::TRIGGER_SOFTWARE).
+    pub const SOFTWARE: &str = "Software";
+    /// [`TriggerActivation`](super::TRIGGER_ACTIVATION) on the rising edge of the signal.
+    pub const RISING_EDGE: &str = "RisingEdge";
+    /// [`TriggerActivation`](super::TRIGGER_ACTIVATION) on the falling edge of the signal.
+    pub const FALLING_EDGE: &str = "FallingEdge";
+    /// [`ExposureMode`](super::EXPOSURE_MODE) controlled by
+    /// [`ExposureTime`](super::EXPOSURE_TIME).
+    pub const TIMED: &str = "Timed";
+    /// User set of the factory settings.
+    pub const USER_SET_DEFAULT: &str = "Default";
+    /// Prefix of user sets, which is followed by the number of the set, e.g. `UserSet1`.
+    pub const USER_SET_PREFIX: &str = "UserSet";
+}
+
+/// Names of categories defined in `SFNC`.
+pub mod category {
+    /// The root category.
+    pub const ROOT: &str = "Root";
+    /// Features of device information and control.
+    pub const DEVICE_CONTROL: &str = "DeviceControl";
+    /// Features of image size and format.
+    pub const IMAGE_FORMAT_CONTROL: &str = "ImageFormatControl";
+    /// Features of image acquisition, trigger and exposure.
+    pub const ACQUISITION_CONTROL: &str = "AcquisitionControl";
+    /// Features of video signal conditioning.
+    pub const ANALOG_CONTROL: &str = "AnalogControl";
+    /// Features of user sets.
+    pub const USER_SET_CONTROL: &str = "UserSetControl";
+    /// Features of the transport layer.
+    pub const TRANSPORT_LAYER_CONTROL: &str = "TransportLayerControl";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{DefaultGenApiCtxt, FromXml, ParamsCtxt},
+        *,
+    };
+
+    const XML: &str = r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="2"
+          SubMinorVersion="3"
+          ToolTip="ToolTiptest"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210"
+          xmlns="http://www.genicam.org/GenApi/Version_1_0"
+          xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+          xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_0 GenApiSchema.xsd">
+
+            <Integer Name="Width">
+                <Value>640</Value>
+            </Integer>
+
+            <Float Name="ExposureTimeAbs">
+                <Value>1000.0</Value>
+            </Float>
+
+            <Integer Name="Gain">
+                <Value>1</Value>
+            </Integer>
+
+            <Integer Name="GainRaw">
+                <Value>2</Value>
+            </Integer>
+
+        </RegisterDescription>
+        "#;
+
+    #[test]
+    fn test_round_trip() {
+        for &feature in SfncFeature::ALL {
+            assert_eq!(feature.as_str().parse::<SfncFeature>().unwrap(), feature);
+            assert_eq!(feature.to_string(), feature.as_str());
+            if let Some((legacy, _)) = feature.legacy_name() {
+                assert_eq!(legacy.parse::<SfncFeature>().unwrap(), feature);
+            }
+        }
+
+        assert_eq!(
+            EXPOSURE_TIME.parse::<SfncFeature>().unwrap(),
+            SfncFeature::ExposureTime
+        );
+        assert_eq!(
+            TL_PARAMS_LOCKED.parse::<SfncFeature>().unwrap(),
+            SfncFeature::TlParamsLocked
+        );
+        assert_eq!(
+            SfncFeature::ExposureTime.legacy_name(),
+            Some(("ExposureTimeAbs", SfncVersion::new(2, 0)))
+        );
+        assert_eq!(SfncFeature::Width.legacy_name(), None);
+        assert!("ExposureTiem".parse::<SfncFeature>().is_err());
+    }
+
+    #[test]
+    fn test_get() {
+        let ctxt = ParamsCtxt {
+            ctrl: (),
+            ctxt: DefaultGenApiCtxt::from_xml(&XML).unwrap(),
+        };
+
+        assert_eq!(ctxt.get(SfncFeature::Width), ctxt.node(WIDTH));
+        // Legacy names are looked up only if the current name is missing.
+        assert_eq!(
+            ctxt.get(SfncFeature::ExposureTime),
+            ctxt.node("ExposureTimeAbs")
+        );
+        assert_eq!(ctxt.get(SfncFeature::Gain), ctxt.node(GAIN));
+        assert!(ctxt.get(SfncFeature::Height).is_none());
+    }
+
+    /// The helpers must refer to the constants instead of spelling feature names.
+    #[test]
+    fn test_helpers_use_constants() {
+        let sources = [
+            ("camera.rs", include_str!("../camera.rs")),
+            ("user_set.rs", include_str!("../user_set.rs")),
+        ];
+        for (file, source) in &sources {
+            // Tests and doc comments may spell feature names.
+            let code = source.split("#[cfg(test)]").next().unwrap();
+            let code: Vec<_> = code
+                .lines()
+                .filter(|line| !line.trim_start().starts_with("//"))
+                .collect();
+            for line in code {
+                for feature in SfncFeature::ALL {
+                    let quoted = format!("\"{}\"", feature.as_str());
+                    assert!(
+                        !line.contains(&quoted),
+                        "{} spells {} instead of using the constant: {}",
+                        file,
+                        quoted,
+                        line
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,318 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains [`StreamingWhitelist`], the features writable while streaming is active.
+//!
+//! [`Camera::start_streaming`](crate::Camera::start_streaming) sets `TLParamsLocked` to `1`, and
+//! features whose `pIsLocked` refers to the lock node turn read-only until the streaming stops.
+//! The other features, e.g. `ExposureTime` and `Gain` in most cameras, stay writable.
+//!
+//! The whitelist is detected from the node graph alone. The device is never read, so the lock is
+//! asserted only hypothetically: a lock condition which depends on `TLParamsLocked` in any way is
+//! regarded as satisfied.
+
+use std::collections::{BTreeSet, HashSet};
+
+use cameleon_genapi::{elem_type::AccessMode, store::NodeData, NodeId};
+
+use super::{sfnc, watcher::value_chain, NodeStore};
+
+/// Name of the node which locks the transport layer parameters while streaming.
+pub const LOCK_NODE: &str = sfnc::TL_PARAMS_LOCKED;
+
+/// Features which are allowed to be written while streaming is active.
+///
+/// The whitelist consists of the features detected from the `GenApi` context and the overrides by
+/// the user. Overrides are kept when the context is reloaded.
+///
+/// # Examples
+/// ```no_run
+/// # use cameleon::u3v;
+/// # let mut cameras = u3v::enumerate_cameras().unwrap();
+/// # let mut camera = cameras.pop().unwrap();
+/// camera.open().unwrap();
+/// camera.load_context().unwrap();
+///
+/// let whitelist = camera.streaming_whitelist().unwrap();
+/// // The device applies `Width` on the fly even though it's locked in the xml.
+/// whitelist.add("Width");
+/// // Refuses to change `Gain` while streaming.
+/// whitelist.remove("Gain");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StreamingWhitelist {
+    /// `None` until the whitelist is detected from the context.
+    detected: Option<BTreeSet<String>>,
+    added: BTreeSet<String>,
+    removed: BTreeSet<String>,
+}
+
+impl StreamingWhitelist {
+    /// Returns `true` if `feature` is allowed to be written while streaming is active.
+    #[must_use]
+    pub fn contains(&self, feature: &str) -> bool {
+        if self.added.contains(feature) {
+            return true;
+        }
+        !self.removed.contains(feature)
+            && matches!(&self.detected, Some(detected) if detected.contains(feature))
+    }
+
+    /// Allows `feature` to be written while streaming is active.
+    pub fn add(&mut self, feature: impl Into<String>) {
+        let feature = feature.into();
+        self.removed.remove(&feature);
+        self.added.insert(feature);
+    }
+
+    /// Refuses writes to `feature` while streaming is active.
+    pub fn remove(&mut self, feature: &str) {
+        self.added.remove(feature);
+        self.removed.insert(feature.to_string());
+    }
+
+    /// Discards all overrides by [`Self::add`] and [`Self::remove`].
+    pub fn reset(&mut self) {
+        self.added.clear();
+        self.removed.clear();
+    }
+
+    /// Returns the whitelisted features in alphabetical order.
+    pub fn features(&self) -> impl Iterator<Item = &str> {
+        self.detected
+            .iter()
+            .flatten()
+            .chain(&self.added)
+            .filter(move |feature| self.contains(feature))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(String::as_str)
+    }
+
+    /// Returns `true` if the whitelist is already detected from the context.
+    pub(crate) fn is_detected(&self) -> bool {
+        self.detected.is_some()
+    }
+
+    /// Detects the whitelist from `ns`, overrides are kept.
+    pub(crate) fn detect(&mut self, ns: &impl NodeStore) {
+        self.detected = Some(detect(ns));
+    }
+
+    /// Forgets the detected whitelist, e.g. when the context is replaced.
+    pub(crate) fn invalidate(&mut self) {
+        self.detected = None;
+    }
+}
+
+/// Returns the names of features writable while the lock node is set.
+fn detect(ns: &impl NodeStore) -> BTreeSet<String> {
+    let lock = ns.id_by_name(LOCK_NODE);
+    let mut features = BTreeSet::new();
+    ns.visit_nodes(|node| {
+        if !is_feature(node) {
+            return;
+        }
+        let nid = node.node_base().id();
+        if Some(nid) != lock && writable_while_locked(ns, nid, lock) {
+            features.insert(nid.name(ns).to_string());
+        }
+    });
+    features
+}
+
+fn is_feature(node: &NodeData) -> bool {
+    matches!(
+        node,
+        NodeData::Integer(_)
+            | NodeData::IntReg(_)
+            | NodeData::MaskedIntReg(_)
+            | NodeData::Boolean(_)
+            | NodeData::Command(_)
+            | NodeData::Enumeration(_)
+            | NodeData::Float(_)
+            | NodeData::FloatReg(_)
+            | NodeData::String(_)
+            | NodeData::StringReg(_)
+    )
+}
+
+/// Evaluates whether `nid` is writable if `lock` is set. Every node which `nid` refers to as its
+/// value must be writable, so that a feature backed by a locked register is locked as well.
+fn writable_while_locked(ns: &impl NodeStore, nid: NodeId, lock: Option<NodeId>) -> bool {
+    value_chain(ns, nid).into_iter().all(|nid| {
+        let node = match ns.node_opt(nid) {
+            Some(node) if is_feature(node) || is_converter(node) => node,
+            _ => return true,
+        };
+        let base = node.node_base();
+        let is_locked = match (base.p_is_locked(), lock) {
+            (Some(p_is_locked), Some(lock)) => depends_on(ns, p_is_locked, lock),
+            _ => false,
+        };
+        !is_locked && base.imposed_access_mode() != AccessMode::RO && register_writable(node)
+    })
+}
+
+fn is_converter(node: &NodeData) -> bool {
+    matches!(node, NodeData::Converter(_) | NodeData::IntConverter(_))
+}
+
+fn register_writable(node: &NodeData) -> bool {
+    let access_mode = match node {
+        NodeData::IntReg(n) => n.register_base().access_mode(),
+        NodeData::MaskedIntReg(n) => n.register_base().access_mode(),
+        NodeData::FloatReg(n) => n.register_base().access_mode(),
+        NodeData::StringReg(n) => n.register_base().access_mode(),
+        _ => return true,
+    };
+    access_mode != AccessMode::RO
+}
+
+/// Returns `true` if the value of `nid` depends on `target`, following `pValue` and `pVariable`.
+fn depends_on(ns: &impl NodeStore, nid: NodeId, target: NodeId) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![nid];
+    while let Some(nid) = stack.pop() {
+        if !visited.insert(nid) {
+            continue;
+        }
+        for nid in value_chain(ns, nid) {
+            if nid == target {
+                return true;
+            }
+            let p_variables = match ns.node_opt(nid) {
+                Some(NodeData::SwissKnife(n)) => n.p_variables(),
+                Some(NodeData::IntSwissKnife(n)) => n.p_variables(),
+                Some(NodeData::Converter(n)) => n.p_variables(),
+                Some(NodeData::IntConverter(n)) => n.p_variables(),
+                _ => &[],
+            };
+            stack.extend(p_variables.iter().map(|var| var.value()));
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{DefaultGenApiCtxt, FromXml, GenApiCtxt},
+        *,
+    };
+
+    const XML: &str = r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="2"
+          SubMinorVersion="3"
+          ToolTip="ToolTiptest"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210"
+          xmlns="http://www.genicam.org/GenApi/Version_1_0"
+          xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+          xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_0 GenApiSchema.xsd">
+
+            <Integer Name="TLParamsLocked">
+                <Value>0</Value>
+            </Integer>
+
+            <Float Name="ExposureTime">
+                <Value>100.0</Value>
+            </Float>
+
+            <Integer Name="Width">
+                <pIsLocked>TLParamsLocked</pIsLocked>
+                <Value>640</Value>
+            </Integer>
+
+            <IntSwissKnife Name="HeightLocked">
+                <pVariable Name="LOCKED">TLParamsLocked</pVariable>
+                <pVariable Name="MODE">SensorMode</pVariable>
+                <Formula>LOCKED || MODE</Formula>
+            </IntSwissKnife>
+
+            <Integer Name="Height">
+                <pIsLocked>HeightLocked</pIsLocked>
+                <Value>480</Value>
+            </Integer>
+
+            <Integer Name="SensorMode">
+                <Value>0</Value>
+            </Integer>
+
+            <Integer Name="OffsetX">
+                <pIsLocked>SensorMode</pIsLocked>
+                <Value>0</Value>
+            </Integer>
+
+            <Float Name="Gain">
+                <pValue>GainReg</pValue>
+            </Float>
+
+            <FloatReg Name="GainReg">
+              <pIsLocked>TLParamsLocked</pIsLocked>
+              <Address>0x0</Address>
+              <Length>8</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </FloatReg>
+
+            <Integer Name="DeviceTemperature">
+                <ImposedAccessMode>RO</ImposedAccessMode>
+                <Value>40</Value>
+            </Integer>
+
+            <Port Name="Device">
+            </Port>
+
+        </RegisterDescription>
+        "#;
+
+    #[test]
+    fn test_detect() {
+        let ctxt = DefaultGenApiCtxt::from_xml(&XML).unwrap();
+        let mut whitelist = StreamingWhitelist::default();
+        assert!(!whitelist.contains("ExposureTime"));
+
+        whitelist.detect(ctxt.node_store());
+        assert!(whitelist.is_detected());
+        // `Width` is locked directly, `Height` through a formula and `Gain` through its register.
+        // `OffsetX` doesn't depend on the lock, and `DeviceTemperature` is never writable.
+        assert_eq!(
+            whitelist.features().collect::<Vec<_>>(),
+            vec!["ExposureTime", "OffsetX", "SensorMode"]
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let ctxt = DefaultGenApiCtxt::from_xml(&XML).unwrap();
+        let mut whitelist = StreamingWhitelist::default();
+        whitelist.add("Width");
+        whitelist.remove("ExposureTime");
+        whitelist.detect(ctxt.node_store());
+        assert_eq!(
+            whitelist.features().collect::<Vec<_>>(),
+            vec!["OffsetX", "SensorMode", "Width"]
+        );
+
+        // Overrides are kept when the context is reloaded.
+        whitelist.invalidate();
+        assert!(whitelist.contains("Width"));
+        assert!(!whitelist.contains("OffsetX"));
+        whitelist.detect(ctxt.node_store());
+        assert!(!whitelist.contains("ExposureTime"));
+
+        whitelist.reset();
+        assert!(whitelist.contains("ExposureTime"));
+        assert!(!whitelist.contains("Width"));
+    }
+}
@@ -0,0 +1,652 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides transactional writes of features, see [`ParamsCtxt::transaction`].
+//!
+//! NOTE: A transaction is NOT atomic on the device side. The writes are applied to the device one
+//! by one, so the device may be observed in an intermediate state, and a rollback is just a
+//! sequence of writes which may fail as well. What a transaction guarantees is that the host
+//! knows which registers are written and how to restore them.
+//!
+//! Writes to registers which can't be read, i.e. write-only registers and registers of command
+//! nodes, are journaled without their prior values. They are left as written on rollback and
+//! reported as `unrestored`.
+
+use cameleon_genapi::{
+    store::{NodeData, ValueData, ValueId},
+    GenApiError, GenApiResult, NodeId, NodeStore, ValueStore,
+};
+
+use super::{watcher, DeviceControl, FeatureValue, GenApiCtxt, Node, ParamsCtxt};
+use crate::{ControlError, ControlResult};
+
+/// A specialized `Result` type for [`ParamsCtxt::transaction`].
+pub type TransactionResult<T> = std::result::Result<T, TransactionError>;
+
+/// An error type returned when a transaction fails.
+#[derive(Debug, thiserror::Error)]
+pub enum TransactionError {
+    /// The transaction failed, and all the applied writes are rolled back except for the writes
+    /// in `unrestored`.
+    #[error("transaction is rolled back: {cause}")]
+    RolledBack {
+        /// The error which made the transaction fail.
+        cause: GenApiError,
+        /// Addresses of the registers which were written without their prior values, e.g.
+        /// registers of command nodes, in the order they were written.
+        unrestored: Vec<u64>,
+    },
+
+    /// The transaction failed, and some of the applied writes failed to be rolled back. The
+    /// device may be left in an inconsistent state.
+    #[error("transaction failed: {cause}, and {} writes failed to be rolled back", failures.len())]
+    RollbackFailed {
+        /// The error which made the transaction fail.
+        cause: GenApiError,
+        /// Writes which failed to be rolled back, in the order they were attempted.
+        failures: Vec<RollbackFailure>,
+        /// Addresses of the registers which were written without their prior values, see
+        /// [`Self::RolledBack`].
+        unrestored: Vec<u64>,
+    },
+}
+
+/// A write which failed to be rolled back.
+#[derive(Debug)]
+pub struct RollbackFailure {
+    /// Address of the register.
+    pub address: u64,
+    /// The value of the register before the transaction wrote it.
+    pub prior_value: Vec<u8>,
+    /// The error of the write.
+    pub error: ControlError,
+}
+
+/// A transaction passed to the closure of [`ParamsCtxt::transaction`].
+pub struct Transaction<'a, Ctrl, Ctxt> {
+    ctxt: ParamsCtxt<JournaledCtrl<'a, Ctrl>, &'a mut Ctxt>,
+    /// Nodes written by [`Self::set`], watchers of them are notified on rollback.
+    written: Vec<NodeId>,
+    /// Prior values of the nodes which hold their values in the value store, e.g. a selector
+    /// without a register. Only the first snapshot of each value is kept.
+    snapshots: Vec<(ValueId, ValueData)>,
+}
+
+impl<'a, Ctrl, Ctxt> Transaction<'a, Ctrl, Ctxt>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    /// Writes `value` to `node`.
+    ///
+    /// `IInteger`, `IFloat`, `IBoolean`, `IString` and `IEnumeration` nodes are supported. An
+    /// enumeration accepts either the name of an entry as [`FeatureValue::String`] or the value
+    /// of an entry as [`FeatureValue::Integer`], and a float accepts an integer as well.
+    ///
+    /// If `node` isn't readable, the registers it writes are journaled without their prior
+    /// values and aren't restored on rollback.
+    pub fn set(&mut self, node: Node, value: impl Into<FeatureValue>) -> GenApiResult<()> {
+        self.snapshot(node);
+        let restore = self.is_readable(node)?;
+        self.journaled(restore, |ctxt| ctxt.write_feature(node, value.into()))?;
+        self.written.push(node.0);
+        Ok(())
+    }
+
+    /// Executes the command `node`.
+    ///
+    /// The registers written by the command are journaled without their prior values, because a
+    /// command can't be undone by restoring its register.
+    pub fn execute(&mut self, node: Node) -> GenApiResult<()> {
+        let command = node
+            .as_command(&self.ctxt)
+            .ok_or_else(|| self.ctxt.mismatch(node, "a command"))?;
+        self.journaled(false, |ctxt| command.execute(ctxt))
+    }
+
+    /// Writes `value` to the node named `name`, see [`Self::set`].
+    pub fn set_by_name(&mut self, name: &str, value: impl Into<FeatureValue>) -> GenApiResult<()> {
+        let node = self.ctxt.node(name).ok_or_else(|| {
+            GenApiError::InvalidNode(format!("no node named `{}` exists", name).into())
+        })?;
+        self.set(node, value)
+    }
+
+    /// Returns the context of the transaction, which can be used to read features.
+    ///
+    /// Registers written through the returned context are rolled back as well, but watchers are
+    /// notified of the rollback only for nodes written by [`Self::set`].
+    pub fn params_ctxt(&mut self) -> &mut ParamsCtxt<JournaledCtrl<'a, Ctrl>, &'a mut Ctxt> {
+        &mut self.ctxt
+    }
+
+    fn journaled<R>(
+        &mut self,
+        restore: bool,
+        f: impl FnOnce(&mut ParamsCtxt<JournaledCtrl<'a, Ctrl>, &'a mut Ctxt>) -> GenApiResult<R>,
+    ) -> GenApiResult<R> {
+        self.ctxt.ctrl.restore = restore;
+        let res = f(&mut self.ctxt);
+        self.ctxt.ctrl.restore = true;
+        res
+    }
+
+    fn is_readable(&mut self, node: Node) -> GenApiResult<bool> {
+        let ctxt = &mut self.ctxt;
+        if let Some(node) = node.as_integer(ctxt) {
+            node.is_readable(ctxt)
+        } else if let Some(node) = node.as_float(ctxt) {
+            node.is_readable(ctxt)
+        } else if let Some(node) = node.as_boolean(ctxt) {
+            node.is_readable(ctxt)
+        } else if let Some(node) = node.as_string(ctxt) {
+            node.is_readable(ctxt)
+        } else if let Some(node) = node.as_enumeration(ctxt) {
+            node.is_readable(ctxt)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Records the values which `node` holds in the value store rather than in registers.
+    fn snapshot(&mut self, node: Node) {
+        let snapshots = &mut self.snapshots;
+        self.ctxt.ctxt.enter(|ns, value_ctxt| {
+            for nid in watcher::value_chain(ns, node.0) {
+                let id = match value_id(ns.node_opt(nid)) {
+                    Some(id) => id,
+                    None => continue,
+                };
+                if snapshots.iter().any(|(snapshot_id, _)| *snapshot_id == id) {
+                    continue;
+                }
+                if let Some(value) = value_ctxt.value_store().value_opt(id) {
+                    snapshots.push((id, value.clone()));
+                }
+            }
+        });
+    }
+}
+
+/// Returns the id of the value which `node` holds in the value store.
+fn value_id(node: Option<&NodeData>) -> Option<ValueId> {
+    match node? {
+        NodeData::Integer(n) => n.value_kind().imm().map(Into::into),
+        NodeData::Float(n) => n.value_kind().imm().map(Into::into),
+        NodeData::Boolean(n) => n.value_elem().imm().map(Into::into),
+        NodeData::Enumeration(n) => n.value_elem().imm().map(Into::into),
+        NodeData::String(n) => n.value_elem().imm().map(Into::into),
+        _ => None,
+    }
+}
+
+/// A [`DeviceControl`] which records the value of a register before writing it.
+pub struct JournaledCtrl<'a, Ctrl> {
+    inner: &'a mut Ctrl,
+    /// Writes applied in the transaction, in the order they were applied.
+    journal: Vec<JournalEntry>,
+    /// `false` while writing a node whose registers can't be read.
+    restore: bool,
+}
+
+struct JournalEntry {
+    address: u64,
+    /// The value of the register before the write, `None` if the register isn't restored.
+    prior_value: Option<Vec<u8>>,
+}
+
+impl<'a, Ctrl: DeviceControl> DeviceControl for JournaledCtrl<'a, Ctrl> {
+    fn open(&mut self) -> ControlResult<()> {
+        self.inner.open()
+    }
+
+    fn close(&mut self) -> ControlResult<()> {
+        self.inner.close()
+    }
+
+    fn is_opened(&self) -> bool {
+        self.inner.is_opened()
+    }
+
+    fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+        self.inner.read(address, buf)
+    }
+
+    /// Reads the prior value of the register, then writes `data`. The write is refused if the
+    /// prior value can't be read, because it couldn't be rolled back, unless the write is issued
+    /// by [`Transaction::execute`] or by [`Transaction::set`] to a node which isn't readable.
+    fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+        let prior_value = if self.restore {
+            let mut prior_value = vec![0; data.len()];
+            self.inner.read(address, &mut prior_value)?;
+            Some(prior_value)
+        } else {
+            None
+        };
+        self.inner.write(address, data)?;
+        self.journal.push(JournalEntry {
+            address,
+            prior_value,
+        });
+        Ok(())
+    }
+
+    fn genapi(&mut self) -> ControlResult<String> {
+        self.inner.genapi()
+    }
+
+    fn enable_streaming(&mut self) -> ControlResult<()> {
+        self.inner.enable_streaming()
+    }
+
+    fn disable_streaming(&mut self) -> ControlResult<()> {
+        self.inner.disable_streaming()
+    }
+}
+
+pub(super) fn run<Ctrl, Ctxt, F, R>(
+    params_ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    f: F,
+) -> TransactionResult<R>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+    F: FnOnce(&mut Transaction<'_, Ctrl, Ctxt>) -> GenApiResult<R>,
+{
+    let mut tx = Transaction {
+        ctxt: ParamsCtxt {
+            ctrl: JournaledCtrl {
+                inner: &mut params_ctxt.ctrl,
+                journal: vec![],
+                restore: true,
+            },
+            ctxt: &mut params_ctxt.ctxt,
+        },
+        written: vec![],
+        snapshots: vec![],
+    };
+    let cause = match f(&mut tx) {
+        Ok(res) => return Ok(res),
+        Err(cause) => cause,
+    };
+    let Transaction {
+        ctxt,
+        written,
+        snapshots,
+    } = tx;
+    let journal = ctxt.ctrl.journal;
+
+    // Restores the registers in the reverse order, so that a selector is restored after the
+    // features it selects are restored.
+    let mut failures = vec![];
+    let mut unrestored = vec![];
+    for JournalEntry {
+        address,
+        prior_value,
+    } in journal.into_iter().rev()
+    {
+        let prior_value = match prior_value {
+            Some(prior_value) => prior_value,
+            None => {
+                unrestored.push(address);
+                continue;
+            }
+        };
+        if let Err(error) = params_ctxt.ctrl.write(address, &prior_value) {
+            failures.push(RollbackFailure {
+                address,
+                prior_value,
+                error,
+            });
+        }
+    }
+    unrestored.reverse();
+
+    // Cached values are inconsistent with the device and the restored value store after the
+    // rollback.
+    params_ctxt.ctxt.enter(|_, value_ctxt| {
+        for (id, value) in snapshots {
+            value_ctxt.value_store_mut().update(id, value);
+        }
+        value_ctxt.clear_cache();
+    });
+    for nid in written {
+        params_ctxt.notify(nid, None);
+    }
+
+    if failures.is_empty() {
+        Err(TransactionError::RolledBack { cause, unrestored })
+    } else {
+        Err(TransactionError::RollbackFailed {
+            cause,
+            failures,
+            unrestored,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::{
+        super::{DefaultGenApiCtxt, FeatureChange, FromXml},
+        *,
+    };
+
+    const XML: &str = r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="2"
+          SubMinorVersion="3"
+          ToolTip="ToolTiptest"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210"
+          xmlns="http://www.genicam.org/GenApi/Version_1_0"
+          xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+          xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_0 GenApiSchema.xsd">
+
+            <Enumeration Name="PixelFormat">
+                <EnumEntry Name="Mono8">
+                    <Value>1</Value>
+                </EnumEntry>
+                <EnumEntry Name="RGB8">
+                    <Value>2</Value>
+                </EnumEntry>
+                <pValue>PixelFormatReg</pValue>
+            </Enumeration>
+
+            <IntReg Name="PixelFormatReg">
+              <Address>0x0</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <IntReg Name="Width">
+              <Address>0x4</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Enumeration Name="GainSelector">
+                <EnumEntry Name="AnalogAll">
+                    <Value>0</Value>
+                </EnumEntry>
+                <EnumEntry Name="DigitalAll">
+                    <Value>1</Value>
+                </EnumEntry>
+                <pValue>GainSelectorReg</pValue>
+                <pSelected>Gain</pSelected>
+            </Enumeration>
+
+            <IntReg Name="GainSelectorReg">
+              <Address>0x8</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <IntReg Name="Gain">
+              <Address>0xc</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <IntReg Name="BrokenReg">
+              <Address>0x10</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Integer Name="HostSelector">
+                <Value>0</Value>
+                <Min>0</Min>
+                <Max>1</Max>
+                <pSelected>Width</pSelected>
+            </Integer>
+
+            <IntReg Name="WriteOnlyReg">
+              <Address>0x14</Address>
+              <Length>4</Length>
+              <AccessMode>WO</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Command Name="Reset">
+                <pValue>ResetReg</pValue>
+                <CommandValue>1</CommandValue>
+            </Command>
+
+            <IntReg Name="ResetReg">
+              <Address>0x18</Address>
+              <Length>4</Length>
+              <AccessMode>WO</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Port Name="Device">
+            </Port>
+
+        </RegisterDescription>
+        "#;
+
+    const SELECTOR: u64 = 0x8;
+    const GAIN: u64 = 0xc;
+    const WRITE_ONLY: u64 = 0x14;
+    const RESET: u64 = 0x18;
+
+    /// A device whose `Gain` register is multiplexed by the selector.
+    struct Memory {
+        mem: Vec<u8>,
+        gains: [u32; 2],
+        /// Writes to these addresses fail.
+        fail_at: Vec<u64>,
+    }
+
+    impl Memory {
+        fn register(&self, address: u64) -> u32 {
+            if address == GAIN {
+                self.gains[self.register(SELECTOR) as usize]
+            } else {
+                let address = address as usize;
+                u32::from_le_bytes(self.mem[address..address + 4].try_into().unwrap())
+            }
+        }
+    }
+
+    impl DeviceControl for Memory {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            if address == WRITE_ONLY || address == RESET {
+                return Err(ControlError::Io(anyhow::anyhow!("register is write-only")));
+            }
+            buf.copy_from_slice(&self.register(address).to_le_bytes());
+            Ok(())
+        }
+
+        fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+            if self.fail_at.contains(&address) {
+                return Err(ControlError::Io(anyhow::anyhow!("write failed")));
+            }
+
+            if address == GAIN {
+                let selector = self.register(SELECTOR) as usize;
+                self.gains[selector] = u32::from_le_bytes(data.try_into().unwrap());
+            } else {
+                let address = address as usize;
+                self.mem[address..address + data.len()].copy_from_slice(data);
+            }
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            Ok(XML.into())
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+    }
+
+    fn params_ctxt() -> ParamsCtxt<Memory, DefaultGenApiCtxt> {
+        let mut mem = vec![0; 28];
+        // PixelFormat is `Mono8` and Width is 320.
+        mem[0..4].copy_from_slice(&1_u32.to_le_bytes());
+        mem[4..8].copy_from_slice(&320_u32.to_le_bytes());
+        ParamsCtxt {
+            ctrl: Memory {
+                mem,
+                gains: [3, 4],
+                fail_at: vec![0x10],
+            },
+            ctxt: DefaultGenApiCtxt::from_xml(&XML).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_commit() {
+        let mut ctxt = params_ctxt();
+        ctxt.transaction(|tx| {
+            tx.set_by_name("PixelFormat", "RGB8".to_string())?;
+            tx.set_by_name("Width", 640)?;
+            tx.set_by_name("GainSelector", 1)?;
+            tx.set_by_name("Gain", 10)
+        })
+        .unwrap();
+
+        assert_eq!(ctxt.ctrl.register(0x0), 2);
+        assert_eq!(ctxt.ctrl.register(0x4), 640);
+        assert_eq!(ctxt.ctrl.register(SELECTOR), 1);
+        assert_eq!(ctxt.ctrl.gains, [3, 10]);
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut ctxt = params_ctxt();
+        let width = ctxt.node("Width").unwrap().as_integer(&ctxt).unwrap();
+        // Caches the current value.
+        assert_eq!(width.value(&mut ctxt).unwrap(), 320);
+        let watcher = ctxt.subscribe(width.as_node()).unwrap();
+
+        let res = ctxt.transaction(|tx| {
+            tx.set_by_name("PixelFormat", "RGB8".to_string())?;
+            tx.set_by_name("Width", 640)?;
+            tx.set_by_name("GainSelector", 1)?;
+            tx.set_by_name("Gain", 10)?;
+            tx.set_by_name("GainSelector", 0)?;
+            tx.set_by_name("Gain", 20)?;
+            tx.set_by_name("BrokenReg", 1)
+        });
+        assert!(matches!(
+            res,
+            Err(TransactionError::RolledBack {
+                cause: GenApiError::Device(_),
+                ..
+            })
+        ));
+
+        assert_eq!(ctxt.ctrl.register(0x0), 1);
+        assert_eq!(ctxt.ctrl.register(0x4), 320);
+        assert_eq!(ctxt.ctrl.register(SELECTOR), 0);
+        assert_eq!(ctxt.ctrl.gains, [3, 4]);
+
+        // Cached values are discarded.
+        assert_eq!(width.value(&mut ctxt).unwrap(), 320);
+        let pixel_format = ctxt.node("PixelFormat").unwrap();
+        let entry = pixel_format
+            .as_enumeration(&ctxt)
+            .unwrap()
+            .current_entry(&mut ctxt)
+            .unwrap();
+        assert_eq!(entry.value(), 1);
+
+        // The watcher observes the write and then the rollback.
+        assert_eq!(
+            watcher.try_recv(),
+            Some(FeatureChange::Changed(FeatureValue::Integer(640)))
+        );
+        assert_eq!(
+            watcher.try_recv(),
+            Some(FeatureChange::Invalidated(width.as_node()))
+        );
+    }
+
+    #[test]
+    fn test_rollback_failure() {
+        let mut ctxt = params_ctxt();
+        let res = ctxt.transaction(|tx| {
+            tx.set_by_name("PixelFormat", "RGB8".to_string())?;
+            tx.set_by_name("Width", 640)?;
+            // Width can't be restored.
+            tx.ctxt.ctrl.inner.fail_at.push(0x4);
+            tx.set_by_name("BrokenReg", 1)
+        });
+
+        match res {
+            Err(TransactionError::RollbackFailed { failures, .. }) => {
+                assert_eq!(failures.len(), 1);
+                assert_eq!(failures[0].address, 0x4);
+                assert_eq!(failures[0].prior_value, 320_u32.to_le_bytes());
+            }
+            _ => panic!("rollback must fail"),
+        }
+        // Other writes are rolled back.
+        assert_eq!(ctxt.ctrl.register(0x0), 1);
+        assert_eq!(ctxt.ctrl.register(0x4), 640);
+    }
+
+    #[test]
+    fn test_rollback_unrestored() {
+        let mut ctxt = params_ctxt();
+        let reset = ctxt.node("Reset").unwrap();
+        let res = ctxt.transaction(|tx| {
+            tx.set_by_name("HostSelector", 1)?;
+            tx.set_by_name("Width", 640)?;
+            tx.set_by_name("WriteOnlyReg", 5)?;
+            tx.execute(reset)?;
+            tx.set_by_name("BrokenReg", 1)
+        });
+
+        match res {
+            Err(TransactionError::RolledBack { unrestored, .. }) => {
+                assert_eq!(unrestored, [WRITE_ONLY, RESET]);
+            }
+            _ => panic!("transaction must be rolled back"),
+        }
+        assert_eq!(ctxt.ctrl.register(0x4), 320);
+        // Write-only registers are left as written.
+        assert_eq!(ctxt.ctrl.register(WRITE_ONLY), 5);
+        assert_eq!(ctxt.ctrl.register(RESET), 1);
+
+        // The selector held in the value store is restored.
+        let selector = ctxt.node("HostSelector").unwrap();
+        let selector = selector.as_integer(&ctxt).unwrap();
+        assert_eq!(selector.value(&mut ctxt).unwrap(), 0);
+    }
+}
@@ -0,0 +1,634 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides subscription to changes of `GenApi` features.
+//!
+//! Watchers never read the device on their own, they are notified when the context writes or
+//! invalidates a feature. The value is delivered only when it's known without an additional read,
+//! or when the watcher is subscribed with [`ParamsCtxt::subscribe_eager`].
+//!
+//! [`ParamsCtxt::subscribe_eager`]: super::ParamsCtxt::subscribe_eager
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, Weak},
+};
+
+use async_std::channel::{Receiver, Sender};
+use cameleon_genapi::{
+    interface::INode,
+    store::{NodeData, NodeId, NodeStore},
+};
+
+use super::Node;
+
+/// Maximum depth of `pValue` chain which is followed to find the underlying register of a node.
+const MAX_CHAIN_DEPTH: usize = 16;
+
+/// Maximum number of nodes followed to find the nodes which a node depends on.
+const MAX_DEPENDENCIES: usize = 64;
+
+/// A value of a feature.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureValue {
+    /// A value of `IInteger` or `IEnumeration` node.
+    Integer(i64),
+    /// A value of `IFloat` node.
+    Float(f64),
+    /// A value of `IBoolean` node.
+    Boolean(bool),
+    /// A value of `IString` node.
+    String(String),
+}
+
+impl From<i64> for FeatureValue {
+    fn from(value: i64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<f64> for FeatureValue {
+    fn from(value: f64) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<bool> for FeatureValue {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<String> for FeatureValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+/// A change notification delivered to [`FeatureWatcher`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeatureChange {
+    /// The feature has a new value.
+    Changed(FeatureValue),
+
+    /// The value of the feature may be changed by the contained node. The feature needs to be
+    /// read to know the new value.
+    Invalidated(Node),
+}
+
+/// Receives change notifications of a feature.
+///
+/// The subscription is cancelled when the watcher is dropped.
+#[derive(Debug)]
+pub struct FeatureWatcher {
+    node: Node,
+    id: u64,
+    rx: Receiver<FeatureChange>,
+    registry: Weak<Mutex<Registry>>,
+}
+
+impl FeatureWatcher {
+    /// Returns the node which the watcher is subscribing to.
+    pub fn node(&self) -> Node {
+        self.node
+    }
+
+    /// Receives a change notification.
+    ///
+    /// Returns `None` if the `GenApi` context is dropped and no notification is left.
+    pub async fn recv(&self) -> Option<FeatureChange> {
+        self.rx.recv().await.ok()
+    }
+
+    /// Tries to receive a change notification.
+    /// This method doesn't wait arrival of a notification and immediately returns `None` if there
+    /// is no pending notification.
+    pub fn try_recv(&self) -> Option<FeatureChange> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Drop for FeatureWatcher {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry.upgrade() {
+            let mut registry = registry.lock().unwrap();
+            registry.entries.retain(|ent| ent.id != self.id);
+        }
+    }
+}
+
+/// Subscriptions of features in a `GenApi` context.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureWatchers {
+    inner: Arc<Mutex<Registry>>,
+}
+
+impl FeatureWatchers {
+    /// Returns `true` if no watcher is subscribing.
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().entries.is_empty()
+    }
+
+    pub(super) fn subscribe(&self, node: Node, eager: bool) -> FeatureWatcher {
+        let (tx, rx) = async_std::channel::unbounded();
+        let mut registry = self.inner.lock().unwrap();
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.entries.push(Entry {
+            id,
+            nid: node.0,
+            eager,
+            tx,
+        });
+
+        FeatureWatcher {
+            node,
+            id,
+            rx,
+            registry: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Returns watchers whose feature may be changed by `source`.
+    pub(super) fn affected_by(&self, ns: &impl NodeStore, source: NodeId) -> Vec<Target> {
+        let registry = self.inner.lock().unwrap();
+        if registry.entries.is_empty() {
+            return vec![];
+        }
+
+        let changed = changed_by(ns, source);
+        registry
+            .entries
+            .iter()
+            .filter(|ent| {
+                dependencies(ns, ent.nid).into_iter().any(|nid| {
+                    changed.contains(&nid)
+                        || p_invalidators(ns, nid)
+                            .iter()
+                            .any(|inv| changed.contains(inv))
+                })
+            })
+            .map(|ent| Target {
+                nid: ent.nid,
+                eager: ent.eager,
+                tx: ent.tx.clone(),
+            })
+            .collect()
+    }
+}
+
+/// A watcher to be notified.
+pub(super) struct Target {
+    pub(super) nid: NodeId,
+    pub(super) eager: bool,
+    tx: Sender<FeatureChange>,
+}
+
+impl Target {
+    pub(super) fn notify(&self, change: FeatureChange) {
+        // The channel is unbounded, so sending fails only when the watcher is already dropped.
+        self.tx.try_send(change).ok();
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    next_id: u64,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: u64,
+    nid: NodeId,
+    eager: bool,
+    tx: Sender<FeatureChange>,
+}
+
+/// Returns the node itself and the nodes which the node refers to as its value via `pValue`.
+pub(super) fn value_chain(ns: &impl NodeStore, nid: NodeId) -> Vec<NodeId> {
+    let mut chain = vec![nid];
+    let mut current = nid;
+    while chain.len() < MAX_CHAIN_DEPTH {
+        let next = match ns.node_opt(current) {
+            Some(NodeData::Integer(n)) => n.value_kind().p_value().map(|p| p.p_value()),
+            Some(NodeData::Float(n)) => n.value_kind().p_value().map(|p| p.p_value()),
+            Some(NodeData::Boolean(n)) => n.value_elem().pnode(),
+            Some(NodeData::Command(n)) => n.value_elem().pnode(),
+            Some(NodeData::Enumeration(n)) => n.value_elem().pnode(),
+            Some(NodeData::String(n)) => n.value_elem().pnode(),
+            Some(NodeData::Converter(n)) => Some(n.p_value()),
+            Some(NodeData::IntConverter(n)) => Some(n.p_value()),
+            _ => None,
+        };
+        match next {
+            Some(next) if !chain.contains(&next) => {
+                chain.push(next);
+                current = next;
+            }
+            _ => break,
+        }
+    }
+    chain
+}
+
+/// Returns the nodes whose value may be changed when `source` is changed, i.e. the value chain of
+/// `source` and the value chains of the nodes selected by `source` via `pSelected`.
+pub(super) fn changed_by(ns: &impl NodeStore, source: NodeId) -> Vec<NodeId> {
+    let mut changed = value_chain(ns, source);
+    let selected: Vec<NodeId> = changed
+        .iter()
+        .flat_map(|nid| p_selected(ns, *nid).iter().copied())
+        .collect();
+    for nid in selected {
+        extend_unique(&mut changed, value_chain(ns, nid));
+    }
+    changed
+}
+
+/// Returns the nodes which the value or the range of `nid` depends on, i.e. the value chain of
+/// `nid` and the nodes referred to via `pMin`, `pMax` and `pInc` along the chain.
+fn dependencies(ns: &impl NodeStore, nid: NodeId) -> Vec<NodeId> {
+    let mut deps = value_chain(ns, nid);
+    let mut i = 0;
+    while i < deps.len() && deps.len() < MAX_DEPENDENCIES {
+        for limit in p_limits(ns, deps[i]) {
+            extend_unique(&mut deps, value_chain(ns, limit));
+        }
+        i += 1;
+    }
+    deps
+}
+
+fn extend_unique(nodes: &mut Vec<NodeId>, other: Vec<NodeId>) {
+    for nid in other {
+        if !nodes.contains(&nid) {
+            nodes.push(nid);
+        }
+    }
+}
+
+fn p_limits(ns: &impl NodeStore, nid: NodeId) -> Vec<NodeId> {
+    match ns.node_opt(nid) {
+        Some(NodeData::Integer(n)) => [
+            n.min_elem().pnode(),
+            n.max_elem().pnode(),
+            n.inc_elem().pnode(),
+        ]
+        .iter()
+        .flatten()
+        .copied()
+        .collect(),
+        Some(NodeData::Float(n)) => [
+            n.min_elem().pnode(),
+            n.max_elem().pnode(),
+            n.inc_elem().and_then(|inc| inc.pnode()),
+        ]
+        .iter()
+        .flatten()
+        .copied()
+        .collect(),
+        _ => vec![],
+    }
+}
+
+fn p_selected(ns: &impl NodeStore, nid: NodeId) -> &[NodeId] {
+    match ns.node_opt(nid) {
+        Some(NodeData::Integer(n)) => n.p_selected(),
+        Some(NodeData::Enumeration(n)) => n.p_selected(),
+        Some(NodeData::Boolean(n)) => n.p_selected(),
+        Some(NodeData::IntReg(n)) => n.p_selected(),
+        Some(NodeData::MaskedIntReg(n)) => n.p_selected(),
+        _ => &[],
+    }
+}
+
+fn p_invalidators(ns: &impl NodeStore, nid: NodeId) -> &[NodeId] {
+    match ns.node_opt(nid) {
+        Some(NodeData::IntReg(n)) => n.register_base().p_invalidators(),
+        Some(NodeData::MaskedIntReg(n)) => n.register_base().p_invalidators(),
+        Some(NodeData::FloatReg(n)) => n.register_base().p_invalidators(),
+        Some(NodeData::StringReg(n)) => n.register_base().p_invalidators(),
+        Some(NodeData::Register(n)) => n.register_base().p_invalidators(),
+        _ => &[],
+    }
+}
+
+/// Returns the registers which are read through ports having `ChunkID`.
+pub(super) fn chunk_registers(ns: &impl NodeStore) -> Vec<NodeId> {
+    let mut chunk_ports = HashSet::new();
+    ns.visit_nodes(|node| {
+        if let NodeData::Port(n) = node {
+            if n.chunk_id().is_some() {
+                chunk_ports.insert(n.node_base().id());
+            }
+        }
+    });
+    registers_on(ns, &chunk_ports)
+}
+
+/// Returns the nodes whose `EventID` is `event_id`, and the registers which are read through
+/// ports whose `EventID` is `event_id`.
+pub(super) fn event_nodes(ns: &impl NodeStore, event_id: u64) -> Vec<NodeId> {
+    let mut nodes = vec![];
+    let mut event_ports = HashSet::new();
+    ns.visit_nodes(|node| {
+        let base = node.node_base();
+        if base.event_id() == Some(event_id) {
+            nodes.push(base.id());
+            if let NodeData::Port(_) = node {
+                event_ports.insert(base.id());
+            }
+        }
+    });
+    extend_unique(&mut nodes, registers_on(ns, &event_ports));
+    nodes
+}
+
+fn registers_on(ns: &impl NodeStore, ports: &HashSet<NodeId>) -> Vec<NodeId> {
+    let mut registers = vec![];
+    ns.visit_nodes(|node| {
+        let (nid, p_port) = match node {
+            NodeData::IntReg(n) => (n.node_base().id(), n.register_base().p_port()),
+            NodeData::MaskedIntReg(n) => (n.node_base().id(), n.register_base().p_port()),
+            NodeData::FloatReg(n) => (n.node_base().id(), n.register_base().p_port()),
+            NodeData::StringReg(n) => (n.node_base().id(), n.register_base().p_port()),
+            NodeData::Register(n) => (n.node_base().id(), n.register_base().p_port()),
+            _ => return,
+        };
+        if ports.contains(&p_port) {
+            registers.push(nid);
+        }
+    });
+    registers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{DefaultGenApiCtxt, FromXml, GenApiCtxt, ParamsCtxt},
+        *,
+    };
+    use crate::{ControlResult, DeviceControl};
+
+    const XML: &str = r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="2"
+          SubMinorVersion="3"
+          ToolTip="ToolTiptest"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210"
+          xmlns="http://www.genicam.org/GenApi/Version_1_0"
+          xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+          xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_0 GenApiSchema.xsd">
+
+            <Integer Name="ExposureTime">
+                <pValue>ExposureTimeReg</pValue>
+            </Integer>
+
+            <IntReg Name="ExposureTimeReg">
+              <Address>0x0</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <IntReg Name="ChunkExposureTime">
+              <Address>0x4</Address>
+              <Length>4</Length>
+              <AccessMode>RO</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <IntReg Name="DeviceTemperature">
+              <Address>0x8</Address>
+              <Length>4</Length>
+              <AccessMode>RO</AccessMode>
+              <pPort>Device</pPort>
+              <pInvalidator>EventTemperature</pInvalidator>
+            </IntReg>
+
+            <Integer Name="EventTemperature">
+                <Value>0</Value>
+            </Integer>
+
+            <Integer Name="Gain">
+                <pValue>GainReg</pValue>
+                <pMax>GainMax</pMax>
+            </Integer>
+
+            <IntReg Name="GainReg">
+              <Address>0xc</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Integer Name="GainMax">
+                <Value>10</Value>
+            </Integer>
+
+            <Integer Name="GainSelector">
+                <Value>0</Value>
+                <pSelected>Gain</pSelected>
+            </Integer>
+
+            <IntReg Name="EventExposureEndFrameID">
+              <Address>0x0</Address>
+              <Length>4</Length>
+              <AccessMode>RO</AccessMode>
+              <pPort>EventExposureEnd</pPort>
+            </IntReg>
+
+            <Port Name="EventExposureEnd">
+                <EventID>9001</EventID>
+            </Port>
+
+            <Port Name="Device">
+            </Port>
+
+        </RegisterDescription>
+        "#;
+
+    struct Memory(Vec<u8>);
+
+    impl DeviceControl for Memory {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            let address = address as usize;
+            buf.copy_from_slice(&self.0[address..address + buf.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+            let address = address as usize;
+            self.0[address..address + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            Ok(XML.into())
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+    }
+
+    fn params_ctxt() -> ParamsCtxt<Memory, DefaultGenApiCtxt> {
+        ParamsCtxt {
+            ctrl: Memory(vec![0; 16]),
+            ctxt: DefaultGenApiCtxt::from_xml(&XML).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_local_write() {
+        let mut ctxt = params_ctxt();
+        let node = ctxt.node("ExposureTime").unwrap();
+        let watcher = ctxt.subscribe(node).unwrap();
+
+        node.as_integer(&ctxt)
+            .unwrap()
+            .set_value(&mut ctxt, 10)
+            .unwrap();
+        assert_eq!(
+            watcher.try_recv(),
+            Some(FeatureChange::Changed(FeatureValue::Integer(10)))
+        );
+        assert_eq!(watcher.try_recv(), None);
+    }
+
+    #[test]
+    fn test_chunk_update() {
+        let mut ctxt = params_ctxt();
+        let node = ctxt.node("ChunkExposureTime").unwrap();
+        let watcher = ctxt.subscribe_eager(node).unwrap();
+
+        // Caches the current value.
+        let chunk_node = node.as_integer(&ctxt).unwrap();
+        assert_eq!(chunk_node.value(&mut ctxt).unwrap(), 0);
+
+        // A new chunk arrives.
+        ctxt.ctrl.0[4..8].copy_from_slice(&20_u32.to_le_bytes());
+        ctxt.invalidate(node);
+        assert_eq!(
+            watcher.try_recv(),
+            Some(FeatureChange::Changed(FeatureValue::Integer(20)))
+        );
+    }
+
+    #[test]
+    fn test_event_invalidation() {
+        let mut ctxt = params_ctxt();
+        let node = ctxt.node("DeviceTemperature").unwrap();
+        let event_node = ctxt.node("EventTemperature").unwrap();
+        let watcher = ctxt.subscribe(node).unwrap();
+
+        ctxt.invalidate(event_node);
+        assert_eq!(
+            watcher.try_recv(),
+            Some(FeatureChange::Invalidated(event_node))
+        );
+
+        // Unrelated node doesn't notify the watcher.
+        let exposure_node = ctxt.node("ExposureTime").unwrap();
+        ctxt.invalidate(exposure_node);
+        assert_eq!(watcher.try_recv(), None);
+    }
+
+    #[test]
+    fn test_device_event() {
+        let mut ctxt = params_ctxt();
+        let node = ctxt.node("EventExposureEndFrameID").unwrap();
+        let watcher = ctxt.subscribe(node).unwrap();
+
+        ctxt.invalidate_event(0x1234);
+        assert_eq!(watcher.try_recv(), None);
+
+        ctxt.invalidate_event(0x9001);
+        assert!(matches!(
+            watcher.try_recv(),
+            Some(FeatureChange::Invalidated(_))
+        ));
+    }
+
+    #[test]
+    fn test_limit_and_selector() {
+        let mut ctxt = params_ctxt();
+        let node = ctxt.node("Gain").unwrap();
+        let max_node = ctxt.node("GainMax").unwrap();
+        let selector_node = ctxt.node("GainSelector").unwrap();
+        let watcher = ctxt.subscribe(node).unwrap();
+        let selector_watcher = ctxt.subscribe(selector_node).unwrap();
+
+        max_node
+            .as_integer(&ctxt)
+            .unwrap()
+            .set_value(&mut ctxt, 20)
+            .unwrap();
+        assert_eq!(
+            watcher.try_recv(),
+            Some(FeatureChange::Invalidated(max_node))
+        );
+
+        selector_node
+            .as_integer(&ctxt)
+            .unwrap()
+            .set_value(&mut ctxt, 1)
+            .unwrap();
+        assert_eq!(
+            watcher.try_recv(),
+            Some(FeatureChange::Invalidated(selector_node))
+        );
+        assert_eq!(
+            selector_watcher.try_recv(),
+            Some(FeatureChange::Changed(FeatureValue::Integer(1)))
+        );
+
+        // Writes to the selected node don't change the selector.
+        node.as_integer(&ctxt)
+            .unwrap()
+            .set_value(&mut ctxt, 5)
+            .unwrap();
+        assert_eq!(
+            watcher.try_recv(),
+            Some(FeatureChange::Changed(FeatureValue::Integer(5)))
+        );
+        assert_eq!(selector_watcher.try_recv(), None);
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let ctxt = params_ctxt();
+        let node = ctxt.node("ExposureTime").unwrap();
+        let watcher = ctxt.subscribe(node).unwrap();
+        assert!(!ctxt.ctxt.watchers().unwrap().is_empty());
+
+        drop(watcher);
+        assert!(ctxt.ctxt.watchers().unwrap().is_empty());
+    }
+}
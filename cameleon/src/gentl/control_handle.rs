@@ -0,0 +1,343 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains the device control handle which accesses the remote device through a
+//! `GenTL` producer.
+
+use std::{
+    convert::TryInto,
+    ffi::{c_void, CString},
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use tracing::error;
+
+use crate::{
+    camera::DeviceControl,
+    limits::Limits,
+    load_options::{self, GenApiFile},
+    ControlError, ControlResult,
+};
+
+use super::{ffi, DeviceModules, Interface};
+
+/// This handle provides low level API to read and write data from the device through the remote
+/// device port of a `GenTL` producer.
+pub struct ControlHandle {
+    iface: Arc<Interface>,
+    device_id: CString,
+    modules: Arc<Mutex<DeviceModules>>,
+    limits: Limits,
+}
+
+macro_rules! unwrap_or_log {
+    ($expr:expr) => {{
+        match $expr {
+            Ok(v) => v,
+            Err(error) => {
+                let error: ControlError = error.into();
+                error!(?error);
+                return Err(error);
+            }
+        }
+    }};
+}
+
+impl ControlHandle {
+    /// Returns the limits on the values claimed by the device.
+    #[must_use]
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Sets the limits on the values claimed by the device.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub(super) fn new(
+        iface: Arc<Interface>,
+        device_id: CString,
+        modules: Arc<Mutex<DeviceModules>>,
+    ) -> Self {
+        Self {
+            iface,
+            device_id,
+            modules,
+            limits: Limits::default(),
+        }
+    }
+
+    fn modules(&self) -> ControlResult<MutexGuard<'_, DeviceModules>> {
+        self.modules
+            .lock()
+            .map_err(|e| ControlError::Io(anyhow::Error::msg(e.to_string())))
+    }
+
+    fn port(&self) -> ControlResult<ffi::RawHandle> {
+        self.modules()?.port.ok_or(ControlError::NotOpened)
+    }
+
+    fn xml_url(&self) -> ControlResult<String> {
+        let port = self.port()?;
+        let producer = self.iface.producer();
+        Ok(unwrap_or_log!(producer.info_string(
+            |ty, buf, size| unsafe {
+                (producer.GCGetPortURLInfo)(port.0, 0, ffi::URL_INFO_URL, ty, buf, size)
+            }
+        )))
+    }
+}
+
+impl DeviceControl for ControlHandle {
+    fn open(&mut self) -> ControlResult<()> {
+        if self.is_opened() {
+            return Ok(());
+        }
+
+        let producer = self.iface.producer().clone();
+        let mut device = ffi::RawHandle::null();
+        unwrap_or_log!(producer.check(unsafe {
+            (producer.IFOpenDevice)(
+                self.iface.handle.0,
+                self.device_id.as_ptr(),
+                ffi::DEVICE_ACCESS_CONTROL,
+                &mut device.0,
+            )
+        }));
+        let mut port = ffi::RawHandle::null();
+        if let Err(e) = producer.check(unsafe { (producer.DevGetPort)(device.0, &mut port.0) }) {
+            unsafe { (producer.DevClose)(device.0) };
+            return Err(unwrap_or_log!(Err(e)));
+        }
+
+        let mut modules = self.modules()?;
+        modules.device = Some(device);
+        modules.port = Some(port);
+        Ok(())
+    }
+
+    fn close(&mut self) -> ControlResult<()> {
+        let producer = self.iface.producer().clone();
+        let mut modules = self.modules()?;
+        if let Some(data_stream) = modules.data_stream.take() {
+            unwrap_or_log!(producer.check(unsafe { (producer.DSClose)(data_stream.0) }));
+        }
+        modules.port = None;
+        if let Some(device) = modules.device.take() {
+            unwrap_or_log!(producer.check(unsafe { (producer.DevClose)(device.0) }));
+        }
+        Ok(())
+    }
+
+    fn is_opened(&self) -> bool {
+        matches!(self.modules(), Ok(modules) if modules.device.is_some())
+    }
+
+    fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+        let port = self.port()?;
+        let producer = self.iface.producer();
+        let mut size = buf.len();
+        unwrap_or_log!(producer.check(unsafe {
+            (producer.GCReadPort)(
+                port.0,
+                address,
+                buf.as_mut_ptr().cast::<c_void>(),
+                &mut size,
+            )
+        }));
+        if size == buf.len() {
+            Ok(())
+        } else {
+            Err(ControlError::InvalidDevice(
+                format!("read {} bytes from the port, expected {}", size, buf.len()).into(),
+            ))
+        }
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+        let port = self.port()?;
+        let producer = self.iface.producer();
+        let mut size = data.len();
+        unwrap_or_log!(producer.check(unsafe {
+            (producer.GCWritePort)(port.0, address, data.as_ptr().cast::<c_void>(), &mut size)
+        }));
+        if size == data.len() {
+            Ok(())
+        } else {
+            Err(ControlError::InvalidDevice(
+                format!("wrote {} bytes to the port, expected {}", size, data.len()).into(),
+            ))
+        }
+    }
+
+    fn genapi(&mut self) -> ControlResult<String> {
+        let url = self.xml_url()?;
+        let (file_name, buf) = match unwrap_or_log!(XmlLocation::parse(&url)) {
+            XmlLocation::Local {
+                file_name,
+                address,
+                size,
+            } => {
+                unwrap_or_log!(self.limits.check_xml_size(size));
+                let size: usize = unwrap_or_log!(size.try_into());
+                let mut buf = vec![0; size];
+                unwrap_or_log!(self.read(address, &mut buf));
+                (file_name, buf)
+            }
+            XmlLocation::File(path) => {
+                let file_name = path.to_string_lossy().into_owned();
+                let buf =
+                    unwrap_or_log!(std::fs::read(&path).map_err(|e| ControlError::Io(e.into())));
+                unwrap_or_log!(self.limits.check_xml_size(buf.len()));
+                (file_name, buf)
+            }
+        };
+
+        // The producer doesn't provide the hash, so only the compression is relevant.
+        let file = GenApiFile {
+            address: 0,
+            size: buf.len(),
+            zipped: file_name.to_ascii_lowercase().ends_with(".zip"),
+            sha1: None,
+            max_xml_size: self.limits.max_xml_size,
+        };
+        Ok(unwrap_or_log!(load_options::decode_xml(buf, &file)))
+    }
+
+    /// Does nothing, the producer configures its transport layer when the acquisition is started.
+    fn enable_streaming(&mut self) -> ControlResult<()> {
+        Ok(())
+    }
+
+    /// Does nothing, the producer configures its transport layer when the acquisition is stopped.
+    fn disable_streaming(&mut self) -> ControlResult<()> {
+        Ok(())
+    }
+
+    fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+}
+
+impl Drop for ControlHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!(?e)
+        }
+    }
+}
+
+impl From<ControlHandle> for Box<dyn DeviceControl> {
+    fn from(ctrl: ControlHandle) -> Self {
+        Box::new(ctrl)
+    }
+}
+
+/// Location of `GenApi` XML, parsed from the URL returned by the producer.
+#[derive(Debug, PartialEq, Eq)]
+enum XmlLocation {
+    /// `Local:{file_name};{address};{size}[?SchemaVersion=..]`, address and size are hex numbers.
+    Local {
+        file_name: String,
+        address: u64,
+        size: u64,
+    },
+    /// `File:{path}[?SchemaVersion=..]`.
+    File(PathBuf),
+}
+
+impl XmlLocation {
+    fn parse(url: &str) -> ControlResult<Self> {
+        let err =
+            || ControlError::InvalidDevice(format!("invalid `GenApi` xml url: {}", url).into());
+
+        let (scheme, rest) = url.split_once(':').ok_or_else(err)?;
+        // Query is ignored, `SchemaVersion` is written in the XML itself.
+        let rest = rest.split('?').next().unwrap_or_default();
+        let parse_hex = |s: &str| {
+            let s = s.trim_start_matches("0x").trim_start_matches("0X");
+            u64::from_str_radix(s, 16).map_err(|_| err())
+        };
+
+        match scheme.to_ascii_lowercase().as_str() {
+            "local" => {
+                let mut fields = rest.split(';');
+                let file_name = fields.next().ok_or_else(err)?.to_string();
+                let address = parse_hex(fields.next().ok_or_else(err)?)?;
+                let size = parse_hex(fields.next().ok_or_else(err)?)?;
+                Ok(Self::Local {
+                    file_name,
+                    address,
+                    size,
+                })
+            }
+            "file" => {
+                let path = rest.strip_prefix("///").unwrap_or(rest);
+                // Windows style path is written as `file:///C|/path/to/file.xml`.
+                let path = if path.get(1..2) == Some("|") {
+                    path.replacen('|', ":", 1)
+                } else if url.contains(":///") {
+                    format!("/{}", path)
+                } else {
+                    path.to_string()
+                };
+                Ok(Self::File(path.into()))
+            }
+            _ => Err(err()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_local_url() {
+        let url = "Local:vendor_model.zip;10000;2a00?SchemaVersion=1.1.0";
+        assert_eq!(
+            XmlLocation::parse(url).unwrap(),
+            XmlLocation::Local {
+                file_name: "vendor_model.zip".into(),
+                address: 0x10000,
+                size: 0x2a00,
+            }
+        );
+
+        let url = "local:model.xml;0x100;0x20";
+        assert_eq!(
+            XmlLocation::parse(url).unwrap(),
+            XmlLocation::Local {
+                file_name: "model.xml".into(),
+                address: 0x100,
+                size: 0x20,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_file_url() {
+        assert_eq!(
+            XmlLocation::parse("file:///opt/vendor/model.xml").unwrap(),
+            XmlLocation::File("/opt/vendor/model.xml".into())
+        );
+        assert_eq!(
+            XmlLocation::parse("File:///C|/vendor/model.xml?SchemaVersion=1.0.0").unwrap(),
+            XmlLocation::File("C:/vendor/model.xml".into())
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_url() {
+        assert!(XmlLocation::parse("Local:model.xml;100").is_err());
+        assert!(XmlLocation::parse("Web:http://example.com/model.xml").is_err());
+        assert!(XmlLocation::parse("model.xml").is_err());
+    }
+}
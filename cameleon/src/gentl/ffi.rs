@@ -0,0 +1,255 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains raw bindings to the functions exported by a `GenTL` producer.
+
+#![allow(non_snake_case)]
+
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::c_char,
+    path::Path,
+    ptr,
+};
+
+use libloading::Library;
+
+/// `GC_ERROR` returned from the producer.
+pub(super) type GcError = i32;
+
+pub(super) const GC_ERR_SUCCESS: GcError = 0;
+pub(super) const GC_ERR_RESOURCE_IN_USE: GcError = -1004;
+pub(super) const GC_ERR_ACCESS_DENIED: GcError = -1005;
+pub(super) const GC_ERR_TIMEOUT: GcError = -1011;
+pub(super) const GC_ERR_ABORT: GcError = -1012;
+pub(super) const GC_ERR_BUSY: GcError = -1022;
+
+pub(super) const GENTL_INFINITE: u64 = u64::MAX;
+
+pub(super) const DEVICE_INFO_VENDOR: i32 = 1;
+pub(super) const DEVICE_INFO_MODEL: i32 = 2;
+pub(super) const DEVICE_INFO_SERIAL_NUMBER: i32 = 7;
+
+pub(super) const DEVICE_ACCESS_CONTROL: i32 = 3;
+
+pub(super) const URL_INFO_URL: i32 = 0;
+
+pub(super) const STREAM_INFO_PAYLOAD_SIZE: i32 = 7;
+
+pub(super) const BUFFER_INFO_BASE: i32 = 0;
+pub(super) const BUFFER_INFO_TIMESTAMP: i32 = 3;
+pub(super) const BUFFER_INFO_IS_INCOMPLETE: i32 = 7;
+pub(super) const BUFFER_INFO_SIZE_FILLED: i32 = 9;
+pub(super) const BUFFER_INFO_WIDTH: i32 = 10;
+pub(super) const BUFFER_INFO_HEIGHT: i32 = 11;
+pub(super) const BUFFER_INFO_XOFFSET: i32 = 12;
+pub(super) const BUFFER_INFO_YOFFSET: i32 = 13;
+pub(super) const BUFFER_INFO_XPADDING: i32 = 14;
+pub(super) const BUFFER_INFO_FRAMEID: i32 = 16;
+pub(super) const BUFFER_INFO_IMAGEOFFSET: i32 = 18;
+pub(super) const BUFFER_INFO_PAYLOADTYPE: i32 = 19;
+pub(super) const BUFFER_INFO_PIXELFORMAT: i32 = 20;
+pub(super) const BUFFER_INFO_DELIVERED_CHUNKPAYLOADSIZE: i32 = 23;
+pub(super) const BUFFER_INFO_CHUNKLAYOUTID: i32 = 24;
+pub(super) const BUFFER_INFO_TIMESTAMP_NS: i32 = 28;
+pub(super) const BUFFER_INFO_CONTAINS_CHUNKDATA: i32 = 30;
+
+pub(super) const PAYLOAD_TYPE_IMAGE: usize = 1;
+pub(super) const PAYLOAD_TYPE_CHUNK_DATA: usize = 4;
+pub(super) const PAYLOAD_TYPE_CHUNK_ONLY: usize = 8;
+pub(super) const PAYLOAD_TYPE_GENDC: usize = 11;
+
+pub(super) const EVENT_NEW_BUFFER: i32 = 1;
+
+pub(super) const ACQ_START_FLAGS_DEFAULT: i32 = 0;
+pub(super) const ACQ_STOP_FLAGS_KILL: i32 = 1;
+pub(super) const ACQ_QUEUE_ALL_DISCARD: i32 = 4;
+
+/// A handle of a module opened by the producer.
+///
+/// `GenTL` requires the producer to be thread safe, so the handle can be sent to other threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct RawHandle(pub(super) *mut c_void);
+
+unsafe impl Send for RawHandle {}
+unsafe impl Sync for RawHandle {}
+
+impl RawHandle {
+    pub(super) fn null() -> Self {
+        Self(ptr::null_mut())
+    }
+}
+
+/// `S_EVENT_NEW_BUFFER`.
+#[repr(C)]
+pub(super) struct EventNewBuffer {
+    pub(super) buffer: *mut c_void,
+    pub(super) user_pointer: *mut c_void,
+}
+
+/// An error returned from the producer.
+#[derive(Debug, thiserror::Error)]
+#[error("GenTL producer returned error code {code}: {message}")]
+pub(super) struct ProducerError {
+    pub(super) code: GcError,
+    pub(super) message: String,
+}
+
+pub(super) type ProducerResult<T> = std::result::Result<T, ProducerError>;
+
+type Handle = *mut c_void;
+
+macro_rules! producer_api {
+    ($(fn $name:ident($($arg:ty),*$(,)?);)*) => {
+        /// Function table of the loaded producer.
+        pub(super) struct Producer {
+            $(pub(super) $name: unsafe extern "system" fn($($arg),*) -> GcError,)*
+            /// Keeps the function pointers above valid.
+            _lib: Library,
+        }
+
+        impl Producer {
+            /// Loads the producer from `path` and initializes it.
+            pub(super) fn load(path: &Path) -> ProducerResult<Self> {
+                let load_err = |e: libloading::Error| ProducerError {
+                    code: -1001,
+                    message: format!("failed to load `{}`: {}", path.display(), e),
+                };
+
+                // SAFETY: Loading a library may run its initialization routine, a `.cti` file is
+                // trusted as the user explicitly specifies it.
+                let lib = unsafe { Library::new(path) }.map_err(load_err)?;
+                $(
+                // SAFETY: The signature follows the `GenTL` specification.
+                let $name = *unsafe {
+                    lib.get::<unsafe extern "system" fn($($arg),*) -> GcError>(
+                        concat!(stringify!($name), "\0").as_bytes(),
+                    )
+                }
+                .map_err(load_err)?;
+                )*
+
+                let producer = Self {
+                    $($name,)*
+                    _lib: lib,
+                };
+                producer.check(unsafe { (producer.GCInitLib)() })?;
+                Ok(producer)
+            }
+        }
+    };
+}
+
+producer_api! {
+    fn GCInitLib();
+    fn GCCloseLib();
+    fn GCGetLastError(*mut GcError, *mut c_char, *mut usize);
+    fn TLOpen(*mut Handle);
+    fn TLClose(Handle);
+    fn TLUpdateInterfaceList(Handle, *mut u8, u64);
+    fn TLGetNumInterfaces(Handle, *mut u32);
+    fn TLGetInterfaceID(Handle, u32, *mut c_char, *mut usize);
+    fn TLOpenInterface(Handle, *const c_char, *mut Handle);
+    fn IFClose(Handle);
+    fn IFUpdateDeviceList(Handle, *mut u8, u64);
+    fn IFGetNumDevices(Handle, *mut u32);
+    fn IFGetDeviceID(Handle, u32, *mut c_char, *mut usize);
+    fn IFGetDeviceInfo(Handle, *const c_char, i32, *mut i32, *mut c_void, *mut usize);
+    fn IFOpenDevice(Handle, *const c_char, i32, *mut Handle);
+    fn DevClose(Handle);
+    fn DevGetPort(Handle, *mut Handle);
+    fn DevGetNumDataStreams(Handle, *mut u32);
+    fn DevGetDataStreamID(Handle, u32, *mut c_char, *mut usize);
+    fn DevOpenDataStream(Handle, *const c_char, *mut Handle);
+    fn GCReadPort(Handle, u64, *mut c_void, *mut usize);
+    fn GCWritePort(Handle, u64, *const c_void, *mut usize);
+    fn GCGetPortURLInfo(Handle, u32, i32, *mut i32, *mut c_void, *mut usize);
+    fn DSClose(Handle);
+    fn DSGetInfo(Handle, i32, *mut i32, *mut c_void, *mut usize);
+    fn DSAllocAndAnnounceBuffer(Handle, usize, *mut c_void, *mut Handle);
+    fn DSRevokeBuffer(Handle, Handle, *mut *mut c_void, *mut *mut c_void);
+    fn DSQueueBuffer(Handle, Handle);
+    fn DSFlushQueue(Handle, i32);
+    fn DSStartAcquisition(Handle, i32, u64);
+    fn DSStopAcquisition(Handle, i32);
+    fn DSGetBufferInfo(Handle, Handle, i32, *mut i32, *mut c_void, *mut usize);
+    fn GCRegisterEvent(Handle, i32, *mut Handle);
+    fn GCUnregisterEvent(Handle, i32);
+    fn EventGetData(Handle, *mut c_void, *mut usize, u64);
+    fn EventKill(Handle);
+}
+
+impl Producer {
+    /// Converts `code` into `Result`, the error message is retrieved from the producer.
+    pub(super) fn check(&self, code: GcError) -> ProducerResult<()> {
+        if code == GC_ERR_SUCCESS {
+            return Ok(());
+        }
+
+        let mut last_code = code;
+        let message =
+            read_string(|buf, size| unsafe { (self.GCGetLastError)(&mut last_code, buf, size) })
+                .unwrap_or_default();
+        Err(ProducerError { code, message })
+    }
+
+    /// Retrieves a string by calling `f` twice, first to query the size, and then to fill the
+    /// buffer, as `GenTL` functions do.
+    pub(super) fn string(
+        &self,
+        f: impl FnMut(*mut c_char, *mut usize) -> GcError,
+    ) -> ProducerResult<String> {
+        read_string(f).map_err(|code| self.check(code).unwrap_err())
+    }
+
+    /// Retrieves a value of `*INFO_CMD`, `f` is called with `piType`, `pBuffer`, and `piSize`.
+    pub(super) fn info<T: Copy + Default>(
+        &self,
+        mut f: impl FnMut(*mut i32, *mut c_void, *mut usize) -> GcError,
+    ) -> ProducerResult<T> {
+        let mut info_type = 0;
+        let mut value = T::default();
+        let mut size = std::mem::size_of::<T>();
+        self.check(f(&mut info_type, (&mut value as *mut T).cast(), &mut size))?;
+        Ok(value)
+    }
+
+    /// Retrieves a string value of `*INFO_CMD`, `f` is called with `piType`, `pBuffer`, and
+    /// `piSize`.
+    pub(super) fn info_string(
+        &self,
+        mut f: impl FnMut(*mut i32, *mut c_void, *mut usize) -> GcError,
+    ) -> ProducerResult<String> {
+        let mut info_type = 0;
+        self.string(|buf, size| f(&mut info_type, buf.cast(), size))
+    }
+}
+
+impl Drop for Producer {
+    fn drop(&mut self) {
+        unsafe { (self.GCCloseLib)() };
+    }
+}
+
+fn read_string(
+    mut f: impl FnMut(*mut c_char, *mut usize) -> GcError,
+) -> std::result::Result<String, GcError> {
+    let mut size = 0;
+    let code = f(ptr::null_mut(), &mut size);
+    if code != GC_ERR_SUCCESS {
+        return Err(code);
+    }
+
+    let mut buf = vec![0_u8; size.max(1)];
+    let code = f(buf.as_mut_ptr().cast(), &mut size);
+    if code != GC_ERR_SUCCESS {
+        return Err(code);
+    }
+
+    let s = CStr::from_bytes_until_nul(&buf).map_or_else(
+        |_| String::from_utf8_lossy(&buf).into_owned(),
+        |s| s.to_string_lossy().into_owned(),
+    );
+    Ok(s)
+}
@@ -0,0 +1,274 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides access to cameras through a `GenTL` producer supplied by a vendor.
+//!
+//! `cameleon` acts as a `GenTL` consumer in this mode, the producer (`.cti` file) handles the
+//! transport layer, so cameras whose transport layer isn't supported natively by `cameleon` can be
+//! used with the same [`Camera`] API.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use cameleon::gentl;
+//!
+//! // Enumerates cameras found by the producer.
+//! let mut cameras = gentl::enumerate_cameras("/opt/vendor/lib/vendor.cti").unwrap();
+//!
+//! if cameras.is_empty() {
+//!     return;
+//! }
+//!
+//! let mut camera = cameras.pop().unwrap();
+//! camera.open().unwrap();
+//! camera.load_context().unwrap();
+//!
+//! let payload_rx = camera.start_streaming(3).unwrap();
+//! let payload = async_std::task::block_on(payload_rx.recv()).unwrap();
+//! println!("{}", payload.frame_id());
+//!
+//! camera.close().unwrap();
+//! ```
+#![allow(clippy::missing_panics_doc)]
+
+pub mod control_handle;
+pub mod stream_handle;
+
+mod ffi;
+
+pub use control_handle::ControlHandle;
+pub use stream_handle::StreamHandle;
+
+use std::{
+    ffi::{c_void, CString},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use tracing::warn;
+
+use super::{
+    genapi::DefaultGenApiCtxt, CameleonResult, Camera, CameraInfo, ControlError, StreamError,
+};
+
+use ffi::{Producer, ProducerError, RawHandle};
+
+/// Timeout of updating interface and device lists in milliseconds.
+const UPDATE_LIST_TIMEOUT: u64 = 1000;
+
+/// Enumerate all cameras found by the `GenTL` producer located at `cti_path`.
+///
+/// The producer is kept loaded while any of the returned cameras is alive.
+///
+/// # Examples
+///
+/// ```no_run
+/// use cameleon::gentl;
+///
+/// let mut cameras = gentl::enumerate_cameras("/opt/vendor/lib/vendor.cti").unwrap();
+/// ```
+pub fn enumerate_cameras(
+    cti_path: impl AsRef<Path>,
+) -> CameleonResult<Vec<Camera<ControlHandle, StreamHandle>>> {
+    let system = Arc::new(System::open(cti_path.as_ref()).map_err(ControlError::from)?);
+
+    let mut cameras = vec![];
+    for iface in system.interfaces().map_err(ControlError::from)? {
+        let iface = Arc::new(iface);
+        let device_ids = match iface.device_ids() {
+            Ok(ids) => ids,
+            Err(e) => {
+                warn!(?e, "failed to enumerate devices of an interface");
+                continue;
+            }
+        };
+
+        for device_id in device_ids {
+            let camera_info = iface.camera_info(&device_id);
+            let device = Arc::new(Mutex::new(DeviceModules::default()));
+            let strm = StreamHandle::new(
+                iface.producer().clone(),
+                device.clone(),
+                &device_id.to_string_lossy(),
+            );
+            let ctrl = ControlHandle::new(iface.clone(), device_id, device);
+
+            let camera: Camera<ControlHandle, StreamHandle, DefaultGenApiCtxt> =
+                Camera::new(ctrl, strm, None, camera_info);
+            cameras.push(camera);
+        }
+    }
+
+    Ok(cameras)
+}
+
+/// `System` module of the producer.
+struct System {
+    producer: Arc<Producer>,
+    handle: RawHandle,
+}
+
+impl System {
+    fn open(cti_path: &Path) -> Result<Self, ProducerError> {
+        let producer = Arc::new(Producer::load(cti_path)?);
+        let mut handle = RawHandle::null();
+        producer.check(unsafe { (producer.TLOpen)(&mut handle.0) })?;
+        Ok(Self { producer, handle })
+    }
+
+    fn interfaces(self: &Arc<Self>) -> Result<Vec<Interface>, ProducerError> {
+        let producer = &self.producer;
+        let mut changed = 0;
+        producer.check(unsafe {
+            (producer.TLUpdateInterfaceList)(self.handle.0, &mut changed, UPDATE_LIST_TIMEOUT)
+        })?;
+
+        let mut num = 0;
+        producer.check(unsafe { (producer.TLGetNumInterfaces)(self.handle.0, &mut num) })?;
+        let mut ifaces = Vec::with_capacity(num as usize);
+        for i in 0..num {
+            let id = producer.string(|buf, size| unsafe {
+                (producer.TLGetInterfaceID)(self.handle.0, i, buf, size)
+            })?;
+            let id = to_cstring(id)?;
+            let mut handle = RawHandle::null();
+            producer.check(unsafe {
+                (producer.TLOpenInterface)(self.handle.0, id.as_ptr(), &mut handle.0)
+            })?;
+            ifaces.push(Interface {
+                system: self.clone(),
+                handle,
+            });
+        }
+
+        Ok(ifaces)
+    }
+}
+
+impl Drop for System {
+    fn drop(&mut self) {
+        if let Err(e) = self
+            .producer
+            .check(unsafe { (self.producer.TLClose)(self.handle.0) })
+        {
+            warn!(?e);
+        }
+    }
+}
+
+/// `Interface` module of the producer.
+pub(crate) struct Interface {
+    system: Arc<System>,
+    handle: RawHandle,
+}
+
+impl Interface {
+    fn producer(&self) -> &Arc<Producer> {
+        &self.system.producer
+    }
+
+    fn device_ids(&self) -> Result<Vec<CString>, ProducerError> {
+        let producer = self.producer();
+        let mut changed = 0;
+        producer.check(unsafe {
+            (producer.IFUpdateDeviceList)(self.handle.0, &mut changed, UPDATE_LIST_TIMEOUT)
+        })?;
+
+        let mut num = 0;
+        producer.check(unsafe { (producer.IFGetNumDevices)(self.handle.0, &mut num) })?;
+        (0..num)
+            .map(|i| {
+                let id = producer.string(|buf, size| unsafe {
+                    (producer.IFGetDeviceID)(self.handle.0, i, buf, size)
+                })?;
+                to_cstring(id)
+            })
+            .collect()
+    }
+
+    fn camera_info(&self, device_id: &CString) -> CameraInfo {
+        let info = |cmd| {
+            let producer = self.producer();
+            producer
+                .info_string(|ty, buf, size| unsafe {
+                    (producer.IFGetDeviceInfo)(
+                        self.handle.0,
+                        device_id.as_ptr(),
+                        cmd,
+                        ty,
+                        buf.cast::<c_void>(),
+                        size,
+                    )
+                })
+                .unwrap_or_default()
+        };
+
+        CameraInfo {
+            vendor_name: info(ffi::DEVICE_INFO_VENDOR),
+            model_name: info(ffi::DEVICE_INFO_MODEL),
+            serial_number: info(ffi::DEVICE_INFO_SERIAL_NUMBER),
+        }
+    }
+}
+
+impl Drop for Interface {
+    fn drop(&mut self) {
+        let producer = self.producer();
+        if let Err(e) = producer.check(unsafe { (producer.IFClose)(self.handle.0) }) {
+            warn!(?e);
+        }
+    }
+}
+
+/// Modules of an opened device, shared between [`ControlHandle`] and [`StreamHandle`].
+///
+/// `GenTL` invalidates the data stream when its parent device is closed, so the data stream is
+/// closed together with the device.
+#[derive(Default)]
+pub(crate) struct DeviceModules {
+    device: Option<RawHandle>,
+    port: Option<RawHandle>,
+    data_stream: Option<RawHandle>,
+}
+
+fn to_cstring(s: String) -> Result<CString, ProducerError> {
+    CString::new(s).map_err(|e| ProducerError {
+        code: -1001,
+        message: e.to_string(),
+    })
+}
+
+impl From<ProducerError> for ControlError {
+    fn from(err: ProducerError) -> Self {
+        match err.code {
+            ffi::GC_ERR_RESOURCE_IN_USE | ffi::GC_ERR_ACCESS_DENIED | ffi::GC_ERR_BUSY => {
+                Self::Busy
+            }
+            ffi::GC_ERR_TIMEOUT => Self::Timeout,
+            _ => Self::Io(err.into()),
+        }
+    }
+}
+
+impl From<ProducerError> for StreamError {
+    fn from(err: ProducerError) -> Self {
+        match err.code {
+            ffi::GC_ERR_TIMEOUT => Self::Timeout,
+            _ => Self::Io(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_producer() {
+        assert!(matches!(
+            enumerate_cameras("/nonexistent/producer.cti"),
+            Err(crate::CameleonError::ControlError(ControlError::Io(_)))
+        ));
+    }
+}
@@ -0,0 +1,495 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains the payload stream handle which receives buffers from the data stream of
+//! a `GenTL` producer.
+
+use std::{
+    convert::TryInto,
+    ffi::{c_void, CString},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+
+use async_std::task;
+use cameleon_impl::leak_check::{Resource, Tracked};
+use futures_channel::oneshot;
+use tracing::{error, info, warn};
+
+use crate::{
+    camera::PayloadStream,
+    payload::{
+        FrameId, ImageInfo, Payload, PayloadSender, PayloadStatus, PayloadType, PixelDecoders,
+        PoolHandle,
+    },
+    DeviceControl, StreamError, StreamResult,
+};
+
+use super::{
+    ffi::{self, Producer, RawHandle},
+    DeviceModules,
+};
+
+/// Default number of buffers announced to the data stream.
+const DEFAULT_BUFFER_COUNT: usize = 4;
+
+/// Timeout of waiting for a new buffer event in milliseconds. The streaming loop checks the
+/// cancellation at this interval.
+const NEW_BUFFER_TIMEOUT: u64 = 100;
+
+/// Name of the thread which receives buffers.
+const STREAM_THREAD_NAME: &str = "cameleon-gentl-stream";
+
+/// This type is used to receive buffers from the first data stream of the device.
+pub struct StreamHandle {
+    producer: Arc<Producer>,
+    modules: Arc<Mutex<DeviceModules>>,
+    buffer_count: usize,
+    acquisition: Option<Acquisition>,
+    /// Device id of [`FrameId`].
+    device_id: u64,
+    /// Acquisition generation of [`FrameId`], incremented every time streaming is started.
+    generation: u32,
+    /// Decoders used by [`Payload::to_image`] of the payloads received by this stream.
+    decoders: PixelDecoders,
+}
+
+/// Resources of a running acquisition.
+struct Acquisition {
+    data_stream: RawHandle,
+    event: RawHandle,
+    buffers: Vec<RawHandle>,
+    cancellation_tx: oneshot::Sender<()>,
+    completion_rx: oneshot::Receiver<()>,
+}
+
+impl StreamHandle {
+    /// Returns the number of buffers announced to the data stream.
+    #[must_use]
+    pub fn buffer_count(&self) -> usize {
+        self.buffer_count
+    }
+
+    /// Sets the number of buffers announced to the data stream. The value is used from the next
+    /// acquisition.
+    pub fn set_buffer_count(&mut self, buffer_count: usize) {
+        self.buffer_count = buffer_count.max(1);
+    }
+
+    /// Returns the decoders of the pixel formats which aren't modeled by
+    /// [`PixelFormat`](crate::payload::PixelFormat), which are used by [`Payload::to_image`] of
+    /// the payloads received by this stream.
+    #[must_use]
+    pub fn decoders(&self) -> &PixelDecoders {
+        &self.decoders
+    }
+
+    pub(super) fn new(
+        producer: Arc<Producer>,
+        modules: Arc<Mutex<DeviceModules>>,
+        device_id: &str,
+    ) -> Self {
+        Self {
+            producer,
+            modules,
+            buffer_count: DEFAULT_BUFFER_COUNT,
+            acquisition: None,
+            device_id: FrameId::device_id_from_guid(device_id),
+            generation: 0,
+            decoders: PixelDecoders::default(),
+        }
+    }
+
+    fn modules(&self) -> StreamResult<MutexGuard<'_, DeviceModules>> {
+        self.modules
+            .lock()
+            .map_err(|e| StreamError::Poisoned(e.to_string().into()))
+    }
+
+    fn data_stream(&self) -> StreamResult<RawHandle> {
+        self.modules()?
+            .data_stream
+            .ok_or_else(|| StreamError::Io(anyhow::Error::msg("data stream is not opened")))
+    }
+
+    /// Announces and queues buffers, then starts the acquisition.
+    fn start_acquisition(
+        &self,
+        data_stream: RawHandle,
+    ) -> StreamResult<(RawHandle, Vec<RawHandle>)> {
+        let producer = &self.producer;
+        let payload_size: usize = producer.info(|ty, buf, size| unsafe {
+            (producer.DSGetInfo)(data_stream.0, ffi::STREAM_INFO_PAYLOAD_SIZE, ty, buf, size)
+        })?;
+
+        let mut buffers = Vec::with_capacity(self.buffer_count);
+        for _ in 0..self.buffer_count {
+            let mut buffer = RawHandle::null();
+            producer.check(unsafe {
+                (producer.DSAllocAndAnnounceBuffer)(
+                    data_stream.0,
+                    payload_size,
+                    std::ptr::null_mut(),
+                    &mut buffer.0,
+                )
+            })?;
+            buffers.push(buffer);
+            producer.check(unsafe { (producer.DSQueueBuffer)(data_stream.0, buffer.0) })?;
+        }
+
+        let mut event = RawHandle::null();
+        producer.check(unsafe {
+            (producer.GCRegisterEvent)(data_stream.0, ffi::EVENT_NEW_BUFFER, &mut event.0)
+        })?;
+        producer.check(unsafe {
+            (producer.DSStartAcquisition)(
+                data_stream.0,
+                ffi::ACQ_START_FLAGS_DEFAULT,
+                ffi::GENTL_INFINITE,
+            )
+        })?;
+
+        Ok((event, buffers))
+    }
+
+    /// Stops the acquisition, and revokes all buffers.
+    ///
+    /// All steps are tried even if some of them fail, and the first error is returned.
+    fn stop_acquisition(&self, data_stream: RawHandle, buffers: &[RawHandle]) -> StreamResult<()> {
+        let producer = &self.producer;
+        let mut results = vec![
+            producer.check(unsafe {
+                (producer.DSStopAcquisition)(data_stream.0, ffi::ACQ_STOP_FLAGS_KILL)
+            }),
+            producer.check(unsafe {
+                (producer.DSFlushQueue)(data_stream.0, ffi::ACQ_QUEUE_ALL_DISCARD)
+            }),
+            producer.check(unsafe {
+                (producer.GCUnregisterEvent)(data_stream.0, ffi::EVENT_NEW_BUFFER)
+            }),
+        ];
+        for buffer in buffers {
+            results.push(producer.check(unsafe {
+                (producer.DSRevokeBuffer)(
+                    data_stream.0,
+                    buffer.0,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                )
+            }));
+        }
+
+        results.into_iter().collect::<Result<(), _>>()?;
+        Ok(())
+    }
+}
+
+impl PayloadStream for StreamHandle {
+    fn open(&mut self) -> StreamResult<()> {
+        let producer = self.producer.clone();
+        let mut modules = self.modules()?;
+        if modules.data_stream.is_some() {
+            return Ok(());
+        }
+        let device = modules
+            .device
+            .ok_or_else(|| StreamError::Io(anyhow::Error::msg("device is not opened")))?;
+
+        let mut num = 0;
+        producer.check(unsafe { (producer.DevGetNumDataStreams)(device.0, &mut num) })?;
+        if num == 0 {
+            return Err(StreamError::Io(anyhow::Error::msg(
+                "device doesn't have any data stream",
+            )));
+        }
+        let id = producer
+            .string(|buf, size| unsafe { (producer.DevGetDataStreamID)(device.0, 0, buf, size) })?;
+        let id = CString::new(id).map_err(|e| StreamError::Io(e.into()))?;
+
+        let mut data_stream = RawHandle::null();
+        producer.check(unsafe {
+            (producer.DevOpenDataStream)(device.0, id.as_ptr(), &mut data_stream.0)
+        })?;
+        modules.data_stream = Some(data_stream);
+        Ok(())
+    }
+
+    fn close(&mut self) -> StreamResult<()> {
+        if self.is_loop_running() {
+            self.stop_streaming_loop()?;
+        }
+
+        let producer = self.producer.clone();
+        if let Some(data_stream) = self.modules()?.data_stream.take() {
+            producer.check(unsafe { (producer.DSClose)(data_stream.0) })?;
+        }
+        Ok(())
+    }
+
+    fn start_streaming_loop(
+        &mut self,
+        sender: PayloadSender,
+        _ctrl: &mut dyn DeviceControl,
+    ) -> StreamResult<()> {
+        if self.is_loop_running() {
+            return Err(StreamError::InStreaming);
+        }
+
+        let data_stream = self.data_stream()?;
+        let (event, buffers) = match self.start_acquisition(data_stream) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(?e);
+                return Err(e);
+            }
+        };
+
+        let (cancellation_tx, cancellation_rx) = oneshot::channel();
+        let (completion_tx, completion_rx) = oneshot::channel();
+
+        self.generation = self.generation.wrapping_add(1);
+        let strm_loop = StreamingLoop {
+            producer: self.producer.clone(),
+            data_stream,
+            event,
+            frame_id: FrameId::new(self.device_id, 0, self.generation, 0),
+            decoders: self.decoders.clone(),
+            sender,
+            completion_tx,
+            cancellation_rx,
+        };
+        if let Err(e) = thread::Builder::new()
+            .name(STREAM_THREAD_NAME.into())
+            .spawn(|| strm_loop.run())
+        {
+            self.stop_acquisition(data_stream, &buffers).ok();
+            return Err(StreamError::Io(e.into()));
+        }
+
+        self.acquisition = Some(Acquisition {
+            data_stream,
+            event,
+            buffers,
+            cancellation_tx,
+            completion_rx,
+        });
+
+        info!("start streaming loop successfully");
+        Ok(())
+    }
+
+    fn stop_streaming_loop(&mut self) -> StreamResult<()> {
+        if let Some(acquisition) = self.acquisition.take() {
+            acquisition.cancellation_tx.send(()).map_err(|_| {
+                StreamError::Poisoned("failed to send cancellation signal to streaming loop".into())
+            })?;
+            // Wake up the loop waiting for a new buffer.
+            let producer = &self.producer;
+            if let Err(e) = producer.check(unsafe { (producer.EventKill)(acquisition.event.0) }) {
+                warn!(?e);
+            }
+            task::block_on(acquisition.completion_rx)
+                .map_err(|e| StreamError::Poisoned(e.to_string().into()))?;
+
+            self.stop_acquisition(acquisition.data_stream, &acquisition.buffers)?;
+        }
+
+        info!("stop streaming loop successfully");
+        Ok(())
+    }
+
+    fn is_loop_running(&self) -> bool {
+        self.acquisition.is_some()
+    }
+}
+
+impl Drop for StreamHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!(?e)
+        }
+    }
+}
+
+impl From<StreamHandle> for Box<dyn PayloadStream> {
+    fn from(strm: StreamHandle) -> Self {
+        Box::new(strm)
+    }
+}
+
+struct StreamingLoop {
+    producer: Arc<Producer>,
+    data_stream: RawHandle,
+    event: RawHandle,
+    /// `FrameId` of the acquisition, `block_id` is filled for each payload.
+    frame_id: FrameId,
+    decoders: PixelDecoders,
+    sender: PayloadSender,
+    completion_tx: oneshot::Sender<()>,
+    cancellation_rx: oneshot::Receiver<()>,
+}
+
+impl StreamingLoop {
+    fn run(mut self) {
+        loop {
+            // Stop the loop when
+            // 1. `cancellation_tx` sends signal.
+            // 2. `cancellation_tx` is dropped.
+            if self.cancellation_rx.try_recv().transpose().is_some() {
+                break;
+            }
+
+            let mut new_buffer = ffi::EventNewBuffer {
+                buffer: std::ptr::null_mut(),
+                user_pointer: std::ptr::null_mut(),
+            };
+            let mut size = std::mem::size_of::<ffi::EventNewBuffer>();
+            let producer = &self.producer;
+            let res = producer.check(unsafe {
+                (producer.EventGetData)(
+                    self.event.0,
+                    (&mut new_buffer as *mut ffi::EventNewBuffer).cast::<c_void>(),
+                    &mut size,
+                    NEW_BUFFER_TIMEOUT,
+                )
+            });
+            match res {
+                Ok(()) => {}
+                Err(e) if e.code == ffi::GC_ERR_TIMEOUT || e.code == ffi::GC_ERR_ABORT => continue,
+                Err(e) => {
+                    warn!(?e);
+                    self.sender.try_send(Err(e.into())).ok();
+                    continue;
+                }
+            }
+
+            let buffer = RawHandle(new_buffer.buffer);
+            let payload = self.build_payload(buffer);
+            // Give the buffer back to the producer regardless of the result.
+            if let Err(e) =
+                producer.check(unsafe { (producer.DSQueueBuffer)(self.data_stream.0, buffer.0) })
+            {
+                error!(?e);
+            }
+
+            if let Err(err) = self.sender.try_send(payload) {
+                warn!(?err);
+            }
+        }
+
+        if let Err(e) = self.completion_tx.send(()) {
+            error!(?e);
+        }
+    }
+
+    fn build_payload(&self, buffer: RawHandle) -> StreamResult<Payload> {
+        let producer = &self.producer;
+        let info = |cmd| {
+            producer.info::<usize>(|ty, buf, size| unsafe {
+                (producer.DSGetBufferInfo)(self.data_stream.0, buffer.0, cmd, ty, buf, size)
+            })
+        };
+        let info_u64 = |cmd| {
+            producer.info::<u64>(|ty, buf, size| unsafe {
+                (producer.DSGetBufferInfo)(self.data_stream.0, buffer.0, cmd, ty, buf, size)
+            })
+        };
+        let info_bool = |cmd| {
+            producer
+                .info::<u8>(|ty, buf, size| unsafe {
+                    (producer.DSGetBufferInfo)(self.data_stream.0, buffer.0, cmd, ty, buf, size)
+                })
+                .map(|v| v != 0)
+        };
+
+        if info_bool(ffi::BUFFER_INFO_IS_INCOMPLETE).unwrap_or(false) {
+            return Err(StreamError::InvalidPayload("incomplete buffer".into()));
+        }
+
+        let base = info(ffi::BUFFER_INFO_BASE)? as *const u8;
+        let size_filled = info(ffi::BUFFER_INFO_SIZE_FILLED)?;
+        let block_id = info_u64(ffi::BUFFER_INFO_FRAMEID)?;
+        let timestamp = info_u64(ffi::BUFFER_INFO_TIMESTAMP_NS)
+            .or_else(|_| info_u64(ffi::BUFFER_INFO_TIMESTAMP))
+            .unwrap_or_default();
+        let contains_chunk = info_bool(ffi::BUFFER_INFO_CONTAINS_CHUNKDATA).unwrap_or(false);
+
+        let payload_type = match info(ffi::BUFFER_INFO_PAYLOADTYPE)? {
+            ffi::PAYLOAD_TYPE_IMAGE if contains_chunk => PayloadType::ImageExtendedChunk,
+            ffi::PAYLOAD_TYPE_IMAGE => PayloadType::Image,
+            ffi::PAYLOAD_TYPE_CHUNK_DATA | ffi::PAYLOAD_TYPE_CHUNK_ONLY => PayloadType::Chunk,
+            ffi::PAYLOAD_TYPE_GENDC => PayloadType::GenDc,
+            other => {
+                return Err(StreamError::InvalidPayload(
+                    format!("unsupported payload type: {}", other).into(),
+                ))
+            }
+        };
+
+        let chunk_layout_id = if contains_chunk || payload_type == PayloadType::Chunk {
+            info_u64(ffi::BUFFER_INFO_CHUNKLAYOUTID)
+                .ok()
+                .map(|id| id as u32)
+        } else {
+            None
+        };
+
+        let image_info = if matches!(payload_type, PayloadType::Chunk | PayloadType::GenDc) {
+            None
+        } else {
+            let pixel_format: u32 = info_u64(ffi::BUFFER_INFO_PIXELFORMAT)?.try_into().map_err(
+                |e: std::num::TryFromIntError| StreamError::InvalidPayload(e.to_string().into()),
+            )?;
+            let image_offset = info(ffi::BUFFER_INFO_IMAGEOFFSET).unwrap_or_default();
+            let chunk_size = if contains_chunk {
+                info(ffi::BUFFER_INFO_DELIVERED_CHUNKPAYLOADSIZE).unwrap_or_default()
+            } else {
+                0
+            };
+            Some(ImageInfo {
+                width: info(ffi::BUFFER_INFO_WIDTH)?,
+                height: info(ffi::BUFFER_INFO_HEIGHT)?,
+                x_offset: info(ffi::BUFFER_INFO_XOFFSET)?,
+                y_offset: info(ffi::BUFFER_INFO_YOFFSET)?,
+                pixel_format: pixel_format
+                    .try_into()
+                    .map_err(|e: String| StreamError::InvalidPayload(e.into()))?,
+                image_size: size_filled.saturating_sub(image_offset + chunk_size),
+                x_padding: info(ffi::BUFFER_INFO_XPADDING).unwrap_or_default(),
+            })
+        };
+
+        let mut payload_buf = match self.sender.try_recv() {
+            Ok(mut payload) => std::mem::take(&mut payload.payload),
+            Err(_) => Vec::with_capacity(size_filled),
+        };
+        payload_buf.clear();
+        // SAFETY: The producer guarantees that `size_filled` bytes from `base` are valid until the
+        // buffer is queued again.
+        payload_buf.extend_from_slice(unsafe { std::slice::from_raw_parts(base, size_filled) });
+
+        Ok(Payload {
+            id: block_id,
+            frame_id: FrameId::new(
+                self.frame_id.device_id(),
+                self.frame_id.stream_index(),
+                self.frame_id.generation(),
+                block_id,
+            ),
+            payload_type,
+            chunk_layout_id,
+            image_info,
+            payload: payload_buf,
+            provided: None,
+            valid_payload_size: size_filled,
+            timestamp: Duration::from_nanos(timestamp),
+            incomplete_info: None,
+            status: PayloadStatus::Success,
+            pool: PoolHandle::default(),
+            tracked: Tracked::new(Resource::PoolBuffer),
+            decoders: Some(self.decoders.clone()),
+        })
+    }
+}
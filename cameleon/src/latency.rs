@@ -0,0 +1,405 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains latency histograms of control transactions.
+//!
+//! Each transaction is recorded into a histogram with fixed logarithmic buckets, so recording is
+//! a couple of atomic operations and never allocates or blocks on the hot path.
+//! [`LatencyReport`] summarizes the histograms and is obtained from the control handle.
+
+use std::{fmt, time::Duration};
+
+// Only the U3V control handle records latency.
+#[cfg(feature = "libusb")]
+use std::{
+    convert::TryInto,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+#[cfg(feature = "libusb")]
+use crate::metrics::{self, MetricSink, MetricSource, MetricValue};
+
+/// Number of buckets in a histogram.
+///
+/// Bucket `0` counts transactions shorter than 1us, bucket `i` counts transactions in
+/// `[2^(i-1), 2^i)` us, and the last bucket counts everything longer.
+pub const BUCKET_COUNT: usize = 32;
+
+/// Kind of a control transaction, corresponds to the `GenCP` operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TransactionKind {
+    /// `ReadMem` command.
+    Read,
+    /// `WriteMem` command.
+    Write,
+    /// `ReadMemStacked` or `WriteMemStacked` command.
+    Stacked,
+    /// Vendor specific custom command.
+    Custom,
+}
+
+#[cfg(feature = "libusb")]
+impl TransactionKind {
+    const ALL: [Self; 4] = [Self::Read, Self::Write, Self::Stacked, Self::Custom];
+
+    fn index(self) -> usize {
+        match self {
+            Self::Read => 0,
+            Self::Write => 1,
+            Self::Stacked => 2,
+            Self::Custom => 3,
+        }
+    }
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Stacked => "stacked",
+            Self::Custom => "custom",
+        };
+        f.write_str(s)
+    }
+}
+
+/// The slowest transaction recorded since the last reset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorstTransaction {
+    /// Kind of the transaction.
+    pub kind: TransactionKind,
+    /// Address the transaction accessed.
+    pub address: u64,
+    /// Latency of the transaction.
+    pub latency: Duration,
+}
+
+/// Summary of a single histogram.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistogramSummary {
+    /// Number of transactions in each bucket, see [`HistogramSummary::bucket_range`] for the
+    /// range of each bucket.
+    pub buckets: [u64; BUCKET_COUNT],
+    /// Total number of transactions.
+    pub count: u64,
+    /// Upper bound of the bucket containing the median.
+    pub p50: Duration,
+    /// Upper bound of the bucket containing the 95th percentile.
+    pub p95: Duration,
+    /// Maximum latency.
+    pub max: Duration,
+    /// Sum of the latency of all transactions.
+    pub sum: Duration,
+}
+
+impl HistogramSummary {
+    /// Returns the range of latency counted by the bucket at `index`.
+    ///
+    /// The end of the last bucket is [`Duration::MAX`].
+    ///
+    /// # Panics
+    /// Panics if `index` is not less than [`BUCKET_COUNT`].
+    #[must_use]
+    pub fn bucket_range(index: usize) -> std::ops::Range<Duration> {
+        assert!(index < BUCKET_COUNT);
+        let start = if index == 0 {
+            Duration::from_micros(0)
+        } else {
+            Duration::from_micros(1 << (index - 1))
+        };
+        start..bucket_end(index)
+    }
+
+    #[cfg(feature = "libusb")]
+    fn percentile(&self, p: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::from_micros(0);
+        }
+
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let rank = ((self.count as f64 * p).ceil() as u64).max(1);
+        let mut cumulative = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            cumulative += n;
+            if cumulative >= rank {
+                return bucket_end(i).min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+/// Latency summary of control transactions, returned by the control handle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyReport {
+    /// Histogram of [`TransactionKind::Read`].
+    pub read: HistogramSummary,
+    /// Histogram of [`TransactionKind::Write`].
+    pub write: HistogramSummary,
+    /// Histogram of [`TransactionKind::Stacked`].
+    pub stacked: HistogramSummary,
+    /// Histogram of [`TransactionKind::Custom`].
+    pub custom: HistogramSummary,
+    /// The slowest transaction, `None` if no transaction is recorded.
+    pub worst: Option<WorstTransaction>,
+}
+
+impl LatencyReport {
+    /// Returns the histogram of `kind`.
+    #[must_use]
+    pub fn histogram(&self, kind: TransactionKind) -> &HistogramSummary {
+        match kind {
+            TransactionKind::Read => &self.read,
+            TransactionKind::Write => &self.write,
+            TransactionKind::Stacked => &self.stacked,
+            TransactionKind::Custom => &self.custom,
+        }
+    }
+}
+
+/// Records latency of control transactions.
+#[cfg(feature = "libusb")]
+#[derive(Default)]
+pub(crate) struct LatencyRecorder {
+    histograms: [Histogram; 4],
+    /// Latency of the worst transaction in nanoseconds, used to skip locking `worst` in most
+    /// cases.
+    worst_nanos: AtomicU64,
+    worst: Mutex<Option<WorstTransaction>>,
+}
+
+#[cfg(feature = "libusb")]
+impl LatencyRecorder {
+    pub(crate) fn record(&self, kind: TransactionKind, address: u64, latency: Duration) {
+        let nanos = saturating_nanos(latency);
+        self.histograms[kind.index()].record(latency, nanos);
+
+        if nanos > self.worst_nanos.load(Ordering::Relaxed) {
+            let mut worst = self.worst.lock().unwrap_or_else(PoisonError::into_inner);
+            if !matches!(*worst, Some(worst) if worst.latency >= latency) {
+                *worst = Some(WorstTransaction {
+                    kind,
+                    address,
+                    latency,
+                });
+                self.worst_nanos.store(nanos, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn report(&self) -> LatencyReport {
+        let [read, write, stacked, custom] =
+            TransactionKind::ALL.map(|kind| self.histograms[kind.index()].summary());
+        LatencyReport {
+            read,
+            write,
+            stacked,
+            custom,
+            worst: *self.worst.lock().unwrap_or_else(PoisonError::into_inner),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        let mut worst = self.worst.lock().unwrap_or_else(PoisonError::into_inner);
+        for histogram in &self.histograms {
+            histogram.reset();
+        }
+        *worst = None;
+        self.worst_nanos.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "libusb")]
+impl MetricSource for LatencyRecorder {
+    fn collect(&self, sink: &mut MetricSink<'_>) {
+        for kind in TransactionKind::ALL {
+            let summary = self.histograms[kind.index()].summary();
+            let buckets = (0..BUCKET_COUNT).map(|i| (bucket_end(i), summary.buckets[i]));
+            sink.push(
+                "cameleon_control_latency_seconds",
+                &[("kind", &kind.to_string())],
+                MetricValue::Histogram(metrics::histogram(buckets, summary.sum)),
+            );
+        }
+    }
+}
+
+#[cfg(feature = "libusb")]
+#[derive(Default)]
+struct Histogram {
+    buckets: [AtomicU64; BUCKET_COUNT],
+    max_nanos: AtomicU64,
+    sum_nanos: AtomicU64,
+}
+
+#[cfg(feature = "libusb")]
+impl Histogram {
+    fn record(&self, latency: Duration, nanos: u64) {
+        self.buckets[bucket_index(latency)].fetch_add(1, Ordering::Relaxed);
+        self.max_nanos.fetch_max(nanos, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    fn summary(&self) -> HistogramSummary {
+        let mut buckets = [0; BUCKET_COUNT];
+        for (dst, src) in buckets.iter_mut().zip(&self.buckets) {
+            *dst = src.load(Ordering::Relaxed);
+        }
+        let mut summary = HistogramSummary {
+            buckets,
+            count: buckets.iter().sum(),
+            p50: Duration::from_micros(0),
+            p95: Duration::from_micros(0),
+            max: Duration::from_nanos(self.max_nanos.load(Ordering::Relaxed)),
+            sum: Duration::from_nanos(self.sum_nanos.load(Ordering::Relaxed)),
+        };
+        summary.p50 = summary.percentile(0.5);
+        summary.p95 = summary.percentile(0.95);
+        summary
+    }
+
+    fn reset(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.max_nanos.store(0, Ordering::Relaxed);
+        self.sum_nanos.store(0, Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "libusb")]
+fn bucket_index(latency: Duration) -> usize {
+    let micros = latency.as_micros();
+    let index = (u128::BITS - micros.leading_zeros()) as usize;
+    index.min(BUCKET_COUNT - 1)
+}
+
+fn bucket_end(index: usize) -> Duration {
+    if index == BUCKET_COUNT - 1 {
+        Duration::MAX
+    } else {
+        Duration::from_micros(1 << index)
+    }
+}
+
+#[cfg(feature = "libusb")]
+fn saturating_nanos(latency: Duration) -> u64 {
+    latency.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(all(test, feature = "libusb"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_placement() {
+        assert_eq!(bucket_index(Duration::from_nanos(999)), 0);
+        assert_eq!(bucket_index(Duration::from_micros(1)), 1);
+        assert_eq!(bucket_index(Duration::from_micros(3)), 2);
+        assert_eq!(bucket_index(Duration::from_micros(4)), 3);
+        assert_eq!(bucket_index(Duration::from_millis(1)), 10);
+        assert_eq!(bucket_index(Duration::from_secs(60 * 60)), BUCKET_COUNT - 1);
+
+        for index in 0..BUCKET_COUNT {
+            let range = HistogramSummary::bucket_range(index);
+            assert_eq!(bucket_index(range.start), index);
+            if index != BUCKET_COUNT - 1 {
+                assert_eq!(bucket_index(range.end), index + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let recorder = LatencyRecorder::default();
+        for _ in 0..90 {
+            recorder.record(TransactionKind::Read, 0x100, Duration::from_micros(300));
+        }
+        for _ in 0..10 {
+            recorder.record(TransactionKind::Read, 0x200, Duration::from_millis(20));
+        }
+        recorder.record(TransactionKind::Write, 0x300, Duration::from_millis(50));
+
+        let report = recorder.report();
+        assert_eq!(report.read.count, 100);
+        assert_eq!(report.read.buckets[9], 90);
+        assert_eq!(report.read.buckets[15], 10);
+        assert_eq!(report.read.p50, Duration::from_micros(512));
+        assert_eq!(report.read.p95, Duration::from_millis(20));
+        assert_eq!(report.read.max, Duration::from_millis(20));
+        assert_eq!(report.read.sum, Duration::from_millis(227));
+        assert_eq!(report.write.count, 1);
+        assert_eq!(report.stacked.count, 0);
+        assert_eq!(report.custom.p50, Duration::from_micros(0));
+        assert_eq!(
+            report.worst,
+            Some(WorstTransaction {
+                kind: TransactionKind::Write,
+                address: 0x300,
+                latency: Duration::from_millis(50),
+            })
+        );
+
+        recorder.reset();
+        let report = recorder.report();
+        assert_eq!(report.histogram(TransactionKind::Read).count, 0);
+        assert_eq!(report.read.max, Duration::from_micros(0));
+        assert!(report.worst.is_none());
+    }
+
+    #[test]
+    fn test_concurrent_recording() {
+        const THREADS: u64 = 4;
+        const ITERATIONS: u64 = 10_000;
+        let recorder = LatencyRecorder::default();
+
+        std::thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    for i in 0..ITERATIONS {
+                        let latency = Duration::from_micros(i % 4096);
+                        recorder.record(TransactionKind::Read, i, latency);
+                    }
+                });
+            }
+        });
+
+        // No record is lost even if threads race on the same buckets.
+        let read = recorder.report().read;
+        assert_eq!(read.count, THREADS * ITERATIONS);
+        let sum_micros: u64 = (0..ITERATIONS).map(|i| i % 4096).sum();
+        assert_eq!(read.sum, Duration::from_micros(sum_micros * THREADS));
+        assert_eq!(read.max, Duration::from_micros(4095));
+    }
+
+    /// Recording must be negligible next to a control transaction, which takes tens of
+    /// microseconds even on USB 3.
+    #[test]
+    fn test_recording_overhead() {
+        const ITERATIONS: u32 = 100_000;
+        let recorder = LatencyRecorder::default();
+
+        let started = std::time::Instant::now();
+        for i in 0..ITERATIONS {
+            let latency = Duration::from_micros(u64::from(i % 4096));
+            recorder.record(TransactionKind::Read, u64::from(i), latency);
+        }
+        let per_record = started.elapsed() / ITERATIONS;
+
+        // The bound is loose enough for unoptimized builds on a loaded machine.
+        assert!(
+            per_record < Duration::from_micros(1),
+            "recording took {:?}",
+            per_record
+        );
+    }
+}
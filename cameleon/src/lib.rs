@@ -26,6 +26,9 @@
 //! cameleon = { version = 0.1, features = 'libusb'}
 //! ```
 //!
+//! The `libusb` feature is enabled by default. Disable default features if you only need the
+//! protocol parsers or `GenApi` machinery and want to build without libusb.
+//!
 //! You can enumerate all cameras connected to the host, and start streaming.
 //!
 //! ```rust
@@ -123,15 +126,26 @@
 #![allow(
     clippy::similar_names,
     clippy::missing_errors_doc,
-    clippy::clippy::module_name_repetitions
+    clippy::module_name_repetitions
 )]
 
 pub mod camera;
+#[cfg(feature = "emulator")]
+pub mod emulator;
 pub mod genapi;
+#[cfg(feature = "gentl-consumer")]
+pub mod gentl;
+pub mod latency;
+pub mod limits;
+pub mod load_options;
+pub mod metrics;
+pub mod patch;
 pub mod payload;
 #[cfg(feature = "libusb")]
 pub mod u3v;
+pub mod user_set;
 
+pub use cameleon_impl::error_code::{ErrorCategory, ErrorCode};
 pub use camera::{Camera, CameraInfo, DeviceControl, PayloadStream};
 
 use std::{borrow::Cow, num::TryFromIntError};
@@ -158,9 +172,68 @@ pub enum CameleonError {
     #[error("invalid `GenApi` xml: {0}")]
     InvalidGenApiXml(Cow<'static, str>),
 
+    /// The camera lacks `GenApi` nodes required by the operation.
+    #[error("camera lacks `GenApi` nodes required by the operation: {}", .missing.join(", "))]
+    MissingCapability {
+        /// Names of the missing nodes.
+        missing: Vec<&'static str>,
+    },
+
     /// An error when `GenApi` node operation failed.
     #[error("`GenApi` error: {0}")]
     GenApiError(#[from] cameleon_genapi::GenApiError),
+
+    /// Loading `GenApi` context with [`load_options::LoadOptions`] failed.
+    #[error("failed to load `GenApi` context: {0}")]
+    LoadError(#[from] load_options::LoadError),
+
+    /// Failed to apply a patch script.
+    #[error("failed to apply patch script: {0}")]
+    PatchError(#[from] patch::PatchError),
+
+    /// The camera doesn't have the requested user set.
+    #[error(
+        "camera doesn't have user set `{requested}`, available user sets are: {}",
+        .available.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    UserSetUnavailable {
+        /// The requested user set.
+        requested: user_set::UserSet,
+        /// User sets the camera has.
+        available: Vec<user_set::UserSet>,
+    },
+
+    /// Features read after loading a user set differ from the values when it's saved.
+    #[error(
+        "features differ from the saved values after loading user set `{slot}`: {}",
+        .mismatches.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    UserSetMismatch {
+        /// The loaded user set.
+        slot: user_set::UserSet,
+        /// The features whose values differ.
+        mismatches: Vec<user_set::FeatureMismatch>,
+    },
+
+    /// The camera doesn't complete the command within the timeout.
+    #[error("camera doesn't complete `{command}` within {timeout:?}")]
+    CommandTimeout {
+        /// Name of the command node.
+        command: &'static str,
+        /// The timeout.
+        timeout: std::time::Duration,
+    },
+
+    /// The feature is locked while streaming is active.
+    #[error(
+        "`{feature}` can't be written while streaming is active, stop streaming, modify the \
+         feature and restart streaming, or add it to the streaming whitelist if the camera \
+         accepts the write"
+    )]
+    LockedDuringAcquisition {
+        /// Name of the feature.
+        feature: String,
+    },
 }
 
 /// A specialized `Result` type for device control.
@@ -173,6 +246,13 @@ pub enum ControlError {
     #[error("device is busy")]
     Busy,
 
+    /// The device is already opened by another handle in this process.
+    #[error("device is already opened in this process by `{holder_tag}`")]
+    AlreadyOpenInProcess {
+        /// Tag of the handle which holds the device.
+        holder_tag: String,
+    },
+
     /// The device is disconnected from the host.
     #[error("device is disconnected")]
     Disconnected,
@@ -181,8 +261,10 @@ pub enum ControlError {
     #[error("input/output error: {0}")]
     Io(anyhow::Error),
 
-    /// Timeout has occured when receiveing stream payload.
-    #[error("timeout has occured when receiveing stream payload")]
+    /// The device didn't answer within the timeout of the transaction.
+    ///
+    /// The operation may succeed if it's retried, possibly with a longer timeout.
+    #[error("timeout has occurred while waiting for the device")]
     Timeout,
 
     /// The device is not opened.
@@ -201,6 +283,127 @@ pub enum ControlError {
     /// e.g. try to write too large data that will overrun register.
     #[error("try to write invalid data to the device: {0}")]
     InvalidData(Box<dyn std::error::Error>),
+
+    /// A value claimed by the device exceeds the limit. See [`limits::Limits`] for details.
+    #[error("{limit} claimed by the device exceeds the limit: claimed {claimed}, limit {max}")]
+    LimitExceeded {
+        /// Kind of the exceeded limit.
+        limit: limits::Limit,
+        /// The value claimed by the device.
+        claimed: u64,
+        /// The limit.
+        max: u64,
+    },
+
+    /// The accessed range overflows the 64-bit address space of the device or the address space
+    /// of the host.
+    #[error("range of {len} bytes at address {address:#X} exceeds the address space")]
+    InvalidAddress {
+        /// The first address of the range.
+        address: u64,
+        /// Length of the range.
+        len: u64,
+    },
+
+    /// The device returned fewer bytes than requested for an entry of a batched read.
+    #[error("entry {index} of the batched read returned {read} bytes of {requested} bytes")]
+    PartialRead {
+        /// Index of the failed entry.
+        index: usize,
+        /// Requested length of the entry.
+        requested: usize,
+        /// Length actually returned by the device.
+        read: usize,
+    },
+
+    /// The device wrote a length different from the requested one for an entry of a batched
+    /// write.
+    #[error("entry {index} of the batched write wrote {written} bytes of {requested} bytes")]
+    PartialWrite {
+        /// Index of the failed entry.
+        index: usize,
+        /// Requested length of the entry.
+        requested: usize,
+        /// Length actually written by the device.
+        written: usize,
+    },
+
+    /// The device returned fewer bytes than requested for a chunk of a read split by the
+    /// maximum acknowledge length.
+    #[error("read of {requested} bytes at offset {offset} returned {read} bytes")]
+    PartialChunkRead {
+        /// Offset of the failed chunk from the start of the read.
+        offset: usize,
+        /// Requested length of the chunk.
+        requested: usize,
+        /// Length actually returned by the device.
+        read: usize,
+    },
+
+    /// The device wrote a length different from the requested one for a chunk of a write split
+    /// by the maximum command length.
+    #[error("write of {requested} bytes at offset {offset} wrote {written} bytes")]
+    PartialChunkWrite {
+        /// Offset of the failed chunk from the start of the write.
+        offset: usize,
+        /// Requested length of the chunk.
+        requested: usize,
+        /// Length actually written by the device.
+        written: usize,
+    },
+
+    /// The device answered a command with an ack of another command or request.
+    #[error(
+        "expected ack {expected_ack_id:#06X} of request {expected_request_id}, \
+         but received ack {ack_id:#06X} of request {request_id}"
+    )]
+    UnexpectedAck {
+        /// Ack id corresponding to the sent command.
+        expected_ack_id: u16,
+        /// Ack id of the received ack.
+        ack_id: u16,
+        /// Request id of the sent command.
+        expected_request_id: u16,
+        /// Request id of the received ack.
+        request_id: u16,
+    },
+
+    /// The device kept answering with acks of other requests even after the stale acks were
+    /// discarded.
+    #[error("expected ack of request {expected}, but received ack of request {actual}")]
+    RequestIdMismatch {
+        /// Request id of the sent command.
+        expected: u16,
+        /// Request id of the received ack.
+        actual: u16,
+    },
+
+    /// The zipped `GenApi` xml file retrieved from the device is broken.
+    #[error("zipped xml file is broken: {0}")]
+    CorruptXmlArchive(Cow<'static, str>),
+
+    /// The `GenApi` xml file retrieved from the device doesn't match the sha1 hash reported by
+    /// the device.
+    #[error("sha1 of the retrieved xml file doesn't match the hash reported by the device")]
+    XmlHashMismatch,
+
+    /// The `GenApi` xml file retrieved from the device isn't encoded in UTF-8.
+    #[error("xml file isn't encoded in UTF-8: {0}")]
+    XmlNotUtf8(std::string::FromUtf8Error),
+
+    /// The operation is not supported by the device.
+    #[error("operation is not supported by the device: {0}")]
+    NotSupported(Cow<'static, str>),
+
+    /// The device kept rejecting the command with a retryable status, e.g. busy, until the
+    /// retry policy gave up.
+    #[error("{source} (gave up after {attempts} attempts)")]
+    RetriesExhausted {
+        /// The number of attempts including the first one.
+        attempts: u32,
+        /// The error of the last attempt.
+        source: Box<ControlError>,
+    },
 }
 
 /// A specialized `Result` type for streaming.
@@ -241,11 +444,169 @@ pub enum StreamError {
     #[error("buffer is too small to recieve data")]
     BufferTooSmall,
 
+    /// The device sent a leader or trailer larger than the transfer size, which is the maximum
+    /// size reported by the device plus the headroom.
+    #[error(
+        "{section} of {actual_size} bytes exceeds the transfer size {transfer_size} bytes \
+         (the device reports {reported_size} bytes), \
+         consider setting `{section}_headroom` of the quirks to at least {} bytes",
+        actual_size - reported_size
+    )]
+    SectionOverflow {
+        /// `leader` or `trailer`.
+        section: &'static str,
+        /// The maximum size reported by the device.
+        reported_size: usize,
+        /// The size of the transfer, including the headroom.
+        transfer_size: usize,
+        /// The size of the section sent by the device.
+        actual_size: usize,
+    },
+
+    /// A stage of the payload pipeline failed to process the payload.
+    #[error("pipeline stage failed: {0}")]
+    Pipeline(Cow<'static, str>),
+
     /// Streaming is already started.
     #[error(
         "streaming is already started. can't use the handle from the outside of streaming loop"
     )]
     InStreaming,
+
+    /// The operation is not supported by the handle.
+    #[error("operation is not supported: {0}")]
+    NotSupported(Cow<'static, str>),
+}
+
+impl CameleonError {
+    /// Returns the stable code of the error.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::ControlError(err) => err.code(),
+            Self::StreamError(err) => err.code(),
+            Self::GenApiContextMissing => ErrorCode::GENAPI_CONTEXT_MISSING,
+            Self::InvalidGenApiXml(..) => ErrorCode::INVALID_GENAPI_XML,
+            Self::MissingCapability { .. } => ErrorCode::MISSING_CAPABILITY,
+            Self::GenApiError(err) => err.code(),
+            Self::LoadError(err) => err.code(),
+            Self::PatchError(err) => err.code(),
+            Self::UserSetUnavailable { .. } => ErrorCode::MISSING_CAPABILITY,
+            Self::UserSetMismatch { .. } => ErrorCode::USER_SET_MISMATCH,
+            Self::CommandTimeout { .. } => ErrorCode::TIMEOUT,
+            Self::LockedDuringAcquisition { .. } => ErrorCode::LOCKED_DURING_ACQUISITION,
+        }
+    }
+}
+
+impl ControlError {
+    /// Returns the stable code of the error.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Busy => ErrorCode::BUSY,
+            Self::AlreadyOpenInProcess { .. } => ErrorCode::ALREADY_OPEN,
+            Self::Disconnected => ErrorCode::DISCONNECTED,
+            Self::Io(..) => ErrorCode::IO,
+            Self::Timeout => ErrorCode::TIMEOUT,
+            Self::NotOpened => ErrorCode::NOT_OPENED,
+            Self::InvalidDevice(..) => ErrorCode::INVALID_DEVICE,
+            Self::BufferTooSmall => ErrorCode::BUFFER_TOO_SMALL,
+            Self::InvalidData(..) => ErrorCode::INVALID_DATA,
+            Self::LimitExceeded { .. } => ErrorCode::LIMIT_EXCEEDED,
+            Self::InvalidAddress { .. } => ErrorCode::INVALID_ADDRESS,
+            Self::PartialRead { .. } => ErrorCode::PARTIAL_READ,
+            Self::PartialWrite { .. } => ErrorCode::PARTIAL_WRITE,
+            Self::PartialChunkRead { .. } => ErrorCode::PARTIAL_READ,
+            Self::PartialChunkWrite { .. } => ErrorCode::PARTIAL_WRITE,
+            Self::UnexpectedAck { .. } => ErrorCode::UNEXPECTED_ACK,
+            Self::RequestIdMismatch { .. } => ErrorCode::UNEXPECTED_ACK,
+            Self::CorruptXmlArchive(..) => ErrorCode::CORRUPT_XML_ARCHIVE,
+            Self::XmlHashMismatch => ErrorCode::XML_INTEGRITY,
+            Self::XmlNotUtf8(..) => ErrorCode::INVALID_GENAPI_XML,
+            Self::NotSupported(..) => ErrorCode::NOT_SUPPORTED,
+            Self::RetriesExhausted { .. } => ErrorCode::BUSY,
+        }
+    }
+}
+
+impl StreamError {
+    /// Returns the stable code of the error.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::ReceiveError(..) => ErrorCode::RECEIVE_FAILED,
+            Self::SendError(..) => ErrorCode::SEND_FAILED,
+            Self::InvalidPayload(..) => ErrorCode::INVALID_PAYLOAD,
+            Self::Disconnected => ErrorCode::DISCONNECTED,
+            Self::Io(..) => ErrorCode::IO,
+            Self::Timeout => ErrorCode::TIMEOUT,
+            Self::Poisoned(..) => ErrorCode::STREAM_POISONED,
+            Self::BufferTooSmall => ErrorCode::BUFFER_TOO_SMALL,
+            Self::SectionOverflow { .. } => ErrorCode::SECTION_OVERFLOW,
+            Self::Pipeline(..) => ErrorCode::PIPELINE_FAILED,
+            Self::InStreaming => ErrorCode::IN_STREAMING,
+            Self::NotSupported(..) => ErrorCode::NOT_SUPPORTED,
+        }
+    }
+}
+
+/// Returns the address `len` bytes after `address`, or [`ControlError::InvalidAddress`] if it
+/// overflows.
+pub(crate) fn checked_address(address: u64, len: u64) -> ControlResult<u64> {
+    address
+        .checked_add(len)
+        .ok_or(ControlError::InvalidAddress { address, len })
+}
+
+impl From<cameleon_device::u3v::Error> for ControlError {
+    fn from(err: cameleon_device::u3v::Error) -> ControlError {
+        use cameleon_device::u3v::Error::{
+            BufferIo, CommandTooLong, InvalidDevice, InvalidGuid, InvalidPacket, LibUsb,
+        };
+        use cameleon_device::u3v::LibUsbError::{
+            Access, BadDescriptor, Busy, Interrupted, InvalidParam, Io, NoDevice, NoMem, NotFound,
+            NotSupported, Other, Overflow, Pipe, Timeout,
+        };
+
+        match &err {
+            LibUsb(libusb_error) => match libusb_error {
+                Io | InvalidParam | Access | Overflow | Pipe | Interrupted | NoMem
+                | NotSupported | BadDescriptor | Other => ControlError::Io(err.into()),
+                Busy => ControlError::Busy,
+                NoDevice | NotFound => ControlError::Disconnected,
+                Timeout => ControlError::Timeout,
+            },
+
+            BufferIo(_) | InvalidPacket(_) => ControlError::Io(err.into()),
+
+            InvalidDevice => ControlError::InvalidDevice("invalid device".into()),
+
+            InvalidGuid(_) => ControlError::InvalidDevice(err.to_string().into()),
+
+            CommandTooLong { .. } => ControlError::InvalidData(err.into()),
+        }
+    }
+}
+
+impl From<cameleon_device::u3v::Error> for StreamError {
+    fn from(err: cameleon_device::u3v::Error) -> Self {
+        use cameleon_device::u3v::Error::LibUsb;
+        use cameleon_device::u3v::LibUsbError::{
+            Access, BadDescriptor, Busy, Interrupted, InvalidParam, Io, NoDevice, NoMem, NotFound,
+            NotSupported, Other, Overflow, Pipe, Timeout,
+        };
+
+        match &err {
+            LibUsb(libusb_error) => match libusb_error {
+                Io | InvalidParam | Access | Overflow | Pipe | Interrupted | NoMem
+                | NotSupported | BadDescriptor | Busy | Other => Self::Io(err.into()),
+                NoDevice | NotFound => Self::Disconnected,
+                Timeout => Self::Timeout,
+            },
+            _ => Self::Io(err.into()),
+        }
+    }
 }
 
 impl From<TryFromIntError> for ControlError {
@@ -253,3 +614,218 @@ impl From<TryFromIntError> for ControlError {
         Self::InvalidDevice(format!("internal data has invalid num type: {}", e).into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Codes are part of the public API, never change the tables below.
+
+    #[test]
+    fn test_control_error_code() {
+        let table = [
+            (ControlError::Busy, 0x0001_0004),
+            (
+                ControlError::AlreadyOpenInProcess {
+                    holder_tag: String::new(),
+                },
+                0x0004_0002,
+            ),
+            (ControlError::Disconnected, 0x0001_0002),
+            (ControlError::Io(anyhow::Error::msg("")), 0x0001_0001),
+            (ControlError::Timeout, 0x0001_0003),
+            (ControlError::NotOpened, 0x0004_0001),
+            (ControlError::InvalidDevice("".into()), 0x0002_0001),
+            (ControlError::BufferTooSmall, 0x0004_0003),
+            (ControlError::InvalidData("".into()), 0x0004_0004),
+            (
+                ControlError::LimitExceeded {
+                    limit: limits::Limit::XmlSize,
+                    claimed: 0,
+                    max: 0,
+                },
+                0x0002_0002,
+            ),
+            (
+                ControlError::InvalidAddress { address: 0, len: 0 },
+                0x0004_0005,
+            ),
+            (
+                ControlError::PartialRead {
+                    index: 0,
+                    requested: 0,
+                    read: 0,
+                },
+                0x0002_0005,
+            ),
+            (
+                ControlError::PartialWrite {
+                    index: 0,
+                    requested: 0,
+                    written: 0,
+                },
+                0x0002_0006,
+            ),
+            (
+                ControlError::PartialChunkRead {
+                    offset: 0,
+                    requested: 0,
+                    read: 0,
+                },
+                0x0002_0005,
+            ),
+            (
+                ControlError::PartialChunkWrite {
+                    offset: 0,
+                    requested: 0,
+                    written: 0,
+                },
+                0x0002_0006,
+            ),
+            (
+                ControlError::UnexpectedAck {
+                    expected_ack_id: 0,
+                    ack_id: 0,
+                    expected_request_id: 0,
+                    request_id: 0,
+                },
+                0x0002_0007,
+            ),
+            (
+                ControlError::RequestIdMismatch {
+                    expected: 0,
+                    actual: 0,
+                },
+                0x0002_0007,
+            ),
+            (ControlError::CorruptXmlArchive("".into()), 0x0002_0008),
+            (ControlError::XmlHashMismatch, 0x0003_0003),
+            (
+                ControlError::XmlNotUtf8(String::from_utf8(vec![0xff]).unwrap_err()),
+                0x0003_0001,
+            ),
+            (ControlError::NotSupported("".into()), 0x0004_0007),
+            (
+                ControlError::RetriesExhausted {
+                    attempts: 3,
+                    source: Box::new(ControlError::Timeout),
+                },
+                0x0001_0004,
+            ),
+        ];
+        for (err, code) in &table {
+            assert_eq!(err.code().get(), *code, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_stream_error_code() {
+        let table = [
+            (StreamError::ReceiveError("".into()), 0x0001_0005),
+            (StreamError::SendError("".into()), 0x0001_0006),
+            (StreamError::InvalidPayload("".into()), 0x0002_0003),
+            (StreamError::Disconnected, 0x0001_0002),
+            (StreamError::Io(anyhow::Error::msg("")), 0x0001_0001),
+            (StreamError::Timeout, 0x0001_0003),
+            (StreamError::Poisoned("".into()), 0x0001_0007),
+            (StreamError::BufferTooSmall, 0x0004_0003),
+            (
+                StreamError::SectionOverflow {
+                    section: "leader",
+                    reported_size: 0,
+                    transfer_size: 0,
+                    actual_size: 0,
+                },
+                0x0002_0004,
+            ),
+            (StreamError::Pipeline("".into()), 0x0004_0008),
+            (StreamError::InStreaming, 0x0004_0006),
+            (StreamError::NotSupported("".into()), 0x0004_0007),
+        ];
+        for (err, code) in &table {
+            assert_eq!(err.code().get(), *code, "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn test_cameleon_error_code() {
+        use load_options::{LoadError, LoadPhase};
+
+        let table = [
+            (CameleonError::ControlError(ControlError::Busy), 0x0001_0004),
+            (
+                CameleonError::StreamError(StreamError::Timeout),
+                0x0001_0003,
+            ),
+            (CameleonError::GenApiContextMissing, 0x0004_0009),
+            (CameleonError::InvalidGenApiXml("".into()), 0x0003_0001),
+            (
+                CameleonError::MissingCapability { missing: vec![] },
+                0x0003_0002,
+            ),
+            (
+                CameleonError::GenApiError(cameleon_genapi::GenApiError::NotWritable),
+                0x0003_0004,
+            ),
+            (LoadError::Cancelled { bytes_done: 0 }.into(), 0x0004_000a),
+            (
+                LoadError::DeadlineExceeded {
+                    phase: LoadPhase::Parse,
+                }
+                .into(),
+                0x0001_0009,
+            ),
+            (LoadError::IntegrityError.into(), 0x0003_0003),
+            (
+                CameleonError::UserSetUnavailable {
+                    requested: user_set::UserSet::User(1),
+                    available: vec![],
+                },
+                0x0003_0002,
+            ),
+            (
+                CameleonError::UserSetMismatch {
+                    slot: user_set::UserSet::User(1),
+                    mismatches: vec![],
+                },
+                0x0003_0009,
+            ),
+            (
+                CameleonError::CommandTimeout {
+                    command: "UserSetSave",
+                    timeout: std::time::Duration::from_secs(1),
+                },
+                0x0001_0003,
+            ),
+            (
+                CameleonError::LockedDuringAcquisition {
+                    feature: "Width".into(),
+                },
+                0x0004_000b,
+            ),
+            (
+                LoadError::ControlError(ControlError::NotOpened).into(),
+                0x0004_0001,
+            ),
+            (
+                patch::PatchError::ChecksumMismatch {
+                    expected: "".into(),
+                    actual: "".into(),
+                }
+                .into(),
+                0x0004_0004,
+            ),
+            (
+                patch::PatchError::BootstrapRegister {
+                    line: 1,
+                    address: 0,
+                }
+                .into(),
+                0x0004_0005,
+            ),
+        ];
+        for (err, code) in &table {
+            assert_eq!(err.code().get(), *code, "{:?}", err);
+        }
+    }
+}
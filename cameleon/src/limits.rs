@@ -0,0 +1,207 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains limits on the values claimed by the device.
+//!
+//! A buggy device may claim huge sizes, and the host would allocate memory according to them.
+//! [`Limits`] bounds such values, and the operation fails with [`ControlError::LimitExceeded`] when
+//! the device claims a value exceeding the limit.
+
+use std::{convert::TryInto, fmt, time::Duration};
+
+use super::{ControlError, ControlResult};
+
+/// Default value of [`Limits::max_xml_size`].
+const DEFAULT_MAX_XML_SIZE: usize = 32 * 1024 * 1024;
+
+/// Default value of [`Limits::max_manifest_entries`].
+const DEFAULT_MAX_MANIFEST_ENTRIES: u64 = 64;
+
+/// Default value of [`Limits::max_allocation`].
+const DEFAULT_MAX_ALLOCATION: usize = 1024 * 1024 * 1024;
+
+/// Default value of [`Limits::max_pending_timeout`].
+const DEFAULT_MAX_PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default value of [`Limits::max_pending_wait`].
+const DEFAULT_MAX_PENDING_WAIT: Duration = Duration::from_secs(30);
+
+/// Limits on the values claimed by the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(default))]
+pub struct Limits {
+    /// Maximum size of `GenApi` XML file in bytes. The limit is applied to both compressed and
+    /// uncompressed size.
+    pub max_xml_size: usize,
+
+    /// Maximum number of entries in the manifest table.
+    pub max_manifest_entries: u64,
+
+    /// Maximum size of a single allocation based on the length reported by the device. e.g.
+    /// maximum acknowledge length and required payload size.
+    pub max_allocation: usize,
+
+    /// Maximum timeout the device can request with a pending acknowledge.
+    pub max_pending_timeout: Duration,
+
+    /// Maximum total duration a single command can be extended by pending acknowledges.
+    pub max_pending_wait: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_xml_size: DEFAULT_MAX_XML_SIZE,
+            max_manifest_entries: DEFAULT_MAX_MANIFEST_ENTRIES,
+            max_allocation: DEFAULT_MAX_ALLOCATION,
+            max_pending_timeout: DEFAULT_MAX_PENDING_TIMEOUT,
+            max_pending_wait: DEFAULT_MAX_PENDING_WAIT,
+        }
+    }
+}
+
+impl Limits {
+    /// Returns an error if `size` exceeds [`Limits::max_xml_size`].
+    pub fn check_xml_size(&self, size: impl TryInto<u64>) -> ControlResult<()> {
+        check(Limit::XmlSize, size, self.max_xml_size)
+    }
+
+    /// Returns an error if `num` exceeds [`Limits::max_manifest_entries`].
+    pub fn check_manifest_entries(&self, num: u64) -> ControlResult<()> {
+        check(Limit::ManifestEntries, num, self.max_manifest_entries)
+    }
+
+    /// Returns an error if `size` exceeds [`Limits::max_allocation`].
+    pub fn check_allocation(&self, size: impl TryInto<u64>) -> ControlResult<()> {
+        check(Limit::Allocation, size, self.max_allocation)
+    }
+
+    /// Returns an error if `timeout` exceeds [`Limits::max_pending_timeout`].
+    pub fn check_pending_timeout(&self, timeout: Duration) -> ControlResult<()> {
+        check(
+            Limit::PendingTimeout,
+            timeout.as_millis(),
+            self.max_pending_timeout.as_millis(),
+        )
+    }
+
+    /// Returns an error if `wait` exceeds [`Limits::max_pending_wait`].
+    pub fn check_pending_wait(&self, wait: Duration) -> ControlResult<()> {
+        check(
+            Limit::PendingWait,
+            wait.as_millis(),
+            self.max_pending_wait.as_millis(),
+        )
+    }
+}
+
+/// Kind of the limit, see [`Limits`] for details.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// [`Limits::max_xml_size`]. The unit is byte.
+    XmlSize,
+    /// [`Limits::max_manifest_entries`].
+    ManifestEntries,
+    /// [`Limits::max_allocation`]. The unit is byte.
+    Allocation,
+    /// [`Limits::max_pending_timeout`]. The unit is millisecond.
+    PendingTimeout,
+    /// [`Limits::max_pending_wait`]. The unit is millisecond.
+    PendingWait,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::XmlSize => "xml size",
+            Self::ManifestEntries => "manifest entries",
+            Self::Allocation => "allocation size",
+            Self::PendingTimeout => "pending timeout",
+            Self::PendingWait => "total pending wait",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+fn check(limit: Limit, claimed: impl TryInto<u64>, max: impl TryInto<u64>) -> ControlResult<()> {
+    // Values which don't fit in `u64` are saturated.
+    let claimed = claimed.try_into().unwrap_or(u64::MAX);
+    let max = max.try_into().unwrap_or(u64::MAX);
+    if claimed > max {
+        Err(ControlError::LimitExceeded {
+            limit,
+            claimed,
+            max,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_exceeded(result: ControlResult<()>, expected: Limit, expected_claimed: u64) {
+        match result {
+            Err(ControlError::LimitExceeded { limit, claimed, .. }) => {
+                assert_eq!(limit, expected);
+                assert_eq!(claimed, expected_claimed);
+            }
+            _ => panic!("limit is not enforced: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_default_limits() {
+        let limits = Limits::default();
+        assert!(limits.check_xml_size(1024_usize * 1024).is_ok());
+        assert!(limits.check_manifest_entries(2).is_ok());
+        assert!(limits.check_allocation(1024_u32).is_ok());
+        assert!(limits
+            .check_pending_timeout(Duration::from_millis(100))
+            .is_ok());
+        assert!(limits.check_pending_wait(Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn test_absurd_values() {
+        let limits = Limits::default();
+        assert_exceeded(
+            limits.check_xml_size(u32::MAX),
+            Limit::XmlSize,
+            u64::from(u32::MAX),
+        );
+        assert_exceeded(
+            limits.check_manifest_entries(10_000),
+            Limit::ManifestEntries,
+            10_000,
+        );
+        assert_exceeded(
+            limits.check_allocation(u64::MAX),
+            Limit::Allocation,
+            u64::MAX,
+        );
+        assert_exceeded(
+            limits.check_pending_timeout(Duration::from_millis(u64::from(u16::MAX))),
+            Limit::PendingTimeout,
+            u64::from(u16::MAX),
+        );
+        assert_exceeded(
+            limits.check_pending_wait(Duration::from_secs(3600)),
+            Limit::PendingWait,
+            3_600_000,
+        );
+    }
+
+    #[test]
+    fn test_custom_limits() {
+        let limits = Limits {
+            max_xml_size: 16,
+            ..Limits::default()
+        };
+        assert!(limits.check_xml_size(16_usize).is_ok());
+        assert_exceeded(limits.check_xml_size(17_usize), Limit::XmlSize, 17);
+    }
+}
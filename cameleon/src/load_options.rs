@@ -0,0 +1,850 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains [`LoadOptions`] which configures how
+//! [`Camera::load_context_with_options`] retrieves `GenApi` xml from the device.
+//!
+//! Retrieving `GenApi` xml may take a long time on unreliable links. The retrieval can be cancelled
+//! with [`CancelToken`] and bounded with a deadline, and the failed reads are retried. When a disk
+//! cache is configured, the retrieved bytes are kept in the cache so that the next call resumes
+//! the retrieval instead of starting over.
+//!
+//! Every failure leaves the camera opened, so that the caller can retry loading.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::{Duration, Instant};
+//!
+//! use cameleon::{load_options::{CancelToken, LoadError, LoadOptions}, u3v};
+//!
+//! let mut cameras = u3v::enumerate_cameras().unwrap();
+//! let mut camera = cameras.pop().unwrap();
+//! camera.open().unwrap();
+//!
+//! let cancel = CancelToken::new();
+//! let options = LoadOptions::new()
+//!     .deadline(Instant::now() + Duration::from_secs(30))
+//!     .cancel(cancel.clone())
+//!     .retries(3)
+//!     .cache("/tmp/cameleon");
+//!
+//! // `cancel.cancel()` may be called from another thread.
+//! match camera.load_context_with_options(&options) {
+//!     Ok(_) => {}
+//!     Err(LoadError::Cancelled { bytes_done }) => println!("cancelled after {} bytes", bytes_done),
+//!     Err(e) => println!("{}", e),
+//! }
+//! ```
+//!
+//! [`Camera::load_context_with_options`]: crate::Camera::load_context_with_options
+
+use std::{
+    convert::TryInto,
+    fmt, fs,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use sha1::{Digest, Sha1};
+use tracing::warn;
+
+use super::{
+    checked_address, genapi::ParserConfig, limits::Limits, ControlError, ControlResult,
+    DeviceControl, ErrorCode,
+};
+
+/// Default value of [`LoadOptions::chunk_size`].
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Length of sha1 hash in bytes.
+const HASH_LEN: usize = 20;
+
+/// A specialized `Result` type for [`Camera::load_context_with_options`].
+///
+/// [`Camera::load_context_with_options`]: crate::Camera::load_context_with_options
+pub type LoadResult<T> = std::result::Result<T, LoadError>;
+
+/// An error type returned from [`Camera::load_context_with_options`].
+///
+/// The camera is left opened on any error, and loading can be retried.
+///
+/// [`Camera::load_context_with_options`]: crate::Camera::load_context_with_options
+#[derive(Debug, thiserror::Error)]
+pub enum LoadError {
+    /// Loading is cancelled with [`CancelToken`].
+    #[error("loading `GenApi` context is cancelled after {bytes_done} bytes are retrieved")]
+    Cancelled {
+        /// Number of bytes of the xml file retrieved before the cancellation, including the
+        /// bytes resumed from the cache.
+        bytes_done: usize,
+    },
+
+    /// The deadline has passed before loading completes.
+    #[error("deadline of loading `GenApi` context is exceeded in {phase} phase")]
+    DeadlineExceeded {
+        /// The phase in which the deadline is exceeded.
+        phase: LoadPhase,
+    },
+
+    /// The retrieved xml file doesn't match the sha1 hash reported by the device.
+    #[error("sha1 of the retrieved xml file doesn't match the hash reported by the device")]
+    IntegrityError,
+
+    /// An error from device control, including parsing errors of the xml.
+    #[error("control error: {0}")]
+    ControlError(#[from] ControlError),
+}
+
+impl LoadError {
+    /// Returns the stable code of the error.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Cancelled { .. } => ErrorCode::CANCELLED,
+            Self::DeadlineExceeded { .. } => ErrorCode::DEADLINE_EXCEEDED,
+            Self::IntegrityError => ErrorCode::XML_INTEGRITY,
+            Self::ControlError(err) => err.code(),
+        }
+    }
+}
+
+/// A phase of loading `GenApi` context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadPhase {
+    /// Locating the xml file in the device memory.
+    Locate,
+    /// Retrieving the xml file from the device.
+    Download,
+    /// Parsing the xml.
+    Parse,
+}
+
+impl fmt::Display for LoadPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Locate => f.write_str("locate"),
+            Self::Download => f.write_str("download"),
+            Self::Parse => f.write_str("parse"),
+        }
+    }
+}
+
+/// A token to cancel loading from another thread.
+///
+/// Cloned tokens share the cancellation state.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Constructs a token which is not cancelled.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels loading which the token is passed to.
+    ///
+    /// Loading is cancelled between reads, a read in progress is not interrupted.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if the token is cancelled.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Location of `GenApi` xml file in the device memory.
+///
+/// See [`DeviceControl::genapi_file`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenApiFile {
+    /// Address of the file.
+    pub address: u64,
+    /// Size of the file in bytes.
+    pub size: usize,
+    /// `true` if the file is zipped.
+    pub zipped: bool,
+    /// Sha1 hash of the file, `None` if the device doesn't provide it.
+    pub sha1: Option<[u8; HASH_LEN]>,
+    /// Maximum size of the unzipped xml in bytes.
+    pub max_xml_size: usize,
+}
+
+impl GenApiFile {
+    /// Returns `false` if `data` doesn't match [`GenApiFile::sha1`].
+    ///
+    /// Always returns `true` if the device doesn't provide the hash.
+    #[must_use]
+    pub fn verify(&self, data: &[u8]) -> bool {
+        match &self.sha1 {
+            Some(hash) => Sha1::digest(data)[..] == hash[..],
+            None => true,
+        }
+    }
+}
+
+/// Options applied when loading `GenApi` context.
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// Loading fails if it doesn't complete until the deadline.
+    deadline: Option<Instant>,
+    /// Token to cancel loading.
+    cancel: Option<CancelToken>,
+    /// How many times to retry a failed read.
+    retries: u16,
+    /// Directory of the disk cache.
+    cache: Option<PathBuf>,
+    /// Size of a single read.
+    chunk_size: usize,
+    /// Configuration of the xml parser.
+    pub(crate) parser_config: ParserConfig,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            deadline: None,
+            cancel: None,
+            retries: 0,
+            cache: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            parser_config: ParserConfig::default(),
+        }
+    }
+}
+
+impl LoadOptions {
+    /// Constructs options with default values.
+    ///
+    /// By default, loading has no deadline, is not cancellable, doesn't retry and doesn't use the
+    /// disk cache.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the deadline of loading.
+    ///
+    /// The deadline is checked between reads, so loading may take longer than the deadline by the
+    /// transaction timeout of the device.
+    #[must_use]
+    pub fn deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Sets the token to cancel loading.
+    #[must_use]
+    pub fn cancel(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Sets how many times to retry a failed read of the xml file.
+    ///
+    /// The retry count is applied to each read.
+    #[must_use]
+    pub fn retries(mut self, retries: u16) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the directory of the disk cache.
+    ///
+    /// The cache is used only if the device provides the sha1 hash of the xml file, the hash is
+    /// used as the key of the cache. Failures to access the cache are logged and ignored.
+    #[must_use]
+    pub fn cache(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache = Some(dir.into());
+        self
+    }
+
+    /// Sets the size of a single read of the xml file.
+    ///
+    /// Cancellation and the deadline are checked between reads.
+    ///
+    /// # Panics
+    /// Panics if `size` is zero.
+    #[must_use]
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "chunk size must not be zero");
+        self.chunk_size = size;
+        self
+    }
+
+    /// Sets the configuration of the xml parser.
+    #[must_use]
+    pub fn parser_config(mut self, config: ParserConfig) -> Self {
+        self.parser_config = config;
+        self
+    }
+
+    /// Returns an error if loading is cancelled or the deadline has passed.
+    pub(crate) fn check(&self, phase: LoadPhase, bytes_done: usize) -> LoadResult<()> {
+        if matches!(&self.cancel, Some(token) if token.is_cancelled()) {
+            Err(LoadError::Cancelled { bytes_done })
+        } else if matches!(self.deadline, Some(deadline) if Instant::now() >= deadline) {
+            Err(LoadError::DeadlineExceeded { phase })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Retrieves `GenApi` xml from the device according to `options`.
+///
+/// The size of the xml is checked against [`Limits::max_xml_size`] of `ctrl`.
+pub(crate) fn load_xml<Ctrl: DeviceControl + ?Sized>(
+    ctrl: &mut Ctrl,
+    options: &LoadOptions,
+) -> LoadResult<String> {
+    let limits = ctrl.limits();
+    options.check(LoadPhase::Locate, 0)?;
+    let mut file = match ctrl.genapi_file()? {
+        Some(file) => file,
+        None => {
+            // The xml file can't be read directly, let the device retrieve it.
+            options.check(LoadPhase::Download, 0)?;
+            let xml = ctrl.genapi()?;
+            limits.check_xml_size(xml.len())?;
+            return Ok(xml);
+        }
+    };
+    limits.check_xml_size(file.size)?;
+    file.max_xml_size = file.max_xml_size.min(limits.max_xml_size);
+    checked_address(file.address, file.size as u64)?;
+
+    let cache = match (&options.cache, &file.sha1) {
+        (Some(dir), Some(hash)) => Some(Cache::new(dir, hash)),
+        _ => None,
+    };
+    let buf = match cache.as_ref().and_then(|cache| cache.load_complete(&file)) {
+        Some(buf) => buf,
+        None => download(ctrl, &file, options, cache.as_ref())?,
+    };
+
+    Ok(decode_xml(buf, &file)?)
+}
+
+/// Decodes the xml file retrieved from the device.
+pub(crate) fn decode_xml(buf: Vec<u8>, file: &GenApiFile) -> ControlResult<String> {
+    fn zip_err(err: impl std::fmt::Debug) -> ControlError {
+        ControlError::CorruptXmlArchive(format!("{:?}", err).into())
+    }
+
+    if !file.zipped {
+        return String::from_utf8(buf).map_err(ControlError::XmlNotUtf8);
+    }
+
+    let limits = Limits {
+        max_xml_size: file.max_xml_size,
+        ..Limits::default()
+    };
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(buf)).map_err(zip_err)?;
+    if zip.len() != 1 {
+        return Err(zip_err("more than one files in zipped GenApi XML"));
+    }
+    let mut file = zip.by_index(0).map_err(zip_err)?;
+    limits.check_xml_size(file.size())?;
+    let file_size: usize = file.size().try_into()?;
+    let mut xml = Vec::with_capacity(file_size);
+    file.read_to_end(&mut xml).map_err(zip_err)?;
+    String::from_utf8(xml).map_err(ControlError::XmlNotUtf8)
+}
+
+fn download<Ctrl: DeviceControl + ?Sized>(
+    ctrl: &mut Ctrl,
+    file: &GenApiFile,
+    options: &LoadOptions,
+    cache: Option<&Cache>,
+) -> LoadResult<Vec<u8>> {
+    let mut buf = vec![0; file.size];
+    let mut done = cache.map_or(0, |cache| cache.load_partial(&mut buf));
+
+    if let Err(err) = download_rest(ctrl, file, options, &mut buf, &mut done) {
+        if let Some(cache) = cache {
+            cache.store_partial(&buf[..done]);
+        }
+        return Err(err);
+    }
+
+    if !file.verify(&buf) {
+        if let Some(cache) = cache {
+            cache.remove_partial();
+        }
+        return Err(LoadError::IntegrityError);
+    }
+
+    if let Some(cache) = cache {
+        cache.store_complete(&buf);
+        cache.remove_partial();
+    }
+    Ok(buf)
+}
+
+/// Reads the file from `done` bytes, `done` is updated as the read proceeds.
+fn download_rest<Ctrl: DeviceControl + ?Sized>(
+    ctrl: &mut Ctrl,
+    file: &GenApiFile,
+    options: &LoadOptions,
+    buf: &mut [u8],
+    done: &mut usize,
+) -> LoadResult<()> {
+    while *done < buf.len() {
+        options.check(LoadPhase::Download, *done)?;
+        let len = options.chunk_size.min(buf.len() - *done);
+        let chunk = &mut buf[*done..*done + len];
+        let address = file.address + *done as u64;
+
+        let mut attempt = 0;
+        loop {
+            match ctrl.read(address, chunk) {
+                Ok(()) => break,
+                Err(err @ (ControlError::NotOpened | ControlError::Disconnected)) => {
+                    return Err(err.into())
+                }
+                Err(err) if attempt < options.retries => {
+                    warn!(?err, address, attempt, "retrying read of `GenApi` xml file");
+                    attempt += 1;
+                    options.check(LoadPhase::Download, *done)?;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        *done += len;
+    }
+
+    Ok(())
+}
+
+/// Disk cache of an xml file keyed by its sha1 hash.
+///
+/// A complete file is stored as `<hash>.xml`. A partially retrieved file is stored as
+/// `<hash>.<prefix hash>.partial`, where `<prefix hash>` is the sha1 hash of the retrieved prefix
+/// which the file contains. The prefix is hashed again and verified against `<prefix hash>`
+/// before the retrieval resumes from it.
+struct Cache {
+    dir: PathBuf,
+    key: String,
+}
+
+impl Cache {
+    fn new(dir: &Path, hash: &[u8; HASH_LEN]) -> Self {
+        Self {
+            dir: dir.to_owned(),
+            key: hex(hash),
+        }
+    }
+
+    fn complete(&self) -> PathBuf {
+        self.dir.join(format!("{}.xml", self.key))
+    }
+
+    fn load_complete(&self, file: &GenApiFile) -> Option<Vec<u8>> {
+        let path = self.complete();
+        let buf = fs::read(&path).ok()?;
+        if buf.len() == file.size && file.verify(&buf) {
+            Some(buf)
+        } else {
+            warn!(?path, "discard broken cache of `GenApi` xml file");
+            remove(&path);
+            None
+        }
+    }
+
+    /// Copies a verified prefix into `buf` and returns its length.
+    ///
+    /// Prefixes which don't match their hash are discarded.
+    fn load_partial(&self, buf: &mut [u8]) -> usize {
+        let mut done = 0;
+        for (path, prefix_key) in self.partials() {
+            match fs::read(&path) {
+                Ok(prefix)
+                    if done == 0
+                        && prefix.len() <= buf.len()
+                        && hex(&Sha1::digest(&prefix)) == prefix_key =>
+                {
+                    buf[..prefix.len()].copy_from_slice(&prefix);
+                    done = prefix.len();
+                }
+                _ => {
+                    warn!(?path, "discard broken cache of `GenApi` xml file");
+                    remove(&path);
+                }
+            }
+        }
+        done
+    }
+
+    fn store_complete(&self, buf: &[u8]) {
+        store(&self.complete(), buf);
+    }
+
+    fn store_partial(&self, prefix: &[u8]) {
+        self.remove_partial();
+        if prefix.is_empty() {
+            return;
+        }
+        let prefix_key = hex(&Sha1::digest(prefix));
+        let path = self
+            .dir
+            .join(format!("{}.{}.partial", self.key, prefix_key));
+        store(&path, prefix);
+    }
+
+    fn remove_partial(&self) {
+        for (path, _) in self.partials() {
+            remove(&path);
+        }
+    }
+
+    /// Returns paths of the partially retrieved files and the hashes of their prefixes.
+    fn partials(&self) -> Vec<(PathBuf, String)> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![],
+        };
+        let head = format!("{}.", self.key);
+        entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let prefix_key = path
+                    .file_name()?
+                    .to_str()?
+                    .strip_prefix(&head)?
+                    .strip_suffix(".partial")?
+                    .to_owned();
+                Some((path, prefix_key))
+            })
+            .collect()
+    }
+}
+
+fn hex(hash: &[u8]) -> String {
+    hash.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Writes `data` to `path` via a temporary file so that a crash doesn't leave a truncated file.
+fn store(path: &Path, data: &[u8]) {
+    let tmp = path.with_extension("tmp");
+    let res = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&tmp, data))
+        .and_then(|_| fs::rename(&tmp, path));
+    if let Err(error) = res {
+        warn!(?error, ?path, "failed to store cache of `GenApi` xml file");
+    }
+}
+
+fn remove(path: &Path) {
+    if let Err(error) = fs::remove_file(path) {
+        if error.kind() != std::io::ErrorKind::NotFound {
+            warn!(?error, ?path, "failed to remove cache of `GenApi` xml file");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Device memory holding an xml file at `XML_ADDRESS`.
+    struct Device {
+        memory: Vec<u8>,
+        file: GenApiFile,
+        /// Number of bytes read from the device.
+        bytes_read: usize,
+        /// Cancels the token after the number of reads.
+        cancel_after: Option<(usize, CancelToken)>,
+        /// Number of reads which fail before success.
+        failures: usize,
+        reads: usize,
+        limits: Limits,
+    }
+
+    const XML_ADDRESS: u64 = 0x100;
+
+    impl Device {
+        fn new(xml: &[u8]) -> Self {
+            let mut memory = vec![0; XML_ADDRESS as usize];
+            memory.extend_from_slice(xml);
+            Self {
+                memory,
+                file: GenApiFile {
+                    address: XML_ADDRESS,
+                    size: xml.len(),
+                    zipped: false,
+                    sha1: Some(Sha1::digest(xml).into()),
+                    max_xml_size: Limits::default().max_xml_size,
+                },
+                bytes_read: 0,
+                cancel_after: None,
+                failures: 0,
+                reads: 0,
+                limits: Limits::default(),
+            }
+        }
+    }
+
+    impl DeviceControl for Device {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(ControlError::Timeout);
+            }
+            let address = address as usize;
+            buf.copy_from_slice(&self.memory[address..address + buf.len()]);
+            self.bytes_read += buf.len();
+            self.reads += 1;
+            if let Some((after, token)) = &self.cancel_after {
+                if self.reads == *after {
+                    token.cancel();
+                }
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, _address: u64, _data: &[u8]) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            let file = self.file.clone();
+            let mut buf = vec![0; file.size];
+            self.read(file.address, &mut buf)?;
+            decode_xml(buf, &file)
+        }
+
+        fn genapi_file(&mut self) -> ControlResult<Option<GenApiFile>> {
+            Ok(Some(self.file.clone()))
+        }
+
+        fn limits(&self) -> Limits {
+            self.limits
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+    }
+
+    fn xml() -> Vec<u8> {
+        (0..1000)
+            .map(|i| format!("<Node{}/>", i))
+            .collect::<String>()
+            .into_bytes()
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "cameleon-load-options-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_cancel_and_resume() {
+        let xml = xml();
+        let dir = cache_dir("resume");
+        let mut device = Device::new(&xml);
+        let token = CancelToken::new();
+        device.cancel_after = Some((3, token.clone()));
+        let options = LoadOptions::new()
+            .chunk_size(1000)
+            .cancel(token)
+            .cache(&dir);
+
+        match load_xml(&mut device, &options) {
+            Err(LoadError::Cancelled { bytes_done }) => assert_eq!(bytes_done, 3000),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        // The retrieved prefix is kept under its hash.
+        let cache = Cache::new(&dir, &device.file.sha1.unwrap());
+        let partials = cache.partials();
+        assert_eq!(partials.len(), 1);
+        assert_eq!(partials[0].1, hex(&Sha1::digest(&xml[..3000])));
+
+        // Resumes without the cancelled token, only the rest of the file is read.
+        device.cancel_after = None;
+        device.bytes_read = 0;
+        let options = LoadOptions::new().chunk_size(1000).cache(&dir);
+        let loaded = load_xml(&mut device, &options).unwrap();
+        assert_eq!(device.bytes_read, xml.len() - 3000);
+        assert_eq!(loaded, device.genapi().unwrap());
+        assert!(cache.partials().is_empty());
+
+        // The complete file is served from the cache.
+        device.bytes_read = 0;
+        assert_eq!(load_xml(&mut device, &options).unwrap(), loaded);
+        assert_eq!(device.bytes_read, 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_broken_partial_cache() {
+        let xml = xml();
+        let dir = cache_dir("broken");
+        let mut device = Device::new(&xml);
+        let cache = Cache::new(&dir, &device.file.sha1.unwrap());
+        cache.store_partial(&xml[..100]);
+        // Corrupt the prefix.
+        let (path, _) = cache.partials().pop().unwrap();
+        let mut data = fs::read(&path).unwrap();
+        data[0] ^= 0xff;
+        fs::write(&path, data).unwrap();
+
+        let options = LoadOptions::new().cache(&dir);
+        load_xml(&mut device, &options).unwrap();
+        assert_eq!(device.bytes_read, xml.len());
+        assert!(cache.partials().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_retry_and_integrity() {
+        let xml = xml();
+        let mut device = Device::new(&xml);
+        device.failures = 2;
+        match load_xml(&mut device, &LoadOptions::new().retries(1)) {
+            Err(LoadError::ControlError(ControlError::Timeout)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        device.failures = 2;
+        load_xml(&mut device, &LoadOptions::new().retries(2)).unwrap();
+
+        device.memory[XML_ADDRESS as usize] ^= 0xff;
+        match load_xml(&mut device, &LoadOptions::new()) {
+            Err(LoadError::IntegrityError) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_deadline() {
+        let mut device = Device::new(&xml());
+        let options = LoadOptions::new().deadline(Instant::now());
+        match load_xml(&mut device, &options) {
+            Err(LoadError::DeadlineExceeded { phase }) => assert_eq!(phase, LoadPhase::Locate),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(device.bytes_read, 0);
+    }
+
+    #[test]
+    fn test_file_address_overflow() {
+        let mut device = Device::new(&xml());
+        device.file.address = u64::MAX - 16;
+        match load_xml(&mut device, &LoadOptions::new()) {
+            Err(LoadError::ControlError(ControlError::InvalidAddress { address, len })) => {
+                assert_eq!(address, u64::MAX - 16);
+                assert_eq!(len, device.file.size as u64);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(device.bytes_read, 0);
+    }
+
+    #[test]
+    fn test_xml_size_limit() {
+        use std::io::Write;
+
+        use crate::limits::Limit;
+
+        // The size claimed by the device is checked before downloading.
+        let xml = xml();
+        let mut device = Device::new(&xml);
+        device.limits.max_xml_size = xml.len() - 1;
+        match load_xml(&mut device, &LoadOptions::new()) {
+            Err(LoadError::ControlError(ControlError::LimitExceeded {
+                limit, claimed, ..
+            })) => {
+                assert_eq!(limit, Limit::XmlSize);
+                assert_eq!(claimed, xml.len() as u64);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(device.bytes_read, 0);
+
+        // The uncompressed size is checked against the limits of the handle too.
+        let mut zipped = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        zipped
+            .start_file("device.xml", zip::write::FileOptions::default())
+            .unwrap();
+        zipped.write_all(&xml).unwrap();
+        let zipped = zipped.finish().unwrap().into_inner();
+        let mut device = Device::new(&zipped);
+        device.file.zipped = true;
+        device.limits.max_xml_size = zipped.len();
+        match load_xml(&mut device, &LoadOptions::new()) {
+            Err(LoadError::ControlError(ControlError::LimitExceeded {
+                limit, claimed, ..
+            })) => {
+                assert_eq!(limit, Limit::XmlSize);
+                assert_eq!(claimed, xml.len() as u64);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_decode_xml() {
+        use std::io::Write;
+
+        let xml = xml();
+        let mut zipped = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        zipped
+            .start_file("device.xml", zip::write::FileOptions::default())
+            .unwrap();
+        zipped.write_all(&xml).unwrap();
+        let zipped = zipped.finish().unwrap().into_inner();
+
+        let mut file = Device::new(&zipped).file;
+        file.zipped = true;
+        assert_eq!(decode_xml(zipped.clone(), &file).unwrap().as_bytes(), xml);
+
+        let truncated = zipped[..zipped.len() / 2].to_vec();
+        assert!(matches!(
+            decode_xml(truncated, &file),
+            Err(ControlError::CorruptXmlArchive(_))
+        ));
+
+        file.zipped = false;
+        assert!(matches!(
+            decode_xml(vec![b'<', 0xff, b'>'], &file),
+            Err(ControlError::XmlNotUtf8(_))
+        ));
+    }
+}
@@ -0,0 +1,377 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains the process wide registry of host side metrics.
+//!
+//! Handles register their counters when they are created, and [`snapshot`] collects the current
+//! values of all the live handles as a flat list. Metrics are labeled with `device_serial`, and
+//! stream metrics with `stream_index` as well. The typed statistics of each handle, e.g.
+//! [`LatencyReport`](crate::latency::LatencyReport), are views of the same counters.
+//!
+//! Modules built on top of this crate, e.g. the GenTL producer, register their own counters with
+//! [`register`].
+//!
+//! With the `prometheus` feature, [`encode_text`] formats the snapshot in the Prometheus text
+//! exposition format.
+//!
+//! # Examples
+//! ```
+//! for metric in cameleon::metrics::snapshot() {
+//!     println!("{} {:?}: {:?}", metric.name, metric.labels, metric.value);
+//! }
+//! ```
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+#[cfg(any(test, feature = "libusb"))]
+use std::time::Duration;
+
+/// Label of the serial number of the device.
+pub const DEVICE_SERIAL_LABEL: &str = "device_serial";
+
+/// Label of the index of the stream channel.
+pub const STREAM_INDEX_LABEL: &str = "stream_index";
+
+static REGISTRY: Registry = Registry::new();
+
+/// Returns the current values of all the metrics registered in the process.
+///
+/// Metrics of a handle are unregistered when the handle is dropped.
+#[must_use]
+pub fn snapshot() -> Vec<Metric> {
+    REGISTRY.snapshot()
+}
+
+/// A single metric.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    /// Name of the metric, e.g. `cameleon_stream_received_payloads_total`.
+    pub name: String,
+    /// Labels of the metric as key value pairs.
+    pub labels: Vec<(String, String)>,
+    /// The value.
+    pub value: MetricValue,
+}
+
+impl Metric {
+    /// Returns the value of the label `key`.
+    #[must_use]
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// A value of [`Metric`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricValue {
+    /// A monotonically increasing counter.
+    Counter(u64),
+    /// A value which can go up and down.
+    Gauge(f64),
+    /// A distribution of observations.
+    Histogram(HistogramValue),
+}
+
+/// A distribution of observations in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramValue {
+    /// Upper bound of each bucket in seconds and the cumulative number of observations less
+    /// than the bound. The last bound is infinity.
+    pub buckets: Vec<(f64, u64)>,
+    /// Total number of observations.
+    pub count: u64,
+    /// Sum of the observations in seconds.
+    pub sum: f64,
+}
+
+/// A type which owns counters and reports them as metrics.
+pub trait MetricSource: Send + Sync {
+    /// Pushes the current values of the counters to `sink`.
+    fn collect(&self, sink: &mut MetricSink<'_>);
+}
+
+/// Collects metrics of a [`MetricSource`], attaching the labels of the source.
+pub struct MetricSink<'a> {
+    labels: &'a [(String, String)],
+    metrics: &'a mut Vec<Metric>,
+}
+
+impl MetricSink<'_> {
+    /// Pushes a counter.
+    pub fn counter(&mut self, name: &str, value: u64) {
+        self.push(name, &[], MetricValue::Counter(value));
+    }
+
+    /// Pushes a gauge.
+    pub fn gauge(&mut self, name: &str, value: f64) {
+        self.push(name, &[], MetricValue::Gauge(value));
+    }
+
+    /// Pushes a metric with labels in addition to the labels of the source.
+    pub fn push(&mut self, name: &str, labels: &[(&str, &str)], value: MetricValue) {
+        let labels = self
+            .labels
+            .iter()
+            .cloned()
+            .chain(
+                labels
+                    .iter()
+                    .map(|(k, v)| ((*k).to_string(), (*v).to_string())),
+            )
+            .collect();
+        self.metrics.push(Metric {
+            name: name.to_string(),
+            labels,
+            value,
+        });
+    }
+}
+
+/// Registers `source` with `labels`, the source is unregistered when it's dropped.
+pub fn register<S: MetricSource + 'static>(source: &Arc<S>, labels: Vec<(String, String)>) {
+    REGISTRY.register(source, labels);
+}
+
+/// Returns the labels of a device.
+#[must_use]
+pub fn device_labels(serial: &str) -> Vec<(String, String)> {
+    vec![(DEVICE_SERIAL_LABEL.to_string(), serial.to_string())]
+}
+
+/// Returns the labels of a stream of a device.
+#[must_use]
+pub fn stream_labels(serial: &str, index: usize) -> Vec<(String, String)> {
+    let mut labels = device_labels(serial);
+    labels.push((STREAM_INDEX_LABEL.to_string(), index.to_string()));
+    labels
+}
+
+/// Converts the upper bounds and the counts of histogram buckets to [`HistogramValue`].
+#[cfg(any(test, feature = "libusb"))]
+pub(crate) fn histogram(
+    buckets: impl IntoIterator<Item = (Duration, u64)>,
+    sum: Duration,
+) -> HistogramValue {
+    let mut count = 0;
+    let buckets = buckets
+        .into_iter()
+        .map(|(end, n)| {
+            count += n;
+            let bound = if end == Duration::MAX {
+                f64::INFINITY
+            } else {
+                end.as_secs_f64()
+            };
+            (bound, count)
+        })
+        .collect();
+    HistogramValue {
+        buckets,
+        count,
+        sum: sum.as_secs_f64(),
+    }
+}
+
+struct Registry {
+    entries: Mutex<Vec<Entry>>,
+}
+
+struct Entry {
+    labels: Vec<(String, String)>,
+    source: Weak<dyn MetricSource>,
+}
+
+impl Registry {
+    const fn new() -> Self {
+        Self {
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn register<S: MetricSource + 'static>(&self, source: &Arc<S>, labels: Vec<(String, String)>) {
+        let source: Arc<dyn MetricSource> = source.clone();
+        let mut entries = self.entries();
+        entries.retain(|entry| entry.source.strong_count() > 0);
+        entries.push(Entry {
+            labels,
+            source: Arc::downgrade(&source),
+        });
+    }
+
+    fn snapshot(&self) -> Vec<Metric> {
+        let mut metrics = vec![];
+        let mut entries = self.entries();
+        entries.retain(|entry| match entry.source.upgrade() {
+            Some(source) => {
+                source.collect(&mut MetricSink {
+                    labels: &entry.labels,
+                    metrics: &mut metrics,
+                });
+                true
+            }
+            None => false,
+        });
+        metrics
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Vec<Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Formats `metrics` in the Prometheus text exposition format.
+///
+/// # Examples
+/// ```
+/// let text = cameleon::metrics::encode_text(&cameleon::metrics::snapshot());
+/// print!("{}", text);
+/// ```
+#[cfg(feature = "prometheus")]
+#[must_use]
+pub fn encode_text(metrics: &[Metric]) -> String {
+    use std::fmt::Write;
+
+    // Samples of a metric family must be grouped, keeps the order of the first appearance.
+    let mut names: Vec<&str> = vec![];
+    for metric in metrics {
+        if !names.contains(&metric.name.as_str()) {
+            names.push(&metric.name);
+        }
+    }
+
+    let mut text = String::new();
+    for name in names {
+        let mut family = metrics
+            .iter()
+            .filter(|metric| metric.name == name)
+            .peekable();
+        let ty = match family.peek().map(|metric| &metric.value) {
+            Some(MetricValue::Counter(_)) => "counter",
+            Some(MetricValue::Gauge(_)) => "gauge",
+            _ => "histogram",
+        };
+        writeln!(text, "# TYPE {} {}", name, ty).unwrap();
+        for metric in family {
+            encode_metric(&mut text, metric);
+        }
+    }
+    text
+}
+
+#[cfg(feature = "prometheus")]
+fn encode_metric(text: &mut String, metric: &Metric) {
+    use std::fmt::Write;
+
+    let labels = |extra: Option<(&str, String)>| {
+        let pairs: Vec<_> = metric
+            .labels
+            .iter()
+            .map(|(k, v)| (k.as_str(), escape(v)))
+            .chain(extra)
+            .map(|(k, v)| format!("{}=\"{}\"", k, v))
+            .collect();
+        if pairs.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", pairs.join(","))
+        }
+    };
+    match &metric.value {
+        MetricValue::Counter(value) => {
+            writeln!(text, "{}{} {}", metric.name, labels(None), value).unwrap();
+        }
+        MetricValue::Gauge(value) => {
+            writeln!(text, "{}{} {}", metric.name, labels(None), value).unwrap();
+        }
+        MetricValue::Histogram(histogram) => {
+            for (bound, count) in &histogram.buckets {
+                let le = if bound.is_infinite() {
+                    "+Inf".to_string()
+                } else {
+                    bound.to_string()
+                };
+                let bucket_labels = labels(Some(("le", le)));
+                writeln!(text, "{}_bucket{} {}", metric.name, bucket_labels, count).unwrap();
+            }
+            let labels = labels(None);
+            writeln!(text, "{}_sum{} {}", metric.name, labels, histogram.sum).unwrap();
+            writeln!(text, "{}_count{} {}", metric.name, labels, histogram.count).unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "prometheus")]
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Counters(AtomicU64);
+
+    impl MetricSource for Counters {
+        fn collect(&self, sink: &mut MetricSink<'_>) {
+            sink.counter("test_total", self.0.load(Ordering::Relaxed));
+            let latency = histogram(
+                vec![
+                    (Duration::from_millis(1), 2),
+                    (Duration::from_millis(2), 0),
+                    (Duration::MAX, 1),
+                ],
+                Duration::from_millis(5),
+            );
+            sink.push(
+                "test_latency_seconds",
+                &[("kind", "read")],
+                MetricValue::Histogram(latency),
+            );
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = Registry::new();
+        let counters = Arc::new(Counters::default());
+        registry.register(&counters, stream_labels("SN\"1", 0));
+        counters.0.fetch_add(3, Ordering::Relaxed);
+
+        let metrics = registry.snapshot();
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0].name, "test_total");
+        assert_eq!(metrics[0].value, MetricValue::Counter(3));
+        assert_eq!(metrics[0].label(DEVICE_SERIAL_LABEL), Some("SN\"1"));
+        assert_eq!(metrics[0].label(STREAM_INDEX_LABEL), Some("0"));
+        assert_eq!(metrics[1].label("kind"), Some("read"));
+        match &metrics[1].value {
+            MetricValue::Histogram(histogram) => {
+                assert_eq!(histogram.count, 3);
+                assert_eq!(histogram.buckets[1], (0.002, 2));
+                assert_eq!(histogram.buckets[2], (f64::INFINITY, 3));
+            }
+            value => panic!("{:?}", value),
+        }
+
+        #[cfg(feature = "prometheus")]
+        assert_eq!(
+            encode_text(&metrics[..1]),
+            "# TYPE test_total counter\n\
+             test_total{device_serial=\"SN\\\"1\",stream_index=\"0\"} 3\n"
+        );
+
+        drop(counters);
+        assert!(registry.snapshot().is_empty());
+        assert!(registry.entries().is_empty());
+    }
+}
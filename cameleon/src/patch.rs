@@ -0,0 +1,911 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains register-level patch scripts, see [`Camera::apply_patch`].
+//!
+//! A patch script is a text file which pokes registers of a camera, e.g. to enable a hidden
+//! vendor feature or to fix a miscalibrated default, without rebuilding the application.
+//!
+//! The first line is the header which holds the sha1 checksum of the rest of the file, and each
+//! following line is a step. Registers are accessed in little endian.
+//!
+//! ```text
+//! cameleon-patch 1 sha1=<hex of sha1 of the lines below>
+//! # Comments and empty lines are ignored.
+//! check 0x10000 u32 0x1             # Aborts unless the register holds the value.
+//! check 0x10004 u32 0x0 optional    # Reported, but doesn't abort.
+//! write 0x10008 u32 640
+//! write-masked 0x1000c u32 0xff00 0x1200
+//! delay 10ms
+//! verify 0x10008 u32 640            # Same as `check`, after the writes.
+//! ```
+//!
+//! [`PatchScript::seal`] computes the header for the steps.
+//!
+//! Scripts which write to bootstrap registers, i.e. `ABRM`, `SBRM`, `SIRM` and `EIRM`, are
+//! refused unless [`PatchOptions::allow_bootstrap`] is set.
+//!
+//! [`Camera::apply_patch`]: crate::Camera::apply_patch
+
+use std::{
+    fmt, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use cameleon_device::u3v::register_map::{abrm, sbrm};
+use sha1::{Digest, Sha1};
+
+use super::{checked_address, ControlError, ControlResult, DeviceControl, ErrorCode};
+
+/// Magic of the header line.
+const MAGIC: &str = "cameleon-patch";
+
+/// Version of the script format.
+const VERSION: u32 = 1;
+
+/// A specialized `Result` type for patch scripts.
+pub type PatchResult<T> = std::result::Result<T, PatchError>;
+
+/// An error type returned when a patch script is refused.
+///
+/// Failures while a script is executed are reported in [`PatchReport`] instead.
+#[derive(Debug, thiserror::Error)]
+pub enum PatchError {
+    /// Failed to read the script file.
+    #[error("failed to read patch script `{}`: {source}", path.display())]
+    Io {
+        /// Path of the script.
+        path: PathBuf,
+        /// The error.
+        source: std::io::Error,
+    },
+
+    /// The script is malformed.
+    #[error("invalid patch script at line {line}: {message}")]
+    Invalid {
+        /// Line number starting from 1.
+        line: usize,
+        /// Description of the error.
+        message: String,
+    },
+
+    /// The checksum in the header doesn't match the script.
+    #[error("checksum of patch script doesn't match, expected {expected}, actual {actual}")]
+    ChecksumMismatch {
+        /// The checksum in the header.
+        expected: String,
+        /// The checksum of the script.
+        actual: String,
+    },
+
+    /// The script writes to a bootstrap register without [`PatchOptions::allow_bootstrap`].
+    #[error("step at line {line} writes to bootstrap register at {address:#X}")]
+    BootstrapRegister {
+        /// Line number of the step.
+        line: usize,
+        /// Address of the register.
+        address: u64,
+    },
+
+    /// Failed to locate the bootstrap registers.
+    #[error("failed to locate bootstrap registers: {0}")]
+    ControlError(#[from] ControlError),
+}
+
+impl PatchError {
+    /// Returns the stable code of the error.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Io { .. } | Self::Invalid { .. } | Self::ChecksumMismatch { .. } => {
+                ErrorCode::INVALID_DATA
+            }
+            Self::BootstrapRegister { .. } => ErrorCode::INVALID_ADDRESS,
+            Self::ControlError(err) => err.code(),
+        }
+    }
+}
+
+/// Width of a register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegisterWidth {
+    /// 1 byte.
+    U8,
+    /// 2 bytes.
+    U16,
+    /// 4 bytes.
+    U32,
+    /// 8 bytes.
+    U64,
+}
+
+impl RegisterWidth {
+    /// Size of the register in bytes.
+    #[must_use]
+    pub fn size(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::U16 => 2,
+            Self::U32 => 4,
+            Self::U64 => 8,
+        }
+    }
+
+    fn max(self) -> u64 {
+        match self {
+            Self::U64 => u64::MAX,
+            _ => (1 << (self.size() * 8)) - 1,
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "u8" => Some(Self::U8),
+            "u16" => Some(Self::U16),
+            "u32" => Some(Self::U32),
+            "u64" => Some(Self::U64),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RegisterWidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "u{}", self.size() * 8)
+    }
+}
+
+/// An operation of a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchOp {
+    /// Reads the register and compares it with `expected`, before the writes.
+    Check {
+        /// Address of the register.
+        address: u64,
+        /// Width of the register.
+        width: RegisterWidth,
+        /// The expected value.
+        expected: u64,
+    },
+
+    /// Writes `value` to the register.
+    Write {
+        /// Address of the register.
+        address: u64,
+        /// Width of the register.
+        width: RegisterWidth,
+        /// The value to write.
+        value: u64,
+    },
+
+    /// Replaces the bits of the register selected by `mask` with `value`.
+    MaskedWrite {
+        /// Address of the register.
+        address: u64,
+        /// Width of the register.
+        width: RegisterWidth,
+        /// Bits to replace.
+        mask: u64,
+        /// The value of the bits.
+        value: u64,
+    },
+
+    /// Waits for the duration.
+    Delay(Duration),
+
+    /// Reads the register and compares it with `expected`, after the writes.
+    Verify {
+        /// Address of the register.
+        address: u64,
+        /// Width of the register.
+        width: RegisterWidth,
+        /// The expected value.
+        expected: u64,
+    },
+}
+
+impl PatchOp {
+    /// Returns the address range the operation writes to.
+    fn written_range(&self) -> Option<Range<u64>> {
+        match *self {
+            Self::Write { address, width, .. } | Self::MaskedWrite { address, width, .. } => {
+                Some(address..address.saturating_add(width.size() as u64))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PatchOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Check {
+                address,
+                width,
+                expected,
+            } => write!(f, "check {:#X} {} {:#X}", address, width, expected),
+            Self::Write {
+                address,
+                width,
+                value,
+            } => write!(f, "write {:#X} {} {:#X}", address, width, value),
+            Self::MaskedWrite {
+                address,
+                width,
+                mask,
+                value,
+            } => write!(
+                f,
+                "write-masked {:#X} {} {:#X} {:#X}",
+                address, width, mask, value
+            ),
+            Self::Delay(duration) => write!(f, "delay {}ms", duration.as_millis()),
+            Self::Verify {
+                address,
+                width,
+                expected,
+            } => write!(f, "verify {:#X} {} {:#X}", address, width, expected),
+        }
+    }
+}
+
+/// A step of a patch script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatchStep {
+    /// Line number of the step in the script, starting from 1.
+    pub line: usize,
+    /// The operation.
+    pub op: PatchOp,
+    /// `true` if a failed check of the step doesn't abort the script.
+    pub optional: bool,
+}
+
+/// A parsed patch script whose checksum is verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchScript {
+    steps: Vec<PatchStep>,
+}
+
+impl PatchScript {
+    /// Loads the script from `path`.
+    pub fn load(path: impl AsRef<Path>) -> PatchResult<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| PatchError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&text)
+    }
+
+    /// Parses `text` and verifies its checksum.
+    pub fn parse(text: &str) -> PatchResult<Self> {
+        let (header, body) = text.split_once('\n').unwrap_or((text, ""));
+        let expected = parse_header(header.trim_end_matches('\r'))?;
+        let actual = checksum(body);
+        if !expected.eq_ignore_ascii_case(&actual) {
+            return Err(PatchError::ChecksumMismatch {
+                expected: expected.to_string(),
+                actual,
+            });
+        }
+
+        let mut steps = vec![];
+        for (i, line) in body.lines().enumerate() {
+            let line_no = i + 2;
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if !line.is_empty() {
+                steps.push(parse_step(line_no, line)?);
+            }
+        }
+        Ok(Self { steps })
+    }
+
+    /// Prepends the header with the checksum of `body`, so that the returned script is accepted
+    /// by [`Self::parse`].
+    #[must_use]
+    pub fn seal(body: &str) -> String {
+        format!("{} {} sha1={}\n{}", MAGIC, VERSION, checksum(body), body)
+    }
+
+    /// Returns the steps of the script.
+    #[must_use]
+    pub fn steps(&self) -> &[PatchStep] {
+        &self.steps
+    }
+
+    /// Validates the script against `device`, then executes it unless
+    /// [`PatchOptions::dry_run`] is set.
+    ///
+    /// Steps are executed in order and the execution is aborted on the first failed step, except
+    /// for a failed check of an optional step.
+    pub fn apply<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+        options: &PatchOptions,
+    ) -> PatchResult<PatchReport> {
+        if !options.allow_bootstrap {
+            let bootstrap = bootstrap_ranges(device)?;
+            for step in &self.steps {
+                if let Some(written) = step.op.written_range() {
+                    if bootstrap
+                        .iter()
+                        .any(|r| written.start < r.end && r.start < written.end)
+                    {
+                        return Err(PatchError::BootstrapRegister {
+                            line: step.line,
+                            address: written.start,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut report = PatchReport {
+            steps: Vec::with_capacity(self.steps.len()),
+            dry_run: options.dry_run,
+            aborted: false,
+        };
+        for step in &self.steps {
+            let outcome = if options.dry_run {
+                StepOutcome::Planned
+            } else if report.aborted {
+                StepOutcome::NotRun
+            } else {
+                let outcome = execute(device, step);
+                report.aborted = outcome.aborts(step);
+                outcome
+            };
+            report.steps.push(StepReport {
+                step: *step,
+                outcome,
+            });
+        }
+        Ok(report)
+    }
+}
+
+/// Options of applying a patch script.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PatchOptions {
+    /// Validates the script and returns the plan without accessing the registers.
+    pub dry_run: bool,
+
+    /// Allows the script to write to bootstrap registers.
+    ///
+    /// NOTE: This is unsafe in the sense that a wrong write to bootstrap registers may make the
+    /// camera unreachable until it's power cycled.
+    pub allow_bootstrap: bool,
+}
+
+impl PatchOptions {
+    /// Constructs default options, which execute the script and refuse writes to bootstrap
+    /// registers.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets [`Self::dry_run`].
+    #[must_use]
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Sets [`Self::allow_bootstrap`].
+    #[must_use]
+    pub fn allow_bootstrap(mut self, allow: bool) -> Self {
+        self.allow_bootstrap = allow;
+        self
+    }
+}
+
+/// A result of applying a patch script.
+#[derive(Debug)]
+pub struct PatchReport {
+    /// Results of the steps in the order of the script.
+    pub steps: Vec<StepReport>,
+    /// `true` if the script is applied with [`PatchOptions::dry_run`].
+    pub dry_run: bool,
+    /// `true` if the execution is aborted by a failed step.
+    pub aborted: bool,
+}
+
+impl PatchReport {
+    /// Returns `true` if all the steps are executed, and all the checks of non-optional steps
+    /// pass.
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        !self.dry_run && !self.aborted
+    }
+}
+
+impl fmt::Display for PatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+/// A result of a step.
+#[derive(Debug)]
+pub struct StepReport {
+    /// The step.
+    pub step: PatchStep,
+    /// The outcome of the step.
+    pub outcome: StepOutcome,
+}
+
+impl fmt::Display for StepReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.step.line, self.step.op)?;
+        if self.step.optional {
+            f.write_str(" optional")?;
+        }
+        match &self.outcome {
+            StepOutcome::Planned => f.write_str(": planned"),
+            StepOutcome::Done => f.write_str(": done"),
+            StepOutcome::Written { prior } => write!(f, ": done, prior value {:#X}", prior),
+            StepOutcome::Mismatch { actual } => write!(f, ": mismatch, actual {:#X}", actual),
+            StepOutcome::Failed(e) => write!(f, ": failed, {}", e),
+            StepOutcome::NotRun => f.write_str(": not run"),
+        }
+    }
+}
+
+/// An outcome of a step.
+#[derive(Debug)]
+pub enum StepOutcome {
+    /// The step isn't executed because of [`PatchOptions::dry_run`].
+    Planned,
+    /// The step is executed successfully.
+    Done,
+    /// The write is executed successfully, contains the value of the register before the write
+    /// for masked writes.
+    Written {
+        /// The value before the write.
+        prior: u64,
+    },
+    /// The register doesn't hold the expected value.
+    Mismatch {
+        /// The value of the register.
+        actual: u64,
+    },
+    /// Accessing the register failed.
+    Failed(ControlError),
+    /// The step isn't executed because a preceding step aborts the execution.
+    NotRun,
+}
+
+impl StepOutcome {
+    fn aborts(&self, step: &PatchStep) -> bool {
+        match self {
+            Self::Mismatch { .. } => !step.optional,
+            Self::Failed(_) => true,
+            _ => false,
+        }
+    }
+}
+
+fn execute<Ctrl: DeviceControl + ?Sized>(device: &mut Ctrl, step: &PatchStep) -> StepOutcome {
+    let result = match step.op {
+        PatchOp::Check {
+            address,
+            width,
+            expected,
+        }
+        | PatchOp::Verify {
+            address,
+            width,
+            expected,
+        } => read(device, address, width).map(|actual| {
+            if actual == expected {
+                StepOutcome::Done
+            } else {
+                StepOutcome::Mismatch { actual }
+            }
+        }),
+        PatchOp::Write {
+            address,
+            width,
+            value,
+        } => write(device, address, width, value).map(|()| StepOutcome::Done),
+        PatchOp::MaskedWrite {
+            address,
+            width,
+            mask,
+            value,
+        } => read(device, address, width).and_then(|prior| {
+            let value = (prior & !mask) | (value & mask);
+            write(device, address, width, value).map(|()| StepOutcome::Written { prior })
+        }),
+        PatchOp::Delay(duration) => {
+            thread::sleep(duration);
+            Ok(StepOutcome::Done)
+        }
+    };
+    result.unwrap_or_else(StepOutcome::Failed)
+}
+
+fn read<Ctrl: DeviceControl + ?Sized>(
+    device: &mut Ctrl,
+    address: u64,
+    width: RegisterWidth,
+) -> ControlResult<u64> {
+    let mut buf = [0; 8];
+    device.read(address, &mut buf[..width.size()])?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn write<Ctrl: DeviceControl + ?Sized>(
+    device: &mut Ctrl,
+    address: u64,
+    width: RegisterWidth,
+    value: u64,
+) -> ControlResult<()> {
+    device.write(address, &value.to_le_bytes()[..width.size()])
+}
+
+/// Returns the address ranges of the bootstrap register maps of `device`.
+fn bootstrap_ranges<Ctrl: DeviceControl + ?Sized>(
+    device: &mut Ctrl,
+) -> ControlResult<Vec<Range<u64>>> {
+    let map_end = |registers: &[(u64, u16)]| {
+        registers
+            .iter()
+            .map(|(offset, len)| offset + u64::from(*len))
+            .max()
+            .unwrap_or_default()
+    };
+    let abrm_len = map_end(&[abrm::DEVICE_SOFTWARE_INTERFACE_VERSION]);
+    let sbrm_len = map_end(&[sbrm::CURRENT_SPEED]);
+
+    let sbrm_addr = read(device, abrm::SBRM_ADDRESS.0, RegisterWidth::U64)?;
+    let mut ranges = vec![
+        0..abrm_len,
+        sbrm_addr..checked_address(sbrm_addr, sbrm_len)?,
+    ];
+
+    let capability_addr = checked_address(sbrm_addr, sbrm::U3VCP_CAPABILITY_REGISTER.0)?;
+    let capability = read(device, capability_addr, RegisterWidth::U64)?;
+    let optional_maps = [
+        (0, sbrm::SIRM_ADDRESS, sbrm::SIRM_LENGTH),
+        (1, sbrm::EIRM_ADDRESS, sbrm::EIRM_LENGTH),
+    ];
+    for &(bit, (addr_offset, _), (len_offset, _)) in &optional_maps {
+        if capability >> bit & 1 == 0 {
+            continue;
+        }
+        let addr = read(
+            device,
+            checked_address(sbrm_addr, addr_offset)?,
+            RegisterWidth::U64,
+        )?;
+        let len = read(
+            device,
+            checked_address(sbrm_addr, len_offset)?,
+            RegisterWidth::U32,
+        )?;
+        ranges.push(addr..checked_address(addr, len)?);
+    }
+    Ok(ranges)
+}
+
+fn checksum(body: &str) -> String {
+    Sha1::digest(body.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn parse_header(header: &str) -> PatchResult<&str> {
+    let invalid = |message: &str| PatchError::Invalid {
+        line: 1,
+        message: message.into(),
+    };
+    let mut tokens = header.split_whitespace();
+    if tokens.next() != Some(MAGIC) {
+        return Err(invalid("missing `cameleon-patch` header"));
+    }
+    match tokens.next().map(str::parse::<u32>) {
+        Some(Ok(VERSION)) => {}
+        _ => return Err(invalid("unsupported version")),
+    }
+    match (tokens.next(), tokens.next()) {
+        (Some(sum), None) => sum
+            .strip_prefix("sha1=")
+            .ok_or_else(|| invalid("missing sha1 checksum")),
+        _ => Err(invalid("missing sha1 checksum")),
+    }
+}
+
+fn parse_step(line: usize, text: &str) -> PatchResult<PatchStep> {
+    let invalid = |message: String| PatchError::Invalid { line, message };
+    let mut tokens: Vec<_> = text.split_whitespace().collect();
+    let optional = tokens.last() == Some(&"optional");
+    if optional {
+        tokens.pop();
+    }
+
+    let int = |s: &str| {
+        let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => s.parse(),
+        };
+        parsed.map_err(|_| invalid(format!("invalid integer `{}`", s)))
+    };
+    let width = |s: &str| {
+        RegisterWidth::parse(s).ok_or_else(|| invalid(format!("invalid register width `{}`", s)))
+    };
+    let value = |s: &str, width: RegisterWidth| {
+        let value = int(s)?;
+        if value > width.max() {
+            return Err(invalid(format!("`{}` doesn't fit into {}", s, width)));
+        }
+        Ok(value)
+    };
+
+    let op = match tokens.as_slice() {
+        ["check", address, w, expected] | ["verify", address, w, expected] => {
+            let width = width(w)?;
+            let (address, expected) = (int(address)?, value(expected, width)?);
+            if tokens[0] == "check" {
+                PatchOp::Check {
+                    address,
+                    width,
+                    expected,
+                }
+            } else {
+                PatchOp::Verify {
+                    address,
+                    width,
+                    expected,
+                }
+            }
+        }
+        ["write", address, w, v] => {
+            let width = width(w)?;
+            PatchOp::Write {
+                address: int(address)?,
+                width,
+                value: value(v, width)?,
+            }
+        }
+        ["write-masked", address, w, mask, v] => {
+            let width = width(w)?;
+            PatchOp::MaskedWrite {
+                address: int(address)?,
+                width,
+                mask: value(mask, width)?,
+                value: value(v, width)?,
+            }
+        }
+        ["delay", duration] => PatchOp::Delay(
+            parse_duration(duration)
+                .ok_or_else(|| invalid(format!("invalid duration `{}`, e.g. `10ms`", duration)))?,
+        ),
+        _ => return Err(invalid(format!("invalid step `{}`", text))),
+    };
+
+    if optional && !matches!(op, PatchOp::Check { .. } | PatchOp::Verify { .. }) {
+        return Err(invalid("only checks can be optional".into()));
+    }
+    Ok(PatchStep { line, op, optional })
+}
+
+fn parse_duration(s: &str) -> Option<Duration> {
+    if let Some(ms) = s.strip_suffix("ms") {
+        ms.parse().ok().map(Duration::from_millis)
+    } else {
+        let secs = s.strip_suffix('s')?;
+        secs.parse().ok().map(Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+
+    const SBRM_ADDRESS: u64 = 0x1000;
+    const SIRM_ADDRESS: u64 = 0x2000;
+
+    /// Device memory with `SBRM` at `0x1000` and `SIRM` at `0x2000`, counting writes.
+    struct Memory(Vec<u8>, usize);
+
+    impl Memory {
+        fn new() -> Self {
+            let mut mem = Self(vec![0; 0x4000], 0);
+            let mut set = |address: u64, data: &[u8]| {
+                let address = address as usize;
+                mem.0[address..address + data.len()].copy_from_slice(data);
+            };
+            set(abrm::SBRM_ADDRESS.0, &SBRM_ADDRESS.to_le_bytes());
+            set(
+                SBRM_ADDRESS + sbrm::U3VCP_CAPABILITY_REGISTER.0,
+                &1_u64.to_le_bytes(),
+            );
+            set(
+                SBRM_ADDRESS + sbrm::SIRM_ADDRESS.0,
+                &SIRM_ADDRESS.to_le_bytes(),
+            );
+            set(SBRM_ADDRESS + sbrm::SIRM_LENGTH.0, &0x20_u32.to_le_bytes());
+            set(0x3000, &0x1234_u32.to_le_bytes());
+            mem
+        }
+
+        fn u32_at(&self, address: usize) -> u32 {
+            u32::from_le_bytes(self.0[address..address + 4].try_into().unwrap())
+        }
+    }
+
+    impl DeviceControl for Memory {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            let address = address as usize;
+            buf.copy_from_slice(&self.0[address..address + buf.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+            let address = address as usize;
+            self.0[address..address + data.len()].copy_from_slice(data);
+            self.1 += 1;
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            unreachable!()
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+    }
+
+    fn apply(body: &str, options: &PatchOptions) -> (Memory, PatchResult<PatchReport>) {
+        let mut mem = Memory::new();
+        let script = PatchScript::parse(&PatchScript::seal(body)).unwrap();
+        let report = script.apply(&mut mem, options);
+        (mem, report)
+    }
+
+    #[test]
+    fn test_check_pass() {
+        let body = "\
+            # Fix black level.\n\
+            check 0x3000 u32 0x1234\n\
+            write 0x3004 u16 640\n\
+            delay 1ms\n\
+            verify 0x3004 u16 640\n";
+        let (mem, report) = apply(body, &PatchOptions::new());
+        let report = report.unwrap();
+
+        assert!(report.is_success());
+        assert_eq!(report.steps.len(), 4);
+        assert_eq!(report.steps[1].step.line, 4);
+        assert!(report
+            .steps
+            .iter()
+            .all(|step| matches!(step.outcome, StepOutcome::Done)));
+        assert_eq!(mem.u32_at(0x3004), 640);
+    }
+
+    #[test]
+    fn test_check_fail_abort() {
+        let body = "\
+            check 0x3000 u32 0x1 optional\n\
+            check 0x3000 u32 0x2\n\
+            write 0x3004 u32 640\n";
+        let (mem, report) = apply(body, &PatchOptions::new());
+        let report = report.unwrap();
+
+        assert!(!report.is_success());
+        assert!(report.aborted);
+        assert!(matches!(
+            report.steps[0].outcome,
+            StepOutcome::Mismatch { actual: 0x1234 }
+        ));
+        assert!(matches!(
+            report.steps[1].outcome,
+            StepOutcome::Mismatch { actual: 0x1234 }
+        ));
+        assert!(matches!(report.steps[2].outcome, StepOutcome::NotRun));
+        assert_eq!(mem.1, 0);
+    }
+
+    #[test]
+    fn test_masked_write() {
+        let body = "write-masked 0x3000 u32 0xff0f 0xabcd\n";
+        let (mem, report) = apply(body, &PatchOptions::new());
+        let report = report.unwrap();
+
+        assert!(matches!(
+            report.steps[0].outcome,
+            StepOutcome::Written { prior: 0x1234 }
+        ));
+        assert_eq!(mem.u32_at(0x3000), 0xab3d);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let body = "check 0x3000 u32 0x2\nwrite 0x3004 u32 640\n";
+        let (mem, report) = apply(body, &PatchOptions::new().dry_run(true));
+        let report = report.unwrap();
+
+        assert!(!report.is_success());
+        assert!(report
+            .steps
+            .iter()
+            .all(|step| matches!(step.outcome, StepOutcome::Planned)));
+        assert_eq!(
+            report.to_string(),
+            "line 2: check 0x3000 u32 0x2: planned\nline 3: write 0x3004 u32 0x280: planned\n"
+        );
+        assert_eq!(mem.1, 0);
+    }
+
+    #[test]
+    fn test_bootstrap_register() {
+        // `ABRM`, `SBRM` and `SIRM`.
+        for address in &[0x0, SBRM_ADDRESS + 0x10, SIRM_ADDRESS + 0x1e] {
+            let body = format!("write {:#x} u32 1\n", address);
+            let (mem, report) = apply(&body, &PatchOptions::new().dry_run(true));
+            assert!(matches!(
+                report,
+                Err(PatchError::BootstrapRegister { line: 2, .. })
+            ));
+            assert_eq!(mem.1, 0);
+
+            let (mem, report) = apply(&body, &PatchOptions::new().allow_bootstrap(true));
+            assert!(report.unwrap().is_success());
+            assert_eq!(mem.1, 1);
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let script = PatchScript::seal("write 0x3000 u32 1\n");
+        assert!(PatchScript::parse(&script).is_ok());
+        let tampered = script.replace("u32 1", "u32 2");
+        assert!(matches!(
+            PatchScript::parse(&tampered),
+            Err(PatchError::ChecksumMismatch { .. })
+        ));
+
+        let invalid = |body: &str| match PatchScript::parse(&PatchScript::seal(body)) {
+            Err(PatchError::Invalid { line, .. }) => line,
+            res => panic!("{:?}", res),
+        };
+        assert_eq!(invalid("write 0x3000 u8 0x100"), 2);
+        assert_eq!(invalid("\nwrite 0x3000 u32 1 optional"), 3);
+        assert_eq!(invalid("delay 10"), 2);
+        assert_eq!(invalid("read 0x3000 u32"), 2);
+        assert!(matches!(
+            PatchScript::parse("cameleon-patch 2 sha1=00\n"),
+            Err(PatchError::Invalid { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,401 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides conversion from [`Payload`] and [`Image`] to `ndarray` views.
+//!
+//! The conversion doesn't copy the image, the row stride of the image is mapped to the row stride
+//! of the array, so padded rows don't require a copy either.
+
+use std::borrow::Cow;
+
+use ndarray::{ArrayView2, ArrayView3, ShapeBuilder};
+
+use super::{Image, ImageInfo, Payload, PixelFormat};
+
+/// A specialized `Result` type for array conversion.
+pub type ArrayViewResult<T> = std::result::Result<T, ArrayViewError>;
+
+/// An error type returned when [`Payload`] is converted into an array view.
+#[derive(Debug, thiserror::Error)]
+pub enum ArrayViewError {
+    /// The payload doesn't contain an image.
+    #[error("payload doesn't contain an image")]
+    NoImage,
+
+    /// The pixel format isn't modeled, convert the payload with [`Payload::to_image`] and a
+    /// registered decoder instead.
+    #[error("pixel format code {0:#010x} is not supported")]
+    UnsupportedPixelFormat(u32),
+
+    /// Bit depth or layout of the pixel format doesn't match the requested element type.
+    #[error("pixel format `{pixel_format:?}` can't be viewed as an array of `{element}`")]
+    PixelFormatMismatch {
+        /// Pixel format of the image.
+        pixel_format: PixelFormat,
+        /// Requested element type.
+        element: &'static str,
+    },
+
+    /// The image layout is inconsistent with the pixel format, or the image is not aligned for
+    /// the requested element type.
+    #[error("invalid image layout: {0}")]
+    InvalidLayout(Cow<'static, str>),
+}
+
+/// Element type that an image can be viewed as.
+///
+/// This trait is sealed and implemented for `u8` and `u16`. `U3V` devices send pixels in little
+/// endian and the view doesn't swap bytes, so `u16` is implemented only on little endian hosts.
+pub trait PixelElement: private::Sealed + Copy + 'static {
+    #[doc(hidden)]
+    const NAME: &'static str;
+
+    #[doc(hidden)]
+    fn is_mono(pixel_format: PixelFormat) -> bool;
+
+    #[doc(hidden)]
+    fn channel_count(pixel_format: PixelFormat) -> Option<usize>;
+}
+
+impl PixelElement for u8 {
+    const NAME: &'static str = "u8";
+
+    fn is_mono(pixel_format: PixelFormat) -> bool {
+        use PixelFormat::{BayerBG8, BayerGB8, BayerGR8, BayerRG8, Mono8};
+
+        matches!(
+            pixel_format,
+            Mono8 | BayerGR8 | BayerRG8 | BayerGB8 | BayerBG8
+        )
+    }
+
+    fn channel_count(pixel_format: PixelFormat) -> Option<usize> {
+        use PixelFormat::{BGRa8, RGBa8, BGR8, RGB8};
+
+        match pixel_format {
+            RGB8 | BGR8 => Some(3),
+            RGBa8 | BGRa8 => Some(4),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(target_endian = "little")]
+impl PixelElement for u16 {
+    const NAME: &'static str = "u16";
+
+    fn is_mono(pixel_format: PixelFormat) -> bool {
+        #[allow(clippy::enum_glob_use)]
+        use PixelFormat::*;
+
+        matches!(
+            pixel_format,
+            Mono10
+                | Mono12
+                | Mono14
+                | Mono16
+                | BayerGR10
+                | BayerRG10
+                | BayerGB10
+                | BayerBG10
+                | BayerGR12
+                | BayerRG12
+                | BayerGB12
+                | BayerBG12
+                | BayerGR14
+                | BayerRG14
+                | BayerGB14
+                | BayerBG14
+                | BayerGR16
+                | BayerRG16
+                | BayerGB16
+                | BayerBG16
+        )
+    }
+
+    fn channel_count(pixel_format: PixelFormat) -> Option<usize> {
+        #[allow(clippy::enum_glob_use)]
+        use PixelFormat::*;
+
+        match pixel_format {
+            RGB10 | BGR10 | RGB12 | BGR12 | RGB14 | BGR14 | RGB16 | BGR16 => Some(3),
+            RGBa10 | BGRa10 | RGBa12 | BGRa12 | RGBa14 | BGRa14 | RGBa16 | BGRa16 => Some(4),
+            _ => None,
+        }
+    }
+}
+
+impl Payload {
+    /// Returns a 2D view of the image whose shape is `(height, width)`.
+    ///
+    /// Only unpacked mono and bayer formats are supported, and the bit depth of the pixel format
+    /// must match `T`, e.g. `Mono8` for `u8` and `Mono12` for `u16`.
+    ///
+    /// If the payload is truncated, only the rows that are fully delivered are exposed.
+    pub fn as_array2<T: PixelElement>(&self) -> ArrayViewResult<ArrayView2<'_, T>> {
+        let image_info = self.image_info().ok_or(ArrayViewError::NoImage)?;
+        view2(self.payload(), image_info)
+    }
+
+    /// Returns a 3D view of the image whose shape is `(height, width, channel)`.
+    ///
+    /// Only interleaved RGB formats are supported, e.g. `RGB8` and `BGRa8` for `u8`, `RGB12` for
+    /// `u16`. The order of the channels is the same as the pixel format.
+    ///
+    /// If the payload is truncated, only the rows that are fully delivered are exposed.
+    pub fn as_array3<T: PixelElement>(&self) -> ArrayViewResult<ArrayView3<'_, T>> {
+        let image_info = self.image_info().ok_or(ArrayViewError::NoImage)?;
+        view3(self.payload(), image_info)
+    }
+}
+
+impl Image {
+    /// Returns a 2D view of the image whose shape is `(height, width)`, see
+    /// [`Payload::as_array2`].
+    pub fn as_array2<T: PixelElement>(&self) -> ArrayViewResult<ArrayView2<'_, T>> {
+        view2(self.data(), self.info())
+    }
+
+    /// Returns a 3D view of the image whose shape is `(height, width, channel)`, see
+    /// [`Payload::as_array3`].
+    pub fn as_array3<T: PixelElement>(&self) -> ArrayViewResult<ArrayView3<'_, T>> {
+        view3(self.data(), self.info())
+    }
+}
+
+fn view2<'a, T: PixelElement>(
+    bytes: &'a [u8],
+    image_info: &ImageInfo,
+) -> ArrayViewResult<ArrayView2<'a, T>> {
+    if let PixelFormat::Unknown(code) = image_info.pixel_format {
+        return Err(ArrayViewError::UnsupportedPixelFormat(code));
+    }
+    if !T::is_mono(image_info.pixel_format) {
+        return Err(mismatch::<T>(image_info));
+    }
+
+    let layout = ImageLayout::<T>::new(bytes, image_info, 1)?;
+    let shape = (layout.rows, image_info.width).strides((layout.row_stride, 1));
+    ArrayView2::from_shape(shape, layout.data)
+        .map_err(|e| ArrayViewError::InvalidLayout(e.to_string().into()))
+}
+
+fn view3<'a, T: PixelElement>(
+    bytes: &'a [u8],
+    image_info: &ImageInfo,
+) -> ArrayViewResult<ArrayView3<'a, T>> {
+    if let PixelFormat::Unknown(code) = image_info.pixel_format {
+        return Err(ArrayViewError::UnsupportedPixelFormat(code));
+    }
+    let channels =
+        T::channel_count(image_info.pixel_format).ok_or_else(|| mismatch::<T>(image_info))?;
+
+    let layout = ImageLayout::<T>::new(bytes, image_info, channels)?;
+    let shape = (layout.rows, image_info.width, channels).strides((layout.row_stride, channels, 1));
+    ArrayView3::from_shape(shape, layout.data)
+        .map_err(|e| ArrayViewError::InvalidLayout(e.to_string().into()))
+}
+
+struct ImageLayout<'a, T> {
+    data: &'a [T],
+    /// Number of fully delivered rows.
+    rows: usize,
+    /// Row stride in elements.
+    row_stride: usize,
+}
+
+impl<'a, T: PixelElement> ImageLayout<'a, T> {
+    fn new(bytes: &'a [u8], image_info: &ImageInfo, channels: usize) -> ArrayViewResult<Self> {
+        let elem_size = std::mem::size_of::<T>();
+        let row_len = image_info.width * channels * elem_size;
+
+        if image_info.height == 0 || image_info.width == 0 {
+            return Ok(Self {
+                data: &[],
+                rows: 0,
+                row_stride: 0,
+            });
+        }
+
+        // Rows may be padded, the padding is derived from the image size.
+        let row_stride = image_info.image_size / image_info.height;
+        if row_stride < row_len || row_stride / elem_size * elem_size != row_stride {
+            return Err(ArrayViewError::InvalidLayout(
+                format!(
+                    "row stride {} is inconsistent with the image width {}",
+                    row_stride, image_info.width
+                )
+                .into(),
+            ));
+        }
+
+        let bytes = &bytes[..bytes.len().min(image_info.image_size)];
+        if bytes.as_ptr().align_offset(std::mem::align_of::<T>()) != 0 {
+            return Err(ArrayViewError::InvalidLayout(
+                "image is not aligned for the element type".into(),
+            ));
+        }
+
+        let rows = if bytes.len() < row_len {
+            0
+        } else {
+            ((bytes.len() - row_len) / row_stride + 1).min(image_info.height)
+        };
+
+        // SAFETY: The alignment is checked above, and any bit pattern is valid for the element
+        // types which implement `PixelElement`.
+        let data = unsafe {
+            std::slice::from_raw_parts(bytes.as_ptr().cast::<T>(), bytes.len() / elem_size)
+        };
+
+        Ok(Self {
+            data,
+            rows,
+            row_stride: row_stride / elem_size,
+        })
+    }
+}
+
+fn mismatch<T: PixelElement>(image_info: &ImageInfo) -> ArrayViewError {
+    ArrayViewError::PixelFormatMismatch {
+        pixel_format: image_info.pixel_format,
+        element: T::NAME,
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u8 {}
+    #[cfg(target_endian = "little")]
+    impl Sealed for u16 {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{test_payload, PayloadType, PixelDecoders},
+        *,
+    };
+
+    fn payload(
+        width: usize,
+        height: usize,
+        row_stride: usize,
+        pixel_format: PixelFormat,
+        bytes: Vec<u8>,
+    ) -> Payload {
+        let image_info = ImageInfo {
+            width,
+            height,
+            x_offset: 0,
+            y_offset: 0,
+            pixel_format,
+            image_size: row_stride * height,
+            x_padding: row_stride - (width * pixel_format.bits_per_pixel()).div_ceil(8),
+        };
+        test_payload(PayloadType::Image, Some(image_info), bytes)
+    }
+
+    #[test]
+    fn test_mono8() {
+        let bytes: Vec<u8> = (0..12).collect();
+        let payload = payload(4, 3, 4, PixelFormat::Mono8, bytes.clone());
+
+        let array = payload.as_array2::<u8>().unwrap();
+        assert_eq!(array.dim(), (3, 4));
+        for ((row, col), value) in array.indexed_iter() {
+            assert_eq!(*value, bytes[row * 4 + col]);
+        }
+    }
+
+    #[test]
+    fn test_mono8_padded() {
+        // Each row has 2 bytes padding.
+        let bytes: Vec<u8> = (0..15).collect();
+        let payload = payload(3, 3, 5, PixelFormat::Mono8, bytes.clone());
+
+        let array = payload.as_array2::<u8>().unwrap();
+        assert_eq!(array.dim(), (3, 3));
+        for ((row, col), value) in array.indexed_iter() {
+            assert_eq!(*value, bytes[row * 5 + col]);
+        }
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn test_mono16() {
+        let bytes: Vec<u8> = (0..24).collect();
+        let payload = payload(4, 3, 8, PixelFormat::Mono12, bytes.clone());
+
+        let array = payload.as_array2::<u16>().unwrap();
+        assert_eq!(array.dim(), (3, 4));
+        for ((row, col), value) in array.indexed_iter() {
+            let idx = row * 8 + col * 2;
+            assert_eq!(*value, u16::from_le_bytes([bytes[idx], bytes[idx + 1]]));
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        // Only 2 rows and a half are delivered.
+        let bytes: Vec<u8> = (0..12).collect();
+        let mut payload = payload(4, 4, 5, PixelFormat::Mono8, bytes);
+        payload.valid_payload_size = 12;
+
+        let array = payload.as_array2::<u8>().unwrap();
+        assert_eq!(array.dim(), (2, 4));
+    }
+
+    #[test]
+    fn test_rgb8() {
+        let bytes: Vec<u8> = (0..16).collect();
+        let payload = payload(2, 2, 8, PixelFormat::RGB8, bytes.clone());
+
+        let array = payload.as_array3::<u8>().unwrap();
+        assert_eq!(array.dim(), (2, 2, 3));
+        for ((row, col, ch), value) in array.indexed_iter() {
+            assert_eq!(*value, bytes[row * 8 + col * 3 + ch]);
+        }
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let payload = payload(4, 3, 4, PixelFormat::Mono8, vec![0; 12]);
+        #[cfg(target_endian = "little")]
+        assert!(matches!(
+            payload.as_array2::<u16>(),
+            Err(ArrayViewError::PixelFormatMismatch { .. })
+        ));
+        assert!(matches!(
+            payload.as_array3::<u8>(),
+            Err(ArrayViewError::PixelFormatMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unknown_pixel_format() {
+        let decoders = PixelDecoders::default();
+        let mut payload = payload(4, 3, 4, PixelFormat::Unknown(0x8108_7002), vec![0; 12]);
+        payload.decoders = Some(decoders.clone());
+        assert!(matches!(
+            payload.as_array2::<u8>(),
+            Err(ArrayViewError::UnsupportedPixelFormat(0x8108_7002))
+        ));
+        assert!(matches!(
+            payload.as_array3::<u8>(),
+            Err(ArrayViewError::UnsupportedPixelFormat(0x8108_7002))
+        ));
+
+        // The image decoded by a registered decoder can be viewed as an array.
+        decoders.register(0x8108_7002, |info: &ImageInfo, image: &[u8]| {
+            let info = ImageInfo {
+                pixel_format: PixelFormat::Mono8,
+                ..info.clone()
+            };
+            Ok(Image::new(info, image.to_vec()))
+        });
+        let image = payload.to_image().unwrap();
+        assert_eq!(image.as_array2::<u8>().unwrap().dim(), (3, 4));
+    }
+}
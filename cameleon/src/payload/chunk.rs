@@ -0,0 +1,443 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides [`ChunkIter`], which decodes the chunks of payloads of
+//! [`PayloadType::ImageExtendedChunk`] and [`PayloadType::Chunk`], and [`ChunkControl`], which
+//! serves the decoded chunks to chunk features of `GenApi`.
+//!
+//! Each chunk consists of its data followed by the chunk id and the length of the data, both
+//! are 4 bytes in big endian. So the chunks are decoded from the last byte of the payload to the
+//! first.
+
+use std::{convert::TryInto, iter::FusedIterator};
+
+use crate::{load_options::GenApiFile, ControlResult, DeviceControl};
+
+use super::{Payload, PayloadType};
+
+#[cfg(doc)]
+use crate::genapi::ParamsCtxt;
+
+/// Length of the chunk id and the data length following the data of a chunk.
+const DESCRIPTOR_LEN: usize = 8;
+
+/// A chunk of a payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkBlock<'a> {
+    /// Chunk id, which is referred to by `ChunkID` of a port in `GenApi`.
+    pub id: u32,
+    /// Data of the chunk.
+    pub data: &'a [u8],
+}
+
+/// An error type returned while decoding chunks.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ChunkError {
+    /// The payload type doesn't contain chunks.
+    #[error("payload of type {0:?} doesn't contain chunks")]
+    NotChunkPayload(PayloadType),
+
+    /// The payload is truncated, so the chunks at its tail are lost.
+    #[error("payload is truncated, the chunks are lost")]
+    Truncated,
+
+    /// Fewer bytes than the chunk id and the data length precede `end`, where the chunk ends.
+    #[error("descriptor of the chunk ending at {end} is truncated")]
+    TruncatedDescriptor {
+        /// Offset of the end of the chunk in the payload.
+        end: usize,
+    },
+
+    /// The data length of the chunk runs past the start of the payload.
+    #[error("chunk {id:#x} of {length} bytes runs past the payload, only {available} bytes precede its descriptor")]
+    LengthOverrun {
+        /// Chunk id.
+        id: u32,
+        /// Data length in the descriptor of the chunk.
+        length: u32,
+        /// The number of bytes preceding the descriptor.
+        available: usize,
+    },
+}
+
+/// An iterator over the chunks of a payload, see [`Payload::chunks`].
+///
+/// The chunks are returned from the last chunk in the payload to the first. Once a malformed
+/// chunk is found, its error is returned and the iteration ends.
+#[derive(Clone, Debug)]
+pub struct ChunkIter<'a> {
+    data: &'a [u8],
+    /// End of the chunk to be decoded next, `None` once an error is returned.
+    end: Option<usize>,
+}
+
+impl<'a> ChunkIter<'a> {
+    /// Decodes the chunks in `data`, which must be the valid part of a payload, e.g.
+    /// [`Payload::payload`].
+    #[must_use]
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            end: Some(data.len()),
+        }
+    }
+
+    /// Decodes the chunk ending at `end`, and returns it together with its start.
+    fn decode(&self, end: usize) -> Result<(ChunkBlock<'a>, usize), ChunkError> {
+        let descriptor = end
+            .checked_sub(DESCRIPTOR_LEN)
+            .ok_or(ChunkError::TruncatedDescriptor { end })?;
+        let id = u32::from_be_bytes(self.data[descriptor..descriptor + 4].try_into().unwrap());
+        let length = u32::from_be_bytes(self.data[descriptor + 4..end].try_into().unwrap());
+        let start = descriptor
+            .checked_sub(length as usize)
+            .ok_or(ChunkError::LengthOverrun {
+                id,
+                length,
+                available: descriptor,
+            })?;
+
+        Ok((
+            ChunkBlock {
+                id,
+                data: &self.data[start..descriptor],
+            },
+            start,
+        ))
+    }
+}
+
+impl<'a> Iterator for ChunkIter<'a> {
+    type Item = Result<ChunkBlock<'a>, ChunkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let end = self.end.filter(|&end| end > 0)?;
+        match self.decode(end) {
+            Ok((chunk, start)) => {
+                self.end = Some(start);
+                Some(Ok(chunk))
+            }
+            Err(err) => {
+                self.end = None;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl FusedIterator for ChunkIter<'_> {}
+
+impl Payload {
+    /// Returns an iterator over the chunks of the payload.
+    ///
+    /// The first chunk of [`PayloadType::ImageExtendedChunk`] is the image, which is returned
+    /// last.
+    ///
+    /// # Errors
+    ///
+    /// * [`ChunkError::NotChunkPayload`] if the payload type is [`PayloadType::Image`] or
+    ///   [`PayloadType::GenDc`].
+    /// * [`ChunkError::Truncated`] if the tail of the payload is lost, see
+    ///   [`Payload::incomplete_info`].
+    pub fn chunks(&self) -> Result<ChunkIter<'_>, ChunkError> {
+        match self.payload_type {
+            PayloadType::ImageExtendedChunk | PayloadType::Chunk => {}
+            PayloadType::Image | PayloadType::GenDc => {
+                return Err(ChunkError::NotChunkPayload(self.payload_type))
+            }
+        }
+        if self.incomplete_info.is_some() {
+            return Err(ChunkError::Truncated);
+        }
+
+        Ok(ChunkIter::new(self.payload()))
+    }
+}
+
+/// [`DeviceControl`] which serves the chunks of a payload to chunk features, see
+/// [`ParamsCtxt::with_chunks`].
+///
+/// Accesses other than reading chunks are forwarded to the wrapped control handle.
+pub struct ChunkControl<'a, Ctrl: ?Sized> {
+    ctrl: &'a mut Ctrl,
+    chunks: Vec<ChunkBlock<'a>>,
+}
+
+impl<'a, Ctrl: ?Sized> ChunkControl<'a, Ctrl> {
+    /// Decodes the chunks of `payload` to serve them along with `ctrl`.
+    ///
+    /// # Errors
+    ///
+    /// [`ChunkError`] if `payload` doesn't contain chunks or a chunk is malformed, see
+    /// [`Payload::chunks`].
+    pub fn new(ctrl: &'a mut Ctrl, payload: &'a Payload) -> Result<Self, ChunkError> {
+        let chunks = payload.chunks()?.collect::<Result<_, _>>()?;
+        Ok(Self { ctrl, chunks })
+    }
+
+    /// Returns the chunks of the payload, from the last chunk in the payload to the first.
+    #[must_use]
+    pub fn chunks(&self) -> &[ChunkBlock<'a>] {
+        &self.chunks
+    }
+}
+
+impl<Ctrl> DeviceControl for ChunkControl<'_, Ctrl>
+where
+    Ctrl: DeviceControl + ?Sized,
+{
+    fn open(&mut self) -> ControlResult<()> {
+        self.ctrl.open()
+    }
+
+    fn is_opened(&self) -> bool {
+        self.ctrl.is_opened()
+    }
+
+    fn close(&mut self) -> ControlResult<()> {
+        self.ctrl.close()
+    }
+
+    fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+        self.ctrl.read(address, buf)
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+        self.ctrl.write(address, data)
+    }
+
+    fn genapi(&mut self) -> ControlResult<String> {
+        self.ctrl.genapi()
+    }
+
+    fn genapi_file(&mut self) -> ControlResult<Option<GenApiFile>> {
+        self.ctrl.genapi_file()
+    }
+
+    fn enable_streaming(&mut self) -> ControlResult<()> {
+        self.ctrl.enable_streaming()
+    }
+
+    fn disable_streaming(&mut self) -> ControlResult<()> {
+        self.ctrl.disable_streaming()
+    }
+
+    fn reenable_streaming(&mut self) -> ControlResult<()> {
+        self.ctrl.reenable_streaming()
+    }
+
+    fn chunk_data(&self, chunk_id: u64) -> Option<&[u8]> {
+        self.chunks
+            .iter()
+            .find(|chunk| u64::from(chunk.id) == chunk_id)
+            .map(|chunk| chunk.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{test_payload, IncompleteInfo},
+        *,
+    };
+    use crate::genapi::{DefaultGenApiCtxt, FeatureChange, FeatureValue, FromXml, ParamsCtxt};
+
+    const XML: &str = r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="2"
+          SubMinorVersion="3"
+          ToolTip="ToolTiptest"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210"
+          xmlns="http://www.genicam.org/GenApi/Version_1_0"
+          xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+          xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_0 GenApiSchema.xsd">
+
+            <Integer Name="ChunkExposureTime">
+                <pValue>ChunkExposureTimeReg</pValue>
+            </Integer>
+
+            <IntReg Name="ChunkExposureTimeReg">
+              <Address>0x4</Address>
+              <Length>4</Length>
+              <AccessMode>RO</AccessMode>
+              <pPort>ChunkPort</pPort>
+            </IntReg>
+
+            <Port Name="ChunkPort">
+                <ChunkID>1234</ChunkID>
+            </Port>
+
+        </RegisterDescription>
+        "#;
+
+    struct NoDevice;
+
+    impl DeviceControl for NoDevice {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, _: u64, _: &mut [u8]) -> ControlResult<()> {
+            unreachable!()
+        }
+
+        fn write(&mut self, _: u64, _: &[u8]) -> ControlResult<()> {
+            unreachable!()
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            Ok(XML.into())
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+    }
+
+    /// Encodes `chunks` in the order of the payload, the lengths are taken from the data.
+    fn encode(chunks: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut bytes = vec![];
+        for (id, data) in chunks {
+            bytes.extend_from_slice(data);
+            bytes.extend_from_slice(&id.to_be_bytes());
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        }
+        bytes
+    }
+
+    fn chunk_payload(bytes: Vec<u8>) -> Payload {
+        let mut payload = test_payload(PayloadType::Chunk, None, bytes);
+        payload.chunk_layout_id = Some(1);
+        payload
+    }
+
+    #[test]
+    fn test_chunks() {
+        let payload = chunk_payload(encode(&[
+            (1, &[0xaa; 6]),
+            (0x1234, &[1, 2, 3, 4]),
+            (5, &[]),
+        ]));
+        let chunks: Vec<_> = payload.chunks().unwrap().map(Result::unwrap).collect();
+        assert_eq!(
+            chunks,
+            vec![
+                ChunkBlock { id: 5, data: &[] },
+                ChunkBlock {
+                    id: 0x1234,
+                    data: &[1, 2, 3, 4]
+                },
+                ChunkBlock {
+                    id: 1,
+                    data: &[0xaa; 6]
+                },
+            ]
+        );
+
+        assert_eq!(ChunkIter::new(&[]).count(), 0);
+    }
+
+    #[test]
+    fn test_malformed_chunks() {
+        // The length of the first chunk runs past the start of the payload.
+        let mut bytes = encode(&[(1, &[0; 4]), (2, &[0; 2])]);
+        bytes[11] = 5;
+        let mut chunks = ChunkIter::new(&bytes);
+        assert_eq!(chunks.next().unwrap().unwrap().id, 2);
+        assert_eq!(
+            chunks.next(),
+            Some(Err(ChunkError::LengthOverrun {
+                id: 1,
+                length: 5,
+                available: 4,
+            }))
+        );
+        // The iteration ends at the error.
+        assert!(chunks.next().is_none());
+
+        // Garbage shorter than a descriptor precedes the chunk.
+        let bytes = [&[0; 3][..], &encode(&[(1, &[0; 4])])].concat();
+        let mut chunks = ChunkIter::new(&bytes);
+        assert!(chunks.next().unwrap().is_ok());
+        assert_eq!(
+            chunks.next(),
+            Some(Err(ChunkError::TruncatedDescriptor { end: 3 }))
+        );
+
+        let mut bytes = encode(&[(1, &[0; 4])]);
+        bytes[11] = 0xff;
+        assert!(matches!(
+            ChunkIter::new(&bytes).next(),
+            Some(Err(ChunkError::LengthOverrun { length: 0xff, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_payload_without_chunks() {
+        let mut payload = chunk_payload(encode(&[(1, &[0; 4])]));
+        payload.incomplete_info = Some(IncompleteInfo {
+            expected_size: 16,
+            received_size: 12,
+        });
+        assert!(matches!(payload.chunks(), Err(ChunkError::Truncated)));
+
+        payload.incomplete_info = None;
+        payload.payload_type = PayloadType::Image;
+        assert!(matches!(
+            payload.chunks(),
+            Err(ChunkError::NotChunkPayload(PayloadType::Image))
+        ));
+    }
+
+    #[test]
+    fn test_chunk_features() {
+        let mut ctxt = ParamsCtxt {
+            ctrl: NoDevice,
+            ctxt: DefaultGenApiCtxt::from_xml(&XML).unwrap(),
+        };
+        let node = ctxt.node("ChunkExposureTime").unwrap();
+        let watcher = ctxt.subscribe_eager(node).unwrap();
+        let node = node.as_integer(&ctxt).unwrap();
+        // The chunk port has no data without a payload.
+        assert!(node.value(&mut ctxt).is_err());
+
+        // The cached value of the previous payload isn't returned.
+        for exposure_time in [100_u32, 200] {
+            let data = [&[0; 4][..], &exposure_time.to_le_bytes()].concat();
+            let payload = chunk_payload(encode(&[(1, &[0; 8]), (0x1234, &data)]));
+            let mut chunk_ctxt = ctxt.with_chunks(&payload).unwrap();
+            assert_eq!(
+                node.value(&mut chunk_ctxt).unwrap(),
+                i64::from(exposure_time)
+            );
+            // Watchers are notified of the value in the new payload.
+            assert_eq!(
+                watcher.try_recv(),
+                Some(FeatureChange::Changed(FeatureValue::Integer(i64::from(
+                    exposure_time
+                ))))
+            );
+        }
+    }
+}
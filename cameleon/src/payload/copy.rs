@@ -0,0 +1,320 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides [`Payload::copy_to`], which copies a payload into memory managed by the
+//! caller, e.g. pinned memory staged for GPU or a slot of a shared memory ring.
+//!
+//! The copy is done in a single pass over the rows, so the row padding is stripped or the rows
+//! are placed at the destination stride without an intermediate buffer.
+
+use super::{ImageInfo, Payload};
+use crate::{StreamError, StreamResult};
+
+/// Layout of the destination of [`Payload::copy_to`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyLayout {
+    /// Rows of the image are packed without the row padding sent by the device.
+    Packed,
+    /// The whole payload is copied as received, including the row padding and the chunk data
+    /// following the image.
+    AsReceived,
+    /// Each row of the image starts at a multiple of the stride in bytes. Bytes between the
+    /// rows are left untouched.
+    RowStride(usize),
+}
+
+/// Result of [`Payload::copy_to`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CopyReport {
+    /// The number of bytes written to the destination.
+    pub bytes: usize,
+    /// The number of complete image rows written to the destination, `0` if the payload doesn't
+    /// contain an image.
+    pub rows: usize,
+    /// `true` if the payload is truncated, only the received part is copied then.
+    pub partial: bool,
+}
+
+impl Payload {
+    /// Copies the payload into `dst` in `layout`.
+    ///
+    /// `dst` must be large enough to hold the complete image for [`CopyLayout::Packed`] and
+    /// [`CopyLayout::RowStride`], or the received payload for [`CopyLayout::AsReceived`]. If the
+    /// payload is truncated, the received rows are copied and the rest of `dst` is left
+    /// untouched.
+    ///
+    /// # Errors
+    ///
+    /// * [`StreamError::BufferTooSmall`] if `dst` is too small.
+    /// * [`StreamError::NotSupported`] if the layout requires an image but the payload doesn't
+    ///   contain one, the row length can't be derived from the pixel format, or the stride is
+    ///   smaller than the row length.
+    pub fn copy_to(&self, dst: &mut [u8], layout: CopyLayout) -> StreamResult<CopyReport> {
+        if layout == CopyLayout::AsReceived {
+            return self.copy_as_received(dst);
+        }
+
+        let image_info = self.image_info().ok_or_else(|| {
+            StreamError::NotSupported("the layout requires the payload to contain an image".into())
+        })?;
+        let row = RowLayout::new(image_info)?;
+        let dst_stride = match layout {
+            CopyLayout::RowStride(stride) if stride < row.len => {
+                return Err(StreamError::NotSupported(
+                    format!(
+                        "row stride {} is smaller than the row length {}",
+                        stride, row.len
+                    )
+                    .into(),
+                ))
+            }
+            CopyLayout::RowStride(stride) => stride,
+            _ => row.len,
+        };
+
+        let height = image_info.height;
+        let required = height
+            .checked_sub(1)
+            .map_or(0, |rows| dst_stride * rows + row.len);
+        if dst.len() < required {
+            return Err(StreamError::BufferTooSmall);
+        }
+
+        let image = self.image().unwrap();
+        let mut report = CopyReport::default();
+        for i in 0..height {
+            let src = &image[image.len().min(i * row.stride)..];
+            let len = src.len().min(row.len);
+            let offset = i * dst_stride;
+            dst[offset..offset + len].copy_from_slice(&src[..len]);
+            report.bytes += len;
+            if len < row.len {
+                break;
+            }
+            report.rows += 1;
+        }
+        report.partial = self.is_incomplete() || report.rows < height;
+
+        Ok(report)
+    }
+
+    fn copy_as_received(&self, dst: &mut [u8]) -> StreamResult<CopyReport> {
+        let src = self.payload();
+        if dst.len() < src.len() {
+            return Err(StreamError::BufferTooSmall);
+        }
+        dst[..src.len()].copy_from_slice(src);
+
+        let rows = match self.image_info().map(RowLayout::new) {
+            Some(Ok(row)) => {
+                let image_size = self.image().unwrap().len();
+                // The padding of the last row may be omitted.
+                (image_size + row.stride - row.len) / row.stride
+            }
+            _ => 0,
+        };
+        Ok(CopyReport {
+            bytes: src.len(),
+            rows: rows.min(self.image_info().map_or(0, |info| info.height)),
+            partial: self.is_incomplete(),
+        })
+    }
+}
+
+/// Row layout of the image sent by the device.
+struct RowLayout {
+    /// Length of a row without the padding in bytes.
+    len: usize,
+    /// Length of a row including the padding in bytes.
+    stride: usize,
+}
+
+impl RowLayout {
+    fn new(image_info: &ImageInfo) -> StreamResult<Self> {
+        let bits = image_info.pixel_format.bits_per_pixel();
+        if bits == 0 {
+            return Err(StreamError::NotSupported(
+                format!(
+                    "row length can't be derived from pixel format `{:?}`",
+                    image_info.pixel_format
+                )
+                .into(),
+            ));
+        }
+
+        let len = (image_info.width * bits).div_ceil(8);
+        Ok(Self {
+            len,
+            stride: len + image_info.x_padding,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{test_payload, IncompleteInfo, PayloadType, PixelFormat},
+        *,
+    };
+
+    const WIDTH: usize = 5;
+    const HEIGHT: usize = 3;
+    const PADDING: usize = 3;
+    const STRIDE: usize = WIDTH + PADDING;
+
+    /// Returns a `Mono8` payload whose rows are padded with `0xff`, followed by a chunk of
+    /// 4 bytes. Pixel `(x, y)` is `y * 16 + x`.
+    fn padded_payload() -> Payload {
+        let mut bytes = vec![];
+        for y in 0..HEIGHT {
+            bytes.extend((0..WIDTH).map(|x| (y * 16 + x) as u8));
+            bytes.extend(std::iter::repeat_n(0xff, PADDING));
+        }
+        let image_size = bytes.len();
+        bytes.extend_from_slice(&[0xc0, 0xc1, 0xc2, 0xc3]);
+        let image_info = ImageInfo {
+            width: WIDTH,
+            height: HEIGHT,
+            x_offset: 0,
+            y_offset: 0,
+            pixel_format: PixelFormat::Mono8,
+            image_size,
+            x_padding: PADDING,
+        };
+        test_payload(PayloadType::ImageExtendedChunk, Some(image_info), bytes)
+    }
+
+    /// Truncates the payload in the middle of the second row.
+    fn truncate(payload: &mut Payload) {
+        let received_size = STRIDE + 2;
+        payload.incomplete_info = Some(IncompleteInfo {
+            expected_size: payload.valid_payload_size,
+            received_size,
+        });
+        payload.valid_payload_size = received_size;
+        payload.image_info.as_mut().unwrap().image_size = received_size;
+    }
+
+    fn row(y: usize) -> Vec<u8> {
+        (0..WIDTH).map(|x| (y * 16 + x) as u8).collect()
+    }
+
+    #[test]
+    fn test_packed() {
+        let payload = padded_payload();
+        let mut dst = vec![0; WIDTH * HEIGHT];
+        let report = payload.copy_to(&mut dst, CopyLayout::Packed).unwrap();
+        assert_eq!(
+            report,
+            CopyReport {
+                bytes: WIDTH * HEIGHT,
+                rows: HEIGHT,
+                partial: false,
+            }
+        );
+        assert_eq!(dst, [row(0), row(1), row(2)].concat());
+
+        let mut dst = vec![0; WIDTH * HEIGHT - 1];
+        assert!(matches!(
+            payload.copy_to(&mut dst, CopyLayout::Packed),
+            Err(StreamError::BufferTooSmall)
+        ));
+    }
+
+    #[test]
+    fn test_as_received() {
+        let payload = padded_payload();
+        let mut dst = vec![0; payload.payload().len() + 1];
+        let report = payload.copy_to(&mut dst, CopyLayout::AsReceived).unwrap();
+        assert_eq!(
+            report,
+            CopyReport {
+                bytes: payload.payload().len(),
+                rows: HEIGHT,
+                partial: false,
+            }
+        );
+        assert_eq!(&dst[..report.bytes], payload.payload());
+        assert_eq!(dst[report.bytes], 0);
+
+        let mut dst = vec![0; payload.payload().len() - 1];
+        assert!(matches!(
+            payload.copy_to(&mut dst, CopyLayout::AsReceived),
+            Err(StreamError::BufferTooSmall)
+        ));
+    }
+
+    #[test]
+    fn test_row_stride() {
+        let payload = padded_payload();
+        let stride = 7;
+        let mut dst = vec![0xaa; stride * (HEIGHT - 1) + WIDTH];
+        let report = payload
+            .copy_to(&mut dst, CopyLayout::RowStride(stride))
+            .unwrap();
+        assert_eq!(report.bytes, WIDTH * HEIGHT);
+        assert_eq!(report.rows, HEIGHT);
+        let gap = vec![0xaa; stride - WIDTH];
+        assert_eq!(dst, [row(0), gap.clone(), row(1), gap, row(2)].concat());
+
+        // The stride matching the source reproduces the image if the padding is prefilled.
+        let mut dst = vec![0xff; STRIDE * HEIGHT];
+        payload
+            .copy_to(&mut dst, CopyLayout::RowStride(STRIDE))
+            .unwrap();
+        assert_eq!(dst, payload.image().unwrap());
+
+        assert!(matches!(
+            payload.copy_to(&mut dst, CopyLayout::RowStride(WIDTH - 1)),
+            Err(StreamError::NotSupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut payload = padded_payload();
+        truncate(&mut payload);
+
+        let mut dst = vec![0; WIDTH * HEIGHT];
+        let report = payload.copy_to(&mut dst, CopyLayout::Packed).unwrap();
+        assert_eq!(
+            report,
+            CopyReport {
+                bytes: WIDTH + 2,
+                rows: 1,
+                partial: true,
+            }
+        );
+        assert_eq!(&dst[..WIDTH], row(0).as_slice());
+        assert_eq!(&dst[WIDTH..WIDTH + 2], &row(1)[..2]);
+        assert!(dst[WIDTH + 2..].iter().all(|&b| b == 0));
+
+        let mut dst = vec![0; STRIDE * HEIGHT];
+        let report = payload.copy_to(&mut dst, CopyLayout::AsReceived).unwrap();
+        assert_eq!(
+            report,
+            CopyReport {
+                bytes: STRIDE + 2,
+                rows: 1,
+                partial: true,
+            }
+        );
+    }
+
+    #[test]
+    fn test_no_image() {
+        let mut payload = padded_payload();
+        payload.payload_type = PayloadType::Chunk;
+        payload.image_info = None;
+
+        let mut dst = vec![0; payload.payload().len()];
+        assert!(matches!(
+            payload.copy_to(&mut dst, CopyLayout::Packed),
+            Err(StreamError::NotSupported(_))
+        ));
+        let report = payload.copy_to(&mut dst, CopyLayout::AsReceived).unwrap();
+        assert_eq!(report.rows, 0);
+        assert_eq!(dst, payload.payload());
+    }
+}
@@ -0,0 +1,100 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides buffers managed by the caller, into which the streaming loop receives
+//! payload data directly, see [`StreamParams::deliver_into`].
+//!
+//! [`StreamParams::deliver_into`]: crate::u3v::StreamParams::deliver_into
+
+use std::{fmt, sync::Arc};
+
+/// Memory managed by the caller which receives payload data, e.g. pinned memory staged for GPU
+/// or a slot of a shared memory ring.
+///
+/// The buffer is dropped when the last [`Payload`] holding it is dropped, so an implementation
+/// can return the memory to the caller's own pool in its `Drop`.
+///
+/// [`Payload`]: super::Payload
+pub trait DestinationBuffer: Send + Sync {
+    /// Returns the whole buffer.
+    fn as_slice(&self) -> &[u8];
+
+    /// Returns the whole buffer.
+    fn as_mut_slice(&mut self) -> &mut [u8];
+}
+
+impl DestinationBuffer for Vec<u8> {
+    fn as_slice(&self) -> &[u8] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl DestinationBuffer for Box<[u8]> {
+    fn as_slice(&self) -> &[u8] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+/// Provides [`DestinationBuffer`]s to the streaming loop, see [`StreamParams::deliver_into`].
+///
+/// [`StreamParams::deliver_into`]: crate::u3v::StreamParams::deliver_into
+pub trait BufferProvider: Send + Sync {
+    /// Returns a buffer which receives the next payload, `len` is the upper bound of the payload
+    /// size, see [`StreamParams::maximum_payload_size`].
+    ///
+    /// If `None` or a buffer shorter than `len` is returned, the payload is received into a
+    /// buffer of the internal pool instead.
+    ///
+    /// [`StreamParams::maximum_payload_size`]: crate::u3v::StreamParams::maximum_payload_size
+    fn acquire(&self, len: usize) -> Option<Box<dyn DestinationBuffer>>;
+}
+
+impl fmt::Debug for dyn BufferProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferProvider").finish_non_exhaustive()
+    }
+}
+
+/// A filled [`DestinationBuffer`] held by [`Payload`], which is shared between the clones of
+/// the payload.
+///
+/// [`Payload`]: super::Payload
+#[derive(Clone)]
+pub(crate) struct ProvidedBuffer(Arc<dyn DestinationBuffer>);
+
+impl ProvidedBuffer {
+    #[cfg(feature = "libusb")]
+    pub(crate) fn new(buf: Box<dyn DestinationBuffer>) -> Self {
+        Self(buf.into())
+    }
+
+    pub(crate) fn get(&self) -> &dyn DestinationBuffer {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for ProvidedBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProvidedBuffer")
+            .field("len", &self.0.as_slice().len())
+            .finish()
+    }
+}
+
+/// Buffers are compared by their contents like the pool buffers.
+impl PartialEq for ProvidedBuffer {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_slice() == other.0.as_slice()
+    }
+}
+
+impl Eq for ProvidedBuffer {}
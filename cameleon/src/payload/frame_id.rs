@@ -0,0 +1,182 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// FNV-1a offset basis.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// FNV-1a prime.
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// An identifier of a frame which is unique across devices and acquisitions.
+///
+/// The string representation is `{device_id}-{stream_index}-{generation}-{block_id}`, where
+/// `device_id` is 16 hex digits and the others are decimal numbers, e.g.
+/// `9f1c5e2a44d0b7c3-0-2-1024`.
+///
+/// `FrameId`s are ordered only if they belong to the same acquisition generation of the same
+/// stream, i.e. [`PartialOrd::partial_cmp`] returns `None` for `FrameId`s from different
+/// acquisitions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub struct FrameId {
+    device_id: u64,
+    stream_index: u32,
+    generation: u32,
+    block_id: u64,
+}
+
+impl FrameId {
+    /// Constructs `FrameId`.
+    #[must_use]
+    pub fn new(device_id: u64, stream_index: u32, generation: u32, block_id: u64) -> Self {
+        Self {
+            device_id,
+            stream_index,
+            generation,
+            block_id,
+        }
+    }
+
+    /// Returns the device id computed from `guid` of the device.
+    ///
+    /// The hash is stable across processes and platforms.
+    #[must_use]
+    pub fn device_id_from_guid(guid: &str) -> u64 {
+        guid.bytes().fold(FNV_OFFSET_BASIS, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(FNV_PRIME)
+        })
+    }
+
+    /// Hash of the device GUID, see [`FrameId::device_id_from_guid`].
+    #[must_use]
+    pub fn device_id(&self) -> u64 {
+        self.device_id
+    }
+
+    /// Index of the stream channel of the device.
+    #[must_use]
+    pub fn stream_index(&self) -> u32 {
+        self.stream_index
+    }
+
+    /// Acquisition generation, incremented every time streaming is started.
+    #[must_use]
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Block id of the payload assigned by the device.
+    #[must_use]
+    pub fn block_id(&self) -> u64 {
+        self.block_id
+    }
+
+    fn is_same_acquisition(&self, other: &Self) -> bool {
+        self.device_id == other.device_id
+            && self.stream_index == other.stream_index
+            && self.generation == other.generation
+    }
+}
+
+impl PartialOrd for FrameId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.is_same_acquisition(other) {
+            Some(self.block_id.cmp(&other.block_id))
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for FrameId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:016x}-{}-{}-{}",
+            self.device_id, self.stream_index, self.generation, self.block_id
+        )
+    }
+}
+
+/// An error returned when parsing [`FrameId`] from a string.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid frame id: `{0}`")]
+pub struct ParseFrameIdError(String);
+
+impl FromStr for FrameId {
+    type Err = ParseFrameIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseFrameIdError(s.to_string());
+
+        let mut fields = s.split('-');
+        let mut next = || fields.next().ok_or_else(err);
+        let device_id = next()?;
+        if device_id.len() != 16 {
+            return Err(err());
+        }
+        let device_id = u64::from_str_radix(device_id, 16).map_err(|_| err())?;
+        let stream_index = next()?.parse().map_err(|_| err())?;
+        let generation = next()?.parse().map_err(|_| err())?;
+        let block_id = next()?.parse().map_err(|_| err())?;
+        if fields.next().is_some() {
+            return Err(err());
+        }
+
+        Ok(Self::new(device_id, stream_index, generation, block_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn frames(guid: &str, generation: u32) -> Vec<FrameId> {
+        let device_id = FrameId::device_id_from_guid(guid);
+        (0..4)
+            .map(|block_id| FrameId::new(device_id, 0, generation, block_id))
+            .collect()
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let id = FrameId::new(FrameId::device_id_from_guid("2A2B00000001"), 0, 3, 42);
+        let s = id.to_string();
+        assert_eq!(s.parse::<FrameId>().unwrap(), id);
+
+        assert!("".parse::<FrameId>().is_err());
+        assert!("0-0-0-0".parse::<FrameId>().is_err());
+        assert!(format!("{}-1", s).parse::<FrameId>().is_err());
+    }
+
+    #[test]
+    fn test_uniqueness() {
+        let mut all = vec![];
+        for guid in &["2A2B00000001", "2A2B00000002"] {
+            // Block id is reset when the acquisition is restarted.
+            all.extend(frames(guid, 0));
+            all.extend(frames(guid, 1));
+        }
+
+        let ids: HashSet<_> = all.iter().collect();
+        assert_eq!(ids.len(), all.len());
+        let strings: HashSet<_> = all.iter().map(ToString::to_string).collect();
+        assert_eq!(strings.len(), all.len());
+    }
+
+    #[test]
+    fn test_ordering() {
+        let first = frames("2A2B00000001", 0);
+        let second = frames("2A2B00000001", 1);
+        let other_camera = frames("2A2B00000002", 0);
+
+        assert!(first[0] < first[1]);
+        assert!(first[3] > first[2]);
+        assert_eq!(first[0].partial_cmp(&second[1]), None);
+        assert_eq!(first[0].partial_cmp(&other_camera[1]), None);
+    }
+}
@@ -0,0 +1,466 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides [`GenDcContainer`], which decodes the descriptor of a payload of
+//! [`PayloadType::GenDc`].
+//!
+//! A GenDC container starts with the descriptor, which consists of the container header, the
+//! component headers and the part headers, and the data of the parts follows the descriptor.
+//! Each header is referred to by its offset from the start of the container, and all fields are
+//! little endian.
+//!
+//! The data of a part is located at the flow offset of the part from the data offset of the
+//! container, i.e. the flows of the container are assumed to be received back to back into the
+//! payload.
+
+use std::convert::TryInto;
+
+use super::{Payload, PayloadType, PixelFormat};
+
+const SIGNATURE: u32 = 0x4344_4E47;
+const CONTAINER_HEADER_TYPE: u16 = 0x1000;
+const COMPONENT_HEADER_TYPE: u16 = 0x2000;
+const CONTAINER_HEADER_SIZE: usize = 56;
+const COMPONENT_HEADER_SIZE: usize = 48;
+const PART_HEADER_SIZE: usize = 32;
+const PART_2D_HEADER_SIZE: usize = 44;
+
+/// An error type returned while decoding a GenDC container.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum GenDcError {
+    /// The payload type isn't [`PayloadType::GenDc`].
+    #[error("payload of type {0:?} isn't a GenDC container")]
+    NotGenDcPayload(PayloadType),
+
+    /// The signature of the container isn't `GNDC`.
+    #[error("invalid signature of GenDC container: {0:#x}")]
+    InvalidSignature(u32),
+
+    /// A header has an unexpected header type.
+    #[error("header at {offset} has type {actual:#06x}, but {expected:#06x} is expected")]
+    UnexpectedHeaderType {
+        /// Offset of the header in the container.
+        offset: usize,
+        /// Expected header type.
+        expected: u16,
+        /// Header type in the container.
+        actual: u16,
+    },
+
+    /// A header runs past the end of the payload.
+    #[error("header at {offset} of {size} bytes runs past the payload of {len} bytes")]
+    TruncatedHeader {
+        /// Offset of the header in the container.
+        offset: u64,
+        /// Size of the header.
+        size: usize,
+        /// Length of the payload.
+        len: usize,
+    },
+}
+
+/// Decoded descriptor of a GenDC container, see [`Payload::gendc`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenDcContainer<'a> {
+    data: &'a [u8],
+    /// ID of the container.
+    pub id: u64,
+    /// Components of the container in the order of the component offsets.
+    pub components: Vec<GenDcComponent>,
+}
+
+/// A component of a GenDC container, which is a set of parts from the same source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenDcComponent {
+    /// Type of the component, e.g. `0x0001` for intensity and `0x8001` for metadata.
+    pub type_id: u64,
+    /// ID of the source of the component.
+    pub source_id: u16,
+    /// ID of the region of the component.
+    pub region_id: u16,
+    /// X offset of the region of the component in pixels.
+    pub region_offset_x: u32,
+    /// Y offset of the region of the component in pixels.
+    pub region_offset_y: u32,
+    /// Timestamp of the component in ns.
+    pub timestamp: u64,
+    /// Parts of the component in the order of the part offsets.
+    pub parts: Vec<GenDcPart>,
+}
+
+/// A part of a GenDC component.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenDcPart {
+    /// Header type of the part, which determines [`GenDcPart::kind`].
+    pub header_type: u16,
+    /// Data format of the part, a pixel format code for the parts of an image.
+    pub format: u32,
+    /// Offset of the data of the part from the start of the container.
+    ///
+    /// The offset isn't validated against the payload, see [`GenDcContainer::part_data`].
+    pub offset: u64,
+    /// Size of the data of the part in bytes.
+    pub size: u64,
+    /// Kind of the part with its type specific fields.
+    pub kind: GenDcPartKind,
+}
+
+/// Kind of a GenDC part, which is decoded from the header type of the part.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenDcPartKind {
+    /// Metadata, e.g. GenICam chunk data.
+    Metadata,
+    /// 1D data.
+    Array,
+    /// 2D data, e.g. an image.
+    Image {
+        /// Width in pixels.
+        width: u32,
+        /// Height in pixels.
+        height: u32,
+        /// Padding at the end of each line in bytes.
+        x_padding: u16,
+        /// Padding at the end of the part in bytes.
+        y_padding: u16,
+    },
+    /// Header type which isn't known to this crate, e.g. a compressed format.
+    Unknown,
+}
+
+impl GenDcPart {
+    /// Returns the pixel format of the part if the part is an image.
+    #[must_use]
+    pub fn pixel_format(&self) -> Option<PixelFormat> {
+        match self.kind {
+            GenDcPartKind::Image { .. } => Some(PixelFormat::from_code(self.format)),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> GenDcContainer<'a> {
+    /// Decodes the descriptor of the container at the start of `data`, which must be the valid
+    /// part of a payload, e.g. [`Payload::payload`].
+    ///
+    /// # Errors
+    ///
+    /// [`GenDcError`] if the descriptor is malformed or truncated. The data of the parts isn't
+    /// required to be in `data`.
+    pub fn parse(data: &'a [u8]) -> Result<Self, GenDcError> {
+        let header = Header::new(data, 0, CONTAINER_HEADER_SIZE)?;
+        let signature = header.u32(0);
+        if signature != SIGNATURE {
+            return Err(GenDcError::InvalidSignature(signature));
+        }
+        header.expect_type(8, CONTAINER_HEADER_TYPE)?;
+        let id = header.u64(16);
+        let data_offset = header.u64(40);
+        let component_count = header.u32(52) as usize;
+        let offsets = header.offsets(CONTAINER_HEADER_SIZE, component_count)?;
+
+        let components = offsets
+            .into_iter()
+            .map(|offset| parse_component(data, offset, data_offset))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            data,
+            id,
+            components,
+        })
+    }
+
+    /// Returns the data of `part`, or `None` if the data lies out of the payload, e.g. the
+    /// payload is truncated.
+    #[must_use]
+    pub fn part_data(&self, part: &GenDcPart) -> Option<&'a [u8]> {
+        let start: usize = part.offset.try_into().ok()?;
+        let end = start.checked_add(part.size.try_into().ok()?)?;
+        self.data.get(start..end)
+    }
+
+    /// Returns an iterator over the parts of all components with the component each part
+    /// belongs to.
+    pub fn parts(&self) -> impl Iterator<Item = (&GenDcComponent, &GenDcPart)> {
+        self.components
+            .iter()
+            .flat_map(|component| component.parts.iter().map(move |part| (component, part)))
+    }
+}
+
+impl Payload {
+    /// Decodes the GenDC container of the payload.
+    ///
+    /// # Errors
+    ///
+    /// * [`GenDcError::NotGenDcPayload`] if the payload type isn't [`PayloadType::GenDc`].
+    /// * Other [`GenDcError`] if the descriptor of the container is malformed, see
+    ///   [`GenDcContainer::parse`].
+    pub fn gendc(&self) -> Result<GenDcContainer<'_>, GenDcError> {
+        if self.payload_type != PayloadType::GenDc {
+            return Err(GenDcError::NotGenDcPayload(self.payload_type));
+        }
+        GenDcContainer::parse(self.payload())
+    }
+}
+
+fn parse_component(
+    data: &[u8],
+    offset: u64,
+    data_offset: u64,
+) -> Result<GenDcComponent, GenDcError> {
+    let header = Header::new(data, offset, COMPONENT_HEADER_SIZE)?;
+    header.expect_type(0, COMPONENT_HEADER_TYPE)?;
+    let part_count = header.u16(46) as usize;
+    let parts = header
+        .offsets(COMPONENT_HEADER_SIZE, part_count)?
+        .into_iter()
+        .map(|offset| parse_part(data, offset, data_offset))
+        .collect::<Result<_, _>>()?;
+
+    Ok(GenDcComponent {
+        type_id: header.u64(32),
+        source_id: header.u16(12),
+        region_id: header.u16(14),
+        region_offset_x: header.u32(16),
+        region_offset_y: header.u32(20),
+        timestamp: header.u64(24),
+        parts,
+    })
+}
+
+fn parse_part(data: &[u8], offset: u64, data_offset: u64) -> Result<GenDcPart, GenDcError> {
+    let header = Header::new(data, offset, PART_HEADER_SIZE)?;
+    let header_type = header.u16(0);
+    let kind = match header_type & 0xff00 {
+        0x4000 => GenDcPartKind::Metadata,
+        0x4100 => GenDcPartKind::Array,
+        0x4200 if header_type == 0x4200 => {
+            let header = Header::new(data, offset, PART_2D_HEADER_SIZE)?;
+            GenDcPartKind::Image {
+                width: header.u32(32),
+                height: header.u32(36),
+                x_padding: header.u16(40),
+                y_padding: header.u16(42),
+            }
+        }
+        _ => GenDcPartKind::Unknown,
+    };
+
+    Ok(GenDcPart {
+        header_type,
+        format: header.u32(8),
+        offset: data_offset.saturating_add(header.u64(16)),
+        size: header.u64(24),
+        kind,
+    })
+}
+
+/// Fields of a header whose size is checked against the payload.
+struct Header<'a> {
+    payload: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Header<'a> {
+    fn new(payload: &'a [u8], offset: u64, size: usize) -> Result<Self, GenDcError> {
+        let truncated = || GenDcError::TruncatedHeader {
+            offset,
+            size,
+            len: payload.len(),
+        };
+        let start: usize = offset.try_into().map_err(|_| truncated())?;
+        match start.checked_add(size) {
+            Some(end) if end <= payload.len() => Ok(Self {
+                payload,
+                offset: start,
+            }),
+            _ => Err(truncated()),
+        }
+    }
+
+    fn expect_type(&self, at: usize, expected: u16) -> Result<(), GenDcError> {
+        let actual = self.u16(at);
+        if actual == expected {
+            Ok(())
+        } else {
+            Err(GenDcError::UnexpectedHeaderType {
+                offset: self.offset,
+                expected,
+                actual,
+            })
+        }
+    }
+
+    /// Reads `count` offsets of 8 bytes following the fixed fields of `size` bytes.
+    fn offsets(&self, size: usize, count: usize) -> Result<Vec<u64>, GenDcError> {
+        let total = count
+            .checked_mul(8)
+            .and_then(|len| len.checked_add(size))
+            .unwrap_or(usize::MAX);
+        let header = Header::new(self.payload, self.offset as u64, total)?;
+        Ok((0..count).map(|i| header.u64(size + i * 8)).collect())
+    }
+
+    fn bytes<const N: usize>(&self, at: usize) -> [u8; N] {
+        let start = self.offset + at;
+        self.payload[start..start + N].try_into().unwrap()
+    }
+
+    fn u16(&self, at: usize) -> u16 {
+        u16::from_le_bytes(self.bytes(at))
+    }
+
+    fn u32(&self, at: usize) -> u32 {
+        u32::from_le_bytes(self.bytes(at))
+    }
+
+    fn u64(&self, at: usize) -> u64 {
+        u64::from_le_bytes(self.bytes(at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes a container of a 4x2 Mono8 image component and a metadata component, each with a
+    /// single part, followed by the data of the parts.
+    fn encode() -> Vec<u8> {
+        fn header(buf: &mut Vec<u8>, header_type: u16, size: u32) {
+            buf.extend_from_slice(&header_type.to_le_bytes());
+            buf.extend_from_slice(&0_u16.to_le_bytes());
+            buf.extend_from_slice(&size.to_le_bytes());
+        }
+        fn component(buf: &mut Vec<u8>, type_id: u64, source_id: u16, part_offset: u64) {
+            header(buf, COMPONENT_HEADER_TYPE, 56);
+            for field in [0_u16, 0, source_id, 0] {
+                buf.extend_from_slice(&field.to_le_bytes());
+            }
+            buf.extend_from_slice(&[0; 8]);
+            buf.extend_from_slice(&42_u64.to_le_bytes());
+            buf.extend_from_slice(&type_id.to_le_bytes());
+            buf.extend_from_slice(&[0; 6]);
+            buf.extend_from_slice(&1_u16.to_le_bytes());
+            buf.extend_from_slice(&part_offset.to_le_bytes());
+        }
+        fn part(buf: &mut Vec<u8>, header_type: u16, format: u32, flow: (u64, u64)) {
+            let size = if header_type == 0x4200 { 48 } else { 40 };
+            header(buf, header_type, size);
+            buf.extend_from_slice(&format.to_le_bytes());
+            buf.extend_from_slice(&[0; 4]);
+            buf.extend_from_slice(&flow.0.to_le_bytes());
+            buf.extend_from_slice(&flow.1.to_le_bytes());
+        }
+
+        let descriptor_size = 72 + 56 + 48 + 56 + 40;
+        let mut buf = SIGNATURE.to_le_bytes().to_vec();
+        buf.extend_from_slice(&[1, 1, 0, 0]);
+        header(&mut buf, CONTAINER_HEADER_TYPE, 72);
+        buf.extend_from_slice(&7_u64.to_le_bytes());
+        buf.extend_from_slice(&0_u64.to_le_bytes());
+        buf.extend_from_slice(&12_u64.to_le_bytes());
+        buf.extend_from_slice(&(descriptor_size as u64).to_le_bytes());
+        buf.extend_from_slice(&(descriptor_size as u32).to_le_bytes());
+        buf.extend_from_slice(&2_u32.to_le_bytes());
+        buf.extend_from_slice(&72_u64.to_le_bytes());
+        buf.extend_from_slice(&(72_u64 + 56 + 48).to_le_bytes());
+
+        component(&mut buf, 0x0001, 1, 72 + 56);
+        part(&mut buf, 0x4200, PixelFormat::Mono8.into(), (0, 8));
+        for field in [4_u32, 2, 0, 0] {
+            buf.extend_from_slice(&field.to_le_bytes());
+        }
+        component(&mut buf, 0x8001, 2, 72 + 56 + 48 + 56);
+        part(&mut buf, 0x4000, 0, (8, 4));
+        buf.extend_from_slice(&[0; 8]);
+        assert_eq!(buf.len(), descriptor_size);
+
+        buf.extend(0..12);
+        buf
+    }
+
+    #[test]
+    fn test_parse() {
+        let data = encode();
+        let container = GenDcContainer::parse(&data).unwrap();
+        assert_eq!(container.id, 7);
+        assert_eq!(container.components.len(), 2);
+
+        let image = &container.components[0];
+        assert_eq!(
+            (image.type_id, image.source_id, image.timestamp),
+            (1, 1, 42)
+        );
+        assert_eq!(
+            image.parts,
+            vec![GenDcPart {
+                header_type: 0x4200,
+                format: PixelFormat::Mono8.into(),
+                offset: 272,
+                size: 8,
+                kind: GenDcPartKind::Image {
+                    width: 4,
+                    height: 2,
+                    x_padding: 0,
+                    y_padding: 0
+                },
+            }]
+        );
+        assert_eq!(image.parts[0].pixel_format(), Some(PixelFormat::Mono8));
+        assert_eq!(
+            container.part_data(&image.parts[0]),
+            Some(&[0, 1, 2, 3, 4, 5, 6, 7][..])
+        );
+
+        let (metadata, part) = container.parts().nth(1).unwrap();
+        assert_eq!((metadata.type_id, metadata.source_id), (0x8001, 2));
+        assert_eq!(part.kind, GenDcPartKind::Metadata);
+        assert_eq!(part.pixel_format(), None);
+        assert_eq!(container.part_data(part), Some(&[8, 9, 10, 11][..]));
+
+        // The data of the parts isn't required to decode the descriptor.
+        let container = GenDcContainer::parse(&data[..272]).unwrap();
+        assert_eq!(container.parts().count(), 2);
+        assert_eq!(container.part_data(part), None);
+    }
+
+    #[test]
+    fn test_malformed() {
+        let data = encode();
+        assert_eq!(
+            GenDcContainer::parse(&data[..200]),
+            Err(GenDcError::TruncatedHeader {
+                offset: 176,
+                size: COMPONENT_HEADER_SIZE,
+                len: 200
+            })
+        );
+
+        let mut bad = data.clone();
+        bad[0] = 0;
+        assert!(matches!(
+            GenDcContainer::parse(&bad),
+            Err(GenDcError::InvalidSignature(_))
+        ));
+
+        let mut bad = data.clone();
+        bad[73] = 0x30;
+        assert_eq!(
+            GenDcContainer::parse(&bad),
+            Err(GenDcError::UnexpectedHeaderType {
+                offset: 72,
+                expected: COMPONENT_HEADER_TYPE,
+                actual: 0x3000
+            })
+        );
+
+        // The component count runs the offsets past the payload.
+        let mut bad = data;
+        bad[52..56].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            GenDcContainer::parse(&bad),
+            Err(GenDcError::TruncatedHeader { offset: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,254 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides conversion from [`Payload`] to an owned [`Image`].
+//!
+//! An image of a pixel format which isn't modeled by [`PixelFormat`], e.g. a vendor specific one,
+//! can be converted only if a [`PixelDecoder`] is registered for the format code in
+//! [`PixelDecoders`] of the stream which received the payload.
+
+use std::{
+    borrow::Cow,
+    fmt,
+    sync::{Arc, PoisonError, RwLock},
+};
+
+use super::{ImageInfo, Payload, PixelFormat};
+
+/// A specialized `Result` type for image conversion.
+pub type ImageResult<T> = std::result::Result<T, ImageError>;
+
+/// An error type returned when [`Payload`] is converted into [`Image`].
+#[derive(Debug, thiserror::Error)]
+pub enum ImageError {
+    /// The payload doesn't contain an image.
+    #[error("payload doesn't contain an image")]
+    NoImage,
+
+    /// The pixel format isn't modeled and no decoder is registered for the format code.
+    #[error("pixel format code {0:#010x} is not supported")]
+    UnsupportedPixelFormat(u32),
+
+    /// A registered decoder failed to decode the image.
+    #[error("failed to decode the image: {0}")]
+    Decode(Cow<'static, str>),
+}
+
+/// An owned image converted from [`Payload`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    info: ImageInfo,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Creates an image, `image_size` of `info` is set to the length of `data`.
+    #[must_use]
+    pub fn new(mut info: ImageInfo, data: Vec<u8>) -> Self {
+        info.image_size = data.len();
+        Self { info, data }
+    }
+
+    /// Returns [`ImageInfo`] of the image.
+    #[must_use]
+    pub fn info(&self) -> &ImageInfo {
+        &self.info
+    }
+
+    /// Returns the image bytes.
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the image bytes as `Vec<u8>`.
+    #[must_use]
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Decodes an image of a pixel format which isn't modeled by [`PixelFormat`].
+///
+/// Closures of the signature `Fn(&ImageInfo, &[u8]) -> ImageResult<Image>` implement the trait.
+pub trait PixelDecoder: Send + Sync {
+    /// Decodes `image` described by `image_info`, the returned image should be in a pixel format
+    /// modeled by [`PixelFormat`] so that it can be processed further.
+    fn decode(&self, image_info: &ImageInfo, image: &[u8]) -> ImageResult<Image>;
+}
+
+impl<F> PixelDecoder for F
+where
+    F: Fn(&ImageInfo, &[u8]) -> ImageResult<Image> + Send + Sync,
+{
+    fn decode(&self, image_info: &ImageInfo, image: &[u8]) -> ImageResult<Image> {
+        self(image_info, image)
+    }
+}
+
+/// Decoders of pixel formats which aren't modeled by [`PixelFormat`], registered per stream.
+///
+/// Each stream handle owns its decoders, and the payloads received by the stream refer to them,
+/// so that decoders registered for one camera don't affect the others. Clones of the value share
+/// the same decoders, a decoder registered while streaming is used for the payloads received
+/// afterwards.
+#[derive(Clone, Default)]
+pub struct PixelDecoders(Arc<RwLock<Vec<Registered>>>);
+
+/// A decoder and the pixel format code it's registered for.
+type Registered = (u32, Arc<dyn PixelDecoder>);
+
+impl PixelDecoders {
+    /// Registers `decoder` for the pixel format `code`, the decoder already registered for the
+    /// code is replaced.
+    ///
+    /// Decoders are used only for codes which aren't modeled by [`PixelFormat`].
+    pub fn register(&self, code: u32, decoder: impl PixelDecoder + 'static) {
+        let mut decoders = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        decoders.retain(|(registered, _)| *registered != code);
+        decoders.push((code, Arc::new(decoder)));
+    }
+
+    /// Unregisters the decoder for the pixel format `code`, returns `true` if it's registered.
+    pub fn unregister(&self, code: u32) -> bool {
+        let mut decoders = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        let len = decoders.len();
+        decoders.retain(|(registered, _)| *registered != code);
+        decoders.len() != len
+    }
+
+    fn get(&self, code: u32) -> Option<Arc<dyn PixelDecoder>> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .find(|(registered, _)| *registered == code)
+            .map(|(_, decoder)| decoder.clone())
+    }
+}
+
+impl fmt::Debug for PixelDecoders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let codes: Vec<u32> = self
+            .0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(code, _)| *code)
+            .collect();
+        f.debug_tuple("PixelDecoders")
+            .field(&format_args!("{:x?}", codes))
+            .finish()
+    }
+}
+
+/// Values are equal if they share the same decoders, i.e. one is a clone of the other.
+impl PartialEq for PixelDecoders {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for PixelDecoders {}
+
+impl Payload {
+    /// Converts the image in the payload into an owned [`Image`].
+    ///
+    /// An image of [`PixelFormat::Unknown`] is decoded by the decoder registered for the format
+    /// code in [`PixelDecoders`] of the stream which received the payload,
+    /// [`ImageError::UnsupportedPixelFormat`] is returned if no decoder is registered.
+    pub fn to_image(&self) -> ImageResult<Image> {
+        let image_info = self.image_info().ok_or(ImageError::NoImage)?;
+        let image = self.image().ok_or(ImageError::NoImage)?;
+
+        match image_info.pixel_format {
+            PixelFormat::Unknown(code) => self
+                .decoders
+                .as_ref()
+                .and_then(|decoders| decoders.get(code))
+                .ok_or(ImageError::UnsupportedPixelFormat(code))?
+                .decode(image_info, image),
+            _ => Ok(Image::new(image_info.clone(), image.to_vec())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{test_payload, PayloadType},
+        *,
+    };
+
+    fn payload(
+        pixel_format: PixelFormat,
+        bytes: Vec<u8>,
+        decoders: Option<PixelDecoders>,
+    ) -> Payload {
+        let image_info = ImageInfo {
+            width: 4,
+            height: 2,
+            x_offset: 0,
+            y_offset: 0,
+            pixel_format,
+            image_size: bytes.len(),
+            x_padding: 0,
+        };
+        let mut payload = test_payload(PayloadType::Image, Some(image_info), bytes);
+        payload.decoders = decoders;
+        payload
+    }
+
+    #[test]
+    fn test_to_image() {
+        let bytes: Vec<u8> = (0..8).collect();
+        let image = payload(PixelFormat::Mono8, bytes.clone(), None)
+            .to_image()
+            .unwrap();
+        assert_eq!(image.info().pixel_format, PixelFormat::Mono8);
+        assert_eq!(image.data(), bytes.as_slice());
+    }
+
+    #[test]
+    fn test_decoder() {
+        // A vendor specific format which sends inverted `Mono8`.
+        const CODE: u32 = 0x8108_7001;
+
+        let decoders = PixelDecoders::default();
+        let other = payload(
+            PixelFormat::Unknown(CODE),
+            (0..8).collect(),
+            Some(PixelDecoders::default()),
+        );
+        let payload = payload(
+            PixelFormat::Unknown(CODE),
+            (0..8).collect(),
+            Some(decoders.clone()),
+        );
+        assert!(matches!(
+            payload.to_image(),
+            Err(ImageError::UnsupportedPixelFormat(CODE))
+        ));
+
+        decoders.register(CODE, |info: &ImageInfo, image: &[u8]| {
+            let info = ImageInfo {
+                pixel_format: PixelFormat::Mono8,
+                ..info.clone()
+            };
+            Ok(Image::new(info, image.iter().map(|b| !b).collect()))
+        });
+        let image = payload.to_image().unwrap();
+        assert_eq!(image.info().pixel_format, PixelFormat::Mono8);
+        assert_eq!((image.info().width, image.info().height), (4, 2));
+        assert_eq!(image.data(), &[255, 254, 253, 252, 251, 250, 249, 248]);
+
+        // Decoders of another stream aren't used.
+        assert!(other.to_image().is_err());
+
+        assert_eq!(format!("{:?}", decoders), "PixelDecoders([81087001])");
+        assert!(decoders.unregister(CODE));
+        assert!(!decoders.unregister(CODE));
+        assert!(payload.to_image().is_err());
+    }
+}
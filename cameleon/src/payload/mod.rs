@@ -0,0 +1,635 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains types related to `Payload` sent from the device.
+//!
+//! `Payload` is an abstracted container that is mainly used to transfer an image, but also meta data of the image.
+//! See [`Payload`] and [`ImageInfo`] for more details.
+//!
+//! [`Payload::copy_to`] copies the payload into caller-managed memory, and [`BufferProvider`]
+//! lets the streaming loop receive payload data directly into it. [`BufferPool`] recycles
+//! pre-allocated buffers between the streaming loop and the application.
+
+pub use cameleon_device::PixelFormat;
+pub use chunk::{ChunkBlock, ChunkControl, ChunkError, ChunkIter};
+pub use copy::{CopyLayout, CopyReport};
+pub use delivery::{BufferProvider, DestinationBuffer};
+pub use frame_id::{FrameId, ParseFrameIdError};
+pub use gendc::{GenDcComponent, GenDcContainer, GenDcError, GenDcPart, GenDcPartKind};
+pub use image::{Image, ImageError, ImageResult, PixelDecoder, PixelDecoders};
+#[cfg(feature = "libusb")]
+pub(crate) use pipeline::PipelineRunner;
+pub use pipeline::{
+    FlipVertical, PayloadBuffer, PayloadMetadata, PipelineStage, StageClone, StageError,
+    StageResult, StageStatistics, UnpackMono,
+};
+pub use pool::{BufferPool, BufferPoolStatistics, OverflowPolicy};
+pub use queue::FrameQueue;
+pub use statistics::ReceiverStatistics;
+
+mod chunk;
+mod copy;
+mod delivery;
+mod frame_id;
+mod gendc;
+mod image;
+mod pipeline;
+mod pool;
+mod queue;
+mod statistics;
+
+#[cfg(feature = "ndarray")]
+mod array;
+#[cfg(feature = "ndarray")]
+pub use array::{ArrayViewError, ArrayViewResult, PixelElement};
+
+use std::{
+    sync::{Arc, Weak},
+    time,
+};
+
+use async_std::channel::{Receiver, Sender};
+use cameleon_impl::leak_check::Tracked;
+
+use super::{StreamError, StreamResult};
+
+pub(crate) use delivery::ProvidedBuffer;
+pub(crate) use pool::PoolHandle;
+#[cfg(feature = "libusb")]
+pub(crate) use pool::PooledBuffer;
+pub(crate) use statistics::ReceiverCounters;
+
+/// Represents Payload type of the image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadType {
+    /// Payload contains just an image data only.
+    Image,
+    /// Payload contains multiple data chunks, and its first chunk is an image.
+    ImageExtendedChunk,
+    /// Payload contains multiple data chunks, no gurantee about its first chunk.
+    Chunk,
+    /// Payload contains a GenDC container, see [`Payload::gendc`].
+    GenDc,
+}
+
+/// Image meta information.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageInfo {
+    /// Width of the image.
+    pub width: usize,
+    /// Height of the image.
+    pub height: usize,
+    /// X offset in pixels from the whole image origin. Some devices have capability of
+    /// sending multiple extracted image regions, this fields used for the purpose.
+    pub x_offset: usize,
+    /// Y offset in pixels from the whole image origin. Some devices have capability of
+    /// sending multiple extracted image regions, this fields used for the purpose.
+    pub y_offset: usize,
+    /// [`PixelFormat`] of the image.
+    pub pixel_format: PixelFormat,
+    /// Size of image in bytes.
+    pub image_size: usize,
+    /// Number of padding bytes added to the end of each row of the image.
+    pub x_padding: usize,
+}
+
+/// Describes how much of the payload is lost when the payload is incomplete.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IncompleteInfo {
+    /// Size of the payload reported by the device in bytes.
+    pub expected_size: usize,
+    /// Size of the payload actually received in bytes.
+    pub received_size: usize,
+}
+
+/// Status of the payload reported by the device in the trailer, or detected by the host.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PayloadStatus {
+    /// The device sent the whole payload.
+    #[default]
+    Success,
+    /// The device discarded a part of the payload data.
+    DataDiscarded,
+    /// The device missed a part of the payload data due to inappropriate `SIRM` register
+    /// settings.
+    DataOverrun,
+    /// Transfers of the payload received fewer bytes than expected from the transfer sizes of
+    /// `SIRM` and the payload size reported in the trailer, e.g. a transfer in the middle of
+    /// the payload is lost.
+    ///
+    /// The host detects this status, and the bytes received after the missing ones are packed
+    /// towards the head of the payload.
+    Incomplete {
+        /// The number of bytes missing from the payload.
+        missing_bytes: usize,
+        /// The number of transfers which received fewer bytes than expected.
+        missing_segments: usize,
+    },
+}
+
+/// A payload sent from the device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Payload {
+    pub(crate) id: u64,
+    pub(crate) frame_id: FrameId,
+    pub(crate) payload_type: PayloadType,
+    /// Chunk layout id reported in the trailer, `None` if the payload doesn't contain chunks.
+    pub(crate) chunk_layout_id: Option<u32>,
+    pub(crate) image_info: Option<ImageInfo>,
+    /// Pool buffer holding the payload data, which is empty if the data is received into
+    /// `provided`.
+    pub(crate) payload: Vec<u8>,
+    /// Buffer provided by [`BufferProvider`] holding the payload data.
+    pub(crate) provided: Option<ProvidedBuffer>,
+    pub(crate) valid_payload_size: usize,
+    pub(crate) timestamp: time::Duration,
+    pub(crate) incomplete_info: Option<IncompleteInfo>,
+    pub(crate) status: PayloadStatus,
+    /// The pool to which the buffer is returned on drop if it's taken from [`BufferPool`].
+    pub(crate) pool: PoolHandle,
+    /// Accounts the buffer as checked out while the payload is alive.
+    pub(crate) tracked: Tracked,
+    /// Decoders of the stream which received the payload, see [`Payload::to_image`].
+    pub(crate) decoders: Option<PixelDecoders>,
+}
+
+impl Payload {
+    /// Returns [`PayloadType`] of the payload.
+    pub fn payload_type(&self) -> PayloadType {
+        self.payload_type
+    }
+
+    /// Returns the chunk layout id reported by the device if `payload_type` is
+    /// [`PayloadType::ImageExtendedChunk`] or [`PayloadType::Chunk`].
+    ///
+    /// The id changes when the layout of the chunks changes, e.g. a chunk is enabled, see
+    /// [`Self::chunks`].
+    pub fn chunk_layout_id(&self) -> Option<u32> {
+        self.chunk_layout_id
+    }
+
+    /// Returns [`ImageInfo`] if `payload_type` is [`PayloadType::Image`] or
+    /// [`PayloadType::ImageExtendedChunk`].
+    pub fn image_info(&self) -> Option<&ImageInfo> {
+        self.image_info.as_ref()
+    }
+
+    /// Returns the image bytes in the payload if `payload_type` is [`PayloadType::Image`]  or
+    /// [`PayloadType::ImageExtendedChunk`].
+    pub fn image(&self) -> Option<&[u8]> {
+        let image_info = self.image_info()?;
+        Some(&self.data()[..image_info.image_size])
+    }
+
+    /// Returns the whole payload. Use [`Self::image`] instead if you interested only
+    /// in image region of the payload.
+    pub fn payload(&self) -> &[u8] {
+        &self.data()[..self.valid_payload_size]
+    }
+
+    /// Returns the buffer provided by [`BufferProvider`] if the payload data is received into
+    /// it, see [`StreamParams::deliver_into`].
+    ///
+    /// [`StreamParams::deliver_into`]: crate::u3v::StreamParams::deliver_into
+    pub fn provided_buffer(&self) -> Option<&dyn DestinationBuffer> {
+        self.provided.as_ref().map(ProvidedBuffer::get)
+    }
+
+    /// Returns unique id of `payload`, which sequentially incremented every time the device send a
+    /// `payload`.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Returns [`FrameId`] of `payload`, which is unique across devices and acquisitions.
+    pub fn frame_id(&self) -> FrameId {
+        self.frame_id
+    }
+
+    /// Timestamp of the device when the payload is generated.
+    pub fn timestamp(&self) -> time::Duration {
+        self.timestamp
+    }
+
+    /// Returns [`IncompleteInfo`] if a part of the payload is lost while receiving it.
+    ///
+    /// [`Self::payload`] contains only the bytes actually received in that case.
+    pub fn incomplete_info(&self) -> Option<&IncompleteInfo> {
+        self.incomplete_info.as_ref()
+    }
+
+    /// Returns [`PayloadStatus`] reported by the device in the trailer, or
+    /// [`PayloadStatus::Incomplete`] if the host detects missing transfers.
+    ///
+    /// A payload whose status isn't [`PayloadStatus::Success`] is still delivered unless
+    /// [`ReceiverConfig::deliver_incomplete`] is `false`, the received data may be partially
+    /// invalid then.
+    pub fn status(&self) -> PayloadStatus {
+        self.status
+    }
+
+    /// Returns `true` if a part of the payload is lost while receiving it, or the device reports
+    /// that it failed to send a part of the payload.
+    pub fn is_incomplete(&self) -> bool {
+        self.incomplete_info.is_some() || self.status != PayloadStatus::Success
+    }
+
+    /// Returns the payload as `Vec<u8>`.
+    ///
+    /// The data is copied if it's received into a buffer provided by [`BufferProvider`] or taken
+    /// from [`BufferPool`].
+    pub fn into_vec(mut self) -> Vec<u8> {
+        if self.provided.is_some() || self.pool.is_pooled() {
+            return self.payload().to_vec();
+        }
+        let mut payload = std::mem::take(&mut self.payload);
+        payload.resize(self.valid_payload_size, 0);
+        payload
+    }
+
+    /// Returns the buffer to [`BufferPool`] immediately, which is the same as dropping the
+    /// payload.
+    pub fn release(self) {}
+
+    fn data(&self) -> &[u8] {
+        match &self.provided {
+            Some(provided) => provided.get().as_slice(),
+            None => &self.payload,
+        }
+    }
+}
+
+impl Drop for Payload {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.put(std::mem::take(&mut self.payload));
+        }
+    }
+}
+
+/// Builds a successfully received payload holding `bytes` for tests, the other fields are
+/// assigned by the caller if needed.
+#[cfg(test)]
+pub(crate) fn test_payload(
+    payload_type: PayloadType,
+    image_info: Option<ImageInfo>,
+    bytes: Vec<u8>,
+) -> Payload {
+    Payload {
+        id: 0,
+        frame_id: FrameId::default(),
+        payload_type,
+        chunk_layout_id: None,
+        image_info,
+        valid_payload_size: bytes.len(),
+        payload: bytes,
+        provided: None,
+        timestamp: time::Duration::default(),
+        incomplete_info: None,
+        status: PayloadStatus::Success,
+        pool: PoolHandle::default(),
+        tracked: Tracked::new(cameleon_impl::leak_check::Resource::PoolBuffer),
+        decoders: None,
+    }
+}
+
+/// An Receiver of the `Payload` which is sent from a device.
+///
+/// With `async` feature, the receiver implements [`futures::Stream`], which ends when the
+/// streaming loop is stopped. The streaming loop stops by itself and cancels its pending
+/// transfers once all clones of the receiver are dropped.
+#[derive(Debug, Clone)]
+pub struct PayloadReceiver {
+    /// Sends back `payload` to the device for reusing it.
+    tx: Sender<Payload>,
+
+    /// Receives `payload` from the device.
+    rx: Receiver<StreamResult<Payload>>,
+
+    /// Drop guard which lets [`PayloadSender`] know whether the receiver is alive.
+    _alive: Arc<()>,
+
+    /// Counters updated by [`PayloadSender`].
+    counters: Arc<ReceiverCounters>,
+}
+
+impl PayloadReceiver {
+    /// Receives [`Payload`] sent from the device.
+    pub async fn recv(&self) -> StreamResult<Payload> {
+        self.rx.recv().await?
+    }
+
+    /// Tries to receive [`Payload`].
+    /// This method doesn't wait arrival of `payload` and immediately returns `StreamError` if
+    /// the channel is empty.
+    pub fn try_recv(&self) -> StreamResult<Payload> {
+        self.rx.try_recv()?
+    }
+
+    /// Sends back [`Payload`] to the device to reuse already allocated `payload`.
+    ///
+    /// Sending back `payload` may improve performance of streaming, but not required to call this
+    /// method. A payload received into a buffer provided by [`BufferProvider`] is just dropped,
+    /// and a payload whose buffer is taken from [`BufferPool`] returns the buffer to the pool.
+    pub fn send_back(&self, payload: Payload) {
+        if payload.provided.is_none() && !payload.pool.is_pooled() {
+            self.tx.try_send(payload).ok();
+        }
+    }
+
+    /// Returns [`ReceiverStatistics`] accumulated since the channel is created or the last
+    /// [`Self::reset_statistics`].
+    ///
+    /// The statistics are shared between the clones of the receiver, and can be read while
+    /// streaming without blocking the streaming loop.
+    pub fn statistics(&self) -> ReceiverStatistics {
+        self.counters.snapshot()
+    }
+
+    /// Resets [`ReceiverStatistics`].
+    pub fn reset_statistics(&self) {
+        self.counters.reset();
+    }
+}
+
+/// A sender of the [`Payload`] which is sent to the host.
+#[derive(Debug, Clone)]
+pub struct PayloadSender {
+    /// Receives from the device.
+    tx: Sender<StreamResult<Payload>>,
+    /// Sends back payload to reuse it.
+    rx: Receiver<Payload>,
+    /// Payloads not yet received by the host, see [`Self::drop_oldest`].
+    #[cfg(any(test, feature = "libusb"))]
+    queued: Receiver<StreamResult<Payload>>,
+    /// Dangles once all clones of [`PayloadReceiver`] are dropped.
+    host: Weak<()>,
+    /// Counters of [`ReceiverStatistics`].
+    counters: Arc<ReceiverCounters>,
+    config: ReceiverConfig,
+}
+
+impl PayloadSender {
+    /// Sends [`Payload`] to the host.
+    ///
+    /// An incomplete payload is dropped instead if [`ReceiverConfig::deliver_incomplete`] is
+    /// `false`.
+    pub async fn send(&self, payload: StreamResult<Payload>) -> StreamResult<()> {
+        if self.filter_out(&payload) {
+            return Ok(());
+        }
+        let delivery = payload
+            .as_ref()
+            .ok()
+            .map(|payload| (payload.valid_payload_size, payload.is_incomplete()));
+        self.tx.send(payload).await?;
+        if let Some((size, is_incomplete)) = delivery {
+            self.counters.delivered(size, is_incomplete);
+        }
+        Ok(())
+    }
+
+    /// Tries to send [`Payload`] to the host.
+    /// Returns `StreamError` if the channel is full or empty.
+    ///
+    /// An incomplete payload is dropped instead if [`ReceiverConfig::deliver_incomplete`] is
+    /// `false`.
+    pub fn try_send(&self, payload: StreamResult<Payload>) -> StreamResult<()> {
+        if self.filter_out(&payload) {
+            return Ok(());
+        }
+        let delivery = payload
+            .as_ref()
+            .ok()
+            .map(|payload| (payload.valid_payload_size, payload.is_incomplete()));
+        match self.tx.try_send(payload) {
+            Ok(()) => {
+                if let Some((size, is_incomplete)) = delivery {
+                    self.counters.delivered(size, is_incomplete);
+                }
+                Ok(())
+            }
+            Err(err) => {
+                if delivery.is_some() {
+                    self.counters.underrun();
+                }
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Tries to receive [`Payload`].
+    /// This method doesn't wait arrival of `payload` and immediately returns `StreamError` if
+    /// the channel is empty.
+    pub fn try_recv(&self) -> StreamResult<Payload> {
+        Ok(self.rx.try_recv()?)
+    }
+
+    /// Returns `true` if all clones of [`PayloadReceiver`] are dropped.
+    pub fn is_closed(&self) -> bool {
+        self.host.strong_count() == 0
+    }
+
+    /// Drops the oldest payload not yet received by the host, errors queued before it are
+    /// dropped together. Returns `false` if no payload is queued.
+    ///
+    /// The dropped payload is accounted as an underrun instead of a delivery.
+    #[cfg(any(test, feature = "libusb"))]
+    pub(crate) fn drop_oldest(&self) -> bool {
+        while let Ok(payload) = self.queued.try_recv() {
+            if let Ok(payload) = payload {
+                self.counters
+                    .undeliver(payload.valid_payload_size, payload.is_incomplete());
+                self.counters.underrun();
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns the counters of [`ReceiverStatistics`] shared with the receiver.
+    #[cfg(feature = "libusb")]
+    pub(crate) fn counters(&self) -> Arc<ReceiverCounters> {
+        self.counters.clone()
+    }
+
+    /// Accounts a resynchronization to the next leader in [`ReceiverStatistics`].
+    #[cfg(feature = "libusb")]
+    pub(crate) fn record_resync(&self) {
+        self.counters.resync();
+    }
+
+    /// Returns `true` if `payload` is dropped according to [`ReceiverConfig`] instead of being
+    /// sent.
+    fn filter_out(&self, payload: &StreamResult<Payload>) -> bool {
+        match payload {
+            Ok(payload) if !self.config.deliver_incomplete && payload.is_incomplete() => {
+                self.counters.dropped_incomplete();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Configuration of the channel between [`PayloadSender`] and [`PayloadReceiver`], see
+/// [`channel_with_config`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReceiverConfig {
+    /// Delivers incomplete payloads, see [`Payload::is_incomplete`]. If `false`, incomplete
+    /// payloads are dropped before they reach the receiver, and counted in
+    /// [`ReceiverStatistics::num_dropped_incomplete`].
+    ///
+    /// `true` by default.
+    pub deliver_incomplete: bool,
+}
+
+impl Default for ReceiverConfig {
+    fn default() -> Self {
+        Self {
+            deliver_incomplete: true,
+        }
+    }
+}
+
+/// Creates [`PayloadReceiver`] and [`PayloadSender`].
+pub fn channel(payload_cap: usize, buffer_cap: usize) -> (PayloadSender, PayloadReceiver) {
+    channel_with_config(payload_cap, buffer_cap, ReceiverConfig::default())
+}
+
+/// Creates [`PayloadReceiver`] and [`PayloadSender`] configured with `config`.
+pub fn channel_with_config(
+    payload_cap: usize,
+    buffer_cap: usize,
+    config: ReceiverConfig,
+) -> (PayloadSender, PayloadReceiver) {
+    let (device_tx, host_rx) = async_std::channel::bounded(payload_cap);
+    let (host_tx, device_rx) = async_std::channel::bounded(buffer_cap);
+    let alive = Arc::new(());
+    let counters = Arc::new(ReceiverCounters::new());
+    (
+        PayloadSender {
+            tx: device_tx,
+            rx: device_rx,
+            #[cfg(any(test, feature = "libusb"))]
+            queued: host_rx.clone(),
+            host: Arc::downgrade(&alive),
+            counters: counters.clone(),
+            config,
+        },
+        PayloadReceiver {
+            tx: host_tx,
+            rx: host_rx,
+            _alive: alive,
+            counters,
+        },
+    )
+}
+
+#[cfg(feature = "async")]
+impl futures::Stream for PayloadReceiver {
+    type Item = StreamResult<Payload>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        futures::Stream::poll_next(std::pin::Pin::new(&mut self.rx), cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        futures::Stream::size_hint(&self.rx)
+    }
+}
+
+impl From<async_std::channel::RecvError> for StreamError {
+    fn from(err: async_std::channel::RecvError) -> Self {
+        StreamError::ReceiveError(err.to_string().into())
+    }
+}
+
+impl From<async_std::channel::TryRecvError> for StreamError {
+    fn from(err: async_std::channel::TryRecvError) -> Self {
+        StreamError::ReceiveError(err.to_string().into())
+    }
+}
+
+impl<T> From<async_std::channel::SendError<T>> for StreamError {
+    fn from(err: async_std::channel::SendError<T>) -> Self {
+        StreamError::ReceiveError(err.to_string().into())
+    }
+}
+
+impl From<ChunkError> for StreamError {
+    fn from(err: ChunkError) -> Self {
+        StreamError::InvalidPayload(format!("failed to parse chunk data: {}", err).into())
+    }
+}
+
+impl<T> From<async_std::channel::TrySendError<T>> for StreamError {
+    fn from(err: async_std::channel::TrySendError<T>) -> Self {
+        StreamError::ReceiveError(err.to_string().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receiver_dropped() {
+        let (tx, rx) = channel(1, 1);
+        let rx2 = rx.clone();
+        drop(rx);
+        assert!(!tx.is_closed());
+        drop(rx2);
+        assert!(tx.is_closed());
+    }
+
+    #[test]
+    fn test_drop_incomplete() {
+        let payload = |status| {
+            let mut payload = test_payload(PayloadType::Chunk, None, vec![0; 16]);
+            payload.status = status;
+            payload
+        };
+        let missing = PayloadStatus::Incomplete {
+            missing_bytes: 8,
+            missing_segments: 1,
+        };
+
+        let (tx, rx) = channel(2, 2);
+        tx.try_send(Ok(payload(missing))).unwrap();
+        assert_eq!(rx.try_recv().unwrap().status(), missing);
+
+        let config = ReceiverConfig {
+            deliver_incomplete: false,
+        };
+        let (tx, rx) = channel_with_config(2, 2, config);
+        tx.try_send(Ok(payload(missing))).unwrap();
+        tx.try_send(Ok(payload(PayloadStatus::Success))).unwrap();
+        assert_eq!(rx.try_recv().unwrap().status(), PayloadStatus::Success);
+        assert!(rx.try_recv().is_err());
+        let stats = rx.statistics();
+        assert_eq!((stats.num_delivered, stats.num_dropped_incomplete), (1, 1));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_stream() {
+        use futures::StreamExt;
+
+        let (tx, rx) = channel(3, 3);
+        let producer = std::thread::spawn(move || {
+            for _ in 0..100 {
+                async_std::task::block_on(tx.send(Err(StreamError::Timeout))).unwrap();
+            }
+        });
+
+        // The stream ends when the sender is dropped.
+        let received = async_std::task::block_on(rx.collect::<Vec<_>>());
+        producer.join().unwrap();
+        assert_eq!(received.len(), 100);
+    }
+}
@@ -0,0 +1,614 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides post-processing stages executed on the receive side before a payload is
+//! delivered.
+//!
+//! Stages are executed in order by the streaming loop, so a heavy stage delays the reception of
+//! the following payloads. [`UnpackMono`] and [`FlipVertical`] are provided as built-in stages.
+
+use std::{borrow::Cow, fmt, time::Duration};
+#[cfg(any(test, feature = "libusb"))]
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::Instant,
+};
+
+#[cfg(any(doc, test, feature = "libusb"))]
+use super::Payload;
+use super::{FrameId, ImageInfo, IncompleteInfo, PayloadStatus, PayloadType, PixelFormat};
+
+/// A specialized `Result` type for [`PipelineStage::process`].
+pub type StageResult = std::result::Result<(), StageError>;
+
+/// An error type returned by [`PipelineStage::process`].
+#[derive(Debug, thiserror::Error)]
+pub enum StageError {
+    /// The stage rejects the payload. The payload is counted as rejected and isn't delivered.
+    #[error("payload is rejected: {0}")]
+    Rejected(Cow<'static, str>),
+
+    /// The stage failed to process the payload. The payload isn't delivered and the error is
+    /// sent to the receiver instead.
+    #[error("failed to process payload: {0}")]
+    Failed(Cow<'static, str>),
+}
+
+/// A stage of the post-processing pipeline, see [`StreamParams::pipeline`].
+///
+/// [`StreamParams::pipeline`]: crate::u3v::StreamParams::pipeline
+pub trait PipelineStage: StageClone + Send {
+    /// Returns the name of the stage, which is used to report statistics.
+    fn name(&self) -> &str;
+
+    /// Returns `true` if the stage modifies [`PayloadBuffer::data_mut`] in place.
+    ///
+    /// Otherwise, the stage writes the result to [`PayloadBuffer::output`], which then replaces
+    /// the data of the buffer.
+    fn in_place(&self) -> bool;
+
+    /// Processes the payload.
+    fn process(&mut self, buf: &mut PayloadBuffer, metadata: &PayloadMetadata) -> StageResult;
+}
+
+/// Clones a boxed [`PipelineStage`], which is implemented for all stages implementing `Clone`.
+pub trait StageClone {
+    /// Clones the stage.
+    fn clone_stage(&self) -> Box<dyn PipelineStage>;
+}
+
+impl<T> StageClone for T
+where
+    T: PipelineStage + Clone + 'static,
+{
+    fn clone_stage(&self) -> Box<dyn PipelineStage> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn PipelineStage> {
+    fn clone(&self) -> Self {
+        self.clone_stage()
+    }
+}
+
+impl fmt::Debug for dyn PipelineStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PipelineStage")
+            .field("name", &self.name())
+            .field("in_place", &self.in_place())
+            .finish()
+    }
+}
+
+/// Meta information of the payload under processing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PayloadMetadata {
+    /// Id of the payload, see [`Payload::id`].
+    pub id: u64,
+    /// [`FrameId`] of the payload.
+    pub frame_id: FrameId,
+    /// [`PayloadType`] of the payload.
+    pub payload_type: PayloadType,
+    /// Timestamp of the device when the payload is generated.
+    pub timestamp: Duration,
+    /// [`IncompleteInfo`] if a part of the payload is lost while receiving it.
+    pub incomplete_info: Option<IncompleteInfo>,
+    /// [`PayloadStatus`] reported by the device.
+    pub status: PayloadStatus,
+}
+
+/// Payload data under processing.
+#[derive(Clone, Debug, Default)]
+pub struct PayloadBuffer {
+    data: Vec<u8>,
+    image_info: Option<ImageInfo>,
+    output: Vec<u8>,
+}
+
+impl PayloadBuffer {
+    /// Creates a buffer, `image_size` of `image_info` must not exceed the length of `data`.
+    #[must_use]
+    pub fn new(data: Vec<u8>, image_info: Option<ImageInfo>) -> Self {
+        Self {
+            data,
+            image_info,
+            output: vec![],
+        }
+    }
+
+    /// Returns the whole valid payload.
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the whole valid payload.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Returns the image bytes in the payload.
+    #[must_use]
+    pub fn image(&self) -> Option<&[u8]> {
+        let image_size = self.image_info.as_ref()?.image_size;
+        Some(&self.data[..image_size])
+    }
+
+    /// Returns the image bytes in the payload.
+    pub fn image_mut(&mut self) -> Option<&mut [u8]> {
+        let image_size = self.image_info.as_ref()?.image_size;
+        Some(&mut self.data[..image_size])
+    }
+
+    /// Returns [`ImageInfo`] of the payload.
+    #[must_use]
+    pub fn image_info(&self) -> Option<&ImageInfo> {
+        self.image_info.as_ref()
+    }
+
+    /// Returns [`ImageInfo`] of the payload, which must be updated when a stage changes the
+    /// image layout.
+    pub fn image_info_mut(&mut self) -> Option<&mut ImageInfo> {
+        self.image_info.as_mut()
+    }
+
+    /// Returns the current data and the output of a stage which isn't [in place].
+    ///
+    /// The output is empty when the stage is called, and replaces the data after the stage
+    /// succeeds. The output is discarded for a stage which is in place.
+    ///
+    /// [in place]: PipelineStage::in_place
+    pub fn output(&mut self) -> (&[u8], &mut Vec<u8>) {
+        (&self.data, &mut self.output)
+    }
+
+    /// Returns the data and [`ImageInfo`] of the buffer.
+    #[must_use]
+    pub fn into_parts(self) -> (Vec<u8>, Option<ImageInfo>) {
+        (self.data, self.image_info)
+    }
+}
+
+/// Statistics of a pipeline stage, see [`StreamHandle::pipeline_statistics`].
+///
+/// [`StreamHandle::pipeline_statistics`]: crate::u3v::StreamHandle::pipeline_statistics
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StageStatistics {
+    /// Name of the stage.
+    pub name: String,
+    /// The number of payloads passed to the stage.
+    pub processed_payloads: u64,
+    /// The number of payloads rejected by the stage.
+    pub rejected_payloads: u64,
+    /// The number of payloads which the stage failed to process.
+    pub failed_payloads: u64,
+    /// Total time spent in the stage.
+    pub total_time: Duration,
+    /// The longest time spent in the stage for a payload.
+    pub max_time: Duration,
+}
+
+impl StageStatistics {
+    /// Returns the average time spent in the stage for a payload.
+    #[must_use]
+    pub fn mean_time(&self) -> Duration {
+        if self.processed_payloads == 0 {
+            Duration::default()
+        } else {
+            #[allow(clippy::cast_precision_loss)]
+            let processed = self.processed_payloads as f64;
+            Duration::from_secs_f64(self.total_time.as_secs_f64() / processed)
+        }
+    }
+
+    #[cfg(any(test, feature = "libusb"))]
+    fn record(&mut self, elapsed: Duration, err: Option<&StageError>) {
+        self.processed_payloads += 1;
+        match err {
+            None => {}
+            Some(StageError::Rejected(_)) => self.rejected_payloads += 1,
+            Some(StageError::Failed(_)) => self.failed_payloads += 1,
+        }
+        self.total_time += elapsed;
+        self.max_time = self.max_time.max(elapsed);
+    }
+}
+
+/// Executes pipeline stages on payloads.
+#[cfg(any(test, feature = "libusb"))]
+pub(crate) struct PipelineRunner {
+    stages: Vec<Box<dyn PipelineStage>>,
+    /// Output buffer recycled between stages, so that a stage which isn't in place doesn't
+    /// allocate after the first payload.
+    spare: Vec<u8>,
+    statistics: Arc<Mutex<Vec<StageStatistics>>>,
+}
+
+#[cfg(any(test, feature = "libusb"))]
+impl PipelineRunner {
+    /// Creates a runner, `statistics` is reset for `stages`.
+    pub(crate) fn new(
+        stages: Vec<Box<dyn PipelineStage>>,
+        statistics: Arc<Mutex<Vec<StageStatistics>>>,
+    ) -> Self {
+        *statistics.lock().unwrap_or_else(PoisonError::into_inner) = stages
+            .iter()
+            .map(|stage| StageStatistics {
+                name: stage.name().to_string(),
+                ..StageStatistics::default()
+            })
+            .collect();
+
+        Self {
+            stages,
+            spare: vec![],
+            statistics,
+        }
+    }
+
+    /// Executes the stages on `payload` in order, the execution stops at the first stage which
+    /// returns an error.
+    ///
+    /// The payload buffer is truncated to the valid payload even if an error is returned.
+    pub(crate) fn run(&mut self, payload: &mut Payload) -> StageResult {
+        if self.stages.is_empty() {
+            return Ok(());
+        }
+
+        let metadata = PayloadMetadata {
+            id: payload.id,
+            frame_id: payload.frame_id,
+            payload_type: payload.payload_type,
+            timestamp: payload.timestamp,
+            incomplete_info: payload.incomplete_info,
+            status: payload.status,
+        };
+        let mut data = std::mem::take(&mut payload.payload);
+        data.truncate(payload.valid_payload_size);
+        let mut buf = PayloadBuffer {
+            data,
+            image_info: payload.image_info.take(),
+            output: std::mem::take(&mut self.spare),
+        };
+
+        let mut elapsed = Vec::with_capacity(self.stages.len());
+        let mut res = Ok(());
+        for stage in &mut self.stages {
+            let in_place = stage.in_place();
+            buf.output.clear();
+            let start = Instant::now();
+            res = stage.process(&mut buf, &metadata);
+            elapsed.push(start.elapsed());
+            if res.is_err() {
+                break;
+            }
+            if !in_place {
+                std::mem::swap(&mut buf.data, &mut buf.output);
+            }
+        }
+
+        let mut statistics = self
+            .statistics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let last = elapsed.len() - 1;
+        for (i, (stats, elapsed)) in statistics.iter_mut().zip(elapsed).enumerate() {
+            // Only the last executed stage can return an error.
+            let err = if i == last { res.as_ref().err() } else { None };
+            stats.record(elapsed, err);
+        }
+
+        let PayloadBuffer {
+            data,
+            image_info,
+            output,
+        } = buf;
+        payload.valid_payload_size = data.len();
+        payload.payload = data;
+        payload.image_info = image_info;
+        self.spare = output;
+        res
+    }
+}
+
+/// A built-in stage which unpacks packed mono pixel formats into 16 bits little endian pixels.
+///
+/// `Mono10Packed` and `Mono10p` are unpacked into `Mono10`, `Mono12Packed` and `Mono12p` are
+/// unpacked into `Mono12`. Payloads of other pixel formats are passed through. Chunk data
+/// following the image is kept as is.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UnpackMono;
+
+impl PipelineStage for UnpackMono {
+    fn name(&self) -> &str {
+        "unpack_mono"
+    }
+
+    fn in_place(&self) -> bool {
+        false
+    }
+
+    fn process(&mut self, buf: &mut PayloadBuffer, _: &PayloadMetadata) -> StageResult {
+        let (bits, unpacked_format, packing) = match buf.image_info().map(|i| i.pixel_format) {
+            Some(PixelFormat::Mono10Packed) => (10, PixelFormat::Mono10, Packing::Gev),
+            Some(PixelFormat::Mono12Packed) => (12, PixelFormat::Mono12, Packing::Gev),
+            Some(PixelFormat::Mono10p) => (10, PixelFormat::Mono10, Packing::Lsb),
+            Some(PixelFormat::Mono12p) => (12, PixelFormat::Mono12, Packing::Lsb),
+            _ => {
+                // Passes through the data.
+                let (data, output) = buf.output();
+                output.extend_from_slice(data);
+                return Ok(());
+            }
+        };
+
+        let info = buf.image_info().unwrap();
+        let image_size = info.image_size;
+        // Unpacks complete pixels only if the payload is incomplete.
+        let complete_pixels = match packing {
+            // The first pixel of a pair can be unpacked from the first two bytes.
+            Packing::Gev => image_size / 3 * 2 + usize::from(image_size % 3 == 2),
+            Packing::Lsb => image_size * 8 / bits,
+        };
+        let len = (info.width * info.height).min(complete_pixels);
+        let (data, output) = buf.output();
+        output.reserve(len * 2 + data.len() - image_size);
+        for i in 0..len {
+            let pixel = match packing {
+                Packing::Gev => unpack_gev(&data[i / 2 * 3..], i % 2, bits),
+                Packing::Lsb => unpack_lsb(data, i * bits, bits),
+            };
+            output.extend_from_slice(&pixel.to_le_bytes());
+        }
+        output.extend_from_slice(&data[image_size..]);
+
+        let info = buf.image_info_mut().unwrap();
+        info.pixel_format = unpacked_format;
+        info.image_size = len * 2;
+        // Pixels are unpacked into contiguous rows.
+        info.x_padding = 0;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Packing {
+    /// Two pixels are packed into three bytes, the second byte holds the low bits of both pixels.
+    Gev,
+    /// Pixels are packed into a bit stream from the least significant bit.
+    Lsb,
+}
+
+/// Returns `index`th pixel of the two pixels packed in `bytes`.
+fn unpack_gev(bytes: &[u8], index: usize, bits: usize) -> u16 {
+    let low_bits = bits - 8;
+    let mask = (1 << low_bits) - 1;
+    let (high, low) = if index == 0 {
+        (bytes[0], bytes[1] & mask)
+    } else {
+        (bytes[2], (bytes[1] >> 4) & mask)
+    };
+    (u16::from(high) << low_bits) | u16::from(low)
+}
+
+/// Returns the pixel of `bits` bits starting at `offset` bits of `data`.
+fn unpack_lsb(data: &[u8], offset: usize, bits: usize) -> u16 {
+    let byte = offset / 8;
+    let shift = offset % 8;
+    let mut word = 0_u32;
+    // A pixel of 16 bits at most spans three bytes.
+    for (i, b) in data[byte..].iter().take(3).enumerate() {
+        word |= u32::from(*b) << (i * 8);
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    let pixel = ((word >> shift) & ((1 << bits) - 1)) as u16;
+    pixel
+}
+
+/// A built-in stage which flips the image upside down, e.g. for a camera mounted upside down.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FlipVertical;
+
+impl PipelineStage for FlipVertical {
+    fn name(&self) -> &str {
+        "flip_vertical"
+    }
+
+    fn in_place(&self) -> bool {
+        true
+    }
+
+    fn process(&mut self, buf: &mut PayloadBuffer, _: &PayloadMetadata) -> StageResult {
+        let height = match buf.image_info() {
+            Some(info) if info.height > 1 => info.height,
+            _ => return Ok(()),
+        };
+        let image = buf.image_mut().unwrap();
+        let stride = image.len() / height;
+        if stride * height != image.len() {
+            return Err(StageError::Failed(
+                format!(
+                    "image size {} is not a multiple of the height {}",
+                    image.len(),
+                    height
+                )
+                .into(),
+            ));
+        }
+
+        let (upper, lower) = image.split_at_mut(stride * (height / 2));
+        // The middle row of an odd height image stays.
+        let lower_start = lower.len() - upper.len();
+        for (top, bottom) in upper
+            .chunks_exact_mut(stride)
+            .zip(lower[lower_start..].chunks_exact_mut(stride).rev())
+        {
+            top.swap_with_slice(bottom);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::test_payload, *};
+
+    fn synthetic(pixel_format: PixelFormat, width: usize, height: usize, image: &[u8]) -> Payload {
+        let mut bytes = image.to_vec();
+        let valid_payload_size = bytes.len();
+        // Spare capacity of the receive buffer.
+        bytes.resize(valid_payload_size + 16, 0xff);
+
+        let image_info = ImageInfo {
+            width,
+            height,
+            x_offset: 0,
+            y_offset: 0,
+            pixel_format,
+            image_size: valid_payload_size,
+            x_padding: 0,
+        };
+        let mut payload = test_payload(PayloadType::Image, Some(image_info), bytes);
+        payload.valid_payload_size = valid_payload_size;
+        payload
+    }
+
+    fn runner(
+        stages: Vec<Box<dyn PipelineStage>>,
+    ) -> (PipelineRunner, Arc<Mutex<Vec<StageStatistics>>>) {
+        let statistics = Arc::default();
+        (
+            PipelineRunner::new(stages, Arc::clone(&statistics)),
+            statistics,
+        )
+    }
+
+    fn pixels(payload: &Payload) -> Vec<u16> {
+        payload
+            .image()
+            .unwrap()
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn test_unpack_mono() {
+        let (mut runner, _) = runner(vec![Box::new(UnpackMono)]);
+
+        // 0xabc and 0x123.
+        let mut payload = synthetic(PixelFormat::Mono12Packed, 2, 1, &[0xab, 0x3c, 0x12]);
+        runner.run(&mut payload).unwrap();
+        assert_eq!(pixels(&payload), [0xabc, 0x123]);
+        let info = payload.image_info().unwrap();
+        assert_eq!(info.pixel_format, PixelFormat::Mono12);
+        assert_eq!(info.image_size, 4);
+
+        // 0xabc and 0x123.
+        let mut payload = synthetic(PixelFormat::Mono12p, 2, 1, &[0xbc, 0x3a, 0x12]);
+        runner.run(&mut payload).unwrap();
+        assert_eq!(pixels(&payload), [0xabc, 0x123]);
+
+        // 0x3ff, 0x000, 0x2aa and 0x155.
+        let mut payload = synthetic(PixelFormat::Mono10p, 4, 1, &[0xff, 0x03, 0xa0, 0x6a, 0x55]);
+        runner.run(&mut payload).unwrap();
+        assert_eq!(pixels(&payload), [0x3ff, 0x000, 0x2aa, 0x155]);
+        assert_eq!(
+            payload.image_info().unwrap().pixel_format,
+            PixelFormat::Mono10
+        );
+
+        // Unmodeled formats are passed through.
+        let mut payload = synthetic(PixelFormat::Mono8, 2, 2, &[1, 2, 3, 4]);
+        runner.run(&mut payload).unwrap();
+        assert_eq!(payload.image().unwrap(), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_flip_vertical() {
+        let (mut runner, _) = runner(vec![Box::new(FlipVertical)]);
+
+        let mut payload = synthetic(PixelFormat::Mono8, 2, 3, &[1, 2, 3, 4, 5, 6]);
+        runner.run(&mut payload).unwrap();
+        assert_eq!(payload.image().unwrap(), &[5, 6, 3, 4, 1, 2]);
+
+        let mut payload = synthetic(PixelFormat::Mono8, 2, 4, &[1, 2, 3, 4, 5, 6, 7, 8]);
+        runner.run(&mut payload).unwrap();
+        assert_eq!(payload.image().unwrap(), &[7, 8, 5, 6, 3, 4, 1, 2]);
+
+        let mut payload = synthetic(PixelFormat::Mono8, 2, 3, &[1, 2, 3, 4, 5]);
+        assert!(matches!(
+            runner.run(&mut payload),
+            Err(StageError::Failed(_))
+        ));
+    }
+
+    #[test]
+    fn test_statistics() {
+        /// Rejects payloads of odd ids.
+        #[derive(Clone)]
+        struct RejectOdd;
+
+        impl PipelineStage for RejectOdd {
+            fn name(&self) -> &str {
+                "reject_odd"
+            }
+
+            fn in_place(&self) -> bool {
+                true
+            }
+
+            fn process(
+                &mut self,
+                _: &mut PayloadBuffer,
+                metadata: &PayloadMetadata,
+            ) -> StageResult {
+                if metadata.id % 2 == 1 {
+                    Err(StageError::Rejected("odd id".into()))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+
+        let (mut runner, statistics) = runner(vec![
+            Box::new(UnpackMono),
+            Box::new(RejectOdd),
+            Box::new(FlipVertical),
+        ]);
+
+        for id in 0..4 {
+            // 2x2 image of 0x100, 0x200, 0x300 and 0x400.
+            let mut payload = synthetic(
+                PixelFormat::Mono12Packed,
+                2,
+                2,
+                &[0x10, 0x00, 0x20, 0x30, 0x00, 0x40],
+            );
+            payload.id = id;
+            let res = runner.run(&mut payload);
+            if id % 2 == 1 {
+                assert!(matches!(res, Err(StageError::Rejected(_))));
+            } else {
+                res.unwrap();
+                assert_eq!(pixels(&payload), [0x300, 0x400, 0x100, 0x200]);
+                assert_eq!(payload.payload().len(), 8);
+            }
+        }
+
+        let statistics = statistics.lock().unwrap();
+        let names: Vec<_> = statistics.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["unpack_mono", "reject_odd", "flip_vertical"]);
+        let processed: Vec<_> = statistics.iter().map(|s| s.processed_payloads).collect();
+        assert_eq!(processed, [4, 4, 2]);
+        let rejected: Vec<_> = statistics.iter().map(|s| s.rejected_payloads).collect();
+        assert_eq!(rejected, [0, 2, 0]);
+        for stats in statistics.iter() {
+            assert_eq!(stats.failed_payloads, 0);
+            assert!(stats.max_time <= stats.total_time);
+            assert!(stats.mean_time() <= stats.max_time);
+        }
+    }
+}
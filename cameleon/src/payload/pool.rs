@@ -0,0 +1,356 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides [`BufferPool`], which pre-allocates payload buffers so that the
+//! streaming loop doesn't allocate a buffer for each payload.
+//!
+//! A buffer taken from the pool is held by the delivered [`Payload`], and returned to the pool
+//! when the payload is dropped.
+
+use std::{
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+};
+#[cfg(any(test, feature = "libusb"))]
+use std::{mem, time::Duration};
+
+#[cfg(doc)]
+use super::{FrameQueue, Payload};
+
+/// Behavior of the streaming loop when all buffers of [`BufferPool`] are held by payloads, or of
+/// [`FrameQueue`] when the queue is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Waits until a payload is dropped. The device may drop payloads while the loop waits.
+    #[default]
+    Block,
+    /// Drops the oldest payload not yet received by the application to reuse its buffer, and
+    /// waits as [`OverflowPolicy::Block`] if the application holds all payloads.
+    DropOldest,
+    /// Drops the newest payload. The streaming loop waits as [`OverflowPolicy::Block`], since
+    /// the payloads arriving meanwhile are left to the device, which drops them.
+    ///
+    /// [`FrameQueue`] drops the payload arriving while the queue is full instead.
+    DropNewest,
+}
+
+/// A pool of pre-allocated payload buffers, see [`StreamParams::buffer_count`].
+///
+/// [`StreamParams::buffer_count`]: crate::u3v::StreamParams::buffer_count
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    buffer_len: usize,
+    capacity: usize,
+    state: Mutex<PoolState>,
+    returned: Condvar,
+}
+
+struct PoolState {
+    free: Vec<Vec<u8>>,
+    max_in_use: usize,
+    exhausted: u64,
+}
+
+/// Occupancy of [`BufferPool`], which helps to tune the number of buffers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferPoolStatistics {
+    /// The number of buffers in the pool.
+    pub capacity: usize,
+    /// The number of buffers held by payloads or the streaming loop.
+    pub in_use: usize,
+    /// The largest number of buffers in use at once.
+    pub max_in_use: usize,
+    /// The number of times a buffer is requested while all buffers are in use.
+    pub exhausted: u64,
+}
+
+impl BufferPool {
+    /// Allocates `capacity` buffers of `buffer_len` bytes.
+    #[must_use]
+    pub fn new(capacity: usize, buffer_len: usize) -> Self {
+        let free = (0..capacity).map(|_| vec![0; buffer_len]).collect();
+        Self {
+            inner: Arc::new(PoolInner {
+                buffer_len,
+                capacity,
+                state: Mutex::new(PoolState {
+                    free,
+                    max_in_use: 0,
+                    exhausted: 0,
+                }),
+                returned: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Returns the number of buffers in the pool.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+
+    /// Returns the length of each buffer in bytes.
+    #[must_use]
+    pub fn buffer_len(&self) -> usize {
+        self.inner.buffer_len
+    }
+
+    /// Returns the current occupancy of the pool.
+    #[must_use]
+    pub fn statistics(&self) -> BufferPoolStatistics {
+        let state = self.lock();
+        BufferPoolStatistics {
+            capacity: self.inner.capacity,
+            in_use: self.inner.capacity - state.free.len(),
+            max_in_use: state.max_in_use,
+            exhausted: state.exhausted,
+        }
+    }
+
+    /// Takes a buffer without waiting, `None` is returned if all buffers are in use.
+    #[cfg(any(test, feature = "libusb"))]
+    pub(crate) fn try_take(&self) -> Option<PooledBuffer> {
+        let mut state = self.lock();
+        let buf = self.pop(&mut state);
+        if buf.is_none() {
+            state.exhausted += 1;
+        }
+        buf
+    }
+
+    /// Takes a buffer, waiting up to `timeout` for a buffer to be returned.
+    #[cfg(any(test, feature = "libusb"))]
+    pub(crate) fn take_timeout(&self, timeout: Duration) -> Option<PooledBuffer> {
+        let state = self.lock();
+        let (mut state, _) = self
+            .inner
+            .returned
+            .wait_timeout_while(state, timeout, |state| state.free.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+        self.pop(&mut state)
+    }
+
+    /// Returns `buf` to the pool, the buffer is resized to [`Self::buffer_len`] if it's been
+    /// truncated.
+    pub(crate) fn put(&self, mut buf: Vec<u8>) {
+        buf.resize(self.inner.buffer_len, 0);
+        let mut state = self.lock();
+        if state.free.len() < self.inner.capacity {
+            state.free.push(buf);
+            self.inner.returned.notify_one();
+        }
+    }
+
+    #[cfg(any(test, feature = "libusb"))]
+    fn pop(&self, state: &mut PoolState) -> Option<PooledBuffer> {
+        let buf = state.free.pop()?;
+        state.max_in_use = state.max_in_use.max(self.inner.capacity - state.free.len());
+        Some(PooledBuffer {
+            buf,
+            pool: Some(self.clone()),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        // The state is consistent at any point, so a poisoned lock is recovered.
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for BufferPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferPool")
+            .field("capacity", &self.inner.capacity)
+            .field("buffer_len", &self.inner.buffer_len)
+            .finish()
+    }
+}
+
+/// A buffer taken from [`BufferPool`], which is returned to the pool on drop.
+#[cfg(any(test, feature = "libusb"))]
+pub(crate) struct PooledBuffer {
+    buf: Vec<u8>,
+    /// `None` once the buffer is moved into [`Payload`].
+    pool: Option<BufferPool>,
+}
+
+#[cfg(any(test, feature = "libusb"))]
+impl PooledBuffer {
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        &self.buf
+    }
+
+    #[cfg(feature = "libusb")]
+    pub(crate) fn as_mut_slice(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+
+    /// Splits the buffer into the storage of [`Payload`], which returns the buffer on drop
+    /// instead.
+    pub(crate) fn into_parts(mut self) -> (Vec<u8>, PoolHandle) {
+        (mem::take(&mut self.buf), PoolHandle(self.pool.take()))
+    }
+
+    /// Reassembles the storage of [`Payload`] split by [`Self::into_parts`].
+    #[cfg(feature = "libusb")]
+    pub(crate) fn from_parts(buf: Vec<u8>, pool: BufferPool) -> Self {
+        Self {
+            buf,
+            pool: Some(pool),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "libusb"))]
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.put(mem::take(&mut self.buf));
+        }
+    }
+}
+
+/// The pool to which [`Payload`] returns its buffer on drop.
+///
+/// A clone of the payload owns a copy of the buffer, so the handle isn't inherited by clones.
+#[derive(Default)]
+pub(crate) struct PoolHandle(Option<BufferPool>);
+
+impl PoolHandle {
+    pub(crate) fn is_pooled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub(crate) fn take(&mut self) -> Option<BufferPool> {
+        self.0.take()
+    }
+}
+
+impl Clone for PoolHandle {
+    fn clone(&self) -> Self {
+        Self(None)
+    }
+}
+
+impl fmt::Debug for PoolHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PoolHandle")
+            .field(&self.is_pooled())
+            .finish()
+    }
+}
+
+/// Payloads are compared by their contents regardless of where the buffer comes from.
+impl PartialEq for PoolHandle {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for PoolHandle {}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{channel, test_payload, Payload, PayloadType},
+        *,
+    };
+
+    fn pooled_payload(pool: &BufferPool) -> Payload {
+        let (bytes, pool) = pool.try_take().unwrap().into_parts();
+        let mut payload = test_payload(PayloadType::Chunk, None, bytes);
+        payload.pool = pool;
+        payload
+    }
+
+    #[test]
+    fn test_take_and_return() {
+        let pool = BufferPool::new(2, 16);
+        let first = pool.try_take().unwrap();
+        assert_eq!(first.as_slice().len(), 16);
+        let second = pool.try_take().unwrap();
+        assert!(pool.try_take().is_none());
+        assert!(pool.take_timeout(Duration::from_millis(1)).is_none());
+        assert_eq!(
+            pool.statistics(),
+            BufferPoolStatistics {
+                capacity: 2,
+                in_use: 2,
+                max_in_use: 2,
+                exhausted: 1,
+            }
+        );
+
+        drop(first);
+        drop(second);
+        let stats = pool.statistics();
+        assert_eq!((stats.in_use, stats.max_in_use), (0, 2));
+    }
+
+    #[test]
+    fn test_payload_returns_buffer() {
+        let pool = BufferPool::new(1, 16);
+        let mut payload = pooled_payload(&pool);
+        payload.payload.truncate(4);
+
+        // A clone owns a copy of the buffer, which isn't returned to the pool.
+        let clone = payload.clone();
+        assert_eq!(clone, payload);
+        drop(clone);
+        assert!(pool.try_take().is_none());
+
+        payload.release();
+        let buf = pool.try_take().unwrap();
+        // The truncated buffer is restored to its length.
+        assert_eq!(buf.as_slice().len(), 16);
+        drop(buf);
+
+        // The data is copied so that the buffer stays in the pool.
+        let payload = pooled_payload(&pool);
+        assert_eq!(payload.into_vec().len(), 16);
+        assert_eq!(pool.statistics().in_use, 0);
+    }
+
+    #[test]
+    fn test_blocked_take_wakes_up() {
+        let pool = BufferPool::new(1, 16);
+        let buf = pool.try_take().unwrap();
+        let waiter = {
+            let pool = pool.clone();
+            std::thread::spawn(move || pool.take_timeout(Duration::from_secs(10)).is_some())
+        };
+        std::thread::sleep(Duration::from_millis(10));
+        drop(buf);
+        assert!(waiter.join().unwrap());
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let pool = BufferPool::new(2, 16);
+        let (tx, rx) = channel(2, 2);
+        tx.try_send(Ok(pooled_payload(&pool))).unwrap();
+        tx.try_send(Ok(pooled_payload(&pool))).unwrap();
+        assert!(pool.try_take().is_none());
+
+        // The oldest payload is dropped and its buffer is returned to the pool.
+        assert!(tx.drop_oldest());
+        assert!(pool.try_take().is_some());
+        let stats = rx.statistics();
+        assert_eq!((stats.num_delivered, stats.num_underrun), (1, 1));
+        assert!(rx.try_recv().is_ok());
+        assert!(!tx.drop_oldest());
+
+        // A payload sent back is returned to the pool instead of the channel.
+        rx.send_back(pooled_payload(&pool));
+        assert!(tx.try_recv().is_err());
+        assert_eq!(pool.statistics().in_use, 0);
+    }
+}
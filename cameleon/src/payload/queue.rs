@@ -0,0 +1,384 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides [`FrameQueue`], a bounded queue between [`PayloadReceiver`] and a
+//! consumer which may be slower than the device, e.g. an image processing pipeline.
+//!
+//! A thread forwards payloads from the receiver to the queue, and [`OverflowPolicy`] decides
+//! which payload is dropped while the queue is full.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::Duration,
+};
+
+use async_std::task;
+use tracing::debug;
+
+use super::{OverflowPolicy, Payload, PayloadReceiver, ReceiverStatistics};
+use crate::{StreamError, StreamResult};
+
+/// A bounded queue of payloads received from [`PayloadReceiver`].
+///
+/// A dropped payload is sent back to the receiver, so its buffer is returned to the pool
+/// immediately. It's counted in [`ReceiverStatistics::num_queue_dropped`] and
+/// [`HostStreamStatistics::dropped_payloads`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use cameleon::payload::{FrameQueue, OverflowPolicy};
+/// # let mut cameras = cameleon::u3v::enumerate_cameras().unwrap();
+/// # let mut camera = cameras.pop().unwrap();
+/// # camera.open().unwrap();
+/// # camera.load_context().unwrap();
+///
+/// let payload_rx = camera.start_streaming(3).unwrap();
+/// let queue = FrameQueue::new(payload_rx, 8, OverflowPolicy::DropOldest);
+/// let payload = queue.recv_timeout(Duration::from_secs(1)).unwrap();
+/// // Process the payload.
+/// queue.send_back(payload);
+///
+/// // Keep the latest payloads from now on.
+/// queue.set_policy(OverflowPolicy::DropNewest);
+/// ```
+///
+/// [`HostStreamStatistics::dropped_payloads`]: crate::u3v::HostStreamStatistics::dropped_payloads
+pub struct FrameQueue {
+    shared: Arc<Shared>,
+    /// `None` once the thread is joined.
+    forwarder: Option<thread::JoinHandle<()>>,
+}
+
+struct Shared {
+    capacity: usize,
+    receiver: PayloadReceiver,
+    state: Mutex<QueueState>,
+    /// Notified when an item is pushed or the queue is closed.
+    pushed: Condvar,
+    /// Notified when an item is popped, the policy is changed, or the queue is closed.
+    popped: Condvar,
+}
+
+struct QueueState {
+    items: VecDeque<StreamResult<Payload>>,
+    policy: OverflowPolicy,
+    /// `true` once the receiver is disconnected or the queue is dropped.
+    closed: bool,
+}
+
+impl FrameQueue {
+    /// Creates a queue holding up to `capacity` payloads from `receiver`, the full queue is
+    /// handled according to `policy`.
+    ///
+    /// The receiver is closed when the queue is dropped, which stops the streaming loop unless
+    /// another clone of the receiver is alive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is `0`, or the forwarding thread fails to be spawned.
+    #[must_use]
+    pub fn new(receiver: PayloadReceiver, capacity: usize, policy: OverflowPolicy) -> Self {
+        assert!(capacity > 0, "capacity of `FrameQueue` must be positive");
+        let shared = Arc::new(Shared {
+            capacity,
+            receiver,
+            state: Mutex::new(QueueState {
+                items: VecDeque::with_capacity(capacity),
+                policy,
+                closed: false,
+            }),
+            pushed: Condvar::new(),
+            popped: Condvar::new(),
+        });
+
+        let forwarder = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("cameleon-frame-queue".into())
+                .spawn(move || shared.forward())
+                .expect("failed to spawn the forwarding thread of `FrameQueue`")
+        };
+        Self {
+            shared,
+            forwarder: Some(forwarder),
+        }
+    }
+
+    /// Receives a payload without waiting.
+    ///
+    /// # Errors
+    ///
+    /// [`StreamError::ReceiveError`] is returned if the queue is empty, or the receiver is
+    /// disconnected and all queued payloads have been received. An error sent from the
+    /// streaming loop is returned as is.
+    pub fn try_recv(&self) -> StreamResult<Payload> {
+        let mut state = self.shared.lock();
+        self.shared.pop(&mut state)
+    }
+
+    /// Receives a payload, waiting up to `timeout` for a payload to arrive.
+    ///
+    /// # Errors
+    ///
+    /// [`StreamError::Timeout`] is returned if no payload arrives in time, other errors are
+    /// the same as [`Self::try_recv`].
+    pub fn recv_timeout(&self, timeout: Duration) -> StreamResult<Payload> {
+        let state = self.shared.lock();
+        let (mut state, result) = self
+            .shared
+            .pushed
+            .wait_timeout_while(state, timeout, |state| {
+                state.items.is_empty() && !state.closed
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+        if result.timed_out() {
+            return Err(StreamError::Timeout);
+        }
+        self.shared.pop(&mut state)
+    }
+
+    /// Sends back `payload` to reuse its buffer, see [`PayloadReceiver::send_back`].
+    pub fn send_back(&self, payload: Payload) {
+        self.shared.receiver.send_back(payload);
+    }
+
+    /// Switches the policy applied while the queue is full, which takes effect immediately.
+    pub fn set_policy(&self, policy: OverflowPolicy) {
+        self.shared.lock().policy = policy;
+        // Lets the forwarding thread blocked by `OverflowPolicy::Block` apply the new policy.
+        self.shared.popped.notify_all();
+    }
+
+    /// Returns the policy applied while the queue is full.
+    #[must_use]
+    pub fn policy(&self) -> OverflowPolicy {
+        self.shared.lock().policy
+    }
+
+    /// Returns the number of payloads which the queue holds up to.
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// Returns the number of queued payloads and errors.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.shared.lock().items.len()
+    }
+
+    /// Returns `true` if nothing is queued.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns [`ReceiverStatistics`] of the underlying receiver.
+    #[must_use]
+    pub fn statistics(&self) -> ReceiverStatistics {
+        self.shared.receiver.statistics()
+    }
+}
+
+impl Drop for FrameQueue {
+    fn drop(&mut self) {
+        self.shared.close();
+        // Wakes the forwarding thread waiting for a payload.
+        self.shared.receiver.rx.close();
+        if let Some(forwarder) = self.forwarder.take() {
+            forwarder.join().ok();
+        }
+    }
+}
+
+impl Shared {
+    /// Forwards payloads from the receiver until the receiver is disconnected or the queue is
+    /// closed.
+    fn forward(&self) {
+        while let Ok(item) = task::block_on(self.receiver.rx.recv()) {
+            if !self.push(item) {
+                break;
+            }
+        }
+        self.close();
+    }
+
+    /// Pushes `item` according to the policy, returns `false` if the queue is closed.
+    fn push(&self, item: StreamResult<Payload>) -> bool {
+        let mut state = self.lock();
+        loop {
+            if state.closed {
+                // Dropping the payload returns its buffer to the pool.
+                return false;
+            }
+            if state.items.len() < self.capacity {
+                state.items.push_back(item);
+                self.pushed.notify_one();
+                return true;
+            }
+
+            match state.policy {
+                OverflowPolicy::Block => {
+                    state = self
+                        .popped
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                OverflowPolicy::DropOldest => {
+                    debug!("frame queue is full, drop the oldest payload");
+                    let oldest = state.items.pop_front();
+                    self.discard(oldest.unwrap());
+                }
+                OverflowPolicy::DropNewest => {
+                    debug!("frame queue is full, drop the newest payload");
+                    self.discard(item);
+                    return true;
+                }
+            }
+        }
+    }
+
+    fn pop(&self, state: &mut QueueState) -> StreamResult<Payload> {
+        match state.items.pop_front() {
+            Some(item) => {
+                self.popped.notify_one();
+                item
+            }
+            None if state.closed => Err(StreamError::ReceiveError("frame queue is closed".into())),
+            None => Err(StreamError::ReceiveError("frame queue is empty".into())),
+        }
+    }
+
+    /// Drops `item`, the buffer of a payload is returned to the pool.
+    fn discard(&self, item: StreamResult<Payload>) {
+        if let Ok(payload) = item {
+            self.receiver.counters.queue_dropped();
+            self.receiver.send_back(payload);
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.pushed.notify_all();
+        self.popped.notify_all();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        // The state is consistent at any point, so a poisoned lock is recovered.
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{channel, test_payload, BufferPool, PayloadSender, PayloadType},
+        *,
+    };
+
+    fn pooled_payload(pool: &BufferPool, id: u64) -> Payload {
+        let (bytes, pool) = pool.try_take().unwrap().into_parts();
+        let mut payload = test_payload(PayloadType::Chunk, None, bytes);
+        payload.id = id;
+        payload.pool = pool;
+        payload
+    }
+
+    /// Sends payloads of `ids` and waits until the queue forwards all of them.
+    fn send(tx: &PayloadSender, pool: &BufferPool, queue: &FrameQueue, ids: &[u64]) {
+        for &id in ids {
+            tx.try_send(Ok(pooled_payload(pool, id))).unwrap();
+        }
+        while !tx.queued.is_empty() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        // The last payload may be still in the hand of the forwarding thread.
+        thread::sleep(Duration::from_millis(10));
+        assert!(queue.len() <= queue.capacity());
+    }
+
+    fn recv_ids(queue: &FrameQueue) -> Vec<u64> {
+        let mut ids = vec![];
+        while let Ok(payload) = queue.try_recv() {
+            ids.push(payload.id());
+        }
+        ids
+    }
+
+    #[test]
+    fn test_drop_oldest_and_newest() {
+        let pool = BufferPool::new(8, 16);
+        let (tx, rx) = channel(8, 8);
+        let queue = FrameQueue::new(rx, 2, OverflowPolicy::DropOldest);
+
+        send(&tx, &pool, &queue, &[0, 1, 2, 3]);
+        assert_eq!(recv_ids(&queue), vec![2, 3]);
+        // The buffers of the dropped payloads are returned to the pool immediately.
+        assert_eq!(pool.statistics().in_use, 0);
+        assert_eq!(queue.statistics().num_queue_dropped, 2);
+
+        // The policy is switched while streaming.
+        queue.set_policy(OverflowPolicy::DropNewest);
+        send(&tx, &pool, &queue, &[4, 5, 6]);
+        assert_eq!(recv_ids(&queue), vec![4, 5]);
+        assert_eq!(queue.statistics().num_queue_dropped, 3);
+        assert_eq!(pool.statistics().in_use, 0);
+    }
+
+    #[test]
+    fn test_block() {
+        let pool = BufferPool::new(8, 16);
+        let (tx, rx) = channel(8, 8);
+        let queue = FrameQueue::new(rx, 1, OverflowPolicy::Block);
+
+        send(&tx, &pool, &queue, &[0, 1]);
+        // The second payload waits until the first one is received.
+        assert_eq!(queue.len(), 1);
+        let payload = queue.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(payload.id(), 0);
+        let payload = queue.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(payload.id(), 1);
+        assert!(matches!(
+            queue.recv_timeout(Duration::from_millis(1)),
+            Err(StreamError::Timeout)
+        ));
+        assert_eq!(queue.statistics().num_queue_dropped, 0);
+
+        // A blocked payload is dropped once the policy is switched.
+        send(&tx, &pool, &queue, &[2, 3]);
+        queue.set_policy(OverflowPolicy::DropOldest);
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(recv_ids(&queue), vec![3]);
+        assert_eq!(queue.statistics().num_queue_dropped, 1);
+    }
+
+    #[test]
+    fn test_disconnected() {
+        let pool = BufferPool::new(2, 16);
+        let (tx, rx) = channel(2, 2);
+        let queue = FrameQueue::new(rx, 2, OverflowPolicy::Block);
+        send(&tx, &pool, &queue, &[0]);
+        drop(tx);
+
+        // Queued payloads are still received after the sender is dropped.
+        assert_eq!(queue.recv_timeout(Duration::from_secs(1)).unwrap().id(), 0);
+        assert!(matches!(
+            queue.recv_timeout(Duration::from_secs(1)),
+            Err(StreamError::ReceiveError(_))
+        ));
+
+        // Dropping the queue returns the buffers of the queued payloads.
+        let (tx, rx) = channel(2, 2);
+        let queue = FrameQueue::new(rx, 2, OverflowPolicy::Block);
+        send(&tx, &pool, &queue, &[1]);
+        drop(queue);
+        assert_eq!(pool.statistics().in_use, 0);
+        assert!(tx.is_closed());
+    }
+}
@@ -0,0 +1,228 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides [`ReceiverStatistics`], which counts payloads delivered through
+//! [`PayloadReceiver`].
+//!
+//! The counters are updated with atomics by the streaming loop, so reading them never blocks
+//! the loop.
+
+use std::{
+    convert::TryFrom,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+
+#[cfg(doc)]
+use super::{FrameQueue, OverflowPolicy, Payload, PayloadReceiver, ReceiverConfig};
+
+/// Weight of the latest interval in the moving average of the delivery interval, as a shift.
+const INTERVAL_WEIGHT_SHIFT: u32 = 3;
+
+/// Statistics of payloads delivered to [`PayloadReceiver`], see
+/// [`PayloadReceiver::statistics`].
+///
+/// The names follow the `STREAM_INFO_*` commands of GenTL.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReceiverStatistics {
+    /// The number of payloads delivered to the receiver, including incomplete ones.
+    pub num_delivered: u64,
+    /// The number of payloads dropped because no buffer or channel slot is available.
+    pub num_underrun: u64,
+    /// The number of delivered payloads which are incomplete, see [`Payload::is_incomplete`].
+    pub num_incomplete: u64,
+    /// The number of incomplete payloads dropped instead of being delivered, see
+    /// [`ReceiverConfig::deliver_incomplete`].
+    pub num_dropped_incomplete: u64,
+    /// The number of payloads dropped by [`FrameQueue`] according to its [`OverflowPolicy`].
+    pub num_queue_dropped: u64,
+    /// The number of times the streaming loop resynchronizes to the next leader after receiving
+    /// data without a leader.
+    pub num_resyncs: u64,
+    /// The number of valid payload bytes delivered.
+    pub num_bytes: u64,
+    /// Moving average of the delivery rate in payloads per second, `0.0` until two payloads are
+    /// delivered.
+    ///
+    /// The rate decays while no payload is delivered.
+    pub frame_rate: f64,
+}
+
+/// Counters of [`ReceiverStatistics`] shared between the sender and the receiver.
+#[derive(Debug)]
+pub(crate) struct ReceiverCounters {
+    delivered: AtomicU64,
+    underrun: AtomicU64,
+    incomplete: AtomicU64,
+    dropped_incomplete: AtomicU64,
+    queue_dropped: AtomicU64,
+    resyncs: AtomicU64,
+    bytes: AtomicU64,
+    /// Nanoseconds from `epoch` to the last delivery plus one, `0` if no payload is delivered.
+    last_delivery: AtomicU64,
+    /// Moving average of the delivery interval in nanoseconds, `0` if unknown.
+    interval: AtomicU64,
+    epoch: Instant,
+}
+
+impl ReceiverCounters {
+    pub(crate) fn new() -> Self {
+        Self {
+            delivered: AtomicU64::new(0),
+            underrun: AtomicU64::new(0),
+            incomplete: AtomicU64::new(0),
+            dropped_incomplete: AtomicU64::new(0),
+            queue_dropped: AtomicU64::new(0),
+            resyncs: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            last_delivery: AtomicU64::new(0),
+            interval: AtomicU64::new(0),
+            epoch: Instant::now(),
+        }
+    }
+
+    /// Accounts a payload of `valid_payload_size` bytes as delivered.
+    ///
+    /// The delivery interval is updated without compare-and-swap since a stream has a single
+    /// sender thread.
+    pub(crate) fn delivered(&self, valid_payload_size: usize, is_incomplete: bool) {
+        self.delivered.fetch_add(1, Ordering::Relaxed);
+        self.bytes
+            .fetch_add(valid_payload_size as u64, Ordering::Relaxed);
+        if is_incomplete {
+            self.incomplete.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let now = self.nanos_since_epoch() + 1;
+        let last = self.last_delivery.swap(now, Ordering::Relaxed);
+        if last != 0 {
+            let interval = now.saturating_sub(last);
+            let average = match self.interval.load(Ordering::Relaxed) {
+                0 => interval,
+                average if interval >= average => {
+                    average + ((interval - average) >> INTERVAL_WEIGHT_SHIFT)
+                }
+                average => average - ((average - interval) >> INTERVAL_WEIGHT_SHIFT),
+            };
+            self.interval.store(average.max(1), Ordering::Relaxed);
+        }
+    }
+
+    /// Reverts [`Self::delivered`] for a payload dropped before the host receives it.
+    #[cfg(any(test, feature = "libusb"))]
+    pub(crate) fn undeliver(&self, valid_payload_size: usize, is_incomplete: bool) {
+        self.delivered.fetch_sub(1, Ordering::Relaxed);
+        self.bytes
+            .fetch_sub(valid_payload_size as u64, Ordering::Relaxed);
+        if is_incomplete {
+            self.incomplete.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn underrun(&self) {
+        self.underrun.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn dropped_incomplete(&self) {
+        self.dropped_incomplete.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn queue_dropped(&self) {
+        self.queue_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns [`ReceiverStatistics::num_queue_dropped`].
+    pub(crate) fn num_queue_dropped(&self) -> u64 {
+        self.queue_dropped.load(Ordering::Relaxed)
+    }
+
+    #[cfg(any(test, feature = "libusb"))]
+    pub(crate) fn resync(&self) {
+        self.resyncs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> ReceiverStatistics {
+        let interval = self.interval.load(Ordering::Relaxed);
+        let frame_rate = if interval == 0 {
+            0.0
+        } else {
+            let last = self.last_delivery.load(Ordering::Relaxed);
+            let elapsed = self.nanos_since_epoch().saturating_sub(last);
+            #[allow(clippy::cast_precision_loss)]
+            {
+                1e9 / interval.max(elapsed) as f64
+            }
+        };
+
+        ReceiverStatistics {
+            num_delivered: self.delivered.load(Ordering::Relaxed),
+            num_underrun: self.underrun.load(Ordering::Relaxed),
+            num_incomplete: self.incomplete.load(Ordering::Relaxed),
+            num_dropped_incomplete: self.dropped_incomplete.load(Ordering::Relaxed),
+            num_queue_dropped: self.num_queue_dropped(),
+            num_resyncs: self.resyncs.load(Ordering::Relaxed),
+            num_bytes: self.bytes.load(Ordering::Relaxed),
+            frame_rate,
+        }
+    }
+
+    /// Resets all counters, updates racing with the reset may be lost.
+    pub(crate) fn reset(&self) {
+        for counter in &[
+            &self.delivered,
+            &self.underrun,
+            &self.incomplete,
+            &self.dropped_incomplete,
+            &self.queue_dropped,
+            &self.resyncs,
+            &self.bytes,
+            &self.last_delivery,
+            &self.interval,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn nanos_since_epoch(&self) -> u64 {
+        u64::try_from(self.epoch.elapsed().as_nanos()).unwrap_or(u64::MAX - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let counters = ReceiverCounters::new();
+        counters.delivered(16, false);
+        assert!(counters.snapshot().frame_rate.abs() < f64::EPSILON);
+        std::thread::sleep(Duration::from_millis(10));
+        counters.delivered(8, true);
+        std::thread::sleep(Duration::from_millis(10));
+        counters.delivered(4, false);
+        // The last payload is dropped before the host receives it.
+        counters.undeliver(4, false);
+        counters.underrun();
+        counters.dropped_incomplete();
+        counters.queue_dropped();
+        counters.resync();
+
+        let stats = counters.snapshot();
+        assert_eq!(stats.num_delivered, 2);
+        assert_eq!(stats.num_underrun, 1);
+        assert_eq!(stats.num_incomplete, 1);
+        assert_eq!(stats.num_dropped_incomplete, 1);
+        assert_eq!(stats.num_queue_dropped, 1);
+        assert_eq!(stats.num_resyncs, 1);
+        assert_eq!(stats.num_bytes, 24);
+        // Less than 100 fps as the interval is at least 10ms.
+        assert!(stats.frame_rate > 0.0 && stats.frame_rate <= 100.0);
+
+        counters.reset();
+        assert_eq!(counters.snapshot(), ReceiverStatistics::default());
+    }
+}
@@ -11,39 +11,352 @@
 use std::{
     collections::VecDeque,
     convert::TryInto,
+    num::NonZeroUsize,
+    ops::Range,
     ptr::NonNull,
-    sync::atomic::{AtomicBool, Ordering::SeqCst},
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 
 use cameleon_device::u3v::ReceiveChannel;
+use cameleon_impl::leak_check::{Resource, Tracked};
 use rusb::UsbContext;
 use thiserror::Error;
+use tracing::warn;
 
 use crate::{StreamError, StreamResult};
 
+use super::fairness::{StreamSlot, SCHEDULER};
+
+/// The longest time [`poll_completed`] waits for events before checking [`CancelHandle`].
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The time after which [`AsyncPool`] warns that its cancelled transfers aren't reaped yet, it
+/// keeps waiting for them after the warning.
+const REAP_WARNING_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A token to interrupt [`AsyncPool::poll`] from another thread, e.g. a ctrl-C handler.
+///
+/// Cancelling the handle doesn't cancel pending transfers, it makes the polling return
+/// promptly instead of waiting out its timeout. The transfers are cancelled by
+/// [`AsyncPool::cancel_all`] or when the pool is dropped, which blocks until they're reaped.
+///
+/// A cancelled handle stays cancelled, a new handle is needed to poll again.
+#[derive(Debug, Clone, Default)]
+pub(super) struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    /// Requests the polling to return, which results in an error of [`AsyncPool::poll`].
+    pub(super) fn cancel(&self) {
+        self.0.store(true, SeqCst);
+    }
+
+    /// Returns `true` if [`Self::cancel`] has been called on any clone of the handle.
+    pub(super) fn is_cancelled(&self) -> bool {
+        self.0.load(SeqCst)
+    }
+}
+
+/// A completed bulk-in transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Completion {
+    /// Number of bytes written into the buffer.
+    pub(super) len: usize,
+    /// `true` if the device sent more data than the buffer can hold. The transfer is truncated
+    /// to the buffer size in that case.
+    pub(super) overflowed: bool,
+}
+
+/// A buffer which bulk-in transfers write into.
+///
+/// [`AsyncPool`] owns the buffer while its transfers are pending, so the buffer is neither freed
+/// nor accessed by the caller before libusb is done with it, even if the pool is leaked.
+///
+/// # Safety
+///
+/// The bytes returned by [`TransferBuf::as_mut_slice`] must stay at the same address while the
+/// buffer is moved, e.g. they are in a heap allocation owned by the buffer.
+pub(super) unsafe trait TransferBuf: Default {
+    /// Returns the whole buffer.
+    fn as_mut_slice(&mut self) -> &mut [u8];
+}
+
+// Safety: the bytes are in the heap allocation of the vector.
+unsafe impl TransferBuf for Vec<u8> {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+/// A bulk-in endpoint from which stream packets are read.
+pub(super) trait BulkIn {
+    /// Reads a transfer into `buf[range]`.
+    fn read<B: TransferBuf>(
+        &mut self,
+        buf: &mut B,
+        range: Range<usize>,
+        timeout: Duration,
+    ) -> StreamResult<Completion>;
+
+    /// Reads transfers into `ranges` of `buf` in order, keeping up to `num_transfers` transfers
+    /// outstanding. All transfers are outstanding at once if `num_transfers` is `None`.
+    ///
+    /// `completions` is cleared, then the completion of each range is pushed in order.
+    fn read_all<B: TransferBuf>(
+        &mut self,
+        buf: &mut B,
+        ranges: &[Range<usize>],
+        timeout: Duration,
+        _num_transfers: Option<NonZeroUsize>,
+        completions: &mut Vec<Completion>,
+    ) -> StreamResult<()> {
+        completions.clear();
+        for range in ranges {
+            completions.push(self.read(buf, range.clone(), timeout)?);
+        }
+        Ok(())
+    }
+}
+
+impl BulkIn for ReceiveChannel {
+    fn read<B: TransferBuf>(
+        &mut self,
+        buf: &mut B,
+        range: Range<usize>,
+        timeout: Duration,
+    ) -> StreamResult<Completion> {
+        with_pool(self, None, None, buf, |pool| pool.read(range, timeout))
+    }
+
+    fn read_all<B: TransferBuf>(
+        &mut self,
+        buf: &mut B,
+        ranges: &[Range<usize>],
+        timeout: Duration,
+        num_transfers: Option<NonZeroUsize>,
+        completions: &mut Vec<Completion>,
+    ) -> StreamResult<()> {
+        with_pool(self, None, None, buf, |pool| {
+            StreamLoop::new(pool, num_transfers).read_all(ranges, timeout, completions)
+        })
+    }
+}
+
+/// The receive channels of an emulated device, so that the loops reading them are tested against
+/// the device emulator.
+#[cfg(all(test, feature = "emulator"))]
+impl BulkIn for cameleon_device::emulator::ReceiveChannel {
+    fn read<B: TransferBuf>(
+        &mut self,
+        buf: &mut B,
+        range: Range<usize>,
+        timeout: Duration,
+    ) -> StreamResult<Completion> {
+        use cameleon_device::u3v::{Error, LibUsbError};
+
+        let buf = &mut buf.as_mut_slice()[range];
+        match self.recv(buf, timeout) {
+            Ok(len) => Ok(Completion {
+                len,
+                overflowed: false,
+            }),
+            Err(Error::LibUsb(LibUsbError::Overflow)) => Ok(Completion {
+                len: buf.len(),
+                overflowed: true,
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// A receive channel of an emulated device whose reads return promptly once `cancel` is
+/// cancelled, as the reads of [`ScheduledChannel`] do.
+#[cfg(all(test, feature = "emulator"))]
+pub(super) struct CancellableChannel<'a> {
+    channel: &'a mut cameleon_device::emulator::ReceiveChannel,
+    cancel: &'a CancelHandle,
+}
+
+#[cfg(all(test, feature = "emulator"))]
+impl<'a> CancellableChannel<'a> {
+    pub(super) fn new(
+        channel: &'a mut cameleon_device::emulator::ReceiveChannel,
+        cancel: &'a CancelHandle,
+    ) -> Self {
+        Self { channel, cancel }
+    }
+}
+
+#[cfg(all(test, feature = "emulator"))]
+impl<'a> BulkIn for CancellableChannel<'a> {
+    fn read<B: TransferBuf>(
+        &mut self,
+        buf: &mut B,
+        range: Range<usize>,
+        timeout: Duration,
+    ) -> StreamResult<Completion> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.cancel.is_cancelled() {
+                return Err(AsyncError::Aborted.into());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self
+                .channel
+                .read(buf, range.clone(), remaining.min(CANCEL_CHECK_INTERVAL))
+            {
+                Err(StreamError::Timeout) if remaining > CANCEL_CHECK_INTERVAL => {}
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A receive channel whose transfers are scheduled fairly with the other streams, see
+/// [`super::fairness`].
+///
+/// Reads return promptly once `cancel` is cancelled.
+pub(super) struct ScheduledChannel<'a> {
+    channel: &'a ReceiveChannel,
+    slot: &'a Arc<StreamSlot>,
+    cancel: &'a CancelHandle,
+}
+
+impl<'a> ScheduledChannel<'a> {
+    pub(super) fn new(
+        channel: &'a ReceiveChannel,
+        slot: &'a Arc<StreamSlot>,
+        cancel: &'a CancelHandle,
+    ) -> Self {
+        Self {
+            channel,
+            slot,
+            cancel,
+        }
+    }
+}
+
+impl<'a> BulkIn for ScheduledChannel<'a> {
+    fn read<B: TransferBuf>(
+        &mut self,
+        buf: &mut B,
+        range: Range<usize>,
+        timeout: Duration,
+    ) -> StreamResult<Completion> {
+        with_pool(
+            self.channel,
+            Some(self.slot),
+            Some(self.cancel),
+            buf,
+            |pool| pool.read(range, timeout),
+        )
+    }
+
+    fn read_all<B: TransferBuf>(
+        &mut self,
+        buf: &mut B,
+        ranges: &[Range<usize>],
+        timeout: Duration,
+        num_transfers: Option<NonZeroUsize>,
+        completions: &mut Vec<Completion>,
+    ) -> StreamResult<()> {
+        with_pool(
+            self.channel,
+            Some(self.slot),
+            Some(self.cancel),
+            buf,
+            |pool| StreamLoop::new(pool, num_transfers).read_all(ranges, timeout, completions),
+        )
+    }
+}
+
+/// Moves `buf` into [`AsyncPool`] while `f` runs transfers, and moves it back once all the
+/// transfers are reaped.
+///
+/// `buf` is left as the default value if `f` panics, the buffer is dropped with the pool then.
+fn with_pool<'a, B: TransferBuf, T>(
+    device: &'a ReceiveChannel,
+    slot: Option<&'a Arc<StreamSlot>>,
+    cancel: Option<&CancelHandle>,
+    buf: &mut B,
+    f: impl FnOnce(&mut AsyncPool<'a, B>) -> StreamResult<T>,
+) -> StreamResult<T> {
+    let mut pool = AsyncPool::new(device, std::mem::take(buf));
+    pool.slot = slot;
+    if let Some(cancel) = cancel {
+        pool.cancel = cancel.clone();
+    }
+    let result = f(&mut pool);
+    *buf = pool.into_buf();
+    result
+}
+
 /// Represents a pool of asynchronous transfers, that can be polled to completion.
-pub(super) struct AsyncPool<'a> {
+///
+/// The pool owns the buffer which its transfers write into, and each transfer is filled with a
+/// range of the buffer.
+pub(super) struct AsyncPool<'a, B: TransferBuf> {
     device: &'a ReceiveChannel,
     pending: VecDeque<AsyncTransfer>,
+    /// Slot of the stream if the transfers are scheduled fairly with the other streams.
+    slot: Option<&'a Arc<StreamSlot>>,
+    /// Interrupts [`Self::poll`].
+    cancel: CancelHandle,
+    /// The buffer is dropped after [`Drop`] of the pool reaps all pending transfers.
+    buf: B,
+    /// Head of `buf`, which is obtained once so that `buf` isn't accessed while transfers are
+    /// pending.
+    data: NonNull<u8>,
+    len: usize,
 }
 
-impl<'a> AsyncPool<'a> {
-    pub(super) fn new(device: &'a ReceiveChannel) -> Self {
+impl<'a, B: TransferBuf> AsyncPool<'a, B> {
+    pub(super) fn new(device: &'a ReceiveChannel, mut buf: B) -> Self {
+        let slice = buf.as_mut_slice();
+        let len = slice.len();
+        // The pointer of an empty slice is dangling but non-null, no transfer is filled with it.
+        let data = NonNull::new(slice.as_mut_ptr()).unwrap();
         Self {
             device,
             pending: VecDeque::new(),
+            slot: None,
+            cancel: CancelHandle::default(),
+            buf,
+            data,
+            len,
         }
     }
 
-    pub(super) fn submit(&mut self, buf: &mut [u8]) -> StreamResult<()> {
+    fn read(&mut self, range: Range<usize>, timeout: Duration) -> StreamResult<Completion> {
+        self.submit(range)?;
+        self.poll(timeout)
+    }
+
+    /// Submits a transfer which writes into `range` of the buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of the buffer.
+    pub(super) fn submit(&mut self, range: Range<usize>) -> StreamResult<()> {
+        assert!(range.start <= range.end && range.end <= self.len);
+        if let Some(slot) = self.slot {
+            SCHEDULER.throttle(slot);
+        }
+
         // Safety: If transfer is submitted, it is pushed onto `pending` where it will be
-        // dropped before `device` is freed.
+        // dropped before `device` is freed. `range` is in the buffer, which is owned by the pool
+        // and dropped only after `pending` is reaped. If the pool is leaked, the buffer is
+        // leaked with it.
         unsafe {
             let mut transfer = AsyncTransfer::new_bulk(
                 self.device.device_handle.as_raw(),
                 self.device.iface_info.bulk_in_ep,
-                buf,
+                self.data.as_ptr().add(range.start),
+                range.len(),
+                self.slot.cloned(),
             );
             transfer.submit()?;
             self.pending.push_back(transfer);
@@ -51,14 +364,31 @@ impl<'a> AsyncPool<'a> {
         }
     }
 
-    pub(super) fn poll(&mut self, timeout: Duration) -> StreamResult<usize> {
+    /// Waits for the oldest pending transfer to complete.
+    ///
+    /// Returns an error without waiting out `timeout` once the [`CancelHandle`] of the pool is
+    /// cancelled, the transfer is kept pending then.
+    pub(super) fn poll(&mut self, timeout: Duration) -> StreamResult<Completion> {
+        let cancel = self.cancel.clone();
+        self.poll_with(timeout, Some(&cancel))
+    }
+
+    fn poll_with(
+        &mut self,
+        timeout: Duration,
+        cancel: Option<&CancelHandle>,
+    ) -> StreamResult<Completion> {
         let next = self.pending.front().ok_or(AsyncError::NoTransfersPending)?;
         if poll_completed(
             self.device.device_handle.context(),
             timeout,
             next.completed_flag(),
+            cancel,
         )? {
             let mut transfer = self.pending.pop_front().unwrap();
+            if let Some(slot) = self.slot {
+                SCHEDULER.reaped(slot, Instant::now());
+            }
             Ok(transfer.handle_completed()?)
         } else {
             Err(AsyncError::Timeout.into())
@@ -83,49 +413,139 @@ impl<'a> AsyncPool<'a> {
     pub(super) fn is_empty(&self) -> bool {
         self.pending() == 0
     }
-}
 
-impl<'a> Drop for AsyncPool<'a> {
-    fn drop(&mut self) {
+    /// Cancels and reaps all pending transfers, and returns the buffer.
+    pub(super) fn into_buf(mut self) -> B {
+        self.reap_all();
+        std::mem::take(&mut self.buf)
+    }
+
+    /// Cancels and reaps all pending transfers.
+    ///
+    /// Cancelled transfers complete as soon as events are handled, which the calling thread does
+    /// itself unless another thread holds the event lock. Transfer callbacks take no lock other
+    /// than the queue of [`StreamSlot`], which is never held while waiting, so the event handling
+    /// never waits on a lock held by the calling thread, see [`super::StreamHandle`].
+    ///
+    /// Blocks until every transfer is reaped even if [`CancelHandle`] is cancelled, since freeing
+    /// the buffer of a pending transfer is undefined behavior. A warning is logged once the
+    /// reaping takes longer than [`REAP_WARNING_TIMEOUT`].
+    fn reap_all(&mut self) {
         self.cancel_all();
+        let start = Instant::now();
+        let mut warned = false;
         while !self.is_empty() {
-            self.poll(Duration::from_secs(1)).ok();
+            if !warned && start.elapsed() >= REAP_WARNING_TIMEOUT {
+                warn!(
+                    pending = self.pending(),
+                    "cancelled transfers aren't reaped in time, keep waiting for them"
+                );
+                warned = true;
+            }
+            self.poll_with(REAP_WARNING_TIMEOUT, None).ok();
+        }
+    }
+}
+
+/// Keeps up to `num_transfers` transfers of [`AsyncPool`] outstanding, and submits the next
+/// range as each transfer completes.
+///
+/// Outstanding transfers let the device send data without waiting for the host between
+/// transfers, while limiting them bounds the memory pinned by the host controller.
+pub(super) struct StreamLoop<'p, 'a, B: TransferBuf> {
+    pool: &'p mut AsyncPool<'a, B>,
+    num_transfers: usize,
+}
+
+impl<'p, 'a, B: TransferBuf> StreamLoop<'p, 'a, B> {
+    /// All transfers are outstanding at once if `num_transfers` is `None`.
+    pub(super) fn new(pool: &'p mut AsyncPool<'a, B>, num_transfers: Option<NonZeroUsize>) -> Self {
+        Self {
+            pool,
+            num_transfers: num_transfers.map_or(usize::MAX, NonZeroUsize::get),
         }
     }
+
+    fn read_all(
+        self,
+        ranges: &[Range<usize>],
+        timeout: Duration,
+        completions: &mut Vec<Completion>,
+    ) -> StreamResult<()> {
+        completions.clear();
+        let mut ranges = ranges.iter().cloned();
+        for range in ranges.by_ref().take(self.num_transfers) {
+            self.pool.submit(range)?;
+        }
+
+        while !self.pool.is_empty() {
+            completions.push(self.pool.poll(timeout)?);
+            if let Some(range) = ranges.next() {
+                self.pool.submit(range)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, B: TransferBuf> Drop for AsyncPool<'a, B> {
+    /// Cancels and reaps all pending transfers before the buffer is dropped.
+    fn drop(&mut self) {
+        self.reap_all();
+    }
 }
 
 struct AsyncTransfer {
     ptr: NonNull<libusb1_sys::libusb_transfer>,
+    _tracked: Tracked,
+}
+
+/// User data of a transfer.
+struct TransferState {
+    completed: AtomicBool,
+    /// Slot of the stream which is notified in the event handler when the transfer completes.
+    slot: Option<Arc<StreamSlot>>,
 }
 
 impl AsyncTransfer {
-    /// Invariant: Caller must ensure `device` outlives this transfer.
+    /// Invariant: Caller must ensure `device` and `len` bytes from `buffer` outlive this
+    /// transfer, and the bytes aren't accessed until the transfer is reaped.
+    #[track_caller]
     unsafe fn new_bulk(
         device: *mut libusb1_sys::libusb_device_handle,
         endpoint: u8,
-        buffer: &mut [u8],
+        buffer: *mut u8,
+        len: usize,
+        slot: Option<Arc<StreamSlot>>,
     ) -> Self {
         // non-isochronous endpoints (e.g. control, bulk, interrupt) specify a value of 0
         // This is step 1 of async API
         let ptr = libusb1_sys::libusb_alloc_transfer(0);
         let ptr = NonNull::new(ptr).expect("Could not allocate transfer!");
 
-        let user_data = Box::into_raw(Box::new(AtomicBool::new(false))).cast::<libc::c_void>();
+        let state = TransferState {
+            completed: AtomicBool::new(false),
+            slot,
+        };
+        let user_data = Box::into_raw(Box::new(state)).cast::<libc::c_void>();
 
-        let length = buffer.len() as libc::c_int;
+        let length = len as libc::c_int;
 
         libusb1_sys::libusb_fill_bulk_transfer(
             ptr.as_ptr(),
             device,
             endpoint,
-            buffer.as_ptr() as *mut u8,
+            buffer,
             length,
             Self::transfer_cb,
             user_data,
             0,
         );
 
-        Self { ptr }
+        Self {
+            ptr,
+            _tracked: Tracked::new(Resource::AsyncTransfer),
+        }
     }
 
     //// Part of step 4 of async API the transfer is finished being handled when
@@ -136,11 +556,14 @@ impl AsyncTransfer {
         // because it is freed only with the transfer.
         // After the store to completed, these may no longer be valid if
         // the polling thread freed it after seeing it completed.
-        let completed = unsafe {
+        let state = unsafe {
             let transfer = &mut *transfer;
-            &*transfer.user_data.cast::<AtomicBool>()
+            &*transfer.user_data.cast::<TransferState>()
         };
-        completed.store(true, SeqCst);
+        if let Some(slot) = &state.slot {
+            slot.serviced(Instant::now());
+        }
+        state.completed.store(true, SeqCst);
     }
 
     fn transfer(&self) -> &libusb1_sys::libusb_transfer {
@@ -150,7 +573,7 @@ impl AsyncTransfer {
 
     fn completed_flag(&self) -> &AtomicBool {
         // Safety: transfer and user_data remain valid as long as self
-        unsafe { &*self.transfer().user_data.cast::<AtomicBool>() }
+        unsafe { &(*self.transfer().user_data.cast::<TransferState>()).completed }
     }
 
     // Step 3 of async API
@@ -166,16 +589,28 @@ impl AsyncTransfer {
         }
     }
 
-    fn handle_completed(&mut self) -> StreamResult<usize> {
+    fn handle_completed(&mut self) -> StreamResult<Completion> {
         assert!(self
             .completed_flag()
             .load(std::sync::atomic::Ordering::Relaxed));
         use libusb1_sys::constants::*;
-        let err = match self.transfer().status {
+        let transfer = self.transfer();
+        debug_assert!(transfer.length >= transfer.actual_length);
+        let len = transfer.actual_length as usize;
+        let err = match transfer.status {
             LIBUSB_TRANSFER_COMPLETED => {
-                let transfer = self.transfer();
-                debug_assert!(transfer.length >= transfer.actual_length);
-                return Ok(transfer.actual_length as usize);
+                return Ok(Completion {
+                    len,
+                    overflowed: false,
+                })
+            }
+            // The device sent more data than the buffer can hold, the bytes which fit in the
+            // buffer are still valid.
+            LIBUSB_TRANSFER_OVERFLOW => {
+                return Ok(Completion {
+                    len,
+                    overflowed: true,
+                })
             }
             LIBUSB_TRANSFER_CANCELLED => AsyncError::Cancelled,
             LIBUSB_TRANSFER_ERROR => AsyncError::Other,
@@ -184,7 +619,6 @@ impl AsyncTransfer {
             }
             LIBUSB_TRANSFER_STALL => AsyncError::Stall,
             LIBUSB_TRANSFER_NO_DEVICE => AsyncError::Disconnected,
-            LIBUSB_TRANSFER_OVERFLOW => AsyncError::Overflow,
             _ => unreachable!(),
         };
         Err(err.into())
@@ -195,6 +629,10 @@ impl AsyncTransfer {
 impl Drop for AsyncTransfer {
     fn drop(&mut self) {
         unsafe {
+            // `user_data` isn't freed by libusb.
+            drop(Box::from_raw(
+                self.transfer().user_data.cast::<TransferState>(),
+            ));
             libusb1_sys::libusb_free_transfer(self.ptr.as_ptr());
         }
     }
@@ -205,45 +643,75 @@ impl Drop for AsyncTransfer {
 /// without the events lock held. It also continues polling until completion,
 /// timeout, or error, instead of potentially returning early.
 ///
+/// Events are waited for up to [`CANCEL_CHECK_INTERVAL`] at a time, so
+/// [`AsyncError::Aborted`] is returned promptly once `cancel` is cancelled.
+///
 /// This design is based on
 /// https://libusb.sourceforge.io/api-1.0/libusb_mtasync.html#threadwait
 fn poll_completed(
     ctx: &impl UsbContext,
     timeout: Duration,
     completed: &AtomicBool,
-) -> StreamResult<bool> {
-    use libusb1_sys::{constants::*, *};
+    cancel: Option<&CancelHandle>,
+) -> Result<bool, AsyncError> {
+    poll_with(timeout, completed, cancel, |remaining| {
+        handle_events(ctx, remaining, completed)
+    })
+}
 
+/// Polling loop of [`poll_completed`], `wait` waits for events up to the given duration and
+/// returns a libusb error code.
+fn poll_with(
+    timeout: Duration,
+    completed: &AtomicBool,
+    cancel: Option<&CancelHandle>,
+    mut wait: impl FnMut(Duration) -> i32,
+) -> Result<bool, AsyncError> {
     let deadline = Instant::now() + timeout;
+    let is_cancelled = || cancel.is_some_and(CancelHandle::is_cancelled);
+
+    let mut err = 0;
+    while err == 0 && !completed.load(SeqCst) && deadline > Instant::now() {
+        if is_cancelled() {
+            return Err(AsyncError::Aborted);
+        }
+        let remaining = deadline
+            .saturating_duration_since(Instant::now())
+            .min(CANCEL_CHECK_INTERVAL);
+        err = wait(remaining);
+    }
+
+    match err {
+        0 => Ok(completed.load(SeqCst)),
+        libusb1_sys::constants::LIBUSB_ERROR_TIMEOUT => Ok(false),
+        e => Err(AsyncError::from_libusb_error(e).unwrap_err()),
+    }
+}
+
+/// Handles events of `ctx` or waits for another thread handling them, up to `timeout`.
+fn handle_events(ctx: &impl UsbContext, timeout: Duration, completed: &AtomicBool) -> i32 {
+    use libusb1_sys::*;
+
+    let timeval = libc::timeval {
+        tv_sec: timeout.as_secs().try_into().unwrap(),
+        tv_usec: timeout.subsec_micros().into(),
+    };
 
     unsafe {
         let mut err = 0;
-        while err == 0 && !completed.load(SeqCst) && deadline > Instant::now() {
-            let remaining = deadline.saturating_duration_since(Instant::now());
-            let timeval = libc::timeval {
-                tv_sec: remaining.as_secs().try_into().unwrap(),
-                tv_usec: remaining.subsec_micros().try_into().unwrap(),
-            };
-
-            if libusb_try_lock_events(ctx.as_raw()) == 0 {
-                if !completed.load(SeqCst) && libusb_event_handling_ok(ctx.as_raw()) != 0 {
-                    err = libusb_handle_events_locked(ctx.as_raw(), &timeval as *const _);
-                }
-                libusb_unlock_events(ctx.as_raw());
-            } else {
-                libusb_lock_event_waiters(ctx.as_raw());
-                if !completed.load(SeqCst) && libusb_event_handler_active(ctx.as_raw()) != 0 {
-                    libusb_wait_for_event(ctx.as_raw(), &timeval as *const _);
-                }
-                libusb_unlock_event_waiters(ctx.as_raw());
+        if libusb_try_lock_events(ctx.as_raw()) == 0 {
+            if !completed.load(SeqCst) && libusb_event_handling_ok(ctx.as_raw()) != 0 {
+                err = libusb_handle_events_locked(ctx.as_raw(), &timeval as *const _);
             }
+            libusb_unlock_events(ctx.as_raw());
+        } else {
+            libusb_lock_event_waiters(ctx.as_raw());
+            if !completed.load(SeqCst) && libusb_event_handler_active(ctx.as_raw()) != 0 {
+                libusb_wait_for_event(ctx.as_raw(), &timeval as *const _);
+            }
+            libusb_unlock_event_waiters(ctx.as_raw());
         }
-
-        match err {
-            0 => Ok(completed.load(SeqCst)),
-            LIBUSB_ERROR_TIMEOUT => Ok(false),
-            e => Err(AsyncError::from_libusb_error(e).unwrap_err().into()),
-        }
+        err
     }
 }
 
@@ -257,6 +725,8 @@ enum AsyncError {
     Disconnected,
     #[error("transfer was cancelled")]
     Cancelled,
+    #[error("polling was aborted by the cancel handle")]
+    Aborted,
     #[error("input/output error")]
     Io,
     #[error("invalid parameter")]
@@ -311,7 +781,86 @@ impl From<AsyncError> for StreamError {
     fn from(err: AsyncError) -> Self {
         match err {
             AsyncError::Disconnected => Self::Disconnected,
+            AsyncError::Timeout => Self::Timeout,
             _ => StreamError::Io(err.into()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// Event source which never delivers an event, it sleeps out each wait like libusb does
+    /// when no transfer completes.
+    fn idle(remaining: Duration) -> i32 {
+        thread::sleep(remaining);
+        0
+    }
+
+    #[test]
+    fn test_cancel_poll() {
+        let completed = AtomicBool::new(false);
+        let cancel = CancelHandle::default();
+
+        let canceller = {
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                cancel.cancel();
+                Instant::now()
+            })
+        };
+        let result = poll_with(Duration::from_secs(10), &completed, Some(&cancel), idle);
+        let returned = Instant::now();
+        let cancelled = canceller.join().unwrap();
+        assert!(matches!(result, Err(AsyncError::Aborted)));
+        assert!(returned.saturating_duration_since(cancelled) < Duration::from_millis(100));
+
+        // A cancelled handle returns without waiting.
+        let start = Instant::now();
+        let result = poll_with(Duration::from_secs(10), &completed, Some(&cancel), |_| {
+            panic!("waited for events after cancellation")
+        });
+        assert!(matches!(result, Err(AsyncError::Aborted)));
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        // A completed transfer takes precedence over the cancellation.
+        completed.store(true, SeqCst);
+        assert!(poll_with(Duration::from_secs(10), &completed, Some(&cancel), idle).unwrap());
+    }
+
+    #[test]
+    fn test_poll_completion_and_timeout() {
+        // The transfer completes while events are handled.
+        let completed = AtomicBool::new(false);
+        let mut waits = 0;
+        let result = poll_with(Duration::from_secs(10), &completed, None, |_| {
+            waits += 1;
+            if waits == 3 {
+                completed.store(true, SeqCst);
+            }
+            0
+        });
+        assert!(result.unwrap());
+        assert_eq!(waits, 3);
+
+        // Each wait is bounded, so that the cancellation is checked.
+        let completed = AtomicBool::new(false);
+        let start = Instant::now();
+        let result = poll_with(Duration::from_millis(50), &completed, None, |remaining| {
+            assert!(remaining <= CANCEL_CHECK_INTERVAL);
+            idle(remaining)
+        });
+        assert!(!result.unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // libusb errors are propagated.
+        let result = poll_with(Duration::from_secs(10), &completed, None, |_| {
+            libusb1_sys::constants::LIBUSB_ERROR_NO_DEVICE
+        });
+        assert!(matches!(result, Err(AsyncError::NoDevice)));
+    }
+}
@@ -0,0 +1,169 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains the control channel which [`ControlHandle`] talks to the device through,
+//! and the stream channel which [`StreamHandle`] receives payloads from.
+//!
+//! The channels are the `libusb` channels of a physical device. In tests, the channels of an
+//! emulated device are used instead, so that the handles are tested against the device emulator.
+//!
+//! [`ControlHandle`]: super::ControlHandle
+//! [`StreamHandle`]: super::StreamHandle
+
+use std::{num::NonZeroUsize, ops::Range, time::Duration};
+
+#[cfg(all(test, feature = "emulator"))]
+use cameleon_device::emulator;
+use cameleon_device::u3v;
+
+use crate::{ControlResult, StreamResult};
+
+use super::{
+    async_read::{BulkIn, Completion, TransferBuf},
+    pipeline::Transport,
+};
+
+pub(super) enum ControlChannel {
+    Usb(u3v::ControlChannel),
+    #[cfg(all(test, feature = "emulator"))]
+    Emulated(emulator::ControlChannel),
+}
+
+pub(super) enum StreamChannel {
+    Usb(u3v::ReceiveChannel),
+    #[cfg(all(test, feature = "emulator"))]
+    Emulated(emulator::ReceiveChannel),
+}
+
+macro_rules! delegate {
+    ($self:ident, $channel:ident => $expr:expr) => {
+        match $self {
+            Self::Usb($channel) => $expr,
+            #[cfg(all(test, feature = "emulator"))]
+            Self::Emulated($channel) => $expr,
+        }
+    };
+}
+
+impl ControlChannel {
+    pub(super) fn open(&mut self) -> u3v::Result<()> {
+        delegate!(self, channel => channel.open())
+    }
+
+    pub(super) fn close(&mut self) -> u3v::Result<()> {
+        delegate!(self, channel => channel.close())
+    }
+
+    pub(super) fn is_opened(&self) -> bool {
+        delegate!(self, channel => channel.is_opened())
+    }
+
+    pub(super) fn set_auto_detach_kernel_driver(&mut self, enable: bool) -> u3v::Result<()> {
+        match self {
+            Self::Usb(channel) => channel.set_auto_detach_kernel_driver(enable),
+            // The emulated device isn't bound to any kernel driver.
+            #[cfg(all(test, feature = "emulator"))]
+            Self::Emulated(_) => Ok(()),
+        }
+    }
+
+    pub(super) fn send(&self, buf: &[u8], timeout: Duration) -> u3v::Result<usize> {
+        delegate!(self, channel => channel.send(buf, timeout))
+    }
+
+    pub(super) fn recv(&self, buf: &mut [u8], timeout: Duration) -> u3v::Result<usize> {
+        delegate!(self, channel => channel.recv(buf, timeout))
+    }
+
+    pub(super) fn set_halt(&self, timeout: Duration) -> u3v::Result<()> {
+        delegate!(self, channel => channel.set_halt(timeout))
+    }
+
+    pub(super) fn clear_halt(&mut self) -> u3v::Result<()> {
+        delegate!(self, channel => channel.clear_halt())
+    }
+
+    /// Clears the halt of the endpoints and flushes the stale acks, returns the number of
+    /// flushed acks.
+    pub(super) fn recover(&mut self) -> u3v::Result<usize> {
+        match self {
+            Self::Usb(channel) => channel.recover(),
+            #[cfg(all(test, feature = "emulator"))]
+            Self::Emulated(channel) => {
+                channel.clear_halt()?;
+                let mut buf = vec![0; 1024];
+                let mut flushed = 0;
+                loop {
+                    match channel.recv(&mut buf, Duration::from_millis(10)) {
+                        Ok(_) => flushed += 1,
+                        Err(u3v::Error::LibUsb(u3v::LibUsbError::Timeout)) => return Ok(flushed),
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Transport for ControlChannel {
+    fn send(&mut self, buf: &[u8], timeout: Duration) -> ControlResult<()> {
+        ControlChannel::send(self, buf, timeout)?;
+        Ok(())
+    }
+
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> ControlResult<usize> {
+        Ok(ControlChannel::recv(self, buf, timeout)?)
+    }
+}
+
+impl StreamChannel {
+    pub(super) fn open(&mut self) -> u3v::Result<()> {
+        delegate!(self, channel => channel.open())
+    }
+
+    pub(super) fn close(&mut self) -> u3v::Result<()> {
+        delegate!(self, channel => channel.close())
+    }
+
+    pub(super) fn is_opened(&self) -> bool {
+        delegate!(self, channel => channel.is_opened())
+    }
+
+    pub(super) fn set_auto_detach_kernel_driver(&mut self, enable: bool) -> u3v::Result<()> {
+        match self {
+            Self::Usb(channel) => channel.set_auto_detach_kernel_driver(enable),
+            // The emulated device isn't bound to any kernel driver.
+            #[cfg(all(test, feature = "emulator"))]
+            Self::Emulated(_) => Ok(()),
+        }
+    }
+
+    pub(super) fn clear_halt(&mut self) -> u3v::Result<()> {
+        delegate!(self, channel => channel.clear_halt())
+    }
+}
+
+impl BulkIn for StreamChannel {
+    fn read<B: TransferBuf>(
+        &mut self,
+        buf: &mut B,
+        range: Range<usize>,
+        timeout: Duration,
+    ) -> StreamResult<Completion> {
+        delegate!(self, channel => channel.read(buf, range, timeout))
+    }
+
+    fn read_all<B: TransferBuf>(
+        &mut self,
+        buf: &mut B,
+        ranges: &[Range<usize>],
+        timeout: Duration,
+        num_transfers: Option<NonZeroUsize>,
+        completions: &mut Vec<Completion>,
+    ) -> StreamResult<()> {
+        delegate!(self, channel => {
+            channel.read_all(buf, ranges, timeout, num_transfers, completions)
+        })
+    }
+}
@@ -6,20 +6,38 @@
 
 use std::{
     convert::TryInto,
-    io::Read,
+    ops::Range,
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use cameleon_device::{
     u3v,
     u3v::protocol::{ack, cmd},
 };
-use tracing::error;
-
-use super::register_map::{self, Abrm, ManifestTable, Sbrm, Sirm};
+use cameleon_impl::leak_check::{Resource, Tracked};
+use tracing::{debug, error, warn};
+
+use super::{
+    channel::ControlChannel,
+    in_flight::InFlight,
+    open_options::{OpenOptions, DEFAULT_PIPELINE_DEPTH, DEFAULT_RETRY_COUNT},
+    open_registry::{OpenGuard, OpenRegistry},
+    pipeline::{Pipeline, PipelinedRead},
+    register_map::{self, Abrm, Eirm, ManifestEntry, ManifestTable, Sbrm, Sirm},
+    retry::{self, RetryPolicy},
+    Guid,
+};
 
-use crate::{camera::DeviceControl, genapi::CompressionType, ControlError, ControlResult};
+use crate::{
+    camera::DeviceControl,
+    checked_address,
+    genapi::CompressionType,
+    latency::{LatencyRecorder, LatencyReport, TransactionKind},
+    limits::Limits,
+    load_options::{self, GenApiFile},
+    metrics, ControlError, ControlResult,
+};
 
 /// Initial timeout duration for transaction between device and host.
 /// This value is temporarily used until the device's bootstrap register value is read.
@@ -35,6 +53,33 @@ const INITIAL_MAXIMUM_ACK_LENGTH: u32 = 128;
 
 const PAYLOAD_TRANSFER_SIZE: u32 = 1024 * 64;
 
+/// Length of the prefix and ccd of command and ack packets.
+const PACKET_HEADER_LENGTH: usize = 4 + 8;
+
+/// Length of the address, reserved and length fields of each entry of stacked command scd.
+const STACKED_ENTRY_HEADER_LENGTH: usize = 12;
+
+/// Length of each entry of `WriteMemStacked` ack scd.
+const STACKED_WRITE_ACK_ENTRY_LENGTH: usize = 4;
+
+/// Length of the address field of `WriteMem` command scd.
+const WRITE_MEM_ADDRESS_LENGTH: usize = 8;
+
+/// Alignment of the chunks of a read or write split by the maximum packet length.
+const CHUNK_ALIGNMENT: usize = 4;
+
+/// The maximum number of stale acks discarded while waiting for the ack of a command.
+const MAX_STALE_ACKS: usize = 8;
+
+/// Duration [`PendingTransaction::try_complete`] waits for an ack on the channel.
+const TRY_COMPLETE_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Default tag of the opener, see [`ControlHandle::set_open_tag`].
+pub(super) const DEFAULT_OPEN_TAG: &str = "cameleon-control-handle";
+
+/// Devices opened in the process.
+static OPEN_REGISTRY: OpenRegistry<Mutex<ControlHandle>> = OpenRegistry::new();
+
 /// This handle provides low level API to read and write data from the device.  
 /// See [`ControlHandle::abrm`] and [`register_map`](super::register_map) which provide more
 /// convenient way to communicate with `u3v` specific registers.
@@ -64,12 +109,20 @@ const PAYLOAD_TRANSFER_SIZE: u32 = 1024 * 64;
 /// camera.ctrl.read(address, &mut buffer).unwrap();
 /// ```
 pub struct ControlHandle {
-    inner: u3v::ControlChannel,
+    inner: ControlChannel,
     config: ConnectionConfig,
+    /// Limits on the values claimed by the device.
+    limits: Limits,
     /// Request id of the next packet.
     next_req_id: u16,
     /// Buffer for serializing/deserializing a packet.
     buffer: Vec<u8>,
+    /// Latency histograms of transactions, also registered in [`metrics`].
+    latency: Arc<LatencyRecorder>,
+    /// Commands sent by [`SharedControlHandle::begin_transaction`] whose acks aren't collected.
+    in_flight: InFlight,
+    /// Decoder of device specific status codes.
+    status_decoder: Arc<dyn ack::StatusDecoder + Send + Sync>,
 
     /// Device information.
     info: u3v::DeviceInfo,
@@ -80,8 +133,29 @@ pub struct ControlHandle {
     sbrm: Option<Sbrm>,
     /// Cache for `Sirm`.
     sirm: Option<Sirm>,
+    /// Cache for `Eirm`.
+    eirm: Option<Eirm>,
     /// Cache for `ManifestTable`.
     manifest_table: Option<ManifestTable>,
+
+    /// Tag reported to other openers in the process while the handle is opened.
+    open_tag: String,
+    /// Unregisters the device from the process wide registry when dropped.
+    open_guard: Option<OpenGuard<Mutex<ControlHandle>>>,
+    /// Accounts the channel as opened.
+    tracked: Option<Tracked>,
+}
+
+macro_rules! unwrap_or_log {
+    ($expr:expr) => {{
+        match $expr {
+            Ok(v) => v,
+            Err(error) => {
+                error!(?error);
+                return Err(error.into());
+            }
+        }
+    }};
 }
 
 impl ControlHandle {
@@ -115,10 +189,56 @@ impl ControlHandle {
     /// requests in a single call. In that case, Timeout is reflected to each request.
     ///
     /// In normal use case, no need to modify timeout duration.
+    #[deprecated(note = "use `OpenOptions::timeout_duration` with `ControlHandle::open_with`")]
     pub fn set_timeout_duration(&mut self, duration: Duration) {
         self.config.timeout_duration = duration;
     }
 
+    /// Set the default timeout of each transaction between device, which is used unless a
+    /// per-call timeout is given, e.g. [`ControlHandle::read_mem_timeout`].
+    ///
+    /// `MAXIMUM DEVICE RESPONSE TIME` of [`Abrm`] is the lower bound of the timeout, so a shorter
+    /// timeout is raised to it with a warning. The timeout is kept even if the handle is
+    /// re-opened.
+    pub fn set_default_timeout(&mut self, timeout: Duration) {
+        self.config.requested_timeout = Some(timeout);
+        self.config.timeout_duration = self.effective_timeout(timeout);
+    }
+
+    /// Reads data from the device like [`DeviceControl::read`], but waits up to `timeout` for
+    /// each transaction instead of the default timeout.
+    ///
+    /// This is useful for registers which legitimately take long to answer, e.g. file access.
+    /// `timeout` is bounded below like [`ControlHandle::set_default_timeout`].
+    ///
+    /// # Errors
+    /// [`ControlError::Timeout`] is returned if the device doesn't answer within `timeout`.
+    pub fn read_mem_timeout(
+        &mut self,
+        address: u64,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> ControlResult<()> {
+        self.with_timeout(timeout, |handle| handle.read(address, buf))
+    }
+
+    /// Writes data to the device like [`DeviceControl::write`], but waits up to `timeout` for
+    /// each transaction instead of the default timeout.
+    ///
+    /// This is useful for registers which legitimately take long to answer, e.g. saving a user
+    /// set. `timeout` is bounded below like [`ControlHandle::set_default_timeout`].
+    ///
+    /// # Errors
+    /// [`ControlError::Timeout`] is returned if the device doesn't answer within `timeout`.
+    pub fn write_mem_timeout(
+        &mut self,
+        address: u64,
+        data: &[u8],
+        timeout: Duration,
+    ) -> ControlResult<()> {
+        self.with_timeout(timeout, |handle| handle.write(address, data))
+    }
+
     /// The value determines how many times to retry when pending acknowledge is returned from the
     /// device.
     #[must_use]
@@ -128,44 +248,209 @@ impl ControlHandle {
 
     /// Set the value determines how many times to retry when pending acknowledge is returned from the
     /// device.
+    #[deprecated(note = "use `OpenOptions::retry_count` with `ControlHandle::open_with`")]
     pub fn set_retry_count(&mut self, count: u16) {
         self.config.retry_count = count;
     }
 
+    /// Recovers the control channel whose command and ack went out of sync, e.g. after an aborted
+    /// transfer stalled the endpoints.
+    ///
+    /// The halt of the endpoints is cleared and the stale acks left in the ack endpoint are
+    /// flushed. Commands in flight are forgotten, so their acks are discarded even if they
+    /// arrive later.
+    ///
+    /// Transactions call this automatically once when they fail with a stalled endpoint.
+    pub fn recover(&mut self) -> ControlResult<()> {
+        unwrap_or_log!(self.assert_open());
+        self.in_flight.clear();
+        // Acks arriving later have older request ids, so they are dropped as stale ones.
+        self.next_req_id = self.next_req_id.wrapping_add(1);
+        match self.inner.recover() {
+            Ok(flushed) => {
+                debug!(flushed, "recovered the control channel");
+                Ok(())
+            }
+            Err(err) => {
+                error!(%err, "failed to recover the control channel");
+                Err(err.into())
+            }
+        }
+    }
+
+    /// Returns [`RetryPolicy`] applied to transactions rejected by the device with a retryable
+    /// status.
+    #[must_use]
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.config.retry_policy
+    }
+
+    /// Set [`RetryPolicy`] applied to transactions rejected by the device with a retryable
+    /// status.
+    ///
+    /// Transactions of [`ControlHandle::read_pipelined`] and [`SharedControlHandle::begin_transaction`]
+    /// are
+    /// not retried.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.config.retry_policy = policy;
+    }
+
+    /// The maximum number of commands outstanding in [`ControlHandle::read_pipelined`].
+    ///
+    /// The depth falls back to `1` once the device turns out not to handle pipelined commands.
+    #[must_use]
+    pub fn pipeline_depth(&self) -> u16 {
+        self.config.pipeline_depth
+    }
+
+    /// Returns [`Limits`] on the values claimed by the device.
+    #[must_use]
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Set [`Limits`] on the values claimed by the device.
+    ///
+    /// In normal use case, no need to modify limits.
+    #[deprecated(note = "use `OpenOptions::limits` with `ControlHandle::open_with`")]
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Returns latency histograms of the successful transactions since the handle is created or
+    /// [`ControlHandle::reset_latency_report`] is called.
+    ///
+    /// NOTE: [`ControlHandle::read`] and [`ControlHandle::write`] may send multiple
+    /// requests in a single call. In that case, each request is recorded as a transaction.
+    #[must_use]
+    pub fn latency_report(&self) -> LatencyReport {
+        self.latency.report()
+    }
+
+    /// Clears latency histograms returned by [`ControlHandle::latency_report`].
+    pub fn reset_latency_report(&self) {
+        self.latency.reset();
+    }
+
     /// Returns the device info of the handle.
     pub fn device_info(&self) -> &u3v::DeviceInfo {
         &self.info
     }
 
+    /// Returns the tag reported to other openers in the process, see
+    /// [`ControlHandle::set_open_tag`].
+    #[must_use]
+    pub fn open_tag(&self) -> &str {
+        &self.open_tag
+    }
+
+    /// Set the tag which describes the purpose of the handle.
+    ///
+    /// While the handle is opened, opening the same device with another handle in the process
+    /// fails with [`ControlError::AlreadyOpenInProcess`] which contains the tag.
+    pub fn set_open_tag(&mut self, tag: impl Into<String>) {
+        self.open_tag = tag.into();
+        if let Some(guard) = &self.open_guard {
+            guard.set_tag(&self.open_tag);
+        }
+    }
+
+    /// Sets the decoder of device specific status codes, which gives their meanings to the errors
+    /// returned when the device answers with such a status.
+    ///
+    /// [`ack::DefaultStatusDecoder`] is used by default, which keeps the raw code only.
+    pub fn set_status_decoder(&mut self, decoder: Arc<dyn ack::StatusDecoder + Send + Sync>) {
+        self.status_decoder = decoder;
+    }
+
+    /// Opens the handle, then applies `options`.
+    ///
+    /// The options are applied even if the handle is already opened, except
+    /// [`OpenOptions::detach_kernel_driver`] which takes effect from the next opening. The timeout
+    /// of `options` is used by the transactions while opening too, and replaces the timeout set by
+    /// the previous options, i.e. `None` restores the maximum device response time.
+    pub fn open_with(&mut self, options: &OpenOptions) -> ControlResult<()> {
+        self.config.apply(options);
+        self.limits = options.limits;
+        self.set_open_tag(options.open_tag.clone());
+        if self.is_opened() {
+            self.apply_heartbeat()?;
+        } else {
+            self.open()?;
+        }
+        // The timeout is applied again after opening so that it's bounded below by the maximum
+        // device response time.
+        self.config.timeout_duration = match options.timeout_duration {
+            Some(timeout_duration) => self.effective_timeout(timeout_duration),
+            None => self.cached_abrm()?.maximum_device_response_time(),
+        };
+        Ok(())
+    }
+
     /// Returns [`Abrm`].
+    ///
+    /// `Abrm` is read when the handle is opened and cached afterwards, so this method doesn't
+    /// communicate with the device. Use [`Self::refresh_abrm`] to re-read the registers which
+    /// may be modified by another host, e.g. `USER DEFINED NAME`.
     pub fn abrm(&mut self) -> ControlResult<Abrm> {
-        if let Some(abrm) = self.abrm {
-            return Ok(abrm);
-        }
-        let abrm = Abrm::new(self)?;
-        self.abrm = Some(abrm);
+        self.cached_abrm().cloned()
+    }
 
+    /// Re-reads the mutable registers of the cached [`Abrm`] and returns it.
+    ///
+    /// See [`Abrm::refresh`] for the registers which are re-read.
+    pub fn refresh_abrm(&mut self) -> ControlResult<Abrm> {
+        let mut abrm = self.abrm()?;
+        abrm.refresh(self)?;
+        self.abrm = Some(abrm.clone());
         Ok(abrm)
     }
 
+    /// Sets `USER DEFINED NAME` of the device and updates the cached [`Abrm`] and
+    /// [`Self::device_info`].
+    ///
+    /// See [`Abrm::set_user_defined_name`] for the constraints on `name` and the errors.
+    pub fn set_user_defined_name(&mut self, name: &str) -> ControlResult<()> {
+        let mut abrm = self.abrm()?;
+        abrm.set_user_defined_name(self, name)?;
+        self.abrm = Some(abrm);
+        self.info.user_defined_name = Some(name.into());
+        Ok(())
+    }
+
     /// Returns [`Sbrm`].
     pub fn sbrm(&mut self) -> ControlResult<Sbrm> {
         if let Some(sbrm) = self.sbrm {
             return Ok(sbrm);
         }
-        let addr = self.abrm()?.sbrm_address(self)?;
+        let addr = self.cached_abrm()?.sbrm_address();
         let sbrm = Sbrm::new(self, addr)?;
         self.sbrm = Some(sbrm);
         Ok(sbrm)
     }
 
-    /// Returns [`Sirm`].
+    /// Returns [`Sirm`], which is located by `SIRM ADDRESS` register of [`Sbrm`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use cameleon::u3v;
+    /// # let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// # let mut camera = cameras.pop().unwrap();
+    /// # camera.open().unwrap();
+    /// let ctrl = &mut camera.ctrl;
+    /// let sirm = ctrl.sirm().unwrap();
+    ///
+    /// let alignment = sirm.payload_size_alignment(ctrl).unwrap();
+    /// let payload_size = sirm.required_payload_size(ctrl).unwrap();
+    /// println!("payload size: {}, alignment: {}", payload_size, alignment);
+    /// ```
     pub fn sirm(&mut self) -> ControlResult<Sirm> {
         if let Some(sirm) = self.sirm {
             return Ok(sirm);
         }
 
-        let addr = self.sbrm()?.sirm_address(self)?.ok_or_else(|| {
+        let addr = self.sbrm()?.sirm_address().ok_or_else(|| {
             ControlError::InvalidDevice("the u3v device doesn't have `SIRM ADDRESS`".into())
         })?;
         let sirm = Sirm::new(addr);
@@ -174,32 +459,361 @@ impl ControlHandle {
         Ok(sirm)
     }
 
+    /// Returns [`Eirm`], which is located by `EIRM ADDRESS` register of [`Sbrm`].
+    ///
+    /// Returns [`ControlError::NotSupported`] if the device doesn't have the event interface.
+    pub fn eirm(&mut self) -> ControlResult<Eirm> {
+        if let Some(eirm) = self.eirm {
+            return Ok(eirm);
+        }
+
+        let addr = self.sbrm()?.eirm_address().ok_or_else(|| {
+            ControlError::NotSupported("the u3v device doesn't have `EIRM ADDRESS`".into())
+        })?;
+        let eirm = Eirm::new(addr);
+        self.eirm = Some(eirm);
+
+        Ok(eirm)
+    }
+
     /// Returns [`ManifestTable`].
+    ///
+    /// All entries of the table are read on the first call and cached afterwards.
+    ///
+    /// # Errors
+    /// [`ControlError::LimitExceeded`] is returned if the number of entries exceeds
+    /// [`Limits::max_manifest_entries`].
     pub fn manifest_table(&mut self) -> ControlResult<ManifestTable> {
-        if let Some(manifest_table) = self.manifest_table {
-            return Ok(manifest_table);
+        if let Some(manifest_table) = &self.manifest_table {
+            return Ok(manifest_table.clone());
         }
 
-        let addr = self.abrm()?.manifest_table_address(self)?;
-        let manifest_table = ManifestTable::new(addr);
-        self.manifest_table = Some(manifest_table);
+        let addr = self.cached_abrm()?.manifest_table_address();
+        let limits = self.limits;
+        let manifest_table = ManifestTable::new(self, addr, &limits)?;
+        self.manifest_table = Some(manifest_table.clone());
         Ok(manifest_table)
     }
 
+    /// Downloads the `GenApi` xml file described by `entry`, e.g. an entry of
+    /// [`ManifestTable::entries`].
+    ///
+    /// The file is read in chunks which fit into the maximum ack length, verified with the sha1
+    /// hash of the entry if the device provides it, and unzipped if it's compressed.
+    ///
+    /// # Errors
+    /// [`ControlError::XmlHashMismatch`] is returned if the file doesn't match the hash,
+    /// [`ControlError::CorruptXmlArchive`] if the zipped file is broken, and
+    /// [`ControlError::XmlNotUtf8`] if the xml isn't encoded in UTF-8.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use cameleon::u3v;
+    ///
+    /// let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// if cameras.is_empty() {
+    ///     return;
+    /// }
+    /// let mut camera = cameras.pop().unwrap();
+    /// camera.open().unwrap();
+    ///
+    /// let ctrl = &mut camera.ctrl;
+    /// let table = ctrl.manifest_table().unwrap();
+    /// for entry in table.entries() {
+    ///     let xml = ctrl.genapi_xml(entry).unwrap();
+    ///     println!("{}", xml);
+    /// }
+    /// ```
+    pub fn genapi_xml(&mut self, entry: &ManifestEntry) -> ControlResult<String> {
+        let file = unwrap_or_log!(self.xml_file(entry));
+
+        // Store current capacity so that we can set back it after XML retrieval because this needs exceptional large size of internal buffer.
+        let current_capacity = self.buffer_capacity();
+        let mut buf = vec![0; file.size];
+        let read = self.read(file.address, &mut buf);
+        self.resize_buffer(current_capacity);
+        unwrap_or_log!(read);
+
+        // Verify retrieved xml has correct hash.
+        if !file.verify(&buf) {
+            let error = ControlError::XmlHashMismatch;
+            error!(?error);
+            return Err(error);
+        }
+
+        Ok(unwrap_or_log!(load_options::decode_xml(buf, &file)))
+    }
+
+    /// Reads `entries[i].1` bytes at address `entries[i].0` into `bufs[i]` for each entry.
+    ///
+    /// Entries are batched into `ReadMemStacked` commands as long as they fit into the maximum
+    /// command and ack length, so that scattered registers can be read with fewer round trips.
+    /// If the device doesn't support stacked commands, the entries are read one by one with
+    /// [`DeviceControl::read`].
+    ///
+    /// # Errors
+    /// [`ControlError::PartialRead`] is returned with the index of the failed entry if the device
+    /// returns fewer bytes than requested. Entries before the failed one are already read into
+    /// their buffers.
+    pub fn read_mem_stacked(
+        &mut self,
+        entries: &[(u64, u16)],
+        bufs: &mut [&mut [u8]],
+    ) -> ControlResult<()> {
+        unwrap_or_log!(self.assert_open());
+        verify_read_entries(entries, bufs)?;
+
+        let abrm = unwrap_or_log!(self.cached_abrm());
+        if !unwrap_or_log!(abrm.device_capability()).is_stacked_commands_supported() {
+            for (&(address, _), buf) in entries.iter().zip(bufs.iter_mut()) {
+                self.read(address, buf)?;
+            }
+            return Ok(());
+        }
+
+        let maximum_cmd_length = self.config.maximum_cmd_length as usize;
+        let maximum_ack_length = self.config.maximum_ack_length as usize;
+        let sizes = entries
+            .iter()
+            .map(|&(_, len)| (STACKED_ENTRY_HEADER_LENGTH, len as usize));
+        for range in split_stacked(sizes, maximum_cmd_length, maximum_ack_length) {
+            let group = &entries[range.clone()];
+            let bufs = &mut bufs[range.clone()];
+            if group.len() == 1 && PACKET_HEADER_LENGTH + group[0].1 as usize > maximum_ack_length {
+                // The entry doesn't fit into a single ack, so read it with chunked `ReadMem`.
+                self.read(group[0].0, bufs[0])?;
+                continue;
+            }
+
+            let cmd = unwrap_or_log!(cmd::ReadMemStacked::new(
+                group
+                    .iter()
+                    .map(|&(address, len)| cmd::ReadMem::new(address, len))
+                    .collect()
+            ));
+            let started = Instant::now();
+            let ack: ack::ReadMemStacked = unwrap_or_log!(self.send_cmd(cmd));
+            let result = copy_stacked_data(group, bufs, range.start, ack.data);
+            self.latency
+                .record(TransactionKind::Stacked, group[0].0, started.elapsed());
+            unwrap_or_log!(result);
+        }
+
+        Ok(())
+    }
+
+    /// Reads `entries[i].1` bytes at address `entries[i].0` into `bufs[i]` for each entry,
+    /// keeping up to [`ControlHandle::pipeline_depth`] `ReadMem` commands outstanding.
+    ///
+    /// Pipelining hides the round trip latency of consecutive reads on devices which accept a new
+    /// command before acknowledging the previous one. It is disabled unless enabled by
+    /// [`OpenOptions::pipeline_depth`], in that case the entries are read one by one with
+    /// [`DeviceControl::read`].
+    ///
+    /// If the device answers `Busy` or an unexpected ack, the handle falls back to serial
+    /// transactions, and [`ControlHandle::pipeline_depth`] returns `1` from then on.
+    ///
+    /// # Errors
+    /// [`ControlError::PartialRead`] is returned with the index of the failed entry if the device
+    /// returns fewer bytes than requested.
+    pub fn read_pipelined(
+        &mut self,
+        entries: &[(u64, u16)],
+        bufs: &mut [&mut [u8]],
+    ) -> ControlResult<()> {
+        unwrap_or_log!(self.assert_open());
+        verify_read_entries(entries, bufs)?;
+
+        if self.config.pipeline_depth <= 1 {
+            for (&(address, _), buf) in entries.iter().zip(bufs.iter_mut()) {
+                self.read(address, buf)?;
+            }
+            return Ok(());
+        }
+        // The pipeline doesn't know the commands in flight.
+        unwrap_or_log!(self.drain_in_flight());
+
+        let maximum_read_length =
+            cmd::ReadMem::maximum_read_length(self.config.maximum_ack_length as usize) as usize;
+        let mut reads = vec![];
+        for (entry, (&(address, _), buf)) in entries.iter().zip(bufs.iter_mut()).enumerate() {
+            for (i, chunk) in buf.chunks_mut(maximum_read_length).enumerate() {
+                let address = address + (i * maximum_read_length) as u64;
+                reads.push(PipelinedRead::new(entry, address, chunk));
+            }
+        }
+
+        let mut pipeline = Pipeline {
+            transport: &mut self.inner,
+            depth: self.config.pipeline_depth,
+            timeout: self.config.timeout_duration,
+            retry_count: self.config.retry_count,
+            limits: self.limits,
+            latency: &self.latency,
+        };
+        let result = pipeline.read(&mut reads, &mut self.next_req_id);
+        if let Ok(Some(_)) = result {
+            self.config.pipeline_depth = pipeline.depth;
+        }
+        unwrap_or_log!(result);
+
+        Ok(())
+    }
+
+    /// Writes `entries[i].1` at address `entries[i].0` for each entry, and returns the lengths
+    /// written to each address.
+    ///
+    /// Entries are batched into `WriteMemStacked` commands as long as they fit into the maximum
+    /// command and ack length. If the device doesn't support stacked commands, the entries are
+    /// written one by one with [`DeviceControl::write`].
+    ///
+    /// # Errors
+    /// [`ControlError::PartialWrite`] is returned with the index of the first failed entry if the
+    /// length written by the device doesn't match the length of the entry. Entries in the
+    /// commands sent before the failed one are already written.
+    pub fn write_mem_stacked(&mut self, entries: &[(u64, &[u8])]) -> ControlResult<Vec<u16>> {
+        unwrap_or_log!(self.assert_open());
+        let mut lengths = Vec::with_capacity(entries.len());
+        for &(address, data) in entries {
+            unwrap_or_log!(checked_address(address, data.len() as u64));
+            let len: u16 = data.len().try_into().map_err(|_| {
+                ControlError::InvalidData("data of each entry must fit into u16::MAX bytes".into())
+            })?;
+            lengths.push(len);
+        }
+
+        let abrm = unwrap_or_log!(self.cached_abrm());
+        if !unwrap_or_log!(abrm.device_capability()).is_stacked_commands_supported() {
+            for &(address, data) in entries {
+                self.write(address, data)?;
+            }
+            return Ok(lengths);
+        }
+
+        let maximum_cmd_length = self.config.maximum_cmd_length as usize;
+        let maximum_ack_length = self.config.maximum_ack_length as usize;
+        let sizes = entries.iter().map(|(_, data)| {
+            (
+                STACKED_ENTRY_HEADER_LENGTH + data.len(),
+                STACKED_WRITE_ACK_ENTRY_LENGTH,
+            )
+        });
+        for range in split_stacked(sizes, maximum_cmd_length, maximum_ack_length) {
+            let group = &entries[range.clone()];
+            if group.len() == 1
+                && PACKET_HEADER_LENGTH + STACKED_ENTRY_HEADER_LENGTH + group[0].1.len()
+                    > maximum_cmd_length
+            {
+                // The entry doesn't fit into a single command, so write it with chunked
+                // `WriteMem`.
+                self.write(group[0].0, group[0].1)?;
+                continue;
+            }
+
+            let mut cmds = Vec::with_capacity(group.len());
+            for &(address, data) in group {
+                cmds.push(unwrap_or_log!(cmd::WriteMem::new(address, data)));
+            }
+            let cmd = unwrap_or_log!(cmd::WriteMemStacked::new(cmds));
+            let started = Instant::now();
+            let ack: ack::WriteMemStacked = unwrap_or_log!(self.send_cmd(cmd));
+            // The ack borrows the buffer, so it's verified before recording the latency.
+            let verified =
+                verify_written_lengths(&lengths[range.clone()], range.start, ack.entries);
+            self.latency
+                .record(TransactionKind::Stacked, group[0].0, started.elapsed());
+            unwrap_or_log!(verified);
+        }
+
+        Ok(lengths)
+    }
+
+    /// Sends a device specific custom command of `command_id` whose scd is `scd`, then copies
+    /// the scd of the ack into `response_buf` and returns its length.
+    ///
+    /// `command_id` must be a custom command id, i.e. the most significant bit is set and the id
+    /// is even. The device must answer with the ack id `command_id | 1`.
+    ///
+    /// # Errors
+    /// [`ControlError::UnexpectedAck`] is returned if the device answers with an ack of another
+    /// command. [`ControlError::BufferTooSmall`] is returned if the scd of the ack
+    /// doesn't fit into `response_buf`.
+    pub fn custom_command(
+        &mut self,
+        command_id: u16,
+        scd: &[u8],
+        response_buf: &mut [u8],
+    ) -> ControlResult<usize> {
+        unwrap_or_log!(self.assert_open());
+        if !is_custom_command_id(command_id) {
+            return Err(ControlError::InvalidData(
+                format!("{:#06X} is not a custom command id", command_id).into(),
+            ));
+        }
+        if PACKET_HEADER_LENGTH + scd.len() > self.config.maximum_cmd_length as usize {
+            return Err(ControlError::InvalidData(
+                "scd of the custom command exceeds the maximum command length".into(),
+            ));
+        }
+
+        // Accept any ack the device can send, and check its length against `response_buf` later.
+        let ack_scd_len = (self.config.maximum_ack_length as usize)
+            .saturating_sub(PACKET_HEADER_LENGTH)
+            .try_into()
+            .unwrap_or(u16::MAX);
+        let cmd = unwrap_or_log!(cmd::CustomCommand::new(command_id, scd, ack_scd_len));
+        let started = Instant::now();
+        let recv_len = unwrap_or_log!(self.transact(cmd, Some(command_id | 1)));
+        // Custom commands don't have an address.
+        self.latency
+            .record(TransactionKind::Custom, 0, started.elapsed());
+
+        let ack: ack::CustomAck =
+            unwrap_or_log!(ack::AckPacket::parse_scd(&self.buffer[..recv_len]));
+        let len = ack.data.len();
+        if len > response_buf.len() {
+            return Err(ControlError::BufferTooSmall);
+        }
+        response_buf[..len].copy_from_slice(ack.data);
+        Ok(len)
+    }
+
     pub(super) fn new(device: &u3v::Device) -> ControlResult<Self> {
-        let inner = device.control_channel()?;
+        let inner = ControlChannel::Usb(device.control_channel()?);
+        Ok(Self::with_channel(inner, &device.device_info))
+    }
 
-        Ok(Self {
+    /// Builds a handle over the control channel of an emulated device.
+    #[cfg(all(test, feature = "emulator"))]
+    pub(super) fn new_emulated(device: &cameleon_device::emulator::Device) -> ControlResult<Self> {
+        let inner = ControlChannel::Emulated(device.control_channel()?);
+        Ok(Self::with_channel(inner, &device.device_info))
+    }
+
+    fn with_channel(inner: ControlChannel, info: &u3v::DeviceInfo) -> Self {
+        let latency = Arc::new(LatencyRecorder::default());
+        metrics::register(&latency, metrics::device_labels(&info.serial_number));
+
+        Self {
             inner,
             config: ConnectionConfig::default(),
+            limits: Limits::default(),
             next_req_id: 0,
             buffer: Vec::new(),
-            info: device.device_info.clone(),
+            latency,
+            in_flight: InFlight::default(),
+            status_decoder: Arc::new(ack::DefaultStatusDecoder),
+            info: info.clone(),
             abrm: None,
             sbrm: None,
             sirm: None,
+            eirm: None,
             manifest_table: None,
-        })
+            open_tag: DEFAULT_OPEN_TAG.into(),
+            open_guard: None,
+            tracked: None,
+        }
     }
 
     fn assert_open(&self) -> ControlResult<()> {
@@ -210,29 +824,127 @@ impl ControlHandle {
         }
     }
 
+    /// Returns the cached [`Abrm`], reading it if it's not cached yet.
+    fn cached_abrm(&mut self) -> ControlResult<&Abrm> {
+        if self.abrm.is_none() {
+            self.abrm = Some(Abrm::new(self)?);
+        }
+        Ok(self.abrm.as_ref().unwrap())
+    }
+
+    /// Enables the heartbeat of the device if [`OpenOptions::heartbeat_timeout`] is set.
+    fn apply_heartbeat(&mut self) -> ControlResult<()> {
+        if let Some(timeout) = self.config.heartbeat_timeout {
+            let abrm = self.cached_abrm()?.clone();
+            abrm.set_heartbeat_timeout(self, timeout)?;
+        }
+        Ok(())
+    }
+
     fn initialize_config(&mut self) -> ControlResult<()> {
-        let abrm = self.abrm()?;
-        let sbrm = abrm.sbrm(self)?;
+        let timeout_duration = self.cached_abrm()?.maximum_device_response_time();
+        let sbrm = self.sbrm()?;
 
-        let timeout_duration = abrm.maximum_device_response_time(self)?;
-        let maximum_cmd_length = sbrm.maximum_command_transfer_length(self)?;
-        let maximum_ack_length = sbrm.maximum_acknowledge_trasfer_length(self)?;
+        let maximum_cmd_length = sbrm.maximum_command_transfer_length();
+        let maximum_ack_length = sbrm.maximum_acknowledge_trasfer_length();
+        self.limits.check_allocation(maximum_cmd_length)?;
+        self.limits.check_allocation(maximum_ack_length)?;
 
-        self.config.timeout_duration = timeout_duration;
+        self.config.timeout_duration = match self.config.requested_timeout {
+            Some(timeout) => clamp_timeout(timeout, Some(timeout_duration)),
+            None => timeout_duration,
+        };
         self.config.maximum_cmd_length = maximum_cmd_length;
         self.config.maximum_ack_length = maximum_ack_length;
 
         Ok(())
     }
 
+    /// Returns `timeout` bounded below by the maximum device response time if [`Abrm`] is
+    /// already read.
+    fn effective_timeout(&self, timeout: Duration) -> Duration {
+        let floor = self.abrm.as_ref().map(Abrm::maximum_device_response_time);
+        clamp_timeout(timeout, floor)
+    }
+
+    /// Runs `f` with `timeout` as the timeout of each transaction, then restores the default
+    /// timeout.
+    fn with_timeout<T>(
+        &mut self,
+        timeout: Duration,
+        f: impl FnOnce(&mut Self) -> ControlResult<T>,
+    ) -> ControlResult<T> {
+        let timeout = self.effective_timeout(timeout);
+        let default = std::mem::replace(&mut self.config.timeout_duration, timeout);
+        let result = f(self);
+        self.config.timeout_duration = default;
+        result
+    }
+
     fn send_cmd<'a, T, U>(&'a mut self, cmd: T) -> ControlResult<U>
     where
-        T: cmd::CommandScd,
+        T: cmd::CommandScd + Clone,
         U: ack::ParseScd<'a>,
     {
+        let recv_len = self.transact(cmd, None)?;
+
+        // `ack::AckPacket::parse_scd` is a fast operation, so it's ok to parse the packet again
+        // to avoid a lifetime problem.
+        Ok(ack::AckPacket::parse_scd(&self.buffer[0..recv_len])?)
+    }
+
+    /// Sends `cmd` and receives its final ack into the buffer, then returns the length of the
+    /// ack.
+    ///
+    /// The command is sent again according to [`RetryPolicy`] if the device rejects it with a
+    /// retryable status. The retried command is a new request since the rejected one isn't
+    /// executed.
+    ///
+    /// If the control endpoints are stalled, the channel is recovered by [`Self::recover`] and
+    /// the command is sent once more.
+    fn transact<T: cmd::CommandScd + Clone>(
+        &mut self,
+        cmd: T,
+        ack_id: Option<u16>,
+    ) -> ControlResult<usize> {
+        let policy = self.config.retry_policy;
+        let mdrt = self.abrm.as_ref().map_or(
+            self.config.timeout_duration,
+            Abrm::maximum_device_response_time,
+        );
+        retry::run(&policy, mdrt, |attempt| {
+            if attempt > 1 {
+                self.next_req_id = self.next_req_id.wrapping_add(1);
+            }
+            match self.transact_once(cmd.clone(), ack_id) {
+                Err(err) if is_stalled(&err) => {
+                    warn!(%err, "the control endpoints are stalled, try to recover them");
+                    self.recover()?;
+                    self.transact_once(cmd.clone(), ack_id)
+                }
+                result => result,
+            }
+        })
+    }
+
+    /// Sends `cmd` once and receives its final ack into the buffer, then returns the length of
+    /// the ack.
+    ///
+    /// Acks of other requests are dropped as stale ones, see [`discard_stale_ack`]. If `ack_id`
+    /// is `Some`, an ack of another command is reported as [`ControlError::UnexpectedAck`].
+    fn transact_once<T: cmd::CommandScd>(
+        &mut self,
+        cmd: T,
+        ack_id: Option<u16>,
+    ) -> ControlResult<usize> {
+        // Without pipelining, the transaction queues behind the commands in flight.
+        if self.config.pipeline_depth <= 1 {
+            self.drain_in_flight()?;
+        }
+
         let cmd = cmd.finalize(self.next_req_id);
         let cmd_len = cmd.cmd_len();
-        let ack_len = cmd.maximum_ack_len();
+        let ack_len = cmd.maximum_ack_len().max(self.in_flight.ack_len());
         if self.buffer.len() < std::cmp::max(cmd_len, ack_len) {
             self.buffer.resize(std::cmp::max(cmd_len, ack_len), 0);
         }
@@ -243,87 +955,149 @@ impl ControlHandle {
             .send(&self.buffer[..cmd_len], self.config.timeout_duration)?;
 
         // Receive ack and interpret the packet.
-        let mut retry_count = self.config.retry_count;
-        let mut ok = None;
-        while retry_count > 0 {
-            let recv_len = self
-                .inner
-                .recv(&mut self.buffer, self.config.timeout_duration)?;
+        let mut deadline = Deadline::new(self.config.timeout_duration);
+        let mut stale_acks = 0;
+        let recv_len = loop {
+            let remaining = deadline.remaining().ok_or(ControlError::Timeout)?;
+            let recv_len = self.inner.recv(&mut self.buffer, remaining)?;
+            if self.in_flight.stash(
+                &self.buffer[..recv_len],
+                self.config.retry_count,
+                &self.limits,
+            )? {
+                continue;
+            }
 
-            let ack = ack::AckPacket::parse(&self.buffer[0..recv_len])?;
-            self.verify_ack(&ack)?;
+            let ack =
+                ack::AckPacket::parse_decoded(&self.buffer[0..recv_len], &*self.status_decoder)?;
+            if discard_stale_ack(&ack, self.next_req_id, &mut stale_acks)? {
+                continue;
+            }
+            verify_ack(&ack, self.next_req_id, ack_id)?;
 
-            // Retry up to retry count.
+            // Wait for the final ack with the same request id.
             if ack.scd_kind() == ack::ScdKind::Pending {
                 let pending_ack: ack::Pending = ack.scd_as()?;
-                std::thread::sleep(pending_ack.timeout);
-                retry_count -= 1;
+                deadline.extend(
+                    ack.request_id(),
+                    pending_ack.timeout,
+                    self.config.retry_count,
+                    &self.limits,
+                )?;
                 continue;
             }
 
-            self.next_req_id = self.next_req_id.wrapping_add(1);
-            ok = Some(recv_len);
-            break;
-        }
+            break recv_len;
+        };
+        self.next_req_id = self.next_req_id.wrapping_add(1);
 
-        // This codes seems weird due to a lifetime problem.
-        // `ack::AckPacket::parse` is a fast operation, so it's ok to call it repeatedly.
-        if let Some(recv_len) = ok {
-            Ok(ack::AckPacket::parse(&self.buffer[0..recv_len])
-                .unwrap()
-                .scd_as()?)
-        } else {
-            Err(ControlError::Io(anyhow::Error::msg(
-                "the number of times pending was returned exceeds the retry_count.",
-            )))
-        }
+        Ok(recv_len)
     }
 
-    fn verify_ack(&self, ack: &ack::AckPacket) -> ControlResult<()> {
-        let status = ack.status().kind();
-        if status != ack::StatusKind::GenCp(ack::GenCpStatus::Success) {
-            return Err(ControlError::Io(anyhow::Error::msg(format!(
-                "invalid status: {:?}",
-                ack.status().kind()
-            ))));
+    /// Sends `cmd` without waiting for its ack, and returns the request id of the command.
+    fn begin<T: cmd::CommandScd>(&mut self, cmd: T) -> ControlResult<u16> {
+        self.assert_open()?;
+        if self.config.pipeline_depth <= 1 {
+            self.drain_in_flight()?;
         }
 
-        if ack.request_id() != self.next_req_id {
-            return Err(ControlError::Io(anyhow::Error::msg("request id mismatch")));
+        let request_id = self.next_req_id;
+        let cmd = cmd.finalize(request_id);
+        let cmd_len = cmd.cmd_len();
+        if self.buffer.len() < cmd_len {
+            self.buffer.resize(cmd_len, 0);
         }
+        cmd.serialize(self.buffer.as_mut_slice())?;
+        self.inner
+            .send(&self.buffer[..cmd_len], self.config.timeout_duration)?;
 
-        Ok(())
+        self.in_flight.push(
+            request_id,
+            cmd.maximum_ack_len(),
+            self.config.timeout_duration,
+        );
+        self.next_req_id = self.next_req_id.wrapping_add(1);
+        Ok(request_id)
     }
 
-    fn verify_xml(&mut self, xml: &[u8], ent: register_map::ManifestEntry) -> ControlResult<()> {
-        use sha1::Digest;
+    /// Waits up to `timeout` for the ack of the command of `request_id` sent by
+    /// [`Self::begin`].
+    fn wait_in_flight(
+        &mut self,
+        request_id: u16,
+        timeout: Duration,
+    ) -> ControlResult<Option<ack::AckPacket<'static>>> {
+        self.assert_open()?;
+        self.prepare_in_flight_buffer();
+        self.in_flight.wait(
+            request_id,
+            &mut self.inner,
+            &mut self.buffer,
+            timeout,
+            self.config.retry_count,
+            &self.limits,
+        )
+    }
 
-        if let Some(hash) = ent.sha1_hash(self)? {
-            let xml_hash = sha1::Sha1::digest(xml);
-            if xml_hash.as_slice() == hash {
-                Ok(())
-            } else {
-                Err(ControlError::InvalidDevice(
-                    "sha1 of retrieved xml file isn't same as entry's hash".into(),
-                ))
-            }
-        } else {
-            Ok(())
+    /// Receives the acks of all commands in flight.
+    fn drain_in_flight(&mut self) -> ControlResult<()> {
+        if self.in_flight.is_settled() {
+            return Ok(());
         }
+        self.prepare_in_flight_buffer();
+        self.in_flight.drain(
+            &mut self.inner,
+            &mut self.buffer,
+            self.config.retry_count,
+            &self.limits,
+        )
     }
-}
 
-macro_rules! unwrap_or_log {
-    ($expr:expr) => {{
-        match $expr {
-            Ok(v) => v,
-            Err(error) => {
-                error!(?error);
-                return Err(error.into());
+    fn prepare_in_flight_buffer(&mut self) {
+        let ack_len = self.in_flight.ack_len();
+        if self.buffer.len() < ack_len {
+            self.buffer.resize(ack_len, 0);
+        }
+    }
+
+    /// Locates the newest `GenApi` xml file in the manifest table.
+    fn locate_xml(&mut self) -> ControlResult<ManifestEntry> {
+        let table = self.manifest_table()?;
+        // Use newest version if there are more than one entries.
+        let mut newest_ent = None;
+        for &ent in table.entries() {
+            if ent.file_info().file_type()? == register_map::GenICamFileType::DeviceXml {
+                let version = ent.genicam_file_version();
+                match &newest_ent {
+                    Some((_, cur_version)) if &version <= cur_version => {
+                        // Current entry is newest.
+                    }
+                    _ => newest_ent = Some((ent, version)),
+                }
             }
         }
-    }};
-}
+
+        newest_ent.map(|(ent, _)| ent).ok_or_else(|| {
+            ControlError::InvalidDevice("device doesn't have valid `ManifestEntry`".into())
+        })
+    }
+
+    /// Returns the location of the file described by `entry`.
+    fn xml_file(&self, entry: &ManifestEntry) -> ControlResult<GenApiFile> {
+        let file_info = entry.file_info();
+        let file_size = entry.file_size();
+        self.limits.check_xml_size(file_size)?;
+        let file_address = entry.file_address();
+        checked_address(file_address, file_size)?;
+        Ok(GenApiFile {
+            address: file_address,
+            size: file_size.try_into()?,
+            zipped: matches!(file_info.compression_type()?, CompressionType::Zip),
+            sha1: entry.sha1_hash(),
+            max_xml_size: self.limits.max_xml_size,
+        })
+    }
+}
 
 impl DeviceControl for ControlHandle {
     fn open(&mut self) -> ControlResult<()> {
@@ -331,11 +1105,19 @@ impl DeviceControl for ControlHandle {
             return Ok(());
         }
 
+        // The device is unregistered when `guard` is dropped on failure.
+        let guard = unwrap_or_log!(OPEN_REGISTRY.acquire(&self.info.guid, &self.open_tag));
+        unwrap_or_log!(self
+            .inner
+            .set_auto_detach_kernel_driver(self.config.detach_kernel_driver));
         unwrap_or_log!(self.inner.open());
         // Clean up control channel state.
         unwrap_or_log!(self.inner.set_halt(self.config.timeout_duration));
         unwrap_or_log!(self.inner.clear_halt());
         unwrap_or_log!(self.initialize_config());
+        unwrap_or_log!(self.apply_heartbeat());
+        self.open_guard = Some(guard);
+        self.tracked = Some(Tracked::new(Resource::Channel));
 
         Ok(())
     }
@@ -348,101 +1130,78 @@ impl DeviceControl for ControlHandle {
         if self.is_opened() {
             unwrap_or_log!(self.inner.close());
         }
+        self.in_flight.clear();
+        self.open_guard = None;
+        self.tracked = None;
         Ok(())
     }
 
     fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
         unwrap_or_log!(self.assert_open());
-
-        let cmd = unwrap_or_log!(cmd::WriteMem::new(address, data));
-        let maximum_cmd_length = self.config.maximum_cmd_length;
-
-        for chunk in cmd.chunks(maximum_cmd_length as usize).unwrap() {
-            let chunk_data_len = chunk.data_len();
-            let ack: ack::WriteMem = unwrap_or_log!(self.send_cmd(chunk));
-
-            if ack.length as usize != chunk_data_len {
-                let err_msg = "write mem failed: written length mismatch";
-                return Err(ControlError::Io(anyhow::Error::msg(err_msg)));
+        unwrap_or_log!(checked_address(address, data.len() as u64));
+
+        // Splits data if it doesn't fit into a command of the maximum command length.
+        let maximum_data_len = unwrap_or_log!(maximum_chunk_len(
+            self.config.maximum_cmd_length as usize,
+            PACKET_HEADER_LENGTH + WRITE_MEM_ADDRESS_LENGTH,
+            u16::MAX as usize - WRITE_MEM_ADDRESS_LENGTH,
+        ));
+        unwrap_or_log!(write_chunked(
+            address,
+            data,
+            maximum_data_len,
+            |address, chunk| {
+                let cmd = cmd::WriteMem::new(address, chunk)?;
+                let started = Instant::now();
+                let ack: ack::WriteMem = self.send_cmd(cmd)?;
+                self.latency
+                    .record(TransactionKind::Write, address, started.elapsed());
+                Ok(ack.length as usize)
             }
-        }
+        ));
 
         Ok(())
     }
 
-    fn read(&mut self, mut address: u64, buf: &mut [u8]) -> ControlResult<()> {
+    fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
         unwrap_or_log!(self.assert_open());
+        unwrap_or_log!(checked_address(address, buf.len() as u64));
 
-        // Chunks buffer if buffer length is larger than maximum read length calculated from
-        // maximum ack length.
-        for buf_chunk in buf.chunks_mut(cmd::ReadMem::maximum_read_length(
+        // Splits buffer if the data doesn't fit into an ack of the maximum ack length.
+        let maximum_data_len = unwrap_or_log!(maximum_chunk_len(
             self.config.maximum_ack_length as usize,
-        ) as usize)
-        {
-            let read_len: u16 = buf_chunk.len().try_into().unwrap();
-
-            let cmd = cmd::ReadMem::new(address, read_len);
-            let ack: ack::ReadMem = unwrap_or_log!(self.send_cmd(cmd));
-            buf_chunk.copy_from_slice(ack.data);
-            address += read_len as u64;
-        }
+            PACKET_HEADER_LENGTH,
+            u16::MAX as usize,
+        ));
+        unwrap_or_log!(read_chunked(
+            address,
+            buf,
+            maximum_data_len,
+            |address, chunk| {
+                let cmd = cmd::ReadMem::new(address, chunk.len().try_into()?);
+                let started = Instant::now();
+                let ack: ack::ReadMem = self.send_cmd(cmd)?;
+                let elapsed = started.elapsed();
+                let read = ack.data.len();
+                if read == chunk.len() {
+                    chunk.copy_from_slice(ack.data);
+                }
+                self.latency.record(TransactionKind::Read, address, elapsed);
+                Ok(read)
+            }
+        ));
 
         Ok(())
     }
 
     fn genapi(&mut self) -> ControlResult<String> {
-        fn zip_err(err: impl std::fmt::Debug) -> ControlError {
-            ControlError::InvalidDevice(format!("zipped xml file is broken: {:?}", err).into())
-        }
-
-        let table = unwrap_or_log!(self.manifest_table());
-        // Use newest version if there are more than one entries.
-        let mut newest_ent = None;
-        for ent in unwrap_or_log!(table.entries(self)) {
-            let file_info = unwrap_or_log!(ent.file_info(self));
-            if unwrap_or_log!(file_info.file_type()) == register_map::GenICamFileType::DeviceXml {
-                let version = unwrap_or_log!(ent.genicam_file_version(self));
-                match &newest_ent {
-                    Some((_, cur_version, _)) if &version <= cur_version => {
-                        // Current entry is newest.
-                    }
-                    _ => newest_ent = Some((ent, version, file_info)),
-                }
-            }
-        }
-
-        let (ent, _, file_info) = unwrap_or_log!(newest_ent.ok_or_else(|| {
-            ControlError::InvalidDevice("device doesn't have valid `ManifestEntry`".into())
-        }));
-
-        let file_address: u64 = unwrap_or_log!(ent.file_address(self));
-        let file_size: usize = unwrap_or_log!(unwrap_or_log!(ent.file_size(self)).try_into());
-        let comp_type = unwrap_or_log!(file_info.compression_type());
-
-        // Store current capacity so that we can set back it after XML retrieval because this needs exceptional large size of internal buffer.
-        let current_capacity = self.buffer_capacity();
-        let mut buf = vec![0; file_size];
-        unwrap_or_log!(self.read(file_address, &mut buf));
-        self.resize_buffer(current_capacity);
-
-        // Verify retrieved xml has correct hash.
-        unwrap_or_log!(self.verify_xml(&buf, ent));
-
-        match comp_type {
-            CompressionType::Zip => {
-                let mut zip = zip::ZipArchive::new(std::io::Cursor::new(buf)).unwrap();
-                if zip.len() != 1 {
-                    return Err(zip_err("more than one files in zipped GenApi XML"));
-                }
-                let mut file = unwrap_or_log!(zip.by_index(0).map_err(zip_err));
-                let file_size: usize = unwrap_or_log!(file.size().try_into());
-                let mut xml = Vec::with_capacity(file_size);
-                unwrap_or_log!(file.read_to_end(&mut xml).map_err(zip_err));
-                Ok(String::from_utf8_lossy(&xml).into())
-            }
+        let entry = unwrap_or_log!(self.locate_xml());
+        self.genapi_xml(&entry)
+    }
 
-            CompressionType::Uncompressed => Ok(String::from_utf8_lossy(&buf).into()),
-        }
+    fn genapi_file(&mut self) -> ControlResult<Option<GenApiFile>> {
+        let entry = unwrap_or_log!(self.locate_xml());
+        Ok(Some(unwrap_or_log!(self.xml_file(&entry))))
     }
 
     fn enable_streaming(&mut self) -> ControlResult<()> {
@@ -459,12 +1218,16 @@ impl DeviceControl for ControlHandle {
         let required_leader_size = unwrap_or_log!(sirm.required_leader_size(self));
         let required_payload_size = unwrap_or_log!(sirm.required_payload_size(self));
         let required_trailer_size = unwrap_or_log!(sirm.required_leader_size(self));
+        unwrap_or_log!(self.limits.check_allocation(required_leader_size));
+        unwrap_or_log!(self.limits.check_allocation(required_payload_size));
+        unwrap_or_log!(self.limits.check_allocation(required_trailer_size));
 
-        let payload_transfer_size = align!(PAYLOAD_TRANSFER_SIZE, u32);
-        let payload_transfer_count = (required_payload_size / payload_transfer_size as u64) as u32;
-        let payload_final_transfer1_size =
-            align!(required_payload_size % payload_transfer_size as u64, u64) as u32;
-        let payload_final_transfer2_size = 0;
+        let payload_transfer_size = unwrap_or_log!(negotiate_payload_transfer(
+            &sirm,
+            self,
+            required_payload_size,
+            payload_alignment
+        ));
 
         let maximum_leader_size = if required_leader_size == 0 {
             payload_transfer_size
@@ -477,10 +1240,6 @@ impl DeviceControl for ControlHandle {
             align!(required_trailer_size, u32)
         };
 
-        unwrap_or_log!(sirm.set_payload_transfer_size(self, payload_transfer_size));
-        unwrap_or_log!(sirm.set_payload_transfer_count(self, payload_transfer_count));
-        unwrap_or_log!(sirm.set_payload_final_transfer1_size(self, payload_final_transfer1_size));
-        unwrap_or_log!(sirm.set_payload_final_transfer2_size(self, payload_final_transfer2_size));
         unwrap_or_log!(sirm.set_maximum_leader_size(self, maximum_leader_size));
         unwrap_or_log!(sirm.set_maximum_trailer_size(self, maximum_trailer_size));
         unwrap_or_log!(sirm.enable_stream(self));
@@ -492,6 +1251,20 @@ impl DeviceControl for ControlHandle {
         let sirm = unwrap_or_log!(self.sirm());
         sirm.disable_stream(self)
     }
+
+    fn reenable_streaming(&mut self) -> ControlResult<()> {
+        // The transfer parameters written by `enable_streaming` are kept in `SIRM`.
+        let sirm = unwrap_or_log!(self.sirm());
+        sirm.enable_stream(self)
+    }
+
+    fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
 }
 
 impl Drop for ControlHandle {
@@ -503,6 +1276,9 @@ impl Drop for ControlHandle {
 }
 
 /// Thread safe version of [`ControlHandle`].
+///
+/// The handle can be shared within the process by [`SharedControlHandle::open_shared`] and
+/// [`SharedControlHandle::find_opened`].
 #[derive(Clone)]
 pub struct SharedControlHandle(Arc<Mutex<ControlHandle>>);
 
@@ -543,23 +1319,205 @@ impl SharedControlHandle {
         pub fn buffer_capacity(&self) -> usize,
         /// Thread safe version of [`ControlHandle::resize_buffer`].
         pub fn resize_buffer(&self, size: usize) -> (),
-        #[must_use]
         /// Thread safe version of [`ControlHandle::timeout_duration`].
         #[must_use]
         pub fn timeout_duration(&self) -> Duration,
         /// Thread safe version of [`ControlHandle::set_timeout_duration`].
+        #[deprecated(note = "use `OpenOptions::timeout_duration` with `SharedControlHandle::open_with`")]
+        #[allow(deprecated)]
         pub fn set_timeout_duration(&self, duration: Duration) -> (),
+        /// Thread safe version of [`ControlHandle::set_default_timeout`].
+        pub fn set_default_timeout(&self, timeout: Duration) -> (),
+        /// Thread safe version of [`ControlHandle::read_mem_timeout`].
+        pub fn read_mem_timeout(&self, address: u64, buf: &mut [u8], timeout: Duration) -> ControlResult<()>,
+        /// Thread safe version of [`ControlHandle::write_mem_timeout`].
+        pub fn write_mem_timeout(&self, address: u64, data: &[u8], timeout: Duration) -> ControlResult<()>,
+        /// Thread safe version of [`ControlHandle::pipeline_depth`].
+        #[must_use]
+        pub fn pipeline_depth(&self) -> u16,
         /// Thread safe version of [`ControlHandle::retry_count`].
         #[must_use]
         pub fn retry_count(&self) -> u16,
         /// Thread safe version of [`ControlHandle::set_retry_count`].
-        pub fn set_retry_count(&self, count: u16) -> ()
+        #[deprecated(note = "use `OpenOptions::retry_count` with `SharedControlHandle::open_with`")]
+        #[allow(deprecated)]
+        pub fn set_retry_count(&self, count: u16) -> (),
+        /// Thread safe version of [`ControlHandle::recover`].
+        pub fn recover(&self) -> ControlResult<()>,
+        /// Thread safe version of [`ControlHandle::retry_policy`].
+        #[must_use]
+        pub fn retry_policy(&self) -> RetryPolicy,
+        /// Thread safe version of [`ControlHandle::set_retry_policy`].
+        pub fn set_retry_policy(&self, policy: RetryPolicy) -> (),
+        /// Thread safe version of [`ControlHandle::limits`].
+        #[must_use]
+        pub fn limits(&self) -> Limits,
+        /// Thread safe version of [`ControlHandle::set_limits`].
+        #[deprecated(note = "use `OpenOptions::limits` with `SharedControlHandle::open_with`")]
+        #[allow(deprecated)]
+        pub fn set_limits(&self, limits: Limits) -> (),
+        /// Thread safe version of [`ControlHandle::latency_report`].
+        #[must_use]
+        pub fn latency_report(&self) -> LatencyReport,
+        /// Thread safe version of [`ControlHandle::reset_latency_report`].
+        pub fn reset_latency_report(&self) -> (),
+        /// Thread safe version of [`ControlHandle::set_open_tag`].
+        pub fn set_open_tag(&self, tag: String) -> (),
+        /// Thread safe version of [`ControlHandle::abrm`].
+        pub fn abrm(&self) -> ControlResult<Abrm>,
+        /// Thread safe version of [`ControlHandle::refresh_abrm`].
+        pub fn refresh_abrm(&self) -> ControlResult<Abrm>,
+        /// Thread safe version of [`ControlHandle::set_user_defined_name`].
+        pub fn set_user_defined_name(&self, name: &str) -> ControlResult<()>,
+        /// Thread safe version of [`ControlHandle::manifest_table`].
+        pub fn manifest_table(&self) -> ControlResult<ManifestTable>,
+        /// Thread safe version of [`ControlHandle::set_status_decoder`].
+        pub fn set_status_decoder(&self, decoder: Arc<dyn ack::StatusDecoder + Send + Sync>) -> (),
+        /// Thread safe version of [`ControlHandle::read_mem_stacked`].
+        pub fn read_mem_stacked(&self, entries: &[(u64, u16)], bufs: &mut [&mut [u8]]) -> ControlResult<()>,
+        /// Thread safe version of [`ControlHandle::custom_command`].
+        pub fn custom_command(&self, command_id: u16, scd: &[u8], response_buf: &mut [u8]) -> ControlResult<usize>,
+        /// Thread safe version of [`ControlHandle::read_pipelined`].
+        pub fn read_pipelined(&self, entries: &[(u64, u16)], bufs: &mut [&mut [u8]]) -> ControlResult<()>,
+        /// Thread safe version of [`ControlHandle::write_mem_stacked`].
+        pub fn write_mem_stacked(&self, entries: &[(u64, &[u8])]) -> ControlResult<Vec<u16>>
     );
 
+    /// Thread safe version of [`ControlHandle::open_with`].
+    pub fn open_with(&mut self, options: &OpenOptions) -> ControlResult<()> {
+        self.0.lock().unwrap().open_with(options)
+    }
+
+    /// Opens the handle, and allows other openers in the process to obtain the handle via
+    /// [`SharedControlHandle::find_opened`] instead of failing with
+    /// [`ControlError::AlreadyOpenInProcess`].
+    pub fn open_shared(&mut self) -> ControlResult<()> {
+        let mut inner = self.0.lock().unwrap();
+        inner.open()?;
+        if let Some(guard) = &inner.open_guard {
+            guard.share(&self.0);
+        }
+        Ok(())
+    }
+
+    /// Returns the handle which holds the device of `guid` if the handle is opened by
+    /// [`SharedControlHandle::open_shared`].
+    #[must_use]
+    pub fn find_opened(guid: &Guid) -> Option<Self> {
+        OPEN_REGISTRY.shared(guid).map(Self)
+    }
+
     /// Returns the device info of the handle.
     pub fn device_info(&self) -> u3v::DeviceInfo {
         self.0.lock().unwrap().device_info().clone()
     }
+
+    /// Sends `cmd` and returns without waiting for its ack, so that the caller can do other work
+    /// before collecting the ack with [`PendingTransaction::wait`] or
+    /// [`PendingTransaction::try_complete`].
+    ///
+    /// While the transaction is pending, other transactions on the handle queue behind it, i.e.
+    /// they receive its ack first. If pipelining is enabled by [`OpenOptions::pipeline_depth`],
+    /// they are sent immediately and interleave with the pending transaction instead.
+    ///
+    /// If the returned [`PendingTransaction`] is dropped without collecting the ack, the ack is
+    /// discarded when it arrives.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use cameleon::u3v::{self, SharedControlHandle};
+    /// use cameleon::DeviceControl;
+    /// use cameleon_device::u3v::protocol::cmd;
+    ///
+    /// let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// if cameras.is_empty() {
+    ///     return;
+    /// }
+    /// let camera = cameras.pop().unwrap();
+    /// let mut ctrl = SharedControlHandle::from(camera.ctrl);
+    /// ctrl.open().unwrap();
+    ///
+    /// // Triggers a slow device specific operation.
+    /// let cmd = cmd::WriteMem::new(0x1_0000, &[1, 0, 0, 0]).unwrap();
+    /// let pending = ctrl.begin_transaction(cmd).unwrap();
+    ///
+    /// // Do other work here.
+    ///
+    /// let ack = pending.wait(Duration::from_secs(3)).unwrap();
+    /// assert!(ack.status().is_success());
+    /// ```
+    pub fn begin_transaction<T: cmd::CommandScd>(
+        &self,
+        cmd: T,
+    ) -> ControlResult<PendingTransaction> {
+        let request_id = self.0.lock().unwrap().begin(cmd)?;
+        Ok(PendingTransaction {
+            handle: self.clone(),
+            request_id,
+        })
+    }
+}
+
+/// A transaction sent by [`SharedControlHandle::begin_transaction`] whose ack isn't collected
+/// yet.
+pub struct PendingTransaction {
+    handle: SharedControlHandle,
+    request_id: u16,
+}
+
+impl PendingTransaction {
+    /// Returns the request id of the command.
+    #[must_use]
+    pub fn request_id(&self) -> u16 {
+        self.request_id
+    }
+
+    /// Waits for the ack of the command, and returns it.
+    ///
+    /// The handle is locked while waiting, so other transactions wait for the ack too.
+    ///
+    /// # Errors
+    /// [`ControlError::Timeout`] is returned if neither the ack is received within `timeout` nor
+    /// the device responds in time, in the former case the ack is discarded when it arrives.
+    /// An error is also returned if the device answers with an error status, or the ack has
+    /// already been collected by [`Self::try_complete`].
+    pub fn wait(self, timeout: Duration) -> ControlResult<ack::AckPacket<'static>> {
+        let ack = self
+            .handle
+            .0
+            .lock()
+            .unwrap()
+            .wait_in_flight(self.request_id, timeout);
+        ack?.ok_or(ControlError::Timeout)
+    }
+
+    /// Returns the ack of the command if it has already arrived, otherwise returns `None`
+    /// without blocking.
+    ///
+    /// Once the ack is returned, the transaction is complete and must not be waited anymore.
+    ///
+    /// # Errors
+    /// An error is returned if the device answers with an error status or doesn't respond in
+    /// time.
+    pub fn try_complete(&mut self) -> ControlResult<Option<ack::AckPacket<'static>>> {
+        self.handle
+            .0
+            .lock()
+            .unwrap()
+            .wait_in_flight(self.request_id, TRY_COMPLETE_TIMEOUT)
+    }
+}
+
+impl Drop for PendingTransaction {
+    fn drop(&mut self) {
+        // The ack is already collected if the command isn't in flight.
+        if let Ok(mut handle) = self.handle.0.lock() {
+            handle.in_flight.abandon(self.request_id);
+        }
+    }
 }
 
 impl DeviceControl for SharedControlHandle {
@@ -573,8 +1531,478 @@ impl DeviceControl for SharedControlHandle {
         fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()>,
         fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()>,
         fn genapi(&mut self) -> ControlResult<String>,
+        fn genapi_file(&mut self) -> ControlResult<Option<GenApiFile>>,
         fn enable_streaming(&mut self) -> ControlResult<()>,
-        fn disable_streaming(&mut self) -> ControlResult<()>
+        fn disable_streaming(&mut self) -> ControlResult<()>,
+        fn reenable_streaming(&mut self) -> ControlResult<()>
+    }
+
+    fn limits(&self) -> Limits {
+        self.0.lock().unwrap().limits
+    }
+
+    fn set_limits(&mut self, limits: Limits) {
+        self.0.lock().unwrap().limits = limits;
+    }
+}
+
+/// Splits entries into ranges each of which is sent by a single stacked command.
+///
+/// `sizes` yields the lengths of each entry in command scd and ack scd. An entry which doesn't
+/// fit into a command or an ack forms a range by itself.
+fn split_stacked(
+    sizes: impl IntoIterator<Item = (usize, usize)>,
+    maximum_cmd_length: usize,
+    maximum_ack_length: usize,
+) -> Vec<Range<usize>> {
+    // Scd length must fit into `u16`.
+    let maximum_scd_length = u16::MAX as usize;
+    let maximum_cmd_scd_length =
+        maximum_scd_length.min(maximum_cmd_length.saturating_sub(PACKET_HEADER_LENGTH));
+    let maximum_ack_scd_length =
+        maximum_scd_length.min(maximum_ack_length.saturating_sub(PACKET_HEADER_LENGTH));
+
+    let mut ranges = vec![];
+    let mut start = 0;
+    let mut end = 0;
+    let mut cmd_scd_length = 0;
+    let mut ack_scd_length = 0;
+    for (cmd_len, ack_len) in sizes {
+        if end > start
+            && (cmd_scd_length + cmd_len > maximum_cmd_scd_length
+                || ack_scd_length + ack_len > maximum_ack_scd_length)
+        {
+            ranges.push(start..end);
+            start = end;
+            cmd_scd_length = 0;
+            ack_scd_length = 0;
+        }
+        cmd_scd_length += cmd_len;
+        ack_scd_length += ack_len;
+        end += 1;
+    }
+    if start < end {
+        ranges.push(start..end);
+    }
+
+    ranges
+}
+
+/// Copies `data` of `ReadMemStacked` ack into `bufs`.
+///
+/// `first_index` is the index of `entries[0]` in the entries passed by the user, and used to
+/// report the failed entry.
+fn copy_stacked_data(
+    entries: &[(u64, u16)],
+    bufs: &mut [&mut [u8]],
+    first_index: usize,
+    data: &[u8],
+) -> ControlResult<()> {
+    let mut offset = 0;
+    for (i, (&(_, len), buf)) in entries.iter().zip(bufs.iter_mut()).enumerate() {
+        let len = len as usize;
+        let read = data.len().saturating_sub(offset).min(len);
+        if read < len {
+            return Err(ControlError::PartialRead {
+                index: first_index + i,
+                requested: len,
+                read,
+            });
+        }
+        buf.copy_from_slice(&data[offset..offset + len]);
+        offset += len;
+    }
+
+    Ok(())
+}
+
+/// Verifies the status of `ack` and that it answers the command of `request_id`.
+///
+/// If `ack_id` is `Some`, the final ack must have the id, and a mismatch is reported as
+/// [`ControlError::UnexpectedAck`].
+fn verify_ack(ack: &ack::AckPacket, request_id: u16, ack_id: Option<u16>) -> ControlResult<()> {
+    let status = ack.status();
+    if !status.is_success() {
+        return Err(ControlError::Io(StatusError(*status).into()));
+    }
+
+    match ack_id {
+        Some(expected_ack_id) => {
+            let is_expected_kind = ack.scd_kind() == ack::ScdKind::Pending
+                || ack.custom_command_id() == Some(expected_ack_id);
+            if ack.request_id() != request_id || !is_expected_kind {
+                return Err(ControlError::UnexpectedAck {
+                    expected_ack_id,
+                    ack_id: ack.scd_kind().id(),
+                    expected_request_id: request_id,
+                    request_id: ack.request_id(),
+                });
+            }
+        }
+        None if ack.request_id() != request_id => {
+            return Err(ControlError::RequestIdMismatch {
+                expected: request_id,
+                actual: ack.request_id(),
+            });
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if `ack` doesn't belong to the command of `request_id`, e.g. the ack of a command
+/// which timed out arrives late, and counts it in `stale_acks`.
+///
+/// Such an ack must be dropped and the next one read, otherwise the transaction returns the data
+/// of another command. [`ControlError::RequestIdMismatch`] is returned once more than
+/// [`MAX_STALE_ACKS`] acks are discarded for the command.
+pub(super) fn discard_stale_ack(
+    ack: &ack::AckPacket,
+    request_id: u16,
+    stale_acks: &mut usize,
+) -> ControlResult<bool> {
+    if ack.request_id() == request_id {
+        return Ok(false);
+    }
+
+    *stale_acks += 1;
+    if *stale_acks > MAX_STALE_ACKS {
+        return Err(ControlError::RequestIdMismatch {
+            expected: request_id,
+            actual: ack.request_id(),
+        });
+    }
+    warn!(
+        expected = request_id,
+        actual = ack.request_id(),
+        "discarded a stale ack of another request"
+    );
+    Ok(true)
+}
+
+/// Returns the maximum data length of a chunk which fits into a packet of `maximum_packet_len`
+/// with `header_len` bytes of header, the length is aligned to [`CHUNK_ALIGNMENT`] and is at most
+/// `limit`.
+fn maximum_chunk_len(
+    maximum_packet_len: usize,
+    header_len: usize,
+    limit: usize,
+) -> ControlResult<usize> {
+    let len = maximum_packet_len.saturating_sub(header_len).min(limit) & !(CHUNK_ALIGNMENT - 1);
+    if len == 0 {
+        Err(ControlError::InvalidDevice(
+            format!(
+                "maximum packet length {} is too small to transfer data",
+                maximum_packet_len
+            )
+            .into(),
+        ))
+    } else {
+        Ok(len)
+    }
+}
+
+/// Writes `data` to `address` in chunks of at most `maximum_chunk_len` bytes.
+///
+/// `write_chunk` writes a chunk to its address and returns the length written by the device. The
+/// write is aborted at the first chunk whose written length mismatches.
+fn write_chunked(
+    address: u64,
+    data: &[u8],
+    maximum_chunk_len: usize,
+    mut write_chunk: impl FnMut(u64, &[u8]) -> ControlResult<usize>,
+) -> ControlResult<()> {
+    for (i, chunk) in data.chunks(maximum_chunk_len).enumerate() {
+        let offset = i * maximum_chunk_len;
+        let written = write_chunk(address + offset as u64, chunk)?;
+        if written != chunk.len() {
+            return Err(ControlError::PartialChunkWrite {
+                offset,
+                requested: chunk.len(),
+                written,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads data at `address` into `buf` in chunks of at most `maximum_chunk_len` bytes.
+///
+/// `read_chunk` reads data at its address into a chunk and returns the length returned by the
+/// device. The read is aborted at the first chunk whose returned length mismatches.
+fn read_chunked(
+    address: u64,
+    buf: &mut [u8],
+    maximum_chunk_len: usize,
+    mut read_chunk: impl FnMut(u64, &mut [u8]) -> ControlResult<usize>,
+) -> ControlResult<()> {
+    for (i, chunk) in buf.chunks_mut(maximum_chunk_len).enumerate() {
+        let offset = i * maximum_chunk_len;
+        let requested = chunk.len();
+        let read = read_chunk(address + offset as u64, chunk)?;
+        if read != requested {
+            return Err(ControlError::PartialChunkRead {
+                offset,
+                requested,
+                read,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// An error of an ack whose status isn't success, which is carried in [`ControlError::Io`].
+#[derive(Debug, thiserror::Error)]
+#[error("invalid status: {0}")]
+pub(super) struct StatusError(pub(super) ack::Status);
+
+/// Returns the status of the ack if `err` is caused by the device rejecting a command.
+pub(super) fn rejected_status(err: &ControlError) -> Option<ack::Status> {
+    match err {
+        ControlError::Io(e) => e.downcast_ref::<StatusError>().map(|e| e.0),
+        ControlError::RetriesExhausted { source, .. } => rejected_status(source),
+        _ => None,
+    }
+}
+
+/// Returns `true` if `err` is caused by a stalled endpoint, i.e. `LIBUSB_ERROR_PIPE`.
+fn is_stalled(err: &ControlError) -> bool {
+    match err {
+        ControlError::Io(e) => matches!(
+            e.downcast_ref::<u3v::Error>(),
+            Some(u3v::Error::LibUsb(u3v::LibUsbError::Pipe))
+        ),
+        _ => false,
+    }
+}
+
+/// Transfer sizes of a payload written to `SIRM`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PayloadTransfer {
+    size: u32,
+    count: u32,
+    final1_size: u32,
+    final2_size: u32,
+}
+
+impl PayloadTransfer {
+    /// Splits a payload of `payload_size` into transfers of `size`, the final transfer is padded
+    /// to `alignment`.
+    fn new(size: u32, payload_size: u64, alignment: u64) -> ControlResult<Self> {
+        let remainder = payload_size % u64::from(size);
+        Ok(Self {
+            size,
+            count: (payload_size / u64::from(size)).try_into()?,
+            final1_size: align_up(remainder, alignment).try_into()?,
+            final2_size: 0,
+        })
+    }
+
+    fn write<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        sirm: &Sirm,
+        ctrl: &mut Ctrl,
+    ) -> ControlResult<()> {
+        sirm.set_payload_transfer_size(ctrl, self.size)?;
+        sirm.set_payload_transfer_count(ctrl, self.count)?;
+        sirm.set_payload_final_transfer1_size(ctrl, self.final1_size)?;
+        sirm.set_payload_final_transfer2_size(ctrl, self.final2_size)
+    }
+}
+
+/// Rounds `value` up to a multiple of `alignment`, which must be a power of two.
+fn align_up(value: u64, alignment: u64) -> u64 {
+    (value + (alignment - 1)) & !(alignment - 1)
+}
+
+/// Writes the transfer sizes of a payload of `payload_size` to `sirm`, and returns the payload
+/// transfer size in use.
+///
+/// Some devices reject the transfer sizes computed by the host with `U3V_PAYLOAD_SIZE_NOT_ALIGNED`
+/// or `GENCP_INVALID_PARAMETER` even though they can stream with their own settings. In that case,
+/// the transfer sizes are recomputed from the payload transfer size preferred by the device, i.e.
+/// the current value of the register, and written once again.
+pub(super) fn negotiate_payload_transfer<Ctrl: DeviceControl + ?Sized>(
+    sirm: &Sirm,
+    ctrl: &mut Ctrl,
+    payload_size: u64,
+    alignment: usize,
+) -> ControlResult<u32> {
+    let alignment = alignment as u64;
+    let size = align_up(PAYLOAD_TRANSFER_SIZE.into(), alignment).try_into()?;
+    let attempted = PayloadTransfer::new(size, payload_size, alignment)?;
+    let status = match attempted.write(sirm, ctrl) {
+        Ok(()) => return Ok(attempted.size),
+        Err(e) => match rejected_status(&e) {
+            Some(status) if is_payload_size_rejection(status) => status,
+            _ => return Err(e),
+        },
+    };
+
+    let preferred = sirm.payload_transfer_size(ctrl)?;
+    let rejected = |reason: String| {
+        ControlError::InvalidDevice(
+            format!(
+                "the device rejected payload transfer size {} ({}), and its preferred size {} {}",
+                attempted.size, status, preferred, reason
+            )
+            .into(),
+        )
+    };
+    if preferred == 0 {
+        return Err(rejected("is invalid".into()));
+    }
+
+    // `U3V_PAYLOAD_SIZE_NOT_ALIGNED` means the device requires coarser alignment than `SI_INFO`
+    // reports, so pad the final transfer as the preferred size is aligned.
+    let alignment = match status.kind() {
+        ack::StatusKind::UsbSpecific(ack::UsbSpecificStatus::PayloadSizeNotAligned) => {
+            alignment.max(1 << preferred.trailing_zeros())
+        }
+        _ => alignment,
+    };
+    warn!(
+        attempted = attempted.size,
+        preferred,
+        %status,
+        "the device rejected payload transfer size, fall back to its preferred size"
+    );
+
+    let fallback = PayloadTransfer::new(preferred, payload_size, alignment)?;
+    match fallback.write(sirm, ctrl) {
+        Ok(()) => Ok(fallback.size),
+        Err(e) => match rejected_status(&e) {
+            Some(status) => Err(rejected(format!("is also rejected ({})", status))),
+            None => Err(e),
+        },
+    }
+}
+
+/// Returns `true` if `status` is returned when the device doesn't accept a payload size.
+fn is_payload_size_rejection(status: ack::Status) -> bool {
+    matches!(
+        status.kind(),
+        ack::StatusKind::UsbSpecific(ack::UsbSpecificStatus::PayloadSizeNotAligned)
+            | ack::StatusKind::GenCp(ack::GenCpStatus::InvalidParameter)
+    )
+}
+
+/// Returns `true` if `id` is in the range of custom command ids, i.e. the most significant bit is
+/// set and the id is even.
+fn is_custom_command_id(id: u16) -> bool {
+    id >> 15 == 1 && id & 1 == 0
+}
+
+/// Verifies the number and lengths of `bufs` match `entries`, and the entries are in the address
+/// space.
+fn verify_read_entries(entries: &[(u64, u16)], bufs: &[&mut [u8]]) -> ControlResult<()> {
+    if entries.len() != bufs.len()
+        || entries
+            .iter()
+            .zip(bufs.iter())
+            .any(|(&(_, len), buf)| buf.len() != len as usize)
+    {
+        return Err(ControlError::InvalidData(
+            "the number and lengths of buffers must match the entries".into(),
+        ));
+    }
+    for &(address, len) in entries {
+        unwrap_or_log!(checked_address(address, len.into()));
+    }
+
+    Ok(())
+}
+
+/// Verifies `written` lengths of `WriteMemStacked` ack match `lengths` of the entries.
+///
+/// `first_index` is the index of `lengths[0]` in the entries passed by the user, and used to
+/// report the failed entry.
+fn verify_written_lengths(
+    lengths: &[u16],
+    first_index: usize,
+    written: impl IntoIterator<Item = u3v::Result<u16>>,
+) -> ControlResult<()> {
+    let mut written = written.into_iter();
+    for (i, &len) in lengths.iter().enumerate() {
+        let written = written.next().transpose()?.unwrap_or(0);
+        if written != len {
+            return Err(ControlError::PartialWrite {
+                index: first_index + i,
+                requested: len.into(),
+                written: written.into(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Deadline of a command, which is extended each time the device returns a pending ack.
+pub(super) struct Deadline {
+    at: Instant,
+    /// Total duration extended by pending acks.
+    extended: Duration,
+    /// The number of pending acks returned for the command.
+    pending_count: u16,
+}
+
+impl Deadline {
+    pub(super) fn new(timeout: Duration) -> Self {
+        Self {
+            at: Instant::now() + timeout,
+            extended: Duration::ZERO,
+            pending_count: 0,
+        }
+    }
+
+    pub(super) fn at(&self) -> Instant {
+        self.at
+    }
+
+    /// Returns the duration until the deadline, or `None` if the deadline has passed.
+    pub(super) fn remaining(&self) -> Option<Duration> {
+        self.at
+            .checked_duration_since(Instant::now())
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Returns `true` if the device has returned a pending ack for the command.
+    pub(super) fn is_pending(&self) -> bool {
+        self.pending_count > 0
+    }
+
+    /// Extends the deadline by `pending_timeout` requested by a pending ack of `request_id`.
+    ///
+    /// Returns an error if the device returns pending acks `retry_count` times, or the total
+    /// extension exceeds [`Limits::max_pending_wait`].
+    pub(super) fn extend(
+        &mut self,
+        request_id: u16,
+        pending_timeout: Duration,
+        retry_count: u16,
+        limits: &Limits,
+    ) -> ControlResult<()> {
+        limits.check_pending_timeout(pending_timeout)?;
+        self.pending_count += 1;
+        if self.pending_count >= retry_count {
+            return Err(ControlError::Io(anyhow::Error::msg(
+                "the number of times pending was returned exceeds the retry_count.",
+            )));
+        }
+        self.extended += pending_timeout;
+        limits.check_pending_wait(self.extended)?;
+
+        self.at += pending_timeout;
+        debug!(
+            request_id,
+            ?pending_timeout,
+            pending_count = self.pending_count,
+            "the device returned a pending ack, extending the deadline"
+        );
+        Ok(())
     }
 }
 
@@ -582,24 +2010,76 @@ struct ConnectionConfig {
     /// Timeout duration of each transaction between device.
     timeout_duration: Duration,
 
+    /// Timeout set by [`ControlHandle::set_default_timeout`], which is re-applied when the
+    /// handle is opened.
+    requested_timeout: Option<Duration>,
+
     /// The value determines how many times to retry when pending acknowledge is returned from the
     /// device.
     retry_count: u16,
 
+    /// The maximum number of commands outstanding in pipelined reads.
+    pipeline_depth: u16,
+
+    /// Policy to retry transactions rejected by the device.
+    retry_policy: RetryPolicy,
+
     /// Maximum length of a command sent to device from host. Unit is byte.
     maximum_cmd_length: u32,
 
     /// Maximum length of a acknowledge sent to host from device. Unit is byte.
     maximum_ack_length: u32,
+
+    /// Lets libusb detach the kernel driver bound to the interface while the handle is opened.
+    detach_kernel_driver: bool,
+
+    /// Heartbeat timeout written to the device when the handle is opened.
+    heartbeat_timeout: Option<Duration>,
+}
+
+impl ConnectionConfig {
+    /// Applies the connection related knobs of `options`.
+    fn apply(&mut self, options: &OpenOptions) {
+        self.retry_count = options.retry_count;
+        self.pipeline_depth = options.pipeline_depth;
+        self.requested_timeout = options.timeout_duration;
+        if let Some(timeout_duration) = options.timeout_duration {
+            // ABRM may not be read yet, so the timeout isn't bounded below while opening.
+            self.timeout_duration = timeout_duration;
+        }
+        self.detach_kernel_driver = options.detach_kernel_driver;
+        self.heartbeat_timeout = options.heartbeat_timeout;
+    }
+}
+
+/// Returns `timeout` raised to `floor`, i.e. the maximum device response time, with a warning if
+/// it's shorter.
+fn clamp_timeout(timeout: Duration, floor: Option<Duration>) -> Duration {
+    match floor {
+        Some(floor) if timeout < floor => {
+            warn!(
+                ?timeout,
+                maximum_device_response_time = ?floor,
+                "the timeout is shorter than the maximum device response time, use the latter"
+            );
+            floor
+        }
+        _ => timeout,
+    }
 }
 
 impl Default for ConnectionConfig {
     fn default() -> Self {
         Self {
             timeout_duration: INITIAL_TIMEOUT_DURATION,
-            retry_count: 3,
+            requested_timeout: None,
+            retry_count: DEFAULT_RETRY_COUNT,
+            pipeline_depth: DEFAULT_PIPELINE_DEPTH,
+            retry_policy: RetryPolicy::default(),
             maximum_cmd_length: INITIAL_MAXIMUM_CMD_LENGTH,
             maximum_ack_length: INITIAL_MAXIMUM_ACK_LENGTH,
+            detach_kernel_driver: false,
+            heartbeat_timeout: None,
         }
     }
 }
@@ -615,3 +2095,638 @@ impl From<ControlHandle> for Box<dyn DeviceControl> {
         Box::new(ctrl)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "emulator")]
+    use cameleon_impl::leak_check;
+
+    #[cfg(feature = "emulator")]
+    use crate::u3v::emulated::{emulated_device, open_emulated, open_emulated_with};
+
+    use super::*;
+
+    fn split_stacked_reads(
+        entries: &[(u64, u16)],
+        maximum_cmd_length: usize,
+        maximum_ack_length: usize,
+    ) -> Vec<Range<usize>> {
+        let sizes = entries
+            .iter()
+            .map(|&(_, len)| (STACKED_ENTRY_HEADER_LENGTH, len as usize));
+        split_stacked(sizes, maximum_cmd_length, maximum_ack_length)
+    }
+
+    #[test]
+    fn test_verify_custom_ack() {
+        let ack = ack::AckPacket::custom_ack(3, 0x9001, &[1, 2]).unwrap();
+        assert!(verify_ack(&ack, 3, Some(0x9001)).is_ok());
+
+        let pending = ack::AckPacket::pending_ack(3, Duration::from_millis(10)).unwrap();
+        assert!(verify_ack(&pending, 3, Some(0x9001)).is_ok());
+
+        // Request id mismatch.
+        assert!(matches!(
+            verify_ack(&ack, 4, Some(0x9001)),
+            Err(ControlError::UnexpectedAck {
+                expected_request_id: 4,
+                request_id: 3,
+                ..
+            })
+        ));
+        // Ack of another custom command.
+        assert!(matches!(
+            verify_ack(&ack, 3, Some(0x9003)),
+            Err(ControlError::UnexpectedAck {
+                expected_ack_id: 0x9003,
+                ack_id: 0x9001,
+                ..
+            })
+        ));
+        // Non custom ack.
+        let ack = ack::AckPacket::read_mem_ack(3, &[1, 2]).unwrap();
+        assert!(matches!(
+            verify_ack(&ack, 3, Some(0x9001)),
+            Err(ControlError::UnexpectedAck { ack_id: 0x0801, .. })
+        ));
+        assert!(verify_ack(&ack, 3, None).is_ok());
+        assert!(matches!(
+            verify_ack(&ack, 4, None),
+            Err(ControlError::RequestIdMismatch {
+                expected: 4,
+                actual: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_discard_stale_ack() {
+        let mut stale_acks = 0;
+        let ack = ack::AckPacket::read_mem_ack(5, &[1, 2]).unwrap();
+        assert!(!discard_stale_ack(&ack, 5, &mut stale_acks).unwrap());
+        assert_eq!(stale_acks, 0);
+
+        // Acks of the earlier requests are dropped up to `MAX_STALE_ACKS` times.
+        let stale = ack::AckPacket::read_mem_ack(4, &[3, 4]).unwrap();
+        for _ in 0..MAX_STALE_ACKS {
+            assert!(discard_stale_ack(&stale, 5, &mut stale_acks).unwrap());
+        }
+        assert!(matches!(
+            discard_stale_ack(&stale, 5, &mut stale_acks),
+            Err(ControlError::RequestIdMismatch {
+                expected: 5,
+                actual: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn test_custom_command_id() {
+        assert!(is_custom_command_id(0x8000));
+        assert!(is_custom_command_id(0xfffe));
+        assert!(!is_custom_command_id(0x8001));
+        assert!(!is_custom_command_id(0x0800));
+    }
+
+    #[test]
+    fn test_apply_open_options() {
+        let mut config = ConnectionConfig::default();
+        let options = OpenOptions::new()
+            .timeout_duration(Duration::from_millis(100))
+            .retry_count(10)
+            .pipeline_depth(2)
+            .detach_kernel_driver(true)
+            .heartbeat_timeout(Duration::from_secs(3));
+        config.apply(&options);
+        assert_eq!(config.timeout_duration, Duration::from_millis(100));
+        assert_eq!(config.requested_timeout, Some(Duration::from_millis(100)));
+        assert_eq!(config.retry_count, 10);
+        assert_eq!(config.pipeline_depth, 2);
+        assert!(config.detach_kernel_driver);
+        assert_eq!(config.heartbeat_timeout, Some(Duration::from_secs(3)));
+
+        // The default options restore the default configuration, except the timeout which is
+        // restored from the device when the handle is opened.
+        config.apply(&OpenOptions::default());
+        assert_eq!(config.requested_timeout, None);
+        assert_eq!(config.retry_count, DEFAULT_RETRY_COUNT);
+        assert_eq!(config.pipeline_depth, DEFAULT_PIPELINE_DEPTH);
+        assert!(!config.detach_kernel_driver);
+        assert!(config.heartbeat_timeout.is_none());
+    }
+
+    #[test]
+    fn test_is_stalled() {
+        let stalled: ControlError = u3v::Error::LibUsb(u3v::LibUsbError::Pipe).into();
+        assert!(is_stalled(&stalled));
+
+        let timeout: ControlError = u3v::Error::LibUsb(u3v::LibUsbError::Timeout).into();
+        assert!(!is_stalled(&timeout));
+        let io: ControlError = u3v::Error::LibUsb(u3v::LibUsbError::Io).into();
+        assert!(!is_stalled(&io));
+        assert!(!is_stalled(&ControlError::Disconnected));
+    }
+
+    #[test]
+    fn test_clamp_timeout() {
+        let floor = Duration::from_millis(200);
+        assert_eq!(
+            clamp_timeout(Duration::from_millis(100), Some(floor)),
+            floor
+        );
+        assert_eq!(
+            clamp_timeout(Duration::from_secs(5), Some(floor)),
+            Duration::from_secs(5)
+        );
+        // Without the maximum device response time, the timeout is used as is.
+        assert_eq!(
+            clamp_timeout(Duration::from_millis(100), None),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn test_deadline_extend() {
+        let limits = Limits::default();
+        let mut deadline = Deadline::new(Duration::from_millis(100));
+        let initial = deadline.at();
+        assert!(!deadline.is_pending());
+        assert!(deadline.remaining().is_some());
+
+        deadline
+            .extend(0, Duration::from_millis(500), 3, &limits)
+            .unwrap();
+        deadline
+            .extend(0, Duration::from_millis(500), 3, &limits)
+            .unwrap();
+        assert!(deadline.is_pending());
+        assert_eq!(deadline.at() - initial, Duration::from_secs(1));
+
+        // Consecutive pending acks are capped by the retry count.
+        assert!(matches!(
+            deadline.extend(0, Duration::from_millis(500), 3, &limits),
+            Err(ControlError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_deadline_pending_timeout() {
+        let limits = Limits {
+            max_pending_timeout: Duration::from_secs(1),
+            ..Limits::default()
+        };
+        let mut deadline = Deadline::new(Duration::from_millis(100));
+        let initial = deadline.at();
+        assert!(matches!(
+            deadline.extend(0, Duration::from_millis(1001), 10, &limits),
+            Err(ControlError::LimitExceeded {
+                limit: crate::limits::Limit::PendingTimeout,
+                claimed: 1001,
+                ..
+            })
+        ));
+        // The refused pending ack doesn't extend the deadline.
+        assert!(!deadline.is_pending());
+        assert_eq!(deadline.at(), initial);
+    }
+
+    #[test]
+    fn test_deadline_total_wait() {
+        let limits = Limits {
+            max_pending_wait: Duration::from_secs(1),
+            ..Limits::default()
+        };
+        let mut deadline = Deadline::new(Duration::from_millis(100));
+        deadline
+            .extend(0, Duration::from_millis(600), 10, &limits)
+            .unwrap();
+        assert!(matches!(
+            deadline.extend(0, Duration::from_millis(600), 10, &limits),
+            Err(ControlError::LimitExceeded {
+                limit: crate::limits::Limit::PendingWait,
+                ..
+            })
+        ));
+
+        let deadline = Deadline::new(Duration::ZERO);
+        assert!(deadline.remaining().is_none());
+    }
+
+    #[test]
+    fn test_split_stacked_reads() {
+        let entries = [(0x00, 4), (0x10, 4), (0x20, 8), (0x30, 4)];
+        // Every entry fits into a single command.
+        assert_eq!(split_stacked_reads(&entries, 1024, 1024), vec![0..4]);
+        // Only two entries fit into a command.
+        assert_eq!(
+            split_stacked_reads(&entries, PACKET_HEADER_LENGTH + 24, 1024),
+            vec![0..2, 2..4]
+        );
+        // Ack can contain up to 12 bytes of data.
+        assert_eq!(
+            split_stacked_reads(&entries, 1024, PACKET_HEADER_LENGTH + 12),
+            vec![0..2, 2..4]
+        );
+        // An entry which doesn't fit into an ack forms a range by itself.
+        let entries = [(0x00, 4), (0x10, 64), (0x20, 4)];
+        assert_eq!(
+            split_stacked_reads(&entries, 1024, PACKET_HEADER_LENGTH + 16),
+            vec![0..1, 1..2, 2..3]
+        );
+        assert!(split_stacked_reads(&[], 1024, 1024).is_empty());
+    }
+
+    #[test]
+    fn test_copy_stacked_data() {
+        let entries = [(0x00, 2), (0x10, 3)];
+        let (mut buf0, mut buf1) = ([0; 2], [0; 3]);
+        let mut bufs = [&mut buf0[..], &mut buf1[..]];
+        copy_stacked_data(&entries, &mut bufs, 0, &[1, 2, 3, 4, 5]).unwrap();
+        assert_eq!(buf0, [1, 2]);
+        assert_eq!(buf1, [3, 4, 5]);
+
+        // The device returns fewer bytes than requested.
+        let mut bufs = [&mut buf0[..], &mut buf1[..]];
+        match copy_stacked_data(&entries, &mut bufs, 5, &[6, 7, 8]) {
+            Err(ControlError::PartialRead {
+                index,
+                requested,
+                read,
+            }) => assert_eq!((index, requested, read), (6, 3, 1)),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(buf0, [6, 7]);
+    }
+
+    #[test]
+    fn test_split_stacked_writes() {
+        let entries: [&[u8]; 3] = [&[0; 4], &[0; 8], &[0; 64]];
+        let sizes = entries
+            .iter()
+            .map(|data| (STACKED_ENTRY_HEADER_LENGTH + data.len(), 4));
+        // The first two entries fit into a command, the last one forms a range by itself.
+        assert_eq!(
+            split_stacked(sizes.clone(), PACKET_HEADER_LENGTH + 40, 1024),
+            vec![0..2, 2..3]
+        );
+        // Ack can contain a single entry.
+        assert_eq!(
+            split_stacked(sizes, 1024, PACKET_HEADER_LENGTH + 4),
+            vec![0..1, 1..2, 2..3]
+        );
+    }
+
+    fn ok(lengths: &[u16]) -> Vec<u3v::Result<u16>> {
+        lengths.iter().copied().map(Ok).collect()
+    }
+
+    #[test]
+    fn test_verify_written_lengths() {
+        verify_written_lengths(&[4, 8], 0, ok(&[4, 8])).unwrap();
+
+        match verify_written_lengths(&[4, 8, 2], 3, ok(&[4, 6, 2])) {
+            Err(ControlError::PartialWrite {
+                index,
+                requested,
+                written,
+            }) => assert_eq!((index, requested, written), (4, 8, 6)),
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        // The device acks fewer entries than sent.
+        match verify_written_lengths(&[4, 8], 0, ok(&[4])) {
+            Err(ControlError::PartialWrite { index, written, .. }) => {
+                assert_eq!((index, written), (1, 0));
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_maximum_chunk_len() {
+        // 11 bytes are left for data, which is aligned down to 8 bytes.
+        assert_eq!(maximum_chunk_len(31, 20, usize::MAX).unwrap(), 8);
+        assert_eq!(maximum_chunk_len(usize::MAX, 12, 0xFFFF).unwrap(), 0xFFFC);
+        assert!(matches!(
+            maximum_chunk_len(23, 20, usize::MAX),
+            Err(ControlError::InvalidDevice(_))
+        ));
+    }
+
+    /// Memory of a device whose command and ack can contain 8 bytes of data at most.
+    struct SmallPacketDevice {
+        memory: Vec<u8>,
+        /// Addresses of the transactions in order.
+        transactions: Vec<u64>,
+        /// Address of the transaction where the device transfers only a half of the data.
+        short_at: Option<u64>,
+    }
+
+    impl SmallPacketDevice {
+        const MAXIMUM_DATA_LENGTH: usize = 8;
+
+        fn new() -> Self {
+            Self {
+                memory: (0..64).collect(),
+                transactions: vec![],
+                short_at: None,
+            }
+        }
+
+        fn transfer_len(&mut self, address: u64, len: usize) -> usize {
+            assert!(len <= Self::MAXIMUM_DATA_LENGTH);
+            self.transactions.push(address);
+            if self.short_at == Some(address) {
+                len / 2
+            } else {
+                len
+            }
+        }
+
+        fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<usize> {
+            let len = self.transfer_len(address, data.len());
+            let address = address as usize;
+            self.memory[address..address + len].copy_from_slice(&data[..len]);
+            Ok(len)
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<usize> {
+            let len = self.transfer_len(address, buf.len());
+            let address = address as usize;
+            buf[..len].copy_from_slice(&self.memory[address..address + len]);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_write_chunked() {
+        let max = maximum_chunk_len(
+            PACKET_HEADER_LENGTH + WRITE_MEM_ADDRESS_LENGTH + 10,
+            PACKET_HEADER_LENGTH + WRITE_MEM_ADDRESS_LENGTH,
+            u16::MAX as usize,
+        )
+        .unwrap();
+        assert_eq!(max, SmallPacketDevice::MAXIMUM_DATA_LENGTH);
+
+        let data = [0xFF; 30];
+        let mut device = SmallPacketDevice::new();
+        write_chunked(4, &data, max, |address, chunk| device.write(address, chunk)).unwrap();
+        assert_eq!(device.transactions, vec![4, 12, 20, 28]);
+        assert_eq!(&device.memory[4..34], &data[..]);
+        assert_eq!(device.memory[34], 34);
+
+        // The write is aborted at the failed chunk.
+        let mut device = SmallPacketDevice::new();
+        device.short_at = Some(20);
+        match write_chunked(4, &data, max, |address, chunk| device.write(address, chunk)) {
+            Err(ControlError::PartialChunkWrite {
+                offset,
+                requested,
+                written,
+            }) => assert_eq!((offset, requested, written), (16, 8, 4)),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(device.transactions, vec![4, 12, 20]);
+    }
+
+    #[test]
+    fn test_read_chunked() {
+        let max =
+            maximum_chunk_len(PACKET_HEADER_LENGTH + 10, PACKET_HEADER_LENGTH, 0xFFFF).unwrap();
+        assert_eq!(max, SmallPacketDevice::MAXIMUM_DATA_LENGTH);
+
+        let mut buf = [0; 30];
+        let mut device = SmallPacketDevice::new();
+        read_chunked(4, &mut buf, max, |address, chunk| {
+            device.read(address, chunk)
+        })
+        .unwrap();
+        assert_eq!(device.transactions, vec![4, 12, 20, 28]);
+        assert_eq!(&buf[..], &device.memory[4..34]);
+
+        // The read is aborted at the failed chunk.
+        let mut device = SmallPacketDevice::new();
+        device.short_at = Some(12);
+        match read_chunked(4, &mut buf, max, |address, chunk| {
+            device.read(address, chunk)
+        }) {
+            Err(ControlError::PartialChunkRead {
+                offset,
+                requested,
+                read,
+            }) => assert_eq!((offset, requested, read), (8, 8, 4)),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(device.transactions, vec![4, 12]);
+    }
+
+    /// Reads the serial number in ABRM of the device.
+    #[cfg(feature = "emulator")]
+    fn read_serial_number(handle: &mut ControlHandle) -> String {
+        let (address, len) = u3v::register_map::abrm::SERIAL_NUMBER;
+        let mut buf = vec![0; len as usize];
+        handle.read(address, &mut buf).unwrap();
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        String::from_utf8(buf[..len].to_vec()).unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "emulator")]
+    fn test_emulated_custom_command() {
+        let mut handle = open_emulated("U3VCUST1");
+
+        // The emulator implements no custom command, the rejection is reported with its status.
+        let mut response = [0; 16];
+        let err = handle
+            .custom_command(0x9000, &[1, 2, 3, 4], &mut response)
+            .unwrap_err();
+        assert_eq!(
+            rejected_status(&err).map(|status| *status.kind()),
+            Some(ack::StatusKind::GenCp(ack::GenCpStatus::NotImplemented))
+        );
+
+        // Ids which aren't custom command ids are rejected without sending a command.
+        assert!(matches!(
+            handle.custom_command(0x0800, &[], &mut response),
+            Err(ControlError::InvalidData(_))
+        ));
+
+        // The following transactions still match their acks.
+        assert_eq!(read_serial_number(&mut handle), "U3VCUST1");
+        handle.close().unwrap();
+        leak_check::assert_clean();
+    }
+
+    #[test]
+    #[cfg(feature = "emulator")]
+    fn test_emulated_write_mem_stacked() {
+        let (name, _) = u3v::register_map::abrm::USER_DEFINED_NAME;
+        let (serial, _) = u3v::register_map::abrm::SERIAL_NUMBER;
+        let entries: &[(u64, &[u8])] = &[(name, b"sta"), (name + 3, b"cked"), (name + 7, b"\0")];
+
+        let mut handle = open_emulated("U3VSTCK1");
+        assert_eq!(handle.write_mem_stacked(entries).unwrap(), vec![3, 4, 1]);
+        let mut buf = [0; 8];
+        handle.read(name, &mut buf).unwrap();
+        assert_eq!(&buf, b"stacked\0");
+        let report = handle.latency_report();
+        assert_eq!(report.stacked.count, 1);
+        assert_eq!(report.write.count, 0);
+
+        // The rejection of the read only entry fails the whole command.
+        let err = handle
+            .write_mem_stacked(&[(name, b"x"), (serial, b"x")])
+            .unwrap_err();
+        assert_eq!(
+            rejected_status(&err).map(|status| *status.kind()),
+            Some(ack::StatusKind::GenCp(ack::GenCpStatus::WriteProtect))
+        );
+        assert_eq!(read_serial_number(&mut handle), "U3VSTCK1");
+        handle.close().unwrap();
+        leak_check::assert_clean();
+
+        // Entries are written one by one if the device doesn't support stacked commands.
+        let builder = cameleon_device::emulator::EmulatorBuilder::new().stacked_commands(false);
+        let mut handle = open_emulated_with(builder, "U3VSTCK2");
+        assert_eq!(handle.write_mem_stacked(entries).unwrap(), vec![3, 4, 1]);
+        handle.read(name, &mut buf).unwrap();
+        assert_eq!(&buf, b"stacked\0");
+        let report = handle.latency_report();
+        assert_eq!(report.stacked.count, 0);
+        assert_eq!(report.write.count, 3);
+        handle.close().unwrap();
+        leak_check::assert_clean();
+    }
+
+    #[test]
+    #[cfg(feature = "emulator")]
+    fn test_emulated_read_pipelined() {
+        use cameleon_device::emulator::EmulatorBuilder;
+
+        /// Reads a burst of 20 registers and returns the elapsed time.
+        fn read_burst(handle: &mut ControlHandle) -> Duration {
+            let (address, _) = u3v::register_map::abrm::SERIAL_NUMBER;
+            let entries: Vec<_> = (0..20).map(|i| (address + i % 8, 1)).collect();
+            let mut data = vec![[0; 1]; entries.len()];
+            let mut bufs: Vec<&mut [u8]> = data.iter_mut().map(|buf| &mut buf[..]).collect();
+
+            let started = Instant::now();
+            handle.read_pipelined(&entries, &mut bufs).unwrap();
+            let elapsed = started.elapsed();
+            let read: Vec<_> = data.iter().map(|buf| buf[0]).collect();
+            assert_eq!(read, b"U3VPIPE1U3VPIPE1U3VP");
+            elapsed
+        }
+
+        let builder = EmulatorBuilder::new()
+            .command_queue_depth(2)
+            .command_latency(Duration::from_millis(5));
+        let mut handle = open_emulated_with(builder, "U3VPIPE1");
+        let serial = read_burst(&mut handle);
+
+        // Two commands are processed at once.
+        handle
+            .open_with(&OpenOptions::new().pipeline_depth(2))
+            .unwrap();
+        let pipelined = read_burst(&mut handle);
+        assert_eq!(handle.pipeline_depth(), 2);
+        assert!(
+            pipelined * 4 < serial * 3,
+            "pipelined: {:?}, serial: {:?}",
+            pipelined,
+            serial
+        );
+
+        // The device answers `Busy` to the second command, so the handle falls back to serial
+        // transactions.
+        emulated_device("U3VPIPE1")
+            .set_command_queue_depth(1)
+            .unwrap();
+        read_burst(&mut handle);
+        assert_eq!(handle.pipeline_depth(), 1);
+        handle.close().unwrap();
+        leak_check::assert_clean();
+    }
+
+    #[test]
+    #[cfg(feature = "emulator")]
+    fn test_emulated_pending_ack() {
+        use cameleon_device::fixture::FaultKind;
+
+        let mut handle = open_emulated("U3VPEND1");
+        let device = emulated_device("U3VPEND1");
+
+        // The pending ack extends the deadline, and the final ack is returned.
+        device.inject_fault(FaultKind::Pending).unwrap();
+        assert_eq!(read_serial_number(&mut handle), "U3VPEND1");
+
+        // The command fails if the device requests a longer wait than allowed.
+        let limits = Limits {
+            max_pending_wait: Duration::from_millis(10),
+            ..Limits::default()
+        };
+        handle
+            .open_with(&OpenOptions::new().limits(limits))
+            .unwrap();
+        device.inject_fault(FaultKind::Pending).unwrap();
+        let (address, len) = u3v::register_map::abrm::SERIAL_NUMBER;
+        let mut buf = vec![0; len as usize];
+        assert!(matches!(
+            handle.read(address, &mut buf),
+            Err(ControlError::LimitExceeded {
+                limit: crate::limits::Limit::PendingWait,
+                ..
+            })
+        ));
+
+        // The final ack arriving late is discarded by the next transaction.
+        assert_eq!(read_serial_number(&mut handle), "U3VPEND1");
+        handle.close().unwrap();
+        leak_check::assert_clean();
+    }
+
+    #[test]
+    #[cfg(feature = "emulator")]
+    fn test_emulated_sbrm() {
+        use cameleon_device::emulator::EmulatorBuilder;
+
+        let transactions = |handle: &ControlHandle| {
+            let report = handle.latency_report();
+            report.read.count + report.stacked.count
+        };
+
+        let mut handle = open_emulated("U3VSBRM1");
+        let sbrm = handle.sbrm().unwrap();
+        assert_eq!(sbrm.u3v_version(), semver::Version::new(1, 0, 0));
+        assert_eq!(sbrm.maximum_command_transfer_length(), 1024);
+        assert_eq!(sbrm.maximum_acknowledge_trasfer_length(), 1024);
+        assert_eq!(sbrm.number_of_stream_channel(), 1);
+        assert!(sbrm.sirm_address().is_some());
+        assert!(sbrm.eirm_address().is_some());
+        assert_eq!(sbrm.iidc2_address(), None);
+        let eirm = handle.eirm().unwrap();
+        assert_eq!(
+            eirm.maximum_event_transfer_length(&mut handle).unwrap(),
+            1024
+        );
+
+        // The static registers are read only once.
+        let before = transactions(&handle);
+        handle.sbrm().unwrap();
+        assert_eq!(transactions(&handle), before);
+        handle.close().unwrap();
+        leak_check::assert_clean();
+
+        // A device without the event interface.
+        let builder = EmulatorBuilder::new().event_interface(false);
+        let mut handle = open_emulated_with(builder, "U3VSBRM2");
+        let sbrm = handle.sbrm().unwrap();
+        assert!(sbrm.sirm_address().is_some());
+        assert_eq!(sbrm.eirm_address(), None);
+        assert_eq!(sbrm.eirm_length(), None);
+        assert!(sbrm.eirm().is_none());
+        assert!(matches!(handle.eirm(), Err(ControlError::NotSupported(_))));
+        handle.close().unwrap();
+        leak_check::assert_clean();
+    }
+}
@@ -0,0 +1,531 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module provides diagnostics of U3V cameras, which are designed to back a debugging
+//! command line tool.
+//!
+//! Each function opens the camera specified by its serial number with [`DiagOptions::open`], so
+//! that the diagnostics reflect the behavior of the application which uses the same
+//! [`OpenOptions`]. The functions only read from the device unless
+//! [`DiagOptions::allow_writes`] is set.
+//!
+//! All reports implement `serde::Serialize` when `serde` feature is enabled.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use cameleon::u3v::diag::{self, DiagOptions};
+//!
+//! let options = DiagOptions::new();
+//! for device in diag::list_devices().unwrap() {
+//!     let abrm = diag::dump_abrm(&device.serial_number, &options).unwrap();
+//!     println!("{:#?}", abrm);
+//! }
+//! ```
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use sha1::{Digest, Sha1};
+
+use super::{enumerate_cameras, ControlHandle, HostStreamStatistics, OpenOptions, StreamHandle};
+use crate::{
+    payload::PayloadReceiver, CameleonError, Camera, ControlResult, DeviceControl, StreamError,
+};
+
+/// A specialized `Result` type for diagnostics.
+pub type DiagResult<T> = std::result::Result<T, DiagError>;
+
+/// An error type returned from the diagnostics.
+#[derive(Debug, thiserror::Error)]
+pub enum DiagError {
+    /// No camera has the serial number.
+    #[error("no camera is found with serial number `{0}`")]
+    NotFound(String),
+
+    /// The operation writes to the device, but [`DiagOptions::allow_writes`] isn't set.
+    #[error("`{0}` writes to the device, set `DiagOptions::allow_writes` to perform it")]
+    WriteNotAllowed(&'static str),
+
+    /// An error from the camera.
+    #[error(transparent)]
+    CameleonError(#[from] CameleonError),
+
+    /// An error while writing a file.
+    #[error("input/output error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Options of the diagnostics.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiagOptions {
+    open: OpenOptions,
+    allow_writes: bool,
+}
+
+impl DiagOptions {
+    /// Constructs read-only options which open the camera with default [`OpenOptions`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets options to open the camera, including [`super::Quirks`] of the device.
+    #[must_use]
+    pub fn open(mut self, options: OpenOptions) -> Self {
+        self.open = options;
+        self
+    }
+
+    /// Allows the diagnostics which write to the device, i.e. [`write_register`] and
+    /// [`stream_probe`].
+    #[must_use]
+    pub fn allow_writes(mut self, allow: bool) -> Self {
+        self.allow_writes = allow;
+        self
+    }
+
+    fn check_writable(&self, operation: &'static str) -> DiagResult<()> {
+        if self.allow_writes {
+            Ok(())
+        } else {
+            Err(DiagError::WriteNotAllowed(operation))
+        }
+    }
+}
+
+/// A camera connected to the host, see [`list_devices`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct DeviceReport {
+    /// Vendor name of the camera.
+    pub vendor_name: String,
+    /// Model name of the camera.
+    pub model_name: String,
+    /// Serial number of the camera.
+    pub serial_number: String,
+}
+
+/// Contents of the technology agnostic bootstrap register map, see [`dump_abrm`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct AbrmReport {
+    /// `GenCP` version of the device.
+    pub gencp_version: String,
+    /// Manufacture name of the device.
+    pub manufacturer_name: String,
+    /// Model name of the device.
+    pub model_name: String,
+    /// Family name of the device, `None` if the device doesn't support it.
+    pub family_name: Option<String>,
+    /// Device version.
+    pub device_version: String,
+    /// Manufacturer info of the device.
+    pub manufacturer_info: String,
+    /// Serial number of the device.
+    pub serial_number: String,
+    /// User defined name of the device, `None` if the device doesn't support it.
+    pub user_defined_name: Option<String>,
+    /// Device software interface version, `None` if the device doesn't support it.
+    pub device_software_interface_version: Option<String>,
+    /// Maximum device response time.
+    pub maximum_device_response_time: Duration,
+    /// Address of the manifest table.
+    pub manifest_table_address: u64,
+    /// Address of `Sbrm`.
+    pub sbrm_address: u64,
+    /// Time stamp increment in ns/tick.
+    pub timestamp_increment: u64,
+    /// Whether multi event is supported.
+    pub multi_event_supported: bool,
+    /// Whether stacked commands are supported.
+    pub stacked_commands_supported: bool,
+}
+
+impl AbrmReport {
+    /// Reads the report from `ctrl` without writing to the device.
+    ///
+    /// The timestamp is omitted because latching it requires a write.
+    pub fn read<Ctrl: DeviceControl + ?Sized>(ctrl: &mut Ctrl) -> ControlResult<Self> {
+        let abrm = super::register_map::Abrm::new(ctrl)?;
+        let capability = abrm.device_capability()?;
+
+        Ok(Self {
+            gencp_version: abrm.gencp_version().to_string(),
+            manufacturer_name: abrm.manufacturer_name().into(),
+            model_name: abrm.model_name().into(),
+            family_name: abrm.family_name().map(Into::into),
+            device_version: abrm.device_version().into(),
+            manufacturer_info: abrm.manufacturer_info().into(),
+            serial_number: abrm.serial_number().into(),
+            user_defined_name: abrm.user_defined_name().map(Into::into),
+            device_software_interface_version: abrm
+                .device_software_interface_version()
+                .map(Into::into),
+            maximum_device_response_time: abrm.maximum_device_response_time(),
+            manifest_table_address: abrm.manifest_table_address(),
+            sbrm_address: abrm.sbrm_address(),
+            timestamp_increment: abrm.timestamp_increment(),
+            multi_event_supported: capability.is_multi_event_supported(),
+            stacked_commands_supported: capability.is_stacked_commands_supported(),
+        })
+    }
+}
+
+/// `GenApi` xml saved to a file, see [`download_xml`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct XmlReport {
+    /// Path of the saved file.
+    pub path: PathBuf,
+    /// Size of the xml in bytes.
+    pub size: usize,
+    /// Sha1 hash of the xml in hex.
+    pub sha1: String,
+}
+
+impl XmlReport {
+    /// Retrieves the `GenApi` xml from `ctrl` and saves it to `path`.
+    pub fn save<Ctrl: DeviceControl + ?Sized>(
+        ctrl: &mut Ctrl,
+        path: impl AsRef<Path>,
+    ) -> DiagResult<Self> {
+        let path = path.as_ref();
+        let xml = ctrl.genapi().map_err(CameleonError::from)?;
+        fs::write(path, &xml)?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: xml.len(),
+            sha1: Sha1::digest(xml.as_bytes())
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+        })
+    }
+}
+
+/// Bytes of the device memory, see [`read_register`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RegisterReport {
+    /// Address of the first byte.
+    pub address: u64,
+    /// Bytes read from or written to the device.
+    pub data: Vec<u8>,
+}
+
+impl RegisterReport {
+    /// Reads `len` bytes at `address` from `ctrl`.
+    pub fn read<Ctrl: DeviceControl + ?Sized>(
+        ctrl: &mut Ctrl,
+        address: u64,
+        len: usize,
+    ) -> ControlResult<Self> {
+        let mut data = vec![0; len];
+        ctrl.read(address, &mut data)?;
+        Ok(Self { address, data })
+    }
+}
+
+/// Statistics of the payloads received in [`stream_probe`].
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StreamProbeReport {
+    /// Duration of the probe.
+    pub duration: Duration,
+    /// The number of payloads received, including incomplete ones.
+    pub received_payloads: u64,
+    /// The number of received payloads which are incomplete.
+    pub incomplete_payloads: u64,
+    /// The number of payloads which failed to be received.
+    pub failed_payloads: u64,
+    /// The total size of the received payloads in bytes.
+    pub received_bytes: u64,
+    /// Received payloads per second.
+    pub frame_rate: f64,
+    /// Host side statistics of the stream handle at the end of the probe.
+    pub host_statistics: HostStreamStatistics,
+}
+
+impl StreamProbeReport {
+    /// Receives payloads from `rx` during `duration` and summarizes them.
+    ///
+    /// [`StreamProbeReport::host_statistics`] is left as default.
+    pub fn collect(rx: &PayloadReceiver, duration: Duration) -> Self {
+        let mut report = Self {
+            duration,
+            ..Self::default()
+        };
+
+        let start = Instant::now();
+        while start.elapsed() < duration {
+            match rx.try_recv() {
+                Ok(payload) => {
+                    report.received_payloads += 1;
+                    if payload.is_incomplete() {
+                        report.incomplete_payloads += 1;
+                    }
+                    report.received_bytes += payload.payload().len() as u64;
+                    rx.send_back(payload);
+                }
+                Err(StreamError::ReceiveError(_)) => thread::sleep(Duration::from_millis(1)),
+                Err(_) => report.failed_payloads += 1,
+            }
+        }
+
+        let secs = duration.as_secs_f64();
+        if secs > 0.0 {
+            report.frame_rate = report.received_payloads as f64 / secs;
+        }
+        report
+    }
+}
+
+/// Lists cameras connected to the host without opening them.
+pub fn list_devices() -> DiagResult<Vec<DeviceReport>> {
+    Ok(enumerate_cameras()?
+        .iter()
+        .map(|camera| {
+            let info = camera.info();
+            DeviceReport {
+                vendor_name: info.vendor_name.clone(),
+                model_name: info.model_name.clone(),
+                serial_number: info.serial_number.clone(),
+            }
+        })
+        .collect())
+}
+
+/// Reads the technology agnostic bootstrap register map of the camera.
+pub fn dump_abrm(serial_number: &str, options: &DiagOptions) -> DiagResult<AbrmReport> {
+    let mut camera = open(serial_number, options)?;
+    let report = AbrmReport::read(&mut camera.ctrl).map_err(CameleonError::from)?;
+    camera.close()?;
+    Ok(report)
+}
+
+/// Retrieves the `GenApi` xml of the camera and saves it to `path`.
+pub fn download_xml(
+    serial_number: &str,
+    path: impl AsRef<Path>,
+    options: &DiagOptions,
+) -> DiagResult<XmlReport> {
+    let mut camera = open(serial_number, options)?;
+    let report = XmlReport::save(&mut camera.ctrl, path)?;
+    camera.close()?;
+    Ok(report)
+}
+
+/// Reads `len` bytes at `address` of the camera.
+pub fn read_register(
+    serial_number: &str,
+    address: u64,
+    len: usize,
+    options: &DiagOptions,
+) -> DiagResult<RegisterReport> {
+    let mut camera = open(serial_number, options)?;
+    let report =
+        RegisterReport::read(&mut camera.ctrl, address, len).map_err(CameleonError::from)?;
+    camera.close()?;
+    Ok(report)
+}
+
+/// Writes `data` at `address` of the camera.
+///
+/// Returns [`DiagError::WriteNotAllowed`] unless [`DiagOptions::allow_writes`] is set.
+pub fn write_register(
+    serial_number: &str,
+    address: u64,
+    data: &[u8],
+    options: &DiagOptions,
+) -> DiagResult<RegisterReport> {
+    options.check_writable("write_register")?;
+
+    let mut camera = open(serial_number, options)?;
+    camera
+        .ctrl
+        .write(address, data)
+        .map_err(CameleonError::from)?;
+    camera.close()?;
+    Ok(RegisterReport {
+        address,
+        data: data.to_vec(),
+    })
+}
+
+/// Streams payloads from the camera for `seconds` and reports the statistics.
+///
+/// Returns [`DiagError::WriteNotAllowed`] unless [`DiagOptions::allow_writes`] is set, because
+/// starting acquisition writes to the device.
+pub fn stream_probe(
+    serial_number: &str,
+    seconds: u64,
+    options: &DiagOptions,
+) -> DiagResult<StreamProbeReport> {
+    options.check_writable("stream_probe")?;
+
+    let mut camera = open(serial_number, options)?;
+    camera.load_context()?;
+    let rx = camera.start_streaming(3)?;
+    let mut report = StreamProbeReport::collect(&rx, Duration::from_secs(seconds));
+    camera.stop_streaming()?;
+    report.host_statistics = camera.strm.statistics();
+    camera.close()?;
+    Ok(report)
+}
+
+fn open(
+    serial_number: &str,
+    options: &DiagOptions,
+) -> DiagResult<Camera<ControlHandle, StreamHandle>> {
+    let mut camera = enumerate_cameras()?
+        .into_iter()
+        .find(|camera| camera.info().serial_number == serial_number)
+        .ok_or_else(|| DiagError::NotFound(serial_number.to_string()))?;
+    camera.open_with(&options.open)?;
+    Ok(camera)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payload::{channel, test_payload, PayloadType};
+    use cameleon_device::u3v::register_map::abrm;
+
+    /// Device memory which records writes.
+    struct Memory {
+        mem: Vec<u8>,
+        writes: usize,
+    }
+
+    impl Memory {
+        fn new() -> Self {
+            let mut mem = vec![0; 0x1000];
+            let mut put = |(addr, _): (u64, u16), data: &[u8]| {
+                let addr = addr as usize;
+                mem[addr..addr + data.len()].copy_from_slice(data);
+            };
+            put(abrm::GENCP_VERSION, &0x0001_0003_u32.to_le_bytes());
+            put(abrm::MANUFACTURER_NAME, b"cameleon\0");
+            put(abrm::MODEL_NAME, b"emulated\0");
+            put(abrm::SERIAL_NUMBER, b"CAM0001\0");
+            put(abrm::SBRM_ADDRESS, &0x800_u64.to_le_bytes());
+            // User defined name and stacked commands.
+            put(abrm::DEVICE_CAPABILITY, &(1_u64 | 1 << 13).to_le_bytes());
+            put(abrm::USER_DEFINED_NAME, b"bench\0");
+            put(abrm::MAXIMUM_DEVICE_RESPONSE_TIME, &200_u32.to_le_bytes());
+            Self { mem, writes: 0 }
+        }
+    }
+
+    impl DeviceControl for Memory {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            let address = address as usize;
+            buf.copy_from_slice(&self.mem[address..address + buf.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, _address: u64, _data: &[u8]) -> ControlResult<()> {
+            self.writes += 1;
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            Ok("<RegisterDescription/>".into())
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_abrm_report() {
+        let mut ctrl = Memory::new();
+        let report = AbrmReport::read(&mut ctrl).unwrap();
+
+        assert_eq!(report.gencp_version, "1.3.0");
+        assert_eq!(report.manufacturer_name, "cameleon");
+        assert_eq!(report.model_name, "emulated");
+        assert_eq!(report.serial_number, "CAM0001");
+        assert_eq!(report.family_name, None);
+        assert_eq!(report.user_defined_name.as_deref(), Some("bench"));
+        assert_eq!(report.sbrm_address, 0x800);
+        assert_eq!(
+            report.maximum_device_response_time,
+            Duration::from_millis(200)
+        );
+        assert!(report.stacked_commands_supported);
+        assert!(!report.multi_event_supported);
+        assert_eq!(ctrl.writes, 0);
+    }
+
+    #[test]
+    fn test_register_and_xml_report() {
+        let mut ctrl = Memory::new();
+        let register = RegisterReport::read(&mut ctrl, abrm::MODEL_NAME.0, 8).unwrap();
+        assert_eq!(register.address, abrm::MODEL_NAME.0);
+        assert_eq!(register.data, b"emulated");
+
+        let path = std::env::temp_dir().join(format!("cameleon-diag-{}.xml", std::process::id()));
+        let xml = XmlReport::save(&mut ctrl, &path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "<RegisterDescription/>");
+        assert_eq!(xml.size, 22);
+        assert_eq!(xml.sha1.len(), 40);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(ctrl.writes, 0);
+    }
+
+    #[test]
+    fn test_writes_require_flag() {
+        let options = DiagOptions::new();
+        assert!(matches!(
+            write_register("CAM0001", 0, &[0], &options),
+            Err(DiagError::WriteNotAllowed("write_register"))
+        ));
+        assert!(matches!(
+            stream_probe("CAM0001", 1, &options),
+            Err(DiagError::WriteNotAllowed("stream_probe"))
+        ));
+    }
+
+    #[test]
+    fn test_stream_probe_report() {
+        let (tx, rx) = channel(8, 8);
+        for i in 0..4 {
+            let mut payload = test_payload(PayloadType::Chunk, None, vec![0; 16]);
+            payload.id = i;
+            tx.try_send(Ok(payload)).unwrap();
+        }
+        tx.try_send(Err(StreamError::Timeout)).unwrap();
+
+        let report = StreamProbeReport::collect(&rx, Duration::from_millis(50));
+        assert_eq!(report.received_payloads, 4);
+        assert_eq!(report.incomplete_payloads, 0);
+        assert_eq!(report.failed_payloads, 1);
+        assert_eq!(report.received_bytes, 64);
+        assert!((report.frame_rate - 80.0).abs() < f64::EPSILON);
+    }
+}
@@ -0,0 +1,43 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Helpers to test the handles against emulated devices.
+
+use cameleon_device::emulator::{self, EmulatorBuilder};
+
+use crate::{camera::PayloadStream, DeviceControl};
+
+use super::{ControlHandle, StreamHandle};
+
+/// Builds an emulated device of `serial` and opens a handle over it.
+pub(super) fn open_emulated(serial: &str) -> ControlHandle {
+    open_emulated_with(EmulatorBuilder::new(), serial)
+}
+
+/// Builds an emulated device of `serial` from `builder` and opens a handle over it.
+pub(super) fn open_emulated_with(builder: EmulatorBuilder, serial: &str) -> ControlHandle {
+    builder.serial_number(serial).unwrap().build();
+    let device = emulated_device(serial);
+    let mut handle = ControlHandle::new_emulated(&device).unwrap();
+    handle.open().unwrap();
+    handle
+}
+
+/// Opens a stream handle over the emulated device of `serial`, which is built beforehand, e.g.
+/// by [`open_emulated`].
+pub(super) fn open_emulated_stream(serial: &str) -> StreamHandle {
+    let device = emulated_device(serial);
+    let mut strm = StreamHandle::new_emulated(&device).unwrap().unwrap();
+    strm.open().unwrap();
+    strm
+}
+
+/// Finds the emulated device of `serial`.
+pub(super) fn emulated_device(serial: &str) -> emulator::Device {
+    emulator::enumerate_devices()
+        .unwrap()
+        .into_iter()
+        .find(|device| device.device_info.serial_number == serial)
+        .unwrap()
+}
@@ -0,0 +1,781 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains [`EventReceiver`], which receives events sent from `U3V` device through
+//! the event channel.
+//!
+//! The device sends events only while the event enable flag of [`Eirm`] is set. Each transfer
+//! from the event endpoint carries a `GenCP` event packet, which may contain several events.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::Duration,
+};
+
+use async_std::channel::{self, Receiver, TrySendError};
+use cameleon_device::u3v::{
+    self,
+    protocol::{ack, event::EventPacket},
+};
+use cameleon_impl::leak_check::{Resource, Tracked};
+use futures_channel::oneshot;
+use tracing::{error, info, warn};
+
+use crate::{
+    limits::Limits,
+    metrics::{self, MetricSink, MetricSource},
+    ControlError, ControlResult, DeviceControl, StreamError, StreamResult,
+};
+
+use super::{
+    async_read::BulkIn, control_handle::rejected_status, register_map::Eirm,
+    stream_handle::LoopThread, thread::ThreadConfig, ControlHandle,
+};
+
+/// Default name of the thread which receives event packets.
+const DEFAULT_EVENT_THREAD_NAME: &str = "cameleon-u3v-event";
+
+/// Timeout of each transfer from the event endpoint, the loop checks the cancellation at least
+/// this often.
+const EVENT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Length of the prefix, the `CCD` and the header of an event, a transfer shorter than this
+/// can't carry any event.
+const MINIMUM_EVENT_TRANSFER_LENGTH: usize = 24;
+
+/// An event sent from the device.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceEvent {
+    /// Id of the event, which is referred to as `EventID` in `GenApi` context.
+    pub id: u16,
+    /// Timestamp of the device when the event occurred, in nanoseconds.
+    pub timestamp: u64,
+    /// Data attached to the event, empty if the event has no data.
+    pub data: Vec<u8>,
+}
+
+/// Counters of the event loop, which are registered as metrics of the device.
+#[derive(Debug, Default)]
+struct EventCounters {
+    received_events: AtomicU64,
+    /// Events dropped because the channel returned by [`EventReceiver::start`] is full.
+    dropped_events: AtomicU64,
+    /// Packets skipped because they are invalid or exceed the maximum event transfer length.
+    invalid_packets: AtomicU64,
+}
+
+impl MetricSource for EventCounters {
+    fn collect(&self, sink: &mut MetricSink<'_>) {
+        sink.counter(
+            "cameleon_event_received_events_total",
+            self.received_events.load(Ordering::Relaxed),
+        );
+        sink.counter(
+            "cameleon_event_dropped_events_total",
+            self.dropped_events.load(Ordering::Relaxed),
+        );
+        sink.counter(
+            "cameleon_event_invalid_packets_total",
+            self.invalid_packets.load(Ordering::Relaxed),
+        );
+    }
+}
+
+/// This type is used to receive events from the event channel of the device.
+///
+/// The receiver runs a loop on its own thread, which reads event packets from the event endpoint
+/// and delivers the events through the channel returned by [`EventReceiver::start`], or the
+/// callback passed to [`EventReceiver::start_with_callback`].
+///
+/// The numbers of received events, dropped events and invalid packets are registered as metrics,
+/// see [`metrics`](crate::metrics).
+///
+/// # Examples
+///
+/// ```no_run
+/// use cameleon::u3v;
+///
+/// let mut cameras = u3v::enumerate_cameras().unwrap();
+/// let mut camera = cameras.pop().unwrap();
+/// camera.open().unwrap();
+///
+/// let serial = camera.info().serial_number.clone();
+/// let mut events = u3v::EventReceiver::open_by_serial(&serial).unwrap().unwrap();
+/// let event_rx = events.start(&mut camera.ctrl, 16).unwrap();
+/// let event = async_std::task::block_on(event_rx.recv()).unwrap();
+/// println!("event {:#x} at {}", event.id, event.timestamp);
+///
+/// events.stop(&mut camera.ctrl).unwrap();
+/// ```
+pub struct EventReceiver {
+    /// Inner channel to receive event packets.
+    inner: Arc<Mutex<u3v::ReceiveChannel>>,
+    /// Configuration of the thread running the loop.
+    thread: ThreadConfig,
+    /// Thread running the loop.
+    loop_thread: Option<LoopThread>,
+    /// Accounts the channel as opened.
+    tracked: Option<Tracked>,
+    counters: Arc<EventCounters>,
+}
+
+impl EventReceiver {
+    /// Opens the event channel of the device whose serial number is exactly `serial`.
+    ///
+    /// `None` is returned if no such device is connected, or the device doesn't have the event
+    /// interface.
+    pub fn open_by_serial(serial: &str) -> ControlResult<Option<Self>> {
+        match u3v::Device::open_by_serial(serial)? {
+            Some(device) => Self::new(&device),
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the configuration of the thread, which takes effect from the next start.
+    pub fn set_thread_config(&mut self, config: ThreadConfig) {
+        self.thread = config;
+    }
+
+    /// Enables events and starts the loop, the events are delivered through the returned
+    /// channel which holds up to `cap` events.
+    ///
+    /// Events arriving while the channel is full are dropped. The channel is closed once the
+    /// loop stops.
+    ///
+    /// # Errors
+    ///
+    /// [`StreamError::InStreaming`] is returned if the loop is already running.
+    pub fn start(
+        &mut self,
+        ctrl: &mut ControlHandle,
+        cap: usize,
+    ) -> StreamResult<Receiver<DeviceEvent>> {
+        let (tx, rx) = channel::bounded(cap);
+        let counters = self.counters.clone();
+        self.start_with_callback(ctrl, move |event| match tx.try_send(event) {
+            Ok(()) | Err(TrySendError::Closed(_)) => {}
+            Err(TrySendError::Full(event)) => {
+                counters.dropped_events.fetch_add(1, Ordering::Relaxed);
+                warn!(id = event.id, "event channel is full, drop the event");
+            }
+        })?;
+        Ok(rx)
+    }
+
+    /// Enables events and starts the loop, `f` is called on the thread of the loop for each
+    /// event.
+    ///
+    /// If the device rejects enabling events because the event endpoint is halted, the halt is
+    /// cleared and enabling is retried once.
+    ///
+    /// # Errors
+    ///
+    /// * [`StreamError::InStreaming`] is returned if the loop is already running.
+    /// * [`StreamError::Io`] is returned if the maximum event transfer length of the device
+    ///   exceeds [`Limits::max_allocation`] of `ctrl`.
+    pub fn start_with_callback<F>(&mut self, ctrl: &mut ControlHandle, f: F) -> StreamResult<()>
+    where
+        F: FnMut(DeviceEvent) + Send + 'static,
+    {
+        if self.is_running() {
+            return Err(StreamError::InStreaming);
+        }
+
+        let eirm = ctrl
+            .eirm()
+            .map_err(|e| control_error("failed to locate EIRM", &e))?;
+        let limits = ctrl.limits();
+        let buf = event_buffer(eirm, ctrl, &limits)?;
+
+        self.lock()?.open().map_err(|e| {
+            error!(?e);
+            StreamError::from(e)
+        })?;
+        if self.tracked.is_none() {
+            self.tracked = Some(Tracked::new(Resource::Channel));
+        }
+
+        let inner = self.inner.clone();
+        enable_event(eirm, ctrl, || {
+            lock(&inner)?.clear_halt().map_err(StreamError::from)
+        })?;
+
+        let event_loop = EventLoop {
+            inner: self.inner.clone(),
+            buf,
+            deliver: f,
+            counters: self.counters.clone(),
+        };
+        match LoopThread::spawn(&self.thread, move |cancellation_rx| {
+            event_loop.run(cancellation_rx)
+        }) {
+            Ok(loop_thread) => self.loop_thread = Some(loop_thread),
+            Err(e) => {
+                eirm.disable_event(ctrl).ok();
+                return Err(StreamError::Io(e.into()));
+            }
+        }
+
+        info!(thread = %self.thread.name, "start event loop successfully");
+        Ok(())
+    }
+
+    /// Disables events, then stops the loop.
+    ///
+    /// Pending transfers are cancelled before the loop finishes, and the channel returned by
+    /// [`Self::start`] is closed after that. The loop is stopped even if disabling events
+    /// fails.
+    pub fn stop(&mut self, ctrl: &mut ControlHandle) -> StreamResult<()> {
+        let disabled = ctrl.eirm().and_then(|eirm| eirm.disable_event(ctrl));
+        if let Err(e) = &disabled {
+            warn!(?e, "failed to disable events");
+        }
+        self.stop_loop()?;
+
+        disabled.map_err(|e| control_error("failed to disable events", &e))
+    }
+
+    /// Stops the loop and releases the event interface.
+    ///
+    /// The event enable flag is left as it is, call [`Self::stop`] beforehand to clear it.
+    pub fn close(&mut self) -> StreamResult<()> {
+        self.stop_loop()?;
+        self.tracked = None;
+        self.lock()?.close().map_err(|e| {
+            error!(?e);
+            e.into()
+        })
+    }
+
+    /// Returns `true` if the loop is running.
+    #[must_use]
+    pub fn is_running(&self) -> bool {
+        self.loop_thread.is_some()
+    }
+
+    fn new(device: &u3v::Device) -> ControlResult<Option<Self>> {
+        let inner = device.event_channel()?;
+        Ok(inner.map(|inner| {
+            let counters = Arc::new(EventCounters::default());
+            metrics::register(
+                &counters,
+                metrics::device_labels(&device.device_info.serial_number),
+            );
+            Self {
+                inner: Arc::new(Mutex::new(inner)),
+                thread: ThreadConfig {
+                    name: DEFAULT_EVENT_THREAD_NAME.into(),
+                    ..ThreadConfig::default()
+                },
+                loop_thread: None,
+                tracked: None,
+                counters,
+            }
+        }))
+    }
+
+    fn stop_loop(&mut self) -> StreamResult<()> {
+        if let Some(loop_thread) = &mut self.loop_thread {
+            loop_thread.cancel();
+            loop_thread.join(EVENT_TIMEOUT * 4)?;
+            self.loop_thread = None;
+            info!("stop event loop successfully");
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> StreamResult<MutexGuard<'_, u3v::ReceiveChannel>> {
+        lock(&self.inner)
+    }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            error!(?e)
+        }
+    }
+}
+
+/// Loop reading event packets, the loop holds `inner` during its whole run as the streaming loop
+/// does, see [`super::StreamHandle`].
+struct EventLoop<F> {
+    inner: Arc<Mutex<u3v::ReceiveChannel>>,
+    buf: Vec<u8>,
+    deliver: F,
+    counters: Arc<EventCounters>,
+}
+
+impl<F: FnMut(DeviceEvent)> EventLoop<F> {
+    fn run(mut self, mut cancellation_rx: oneshot::Receiver<()>) {
+        let mut inner = self.inner.lock().unwrap();
+        receive_events(
+            &mut *inner,
+            &mut self.buf,
+            &mut self.deliver,
+            &self.counters,
+            &mut cancellation_rx,
+        );
+        // `deliver` is dropped with `self` after the transfers are reaped, so the channel is
+        // closed only after that.
+    }
+}
+
+/// Reads event packets from `pipe` and passes their events to `deliver` until the cancellation is
+/// signalled or the device is disconnected.
+///
+/// Each read reaps its transfer before returning, so no transfer is pending once this returns.
+fn receive_events<P: BulkIn>(
+    pipe: &mut P,
+    buf: &mut Vec<u8>,
+    deliver: &mut impl FnMut(DeviceEvent),
+    counters: &EventCounters,
+    cancellation_rx: &mut oneshot::Receiver<()>,
+) {
+    loop {
+        if cancellation_rx.try_recv().transpose().is_some() {
+            break;
+        }
+
+        let len = buf.len();
+        let completion = match pipe.read(buf, 0..len, EVENT_TIMEOUT) {
+            Ok(completion) => completion,
+            Err(StreamError::Timeout) => continue,
+            Err(StreamError::Disconnected) => {
+                error!("the device is disconnected, stop event loop");
+                break;
+            }
+            Err(e) => {
+                warn!(?e, "failed to read an event packet");
+                // Back off so that a persistent error doesn't spin the loop.
+                thread::sleep(EVENT_TIMEOUT);
+                continue;
+            }
+        };
+        if completion.overflowed {
+            counters.invalid_packets.fetch_add(1, Ordering::Relaxed);
+            warn!(
+                len,
+                "event packet exceeds the maximum event transfer length, skip it"
+            );
+            continue;
+        }
+
+        match EventPacket::parse(&buf[..completion.len]) {
+            Ok(packet) => {
+                for scd in packet.scd {
+                    counters.received_events.fetch_add(1, Ordering::Relaxed);
+                    deliver(DeviceEvent {
+                        id: scd.event_id,
+                        timestamp: scd.timestamp,
+                        data: scd.data.to_vec(),
+                    });
+                }
+            }
+            Err(e) => {
+                counters.invalid_packets.fetch_add(1, Ordering::Relaxed);
+                warn!(?e, "skip an invalid event packet");
+            }
+        }
+    }
+}
+
+/// Returns the buffer receiving event transfers, whose length is the maximum event transfer length
+/// of the device.
+///
+/// The length is checked against [`Limits::max_allocation`] before allocating, so a device claiming
+/// an absurd length fails to start rather than exhausting the memory.
+fn event_buffer<Ctrl: DeviceControl + ?Sized>(
+    eirm: Eirm,
+    ctrl: &mut Ctrl,
+    limits: &Limits,
+) -> StreamResult<Vec<u8>> {
+    let transfer_len = eirm
+        .maximum_event_transfer_length(ctrl)
+        .map_err(|e| control_error("failed to read maximum event transfer length", &e))?;
+    limits
+        .check_allocation(transfer_len)
+        .map_err(|e| control_error("maximum event transfer length is too large", &e))?;
+    Ok(vec![
+        0;
+        (transfer_len as usize)
+            .max(MINIMUM_EVENT_TRANSFER_LENGTH)
+    ])
+}
+
+/// Sets the event enable flag. If the device rejects it with `U3V_EVENT_ENDPOINT_HALTED`,
+/// `clear_halt` is called and setting the flag is retried once.
+fn enable_event<Ctrl: DeviceControl + ?Sized>(
+    eirm: Eirm,
+    ctrl: &mut Ctrl,
+    clear_halt: impl FnOnce() -> StreamResult<()>,
+) -> StreamResult<()> {
+    let enabled = match eirm.enable_event(ctrl) {
+        Err(e) if is_endpoint_halted(&e) => {
+            warn!("event endpoint is halted, clear the halt and retry enabling events");
+            clear_halt()?;
+            eirm.enable_event(ctrl)
+        }
+        enabled => enabled,
+    };
+
+    enabled.map_err(|e| control_error("failed to enable events", &e))
+}
+
+fn is_endpoint_halted(err: &ControlError) -> bool {
+    rejected_status(err).is_some_and(|status| {
+        matches!(
+            status.kind(),
+            ack::StatusKind::UsbSpecific(ack::UsbSpecificStatus::EventEndpointHalted)
+        )
+    })
+}
+
+fn control_error(context: &str, err: &ControlError) -> StreamError {
+    StreamError::Io(anyhow::Error::msg(format!("{}: {}", context, err)))
+}
+
+fn lock(inner: &Mutex<u3v::ReceiveChannel>) -> StreamResult<MutexGuard<'_, u3v::ReceiveChannel>> {
+    inner.lock().map_err(|cause| {
+        let err = StreamError::Poisoned(cause.to_string().into());
+        error!(?err);
+        err
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::VecDeque, ops::Range, rc::Rc};
+
+    use cameleon_device::u3v::register_map::eirm;
+
+    #[cfg(feature = "emulator")]
+    use cameleon_device::u3v::register_map::abrm;
+
+    #[cfg(feature = "emulator")]
+    use crate::u3v::emulated::{emulated_device, open_emulated};
+    use crate::{
+        limits::Limit,
+        u3v::{
+            async_read::{Completion, TransferBuf},
+            control_handle::StatusError,
+        },
+    };
+
+    use super::*;
+
+    /// Event endpoint of a fake device which sends queued packets, then disconnects.
+    struct FakeEndpoint {
+        packets: VecDeque<Vec<u8>>,
+    }
+
+    impl BulkIn for FakeEndpoint {
+        fn read<B: TransferBuf>(
+            &mut self,
+            buf: &mut B,
+            range: Range<usize>,
+            _timeout: Duration,
+        ) -> StreamResult<Completion> {
+            let buf = &mut buf.as_mut_slice()[range];
+            let packet = self.packets.pop_front().ok_or(StreamError::Disconnected)?;
+            let len = packet.len().min(buf.len());
+            buf[..len].copy_from_slice(&packet[..len]);
+            Ok(Completion {
+                len,
+                overflowed: len < packet.len(),
+            })
+        }
+    }
+
+    fn event_packet(id: u16, timestamp: u64, data: &[u8]) -> Vec<u8> {
+        let mut scd = vec![];
+        // Single event.
+        scd.extend_from_slice(&0_u16.to_le_bytes());
+        scd.extend_from_slice(&id.to_le_bytes());
+        scd.extend_from_slice(&timestamp.to_le_bytes());
+        scd.extend_from_slice(data);
+
+        let mut packet = vec![];
+        packet.extend_from_slice(&0x4556_3355_u32.to_le_bytes());
+        packet.extend_from_slice(&0_u16.to_le_bytes());
+        packet.extend_from_slice(&0x0c00_u16.to_le_bytes());
+        packet.extend_from_slice(&(scd.len() as u16).to_le_bytes());
+        packet.extend_from_slice(&0_u16.to_le_bytes());
+        packet.extend(scd);
+        packet
+    }
+
+    /// `EIRM` at `0x0` which rejects enabling events while the event endpoint is halted.
+    struct EventInterface {
+        bytes: Vec<u8>,
+        halted: Rc<Cell<bool>>,
+    }
+
+    impl EventInterface {
+        fn new(maximum_event_transfer_length: u32) -> Self {
+            let mut bytes = vec![0; 0xc];
+            let offset = eirm::MAXIMUM_EVENT_TRANSFER_LENGTH.0 as usize;
+            bytes[offset..offset + 4].copy_from_slice(&maximum_event_transfer_length.to_le_bytes());
+            Self {
+                bytes,
+                halted: Rc::default(),
+            }
+        }
+    }
+
+    impl DeviceControl for EventInterface {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            let address = address as usize;
+            buf.copy_from_slice(&self.bytes[address..address + buf.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+            if self.halted.get() && address == eirm::EI_CONTROL.0 && data[0] & 1 == 1 {
+                let status = ack::Status::from(ack::UsbSpecificStatus::EventEndpointHalted);
+                return Err(ControlError::Io(StatusError(status).into()));
+            }
+            let address = address as usize;
+            self.bytes[address..address + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            unreachable!()
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_receive_events() {
+        let mut pipe = FakeEndpoint {
+            packets: VecDeque::new(),
+        };
+        pipe.packets.push_back(event_packet(0x4fff, 10, &[]));
+        // An invalid packet is skipped.
+        pipe.packets
+            .push_back(vec![0; MINIMUM_EVENT_TRANSFER_LENGTH]);
+        pipe.packets
+            .push_back(event_packet(0x9001, 20, &[0xaa, 0xbb]));
+        // A packet exceeding the transfer length is skipped.
+        pipe.packets.push_back(event_packet(0x9002, 30, &[0; 64]));
+
+        // The buffer is sized by the maximum event transfer length of the device.
+        let mut device = EventInterface::new(64);
+        let mut buf = event_buffer(Eirm::new(0), &mut device, &Limits::default()).unwrap();
+        assert_eq!(buf.len(), 64);
+
+        let (tx, rx) = channel::bounded(4);
+        let (_cancellation_tx, mut cancellation_rx) = oneshot::channel();
+        let counters = EventCounters::default();
+        receive_events(
+            &mut pipe,
+            &mut buf,
+            &mut |event| tx.try_send(event).unwrap(),
+            &counters,
+            &mut cancellation_rx,
+        );
+        drop(tx);
+        assert_eq!(counters.received_events.load(Ordering::Relaxed), 2);
+        assert_eq!(counters.invalid_packets.load(Ordering::Relaxed), 2);
+
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            DeviceEvent {
+                id: 0x4fff,
+                timestamp: 10,
+                data: vec![],
+            }
+        );
+        assert_eq!(
+            rx.try_recv().unwrap(),
+            DeviceEvent {
+                id: 0x9001,
+                timestamp: 20,
+                data: vec![0xaa, 0xbb],
+            }
+        );
+        assert!(rx.try_recv().is_err());
+        assert!(pipe.packets.is_empty());
+    }
+
+    #[test]
+    fn test_event_buffer_limit() {
+        let limits = Limits {
+            max_allocation: 1024,
+            ..Limits::default()
+        };
+        let eirm = Eirm::new(0);
+
+        let mut device = EventInterface::new(u32::MAX);
+        let err = event_buffer(eirm, &mut device, &limits).unwrap_err();
+        let expected = ControlError::LimitExceeded {
+            limit: Limit::Allocation,
+            claimed: u64::from(u32::MAX),
+            max: 1024,
+        };
+        assert!(matches!(&err, StreamError::Io(_)));
+        assert!(err.to_string().contains(&expected.to_string()));
+
+        let mut device = EventInterface::new(1024);
+        assert_eq!(
+            event_buffer(eirm, &mut device, &limits).unwrap().len(),
+            1024
+        );
+
+        // A length too short to carry an event is extended.
+        let mut device = EventInterface::new(0);
+        assert_eq!(
+            event_buffer(eirm, &mut device, &limits).unwrap().len(),
+            MINIMUM_EVENT_TRANSFER_LENGTH
+        );
+    }
+
+    #[test]
+    fn test_cancel_receiving_events() {
+        let mut pipe = FakeEndpoint {
+            packets: VecDeque::new(),
+        };
+        pipe.packets.push_back(event_packet(0x4fff, 10, &[]));
+
+        let (cancellation_tx, mut cancellation_rx) = oneshot::channel();
+        cancellation_tx.send(()).unwrap();
+        let mut count = 0;
+        receive_events(
+            &mut pipe,
+            &mut vec![0; 64],
+            &mut |_| count += 1,
+            &EventCounters::default(),
+            &mut cancellation_rx,
+        );
+        assert_eq!(count, 0);
+        assert_eq!(pipe.packets.len(), 1);
+    }
+
+    #[test]
+    fn test_enable_halted_endpoint() {
+        let mut device = EventInterface::new(0);
+        let halted = device.halted.clone();
+        halted.set(true);
+        let eirm = Eirm::new(0);
+        let mut cleared = 0;
+        enable_event(eirm, &mut device, || {
+            cleared += 1;
+            halted.set(false);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(cleared, 1);
+        assert!(eirm.is_event_enable(&mut device).unwrap());
+
+        // Enabling fails if the halt persists, the halt is cleared only once.
+        eirm.disable_event(&mut device).unwrap();
+        halted.set(true);
+        let mut cleared = 0;
+        assert!(enable_event(eirm, &mut device, || {
+            cleared += 1;
+            Ok(())
+        })
+        .is_err());
+        assert_eq!(cleared, 1);
+        assert!(!eirm.is_event_enable(&mut device).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "emulator")]
+    fn test_emulated_events() {
+        const TEST_EVENT_ID: u16 = 0x4fff;
+
+        let mut ctrl = open_emulated("U3VEVNT1");
+        let mut pipe = emulated_device("U3VEVNT1")
+            .event_channel()
+            .unwrap()
+            .unwrap();
+        pipe.open().unwrap();
+
+        let eirm = ctrl.eirm().unwrap();
+        let eirm_address = ctrl.sbrm().unwrap().eirm_address().unwrap();
+        let limits = ctrl.limits();
+        let mut buf = event_buffer(eirm, &mut ctrl, &limits).unwrap();
+        assert_eq!(buf.len(), 1024);
+
+        // Events carry the latched timestamp.
+        ctrl.write(abrm::TIMESTAMP_LATCH.0, &1_u32.to_le_bytes())
+            .unwrap();
+        let mut timestamp = [0; 8];
+        ctrl.read(abrm::TIMESTAMP.0, &mut timestamp).unwrap();
+        let timestamp = u64::from_le_bytes(timestamp);
+
+        let mut cleared = 0;
+        enable_event(eirm, &mut ctrl, || {
+            cleared += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(cleared, 0);
+        assert!(eirm.is_event_enable(&mut ctrl).unwrap());
+
+        // Make the device send test events.
+        let test_control = eirm_address + eirm::EVENT_TEST_CONTROL.0;
+        for _ in 0..2 {
+            ctrl.write(test_control, &1_u32.to_le_bytes()).unwrap();
+        }
+
+        let (cancellation_tx, mut cancellation_rx) = oneshot::channel();
+        let mut cancellation_tx = Some(cancellation_tx);
+        let mut events = vec![];
+        let counters = EventCounters::default();
+        receive_events(
+            &mut pipe,
+            &mut buf,
+            &mut |event| {
+                events.push(event);
+                if events.len() == 2 {
+                    cancellation_tx.take().unwrap().send(()).unwrap();
+                }
+            },
+            &counters,
+            &mut cancellation_rx,
+        );
+        assert_eq!(counters.received_events.load(Ordering::Relaxed), 2);
+        assert_eq!(counters.invalid_packets.load(Ordering::Relaxed), 0);
+        let expected = DeviceEvent {
+            id: TEST_EVENT_ID,
+            timestamp,
+            data: vec![],
+        };
+        assert_eq!(events, vec![expected.clone(), expected]);
+
+        // No event is sent once events are disabled.
+        eirm.disable_event(&mut ctrl).unwrap();
+        ctrl.write(test_control, &1_u32.to_le_bytes()).unwrap();
+        assert!(matches!(
+            pipe.read(&mut buf, 0..1024, Duration::from_millis(50)),
+            Err(StreamError::Timeout)
+        ));
+    }
+}
@@ -0,0 +1,397 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains fair scheduling between streams sharing event handling of libusb.
+//!
+//! Completions of all streams are handled by whichever stream thread holds the event lock, then
+//! each stream thread reaps its own completions. A stream submitting transfers at a high rate
+//! keeps the event lock busy, and completions of quieter streams may be left unreaped until the
+//! device drops data.
+//!
+//! Each stream has a [`StreamSlot`] which counts completions serviced in the event handler. A
+//! stream whose completion is left unreaped beyond [`STARVATION_THRESHOLD`] is regarded as
+//! starved, and the other streams hold off new submissions until the starved stream drains its
+//! completions.
+
+use std::{
+    collections::VecDeque,
+    ptr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::metrics::{MetricSink, MetricSource};
+
+/// A stream is regarded as starved when its completion is left unreaped longer than this.
+pub(super) const STARVATION_THRESHOLD: Duration = Duration::from_millis(5);
+
+/// Maximum duration a submission is held off, so that a stream whose peer never reaps its
+/// completions can't be blocked forever.
+const MAX_THROTTLE_DURATION: Duration = Duration::from_millis(20);
+
+/// Scheduler shared by all streams in the process.
+pub(super) static SCHEDULER: Scheduler = Scheduler::new();
+
+/// Tracks streams sharing event handling and throttles submissions while any of them is starved.
+pub(super) struct Scheduler {
+    slots: Mutex<Vec<Weak<StreamSlot>>>,
+    /// Notified when a starved stream reaps a completion, with `slots` locked.
+    starvation_eased: Condvar,
+}
+
+impl Scheduler {
+    pub(super) const fn new() -> Self {
+        Self {
+            slots: Mutex::new(Vec::new()),
+            starvation_eased: Condvar::new(),
+        }
+    }
+
+    /// Registers a new stream, the stream is unregistered when the returned slot is dropped.
+    pub(super) fn register(&self) -> Arc<StreamSlot> {
+        let slot = Arc::new(StreamSlot::new());
+        let mut slots = self.slots();
+        slots.retain(|slot| slot.strong_count() > 0);
+        slots.push(Arc::downgrade(&slot));
+        slot
+    }
+
+    /// Holds off a submission of `slot` while another stream is starved, up to
+    /// [`MAX_THROTTLE_DURATION`].
+    ///
+    /// The calling thread sleeps until a starved stream reaps a completion, see
+    /// [`Self::reaped`].
+    pub(super) fn throttle(&self, slot: &StreamSlot) {
+        self.throttle_for(slot, MAX_THROTTLE_DURATION);
+    }
+
+    /// Called by the thread owning `slot` when it reaps a completed transfer.
+    ///
+    /// Submissions held off by [`Self::throttle`] are woken up if the stream was starved.
+    pub(super) fn reaped(&self, slot: &StreamSlot, now: Instant) {
+        if slot.reaped(now) {
+            // Locking `slots` orders the notification after a throttled thread checks the
+            // starvation, so the thread is either woken up or sees the reaped completion.
+            let _slots = self.slots();
+            self.starvation_eased.notify_all();
+        }
+    }
+
+    fn throttle_for(&self, slot: &StreamSlot, max_duration: Duration) {
+        let start = Instant::now();
+        let mut slots = self.slots();
+        if !Self::is_other_starved_in(&slots, slot, start) {
+            return;
+        }
+
+        slot.throttled.fetch_add(1, Ordering::Relaxed);
+        let deadline = start + max_duration;
+        loop {
+            let now = Instant::now();
+            if now >= deadline || !Self::is_other_starved_in(&slots, slot, now) {
+                return;
+            }
+            slots = self
+                .starvation_eased
+                .wait_timeout(slots, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Returns `true` if any stream other than `slot` is starved at `now`.
+    #[cfg(test)]
+    fn is_other_starved(&self, slot: &StreamSlot, now: Instant) -> bool {
+        Self::is_other_starved_in(&self.slots(), slot, now)
+    }
+
+    fn is_other_starved_in(slots: &[Weak<StreamSlot>], slot: &StreamSlot, now: Instant) -> bool {
+        slots
+            .iter()
+            .filter_map(Weak::upgrade)
+            .any(|other| !ptr::eq(&*other, slot) && other.is_starved(now))
+    }
+
+    fn slots(&self) -> MutexGuard<'_, Vec<Weak<StreamSlot>>> {
+        // Slots are always consistent, so it's safe to ignore poisoning.
+        self.slots.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Per stream state of [`Scheduler`].
+///
+/// Completions are serviced in the thread which holds the event lock, which may not be the
+/// thread owning the stream, so the state is shared between them. The lock of `unreaped` is a
+/// leaf lock held only to access the queue, see the lock ordering of [`super::StreamHandle`].
+pub(super) struct StreamSlot {
+    /// The number of completions serviced in the event handler.
+    serviced: AtomicU64,
+    /// The number of submissions held off for another starved stream.
+    throttled: AtomicU64,
+    /// The number of completions reaped after the stream is starved.
+    starvations: AtomicU64,
+    /// Times when the serviced but not yet reaped completions are serviced, the oldest first.
+    ///
+    /// A stream reaps its transfers in the order of submission, and transfers on the same
+    /// endpoint complete in that order, so the front is always the next completion to reap.
+    unreaped: Mutex<VecDeque<Instant>>,
+}
+
+impl StreamSlot {
+    fn new() -> Self {
+        Self {
+            serviced: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+            starvations: AtomicU64::new(0),
+            unreaped: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Called in the event handler when a transfer of the stream completes.
+    pub(super) fn serviced(&self, now: Instant) {
+        self.serviced.fetch_add(1, Ordering::Relaxed);
+        self.unreaped().push_back(now);
+    }
+
+    /// Pops the oldest unreaped completion, and returns `true` if the stream was starved.
+    fn reaped(&self, now: Instant) -> bool {
+        let oldest = self.unreaped().pop_front();
+        let is_starved = matches!(oldest, Some(oldest) if age(oldest, now) > STARVATION_THRESHOLD);
+        if is_starved {
+            self.starvations.fetch_add(1, Ordering::Relaxed);
+        }
+        is_starved
+    }
+
+    /// Returns counters of the slot.
+    pub(super) fn counters(&self) -> FairnessCounters {
+        FairnessCounters {
+            serviced: self.serviced.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+            starvations: self.starvations.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns how long the oldest unreaped completion has been left at `now`.
+    fn unreaped_age(&self, now: Instant) -> Option<Duration> {
+        self.unreaped().front().map(|oldest| age(*oldest, now))
+    }
+
+    fn is_starved(&self, now: Instant) -> bool {
+        matches!(self.unreaped_age(now), Some(age) if age > STARVATION_THRESHOLD)
+    }
+
+    fn unreaped(&self) -> MutexGuard<'_, VecDeque<Instant>> {
+        // The queue is always consistent, so it's safe to ignore poisoning.
+        self.unreaped.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn age(serviced: Instant, now: Instant) -> Duration {
+    now.saturating_duration_since(serviced)
+}
+
+impl MetricSource for StreamSlot {
+    fn collect(&self, sink: &mut MetricSink<'_>) {
+        let counters = self.counters();
+        sink.counter(
+            "cameleon_stream_serviced_completions_total",
+            counters.serviced,
+        );
+        sink.counter(
+            "cameleon_stream_throttled_submissions_total",
+            counters.throttled,
+        );
+        sink.counter(
+            "cameleon_stream_starvation_events_total",
+            counters.starvations,
+        );
+    }
+}
+
+/// Counters of [`StreamSlot`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(super) struct FairnessCounters {
+    pub(super) serviced: u64,
+    pub(super) throttled: u64,
+    pub(super) starvations: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    /// A quiet stream drops data when its completion is left unreaped longer than this.
+    const DROP_DEADLINE: Duration = Duration::from_millis(20);
+
+    /// Simulates a dominant stream which always has transfers to submit and two quiet streams
+    /// for a second, then returns the number of drops of the quiet streams.
+    ///
+    /// While the dominant stream runs, it holds the event lock and the quiet streams can't reap
+    /// their completions.
+    fn simulate(scheduler: &Scheduler, fair: bool) -> (u64, [Arc<StreamSlot>; 3]) {
+        let dominant = scheduler.register();
+        let quiet = [scheduler.register(), scheduler.register()];
+        let periods = [7, 13];
+        let start = Instant::now();
+
+        let mut drops = 0;
+        for ms in 0..1000 {
+            let now = start + Duration::from_millis(ms);
+            for (slot, period) in quiet.iter().zip(&periods) {
+                if ms % period == 0 {
+                    slot.serviced(now);
+                }
+            }
+
+            if !fair || !scheduler.is_other_starved(&dominant, now) {
+                // The dominant stream submits, handles events and reaps its own completion.
+                dominant.serviced(now);
+                scheduler.reaped(&dominant, now);
+            } else {
+                dominant.throttled.fetch_add(1, Ordering::Relaxed);
+                for slot in &quiet {
+                    while let Some(age) = slot.unreaped_age(now) {
+                        if age > DROP_DEADLINE {
+                            drops += 1;
+                        }
+                        scheduler.reaped(slot, now);
+                    }
+                }
+            }
+        }
+
+        let end = start + Duration::from_millis(1000);
+        for slot in &quiet {
+            if matches!(slot.unreaped_age(end), Some(age) if age > DROP_DEADLINE) {
+                drops += 1;
+            }
+        }
+
+        let [quiet1, quiet2] = quiet;
+        (drops, [dominant, quiet1, quiet2])
+    }
+
+    #[test]
+    fn test_fair_scheduling() {
+        let scheduler = Scheduler::new();
+        let (drops, [dominant, quiet1, quiet2]) = simulate(&scheduler, true);
+        assert_eq!(drops, 0);
+
+        let dominant = dominant.counters();
+        assert_eq!(dominant.starvations, 0);
+        assert!(dominant.throttled > 0);
+        assert_eq!(dominant.serviced + dominant.throttled, 1000);
+        // The dominant stream is held off only for a step per starvation of the quiet streams.
+        assert!(dominant.serviced > 800);
+        // Each quiet stream is starved at most once per completion.
+        assert_eq!(quiet1.counters().serviced, 143);
+        assert!(quiet1.counters().starvations <= 143);
+        assert_eq!(quiet2.counters().serviced, 77);
+    }
+
+    #[test]
+    fn test_unfair_scheduling() {
+        let scheduler = Scheduler::new();
+        let (drops, [dominant, ..]) = simulate(&scheduler, false);
+        assert!(drops > 0);
+        assert_eq!(dominant.counters().throttled, 0);
+    }
+
+    #[test]
+    fn test_unregister() {
+        let scheduler = Scheduler::new();
+        let slot = scheduler.register();
+        let starved = scheduler.register();
+        let now = Instant::now();
+        starved.serviced(now);
+
+        let later = now + STARVATION_THRESHOLD * 2;
+        assert!(scheduler.is_other_starved(&slot, later));
+        assert!(!scheduler.is_other_starved(&starved, later));
+
+        drop(starved);
+        assert!(!scheduler.is_other_starved(&slot, later));
+    }
+
+    #[test]
+    fn test_oldest_unreaped_age() {
+        let scheduler = Scheduler::new();
+        let slot = scheduler.register();
+        let start = Instant::now();
+        slot.serviced(start);
+        slot.serviced(start + Duration::from_millis(4));
+
+        // The age is of the oldest completion, and the next one is aged once it's reaped.
+        let now = start + Duration::from_millis(6);
+        assert_eq!(slot.unreaped_age(now), Some(Duration::from_millis(6)));
+        scheduler.reaped(&slot, now);
+        assert_eq!(slot.unreaped_age(now), Some(Duration::from_millis(2)));
+        assert!(!slot.is_starved(now));
+        scheduler.reaped(&slot, now);
+        assert_eq!(slot.unreaped_age(now), None);
+        assert_eq!(slot.counters().starvations, 1);
+    }
+
+    #[test]
+    fn test_concurrent_service_and_reap() {
+        const NUM_COMPLETIONS: u64 = 10_000;
+        let scheduler = Scheduler::new();
+        let slot = scheduler.register();
+
+        // The event handler services completions while the owning thread reaps them.
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..NUM_COMPLETIONS {
+                    slot.serviced(Instant::now());
+                }
+            });
+
+            let mut reaped = 0;
+            while reaped < NUM_COMPLETIONS {
+                if slot.unreaped_age(Instant::now()).is_some() {
+                    scheduler.reaped(&slot, Instant::now());
+                    reaped += 1;
+                } else {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        assert_eq!(slot.unreaped_age(Instant::now()), None);
+        assert_eq!(slot.counters().serviced, NUM_COMPLETIONS);
+    }
+
+    #[test]
+    fn test_throttle_wakes_up_on_reap() {
+        let scheduler = Scheduler::new();
+        let dominant = scheduler.register();
+        let starved = scheduler.register();
+        starved.serviced(Instant::now() - STARVATION_THRESHOLD * 2);
+
+        let max_duration = Duration::from_secs(60);
+        thread::scope(|s| {
+            s.spawn(|| {
+                while dominant.counters().throttled == 0 {
+                    thread::yield_now();
+                }
+                scheduler.reaped(&starved, Instant::now());
+            });
+
+            let start = Instant::now();
+            scheduler.throttle_for(&dominant, max_duration);
+            // The submission is woken up by the reap rather than held off until the deadline.
+            assert!(start.elapsed() < max_duration / 2);
+        });
+
+        assert_eq!(dominant.counters().throttled, 1);
+        assert_eq!(starved.counters().starvations, 1);
+        assert!(!scheduler.is_other_starved(&dominant, Instant::now()));
+    }
+}
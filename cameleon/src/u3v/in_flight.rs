@@ -0,0 +1,431 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains the bookkeeping of commands whose acks are collected later.
+//!
+//! [`SharedControlHandle::begin_transaction`](super::SharedControlHandle::begin_transaction)
+//! sends a command and returns without waiting for its ack. Until the ack is collected, it may
+//! arrive while another transaction waits for its own ack, so every receive loop of the handle
+//! passes received packets to [`InFlight::stash`] before interpreting them.
+//!
+//! When the transaction is dropped without collecting the ack, the command is marked as
+//! abandoned, and its ack is discarded once received so that the request ids of the following
+//! transactions still match their acks.
+
+use std::time::{Duration, Instant};
+
+use cameleon_device::u3v::protocol::ack;
+use tracing::{debug, warn};
+
+use super::{
+    control_handle::{Deadline, StatusError},
+    pipeline::Transport,
+};
+
+use crate::{limits::Limits, ControlError, ControlResult};
+
+/// Commands sent to the device whose acks aren't collected yet.
+#[derive(Default)]
+pub(super) struct InFlight {
+    commands: Vec<Command>,
+}
+
+struct Command {
+    request_id: u16,
+    /// The maximum length of the ack.
+    ack_len: usize,
+    deadline: Deadline,
+    /// `true` if the transaction is dropped without collecting the ack.
+    abandoned: bool,
+    /// The final ack, or the error reported for the command.
+    result: Option<Result<ack::AckPacket<'static>, Failure>>,
+}
+
+/// An error kept until the transaction is collected, [`ControlError`] can't be kept because it
+/// isn't `Send`.
+enum Failure {
+    Timeout,
+    Io(anyhow::Error),
+}
+
+impl From<Failure> for ControlError {
+    fn from(failure: Failure) -> Self {
+        match failure {
+            Failure::Timeout => Self::Timeout,
+            Failure::Io(e) => Self::Io(e),
+        }
+    }
+}
+
+impl InFlight {
+    /// Registers the command of `request_id` which has just been sent.
+    pub(super) fn push(&mut self, request_id: u16, ack_len: usize, timeout: Duration) {
+        self.commands.push(Command {
+            request_id,
+            ack_len,
+            deadline: Deadline::new(timeout),
+            abandoned: false,
+            result: None,
+        });
+    }
+
+    /// Returns `true` if no command is waiting for its ack.
+    pub(super) fn is_settled(&self) -> bool {
+        self.commands.iter().all(|cmd| cmd.result.is_some())
+    }
+
+    /// Returns the maximum ack length of the commands waiting for their acks.
+    pub(super) fn ack_len(&self) -> usize {
+        self.commands
+            .iter()
+            .filter(|cmd| cmd.result.is_none())
+            .map(|cmd| cmd.ack_len)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Keeps `packet` if it's an ack of a command in flight, and returns `true` in that case.
+    ///
+    /// A pending ack extends the deadline of the command. An error status is kept as the result
+    /// of the command instead of being returned, because it doesn't belong to the transaction
+    /// which received it.
+    pub(super) fn stash(
+        &mut self,
+        packet: &[u8],
+        retry_count: u16,
+        limits: &Limits,
+    ) -> ControlResult<bool> {
+        let ack = ack::AckPacket::parse(packet)?;
+        let pos = match self
+            .commands
+            .iter()
+            .position(|cmd| cmd.request_id == ack.request_id() && cmd.result.is_none())
+        {
+            Some(pos) => pos,
+            None => return Ok(false),
+        };
+
+        let cmd = &mut self.commands[pos];
+        let status = ack.status();
+        if !status.is_success() {
+            cmd.result = Some(Err(Failure::Io(StatusError(*status).into())));
+        } else if ack.scd_kind() == ack::ScdKind::Pending {
+            let pending_ack: ack::Pending = ack.scd_as()?;
+            if let Err(e) =
+                cmd.deadline
+                    .extend(ack.request_id(), pending_ack.timeout, retry_count, limits)
+            {
+                cmd.result = Some(Err(Failure::Io(anyhow::Error::msg(e.to_string()))));
+            }
+        } else {
+            cmd.result = Some(Ok(ack.into_owned()));
+        }
+
+        if cmd.abandoned && cmd.result.is_some() {
+            debug!(
+                request_id = cmd.request_id,
+                "discarded the ack of an abandoned transaction"
+            );
+            self.commands.remove(pos);
+        }
+        Ok(true)
+    }
+
+    /// Receives acks until every command in flight has its result.
+    ///
+    /// Packets which don't belong to any command are discarded.
+    pub(super) fn drain<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        buf: &mut [u8],
+        retry_count: u16,
+        limits: &Limits,
+    ) -> ControlResult<()> {
+        loop {
+            self.expire();
+            let deadline = match self
+                .commands
+                .iter()
+                .filter(|cmd| cmd.result.is_none())
+                .map(|cmd| cmd.deadline.at())
+                .min()
+            {
+                Some(deadline) => deadline,
+                None => return Ok(()),
+            };
+            self.recv(transport, buf, deadline, retry_count, limits)?;
+        }
+    }
+
+    /// Waits up to `timeout` for the result of the command of `request_id`, and returns `None`
+    /// if `timeout` elapses first.
+    ///
+    /// Acks of other commands in flight are kept while waiting.
+    pub(super) fn wait<T: Transport>(
+        &mut self,
+        request_id: u16,
+        transport: &mut T,
+        buf: &mut [u8],
+        timeout: Duration,
+        retry_count: u16,
+        limits: &Limits,
+    ) -> ControlResult<Option<ack::AckPacket<'static>>> {
+        let until = Instant::now() + timeout;
+        loop {
+            self.expire();
+            let pos = self
+                .commands
+                .iter()
+                .position(|cmd| cmd.request_id == request_id && !cmd.abandoned)
+                .ok_or_else(|| {
+                    ControlError::InvalidData(
+                        format!(
+                            "the transaction of request id {} isn't in flight",
+                            request_id
+                        )
+                        .into(),
+                    )
+                })?;
+            if self.commands[pos].result.is_some() {
+                let result = self.commands.remove(pos).result.unwrap();
+                return result.map(Some).map_err(Into::into);
+            }
+
+            let deadline = self.commands[pos].deadline.at().min(until);
+            if Instant::now() >= until {
+                return Ok(None);
+            }
+            self.recv(transport, buf, deadline, retry_count, limits)?;
+        }
+    }
+
+    /// Marks the command of `request_id` as abandoned, then its ack is discarded once received.
+    pub(super) fn abandon(&mut self, request_id: u16) {
+        if let Some(pos) = self
+            .commands
+            .iter()
+            .position(|cmd| cmd.request_id == request_id)
+        {
+            if self.commands[pos].result.is_some() {
+                self.commands.remove(pos);
+            } else {
+                self.commands[pos].abandoned = true;
+            }
+        }
+    }
+
+    /// Forgets all commands, e.g. when the channel is closed.
+    pub(super) fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    /// Receives a packet until `deadline`, and keeps it if it belongs to a command in flight.
+    fn recv<T: Transport>(
+        &mut self,
+        transport: &mut T,
+        buf: &mut [u8],
+        deadline: Instant,
+        retry_count: u16,
+        limits: &Limits,
+    ) -> ControlResult<()> {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(());
+        }
+        match transport.recv(buf, remaining) {
+            Ok(len) => {
+                if !self.stash(&buf[..len], retry_count, limits)? {
+                    warn!("discarded an ack which doesn't belong to any transaction in flight");
+                }
+                Ok(())
+            }
+            Err(ControlError::Timeout) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reports a timeout for the commands whose deadline has passed.
+    fn expire(&mut self) {
+        let now = Instant::now();
+        self.commands.retain(|cmd| {
+            // Abandoned commands are forgotten once their deadline passes.
+            !(cmd.abandoned && cmd.result.is_none() && cmd.deadline.at() <= now)
+        });
+        for cmd in &mut self.commands {
+            if cmd.result.is_none() && cmd.deadline.at() <= now {
+                cmd.result = Some(Err(Failure::Timeout));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, convert::TryInto};
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Acks waiting to be received, which time out immediately when empty.
+    #[derive(Default)]
+    struct Wire(VecDeque<Vec<u8>>);
+
+    impl Wire {
+        fn push(&mut self, ack: &ack::AckPacket) {
+            let mut buf = vec![];
+            ack.serialize(&mut buf).unwrap();
+            self.0.push_back(buf);
+        }
+    }
+
+    impl Transport for Wire {
+        fn send(&mut self, _buf: &[u8], _timeout: Duration) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn recv(&mut self, buf: &mut [u8], _timeout: Duration) -> ControlResult<usize> {
+            let ack = self.0.pop_front().ok_or(ControlError::Timeout)?;
+            buf[..ack.len()].copy_from_slice(&ack);
+            Ok(ack.len())
+        }
+    }
+
+    fn wait(
+        in_flight: &mut InFlight,
+        wire: &mut Wire,
+        request_id: u16,
+        timeout: Duration,
+    ) -> ControlResult<Option<ack::AckPacket<'static>>> {
+        let mut buf = vec![0; 64];
+        in_flight.wait(request_id, wire, &mut buf, timeout, 3, &Limits::default())
+    }
+
+    fn read_data(ack: &ack::AckPacket) -> u32 {
+        u32::from_le_bytes(
+            ack.scd_as::<ack::ReadMem>()
+                .unwrap()
+                .data
+                .try_into()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_interleaved_transaction() {
+        let mut in_flight = InFlight::default();
+        let mut wire = Wire::default();
+        let limits = Limits::default();
+        in_flight.push(0, 16, TIMEOUT);
+
+        // A long latency command is pending while another transaction of request id 1 receives
+        // its ack.
+        wire.push(&ack::AckPacket::pending_ack(0, Duration::from_millis(100)).unwrap());
+        wire.push(&ack::AckPacket::read_mem_ack(1, &[1, 0, 0, 0]).unwrap());
+        wire.push(&ack::AckPacket::read_mem_ack(0, &[2, 0, 0, 0]).unwrap());
+        let mut buf = vec![0; 64];
+        let received = loop {
+            let len = wire.recv(&mut buf, TIMEOUT).unwrap();
+            if !in_flight.stash(&buf[..len], 3, &limits).unwrap() {
+                break ack::AckPacket::parse(&buf[..len]).unwrap().into_owned();
+            }
+        };
+        assert_eq!(received.request_id(), 1);
+        assert_eq!(read_data(&received), 1);
+
+        let ack = wait(&mut in_flight, &mut wire, 0, TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(read_data(&ack), 2);
+        assert!(in_flight.is_settled());
+        assert!(in_flight.commands.is_empty());
+    }
+
+    #[test]
+    fn test_wait_timeout_keeps_transaction() {
+        let mut in_flight = InFlight::default();
+        let mut wire = Wire::default();
+        in_flight.push(0, 16, TIMEOUT);
+
+        assert!(wait(&mut in_flight, &mut wire, 0, Duration::ZERO)
+            .unwrap()
+            .is_none());
+        assert!(!in_flight.is_settled());
+
+        wire.push(&ack::AckPacket::read_mem_ack(0, &[3, 0, 0, 0]).unwrap());
+        let ack = wait(&mut in_flight, &mut wire, 0, TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(read_data(&ack), 3);
+    }
+
+    #[test]
+    fn test_error_status() {
+        let mut in_flight = InFlight::default();
+        let mut wire = Wire::default();
+        in_flight.push(0, 16, TIMEOUT);
+        in_flight.push(1, 16, TIMEOUT);
+
+        wire.push(&ack::AckPacket::error_ack(
+            0,
+            ack::ScdKind::ReadMem,
+            ack::GenCpStatus::InvalidAddress,
+        ));
+        wire.push(&ack::AckPacket::read_mem_ack(1, &[4, 0, 0, 0]).unwrap());
+        in_flight
+            .drain(&mut wire, &mut [0; 64], 3, &Limits::default())
+            .unwrap();
+        assert!(in_flight.is_settled());
+
+        // Each transaction receives its own result regardless of the order of collection.
+        let ack = wait(&mut in_flight, &mut wire, 1, TIMEOUT)
+            .unwrap()
+            .unwrap();
+        assert_eq!(read_data(&ack), 4);
+        assert!(matches!(
+            wait(&mut in_flight, &mut wire, 0, TIMEOUT),
+            Err(ControlError::Io(_))
+        ));
+        // The results can be collected only once.
+        assert!(matches!(
+            wait(&mut in_flight, &mut wire, 0, TIMEOUT),
+            Err(ControlError::InvalidData(_))
+        ));
+    }
+
+    #[test]
+    fn test_abandoned_ack_is_drained() {
+        let mut in_flight = InFlight::default();
+        let mut wire = Wire::default();
+        in_flight.push(0, 16, TIMEOUT);
+        in_flight.abandon(0);
+        assert!(!in_flight.is_settled());
+
+        wire.push(&ack::AckPacket::read_mem_ack(0, &[5, 0, 0, 0]).unwrap());
+        in_flight
+            .drain(&mut wire, &mut [0; 64], 3, &Limits::default())
+            .unwrap();
+        assert!(in_flight.is_settled());
+        assert!(in_flight.commands.is_empty());
+        assert!(wire.0.is_empty());
+    }
+
+    #[test]
+    fn test_deadline_expires() {
+        let mut in_flight = InFlight::default();
+        let mut wire = Wire::default();
+        in_flight.push(0, 16, Duration::ZERO);
+        in_flight.push(1, 16, Duration::ZERO);
+        in_flight.abandon(1);
+
+        in_flight
+            .drain(&mut wire, &mut [0; 64], 3, &Limits::default())
+            .unwrap();
+        assert!(matches!(
+            wait(&mut in_flight, &mut wire, 0, TIMEOUT),
+            Err(ControlError::Timeout)
+        ));
+        assert!(in_flight.commands.is_empty());
+    }
+}
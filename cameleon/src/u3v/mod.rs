@@ -25,10 +25,10 @@
 //!
 //! let ctrl = &mut camera.ctrl;
 //! // Get Abrm.
-//! let abrm = ctrl.abrm().unwrap();
+//! let mut abrm = ctrl.abrm().unwrap();
 //!
 //! // Read serial number from ABRM.
-//! let serial_number = abrm.serial_number(ctrl).unwrap();
+//! let serial_number = abrm.serial_number();
 //! println!("{}", serial_number);
 //!
 //! // Check user defined name feature is supported.
@@ -36,7 +36,7 @@
 //! let device_capability = abrm.device_capability().unwrap();
 //! if device_capability.is_user_defined_name_supported() {
 //!     // Read from user defined name register.
-//!     let user_defined_name = abrm.user_defined_name(ctrl).unwrap().unwrap();
+//!     let user_defined_name = abrm.user_defined_name().unwrap();
 //!     println!("{}", user_defined_name);
 //!
 //!     // Write new name to the register.
@@ -46,21 +46,40 @@
 #![allow(clippy::missing_panics_doc)]
 
 pub mod control_handle;
+pub mod diag;
+pub mod event;
+pub mod open_options;
 pub mod register_map;
 pub mod stream_handle;
 
 mod async_read;
-
-pub use control_handle::{ControlHandle, SharedControlHandle};
-pub use stream_handle::{StreamHandle, StreamParams};
-
-pub use cameleon_device::u3v::DeviceInfo;
+mod channel;
+#[cfg(all(test, feature = "emulator"))]
+mod emulated;
+mod fairness;
+mod in_flight;
+mod open_registry;
+mod pipeline;
+mod quirks;
+mod retry;
+mod thread;
+
+pub use control_handle::{ControlHandle, PendingTransaction, SharedControlHandle};
+pub use event::{DeviceEvent, EventReceiver};
+pub use open_options::OpenOptions;
+pub use quirks::Quirks;
+pub use retry::RetryPolicy;
+pub use stream_handle::{HostStreamStatistics, StreamHandle, StreamParams, StreamStatistics};
+pub use thread::{ThreadConfig, ThreadPriority};
+
+pub use cameleon_device::{
+    u3v::{BusSpeed, DeviceFilter, DeviceInfo},
+    Guid, ParseGuidError,
+};
 
 use cameleon_device::u3v;
 
-use super::{
-    genapi::DefaultGenApiCtxt, CameleonResult, Camera, CameraInfo, ControlError, StreamError,
-};
+use super::{genapi::DefaultGenApiCtxt, CameleonResult, Camera, CameraInfo, ControlError};
 
 /// Enumerate all U3V compatible cameras connected to the host.
 ///
@@ -75,7 +94,26 @@ use super::{
 /// let mut cameras = u3v::enumerate_cameras().unwrap();
 /// ```
 pub fn enumerate_cameras() -> CameleonResult<Vec<Camera<ControlHandle, StreamHandle>>> {
-    let devices = u3v::enumerate_devices().map_err(ControlError::from)?;
+    enumerate_cameras_with(&DeviceFilter::default())
+}
+
+/// Enumerate U3V compatible cameras which match `filter`.
+///
+/// Devices which don't match `filter` are skipped before most of their descriptors are read,
+/// see [`DeviceFilter`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use cameleon::u3v;
+///
+/// let filter = u3v::DeviceFilter::new().serial_number("CAM0001");
+/// let mut cameras = u3v::enumerate_cameras_with(&filter).unwrap();
+/// ```
+pub fn enumerate_cameras_with(
+    filter: &DeviceFilter,
+) -> CameleonResult<Vec<Camera<ControlHandle, StreamHandle>>> {
+    let devices = u3v::enumerate_devices_with(filter).map_err(ControlError::from)?;
 
     let mut cameras: Vec<Camera<ControlHandle, StreamHandle>> = Vec::with_capacity(devices.len());
 
@@ -103,46 +141,32 @@ pub fn enumerate_cameras() -> CameleonResult<Vec<Camera<ControlHandle, StreamHan
     Ok(cameras)
 }
 
-impl From<u3v::Error> for ControlError {
-    fn from(err: u3v::Error) -> ControlError {
-        use u3v::Error::{BufferIo, InvalidDevice, InvalidPacket, LibUsb};
-        use u3v::LibUsbError::{
-            Access, BadDescriptor, Busy, Interrupted, InvalidParam, Io, NoDevice, NoMem, NotFound,
-            NotSupported, Other, Overflow, Pipe, Timeout,
-        };
-
-        match &err {
-            LibUsb(libusb_error) => match libusb_error {
-                Io | InvalidParam | Access | Overflow | Pipe | Interrupted | NoMem
-                | NotSupported | BadDescriptor | Other => ControlError::Io(err.into()),
-                Busy => ControlError::Busy,
-                NoDevice | NotFound => ControlError::Disconnected,
-                Timeout => ControlError::Timeout,
-            },
-
-            BufferIo(_) | InvalidPacket(_) => ControlError::Io(err.into()),
-
-            InvalidDevice => ControlError::InvalidDevice("invalid device".into()),
-        }
+impl<Ctxt> Camera<ControlHandle, StreamHandle, Ctxt> {
+    /// Opens the camera with `options`, see [`Camera::open`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use cameleon::u3v;
+    ///
+    /// let options = u3v::OpenOptions::new().retry_count(5);
+    /// let mut cameras = u3v::enumerate_cameras().unwrap();
+    /// for camera in &mut cameras {
+    ///     camera.open_with(&options).unwrap();
+    /// }
+    /// ```
+    pub fn open_with(&mut self, options: &OpenOptions) -> CameleonResult<()> {
+        self.ctrl.open_with(options)?;
+        self.strm.open_with(options)?;
+        Ok(())
     }
 }
 
-impl From<u3v::Error> for StreamError {
-    fn from(err: u3v::Error) -> Self {
-        use u3v::Error::LibUsb;
-        use u3v::LibUsbError::{
-            Access, BadDescriptor, Busy, Interrupted, InvalidParam, Io, NoDevice, NoMem, NotFound,
-            NotSupported, Other, Overflow, Pipe, Timeout,
-        };
-
-        match &err {
-            LibUsb(libusb_error) => match libusb_error {
-                Io | InvalidParam | Access | Overflow | Pipe | Interrupted | NoMem
-                | NotSupported | BadDescriptor | Busy | Other => Self::Io(err.into()),
-                NoDevice | NotFound => Self::Disconnected,
-                Timeout => Self::Timeout,
-            },
-            _ => Self::Io(err.into()),
-        }
+impl<Ctxt> Camera<SharedControlHandle, StreamHandle, Ctxt> {
+    /// Opens the camera with `options`, see [`Camera::open`].
+    pub fn open_with(&mut self, options: &OpenOptions) -> CameleonResult<()> {
+        self.ctrl.open_with(options)?;
+        self.strm.open_with(options)?;
+        Ok(())
     }
 }
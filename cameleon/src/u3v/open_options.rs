@@ -0,0 +1,277 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains [`OpenOptions`] which configures handles when they are opened.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use cameleon::u3v;
+//!
+//! let options = u3v::OpenOptions::new()
+//!     .timeout_duration(Duration::from_millis(1000))
+//!     .retry_count(5);
+//!
+//! // The same options can be applied to all cameras.
+//! for mut camera in u3v::enumerate_cameras().unwrap() {
+//!     camera.open_with(&options).unwrap();
+//! }
+//! ```
+
+use std::time::Duration;
+
+use crate::limits::Limits;
+
+use super::{control_handle::DEFAULT_OPEN_TAG, Quirks, ThreadConfig};
+
+/// Default value of [`OpenOptions::retry_count`].
+pub(super) const DEFAULT_RETRY_COUNT: u16 = 3;
+
+/// Default value of [`OpenOptions::pipeline_depth`], pipelining is disabled.
+pub(super) const DEFAULT_PIPELINE_DEPTH: u16 = 1;
+
+/// Options applied to the handles when they are opened.
+///
+/// Default values are the same as the values of the handles which are opened without options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(default))]
+pub struct OpenOptions {
+    /// Timeout duration of each transaction between device. If `None`, the maximum device
+    /// response time reported by the device is used.
+    pub(super) timeout_duration: Option<Duration>,
+    /// The value determines how many times to retry when pending acknowledge is returned from the
+    /// device.
+    pub(super) retry_count: u16,
+    /// The maximum number of commands outstanding in
+    /// [`ControlHandle::read_pipelined`](super::ControlHandle::read_pipelined).
+    pub(super) pipeline_depth: u16,
+    /// Limits on the values claimed by the device.
+    pub(super) limits: Limits,
+    /// The tag reported to other openers in the process.
+    pub(super) open_tag: String,
+    /// Configuration of the thread which receives stream packets.
+    pub(super) stream_thread: ThreadConfig,
+    /// Workarounds for the device.
+    pub(super) quirks: Quirks,
+    /// Lets libusb detach the kernel driver bound to the interfaces while the handles are opened.
+    pub(super) detach_kernel_driver: bool,
+    /// Heartbeat timeout of the device. If `None`, the heartbeat configuration is left as is.
+    pub(super) heartbeat_timeout: Option<Duration>,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self {
+            timeout_duration: None,
+            retry_count: DEFAULT_RETRY_COUNT,
+            pipeline_depth: DEFAULT_PIPELINE_DEPTH,
+            limits: Limits::default(),
+            open_tag: DEFAULT_OPEN_TAG.into(),
+            stream_thread: ThreadConfig::default(),
+            quirks: Quirks::default(),
+            detach_kernel_driver: false,
+            heartbeat_timeout: None,
+        }
+    }
+}
+
+impl OpenOptions {
+    /// Constructs options with default values.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets timeout duration of each transaction between device.
+    ///
+    /// The timeout is bounded below by the maximum device response time, see
+    /// [`ControlHandle::set_default_timeout`](super::ControlHandle::set_default_timeout).
+    #[must_use]
+    pub fn timeout_duration(mut self, duration: Duration) -> Self {
+        self.timeout_duration = Some(duration);
+        self
+    }
+
+    /// Sets the value determines how many times to retry when pending acknowledge is returned
+    /// from the device.
+    #[must_use]
+    pub fn retry_count(mut self, count: u16) -> Self {
+        self.retry_count = count;
+        self
+    }
+
+    /// Sets the maximum number of commands outstanding in
+    /// [`ControlHandle::read_pipelined`](super::ControlHandle::read_pipelined).
+    ///
+    /// `GenCP` has no register which advertises the depth of the command queue of the device, so
+    /// set the depth documented by the vendor. `1` disables pipelining.
+    #[must_use]
+    pub fn pipeline_depth(mut self, depth: u16) -> Self {
+        self.pipeline_depth = depth;
+        self
+    }
+
+    /// Sets [`Limits`] on the values claimed by the device.
+    #[must_use]
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Sets the tag reported to other openers in the process, see
+    /// [`super::ControlHandle::set_open_tag`].
+    #[must_use]
+    pub fn open_tag(mut self, tag: impl Into<String>) -> Self {
+        self.open_tag = tag.into();
+        self
+    }
+
+    /// Sets configuration of the thread which receives stream packets.
+    #[must_use]
+    pub fn stream_thread(mut self, config: ThreadConfig) -> Self {
+        self.stream_thread = config;
+        self
+    }
+
+    /// Sets [`Quirks`], workarounds for the device.
+    #[must_use]
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Sets whether libusb detaches the kernel driver bound to the interfaces while the handles
+    /// are opened, and reattaches it when they're closed.
+    ///
+    /// Opening fails on platforms which can't detach kernel drivers if `detach` is `true`.
+    #[must_use]
+    pub fn detach_kernel_driver(mut self, detach: bool) -> Self {
+        self.detach_kernel_driver = detach;
+        self
+    }
+
+    /// Sets the heartbeat timeout of the device, which is enabled when the control handle is
+    /// opened.
+    ///
+    /// The heartbeat is a watchdog of the device which regards the host as gone once it receives
+    /// no command for `timeout`, see
+    /// [`Abrm::set_heartbeat_timeout`](super::register_map::Abrm::set_heartbeat_timeout).
+    #[must_use]
+    pub fn heartbeat_timeout(mut self, timeout: Duration) -> Self {
+        self.heartbeat_timeout = Some(timeout);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let limits = Limits {
+            max_xml_size: 1024,
+            ..Limits::default()
+        };
+        let thread = ThreadConfig {
+            name: "stream".into(),
+            ..ThreadConfig::default()
+        };
+        let quirks = Quirks {
+            leader_headroom: 64,
+            ..Quirks::default()
+        };
+        let options = OpenOptions::new()
+            .timeout_duration(Duration::from_millis(100))
+            .retry_count(10)
+            .pipeline_depth(2)
+            .limits(limits)
+            .open_tag("tag")
+            .stream_thread(thread.clone())
+            .quirks(quirks)
+            .detach_kernel_driver(true)
+            .heartbeat_timeout(Duration::from_secs(3));
+
+        assert_eq!(options.timeout_duration, Some(Duration::from_millis(100)));
+        assert_eq!(options.retry_count, 10);
+        assert_eq!(options.pipeline_depth, 2);
+        assert_eq!(options.limits, limits);
+        assert_eq!(options.open_tag, "tag");
+        assert_eq!(options.stream_thread, thread);
+        assert_eq!(options.quirks, quirks);
+        assert!(options.detach_kernel_driver);
+        assert_eq!(options.heartbeat_timeout, Some(Duration::from_secs(3)));
+
+        let default = OpenOptions::default();
+        assert!(default.timeout_duration.is_none());
+        assert_eq!(default.retry_count, DEFAULT_RETRY_COUNT);
+        assert_eq!(default.pipeline_depth, DEFAULT_PIPELINE_DEPTH);
+        assert_eq!(default.limits, Limits::default());
+        assert_eq!(default.open_tag, DEFAULT_OPEN_TAG);
+        assert_eq!(default.stream_thread, ThreadConfig::default());
+        assert_eq!(default.quirks, Quirks::default());
+        assert!(!default.detach_kernel_driver);
+        assert!(default.heartbeat_timeout.is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize() {
+        let options: OpenOptions = toml::from_str(
+            r#"
+            retry_count = 5
+            open_tag = "inspection"
+            timeout_duration = { secs = 1, nanos = 0 }
+            detach_kernel_driver = true
+            heartbeat_timeout = { secs = 3, nanos = 0 }
+
+            [limits]
+            max_xml_size = 1024
+            max_manifest_entries = 4
+            max_allocation = 4096
+            max_pending_timeout = { secs = 2, nanos = 0 }
+            max_pending_wait = { secs = 5, nanos = 0 }
+
+            [stream_thread]
+            name = "stream"
+            core_affinity = [1]
+            priority = "High"
+
+            [quirks]
+            leader_headroom = 64
+            "#,
+        )
+        .unwrap();
+
+        let expected = OpenOptions::new()
+            .retry_count(5)
+            .open_tag("inspection")
+            .timeout_duration(Duration::from_secs(1))
+            .detach_kernel_driver(true)
+            .heartbeat_timeout(Duration::from_secs(3))
+            .limits(Limits {
+                max_xml_size: 1024,
+                max_manifest_entries: 4,
+                max_allocation: 4096,
+                max_pending_timeout: Duration::from_secs(2),
+                max_pending_wait: Duration::from_secs(5),
+            })
+            .stream_thread(ThreadConfig {
+                name: "stream".into(),
+                core_affinity: Some(vec![1]),
+                priority: Some(super::super::ThreadPriority::High),
+            })
+            .quirks(Quirks {
+                leader_headroom: 64,
+                trailer_headroom: 0,
+            });
+        assert_eq!(options, expected);
+
+        // Omitted fields are default.
+        let options: OpenOptions = toml::from_str("retry_count = 5").unwrap();
+        assert_eq!(options, OpenOptions::new().retry_count(5));
+    }
+}
@@ -0,0 +1,219 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains the registry of devices opened in the process.
+//!
+//! `libusb` only reports `Busy` when the same device is opened twice in the process, so the
+//! registry keeps track of who holds the device and reports it instead.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+};
+
+use crate::{ControlError, ControlResult};
+
+use super::Guid;
+
+/// A registry of the devices opened in the process, keyed by the GUID of the device.
+///
+/// `T` is the type of the handle which can be shared with other openers.
+pub(super) struct OpenRegistry<T> {
+    entries: Mutex<BTreeMap<Guid, Entry<T>>>,
+}
+
+struct Entry<T> {
+    /// Tag of the opener, used in the error message.
+    tag: String,
+    /// Token held by [`OpenGuard`], the entry is stale if the token is already dropped.
+    token: Weak<()>,
+    /// The handle shared with other openers if the holder opts into sharing.
+    shared: Option<Weak<T>>,
+}
+
+impl<T> Entry<T> {
+    fn is_alive(&self) -> bool {
+        self.token.strong_count() > 0
+    }
+}
+
+impl<T> OpenRegistry<T> {
+    pub(super) const fn new() -> Self {
+        Self {
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Registers the device as opened by `tag`.
+    ///
+    /// The device is unregistered when the returned guard is dropped.
+    pub(super) fn acquire(&'static self, guid: &Guid, tag: &str) -> ControlResult<OpenGuard<T>> {
+        let mut entries = self.entries();
+        if let Some(entry) = entries.get(guid).filter(|entry| entry.is_alive()) {
+            return Err(ControlError::AlreadyOpenInProcess {
+                holder_tag: entry.tag.clone(),
+            });
+        }
+
+        let token = Arc::new(());
+        entries.insert(
+            *guid,
+            Entry {
+                tag: tag.to_string(),
+                token: Arc::downgrade(&token),
+                shared: None,
+            },
+        );
+
+        Ok(OpenGuard {
+            registry: self,
+            guid: *guid,
+            token,
+        })
+    }
+
+    /// Returns the handle which holds the device if the holder opts into sharing.
+    pub(super) fn shared(&self, guid: &Guid) -> Option<Arc<T>> {
+        self.entries()
+            .get(guid)
+            .filter(|entry| entry.is_alive())
+            .and_then(|entry| entry.shared.as_ref()?.upgrade())
+    }
+
+    fn entries(&self) -> MutexGuard<'_, BTreeMap<Guid, Entry<T>>> {
+        // The registry is always consistent, so it's safe to ignore poisoning.
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A guard which unregisters the device from [`OpenRegistry`] when dropped.
+pub(super) struct OpenGuard<T: 'static> {
+    registry: &'static OpenRegistry<T>,
+    guid: Guid,
+    token: Arc<()>,
+}
+
+impl<T> OpenGuard<T> {
+    /// Allows other openers to obtain `handle` via [`OpenRegistry::shared`].
+    pub(super) fn share(&self, handle: &Arc<T>) {
+        if let Some(entry) = self.registry.entries().get_mut(&self.guid) {
+            entry.shared = Some(Arc::downgrade(handle));
+        }
+    }
+
+    /// Sets the tag of the opener.
+    pub(super) fn set_tag(&self, tag: &str) {
+        if let Some(entry) = self.registry.entries().get_mut(&self.guid) {
+            entry.tag = tag.to_string();
+        }
+    }
+}
+
+impl<T> Drop for OpenGuard<T> {
+    fn drop(&mut self) {
+        let mut entries = self.registry.entries();
+        // The entry may be replaced by another opener after this guard became stale.
+        let is_own = matches!(
+            entries.get(&self.guid),
+            Some(entry) if Weak::ptr_eq(&entry.token, &Arc::downgrade(&self.token))
+        );
+        if is_own {
+            entries.remove(&self.guid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Barrier, thread};
+
+    use super::*;
+
+    fn guid(s: &str) -> Guid {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_concurrent_open() {
+        static REGISTRY: OpenRegistry<()> = OpenRegistry::new();
+        let barrier = Arc::new(Barrier::new(2));
+
+        let handles: Vec<_> = ["camera", "gentl-device-module"]
+            .iter()
+            .map(|tag| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    match REGISTRY.acquire(&guid("2A2B00000001"), tag) {
+                        Ok(_guard) => {
+                            // Keep the guard alive until both threads try to open.
+                            barrier.wait();
+                            Ok(*tag)
+                        }
+                        Err(ControlError::AlreadyOpenInProcess { holder_tag }) => {
+                            barrier.wait();
+                            Err(holder_tag)
+                        }
+                        Err(e) => panic!("unexpected error: {}", e),
+                    }
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        let holder = results.iter().find_map(|res| res.as_ref().ok()).unwrap();
+        let holder_tag = results.iter().find_map(|res| res.as_ref().err()).unwrap();
+        assert_eq!(holder_tag, holder);
+
+        // Both guards are dropped.
+        assert!(REGISTRY.acquire(&guid("2a2b-00000001"), "camera").is_ok());
+    }
+
+    #[test]
+    fn test_release_on_panic() {
+        static REGISTRY: OpenRegistry<()> = OpenRegistry::new();
+
+        let res = thread::spawn(|| {
+            let _guard = REGISTRY.acquire(&guid("2A2B00000002"), "camera").unwrap();
+            panic!("panic while the device is opened");
+        })
+        .join();
+        assert!(res.is_err());
+
+        assert!(REGISTRY.acquire(&guid("2A2B00000002"), "camera").is_ok());
+    }
+
+    #[test]
+    fn test_shared_handle() {
+        static REGISTRY: OpenRegistry<Mutex<u32>> = OpenRegistry::new();
+
+        let id = guid("2A2B00000003");
+        let handle = Arc::new(Mutex::new(0));
+        let guard = REGISTRY.acquire(&id, "camera").unwrap();
+        assert!(REGISTRY.shared(&id).is_none());
+
+        guard.share(&handle);
+        let shared = REGISTRY.shared(&id).unwrap();
+        *shared.lock().unwrap() += 1;
+        assert_eq!(*handle.lock().unwrap(), 1);
+        assert!(REGISTRY.acquire(&id, "gentl-device-module").is_err());
+
+        drop(guard);
+        assert!(REGISTRY.shared(&id).is_none());
+    }
+
+    #[test]
+    fn test_guid_forms() {
+        static REGISTRY: OpenRegistry<()> = OpenRegistry::new();
+
+        let _guard = REGISTRY.acquire(&guid("2A2B00000004"), "camera").unwrap();
+        // The same device reported in another form.
+        match REGISTRY.acquire(&guid("{2a2b-00000004}"), "gentl-device-module") {
+            Err(ControlError::AlreadyOpenInProcess { holder_tag }) => {
+                assert_eq!(holder_tag, "camera");
+            }
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+    }
+}
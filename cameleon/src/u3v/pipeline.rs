@@ -0,0 +1,498 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains pipelined `ReadMem` transactions.
+//!
+//! Some devices accept a new command before sending back the ack of the previous one. For such
+//! devices, [`Pipeline`] keeps up to `depth` commands outstanding so that round trips of
+//! consecutive reads overlap. Each command has a distinct request id, and acks are matched to
+//! their commands by the id, so data is always copied into the buffer of its own read.
+//!
+//! `GenCP` has no register which reports the depth of the command queue, so pipelining is enabled
+//! only when the user opts in. If the device answers `Busy` or sends an ack which doesn't belong
+//! to any outstanding command, the pipeline stops submitting, drains the outstanding commands,
+//! then reads the rest serially.
+
+use std::{
+    collections::VecDeque,
+    convert::TryInto,
+    time::{Duration, Instant},
+};
+
+use cameleon_device::u3v::protocol::{
+    ack::{self, GenCpStatus, StatusKind},
+    cmd::{self, CommandScd},
+};
+use tracing::warn;
+
+use super::control_handle::{discard_stale_ack, Deadline, StatusError};
+
+use crate::{
+    latency::{LatencyRecorder, TransactionKind},
+    limits::Limits,
+    ControlError, ControlResult,
+};
+
+/// Length of the prefix and ccd of an ack packet.
+const ACK_HEADER_LENGTH: usize = 4 + 8;
+
+/// Length of the scd of a pending ack.
+const PENDING_ACK_SCD_LENGTH: usize = 4;
+
+/// Sends commands to and receives acks from the device.
+pub(super) trait Transport {
+    fn send(&mut self, buf: &[u8], timeout: Duration) -> ControlResult<()>;
+
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> ControlResult<usize>;
+}
+
+/// A read served by a single `ReadMem` command.
+pub(super) struct PipelinedRead<'a> {
+    /// Index of the entry passed by the user, used to report the failed entry.
+    pub(super) entry: usize,
+    pub(super) address: u64,
+    /// The length must fit into a single ack.
+    pub(super) buf: &'a mut [u8],
+    /// `true` once the data is copied into `buf`.
+    pub(super) done: bool,
+}
+
+impl<'a> PipelinedRead<'a> {
+    pub(super) fn new(entry: usize, address: u64, buf: &'a mut [u8]) -> Self {
+        Self {
+            entry,
+            address,
+            buf,
+            done: false,
+        }
+    }
+}
+
+/// The reason why [`Pipeline`] fell back to serial transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Fallback {
+    /// The device answered `Busy` to a command.
+    Busy,
+    /// The device sent an ack which doesn't belong to any outstanding command.
+    Mismatch,
+}
+
+/// A command sent to the device whose final ack isn't received yet.
+struct Outstanding {
+    request_id: u16,
+    /// Index of the read in the slice passed to [`Pipeline::read`].
+    read: usize,
+    sent_at: Instant,
+    deadline: Deadline,
+}
+
+pub(super) struct Pipeline<'a, T> {
+    pub(super) transport: &'a mut T,
+    /// The maximum number of outstanding commands.
+    pub(super) depth: u16,
+    /// Timeout duration of each command.
+    pub(super) timeout: Duration,
+    /// The maximum number of times a pending ack is accepted for each command.
+    pub(super) retry_count: u16,
+    pub(super) limits: Limits,
+    pub(super) latency: &'a LatencyRecorder,
+}
+
+impl<'a, T: Transport> Pipeline<'a, T> {
+    /// Reads all `reads`, and returns the reason if the pipeline fell back to serial
+    /// transactions on the way.
+    ///
+    /// `next_req_id` is the request id of the first command, and is advanced past the ids used.
+    pub(super) fn read(
+        &mut self,
+        reads: &mut [PipelinedRead],
+        next_req_id: &mut u16,
+    ) -> ControlResult<Option<Fallback>> {
+        let fallback = self.run(reads, next_req_id)?;
+        if let Some(reason) = fallback {
+            warn!(
+                ?reason,
+                depth = self.depth,
+                "the device can't handle pipelined commands, falling back to serial transactions"
+            );
+            self.depth = 1;
+            self.run(reads, next_req_id)?;
+        }
+
+        Ok(fallback)
+    }
+
+    /// Sends commands of the reads which aren't done yet while keeping up to `depth` commands
+    /// outstanding.
+    ///
+    /// Once the device answers `Busy` or an unknown ack, no command is sent anymore and the
+    /// outstanding commands are drained. In serial mode, i.e. `depth` is 1, they are errors as is
+    /// the case with [`super::ControlHandle`].
+    fn run(
+        &mut self,
+        reads: &mut [PipelinedRead],
+        next_req_id: &mut u16,
+    ) -> ControlResult<Option<Fallback>> {
+        let depth = usize::from(self.depth.max(1));
+        let ack_len = reads.iter().map(|read| read.buf.len()).max().unwrap_or(0);
+        let mut ack_buf = vec![0; ACK_HEADER_LENGTH + ack_len.max(PENDING_ACK_SCD_LENGTH)];
+        let mut cmd_buf = vec![];
+
+        let mut outstanding = VecDeque::with_capacity(depth);
+        let mut next = 0;
+        let mut fallback = None;
+        let mut stale_acks = 0;
+        loop {
+            // A pending ack pauses further submissions until the command is resolved.
+            let paused = outstanding
+                .iter()
+                .any(|cmd: &Outstanding| cmd.deadline.is_pending());
+            while fallback.is_none() && !paused && outstanding.len() < depth {
+                let read = match reads[next..].iter().position(|read| !read.done) {
+                    Some(pos) => next + pos,
+                    None => break,
+                };
+                next = read + 1;
+
+                let len = reads[read].buf.len().try_into().unwrap();
+                let cmd = cmd::ReadMem::new(reads[read].address, len).finalize(*next_req_id);
+                cmd_buf.clear();
+                cmd.serialize(&mut cmd_buf)?;
+                self.transport.send(&cmd_buf, self.timeout)?;
+                let sent_at = Instant::now();
+                outstanding.push_back(Outstanding {
+                    request_id: *next_req_id,
+                    read,
+                    sent_at,
+                    deadline: Deadline::new(self.timeout),
+                });
+                *next_req_id = next_req_id.wrapping_add(1);
+            }
+
+            let deadline = match outstanding.iter().map(|cmd| cmd.deadline.at()).min() {
+                Some(deadline) => deadline,
+                None => break,
+            };
+            let now = Instant::now();
+            let recv_len = match self
+                .transport
+                .recv(&mut ack_buf, deadline.saturating_duration_since(now))
+            {
+                Ok(len) if Instant::now() <= deadline => len,
+                // Commands left outstanding while draining are read again serially.
+                Ok(_) | Err(ControlError::Timeout) if fallback.is_some() => break,
+                Ok(_) => return Err(ControlError::Timeout),
+                Err(e) => return Err(e),
+            };
+
+            let ack = ack::AckPacket::parse(&ack_buf[..recv_len])?;
+            let pos = match outstanding
+                .iter()
+                .position(|cmd| cmd.request_id == ack.request_id())
+            {
+                Some(pos) => pos,
+                // In serial mode, an unknown ack is a stale one left by an earlier command.
+                None if depth == 1 => {
+                    discard_stale_ack(&ack, outstanding[0].request_id, &mut stale_acks)?;
+                    continue;
+                }
+                None => {
+                    fallback.get_or_insert(Fallback::Mismatch);
+                    continue;
+                }
+            };
+
+            let status = ack.status();
+            if !status.is_success() {
+                if depth > 1 && status.kind() == &StatusKind::GenCp(GenCpStatus::Busy) {
+                    outstanding.remove(pos);
+                    fallback.get_or_insert(Fallback::Busy);
+                    continue;
+                }
+                return Err(ControlError::Io(StatusError(*status).into()));
+            }
+
+            if ack.scd_kind() == ack::ScdKind::Pending {
+                let pending_ack: ack::Pending = ack.scd_as()?;
+                outstanding[pos].deadline.extend(
+                    ack.request_id(),
+                    pending_ack.timeout,
+                    self.retry_count,
+                    &self.limits,
+                )?;
+                continue;
+            }
+
+            let cmd = outstanding.remove(pos).unwrap();
+            let data = ack.scd_as::<ack::ReadMem>()?.data;
+            let read = &mut reads[cmd.read];
+            if data.len() != read.buf.len() {
+                return Err(ControlError::PartialRead {
+                    index: read.entry,
+                    requested: read.buf.len(),
+                    read: data.len(),
+                });
+            }
+            read.buf.copy_from_slice(data);
+            read.done = true;
+            self.latency
+                .record(TransactionKind::Read, read.address, cmd.sent_at.elapsed());
+        }
+
+        Ok(fallback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const TRANSIT: Duration = Duration::from_micros(100);
+    const SERVICE: Duration = Duration::from_micros(100);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Event {
+        Send(u16),
+        Recv(u16, ack::ScdKind),
+    }
+
+    /// Device which has a command queue of `queue_depth`, and whose time advances only while the
+    /// host waits for an ack.
+    ///
+    /// Each packet takes [`TRANSIT`] to reach the other side, and each command takes [`SERVICE`]
+    /// to be processed.
+    struct FakeDevice {
+        queue_depth: usize,
+        memory: Vec<u8>,
+        /// Addresses whose first command is answered with a pending ack.
+        pending: HashSet<u64>,
+        now: Duration,
+        /// The time when the device finishes the last accepted command.
+        free_at: Duration,
+        /// The times when accepted commands are finished.
+        finishing: Vec<Duration>,
+        /// Acks on the wire with their arrival time.
+        acks: Vec<(Duration, Vec<u8>)>,
+        events: Vec<Event>,
+    }
+
+    impl FakeDevice {
+        fn new(queue_depth: usize) -> Self {
+            Self {
+                queue_depth,
+                memory: (0..=255).collect(),
+                pending: HashSet::new(),
+                now: Duration::ZERO,
+                free_at: Duration::ZERO,
+                finishing: vec![],
+                acks: vec![],
+                events: vec![],
+            }
+        }
+
+        fn push_ack(&mut self, arrival: Duration, ack: &ack::AckPacket) {
+            let mut buf = vec![];
+            ack.serialize(&mut buf).unwrap();
+            self.acks.push((arrival, buf));
+        }
+    }
+
+    impl Transport for FakeDevice {
+        fn send(&mut self, buf: &[u8], _timeout: Duration) -> ControlResult<()> {
+            let request_id = u16::from_le_bytes(buf[10..12].try_into().unwrap());
+            let address = u64::from_le_bytes(buf[12..20].try_into().unwrap());
+            let len = u16::from_le_bytes(buf[22..24].try_into().unwrap()) as usize;
+            self.events.push(Event::Send(request_id));
+
+            let arrival = self.now + TRANSIT;
+            self.finishing.retain(|&finish| finish > arrival);
+            if self.finishing.len() >= self.queue_depth {
+                let ack =
+                    ack::AckPacket::error_ack(request_id, ack::ScdKind::ReadMem, GenCpStatus::Busy);
+                self.push_ack(arrival + TRANSIT, &ack);
+                return Ok(());
+            }
+
+            let finish = arrival.max(self.free_at) + SERVICE;
+            self.free_at = finish;
+            self.finishing.push(finish);
+            if self.pending.remove(&address) {
+                let ack =
+                    ack::AckPacket::pending_ack(request_id, Duration::from_millis(1)).unwrap();
+                self.push_ack(arrival + TRANSIT, &ack);
+            }
+            let address = address as usize;
+            let data = self.memory[address..address + len].to_vec();
+            let ack = ack::AckPacket::read_mem_ack(request_id, &data).unwrap();
+            self.push_ack(finish + TRANSIT, &ack);
+            Ok(())
+        }
+
+        fn recv(&mut self, buf: &mut [u8], _timeout: Duration) -> ControlResult<usize> {
+            let next = (0..self.acks.len())
+                .min_by_key(|&i| self.acks[i].0)
+                .ok_or(ControlError::Timeout)?;
+            let (arrival, ack) = self.acks.remove(next);
+            self.now = self.now.max(arrival);
+            buf[..ack.len()].copy_from_slice(&ack);
+
+            let parsed = ack::AckPacket::parse(&ack).unwrap();
+            self.events
+                .push(Event::Recv(parsed.request_id(), parsed.scd_kind()));
+            Ok(ack.len())
+        }
+    }
+
+    /// Reads 4 bytes from each of `addresses`, and returns the read data.
+    fn read(
+        device: &mut FakeDevice,
+        depth: u16,
+        addresses: &[u64],
+    ) -> (ControlResult<Option<Fallback>>, Vec<[u8; 4]>) {
+        let latency = LatencyRecorder::default();
+        let mut pipeline = Pipeline {
+            transport: device,
+            depth,
+            timeout: Duration::from_secs(1),
+            retry_count: 3,
+            limits: Limits::default(),
+            latency: &latency,
+        };
+        let mut bufs = vec![[0; 4]; addresses.len()];
+        let mut reads: Vec<_> = addresses
+            .iter()
+            .zip(bufs.iter_mut())
+            .enumerate()
+            .map(|(i, (&address, buf))| PipelinedRead::new(i, address, buf))
+            .collect();
+        let result = pipeline.read(&mut reads, &mut 0);
+        drop(reads);
+        (result, bufs)
+    }
+
+    fn expected(addresses: &[u64]) -> Vec<[u8; 4]> {
+        addresses
+            .iter()
+            .map(|&address| {
+                let a = address as u8;
+                [a, a + 1, a + 2, a + 3]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pipelined_read() {
+        let addresses: Vec<u64> = (0..20).map(|i| i * 8).collect();
+
+        let mut serial = FakeDevice::new(2);
+        let (result, bufs) = read(&mut serial, 1, &addresses);
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(bufs, expected(&addresses));
+
+        let mut pipelined = FakeDevice::new(2);
+        let (result, bufs) = read(&mut pipelined, 2, &addresses);
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(bufs, expected(&addresses));
+
+        // Each serial read takes a round trip, while pipelined reads overlap their transit.
+        assert_eq!(serial.now, (TRANSIT * 2 + SERVICE) * 20);
+        assert!(pipelined.now * 10 < serial.now * 6);
+    }
+
+    #[test]
+    fn test_busy_fallback() {
+        let addresses: Vec<u64> = (0..6).map(|i| i * 4).collect();
+        let mut device = FakeDevice::new(1);
+        let (result, bufs) = read(&mut device, 2, &addresses);
+        assert_eq!(result.unwrap(), Some(Fallback::Busy));
+        assert_eq!(bufs, expected(&addresses));
+
+        // After the busy ack, commands are sent one at a time.
+        let first_busy = device
+            .events
+            .iter()
+            .position(|&e| e == Event::Recv(1, ack::ScdKind::ReadMem))
+            .unwrap();
+        // The first command is still outstanding when the busy ack is received.
+        let mut outstanding = 1;
+        for event in &device.events[first_busy + 1..] {
+            match event {
+                Event::Send(_) => outstanding += 1,
+                Event::Recv(..) => outstanding -= 1,
+            }
+            assert!(outstanding <= 1);
+        }
+    }
+
+    #[test]
+    fn test_mismatch_fallback() {
+        let addresses = [0, 4, 8, 12];
+        let mut device = FakeDevice::new(2);
+        // A stale ack left by an aborted transaction.
+        let stale = ack::AckPacket::read_mem_ack(0xffff, &[0; 4]).unwrap();
+        device.push_ack(Duration::ZERO, &stale);
+
+        let (result, bufs) = read(&mut device, 2, &addresses);
+        assert_eq!(result.unwrap(), Some(Fallback::Mismatch));
+        assert_eq!(bufs, expected(&addresses));
+    }
+
+    #[test]
+    fn test_serial_resync() {
+        let addresses = [0, 4, 8];
+        let mut device = FakeDevice::new(1);
+        // The ack of a command which timed out arrives late.
+        let delayed = ack::AckPacket::read_mem_ack(0xfffe, &[0xff; 4]).unwrap();
+        device.push_ack(Duration::ZERO, &delayed);
+
+        let (result, bufs) = read(&mut device, 1, &addresses);
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(bufs, expected(&addresses));
+    }
+
+    #[test]
+    fn test_serial_request_id_mismatch() {
+        let mut device = FakeDevice::new(1);
+        for request_id in 0x100..0x110 {
+            let stale = ack::AckPacket::read_mem_ack(request_id, &[0xff; 4]).unwrap();
+            device.push_ack(Duration::ZERO, &stale);
+        }
+
+        let (result, _) = read(&mut device, 1, &[0]);
+        assert!(matches!(
+            result,
+            Err(ControlError::RequestIdMismatch {
+                expected: 0,
+                actual: 0x108
+            })
+        ));
+    }
+
+    #[test]
+    fn test_pending_pauses_submission() {
+        let addresses = [0, 4, 8, 12];
+        let mut device = FakeDevice::new(2);
+        device.pending.insert(4);
+
+        let (result, bufs) = read(&mut device, 2, &addresses);
+        assert_eq!(result.unwrap(), None);
+        assert_eq!(bufs, expected(&addresses));
+
+        // No command is sent until the final ack of the pending command is received.
+        let events = &device.events;
+        let pending = events
+            .iter()
+            .position(|&e| e == Event::Recv(1, ack::ScdKind::Pending))
+            .unwrap();
+        let resolved = events
+            .iter()
+            .position(|&e| e == Event::Recv(1, ack::ScdKind::ReadMem))
+            .unwrap();
+        assert!(events[pending..resolved]
+            .iter()
+            .all(|e| !matches!(e, Event::Send(_))));
+    }
+}
@@ -0,0 +1,30 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains [`Quirks`], workarounds for devices which don't follow the specification.
+
+/// Workarounds for devices which don't follow the specification.
+///
+/// All workarounds are disabled by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(default))]
+pub struct Quirks {
+    /// Bytes added to the maximum leader size reported by the device.
+    ///
+    /// Some devices under-report the maximum leader size and send larger leaders, e.g. leaders
+    /// with extended chunk information. Such a leader is lost, and the streaming loop raises the
+    /// headroom to receive the following leaders up to
+    /// [`StreamParams::max_section_transfer_size`]. A leader exceeding it fails to be received
+    /// with [`StreamError::SectionOverflow`], which reports the size required to receive it.
+    ///
+    /// Setting the headroom in advance avoids losing the first leader.
+    ///
+    /// [`StreamError::SectionOverflow`]: crate::StreamError::SectionOverflow
+    /// [`StreamParams::max_section_transfer_size`]: super::StreamParams::max_section_transfer_size
+    pub leader_headroom: usize,
+
+    /// Bytes added to the maximum trailer size reported by the device, see
+    /// [`Quirks::leader_headroom`].
+    pub trailer_headroom: usize,
+}
@@ -28,10 +28,10 @@
 //!
 //! let ctrl = &mut camera.ctrl;
 //! // Get Abrm.
-//! let abrm = ctrl.abrm().unwrap();
+//! let mut abrm = ctrl.abrm().unwrap();
 //!
 //! // Read serial number from ABRM.
-//! let serial_number = abrm.serial_number(ctrl).unwrap();
+//! let serial_number = abrm.serial_number();
 //! println!("{}", serial_number);
 //!
 //! // Check user defined name feature is supported.
@@ -39,28 +39,39 @@
 //! let device_capability = abrm.device_capability().unwrap();
 //! if device_capability.is_user_defined_name_supported() {
 //!     // Read from user defined name register.
-//!     let user_defined_name = abrm.user_defined_name(ctrl).unwrap().unwrap();
+//!     let user_defined_name = abrm.user_defined_name().unwrap();
 //!     println!("{}", user_defined_name);
 //!
 //!     // Write new name to the register.
 //!     abrm.set_user_defined_name(ctrl, "cameleon").unwrap();
 //! }
 //! ```
-use std::{convert::TryInto, time::Duration};
+use std::{
+    convert::TryInto,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use cameleon_device::u3v::{
     self,
-    register_map::{abrm, manifest_entry, sbrm, sirm},
+    protocol::ack,
+    register_map::{abrm, eirm, manifest_entry, sbrm, sirm},
+};
+
+use crate::{
+    checked_address, genapi::CompressionType, limits::Limits, ControlError, ControlResult,
+    DeviceControl,
 };
 
-use crate::{genapi::CompressionType, ControlError, ControlResult, DeviceControl};
+use super::control_handle::StatusError;
 
 /// Represent Technology Agnostic Boot Register Map (`ABRM`), refer to `GenCP` specification for more
 /// information about `ABRM`.
 ///
-/// To maintain consistency with the device data, `Abrm` doesn't cache any data. It means
-/// that all methods of this struct cause communication with the device every time, thus the device
-/// is expected to be opened when methods are called.
+/// Static registers of `Abrm` are read once when `Abrm` is constructed and the getters return the
+/// cached values. `USER DEFINED NAME` is also cached, call [`Abrm::refresh`] to re-read it when
+/// the register may be modified by another host. `TIMESTAMP` and `DEVICE CONFIGURATION` are
+/// never cached, thus the device is expected to be opened when the methods accessing them are
+/// called.
 ///
 /// # Examples
 ///
@@ -83,10 +94,10 @@ use crate::{genapi::CompressionType, ControlError, ControlResult, DeviceControl}
 ///
 /// let ctrl = &mut camera.ctrl;
 /// // Get Abrm.
-/// let abrm = ctrl.abrm().unwrap();
+/// let mut abrm = ctrl.abrm().unwrap();
 ///
 /// // Read serial number from ABRM.
-/// let serial_number = abrm.serial_number(ctrl).unwrap();
+/// let serial_number = abrm.serial_number();
 /// println!("{}", serial_number);
 ///
 /// // Check user defined name feature is supported.
@@ -94,166 +105,228 @@ use crate::{genapi::CompressionType, ControlError, ControlResult, DeviceControl}
 /// let device_capability = abrm.device_capability().unwrap();
 /// if device_capability.is_user_defined_name_supported() {
 ///     // Read from user defined name register.
-///     let user_defined_name = abrm.user_defined_name(ctrl).unwrap().unwrap();
+///     let user_defined_name = abrm.user_defined_name().unwrap();
 ///     println!("{}", user_defined_name);
 ///
 ///     // Write new name to the register.
 ///     abrm.set_user_defined_name(ctrl, "cameleon").unwrap();
 /// }
 /// ```
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Abrm {
+    gencp_version: u32,
+    manufacturer_name: String,
+    model_name: String,
+    family_name: Option<String>,
+    device_version: String,
+    manufacturer_info: String,
+    serial_number: String,
+    user_defined_name: Option<String>,
+    manifest_table_address: u64,
+    sbrm_address: u64,
+    timestamp_increment: u64,
+    device_software_interface_version: Option<String>,
+    maximum_device_response_time: Duration,
     device_capability: DeviceCapability,
 }
 
 impl Abrm {
-    /// Constructs new `Abrm`, consider using [`super::ControlHandle::abrm`] instead.
+    /// Constructs new `Abrm` by reading the static registers, consider using
+    /// [`super::ControlHandle::abrm`] instead.
     pub fn new<Ctrl: DeviceControl + ?Sized>(device: &mut Ctrl) -> ControlResult<Self> {
-        let (capability_addr, capability_len) = abrm::DEVICE_CAPABILITY;
-        let device_capability = read_register(device, capability_addr, capability_len)?;
+        fn read<T, Ctrl>(device: &mut Ctrl, register: (u64, u16)) -> ControlResult<T>
+        where
+            T: ParseBytes,
+            Ctrl: DeviceControl + ?Sized,
+        {
+            read_register(device, register.0, register.1)
+        }
+
+        let device_capability: DeviceCapability = read(device, abrm::DEVICE_CAPABILITY)?;
+
+        let family_name = if device_capability.is_family_name_supported() {
+            Some(read(device, abrm::FAMILY_NAME)?)
+        } else {
+            None
+        };
+        let device_software_interface_version =
+            if device_capability.is_device_software_interface_version_supported() {
+                Some(read(device, abrm::DEVICE_SOFTWARE_INTERFACE_VERSION)?)
+            } else {
+                None
+            };
+
+        let mut abrm = Self {
+            gencp_version: read(device, abrm::GENCP_VERSION)?,
+            manufacturer_name: read(device, abrm::MANUFACTURER_NAME)?,
+            model_name: read(device, abrm::MODEL_NAME)?,
+            family_name,
+            device_version: read(device, abrm::DEVICE_VERSION)?,
+            manufacturer_info: read(device, abrm::MANUFACTURER_INFO)?,
+            serial_number: read(device, abrm::SERIAL_NUMBER)?,
+            user_defined_name: None,
+            manifest_table_address: read(device, abrm::MANIFEST_TABLE_ADDRESS)?,
+            sbrm_address: read(device, abrm::SBRM_ADDRESS)?,
+            timestamp_increment: read(device, abrm::TIMESTAMP_INCREMENT)?,
+            device_software_interface_version,
+            maximum_device_response_time: read(device, abrm::MAXIMUM_DEVICE_RESPONSE_TIME)?,
+            device_capability,
+        };
+        abrm.refresh(device)?;
 
-        Ok(Self { device_capability })
+        Ok(abrm)
+    }
+
+    /// Re-reads the registers which may be modified after `Abrm` is constructed, i.e.
+    /// `USER DEFINED NAME`.
+    pub fn refresh<Ctrl: DeviceControl + ?Sized>(
+        &mut self,
+        device: &mut Ctrl,
+    ) -> ControlResult<()> {
+        self.user_defined_name = if self.device_capability.is_user_defined_name_supported() {
+            Some(self.read_register(device, abrm::USER_DEFINED_NAME)?)
+        } else {
+            None
+        };
+
+        Ok(())
     }
 
     /// Returns [`Sbrm`], consider using [`super::ControlHandle::sbrm`] instead.
     pub fn sbrm<Ctrl: DeviceControl + ?Sized>(&self, device: &mut Ctrl) -> ControlResult<Sbrm> {
-        let sbrm_address = self.sbrm_address(device)?;
-        Sbrm::new(device, sbrm_address)
+        Sbrm::new(device, self.sbrm_address)
     }
 
     /// Returns [`ManifestTable`], consider using [`super::ControlHandle::manifest_table`] instead.
+    ///
+    /// All entries of the table are read, so `limits` is checked before reading them.
     pub fn manifest_table<Ctrl: DeviceControl + ?Sized>(
         &self,
         device: &mut Ctrl,
+        limits: &Limits,
     ) -> ControlResult<ManifestTable> {
-        Ok(ManifestTable::new(self.manifest_table_address(device)?))
+        ManifestTable::new(device, self.manifest_table_address, limits)
     }
 
     /// `GenCP` version of the device.
-    pub fn gencp_version<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<semver::Version> {
-        let gencp_version: u32 = self.read_register(device, abrm::GENCP_VERSION)?;
-        let gencp_version_minor = gencp_version & 0xff;
-        let gencp_version_major = (gencp_version >> 16) & 0xff;
-        Ok(semver::Version::new(
+    #[must_use]
+    pub fn gencp_version(&self) -> semver::Version {
+        let gencp_version_minor = self.gencp_version & 0xff;
+        let gencp_version_major = (self.gencp_version >> 16) & 0xff;
+        semver::Version::new(
             u64::from(gencp_version_major),
             u64::from(gencp_version_minor),
             0,
-        ))
+        )
     }
 
     /// Manufacture name of the device.
-    pub fn manufacturer_name<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<String> {
-        self.read_register(device, abrm::MANUFACTURER_NAME)
+    #[must_use]
+    pub fn manufacturer_name(&self) -> &str {
+        &self.manufacturer_name
     }
 
     /// Model name of the device.
-    pub fn model_name<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<String> {
-        self.read_register(device, abrm::MODEL_NAME)
+    #[must_use]
+    pub fn model_name(&self) -> &str {
+        &self.model_name
     }
 
     /// Family name of the device.  
     ///
     /// NOTE: Some device doesn't support this feature.
     /// Please refer to [`DeviceCapability`] to see whether the feature is available on the device.
-    pub fn family_name<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Option<String>> {
-        if self.device_capability.is_family_name_supported() {
-            self.read_register(device, abrm::FAMILY_NAME).map(Some)
-        } else {
-            Ok(None)
-        }
+    #[must_use]
+    pub fn family_name(&self) -> Option<&str> {
+        self.family_name.as_deref()
     }
 
     /// Device version, this information represents manufacturer specific information.
-    pub fn device_version<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<String> {
-        self.read_register(device, abrm::DEVICE_VERSION)
+    #[must_use]
+    pub fn device_version(&self) -> &str {
+        &self.device_version
     }
 
     /// Manufacturer info of the device, this information represents manufacturer specific
     /// information.
-    pub fn manufacturer_info<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<String> {
-        self.read_register(device, abrm::MANUFACTURER_INFO)
+    #[must_use]
+    pub fn manufacturer_info(&self) -> &str {
+        &self.manufacturer_info
     }
 
     /// Serial number of the device.
-    pub fn serial_number<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<String> {
-        self.read_register(device, abrm::SERIAL_NUMBER)
+    #[must_use]
+    pub fn serial_number(&self) -> &str {
+        &self.serial_number
     }
 
     /// User defined name of the device.
     ///
+    /// The value is cached, call [`Self::refresh`] to re-read it from the device.
+    ///
     /// NOTE: Some device doesn't support this feature.
     /// Please refer to [`DeviceCapability`] to see whether the feature is available on the device.
-    pub fn user_defined_name<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Option<String>> {
-        if self.device_capability.is_user_defined_name_supported() {
-            self.read_register(device, abrm::USER_DEFINED_NAME)
-                .map(Some)
-        } else {
-            Ok(None)
-        }
+    #[must_use]
+    pub fn user_defined_name(&self) -> Option<&str> {
+        self.user_defined_name.as_deref()
     }
 
-    /// Set user defined name of the device.
+    /// Set user defined name of the device, the cached name is updated as well.
+    ///
+    /// The name is written NUL padded to the register length, then the register is read back to
+    /// confirm that the device stored it.
     ///
     /// # Arguments
     ///
-    /// * `name` - A user defined name. The encoding must be ascii and the length must be less than 64.
+    /// * `name` - A user defined name. The encoding must be ascii and the length must be at most 64.
     ///
-    /// NOTE: Some device doesn't support this feature.
-    /// Please refer to [`DeviceCapability`] to see whether the feature is available on the device.
+    /// # Errors
+    ///
+    /// * [`ControlError::NotSupported`] if the device doesn't support user defined name.
+    ///   Please refer to [`DeviceCapability`] to see whether the feature is available on the device.
+    /// * [`ControlError::InvalidData`] if `name` isn't ascii or is longer than the register.
+    /// * [`ControlError::InvalidDevice`] if the value read back differs from `name`.
     pub fn set_user_defined_name<Ctrl: DeviceControl + ?Sized>(
-        &self,
+        &mut self,
         device: &mut Ctrl,
         name: &str,
     ) -> ControlResult<()> {
         if !self.device_capability.is_user_defined_name_supported() {
-            return Ok(());
+            return Err(ControlError::NotSupported(
+                "the device doesn't support user defined name".into(),
+            ));
         }
 
-        self.write_register(device, abrm::USER_DEFINED_NAME, name)
+        self.write_register(device, abrm::USER_DEFINED_NAME, name)?;
+        let written: String = self.read_register(device, abrm::USER_DEFINED_NAME)?;
+        if written != name {
+            return Err(ControlError::InvalidDevice(
+                format!(
+                    "user defined name is not stored: written `{}`, read back `{}`",
+                    name, written
+                )
+                .into(),
+            ));
+        }
+
+        self.user_defined_name = Some(written);
+        Ok(())
     }
 
     /// The initial address of manifest table.
     ///
     /// To obtain [`ManifestTable`], it is easier to use [`Self::manifest_table`].
-    pub fn manifest_table_address<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<u64> {
-        self.read_register(device, abrm::MANIFEST_TABLE_ADDRESS)
+    #[must_use]
+    pub fn manifest_table_address(&self) -> u64 {
+        self.manifest_table_address
     }
 
     /// The initial address of `Sbrm`.
     ///
     /// To obtain [`Sbrm`], it is easier to use [`Self::sbrm`].
-    pub fn sbrm_address<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<u64> {
-        self.read_register(device, abrm::SBRM_ADDRESS)
+    #[must_use]
+    pub fn sbrm_address(&self) -> u64 {
+        self.sbrm_address
     }
 
     /// Timestamp that represents device internal clock in ns.
@@ -272,41 +345,63 @@ impl Abrm {
         self.write_register(device, abrm::TIMESTAMP_LATCH, 1_u32)
     }
 
-    /// Time stamp increment that indicates the ns/tick of the device internal clock.
+    /// Latches the device internal clock and returns the latched timestamp in ns.
     ///
-    /// For example a value of 1000 indicates the device clock runs at 1MHz.
-    pub fn timestamp_increment<Ctrl: DeviceControl + ?Sized>(
+    /// This is [`Self::set_timestamp_latch_bit`] followed by [`Self::timestamp`].
+    pub fn latch_timestamp<Ctrl: DeviceControl + ?Sized>(
         &self,
         device: &mut Ctrl,
     ) -> ControlResult<u64> {
-        self.read_register(device, abrm::TIMESTAMP_INCREMENT)
+        self.set_timestamp_latch_bit(device)?;
+        self.timestamp(device)
+    }
+
+    /// Estimates the offset between the host clock and the device internal clock.
+    ///
+    /// The host time is sampled before and after [`Self::latch_timestamp`], and the device is
+    /// assumed to latch its clock at the midpoint of the round trip. The offset is therefore
+    /// accurate to within half of the round trip time, see [`ClockOffset`].
+    pub fn clock_offset<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+    ) -> ControlResult<ClockOffset> {
+        let host_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let start = Instant::now();
+        let device_time = self.latch_timestamp(device)?;
+        let round_trip_time = start.elapsed();
+
+        let host_time = host_time + round_trip_time / 2;
+        Ok(ClockOffset {
+            offset: host_time.as_nanos() as i128 - i128::from(device_time),
+            uncertainty: round_trip_time / 2,
+            round_trip_time,
+        })
+    }
+
+    /// Time stamp increment that indicates the ns/tick of the device internal clock.
+    ///
+    /// For example a value of 1000 indicates the device clock runs at 1MHz, so a timestamp
+    /// in ticks is converted to ns by multiplying it by this value.
+    #[must_use]
+    pub fn timestamp_increment(&self) -> u64 {
+        self.timestamp_increment
     }
 
     /// Device software version.
     ///
     /// NOTE: Some device doesn't support this feature.
     /// Please refer to [`DeviceCapability`] to see whether the feature is available on the device.
-    pub fn device_software_interface_version<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Option<String>> {
-        if self
-            .device_capability
-            .is_device_software_interface_version_supported()
-        {
-            self.read_register(device, abrm::DEVICE_SOFTWARE_INTERFACE_VERSION)
-                .map(Some)
-        } else {
-            Ok(None)
-        }
+    #[must_use]
+    pub fn device_software_interface_version(&self) -> Option<&str> {
+        self.device_software_interface_version.as_deref()
     }
 
     /// Maximum device response time.
-    pub fn maximum_device_response_time<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Duration> {
-        self.read_register(device, abrm::MAXIMUM_DEVICE_RESPONSE_TIME)
+    #[must_use]
+    pub fn maximum_device_response_time(&self) -> Duration {
+        self.maximum_device_response_time
     }
 
     /// Device capability.
@@ -331,6 +426,50 @@ impl Abrm {
         self.write_register(device, abrm::DEVICE_CONFIGURATION, config)
     }
 
+    /// Heartbeat timeout of the device.
+    pub fn heartbeat_timeout<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+    ) -> ControlResult<Duration> {
+        let timeout: u32 = self.read_register(device, abrm::HEARTBEAT_TIMEOUT)?;
+        Ok(Duration::from_millis(timeout.into()))
+    }
+
+    /// Sets the heartbeat timeout of the device, then enables the heartbeat.
+    ///
+    /// The heartbeat is a watchdog of the device, the device regards the host as gone once it
+    /// receives no command for `timeout`. The timeout is truncated to milliseconds.
+    ///
+    /// # Errors
+    ///
+    /// * [`ControlError::NotSupported`] if the device doesn't support heartbeat.
+    ///   Please refer to [`DeviceCapability`] to see whether the feature is available on the device.
+    /// * [`ControlError::InvalidData`] if `timeout` is zero or doesn't fit into the register.
+    pub fn set_heartbeat_timeout<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+        timeout: Duration,
+    ) -> ControlResult<()> {
+        if !self.device_capability.is_heartbeat_supported() {
+            return Err(ControlError::NotSupported(
+                "the device doesn't support heartbeat".into(),
+            ));
+        }
+        let millis: u32 = match timeout.as_millis().try_into() {
+            Ok(millis) if millis != 0 => millis,
+            _ => {
+                return Err(ControlError::InvalidData(
+                    format!("heartbeat timeout `{:?}` is out of range", timeout).into(),
+                ))
+            }
+        };
+
+        self.write_register(device, abrm::HEARTBEAT_TIMEOUT, millis)?;
+        let mut config = self.device_configuration(device)?;
+        config.set_heartbeat_enable_bit();
+        self.write_device_configuration(device, config)
+    }
+
     fn read_register<T, Ctrl: DeviceControl + ?Sized>(
         &self,
         device: &mut Ctrl,
@@ -357,159 +496,188 @@ impl Abrm {
 
 /// Represent Technology Specific Boot Register Map (SBRM).
 ///
-/// To maintain consistency with the device data, `Sbrm` doesn't cache any data. It means
-/// that all methods of this struct cause communication with the device every time, thus the device
-/// is expected to be opened when methods are called.
+/// Registers of `Sbrm` are static except for `CURRENT SPEED`, so they are read once when `Sbrm`
+/// is constructed and the getters return the cached values. [`Sbrm::current_speed`] causes
+/// communication with the device every time, thus the device is expected to be opened when it's
+/// called.
 #[derive(Clone, Copy, Debug)]
 pub struct Sbrm {
     sbrm_addr: u64,
     capability: U3VCapablitiy,
+    u3v_version: u32,
+    maximum_command_transfer_length: u32,
+    maximum_acknowledge_transfer_length: u32,
+    number_of_stream_channel: u32,
+    sirm: Option<(u64, u32)>,
+    eirm: Option<(u64, u32)>,
+    iidc2_address: Option<u64>,
 }
 
 impl Sbrm {
-    /// Constructs new `Sbrm`, consider using [`super::ControlHandle::sbrm`] isntead.
+    /// Constructs new `Sbrm` by reading the static registers, consider using
+    /// [`super::ControlHandle::sbrm`] isntead.
     pub fn new<Ctrl: DeviceControl + ?Sized>(
         device: &mut Ctrl,
         sbrm_addr: u64,
     ) -> ControlResult<Self> {
-        let (capability_offset, capability_len) = sbrm::U3VCP_CAPABILITY_REGISTER;
-        let capability_addr = capability_offset + sbrm_addr;
-        let capability = read_register(device, capability_addr, capability_len)?;
+        fn read<T, Ctrl>(
+            device: &mut Ctrl,
+            sbrm_addr: u64,
+            register: (u64, u16),
+        ) -> ControlResult<T>
+        where
+            T: ParseBytes,
+            Ctrl: DeviceControl + ?Sized,
+        {
+            let (offset, len) = register;
+            read_register(device, checked_address(sbrm_addr, offset)?, len)
+        }
+
+        let capability: U3VCapablitiy = read(device, sbrm_addr, sbrm::U3VCP_CAPABILITY_REGISTER)?;
+
+        let sirm = if capability.is_sirm_available() {
+            Some((
+                read(device, sbrm_addr, sbrm::SIRM_ADDRESS)?,
+                read(device, sbrm_addr, sbrm::SIRM_LENGTH)?,
+            ))
+        } else {
+            None
+        };
+        let eirm = if capability.is_eirm_available() {
+            Some((
+                read(device, sbrm_addr, sbrm::EIRM_ADDRESS)?,
+                read(device, sbrm_addr, sbrm::EIRM_LENGTH)?,
+            ))
+        } else {
+            None
+        };
+        let iidc2_address = if capability.is_iidc2_available() {
+            Some(read(device, sbrm_addr, sbrm::IIDC2_ADDRESS)?)
+        } else {
+            None
+        };
 
         Ok(Self {
             sbrm_addr,
             capability,
+            u3v_version: read(device, sbrm_addr, sbrm::U3V_VERSION)?,
+            maximum_command_transfer_length: read(
+                device,
+                sbrm_addr,
+                sbrm::MAXIMUM_COMMAND_TRANSFER_LENGTH,
+            )?,
+            maximum_acknowledge_transfer_length: read(
+                device,
+                sbrm_addr,
+                sbrm::MAXIMUM_ACKNOWLEDGE_TRANSFER_LENGTH,
+            )?,
+            number_of_stream_channel: read(device, sbrm_addr, sbrm::NUMBER_OF_STREAM_CHANNELS)?,
+            sirm,
+            eirm,
+            iidc2_address,
         })
     }
 
+    /// Constructs new `Sbrm` located by `SBRM ADDRESS` register, without reading the other
+    /// registers of [`Abrm`].
+    pub fn locate<Ctrl: DeviceControl + ?Sized>(device: &mut Ctrl) -> ControlResult<Self> {
+        let (addr, len) = abrm::SBRM_ADDRESS;
+        let sbrm_addr = read_register(device, addr, len)?;
+        Self::new(device, sbrm_addr)
+    }
+
     /// Version of U3V of the device.
-    pub fn u3v_version<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<semver::Version> {
-        let u3v_version: u32 = self.read_register(device, sbrm::U3V_VERSION)?;
-        let u3v_version_minor = u3v_version & 0xff;
-        let u3v_version_major = (u3v_version >> 16) & 0xff;
+    #[must_use]
+    pub fn u3v_version(&self) -> semver::Version {
+        let u3v_version_minor = self.u3v_version & 0xff;
+        let u3v_version_major = (self.u3v_version >> 16) & 0xff;
 
-        Ok(semver::Version::new(
+        semver::Version::new(
             u64::from(u3v_version_major),
             u64::from(u3v_version_minor),
             0,
-        ))
+        )
     }
 
     /// Maximum command transfer length in bytes.
     ///
     /// This value specifies the maximum byte length of the command which is sent from the host to
     /// the device at one time.
-    pub fn maximum_command_transfer_length<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<u32> {
-        self.read_register(device, sbrm::MAXIMUM_COMMAND_TRANSFER_LENGTH)
+    #[must_use]
+    pub fn maximum_command_transfer_length(&self) -> u32 {
+        self.maximum_command_transfer_length
     }
 
     /// Maximum acknowledge transfer length in bytes.
     ///
     /// This value specifies the maximum byte length of the acknowledge command which is sent from the device to
     /// the host at one time.
-    pub fn maximum_acknowledge_trasfer_length<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<u32> {
-        self.read_register(device, sbrm::MAXIMUM_ACKNOWLEDGE_TRANSFER_LENGTH)
+    #[must_use]
+    pub fn maximum_acknowledge_trasfer_length(&self) -> u32 {
+        self.maximum_acknowledge_transfer_length
     }
 
     /// The number of stream channels the device has.
-    pub fn number_of_stream_channel<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<u32> {
-        self.read_register(device, sbrm::NUMBER_OF_STREAM_CHANNELS)
+    #[must_use]
+    pub fn number_of_stream_channel(&self) -> u32 {
+        self.number_of_stream_channel
     }
 
     /// Return [`Sirm`] if it's available.
-    pub fn sirm<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Option<Sirm>> {
-        Ok(self.sirm_address(device)?.map(Sirm::new))
+    #[must_use]
+    pub fn sirm(&self) -> Option<Sirm> {
+        self.sirm_address().map(Sirm::new)
     }
 
     /// The initial address of `Sirm`.
     ///
     /// NOTE: Some device doesn't support this feature.
-    /// Please refer to [`U3VCapablitiy`] to see whether the feature is available on the device.
-    pub fn sirm_address<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Option<u64>> {
-        if self.capability.is_sirm_available() {
-            self.read_register(device, sbrm::SIRM_ADDRESS).map(Some)
-        } else {
-            Ok(None)
-        }
+    /// `None` is returned if [`U3VCapablitiy::is_sirm_available`] is `false`.
+    #[must_use]
+    pub fn sirm_address(&self) -> Option<u64> {
+        self.sirm.map(|(addr, _)| addr)
     }
 
     /// The length of `Sirm`.
     ///
     /// NOTE: Some device doesn't support this feature.
-    /// Please refer to [`U3VCapablitiy`] to see whether the feature is available on the device.
-    pub fn sirm_length<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Option<u32>> {
-        if self.capability.is_sirm_available() {
-            self.read_register(device, sbrm::SIRM_LENGTH).map(Some)
-        } else {
-            Ok(None)
-        }
+    /// `None` is returned if [`U3VCapablitiy::is_sirm_available`] is `false`.
+    #[must_use]
+    pub fn sirm_length(&self) -> Option<u32> {
+        self.sirm.map(|(_, len)| len)
+    }
+
+    /// Return [`Eirm`] if it's available.
+    #[must_use]
+    pub fn eirm(&self) -> Option<Eirm> {
+        self.eirm_address().map(Eirm::new)
     }
 
     /// The initial address of `Eirm`.
     ///
-    ///
     /// NOTE: Some device doesn't support this feature.
-    /// Please refer to [`U3VCapablitiy`] to see whether the feature is available on the device.
-    pub fn eirm_address<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Option<u64>> {
-        if self.capability.is_eirm_available() {
-            self.read_register(device, sbrm::EIRM_ADDRESS).map(Some)
-        } else {
-            Ok(None)
-        }
+    /// `None` is returned if [`U3VCapablitiy::is_eirm_available`] is `false`.
+    #[must_use]
+    pub fn eirm_address(&self) -> Option<u64> {
+        self.eirm.map(|(addr, _)| addr)
     }
 
     /// The length of `Eirm`.
     ///
     /// NOTE: Some device doesn't support this feature.
-    /// Please refer to [`U3VCapablitiy`] to see whether the feature is available on the device.
-    pub fn eirm_length<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Option<u32>> {
-        if self.capability.is_eirm_available() {
-            self.read_register(device, sbrm::EIRM_LENGTH).map(Some)
-        } else {
-            Ok(None)
-        }
+    /// `None` is returned if [`U3VCapablitiy::is_eirm_available`] is `false`.
+    #[must_use]
+    pub fn eirm_length(&self) -> Option<u32> {
+        self.eirm.map(|(_, len)| len)
     }
 
     /// The initial address of `IIDC2`.
     ///
     /// NOTE: Some device doesn't support this feature.
-    /// Please refer to [`U3VCapablitiy`] to see whether the feature is available on the device.
-    pub fn iidc2_address<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Option<u64>> {
-        if self.capability.is_iidc2_available() {
-            self.read_register(device, sbrm::IIDC2_ADDRESS).map(Some)
-        } else {
-            Ok(None)
-        }
+    /// `None` is returned if [`U3VCapablitiy::is_iidc2_available`] is `false`.
+    #[must_use]
+    pub fn iidc2_address(&self) -> Option<u64> {
+        self.iidc2_address
     }
 
     /// Current bus speed used to communication.
@@ -531,7 +699,7 @@ impl Sbrm {
         Ctrl: DeviceControl + ?Sized,
     {
         let (offset, len) = register;
-        let addr = offset + self.sbrm_addr;
+        let addr = checked_address(self.sbrm_addr, offset)?;
         read_register(device, addr, len)
     }
 }
@@ -565,29 +733,32 @@ impl Sirm {
     ) -> ControlResult<usize> {
         let si_info: u32 = self.read_register(device, sirm::SI_INFO)?;
         // Upper 8 bits specifies the exp of the alignment.
-        Ok(1 << (si_info >> 24))
+        let exp = si_info >> 24;
+        1_usize.checked_shl(exp).ok_or_else(|| {
+            ControlError::InvalidDevice(
+                format!("payload size alignment 2^{} exceeds the address space", exp).into(),
+            )
+        })
     }
 
-    /// Enables stream.
+    /// Enables stream, then verifies that the device reports stream is enabled.
     ///
     /// It's forbidden to write to SIRM registers while stream is enabled.
     pub fn enable_stream<Ctrl: DeviceControl + ?Sized>(
         &self,
         device: &mut Ctrl,
     ) -> ControlResult<()> {
-        let value = 1_u32;
-        self.write_register(device, sirm::SI_CONTROL, value)
+        self.set_stream_enable(device, true)
     }
 
-    /// Disables stream.
+    /// Disables stream, then verifies that the device reports stream is disabled.
     ///
     /// It's forbidden to write to SIRM registers while stream is enabled.
     pub fn disable_stream<Ctrl: DeviceControl + ?Sized>(
         &self,
         device: &mut Ctrl,
     ) -> ControlResult<()> {
-        let value = 0_u32;
-        self.write_register(device, sirm::SI_CONTROL, value)
+        self.set_stream_enable(device, false)
     }
 
     /// Returns `true` if stream is enabled.
@@ -688,12 +859,15 @@ impl Sirm {
     }
 
     /// Set payload transfer size.
+    ///
+    /// `size` must be a multiple of [`Self::payload_size_alignment`], otherwise an error with
+    /// `U3V_PAYLOAD_SIZE_NOT_ALIGNED` status is returned without writing to the device.
     pub fn set_payload_transfer_size<Ctrl: DeviceControl + ?Sized>(
         &self,
         device: &mut Ctrl,
         size: u32,
     ) -> ControlResult<()> {
-        self.write_register(device, sirm::PAYLOAD_TRANSFER_SIZE, size)
+        self.write_payload_size(device, sirm::PAYLOAD_TRANSFER_SIZE, size)
     }
 
     /// Payload transfer count.
@@ -722,12 +896,14 @@ impl Sirm {
     }
 
     /// Sets payload final transfer1 size.
+    ///
+    /// `size` must be aligned as [`Self::set_payload_transfer_size`] requires.
     pub fn set_payload_final_transfer1_size<Ctrl: DeviceControl + ?Sized>(
         &self,
         device: &mut Ctrl,
         size: u32,
     ) -> ControlResult<()> {
-        self.write_register(device, sirm::PAYLOAD_FINAL_TRANSFER1_SIZE, size)
+        self.write_payload_size(device, sirm::PAYLOAD_FINAL_TRANSFER1_SIZE, size)
     }
 
     /// Payload final transfer1 size.
@@ -738,13 +914,54 @@ impl Sirm {
         self.read_register(device, sirm::PAYLOAD_FINAL_TRANSFER2_SIZE)
     }
 
-    /// Set payload final transfer1 size.
+    /// Set payload final transfer2 size.
+    ///
+    /// `size` must be aligned as [`Self::set_payload_transfer_size`] requires.
     pub fn set_payload_final_transfer2_size<Ctrl: DeviceControl + ?Sized>(
         &self,
         device: &mut Ctrl,
         size: u32,
     ) -> ControlResult<()> {
-        self.write_register(device, sirm::PAYLOAD_FINAL_TRANSFER2_SIZE, size)
+        self.write_payload_size(device, sirm::PAYLOAD_FINAL_TRANSFER2_SIZE, size)
+    }
+
+    fn set_stream_enable<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+        enable: bool,
+    ) -> ControlResult<()> {
+        self.write_register(device, sirm::SI_CONTROL, u32::from(enable))?;
+        if self.is_stream_enable(device)? == enable {
+            Ok(())
+        } else {
+            Err(ControlError::InvalidDevice(
+                format!(
+                    "stream enable bit of `SI_CONTROL` isn't {} after writing it",
+                    if enable { "set" } else { "cleared" }
+                )
+                .into(),
+            ))
+        }
+    }
+
+    /// Writes `size` to a payload size register after verifying its alignment, as the device
+    /// rejects an unaligned size with `U3V_PAYLOAD_SIZE_NOT_ALIGNED`.
+    fn write_payload_size<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+        register: (u64, u16),
+        size: u32,
+    ) -> ControlResult<()> {
+        let alignment = self.payload_size_alignment(device)?;
+        if size as usize & (alignment - 1) != 0 {
+            let status = ack::Status::from(ack::UsbSpecificStatus::PayloadSizeNotAligned);
+            let err = anyhow::Error::new(StatusError(status)).context(format!(
+                "payload size {} isn't aligned to {} bytes",
+                size, alignment
+            ));
+            return Err(ControlError::Io(err));
+        }
+        self.write_register(device, register, size)
     }
 
     fn read_register<T, Ctrl>(&self, device: &mut Ctrl, register: (u64, u16)) -> ControlResult<T>
@@ -753,7 +970,7 @@ impl Sirm {
         Ctrl: DeviceControl + ?Sized,
     {
         let (offset, len) = register;
-        let addr = offset + self.sirm_addr;
+        let addr = checked_address(self.sirm_addr, offset)?;
         read_register(device, addr, len)
     }
 
@@ -764,131 +981,333 @@ impl Sirm {
         data: impl DumpBytes,
     ) -> ControlResult<()> {
         let (offset, len) = register;
-        let addr = self.sirm_addr + offset;
+        let addr = checked_address(self.sirm_addr, offset)?;
         let mut buf = vec![0; len as usize];
         data.dump_bytes(&mut buf)?;
         device.write(addr, &buf)
     }
 }
 
-/// `ManifestTable` provides iterator of [`ManifestEntry`].
+/// Represent Event Interface Register Map (EIRM).
+///
+/// As with [`Sirm`], `Eirm` doesn't cache any data, thus the device is expected to be opened when
+/// methods are called.
 #[derive(Clone, Copy, Debug)]
-pub struct ManifestTable {
-    manifest_address: u64,
+pub struct Eirm {
+    eirm_addr: u64,
 }
 
-impl ManifestTable {
-    /// Constructs new `ManifestEntry`, consider using [`super::ControlHandle::manifest_table`]
-    /// instead.
+impl Eirm {
+    /// Constructs new `Eirm`, consider using [`super::ControlHandle::eirm`] instead.
+    ///
+    /// To construct `Eirm`, Use [`Sbrm::eirm`] also can be used.
     #[must_use]
-    pub fn new(manifest_address: u64) -> Self {
-        Self { manifest_address }
+    pub fn new(eirm_addr: u64) -> Self {
+        Self { eirm_addr }
     }
 
-    /// Returns iterator of [`ManifestEntry`].
-    pub fn entries<Ctrl: DeviceControl + ?Sized>(
+    /// Sets the event enable flag, then verifies that the device reports the flag is set.
+    ///
+    /// The device rejects the write with `U3V_EVENT_ENDPOINT_HALTED` status if the event
+    /// endpoint is halted, the host must clear the halt before retrying.
+    pub fn enable_event<Ctrl: DeviceControl + ?Sized>(
         &self,
         device: &mut Ctrl,
-    ) -> ControlResult<impl Iterator<Item = ManifestEntry>> {
-        let entry_num: u64 = self.read_register(device, (0, 8))?;
-        let first_entry_addr = self.manifest_address + 8;
+    ) -> ControlResult<()> {
+        self.set_event_enable(device, true)
+    }
 
-        Ok((0..entry_num)
-            .into_iter()
-            .map(move |i| ManifestEntry::new(first_entry_addr + i * 64)))
+    /// Clears the event enable flag, then verifies that the device reports the flag is cleared.
+    pub fn disable_event<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+    ) -> ControlResult<()> {
+        self.set_event_enable(device, false)
     }
 
-    fn read_register<T, Ctrl: DeviceControl + ?Sized>(
+    /// Returns `true` if the event enable flag is set.
+    pub fn is_event_enable<Ctrl: DeviceControl + ?Sized>(
         &self,
         device: &mut Ctrl,
-        register: (u64, u16),
-    ) -> ControlResult<T>
+    ) -> ControlResult<bool> {
+        let ei_ctrl: u32 = self.read_register(device, eirm::EI_CONTROL)?;
+        Ok((ei_ctrl & 1) == 1)
+    }
+
+    /// Maximum size of an event transfer in bytes.
+    ///
+    /// The host must be able to receive a transfer of this size from the event endpoint.
+    pub fn maximum_event_transfer_length<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+    ) -> ControlResult<u32> {
+        self.read_register(device, eirm::MAXIMUM_EVENT_TRANSFER_LENGTH)
+    }
+
+    fn set_event_enable<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+        enable: bool,
+    ) -> ControlResult<()> {
+        self.write_register(device, eirm::EI_CONTROL, u32::from(enable))?;
+        if self.is_event_enable(device)? == enable {
+            Ok(())
+        } else {
+            Err(ControlError::InvalidDevice(
+                format!(
+                    "event enable bit of `EI_CONTROL` isn't {} after writing it",
+                    if enable { "set" } else { "cleared" }
+                )
+                .into(),
+            ))
+        }
+    }
+
+    fn read_register<T, Ctrl>(&self, device: &mut Ctrl, register: (u64, u16)) -> ControlResult<T>
     where
         T: ParseBytes,
+        Ctrl: DeviceControl + ?Sized,
     {
         let (offset, len) = register;
-        read_register(device, offset + self.manifest_address, len)
+        let addr = checked_address(self.eirm_addr, offset)?;
+        read_register(device, addr, len)
+    }
+
+    fn write_register<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+        register: (u64, u16),
+        data: impl DumpBytes,
+    ) -> ControlResult<()> {
+        let (offset, len) = register;
+        let addr = checked_address(self.eirm_addr, offset)?;
+        let mut buf = vec![0; len as usize];
+        data.dump_bytes(&mut buf)?;
+        device.write(addr, &buf)
+    }
+}
+
+/// Streaming counters maintained by the device, see [`DeviceCounterRegisters::read`].
+///
+/// Each counter is `None` if its register isn't configured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeviceStreamCounters {
+    /// The number of payloads dropped inside the device.
+    pub payload_drop_count: Option<u32>,
+    /// The number of errors detected on the link by the device.
+    pub link_error_count: Option<u32>,
+}
+
+/// Addresses of streaming counters maintained by the device.
+///
+/// The U3V standard doesn't define such counters, so they are vendor specific. Take the addresses
+/// from the vendor's documentation or from the `pAddress` of the corresponding GenApi nodes.
+/// Each counter is a 4 bytes register.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeviceCounterRegisters {
+    /// Address of the number of payloads dropped inside the device.
+    pub payload_drop_count: Option<u64>,
+    /// Address of the number of errors detected on the link by the device.
+    pub link_error_count: Option<u64>,
+}
+
+impl DeviceCounterRegisters {
+    /// Reads the configured counters, unconfigured ones are `None`.
+    pub fn read<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        device: &mut Ctrl,
+    ) -> ControlResult<DeviceStreamCounters> {
+        let mut read_if_configured = |address: Option<u64>| {
+            address
+                .map(|address| read_register(device, address, 4))
+                .transpose()
+        };
+
+        Ok(DeviceStreamCounters {
+            payload_drop_count: read_if_configured(self.payload_drop_count)?,
+            link_error_count: read_if_configured(self.link_error_count)?,
+        })
+    }
+}
+
+/// Offset between the host clock and the device internal clock, see [`Abrm::clock_offset`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClockOffset {
+    /// Host time since [`UNIX_EPOCH`] minus device timestamp in ns.
+    ///
+    /// A device timestamp is converted to the host time by adding this value.
+    pub offset: i128,
+    /// Maximum error of `offset`, i.e. half of `round_trip_time`.
+    pub uncertainty: Duration,
+    /// Time taken to latch and read the device timestamp.
+    pub round_trip_time: Duration,
+}
+
+impl ClockOffset {
+    /// Converts a device timestamp in ns to the host time since [`UNIX_EPOCH`].
+    ///
+    /// Returns `None` if the result is before [`UNIX_EPOCH`] or overflows.
+    #[must_use]
+    pub fn to_host_time(&self, device_timestamp: u64) -> Option<Duration> {
+        let nanos = i128::from(device_timestamp).checked_add(self.offset)?;
+        let nanos: u128 = nanos.try_into().ok()?;
+        let secs = (nanos / 1_000_000_000).try_into().ok()?;
+        Some(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+    }
+}
+
+/// `ManifestTable` holds [`ManifestEntry`]s which describe `GenApi` XML files of the device.
+///
+/// The table is static, so all entries are read once when `ManifestTable` is constructed.
+#[derive(Clone, Debug)]
+pub struct ManifestTable {
+    manifest_address: u64,
+    entries: Vec<ManifestEntry>,
+}
+
+impl ManifestTable {
+    /// Constructs new `ManifestTable` by reading all its entries, consider using
+    /// [`super::ControlHandle::manifest_table`] instead.
+    ///
+    /// # Errors
+    /// [`ControlError::LimitExceeded`] is returned if the number of entries exceeds
+    /// [`Limits::max_manifest_entries`].
+    pub fn new<Ctrl: DeviceControl + ?Sized>(
+        device: &mut Ctrl,
+        manifest_address: u64,
+        limits: &Limits,
+    ) -> ControlResult<Self> {
+        let entry_num: u64 = read_register(device, manifest_address, 8)?;
+        limits.check_manifest_entries(entry_num)?;
+
+        let first_entry_addr = checked_address(manifest_address, 8)?;
+        // Make sure the address of the last entry doesn't overflow.
+        let table_len = entry_num
+            .checked_mul(64)
+            .ok_or(ControlError::InvalidAddress {
+                address: first_entry_addr,
+                len: u64::MAX,
+            })?;
+        checked_address(first_entry_addr, table_len)?;
+
+        let entries = (0..entry_num)
+            .map(|i| ManifestEntry::new(device, first_entry_addr + i * 64))
+            .collect::<ControlResult<_>>()?;
+
+        Ok(Self {
+            manifest_address,
+            entries,
+        })
+    }
+
+    /// The initial address of the table.
+    #[must_use]
+    pub fn manifest_address(&self) -> u64 {
+        self.manifest_address
+    }
+
+    /// Returns the number of entries in the table.
+    #[must_use]
+    pub fn entry_num(&self) -> u64 {
+        self.entries.len() as u64
+    }
+
+    /// Returns [`ManifestEntry`]s of the table.
+    #[must_use]
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
     }
 }
 
 /// Manifest entry describes `GenApi` XML properties.
+///
+/// All fields of the entry are read once when `ManifestEntry` is constructed.
 #[derive(Clone, Copy, Debug)]
 pub struct ManifestEntry {
     entry_addr: u64,
+    file_version: u32,
+    file_info: GenICamFileInfo,
+    file_address: u64,
+    file_size: u64,
+    sha1_hash: Option<[u8; 20]>,
 }
 
 impl ManifestEntry {
-    /// Construct `ManifestEntry` from its initial address.
+    /// Construct `ManifestEntry` by reading the entry located at `entry_addr`.
     /// Using [`ManifestTable::entries`] is recommended to obtain `ManifestEntry`.
+    pub fn new<Ctrl: DeviceControl + ?Sized>(
+        device: &mut Ctrl,
+        entry_addr: u64,
+    ) -> ControlResult<Self> {
+        fn field<T: ParseBytes>(buf: &[u8], register: (u64, u16)) -> ControlResult<T> {
+            let (offset, len) = (register.0 as usize, register.1 as usize);
+            T::parse_bytes(&buf[offset..offset + len])
+        }
+
+        // Read the whole entry at once instead of reading each field.
+        let (sha1_offset, sha1_len) = manifest_entry::SHA1_HASH;
+        let entry_len = sha1_offset + u64::from(sha1_len);
+        checked_address(entry_addr, entry_len)?;
+        let mut buf = vec![0; entry_len as usize];
+        device.read(entry_addr, &mut buf)?;
+
+        let mut sha1_hash = [0; 20];
+        sha1_hash.copy_from_slice(&buf[sha1_offset as usize..]);
+        // All bytes are 0 in case the hash is not available.
+        let sha1_hash = if sha1_hash.iter().all(|byte| *byte == 0) {
+            None
+        } else {
+            Some(sha1_hash)
+        };
+
+        Ok(Self {
+            entry_addr,
+            file_version: field(&buf, manifest_entry::GENICAM_FILE_VERSION)?,
+            file_info: field(&buf, manifest_entry::FILE_FORMAT_INFO)?,
+            file_address: field(&buf, manifest_entry::REGISTER_ADDRESS)?,
+            file_size: field(&buf, manifest_entry::FILE_SIZE)?,
+            sha1_hash,
+        })
+    }
+
+    /// Register address where the entry is located in the manifest table.
     #[must_use]
-    pub fn new(entry_addr: u64) -> Self {
-        Self { entry_addr }
+    pub fn entry_address(&self) -> u64 {
+        self.entry_addr
     }
 
     /// `GenICam` file version.
-    pub fn genicam_file_version<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<semver::Version> {
-        let file_version: u32 = self.read_register(device, manifest_entry::GENICAM_FILE_VERSION)?;
-        let subminor = file_version & 0xff;
-        let minor = (file_version >> 16) & 0xff;
-        let major = (file_version >> 24) & 0xff;
+    #[must_use]
+    pub fn genicam_file_version(&self) -> semver::Version {
+        let subminor = self.file_version & 0xff;
+        let minor = (self.file_version >> 16) & 0xff;
+        let major = (self.file_version >> 24) & 0xff;
 
-        Ok(semver::Version::new(
-            u64::from(major),
-            u64::from(minor),
-            u64::from(subminor),
-        ))
+        semver::Version::new(u64::from(major), u64::from(minor), u64::from(subminor))
     }
 
     /// Register address where `GenApi` XML file is located.
-    pub fn file_address<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<u64> {
-        self.read_register(device, manifest_entry::REGISTER_ADDRESS)
+    #[must_use]
+    pub fn file_address(&self) -> u64 {
+        self.file_address
     }
 
     /// `GenApi` XML file size in bytes.
-    pub fn file_size<Ctrl: DeviceControl + ?Sized>(&self, device: &mut Ctrl) -> ControlResult<u64> {
-        self.read_register(device, manifest_entry::FILE_SIZE)
+    #[must_use]
+    pub fn file_size(&self) -> u64 {
+        self.file_size
     }
 
     /// `GenApi` XML file info.
-    pub fn file_info<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<GenICamFileInfo> {
-        self.read_register(device, manifest_entry::FILE_FORMAT_INFO)
+    #[must_use]
+    pub fn file_info(&self) -> GenICamFileInfo {
+        self.file_info
     }
 
     /// SHA1 hash of the file. In case the hash is not available, return None.
-    pub fn sha1_hash<Ctrl: DeviceControl + ?Sized>(
-        &self,
-        device: &mut Ctrl,
-    ) -> ControlResult<Option<[u8; 20]>> {
-        // We don't use `self.read_register` here for perf.
-        let mut sha1_hash: [u8; 20] = [0; 20];
-        let addr = self.entry_addr + manifest_entry::SHA1_HASH.0;
-        device.read(addr, &mut sha1_hash)?;
-
-        // All bytes are 0 in case the hash is not available.
-        if sha1_hash.iter().all(|byte| *byte == 0) {
-            Ok(None)
-        } else {
-            Ok(Some(sha1_hash))
-        }
-    }
-
-    fn read_register<T, Ctrl>(&self, device: &mut Ctrl, register: (u64, u16)) -> ControlResult<T>
-    where
-        T: ParseBytes,
-        Ctrl: DeviceControl + ?Sized,
-    {
-        let (offset, len) = register;
-        let addr = offset + self.entry_addr;
-        read_register(device, addr, len)
+    #[must_use]
+    pub fn sha1_hash(&self) -> Option<[u8; 20]> {
+        self.sha1_hash
     }
 }
 
@@ -901,6 +1320,7 @@ fn read_register<T, Ctrl: DeviceControl + ?Sized>(
 where
     T: ParseBytes,
 {
+    checked_address(addr, u64::from(len))?;
     let len = len as usize;
     let mut buf = vec![0; len];
     device.read(addr, &mut buf[..len])?;
@@ -929,6 +1349,24 @@ macro_rules! unset_bit {
 #[derive(Clone, Copy, Debug)]
 pub struct DeviceConfiguration(u64);
 impl DeviceConfiguration {
+    /// Indicate heartbeat is enabled on the device.
+    #[must_use]
+    pub fn is_heartbeat_enabled(self) -> bool {
+        is_bit_set!(self.0, 0)
+    }
+
+    /// Sets heartbeat enable bit.
+    /// To reflect the configuration change, call [`Abrm::write_device_configuration`].
+    pub fn set_heartbeat_enable_bit(&mut self) {
+        set_bit!(self.0, 0)
+    }
+
+    /// Unsets heartbeat enable bit of the device.
+    /// To reflect the configuration change, call [`Abrm::write_device_configuration`].
+    pub fn disable_heartbeat(&mut self) {
+        unset_bit!(self.0, 0)
+    }
+
     /// Indicate multi event is enabled on the device.
     #[must_use]
     pub fn is_multi_event_enabled(self) -> bool {
@@ -959,6 +1397,12 @@ impl DeviceCapability {
         is_bit_set!(self.0, 0)
     }
 
+    /// Indicate whether access privilege and heartbeat are supported or not.
+    #[must_use]
+    pub fn is_heartbeat_supported(self) -> bool {
+        is_bit_set!(self.0, 1)
+    }
+
     /// Indicate whether family name is supported or not.
     #[must_use]
     pub fn is_family_name_supported(self) -> bool {
@@ -1009,6 +1453,7 @@ impl U3VCapablitiy {
 }
 
 /// XML file information.
+#[derive(Clone, Copy, Debug)]
 pub struct GenICamFileInfo(u32);
 
 impl GenICamFileInfo {
@@ -1212,3 +1657,500 @@ impl_dump_bytes_for_numeric!(i8);
 impl_dump_bytes_for_numeric!(i16);
 impl_dump_bytes_for_numeric!(i32);
 impl_dump_bytes_for_numeric!(i64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Registers which all hold `u64` value.
+    struct Registers(u64);
+
+    impl DeviceControl for Registers {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, _address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            let value = self.0.to_le_bytes();
+            let len = buf.len().min(value.len());
+            buf[..len].copy_from_slice(&value[..len]);
+            Ok(())
+        }
+
+        fn write(&mut self, _address: u64, _data: &[u8]) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            unreachable!()
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+    }
+
+    /// `SIRM` at `0x0` whose `SI_CONTROL` ignores writes if `stuck` is set.
+    struct SirmMemory {
+        bytes: Vec<u8>,
+        stuck: bool,
+        writes: usize,
+    }
+
+    impl SirmMemory {
+        fn new(alignment_exp: u8) -> Self {
+            let mut bytes = vec![0; 0x30];
+            bytes[3] = alignment_exp;
+            Self {
+                bytes,
+                stuck: false,
+                writes: 0,
+            }
+        }
+    }
+
+    impl DeviceControl for SirmMemory {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            let address = address as usize;
+            buf.copy_from_slice(&self.bytes[address..address + buf.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+            self.writes += 1;
+            if self.stuck && address == sirm::SI_CONTROL.0 {
+                return Ok(());
+            }
+            let address = address as usize;
+            self.bytes[address..address + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            unreachable!()
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+    }
+
+    fn is_invalid_address<T>(result: ControlResult<T>) -> bool {
+        matches!(result, Err(ControlError::InvalidAddress { .. }))
+    }
+
+    #[test]
+    fn test_address_overflow() {
+        let mut device = Registers(u64::MAX);
+
+        assert!(is_invalid_address(Sbrm::new(&mut device, u64::MAX)));
+
+        let sirm = Sirm::new(u64::MAX - 1);
+        assert!(is_invalid_address(sirm.is_stream_enable(&mut device)));
+        assert!(is_invalid_address(sirm.enable_stream(&mut device)));
+        // The exponent of the alignment exceeds the width of `usize`.
+        assert!(matches!(
+            Sirm::new(0).payload_size_alignment(&mut device),
+            Err(ControlError::InvalidDevice(_))
+        ));
+
+        let limits = Limits {
+            max_manifest_entries: u64::MAX,
+            ..Limits::default()
+        };
+        assert!(is_invalid_address(ManifestTable::new(
+            &mut device,
+            u64::MAX - 4,
+            &limits
+        )));
+        assert!(is_invalid_address(ManifestEntry::new(
+            &mut device,
+            u64::MAX - 8
+        )));
+    }
+
+    #[test]
+    fn test_manifest_entries_overflow() {
+        let limits = Limits {
+            max_manifest_entries: u64::MAX,
+            ..Limits::default()
+        };
+        // The table claims entries which overflow the address space.
+        let mut device = Registers(u64::MAX / 32);
+        assert!(is_invalid_address(ManifestTable::new(
+            &mut device,
+            0x1000,
+            &limits
+        )));
+
+        // The number of entries is checked before reading them.
+        assert!(matches!(
+            ManifestTable::new(&mut device, 0x1000, &Limits::default()),
+            Err(ControlError::LimitExceeded { .. })
+        ));
+
+        let mut device = Registers(2);
+        let table = ManifestTable::new(&mut device, 0x1000, &limits).unwrap();
+        let addresses: Vec<_> = table
+            .entries()
+            .iter()
+            .map(ManifestEntry::entry_address)
+            .collect();
+        assert_eq!(addresses, vec![0x1008, 0x1048]);
+    }
+
+    #[test]
+    fn test_manifest_entry_cache() {
+        let mut device = SirmMemory::new(0);
+        device.bytes = vec![0; 0x80];
+        let mut put = |(offset, len): (u64, u16), value: &[u8]| {
+            let offset = 0x40 + offset as usize;
+            device.bytes[offset..offset + len as usize].copy_from_slice(value);
+        };
+        put(
+            manifest_entry::GENICAM_FILE_VERSION,
+            &0x0102_0003_u32.to_le_bytes(),
+        );
+        put(
+            manifest_entry::REGISTER_ADDRESS,
+            &0x10_0000_u64.to_le_bytes(),
+        );
+        put(manifest_entry::FILE_SIZE, &0x2000_u64.to_le_bytes());
+        put(manifest_entry::SHA1_HASH, &[0xaa; 20]);
+
+        let entry = ManifestEntry::new(&mut device, 0x40).unwrap();
+        // Entries are cached.
+        device.bytes.iter_mut().for_each(|b| *b = 0);
+
+        assert_eq!(entry.genicam_file_version(), semver::Version::new(1, 2, 3));
+        assert_eq!(entry.file_address(), 0x10_0000);
+        assert_eq!(entry.file_size(), 0x2000);
+        assert_eq!(entry.sha1_hash(), Some([0xaa; 20]));
+        assert_eq!(
+            ManifestEntry::new(&mut device, 0x40).unwrap().sha1_hash(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_abrm_cache() {
+        let mut device = SirmMemory::new(0);
+        device.bytes = vec![0; 0x300];
+        let put = |device: &mut SirmMemory, (offset, _): (u64, u16), value: &[u8]| {
+            let offset = offset as usize;
+            device.bytes[offset..offset + value.len()].copy_from_slice(value);
+        };
+        put(
+            &mut device,
+            abrm::GENCP_VERSION,
+            &0x0001_0003_u32.to_le_bytes(),
+        );
+        put(&mut device, abrm::MODEL_NAME, b"emulated\0");
+        put(&mut device, abrm::SERIAL_NUMBER, b"CAM0001\0");
+        put(&mut device, abrm::USER_DEFINED_NAME, b"bench\0");
+        // Only user defined name is supported.
+        put(&mut device, abrm::DEVICE_CAPABILITY, &1_u64.to_le_bytes());
+        put(
+            &mut device,
+            abrm::MANIFEST_TABLE_ADDRESS,
+            &0x800_u64.to_le_bytes(),
+        );
+        put(&mut device, abrm::SBRM_ADDRESS, &u64::MAX.to_le_bytes());
+        put(
+            &mut device,
+            abrm::TIMESTAMP_INCREMENT,
+            &1000_u64.to_le_bytes(),
+        );
+        put(&mut device, abrm::TIMESTAMP, &42_u64.to_le_bytes());
+
+        let mut abrm = Abrm::new(&mut device).unwrap();
+        // Static registers are cached.
+        put(&mut device, abrm::MODEL_NAME, b"modified\0");
+        put(&mut device, abrm::USER_DEFINED_NAME, b"renamed\0");
+
+        assert_eq!(abrm.gencp_version(), semver::Version::new(1, 3, 0));
+        assert_eq!(abrm.model_name(), "emulated");
+        assert_eq!(abrm.serial_number(), "CAM0001");
+        assert_eq!(abrm.family_name(), None);
+        assert_eq!(abrm.manifest_table_address(), 0x800);
+        assert_eq!(abrm.timestamp_increment(), 1000);
+        assert_eq!(abrm.user_defined_name(), Some("bench"));
+        assert!(is_invalid_address(abrm.sbrm(&mut device)));
+
+        // Mutable registers are re-read on demand.
+        abrm.refresh(&mut device).unwrap();
+        assert_eq!(abrm.user_defined_name(), Some("renamed"));
+        assert_eq!(abrm.model_name(), "emulated");
+        abrm.set_user_defined_name(&mut device, "cameleon").unwrap();
+        assert_eq!(abrm.user_defined_name(), Some("cameleon"));
+        assert_eq!(&device.bytes[0x184..0x18d], b"cameleon\0");
+
+        // Timestamp is never cached.
+        assert_eq!(abrm.timestamp(&mut device).unwrap(), 42);
+        put(&mut device, abrm::TIMESTAMP, &43_u64.to_le_bytes());
+        assert_eq!(abrm.timestamp(&mut device).unwrap(), 43);
+    }
+
+    #[test]
+    fn test_latch_timestamp() {
+        let mut device = SirmMemory::new(0);
+        device.bytes = vec![0; 0x300];
+        let (timestamp, _) = abrm::TIMESTAMP;
+        let timestamp = timestamp as usize;
+        device.bytes[timestamp..timestamp + 8].copy_from_slice(&42_u64.to_le_bytes());
+        let abrm = Abrm::new(&mut device).unwrap();
+
+        let writes = device.writes;
+        assert_eq!(abrm.latch_timestamp(&mut device).unwrap(), 42);
+        assert_eq!(device.writes, writes + 1);
+        let (latch, _) = abrm::TIMESTAMP_LATCH;
+        let latch = latch as usize;
+        assert_eq!(&device.bytes[latch..latch + 4], &1_u32.to_le_bytes());
+
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let offset = abrm.clock_offset(&mut device).unwrap();
+        let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        assert_eq!(offset.uncertainty, offset.round_trip_time / 2);
+        let host_time = offset.to_host_time(42).unwrap();
+        assert!(before <= host_time && host_time <= after);
+
+        let offset = ClockOffset {
+            offset: -100,
+            uncertainty: Duration::default(),
+            round_trip_time: Duration::default(),
+        };
+        assert_eq!(
+            offset.to_host_time(1_000_000_100),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(offset.to_host_time(99), None);
+    }
+
+    #[test]
+    fn test_set_user_defined_name() {
+        let (addr, len) = abrm::USER_DEFINED_NAME;
+        let (addr, len) = (addr as usize, len as usize);
+        let mut device = SirmMemory::new(0);
+        device.bytes = vec![0; 0x300];
+        let capability = abrm::DEVICE_CAPABILITY.0 as usize;
+        device.bytes[capability..capability + 8].copy_from_slice(&1_u64.to_le_bytes());
+        let mut abrm = Abrm::new(&mut device).unwrap();
+
+        // The name fills the whole register.
+        let name = "a".repeat(len);
+        abrm.set_user_defined_name(&mut device, &name).unwrap();
+        assert_eq!(abrm.user_defined_name(), Some(name.as_str()));
+        assert_eq!(&device.bytes[addr..addr + len], name.as_bytes());
+
+        // A shorter name is NUL padded.
+        abrm.set_user_defined_name(&mut device, "cameleon").unwrap();
+        assert_eq!(abrm.user_defined_name(), Some("cameleon"));
+        assert_eq!(&device.bytes[addr..addr + 8], b"cameleon");
+        assert!(device.bytes[addr + 8..addr + len].iter().all(|&b| b == 0));
+        abrm.refresh(&mut device).unwrap();
+        assert_eq!(abrm.user_defined_name(), Some("cameleon"));
+
+        // Too long and non ascii names are rejected without touching the device.
+        let writes = device.writes;
+        assert!(matches!(
+            abrm.set_user_defined_name(&mut device, &"a".repeat(len + 1)),
+            Err(ControlError::InvalidData(..))
+        ));
+        assert!(matches!(
+            abrm.set_user_defined_name(&mut device, "caméléon"),
+            Err(ControlError::InvalidData(..))
+        ));
+        assert_eq!(device.writes, writes);
+        assert_eq!(abrm.user_defined_name(), Some("cameleon"));
+
+        // The capability bit is checked.
+        device.bytes[capability..capability + 8].copy_from_slice(&0_u64.to_le_bytes());
+        let mut abrm = Abrm::new(&mut device).unwrap();
+        assert!(matches!(
+            abrm.set_user_defined_name(&mut device, "cameleon"),
+            Err(ControlError::NotSupported(..))
+        ));
+        assert_eq!(device.writes, writes);
+    }
+
+    #[test]
+    fn test_set_heartbeat_timeout() {
+        let mut device = SirmMemory::new(0);
+        device.bytes = vec![0; 0x300];
+        let capability = abrm::DEVICE_CAPABILITY.0 as usize;
+        // Heartbeat is supported.
+        device.bytes[capability..capability + 8].copy_from_slice(&0b10_u64.to_le_bytes());
+        let abrm = Abrm::new(&mut device).unwrap();
+        assert!(!abrm
+            .device_configuration(&mut device)
+            .unwrap()
+            .is_heartbeat_enabled());
+
+        abrm.set_heartbeat_timeout(&mut device, Duration::from_millis(1500))
+            .unwrap();
+        assert_eq!(
+            abrm.heartbeat_timeout(&mut device).unwrap(),
+            Duration::from_millis(1500)
+        );
+        assert!(abrm
+            .device_configuration(&mut device)
+            .unwrap()
+            .is_heartbeat_enabled());
+
+        // Timeouts out of the register are rejected without touching the device.
+        let writes = device.writes;
+        assert!(matches!(
+            abrm.set_heartbeat_timeout(&mut device, Duration::from_micros(10)),
+            Err(ControlError::InvalidData(..))
+        ));
+        assert!(matches!(
+            abrm.set_heartbeat_timeout(&mut device, Duration::from_secs(u64::MAX)),
+            Err(ControlError::InvalidData(..))
+        ));
+        assert_eq!(device.writes, writes);
+
+        // The capability bit is checked.
+        device.bytes[capability..capability + 8].copy_from_slice(&0_u64.to_le_bytes());
+        let abrm = Abrm::new(&mut device).unwrap();
+        assert!(matches!(
+            abrm.set_heartbeat_timeout(&mut device, Duration::from_secs(1)),
+            Err(ControlError::NotSupported(..))
+        ));
+        assert_eq!(device.writes, writes);
+    }
+
+    #[test]
+    fn test_sbrm_without_eirm() {
+        let mut device = SirmMemory::new(0);
+        device.bytes = vec![0; 0x100];
+        let mut put = |(offset, len): (u64, u16), value: u64| {
+            let offset = offset as usize;
+            let len = len as usize;
+            device.bytes[offset..offset + len].copy_from_slice(&value.to_le_bytes()[..len]);
+        };
+        put(sbrm::U3V_VERSION, 0x0001_0000);
+        put(sbrm::MAXIMUM_COMMAND_TRANSFER_LENGTH, 1024);
+        put(sbrm::MAXIMUM_ACKNOWLEDGE_TRANSFER_LENGTH, 2048);
+        put(sbrm::NUMBER_OF_STREAM_CHANNELS, 1);
+        // Only `SIRM` is available.
+        put(sbrm::U3VCP_CAPABILITY_REGISTER, 0b1);
+        put(sbrm::SIRM_ADDRESS, 0x80);
+        put(sbrm::SIRM_LENGTH, 0x40);
+        put(sbrm::EIRM_ADDRESS, 0xc0);
+        put(sbrm::EIRM_LENGTH, 0x20);
+
+        let sbrm = Sbrm::new(&mut device, 0).unwrap();
+        // Static registers are cached.
+        device.bytes.iter_mut().for_each(|b| *b = 0xff);
+
+        assert_eq!(sbrm.u3v_version(), semver::Version::new(1, 0, 0));
+        assert_eq!(sbrm.maximum_command_transfer_length(), 1024);
+        assert_eq!(sbrm.maximum_acknowledge_trasfer_length(), 2048);
+        assert_eq!(sbrm.number_of_stream_channel(), 1);
+        assert_eq!(sbrm.sirm_address(), Some(0x80));
+        assert_eq!(sbrm.sirm_length(), Some(0x40));
+        assert!(sbrm.sirm().is_some());
+        assert_eq!(sbrm.eirm_address(), None);
+        assert_eq!(sbrm.eirm_length(), None);
+        assert_eq!(sbrm.iidc2_address(), None);
+    }
+
+    #[test]
+    fn test_payload_size_alignment() {
+        let mut device = SirmMemory::new(3);
+        let sirm = Sirm::new(0);
+        assert_eq!(sirm.payload_size_alignment(&mut device).unwrap(), 8);
+
+        sirm.set_payload_transfer_size(&mut device, 0x1000).unwrap();
+        sirm.set_payload_final_transfer1_size(&mut device, 0x18)
+            .unwrap();
+        sirm.set_payload_final_transfer2_size(&mut device, 0)
+            .unwrap();
+        assert_eq!(sirm.payload_transfer_size(&mut device).unwrap(), 0x1000);
+        assert_eq!(
+            sirm.payload_final_transfer1_size(&mut device).unwrap(),
+            0x18
+        );
+        assert_eq!(device.writes, 3);
+
+        // Unaligned sizes are rejected as the device does, without writing to the device.
+        for result in &[
+            sirm.set_payload_transfer_size(&mut device, 0x1001),
+            sirm.set_payload_final_transfer1_size(&mut device, 0x1c),
+            sirm.set_payload_final_transfer2_size(&mut device, 4),
+        ] {
+            match result {
+                Err(ControlError::Io(e)) => assert_eq!(
+                    e.downcast_ref::<StatusError>().unwrap().0.kind(),
+                    &ack::StatusKind::UsbSpecific(ack::UsbSpecificStatus::PayloadSizeNotAligned)
+                ),
+                res => panic!("unexpected result: {:?}", res),
+            }
+        }
+        assert_eq!(device.writes, 3);
+        assert_eq!(sirm.payload_transfer_size(&mut device).unwrap(), 0x1000);
+    }
+
+    #[test]
+    fn test_stream_enable() {
+        let mut device = SirmMemory::new(0);
+        let sirm = Sirm::new(0);
+        sirm.enable_stream(&mut device).unwrap();
+        assert!(sirm.is_stream_enable(&mut device).unwrap());
+        sirm.disable_stream(&mut device).unwrap();
+        assert!(!sirm.is_stream_enable(&mut device).unwrap());
+
+        // The device doesn't reflect the write.
+        device.stuck = true;
+        assert!(matches!(
+            sirm.enable_stream(&mut device),
+            Err(ControlError::InvalidDevice(_))
+        ));
+        sirm.disable_stream(&mut device).unwrap();
+    }
+
+    #[test]
+    fn test_event_enable() {
+        // `EIRM` follows `SIRM` in the memory.
+        let mut device = SirmMemory::new(0);
+        device.bytes[0x14..0x18].copy_from_slice(&256_u32.to_le_bytes());
+        let eirm = Eirm::new(0x10);
+        assert_eq!(
+            eirm.maximum_event_transfer_length(&mut device).unwrap(),
+            256
+        );
+
+        eirm.enable_event(&mut device).unwrap();
+        assert!(eirm.is_event_enable(&mut device).unwrap());
+        eirm.disable_event(&mut device).unwrap();
+        assert!(!eirm.is_event_enable(&mut device).unwrap());
+    }
+}
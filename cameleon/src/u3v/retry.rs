@@ -0,0 +1,197 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains [`RetryPolicy`], which retries transactions rejected by a busy device.
+
+use std::{thread, time::Duration};
+
+use tracing::warn;
+
+use crate::{ControlError, ControlResult};
+
+use super::control_handle::rejected_status;
+
+/// Policy to retry a transaction whose ack status is retryable, i.e. `GENCP_BUSY`,
+/// `GENCP_TIMEOUT` or a device specific status decoded as retryable.
+///
+/// The n-th retry waits `n * backoff` before sending the command again.
+///
+/// Only commands rejected by the device are retried, so a `WriteMem` acknowledged with a partial
+/// length is never written twice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts including the first one. `1` disables retrying.
+    pub max_attempts: u32,
+
+    /// The base duration of the linear backoff. The maximum device response time is used if
+    /// `None`.
+    pub backoff: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Constructs a policy which never retries.
+    #[must_use]
+    pub fn never() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: None,
+        }
+    }
+
+    /// Returns the delay before the `retry`-th retry, which starts from 1.
+    fn delay(&self, retry: u32, maximum_device_response_time: Duration) -> Duration {
+        self.backoff.unwrap_or(maximum_device_response_time) * retry
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: None,
+        }
+    }
+}
+
+/// Calls `transact` with the attempt number starting from 1 until it succeeds, fails with a
+/// non-retryable error, or `policy` gives up.
+///
+/// If `policy` gives up after retrying, the last error is wrapped in
+/// [`ControlError::RetriesExhausted`].
+pub(super) fn run<T>(
+    policy: &RetryPolicy,
+    maximum_device_response_time: Duration,
+    mut transact: impl FnMut(u32) -> ControlResult<T>,
+) -> ControlResult<T> {
+    let mut attempt = 1;
+    loop {
+        let err = match transact(attempt) {
+            Ok(value) => return Ok(value),
+            Err(err) => err,
+        };
+        let status = match rejected_status(&err) {
+            Some(status) if status.is_retryable() => status,
+            _ => return Err(err),
+        };
+
+        if attempt >= policy.max_attempts {
+            return Err(if attempt == 1 {
+                err
+            } else {
+                ControlError::RetriesExhausted {
+                    attempts: attempt,
+                    source: Box::new(err),
+                }
+            });
+        }
+
+        let delay = policy.delay(attempt, maximum_device_response_time);
+        warn!(
+            attempt,
+            max_attempts = policy.max_attempts,
+            ?delay,
+            %status,
+            "the device rejected the command with a retryable status, retry it"
+        );
+        thread::sleep(delay);
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cameleon_device::u3v::protocol::ack;
+
+    use super::{super::control_handle::StatusError, *};
+
+    fn rejected(status: ack::GenCpStatus) -> ControlError {
+        ControlError::Io(StatusError(status.into()).into())
+    }
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff: Some(Duration::from_millis(1)),
+        }
+    }
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy::default();
+        let mdrt = Duration::from_millis(200);
+        assert_eq!(policy.delay(1, mdrt), mdrt);
+        assert_eq!(policy.delay(2, mdrt), mdrt * 2);
+
+        let policy = RetryPolicy {
+            backoff: Some(Duration::from_millis(10)),
+            ..policy
+        };
+        assert_eq!(policy.delay(3, mdrt), Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_retry_busy() {
+        let mut attempts = vec![];
+        let result = run(&policy(3), Duration::default(), |attempt| {
+            attempts.push(attempt);
+            if attempt < 3 {
+                Err(rejected(ack::GenCpStatus::Busy))
+            } else {
+                Ok(attempt)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(attempts, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_retries_exhausted() {
+        let mut count = 0;
+        let err = run(&policy(3), Duration::default(), |_| -> ControlResult<()> {
+            count += 1;
+            Err(rejected(ack::GenCpStatus::Timeout))
+        })
+        .unwrap_err();
+        assert_eq!(count, 3);
+        assert!(matches!(
+            &err,
+            ControlError::RetriesExhausted { attempts: 3, .. }
+        ));
+        assert!(err.to_string().contains("3 attempts"));
+        // The status is still available to the caller.
+        assert!(rejected_status(&err).unwrap().is_retryable());
+
+        // The error is returned as is if the policy doesn't retry.
+        let err = run(
+            &RetryPolicy::never(),
+            Duration::default(),
+            |_| -> ControlResult<()> { Err(rejected(ack::GenCpStatus::Busy)) },
+        )
+        .unwrap_err();
+        assert!(matches!(err, ControlError::Io(..)));
+    }
+
+    #[test]
+    fn test_no_retry() {
+        // Neither non-retryable statuses nor other errors, e.g. a partial write, are retried.
+        for err in [
+            rejected(ack::GenCpStatus::AccessDenied),
+            ControlError::PartialChunkWrite {
+                offset: 0,
+                requested: 4,
+                written: 2,
+            },
+            ControlError::Timeout,
+        ] {
+            let mut err = Some(err);
+            let mut count = 0;
+            let result = run(&policy(3), Duration::default(), |_| -> ControlResult<()> {
+                count += 1;
+                Err(err.take().unwrap())
+            });
+            assert!(result.is_err());
+            assert_eq!(count, 1);
+        }
+    }
+}
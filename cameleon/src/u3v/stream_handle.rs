@@ -5,32 +5,101 @@
 //! This module contains low level streaming implementation for `U3V` device.
 
 use std::{
-    convert::TryInto,
-    sync::{Arc, Mutex, MutexGuard},
+    collections::HashSet,
+    convert::TryFrom,
+    io,
+    num::NonZeroUsize,
+    ops::Range,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
     time::Duration,
 };
 
-use async_std::task;
-use cameleon_device::u3v::{self, protocol::stream as u3v_stream};
-use futures::channel::oneshot;
-use tracing::{error, info, warn};
+use async_std::{future, task};
+use cameleon_device::{
+    u3v::{self, protocol::stream as u3v_stream},
+    PixelFormat,
+};
+use cameleon_impl::leak_check::{Resource, Tracked};
+use futures_channel::oneshot;
+use tracing::{debug, error, info, warn};
 
 use crate::{
     camera::PayloadStream,
-    payload::{ImageInfo, Payload, PayloadSender, PayloadType},
+    metrics::{self, MetricSink, MetricSource},
+    payload::{
+        BufferPool, BufferPoolStatistics, BufferProvider, ChunkIter, DestinationBuffer, FrameId,
+        ImageInfo, IncompleteInfo, OverflowPolicy, Payload, PayloadSender, PayloadStatus,
+        PayloadType, PipelineRunner, PipelineStage, PixelDecoders, PoolHandle, PooledBuffer,
+        ProvidedBuffer, ReceiverCounters, StageError, StageStatistics,
+    },
     ControlError, ControlResult, DeviceControl, StreamError, StreamResult,
 };
 
-use super::{async_read::AsyncPool, register_map::Abrm};
+use super::{
+    async_read::{BulkIn, CancelHandle, Completion, ScheduledChannel, TransferBuf},
+    channel::StreamChannel,
+    fairness::{StreamSlot, SCHEDULER},
+    open_options::OpenOptions,
+    quirks::Quirks,
+    register_map::{Abrm, DeviceCounterRegisters, DeviceStreamCounters, Sirm},
+    thread::ThreadConfig,
+};
+
+#[cfg(all(test, feature = "emulator"))]
+use super::async_read::CancellableChannel;
 
 /// This type is used to receive stream packets from the device.
+///
+/// # Lock ordering
+///
+/// The handle, the streaming loop, the control handle and libusb event handling follow the rules
+/// below so that closing the handle or dropping it never waits on progress which requires a lock
+/// the closing thread holds.
+///
+/// 1. The libusb event lock is the innermost lock except for leaf locks. Transfer callbacks run
+///    while the lock is held, so they only touch atomics and the queue of unreaped completions
+///    of the stream, whose lock is never held while waiting or taking another lock.
+/// 2. The streaming loop holds `inner` during its whole run, and signals its completion only
+///    after releasing it. Other threads lock `inner` only after the loop has been stopped and
+///    its thread joined.
+/// 3. The streaming loop never locks the control handle. A thread holding the lock of the
+///    control handle, e.g. the lock of [`super::SharedControlHandle`], can stop the loop.
+/// 4. Stopping the loop interrupts the transfer in progress, and waits for its completion up to
+///    four times [`StreamParams::timeout`] before joining the thread, so a stuck loop results in
+///    [`StreamError::Timeout`] instead of a hang. Pending transfers are reaped by the thread
+///    which submitted them, handling events itself if no other thread does, see rule 1.
 pub struct StreamHandle {
     /// Inner channel to receive payload data.
-    pub inner: Arc<Mutex<u3v::ReceiveChannel>>,
+    inner: Arc<Mutex<StreamChannel>>,
     /// Parameters for streaming.
     params: StreamParams,
-    cancellation_tx: Option<oneshot::Sender<()>>,
-    completion_rx: Option<oneshot::Receiver<()>>,
+    /// Thread running the streaming loop.
+    loop_thread: Option<LoopThread>,
+    /// Device id of [`FrameId`].
+    device_id: u64,
+    /// Acquisition generation of [`FrameId`], incremented every time streaming is started.
+    generation: u32,
+    /// Host side counters updated by the streaming loop.
+    counters: Arc<StreamCounters>,
+    /// Slot of the stream in the fair scheduler.
+    slot: Arc<StreamSlot>,
+    /// Statistics of the pipeline stages updated by the streaming loop.
+    stage_statistics: Arc<Mutex<Vec<StageStatistics>>>,
+    /// Pause state shared with the streaming loop.
+    pause: Arc<PauseControl>,
+    /// Pool of payload buffers, see [`StreamParams::buffer_count`].
+    buffer_pool: Arc<PoolSlot>,
+    /// Headroom raised by the streaming loop, which is written back to [`StreamParams::quirks`]
+    /// when the loop stops.
+    renegotiated: Arc<RenegotiatedHeadroom>,
+    /// Interrupts the transfers of the streaming loop when the loop is stopped.
+    cancel: CancelHandle,
+    /// Accounts the channel as opened.
+    tracked: Option<Tracked>,
 }
 
 macro_rules! unwrap_or_poisoned {
@@ -46,44 +115,66 @@ macro_rules! unwrap_or_poisoned {
 impl StreamHandle {
     /// Read leader of a stream packet.
     ///
-    /// Buffer size must be equal or larger than [`StreamParams::leader_size`].
+    /// Buffer size must be equal or larger than [`StreamParams::leader_transfer_size`].
     pub fn read_leader<'a>(&self, buf: &'a mut [u8]) -> StreamResult<u3v_stream::Leader<'a>> {
         if self.is_loop_running() {
             Err(StreamError::InStreaming)
         } else {
-            read_leader(
-                &mut unwrap_or_poisoned!(self.inner.lock())?,
-                &self.params,
-                buf,
-            )
+            bounce(buf, |bounce_buf| {
+                read_leader(
+                    &mut *unwrap_or_poisoned!(self.inner.lock())?,
+                    &self.params,
+                    &self.counters,
+                    bounce_buf,
+                )
+                .map(|_| ())
+            })?;
+            u3v_stream::Leader::parse(buf)
+                .map_err(|e| StreamError::InvalidPayload(format!("{}", e).into()))
         }
     }
 
     /// Read payload of a stream packet.
+    ///
+    /// If the device sends more data than `buf` can hold, the data is truncated to the size of
+    /// `buf`.
+    ///
+    /// The data is received into an internal buffer and copied into `buf`. Payloads delivered
+    /// by the streaming loop aren't copied.
     pub fn read_payload(&self, buf: &mut [u8]) -> StreamResult<usize> {
         if self.is_loop_running() {
             Err(StreamError::InStreaming)
         } else {
-            read_payload(
-                &mut unwrap_or_poisoned!(self.inner.lock())?,
-                &self.params,
-                buf,
-            )
+            bounce(buf, |bounce_buf| {
+                read_payload(
+                    &mut *unwrap_or_poisoned!(self.inner.lock())?,
+                    &self.params,
+                    bounce_buf,
+                    &mut PayloadScratch::default(),
+                )
+                .map(|read| read.len)
+            })
         }
     }
 
     /// Read trailer of a stream packet.
     ///
-    /// Buffer size must be equal of larger than [`StreamParams::trailer_size`].
+    /// Buffer size must be equal of larger than [`StreamParams::trailer_transfer_size`].
     pub fn read_trailer<'a>(&self, buf: &'a mut [u8]) -> StreamResult<u3v_stream::Trailer<'a>> {
         if self.is_loop_running() {
             Err(StreamError::InStreaming)
         } else {
-            read_trailer(
-                &mut unwrap_or_poisoned!(self.inner.lock())?,
-                &self.params,
-                buf,
-            )
+            bounce(buf, |bounce_buf| {
+                read_trailer(
+                    &mut *unwrap_or_poisoned!(self.inner.lock())?,
+                    &self.params,
+                    &self.counters,
+                    bounce_buf,
+                )
+                .map(|_| ())
+            })?;
+            u3v_stream::Trailer::parse(buf)
+                .map_err(|e| StreamError::InvalidPayload(format!("invalid trailer: {}", e).into()))
         }
     }
 
@@ -93,34 +184,218 @@ impl StreamHandle {
         &self.params
     }
 
-    ///  Return mutable params.
-    pub fn params_mut(&mut self) -> &mut StreamParams {
-        &mut self.params
+    /// Return mutable params.
+    ///
+    /// # Errors
+    ///
+    /// [`StreamError::InStreaming`] if the streaming loop is running, the parameters are fixed
+    /// until it's stopped.
+    pub fn params_mut(&mut self) -> StreamResult<&mut StreamParams> {
+        if self.is_loop_running() {
+            Err(StreamError::InStreaming)
+        } else {
+            Ok(&mut self.params)
+        }
+    }
+
+    /// Opens the handle, then applies `options`.
+    ///
+    /// [`OpenOptions::detach_kernel_driver`] takes effect from the next opening if the handle is
+    /// already opened.
+    pub fn open_with(&mut self, options: &OpenOptions) -> StreamResult<()> {
+        self.params.apply_open_options(options);
+        {
+            let mut inner = unwrap_or_poisoned!(self.inner.lock())?;
+            if !inner.is_opened() {
+                inner
+                    .set_auto_detach_kernel_driver(options.detach_kernel_driver)
+                    .map_err(|e| {
+                        error!(?e);
+                        StreamError::from(e)
+                    })?;
+            }
+        }
+        self.open()
+    }
+
+    /// Returns the name of the thread which receives stream packets.
+    /// Returns `None` if the streaming loop is not running.
+    #[must_use]
+    pub fn thread_name(&self) -> Option<&str> {
+        self.loop_thread.as_ref().map(|thread| thread.name.as_str())
+    }
+
+    /// Returns host side statistics accumulated over the lifetime of the handle.
+    #[must_use]
+    pub fn statistics(&self) -> HostStreamStatistics {
+        let fairness = self.slot.counters();
+        HostStreamStatistics {
+            serviced_completions: fairness.serviced,
+            throttled_submissions: fairness.throttled,
+            starvation_events: fairness.starvations,
+            ..self.counters.snapshot()
+        }
+    }
+
+    /// Returns the occupancy of the pool of payload buffers, `None` if the pool isn't used, see
+    /// [`StreamParams::buffer_count`].
+    #[must_use]
+    pub fn buffer_pool_statistics(&self) -> Option<BufferPoolStatistics> {
+        self.buffer_pool.get().as_ref().map(BufferPool::statistics)
+    }
+
+    /// Returns statistics of each stage of [`StreamParams::pipeline`], in the order of the stages.
+    ///
+    /// The statistics are reset every time streaming is started.
+    #[must_use]
+    pub fn pipeline_statistics(&self) -> Vec<StageStatistics> {
+        self.stage_statistics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns host side statistics together with the counters maintained by the device.
+    ///
+    /// NOTE: The counters are vendor specific, so only the ones configured in `registers` are
+    /// read and the others are `None`.
+    pub fn device_statistics<Ctrl: DeviceControl + ?Sized>(
+        &self,
+        ctrl: &mut Ctrl,
+        registers: &DeviceCounterRegisters,
+    ) -> ControlResult<StreamStatistics> {
+        Ok(StreamStatistics {
+            host: self.statistics(),
+            device: registers.read(ctrl)?,
+        })
+    }
+
+    /// Starts streaming, i.e. enables the stream interface of the device, then starts the
+    /// streaming loop.
+    ///
+    /// The handle can be started again after [`Self::stop`] without reopening the device.
+    ///
+    /// # Errors
+    ///
+    /// [`StreamError::InStreaming`] if the streaming loop is already running. The stream
+    /// interface is disabled again if the loop fails to start.
+    pub fn start(
+        &mut self,
+        sender: PayloadSender,
+        ctrl: &mut dyn DeviceControl,
+    ) -> StreamResult<()> {
+        if self.is_loop_running() {
+            return Err(StreamError::InStreaming);
+        }
+
+        ctrl.enable_streaming().map_err(|e| {
+            StreamError::Io(anyhow::Error::msg(format!(
+                "failed to enable streaming: {}",
+                e
+            )))
+        })?;
+        if let Err(err) = self.start_streaming_loop(sender, ctrl) {
+            if let Err(e) = ctrl.disable_streaming() {
+                warn!(?e, "failed to disable streaming after a failed start");
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Stops streaming, so that the handle can be started again with [`Self::start`].
+    ///
+    /// The stream interface of the device is disabled first, so the streaming loop drains the
+    /// data the device has already sent instead of cancelling transfers in the middle of a
+    /// payload. Then the loop is stopped in the same way as
+    /// [`PayloadStream::stop_streaming_loop`]. Finally the halt of the stream endpoint is
+    /// cleared, since a transfer cancelled in flight may leave the endpoint halted without the
+    /// loop observing it.
+    ///
+    /// Does nothing if the streaming loop isn't running, so stopping twice or stopping a stream
+    /// which has never been started is harmless.
+    ///
+    /// # Errors
+    ///
+    /// [`StreamError::Timeout`] if the loop doesn't finish within four times
+    /// [`StreamParams::timeout`], the method can be called again then. A failure to disable the
+    /// stream interface is returned after the loop is stopped.
+    pub fn stop(&mut self, ctrl: &mut dyn DeviceControl) -> StreamResult<()> {
+        if !self.is_loop_running() {
+            return Ok(());
+        }
+
+        let inner = self.inner.clone();
+        stop_streaming(
+            ctrl,
+            || self.stop_streaming_loop(),
+            || {
+                let mut inner = unwrap_or_poisoned!(inner.lock())?;
+                if inner.is_opened() {
+                    inner.clear_halt()?;
+                }
+                Ok(())
+            },
+        )
     }
 
     pub(super) fn new(device: &u3v::Device) -> ControlResult<Option<Self>> {
         let inner = device.stream_channel()?;
-        Ok(inner.map(|inner| Self {
+        Ok(inner.map(|inner| Self::with_channel(StreamChannel::Usb(inner), &device.device_info)))
+    }
+
+    /// Builds a handle over the stream channel of an emulated device.
+    #[cfg(all(test, feature = "emulator"))]
+    pub(super) fn new_emulated(
+        device: &cameleon_device::emulator::Device,
+    ) -> ControlResult<Option<Self>> {
+        let inner = device.stream_channel()?;
+        Ok(inner
+            .map(|inner| Self::with_channel(StreamChannel::Emulated(inner), &device.device_info)))
+    }
+
+    fn with_channel(inner: StreamChannel, info: &u3v::DeviceInfo) -> Self {
+        let counters = Arc::default();
+        let slot = SCHEDULER.register();
+        let buffer_pool = Arc::default();
+        register_metrics(&counters, &slot, &buffer_pool, &info.serial_number);
+        Self {
             inner: Arc::new(Mutex::new(inner)),
             params: StreamParams::default(),
-            cancellation_tx: None,
-            completion_rx: None,
-        }))
+            loop_thread: None,
+            device_id: FrameId::device_id_from_guid(&info.guid.to_string()),
+            generation: 0,
+            counters,
+            slot,
+            stage_statistics: Arc::default(),
+            pause: Arc::default(),
+            buffer_pool,
+            renegotiated: Arc::default(),
+            cancel: CancelHandle::default(),
+            tracked: None,
+        }
     }
 }
 
 impl PayloadStream for StreamHandle {
     fn open(&mut self) -> StreamResult<()> {
-        unwrap_or_poisoned!(self.inner.lock())?.open().map_err(|e| {
-            error!(?e);
-            e.into()
-        })
+        unwrap_or_poisoned!(self.inner.lock())?
+            .open()
+            .map_err(|e| {
+                error!(?e);
+                StreamError::from(e)
+            })?;
+        if self.tracked.is_none() {
+            self.tracked = Some(Tracked::new(Resource::Channel));
+        }
+        Ok(())
     }
 
     fn close(&mut self) -> StreamResult<()> {
         if self.is_loop_running() {
             self.stop_streaming_loop()?;
         }
+        self.tracked = None;
         unwrap_or_poisoned!(self.inner.lock())?
             .close()
             .map_err(|e| {
@@ -134,48 +409,102 @@ impl PayloadStream for StreamHandle {
         sender: PayloadSender,
         ctrl: &mut dyn DeviceControl,
     ) -> StreamResult<()> {
-        self.params = StreamParams::from_control(ctrl).map_err(|e| {
+        if self.is_loop_running() {
+            return Err(StreamError::InStreaming);
+        }
+        if self.params.buffer_provider.is_some() && !self.params.pipeline.is_empty() {
+            return Err(StreamError::NotSupported(
+                "pipeline stages can't be executed on payloads delivered into provided buffers"
+                    .into(),
+            ));
+        }
+
+        let params = StreamParams::from_control(ctrl).map_err(|e| {
             StreamError::Io(anyhow::Error::msg(format!(
                 "failed to setup streaming parameters: {}",
                 e
             )))
         })?;
-
-        if self.is_loop_running() {
-            return Err(StreamError::InStreaming);
-        }
-
-        let (cancellation_tx, cancellation_rx) = oneshot::channel();
-        let (completion_tx, completion_rx) = oneshot::channel();
-        self.cancellation_tx = Some(cancellation_tx);
-        self.completion_rx = Some(completion_rx);
-
+        self.params = StreamParams {
+            thread: std::mem::take(&mut self.params.thread),
+            pipeline: std::mem::take(&mut self.params.pipeline),
+            buffer_provider: self.params.buffer_provider.take(),
+            decoders: self.params.decoders.clone(),
+            quirks: self.params.quirks,
+            num_transfers: self.params.num_transfers,
+            buffer_count: self.params.buffer_count,
+            overflow_policy: self.params.overflow_policy,
+            max_section_transfer_size: self.params.max_section_transfer_size,
+            ..params
+        };
+        let buffer_pool = self
+            .buffer_pool
+            .prepare(self.params.buffer_count, self.params.maximum_payload_size());
+
+        self.generation = self.generation.wrapping_add(1);
+        self.pause = Arc::default();
+        self.renegotiated = Arc::new(RenegotiatedHeadroom::new(&self.params.quirks));
+        self.cancel = CancelHandle::default();
+        *self
+            .counters
+            .receiver
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(sender.counters());
         let strm_loop = StreamingLoop {
-            inner: self.inner.clone(),
             params: self.params.clone(),
+            frame_id: FrameId::new(self.device_id, 0, self.generation, 0),
+            counters: self.counters.clone(),
+            pipeline: PipelineRunner::new(
+                self.params.pipeline.clone(),
+                self.stage_statistics.clone(),
+            ),
+            pause: self.pause.clone(),
+            buffer_pool,
+            renegotiated: self.renegotiated.clone(),
+            cancel: self.cancel.clone(),
             sender,
-            completion_tx,
-            cancellation_rx,
         };
-        std::thread::spawn(|| {
-            strm_loop.run();
-        });
+        let inner = self.inner.clone();
+        let slot = self.slot.clone();
+        let cancel = self.cancel.clone();
+        self.loop_thread = Some(
+            LoopThread::spawn(&self.params.thread, move |cancellation_rx| {
+                let mut inner = inner.lock().unwrap();
+                match &mut *inner {
+                    StreamChannel::Usb(channel) => {
+                        let mut pipe = ScheduledChannel::new(channel, &slot, &cancel);
+                        strm_loop.run(&mut pipe, cancellation_rx)
+                    }
+                    // The emulated channel isn't scheduled, its reads are only interrupted.
+                    #[cfg(all(test, feature = "emulator"))]
+                    StreamChannel::Emulated(channel) => {
+                        let mut pipe = CancellableChannel::new(channel, &cancel);
+                        strm_loop.run(&mut pipe, cancellation_rx)
+                    }
+                }
+            })
+            .map_err(|e| StreamError::Io(e.into()))?,
+        );
 
-        info!("start streaming loop successfully");
+        info!(thread = %self.params.thread.name, "start streaming loop successfully");
         Ok(())
     }
 
+    /// Stops the streaming loop and joins its thread.
+    ///
+    /// Returns [`StreamError::Timeout`] if the loop doesn't finish within four times
+    /// [`StreamParams::timeout`], the loop is still regarded as running then and the method can
+    /// be called again.
     fn stop_streaming_loop(&mut self) -> StreamResult<()> {
-        if self.is_loop_running() {
-            let (cancellation_tx, completion_rx) = (
-                self.cancellation_tx.take().unwrap(),
-                self.completion_rx.take().unwrap(),
-            );
-            cancellation_tx.send(()).map_err(|_| {
-                StreamError::Poisoned("failed to send cancellation signal to streaming loop".into())
-            })?;
-            task::block_on(completion_rx)
-                .map_err(|e| StreamError::Poisoned(e.to_string().into()))?;
+        if let Some(loop_thread) = &mut self.loop_thread {
+            loop_thread.cancel();
+            // Interrupt the transfer in progress instead of waiting out its timeout.
+            self.cancel.cancel();
+            // Wake the loop up if it's paused.
+            self.pause.resume();
+            loop_thread.join(self.params.timeout * 4)?;
+            self.loop_thread = None;
+            self.renegotiated.write_back(&mut self.params.quirks);
         }
 
         info!("stop streaming loop successfully");
@@ -183,8 +512,44 @@ impl PayloadStream for StreamHandle {
     }
 
     fn is_loop_running(&self) -> bool {
-        debug_assert_eq!(self.completion_rx.is_some(), self.cancellation_tx.is_some());
-        self.completion_rx.is_some()
+        self.loop_thread.is_some()
+    }
+
+    /// Pauses the streaming loop.
+    ///
+    /// The loop finishes or discards the payload in flight, and parks once no more data arrives
+    /// within [`StreamParams::timeout`], so the acquisition should be stopped before calling this
+    /// method. Returns [`StreamError::Timeout`] if the loop doesn't park within four times
+    /// [`StreamParams::timeout`], the loop keeps running in that case.
+    ///
+    /// The buffers, the transfer parameters and the generation of [`FrameId`] are kept.
+    fn pause_streaming_loop(&mut self) -> StreamResult<()> {
+        if !self.is_loop_running() {
+            return Ok(());
+        }
+
+        if self.pause.pause(self.params.timeout * 4) {
+            info!("pause streaming loop successfully");
+            Ok(())
+        } else {
+            let err = StreamError::Timeout;
+            error!(
+                ?err,
+                "streaming loop doesn't park, the device may be still streaming"
+            );
+            Err(err)
+        }
+    }
+
+    /// Resumes the streaming loop paused by [`PayloadStream::pause_streaming_loop`].
+    fn resume_streaming_loop(&mut self) -> StreamResult<()> {
+        self.pause.resume();
+        info!("resume streaming loop successfully");
+        Ok(())
+    }
+
+    fn is_loop_paused(&self) -> bool {
+        self.is_loop_running() && self.pause.is_paused()
     }
 }
 
@@ -203,30 +568,48 @@ impl From<StreamHandle> for Box<dyn PayloadStream> {
 }
 
 struct StreamingLoop {
-    inner: Arc<Mutex<u3v::ReceiveChannel>>,
     params: StreamParams,
+    /// `FrameId` of the acquisition, `block_id` is filled for each payload.
+    frame_id: FrameId,
+    counters: Arc<StreamCounters>,
+    pipeline: PipelineRunner,
+    pause: Arc<PauseControl>,
+    buffer_pool: Option<BufferPool>,
+    renegotiated: Arc<RenegotiatedHeadroom>,
+    cancel: CancelHandle,
     sender: PayloadSender,
-    completion_tx: oneshot::Sender<()>,
-    cancellation_rx: oneshot::Receiver<()>,
 }
 
 impl StreamingLoop {
-    fn run(mut self) {
-        let mut trailer_buf = vec![0; self.params.trailer_size];
+    /// Receives payloads from `pipe` until the loop is cancelled or all the receivers are
+    /// dropped.
+    fn run<P: BulkIn>(mut self, pipe: &mut P, mut cancellation_rx: oneshot::Receiver<()>) {
+        let mut trailer_buf = vec![0; self.params.trailer_transfer_size()];
         let mut payload_buf_opt = None;
-        let mut leader_buf = vec![0; self.params.leader_size];
-        let mut inner = self.inner.lock().unwrap();
+        let mut payload_scratch = PayloadScratch::default();
+        let mut leader_buf = vec![0; self.params.leader_transfer_size()];
+        let mut unknown_formats = UnknownFormats::default();
+        let mut blocks = BlockTracker::default();
+        // `true` while transfers are skipped until the next leader.
+        let mut resyncing = false;
 
         loop {
             macro_rules! unwrap_or_continue {
                 ($result:expr, $payload_buf:expr) => {
                     match $result {
                         Ok(v) => v,
+                        // The transfer is interrupted to stop the loop.
+                        Err(_) if self.cancel.is_cancelled() => break,
                         Err(e) => {
                             warn!(?e);
                             // Reuse `payload_buf`.
                             payload_buf_opt = $payload_buf;
-                            self.sender.try_send(Err(e)).ok();
+                            StreamCounters::increment(&self.counters.failed);
+                            if self.params.renegotiate(&e) {
+                                self.renegotiated.store(&self.params.quirks);
+                            } else {
+                                self.sender.try_send(Err(e)).ok();
+                            }
                             continue;
                         }
                     }
@@ -236,194 +619,1069 @@ impl StreamingLoop {
             // Stop the loop when
             // 1. `cancellation_tx` sends signal.
             // 2. `cancellation_tx` is dropped.
-            if self.cancellation_rx.try_recv().transpose().is_some() {
+            // 3. All receivers are dropped, pending transfers are cancelled on the way out.
+            if cancellation_rx.try_recv().transpose().is_some() || self.cancel.is_cancelled() {
+                break;
+            }
+            if self.sender.is_closed() {
+                info!("all payload receivers are dropped, stop streaming loop");
                 break;
             }
 
+            // The transfer sizes may be renegotiated after an overflow.
+            leader_buf.resize(self.params.leader_transfer_size(), 0);
+            trailer_buf.resize(self.params.trailer_transfer_size(), 0);
+
             let maximum_payload_size = self.params.maximum_payload_size();
             let mut payload_buf = match payload_buf_opt.take() {
                 Some(payload_buf) => payload_buf,
-                None => match self.sender.try_recv() {
-                    Ok(mut payload) => {
-                        if payload.payload.len() != maximum_payload_size {
-                            payload.payload.resize(maximum_payload_size, 0);
-                        }
-                        payload.payload
-                    }
-                    Err(_) => {
-                        vec![0; maximum_payload_size]
-                    }
+                None => match self.acquire_payload_buf(maximum_payload_size) {
+                    Some(payload_buf) => payload_buf,
+                    // All buffers of the pool are in use, check the cancellation and retry.
+                    None => continue,
                 },
             };
 
-            let leader = match read_leader(&mut inner, &self.params, &mut trailer_buf) {
+            let leader = match read_leader(pipe, &self.params, &self.counters, &mut leader_buf) {
                 Ok(leader) => leader,
+                // The transfer is interrupted to stop the loop.
+                Err(_) if self.cancel.is_cancelled() => break,
+                Err(StreamError::Timeout) if self.pause.park() => {
+                    // The pipe is drained and the loop has been paused.
+                    blocks.resume();
+                    StreamCounters::increment(&self.counters.pauses);
+                    payload_buf_opt = Some(payload_buf);
+                    continue;
+                }
+                Err(StreamError::InvalidPayload(reason)) => {
+                    // Payload data arrives without a leader if the leader is lost, the transfers
+                    // are skipped until the next leader arrives.
+                    debug!(%reason, "skip a transfer which isn't a leader");
+                    if !resyncing {
+                        resyncing = true;
+                        self.sender.record_resync();
+                    }
+                    payload_buf_opt = Some(payload_buf);
+                    continue;
+                }
+                Err(err) if self.params.renegotiate(&err) => {
+                    // The leader is lost, the following leaders fit in the transfer.
+                    self.renegotiated.store(&self.params.quirks);
+                    StreamCounters::increment(&self.counters.failed);
+                    payload_buf_opt = Some(payload_buf);
+                    continue;
+                }
                 Err(err) => {
-                    // Report and send error if the error is fatal.
-                    if matches!(err, StreamError::Io(..) | StreamError::Disconnected) {
+                    // Report and send error if the error is fatal or needs a workaround.
+                    if matches!(
+                        err,
+                        StreamError::Io(..)
+                            | StreamError::Disconnected
+                            | StreamError::SectionOverflow { .. }
+                    ) {
                         error!(?err);
+                        StreamCounters::increment(&self.counters.failed);
                         self.sender.try_send(Err(err)).ok();
                     }
                     payload_buf_opt = Some(payload_buf);
                     continue;
                 }
             };
-            let read_payload_size = unwrap_or_continue!(
-                read_payload(&mut inner, &self.params, &mut payload_buf),
-                Some(payload_buf)
-            );
-            let trailer = unwrap_or_continue!(
-                read_trailer(&mut inner, &self.params, &mut leader_buf),
+            resyncing = false;
+            blocks.observe(leader.block_id(), &self.counters);
+            let mut payload = unwrap_or_continue!(
+                receive_payload(
+                    pipe,
+                    &self.params,
+                    &self.counters,
+                    leader,
+                    self.frame_id,
+                    &mut payload_buf,
+                    &mut payload_scratch,
+                    &mut trailer_buf
+                ),
                 Some(payload_buf)
             );
-
-            let payload = unwrap_or_continue!(
-                PayloadBuilder {
-                    leader,
-                    payload_buf,
-                    read_payload_size,
-                    trailer
+            StreamCounters::increment(&self.counters.received);
+            if payload.is_incomplete() {
+                StreamCounters::increment(&self.counters.incomplete);
+            }
+            if let PayloadStatus::Incomplete {
+                missing_bytes,
+                missing_segments,
+            } = payload.status()
+            {
+                StreamCounters::add(&self.counters.missing_segments, missing_segments);
+                StreamCounters::add(&self.counters.missing_bytes, missing_bytes);
+            }
+            if unknown_formats.observe(&payload) {
+                StreamCounters::increment(&self.counters.unknown_format);
+            }
+            if let Err(err) = self.pipeline.run(&mut payload) {
+                // Reuse the buffer truncated by the pipeline.
+                payload_buf_opt = Some(PayloadBuf::reclaim(payload, maximum_payload_size));
+                match err {
+                    StageError::Rejected(reason) => {
+                        debug!(%reason, "payload is rejected by the pipeline");
+                        StreamCounters::increment(&self.counters.rejected);
+                    }
+                    StageError::Failed(_) => {
+                        warn!(?err);
+                        StreamCounters::increment(&self.counters.failed);
+                        let err = StreamError::Pipeline(err.to_string().into());
+                        self.sender.try_send(Err(err)).ok();
+                    }
                 }
-                .build(),
-                None
-            );
+                continue;
+            }
             if let Err(err) = self.sender.try_send(Ok(payload)) {
                 warn!(?err);
+                StreamCounters::increment(&self.counters.dropped);
+            }
+        }
+    }
+
+    /// Returns a buffer provided by [`StreamParams::buffer_provider`], or a buffer of the pool if
+    /// no buffer is provided.
+    ///
+    /// Returns `None` if all buffers of [`BufferPool`] are still in use after waiting for
+    /// [`StreamParams::timeout`].
+    fn acquire_payload_buf(&self, len: usize) -> Option<PayloadBuf> {
+        if let Some(provider) = &self.params.buffer_provider {
+            match provider.acquire(len) {
+                Some(buf) if buf.as_slice().len() >= len => return Some(PayloadBuf::Provided(buf)),
+                Some(buf) => warn!(
+                    provided_len = buf.as_slice().len(),
+                    len, "provided buffer is too small, receiving into a pool buffer"
+                ),
+                None => {}
             }
         }
 
-        if let Err(e) = self.completion_tx.send(()) {
-            error!(?e);
+        if let Some(pool) = &self.buffer_pool {
+            if let Some(buf) = pool.try_take() {
+                return Some(PayloadBuf::Pooled(buf));
+            }
+            if self.params.overflow_policy == OverflowPolicy::DropOldest
+                && self.sender.drop_oldest()
+            {
+                debug!("buffer pool is exhausted, drop the oldest payload");
+                StreamCounters::increment(&self.counters.dropped);
+            }
+            return pool
+                .take_timeout(self.params.timeout)
+                .map(PayloadBuf::Pooled);
         }
+
+        Some(match self.sender.try_recv() {
+            Ok(payload) => PayloadBuf::reclaim(payload, len),
+            Err(_) => PayloadBuf::Pool(vec![0; len]),
+        })
     }
 }
 
-struct PayloadBuilder<'a> {
-    leader: u3v_stream::Leader<'a>,
-    payload_buf: Vec<u8>,
-    read_payload_size: usize,
-    trailer: u3v_stream::Trailer<'a>,
+/// Buffer which receives payload data.
+enum PayloadBuf {
+    /// A buffer of the pool, which is recycled through [`PayloadSender`].
+    Pool(Vec<u8>),
+    /// A buffer provided by [`StreamParams::buffer_provider`].
+    Provided(Box<dyn DestinationBuffer>),
+    /// A buffer taken from [`BufferPool`], which is returned to the pool on drop.
+    Pooled(PooledBuffer),
 }
 
-impl<'a> PayloadBuilder<'a> {
-    fn build(self) -> StreamResult<Payload> {
-        let payload_status = self.trailer.payload_status();
-        if payload_status != u3v_stream::PayloadStatus::Success {
-            return Err(StreamError::InvalidPayload(
-                format!("trailer status indicates error: {:?}", payload_status).into(),
-            ));
+impl PayloadBuf {
+    /// Reclaims the buffer of `payload` which isn't delivered, the buffer is resized to `len`.
+    fn reclaim(mut payload: Payload, len: usize) -> Self {
+        let mut buf = std::mem::take(&mut payload.payload);
+        buf.resize(len, 0);
+        match payload.pool.take() {
+            Some(pool) => Self::Pooled(PooledBuffer::from_parts(buf, pool)),
+            None => Self::Pool(buf),
         }
+    }
 
-        if self.trailer.valid_payload_size() > self.read_payload_size as u64 {
-            let err_msg = format!("the actual read payload size is smaller than the size specified in the trailer: expected {}, but got {}",
-                                  self.trailer.valid_payload_size(),
-                                  self.read_payload_size);
-            return Err(StreamError::InvalidPayload(err_msg.into()));
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            Self::Pool(buf) => buf,
+            Self::Provided(buf) => buf.as_slice(),
+            Self::Pooled(buf) => buf.as_slice(),
         }
+    }
 
-        match self.leader.payload_type() {
-            u3v_stream::PayloadType::Image => self.build_image_payload(),
-            u3v_stream::PayloadType::ImageExtendedChunk => self.build_image_extended_payload(),
-            u3v_stream::PayloadType::Chunk => self.build_chunk_payload(),
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        match self {
+            Self::Pool(buf) => buf,
+            Self::Provided(buf) => buf.as_mut_slice(),
+            Self::Pooled(buf) => buf.as_mut_slice(),
         }
     }
 
-    fn build_image_payload(self) -> StreamResult<Payload> {
-        let leader: u3v_stream::ImageLeader = self.specific_leader_as()?;
-        let trailer: u3v_stream::ImageTrailer = self.specific_trailer_as()?;
-
-        let id = self.leader.block_id();
-        let valid_payload_size = self.trailer.valid_payload_size() as usize;
-
-        let image_info = Some(ImageInfo {
-            width: leader.width() as usize,
-            height: trailer.actual_height() as usize,
-            x_offset: leader.x_offset() as usize,
-            y_offset: leader.y_offset() as usize,
-            pixel_format: leader.pixel_format(),
-            image_size: valid_payload_size,
-        });
+    /// Splits the buffer into the storage of [`Payload`], only a buffer of the pool is accounted
+    /// as a pool buffer.
+    fn into_storage(self) -> (Vec<u8>, Option<ProvidedBuffer>, PoolHandle, Tracked) {
+        match self {
+            Self::Pool(buf) => (
+                buf,
+                None,
+                PoolHandle::default(),
+                Tracked::new(Resource::PoolBuffer),
+            ),
+            Self::Provided(buf) => (
+                vec![],
+                Some(ProvidedBuffer::new(buf)),
+                PoolHandle::default(),
+                Tracked::new(Resource::ProvidedBuffer),
+            ),
+            Self::Pooled(buf) => {
+                let (buf, pool) = buf.into_parts();
+                (buf, None, pool, Tracked::new(Resource::PoolBuffer))
+            }
+        }
+    }
+}
 
-        Ok(Payload {
-            id,
-            payload_type: PayloadType::Image,
-            image_info,
-            payload: self.payload_buf,
-            valid_payload_size,
-            timestamp: leader.timestamp(),
-        })
+impl Default for PayloadBuf {
+    fn default() -> Self {
+        Self::Pool(vec![])
     }
+}
 
-    fn build_image_extended_payload(self) -> StreamResult<Payload> {
-        const CHUNK_ID_LEN: usize = 4;
-        const CHUNK_SIZE_LEN: usize = 4;
+// Safety: the bytes are in the heap allocation of the vector, or of the boxed
+// `DestinationBuffer`, so the transfers write directly into the storage of `Payload`.
+unsafe impl TransferBuf for PayloadBuf {
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        PayloadBuf::as_mut_slice(self)
+    }
+}
 
-        let leader: u3v_stream::ImageExtendedChunkLeader = self.specific_leader_as()?;
-        let trailer: u3v_stream::ImageExtendedChunkTrailer = self.specific_trailer_as()?;
+/// Runs `read` with an owned buffer as long as `buf`, and copies the buffer into `buf`.
+///
+/// Transfers write only into buffers which they own, see [`TransferBuf`], so a buffer borrowed
+/// from the caller is bounced through an owned one.
+fn bounce<T>(
+    buf: &mut [u8],
+    read: impl FnOnce(&mut Vec<u8>) -> StreamResult<T>,
+) -> StreamResult<T> {
+    let mut bounce_buf = vec![0; buf.len()];
+    let result = read(&mut bounce_buf)?;
+    buf.copy_from_slice(&bounce_buf);
+    Ok(result)
+}
 
-        let id = self.leader.block_id();
-        let valid_payload_size = self.trailer.valid_payload_size() as usize;
-
-        // Extract image size from the first chunk of the paload data.
-        // Chunk data is designed to be decoded from the last byte to the first byte.
-        // Use chunk parser of `cameleon_genapi` once it gets implemented.
-        let mut current_offset = valid_payload_size;
-        let image_size = loop {
-            current_offset = current_offset.checked_sub(CHUNK_SIZE_LEN).ok_or_else(|| {
-                StreamError::InvalidPayload("failed to parse chunk data: size field missing".into())
-            })?;
-            let data_size = u32::from_be_bytes(
-                self.payload_buf[current_offset..current_offset + CHUNK_SIZE_LEN]
-                    .try_into()
-                    .unwrap(),
-            ) as usize;
-            current_offset = current_offset.checked_sub(data_size + CHUNK_ID_LEN).ok_or_else(|| {
-                StreamError::InvalidPayload(
-                    "failed to parse chunk data: chunk data size is smaller than specified size".into()
-                )
-            })?;
+/// Stops streaming in the order described in [`StreamHandle::stop`].
+///
+/// `stop_loop` stops the streaming loop, and `clear_halt` clears the halt of the stream
+/// endpoint once the loop has released the channel.
+fn stop_streaming(
+    ctrl: &mut dyn DeviceControl,
+    stop_loop: impl FnOnce() -> StreamResult<()>,
+    clear_halt: impl FnOnce() -> StreamResult<()>,
+) -> StreamResult<()> {
+    // The loop is stopped even if the device can't be reached, e.g. it's been disconnected.
+    let disabled = ctrl.disable_streaming();
+    if let Err(e) = &disabled {
+        warn!(
+            ?e,
+            "failed to disable streaming, stop the streaming loop anyway"
+        );
+    }
+    stop_loop()?;
+    clear_halt()?;
+
+    disabled.map_err(|e| {
+        StreamError::Io(anyhow::Error::msg(format!(
+            "failed to disable streaming: {}",
+            e
+        )))
+    })
+}
 
-            if current_offset == 0 {
-                break data_size;
-            }
-        };
+/// Thread running a streaming loop, see the lock ordering in [`StreamHandle`].
+///
+/// [`super::EventReceiver`] runs its loop on the same kind of thread.
+pub(super) struct LoopThread {
+    name: String,
+    /// `None` once the loop is cancelled.
+    cancellation_tx: Option<oneshot::Sender<()>>,
+    completion_rx: oneshot::Receiver<()>,
+    /// `None` once the thread is joined.
+    handle: Option<thread::JoinHandle<()>>,
+}
 
-        let image_info = Some(ImageInfo {
-            width: leader.width() as usize,
-            height: trailer.actual_height() as usize,
-            x_offset: leader.x_offset() as usize,
-            y_offset: leader.y_offset() as usize,
-            pixel_format: leader.pixel_format(),
-            image_size,
-        });
+impl LoopThread {
+    /// Spawns a thread configured by `config` which runs `f`.
+    ///
+    /// `f` receives the cancellation signal, completion is signalled after `f` returns, i.e.
+    /// after everything `f` owns including its lock guards has been dropped.
+    pub(super) fn spawn<F>(config: &ThreadConfig, f: F) -> io::Result<Self>
+    where
+        F: FnOnce(oneshot::Receiver<()>) + Send + 'static,
+    {
+        let (cancellation_tx, cancellation_rx) = oneshot::channel();
+        let (completion_tx, completion_rx) = oneshot::channel();
+        let handle = config.spawn(move || {
+            f(cancellation_rx);
+            completion_tx.send(()).ok();
+        })?;
 
-        Ok(Payload {
-            id,
-            payload_type: PayloadType::ImageExtendedChunk,
-            image_info,
-            payload: self.payload_buf,
-            valid_payload_size,
-            timestamp: leader.timestamp(),
+        Ok(Self {
+            name: config.name.clone(),
+            cancellation_tx: Some(cancellation_tx),
+            completion_rx,
+            handle: Some(handle),
         })
     }
 
-    fn build_chunk_payload(self) -> StreamResult<Payload> {
-        let leader: u3v_stream::ChunkLeader = self.specific_leader_as()?;
-        let _: u3v_stream::ChunkTrailer = self.specific_trailer_as()?;
+    /// Requests the loop to stop, the loop stops after the iteration in progress.
+    pub(super) fn cancel(&mut self) {
+        if let Some(cancellation_tx) = self.cancellation_tx.take() {
+            // The loop has already finished if the receiver is dropped.
+            cancellation_tx.send(()).ok();
+        }
+    }
 
-        let id = self.leader.block_id();
-        let valid_payload_size = self.trailer.valid_payload_size() as usize;
+    /// Waits for the loop to finish up to `timeout`, then joins the thread.
+    ///
+    /// The thread is joined only after the completion is signalled, so this never blocks longer
+    /// than `timeout` on a loop which is stuck.
+    pub(super) fn join(&mut self, timeout: Duration) -> StreamResult<()> {
+        // The sender is dropped without sending only if the loop panics, the thread is joined in
+        // that case too.
+        if task::block_on(future::timeout(timeout, &mut self.completion_rx)).is_err() {
+            warn!(thread = %self.name, "loop doesn't finish in time");
+            return Err(StreamError::Timeout);
+        }
 
-        Ok(Payload {
-            id,
-            payload_type: PayloadType::Chunk,
+        match self.handle.take().map(thread::JoinHandle::join) {
+            Some(Err(_)) => Err(StreamError::Poisoned(
+                format!("loop thread `{}` panicked", self.name).into(),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Host side statistics of the streaming loop, see [`StreamHandle::statistics`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct HostStreamStatistics {
+    /// The number of payloads received from the device, including incomplete ones.
+    pub received_payloads: u64,
+    /// The number of received payloads which are incomplete.
+    pub incomplete_payloads: u64,
+    /// The number of transfers missing within received payloads, see
+    /// [`PayloadStatus::Incomplete`].
+    pub missing_segments: u64,
+    /// The number of bytes missing within received payloads, see
+    /// [`PayloadStatus::Incomplete`].
+    pub missing_bytes: u64,
+    /// The number of payloads which failed to be received.
+    pub failed_payloads: u64,
+    /// The number of received payloads dropped because the receiver is full, including
+    /// payloads dropped by [`FrameQueue`](crate::payload::FrameQueue) in the latest streaming.
+    pub dropped_payloads: u64,
+    /// The number of received payloads whose pixel format isn't modeled, see
+    /// [`PixelFormat::Unknown`].
+    pub unknown_format_payloads: u64,
+    /// The number of received payloads rejected by a stage of [`StreamParams::pipeline`].
+    pub rejected_payloads: u64,
+    /// The number of block ids missing between received payloads, which implies payloads
+    /// lost in the device or on the link.
+    ///
+    /// Block ids skipped while the streaming is paused are counted in
+    /// [`HostStreamStatistics::paused_blocks`] instead.
+    pub lost_blocks: u64,
+    /// The number of block ids skipped while the streaming is paused, see
+    /// [`PayloadStream::pause_streaming_loop`].
+    pub paused_blocks: u64,
+    /// The number of times the streaming loop has been paused.
+    pub pauses: u64,
+    /// The largest leader size observed, including leaders which exceed the transfer size.
+    ///
+    /// This value can be compared with [`StreamParams::leader_size`] to derive
+    /// [`Quirks::leader_headroom`].
+    pub max_leader_size: u64,
+    /// The largest trailer size observed, including trailers which exceed the transfer size.
+    pub max_trailer_size: u64,
+    /// The number of transfer completions of the stream serviced by the event handler, which may
+    /// run on the thread of another stream sharing the libusb context.
+    pub serviced_completions: u64,
+    /// The number of submissions held off to let another starved stream drain its completions.
+    pub throttled_submissions: u64,
+    /// The number of completions reaped after they have been left unreaped longer than the
+    /// starvation threshold.
+    pub starvation_events: u64,
+}
+
+/// Host and device side statistics of the stream, see [`StreamHandle::device_statistics`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamStatistics {
+    /// Statistics measured by the host.
+    pub host: HostStreamStatistics,
+    /// Counters maintained by the device.
+    pub device: DeviceStreamCounters,
+}
+
+impl StreamStatistics {
+    /// Returns the increase of each counter since `earlier`.
+    ///
+    /// A device counter is `None` if it's absent in either statistics. Device counters are
+    /// assumed to wrap around. The largest observed sizes are not counters, so they are kept as
+    /// is.
+    #[must_use]
+    pub fn delta_since(&self, earlier: &Self) -> Self {
+        let host = |now: u64, earlier: u64| now.saturating_sub(earlier);
+        let device = |now: Option<u32>, earlier: Option<u32>| Some(now?.wrapping_sub(earlier?));
+
+        Self {
+            host: HostStreamStatistics {
+                received_payloads: host(
+                    self.host.received_payloads,
+                    earlier.host.received_payloads,
+                ),
+                incomplete_payloads: host(
+                    self.host.incomplete_payloads,
+                    earlier.host.incomplete_payloads,
+                ),
+                missing_segments: host(self.host.missing_segments, earlier.host.missing_segments),
+                missing_bytes: host(self.host.missing_bytes, earlier.host.missing_bytes),
+                failed_payloads: host(self.host.failed_payloads, earlier.host.failed_payloads),
+                dropped_payloads: host(self.host.dropped_payloads, earlier.host.dropped_payloads),
+                unknown_format_payloads: host(
+                    self.host.unknown_format_payloads,
+                    earlier.host.unknown_format_payloads,
+                ),
+                rejected_payloads: host(
+                    self.host.rejected_payloads,
+                    earlier.host.rejected_payloads,
+                ),
+                lost_blocks: host(self.host.lost_blocks, earlier.host.lost_blocks),
+                paused_blocks: host(self.host.paused_blocks, earlier.host.paused_blocks),
+                pauses: host(self.host.pauses, earlier.host.pauses),
+                max_leader_size: self.host.max_leader_size,
+                max_trailer_size: self.host.max_trailer_size,
+                serviced_completions: host(
+                    self.host.serviced_completions,
+                    earlier.host.serviced_completions,
+                ),
+                throttled_submissions: host(
+                    self.host.throttled_submissions,
+                    earlier.host.throttled_submissions,
+                ),
+                starvation_events: host(
+                    self.host.starvation_events,
+                    earlier.host.starvation_events,
+                ),
+            },
+            device: DeviceStreamCounters {
+                payload_drop_count: device(
+                    self.device.payload_drop_count,
+                    earlier.device.payload_drop_count,
+                ),
+                link_error_count: device(
+                    self.device.link_error_count,
+                    earlier.device.link_error_count,
+                ),
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+struct StreamCounters {
+    received: AtomicU64,
+    incomplete: AtomicU64,
+    missing_segments: AtomicU64,
+    missing_bytes: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    unknown_format: AtomicU64,
+    rejected: AtomicU64,
+    lost_blocks: AtomicU64,
+    paused_blocks: AtomicU64,
+    pauses: AtomicU64,
+    max_leader_size: AtomicU64,
+    max_trailer_size: AtomicU64,
+    /// Counters of the receiver of the latest streaming, which counts payloads dropped by
+    /// [`FrameQueue`](crate::payload::FrameQueue).
+    receiver: Mutex<Option<Arc<ReceiverCounters>>>,
+}
+
+impl StreamCounters {
+    fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn add(counter: &AtomicU64, value: usize) {
+        counter.fetch_add(value as u64, Ordering::Relaxed);
+    }
+
+    fn observe_size(max: &AtomicU64, size: usize) {
+        max.fetch_max(size as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HostStreamStatistics {
+        let queue_dropped = self
+            .receiver
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map_or(0, |receiver| receiver.num_queue_dropped());
+        HostStreamStatistics {
+            received_payloads: self.received.load(Ordering::Relaxed),
+            incomplete_payloads: self.incomplete.load(Ordering::Relaxed),
+            missing_segments: self.missing_segments.load(Ordering::Relaxed),
+            missing_bytes: self.missing_bytes.load(Ordering::Relaxed),
+            failed_payloads: self.failed.load(Ordering::Relaxed),
+            dropped_payloads: self.dropped.load(Ordering::Relaxed) + queue_dropped,
+            unknown_format_payloads: self.unknown_format.load(Ordering::Relaxed),
+            rejected_payloads: self.rejected.load(Ordering::Relaxed),
+            lost_blocks: self.lost_blocks.load(Ordering::Relaxed),
+            paused_blocks: self.paused_blocks.load(Ordering::Relaxed),
+            pauses: self.pauses.load(Ordering::Relaxed),
+            max_leader_size: self.max_leader_size.load(Ordering::Relaxed),
+            max_trailer_size: self.max_trailer_size.load(Ordering::Relaxed),
+            ..HostStreamStatistics::default()
+        }
+    }
+}
+
+impl MetricSource for StreamCounters {
+    fn collect(&self, sink: &mut MetricSink<'_>) {
+        let stats = self.snapshot();
+        let counters = [
+            ("received_payloads", stats.received_payloads),
+            ("incomplete_payloads", stats.incomplete_payloads),
+            ("missing_segments", stats.missing_segments),
+            ("missing_bytes", stats.missing_bytes),
+            ("failed_payloads", stats.failed_payloads),
+            ("dropped_payloads", stats.dropped_payloads),
+            ("unknown_format_payloads", stats.unknown_format_payloads),
+            ("rejected_payloads", stats.rejected_payloads),
+            ("lost_blocks", stats.lost_blocks),
+            ("paused_blocks", stats.paused_blocks),
+            ("pauses", stats.pauses),
+        ];
+        for (name, value) in counters {
+            sink.counter(&format!("cameleon_stream_{}_total", name), value);
+        }
+        #[allow(clippy::cast_precision_loss)]
+        {
+            sink.gauge(
+                "cameleon_stream_max_leader_size_bytes",
+                stats.max_leader_size as f64,
+            );
+            sink.gauge(
+                "cameleon_stream_max_trailer_size_bytes",
+                stats.max_trailer_size as f64,
+            );
+        }
+    }
+}
+
+/// Registers the host side counters of the stream in [`metrics`].
+///
+/// The device has a single stream channel, so the stream index is always `0`.
+fn register_metrics(
+    counters: &Arc<StreamCounters>,
+    slot: &Arc<StreamSlot>,
+    buffer_pool: &Arc<PoolSlot>,
+    serial: &str,
+) {
+    metrics::register(counters, metrics::stream_labels(serial, 0));
+    metrics::register(slot, metrics::stream_labels(serial, 0));
+    metrics::register(buffer_pool, metrics::stream_labels(serial, 0));
+}
+
+/// Holds [`BufferPool`] of the stream across acquisitions.
+#[derive(Default)]
+struct PoolSlot(Mutex<Option<BufferPool>>);
+
+impl PoolSlot {
+    fn get(&self) -> MutexGuard<'_, Option<BufferPool>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the pool of `buffer_count` buffers of `buffer_len` bytes, the current pool is
+    /// reused if it matches.
+    fn prepare(&self, buffer_count: Option<NonZeroUsize>, buffer_len: usize) -> Option<BufferPool> {
+        let mut pool = self.get();
+        *pool = buffer_count.map(|count| match pool.take() {
+            Some(pool) if pool.capacity() == count.get() && pool.buffer_len() == buffer_len => pool,
+            _ => BufferPool::new(count.get(), buffer_len),
+        });
+        pool.clone()
+    }
+}
+
+impl MetricSource for PoolSlot {
+    fn collect(&self, sink: &mut MetricSink<'_>) {
+        let stats = match self.get().as_ref() {
+            Some(pool) => pool.statistics(),
+            None => return,
+        };
+        #[allow(clippy::cast_precision_loss)]
+        {
+            sink.gauge("cameleon_stream_pool_buffers", stats.capacity as f64);
+            sink.gauge("cameleon_stream_pool_buffers_in_use", stats.in_use as f64);
+            sink.gauge(
+                "cameleon_stream_pool_max_buffers_in_use",
+                stats.max_in_use as f64,
+            );
+        }
+        sink.counter("cameleon_stream_pool_exhausted_total", stats.exhausted);
+    }
+}
+
+/// Tracks block ids of the received payloads to count the missing ones.
+#[derive(Default)]
+struct BlockTracker {
+    last: Option<u64>,
+    /// `true` if the loop has been resumed since the last payload, the gap is intentional then.
+    resumed: bool,
+}
+
+impl BlockTracker {
+    fn observe(&mut self, block_id: u64, counters: &StreamCounters) {
+        // A block id going backwards means the device has restarted counting, there is no gap.
+        if let Some(missing) = self
+            .last
+            .and_then(|last| block_id.checked_sub(last))
+            .and_then(|diff| diff.checked_sub(1))
+        {
+            let counter = if self.resumed {
+                &counters.paused_blocks
+            } else {
+                &counters.lost_blocks
+            };
+            counter.fetch_add(missing, Ordering::Relaxed);
+        }
+        self.last = Some(block_id);
+        self.resumed = false;
+    }
+
+    fn resume(&mut self) {
+        self.resumed = true;
+    }
+}
+
+/// Headroom of the leader and trailer transfers raised by the streaming loop, see
+/// [`StreamParams::max_section_transfer_size`].
+#[derive(Debug, Default)]
+struct RenegotiatedHeadroom {
+    leader: AtomicUsize,
+    trailer: AtomicUsize,
+}
+
+impl RenegotiatedHeadroom {
+    fn new(quirks: &Quirks) -> Self {
+        Self {
+            leader: AtomicUsize::new(quirks.leader_headroom),
+            trailer: AtomicUsize::new(quirks.trailer_headroom),
+        }
+    }
+
+    fn store(&self, quirks: &Quirks) {
+        self.leader.store(quirks.leader_headroom, Ordering::Relaxed);
+        self.trailer
+            .store(quirks.trailer_headroom, Ordering::Relaxed);
+    }
+
+    fn write_back(&self, quirks: &mut Quirks) {
+        quirks.leader_headroom = self.leader.load(Ordering::Relaxed);
+        quirks.trailer_headroom = self.trailer.load(Ordering::Relaxed);
+    }
+}
+
+/// Pause state shared between [`StreamHandle`] and the streaming loop.
+#[derive(Default)]
+struct PauseControl {
+    state: Mutex<PauseState>,
+    changed: Condvar,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum PauseState {
+    #[default]
+    Running,
+    /// The loop parks once the pipe is drained.
+    Requested,
+    Paused,
+}
+
+impl PauseControl {
+    /// Requests the loop to pause, and waits until the loop parks.
+    ///
+    /// Returns `false` if the loop doesn't park within `timeout`, the request is withdrawn then.
+    fn pause(&self, timeout: Duration) -> bool {
+        let mut state = self.lock();
+        if *state == PauseState::Running {
+            *state = PauseState::Requested;
+        }
+        let (mut state, _) = self
+            .changed
+            .wait_timeout_while(state, timeout, |state| *state == PauseState::Requested)
+            .unwrap_or_else(PoisonError::into_inner);
+        if *state == PauseState::Requested {
+            *state = PauseState::Running;
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Lets the parked loop run, or withdraws the pending request.
+    fn resume(&self) {
+        *self.lock() = PauseState::Running;
+        self.changed.notify_all();
+    }
+
+    fn is_paused(&self) -> bool {
+        *self.lock() == PauseState::Paused
+    }
+
+    /// Parks the calling loop until resumed if pause is requested.
+    ///
+    /// Returns `true` if the loop has been parked.
+    fn park(&self) -> bool {
+        let mut state = self.lock();
+        if *state != PauseState::Requested {
+            return false;
+        }
+        *state = PauseState::Paused;
+        self.changed.notify_all();
+        let _state = self
+            .changed
+            .wait_while(state, |state| *state == PauseState::Paused)
+            .unwrap_or_else(PoisonError::into_inner);
+        true
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PauseState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Tracks payloads whose pixel format isn't modeled.
+///
+/// Such payloads are delivered as is, a warning is emitted only for the first payload of each
+/// format so that a stream of them doesn't flood the log.
+#[derive(Default)]
+struct UnknownFormats {
+    warned: HashSet<u32>,
+}
+
+impl UnknownFormats {
+    /// Returns `true` if the pixel format of `payload` isn't modeled.
+    fn observe(&mut self, payload: &Payload) -> bool {
+        let code = match payload.image_info().map(|info| info.pixel_format) {
+            Some(PixelFormat::Unknown(code)) => code,
+            _ => return false,
+        };
+        if self.warned.insert(code) {
+            warn!(
+                pixel_format = %format_args!("{:#010x}", code),
+                "received a payload of an unknown pixel format, register a decoder to convert it"
+            );
+        }
+        true
+    }
+}
+
+/// Receives the payload and trailer following `leader`, and assembles them into [`Payload`].
+///
+/// `payload_buf` is moved into the returned [`Payload`] only when it succeeds.
+#[allow(clippy::too_many_arguments)]
+fn receive_payload<P: BulkIn + ?Sized>(
+    pipe: &mut P,
+    params: &StreamParams,
+    counters: &StreamCounters,
+    leader: u3v_stream::Leader<'_>,
+    frame_id: FrameId,
+    payload_buf: &mut PayloadBuf,
+    scratch: &mut PayloadScratch,
+    trailer_buf: &mut Vec<u8>,
+) -> StreamResult<Payload> {
+    let read = read_payload(pipe, params, payload_buf, scratch)?;
+    let trailer = if read.overflowed {
+        warn!(
+            read_payload_size = read.len,
+            "payload is truncated, resynchronizing to the trailer"
+        );
+        resync_trailer(pipe, params, counters, trailer_buf)?
+    } else {
+        read_trailer(pipe, params, counters, trailer_buf)?
+    };
+
+    PayloadBuilder {
+        leader,
+        frame_id,
+        payload_buf: std::mem::take(payload_buf),
+        read,
+        trailer,
+        decoders: &params.decoders,
+    }
+    .build()
+}
+
+struct PayloadBuilder<'a> {
+    leader: u3v_stream::Leader<'a>,
+    frame_id: FrameId,
+    payload_buf: PayloadBuf,
+    read: &'a ReadPayload,
+    trailer: u3v_stream::Trailer<'a>,
+    decoders: &'a PixelDecoders,
+}
+
+impl<'a> PayloadBuilder<'a> {
+    /// Builds [`Payload`].
+    ///
+    /// A payload whose trailer reports an error status, whose transfers are missing, or which is
+    /// shorter than the size reported in the trailer, is delivered as an incomplete payload
+    /// instead of an error.
+    fn build(self) -> StreamResult<Payload> {
+        match self.status() {
+            PayloadStatus::Success => {}
+            PayloadStatus::Incomplete {
+                missing_bytes,
+                missing_segments,
+            } => warn!(
+                block_id = self.leader.block_id(),
+                missing_bytes, missing_segments, "transfers of the payload are missing"
+            ),
+            status => warn!(
+                block_id = self.leader.block_id(),
+                ?status,
+                "the device failed to send a part of the payload"
+            ),
+        }
+        if !self.read.overflowed && self.is_truncated() {
+            warn!(
+                block_id = self.leader.block_id(),
+                expected_payload_size = self.expected_payload_size(),
+                read_payload_size = self.read.len,
+                "the payload is shorter than the size specified in the trailer"
+            );
+        }
+
+        match self.leader.payload_type() {
+            u3v_stream::PayloadType::Image => self.build_image_payload(),
+            u3v_stream::PayloadType::ImageExtendedChunk => self.build_image_extended_payload(),
+            u3v_stream::PayloadType::Chunk => self.build_chunk_payload(),
+            u3v_stream::PayloadType::GenDc => self.build_gendc_payload(),
+            u3v_stream::PayloadType::Unknown(ty) => Err(StreamError::InvalidPayload(
+                format!("unknown payload type: {:#06x}", ty).into(),
+            )),
+        }
+    }
+
+    fn build_image_payload(self) -> StreamResult<Payload> {
+        let leader: u3v_stream::ImageLeader = self.specific_leader_as()?;
+        let trailer: u3v_stream::ImageTrailer = self.specific_trailer_as()?;
+
+        let id = self.leader.block_id();
+        let valid_payload_size = self.valid_payload_size();
+        let incomplete_info = self.incomplete_info();
+        let status = self.status();
+
+        let image_info = Some(ImageInfo {
+            width: leader.width() as usize,
+            height: trailer.actual_height() as usize,
+            x_offset: leader.x_offset() as usize,
+            y_offset: leader.y_offset() as usize,
+            pixel_format: leader.pixel_format(),
+            image_size: valid_payload_size,
+            x_padding: leader.x_padding() as usize,
+        });
+
+        let frame_id = self.frame_id(id);
+        let (payload, provided, pool, tracked) = self.payload_buf.into_storage();
+        Ok(Payload {
+            id,
+            frame_id,
+            payload_type: PayloadType::Image,
+            chunk_layout_id: None,
+            image_info,
+            payload,
+            provided,
+            valid_payload_size,
+            timestamp: leader.timestamp(),
+            incomplete_info,
+            status,
+            pool,
+            tracked,
+            decoders: Some(self.decoders.clone()),
+        })
+    }
+
+    fn build_image_extended_payload(self) -> StreamResult<Payload> {
+        let leader: u3v_stream::ImageExtendedChunkLeader = self.specific_leader_as()?;
+        let trailer: u3v_stream::ImageExtendedChunkTrailer = self.specific_trailer_as()?;
+
+        let id = self.leader.block_id();
+        let valid_payload_size = self.valid_payload_size();
+        let incomplete_info = self.incomplete_info();
+        let status = self.status();
+
+        // The image is the first chunk of the payload, which is decoded last.
+        let image_size = if self.is_truncated() {
+            // Chunks are lost together with the tail of the payload, all the received bytes are
+            // regarded as an image.
+            valid_payload_size
+        } else {
+            let mut image_size = 0;
+            for chunk in ChunkIter::new(&self.payload_buf.as_slice()[..valid_payload_size]) {
+                image_size = chunk?.data.len();
+            }
+            image_size
+        };
+
+        let image_info = Some(ImageInfo {
+            width: leader.width() as usize,
+            height: trailer.actual_height() as usize,
+            x_offset: leader.x_offset() as usize,
+            y_offset: leader.y_offset() as usize,
+            pixel_format: leader.pixel_format(),
+            image_size,
+            x_padding: leader.x_padding() as usize,
+        });
+
+        let frame_id = self.frame_id(id);
+        let (payload, provided, pool, tracked) = self.payload_buf.into_storage();
+        Ok(Payload {
+            id,
+            frame_id,
+            payload_type: PayloadType::ImageExtendedChunk,
+            chunk_layout_id: Some(trailer.chunk_layout_id()),
+            image_info,
+            payload,
+            provided,
+            valid_payload_size,
+            timestamp: leader.timestamp(),
+            incomplete_info,
+            status,
+            pool,
+            tracked,
+            decoders: Some(self.decoders.clone()),
+        })
+    }
+
+    fn build_chunk_payload(self) -> StreamResult<Payload> {
+        let leader: u3v_stream::ChunkLeader = self.specific_leader_as()?;
+        let trailer: u3v_stream::ChunkTrailer = self.specific_trailer_as()?;
+
+        let id = self.leader.block_id();
+        let valid_payload_size = self.valid_payload_size();
+        let incomplete_info = self.incomplete_info();
+        let status = self.status();
+
+        let frame_id = self.frame_id(id);
+        let (payload, provided, pool, tracked) = self.payload_buf.into_storage();
+        Ok(Payload {
+            id,
+            frame_id,
+            payload_type: PayloadType::Chunk,
+            chunk_layout_id: Some(trailer.chunk_layout_id()),
+            image_info: None,
+            payload,
+            provided,
+            valid_payload_size,
+            timestamp: leader.timestamp(),
+            incomplete_info,
+            status,
+            pool,
+            tracked,
+            decoders: Some(self.decoders.clone()),
+        })
+    }
+
+    fn build_gendc_payload(self) -> StreamResult<Payload> {
+        let leader: u3v_stream::GenDcLeader = self.specific_leader_as()?;
+
+        let id = self.leader.block_id();
+        let valid_payload_size = self.valid_payload_size();
+        let incomplete_info = self.incomplete_info();
+        let status = self.status();
+
+        let frame_id = self.frame_id(id);
+        let (payload, provided, pool, tracked) = self.payload_buf.into_storage();
+        Ok(Payload {
+            id,
+            frame_id,
+            payload_type: PayloadType::GenDc,
+            chunk_layout_id: None,
             image_info: None,
-            payload: self.payload_buf,
+            payload,
+            provided,
             valid_payload_size,
             timestamp: leader.timestamp(),
+            incomplete_info,
+            status,
+            pool,
+            tracked,
+            decoders: Some(self.decoders.clone()),
+        })
+    }
+
+    /// Returns the size of valid payload data, which is truncated to the received bytes when the
+    /// payload is incomplete.
+    fn valid_payload_size(&self) -> usize {
+        let valid_payload_size = self.expected_payload_size();
+        if self.is_truncated() {
+            valid_payload_size.min(self.read.len)
+        } else {
+            valid_payload_size
+        }
+    }
+
+    /// Returns `true` if a part of the payload is lost, i.e. the device sent more data than the
+    /// buffer can hold, or less data than the size reported in the trailer.
+    fn is_truncated(&self) -> bool {
+        self.read.overflowed || self.expected_payload_size() > self.read.len
+    }
+
+    /// Returns [`PayloadStatus::Incomplete`] if transfers are missing, otherwise the status
+    /// reported in the trailer.
+    fn status(&self) -> PayloadStatus {
+        let (missing_bytes, missing_segments) = self.read.missing(self.expected_payload_size());
+        if missing_segments != 0 {
+            return PayloadStatus::Incomplete {
+                missing_bytes,
+                missing_segments,
+            };
+        }
+
+        match self.trailer.payload_status() {
+            u3v_stream::PayloadStatus::Success => PayloadStatus::Success,
+            u3v_stream::PayloadStatus::DataDiscarded => PayloadStatus::DataDiscarded,
+            u3v_stream::PayloadStatus::DataOverrun => PayloadStatus::DataOverrun,
+        }
+    }
+
+    /// Returns the payload size reported in the trailer, which is saturated to `usize::MAX` if it
+    /// exceeds the address space of the host.
+    fn expected_payload_size(&self) -> usize {
+        usize::try_from(self.trailer.valid_payload_size()).unwrap_or(usize::MAX)
+    }
+
+    fn incomplete_info(&self) -> Option<IncompleteInfo> {
+        self.is_truncated().then(|| IncompleteInfo {
+            expected_size: self.expected_payload_size(),
+            received_size: self.read.len,
         })
     }
 
+    fn frame_id(&self, block_id: u64) -> FrameId {
+        let acq = self.frame_id;
+        FrameId::new(
+            acq.device_id(),
+            acq.stream_index(),
+            acq.generation(),
+            block_id,
+        )
+    }
+
     fn specific_leader_as<T: u3v_stream::SpecificLeader>(&self) -> StreamResult<T> {
         self.leader
             .specific_leader_as()
@@ -440,12 +1698,12 @@ impl<'a> PayloadBuilder<'a> {
 /// Parameters to receive stream packets.
 ///
 /// Both [`StreamHandle`] doesn't check the integrity of the parameters. That's up to user.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct StreamParams {
-    /// Maximum leader size.
+    /// Maximum leader size reported by the device.
     pub leader_size: usize,
 
-    /// Maximum trailer size.
+    /// Maximum trailer size reported by the device.
     pub trailer_size: usize,
 
     /// Payload transfer size.
@@ -462,6 +1720,83 @@ pub struct StreamParams {
 
     /// Timeout duration of each transaction between device.
     pub timeout: Duration,
+
+    /// Configuration of the thread which receives stream packets.
+    ///
+    /// This value is kept when the other parameters are rebuilt from the device at the start of
+    /// streaming.
+    pub thread: ThreadConfig,
+
+    /// Stages executed in order on each received payload before it's delivered.
+    ///
+    /// The stages are cloned into the streaming loop when streaming is started, so changes take
+    /// effect from the next start. This value is kept when the other parameters are rebuilt from
+    /// the device at the start of streaming.
+    pub pipeline: Vec<Box<dyn PipelineStage>>,
+
+    /// Provider of the buffers which receive payload data directly, see
+    /// [`StreamParams::deliver_into`].
+    ///
+    /// This value is kept when the other parameters are rebuilt from the device at the start of
+    /// streaming.
+    pub buffer_provider: Option<Arc<dyn BufferProvider>>,
+
+    /// Decoders of the pixel formats which aren't modeled by [`PixelFormat`], which are used by
+    /// [`Payload::to_image`] of the payloads received by this stream.
+    ///
+    /// Decoders can be registered while streaming, the decoders are shared with the streaming
+    /// loop. This value is kept when the other parameters are rebuilt from the device at the
+    /// start of streaming.
+    pub decoders: PixelDecoders,
+
+    /// Workarounds for the device, the headroom is added to the leader and trailer sizes.
+    ///
+    /// This value is kept when the other parameters are rebuilt from the device at the start of
+    /// streaming.
+    pub quirks: Quirks,
+
+    /// The maximum number of payload transfers outstanding at once. Each transfer receives
+    /// [`Self::payload_size`] bytes, and the next transfer is submitted as each one completes.
+    ///
+    /// All transfers of a payload are submitted at once if `None`. Fewer transfers pin less
+    /// memory in the host controller, while more transfers let the device send data without
+    /// waiting for the host.
+    ///
+    /// This value is kept when the other parameters are rebuilt from the device at the start of
+    /// streaming.
+    pub num_transfers: Option<NonZeroUsize>,
+
+    /// The number of payload buffers pre-allocated in [`BufferPool`] at the start of streaming,
+    /// each of which has [`Self::maximum_payload_size`] bytes.
+    ///
+    /// A buffer is returned to the pool when the payload holding it is dropped. If `None`, a
+    /// buffer is allocated for each payload unless it's sent back by
+    /// [`PayloadReceiver::send_back`].
+    ///
+    /// This value is kept when the other parameters are rebuilt from the device at the start of
+    /// streaming.
+    ///
+    /// [`PayloadReceiver::send_back`]: crate::payload::PayloadReceiver::send_back
+    pub buffer_count: Option<NonZeroUsize>,
+
+    /// Behavior when all buffers of [`BufferPool`] are in use.
+    ///
+    /// This value is kept when the other parameters are rebuilt from the device at the start of
+    /// streaming.
+    pub overflow_policy: OverflowPolicy,
+
+    /// Upper bound of the leader and trailer transfer sizes renegotiated by the streaming loop.
+    ///
+    /// When the device sends a leader or trailer larger than the transfer size, the section is
+    /// lost and the streaming loop raises [`Quirks::leader_headroom`] or
+    /// [`Quirks::trailer_headroom`] so that the following sections fit in the transfer, as long
+    /// as the section size doesn't exceed this value. Otherwise
+    /// [`StreamError::SectionOverflow`] is sent to the receiver. The raised headroom is written
+    /// back to [`Self::quirks`] of [`StreamHandle::params`] when the loop stops.
+    ///
+    /// This value is kept when the other parameters are rebuilt from the device at the start of
+    /// streaming.
+    pub max_section_transfer_size: usize,
 }
 
 impl StreamParams {
@@ -471,39 +1806,142 @@ impl StreamParams {
     pub fn maximum_payload_size(&self) -> usize {
         self.payload_size * self.payload_count + self.payload_final1_size + self.payload_final2_size
     }
-}
 
-impl StreamParams {
-    /// Construct `StreamParams`.
+    /// Returns the size of leader transfers, which is the maximum leader size plus
+    /// [`Quirks::leader_headroom`].
     #[must_use]
-    pub fn new(
-        leader_size: usize,
-        trailer_size: usize,
-        payload_size: usize,
-        payload_count: usize,
-        payload_final1_size: usize,
-        payload_final2_size: usize,
-        timeout: Duration,
-    ) -> Self {
-        Self {
-            leader_size,
-            trailer_size,
-            payload_size,
-            payload_count,
+    pub fn leader_transfer_size(&self) -> usize {
+        self.leader_size + self.quirks.leader_headroom
+    }
+
+    /// Returns the size of trailer transfers, which is the maximum trailer size plus
+    /// [`Quirks::trailer_headroom`].
+    #[must_use]
+    pub fn trailer_transfer_size(&self) -> usize {
+        self.trailer_size + self.quirks.trailer_headroom
+    }
+
+    /// Raises the headroom of the section which overflowed with `err`, so that the section of
+    /// the size fits in the transfer. Returns `false` if `err` isn't
+    /// [`StreamError::SectionOverflow`] or the section exceeds
+    /// [`Self::max_section_transfer_size`].
+    fn renegotiate(&mut self, err: &StreamError) -> bool {
+        let (section, reported_size, transfer_size, actual_size) = match *err {
+            StreamError::SectionOverflow {
+                section,
+                reported_size,
+                transfer_size,
+                actual_size,
+            } => (section, reported_size, transfer_size, actual_size),
+            _ => return false,
+        };
+        if actual_size > self.max_section_transfer_size {
+            error!(
+                section,
+                actual_size,
+                max_section_transfer_size = self.max_section_transfer_size,
+                "section exceeds the maximum transfer size, the transfer size isn't renegotiated"
+            );
+            return false;
+        }
+
+        let headroom = actual_size.saturating_sub(reported_size);
+        if section == "leader" {
+            self.quirks.leader_headroom = headroom;
+        } else {
+            self.quirks.trailer_headroom = headroom;
+        }
+        warn!(
+            section,
+            actual_size,
+            expected_size = transfer_size,
+            "section exceeds the transfer size, renegotiate the transfer size to {} bytes",
+            actual_size
+        );
+        true
+    }
+
+    /// Applies the stream related knobs of `options`, which are kept when the other parameters
+    /// are rebuilt from the device.
+    fn apply_open_options(&mut self, options: &OpenOptions) {
+        self.thread = options.stream_thread.clone();
+        self.quirks = options.quirks;
+    }
+}
+
+impl Default for StreamParams {
+    fn default() -> Self {
+        Self::new(0, 0, 0, 0, 0, 0, Duration::default())
+    }
+}
+
+impl StreamParams {
+    /// Construct `StreamParams`.
+    #[must_use]
+    pub fn new(
+        leader_size: usize,
+        trailer_size: usize,
+        payload_size: usize,
+        payload_count: usize,
+        payload_final1_size: usize,
+        payload_final2_size: usize,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            leader_size,
+            trailer_size,
+            payload_size,
+            payload_count,
             payload_final1_size,
             payload_final2_size,
             timeout,
+            thread: ThreadConfig::default(),
+            pipeline: vec![],
+            buffer_provider: None,
+            decoders: PixelDecoders::default(),
+            quirks: Quirks::default(),
+            num_transfers: None,
+            buffer_count: None,
+            overflow_policy: OverflowPolicy::default(),
+            max_section_transfer_size: DEFAULT_MAX_SECTION_TRANSFER_SIZE,
         }
     }
 
+    /// Lets the streaming loop receive payload data directly into the buffers provided by
+    /// `provider`, instead of the buffers of the internal pool.
+    ///
+    /// Leaders and trailers are still received into internal buffers. A payload received into a
+    /// provided buffer isn't recycled by [`PayloadReceiver::send_back`], the buffer is dropped
+    /// together with the last clone of the payload instead. [`Self::pipeline`] must be empty,
+    /// otherwise starting the streaming loop fails with [`StreamError::NotSupported`].
+    ///
+    /// [`PayloadReceiver::send_back`]: crate::payload::PayloadReceiver::send_back
+    pub fn deliver_into<P: BufferProvider + 'static>(&mut self, provider: P) -> &mut Self {
+        self.buffer_provider = Some(Arc::new(provider));
+        self
+    }
+
     /// Build `StreamParams` from [`DeviceControl`].
+    ///
+    /// The payload transfer sizes are the ones in use by the device, which may be the device
+    /// preferred sizes if it rejected the sizes written by [`DeviceControl::enable_streaming`].
     pub fn from_control<Ctrl: DeviceControl + ?Sized>(ctrl: &mut Ctrl) -> ControlResult<Self> {
         let abrm = Abrm::new(ctrl)?;
-        let sirm = abrm.sbrm(ctrl)?.sirm(ctrl)?.ok_or_else(|| {
+        let sirm = abrm.sbrm(ctrl)?.sirm().ok_or_else(|| {
             let msg = "the U3V device doesn't have `SIRM`";
             error!(msg);
             ControlError::InvalidDevice(msg.into())
         })?;
+        Self::from_sirm(ctrl, &sirm, abrm.maximum_device_response_time())
+    }
+
+    /// Build `StreamParams` from the registers of [`Sirm`], `timeout` is used as the timeout of
+    /// each transfer.
+    pub fn from_sirm<Ctrl: DeviceControl + ?Sized>(
+        ctrl: &mut Ctrl,
+        sirm: &Sirm,
+        timeout: Duration,
+    ) -> ControlResult<Self> {
         let leader_size = sirm.maximum_leader_size(ctrl)? as usize;
         let trailer_size = sirm.maximum_trailer_size(ctrl)? as usize;
 
@@ -511,7 +1949,6 @@ impl StreamParams {
         let payload_count = sirm.payload_transfer_count(ctrl)? as usize;
         let payload_final1_size = sirm.payload_final_transfer1_size(ctrl)? as usize;
         let payload_final2_size = sirm.payload_final_transfer2_size(ctrl)? as usize;
-        let timeout = abrm.maximum_device_response_time(ctrl)?;
 
         Ok(Self::new(
             leader_size,
@@ -525,73 +1962,1620 @@ impl StreamParams {
     }
 }
 
-fn read_leader<'a>(
-    inner: &mut MutexGuard<'_, u3v::ReceiveChannel>,
+/// Default of [`StreamParams::max_section_transfer_size`].
+const DEFAULT_MAX_SECTION_TRANSFER_SIZE: usize = 4096;
+
+/// Magic of the leader header.
+const LEADER_MAGIC: u32 = 0x4C56_3355;
+
+/// Magic of the trailer header.
+const TRAILER_MAGIC: u32 = 0x5456_3355;
+
+/// Maximum number of transfers skipped while resynchronizing to the trailer.
+const MAX_RESYNC_TRANSFERS: usize = 16;
+
+/// Buffers reused by [`read_payload`] across payloads, so that receiving a payload doesn't
+/// allocate.
+#[derive(Default)]
+struct PayloadScratch {
+    ranges: Vec<Range<usize>>,
+    completions: Vec<Completion>,
+    /// Receives the tail of a payload shifted by a zero-length packet.
+    spare: Vec<u8>,
+    read: ReadPayload,
+}
+
+/// Result of reading a payload.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ReadPayload {
+    /// Number of bytes read into the buffer.
+    len: usize,
+    /// `true` if the device sent more data than the buffer can hold.
+    overflowed: bool,
+    /// Transfers of the payload in the order in which the device sends them.
+    segments: Vec<Segment>,
+}
+
+/// A transfer of the payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Segment {
+    /// Size of the transfer derived from `SIRM`.
+    size: usize,
+    /// Number of bytes received by the transfer.
+    received: usize,
+}
+
+impl ReadPayload {
+    /// Returns the number of bytes and transfers missing from a payload of `expected_size`
+    /// bytes.
+    ///
+    /// A transfer is missing if it received fewer bytes than the part of the payload it carries,
+    /// so a short final transfer of a payload smaller than the transfers isn't missing.
+    fn missing(&self, expected_size: usize) -> (usize, usize) {
+        let mut offset = 0;
+        let mut missing_bytes = 0;
+        let mut missing_segments = 0;
+        for segment in &self.segments {
+            let expected = segment.size.min(expected_size.saturating_sub(offset));
+            if segment.received < expected {
+                missing_bytes += expected - segment.received;
+                missing_segments += 1;
+            }
+            offset += segment.size;
+        }
+        (missing_bytes, missing_segments)
+    }
+}
+
+fn read_leader<'a, P: BulkIn + ?Sized>(
+    pipe: &mut P,
     params: &StreamParams,
-    buf: &'a mut [u8],
+    counters: &StreamCounters,
+    buf: &'a mut Vec<u8>,
 ) -> StreamResult<u3v_stream::Leader<'a>> {
-    let leader_size = params.leader_size;
-    recv(inner, params, buf, leader_size)?;
+    let transfer_size = params.leader_transfer_size();
+    let completion = recv_section(pipe, params, buf, transfer_size)?;
+    // The transfer completes without overflow if its size is a multiple of the maximum packet
+    // size, so the size in the header is checked as well.
+    let actual_size = section_size(&buf[..completion.len], LEADER_MAGIC);
+    if let Some(actual_size) = actual_size {
+        StreamCounters::observe_size(&counters.max_leader_size, actual_size);
+    }
+    if completion.overflowed || matches!(actual_size, Some(size) if size > transfer_size) {
+        return Err(section_overflow(
+            "leader",
+            params.leader_size,
+            transfer_size,
+            actual_size,
+        ));
+    }
 
-    u3v_stream::Leader::parse(buf).map_err(|e| StreamError::InvalidPayload(format!("{}", e).into()))
+    u3v_stream::Leader::parse(&buf[..completion.len])
+        .map_err(|e| StreamError::InvalidPayload(format!("{}", e).into()))
 }
 
-fn read_payload(
-    inner: &mut MutexGuard<'_, u3v::ReceiveChannel>,
+/// Reads payload into `buf` and returns the number of bytes read.
+///
+/// The transfers write directly into `buf`, so the payload isn't copied on the way to
+/// [`Payload`] unless a transfer ends with a short or zero-length packet. The data received after
+/// such a transfer is then moved towards the head of `buf`, which copies it within `buf`.
+fn read_payload<'s, P: BulkIn + ?Sized, B: TransferBuf>(
+    pipe: &mut P,
     params: &StreamParams,
-    buf: &mut [u8],
-) -> StreamResult<usize> {
-    let payload_size = params.payload_size;
-    let mut async_pool = AsyncPool::new(inner);
-    let mut cursor = 0;
-    for _ in 0..params.payload_count {
-        async_pool.submit(&mut buf[cursor..cursor + payload_size])?;
-        cursor += payload_size;
+    buf: &mut B,
+    scratch: &'s mut PayloadScratch,
+) -> StreamResult<&'s ReadPayload> {
+    let PayloadScratch {
+        ranges,
+        completions,
+        spare,
+        read,
+    } = scratch;
+    read.len = 0;
+    read.overflowed = false;
+    read.segments.clear();
+    let final_sizes = [params.payload_final1_size, params.payload_final2_size];
+    let sizes = (0..params.payload_count)
+        .map(|_| params.payload_size)
+        .chain(final_sizes.iter().copied().filter(|size| *size != 0));
+    read.segments
+        .extend(sizes.map(|size| Segment { size, received: 0 }));
+    let total_size: usize = read.segments.iter().map(|segment| segment.size).sum();
+    if buf.as_mut_slice().len() < total_size {
+        return Err(StreamError::BufferTooSmall);
     }
 
-    if params.payload_final1_size != 0 {
-        async_pool.submit(&mut buf[cursor..cursor + params.payload_final1_size])?;
-        cursor += params.payload_final1_size;
+    ranges.clear();
+    let mut start = 0;
+    for segment in &read.segments {
+        ranges.push(start..start + segment.size);
+        start += segment.size;
     }
-    if params.payload_final2_size != 0 {
-        async_pool.submit(&mut buf[cursor..cursor + params.payload_final2_size])?;
+    pipe.read_all(
+        buf,
+        ranges,
+        params.timeout,
+        params.num_transfers,
+        completions,
+    )?;
+    let buf = buf.as_mut_slice();
+
+    // Pack the received bytes, each transfer may end with a short packet.
+    let mut offset = 0;
+    for (segment, completion) in read.segments.iter_mut().zip(completions.iter()) {
+        if offset != read.len {
+            buf.copy_within(offset..offset + completion.len, read.len);
+        }
+        read.len += completion.len;
+        offset += segment.size;
+        read.overflowed |= completion.overflowed;
+        segment.received = completion.len;
     }
 
-    let mut read_len = 0;
-    while !async_pool.is_empty() {
-        read_len += async_pool.poll(params.timeout)?;
+    // A zero-length packet terminating the previous section is read by the first transfer, the
+    // tail of the payload is still left in the device in that case.
+    let is_shifted = matches!(completions.first(), Some(c) if c.len == 0)
+        && matches!(
+            (read.segments.last(), completions.last()),
+            (Some(segment), Some(c)) if c.len == segment.size
+        );
+    if is_shifted {
+        debug!("zero-length packet is received at the head of the payload");
+        let spare_size = read.segments[0].size;
+        spare.resize(spare_size, 0);
+        let completion = pipe.read(spare, 0..spare_size, params.timeout)?;
+        let room = buf.len() - read.len;
+        let copy_len = completion.len.min(room);
+        buf[read.len..read.len + copy_len].copy_from_slice(&spare[..copy_len]);
+        read.len += copy_len;
+        read.overflowed |= completion.overflowed || completion.len > room;
+        // The transfers carry the payload from the second one, so the packed bytes are the head
+        // of the payload without a gap.
+        let mut rest = read.len;
+        for segment in &mut read.segments {
+            segment.received = segment.size.min(rest);
+            rest -= segment.received;
+        }
     }
 
-    Ok(read_len)
+    Ok(read)
 }
 
-fn read_trailer<'a>(
-    inner: &mut MutexGuard<'_, u3v::ReceiveChannel>,
+fn read_trailer<'a, P: BulkIn + ?Sized>(
+    pipe: &mut P,
     params: &StreamParams,
-    buf: &'a mut [u8],
+    counters: &StreamCounters,
+    buf: &'a mut Vec<u8>,
 ) -> StreamResult<u3v_stream::Trailer<'a>> {
-    let trailer_size = params.trailer_size as usize;
-    recv(inner, params, buf, trailer_size)?;
+    let transfer_size = params.trailer_transfer_size();
+    let completion = recv_section(pipe, params, buf, transfer_size)?;
+    let actual_size = section_size(&buf[..completion.len], TRAILER_MAGIC);
+    if let Some(actual_size) = actual_size {
+        StreamCounters::observe_size(&counters.max_trailer_size, actual_size);
+    }
+    if completion.overflowed || matches!(actual_size, Some(size) if size > transfer_size) {
+        return Err(section_overflow(
+            "trailer",
+            params.trailer_size,
+            transfer_size,
+            actual_size,
+        ));
+    }
 
-    u3v_stream::Trailer::parse(buf)
+    u3v_stream::Trailer::parse(&buf[..completion.len])
         .map_err(|e| StreamError::InvalidPayload(format!("invalid trailer: {}", e).into()))
 }
 
-fn recv(
-    inner: &mut MutexGuard<'_, u3v::ReceiveChannel>,
+/// Returns the section size written in the header of a leader or trailer.
+fn section_size(buf: &[u8], magic: u32) -> Option<usize> {
+    if buf.len() < 8 || buf[..4] != magic.to_le_bytes() {
+        return None;
+    }
+    Some(u16::from_le_bytes([buf[6], buf[7]]).into())
+}
+
+/// Returns an error for a leader or trailer which doesn't fit in the transfer.
+///
+/// A diagnostic error is returned if the size is known from the header.
+fn section_overflow(
+    section: &'static str,
+    reported_size: usize,
+    transfer_size: usize,
+    actual_size: Option<usize>,
+) -> StreamError {
+    match actual_size {
+        Some(actual_size) if actual_size > transfer_size => {
+            let err = StreamError::SectionOverflow {
+                section,
+                reported_size,
+                transfer_size,
+                actual_size,
+            };
+            warn!(%err);
+            err
+        }
+        _ => StreamError::InvalidPayload(
+            format!(
+                "{} is larger than the transfer size {}",
+                section, transfer_size
+            )
+            .into(),
+        ),
+    }
+}
+
+/// Skips the rest of a truncated payload until the trailer is found.
+fn resync_trailer<'a, P: BulkIn + ?Sized>(
+    pipe: &mut P,
     params: &StreamParams,
-    buf: &mut [u8],
+    counters: &StreamCounters,
+    buf: &'a mut Vec<u8>,
+) -> StreamResult<u3v_stream::Trailer<'a>> {
+    let trailer_size = params.trailer_transfer_size();
+    let mut skipped = 0;
+    let mut trailer_len = None;
+    for _ in 0..MAX_RESYNC_TRANSFERS {
+        let completion = recv(pipe, params, buf, trailer_size)?;
+        if !completion.overflowed && u3v_stream::Trailer::parse(&buf[..completion.len]).is_ok() {
+            trailer_len = Some(completion.len);
+            break;
+        }
+        skipped += completion.len;
+    }
+
+    let trailer_len = trailer_len.ok_or_else(|| {
+        StreamError::InvalidPayload(
+            format!("trailer is not found after skipping {} bytes", skipped).into(),
+        )
+    })?;
+    debug!(skipped, "resynchronized to the trailer");
+    let trailer = u3v_stream::Trailer::parse(&buf[..trailer_len])
+        .map_err(|e| StreamError::InvalidPayload(format!("invalid trailer: {}", e).into()))?;
+    StreamCounters::observe_size(&counters.max_trailer_size, trailer.trailer_size().into());
+    Ok(trailer)
+}
+
+/// Receives a leader or trailer.
+///
+/// A zero-length packet terminating the previous section is consumed silently.
+fn recv_section<P: BulkIn + ?Sized>(
+    pipe: &mut P,
+    params: &StreamParams,
+    buf: &mut Vec<u8>,
     len: usize,
-) -> StreamResult<usize> {
+) -> StreamResult<Completion> {
+    let completion = recv(pipe, params, buf, len)?;
+    if completion.len == 0 && len != 0 && !completion.overflowed {
+        debug!("zero-length packet is received at the head of the section");
+        recv(pipe, params, buf, len)
+    } else {
+        Ok(completion)
+    }
+}
+
+fn recv<P: BulkIn + ?Sized>(
+    pipe: &mut P,
+    params: &StreamParams,
+    buf: &mut Vec<u8>,
+    len: usize,
+) -> StreamResult<Completion> {
     if len == 0 {
-        return Ok(0);
+        return Ok(Completion {
+            len: 0,
+            overflowed: false,
+        });
     }
 
     if buf.len() < len {
         return Err(StreamError::BufferTooSmall);
     }
 
-    inner
-        .recv(&mut buf[..len], params.timeout)
-        .map_err(|e| e.into())
+    pipe.read(buf, 0..len, params.timeout)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, ops::Range, sync::atomic::AtomicBool};
+
+    use cameleon_device::{
+        fixture::{Fixture, StreamSettings},
+        u3v::protocol::ack,
+    };
+
+    use crate::{
+        metrics::MetricValue,
+        payload::{Image, ImageInfo},
+        u3v::control_handle::{negotiate_payload_transfer, StatusError},
+    };
+
+    #[cfg(feature = "emulator")]
+    use cameleon_impl::leak_check;
+
+    #[cfg(feature = "emulator")]
+    use crate::u3v::emulated::{open_emulated, open_emulated_stream};
+
+    use super::*;
+
+    const MAX_PACKET_SIZE: usize = 64;
+
+    /// Bulk-in endpoint of a fake device which sends sections of stream packets.
+    struct FakeDevice {
+        sections: VecDeque<Vec<u8>>,
+        /// Sends a zero-length packet after each section whose size is an exact multiple of
+        /// [`MAX_PACKET_SIZE`].
+        emit_zlp: bool,
+    }
+
+    impl FakeDevice {
+        fn new(emit_zlp: bool) -> Self {
+            Self {
+                sections: VecDeque::new(),
+                emit_zlp,
+            }
+        }
+
+        fn send_image(&mut self, block_id: u64, width: u32, height: u32, section_size: usize) {
+            self.send_image_in(block_id, width, height, section_size, PixelFormat::Mono8);
+        }
+
+        fn send_image_in(
+            &mut self,
+            block_id: u64,
+            width: u32,
+            height: u32,
+            section_size: usize,
+            pixel_format: PixelFormat,
+        ) {
+            let image: Vec<u8> = (0..width * height).map(|i| (i % 251) as u8).collect();
+            // Payload type, Image.
+            let leader = Self::leader(block_id, 0x0001, width, height, section_size, pixel_format);
+
+            let mut trailer = vec![];
+            trailer.extend_from_slice(&0x5456_3355_u32.to_le_bytes());
+            trailer.extend_from_slice(&0_u16.to_le_bytes());
+            trailer.extend_from_slice(&32_u16.to_le_bytes());
+            trailer.extend_from_slice(&block_id.to_le_bytes());
+            // Payload status, Success.
+            trailer.extend_from_slice(&0_u16.to_le_bytes());
+            trailer.extend_from_slice(&0_u16.to_le_bytes());
+            trailer.extend_from_slice(&(image.len() as u64).to_le_bytes());
+            trailer.extend_from_slice(&height.to_le_bytes());
+            trailer.resize(section_size.max(trailer.len()), 0);
+
+            self.send(leader);
+            self.send(image);
+            self.send(trailer);
+        }
+
+        /// Sends an image followed by a chunk whose size field is `chunk_size`.
+        fn send_chunk_image(&mut self, block_id: u64, width: u32, height: u32, chunk_size: u32) {
+            let mut payload: Vec<u8> = (0..width * height).map(|i| (i % 251) as u8).collect();
+            // Image chunk id and size.
+            payload.extend_from_slice(&1_u32.to_be_bytes());
+            payload.extend_from_slice(&chunk_size.to_be_bytes());
+            // Payload type, ImageExtendedChunk.
+            let leader = Self::leader(block_id, 0x4001, width, height, 0, PixelFormat::Mono8);
+
+            let mut trailer = vec![];
+            trailer.extend_from_slice(&0x5456_3355_u32.to_le_bytes());
+            trailer.extend_from_slice(&0_u16.to_le_bytes());
+            trailer.extend_from_slice(&36_u16.to_le_bytes());
+            trailer.extend_from_slice(&block_id.to_le_bytes());
+            // Payload status, Success.
+            trailer.extend_from_slice(&0_u16.to_le_bytes());
+            trailer.extend_from_slice(&0_u16.to_le_bytes());
+            trailer.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+            trailer.extend_from_slice(&height.to_le_bytes());
+            // Chunk layout id.
+            trailer.extend_from_slice(&0_u32.to_le_bytes());
+
+            self.send(leader);
+            self.send(payload);
+            self.send(trailer);
+        }
+
+        fn leader(
+            block_id: u64,
+            payload_type: u16,
+            width: u32,
+            height: u32,
+            section_size: usize,
+            pixel_format: PixelFormat,
+        ) -> Vec<u8> {
+            let mut leader = vec![];
+            leader.extend_from_slice(&0x4C56_3355_u32.to_le_bytes());
+            leader.extend_from_slice(&0_u16.to_le_bytes());
+            leader.extend_from_slice(&52_u16.to_le_bytes());
+            leader.extend_from_slice(&block_id.to_le_bytes());
+            leader.extend_from_slice(&0_u16.to_le_bytes());
+            leader.extend_from_slice(&payload_type.to_le_bytes());
+            leader.extend_from_slice(&100_u64.to_le_bytes());
+            leader.extend_from_slice(&u32::from(pixel_format).to_le_bytes());
+            leader.extend_from_slice(&width.to_le_bytes());
+            leader.extend_from_slice(&height.to_le_bytes());
+            leader.extend_from_slice(&[0; 12]);
+            leader.resize(section_size.max(leader.len()), 0);
+            leader
+        }
+
+        fn send(&mut self, section: Vec<u8>) {
+            let is_exact_multiple =
+                section.len() / MAX_PACKET_SIZE * MAX_PACKET_SIZE == section.len();
+            self.sections.push_back(section);
+            if self.emit_zlp && is_exact_multiple {
+                self.sections.push_back(vec![]);
+            }
+        }
+    }
+
+    impl BulkIn for FakeDevice {
+        fn read<B: TransferBuf>(
+            &mut self,
+            buf: &mut B,
+            range: Range<usize>,
+            _timeout: Duration,
+        ) -> StreamResult<Completion> {
+            let buf = &mut buf.as_mut_slice()[range];
+            let section = self.sections.front_mut().ok_or(StreamError::Timeout)?;
+            if section.len() <= buf.len() {
+                let len = section.len();
+                buf[..len].copy_from_slice(section);
+                self.sections.pop_front();
+                // A zero-length packet terminates the transfer if the buffer isn't filled.
+                if len < buf.len() && matches!(self.sections.front(), Some(zlp) if zlp.is_empty()) {
+                    self.sections.pop_front();
+                }
+                Ok(Completion {
+                    len,
+                    overflowed: false,
+                })
+            } else {
+                // The rest of the packet which doesn't fit in the buffer is discarded.
+                let len = buf.len();
+                let mut consumed = len / MAX_PACKET_SIZE * MAX_PACKET_SIZE;
+                if consumed != len {
+                    consumed = (consumed + MAX_PACKET_SIZE).min(section.len());
+                }
+                buf.copy_from_slice(&section[..len]);
+                section.drain(..consumed);
+                Ok(Completion {
+                    len,
+                    overflowed: consumed != len,
+                })
+            }
+        }
+    }
+
+    fn params(payload_final1_size: usize) -> StreamParams {
+        StreamParams::new(
+            64,
+            64,
+            128,
+            2,
+            payload_final1_size,
+            0,
+            Duration::from_secs(1),
+        )
+    }
+
+    fn receive(device: &mut FakeDevice, params: &StreamParams) -> StreamResult<Payload> {
+        receive_counted(device, params, &StreamCounters::default())
+    }
+
+    fn receive_counted(
+        device: &mut FakeDevice,
+        params: &StreamParams,
+        counters: &StreamCounters,
+    ) -> StreamResult<Payload> {
+        let mut leader_buf = vec![0; params.leader_transfer_size()];
+        let mut payload_buf = PayloadBuf::Pool(vec![0; params.maximum_payload_size()]);
+        let mut trailer_buf = vec![0; params.trailer_transfer_size()];
+        let leader = read_leader(device, params, counters, &mut leader_buf)?;
+        receive_payload(
+            device,
+            params,
+            counters,
+            leader,
+            FrameId::default(),
+            &mut payload_buf,
+            &mut PayloadScratch::default(),
+            &mut trailer_buf,
+        )
+    }
+
+    #[test]
+    fn test_leader_larger_than_reported() {
+        /// Sends an image whose leader is 96 bytes although the device reports 64 bytes.
+        fn send_image(device: &mut FakeDevice) {
+            device.send_image(0, 16, 20, 64);
+            let leader = device.sections.front_mut().unwrap();
+            leader.resize(96, 0);
+            leader[6..8].copy_from_slice(&96_u16.to_le_bytes());
+        }
+
+        let mut params = params(64);
+        let counters = StreamCounters::default();
+        let mut device = FakeDevice::new(false);
+        send_image(&mut device);
+        match receive_counted(&mut device, &params, &counters) {
+            Err(StreamError::SectionOverflow {
+                section,
+                reported_size,
+                transfer_size,
+                actual_size,
+            }) => {
+                assert_eq!(section, "leader");
+                assert_eq!((reported_size, transfer_size, actual_size), (64, 64, 96));
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(counters.snapshot().max_leader_size, 96);
+
+        // The headroom derived from the statistics.
+        params.quirks.leader_headroom = 32;
+        let counters = StreamCounters::default();
+        let mut device = FakeDevice::new(false);
+        send_image(&mut device);
+        let payload = receive_counted(&mut device, &params, &counters).unwrap();
+        assert_eq!(payload.image().unwrap().len(), 16 * 20);
+        let statistics = counters.snapshot();
+        assert_eq!(statistics.max_leader_size, 96);
+        assert_eq!(statistics.max_trailer_size, 32);
+    }
+
+    #[test]
+    fn test_apply_open_options() {
+        let mut params = params(64);
+        let quirks = Quirks {
+            leader_headroom: 32,
+            ..Quirks::default()
+        };
+        let thread = ThreadConfig {
+            name: "stream".into(),
+            ..ThreadConfig::default()
+        };
+        params.apply_open_options(
+            &OpenOptions::new()
+                .quirks(quirks)
+                .stream_thread(thread.clone()),
+        );
+        assert_eq!(params.quirks, quirks);
+        assert_eq!(params.thread, thread);
+
+        // The headroom reaches the leader transfer, so an under-reported leader is received.
+        assert_eq!(params.leader_transfer_size(), 96);
+        let mut device = FakeDevice::new(false);
+        device.send_image(0, 16, 20, 64);
+        let leader = device.sections.front_mut().unwrap();
+        leader.resize(96, 0);
+        leader[6..8].copy_from_slice(&96_u16.to_le_bytes());
+        let payload = receive(&mut device, &params).unwrap();
+        assert_eq!(payload.image().unwrap().len(), 16 * 20);
+    }
+
+    #[test]
+    fn test_renegotiate_section_size() {
+        /// Sends an image whose leader is 96 bytes, which is larger than the leader buffer of
+        /// 64 bytes prepared from the size reported by the device.
+        fn send_image(device: &mut FakeDevice, block_id: u64) {
+            device.send_image(block_id, 16, 20, 64);
+            let leader = device.sections.front_mut().unwrap();
+            leader.resize(96, 0);
+            leader[6..8].copy_from_slice(&96_u16.to_le_bytes());
+        }
+
+        let mut params = params(64);
+        let counters = StreamCounters::default();
+        let mut device = FakeDevice::new(false);
+        send_image(&mut device, 0);
+        let err = receive_counted(&mut device, &params, &counters).unwrap_err();
+        assert!(params.renegotiate(&err));
+        assert_eq!(params.quirks.leader_headroom, 32);
+        assert_eq!(params.leader_transfer_size(), 96);
+
+        // The first payload is lost, and the following ones are received.
+        device.sections.clear();
+        send_image(&mut device, 1);
+        let payload = receive_counted(&mut device, &params, &counters).unwrap();
+        assert_eq!(payload.id(), 1);
+        assert_eq!(payload.image().unwrap().len(), 16 * 20);
+
+        // The raised headroom is written back to the parameters of the handle.
+        let renegotiated = RenegotiatedHeadroom::new(&Quirks::default());
+        renegotiated.store(&params.quirks);
+        let mut quirks = Quirks::default();
+        renegotiated.write_back(&mut quirks);
+        assert_eq!(quirks, params.quirks);
+
+        // The transfer size isn't raised beyond the maximum.
+        let mut params = self::params(64);
+        params.max_section_transfer_size = 80;
+        let mut device = FakeDevice::new(false);
+        send_image(&mut device, 0);
+        let err = receive_counted(&mut device, &params, &counters).unwrap_err();
+        assert!(!params.renegotiate(&err));
+        assert_eq!(params.leader_transfer_size(), 64);
+        assert!(!params.renegotiate(&StreamError::Timeout));
+    }
+
+    #[test]
+    fn test_zero_length_packets() {
+        let params = params(64);
+        let mut device = FakeDevice::new(true);
+        // All of leader, payload, and trailer are exact multiples of the max packet size.
+        device.send_image(0, 16, 20, 64);
+        device.send_image(1, 16, 20, 64);
+
+        for id in 0..2 {
+            let payload = receive(&mut device, &params).unwrap();
+            assert_eq!(payload.id(), id);
+            assert!(!payload.is_incomplete());
+            let expected: Vec<u8> = (0..320).map(|i| (i % 251) as u8).collect();
+            assert_eq!(payload.image().unwrap(), expected.as_slice());
+        }
+        // Only the zero-length packet following the last trailer is left.
+        assert_eq!(device.sections, [vec![]]);
+    }
+
+    #[cfg(feature = "leak-check")]
+    #[test]
+    fn test_leak_check() {
+        use cameleon_impl::leak_check;
+
+        let params = params(64);
+        let mut device = FakeDevice::new(true);
+        device.send_image(0, 16, 20, 64);
+
+        // The buffer is intentionally kept alive.
+        let payload = receive(&mut device, &params).unwrap();
+        let err = std::panic::catch_unwind(leak_check::assert_clean).unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains(&format!("pool buffer created at {}", file!())));
+
+        drop(payload);
+        leak_check::assert_clean();
+    }
+
+    /// Provides buffers filled with `0xaa`.
+    struct Provider;
+
+    impl BufferProvider for Provider {
+        fn acquire(&self, len: usize) -> Option<Box<dyn DestinationBuffer>> {
+            Some(Box::new(vec![0xaa; len]))
+        }
+    }
+
+    fn receive_into(
+        device: &mut FakeDevice,
+        params: &StreamParams,
+        provider: &dyn BufferProvider,
+    ) -> StreamResult<Payload> {
+        let payload_buf =
+            PayloadBuf::Provided(provider.acquire(params.maximum_payload_size()).unwrap());
+        receive_in(device, params, payload_buf)
+    }
+
+    fn receive_in(
+        device: &mut FakeDevice,
+        params: &StreamParams,
+        mut payload_buf: PayloadBuf,
+    ) -> StreamResult<Payload> {
+        let counters = StreamCounters::default();
+        let mut leader_buf = vec![0; params.leader_transfer_size()];
+        let mut trailer_buf = vec![0; params.trailer_transfer_size()];
+        let leader = read_leader(device, params, &counters, &mut leader_buf)?;
+        receive_payload(
+            device,
+            params,
+            &counters,
+            leader,
+            FrameId::default(),
+            &mut payload_buf,
+            &mut PayloadScratch::default(),
+            &mut trailer_buf,
+        )
+    }
+
+    #[test]
+    fn test_deliver_into() {
+        let mut params = params(64);
+        params.deliver_into(Provider);
+        let provider = params.buffer_provider.clone().unwrap();
+        let mut device = FakeDevice::new(false);
+        device.send_image(0, 16, 20, 64);
+        device.send_chunk_image(1, 16, 16, 256);
+
+        let payload = receive_into(&mut device, &params, &*provider).unwrap();
+        let expected: Vec<u8> = (0..320).map(|i| (i % 251) as u8).collect();
+        assert_eq!(payload.image().unwrap(), expected.as_slice());
+        // The data is received into the provided buffer without touching the pool.
+        let provided = payload.provided_buffer().unwrap();
+        assert_eq!(provided.as_slice().as_ptr(), payload.payload().as_ptr());
+        assert!(payload.payload.is_empty());
+        assert_eq!(payload.clone(), payload);
+        assert_eq!(payload.clone().into_vec(), expected);
+
+        // Chunks are parsed from the provided buffer.
+        let payload = receive_into(&mut device, &params, &*provider).unwrap();
+        assert_eq!(payload.image_info().unwrap().image_size, 256);
+        assert!(payload.provided_buffer().is_some());
+
+        // A payload in a provided buffer isn't recycled to the pool.
+        let (tx, rx) = crate::payload::channel(1, 1);
+        rx.send_back(payload);
+        assert!(tx.try_recv().is_err());
+    }
+
+    #[cfg(feature = "leak-check")]
+    #[test]
+    fn test_deliver_into_bypasses_pool() {
+        use cameleon_impl::leak_check::{self, Resource};
+
+        let params = params(64);
+        let mut device = FakeDevice::new(false);
+        device.send_image(0, 16, 20, 64);
+
+        let payload = receive_into(&mut device, &params, &Provider).unwrap();
+        let current = std::thread::current();
+        let live: Vec<_> = leak_check::live()
+            .into_iter()
+            .filter(|r| r.thread.as_deref() == current.name())
+            .map(|r| r.resource)
+            .collect();
+        assert_eq!(live, [Resource::ProvidedBuffer]);
+
+        drop(payload);
+        leak_check::assert_clean();
+    }
+
+    /// Overwrites the trailer of the last sent payload, `emit_zlp` must be `false`.
+    fn patch_trailer(device: &mut FakeDevice, offset: usize, bytes: &[u8]) {
+        let trailer = device.sections.back_mut().unwrap();
+        trailer[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    #[test]
+    fn test_trailer_error_status() {
+        let params = params(64);
+        let mut device = FakeDevice::new(false);
+        device.send_image(0, 16, 20, 64);
+        // Payload status, DataDiscarded.
+        patch_trailer(&mut device, 16, &0xA100_u16.to_le_bytes());
+        device.send_image(1, 16, 20, 64);
+
+        let payload = receive(&mut device, &params).unwrap();
+        assert_eq!(payload.id(), 0);
+        assert_eq!(payload.status(), PayloadStatus::DataDiscarded);
+        assert!(payload.is_incomplete());
+        assert!(payload.incomplete_info().is_none());
+        assert_eq!(payload.image().unwrap().len(), 16 * 20);
+
+        let payload = receive(&mut device, &params).unwrap();
+        assert_eq!(payload.status(), PayloadStatus::Success);
+        assert!(!payload.is_incomplete());
+    }
+
+    #[test]
+    fn test_short_payload() {
+        let params = params(64);
+        let mut device = FakeDevice::new(false);
+        device.send_image(0, 16, 20, 64);
+        // The trailer reports more data than the device has sent.
+        patch_trailer(&mut device, 20, &330_u64.to_le_bytes());
+
+        let payload = receive(&mut device, &params).unwrap();
+        assert_eq!(payload.status(), PayloadStatus::Success);
+        assert_eq!(
+            payload.incomplete_info(),
+            Some(&IncompleteInfo {
+                expected_size: 330,
+                received_size: 320,
+            })
+        );
+        assert_eq!(payload.payload().len(), 320);
+        assert_eq!(payload.image_info().unwrap().image_size, 320);
+    }
+
+    #[test]
+    fn test_missing_transfer() {
+        let params = params(64);
+        let mut device = FakeDevice::new(false);
+        device.send_image(0, 16, 20, 64);
+        // The second transfer of 128 bytes is cut short by 64 bytes.
+        let image = device.sections.remove(1).unwrap();
+        device.sections.insert(1, image[..128].to_vec());
+        device.sections.insert(2, image[128..192].to_vec());
+        device.sections.insert(3, image[256..].to_vec());
+
+        let payload = receive(&mut device, &params).unwrap();
+        assert_eq!(
+            payload.status(),
+            PayloadStatus::Incomplete {
+                missing_bytes: 64,
+                missing_segments: 1,
+            }
+        );
+        assert!(payload.is_incomplete());
+        // The bytes following the missing ones are packed.
+        assert_eq!(&payload.payload()[..192], &image[..192]);
+        assert_eq!(&payload.payload()[192..], &image[256..]);
+        assert!(device.sections.is_empty());
+    }
+
+    #[test]
+    fn test_missing_leader() {
+        let params = params(64);
+        let mut device = FakeDevice::new(false);
+        device.send_image(0, 16, 20, 64);
+        // The leader of the first payload is lost.
+        device.sections.pop_front();
+        device.send_image(1, 16, 20, 64);
+
+        // Transfers are skipped as the streaming loop does until the next leader arrives.
+        let mut skipped = 0;
+        let payload = loop {
+            match receive(&mut device, &params) {
+                Ok(payload) => break payload,
+                Err(StreamError::InvalidPayload(_)) if skipped < MAX_RESYNC_TRANSFERS => {
+                    skipped += 1;
+                }
+                Err(err) => panic!("unexpected error: {:?}", err),
+            }
+        };
+        assert!(skipped > 0);
+        assert_eq!(payload.id(), 1);
+        assert!(!payload.is_incomplete());
+        assert!(device.sections.is_empty());
+    }
+
+    #[test]
+    fn test_zero_copy() {
+        let params = params(64);
+        let mut device = FakeDevice::new(false);
+        device.send_image(0, 16, 20, 64);
+
+        // The payload holds the buffer which the transfers wrote into.
+        let mut buf = vec![0; params.maximum_payload_size()];
+        let head = buf.as_mut_ptr();
+        let payload = receive_in(&mut device, &params, PayloadBuf::Pool(buf)).unwrap();
+        assert_eq!(payload.payload().as_ptr(), head as *const u8);
+        assert_eq!(payload.payload().len(), 320);
+    }
+
+    #[test]
+    fn test_buffer_pool() {
+        let params = params(64);
+        let pool = BufferPool::new(1, params.maximum_payload_size());
+        let mut device = FakeDevice::new(false);
+        device.send_image(0, 16, 20, 64);
+        device.send_image(1, 16, 20, 64);
+
+        let buf = PayloadBuf::Pooled(pool.try_take().unwrap());
+        let payload = receive_in(&mut device, &params, buf).unwrap();
+        let expected: Vec<u8> = (0..320).map(|i| (i % 251) as u8).collect();
+        assert_eq!(payload.image().unwrap(), expected.as_slice());
+        assert!(pool.try_take().is_none());
+
+        // The buffer is returned to the pool when the payload is dropped.
+        drop(payload);
+        let buf = PayloadBuf::Pooled(pool.try_take().unwrap());
+        let payload = receive_in(&mut device, &params, buf).unwrap();
+        assert_eq!(payload.id(), 1);
+
+        // A payload rejected by the pipeline gives its buffer back to the loop.
+        let buf = PayloadBuf::reclaim(payload, params.maximum_payload_size());
+        assert!(matches!(&buf, PayloadBuf::Pooled(buf) if buf.as_slice().len() == 320));
+        drop(buf);
+        assert_eq!(
+            pool.statistics(),
+            BufferPoolStatistics {
+                capacity: 1,
+                in_use: 0,
+                max_in_use: 1,
+                exhausted: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_oversized_payload() {
+        // The last transfer isn't a multiple of the max packet size, so the packet overflows.
+        let params = params(40);
+        let mut device = FakeDevice::new(false);
+        device.send_image(0, 16, 25, 0);
+        device.send_image(1, 16, 18, 0);
+
+        let payload = receive(&mut device, &params).unwrap();
+        assert_eq!(payload.id(), 0);
+        assert_eq!(
+            payload.incomplete_info(),
+            Some(&IncompleteInfo {
+                expected_size: 400,
+                received_size: 296,
+            })
+        );
+        let expected: Vec<u8> = (0..296).map(|i| (i % 251) as u8).collect();
+        assert_eq!(payload.payload(), expected.as_slice());
+        assert_eq!(payload.image_info().unwrap().image_size, 296);
+
+        // The stream is resynchronized to the next payload.
+        let payload = receive(&mut device, &params).unwrap();
+        assert_eq!(payload.id(), 1);
+        assert!(!payload.is_incomplete());
+        assert_eq!(payload.payload().len(), 288);
+        assert!(device.sections.is_empty());
+    }
+
+    #[test]
+    fn test_chunk_size_overflow() {
+        // The payload of 264 bytes is received in transfers of 128, 128 and 8 bytes.
+        let params = params(40);
+        let mut device = FakeDevice::new(false);
+        device.send_chunk_image(0, 16, 16, 256);
+        device.send_chunk_image(1, 16, 16, u32::MAX);
+        device.send_chunk_image(2, 16, 16, u32::MAX - 3);
+
+        let payload = receive(&mut device, &params).unwrap();
+        assert_eq!(payload.payload_type(), PayloadType::ImageExtendedChunk);
+        assert_eq!(payload.image_info().unwrap().image_size, 256);
+        assert_eq!(payload.chunk_layout_id(), Some(0));
+        let chunk = payload.chunks().unwrap().next().unwrap().unwrap();
+        assert_eq!(chunk.id, 1);
+        assert_eq!(chunk.data, payload.image().unwrap());
+        for _ in 0..2 {
+            assert!(matches!(
+                receive(&mut device, &params),
+                Err(StreamError::InvalidPayload(_))
+            ));
+        }
+    }
+
+    /// Register memory of a device which has `SIRM` of `sirm_length` at `0x2000`.
+    struct Registers {
+        memory: Vec<u8>,
+        /// The status returned for the next write to the payload transfer size.
+        reject_payload_size: Option<ack::Status>,
+    }
+
+    impl Registers {
+        const SBRM_ADDRESS: usize = 0x1000;
+        const SIRM_ADDRESS: usize = 0x2000;
+
+        fn new(sirm_length: u32) -> Self {
+            let mut registers = Self {
+                memory: vec![0; 0x3000],
+                reject_payload_size: None,
+            };
+            registers.write_u64(0x01D8, Self::SBRM_ADDRESS as u64);
+            // `SIRM` is available.
+            registers.write_u64(Self::SBRM_ADDRESS + 0x04, 1);
+            registers.write_u64(Self::SBRM_ADDRESS + 0x20, Self::SIRM_ADDRESS as u64);
+            registers.write_u32(Self::SBRM_ADDRESS + 0x28, sirm_length);
+            registers
+        }
+
+        /// Vendor specific counters placed after `SIRM`.
+        const COUNTERS: DeviceCounterRegisters = DeviceCounterRegisters {
+            payload_drop_count: Some(0x2800),
+            link_error_count: Some(0x2804),
+        };
+
+        fn set_counters(&mut self, payload_drop_count: u32, link_error_count: u32) {
+            self.write_u32(0x2800, payload_drop_count);
+            self.write_u32(0x2804, link_error_count);
+        }
+
+        fn write_u32(&mut self, address: usize, value: u32) {
+            self.memory[address..address + 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        fn write_u64(&mut self, address: usize, value: u64) {
+            self.memory[address..address + 8].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    impl DeviceControl for Registers {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            let address = address as usize;
+            buf.copy_from_slice(&self.memory[address..address + buf.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+            let address = address as usize;
+            if address == Self::SIRM_ADDRESS + 0x1C {
+                if let Some(status) = self.reject_payload_size.take() {
+                    return Err(ControlError::Io(StatusError(status).into()));
+                }
+            }
+            self.memory[address..address + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            unreachable!()
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_device_counters() {
+        let mut registers = Registers::new(0x30);
+        registers.set_counters(3, 1);
+        assert_eq!(
+            Registers::COUNTERS.read(&mut registers).unwrap(),
+            DeviceStreamCounters {
+                payload_drop_count: Some(3),
+                link_error_count: Some(1),
+            }
+        );
+
+        // Only the drop counter is configured.
+        let only_drops = DeviceCounterRegisters {
+            link_error_count: None,
+            ..Registers::COUNTERS
+        };
+        let counters = only_drops.read(&mut registers).unwrap();
+        assert_eq!(counters.payload_drop_count, Some(3));
+        assert!(counters.link_error_count.is_none());
+
+        // Nothing is read from the device unless configured.
+        assert_eq!(
+            DeviceCounterRegisters::default()
+                .read(&mut registers)
+                .unwrap(),
+            DeviceStreamCounters::default()
+        );
+    }
+
+    #[test]
+    fn test_payload_size_fallback() {
+        let sirm = Sirm::new(Registers::SIRM_ADDRESS as u64);
+        let payload_size = 0x8000 * 3 + 10;
+        let setup = |status: Option<ack::Status>| {
+            let mut registers = Registers::new(0x30);
+            // 8 bytes alignment, and the device prefers transfers of 32 KiB.
+            registers.write_u32(Registers::SIRM_ADDRESS, 3 << 24);
+            registers.write_u32(Registers::SIRM_ADDRESS + 0x1C, 0x8000);
+            registers.reject_payload_size = status;
+            registers
+        };
+
+        // The host preferred size is accepted.
+        let mut registers = setup(None);
+        assert_eq!(
+            negotiate_payload_transfer(&sirm, &mut registers, payload_size, 8).unwrap(),
+            0x10000
+        );
+
+        let mut registers = setup(Some(ack::GenCpStatus::InvalidParameter.into()));
+        assert_eq!(
+            negotiate_payload_transfer(&sirm, &mut registers, payload_size, 8).unwrap(),
+            0x8000
+        );
+        let params = StreamParams::from_control(&mut registers).unwrap();
+        assert_eq!(params.payload_size, 0x8000);
+        assert_eq!(params.payload_count, 3);
+        assert_eq!(params.payload_final1_size, 16);
+        assert_eq!(params.payload_final2_size, 0);
+
+        // The final transfer is padded as the preferred size is aligned.
+        let mut registers = setup(Some(ack::UsbSpecificStatus::PayloadSizeNotAligned.into()));
+        negotiate_payload_transfer(&sirm, &mut registers, payload_size, 8).unwrap();
+        let params = StreamParams::from_control(&mut registers).unwrap();
+        assert_eq!(params.payload_size, 0x8000);
+        assert_eq!(params.payload_final1_size, 0x8000);
+        let timeout = Duration::from_millis(10);
+        let from_sirm = StreamParams::from_sirm(&mut registers, &sirm, timeout).unwrap();
+        assert_eq!(from_sirm.payload_size, params.payload_size);
+        assert_eq!(from_sirm.payload_final1_size, params.payload_final1_size);
+        assert_eq!(from_sirm.timeout, timeout);
+        assert!(from_sirm.num_transfers.is_none());
+
+        // Other failures aren't recovered.
+        let mut registers = setup(Some(ack::GenCpStatus::AccessDenied.into()));
+        assert!(matches!(
+            negotiate_payload_transfer(&sirm, &mut registers, payload_size, 8),
+            Err(ControlError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_payload_size_fallback_rejected() {
+        let sirm = Sirm::new(Registers::SIRM_ADDRESS as u64);
+        let mut registers = Registers::new(0x30);
+        registers.reject_payload_size = Some(ack::GenCpStatus::InvalidParameter.into());
+        // The device has no preferred size.
+        let err = negotiate_payload_transfer(&sirm, &mut registers, 0x8000, 8).unwrap_err();
+        let msg = err.to_string();
+        assert!(matches!(err, ControlError::InvalidDevice(_)));
+        assert!(msg.contains("payload transfer size 65536"), "{}", msg);
+        assert!(msg.contains("preferred size 0"), "{}", msg);
+    }
+
+    #[test]
+    fn test_statistics_delta() {
+        let counters = StreamCounters::default();
+        let mut registers = Registers::new(0x30);
+        registers.set_counters(u32::MAX, 0);
+        let statistics = |counters: &StreamCounters, registers: &mut Registers| StreamStatistics {
+            host: counters.snapshot(),
+            device: Registers::COUNTERS.read(registers).unwrap(),
+        };
+        let earlier = statistics(&counters, &mut registers);
+
+        // The device drops two payloads, so the host receives one incomplete payload and fails
+        // to receive another.
+        StreamCounters::increment(&counters.received);
+        StreamCounters::increment(&counters.received);
+        StreamCounters::increment(&counters.incomplete);
+        StreamCounters::increment(&counters.failed);
+        registers.set_counters(1, 0);
+
+        let delta = statistics(&counters, &mut registers).delta_since(&earlier);
+        assert_eq!(
+            delta.host,
+            HostStreamStatistics {
+                received_payloads: 2,
+                incomplete_payloads: 1,
+                failed_payloads: 1,
+                ..HostStreamStatistics::default()
+            }
+        );
+        assert_eq!(delta.device.payload_drop_count, Some(2));
+        assert_eq!(delta.device.link_error_count, Some(0));
+
+        let absent = StreamStatistics::default();
+        assert!(delta
+            .delta_since(&absent)
+            .device
+            .payload_drop_count
+            .is_none());
+    }
+
+    #[test]
+    fn test_block_gaps() {
+        let counters = StreamCounters::default();
+        let mut blocks = BlockTracker::default();
+        for block_id in &[1, 2, 5] {
+            blocks.observe(*block_id, &counters);
+        }
+        // Block ids skipped across a pause are an intentional gap.
+        blocks.resume();
+        blocks.observe(9, &counters);
+        blocks.observe(10, &counters);
+        // The device restarts counting.
+        blocks.observe(0, &counters);
+
+        let statistics = counters.snapshot();
+        assert_eq!(statistics.lost_blocks, 2);
+        assert_eq!(statistics.paused_blocks, 3);
+    }
+
+    #[test]
+    fn test_metrics() {
+        const SERIAL: &str = "stream-metrics-test";
+        let counters = Arc::new(StreamCounters::default());
+        let slot = SCHEDULER.register();
+        register_metrics(&counters, &slot, &Arc::default(), SERIAL);
+
+        // Receives two payloads like the streaming loop.
+        let params = params(64);
+        let mut device = FakeDevice::new(false);
+        let mut blocks = BlockTracker::default();
+        for block_id in &[1, 3] {
+            device.send_image(*block_id, 16, 20, 64);
+            let payload = receive_counted(&mut device, &params, &counters).unwrap();
+            blocks.observe(*block_id, &counters);
+            StreamCounters::increment(&counters.received);
+            assert!(payload.incomplete_info().is_none());
+        }
+
+        let metrics: Vec<_> = metrics::snapshot()
+            .into_iter()
+            .filter(|metric| metric.label(metrics::DEVICE_SERIAL_LABEL) == Some(SERIAL))
+            .collect();
+        assert!(metrics
+            .iter()
+            .all(|metric| metric.label(metrics::STREAM_INDEX_LABEL) == Some("0")));
+        let value = |name: &str| {
+            metrics
+                .iter()
+                .find(|metric| metric.name == name)
+                .map(|metric| metric.value.clone())
+        };
+        assert_eq!(
+            value("cameleon_stream_received_payloads_total"),
+            Some(MetricValue::Counter(2))
+        );
+        assert_eq!(
+            value("cameleon_stream_lost_blocks_total"),
+            Some(MetricValue::Counter(1))
+        );
+        assert!(matches!(
+            value("cameleon_stream_max_leader_size_bytes"),
+            Some(MetricValue::Gauge(size)) if size > 0.0
+        ));
+        assert_eq!(
+            value("cameleon_stream_serviced_completions_total"),
+            Some(MetricValue::Counter(0))
+        );
+
+        // Metrics are unregistered with the handle.
+        drop((counters, slot));
+        assert!(metrics::snapshot()
+            .iter()
+            .all(|metric| metric.label(metrics::DEVICE_SERIAL_LABEL) != Some(SERIAL)));
+    }
+
+    #[test]
+    fn test_pause_control() {
+        let pause = Arc::new(PauseControl::default());
+        let (parked_tx, parked_rx) = std::sync::mpsc::channel();
+        let strm_loop = {
+            let pause = pause.clone();
+            std::thread::spawn(move || {
+                // Poll like the streaming loop waiting for a leader.
+                while !pause.park() {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                parked_tx.send(()).unwrap();
+            })
+        };
+
+        assert!(pause.pause(Duration::from_secs(10)));
+        assert!(pause.is_paused());
+        // The loop stays parked until resumed.
+        assert!(parked_rx.try_recv().is_err());
+
+        pause.resume();
+        parked_rx.recv().unwrap();
+        strm_loop.join().unwrap();
+        assert!(!pause.is_paused());
+
+        // Nothing parks, so the request is withdrawn.
+        assert!(!pause.pause(Duration::from_millis(10)));
+        assert!(!pause.park());
+    }
+
+    #[test]
+    fn test_unknown_pixel_format() {
+        const CODE: u32 = 0x8108_7003;
+
+        let src = format!(
+            "[stream]\nwidth = 16\nheight = 20\npixel_format = {{ Raw = {} }}\n",
+            CODE
+        );
+        let stream: StreamSettings = Fixture::from_toml(&src, "").unwrap().stream.unwrap();
+        let pixel_format = PixelFormat::from(stream.pixel_format);
+
+        let mut device = FakeDevice::new(true);
+        for id in 0..3 {
+            device.send_image_in(id, stream.width, stream.height, 64, pixel_format);
+        }
+        device.send_image(3, stream.width, stream.height, 64);
+
+        let params = params(64);
+        let decoders = params.decoders.clone();
+        let counters = Arc::new(StreamCounters::default());
+        let (tx, rx) = crate::payload::channel(4, 4);
+        let strm_loop = StreamingLoop {
+            renegotiated: Arc::new(RenegotiatedHeadroom::new(&params.quirks)),
+            params,
+            frame_id: FrameId::default(),
+            counters: counters.clone(),
+            pipeline: PipelineRunner::new(vec![], Arc::default()),
+            pause: Arc::default(),
+            buffer_pool: None,
+            cancel: CancelHandle::default(),
+            sender: tx,
+        };
+        let mut loop_thread = LoopThread::spawn(&ThreadConfig::default(), move |cancellation_rx| {
+            strm_loop.run(&mut device, cancellation_rx)
+        })
+        .unwrap();
+
+        // All payloads are delivered, and the unknown format is counted.
+        let payloads: Vec<_> = (0..4).map(|_| task::block_on(rx.recv()).unwrap()).collect();
+        loop_thread.cancel();
+        loop_thread.join(Duration::from_secs(10)).unwrap();
+        assert_eq!(
+            payloads.iter().map(Payload::id).collect::<Vec<_>>(),
+            [0, 1, 2, 3]
+        );
+        assert!(payloads.iter().all(|p| p.image().unwrap().len() == 320));
+        assert_eq!(counters.snapshot().unknown_format_payloads, 3);
+        assert_eq!(
+            payloads[0].image_info().unwrap().pixel_format,
+            PixelFormat::Unknown(CODE)
+        );
+        assert!(payloads[0].to_image().is_err());
+
+        // The decoder registered to the stream converts the image of the unknown format, even if
+        // it's registered after the payload is received.
+        decoders.register(CODE, |info: &ImageInfo, image: &[u8]| {
+            let info = ImageInfo {
+                pixel_format: PixelFormat::Mono8,
+                ..info.clone()
+            };
+            Ok(Image::new(info, image.to_vec()))
+        });
+        let image = payloads[0].to_image().unwrap();
+        assert_eq!(image.info().pixel_format, PixelFormat::Mono8);
+        assert_eq!(image.data(), payloads[3].image().unwrap());
+    }
+
+    /// Xorshift generator which randomizes the timing of the stress test reproducibly.
+    struct Jitter(u64);
+
+    impl Jitter {
+        fn next(&mut self, max: Duration) -> Duration {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            Duration::from_micros(self.0 % max.as_micros() as u64)
+        }
+    }
+
+    /// Starts and stops the stream of an emulated device repeatedly, stopping at random points
+    /// while the loop is blocked in a transfer, on a watchdog thread so that a deadlock fails the
+    /// test instead of hanging it.
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn test_stop_streaming_loop_stress() {
+        const SERIAL: &str = "U3VSTRS1";
+        let loop_name = format!("cameleon-stress-{}", SERIAL);
+
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let stress = thread::spawn(move || {
+            let mut ctrl = open_emulated(SERIAL);
+            let mut strm = open_emulated_stream(SERIAL);
+            strm.params_mut().unwrap().thread = ThreadConfig {
+                name: loop_name.clone(),
+                ..ThreadConfig::default()
+            };
+
+            for i in 0..200 {
+                let mut jitter = Jitter(i + 1);
+                let (sender, receiver) = crate::payload::channel(4, 4);
+                strm.start(sender, &mut ctrl).unwrap();
+                if i % 2 == 0 {
+                    let payload = task::block_on(receiver.recv()).unwrap();
+                    receiver.send_back(payload);
+                }
+
+                thread::sleep(jitter.next(Duration::from_millis(2)));
+                // The parameters are negotiated with the device on start.
+                let bound = strm.params().timeout * 4;
+                let start = std::time::Instant::now();
+                strm.stop(&mut ctrl).unwrap();
+                assert!(start.elapsed() < bound);
+
+                // No transfer or buffer outlives the stopped loop.
+                drop(receiver);
+                let leaked: Vec<_> = leak_check::live()
+                    .into_iter()
+                    .filter(|r| r.thread.as_deref() == Some(&*loop_name))
+                    .map(|r| r.to_string())
+                    .collect();
+                assert!(leaked.is_empty(), "leaked by the loop: {:?}", leaked);
+            }
+            assert_eq!(strm.statistics().failed_payloads, 0);
+
+            strm.close().unwrap();
+            ctrl.close().unwrap();
+            drop(strm);
+            leak_check::assert_clean_on(&[&loop_name]);
+            done_tx.send(()).unwrap();
+        });
+
+        match done_rx.recv_timeout(Duration::from_secs(60)) {
+            Ok(()) => stress.join().unwrap(),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                panic!("starting and stopping deadlocked")
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                std::panic::resume_unwind(stress.join().unwrap_err())
+            }
+        }
+    }
+
+    /// Stream interface of a fake device, the device sends images while it's enabled.
+    #[derive(Default)]
+    struct StreamInterface {
+        enabled: Arc<AtomicBool>,
+        fail_disable: bool,
+    }
+
+    impl DeviceControl for StreamInterface {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, _: u64, _: &mut [u8]) -> ControlResult<()> {
+            unreachable!()
+        }
+
+        fn write(&mut self, _: u64, _: &[u8]) -> ControlResult<()> {
+            unreachable!()
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            unreachable!()
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            self.enabled.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            if self.fail_disable {
+                return Err(ControlError::Disconnected);
+            }
+            self.enabled.store(false, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    /// Spawns a loop which receives the images sent while `enabled` is set.
+    fn spawn_receiving_loop(enabled: Arc<AtomicBool>, params: StreamParams) -> LoopThread {
+        LoopThread::spawn(&ThreadConfig::default(), move |mut cancellation_rx| {
+            let mut device = FakeDevice::new(false);
+            let mut block_id = 0;
+            while cancellation_rx.try_recv().transpose().is_none() {
+                if enabled.load(Ordering::Relaxed) {
+                    device.send_image(block_id, 16, 20, 64);
+                    block_id += 1;
+                }
+                while !device.sections.is_empty() {
+                    receive(&mut device, &params).unwrap();
+                }
+                thread::yield_now();
+            }
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_restart_streaming() {
+        let params = params(64);
+        let bound = params.timeout * 4;
+        let mut ctrl = StreamInterface::default();
+
+        for i in 0..50 {
+            ctrl.enable_streaming().unwrap();
+            let mut loop_thread = Some(spawn_receiving_loop(ctrl.enabled.clone(), params.clone()));
+            thread::sleep(Jitter(i + 1).next(Duration::from_millis(2)));
+
+            let enabled = ctrl.enabled.clone();
+            let mut halts_cleared = 0;
+            stop_streaming(
+                &mut ctrl,
+                || {
+                    // The device is disabled before the loop is cancelled.
+                    assert!(!enabled.load(Ordering::Relaxed));
+                    let mut loop_thread = loop_thread.take().unwrap();
+                    loop_thread.cancel();
+                    loop_thread.join(bound)
+                },
+                || {
+                    halts_cleared += 1;
+                    Ok(())
+                },
+            )
+            .unwrap();
+            assert_eq!(halts_cleared, 1);
+        }
+
+        // The loop is stopped even if the device can't be disabled, the error is returned
+        // afterwards.
+        ctrl.fail_disable = true;
+        let mut loop_thread = spawn_receiving_loop(Arc::default(), params);
+        let err = stop_streaming(
+            &mut ctrl,
+            || {
+                loop_thread.cancel();
+                loop_thread.join(bound)
+            },
+            || Ok(()),
+        )
+        .unwrap_err();
+        assert!(matches!(err, StreamError::Io(_)));
+        assert!(loop_thread.handle.is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "emulator")]
+    fn test_emulated_restart() {
+        let mut ctrl = open_emulated("U3VRSTR1");
+        let mut strm = open_emulated_stream("U3VRSTR1");
+        let sirm = ctrl.sirm().unwrap();
+
+        // Stopping a stream which has never been started does nothing.
+        strm.stop(&mut ctrl).unwrap();
+
+        let mut frame_ids = vec![];
+        for _ in 0..50 {
+            let (sender, receiver) = crate::payload::channel(4, 4);
+            strm.start(sender, &mut ctrl).unwrap();
+            assert!(sirm.is_stream_enable(&mut ctrl).unwrap());
+            assert!(matches!(
+                strm.start(crate::payload::channel(1, 1).0, &mut ctrl),
+                Err(StreamError::InStreaming)
+            ));
+
+            let payload = task::block_on(receiver.recv()).unwrap();
+            let image_info = payload.image_info().unwrap();
+            assert_eq!((image_info.width, image_info.height), (640, 480));
+            assert_eq!(payload.image().unwrap().len(), 640 * 480);
+            frame_ids.push(payload.frame_id());
+            receiver.send_back(payload);
+
+            strm.stop(&mut ctrl).unwrap();
+            assert!(!sirm.is_stream_enable(&mut ctrl).unwrap());
+            // Stopping twice does nothing.
+            strm.stop(&mut ctrl).unwrap();
+        }
+        // Each start is a new acquisition.
+        assert!(frame_ids
+            .windows(2)
+            .all(|ids| ids[0].generation() != ids[1].generation()));
+        assert_eq!(strm.statistics().failed_payloads, 0);
+
+        strm.close().unwrap();
+        ctrl.close().unwrap();
+        drop(strm);
+        leak_check::assert_clean();
+    }
+
+    #[test]
+    fn test_stop_stuck_streaming_loop() {
+        let mut loop_thread = LoopThread::spawn(&ThreadConfig::default(), |_| {
+            thread::sleep(Duration::from_millis(200));
+        })
+        .unwrap();
+
+        loop_thread.cancel();
+        assert!(matches!(
+            loop_thread.join(Duration::from_millis(10)),
+            Err(StreamError::Timeout)
+        ));
+        // Stopping can be retried.
+        loop_thread.join(Duration::from_secs(5)).unwrap();
+
+        let mut loop_thread =
+            LoopThread::spawn(&ThreadConfig::default(), |_| panic!("loop panicked")).unwrap();
+        assert!(matches!(
+            loop_thread.join(Duration::from_secs(5)),
+            Err(StreamError::Poisoned(_))
+        ));
+    }
 }
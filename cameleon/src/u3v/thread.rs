@@ -0,0 +1,241 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains configuration of threads spawned by handles.
+//!
+//! Core affinity and priority are applied on a best-effort basis. If the platform doesn't support
+//! the configuration or the configuration is invalid, a warning is logged and the thread runs with
+//! the default setting.
+
+use std::{borrow::Cow, io, thread};
+
+use tracing::warn;
+
+/// Default name of the thread which receives stream packets.
+const DEFAULT_STREAM_THREAD_NAME: &str = "cameleon-u3v-stream";
+
+/// Configuration of the thread which receives stream packets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(default))]
+pub struct ThreadConfig {
+    /// Name of the thread.
+    pub name: String,
+
+    /// Indices of the cores which the thread is allowed to run on. If `None`, the thread is
+    /// allowed to run on any core.
+    ///
+    /// Supported on Linux and Windows.
+    pub core_affinity: Option<Vec<usize>>,
+
+    /// Priority of the thread. If `None`, the thread inherits the priority of the process.
+    ///
+    /// Supported on Linux and Windows. [`ThreadPriority::Realtime`] usually requires a privilege.
+    pub priority: Option<ThreadPriority>,
+}
+
+impl Default for ThreadConfig {
+    fn default() -> Self {
+        Self {
+            name: DEFAULT_STREAM_THREAD_NAME.into(),
+            core_affinity: None,
+            priority: None,
+        }
+    }
+}
+
+/// Priority of a thread.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+pub enum ThreadPriority {
+    /// Lower than normal threads.
+    Low,
+    /// Same as normal threads.
+    Normal,
+    /// Higher than normal threads.
+    High,
+    /// Real-time scheduling. `SCHED_FIFO` on Linux, `THREAD_PRIORITY_TIME_CRITICAL` on Windows.
+    Realtime,
+}
+
+impl ThreadConfig {
+    /// Spawns a thread configured by `self`.
+    pub(super) fn spawn<F>(&self, f: F) -> io::Result<thread::JoinHandle<()>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let config = self.clone();
+        thread::Builder::new()
+            .name(self.name.clone())
+            .spawn(move || {
+                config.apply_to_current();
+                f()
+            })
+    }
+
+    /// Applies core affinity and priority to the current thread.
+    fn apply_to_current(&self) {
+        if let Some(cores) = &self.core_affinity {
+            if let Err(e) = set_core_affinity(cores) {
+                warn!(thread = %self.name, "failed to set core affinity: {}", e);
+            }
+        }
+
+        if let Some(priority) = self.priority {
+            if let Err(e) = set_priority(priority) {
+                warn!(thread = %self.name, "failed to set thread priority: {}", e);
+            }
+        }
+    }
+}
+
+type ApplyResult = std::result::Result<(), Cow<'static, str>>;
+
+fn invalid_core(core: usize) -> Cow<'static, str> {
+    format!("core index {} is out of range", core).into()
+}
+
+#[cfg(target_os = "linux")]
+fn set_core_affinity(cores: &[usize]) -> ApplyResult {
+    // SAFETY: `cpu_set_t` is a plain bit set, so zeroed value is valid.
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    #[allow(clippy::cast_sign_loss)]
+    let core_num = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }.max(1) as usize;
+    for &core in cores {
+        if core >= core_num.min(libc::CPU_SETSIZE as usize) {
+            return Err(invalid_core(core));
+        }
+        unsafe { libc::CPU_SET(core, &mut set) };
+    }
+
+    // SAFETY: `set` is initialized above, and `0` means the calling thread.
+    let ret = unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error().to_string().into())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_priority(priority: ThreadPriority) -> ApplyResult {
+    if priority == ThreadPriority::Realtime {
+        // SAFETY: `sched_param` is initialized, and `pthread_self` is always valid.
+        let ret = unsafe {
+            let param = libc::sched_param {
+                sched_priority: libc::sched_get_priority_max(libc::SCHED_FIFO),
+            };
+            libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+        };
+        return if ret == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::from_raw_os_error(ret).to_string().into())
+        };
+    }
+
+    let nice = match priority {
+        ThreadPriority::Low => 10,
+        ThreadPriority::Normal => 0,
+        ThreadPriority::High => -10,
+        ThreadPriority::Realtime => unreachable!(),
+    };
+    // On Linux, `setpriority` with a thread id changes the nice value of the thread only.
+    // SAFETY: `gettid` always succeeds.
+    #[allow(clippy::cast_sign_loss)]
+    let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+    let ret = unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error().to_string().into())
+    }
+}
+
+#[cfg(windows)]
+fn set_core_affinity(cores: &[usize]) -> ApplyResult {
+    use winapi::um::{processthreadsapi::GetCurrentThread, winbase::SetThreadAffinityMask};
+
+    let mut mask: usize = 0;
+    for &core in cores {
+        if core >= std::mem::size_of::<usize>() * 8 {
+            return Err(invalid_core(core));
+        }
+        mask |= 1 << core;
+    }
+
+    // SAFETY: `GetCurrentThread` returns a pseudo handle which is always valid.
+    let ret = unsafe { SetThreadAffinityMask(GetCurrentThread(), mask) };
+    if ret == 0 {
+        Err(io::Error::last_os_error().to_string().into())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+fn set_priority(priority: ThreadPriority) -> ApplyResult {
+    use winapi::um::{
+        processthreadsapi::{GetCurrentThread, SetThreadPriority},
+        winbase::{
+            THREAD_PRIORITY_ABOVE_NORMAL, THREAD_PRIORITY_BELOW_NORMAL, THREAD_PRIORITY_NORMAL,
+            THREAD_PRIORITY_TIME_CRITICAL,
+        },
+    };
+
+    let priority = match priority {
+        ThreadPriority::Low => THREAD_PRIORITY_BELOW_NORMAL,
+        ThreadPriority::Normal => THREAD_PRIORITY_NORMAL,
+        ThreadPriority::High => THREAD_PRIORITY_ABOVE_NORMAL,
+        ThreadPriority::Realtime => THREAD_PRIORITY_TIME_CRITICAL,
+    };
+
+    // SAFETY: `GetCurrentThread` returns a pseudo handle which is always valid.
+    let ret = unsafe { SetThreadPriority(GetCurrentThread(), priority as i32) };
+    if ret == 0 {
+        Err(io::Error::last_os_error().to_string().into())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn set_core_affinity(_: &[usize]) -> ApplyResult {
+    Err("core affinity is not supported on this platform".into())
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn set_priority(_: ThreadPriority) -> ApplyResult {
+    Err("thread priority is not supported on this platform".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_name() {
+        let config = ThreadConfig {
+            name: "cameleon-test".into(),
+            ..ThreadConfig::default()
+        };
+        let handle = config
+            .spawn(|| {
+                assert_eq!(thread::current().name(), Some("cameleon-test"));
+            })
+            .unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_invalid_core() {
+        assert!(set_core_affinity(&[usize::MAX]).is_err());
+
+        // Invalid core index must not panic the spawned thread.
+        let config = ThreadConfig {
+            core_affinity: Some(vec![usize::MAX]),
+            ..ThreadConfig::default()
+        };
+        config.spawn(|| {}).unwrap().join().unwrap();
+    }
+}
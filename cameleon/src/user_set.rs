@@ -0,0 +1,648 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains types to persist the camera configuration in user sets.
+//!
+//! A user set is a slot in the non-volatile memory of the camera which holds the settings of
+//! features. A slot is selected by `UserSetSelector`, then saved and loaded by `UserSetSave` and
+//! `UserSetLoad` defined in `GenApi SFNC`. The slot loaded at power-up is selected by
+//! `UserSetDefault`.
+//!
+//! See [`Camera::save_user_set`](crate::Camera::save_user_set) and
+//! [`Camera::load_user_set`](crate::Camera::load_user_set).
+
+use std::{fmt, thread, time::Duration, time::Instant};
+
+use super::{
+    genapi::{sfnc::entry, EnumerationNode, FeatureValue, GenApiCtxt, ParamsCtxt, SfncFeature},
+    CameleonError, CameleonResult, DeviceControl,
+};
+
+/// A slot of user sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UserSet {
+    /// The factory settings, which can be loaded but not saved.
+    Default,
+    /// `UserSet1`, `UserSet2` and so on.
+    User(u32),
+}
+
+impl fmt::Display for UserSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => f.write_str(entry::USER_SET_DEFAULT),
+            Self::User(n) => write!(f, "{}{}", entry::USER_SET_PREFIX, n),
+        }
+    }
+}
+
+/// Options of saving and loading user sets.
+#[derive(Debug, Clone)]
+pub struct UserSetOptions {
+    /// Features read when a user set is saved, and compared with the values read after the user
+    /// set is loaded.
+    pub verified_features: Vec<String>,
+
+    /// Maximum duration to wait for the camera to complete saving or loading a user set.
+    pub timeout: Duration,
+
+    /// Interval of polling whether the camera completes saving or loading a user set.
+    pub poll_interval: Duration,
+}
+
+impl Default for UserSetOptions {
+    fn default() -> Self {
+        Self {
+            verified_features: vec![],
+            timeout: Duration::from_secs(5),
+            poll_interval: Duration::from_millis(10),
+        }
+    }
+}
+
+impl UserSetOptions {
+    /// Adds `feature` to [`Self::verified_features`].
+    #[must_use]
+    pub fn verify(mut self, feature: impl Into<String>) -> Self {
+        self.verified_features.push(feature.into());
+        self
+    }
+}
+
+/// Values of features read when a user set is saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserSetSnapshot {
+    slot: UserSet,
+    values: Vec<(String, FeatureValue)>,
+}
+
+impl UserSetSnapshot {
+    /// Returns the saved slot.
+    #[must_use]
+    pub fn slot(&self) -> UserSet {
+        self.slot
+    }
+
+    /// Returns the features and their values at the time of saving.
+    #[must_use]
+    pub fn values(&self) -> &[(String, FeatureValue)] {
+        &self.values
+    }
+}
+
+/// A feature whose value after loading a user set differs from the saved one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureMismatch {
+    /// Name of the feature.
+    pub feature: String,
+    /// The value when the user set is saved.
+    pub expected: FeatureValue,
+    /// The value after the user set is loaded.
+    pub actual: FeatureValue,
+}
+
+impl fmt::Display for FeatureMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`{}` is {:?}, expected {:?}",
+            self.feature, self.actual, self.expected
+        )
+    }
+}
+
+/// Selects `slot`, then reads the features to be verified and saves the user set.
+pub(crate) fn save<Ctrl, Ctxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    slot: UserSet,
+    options: &UserSetOptions,
+) -> CameleonResult<UserSetSnapshot>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    select(ctxt, SfncFeature::UserSetSelector, slot)?;
+    let values = read_features(ctxt, &options.verified_features)?;
+    execute_and_wait(ctxt, SfncFeature::UserSetSave, options)?;
+    Ok(UserSetSnapshot { slot, values })
+}
+
+/// Selects `slot` and loads the user set, then verifies the features in `expected` if any.
+pub(crate) fn load<Ctrl, Ctxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    slot: UserSet,
+    expected: Option<&UserSetSnapshot>,
+    options: &UserSetOptions,
+) -> CameleonResult<()>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    select(ctxt, SfncFeature::UserSetSelector, slot)?;
+    execute_and_wait(ctxt, SfncFeature::UserSetLoad, options)?;
+    // Loading a user set changes features behind the context.
+    ctxt.ctxt.clear_cache();
+
+    let expected = match expected {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    let mut mismatches = vec![];
+    for (feature, value) in &expected.values {
+        let actual = read_feature(ctxt, feature)?;
+        if &actual != value {
+            mismatches.push(FeatureMismatch {
+                feature: feature.clone(),
+                expected: value.clone(),
+                actual,
+            });
+        }
+    }
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(CameleonError::UserSetMismatch { slot, mismatches })
+    }
+}
+
+/// Sets `slot` to the user set loaded at power-up.
+pub(crate) fn set_default<Ctrl, Ctxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    slot: UserSet,
+) -> CameleonResult<()>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    // Older cameras have `UserSetDefaultSelector` instead, which is looked up as the legacy
+    // name of `UserSetDefault`.
+    select(ctxt, SfncFeature::UserSetDefault, slot)
+}
+
+/// Sets the entry corresponding to `slot` to the enumeration node `feature`.
+fn select<Ctrl, Ctxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    feature: SfncFeature,
+    slot: UserSet,
+) -> CameleonResult<()>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    let selector = enumeration_node(ctxt, feature)?;
+    let entry_name = slot.to_string();
+    let entry = selector
+        .entries(ctxt)
+        .iter()
+        .find(|ent| ent.name() == entry_name || ent.symbolic() == Some(&entry_name));
+    match entry {
+        Some(entry) => {
+            let value = entry.value();
+            selector.set_entry_by_value(ctxt, value)?;
+            Ok(())
+        }
+        None => Err(CameleonError::UserSetUnavailable {
+            requested: slot,
+            available: available_slots(ctxt, selector),
+        }),
+    }
+}
+
+fn enumeration_node<Ctrl, Ctxt>(
+    ctxt: &ParamsCtxt<Ctrl, Ctxt>,
+    feature: SfncFeature,
+) -> CameleonResult<EnumerationNode>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    ctxt.get(feature)
+        .and_then(|node| node.as_enumeration(ctxt))
+        .ok_or(CameleonError::MissingCapability {
+            missing: vec![feature.as_str()],
+        })
+}
+
+/// Returns the slots in the entries of `selector`.
+fn available_slots<Ctrl, Ctxt>(
+    ctxt: &ParamsCtxt<Ctrl, Ctxt>,
+    selector: EnumerationNode,
+) -> Vec<UserSet>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    selector
+        .entries(ctxt)
+        .iter()
+        .filter_map(|ent| parse_slot(ent.symbolic().unwrap_or_else(|| ent.name())))
+        .collect()
+}
+
+fn parse_slot(name: &str) -> Option<UserSet> {
+    match name {
+        entry::USER_SET_DEFAULT => Some(UserSet::Default),
+        _ => name
+            .strip_prefix(entry::USER_SET_PREFIX)
+            .and_then(|n| n.parse().ok())
+            .map(UserSet::User),
+    }
+}
+
+/// Executes the command node `name`, then waits for the camera to complete it.
+fn execute_and_wait<Ctrl, Ctxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    feature: SfncFeature,
+    options: &UserSetOptions,
+) -> CameleonResult<()>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    let command = ctxt
+        .get(feature)
+        .and_then(|node| node.as_command(ctxt))
+        .ok_or(CameleonError::MissingCapability {
+            missing: vec![feature.as_str()],
+        })?;
+    command.execute(ctxt)?;
+
+    let started = Instant::now();
+    while !command.is_done(ctxt)? {
+        if started.elapsed() >= options.timeout {
+            return Err(CameleonError::CommandTimeout {
+                command: feature.as_str(),
+                timeout: options.timeout,
+            });
+        }
+        thread::sleep(options.poll_interval);
+    }
+    Ok(())
+}
+
+fn read_features<Ctrl, Ctxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    features: &[String],
+) -> CameleonResult<Vec<(String, FeatureValue)>>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    features
+        .iter()
+        .map(|feature| Ok((feature.clone(), read_feature(ctxt, feature)?)))
+        .collect()
+}
+
+fn read_feature<Ctrl, Ctxt>(
+    ctxt: &mut ParamsCtxt<Ctrl, Ctxt>,
+    feature: &str,
+) -> CameleonResult<FeatureValue>
+where
+    Ctrl: DeviceControl,
+    Ctxt: GenApiCtxt,
+{
+    let invalid = |reason| {
+        cameleon_genapi::GenApiError::InvalidNode(format!("`{}` {}", feature, reason).into())
+    };
+    let node = ctxt.node(feature).ok_or_else(|| invalid("is not found"))?;
+    Ok(ctxt
+        .feature_value(node)?
+        .ok_or_else(|| invalid("doesn't have a value"))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::{
+        super::{
+            genapi::{DefaultGenApiCtxt, FromXml},
+            ControlResult,
+        },
+        *,
+    };
+
+    const XML: &str = r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="2"
+          SubMinorVersion="3"
+          ToolTip="ToolTiptest"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210"
+          xmlns="http://www.genicam.org/GenApi/Version_1_0"
+          xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+          xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_0 GenApiSchema.xsd">
+
+            <Enumeration Name="UserSetSelector">
+                <EnumEntry Name="Default">
+                    <Value>0</Value>
+                </EnumEntry>
+                <EnumEntry Name="UserSet1">
+                    <Value>1</Value>
+                </EnumEntry>
+                <EnumEntry Name="UserSet2">
+                    <Value>2</Value>
+                </EnumEntry>
+                <pValue>UserSetSelectorReg</pValue>
+            </Enumeration>
+
+            <IntReg Name="UserSetSelectorReg">
+              <Address>0x0</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Command Name="UserSetSave">
+                <pValue>UserSetSaveReg</pValue>
+                <CommandValue>1</CommandValue>
+            </Command>
+
+            <IntReg Name="UserSetSaveReg">
+              <Address>0x4</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Command Name="UserSetLoad">
+                <pValue>UserSetLoadReg</pValue>
+                <CommandValue>1</CommandValue>
+            </Command>
+
+            <IntReg Name="UserSetLoadReg">
+              <Address>0x8</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <Enumeration Name="UserSetDefault">
+                <EnumEntry Name="Default">
+                    <Value>0</Value>
+                </EnumEntry>
+                <EnumEntry Name="UserSet1">
+                    <Value>1</Value>
+                </EnumEntry>
+                <EnumEntry Name="UserSet2">
+                    <Value>2</Value>
+                </EnumEntry>
+                <pValue>UserSetDefaultReg</pValue>
+            </Enumeration>
+
+            <IntReg Name="UserSetDefaultReg">
+              <Address>0xc</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <IntReg Name="Width">
+              <Address>0x10</Address>
+              <Length>4</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </IntReg>
+
+            <FloatReg Name="Gain">
+              <Address>0x18</Address>
+              <Length>8</Length>
+              <AccessMode>RW</AccessMode>
+              <pPort>Device</pPort>
+            </FloatReg>
+
+            <Port Name="Device">
+            </Port>
+
+        </RegisterDescription>
+        "#;
+
+    const SELECTOR: usize = 0x0;
+    const SAVE: u64 = 0x4;
+    const LOAD: u64 = 0x8;
+    const DEFAULT: usize = 0xc;
+    /// Features from this address are saved to user sets.
+    const SETTINGS: usize = 0x10;
+
+    /// A device which completes saving and loading user sets after the command register is
+    /// polled `busy_polls` times.
+    struct Memory {
+        mem: Vec<u8>,
+        slots: [Vec<u8>; 3],
+        busy_polls: usize,
+        pending: Option<(u64, usize)>,
+    }
+
+    impl Memory {
+        fn register(&self, address: usize) -> u32 {
+            u32::from_le_bytes(self.mem[address..address + 4].try_into().unwrap())
+        }
+
+        fn complete(&mut self, command: u64) {
+            let slot = self.register(SELECTOR) as usize;
+            if command == SAVE {
+                self.slots[slot] = self.mem[SETTINGS..].to_vec();
+            } else {
+                let settings = self.slots[slot].clone();
+                self.mem[SETTINGS..].copy_from_slice(&settings);
+            }
+        }
+    }
+
+    impl DeviceControl for Memory {
+        fn open(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn is_opened(&self) -> bool {
+            true
+        }
+
+        fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+            if let Some((command, polls)) = self.pending {
+                if command == address {
+                    if polls == 0 {
+                        self.complete(command);
+                        self.pending = None;
+                        self.mem[address as usize..address as usize + 4].fill(0);
+                    } else {
+                        self.pending = Some((command, polls.saturating_sub(1)));
+                    }
+                }
+            }
+
+            let address = address as usize;
+            buf.copy_from_slice(&self.mem[address..address + buf.len()]);
+            Ok(())
+        }
+
+        fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+            if address == SAVE || address == LOAD {
+                self.pending = Some((address, self.busy_polls));
+            }
+
+            let address = address as usize;
+            self.mem[address..address + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn genapi(&mut self) -> ControlResult<String> {
+            Ok(XML.into())
+        }
+
+        fn enable_streaming(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+
+        fn disable_streaming(&mut self) -> ControlResult<()> {
+            Ok(())
+        }
+    }
+
+    fn params_ctxt(busy_polls: usize) -> ParamsCtxt<Memory, DefaultGenApiCtxt> {
+        let mut mem = vec![0; 0x20];
+        // Width is 320 and Gain is 1.5.
+        mem[0x10..0x14].copy_from_slice(&320_u32.to_le_bytes());
+        mem[0x18..0x20].copy_from_slice(&1.5_f64.to_le_bytes());
+        let slots = [
+            mem[SETTINGS..].to_vec(),
+            mem[SETTINGS..].to_vec(),
+            mem[SETTINGS..].to_vec(),
+        ];
+        ParamsCtxt {
+            ctrl: Memory {
+                mem,
+                slots,
+                busy_polls,
+                pending: None,
+            },
+            ctxt: DefaultGenApiCtxt::from_xml(&XML).unwrap(),
+        }
+    }
+
+    fn set_width(ctxt: &mut ParamsCtxt<Memory, DefaultGenApiCtxt>, value: i64) {
+        let width = ctxt.node("Width").unwrap().as_integer(ctxt).unwrap();
+        width.set_value(ctxt, value).unwrap();
+    }
+
+    fn options() -> UserSetOptions {
+        UserSetOptions {
+            poll_interval: Duration::from_millis(1),
+            ..UserSetOptions::default()
+        }
+        .verify("Width")
+        .verify("Gain")
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut ctxt = params_ctxt(2);
+        let options = options();
+
+        set_width(&mut ctxt, 640);
+        let snapshot = save(&mut ctxt, UserSet::User(1), &options).unwrap();
+        assert_eq!(snapshot.slot(), UserSet::User(1));
+        assert_eq!(
+            snapshot.values(),
+            &[
+                ("Width".to_string(), FeatureValue::Integer(640)),
+                ("Gain".to_string(), FeatureValue::Float(1.5)),
+            ]
+        );
+
+        set_width(&mut ctxt, 320);
+        load(&mut ctxt, UserSet::User(1), Some(&snapshot), &options).unwrap();
+        assert_eq!(read_feature(&mut ctxt, "Width").unwrap(), 640_i64.into());
+    }
+
+    #[test]
+    fn test_load_mismatch() {
+        let mut ctxt = params_ctxt(2);
+        let options = options();
+
+        set_width(&mut ctxt, 640);
+        let snapshot = save(&mut ctxt, UserSet::User(2), &options).unwrap();
+        // Another host overwrites the user set.
+        ctxt.ctrl.slots[2][0..4].copy_from_slice(&1024_u32.to_le_bytes());
+
+        match load(&mut ctxt, UserSet::User(2), Some(&snapshot), &options) {
+            Err(CameleonError::UserSetMismatch { slot, mismatches }) => {
+                assert_eq!(slot, UserSet::User(2));
+                assert_eq!(
+                    mismatches,
+                    vec![FeatureMismatch {
+                        feature: "Width".into(),
+                        expected: FeatureValue::Integer(640),
+                        actual: FeatureValue::Integer(1024),
+                    }]
+                );
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_unavailable_slot() {
+        let mut ctxt = params_ctxt(0);
+        match save(&mut ctxt, UserSet::User(3), &options()) {
+            Err(CameleonError::UserSetUnavailable {
+                requested,
+                available,
+            }) => {
+                assert_eq!(requested, UserSet::User(3));
+                assert_eq!(
+                    available,
+                    vec![UserSet::Default, UserSet::User(1), UserSet::User(2)]
+                );
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        // The command must not be executed.
+        assert!(ctxt.ctrl.pending.is_none());
+    }
+
+    #[test]
+    fn test_set_default() {
+        let mut ctxt = params_ctxt(0);
+        set_default(&mut ctxt, UserSet::User(2)).unwrap();
+        assert_eq!(ctxt.ctrl.register(DEFAULT), 2);
+    }
+
+    #[test]
+    fn test_command_timeout() {
+        let mut ctxt = params_ctxt(usize::MAX);
+        let options = UserSetOptions {
+            timeout: Duration::from_millis(20),
+            ..options()
+        };
+        match load(&mut ctxt, UserSet::User(1), None, &options) {
+            Err(CameleonError::CommandTimeout { command, timeout }) => {
+                assert_eq!(command, "UserSetLoad");
+                assert_eq!(timeout, Duration::from_millis(20));
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_parse_slot() {
+        assert_eq!(parse_slot("Default"), Some(UserSet::Default));
+        assert_eq!(parse_slot("UserSet12"), Some(UserSet::User(12)));
+        assert_eq!(parse_slot("UserSet"), None);
+        assert_eq!(parse_slot("Factory"), None);
+    }
+}
@@ -0,0 +1,60 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Compares iterating the lengths of a `WriteMemStacked` ack lazily with collecting them into
+//! `Vec` as the parser used to do.
+//!
+//! Run with `cargo bench -p cameleon-device --bench write_mem_stacked`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use cameleon_device::u3v::protocol::ack::{AckPacket, WriteMemStacked};
+
+/// Builds a `WriteMemStacked` ack which has `entries` written lengths.
+fn raw_ack(entries: u16) -> Vec<u8> {
+    let mut scd = vec![];
+    for len in 0..entries {
+        scd.extend(&[0, 0]);
+        scd.extend(&len.to_le_bytes());
+    }
+
+    let mut raw = vec![];
+    raw.extend(&0x4356_3355_u32.to_le_bytes());
+    // Status code.
+    raw.extend(&0x0000_u16.to_le_bytes());
+    // Command id.
+    raw.extend(&0x0809_u16.to_le_bytes());
+    raw.extend(&(scd.len() as u16).to_le_bytes());
+    // Request id.
+    raw.extend(&1_u16.to_le_bytes());
+    raw.extend(&scd);
+    raw
+}
+
+fn write_mem_stacked(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write_mem_stacked_ack");
+    for entries in [1, 8, 32] {
+        let raw = raw_ack(entries);
+        let ack = AckPacket::parse(&raw).unwrap();
+
+        group.bench_with_input(BenchmarkId::new("lazy", entries), &ack, |b, ack| {
+            b.iter(|| {
+                let entries = black_box(ack).scd_as::<WriteMemStacked>().unwrap().entries;
+                entries.map(|len| u32::from(len.unwrap())).sum::<u32>()
+            });
+        });
+
+        group.bench_with_input(BenchmarkId::new("collected", entries), &ack, |b, ack| {
+            b.iter(|| {
+                let entries = black_box(ack).scd_as::<WriteMemStacked>().unwrap().entries;
+                let lengths = entries.collect::<Result<Vec<u16>, _>>().unwrap();
+                lengths.iter().map(|&len| u32::from(len)).sum::<u32>()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, write_mem_stacked);
+criterion_main!(benches);
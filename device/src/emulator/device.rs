@@ -2,11 +2,14 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use crate::u3v::{DeviceInfo, Result};
+use crate::{
+    fixture::FaultKind,
+    u3v::{DeviceInfo, Result},
+};
 
 use super::{
     channel::{ControlChannel, ReceiveChannel},
-    emulator_impl::{DeviceHandle, IfaceKind},
+    emulator_impl::{DeviceHandle, DevicePool, IfaceKind},
 };
 
 pub struct Device {
@@ -30,6 +33,39 @@ impl Device {
         Ok(Some(ReceiveChannel::new(handle)))
     }
 
+    /// Unplugs the emulated device from the host.
+    ///
+    /// The device disappears from [`super::enumerate_devices`], and transfers on the channels
+    /// opened before fail with `NoDevice` as if a real device were unplugged. The device keeps its
+    /// memory, so it's found again with the same GUID after [`Device::replug`].
+    pub fn unplug(&self) -> Result<()> {
+        log::info! {"{}: unplug device", self.log_name()};
+        DevicePool::with(|pool| pool.unplug(self.device_id))
+    }
+
+    /// Injects `kind` to the next control command, before the faults of the fixture which the
+    /// device is built from.
+    ///
+    /// [`FaultKind::RejectPayloadSize`] is injected to the next command writing the payload
+    /// transfer size instead.
+    pub fn inject_fault(&self, kind: FaultKind) -> Result<()> {
+        DevicePool::with(|pool| pool.inject_fault(self.device_id, kind))
+    }
+
+    /// Sets the number of control commands the device processes at once, see
+    /// [`super::EmulatorBuilder::command_queue_depth`].
+    ///
+    /// Commands already in process are kept even if they exceed the new depth.
+    pub fn set_command_queue_depth(&self, depth: usize) -> Result<()> {
+        DevicePool::with(|pool| pool.set_command_queue_depth(self.device_id, depth))
+    }
+
+    /// Plugs the device unplugged by [`Device::unplug`] into the host again.
+    pub fn replug(&self) -> Result<()> {
+        log::info! {"{}: replug device", self.log_name()};
+        DevicePool::with(|pool| pool.replug(self.device_id))
+    }
+
     pub(super) fn new(device_id: u32, device_info: DeviceInfo) -> Self {
         let device = Self {
             device_id,
@@ -0,0 +1,92 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Limits the number of control commands processed at once.
+///
+/// A command sent while the queue is full is answered with `Busy`, as a device whose command
+/// queue depth is exceeded does.
+pub(super) struct CommandQueue {
+    depth: AtomicUsize,
+    processing: AtomicUsize,
+    /// Time to process each command.
+    latency: Duration,
+}
+
+impl CommandQueue {
+    pub(super) fn new(depth: usize, latency: Duration) -> Self {
+        Self {
+            depth: AtomicUsize::new(depth),
+            processing: AtomicUsize::new(0),
+            latency,
+        }
+    }
+
+    pub(super) fn set_depth(&self, depth: usize) {
+        self.depth.store(depth, Ordering::Relaxed);
+    }
+
+    pub(super) fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Starts processing a command, returns `None` if the queue is full.
+    ///
+    /// The command leaves the queue when the returned slot is dropped.
+    pub(super) fn enter(self: &Arc<Self>) -> Option<CommandSlot> {
+        let depth = self.depth.load(Ordering::Relaxed);
+        self.processing
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |processing| {
+                (processing < depth).then(|| processing + 1)
+            })
+            .ok()?;
+        Some(CommandSlot(self.clone()))
+    }
+}
+
+impl Default for CommandQueue {
+    fn default() -> Self {
+        Self::new(1, Duration::ZERO)
+    }
+}
+
+/// A command in [`CommandQueue`].
+pub(super) struct CommandSlot(Arc<CommandQueue>);
+
+impl Drop for CommandSlot {
+    fn drop(&mut self) {
+        self.0.processing.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depth() {
+        let queue = Arc::new(CommandQueue::new(2, Duration::ZERO));
+        let first = queue.enter().unwrap();
+        let second = queue.enter().unwrap();
+        assert!(queue.enter().is_none());
+
+        drop(first);
+        let third = queue.enter().unwrap();
+
+        // Commands already in the queue are kept when the depth is reduced.
+        queue.set_depth(1);
+        assert!(queue.enter().is_none());
+        drop(second);
+        assert!(queue.enter().is_none());
+        drop(third);
+        assert!(queue.enter().is_some());
+    }
+}
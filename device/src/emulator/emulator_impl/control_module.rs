@@ -2,10 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-};
+use std::{borrow::Cow, ops::Range, sync::Arc, time::Duration};
 
 use async_std::{
     channel::{self, Receiver, Sender},
@@ -14,23 +11,35 @@ use async_std::{
     task,
 };
 
-use cameleon_impl::memory::{prelude::*, MemoryError};
+use cameleon_impl::memory::{prelude::*, MemoryError, MemoryResult};
+
+use crate::fixture::FaultKind;
 
 use super::{
+    command_queue::{CommandQueue, CommandSlot},
     device::Timestamp,
+    fault::FaultInjector,
     interface::IfaceState,
-    memory::{Memory, SBRM},
+    memory::{Memory, EIRM, SBRM, SIRM},
     memory_event_handler::MemoryEventHandler,
     shared_queue::SharedQueue,
     signal::{ControlSignal, InterfaceSignal},
+    user_memory::UserMemory,
     IfaceKind,
 };
 
 use super::control_protocol::{ack, ack::AckSerialize, cmd};
 
+/// Timeout of the pending ack sent by [`FaultKind::Pending`]. The final ack is sent after the half
+/// of it.
+const PENDING_TIMEOUT: Duration = Duration::from_millis(100);
+
 pub(super) struct ControlModule {
     iface_state: IfaceState,
     memory: Arc<Mutex<Memory>>,
+    user_memory: Arc<UserMemory>,
+    faults: Arc<FaultInjector>,
+    commands: Arc<CommandQueue>,
     timestamp: Timestamp,
     queue: SharedQueue<Vec<u8>>,
 }
@@ -39,12 +48,18 @@ impl ControlModule {
     pub(super) fn new(
         iface_state: IfaceState,
         memory: Arc<Mutex<Memory>>,
+        user_memory: Arc<UserMemory>,
+        faults: Arc<FaultInjector>,
+        commands: Arc<CommandQueue>,
         timestamp: Timestamp,
         queue: SharedQueue<Vec<u8>>,
     ) -> Self {
         Self {
             iface_state,
             memory,
+            user_memory,
+            faults,
+            commands,
             timestamp,
             queue,
         }
@@ -60,6 +75,9 @@ impl ControlModule {
         let mut worker_manager = WorkerManager::new(
             self.iface_state.clone(),
             self.memory.clone(),
+            self.user_memory.clone(),
+            self.faults.clone(),
+            self.commands.clone(),
             self.timestamp.clone(),
             event_handler,
             self.queue.clone(),
@@ -76,9 +94,16 @@ impl ControlModule {
 
                 ControlSignal::CancelJobs(_completed) => worker_manager.wait_completion().await,
 
-                ControlSignal::ClearSiRegister | ControlSignal::ClearEiRegister => {
-                    // TODO:
-                    todo!()
+                ControlSignal::ClearSiRegister => {
+                    if let Err(e) = self.memory.lock().await.write::<SIRM::Control>(0) {
+                        log::error!("failed to clear SIRM control: {}", e);
+                    }
+                }
+
+                ControlSignal::ClearEiRegister => {
+                    if let Err(e) = self.memory.lock().await.write::<EIRM::Control>(0) {
+                        log::error!("failed to clear EIRM control: {}", e);
+                    }
                 }
 
                 ControlSignal::Shutdown => {
@@ -93,13 +118,14 @@ impl ControlModule {
 struct WorkerManager {
     iface_state: IfaceState,
     memory: Arc<Mutex<Memory>>,
+    user_memory: Arc<UserMemory>,
+    faults: Arc<FaultInjector>,
+    commands: Arc<CommandQueue>,
     timestamp: Timestamp,
 
     queue: SharedQueue<Vec<u8>>,
     signal_tx: Sender<InterfaceSignal>,
 
-    on_processing: Arc<AtomicBool>,
-
     memory_event_handler: MemoryEventHandler,
 
     maximum_cmd_length: usize,
@@ -114,16 +140,19 @@ struct WorkerManager {
 }
 
 impl WorkerManager {
+    #[allow(clippy::too_many_arguments)]
     async fn new(
         iface_state: IfaceState,
         memory: Arc<Mutex<Memory>>,
+        user_memory: Arc<UserMemory>,
+        faults: Arc<FaultInjector>,
+        commands: Arc<CommandQueue>,
         timestamp: Timestamp,
         memory_event_handler: MemoryEventHandler,
         queue: SharedQueue<Vec<u8>>,
         signal_tx: Sender<InterfaceSignal>,
     ) -> Self {
         let (completed_tx, completed_rx) = channel::bounded(1);
-        let on_processing = Arc::new(AtomicBool::new(false));
         let (maximum_cmd_length, maximum_ack_length) = {
             let memory = memory.lock().await;
             (
@@ -137,13 +166,14 @@ impl WorkerManager {
         Self {
             iface_state,
             memory,
+            user_memory,
+            faults,
+            commands,
             timestamp,
 
             queue,
             signal_tx,
 
-            on_processing,
-
             memory_event_handler,
 
             maximum_cmd_length,
@@ -158,13 +188,15 @@ impl WorkerManager {
         Worker {
             iface_state: self.iface_state.clone(),
             memory: self.memory.clone(),
+            user_memory: self.user_memory.clone(),
+            faults: self.faults.clone(),
+            commands: self.commands.clone(),
+            slot: std::sync::Mutex::new(None),
             timestamp: self.timestamp.clone(),
 
             queue: self.queue.clone(),
             signal_tx: self.signal_tx.clone(),
 
-            on_processing: self.on_processing.clone(),
-
             memory_event_handler: self.memory_event_handler.clone(),
 
             maximum_cmd_length: self.maximum_cmd_length,
@@ -189,13 +221,16 @@ impl WorkerManager {
 pub(super) struct Worker {
     iface_state: IfaceState,
     pub(super) memory: Arc<Mutex<Memory>>,
+    user_memory: Arc<UserMemory>,
+    faults: Arc<FaultInjector>,
+    commands: Arc<CommandQueue>,
+    /// Slot of the command in the command queue, which is released when the ack is sent.
+    slot: std::sync::Mutex<Option<CommandSlot>>,
     pub(super) timestamp: Timestamp,
 
     queue: SharedQueue<Vec<u8>>,
     signal_tx: Sender<InterfaceSignal>,
 
-    on_processing: Arc<AtomicBool>,
-
     memory_event_handler: MemoryEventHandler,
 
     maximum_cmd_length: usize,
@@ -205,7 +240,6 @@ pub(super) struct Worker {
 }
 
 impl Worker {
-    // TODO: Emulate pending situation.
     async fn run(self, command: Vec<u8>) {
         let cmd_packet = match self.try_parse_command(&command) {
             Some(packet) => packet,
@@ -213,6 +247,15 @@ impl Worker {
         };
         let ccd = cmd_packet.ccd();
 
+        if let Some(fault) = self
+            .faults
+            .inject(writes_payload_transfer_size(&cmd_packet))
+        {
+            if self.inject_fault(fault, &cmd_packet).await {
+                return;
+            }
+        }
+
         // If sent command length is larger than SBRM::MaximumCommandTransferLength, return error.
         if (self.maximum_cmd_length) < command.len() {
             let ack = ack::ErrorAck::new(ack::GenCpStatus::InvalidParameter, ccd.scd_kind())
@@ -236,16 +279,19 @@ impl Worker {
             return;
         }
 
-        // If another thread is processing command simultaneously, return busy error ack.
-        if self
-            .on_processing
-            .compare_exchange(true, false, Ordering::Relaxed, Ordering::Relaxed)
-            .is_ok()
-        {
-            let ack = ack::ErrorAck::new(ack::GenCpStatus::Busy, ccd.scd_kind())
-                .finalize(ccd.request_id());
-            self.enqueue_or_halt(&ack);
-            return;
+        // If the command queue is full, return busy error ack.
+        match self.commands.enter() {
+            Some(slot) => *self.slot.lock().unwrap() = Some(slot),
+            None => {
+                let ack = ack::ErrorAck::new(ack::GenCpStatus::Busy, ccd.scd_kind())
+                    .finalize(ccd.request_id());
+                self.enqueue_or_halt(&ack);
+                return;
+            }
+        }
+        let latency = self.commands.latency();
+        if !latency.is_zero() {
+            task::sleep(latency).await;
         }
 
         match ccd.scd_kind() {
@@ -253,9 +299,8 @@ impl Worker {
             cmd::ScdKind::WriteMem => self.process_write_mem(cmd_packet).await,
             cmd::ScdKind::ReadMemStacked => self.process_read_mem_stacked(cmd_packet).await,
             cmd::ScdKind::WriteMemStacked => self.process_write_mem_stacked(cmd_packet).await,
+            cmd::ScdKind::Custom(_) => self.process_custom(cmd_packet),
         }
-
-        self.on_processing.store(false, Ordering::Relaxed);
     }
 
     fn try_parse_command<'a>(&self, command: &'a [u8]) -> Option<cmd::CommandPacket<'a>> {
@@ -288,9 +333,9 @@ impl Worker {
         let address = scd.address as usize;
         let read_length = scd.read_length as usize;
 
-        match memory.read_raw(address..address + read_length) {
+        match self.read_raw(&memory, address..address + read_length) {
             Ok(data) => {
-                let ack = ack::ReadMem::new(data).finalize(req_id);
+                let ack = ack::ReadMem::new(&data).finalize(req_id);
                 self.enqueue_or_halt(&ack);
             }
 
@@ -322,7 +367,7 @@ impl Worker {
         let scd_kind = ccd.scd_kind();
 
         let mut memory = self.memory.lock().await;
-        match memory.write_raw(scd.address as usize, scd.data) {
+        match self.write_raw(&mut memory, scd.address as usize, scd.data) {
             Ok(()) => {
                 // Explicitly drop memory to avoid race condition.
                 drop(memory);
@@ -358,7 +403,7 @@ impl Worker {
     }
 
     async fn process_read_mem_stacked(&self, command: cmd::CommandPacket<'_>) {
-        let _scd: cmd::WriteMemStacked = match self.try_extract_scd(&command) {
+        let scd: cmd::ReadMemStacked = match self.try_extract_scd(&command) {
             Some(scd) => scd,
             None => return,
         };
@@ -366,13 +411,41 @@ impl Worker {
         let req_id = ccd.request_id();
         let scd_kind = ccd.scd_kind();
 
-        // TODO: Should we implement this command?
-        let ack = ack::ErrorAck::new(ack::GenCpStatus::NotImplemented, scd_kind).finalize(req_id);
+        let memory = self.memory.lock().await;
+        let mut data = vec![];
+        for entry in &scd.entries {
+            let address = entry.address as usize;
+            let read_length = entry.read_length as usize;
+            match self.read_raw(&memory, address..address + read_length) {
+                Ok(read) => data.extend_from_slice(&read),
+
+                Err(MemoryError::InvalidAddress) => {
+                    let ack = ack::ErrorAck::new(ack::GenCpStatus::InvalidAddress, scd_kind)
+                        .finalize(req_id);
+                    self.enqueue_or_halt(&ack);
+                    return;
+                }
+
+                Err(MemoryError::AddressNotReadable) => {
+                    let ack = ack::ErrorAck::new(ack::GenCpStatus::AccessDenied, scd_kind)
+                        .finalize(req_id);
+                    self.enqueue_or_halt(&ack);
+                    return;
+                }
+
+                Err(MemoryError::AddressNotWritable)
+                | Err(MemoryError::InvalidRegisterData(..)) => {
+                    unreachable!()
+                }
+            }
+        }
+
+        let ack = ack::ReadMemStacked::new(&data).finalize(req_id);
         self.enqueue_or_halt(&ack);
     }
 
     async fn process_write_mem_stacked(&self, command: cmd::CommandPacket<'_>) {
-        let _scd: cmd::WriteMemStacked = match self.try_extract_scd(&command) {
+        let scd: cmd::WriteMemStacked = match self.try_extract_scd(&command) {
             Some(scd) => scd,
             None => return,
         };
@@ -380,8 +453,96 @@ impl Worker {
         let req_id = ccd.request_id();
         let scd_kind = ccd.scd_kind();
 
-        // TODO: Should we implement this command?
-        let ack = ack::ErrorAck::new(ack::GenCpStatus::NotImplemented, scd_kind).finalize(req_id);
+        // Entries are written in order, and the ack reports an error of the first failing entry
+        // like a sequence of `WriteMem`.
+        let mut lengths = Vec::with_capacity(scd.entries.len());
+        for entry in &scd.entries {
+            let mut memory = self.memory.lock().await;
+            let error_status = match self.write_raw(&mut memory, entry.address as usize, entry.data)
+            {
+                Ok(()) => {
+                    // Explicitly drop memory to avoid race condition.
+                    drop(memory);
+
+                    if let Err(error_ack) = self
+                        .memory_event_handler
+                        .handle_events(self, scd_kind)
+                        .await
+                    {
+                        self.enqueue_or_halt(&error_ack.finalize(req_id));
+                        return;
+                    }
+                    lengths.push(entry.data.len() as u16);
+                    continue;
+                }
+
+                Err(MemoryError::InvalidAddress) => ack::GenCpStatus::InvalidAddress,
+                Err(MemoryError::AddressNotWritable) => ack::GenCpStatus::WriteProtect,
+                Err(MemoryError::AddressNotReadable)
+                | Err(MemoryError::InvalidRegisterData(..)) => {
+                    unreachable!()
+                }
+            };
+
+            let ack = ack::ErrorAck::new(error_status, scd_kind).finalize(req_id);
+            self.enqueue_or_halt(&ack);
+            return;
+        }
+
+        let ack = ack::WriteMemStacked::new(lengths).finalize(req_id);
+        self.enqueue_or_halt(&ack);
+    }
+
+    /// Reads `range` from the user memory if it's placed there, otherwise from `memory`.
+    fn read_raw<'a>(&self, memory: &'a Memory, range: Range<usize>) -> MemoryResult<Cow<'a, [u8]>> {
+        match self.user_memory.read(range.clone()) {
+            Some(data) => data.map(Cow::Owned),
+            None => memory.read_raw(range).map(Cow::Borrowed),
+        }
+    }
+
+    /// Writes `data` to the user memory if it's placed there, otherwise to `memory`.
+    fn write_raw(&self, memory: &mut Memory, address: usize, data: &[u8]) -> MemoryResult<()> {
+        match self.user_memory.write(address, data) {
+            Some(result) => result,
+            None => memory.write_raw(address, data),
+        }
+    }
+
+    /// Responds to `command` as `fault`, and returns `true` if the command must not be handled.
+    async fn inject_fault(&self, fault: FaultKind, command: &cmd::CommandPacket<'_>) -> bool {
+        let ccd = command.ccd();
+        let ack = match fault {
+            // Nothing is sent back.
+            FaultKind::Timeout => return true,
+            FaultKind::Stall => {
+                self.try_send_signal(InterfaceSignal::Halt(IfaceKind::Control));
+                return true;
+            }
+            FaultKind::Disconnect => {
+                self.try_send_signal(InterfaceSignal::Disconnect);
+                return true;
+            }
+            FaultKind::Pending => {
+                let ack = ack::Pending::new(PENDING_TIMEOUT).finalize(ccd.request_id());
+                self.enqueue_or_halt(&ack);
+                task::sleep(PENDING_TIMEOUT / 2).await;
+                return false;
+            }
+            FaultKind::Busy => ack::ErrorAck::new(ack::GenCpStatus::Busy, ccd.scd_kind()),
+            FaultKind::RejectPayloadSize => ack::ErrorAck::new(
+                ack::UsbSpecificStatus::PayloadSizeNotAligned,
+                ccd.scd_kind(),
+            ),
+        };
+        self.enqueue_or_halt(&ack.finalize(ccd.request_id()));
+        true
+    }
+
+    fn process_custom(&self, command: cmd::CommandPacket<'_>) {
+        let ccd = command.ccd();
+        let ack = ack::ErrorAck::new(ack::GenCpStatus::NotImplemented, ccd.scd_kind())
+            .finalize(ccd.request_id());
         self.enqueue_or_halt(&ack);
     }
 
@@ -422,6 +583,9 @@ impl Worker {
             buf
         };
 
+        // Leave the command queue before the host receives the ack, so that the next command
+        // isn't rejected.
+        self.slot.lock().unwrap().take();
         if !self.queue.enqueue(buf) {
             log::warn!("control queue is full, entering a halted state");
             self.try_send_signal(InterfaceSignal::Halt(IfaceKind::Control));
@@ -437,3 +601,26 @@ impl Worker {
         }
     }
 }
+
+/// Returns `true` if `command` writes the payload transfer size of `SIRM`.
+fn writes_payload_transfer_size(command: &cmd::CommandPacket<'_>) -> bool {
+    use SIRM::PayloadTransferSize;
+    let target = PayloadTransferSize::ADDRESS as u64
+        ..(PayloadTransferSize::ADDRESS + PayloadTransferSize::LENGTH) as u64;
+    let overlaps =
+        |address: u64, len: usize| address < target.end && target.start < address + len as u64;
+
+    match command.ccd().scd_kind() {
+        cmd::ScdKind::WriteMem => command
+            .scd_as::<cmd::WriteMem>()
+            .is_ok_and(|scd| overlaps(scd.address, scd.data.len())),
+        cmd::ScdKind::WriteMemStacked => {
+            command.scd_as::<cmd::WriteMemStacked>().is_ok_and(|scd| {
+                scd.entries
+                    .iter()
+                    .any(|entry| overlaps(entry.address, entry.data.len()))
+            })
+        }
+        _ => false,
+    }
+}
@@ -103,6 +103,9 @@ pub(super) mod cmd {
                 0x0802 => Ok(Self::WriteMem),
                 0x0806 => Ok(Self::ReadMemStacked),
                 0x0808 => Ok(Self::WriteMemStacked),
+                // Ids with the most significant bit set are reserved for custom commands, and
+                // the id of the ack is the command id + 1.
+                id if id & 0x8000 != 0 && id & 1 == 0 && id != u16::MAX - 1 => Ok(Self::Custom(id)),
                 _ => Err(ProtocolError::InvalidPacket("invalid  command id".into())),
             }
         }
@@ -191,7 +194,7 @@ pub(super) mod cmd {
     #[cfg(test)]
     mod tests {
         use super::*;
-        use crate::u3v::protocol::cmd::CommandScd;
+        use crate::u3v::protocol::cmd::{CommandScd, CustomCommand};
 
         #[test]
         fn test_read_mem() {
@@ -270,6 +273,23 @@ pub(super) mod cmd {
             assert_eq!(parsed_scd.entries[1].address, 0xf0);
             assert_eq!(parsed_scd.entries[1].data, data1);
         }
+
+        #[test]
+        fn test_custom() {
+            let cmd = CustomCommand::new(0x9000, &[0, 1], 4).unwrap().finalize(1);
+            let mut buf = vec![];
+            cmd.serialize(&mut buf).unwrap();
+
+            let parsed_cmd = CommandPacket::parse(&buf).unwrap();
+            assert_eq!(parsed_cmd.ccd.scd_kind(), ScdKind::Custom(0x9000));
+            assert_eq!(parsed_cmd.ccd.request_id(), 1);
+
+            // Odd ids and ids without the custom bit aren't commands.
+            for id in [0x9001_u16, 0x0804] {
+                buf[6..8].copy_from_slice(&id.to_le_bytes());
+                assert!(CommandPacket::parse(&buf).is_err());
+            }
+        }
     }
 }
 
@@ -278,7 +298,7 @@ pub(super) mod ack {
     use std::{convert::TryFrom, io::Write, time};
 
     use crate::u3v::protocol::{
-        ack::{AckCcd, Status, StatusKind},
+        ack::{AckCcd, Status},
         cmd,
         util::WriteBytes,
     };
@@ -286,7 +306,6 @@ pub(super) mod ack {
     use super::ProtocolResult;
     pub(in super::super) use crate::u3v::protocol::ack::{
         GenCpStatus, Pending, ReadMem, ReadMemStacked, ScdKind, UsbSpecificStatus, WriteMem,
-        WriteMemStacked,
     };
 
     pub(in super::super) struct AckPacket<T> {
@@ -302,48 +321,22 @@ pub(super) mod ack {
 
         pub(in super::super) fn serialize(&self, mut buf: impl Write) -> ProtocolResult<()> {
             buf.write_bytes(Self::PREFIX_MAGIC)?;
-            self.ccd.serialize(&mut buf)?;
+            buf.write_bytes(self.ccd.status().code())?;
+            buf.write_bytes(self.ccd.scd_kind().id())?;
+            buf.write_bytes(self.ccd.scd_len())?;
+            buf.write_bytes(self.ccd.request_id())?;
             self.scd.serialize(&mut buf)?;
             Ok(())
         }
 
         fn from_scd(scd: T, request_id: u16) -> Self {
-            let ccd = AckCcd::new(&scd, request_id);
-            Self { ccd, scd }
-        }
-    }
-
-    impl AckCcd {
-        fn new(scd: &impl AckSerialize, request_id: u16) -> Self {
-            Self {
+            let ccd = AckCcd {
                 status: scd.status(),
                 scd_kind: scd.scd_kind(),
                 request_id,
                 scd_len: scd.scd_len(),
-            }
-        }
-
-        fn serialize(&self, mut buf: impl Write) -> ProtocolResult<()> {
-            buf.write_bytes(self.status().code())?;
-            self.scd_kind().serialize(&mut buf)?;
-            buf.write_bytes(self.scd_len())?;
-            buf.write_bytes(self.request_id())?;
-            Ok(())
-        }
-    }
-
-    impl ScdKind {
-        fn serialize(self, mut buf: impl Write) -> ProtocolResult<()> {
-            let raw: u16 = match self {
-                Self::ReadMem => 0x0801,
-                Self::WriteMem => 0x0803,
-                Self::Pending => 0x0805,
-                Self::ReadMemStacked => 0x0807,
-                Self::WriteMemStacked => 0x0809,
             };
-
-            buf.write_bytes(raw)?;
-            Ok(())
+            Self { ccd, scd }
         }
     }
 
@@ -370,7 +363,7 @@ pub(super) mod ack {
 
     impl<'a> AckSerialize for ReadMem<'a> {
         fn serialize(&self, mut buf: impl Write) -> ProtocolResult<()> {
-            buf.write_all(&self.data)?;
+            buf.write_all(self.data)?;
             Ok(())
         }
 
@@ -389,7 +382,7 @@ pub(super) mod ack {
         }
     }
 
-    impl<'a> AckSerialize for WriteMem {
+    impl AckSerialize for WriteMem {
         fn serialize(&self, mut buf: impl Write) -> ProtocolResult<()> {
             buf.write_bytes(0_u16)?;
             buf.write_bytes(self.length)?;
@@ -406,8 +399,8 @@ pub(super) mod ack {
     }
 
     impl Pending {
-        pub(in super::super) fn _new(timeout: time::Duration) -> Self {
-            debug_assert!(timeout.as_millis() <= u128::from(std::u16::MAX));
+        pub(in super::super) fn new(timeout: time::Duration) -> Self {
+            debug_assert!(timeout.as_millis() <= u128::from(u16::MAX));
             Self { timeout }
         }
     }
@@ -429,7 +422,7 @@ pub(super) mod ack {
     }
 
     impl<'a> ReadMemStacked<'a> {
-        pub(in super::super) fn _new(data: &'a [u8]) -> Self {
+        pub(in super::super) fn new(data: &'a [u8]) -> Self {
             debug_assert!(u16::try_from(data.len()).is_ok());
             Self { data }
         }
@@ -437,7 +430,7 @@ pub(super) mod ack {
 
     impl<'a> AckSerialize for ReadMemStacked<'a> {
         fn serialize(&self, mut buf: impl Write) -> ProtocolResult<()> {
-            buf.write_all(&self.data)?;
+            buf.write_all(self.data)?;
             Ok(())
         }
 
@@ -450,8 +443,13 @@ pub(super) mod ack {
         }
     }
 
+    /// Scd of `WriteMemStacked` ack, which holds the written length of each entry.
+    pub(in super::super) struct WriteMemStacked {
+        lengths: Vec<u16>,
+    }
+
     impl WriteMemStacked {
-        pub(in super::super) fn _new(lengths: Vec<u16>) -> Self {
+        pub(in super::super) fn new(lengths: Vec<u16>) -> Self {
             debug_assert!(u16::try_from(Self::scd_len(&lengths)).is_ok());
             Self { lengths }
         }
@@ -515,61 +513,6 @@ pub(super) mod ack {
         }
     }
 
-    impl GenCpStatus {
-        fn as_code(self) -> u16 {
-            use GenCpStatus::{
-                AccessDenied, BadAlignment, Busy, GenericError, InvalidAddress, InvalidHeader,
-                InvalidParameter, NotImplemented, Success, Timeout, WriteProtect, WrongConfig,
-            };
-            match self {
-                Success => 0x0000,
-                NotImplemented => 0x8001,
-                InvalidParameter => 0x8002,
-                InvalidAddress => 0x8003,
-                WriteProtect => 0x8004,
-                BadAlignment => 0x8005,
-                AccessDenied => 0x8006,
-                Busy => 0x8007,
-                Timeout => 0x800B,
-                InvalidHeader => 0x800E,
-                WrongConfig => 0x800F,
-                GenericError => 0x8FFF,
-            }
-        }
-    }
-
-    impl UsbSpecificStatus {
-        fn as_code(self) -> u16 {
-            use UsbSpecificStatus::{
-                EventEndpointHalted, InvalidSiState, PayloadSizeNotAligned, ResendNotSupported,
-                StreamEndpointHalted,
-            };
-            match self {
-                ResendNotSupported => 0xA001,
-                StreamEndpointHalted => 0xA002,
-                PayloadSizeNotAligned => 0xA003,
-                InvalidSiState => 0xA004,
-                EventEndpointHalted => 0xA005,
-            }
-        }
-    }
-
-    impl From<GenCpStatus> for Status {
-        fn from(cp_status: GenCpStatus) -> Status {
-            let code = cp_status.as_code();
-            let kind = StatusKind::GenCp(cp_status);
-            Self { code, kind }
-        }
-    }
-
-    impl From<UsbSpecificStatus> for Status {
-        fn from(usb_status: UsbSpecificStatus) -> Status {
-            let code = usb_status.as_code();
-            let kind = StatusKind::UsbSpecific(usb_status);
-            Self { code, kind }
-        }
-    }
-
     impl From<cmd::ScdKind> for ScdKind {
         fn from(kind: cmd::ScdKind) -> Self {
             match kind {
@@ -577,6 +520,8 @@ pub(super) mod ack {
                 cmd::ScdKind::WriteMem => ScdKind::WriteMem,
                 cmd::ScdKind::ReadMemStacked => ScdKind::ReadMemStacked,
                 cmd::ScdKind::WriteMemStacked => ScdKind::WriteMemStacked,
+                // Ack id of a custom command is the command id plus one.
+                cmd::ScdKind::Custom(id) => ScdKind::Custom(id + 1),
             }
         }
     }
@@ -584,7 +529,7 @@ pub(super) mod ack {
     #[cfg(test)]
     mod tests {
         use super::*;
-        use crate::u3v::protocol::ack as host_side_ack;
+        use crate::u3v::protocol::ack::{self as host_side_ack, StatusKind};
 
         #[test]
         fn test_read_mem() {
@@ -620,7 +565,7 @@ pub(super) mod ack {
         #[test]
         fn test_pending() {
             let timeout = time::Duration::from_millis(700);
-            let command = Pending::_new(timeout).finalize(1);
+            let command = Pending::new(timeout).finalize(1);
             let mut buf = vec![];
             command.serialize(&mut buf).unwrap();
 
@@ -636,7 +581,7 @@ pub(super) mod ack {
         #[test]
         fn test_read_mem_stacked() {
             let data = &[0, 1, 2, 3, 4, 5, 6, 7, 8];
-            let command = ReadMemStacked::_new(data).finalize(1);
+            let command = ReadMemStacked::new(data).finalize(1);
             let mut buf = vec![];
             command.serialize(&mut buf).unwrap();
 
@@ -652,7 +597,7 @@ pub(super) mod ack {
         #[test]
         fn test_write_mem_stacked() {
             let lengths = vec![8, 16];
-            let command = WriteMemStacked::_new(lengths.clone()).finalize(1);
+            let command = WriteMemStacked::new(lengths.clone()).finalize(1);
             let mut buf = vec![];
             command.serialize(&mut buf).unwrap();
 
@@ -661,8 +606,9 @@ pub(super) mod ack {
             assert_eq!(parsed.scd_kind(), ScdKind::WriteMemStacked);
             assert_eq!(parsed.request_id(), 1);
 
-            let parsed_scd = parsed.scd_as::<WriteMemStacked>().unwrap();
-            assert_eq!(parsed_scd.lengths, lengths);
+            let parsed_scd = parsed.scd_as::<host_side_ack::WriteMemStacked>().unwrap();
+            let parsed_lengths: Vec<u16> = parsed_scd.entries.map(Result::unwrap).collect();
+            assert_eq!(parsed_lengths, lengths);
         }
 
         #[test]
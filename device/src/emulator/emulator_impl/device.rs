@@ -11,12 +11,18 @@ use async_std::{
 };
 use futures::channel::oneshot;
 
-use crate::u3v::DeviceInfo;
+use crate::{
+    fixture::{FaultKind, StreamSettings},
+    u3v::DeviceInfo,
+};
 
 use super::{
+    command_queue::CommandQueue,
     fake_protocol::{FakeAckPacket, FakeReqPacket},
+    fault::FaultInjector,
     interface::Interface,
     memory::Memory,
+    user_memory::UserMemory,
 };
 
 const REQ_PACKET_CHANNEL_CAPACITY: usize = 1;
@@ -25,16 +31,31 @@ const ACK_PACKET_CHANNEL_CAPACITY: usize = 1;
 pub(super) struct Device {
     timestamp: Timestamp,
     memory: Arc<Mutex<Memory>>,
+    user_memory: Arc<UserMemory>,
+    faults: Arc<FaultInjector>,
+    commands: Arc<CommandQueue>,
+    stream_settings: StreamSettings,
     shutdown_tx: Option<oneshot::Sender<()>>,
     completion_rx: Option<oneshot::Receiver<()>>,
     device_info: DeviceInfo,
 }
 
 impl Device {
-    pub(super) fn new(memory: Memory, device_info: DeviceInfo) -> Self {
+    pub(super) fn new(
+        memory: Memory,
+        user_memory: UserMemory,
+        faults: FaultInjector,
+        commands: CommandQueue,
+        stream_settings: StreamSettings,
+        device_info: DeviceInfo,
+    ) -> Self {
         Self {
             timestamp: Timestamp::new(),
             memory: Arc::new(Mutex::new(memory)),
+            user_memory: Arc::new(user_memory),
+            faults: Arc::new(faults),
+            commands: Arc::new(commands),
+            stream_settings,
             shutdown_tx: None,
             completion_rx: None,
             device_info,
@@ -53,12 +74,15 @@ impl Device {
         self.completion_rx = Some(completion_rx);
 
         task::spawn(
-            Interface::new(self.memory.clone(), self.timestamp.clone()).run(
-                ack_tx,
-                req_rx,
-                shutdown_rx,
-                completion_tx,
-            ),
+            Interface::new(
+                self.memory.clone(),
+                self.user_memory.clone(),
+                self.faults.clone(),
+                self.commands.clone(),
+                self.timestamp.clone(),
+                self.stream_settings.clone(),
+            )
+            .run(ack_tx, req_rx, shutdown_rx, completion_tx),
         );
 
         (req_tx, ack_rx)
@@ -77,6 +101,14 @@ impl Device {
     pub(super) fn device_info(&self) -> &DeviceInfo {
         &self.device_info
     }
+
+    pub(super) fn inject_fault(&self, kind: FaultKind) {
+        self.faults.queue(kind);
+    }
+
+    pub(super) fn set_command_queue_depth(&self, depth: usize) {
+        self.commands.set_depth(depth);
+    }
 }
 
 impl Drop for Device {
@@ -63,24 +63,17 @@ impl DeviceHandle {
     }
 
     pub(crate) fn write_bulk(&self, buf: &[u8], timeout: Duration) -> Result<usize> {
-        let start = Instant::now();
-
-        while timeout.checked_sub(start.elapsed()).is_some() {
-            let req = FakeReqPacket::new(self.iface_kind, FakeReqKind::Send(buf.to_vec()));
-            let ack = self.send_packet(req)?;
-
-            match ack.kind {
-                SendAck => {
-                    return Ok(buf.len());
-                }
-                IfaceHalted => {
-                    return Err(LibUsbError::Pipe.into());
-                }
-                _ => unreachable!(),
-            }
+        if timeout.is_zero() {
+            return Err(LibUsbError::Timeout.into());
         }
 
-        Err(LibUsbError::Timeout.into())
+        let req = FakeReqPacket::new(self.iface_kind, FakeReqKind::Send(buf.to_vec()));
+        let ack = self.send_packet(req)?;
+        match ack.kind {
+            SendAck => Ok(buf.len()),
+            IfaceHalted => Err(LibUsbError::Pipe.into()),
+            _ => unreachable!(),
+        }
     }
 
     pub(crate) fn set_halt(&self) -> Result<()> {
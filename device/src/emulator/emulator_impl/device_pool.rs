@@ -11,7 +11,10 @@ use async_std::{
 };
 use lazy_static::lazy_static;
 
-use crate::u3v::{DeviceInfo, LibUsbError, Result};
+use crate::{
+    fixture::FaultKind,
+    u3v::{DeviceInfo, LibUsbError, Result},
+};
 
 use super::{
     device::Device,
@@ -35,8 +38,13 @@ impl DevicePool {
         Ok(ctx.device_info())
     }
 
+    /// IDs of the devices plugged into the host.
     pub(crate) fn device_ids(&self) -> Vec<u32> {
-        self.contexts.iter().map(|ctx| ctx.device_id).collect()
+        self.contexts
+            .iter()
+            .filter(|ctx| ctx.is_plugged())
+            .map(|ctx| ctx.device_id)
+            .collect()
     }
 
     pub(crate) fn with<F, R>(f: F) -> R
@@ -44,7 +52,7 @@ impl DevicePool {
         F: FnOnce(&mut DevicePool) -> R,
     {
         let mut pool = task::block_on(DEVICE_POOL.lock());
-        f(&mut *pool)
+        f(&mut pool)
     }
 
     pub(super) fn claim_interface(
@@ -61,6 +69,26 @@ impl DevicePool {
         Ok(())
     }
 
+    pub(crate) fn unplug(&mut self, device_id: u32) -> Result<()> {
+        self.ctx_mut(device_id)?.unplug();
+        Ok(())
+    }
+
+    pub(crate) fn replug(&mut self, device_id: u32) -> Result<()> {
+        self.ctx_mut(device_id)?.replug();
+        Ok(())
+    }
+
+    pub(crate) fn inject_fault(&self, device_id: u32, kind: FaultKind) -> Result<()> {
+        self.ctx(device_id)?.device.inject_fault(kind);
+        Ok(())
+    }
+
+    pub(crate) fn set_command_queue_depth(&self, device_id: u32, depth: usize) -> Result<()> {
+        self.ctx(device_id)?.device.set_command_queue_depth(depth);
+        Ok(())
+    }
+
     pub(super) fn pool_and_run(&mut self, device: Device) {
         let ctx = Context::run(device, self.next_id);
 
@@ -99,6 +127,9 @@ struct Context {
     /// Hold interface state.
     /// Currently just holds claimed state.
     iface_state: HashMap<IfaceKind, bool>,
+
+    /// `false` while the device is unplugged from the host.
+    is_plugged: bool,
 }
 
 impl Context {
@@ -117,11 +148,14 @@ impl Context {
             device_id,
             channel: Arc::new(Mutex::new(channel)),
             iface_state,
+            is_plugged: true,
         }
     }
 
     fn claim_interface(&mut self, iface: IfaceKind) -> Result<DevicePipe> {
-        if self.is_claimed(iface) {
+        if !self.is_plugged() {
+            Err(LibUsbError::NoDevice.into())
+        } else if self.is_claimed(iface) {
             Err(LibUsbError::Busy.into())
         } else {
             *self.iface_state.get_mut(&iface).unwrap() = true;
@@ -137,6 +171,32 @@ impl Context {
         self.iface_state[&iface]
     }
 
+    fn is_plugged(&self) -> bool {
+        self.is_plugged
+    }
+
+    /// Shuts down the device while keeping its memory.
+    ///
+    /// Pipes handed out before are closed by the shutdown, so transfers on them fail with
+    /// `NoDevice` in the same way as a real device.
+    fn unplug(&mut self) {
+        self.device.shutdown();
+        self.is_plugged = false;
+    }
+
+    /// Restarts the device unplugged by [`Context::unplug`] with a fresh pipe.
+    fn replug(&mut self) {
+        if self.is_plugged {
+            return;
+        }
+
+        self.channel = Arc::new(Mutex::new(self.device.run()));
+        for claimed in self.iface_state.values_mut() {
+            *claimed = false;
+        }
+        self.is_plugged = true;
+    }
+
     fn device_info(&self) -> DeviceInfo {
         self.device.device_info().clone()
     }
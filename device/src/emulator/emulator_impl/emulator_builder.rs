@@ -2,40 +2,62 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use std::{fs, path::Path, time::Duration};
+
 use rand::seq::SliceRandom;
 use semver::Version;
 use thiserror::Error;
 
-use crate::u3v::{BusSpeed, DeviceInfo};
+use crate::{
+    fixture::{
+        self, DeviceIdentity, Fixture, FixtureError, GenApiSource, RegisterDef, RegisterType,
+        RegisterValue,
+    },
+    u3v::{BusSpeed, DeviceInfo},
+    Guid,
+};
 
 use super::{
+    command_queue::CommandQueue,
     device::Device,
     device_pool::DevicePool,
-    memory::{Memory, ABRM, SBRM},
+    fault::FaultInjector,
+    memory::{ManifestTable, Memory, ABRM, MEMORY_END, SBRM, SIRM},
+    stream_module,
+    user_memory::UserMemory,
 };
 
-use cameleon_impl::memory::prelude::*;
+use cameleon_impl::memory::{prelude::*, AccessRight};
 
 #[derive(Debug, Error)]
 pub enum BuilderError {
     #[error("invalid string: {0}")]
     InvalidString(String),
+
+    #[error("invalid fixture: {0}")]
+    InvalidFixture(#[from] FixtureError),
+
+    #[error("invalid register: {0}")]
+    InvalidRegister(String),
 }
 
 pub type BuilderResult<T> = std::result::Result<T, BuilderError>;
 
+/// Vendor ID embedded in GUID of emulated devices, which isn't assigned to any vendor.
+const EMULATOR_VENDOR_ID: u16 = 0xFFFF;
+
 /// USB3 emulated device builder.
 /// All initial configuration of the device must be done via this builder.
 ///
 /// An emulator is passed to the device pool and user can't control the emulator itself directly
 /// once build process is finished by calling [`EmulatorBuilder::build`].
 ///
-/// Emulators in the device pool can be found by [`crate::u3v::enumerate_devices`] and controlled via
-/// [`crate::u3v::Device`] in the same way as real device.
+/// Emulators in the device pool can be found by [`crate::emulator::enumerate_devices`] and
+/// controlled via [`crate::emulator::Device`] in the same way as real device.
 ///
 /// # Example
 /// ```rust
-/// use cameleon_device::u3v::{EmulatorBuilder, enumerate_devices};
+/// use cameleon_device::emulator::{EmulatorBuilder, enumerate_devices};
 ///
 /// // Build device with default configuration and pass it to the device pool.
 /// // Now the device pool has one device.
@@ -51,6 +73,13 @@ pub type BuilderResult<T> = std::result::Result<T, BuilderError>;
 /// ```
 pub struct EmulatorBuilder {
     memory: Memory,
+    /// Settings which are not stored in the device memory. Identity strings are stored in the
+    /// memory instead of `fixture.device`.
+    fixture: Fixture,
+    /// `GenApi` XML of the fixture and its address, which is served instead of the built-in XML.
+    xml: Option<(usize, String)>,
+    command_queue_depth: usize,
+    command_latency: Duration,
 }
 
 impl EmulatorBuilder {
@@ -67,18 +96,137 @@ impl EmulatorBuilder {
             .collect();
         memory.write::<ABRM::SerialNumber>(serial_number).unwrap();
 
-        Self { memory }
+        Self {
+            memory,
+            fixture: Fixture::default(),
+            xml: None,
+            command_queue_depth: 1,
+            command_latency: Duration::ZERO,
+        }
+    }
+
+    /// Constructs a builder from the fixture at `path`. See [`crate::fixture`] for the format.
+    ///
+    /// # Errors
+    /// See [`Self::with_fixture`], in addition [`BuilderError::InvalidFixture`] is returned with
+    /// the line where the error is found if the fixture is invalid.
+    pub fn from_fixture(path: impl AsRef<Path>) -> BuilderResult<Self> {
+        Self::with_fixture(Fixture::from_path(path)?)
+    }
+
+    /// Constructs a builder from `fixture`.
+    ///
+    /// The registers and `GenApi` XML of the fixture are served by the emulator, and the faults
+    /// are injected to its control channel. The stream settings determine the required payload
+    /// size of `SIRM` and the image frames sent on the stream channel.
+    ///
+    /// # Errors
+    /// If an identity string is not ASCII string or the length is larger than 64, then
+    /// [`BuilderError::InvalidString`] is returned.
+    /// If a register overlaps the built-in registers of the emulator, then
+    /// [`BuilderError::InvalidRegister`] is returned.
+    /// If the `GenApi` XML file can't be read, then [`BuilderError::InvalidFixture`] is returned.
+    pub fn with_fixture(mut fixture: Fixture) -> BuilderResult<Self> {
+        let mut builder = Self::new();
+
+        let identity = std::mem::take(&mut fixture.device);
+        macro_rules! write_identity {
+            ($($field:ident => $reg:ident,)*) => {
+                $(
+                if let Some(value) = identity.$field {
+                    builder
+                        .memory
+                        .write::<ABRM::$reg>(value)
+                        .map_err(|e| BuilderError::InvalidString(format!("{}", e)))?;
+                }
+                )*
+            };
+        }
+        write_identity! {
+            vendor_name => ManufacturerName,
+            model_name => ModelName,
+            family_name => FamilyName,
+            device_version => DeviceVersion,
+            manufacturer_info => ManufacturerInfo,
+            serial_number => SerialNumber,
+            user_defined_name => UserDefinedName,
+        }
+
+        let mut end = MEMORY_END as u64;
+        for reg in &fixture.registers {
+            if reg.address < MEMORY_END as u64 {
+                return Err(BuilderError::InvalidRegister(format!(
+                    "register `{}` overlaps the built-in registers below {:#x}",
+                    reg.name, MEMORY_END
+                )));
+            }
+            end = end.max(reg.address + reg.length);
+        }
+
+        if let Some(genapi) = &fixture.genapi {
+            let xml = match genapi {
+                GenApiSource::Inline { xml } => xml.clone(),
+                GenApiSource::File { file } => {
+                    fs::read_to_string(file).map_err(FixtureError::from)?
+                }
+            };
+            // Place the XML after all registers with 8 bytes alignment.
+            let address = end
+                .checked_add(7)
+                .filter(|address| address.checked_add(xml.len() as u64).is_some())
+                .ok_or_else(|| {
+                    BuilderError::InvalidRegister("no room for `GenApi` XML after registers".into())
+                })?
+                & !7;
+            builder.xml = Some((address as usize, xml));
+        }
+
+        if let Some(stream) = &fixture.stream {
+            builder
+                .memory
+                .write::<SIRM::RequiredPayloadSize>(stream_module::payload_size(stream))
+                .unwrap();
+        }
+
+        builder.fixture = fixture;
+        Ok(builder)
+    }
+
+    /// Exports the configuration of the builder as a fixture, which can be loaded by
+    /// [`Self::from_fixture`] after written to a file by [`Fixture::to_toml`].
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn to_fixture(&self) -> Fixture {
+        use ABRM::{
+            DeviceVersion, FamilyName, ManufacturerInfo, ManufacturerName, ModelName, SerialNumber,
+            UserDefinedName,
+        };
+
+        let device = DeviceIdentity {
+            vendor_name: Some(self.memory.read::<ManufacturerName>().unwrap()),
+            model_name: Some(self.memory.read::<ModelName>().unwrap()),
+            family_name: Some(self.memory.read::<FamilyName>().unwrap()),
+            device_version: Some(self.memory.read::<DeviceVersion>().unwrap()),
+            manufacturer_info: Some(self.memory.read::<ManufacturerInfo>().unwrap()),
+            serial_number: Some(self.memory.read::<SerialNumber>().unwrap()),
+            user_defined_name: Some(self.memory.read::<UserDefinedName>().unwrap()),
+        };
+
+        Fixture {
+            device,
+            ..self.fixture.clone()
+        }
     }
 
     /// Build an emulator and pass it to the device pool. User can't control the emulator itself
     /// directly once call this method.
     ///
-    /// Emulators in the device pool can be found by [`crate::u3v::enumerate_devices`] and controlled via
-    /// [`crate::u3v::Device`] in the same way as real device.
+    /// Emulators in the device pool can be found by [`crate::emulator::enumerate_devices`] and
+    /// controlled via [`crate::emulator::Device`] in the same way as real device.
     ///
     /// # Example
     /// ```rust
-    /// use cameleon_device::u3v::EmulatorBuilder;
+    /// use cameleon_device::emulator::EmulatorBuilder;
     ///
     /// // Build device with default configuration and pass it to the device pool.
     /// // Now the device pool has one device.
@@ -89,9 +237,38 @@ impl EmulatorBuilder {
     /// EmulatorBuilder::new().user_defined_name("My Camera").unwrap().serial_number("CAM1984").unwrap().build();
     ///
     /// ```
-    pub fn build(self) {
+    pub fn build(mut self) {
         let device_info = self.build_device_info();
-        let device = Device::new(self.memory, device_info);
+
+        let mut user_memory = UserMemory::default();
+        for reg in &self.fixture.registers {
+            let access = match reg.access {
+                fixture::AccessRight::RO => AccessRight::RO,
+                fixture::AccessRight::WO => AccessRight::WO,
+                fixture::AccessRight::RW => AccessRight::RW,
+            };
+            user_memory.add_region(reg.address as usize, access, initial_data(reg));
+        }
+        if let Some((address, xml)) = self.xml {
+            let len = xml.len() as u64;
+            user_memory.add_region(address, AccessRight::RO, xml.into_bytes());
+            self.memory
+                .write::<ManifestTable::RegisterAddress>(address as u64)
+                .unwrap();
+            self.memory.write::<ManifestTable::FileSize>(len).unwrap();
+        }
+
+        let faults = FaultInjector::new(self.fixture.faults);
+        let commands = CommandQueue::new(self.command_queue_depth, self.command_latency);
+        let stream = self.fixture.stream.unwrap_or_default();
+        let device = Device::new(
+            self.memory,
+            user_memory,
+            faults,
+            commands,
+            stream,
+            device_info,
+        );
         DevicePool::with(|pool| pool.pool_and_run(device));
     }
 
@@ -108,14 +285,14 @@ impl EmulatorBuilder {
     /// # Examples
     ///
     /// ```rust
-    /// use cameleon_device::u3v::EmulatorBuilder;
+    /// use cameleon_device::emulator::EmulatorBuilder;
     ///
     /// assert!(EmulatorBuilder::new().serial_number("CAM1984").is_ok());
     /// assert!(EmulatorBuilder::new().serial_number("カム1984年").is_err());
     /// ```
     pub fn serial_number(mut self, serial: &str) -> BuilderResult<Self> {
         self.memory
-            .write::<ABRM::UserDefinedName>(serial.into())
+            .write::<ABRM::SerialNumber>(serial.into())
             .map_err(|e| BuilderError::InvalidString(format! {"{}", e}))?;
         Ok(self)
     }
@@ -133,7 +310,7 @@ impl EmulatorBuilder {
     /// # Examples
     ///
     /// ```rust
-    /// use cameleon_device::u3v::EmulatorBuilder;
+    /// use cameleon_device::emulator::EmulatorBuilder;
     ///
     /// assert!(EmulatorBuilder::new().user_defined_name("user define name").is_ok());
     /// assert!(EmulatorBuilder::new().user_defined_name("使用者が定義した名前").is_err());
@@ -145,6 +322,86 @@ impl EmulatorBuilder {
         Ok(self)
     }
 
+    /// Sets the number of control commands the device processes at once. A command sent while
+    /// `depth` commands are in process is answered with `Busy`. The depth is 1 by default.
+    ///
+    /// The depth can be changed after the device is built with
+    /// [`crate::emulator::Device::set_command_queue_depth`].
+    #[must_use]
+    pub fn command_queue_depth(mut self, depth: usize) -> Self {
+        self.command_queue_depth = depth;
+        self
+    }
+
+    /// Sets the time the device takes to process each control command. Commands are processed
+    /// without delay by default.
+    #[must_use]
+    pub fn command_latency(mut self, latency: Duration) -> Self {
+        self.command_latency = latency;
+        self
+    }
+
+    /// Sets whether the device advertises stacked commands (`ReadMemStacked` and
+    /// `WriteMemStacked`) in its device capability register. Stacked commands are advertised by
+    /// default.
+    ///
+    /// The emulator serves stacked commands either way, so this is used to emulate a device which
+    /// doesn't support them.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use cameleon_device::emulator::EmulatorBuilder;
+    ///
+    /// EmulatorBuilder::new().stacked_commands(false).build();
+    /// ```
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn stacked_commands(mut self, enabled: bool) -> Self {
+        const STACKED_COMMANDS_BIT: usize = 13;
+
+        let mut capability = self.memory.read::<ABRM::DeviceCapability>().unwrap();
+        let mask = 1 << (STACKED_COMMANDS_BIT % 8);
+        if enabled {
+            capability[STACKED_COMMANDS_BIT / 8] |= mask;
+        } else {
+            capability[STACKED_COMMANDS_BIT / 8] &= !mask;
+        }
+        self.memory
+            .write::<ABRM::DeviceCapability>(capability)
+            .unwrap();
+        self
+    }
+
+    /// Sets whether the device advertises the event interface register map (EIRM) in its U3V
+    /// capability register. The event interface is advertised by default.
+    ///
+    /// This is used to emulate a device which has no event endpoint.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use cameleon_device::emulator::EmulatorBuilder;
+    ///
+    /// EmulatorBuilder::new().event_interface(false).build();
+    /// ```
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn event_interface(mut self, enabled: bool) -> Self {
+        const EIRM_AVAILABLE_BIT: u8 = 1;
+
+        let mut capability = self.memory.read::<SBRM::U3VCapability>().unwrap();
+        if enabled {
+            capability[0] |= 1 << EIRM_AVAILABLE_BIT;
+        } else {
+            capability[0] &= !(1 << EIRM_AVAILABLE_BIT);
+        }
+        self.memory
+            .write::<SBRM::U3VCapability>(capability)
+            .unwrap();
+        self
+    }
+
     fn build_device_info(&self) -> DeviceInfo {
         use ABRM::{
             DeviceVersion, FamilyName, GenCpVersionMajor, GenCpVersionMinor, ManufacturerInfo,
@@ -171,16 +428,16 @@ impl EmulatorBuilder {
         // TODO: Read from SBRM.
         let u3v_version = Version::new(1, 0, 0);
 
-        // Device guid consists of 12 characters.
-        // First 4 characters are vendor ID and last 8 characters are unique id assigned by a vendor.
-        // We use a serial number as unique id.
-        let serial_len = serial_number.len();
-        let guid = if serial_len > 8 {
-            format!("EMU-{}", &serial_number[serial_len - 8..])
-        } else {
-            let pad: String = std::iter::repeat('0').take(8 - serial_len).collect();
-            format!("EMU-{}{}", pad, serial_number)
-        };
+        // Device guid consists of 16 bit vendor ID followed by 32 bit unique id assigned by a
+        // vendor. We use FNV-1a hash of a serial number as unique id.
+        let unique_id = serial_number.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        });
+        let mut guid_bytes = [0; 6];
+        guid_bytes[..2].copy_from_slice(&EMULATOR_VENDOR_ID.to_be_bytes());
+        guid_bytes[2..].copy_from_slice(&unique_id.to_be_bytes());
+        // Ok to unwrap because the length is always 6.
+        let guid = Guid::from_bytes(&guid_bytes).unwrap();
 
         DeviceInfo {
             gencp_version,
@@ -210,8 +467,75 @@ impl EmulatorBuilder {
     }
 }
 
+/// Encodes the initial value of `reg` in little endian, which is the endianness of the emulator.
+fn initial_data(reg: &RegisterDef) -> Vec<u8> {
+    let mut data = vec![0; reg.length as usize];
+    let value = match (&reg.default, reg.ty) {
+        (None, _) => return data,
+        (Some(RegisterValue::Integer(i)), RegisterType::F32) => (*i as f32).to_le_bytes().to_vec(),
+        (Some(RegisterValue::Integer(i)), RegisterType::F64) => (*i as f64).to_le_bytes().to_vec(),
+        (Some(RegisterValue::Float(f)), RegisterType::F32) => (*f as f32).to_le_bytes().to_vec(),
+        (Some(RegisterValue::Float(f)), _) => f.to_le_bytes().to_vec(),
+        // The value is validated to fit in the register, so the truncated bytes are the value.
+        (Some(RegisterValue::Integer(i)), _) => i.to_le_bytes().to_vec(),
+        (Some(RegisterValue::String(s)), _) => s.as_bytes().to_vec(),
+        (Some(RegisterValue::Bytes(b)), _) => b.clone(),
+    };
+    let len = value.len().min(data.len());
+    data[..len].copy_from_slice(&value[..len]);
+    data
+}
+
 impl Default for EmulatorBuilder {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_round_trip() {
+        let builder = EmulatorBuilder::new()
+            .user_defined_name("Round Trip")
+            .unwrap();
+        let fixture = builder.to_fixture();
+
+        let path = std::env::temp_dir().join(format!(
+            "cameleon_fixture_round_trip_{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&path, fixture.to_toml()).unwrap();
+        let reloaded = EmulatorBuilder::from_fixture(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let reloaded = reloaded.unwrap();
+        assert_eq!(reloaded.to_fixture(), fixture);
+        assert_eq!(
+            reloaded.build_device_info().user_defined_name.as_deref(),
+            Some("Round Trip")
+        );
+    }
+
+    #[test]
+    fn test_from_example_fixture() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mono_camera.toml");
+        let builder = EmulatorBuilder::from_fixture(path).unwrap();
+        let device_info = builder.build_device_info();
+        assert_eq!(device_info.model_name, "MonoCamera");
+        assert_eq!(device_info.serial_number, "MONO0001");
+        assert_eq!(builder.to_fixture().registers.len(), 3);
+    }
+
+    #[test]
+    fn test_register_overlapping_built_in_registers() {
+        let src = "[[registers]]\nname = \"Gain\"\naddress = 0x0\nlength = 4\ntype = \"u32\"\n";
+        let fixture = Fixture::from_toml(src, "").unwrap();
+        assert!(matches!(
+            EmulatorBuilder::with_fixture(fixture),
+            Err(BuilderError::InvalidRegister(_))
+        ));
+    }
+}
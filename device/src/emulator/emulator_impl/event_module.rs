@@ -16,6 +16,8 @@ use super::{
 pub(super) struct EventModule {
     queue: SharedQueue<Vec<u8>>,
     timestamp: u64,
+    /// Request id of the next event packet.
+    request_id: u16,
 
     enabled: bool,
 }
@@ -25,6 +27,7 @@ impl EventModule {
         Self {
             queue,
             timestamp,
+            request_id: 0,
             enabled: false,
         }
     }
@@ -36,13 +39,9 @@ impl EventModule {
     ) {
         while let Some(signal) = signal_rx.next().await {
             match signal {
-                EventSignal::_EventData {
-                    event_id,
-                    data,
-                    request_id,
-                } => {
+                EventSignal::EventData { event_id, data } => {
                     if self.enabled {
-                        self.enqueue_or_halt(event_id, &data, request_id, &signal_tx)
+                        self.enqueue_or_halt(event_id, &data, &signal_tx)
                     } else {
                         log::warn! {"receive event data signal, but event module is currently disabled"}
                     }
@@ -52,7 +51,7 @@ impl EventModule {
                     self.timestamp = timestamp;
                 }
 
-                EventSignal::_Enable => {
+                EventSignal::Enable => {
                     if self.enabled {
                         log::warn! {"receive event enable signal, but event module is already enabled"}
                     } else {
@@ -77,13 +76,7 @@ impl EventModule {
         }
     }
 
-    fn enqueue_or_halt(
-        &mut self,
-        event_id: u16,
-        data: &[u8],
-        request_id: u16,
-        signal_tx: &Sender<InterfaceSignal>,
-    ) {
+    fn enqueue_or_halt(&mut self, event_id: u16, data: &[u8], signal_tx: &Sender<InterfaceSignal>) {
         let scd = match event_packet::EventScd::single_event(event_id, data, self.timestamp) {
             Ok(scd) => scd,
             Err(e) => {
@@ -93,10 +86,11 @@ impl EventModule {
         };
 
         let mut bytes = vec![];
-        if let Err(e) = scd.finalize(request_id).serialize(&mut bytes) {
+        if let Err(e) = scd.finalize(self.request_id).serialize(&mut bytes) {
             log::error!("cant't serialize event packet: cause {}", e);
             return;
         }
+        self.request_id = self.request_id.wrapping_add(1);
 
         if !self.queue.enqueue(bytes) {
             log::warn!("event queue is full, entering a halted state",);
@@ -289,24 +283,22 @@ mod tests {
     #[test]
     fn test_signal() {
         let (signal_tx, mut iface_signal_rx, queue) = spawn_module();
-        signal_tx.try_send(EventSignal::_Enable).unwrap();
+        signal_tx.try_send(EventSignal::Enable).unwrap();
 
         // Test EventData signal.
         let event_id = 10;
-        let request_id = 20;
         let data = vec![1, 2, 3];
         signal_tx
-            .try_send(EventSignal::_EventData {
+            .try_send(EventSignal::EventData {
                 event_id,
                 data: data.clone(),
-                request_id,
             })
             .unwrap();
 
         let received = receive_data(&queue).unwrap();
 
         let event_packet = event::EventPacket::parse(&received).unwrap();
-        assert_eq!(event_packet.request_id(), request_id);
+        assert_eq!(event_packet.request_id(), 0);
         assert_eq!(event_packet.scd.len(), 1);
         assert_eq!(&event_packet.scd[0].data, &data.as_slice());
 
@@ -316,14 +308,11 @@ mod tests {
             .try_send(EventSignal::UpdateTimestamp(timestamp))
             .unwrap();
         signal_tx
-            .try_send(EventSignal::_EventData {
-                event_id,
-                data,
-                request_id,
-            })
+            .try_send(EventSignal::EventData { event_id, data })
             .unwrap();
         let received = receive_data(&queue).unwrap();
         let event_packet = event::EventPacket::parse(&received).unwrap();
+        assert_eq!(event_packet.request_id(), 1);
         assert_eq!(event_packet.scd[0].timestamp, timestamp);
 
         // Clean up.
@@ -0,0 +1,78 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{collections::VecDeque, sync::Mutex};
+
+use crate::fixture::{Fault, FaultKind};
+
+/// Decides the fault injected to each control command, see [`Fault`].
+///
+/// Faults queued by [`FaultInjector::queue`] are injected before the faults of the fixture.
+#[derive(Default)]
+pub(super) struct FaultInjector {
+    faults: Vec<Fault>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Number of commands handled so far.
+    commands: u64,
+    /// Number of commands each fault has been injected to.
+    injected: Vec<u64>,
+    /// Faults injected to the next commands.
+    queued: VecDeque<FaultKind>,
+}
+
+impl FaultInjector {
+    pub(super) fn new(faults: Vec<Fault>) -> Self {
+        let state = State {
+            injected: vec![0; faults.len()],
+            ..State::default()
+        };
+        Self {
+            faults,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Injects `kind` to the next command, or to the next command writing the payload transfer
+    /// size if `kind` is [`FaultKind::RejectPayloadSize`].
+    pub(super) fn queue(&self, kind: FaultKind) {
+        self.state.lock().unwrap().queued.push_back(kind);
+    }
+
+    /// Counts a command and returns the fault injected to it.
+    ///
+    /// [`FaultKind::RejectPayloadSize`] is injected only to commands writing the payload transfer
+    /// size, which is indicated by `writes_payload_size`.
+    pub(super) fn inject(&self, writes_payload_size: bool) -> Option<FaultKind> {
+        let mut state = self.state.lock().unwrap();
+        let State {
+            commands,
+            injected,
+            queued,
+        } = &mut *state;
+        let command = *commands;
+        *commands += 1;
+
+        let applicable =
+            |kind: FaultKind| kind != FaultKind::RejectPayloadSize || writes_payload_size;
+        if let Some(index) = queued.iter().position(|kind| applicable(*kind)) {
+            return queued.remove(index);
+        }
+
+        let (fault, injected) =
+            self.faults
+                .iter()
+                .zip(injected.iter_mut())
+                .find(|(fault, injected)| {
+                    applicable(fault.kind)
+                        && fault.after_commands <= command
+                        && **injected < fault.count
+                })?;
+        *injected += 1;
+        Some(fault.kind)
+    }
+}
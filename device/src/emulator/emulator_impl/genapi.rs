@@ -5,7 +5,7 @@
 use cameleon_impl::memory::{prelude::*, register_map};
 use const_format::formatcp;
 
-use super::memory::GENAPI_XML_ADDRESS;
+use super::memory::GENAPI_REG_ADDRESS;
 
 pub(super) const MODEL_NAME: &str = "CameleonU3VEmulator";
 pub(super) const VENDOR_NAME: &str = "CameleonProjectDevelopers";
@@ -25,7 +25,7 @@ pub(super) const PORT_NAME: &str = "Device";
 const PRODUCT_GUID: &str = "eaabe337-2c3b-4e0b-b9b9-e67b347c4da8";
 const VERSION_GUID: &str = "0d29949b-5cd9-4f08-93fb-eea24950de3f";
 
-#[register_map(base=GENAPI_XML_ADDRESS, endianness=LE)]
+#[register_map(base=GENAPI_REG_ADDRESS, endianness=LE)]
 pub(super) enum GenApiReg {
     /// Start acquisition of images when the register is set to 1.
     #[register(len = 1, access = WO, ty = u8)]
@@ -107,6 +107,14 @@ xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_1 http://www.genicam
         <Endianess>LittleEndian</Endianess>
     </IntReg>
 
+    <Integer Name="TLParamsLocked" NameSpace="Standard">
+        <ToolTip>Used by the Transport Layer to prevent critical features from changing during acquisition.</ToolTip>
+        <Visibility>Invisible</Visibility>
+        <Value>0</Value>
+        <Min>0</Min>
+        <Max>1</Max>
+    </Integer>
+
 </RegisterDescription>"#,
     acquisition_start_addr = GenApiReg::AcquisitionStart::ADDRESS,
     acquisition_start_len = GenApiReg::AcquisitionStart::LENGTH,
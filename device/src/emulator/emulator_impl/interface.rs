@@ -12,21 +12,30 @@ use async_std::{
 };
 use futures::{channel::oneshot, select, FutureExt};
 
+use crate::fixture::StreamSettings;
+
 use super::{
+    command_queue::CommandQueue,
     control_module::ControlModule,
     device::Timestamp,
     event_module::EventModule,
     fake_protocol::{FakeAckKind, FakeAckPacket, FakeReqKind, FakeReqPacket, IfaceKind},
+    fault::FaultInjector,
     memory::Memory,
     shared_queue::SharedQueue,
     signal::{ControlSignal, EventSignal, InterfaceSignal, StreamSignal},
     stream_module::StreamModule,
+    user_memory::UserMemory,
 };
 
 pub(super) struct Interface {
     iface_state: IfaceState,
     memory: Arc<Mutex<Memory>>,
+    user_memory: Arc<UserMemory>,
+    faults: Arc<FaultInjector>,
+    commands: Arc<CommandQueue>,
     timestamp: Timestamp,
+    stream_settings: StreamSettings,
 
     ctrl_queue: SharedQueue<Vec<u8>>,
     event_queue: SharedQueue<Vec<u8>>,
@@ -37,11 +46,22 @@ const SHARED_QUEUE_SIZE: usize = 32;
 const CHANNEL_CAPACITY: usize = 128;
 
 impl Interface {
-    pub(super) fn new(memory: Arc<Mutex<Memory>>, timestamp: Timestamp) -> Self {
+    pub(super) fn new(
+        memory: Arc<Mutex<Memory>>,
+        user_memory: Arc<UserMemory>,
+        faults: Arc<FaultInjector>,
+        commands: Arc<CommandQueue>,
+        timestamp: Timestamp,
+        stream_settings: StreamSettings,
+    ) -> Self {
         Self {
             iface_state: IfaceState::new(),
             memory,
+            user_memory,
+            faults,
+            commands,
             timestamp,
+            stream_settings,
 
             ctrl_queue: SharedQueue::new(SHARED_QUEUE_SIZE),
             event_queue: SharedQueue::new(SHARED_QUEUE_SIZE),
@@ -86,11 +106,13 @@ impl Interface {
                 },
 
                 signal = signal_rx.next().fuse() => {
-                    if let Some(signal) = signal {
-                        self.handle_signal(signal, &signal_tx).await
-                    } else{
-                        log::error!("all modules are dropped");
-                        break
+                    match signal {
+                        Some(InterfaceSignal::Disconnect) => break,
+                        Some(signal) => self.handle_signal(signal, &signal_tx).await,
+                        None => {
+                            log::error!("all modules are dropped");
+                            break
+                        }
                     }
                 }
 
@@ -116,6 +138,9 @@ impl Interface {
         let control_module = ControlModule::new(
             self.iface_state.clone(),
             self.memory.clone(),
+            self.user_memory.clone(),
+            self.faults.clone(),
+            self.commands.clone(),
             self.timestamp.clone(),
             self.ctrl_queue.clone(),
         );
@@ -138,7 +163,12 @@ impl Interface {
         let (stream_signal_tx, stream_signal_rx) = channel::bounded(CHANNEL_CAPACITY);
 
         // Construct and spawn control module.
-        let stream_module = StreamModule::new(self.timestamp.clone(), self.stream_queue.clone());
+        let stream_module = StreamModule::new(
+            self.timestamp.clone(),
+            self.stream_queue.clone(),
+            self.memory.clone(),
+            self.stream_settings.clone(),
+        );
         task::spawn(stream_module.run(signal_tx, stream_signal_rx));
 
         stream_signal_tx
@@ -159,14 +189,14 @@ impl Interface {
             self.iface_state
                 .set_state(iface, IfaceStateKind::Ready)
                 .await;
-            send_ack(&ack_tx, iface, FakeAckKind::ClearHaltAck);
+            send_ack(ack_tx, iface, FakeAckKind::ClearHaltAck);
             return;
         }
 
         // Handle set halt request.
         if req_kind.is_set_halt() {
             self.set_halt(iface, signal_tx).await;
-            send_ack(&ack_tx, iface, FakeAckKind::SetHaltAck);
+            send_ack(ack_tx, iface, FakeAckKind::SetHaltAck);
             return;
         }
 
@@ -189,12 +219,12 @@ impl Interface {
                     Some(data) => FakeAckKind::RecvAck(data),
                     None => FakeAckKind::RecvNak,
                 };
-                send_ack(&ack_tx, iface, ack_kind);
+                send_ack(ack_tx, iface, ack_kind);
             }
 
             (IfaceKind::Control, FakeReqKind::Send(data)) => {
                 signal_tx.send_ctrl(ControlSignal::ReceiveData(data));
-                send_ack(&ack_tx, iface, FakeAckKind::SendAck);
+                send_ack(ack_tx, iface, FakeAckKind::SendAck);
             }
 
             (iface, req) => {
@@ -203,7 +233,7 @@ impl Interface {
                     iface,
                     req
                 );
-                send_ack(&ack_tx, iface, FakeAckKind::BrokenReq);
+                send_ack(ack_tx, iface, FakeAckKind::BrokenReq);
             }
         };
     }
@@ -215,6 +245,8 @@ impl Interface {
             InterfaceSignal::ToEvent(signal) => signal_tx.send_event(signal),
             InterfaceSignal::ToStream(signal) => signal_tx.send_stream(signal),
             InterfaceSignal::Halt(iface) => self.set_halt(iface, signal_tx).await,
+            // Handled by the main loop.
+            InterfaceSignal::Disconnect => unreachable!(),
         }
     }
 
@@ -4,14 +4,20 @@
 
 use cameleon_impl::memory::{memory, register_map, Register};
 
-use super::genapi;
+use super::genapi::{self, GenApiReg};
 
 const ABRM_ADDRESS: usize = 0;
 const SBRM_ADDRESS: usize = 0xffff;
 const SIRM_ADDRESS: usize = SBRM::base() + SBRM::size();
-const MANIFEST_TABLE_ADDRESS: usize = SIRM::base() + SIRM::size();
-pub(super) const GENAPI_XML_ADDRESS: usize = ManifestTable::base() + ManifestTable::size();
+const EIRM_ADDRESS: usize = SIRM::base() + SIRM::size();
+/// Registers referenced by the built-in `GenApi` XML.
+pub(super) const GENAPI_REG_ADDRESS: usize = EIRM::base() + EIRM::size();
+const MANIFEST_TABLE_ADDRESS: usize = GenApiReg::base() + GenApiReg::size();
+const GENAPI_XML_ADDRESS: usize = ManifestTable::base() + ManifestTable::size();
 const GENAPI_XML_LENGTH: usize = genapi::GENAPI_XML.len();
+/// End of the built-in register maps, regions of [`super::user_memory::UserMemory`] are placed
+/// after it.
+pub(super) const MEMORY_END: usize = GenApiXml::base() + GenApiXml::size();
 
 /// Offset | Value | Description.
 ///      0 |     1 | User Defined Name is supported.
@@ -24,12 +30,12 @@ const GENAPI_XML_LENGTH: usize = genapi::GENAPI_XML.len();
 ///     10 |     1 | Endianness Register is supported.
 ///     11 |     1 | Written Length Field is supported.
 ///     12 |     0 | Multi Event is currently NOT supported.
-///     13 |     0 | Stacked Commands is NOT supported.
+///     13 |     1 | Stacked Commands is supported.
 ///     14 |     1 | Device Software Interface Version is supported.
 ///  15-63 |     0 | Reserved. All remained bits are set to 0.
 const DEVICE_CAPABILITY: &[u8] = &[
     0b0000_1001,
-    0b0100_1111,
+    0b0110_1111,
     0b0000_0000,
     0b0000_0000,
     0b0000_0000,
@@ -74,6 +80,8 @@ pub(super) struct Memory {
     abrm: ABRM,
     sbrm: SBRM,
     sirm: SIRM,
+    eirm: EIRM,
+    genapi_reg: GenApiReg,
     manifest_table: ManifestTable,
     genapi_xml: GenApiXml,
 }
@@ -143,8 +151,8 @@ pub(super) enum ABRM {
     #[register(len = 4, access = RO, ty = Bytes)]
     ProtocolEndianness = &[0xFF, 0xFF, 0xFF, 0xFF], // Little endian.
 
-    #[register(len = 4, access = NA, ty = Bytes)]
-    ImplementationEndianness,
+    #[register(len = 4, access = RO, ty = Bytes)]
+    ImplementationEndianness = &[0xFF, 0xFF, 0xFF, 0xFF], // Little endian.
 
     #[register(len = 64, access = RO, ty = String)]
     DeviceSoftwareInterfaceVersion = "1.0.0",
@@ -180,10 +188,10 @@ pub(super) enum SBRM {
     SirmLength = SIRM::size() as u32,
 
     #[register(len = 8, access = RO, ty = u64)]
-    EirmAddress, // TODO: Filled after SIRM register map is implemented.
+    EirmAddress = EIRM_ADDRESS,
 
     #[register(len = 4, access = RO, ty = u32)]
-    EirmLength, // TODO: Filled after EIRM register map is implmeneted.
+    EirmLength = EIRM::size() as u32,
 
     #[register(len = 8, access = NA, ty = u64)]
     Iidc2Address,
@@ -205,8 +213,9 @@ pub(super) enum SIRM {
     #[register(len = 4, access = RW, ty = u32)]
     Control = 0,
 
+    /// Payload size of the default stream settings, see [`crate::fixture::StreamSettings`].
     #[register(len = 8, access = RO, ty = u64)]
-    RequiredPayloadSize = 15_151_104,
+    RequiredPayloadSize = 307_200,
 
     #[register(len = 4, access = RO, ty = u32)]
     RequiredLeaderSize = 1024,
@@ -233,6 +242,21 @@ pub(super) enum SIRM {
     MaximumTrailerSize = 0,
 }
 
+/// Event id of the test event sent when 1 is written to [`EIRM::EventTestControl`].
+pub(super) const TEST_EVENT_ID: u16 = 0x4FFF;
+
+#[register_map(base = EIRM_ADDRESS, endianness = LE)]
+pub(super) enum EIRM {
+    #[register(len = 4, access = RW, ty = u32)]
+    Control = 0,
+
+    #[register(len = 4, access = RO, ty = u32)]
+    MaximumEventTransferLength = 1024,
+
+    #[register(len = 4, access = RW, ty = u32)]
+    EventTestControl = 0,
+}
+
 const MANIFEST_ENTRY0_BF_OFFSET: usize = (ManifestTable::GenICamFileVersionMajor::ADDRESS
     + ManifestTable::GenICamFileVersionMajor::LENGTH)
     - MANIFEST_TABLE_ADDRESS;
@@ -283,3 +307,39 @@ pub(super) enum GenApiXml {
     #[register(len = GENAPI_XML_LENGTH, access = RO, ty = String)]
     Xml = genapi::GENAPI_XML,
 }
+
+#[cfg(test)]
+mod tests {
+    use cameleon_impl::memory::prelude::*;
+
+    use crate::u3v::register_map::{BootstrapMap, BOOTSTRAP_ACCESS_RIGHTS};
+
+    use super::*;
+
+    /// Verifies that the effective access rights of the bootstrap registers follow the table
+    /// shared with the other register maps.
+    #[test]
+    fn test_bootstrap_access_rights() {
+        let mut memory = Memory::new();
+        for entry in BOOTSTRAP_ACCESS_RIGHTS {
+            let base = match entry.map {
+                BootstrapMap::Abrm => ABRM_ADDRESS,
+                BootstrapMap::Sbrm => SBRM_ADDRESS,
+                BootstrapMap::Sirm => SIRM_ADDRESS,
+                BootstrapMap::Eirm => EIRM_ADDRESS,
+            };
+            let (offset, len) = entry.register;
+            let start = base + offset as usize;
+            let range = start..start + len as usize;
+
+            let readable = memory.read_raw(range.clone()).is_ok();
+            let writable = memory.write_raw(start, &vec![0; range.len()]).is_ok();
+            if entry.optional && !readable && !writable {
+                // The capability is not supported by the emulator.
+                continue;
+            }
+            assert_eq!(readable, entry.access.is_readable(), "{}", entry.name);
+            assert_eq!(writable, entry.access.is_writable(), "{}", entry.name);
+        }
+    }
+}
@@ -10,7 +10,7 @@ use cameleon_impl::memory::{prelude::*, MemoryObserver};
 use super::{
     control_module::Worker,
     control_protocol::{ack, cmd},
-    memory::{Memory, ABRM, SIRM, SIRM_ALIGNMENT},
+    memory::{Memory, ABRM, EIRM, SIRM, SIRM_ALIGNMENT, TEST_EVENT_ID},
     signal::{EventSignal, StreamSignal},
 };
 
@@ -237,6 +237,53 @@ impl SiControlHandler {
     }
 }
 
+define_handler!(EiControlHandler, EIRM::Control, MemoryEvent::EiControl);
+impl EiControlHandler {
+    /// Handle `MemoryEvent::EiControl`
+    async fn handle_events(worker: &Worker, scd_kind: cmd::ScdKind) -> Result<(), ack::ErrorAck> {
+        let value = Self::read(&*worker.memory.lock().await, scd_kind)?;
+
+        if value == 1 {
+            worker.try_send_signal(EventSignal::Enable);
+            Ok(())
+        } else if value == 0 {
+            let (completed_tx, completed_rx) = oneshot::channel();
+            worker.try_send_signal(EventSignal::Disable(completed_tx));
+            completed_rx.await.ok();
+            Ok(())
+        } else {
+            Err(ack::ErrorAck::new(ack::GenCpStatus::GenericError, scd_kind))
+        }
+    }
+}
+
+define_handler!(
+    EventTestControlHandler,
+    EIRM::EventTestControl,
+    MemoryEvent::EventTestControl
+);
+impl EventTestControlHandler {
+    /// Handle `MemoryEvent::EventTestControl`.
+    ///
+    /// If 1 is written to `EventTestControl`, a test event without data is sent to the host.
+    async fn handle_events(worker: &Worker, scd_kind: cmd::ScdKind) -> Result<(), ack::ErrorAck> {
+        let value = Self::read(&*worker.memory.lock().await, scd_kind)?;
+        match value {
+            0 => return Ok(()),
+            1 => {}
+            _ => return Err(ack::ErrorAck::new(ack::GenCpStatus::GenericError, scd_kind)),
+        }
+
+        let signal = EventSignal::EventData {
+            event_id: TEST_EVENT_ID,
+            data: vec![],
+        };
+        worker.try_send_signal(signal);
+
+        Ok(())
+    }
+}
+
 /// This macro defines handler for registers of SIRM which are related to streaming data size.
 ///
 /// A handler defined by this macro works as a verifier which verify the written size has correct
@@ -295,6 +342,8 @@ define_handler_for_sirm!(
 enum MemoryEvent {
     TimestampLatch,
     SiControl,
+    EiControl,
+    EventTestControl,
     MaximumLeaderSize,
     PayloadTransferSize,
     PayloadFinalTransferSize1,
@@ -305,12 +354,15 @@ enum MemoryEvent {
 impl MemoryEvent {
     async fn process(self, worker: &Worker, scd_kind: cmd::ScdKind) -> Result<(), ack::ErrorAck> {
         use MemoryEvent::{
-            MaximumLeaderSize, MaximumTrailerSize, PayloadFinalTransferSize1,
-            PayloadFinalTransferSize2, PayloadTransferSize, SiControl, TimestampLatch,
+            EiControl, EventTestControl, MaximumLeaderSize, MaximumTrailerSize,
+            PayloadFinalTransferSize1, PayloadFinalTransferSize2, PayloadTransferSize, SiControl,
+            TimestampLatch,
         };
         match self {
             TimestampLatch => TimestampLatchHandler::handle_events(worker, scd_kind).await,
             SiControl => SiControlHandler::handle_events(worker, scd_kind).await,
+            EiControl => EiControlHandler::handle_events(worker, scd_kind).await,
+            EventTestControl => EventTestControlHandler::handle_events(worker, scd_kind).await,
             MaximumLeaderSize => MaximumLeaderSizeHandler::handle_events(worker, scd_kind).await,
             PayloadTransferSize => {
                 PayloadTransferSizeHandler::handle_events(worker, scd_kind).await
@@ -328,6 +380,8 @@ impl MemoryEvent {
     fn register_events(memory: &mut Memory, sender: &Sender<Self>) {
         TimestampLatchHandler::register(memory, sender);
         SiControlHandler::register(memory, sender);
+        EiControlHandler::register(memory, sender);
+        EventTestControlHandler::register(memory, sender);
         MaximumLeaderSizeHandler::register(memory, sender);
         PayloadTransferSizeHandler::register(memory, sender);
         PayloadFinalTransferSize1Handler::register(memory, sender);
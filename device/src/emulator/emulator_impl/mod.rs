@@ -2,6 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+mod command_queue;
 mod control_module;
 mod control_protocol;
 mod device;
@@ -10,6 +11,7 @@ mod device_pool;
 mod emulator_builder;
 mod event_module;
 mod fake_protocol;
+mod fault;
 mod genapi;
 mod interface;
 mod memory;
@@ -17,6 +19,7 @@ mod memory_event_handler;
 mod shared_queue;
 mod signal;
 mod stream_module;
+mod user_memory;
 
 pub use emulator_builder::*;
 
@@ -24,6 +24,19 @@ impl<T> SharedQueue<T> {
         }
     }
 
+    /// Enqueues all of `elems`, or none of them if the queue has no room for all of them.
+    pub(super) fn enqueue_all(&self, elems: Vec<T>) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.len() + elems.len() <= self.cap {
+            for elem in elems {
+                inner.push_front(elem);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
     pub(super) fn dequeue(&self) -> Option<T> {
         self.inner.lock().unwrap().pop_back()
     }
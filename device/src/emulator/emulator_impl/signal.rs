@@ -27,17 +27,13 @@ pub(super) enum ControlSignal {
 /// Signal sent to event module.
 pub(super) enum EventSignal {
     /// Signal to send event data to tha host.
-    _EventData {
-        event_id: u16,
-        data: Vec<u8>,
-        request_id: u16,
-    },
+    EventData { event_id: u16, data: Vec<u8> },
 
     /// Signal to update timestamp
     UpdateTimestamp(u64),
 
     /// signal to enable event module.
-    _Enable,
+    Enable,
 
     /// signal to disable event module.
     Disable(oneshot::Sender<()>),
@@ -64,6 +60,8 @@ pub(super) enum InterfaceSignal {
     ToEvent(EventSignal),
     ToStream(StreamSignal),
     Halt(IfaceKind),
+    /// Signal to stop responding to the host as if the device were disconnected.
+    Disconnect,
 }
 
 impl From<ControlSignal> for InterfaceSignal {
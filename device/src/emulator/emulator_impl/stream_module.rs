@@ -2,30 +2,61 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use std::{sync::Arc, time::Duration};
+
 use async_std::{
     channel::{Receiver, Sender},
+    future,
     prelude::*,
+    sync::Mutex,
+};
+
+use cameleon_impl::memory::prelude::*;
+
+use crate::{
+    fixture::{StreamPayload, StreamSettings},
+    PixelFormat,
 };
 
 use super::{
     device::Timestamp,
+    memory::{Memory, SIRM},
     shared_queue::SharedQueue,
     signal::{InterfaceSignal, StreamSignal},
 };
 
-// TODO: Implement stream module.
+/// Interval between frames sent while the stream module is enabled.
+const FRAME_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Stream module, which sends a frame every [`FRAME_INTERVAL`] while `SIRM` is enabled.
+///
+/// A frame is sent as a leader, payload transfers split by the transfer sizes written to `SIRM`,
+/// and a trailer. The payload is an image or a GenDC container, see [`StreamPayload`]. The whole frame is dropped if the stream queue has no room for it, so the host
+/// never receives a partial frame.
 pub(super) struct StreamModule {
-    _queue: SharedQueue<Vec<u8>>,
-    _timestamp: Timestamp,
+    queue: SharedQueue<Vec<u8>>,
+    timestamp: Timestamp,
+    memory: Arc<Mutex<Memory>>,
+    settings: StreamSettings,
 
+    /// Block ID of the next frame.
+    block_id: u64,
     enabled: bool,
 }
 
 impl StreamModule {
-    pub(super) fn new(timestamp: Timestamp, queue: SharedQueue<Vec<u8>>) -> Self {
+    pub(super) fn new(
+        timestamp: Timestamp,
+        queue: SharedQueue<Vec<u8>>,
+        memory: Arc<Mutex<Memory>>,
+        settings: StreamSettings,
+    ) -> Self {
         Self {
-            _timestamp: timestamp,
-            _queue: queue,
+            queue,
+            timestamp,
+            memory,
+            settings,
+            block_id: 0,
             enabled: false,
         }
     }
@@ -35,9 +66,21 @@ impl StreamModule {
         _signal_tx: Sender<InterfaceSignal>,
         mut signal_rx: Receiver<StreamSignal>,
     ) {
-        while let Some(signal) = signal_rx.next().await {
+        loop {
+            let signal = if self.enabled {
+                match future::timeout(FRAME_INTERVAL, signal_rx.next()).await {
+                    Ok(signal) => signal,
+                    Err(_) => {
+                        self.send_frame().await;
+                        continue;
+                    }
+                }
+            } else {
+                signal_rx.next().await
+            };
+
             match signal {
-                StreamSignal::Enable => {
+                Some(StreamSignal::Enable) => {
                     if self.enabled {
                         log::warn! {"receive stream enable signal, but stream module is already enabled"}
                     } else {
@@ -46,19 +89,491 @@ impl StreamModule {
                     }
                 }
 
-                StreamSignal::Disable(_completed) => {
+                Some(StreamSignal::Disable(_completed)) => {
                     if self.enabled {
                         self.enabled = false;
+                        // Frames left in the queue are never completed by the host.
+                        self.queue.clear();
                         log::info! {"stream module is disabled"};
                     } else {
                         log::warn! {"receive stream disable signal, but stream module is already disabled"}
                     }
                 }
 
-                StreamSignal::Shutdown => {
+                Some(StreamSignal::Shutdown) | None => {
                     break;
                 }
             }
         }
     }
+
+    async fn send_frame(&mut self) {
+        let sizes = match TransferSizes::read(&*self.memory.lock().await) {
+            Ok(sizes) => sizes,
+            Err(e) => {
+                log::error!("can't read SIRM: cause {}", e);
+                return;
+            }
+        };
+
+        let block_id = self.block_id;
+        self.block_id = self.block_id.wrapping_add(1);
+        let timestamp = self.timestamp.as_nanos().await;
+        let frame = match self.settings.payload {
+            StreamPayload::Image => frame::image_frame(&self.settings, &sizes, block_id, timestamp),
+            StreamPayload::GenDc => frame::gendc_frame(&self.settings, &sizes, block_id, timestamp),
+        };
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                log::error!("can't generate frame: cause {}", e);
+                return;
+            }
+        };
+        if !self.queue.enqueue_all(frame) {
+            log::warn!("stream queue is full, frame {} is dropped", block_id);
+        }
+    }
+}
+
+/// Returns the size of the payload of a frame sent with `settings`, which is written to
+/// `RequiredPayloadSize` of `SIRM`.
+pub(super) fn payload_size(settings: &StreamSettings) -> u64 {
+    let image_size = frame::image_size(settings);
+    match settings.payload {
+        StreamPayload::Image => image_size,
+        StreamPayload::GenDc => frame::GENDC_DESCRIPTOR_SIZE + image_size + frame::METADATA_SIZE,
+    }
+}
+
+/// Sizes written to `SIRM` by the host, which determine how a frame is split into transfers.
+#[derive(Debug, Clone, Copy)]
+struct TransferSizes {
+    payload_size: u64,
+    payload_transfer_size: u32,
+    payload_transfer_count: u32,
+    payload_final_transfer_size1: u32,
+    payload_final_transfer_size2: u32,
+}
+
+impl TransferSizes {
+    fn read(memory: &Memory) -> cameleon_impl::memory::MemoryResult<Self> {
+        Ok(Self {
+            payload_size: memory.read::<SIRM::RequiredPayloadSize>()?,
+            payload_transfer_size: memory.read::<SIRM::PayloadTransferSize>()?,
+            payload_transfer_count: memory.read::<SIRM::PayloadTransferCount>()?,
+            payload_final_transfer_size1: memory.read::<SIRM::PayloadFinalTransferSize1>()?,
+            payload_final_transfer_size2: memory.read::<SIRM::PayloadFinalTransferSize2>()?,
+        })
+    }
+
+    /// Sizes of the payload transfers in the order they are sent.
+    fn transfers(&self) -> impl Iterator<Item = usize> {
+        std::iter::repeat_n(
+            self.payload_transfer_size,
+            self.payload_transfer_count as usize,
+        )
+        .chain([
+            self.payload_final_transfer_size1,
+            self.payload_final_transfer_size2,
+        ])
+        .filter(|size| *size != 0)
+        .map(|size| size as usize)
+    }
+}
+
+mod frame {
+    use std::io;
+
+    use crate::u3v::protocol::util::WriteBytes;
+
+    use super::{PixelFormat, StreamSettings, TransferSizes};
+
+    const LEADER_MAGIC: u32 = 0x4C56_3355;
+    const TRAILER_MAGIC: u32 = 0x5456_3355;
+    const IMAGE_PAYLOAD_TYPE: u16 = 0x0001;
+    const GENDC_PAYLOAD_TYPE: u16 = 0x000B;
+    const IMAGE_LEADER_SIZE: u16 = 52;
+    const IMAGE_TRAILER_SIZE: u16 = 32;
+    const GENDC_LEADER_SIZE: u16 = 28;
+    const GENDC_TRAILER_SIZE: u16 = 28;
+
+    const GENDC_SIGNATURE: u32 = 0x4344_4E47;
+    const CONTAINER_HEADER_TYPE: u16 = 0x1000;
+    const COMPONENT_HEADER_TYPE: u16 = 0x2000;
+    const METADATA_PART_HEADER_TYPE: u16 = 0x4000;
+    const PART_2D_HEADER_TYPE: u16 = 0x4200;
+    const INTENSITY_COMPONENT_TYPE: u64 = 0x0001;
+    const METADATA_COMPONENT_TYPE: u64 = 0x8001;
+
+    /// Sizes of the headers of the GenDC container, each component has a single part.
+    const CONTAINER_HEADER_SIZE: u32 = 56 + 8 * 2;
+    const COMPONENT_HEADER_SIZE: u32 = 48 + 8;
+    const PART_2D_HEADER_SIZE: u32 = 48;
+    const METADATA_PART_HEADER_SIZE: u32 = 40;
+    pub(super) const GENDC_DESCRIPTOR_SIZE: u64 = (CONTAINER_HEADER_SIZE
+        + COMPONENT_HEADER_SIZE * 2
+        + PART_2D_HEADER_SIZE
+        + METADATA_PART_HEADER_SIZE) as u64;
+    /// Size of the metadata part, which holds the block ID and the timestamp.
+    pub(super) const METADATA_SIZE: u64 = 16;
+
+    /// Returns the size of an image sent with `settings`.
+    pub(super) fn image_size(settings: &StreamSettings) -> u64 {
+        let bits = u64::from(settings.width)
+            * u64::from(settings.height)
+            * PixelFormat::from(settings.pixel_format).bits_per_pixel() as u64;
+        bits.div_ceil(8)
+    }
+
+    /// Returns the transfers of an image frame, the `i`th byte of the payload is
+    /// `block_id + i` truncated to `u8`.
+    pub(super) fn image_frame(
+        settings: &StreamSettings,
+        sizes: &TransferSizes,
+        block_id: u64,
+        timestamp: u64,
+    ) -> io::Result<Vec<Vec<u8>>> {
+        let payload: Vec<u8> = (0..sizes.payload_size)
+            .map(|i| block_id.wrapping_add(i) as u8)
+            .collect();
+
+        let leader = leader(settings, block_id, timestamp)?;
+        let trailer = |valid_payload_size| trailer(settings, block_id, valid_payload_size);
+        split_frame(leader, &payload, sizes, trailer)
+    }
+
+    /// Returns the transfers of a GenDC frame, whose container consists of an image component
+    /// filled like [`image_frame`] and a metadata component.
+    pub(super) fn gendc_frame(
+        settings: &StreamSettings,
+        sizes: &TransferSizes,
+        block_id: u64,
+        timestamp: u64,
+    ) -> io::Result<Vec<Vec<u8>>> {
+        let payload = gendc_container(settings, block_id, timestamp)?;
+
+        let mut leader = Vec::with_capacity(GENDC_LEADER_SIZE as usize);
+        generic_leader(&mut leader, GENDC_LEADER_SIZE, block_id, GENDC_PAYLOAD_TYPE)?;
+        leader.write_bytes(timestamp)?;
+        let trailer = |valid_payload_size| {
+            let mut buf = Vec::with_capacity(GENDC_TRAILER_SIZE as usize);
+            generic_trailer(&mut buf, GENDC_TRAILER_SIZE, block_id, valid_payload_size)?;
+            Ok(buf)
+        };
+        split_frame(leader, &payload, sizes, trailer)
+    }
+
+    /// Splits `payload` into the transfers following `leader` by `sizes`, then appends the
+    /// trailer returned by `trailer` with the number of bytes sent.
+    fn split_frame(
+        leader: Vec<u8>,
+        payload: &[u8],
+        sizes: &TransferSizes,
+        trailer: impl FnOnce(u64) -> io::Result<Vec<u8>>,
+    ) -> io::Result<Vec<Vec<u8>>> {
+        let mut transfers = vec![leader];
+        let mut rest = payload;
+        for size in sizes.transfers() {
+            if rest.is_empty() {
+                break;
+            }
+            let (transfer, tail) = rest.split_at(size.min(rest.len()));
+            transfers.push(transfer.to_vec());
+            rest = tail;
+        }
+        let valid_payload_size = (payload.len() - rest.len()) as u64;
+        transfers.push(trailer(valid_payload_size)?);
+        Ok(transfers)
+    }
+
+    /// Returns the GenDC container of a frame. The data of the image part follows the
+    /// descriptor, and the data of the metadata part follows the image.
+    fn gendc_container(
+        settings: &StreamSettings,
+        block_id: u64,
+        timestamp: u64,
+    ) -> io::Result<Vec<u8>> {
+        let image_size = image_size(settings);
+        let data_size = image_size + METADATA_SIZE;
+        let image_component = u64::from(CONTAINER_HEADER_SIZE);
+        let image_part = image_component + u64::from(COMPONENT_HEADER_SIZE);
+        let metadata_component = image_part + u64::from(PART_2D_HEADER_SIZE);
+        let metadata_part = metadata_component + u64::from(COMPONENT_HEADER_SIZE);
+
+        let mut buf = Vec::with_capacity((GENDC_DESCRIPTOR_SIZE + data_size) as usize);
+        buf.write_bytes(GENDC_SIGNATURE)?;
+        // Version 1.1.0 and reserved.
+        buf.write_bytes(1_u8)?;
+        buf.write_bytes(1_u8)?;
+        buf.write_bytes(0_u8)?;
+        buf.write_bytes(0_u8)?;
+        buf.write_bytes(CONTAINER_HEADER_TYPE)?;
+        // Flags.
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(CONTAINER_HEADER_SIZE)?;
+        buf.write_bytes(block_id)?;
+        // Variable fields.
+        buf.write_bytes(0_u64)?;
+        buf.write_bytes(data_size)?;
+        buf.write_bytes(GENDC_DESCRIPTOR_SIZE)?;
+        buf.write_bytes(GENDC_DESCRIPTOR_SIZE as u32)?;
+        // Component count and offsets.
+        buf.write_bytes(2_u32)?;
+        buf.write_bytes(image_component)?;
+        buf.write_bytes(metadata_component)?;
+
+        let pixel_format: u32 = PixelFormat::from(settings.pixel_format).into();
+        component_header(
+            &mut buf,
+            INTENSITY_COMPONENT_TYPE,
+            pixel_format,
+            timestamp,
+            image_part,
+        )?;
+        part_header(
+            &mut buf,
+            PART_2D_HEADER_TYPE,
+            PART_2D_HEADER_SIZE,
+            pixel_format,
+            0,
+            image_size,
+        )?;
+        buf.write_bytes(settings.width)?;
+        buf.write_bytes(settings.height)?;
+        // X padding, Y padding and reserved.
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(0_u32)?;
+
+        component_header(
+            &mut buf,
+            METADATA_COMPONENT_TYPE,
+            0,
+            timestamp,
+            metadata_part,
+        )?;
+        part_header(
+            &mut buf,
+            METADATA_PART_HEADER_TYPE,
+            METADATA_PART_HEADER_SIZE,
+            0,
+            image_size,
+            METADATA_SIZE,
+        )?;
+        // Type specific info.
+        buf.write_bytes(0_u64)?;
+        debug_assert_eq!(buf.len() as u64, GENDC_DESCRIPTOR_SIZE);
+
+        buf.extend((0..image_size).map(|i| block_id.wrapping_add(i) as u8));
+        buf.write_bytes(block_id)?;
+        buf.write_bytes(timestamp)?;
+        Ok(buf)
+    }
+
+    /// Writes the header of a component which has a single part at `part_offset`.
+    fn component_header(
+        buf: &mut Vec<u8>,
+        type_id: u64,
+        format: u32,
+        timestamp: u64,
+        part_offset: u64,
+    ) -> io::Result<()> {
+        buf.write_bytes(COMPONENT_HEADER_TYPE)?;
+        // Flags.
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(COMPONENT_HEADER_SIZE)?;
+        // Reserved, group ID, source ID and region ID.
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(0_u16)?;
+        // Region offset X and Y.
+        buf.write_bytes(0_u32)?;
+        buf.write_bytes(0_u32)?;
+        buf.write_bytes(timestamp)?;
+        buf.write_bytes(type_id)?;
+        buf.write_bytes(format)?;
+        // Reserved.
+        buf.write_bytes(0_u16)?;
+        // Part count and offsets.
+        buf.write_bytes(1_u16)?;
+        buf.write_bytes(part_offset)
+    }
+
+    /// Writes the header common to all parts, whose data lies at `flow_offset` of the flow
+    /// following the descriptor.
+    fn part_header(
+        buf: &mut Vec<u8>,
+        header_type: u16,
+        header_size: u32,
+        format: u32,
+        flow_offset: u64,
+        data_size: u64,
+    ) -> io::Result<()> {
+        buf.write_bytes(header_type)?;
+        // Flags.
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(header_size)?;
+        buf.write_bytes(format)?;
+        // Reserved and flow ID.
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(flow_offset)?;
+        buf.write_bytes(data_size)
+    }
+
+    fn generic_leader(
+        buf: &mut Vec<u8>,
+        leader_size: u16,
+        block_id: u64,
+        payload_type: u16,
+    ) -> io::Result<()> {
+        buf.write_bytes(LEADER_MAGIC)?;
+        // Reserved.
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(leader_size)?;
+        buf.write_bytes(block_id)?;
+        // Reserved.
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(payload_type)
+    }
+
+    fn generic_trailer(
+        buf: &mut Vec<u8>,
+        trailer_size: u16,
+        block_id: u64,
+        valid_payload_size: u64,
+    ) -> io::Result<()> {
+        buf.write_bytes(TRAILER_MAGIC)?;
+        // Reserved.
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(trailer_size)?;
+        buf.write_bytes(block_id)?;
+        // Status, `U3V_STATUS_SUCCESS`.
+        buf.write_bytes(0_u16)?;
+        // Reserved.
+        buf.write_bytes(0_u16)?;
+        buf.write_bytes(valid_payload_size)
+    }
+
+    fn leader(settings: &StreamSettings, block_id: u64, timestamp: u64) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(IMAGE_LEADER_SIZE as usize);
+        generic_leader(&mut buf, IMAGE_LEADER_SIZE, block_id, IMAGE_PAYLOAD_TYPE)?;
+        buf.write_bytes(timestamp)?;
+        buf.write_bytes::<u32>(PixelFormat::from(settings.pixel_format).into())?;
+        buf.write_bytes(settings.width)?;
+        buf.write_bytes(settings.height)?;
+        // X offset and Y offset.
+        buf.write_bytes(0_u32)?;
+        buf.write_bytes(0_u32)?;
+        // X padding.
+        buf.write_bytes(0_u16)?;
+        // Reserved.
+        buf.write_bytes(0_u16)?;
+        Ok(buf)
+    }
+
+    fn trailer(
+        settings: &StreamSettings,
+        block_id: u64,
+        valid_payload_size: u64,
+    ) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(IMAGE_TRAILER_SIZE as usize);
+        generic_trailer(&mut buf, IMAGE_TRAILER_SIZE, block_id, valid_payload_size)?;
+        // Actual height.
+        buf.write_bytes(settings.height)?;
+        Ok(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        fixture::StreamPixelFormat,
+        u3v::protocol::stream::{
+            GenDcLeader, ImageLeader, ImageTrailer, Leader, PayloadType, Trailer,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_image_frame() {
+        let settings = StreamSettings {
+            width: 4,
+            height: 3,
+            pixel_format: StreamPixelFormat::Mono8,
+            payload: StreamPayload::Image,
+        };
+        let sizes = TransferSizes {
+            payload_size: 12,
+            payload_transfer_size: 4,
+            payload_transfer_count: 2,
+            payload_final_transfer_size1: 0,
+            payload_final_transfer_size2: 4,
+        };
+        let frame = frame::image_frame(&settings, &sizes, 7, 100).unwrap();
+        assert_eq!(frame.len(), 5);
+
+        let leader = Leader::parse(&frame[0]).unwrap();
+        assert_eq!(leader.block_id(), 7);
+        assert_eq!(leader.payload_type(), PayloadType::Image);
+        let image_leader: ImageLeader = leader.specific_leader_as().unwrap();
+        assert_eq!(image_leader.pixel_format(), PixelFormat::Mono8);
+        assert_eq!((image_leader.width(), image_leader.height()), (4, 3));
+        assert_eq!(image_leader.timestamp(), Duration::from_nanos(100));
+
+        let payload = frame[1..4].concat();
+        assert_eq!(frame[1].len(), 4);
+        assert_eq!(payload, (7..19).collect::<Vec<u8>>());
+
+        let trailer = Trailer::parse(&frame[4]).unwrap();
+        assert_eq!(trailer.block_id(), 7);
+        assert_eq!(trailer.valid_payload_size(), 12);
+        let image_trailer: ImageTrailer = trailer.specific_trailer_as().unwrap();
+        assert_eq!(image_trailer.actual_height(), 3);
+    }
+
+    #[test]
+    fn test_gendc_frame() {
+        let settings = StreamSettings {
+            width: 4,
+            height: 3,
+            pixel_format: StreamPixelFormat::Mono8,
+            payload: StreamPayload::GenDc,
+        };
+        let payload_size = payload_size(&settings);
+        assert_eq!(payload_size, frame::GENDC_DESCRIPTOR_SIZE + 12 + 16);
+        let sizes = TransferSizes {
+            payload_size,
+            payload_transfer_size: 256,
+            payload_transfer_count: 1,
+            payload_final_transfer_size1: 256,
+            payload_final_transfer_size2: 0,
+        };
+        let frame = frame::gendc_frame(&settings, &sizes, 7, 100).unwrap();
+        assert_eq!(frame.len(), 4);
+
+        let leader = Leader::parse(&frame[0]).unwrap();
+        assert_eq!(leader.payload_type(), PayloadType::GenDc);
+        let gendc_leader: GenDcLeader = leader.specific_leader_as().unwrap();
+        assert_eq!(gendc_leader.timestamp(), Duration::from_nanos(100));
+
+        let container = frame[1..3].concat();
+        assert_eq!(container.len() as u64, payload_size);
+        // Signature `GNDC`, and the data offset.
+        assert_eq!(&container[..4], b"GNDC");
+        assert_eq!(
+            container[40..48],
+            frame::GENDC_DESCRIPTOR_SIZE.to_le_bytes()
+        );
+        let data = &container[frame::GENDC_DESCRIPTOR_SIZE as usize..];
+        assert_eq!(data[..12], (7..19).collect::<Vec<u8>>()[..]);
+        assert_eq!(data[12..20], 7_u64.to_le_bytes());
+        assert_eq!(data[20..], 100_u64.to_le_bytes());
+
+        let trailer = Trailer::parse(&frame[3]).unwrap();
+        assert_eq!(trailer.valid_payload_size(), payload_size);
+    }
 }
@@ -0,0 +1,88 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{ops::Range, sync::Mutex};
+
+use cameleon_impl::memory::{AccessRight, MemoryError, MemoryResult};
+
+/// Memory regions placed outside of the built-in register maps, e.g. registers and `GenApi` XML
+/// defined by a fixture.
+///
+/// Regions never overlap each other nor the built-in register maps.
+#[derive(Default)]
+pub(super) struct UserMemory {
+    regions: Mutex<Vec<Region>>,
+}
+
+struct Region {
+    start: usize,
+    access: AccessRight,
+    data: Vec<u8>,
+}
+
+impl Region {
+    fn range(&self) -> Range<usize> {
+        self.start..self.start + self.data.len()
+    }
+}
+
+impl UserMemory {
+    pub(super) fn add_region(&mut self, start: usize, access: AccessRight, data: Vec<u8>) {
+        self.regions.get_mut().unwrap().push(Region {
+            start,
+            access,
+            data,
+        });
+    }
+
+    /// Reads `range` from the region containing it.
+    ///
+    /// `None` is returned if `range` doesn't overlap any region, then the built-in register maps
+    /// should be read instead.
+    pub(super) fn read(&self, range: Range<usize>) -> Option<MemoryResult<Vec<u8>>> {
+        let regions = self.regions.lock().unwrap();
+        Some(find_region(&regions, &range)?.and_then(|index| {
+            let region = &regions[index];
+            if !region.access.is_readable() {
+                return Err(MemoryError::AddressNotReadable);
+            }
+            let offset = range.start - region.start;
+            Ok(region.data[offset..offset + range.len()].to_vec())
+        }))
+    }
+
+    /// Writes `data` to the region containing it.
+    ///
+    /// `None` is returned if `data` doesn't overlap any region, then the built-in register maps
+    /// should be written instead.
+    pub(super) fn write(&self, address: usize, data: &[u8]) -> Option<MemoryResult<()>> {
+        let mut regions = self.regions.lock().unwrap();
+        let range = address..address.checked_add(data.len())?;
+        Some(find_region(&regions, &range)?.and_then(|index| {
+            let region = &mut regions[index];
+            if !region.access.is_writable() {
+                return Err(MemoryError::AddressNotWritable);
+            }
+            let offset = address - region.start;
+            region.data[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }))
+    }
+}
+
+/// Finds the index of the region containing `range`, an error is returned if `range` crosses the
+/// boundary of a region.
+fn find_region(regions: &[Region], range: &Range<usize>) -> Option<MemoryResult<usize>> {
+    let index = regions.iter().position(|region| {
+        let region = region.range();
+        range.start < region.end && region.start < range.end
+    })?;
+
+    let region = regions[index].range();
+    if region.start <= range.start && range.end <= region.end {
+        Some(Ok(index))
+    } else {
+        Some(Err(MemoryError::InvalidAddress))
+    }
+}
@@ -0,0 +1,632 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module defines the fixture format which describes an emulated device.
+//!
+//! A fixture is a TOML document, so that a camera behavior can be reproduced without writing
+//! Rust code. `EmulatorBuilder::from_fixture` of the emulator builds a device serving the registers
+//! and `GenApi` XML of a fixture and injecting its faults.
+//!
+//! ```toml
+//! [device]
+//! vendor_name = "Cameleon"
+//! model_name = "FixtureCamera"
+//! serial_number = "CAM1984"
+//!
+//! [genapi]
+//! file = "camera.xml" # Or `xml = "<RegisterDescription ..."`.
+//!
+//! [[registers]]
+//! name = "Width"
+//! address = 0x30000
+//! length = 4
+//! type = "u32"
+//! access = "RW"
+//! default = 640
+//!
+//! [[faults]]
+//! kind = "timeout"
+//! after_commands = 10
+//! count = 1
+//!
+//! [stream]
+//! width = 640
+//! height = 480
+//! pixel_format = "Mono8"
+//! payload = "image" # Or `"gendc"`.
+//! ```
+
+use std::{
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use toml::Spanned;
+
+use crate::PixelFormat;
+
+#[derive(Debug, Error)]
+pub enum FixtureError {
+    #[error("failed to read fixture: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("line {line}, column {column}: {message}")]
+    Invalid {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+pub type FixtureResult<T> = std::result::Result<T, FixtureError>;
+
+/// A description of an emulated device.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Fixture {
+    /// Identity strings of the device.
+    pub device: DeviceIdentity,
+    /// `GenApi` XML served by the device, the built-in XML is served if `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genapi: Option<GenApiSource>,
+    /// Registers defined in addition to the bootstrap registers.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub registers: Vec<RegisterDef>,
+    /// Faults injected while the device is running.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub faults: Vec<Fault>,
+    /// Settings of the stream, the default settings are used if `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<StreamSettings>,
+}
+
+/// Identity strings of the device, written to `ABRM`.
+///
+/// Fields set to `None` keep the default value of the emulator.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceIdentity {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vendor_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manufacturer_info: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_defined_name: Option<String>,
+}
+
+/// Location of `GenApi` XML.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GenApiSource {
+    /// XML written in the fixture itself.
+    Inline { xml: String },
+    /// XML file, a relative path is resolved against the directory of the fixture.
+    File { file: PathBuf },
+}
+
+/// A register of the device.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegisterDef {
+    pub name: String,
+    pub address: u64,
+    /// Length of the register in bytes.
+    pub length: u64,
+    #[serde(rename = "type")]
+    pub ty: RegisterType,
+    #[serde(default)]
+    pub access: AccessRight,
+    /// Initial value of the register, the register is filled with zero if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<RegisterValue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+    String,
+    Bytes,
+}
+
+impl RegisterType {
+    /// Returns the size of the type in bytes, `None` if the type has variable length.
+    #[must_use]
+    pub fn size(self) -> Option<u64> {
+        match self {
+            Self::U8 | Self::I8 => Some(1),
+            Self::U16 | Self::I16 => Some(2),
+            Self::U32 | Self::I32 | Self::F32 => Some(4),
+            Self::U64 | Self::I64 | Self::F64 => Some(8),
+            Self::String | Self::Bytes => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccessRight {
+    RO,
+    WO,
+    #[default]
+    RW,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum RegisterValue {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+}
+
+/// A fault injected after the device handles `after_commands` control commands.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub after_commands: u64,
+    /// Number of consecutive commands the fault is injected to.
+    #[serde(default = "default_fault_count")]
+    pub count: u64,
+}
+
+fn default_fault_count() -> u64 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaultKind {
+    /// The device doesn't respond to the command.
+    Timeout,
+    /// The device responds with `GENCP_BUSY`.
+    Busy,
+    /// The device stalls the control endpoint.
+    Stall,
+    /// The device is disconnected.
+    Disconnect,
+    /// The device rejects writes to the payload transfer size of `SIRM` with
+    /// `U3V_PAYLOAD_SIZE_NOT_ALIGNED`.
+    ///
+    /// Unlike other faults, only commands writing the payload transfer size count towards `count`.
+    RejectPayloadSize,
+    /// The device responds with a pending ack before handling the command, then responds with the
+    /// ack of the command within the timeout of the pending ack.
+    Pending,
+}
+
+/// Settings of the image frames sent by the emulator.
+///
+/// The default settings are 640x480 `Mono8` images.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StreamSettings {
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: StreamPixelFormat,
+    #[serde(default)]
+    pub payload: StreamPayload,
+}
+
+impl Default for StreamSettings {
+    fn default() -> Self {
+        Self {
+            width: 640,
+            height: 480,
+            pixel_format: StreamPixelFormat::Mono8,
+            payload: StreamPayload::default(),
+        }
+    }
+}
+
+/// Payload type of the frames sent by the emulator.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamPayload {
+    /// The image only.
+    #[default]
+    Image,
+    /// A GenDC container of an image component and a metadata component, the metadata holds the
+    /// block ID and the timestamp of the frame.
+    #[serde(rename = "gendc")]
+    GenDc,
+}
+
+/// Pixel formats of the stream, which determine the required payload size of the emulator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StreamPixelFormat {
+    Mono8,
+    Mono16,
+    RGB8,
+    BGR8,
+    BayerRG8,
+    /// A raw pixel format code, e.g. a vendor specific one which isn't modeled by
+    /// [`PixelFormat`]. Its bits per pixel are taken from the code as defined by PFNC.
+    Raw(u32),
+}
+
+impl From<StreamPixelFormat> for PixelFormat {
+    fn from(format: StreamPixelFormat) -> Self {
+        match format {
+            StreamPixelFormat::Mono8 => Self::Mono8,
+            StreamPixelFormat::Mono16 => Self::Mono16,
+            StreamPixelFormat::RGB8 => Self::RGB8,
+            StreamPixelFormat::BGR8 => Self::BGR8,
+            StreamPixelFormat::BayerRG8 => Self::BayerRG8,
+            StreamPixelFormat::Raw(code) => Self::from_code(code),
+        }
+    }
+}
+
+/// Fixture as written in the document, with spans to report errors.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawFixture {
+    #[serde(default)]
+    device: DeviceIdentity,
+    genapi: Option<Spanned<GenApiSource>>,
+    #[serde(default)]
+    registers: Vec<Spanned<RegisterDef>>,
+    #[serde(default)]
+    faults: Vec<Fault>,
+    stream: Option<Spanned<StreamSettings>>,
+}
+
+impl Fixture {
+    /// Loads and validates the fixture at `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> FixtureResult<Self> {
+        let path = path.as_ref();
+        let src = fs::read_to_string(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::from_toml(&src, base_dir)
+    }
+
+    /// Parses and validates the fixture, a relative path of `GenApi` XML is resolved against
+    /// `base_dir` into an absolute path.
+    pub fn from_toml(src: &str, base_dir: impl AsRef<Path>) -> FixtureResult<Self> {
+        let raw: RawFixture = toml::from_str(src).map_err(|e| {
+            let offset = e.span().map_or(0, |span| span.start);
+            invalid(src, offset, e.message().to_string())
+        })?;
+
+        let genapi = raw
+            .genapi
+            .map(|genapi| {
+                let span = genapi.span();
+                match genapi.into_inner() {
+                    GenApiSource::File { file } => {
+                        let joined = base_dir.as_ref().join(file);
+                        match fs::canonicalize(&joined) {
+                            Ok(file) if file.is_file() => Ok(GenApiSource::File { file }),
+                            _ => Err(invalid(
+                                src,
+                                span.start,
+                                format!("`GenApi` XML file `{}` is not found", joined.display()),
+                            )),
+                        }
+                    }
+                    inline => Ok(inline),
+                }
+            })
+            .transpose()?;
+
+        for (i, reg) in raw.registers.iter().enumerate() {
+            validate_register(reg.get_ref()).map_err(|msg| invalid(src, reg.span().start, msg))?;
+
+            let range = register_range(reg.get_ref());
+            if let Some(prev) = raw.registers[..i]
+                .iter()
+                .find(|prev| overlaps(&register_range(prev.get_ref()), &range))
+            {
+                let (prev_line, _) = line_column(src, prev.span().start);
+                return Err(invalid(
+                    src,
+                    reg.span().start,
+                    format!(
+                        "register `{}` overlaps with register `{}` defined at line {}",
+                        reg.get_ref().name,
+                        prev.get_ref().name,
+                        prev_line
+                    ),
+                ));
+            }
+        }
+
+        if let Some(stream) = &raw.stream {
+            let settings = stream.get_ref();
+            if settings.width == 0 || settings.height == 0 {
+                return Err(invalid(
+                    src,
+                    stream.span().start,
+                    "stream width and height must be positive".into(),
+                ));
+            }
+            if PixelFormat::from(settings.pixel_format).bits_per_pixel() == 0 {
+                return Err(invalid(
+                    src,
+                    stream.span().start,
+                    "bits per pixel of the stream pixel format must be positive".into(),
+                ));
+            }
+        }
+
+        Ok(Self {
+            device: raw.device,
+            genapi,
+            registers: raw.registers.into_iter().map(Spanned::into_inner).collect(),
+            faults: raw.faults,
+            stream: raw.stream.map(Spanned::into_inner),
+        })
+    }
+
+    /// Serializes the fixture into a TOML document.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn to_toml(&self) -> String {
+        // Serialization never fails because all keys are strings and no value is out of range.
+        toml::to_string_pretty(self).unwrap()
+    }
+}
+
+fn validate_register(reg: &RegisterDef) -> Result<(), String> {
+    if reg.length == 0 {
+        return Err(format!("register `{}` has zero length", reg.name));
+    }
+    if reg.address.checked_add(reg.length).is_none() {
+        return Err(format!("register `{}` exceeds the address space", reg.name));
+    }
+    if let Some(size) = reg.ty.size() {
+        if size != reg.length {
+            return Err(format!(
+                "register `{}` has length {}, but `{:?}` requires {}",
+                reg.name, reg.length, reg.ty, size
+            ));
+        }
+    }
+
+    let default = match &reg.default {
+        Some(default) => default,
+        None => return Ok(()),
+    };
+    let fits = match (reg.ty, default) {
+        (
+            RegisterType::F32 | RegisterType::F64,
+            RegisterValue::Float(_) | RegisterValue::Integer(_),
+        ) => true,
+        (RegisterType::String, RegisterValue::String(s)) => {
+            s.is_ascii() && s.len() as u64 <= reg.length
+        }
+        (RegisterType::Bytes, RegisterValue::Bytes(b)) => b.len() as u64 <= reg.length,
+        (ty, RegisterValue::Integer(i)) => integer_fits(ty, *i),
+        _ => false,
+    };
+    if fits {
+        Ok(())
+    } else {
+        Err(format!(
+            "default value of register `{}` doesn't fit in `{:?}` of length {}",
+            reg.name, reg.ty, reg.length
+        ))
+    }
+}
+
+fn integer_fits(ty: RegisterType, i: i64) -> bool {
+    use std::convert::TryFrom;
+    match ty {
+        RegisterType::U8 => u8::try_from(i).is_ok(),
+        RegisterType::U16 => u16::try_from(i).is_ok(),
+        RegisterType::U32 => u32::try_from(i).is_ok(),
+        RegisterType::U64 => i >= 0,
+        RegisterType::I8 => i8::try_from(i).is_ok(),
+        RegisterType::I16 => i16::try_from(i).is_ok(),
+        RegisterType::I32 => i32::try_from(i).is_ok(),
+        RegisterType::I64 => true,
+        _ => false,
+    }
+}
+
+fn register_range(reg: &RegisterDef) -> Range<u64> {
+    reg.address..reg.address.saturating_add(reg.length)
+}
+
+fn overlaps(lhs: &Range<u64>, rhs: &Range<u64>) -> bool {
+    lhs.start < rhs.end && rhs.start < lhs.end
+}
+
+pub(crate) fn invalid(src: &str, offset: usize, message: String) -> FixtureError {
+    let (line, column) = line_column(src, offset);
+    FixtureError::Invalid {
+        line,
+        column,
+        message,
+    }
+}
+
+/// Returns 1-based line and column of `offset` in `src`.
+fn line_column(src: &str, offset: usize) -> (usize, usize) {
+    let head = &src[..offset.min(src.len())];
+    let line = head.matches('\n').count() + 1;
+    let column = head.len() - head.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+    }
+
+    #[test]
+    fn test_load_example_fixtures() {
+        let fixture = Fixture::from_path(fixture_dir().join("mono_camera.toml")).unwrap();
+        assert_eq!(fixture.device.model_name.as_deref(), Some("MonoCamera"));
+        assert!(matches!(
+            fixture.genapi,
+            Some(GenApiSource::File { ref file }) if file.ends_with("mono_camera.xml")
+        ));
+        assert_eq!(fixture.registers.len(), 3);
+        assert_eq!(
+            fixture.registers[0].default,
+            Some(RegisterValue::Integer(640))
+        );
+        assert_eq!(
+            fixture.stream.unwrap().pixel_format,
+            StreamPixelFormat::Mono8
+        );
+
+        let fixture = Fixture::from_path(fixture_dir().join("flaky_camera.toml")).unwrap();
+        assert!(matches!(fixture.genapi, Some(GenApiSource::Inline { .. })));
+        assert_eq!(
+            fixture.faults,
+            vec![
+                Fault {
+                    kind: FaultKind::Timeout,
+                    after_commands: 5,
+                    count: 2,
+                },
+                Fault {
+                    kind: FaultKind::Disconnect,
+                    after_commands: 100,
+                    count: 1,
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlapping_registers() {
+        let src = r#"
+[[registers]]
+name = "Width"
+address = 0x100
+length = 4
+type = "u32"
+
+[[registers]]
+name = "Height"
+address = 0x102
+length = 4
+type = "u32"
+"#;
+        match Fixture::from_toml(src, "").unwrap_err() {
+            FixtureError::Invalid { line, message, .. } => {
+                assert_eq!(line, 8);
+                assert!(message.contains("`Height` overlaps with register `Width`"));
+                assert!(message.contains("line 2"));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_missing_xml_file() {
+        let src = "[device]\nmodel_name = \"Camera\"\n\n[genapi]\nfile = \"missing.xml\"\n";
+        match Fixture::from_toml(src, fixture_dir()).unwrap_err() {
+            FixtureError::Invalid { line, message, .. } => {
+                assert_eq!(line, 4);
+                assert!(message.contains("missing.xml"));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_invalid_register() {
+        let src = "[[registers]]\nname = \"Gain\"\naddress = 0\nlength = 2\ntype = \"u32\"\n";
+        assert!(matches!(
+            Fixture::from_toml(src, "").unwrap_err(),
+            FixtureError::Invalid { line: 1, .. }
+        ));
+
+        let src = "[[registers]]\nname = \"Gain\"\naddress = 0\nlength = 1\ntype = \"u8\"\ndefault = 256\n";
+        assert!(Fixture::from_toml(src, "").is_err());
+
+        let src = "[[registers]]\nname = \"Gain\"\naddress = 0\nlength = 1\ntype = \"u8\"\nacess = \"RO\"\n";
+        match Fixture::from_toml(src, "").unwrap_err() {
+            FixtureError::Invalid { line, .. } => assert_eq!(line, 6),
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_stream_payload() {
+        let src = "[stream]\nwidth = 16\nheight = 8\npixel_format = \"Mono8\"\n";
+        let stream = Fixture::from_toml(src, "").unwrap().stream.unwrap();
+        assert_eq!(stream.payload, StreamPayload::Image);
+
+        let src = format!("{}payload = \"gendc\"\n", src);
+        let stream = Fixture::from_toml(&src, "").unwrap().stream.unwrap();
+        assert_eq!(stream.payload, StreamPayload::GenDc);
+    }
+
+    #[test]
+    fn test_raw_pixel_format() {
+        let src = "[stream]\nwidth = 16\nheight = 8\npixel_format = { Raw = 0x8108_0001 }\n";
+        let stream = Fixture::from_toml(src, "").unwrap().stream.unwrap();
+        assert_eq!(stream.pixel_format, StreamPixelFormat::Raw(0x8108_0001));
+        assert_eq!(
+            PixelFormat::from(stream.pixel_format),
+            PixelFormat::Unknown(0x8108_0001)
+        );
+        assert_eq!(
+            PixelFormat::from(StreamPixelFormat::Raw(0x0108_0001)),
+            PixelFormat::Mono8
+        );
+
+        let src = "[stream]\nwidth = 16\nheight = 8\npixel_format = { Raw = 0x8100_0001 }\n";
+        assert!(Fixture::from_toml(src, "").is_err());
+    }
+
+    #[test]
+    fn test_reject_payload_size_fault() {
+        let src = "[[faults]]\nkind = \"reject_payload_size\"\nafter_commands = 0\n";
+        let fixture = Fixture::from_toml(src, "").unwrap();
+        assert_eq!(
+            fixture.faults,
+            vec![Fault {
+                kind: FaultKind::RejectPayloadSize,
+                after_commands: 0,
+                count: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let fixture = Fixture::from_path(fixture_dir().join("mono_camera.toml")).unwrap();
+        let reloaded = Fixture::from_toml(&fixture.to_toml(), "").unwrap();
+        assert_eq!(fixture, reloaded);
+
+        let fixture = Fixture::from_path(fixture_dir().join("flaky_camera.toml")).unwrap();
+        let reloaded = Fixture::from_toml(&fixture.to_toml(), "").unwrap();
+        assert_eq!(fixture, reloaded);
+
+        // A relative path is resolved only once.
+        let fixture = Fixture::from_path("tests/fixtures/mono_camera.toml").unwrap();
+        let reloaded = Fixture::from_toml(&fixture.to_toml(), "tests/fixtures").unwrap();
+        assert_eq!(fixture, reloaded);
+    }
+}
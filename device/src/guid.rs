@@ -0,0 +1,289 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! GUID of a device or a `GenApi` xml file.
+//!
+//! GUIDs are found in several forms, e.g. `2A2B00000001` in the U3V device descriptor and
+//! `{9F1C5E2A-44D0-B7C3-0000-000000000001}` in `ProductGuid` attribute of `GenApi` xml.
+//! [`Guid`] normalizes them so that the same GUID compares equal regardless of its form.
+
+use std::{convert::TryFrom, fmt, str::FromStr};
+
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Lengths of the hyphen separated groups of U3V device GUID, i.e. 16 bit vendor id followed by
+/// 32 bit unique id.
+const DEVICE_GROUPS: &[usize] = &[4, 8];
+
+/// Lengths of the hyphen separated groups of 128 bit GUID.
+const GUID128_GROUPS: &[usize] = &[8, 4, 4, 4, 12];
+
+/// A GUID which is either 48 bit U3V device GUID or 128 bit GUID.
+///
+/// `Guid` is parsed from both of hyphenated and bare hex forms case-insensitively, and
+/// optionally enclosed in braces. The canonical string representation is lowercase and
+/// hyphenated, e.g. `2a2b-00000001` and `9f1c5e2a-44d0-b7c3-0000-000000000001`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Guid {
+    bytes: [u8; 16],
+    len: u8,
+}
+
+impl Guid {
+    /// Constructs `Guid` from its bytes.
+    ///
+    /// The length of `bytes` must be 6 or 16.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ParseGuidError> {
+        if Self::groups(bytes.len()).is_none() {
+            let input = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            return Err(ParseGuidError::new(
+                input,
+                "the length must be 6 or 16 bytes",
+            ));
+        }
+
+        let mut guid = Self {
+            bytes: [0; 16],
+            len: bytes.len() as u8,
+        };
+        guid.bytes[..bytes.len()].copy_from_slice(bytes);
+        Ok(guid)
+    }
+
+    /// Returns the bytes of the GUID in the order of its string representation.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    /// Returns `true` if the GUID is 48 bit U3V device GUID.
+    #[must_use]
+    pub fn is_device_guid(&self) -> bool {
+        self.len == 6
+    }
+
+    /// Returns the USB vendor id embedded in the first 16 bits of U3V device GUID, or `None` if
+    /// the GUID is 128 bit GUID.
+    #[must_use]
+    pub fn vendor_id(&self) -> Option<u16> {
+        if self.is_device_guid() {
+            Some(u16::from_be_bytes([self.bytes[0], self.bytes[1]]))
+        } else {
+            None
+        }
+    }
+
+    fn groups(len: usize) -> Option<&'static [usize]> {
+        match len {
+            6 => Some(DEVICE_GROUPS),
+            16 => Some(GUID128_GROUPS),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut digits = self.as_bytes().iter().flat_map(|b| [b >> 4, b & 0xf]);
+        for (i, &len) in Self::groups(self.len as usize).unwrap().iter().enumerate() {
+            if i != 0 {
+                f.write_str("-")?;
+            }
+            for digit in digits.by_ref().take(len) {
+                write!(f, "{:x}", digit)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Guid({})", self)
+    }
+}
+
+/// An error returned when parsing [`Guid`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid GUID `{input}`: {reason}")]
+pub struct ParseGuidError {
+    input: String,
+    reason: &'static str,
+}
+
+impl ParseGuidError {
+    fn new(input: String, reason: &'static str) -> Self {
+        Self { input, reason }
+    }
+
+    /// Returns the input which failed to be parsed.
+    #[must_use]
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+}
+
+impl FromStr for Guid {
+    type Err = ParseGuidError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = |reason| ParseGuidError::new(s.to_string(), reason);
+
+        let body = match s.strip_prefix('{') {
+            Some(rest) => rest
+                .strip_suffix('}')
+                .ok_or_else(|| err("unbalanced braces"))?,
+            None => s,
+        };
+
+        let digits: Vec<u8> = body
+            .bytes()
+            .filter(|&b| b != b'-')
+            .map(|b| {
+                char::from(b)
+                    .to_digit(16)
+                    .map(|d| d as u8)
+                    .ok_or_else(|| err("contains a non hex digit"))
+            })
+            .collect::<Result<_, _>>()?;
+        if digits.len() % 2 == 1 {
+            return Err(err("the number of hex digits must be 12 or 32"));
+        }
+        let groups = Self::groups(digits.len() / 2)
+            .ok_or_else(|| err("the number of hex digits must be 12 or 32"))?;
+
+        // Hyphens are optional, but they must be at the canonical positions if exist.
+        if body.contains('-') {
+            let lens: Vec<usize> = body.split('-').map(str::len).collect();
+            if lens != groups {
+                return Err(err("hyphens are misplaced"));
+            }
+        }
+
+        let bytes: Vec<u8> = digits.chunks(2).map(|d| d[0] << 4 | d[1]).collect();
+        Self::from_bytes(&bytes).map_err(|_| err("the number of hex digits must be 12 or 32"))
+    }
+}
+
+impl TryFrom<&str> for Guid {
+    type Error = ParseGuidError;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl TryFrom<String> for Guid {
+    type Error = ParseGuidError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Guid> for String {
+    fn from(guid: Guid) -> Self {
+        guid.to_string()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Guid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Guid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let device: Guid = "2A2B00000001".parse().unwrap();
+        assert_eq!(device.as_bytes(), &[0x2a, 0x2b, 0, 0, 0, 1]);
+        assert!(device.is_device_guid());
+        assert_eq!(device.vendor_id(), Some(0x2a2b));
+        assert_eq!(device.to_string(), "2a2b-00000001");
+
+        for s in &[
+            "9F1C5E2A44D0B7C30000000000000001",
+            "9f1c5e2a-44d0-b7c3-0000-000000000001",
+            "{9F1C5E2A-44D0-B7C3-0000-000000000001}",
+        ] {
+            let guid: Guid = s.parse().unwrap();
+            assert!(!guid.is_device_guid());
+            assert_eq!(guid.vendor_id(), None);
+            assert_eq!(guid.to_string(), "9f1c5e2a-44d0-b7c3-0000-000000000001");
+            assert_eq!(guid.as_bytes().len(), 16);
+        }
+
+        let guid = Guid::from_bytes(&[0x2a, 0x2b, 0, 0, 0, 1]).unwrap();
+        assert_eq!(guid, device);
+        assert_eq!(String::from(guid), "2a2b-00000001");
+    }
+
+    #[test]
+    fn test_invalid() {
+        for s in &[
+            "",
+            "2A2B0000001",
+            "2A2B000000012",
+            "2A2B0000000G",
+            "2A2B0-0000001",
+            "2A2B-0000-0001",
+            "{2A2B00000001",
+            "9f1c5e2a44d0-b7c3-0000-000000000001",
+        ] {
+            let err = s.parse::<Guid>().unwrap_err();
+            assert_eq!(err.input(), *s);
+            assert!(err.to_string().contains(&format!("`{}`", s)));
+        }
+        assert!(Guid::from_bytes(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_normalized_eq() {
+        let forms = [
+            "2A2B00000001",
+            "2a2b00000001",
+            "2a2b-00000001",
+            "{2A2B-00000001}",
+        ];
+        let guids: HashSet<Guid> = forms.iter().map(|s| s.parse().unwrap()).collect();
+        assert_eq!(guids.len(), 1);
+
+        let a: Guid = "2A2B00000001".parse().unwrap();
+        let b: Guid = "2A2B00000002".parse().unwrap();
+        assert!(a < b);
+    }
+
+    #[test]
+    #[cfg(feature = "fixture")]
+    fn test_serde() {
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Wrapper {
+            guid: Guid,
+        }
+
+        let wrapper: Wrapper = toml::from_str(r#"guid = "2A2B00000001""#).unwrap();
+        assert_eq!(wrapper.guid.to_string(), "2a2b-00000001");
+        let s = toml::to_string(&wrapper).unwrap();
+        assert_eq!(s.trim(), r#"guid = "2a2b-00000001""#);
+        assert_eq!(toml::from_str::<Wrapper>(&s).unwrap(), wrapper);
+
+        assert!(toml::from_str::<Wrapper>(r#"guid = "2A2B""#).is_err());
+    }
+}
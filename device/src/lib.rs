@@ -10,12 +10,24 @@
     clippy::cast_possible_truncation
 )]
 
-#[cfg(feature = "libusb")]
 pub mod u3v;
 
-//// TODO: finish implementation.
-//mod emulator;
+/// Emulated U3V devices, which can be controlled in the same way as real devices.
+///
+/// TODO: finish implementation, e.g. the event module doesn't send events yet. Until then the
+/// module is built only with `emulator` feature.
+#[cfg(feature = "emulator")]
+pub mod emulator;
 
+/// Fixtures describing emulated devices. The module is built only with `fixture` feature, which
+/// is enabled by `emulator` feature.
+#[cfg(feature = "fixture")]
+pub mod fixture;
+mod guid;
 mod pixel_format;
+/// Soak scenarios driving a device for a long time. The module is built only with `soak` feature.
+#[cfg(feature = "soak")]
+pub mod soak;
 
+pub use guid::{Guid, ParseGuidError};
 pub use pixel_format::PixelFormat;
@@ -809,6 +809,31 @@ pub enum PixelFormat {
 
     /// Data 64-bit floating point.
     Data64f,
+
+    /// A pixel format which isn't modeled, e.g. a vendor specific one. The raw code is preserved.
+    Unknown(u32),
+}
+
+impl PixelFormat {
+    /// Converts a pixel format code into [`PixelFormat`], a code which isn't modeled is converted
+    /// into [`PixelFormat::Unknown`].
+    #[must_use]
+    pub fn from_code(code: u32) -> Self {
+        Self::try_from(code).unwrap_or(Unknown(code))
+    }
+
+    /// Returns `true` if the pixel format isn't modeled.
+    #[must_use]
+    pub fn is_unknown(self) -> bool {
+        matches!(self, Unknown(..))
+    }
+
+    /// Returns the number of bits occupied by a pixel, which is encoded in the code of the pixel
+    /// format. Padding bits of unpacked formats are included, e.g. `16` for `Mono10`.
+    #[must_use]
+    pub fn bits_per_pixel(self) -> usize {
+        ((u32::from(self) >> 16) & 0xff) as usize
+    }
 }
 
 impl TryFrom<u32> for PixelFormat {
@@ -1358,6 +1383,7 @@ impl From<PixelFormat> for u32 {
             Data64 => 0x0140_011D,
             Data64s => 0x0140_011E,
             Data64f => 0x0140_011F,
+            Unknown(code) => code,
         }
     }
 }
@@ -0,0 +1,735 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! This module contains the soak scenario runner which drives an emulated device with synthetic
+//! load for a long time.
+//!
+//! A scenario is a TOML document describing the load, and [`SoakRunner`] executes it against a
+//! [`SoakTarget`], collecting [`SoakSnapshot`]s periodically. [`SoakReport::violations`] checks
+//! the invariants which must hold after the run.
+//!
+//! ```toml
+//! duration = 30.0 # Seconds.
+//! seed = 1984
+//! snapshot_interval = 1.0
+//!
+//! [control]
+//! rate = 200.0 # Transactions per second.
+//! mix = { read = 6, write = 3, stacked = 1 }
+//!
+//! [stream]
+//! frame_rate = 30.0
+//!
+//! [events]
+//! rate = 2.0
+//!
+//! [[faults]]
+//! kind = "busy"
+//! probability = 0.001 # Per control transaction.
+//! ```
+
+use std::{
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+use thiserror::Error;
+use toml::Spanned;
+
+use crate::fixture::{invalid, FaultKind, FixtureResult};
+
+/// A description of synthetic load.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoakScenario {
+    /// Duration of the run.
+    pub duration: Duration,
+    /// Seed of the random number generator, the same seed generates the same schedule.
+    pub seed: u64,
+    /// Interval of snapshots.
+    pub snapshot_interval: Duration,
+    pub control: ControlLoad,
+    /// Frames per second, no frame is generated if `None`.
+    pub frame_rate: Option<f64>,
+    /// Events per second, no event is generated if `None`.
+    pub event_rate: Option<f64>,
+    pub faults: Vec<SoakFault>,
+    /// Allowed relative deviation of the number of received frames from the expected number.
+    pub frame_tolerance: f64,
+}
+
+/// Control transactions issued during the run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlLoad {
+    /// Transactions per second.
+    pub rate: f64,
+    /// Relative weights of each kind of transaction.
+    pub mix: ControlMix,
+}
+
+/// Relative weights of each kind of control transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ControlMix {
+    #[serde(default)]
+    pub read: u32,
+    #[serde(default)]
+    pub write: u32,
+    #[serde(default)]
+    pub stacked: u32,
+}
+
+impl Default for ControlMix {
+    fn default() -> Self {
+        Self {
+            read: 1,
+            write: 1,
+            stacked: 0,
+        }
+    }
+}
+
+impl ControlMix {
+    fn total(self) -> u32 {
+        self.read + self.write + self.stacked
+    }
+
+    fn pick(self, rng: &mut impl Rng) -> ControlOp {
+        let n = rng.gen_range(0..self.total());
+        if n < self.read {
+            ControlOp::Read
+        } else if n < self.read + self.write {
+            ControlOp::Write
+        } else {
+            ControlOp::Stacked
+        }
+    }
+}
+
+/// A fault injected to a control transaction with `probability`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoakFault {
+    pub kind: FaultKind,
+    pub probability: f64,
+}
+
+/// Kind of a control transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlOp {
+    Read,
+    Write,
+    Stacked,
+}
+
+/// An action of the schedule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoakAction {
+    /// Issue a control transaction, the device injects `fault` into it if any.
+    Control {
+        op: ControlOp,
+        fault: Option<FaultKind>,
+    },
+    /// The device sends a frame.
+    Frame,
+    /// The device sends an event.
+    Event,
+    /// Take a snapshot of the statistics.
+    Snapshot,
+}
+
+/// Scenario as written in the document, with spans to report errors.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawScenario {
+    duration: Spanned<f64>,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "default_snapshot_interval")]
+    snapshot_interval: Spanned<f64>,
+    control: Spanned<RawControl>,
+    stream: Option<RawStream>,
+    events: Option<RawEvents>,
+    #[serde(default)]
+    faults: Vec<Spanned<SoakFault>>,
+    #[serde(default = "default_frame_tolerance")]
+    frame_tolerance: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawControl {
+    rate: Spanned<f64>,
+    #[serde(default)]
+    mix: ControlMix,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawStream {
+    frame_rate: Spanned<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEvents {
+    rate: Spanned<f64>,
+}
+
+fn default_snapshot_interval() -> Spanned<f64> {
+    Spanned::new(0..0, 1.0)
+}
+
+fn default_frame_tolerance() -> f64 {
+    0.05
+}
+
+impl SoakScenario {
+    /// Loads and validates the scenario at `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> FixtureResult<Self> {
+        let src = fs::read_to_string(path)?;
+        Self::from_toml(&src)
+    }
+
+    /// Parses and validates the scenario.
+    pub fn from_toml(src: &str) -> FixtureResult<Self> {
+        let raw: RawScenario = toml::from_str(src).map_err(|e| {
+            let offset = e.span().map_or(0, |span| span.start);
+            invalid(src, offset, e.message().to_string())
+        })?;
+
+        let positive = |value: &Spanned<f64>, name: &str| {
+            if value.get_ref().is_finite() && *value.get_ref() > 0.0 {
+                Ok(*value.get_ref())
+            } else {
+                Err(invalid(
+                    src,
+                    value.span().start,
+                    format!("{} must be positive", name),
+                ))
+            }
+        };
+        let duration = Duration::from_secs_f64(positive(&raw.duration, "duration")?);
+        let snapshot_interval =
+            Duration::from_secs_f64(positive(&raw.snapshot_interval, "snapshot interval")?);
+        let frame_rate = raw
+            .stream
+            .map(|stream| positive(&stream.frame_rate, "frame rate"))
+            .transpose()?;
+        let event_rate = raw
+            .events
+            .map(|events| positive(&events.rate, "event rate"))
+            .transpose()?;
+
+        let control = ControlLoad {
+            rate: positive(&raw.control.get_ref().rate, "control rate")?,
+            mix: raw.control.get_ref().mix,
+        };
+        if control.mix.total() == 0 {
+            return Err(invalid(
+                src,
+                raw.control.span().start,
+                "control mix must have a positive weight".into(),
+            ));
+        }
+
+        for fault in &raw.faults {
+            let message = match fault.get_ref() {
+                SoakFault {
+                    kind: FaultKind::Disconnect,
+                    ..
+                } => "disconnect fault can't be injected during a soak run",
+                SoakFault {
+                    kind: FaultKind::RejectPayloadSize,
+                    ..
+                } => "reject_payload_size fault only applies to starting a stream",
+                SoakFault { probability, .. } if !(0.0..=1.0).contains(probability) => {
+                    "fault probability must be in [0, 1]"
+                }
+                _ => continue,
+            };
+            return Err(invalid(src, fault.span().start, message.into()));
+        }
+
+        Ok(Self {
+            duration,
+            seed: raw.seed,
+            snapshot_interval,
+            control,
+            frame_rate,
+            event_rate,
+            faults: raw.faults.into_iter().map(Spanned::into_inner).collect(),
+            frame_tolerance: raw.frame_tolerance,
+        })
+    }
+
+    /// Returns the schedule of the scenario, sorted by time.
+    ///
+    /// A snapshot is always taken at the end of the schedule.
+    #[must_use]
+    pub fn schedule(&self) -> Schedule<'_> {
+        Schedule {
+            scenario: self,
+            rng: StdRng::seed_from_u64(self.seed),
+            control: Ticker::new(Some(self.control.rate)),
+            frame: Ticker::new(self.frame_rate),
+            event: Ticker::new(self.event_rate),
+            snapshot: Ticker::new(Some(1.0 / self.snapshot_interval.as_secs_f64())),
+            finished: false,
+        }
+    }
+
+    /// Returns the number of frames generated during the run.
+    #[must_use]
+    pub fn expected_frames(&self) -> u64 {
+        self.frame_rate
+            .map_or(0, |rate| Ticker::count_within(rate, self.duration))
+    }
+}
+
+/// Generates ticks at a fixed rate.
+struct Ticker {
+    period: Option<Duration>,
+    count: u32,
+}
+
+impl Ticker {
+    fn new(rate: Option<f64>) -> Self {
+        Self {
+            period: rate.map(|rate| Duration::from_secs_f64(1.0 / rate)),
+            count: 0,
+        }
+    }
+
+    fn next_at(&self) -> Option<Duration> {
+        self.period.map(|period| period * self.count)
+    }
+
+    fn count_within(rate: f64, duration: Duration) -> u64 {
+        let period = Duration::from_secs_f64(1.0 / rate);
+        (duration.as_nanos() / period.as_nanos()) as u64 + 1
+    }
+}
+
+/// Schedule of a scenario, yields each action with the elapsed time it's due at.
+pub struct Schedule<'a> {
+    scenario: &'a SoakScenario,
+    rng: StdRng,
+    control: Ticker,
+    frame: Ticker,
+    event: Ticker,
+    snapshot: Ticker,
+    finished: bool,
+}
+
+impl Schedule<'_> {
+    fn inject_fault(&mut self) -> Option<FaultKind> {
+        for fault in &self.scenario.faults {
+            if self.rng.gen_bool(fault.probability) {
+                return Some(fault.kind);
+            }
+        }
+        None
+    }
+}
+
+impl Iterator for Schedule<'_> {
+    type Item = (Duration, SoakAction);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        let duration = self.scenario.duration;
+        let tickers = [&self.control, &self.frame, &self.event, &self.snapshot];
+        let next = tickers
+            .iter()
+            .enumerate()
+            .filter_map(|(i, ticker)| ticker.next_at().map(|at| (at, i)))
+            .filter(|(at, _)| *at <= duration)
+            .min();
+
+        let (at, index) = if let Some(next) = next {
+            next
+        } else {
+            self.finished = true;
+            return Some((duration, SoakAction::Snapshot));
+        };
+
+        let action = match index {
+            0 => {
+                self.control.count += 1;
+                let op = self.scenario.control.mix.pick(&mut self.rng);
+                let fault = self.inject_fault();
+                SoakAction::Control { op, fault }
+            }
+            1 => {
+                self.frame.count += 1;
+                SoakAction::Frame
+            }
+            2 => {
+                self.event.count += 1;
+                SoakAction::Event
+            }
+            _ => {
+                self.snapshot.count += 1;
+                SoakAction::Snapshot
+            }
+        };
+        Some((at, action))
+    }
+}
+
+/// Statistics collected from the host and the device at a point of the run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SoakSnapshot {
+    /// Elapsed time since the run started.
+    pub elapsed: Duration,
+    /// The number of control transactions completed successfully.
+    pub control_transactions: u64,
+    /// The number of control transactions failed.
+    pub control_errors: u64,
+    /// The number of acknowledges whose request id doesn't match the command.
+    pub request_id_mismatches: u64,
+    /// The number of frames received by the host.
+    pub frames_received: u64,
+    /// The number of events received by the host.
+    pub events_received: u64,
+    /// The number of pool buffers taken out of the pool and not yet returned.
+    pub outstanding_buffers: u64,
+}
+
+/// The device and the host under a soak run.
+pub trait SoakTarget {
+    /// Issues a control transaction, the device injects `fault` into it if any.
+    fn control(&mut self, op: ControlOp, fault: Option<FaultKind>);
+
+    /// Makes the device send a frame.
+    fn frame(&mut self);
+
+    /// Makes the device send an event.
+    fn event(&mut self);
+
+    /// Returns statistics collected so far, [`SoakSnapshot::elapsed`] is filled by the runner.
+    fn snapshot(&mut self) -> SoakSnapshot;
+}
+
+/// Executes a scenario against a [`SoakTarget`].
+pub struct SoakRunner<'a> {
+    scenario: &'a SoakScenario,
+    paced: bool,
+}
+
+impl<'a> SoakRunner<'a> {
+    /// Constructs a runner which executes the schedule as fast as possible.
+    #[must_use]
+    pub fn new(scenario: &'a SoakScenario) -> Self {
+        Self {
+            scenario,
+            paced: false,
+        }
+    }
+
+    /// Executes each action at its due time, so that the run takes the duration of the scenario.
+    #[must_use]
+    pub fn paced(mut self, paced: bool) -> Self {
+        self.paced = paced;
+        self
+    }
+
+    /// Runs the scenario to the end.
+    pub fn run(&self, target: &mut impl SoakTarget) -> SoakReport {
+        let start = Instant::now();
+        let mut snapshots = vec![];
+
+        for (at, action) in self.scenario.schedule() {
+            if self.paced {
+                if let Some(wait) = at.checked_sub(start.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+
+            match action {
+                SoakAction::Control { op, fault } => target.control(op, fault),
+                SoakAction::Frame => target.frame(),
+                SoakAction::Event => target.event(),
+                SoakAction::Snapshot => {
+                    let mut snapshot = target.snapshot();
+                    snapshot.elapsed = at;
+                    snapshots.push(snapshot);
+                }
+            }
+        }
+
+        SoakReport {
+            expected_frames: self.scenario.expected_frames(),
+            frame_tolerance: self.scenario.frame_tolerance,
+            snapshots,
+        }
+    }
+}
+
+/// Snapshots collected during a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoakReport {
+    expected_frames: u64,
+    frame_tolerance: f64,
+    /// Snapshots in the order of time, the last one is taken at the end of the run.
+    pub snapshots: Vec<SoakSnapshot>,
+}
+
+/// An invariant broken during a soak run.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SoakViolation {
+    #[error("{count} pool buffers are not returned at the end of the run")]
+    LeakedBuffers { count: u64 },
+
+    #[error("{count} acknowledges have mismatched request ids")]
+    RequestIdMismatch { count: u64 },
+
+    #[error("counter `{counter}` decreased at {at:?}")]
+    CounterDecreased { counter: &'static str, at: Duration },
+
+    #[error("{actual} frames are received, but {expected} frames are expected")]
+    UnexpectedFrameCount { expected: u64, actual: u64 },
+}
+
+impl SoakReport {
+    /// Returns the last snapshot.
+    #[must_use]
+    pub fn last(&self) -> Option<&SoakSnapshot> {
+        self.snapshots.last()
+    }
+
+    /// Checks invariants of the run, returns all broken invariants.
+    #[must_use]
+    pub fn violations(&self) -> Vec<SoakViolation> {
+        let mut violations = vec![];
+
+        for pair in self.snapshots.windows(2) {
+            let (prev, next) = (&pair[0], &pair[1]);
+            let counters = [
+                (
+                    "control_transactions",
+                    prev.control_transactions,
+                    next.control_transactions,
+                ),
+                ("control_errors", prev.control_errors, next.control_errors),
+                (
+                    "frames_received",
+                    prev.frames_received,
+                    next.frames_received,
+                ),
+                (
+                    "events_received",
+                    prev.events_received,
+                    next.events_received,
+                ),
+            ];
+            for (counter, prev, next) in counters {
+                if next < prev {
+                    violations.push(SoakViolation::CounterDecreased {
+                        counter,
+                        at: pair[1].elapsed,
+                    });
+                }
+            }
+        }
+
+        let last = match self.last() {
+            Some(last) => last,
+            None => return violations,
+        };
+        if last.outstanding_buffers != 0 {
+            violations.push(SoakViolation::LeakedBuffers {
+                count: last.outstanding_buffers,
+            });
+        }
+        if last.request_id_mismatches != 0 {
+            violations.push(SoakViolation::RequestIdMismatch {
+                count: last.request_id_mismatches,
+            });
+        }
+
+        #[allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        let margin = (self.expected_frames as f64 * self.frame_tolerance).ceil() as u64;
+        let expected = self.expected_frames;
+        if last.frames_received + margin < expected || expected + margin < last.frames_received {
+            violations.push(SoakViolation::UnexpectedFrameCount {
+                expected,
+                actual: last.frames_received,
+            });
+        }
+
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn scenario_path() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/soak_ci.toml")
+    }
+
+    /// A device and a host connected in memory.
+    #[derive(Default)]
+    struct Loopback {
+        next_request_id: u16,
+        /// Request id of a timed out transaction whose acknowledge arrives late.
+        late_ack: Option<u16>,
+        stats: SoakSnapshot,
+        pool: u64,
+        /// Leaks a pool buffer at every this number of frames if not zero.
+        leak_every: u64,
+    }
+
+    impl SoakTarget for Loopback {
+        fn control(&mut self, _op: ControlOp, fault: Option<FaultKind>) {
+            let request_id = self.next_request_id;
+            self.next_request_id = self.next_request_id.wrapping_add(1);
+            // The host discards a late acknowledge unless its request id collides.
+            if self.late_ack.take() == Some(request_id) {
+                self.stats.request_id_mismatches += 1;
+            }
+
+            match fault {
+                // The host retries a busy transaction until it succeeds.
+                // The host waits for the final ack of a pending transaction.
+                Some(FaultKind::Busy | FaultKind::Pending) | None => {
+                    self.stats.control_transactions += 1;
+                }
+                Some(FaultKind::Timeout) => {
+                    self.late_ack = Some(request_id);
+                    self.stats.control_errors += 1;
+                }
+                Some(_) => self.stats.control_errors += 1,
+            }
+        }
+
+        fn frame(&mut self) {
+            self.pool += 1;
+            self.stats.frames_received += 1;
+            if self.stats.frames_received.checked_rem(self.leak_every) != Some(0) {
+                self.pool -= 1;
+            }
+        }
+
+        fn event(&mut self) {
+            self.stats.events_received += 1;
+        }
+
+        fn snapshot(&mut self) -> SoakSnapshot {
+            SoakSnapshot {
+                outstanding_buffers: self.pool,
+                ..self.stats
+            }
+        }
+    }
+
+    #[test]
+    fn test_ci_scenario() {
+        let scenario = SoakScenario::from_path(scenario_path()).unwrap();
+        assert_eq!(scenario.duration, Duration::from_secs(30));
+
+        let report = SoakRunner::new(&scenario).run(&mut Loopback::default());
+        assert!(report.violations().is_empty(), "{:?}", report.violations());
+
+        // A snapshot per second, including both ends.
+        assert_eq!(report.snapshots.len(), 32);
+        let last = report.last().unwrap();
+        assert_eq!(last.elapsed, scenario.duration);
+        assert_eq!(last.frames_received, 901);
+        assert_eq!(last.events_received, 61);
+        assert_eq!(last.control_transactions + last.control_errors, 6001);
+        assert!(last.control_errors > 0);
+    }
+
+    #[test]
+    fn test_deterministic_schedule() {
+        let scenario = SoakScenario::from_path(scenario_path()).unwrap();
+        let first: Vec<_> = scenario.schedule().collect();
+        let second: Vec<_> = scenario.schedule().collect();
+        assert_eq!(first, second);
+        assert!(first.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    }
+
+    #[test]
+    fn test_violations() {
+        let scenario = SoakScenario::from_path(scenario_path()).unwrap();
+        let mut target = Loopback {
+            leak_every: 100,
+            ..Loopback::default()
+        };
+        let mut report = SoakRunner::new(&scenario).run(&mut target);
+        assert_eq!(
+            report.violations(),
+            vec![SoakViolation::LeakedBuffers { count: 9 }]
+        );
+
+        report.snapshots[3].frames_received = 0;
+        let last = report.snapshots.last_mut().unwrap();
+        last.outstanding_buffers = 0;
+        last.frames_received = 1000;
+        assert_eq!(
+            report.violations(),
+            vec![
+                SoakViolation::CounterDecreased {
+                    counter: "frames_received",
+                    at: Duration::from_secs(3),
+                },
+                SoakViolation::UnexpectedFrameCount {
+                    expected: 901,
+                    actual: 1000
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_scenario() {
+        let src = "duration = 10.0\n\n[control]\nrate = 100.0\n\n[[faults]]\nkind = \"disconnect\"\nprobability = 0.1\n";
+        match SoakScenario::from_toml(src).unwrap_err() {
+            crate::fixture::FixtureError::Invalid { line, message, .. } => {
+                assert_eq!(line, 6);
+                assert!(message.contains("disconnect"));
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+
+        let src = "duration = 10.0\n[control]\nrate = 100.0\n[[faults]]\nkind = \"reject_payload_size\"\nprobability = 0.1\n";
+        assert!(SoakScenario::from_toml(src).is_err());
+
+        let src = "duration = 0.0\n[control]\nrate = 100.0\n";
+        assert!(SoakScenario::from_toml(src).is_err());
+        let src = "duration = 1.0\n[control]\nrate = 100.0\nmix = { read = 0 , write = 0 }\n";
+        assert!(SoakScenario::from_toml(src).is_err());
+    }
+
+    /// Runs the scenario at `CAMELEON_SOAK_SCENARIO`, or the CI scenario if not set, in real time.
+    ///
+    /// Run with `cargo test -p cameleon-device soak_in_real_time -- --ignored` for a long run.
+    #[test]
+    #[ignore]
+    fn soak_in_real_time() {
+        let path =
+            std::env::var_os("CAMELEON_SOAK_SCENARIO").map_or_else(scenario_path, PathBuf::from);
+        let scenario = SoakScenario::from_path(path).unwrap();
+        let report = SoakRunner::new(&scenario)
+            .paced(true)
+            .run(&mut Loopback::default());
+        assert!(report.violations().is_empty(), "{:?}", report.violations());
+    }
+}
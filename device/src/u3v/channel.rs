@@ -4,10 +4,19 @@
 
 use std::time;
 
-use crate::u3v::Result;
+use crate::u3v::{Error, LibUsbError, Result};
 
 use super::device::RusbDevHandle;
 
+/// Timeout of each read flushing stale acks in [`ControlChannel::recover`].
+const STALE_ACK_TIMEOUT: time::Duration = time::Duration::from_millis(10);
+
+/// The maximum number of stale acks flushed in [`ControlChannel::recover`].
+const MAX_STALE_ACKS: usize = 16;
+
+/// Length of the buffer receiving stale acks, a longer ack is discarded as an overflow.
+const STALE_ACK_BUFFER_LEN: usize = 1024;
+
 pub struct ControlChannel {
     pub device_handle: RusbDevHandle,
     pub iface_info: ControlIfaceInfo,
@@ -40,6 +49,15 @@ impl ControlChannel {
         self.is_opened
     }
 
+    /// Lets libusb detach the kernel driver bound to the interface when the interface is
+    /// claimed, and reattach it when the interface is released.
+    ///
+    /// Fails with [`LibUsbError::NotSupported`] on platforms which can't detach kernel drivers,
+    /// unless `enable` is `false`.
+    pub fn set_auto_detach_kernel_driver(&mut self, enable: bool) -> Result<()> {
+        set_auto_detach_kernel_driver(&mut self.device_handle, enable)
+    }
+
     pub fn send(&self, buf: &[u8], timeout: time::Duration) -> Result<usize> {
         Ok(self
             .device_handle
@@ -65,6 +83,17 @@ impl ControlChannel {
         Ok(())
     }
 
+    /// Recovers the channel whose command and ack went out of sync, e.g. after an aborted
+    /// transfer stalled the endpoints.
+    ///
+    /// Clears the halt of both endpoints, then flushes the acks left in the ack endpoint.
+    /// Returns the number of flushed acks.
+    pub fn recover(&mut self) -> Result<usize> {
+        self.clear_halt()?;
+        let mut buf = vec![0; STALE_ACK_BUFFER_LEN];
+        flush_stale_acks(|| self.recv(&mut buf, STALE_ACK_TIMEOUT))
+    }
+
     pub(super) fn new(device_handle: RusbDevHandle, iface_info: ControlIfaceInfo) -> Self {
         Self {
             device_handle,
@@ -106,6 +135,15 @@ impl ReceiveChannel {
         self.is_opened
     }
 
+    /// Lets libusb detach the kernel driver bound to the interface when the interface is
+    /// claimed, and reattach it when the interface is released.
+    ///
+    /// Fails with [`LibUsbError::NotSupported`] on platforms which can't detach kernel drivers,
+    /// unless `enable` is `false`.
+    pub fn set_auto_detach_kernel_driver(&mut self, enable: bool) -> Result<()> {
+        set_auto_detach_kernel_driver(&mut self.device_handle, enable)
+    }
+
     pub fn recv(&self, buf: &mut [u8], timeout: time::Duration) -> Result<usize> {
         Ok(self
             .device_handle
@@ -145,6 +183,29 @@ pub struct ReceiveIfaceInfo {
     pub bulk_in_ep: u8,
 }
 
+/// Calls `recv` until it times out, and returns the number of received packets.
+///
+/// A packet which overflows the buffer is also counted. Gives up with [`LibUsbError::Pipe`] if
+/// the packets keep arriving.
+fn flush_stale_acks(mut recv: impl FnMut() -> Result<usize>) -> Result<usize> {
+    for flushed in 0..MAX_STALE_ACKS {
+        match recv() {
+            Ok(_) | Err(Error::LibUsb(LibUsbError::Overflow)) => {}
+            Err(Error::LibUsb(LibUsbError::Timeout)) => return Ok(flushed),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(Error::LibUsb(LibUsbError::Pipe))
+}
+
+fn set_auto_detach_kernel_driver(handle: &mut RusbDevHandle, enable: bool) -> Result<()> {
+    match handle.set_auto_detach_kernel_driver(enable) {
+        Err(rusb::Error::NotSupported) if !enable => Ok(()),
+        result => Ok(result?),
+    }
+}
+
 fn set_halt(handle: &RusbDevHandle, endpoint_number: u8, timeout: time::Duration) -> Result<()> {
     let request_type = rusb::request_type(
         rusb::Direction::Out,
@@ -166,3 +227,35 @@ fn set_halt(handle: &RusbDevHandle, endpoint_number: u8, timeout: time::Duration
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeout() -> Result<usize> {
+        Err(Error::LibUsb(LibUsbError::Timeout))
+    }
+
+    #[test]
+    fn test_flush_stale_acks() {
+        assert_eq!(flush_stale_acks(timeout).unwrap(), 0);
+
+        // A stale ack and an overflowed one are flushed.
+        let mut packets =
+            vec![Ok(20), Err(Error::LibUsb(LibUsbError::Overflow)), timeout()].into_iter();
+        assert_eq!(flush_stale_acks(|| packets.next().unwrap()).unwrap(), 2);
+
+        // Other errors are reported.
+        let mut packets = vec![Ok(20), Err(Error::LibUsb(LibUsbError::NoDevice))].into_iter();
+        assert!(matches!(
+            flush_stale_acks(|| packets.next().unwrap()),
+            Err(Error::LibUsb(LibUsbError::NoDevice))
+        ));
+
+        // Gives up if acks keep arriving.
+        assert!(matches!(
+            flush_stale_acks(|| Ok(20)),
+            Err(Error::LibUsb(LibUsbError::Pipe))
+        ));
+    }
+}
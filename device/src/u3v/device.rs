@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use crate::u3v::{DeviceInfo, Result};
+use crate::u3v::{enumerate_devices_with, DeviceFilter, DeviceInfo, Result};
 
 use super::channel::{ControlChannel, ControlIfaceInfo, ReceiveChannel, ReceiveIfaceInfo};
 
@@ -59,6 +59,15 @@ impl Device {
         &self.device_info
     }
 
+    /// Finds the device whose serial number is exactly `serial`.
+    ///
+    /// Returns `None` if no such device is connected. If several devices share the serial number,
+    /// the first one found is returned.
+    pub fn open_by_serial(serial: &str) -> Result<Option<Self>> {
+        let filter = DeviceFilter::new().serial_number(serial);
+        Ok(enumerate_devices_with(&filter)?.into_iter().next())
+    }
+
     pub(super) fn new(
         device: RusbDevice,
         ctrl_iface_info: ControlIfaceInfo,
@@ -4,7 +4,7 @@
 
 use semver::Version;
 
-use crate::u3v::{protocol::util::ReadBytes, BusSpeed, DeviceInfo, Error, Result};
+use crate::u3v::{protocol::util::ReadBytes, BusSpeed, DeviceFilter, DeviceInfo, Error, Result};
 
 use super::{
     channel::{ControlIfaceInfo, ReceiveIfaceInfo},
@@ -21,14 +21,30 @@ const IAD_FUNCTION_PROTOCOL: u8 = 0x00;
 
 const USB3V_SUBCLASS: u8 = 0x05;
 
+lazy_static::lazy_static! {
+    /// Result of initializing libusb, which is checked before `rusb::GlobalContext` is used.
+    /// `rusb::GlobalContext` panics if libusb fails to initialize, e.g. in a container without
+    /// usbfs.
+    static ref LIBUSB_INIT: std::result::Result<(), rusb::Error> = rusb::Context::new().map(drop);
+}
+
 pub fn enumerate_devices() -> Result<Vec<Device>> {
+    enumerate_devices_with(&DeviceFilter::default())
+}
+
+/// Enumerates devices which match `filter`.
+///
+/// Devices failing the checks against the USB device descriptor are skipped without opening
+/// them, and the U3V device descriptor is read only until the device fails the filter.
+pub fn enumerate_devices_with(filter: &DeviceFilter) -> Result<Vec<Device>> {
+    (*LIBUSB_INIT)?;
     let rusb_device_list = rusb::DeviceList::new()?;
     let builders = rusb_device_list
         .iter()
-        .filter_map(|dev| DeviceBuilder::new(dev).ok().flatten());
+        .filter_map(|dev| DeviceBuilder::new(dev, filter).ok().flatten());
 
     Ok(builders
-        .filter_map(|builder| builder.build().ok())
+        .filter_map(|builder| builder.build(filter).ok().flatten())
         .collect())
 }
 
@@ -39,8 +55,11 @@ struct DeviceBuilder {
 }
 
 impl DeviceBuilder {
-    fn new(device: RusbDevice) -> Result<Option<Self>> {
+    fn new(device: RusbDevice, filter: &DeviceFilter) -> Result<Option<Self>> {
         let device_desc = device.device_descriptor()?;
+        if !filter.matches_usb_ids(device_desc.vendor_id(), device_desc.product_id()) {
+            return Ok(None);
+        }
 
         if device_desc.class_code() == MISCELLANEOUS_CLASS
             && device_desc.sub_class_code() == DEVICE_SUBCLASS
@@ -58,12 +77,10 @@ impl DeviceBuilder {
         Ok(None)
     }
 
-    fn build(self) -> Result<Device> {
+    /// Returns `None` if the device doesn't match `filter`.
+    fn build(self, filter: &DeviceFilter) -> Result<Option<Device>> {
         // TODO: Log it when device is broken or invalid.
         let mut dev_channel = self.device.open()?;
-        if dev_channel.active_configuration()? != self.config_desc.number() {
-            dev_channel.set_active_configuration(self.config_desc.number())?;
-        }
 
         // Skip interfaces while control interface is appeared.
         let mut interfaces = self
@@ -83,7 +100,15 @@ impl DeviceBuilder {
             .ok_or(Error::InvalidDevice)?;
         let device_info_desc = ctrl_iface_desc.extra().ok_or(Error::InvalidDevice)?;
         let device_info_desc = DeviceInfoDescriptor::from_bytes(device_info_desc)?;
-        let device_info = device_info_desc.interpret(&dev_channel)?;
+        let device_info = match device_info_desc.interpret(&dev_channel, filter)? {
+            Some(device_info) => device_info,
+            None => return Ok(None),
+        };
+
+        // Configure the device only after it passes the filter.
+        if dev_channel.active_configuration()? != self.config_desc.number() {
+            dev_channel.set_active_configuration(self.config_desc.number())?;
+        }
 
         // Retrieve event and stream interface information if exists.
         let receive_ifaces = interfaces.filter_map(|iface| ReceiveIfaceInfo::new(&iface));
@@ -112,13 +137,13 @@ impl DeviceBuilder {
             None => (None, None),
         };
 
-        Ok(Device::new(
+        Ok(Some(Device::new(
             self.device,
             ctrl_iface_info,
             event_iface,
             stream_iface,
             device_info,
-        ))
+        )))
     }
 
     fn find_u3v_iad(
@@ -326,7 +351,24 @@ impl DeviceInfoDescriptor {
         })
     }
 
-    fn interpret(&self, channel: &RusbDevHandle) -> Result<DeviceInfo> {
+    /// Reads the string descriptors into [`DeviceInfo`], or returns `None` as soon as the device
+    /// turns out not to match `filter`.
+    fn interpret(
+        &self,
+        channel: &RusbDevHandle,
+        filter: &DeviceFilter,
+    ) -> Result<Option<DeviceInfo>> {
+        let guid = channel
+            .read_string_descriptor_ascii(self.guid_idx)?
+            .parse()?;
+        if !filter.matches_guid(&guid) {
+            return Ok(None);
+        }
+        let serial_number = channel.read_string_descriptor_ascii(self.serial_number_idx)?;
+        if !filter.matches_serial_number(&serial_number) {
+            return Ok(None);
+        }
+
         let gencp_version = Version::new(
             self.gencp_version_major.into(),
             self.gencp_version_minor.into(),
@@ -339,7 +381,6 @@ impl DeviceInfoDescriptor {
             0,
         );
 
-        let guid = channel.read_string_descriptor_ascii(self.guid_idx)?;
         let vendor_name = channel.read_string_descriptor_ascii(self.vendor_name_idx)?;
         let model_name = channel.read_string_descriptor_ascii(self.model_name_idx)?;
         let family_name = if self.family_name_idx == 0 {
@@ -350,7 +391,6 @@ impl DeviceInfoDescriptor {
 
         let device_version = channel.read_string_descriptor_ascii(self.device_version_idx)?;
         let manufacturer_info = channel.read_string_descriptor_ascii(self.manufacturer_info_idx)?;
-        let serial_number = channel.read_string_descriptor_ascii(self.serial_number_idx)?;
         let user_defined_name = if self.user_defined_name_idx == 0 {
             None
         } else {
@@ -370,7 +410,7 @@ impl DeviceInfoDescriptor {
             return Err(Error::InvalidDevice);
         };
 
-        Ok(DeviceInfo {
+        Ok(Some(DeviceInfo {
             gencp_version,
             u3v_version,
             guid,
@@ -382,7 +422,7 @@ impl DeviceInfoDescriptor {
             serial_number,
             user_defined_name,
             supported_speed,
-        })
+        }))
     }
 }
 
@@ -0,0 +1,174 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::Guid;
+
+use super::DeviceInfo;
+
+/// A filter which selects devices to enumerate, see [`enumerate_devices_with`].
+///
+/// Criteria are combined with AND, and an empty filter matches all devices.
+/// Vendor id and product id are checked against the USB device descriptor, so a device which
+/// fails them is skipped without reading its U3V descriptors.
+///
+/// # Examples
+///
+/// ```
+/// use cameleon_device::u3v::DeviceFilter;
+///
+/// let filter = DeviceFilter::new()
+///     .vendor_id(0x2a2b)
+///     .serial_number_prefix("CAM");
+/// ```
+///
+/// [`enumerate_devices_with`]: super::enumerate_devices_with
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceFilter {
+    vendor_id: Option<u16>,
+    product_id: Option<u16>,
+    serial_number: Option<SerialNumber>,
+    guid: Option<Guid>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum SerialNumber {
+    Exact(String),
+    Prefix(String),
+}
+
+impl DeviceFilter {
+    /// Constructs a filter which matches all devices.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Selects devices whose USB vendor id is `vendor_id`.
+    #[must_use]
+    pub fn vendor_id(mut self, vendor_id: u16) -> Self {
+        self.vendor_id = Some(vendor_id);
+        self
+    }
+
+    /// Selects devices whose USB product id is `product_id`.
+    #[must_use]
+    pub fn product_id(mut self, product_id: u16) -> Self {
+        self.product_id = Some(product_id);
+        self
+    }
+
+    /// Selects devices whose serial number is exactly `serial_number`.
+    ///
+    /// This overrides [`Self::serial_number_prefix`].
+    #[must_use]
+    pub fn serial_number(mut self, serial_number: impl Into<String>) -> Self {
+        self.serial_number = Some(SerialNumber::Exact(serial_number.into()));
+        self
+    }
+
+    /// Selects devices whose serial number starts with `prefix`.
+    ///
+    /// This overrides [`Self::serial_number`].
+    #[must_use]
+    pub fn serial_number_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.serial_number = Some(SerialNumber::Prefix(prefix.into()));
+        self
+    }
+
+    /// Selects the device whose GUID is `guid`.
+    ///
+    /// The vendor id embedded in `guid` is also checked against the USB device descriptor.
+    #[must_use]
+    pub fn guid(mut self, guid: Guid) -> Self {
+        self.guid = Some(guid);
+        self
+    }
+
+    /// Returns `true` if the filter matches all devices.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns `true` if the ids read from the USB device descriptor may match the filter.
+    #[must_use]
+    pub fn matches_usb_ids(&self, vendor_id: u16, product_id: u16) -> bool {
+        let guid_vendor_id = self.guid.as_ref().and_then(Guid::vendor_id);
+        self.vendor_id.is_none_or(|id| id == vendor_id)
+            && self.product_id.is_none_or(|id| id == product_id)
+            && guid_vendor_id.is_none_or(|id| id == vendor_id)
+    }
+
+    /// Returns `true` if `guid` matches the filter.
+    #[must_use]
+    pub fn matches_guid(&self, guid: &Guid) -> bool {
+        self.guid.as_ref().is_none_or(|expected| expected == guid)
+    }
+
+    /// Returns `true` if `serial_number` matches the filter.
+    #[must_use]
+    pub fn matches_serial_number(&self, serial_number: &str) -> bool {
+        match &self.serial_number {
+            Some(SerialNumber::Exact(expected)) => serial_number == expected,
+            Some(SerialNumber::Prefix(prefix)) => serial_number.starts_with(prefix.as_str()),
+            None => true,
+        }
+    }
+
+    /// Returns `true` if the U3V device information matches the filter.
+    ///
+    /// Vendor id and product id aren't checked since they aren't part of [`DeviceInfo`].
+    #[must_use]
+    pub fn matches(&self, info: &DeviceInfo) -> bool {
+        self.matches_guid(&info.guid) && self.matches_serial_number(&info.serial_number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usb_ids() {
+        assert!(DeviceFilter::new().is_empty());
+        assert!(DeviceFilter::new().matches_usb_ids(0x2a2b, 0x0001));
+
+        let filter = DeviceFilter::new().vendor_id(0x2a2b).product_id(0x0001);
+        assert!(!filter.is_empty());
+        assert!(filter.matches_usb_ids(0x2a2b, 0x0001));
+        assert!(!filter.matches_usb_ids(0x2a2c, 0x0001));
+        assert!(!filter.matches_usb_ids(0x2a2b, 0x0002));
+
+        // The vendor id embedded in the device GUID is checked without reading the GUID.
+        let filter = DeviceFilter::new().guid("2A2B00000001".parse().unwrap());
+        assert!(filter.matches_usb_ids(0x2a2b, 0x0001));
+        assert!(!filter.matches_usb_ids(0x2a2c, 0x0001));
+    }
+
+    #[test]
+    fn test_serial_number() {
+        let filter = DeviceFilter::new().serial_number("CAM0001");
+        assert!(filter.matches_serial_number("CAM0001"));
+        assert!(!filter.matches_serial_number("CAM00010"));
+        assert!(!filter.matches_serial_number("CAM"));
+
+        let filter = DeviceFilter::new().serial_number_prefix("CAM");
+        assert!(filter.matches_serial_number("CAM0001"));
+        assert!(filter.matches_serial_number("CAM"));
+        assert!(!filter.matches_serial_number("cam0001"));
+
+        // The last serial number criterion wins.
+        let filter = filter.serial_number("CAM0001");
+        assert!(!filter.matches_serial_number("CAM0002"));
+    }
+
+    #[test]
+    fn test_guid() {
+        let guid: Guid = "2A2B00000001".parse().unwrap();
+        let filter = DeviceFilter::new().guid(guid);
+        assert!(filter.matches_guid(&"2a2b-00000001".parse().unwrap()));
+        assert!(!filter.matches_guid(&"2a2b-00000002".parse().unwrap()));
+        assert!(DeviceFilter::new().matches_guid(&guid));
+    }
+}
@@ -6,6 +6,8 @@ use std::fmt;
 
 use semver::Version;
 
+use crate::Guid;
+
 /// Device information in class-specific device descriptor.
 #[derive(Clone, Debug)]
 pub struct DeviceInfo {
@@ -15,9 +17,9 @@ pub struct DeviceInfo {
     /// USB3-Vision version the device provides.
     pub u3v_version: Version,
 
-    /// Device GUID consists of 12 characters.
-    /// First 4 characters are vendor ID and last 8 characters are unique id assigned by a vendor.
-    pub guid: String,
+    /// Device GUID consists of 12 hex digits.
+    /// First 4 digits are vendor ID and last 8 digits are unique id assigned by a vendor.
+    pub guid: Guid,
 
     /// Manufacturer name of the device.
     pub vendor_name: String,
@@ -11,14 +11,22 @@ pub mod prelude {
     use super::protocol;
 }
 
+#[cfg(feature = "libusb")]
 mod channel;
+#[cfg(feature = "libusb")]
 mod device;
+#[cfg(feature = "libusb")]
 mod device_builder;
+mod device_filter;
 mod device_info;
 
+#[cfg(feature = "libusb")]
 pub use channel::{ControlChannel, ReceiveChannel};
+#[cfg(feature = "libusb")]
 pub use device::Device;
-pub use device_builder::enumerate_devices;
+#[cfg(feature = "libusb")]
+pub use device_builder::{enumerate_devices, enumerate_devices_with};
+pub use device_filter::DeviceFilter;
 pub use device_info::{BusSpeed, DeviceInfo};
 
 use std::borrow::Cow;
@@ -38,6 +46,12 @@ pub enum Error {
 
     #[error("device doesn't follow the specification")]
     InvalidDevice,
+
+    #[error("device reports an invalid GUID: {0}")]
+    InvalidGuid(#[from] crate::ParseGuidError),
+
+    #[error("command length {len} exceeds the maximum command length {max}")]
+    CommandTooLong { len: usize, max: usize },
 }
 
 /// Errors raised from libusb.
@@ -75,6 +89,7 @@ pub enum LibUsbError {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+#[cfg(feature = "libusb")]
 impl From<rusb::Error> for Error {
     fn from(err: rusb::Error) -> Error {
         use LibUsbError::{
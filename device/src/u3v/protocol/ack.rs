@@ -2,30 +2,175 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::{io::Cursor, time};
+use std::{
+    borrow::Cow,
+    convert::TryInto,
+    fmt,
+    io::{Cursor, Write},
+    time,
+};
 
 use crate::u3v::{Error, Result};
 
-use super::util::{self, ReadBytes};
+use super::util::{self, ReadBytes, WriteBytes};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct AckPacket<'a> {
     ccd: AckCcd,
-    raw_scd: &'a [u8],
+    raw_scd: Cow<'a, [u8]>,
 }
 
 impl<'a> AckPacket<'a> {
     const PREFIX_MAGIC: u32 = 0x4356_3355;
 
+    // Magic + CCD length.
+    const HEADER_LENGTH: usize = 4 + 8;
+
+    /// Parses `buf` with [`Strictness::Lenient`].
     pub fn parse(buf: &'a (impl AsRef<[u8]> + ?Sized)) -> Result<Self> {
-        let mut cursor = Cursor::new(buf.as_ref());
+        Self::parse_with(buf, Strictness::Lenient)
+    }
 
-        Self::parse_prefix(&mut cursor)?;
+    /// Parses `buf`, bytes following the scd are handled according to `strictness`.
+    ///
+    /// Returns an error if the scd length declared in the ccd exceeds the bytes following the
+    /// header.
+    pub fn parse_with(
+        buf: &'a (impl AsRef<[u8]> + ?Sized),
+        strictness: Strictness,
+    ) -> Result<Self> {
+        let options = ParseOptions {
+            scd: strictness,
+            ..ParseOptions::default()
+        };
+        Self::parse_with_options(buf, options, &DefaultStatusDecoder)
+    }
 
-        let ccd = AckCcd::parse(&mut cursor)?;
+    /// Parses `buf` with [`ParseOptions::lenient`], i.e. bytes following the scd are ignored and
+    /// status codes unknown to this crate are parsed as [`GenCpStatus::Unknown`] or
+    /// [`UsbSpecificStatus::Unknown`].
+    pub fn parse_lenient(buf: &'a (impl AsRef<[u8]> + ?Sized)) -> Result<Self> {
+        Self::parse_with_options(buf, ParseOptions::lenient(), &DefaultStatusDecoder)
+    }
 
-        let raw_scd = &cursor.get_ref()[cursor.position() as usize..];
-        Ok(Self { ccd, raw_scd })
+    /// Parses `buf` according to `options`, and decodes a device specific status with `decoder`.
+    pub fn parse_with_options(
+        buf: &'a (impl AsRef<[u8]> + ?Sized),
+        options: ParseOptions,
+        decoder: &dyn StatusDecoder,
+    ) -> Result<Self> {
+        let (ccd, raw_scd) = Self::parse_raw(buf.as_ref(), options, decoder)?;
+        Ok(Self {
+            ccd,
+            raw_scd: Cow::Borrowed(raw_scd),
+        })
+    }
+
+    /// Parses `buf` with [`Strictness::Lenient`], and decodes a device specific status with
+    /// `decoder`.
+    pub fn parse_decoded(
+        buf: &'a (impl AsRef<[u8]> + ?Sized),
+        decoder: &dyn StatusDecoder,
+    ) -> Result<Self> {
+        Self::parse_with_options(buf, ParseOptions::default(), decoder)
+    }
+
+    /// Parses `buf` and interprets its scd as `T`.
+    ///
+    /// Unlike [`Self::scd_as`], the returned scd borrows `buf` instead of the packet.
+    pub fn parse_scd<T: ParseScd<'a>>(buf: &'a (impl AsRef<[u8]> + ?Sized)) -> Result<T> {
+        let (ccd, raw_scd) =
+            Self::parse_raw(buf.as_ref(), ParseOptions::default(), &DefaultStatusDecoder)?;
+        T::parse(raw_scd, &ccd)
+    }
+
+    /// Converts the packet into one which owns its scd, so that it outlives the receive buffer.
+    #[must_use]
+    pub fn into_owned(self) -> AckPacket<'static> {
+        AckPacket {
+            ccd: self.ccd,
+            raw_scd: Cow::Owned(self.raw_scd.into_owned()),
+        }
+    }
+
+    /// Constructs an ack for `ReadMem` command with read `data`.
+    pub fn read_mem_ack(request_id: u16, data: &'a [u8]) -> Result<Self> {
+        Self::new(ScdKind::ReadMem, request_id, Cow::Borrowed(data))
+    }
+
+    /// Constructs an ack for `WriteMem` command which has written `length` bytes.
+    #[must_use]
+    pub fn write_mem_ack(request_id: u16, length: u16) -> Self {
+        let mut scd = Vec::with_capacity(4);
+        scd.write_bytes(0_u16).unwrap();
+        scd.write_bytes(length).unwrap();
+        Self::new(ScdKind::WriteMem, request_id, scd.into()).unwrap()
+    }
+
+    /// Constructs a pending ack which requests the host to wait for `timeout` before the
+    /// actual ack.
+    ///
+    /// Returns an error if `timeout` doesn't fit into `u16` in milliseconds.
+    pub fn pending_ack(request_id: u16, timeout: time::Duration) -> Result<Self> {
+        let timeout_ms: u16 = timeout.as_millis().try_into().map_err(|_| {
+            Error::InvalidPacket("timeout of PendingAck must fit into u16 in ms".into())
+        })?;
+        let mut scd = Vec::with_capacity(4);
+        scd.write_bytes(0_u16)?;
+        scd.write_bytes(timeout_ms)?;
+        Self::new(ScdKind::Pending, request_id, scd.into())
+    }
+
+    /// Constructs an ack for `ReadMemStacked` command with concatenated read `data`.
+    pub fn read_mem_stacked_ack(request_id: u16, data: &'a [u8]) -> Result<Self> {
+        Self::new(ScdKind::ReadMemStacked, request_id, Cow::Borrowed(data))
+    }
+
+    /// Constructs an ack for `WriteMemStacked` command which has written `lengths` bytes to each
+    /// address.
+    pub fn write_mem_stacked_ack(request_id: u16, lengths: &[u16]) -> Result<Self> {
+        let mut scd = Vec::with_capacity(lengths.len() * 4);
+        for length in lengths {
+            scd.write_bytes(0_u16)?;
+            scd.write_bytes(*length)?;
+        }
+        Self::new(ScdKind::WriteMemStacked, request_id, scd.into())
+    }
+
+    /// Constructs an ack of a custom command whose ack id is `ack_id`.
+    ///
+    /// Returns an error if `ack_id` isn't a custom ack id, i.e. the most significant bit isn't set
+    /// or the id is even.
+    pub fn custom_ack(request_id: u16, ack_id: u16, data: &'a [u8]) -> Result<Self> {
+        if !is_custom_ack_id(ack_id) {
+            let msg = format!("invalid custom ack id {:#06X}", ack_id);
+            return Err(Error::InvalidPacket(msg.into()));
+        }
+        Self::new(ScdKind::Custom(ack_id), request_id, Cow::Borrowed(data))
+    }
+
+    /// Constructs an ack without scd which reports `status` in response to the command of
+    /// `scd_kind`.
+    #[must_use]
+    pub fn error_ack(request_id: u16, scd_kind: ScdKind, status: impl Into<Status>) -> Self {
+        let mut ack = Self::new(scd_kind, request_id, Cow::Borrowed(&[])).unwrap();
+        ack.ccd.status = status.into();
+        ack
+    }
+
+    /// Serializes the packet in the format [`Self::parse`] accepts.
+    pub fn serialize(&self, mut buf: impl Write) -> Result<()> {
+        buf.write_bytes(Self::PREFIX_MAGIC)?;
+        self.ccd.serialize(&mut buf)?;
+        buf.write_all(&self.raw_scd)?;
+
+        Ok(())
+    }
+
+    /// Length of the serialized packet.
+    #[must_use]
+    pub fn ack_len(&self) -> usize {
+        Self::HEADER_LENGTH + self.raw_scd.len()
     }
 
     #[must_use]
@@ -39,12 +184,12 @@ impl<'a> AckPacket<'a> {
     }
 
     #[must_use]
-    pub fn raw_scd(&self) -> &'a [u8] {
-        self.raw_scd
+    pub fn raw_scd(&self) -> &[u8] {
+        &self.raw_scd
     }
 
-    pub fn scd_as<T: ParseScd<'a>>(&self) -> Result<T> {
-        T::parse(self.raw_scd, &self.ccd)
+    pub fn scd_as<'b, T: ParseScd<'b>>(&'b self) -> Result<T> {
+        T::parse(&self.raw_scd, &self.ccd)
     }
 
     #[must_use]
@@ -57,6 +202,65 @@ impl<'a> AckPacket<'a> {
         self.ccd.request_id
     }
 
+    /// Returns the ack id if the packet is an ack of a custom command.
+    #[must_use]
+    pub fn custom_command_id(&self) -> Option<u16> {
+        match self.ccd.scd_kind {
+            ScdKind::Custom(id) => Some(id),
+            _ => None,
+        }
+    }
+
+    fn new(scd_kind: ScdKind, request_id: u16, raw_scd: Cow<'a, [u8]>) -> Result<Self> {
+        let scd_len = raw_scd.len().try_into().map_err(|_| {
+            Error::InvalidPacket("scd length of ack packet must fit into u16".into())
+        })?;
+        let ccd = AckCcd {
+            status: GenCpStatus::Success.into(),
+            scd_kind,
+            request_id,
+            scd_len,
+        };
+        Ok(Self { ccd, raw_scd })
+    }
+
+    fn parse_raw(
+        buf: &'a [u8],
+        options: ParseOptions,
+        decoder: &dyn StatusDecoder,
+    ) -> Result<(AckCcd, &'a [u8])> {
+        let mut cursor = Cursor::new(buf);
+
+        Self::parse_prefix(&mut cursor)?;
+
+        let ccd = AckCcd::parse(&mut cursor, options.status, decoder)?;
+
+        let rest = &cursor.get_ref()[cursor.position() as usize..];
+        let scd_len = ccd.scd_len as usize;
+        if rest.len() < scd_len {
+            return Err(Error::InvalidPacket(
+                format!(
+                    "scd length declared in ccd is {} bytes, but only {} bytes follow the header",
+                    scd_len,
+                    rest.len()
+                )
+                .into(),
+            ));
+        }
+        if rest.len() > scd_len && options.scd == Strictness::Strict {
+            return Err(Error::InvalidPacket(
+                format!(
+                    "scd length declared in ccd is {} bytes, but {} bytes follow the header",
+                    scd_len,
+                    rest.len()
+                )
+                .into(),
+            ));
+        }
+
+        Ok((ccd, &rest[..scd_len]))
+    }
+
     fn parse_prefix(cursor: &mut Cursor<&[u8]>) -> Result<()> {
         let magic: u32 = cursor.read_bytes()?;
         if magic == Self::PREFIX_MAGIC {
@@ -67,6 +271,62 @@ impl<'a> AckPacket<'a> {
     }
 }
 
+/// Determines how [`AckPacket::parse_with`] handles a deviation from the specification, see
+/// [`ParseOptions`] for the deviations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strictness {
+    /// The deviation is tolerated.
+    #[default]
+    Lenient,
+    /// The deviation is rejected as an invalid packet.
+    Strict,
+}
+
+/// Options of [`AckPacket::parse_with_options`].
+///
+/// The default options ignore bytes following the scd, and reject status codes unknown to this
+/// crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// How bytes following the scd are handled.
+    pub scd: Strictness,
+    /// How status codes in the `GenCP` and `U3V` namespaces unknown to this crate are handled.
+    /// Unknown codes are parsed as [`GenCpStatus::Unknown`] or [`UsbSpecificStatus::Unknown`]
+    /// if lenient.
+    ///
+    /// Codes in the reserved namespace are rejected regardless of this option.
+    pub status: Strictness,
+}
+
+impl ParseOptions {
+    /// Options which reject all the deviations.
+    #[must_use]
+    pub fn strict() -> Self {
+        Self {
+            scd: Strictness::Strict,
+            status: Strictness::Strict,
+        }
+    }
+
+    /// Options which tolerate all the deviations.
+    #[must_use]
+    pub fn lenient() -> Self {
+        Self {
+            scd: Strictness::Lenient,
+            status: Strictness::Lenient,
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            scd: Strictness::Lenient,
+            status: Strictness::Strict,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct AckCcd {
     pub(crate) status: Status,
@@ -96,8 +356,12 @@ impl AckCcd {
         self.scd_len
     }
 
-    fn parse(cursor: &mut Cursor<&[u8]>) -> Result<Self> {
-        let status = Status::parse(cursor)?;
+    fn parse(
+        cursor: &mut Cursor<&[u8]>,
+        strictness: Strictness,
+        decoder: &dyn StatusDecoder,
+    ) -> Result<Self> {
+        let status = Status::parse_decoded(cursor, strictness, decoder)?;
         let scd_kind = ScdKind::parse(cursor)?;
         let scd_len = cursor.read_bytes()?;
         let request_id = cursor.read_bytes()?;
@@ -109,12 +373,22 @@ impl AckCcd {
             scd_len,
         })
     }
+
+    fn serialize(&self, mut buf: impl Write) -> Result<()> {
+        buf.write_bytes(self.status.code)?;
+        buf.write_bytes(self.scd_kind.id())?;
+        buf.write_bytes(self.scd_len)?;
+        buf.write_bytes(self.request_id)?;
+        Ok(())
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Status {
     pub(crate) code: u16,
     pub(crate) kind: StatusKind,
+    /// Meaning of the device specific status given by [`StatusDecoder`].
+    pub(crate) device_specific: Option<DeviceSpecificStatus>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -161,6 +435,87 @@ pub enum GenCpStatus {
 
     /// Generic error.
     GenericError,
+
+    /// A status code unknown to this crate, e.g. defined in a newer revision of `GenCP`.
+    ///
+    /// Parsed only with [`ParseOptions::status`] set to [`Strictness::Lenient`].
+    Unknown(u16),
+}
+
+/// Meaning of a device specific status defined by the vendor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeviceSpecificStatus {
+    /// Identifier of the status, e.g. the name of the variant of an enum defined by a vendor
+    /// support crate.
+    pub name: &'static str,
+    /// Description of the status returned by [`Status::description`].
+    pub description: &'static str,
+    pub severity: Severity,
+    /// `true` if the command may succeed when it is sent again, returned by
+    /// [`Status::is_retryable`].
+    pub retryable: bool,
+}
+
+/// Severity of a device specific status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    /// The command is processed, but the device reports a condition to be noticed.
+    Warning,
+    /// The command failed.
+    Error,
+}
+
+/// Decodes device specific status codes whose meanings are defined by the vendor.
+///
+/// A decoder passed to [`AckPacket::parse_decoded`] gives meanings to the codes in the device
+/// specific namespace, which are reflected in [`Status::description`] and
+/// [`Status::is_retryable`]. [`DefaultStatusDecoder`] is used by the other parse functions.
+///
+/// # Examples
+///
+/// ```rust
+/// use cameleon_device::u3v::protocol::ack::{
+///     AckPacket, DeviceSpecificStatus, Severity, StatusDecoder,
+/// };
+///
+/// struct VendorDecoder;
+///
+/// impl StatusDecoder for VendorDecoder {
+///     fn decode(&self, code: u16) -> Option<DeviceSpecificStatus> {
+///         match code {
+///             0xC001 => Some(DeviceSpecificStatus {
+///                 name: "SensorNotReady",
+///                 description: "the sensor is not ready",
+///                 severity: Severity::Error,
+///                 retryable: true,
+///             }),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// // Status code 0xC001 in response to `ReadMem` command.
+/// let packet = [
+///     0x55, 0x33, 0x56, 0x43, 0x01, 0xC0, 0x01, 0x08, 0x00, 0x00, 0x01, 0x00,
+/// ];
+/// let ack = AckPacket::parse_decoded(&packet, &VendorDecoder).unwrap();
+/// assert_eq!(ack.status().description(), "the sensor is not ready");
+/// assert!(ack.status().is_retryable());
+/// ```
+pub trait StatusDecoder {
+    /// Returns the meaning of the device specific status `code`, or `None` if the code is
+    /// unknown to the decoder.
+    fn decode(&self, code: u16) -> Option<DeviceSpecificStatus>;
+}
+
+/// A decoder which doesn't know any device specific status.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultStatusDecoder;
+
+impl StatusDecoder for DefaultStatusDecoder {
+    fn decode(&self, _code: u16) -> Option<DeviceSpecificStatus> {
+        None
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -180,6 +535,11 @@ pub enum UsbSpecificStatus {
     /// Command that attempts to enable stream is failed because streaming interface is invalid
     /// state.
     InvalidSiState,
+
+    /// A status code unknown to this crate.
+    ///
+    /// Parsed only with [`ParseOptions::status`] set to [`Strictness::Lenient`].
+    Unknown(u16),
 }
 
 impl Status {
@@ -198,21 +558,64 @@ impl Status {
         self.code
     }
 
+    /// Returns `true` if the command may succeed when it is sent again, i.e. the status is
+    /// [`GenCpStatus::Busy`], [`GenCpStatus::Timeout`] or a device specific status decoded as
+    /// retryable.
+    #[must_use]
+    pub fn is_retryable(self) -> bool {
+        match self.kind {
+            StatusKind::GenCp(status) => matches!(status, GenCpStatus::Busy | GenCpStatus::Timeout),
+            StatusKind::UsbSpecific(_) => false,
+            StatusKind::DeviceSpecific => matches!(self.device_specific, Some(s) if s.retryable),
+        }
+    }
+
     #[must_use]
-    pub fn kind(self) -> StatusKind {
-        self.kind
+    pub fn kind(&self) -> &StatusKind {
+        &self.kind
     }
 
+    /// Returns the meaning of the device specific status if it's decoded by [`StatusDecoder`].
+    #[must_use]
+    pub fn device_specific(&self) -> Option<&DeviceSpecificStatus> {
+        self.device_specific.as_ref()
+    }
+
+    /// Returns the description of the status defined in `GenCP` and `U3V` specifications.
+    ///
+    /// The meaning of a device specific status isn't defined unless it's decoded by
+    /// [`StatusDecoder`], use [`Self::code`] or `Display` implementation to obtain the raw code.
+    #[must_use]
+    pub fn description(&self) -> &'static str {
+        match self.kind {
+            StatusKind::GenCp(status) => status.description(),
+            StatusKind::UsbSpecific(status) => status.description(),
+            StatusKind::DeviceSpecific => self
+                .device_specific
+                .map_or("device specific status", |s| s.description),
+        }
+    }
+
+    #[cfg(test)]
     fn parse(cursor: &mut Cursor<&[u8]>) -> Result<Self> {
+        Self::parse_decoded(cursor, Strictness::Strict, &DefaultStatusDecoder)
+    }
+
+    fn parse_decoded(
+        cursor: &mut Cursor<&[u8]>,
+        strictness: Strictness,
+        decoder: &dyn StatusDecoder,
+    ) -> Result<Self> {
         let code: u16 = cursor.read_bytes()?;
 
-        let namespace = (code >> 13) & 0x11;
+        let namespace = (code >> 13) & 0b11;
         match namespace {
-            0b00 => Self::parse_gencp_status(code),
-            0b01 => Self::parse_usb_status(code),
+            0b00 => Self::parse_gencp_status(code, strictness),
+            0b01 => Self::parse_usb_status(code, strictness),
             0b10 => Ok(Self {
                 code,
                 kind: StatusKind::DeviceSpecific,
+                device_specific: decoder.decode(code),
             }),
             _ => Err(Error::InvalidPacket(
                 "invalid ack status code, namespace is set to 0b11".into(),
@@ -220,10 +623,10 @@ impl Status {
         }
     }
 
-    fn parse_gencp_status(code: u16) -> Result<Self> {
+    fn parse_gencp_status(code: u16, strictness: Strictness) -> Result<Self> {
         use GenCpStatus::{
             AccessDenied, BadAlignment, Busy, GenericError, InvalidAddress, InvalidHeader,
-            InvalidParameter, NotImplemented, Success, Timeout, WriteProtect, WrongConfig,
+            InvalidParameter, NotImplemented, Success, Timeout, Unknown, WriteProtect, WrongConfig,
         };
 
         debug_assert!((code >> 13).trailing_zeros() >= 2);
@@ -241,6 +644,7 @@ impl Status {
             0x800E => InvalidHeader,
             0x800F => WrongConfig,
             0x8FFF => GenericError,
+            _ if strictness == Strictness::Lenient => Unknown(code),
             _ => {
                 return Err(Error::InvalidPacket(
                     format! {"invalid gencp status code {:#X}", code}.into(),
@@ -251,13 +655,14 @@ impl Status {
         Ok(Self {
             code,
             kind: StatusKind::GenCp(status),
+            device_specific: None,
         })
     }
 
-    fn parse_usb_status(code: u16) -> Result<Self> {
+    fn parse_usb_status(code: u16, strictness: Strictness) -> Result<Self> {
         use UsbSpecificStatus::{
             EventEndpointHalted, InvalidSiState, PayloadSizeNotAligned, ResendNotSupported,
-            StreamEndpointHalted,
+            StreamEndpointHalted, Unknown,
         };
 
         debug_assert!(code >> 13 & 0b11 == 0b01);
@@ -268,6 +673,7 @@ impl Status {
             0xA003 => PayloadSizeNotAligned,
             0xA004 => InvalidSiState,
             0xA005 => EventEndpointHalted,
+            _ if strictness == Strictness::Lenient => Unknown(code),
             _ => {
                 return Err(Error::InvalidPacket(
                     format! {"invalid usb status code {:#X}", code}.into(),
@@ -278,10 +684,138 @@ impl Status {
         Ok(Self {
             code,
             kind: StatusKind::UsbSpecific(status),
+            device_specific: None,
         })
     }
 }
 
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (status code: {:#06X})",
+            self.description(),
+            self.code
+        )
+    }
+}
+
+impl GenCpStatus {
+    /// Returns the description of the status defined in `GenCP` specification.
+    #[must_use]
+    pub fn description(self) -> &'static str {
+        use GenCpStatus::{
+            AccessDenied, BadAlignment, Busy, GenericError, InvalidAddress, InvalidHeader,
+            InvalidParameter, NotImplemented, Success, Timeout, Unknown, WriteProtect, WrongConfig,
+        };
+
+        match self {
+            Success => "success",
+            NotImplemented => "command not implemented in the device",
+            InvalidParameter => {
+                "at least one command parameter of CCD or SCD is invalid or out of range"
+            }
+            InvalidAddress => "attempt to access a not existing register address",
+            WriteProtect => "attempt to write to a read only register",
+            BadAlignment => "attempt to access registers with an address which is not aligned",
+            AccessDenied => {
+                "attempt to read a non-readable or write a non-writable register address"
+            }
+            Busy => "the command receiver is currently busy",
+            Timeout => "timeout waiting for an acknowledge",
+            InvalidHeader => "header is inconsistent with data",
+            WrongConfig => {
+                "the current receiver configuration does not allow the execution of the sent command"
+            }
+            GenericError => "generic error",
+            Unknown(_) => "unknown gencp status",
+        }
+    }
+
+    fn code(self) -> u16 {
+        use GenCpStatus::{
+            AccessDenied, BadAlignment, Busy, GenericError, InvalidAddress, InvalidHeader,
+            InvalidParameter, NotImplemented, Success, Timeout, Unknown, WriteProtect, WrongConfig,
+        };
+
+        match self {
+            Success => 0x0000,
+            NotImplemented => 0x8001,
+            InvalidParameter => 0x8002,
+            InvalidAddress => 0x8003,
+            WriteProtect => 0x8004,
+            BadAlignment => 0x8005,
+            AccessDenied => 0x8006,
+            Busy => 0x8007,
+            Timeout => 0x800B,
+            InvalidHeader => 0x800E,
+            WrongConfig => 0x800F,
+            GenericError => 0x8FFF,
+            Unknown(code) => code,
+        }
+    }
+}
+
+impl UsbSpecificStatus {
+    /// Returns the description of the status defined in `U3V` specification.
+    #[must_use]
+    pub fn description(self) -> &'static str {
+        use UsbSpecificStatus::{
+            EventEndpointHalted, InvalidSiState, PayloadSizeNotAligned, ResendNotSupported,
+            StreamEndpointHalted, Unknown,
+        };
+
+        match self {
+            ResendNotSupported => "resend command is not supported by USB device",
+            StreamEndpointHalted => "stream endpoint is halted when stream enable flag is set",
+            PayloadSizeNotAligned => {
+                "command that attempts to set payload size is invalid because of bad alignment"
+            }
+            InvalidSiState => {
+                "command that attempts to enable stream is failed because streaming interface is in invalid state"
+            }
+            EventEndpointHalted => "event endpoint is halted when event enable flag is set",
+            Unknown(_) => "unknown usb specific status",
+        }
+    }
+
+    fn code(self) -> u16 {
+        use UsbSpecificStatus::{
+            EventEndpointHalted, InvalidSiState, PayloadSizeNotAligned, ResendNotSupported,
+            StreamEndpointHalted, Unknown,
+        };
+
+        match self {
+            ResendNotSupported => 0xA001,
+            StreamEndpointHalted => 0xA002,
+            PayloadSizeNotAligned => 0xA003,
+            InvalidSiState => 0xA004,
+            EventEndpointHalted => 0xA005,
+            Unknown(code) => code,
+        }
+    }
+}
+
+impl From<GenCpStatus> for Status {
+    fn from(status: GenCpStatus) -> Self {
+        Self {
+            code: status.code(),
+            kind: StatusKind::GenCp(status),
+            device_specific: None,
+        }
+    }
+}
+
+impl From<UsbSpecificStatus> for Status {
+    fn from(status: UsbSpecificStatus) -> Self {
+        Self {
+            code: status.code(),
+            kind: StatusKind::UsbSpecific(status),
+            device_specific: None,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ScdKind {
     ReadMem,
@@ -289,6 +823,8 @@ pub enum ScdKind {
     ReadMemStacked,
     WriteMemStacked,
     Pending,
+    /// Ack of a device specific custom command, contains the ack id.
+    Custom(u16),
 }
 
 impl ScdKind {
@@ -300,11 +836,31 @@ impl ScdKind {
             0x0805 => Ok(ScdKind::Pending),
             0x0807 => Ok(ScdKind::ReadMemStacked),
             0x0809 => Ok(ScdKind::WriteMemStacked),
+            id if is_custom_ack_id(id) => Ok(ScdKind::Custom(id)),
             _ => Err(Error::InvalidPacket(
                 format!("unknown ack command id {:#X}", id).into(),
             )),
         }
     }
+
+    /// Returns the ack id of the kind.
+    #[must_use]
+    pub fn id(self) -> u16 {
+        match self {
+            ScdKind::ReadMem => 0x0801,
+            ScdKind::WriteMem => 0x0803,
+            ScdKind::Pending => 0x0805,
+            ScdKind::ReadMemStacked => 0x0807,
+            ScdKind::WriteMemStacked => 0x0809,
+            ScdKind::Custom(id) => id,
+        }
+    }
+}
+
+/// Returns `true` if `id` is in the range of custom ack ids, i.e. the most significant bit is set
+/// and the id is odd.
+fn is_custom_ack_id(id: u16) -> bool {
+    id >> 15 == 1 && id & 1 == 1
 }
 
 pub trait ParseScd<'a>: Sized {
@@ -327,8 +883,47 @@ pub struct ReadMemStacked<'a> {
     pub data: &'a [u8],
 }
 
-pub struct WriteMemStacked {
-    pub lengths: Vec<u16>,
+pub struct WriteMemStacked<'a> {
+    pub entries: WriteMemStackedEntries<'a>,
+}
+
+/// Written lengths in `WriteMemStacked` ack scd.
+///
+/// The reserved bytes of each entry are validated lazily while iterating, so parsing the ack
+/// doesn't allocate.
+#[derive(Debug, Clone)]
+pub struct WriteMemStackedEntries<'a> {
+    raw: &'a [u8],
+}
+
+impl<'a> Iterator for WriteMemStackedEntries<'a> {
+    type Item = Result<u16>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.raw.is_empty() {
+            return None;
+        }
+        let (entry, rest) = self.raw.split_at(4);
+        self.raw = rest;
+
+        if entry[..2] != [0, 0] {
+            return Some(Err(Error::InvalidPacket(
+                "the first two bytes of each WriteMemStackedAck SCD must be set to zero".into(),
+            )));
+        }
+        Some(Ok(u16::from_le_bytes([entry[2], entry[3]])))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for WriteMemStackedEntries<'_> {
+    fn len(&self) -> usize {
+        self.raw.len() / 4
+    }
 }
 
 pub struct CustomAck<'a> {
@@ -373,6 +968,13 @@ impl<'a> ParseScd<'a> for Pending {
     }
 }
 
+impl<'a> ParseScd<'a> for CustomAck<'a> {
+    fn parse(buf: &'a [u8], ccd: &AckCcd) -> Result<Self> {
+        let data = util::read_bytes(&mut Cursor::new(buf), ccd.scd_len)?;
+        Ok(Self { data })
+    }
+}
+
 impl<'a> ParseScd<'a> for ReadMemStacked<'a> {
     fn parse(buf: &'a [u8], ccd: &AckCcd) -> Result<Self> {
         let data = util::read_bytes(&mut Cursor::new(buf), ccd.scd_len)?;
@@ -381,25 +983,18 @@ impl<'a> ParseScd<'a> for ReadMemStacked<'a> {
     }
 }
 
-impl<'a> ParseScd<'a> for WriteMemStacked {
+impl<'a> ParseScd<'a> for WriteMemStacked<'a> {
     fn parse(buf: &'a [u8], ccd: &AckCcd) -> Result<Self> {
-        let mut cursor = Cursor::new(buf);
-        let mut to_read = ccd.scd_len as usize;
-        let mut lengths = Vec::with_capacity(to_read as usize / 4);
-
-        while to_read > 0 {
-            let reserved: u16 = cursor.read_bytes()?;
-            if reserved != 0 {
-                return Err(Error::InvalidPacket(
-                    "the first two bytes of each WriteMemStackedAck SCD must be set to zero".into(),
-                ));
-            }
-            let length = cursor.read_bytes()?;
-            lengths.push(length);
-            to_read -= 4;
+        if ccd.scd_len & 0b11 != 0 {
+            return Err(Error::InvalidPacket(
+                "the length of WriteMemStackedAck SCD must be a multiple of 4".into(),
+            ));
         }
+        let raw = util::read_bytes(&mut Cursor::new(buf), ccd.scd_len)?;
 
-        Ok(Self { lengths })
+        Ok(Self {
+            entries: WriteMemStackedEntries { raw },
+        })
     }
 }
 
@@ -424,6 +1019,19 @@ mod tests {
         ccd
     }
 
+    #[test]
+    fn test_into_owned() {
+        let scd = &[0x01, 0x02, 0x03, 0x04];
+        let mut raw_packet = serialize_header(0x0000, 0x0801, scd.len() as u16, 1);
+        raw_packet.extend(scd);
+
+        let ack = AckPacket::parse(&raw_packet).unwrap();
+        let owned = ack.clone().into_owned();
+        drop(raw_packet);
+        assert_eq!(owned.request_id(), 1);
+        assert_eq!(owned.raw_scd(), scd);
+    }
+
     #[test]
     fn test_read_mem_ack() {
         let scd = &[0x01, 0x02, 0x03, 0x04];
@@ -455,6 +1063,31 @@ mod tests {
         assert_eq!(parsed_scd.length, 0x0a);
     }
 
+    #[test]
+    fn test_custom_ack() {
+        let scd = &[0x01, 0x02, 0x03];
+        let mut raw_packet = serialize_header(0x0000, 0x9001, scd.len() as u16, 1);
+        raw_packet.extend(scd);
+
+        let ack = AckPacket::parse(&raw_packet).unwrap();
+        assert!(ack.status().is_success());
+        assert_eq!(ack.scd_kind(), ScdKind::Custom(0x9001));
+        assert_eq!(ack.custom_command_id(), Some(0x9001));
+        assert_eq!(ack.scd_as::<CustomAck>().unwrap().data, scd);
+
+        let mut buf = vec![];
+        AckPacket::custom_ack(1, 0x9001, scd)
+            .unwrap()
+            .serialize(&mut buf)
+            .unwrap();
+        assert_eq!(buf, raw_packet);
+        assert!(AckPacket::custom_ack(1, 0x9000, scd).is_err());
+
+        // Neither standard nor custom ack id.
+        let raw_packet = serialize_header(0x0000, 0x0901, 0, 1);
+        assert!(AckPacket::parse(&raw_packet).is_err());
+    }
+
     #[test]
     fn test_read_mem_stacked_ack() {
         let scd = &[0x01, 0x02, 0x03, 0x04];
@@ -485,7 +1118,29 @@ mod tests {
         assert_eq!(ack.request_id(), 1);
 
         let parsed_scd = ack.scd_as::<WriteMemStacked>().unwrap();
-        assert_eq!(&parsed_scd.lengths, &[3, 10]);
+        assert_eq!(parsed_scd.entries.len(), 2);
+        let lengths: Result<Vec<u16>> = parsed_scd.entries.collect();
+        assert_eq!(lengths.unwrap(), &[3, 10]);
+    }
+
+    #[test]
+    fn test_malformed_write_mem_stacked_ack() {
+        // Reserved bytes of the second entry are not zero.
+        let scd = [0x00, 0x00, 0x03, 0x00, 0x01, 0x00, 0x0a, 0x00];
+        let mut raw_packet = serialize_header(0x0000, 0x0809, scd.len() as u16, 1);
+        raw_packet.extend(&scd);
+        let ack = AckPacket::parse(&raw_packet).unwrap();
+        let mut entries = ack.scd_as::<WriteMemStacked>().unwrap().entries;
+        assert_eq!(entries.next().unwrap().unwrap(), 3);
+        assert!(entries.next().unwrap().is_err());
+        assert!(entries.next().is_none());
+
+        // The length of scd is not a multiple of 4.
+        let scd = [0x00, 0x00, 0x03, 0x00, 0x00, 0x00];
+        let mut raw_packet = serialize_header(0x0000, 0x0809, scd.len() as u16, 1);
+        raw_packet.extend(&scd);
+        let ack = AckPacket::parse(&raw_packet).unwrap();
+        assert!(ack.scd_as::<WriteMemStacked>().is_err());
     }
 
     #[test]
@@ -506,6 +1161,142 @@ mod tests {
         assert_eq!(parsed_scd.timeout, Duration::from_millis(700));
     }
 
+    fn round_trip(ack: &AckPacket) -> Vec<u8> {
+        let mut buf = vec![];
+        ack.serialize(&mut buf).unwrap();
+        assert_eq!(buf.len(), ack.ack_len());
+        assert_eq!(&AckPacket::parse(&buf).unwrap(), ack);
+        buf
+    }
+
+    #[test]
+    fn test_serialize_read_mem_ack() {
+        let data = &[0x01, 0x02, 0x03, 0x04];
+        let ack = AckPacket::read_mem_ack(1, data).unwrap();
+        let buf = round_trip(&ack);
+
+        let mut expected = serialize_header(0x0000, 0x0801, 4, 1);
+        expected.extend(data);
+        assert_eq!(buf, expected);
+        let parsed = AckPacket::parse_scd::<ReadMem>(&buf).unwrap();
+        assert_eq!(parsed.data, data);
+    }
+
+    #[test]
+    fn test_serialize_write_mem_ack() {
+        let ack = AckPacket::write_mem_ack(2, 10);
+        let buf = round_trip(&ack);
+
+        let mut expected = serialize_header(0x0000, 0x0803, 4, 2);
+        expected.extend(&[0x00, 0x00, 0x0a, 0x00]);
+        assert_eq!(buf, expected);
+        assert_eq!(ack.scd_as::<WriteMem>().unwrap().length, 10);
+    }
+
+    #[test]
+    fn test_serialize_pending_ack() {
+        use std::time::Duration;
+
+        let ack = AckPacket::pending_ack(3, Duration::from_millis(700)).unwrap();
+        let buf = round_trip(&ack);
+
+        let mut expected = serialize_header(0x0000, 0x0805, 4, 3);
+        expected.extend(&[0x00, 0x00, 0xbc, 0x02]);
+        assert_eq!(buf, expected);
+        let parsed = AckPacket::parse(&buf).unwrap();
+        assert_eq!(
+            parsed.scd_as::<Pending>().unwrap().timeout,
+            Duration::from_millis(700)
+        );
+
+        assert!(AckPacket::pending_ack(3, Duration::from_secs(66)).is_err());
+    }
+
+    #[test]
+    fn test_serialize_read_mem_stacked_ack() {
+        let data = &[0x01, 0x02, 0x03, 0x04, 0x05];
+        let ack = AckPacket::read_mem_stacked_ack(4, data).unwrap();
+        let buf = round_trip(&ack);
+
+        let mut expected = serialize_header(0x0000, 0x0807, 5, 4);
+        expected.extend(data);
+        assert_eq!(buf, expected);
+        let parsed = AckPacket::parse_scd::<ReadMemStacked>(&buf).unwrap();
+        assert_eq!(parsed.data, data);
+    }
+
+    #[test]
+    fn test_serialize_write_mem_stacked_ack() {
+        let ack = AckPacket::write_mem_stacked_ack(5, &[3, 10]).unwrap();
+        let buf = round_trip(&ack);
+
+        let mut expected = serialize_header(0x0000, 0x0809, 8, 5);
+        expected.extend(&[0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x0a, 0x00]);
+        assert_eq!(buf, expected);
+        let entries = ack.scd_as::<WriteMemStacked>().unwrap().entries;
+        assert_eq!(
+            entries.map(|len| len.unwrap()).collect::<Vec<_>>(),
+            &[3, 10]
+        );
+    }
+
+    #[test]
+    fn test_serialize_error_ack() {
+        let ack = AckPacket::error_ack(6, ScdKind::WriteMem, GenCpStatus::WriteProtect);
+        let buf = round_trip(&ack);
+        assert_eq!(buf, serialize_header(0x8004, 0x0803, 0, 6));
+        assert!(ack.status().is_fatal());
+
+        let ack = AckPacket::error_ack(7, ScdKind::ReadMem, UsbSpecificStatus::InvalidSiState);
+        let buf = round_trip(&ack);
+        assert_eq!(buf, serialize_header(0xA004, 0x0801, 0, 7));
+        assert_eq!(
+            *ack.status().kind(),
+            StatusKind::UsbSpecific(UsbSpecificStatus::InvalidSiState)
+        );
+    }
+
+    #[test]
+    fn test_scd_length_mismatch() {
+        let scds: &[(u16, &[u8])] = &[
+            (0x0801, &[0x01, 0x02, 0x03, 0x04]),
+            (0x0803, &[0x00, 0x00, 0x0a, 0x00]),
+            (0x0805, &[0x00, 0x00, 0xbc, 0x02]),
+            (0x0807, &[0x01, 0x02, 0x03, 0x04]),
+            (0x0809, &[0x00, 0x00, 0x03, 0x00]),
+        ];
+
+        for (command_id, scd) in scds {
+            let mut packet = serialize_header(0x0000, *command_id, scd.len() as u16, 1);
+            packet.extend(*scd);
+
+            // Truncated packet.
+            let truncated = &packet[..packet.len() - 1];
+            match AckPacket::parse(truncated) {
+                Err(Error::InvalidPacket(msg)) => {
+                    assert!(
+                        msg.contains("4 bytes") && msg.contains("only 3 bytes"),
+                        "{}",
+                        msg
+                    );
+                }
+                res => panic!("unexpected result: {:?}", res),
+            }
+
+            // Over-long packet.
+            let mut over_long = packet.clone();
+            over_long.extend(&[0xff, 0xff]);
+            let ack = AckPacket::parse(&over_long).unwrap();
+            assert_eq!(ack.raw_scd(), *scd);
+            assert_eq!(ack, AckPacket::parse(&packet).unwrap());
+            assert!(matches!(
+                AckPacket::parse_with(&over_long, Strictness::Strict),
+                Err(Error::InvalidPacket(_))
+            ));
+            assert!(AckPacket::parse_with(&packet, Strictness::Strict).is_ok());
+        }
+    }
+
     #[test]
     fn test_gencp_error_status() {
         let mut code_buf = vec![0; 2];
@@ -531,4 +1322,115 @@ mod tests {
             _ => panic!("must be USB specific error status"),
         }
     }
+
+    #[test]
+    fn test_unknown_status() {
+        // A `GenCP` code defined in a newer revision, and a `U3V` code.
+        for &(code, description) in &[
+            (0x8008_u16, "unknown gencp status"),
+            (0xA006, "unknown usb specific status"),
+        ] {
+            let raw_packet = serialize_header(code, 0x0801, 0, 1);
+            assert!(AckPacket::parse(&raw_packet).is_err());
+            assert!(AckPacket::parse_with_options(
+                &raw_packet,
+                ParseOptions::strict(),
+                &DefaultStatusDecoder
+            )
+            .is_err());
+
+            let ack = AckPacket::parse_lenient(&raw_packet).unwrap();
+            let status = ack.status();
+            assert_eq!(status.code(), code);
+            assert!(status.is_fatal());
+            assert!(!status.is_retryable());
+            assert_eq!(status.description(), description);
+        }
+
+        let raw_packet = serialize_header(0x8008, 0x0801, 0, 1);
+        let ack = AckPacket::parse_lenient(&raw_packet).unwrap();
+        assert_eq!(
+            *ack.status().kind(),
+            StatusKind::GenCp(GenCpStatus::Unknown(0x8008))
+        );
+        let raw_packet = serialize_header(0xA006, 0x0801, 0, 1);
+        let ack = AckPacket::parse_lenient(&raw_packet).unwrap();
+        assert_eq!(
+            *ack.status().kind(),
+            StatusKind::UsbSpecific(UsbSpecificStatus::Unknown(0xA006))
+        );
+        // The reserved namespace is rejected even in the lenient mode.
+        let raw_packet = serialize_header(0xE001, 0x0801, 0, 1);
+        assert!(AckPacket::parse_lenient(&raw_packet).is_err());
+
+        // Unknown statuses are serialized with their raw codes.
+        let ack = AckPacket::error_ack(1, ScdKind::ReadMem, GenCpStatus::Unknown(0x8008));
+        let mut buf = vec![];
+        ack.serialize(&mut buf).unwrap();
+        assert_eq!(buf, serialize_header(0x8008, 0x0801, 0, 1));
+    }
+
+    #[test]
+    fn test_decode_device_specific_status() {
+        struct Decoder;
+
+        impl StatusDecoder for Decoder {
+            fn decode(&self, code: u16) -> Option<DeviceSpecificStatus> {
+                (code == 0xC001).then_some(DeviceSpecificStatus {
+                    name: "SensorNotReady",
+                    description: "the sensor is not ready",
+                    severity: Severity::Error,
+                    retryable: true,
+                })
+            }
+        }
+
+        let raw_packet = serialize_header(0xC001, 0x0801, 0, 1);
+        let ack = AckPacket::parse_decoded(&raw_packet, &Decoder).unwrap();
+        let status = ack.status();
+        assert_eq!(*status.kind(), StatusKind::DeviceSpecific);
+        assert_eq!(status.device_specific().unwrap().name, "SensorNotReady");
+        assert!(status.is_retryable());
+        assert!(status.is_fatal());
+        assert_eq!(
+            status.to_string(),
+            "the sensor is not ready (status code: 0xC001)"
+        );
+
+        // Unknown codes and the default decoder keep the raw code.
+        let raw_packet = serialize_header(0xC002, 0x0801, 0, 1);
+        let ack = AckPacket::parse_decoded(&raw_packet, &Decoder).unwrap();
+        assert!(ack.status().device_specific().is_none());
+        assert!(!ack.status().is_retryable());
+        let raw_packet = serialize_header(0xC001, 0x0801, 0, 1);
+        let ack = AckPacket::parse(&raw_packet).unwrap();
+        assert_eq!(ack.status().description(), "device specific status");
+    }
+
+    #[test]
+    fn test_status_description() {
+        let status = Status::from(GenCpStatus::Busy);
+        assert!(status.is_retryable());
+        assert_eq!(
+            status.description(),
+            "the command receiver is currently busy"
+        );
+        assert_eq!(
+            status.to_string(),
+            "the command receiver is currently busy (status code: 0x8007)"
+        );
+        assert!(Status::from(GenCpStatus::Timeout).is_retryable());
+        assert!(!Status::from(GenCpStatus::WriteProtect).is_retryable());
+        assert!(!Status::from(UsbSpecificStatus::InvalidSiState).is_retryable());
+
+        let mut code_buf = vec![0; 2];
+        code_buf.as_mut_slice().write_bytes(0xC123_u16).unwrap();
+        let status = Status::parse(&mut Cursor::new(code_buf.as_slice())).unwrap();
+        assert_eq!(*status.kind(), StatusKind::DeviceSpecific);
+        assert!(!status.is_retryable());
+        assert_eq!(
+            status.to_string(),
+            "device specific status (status code: 0xC123)"
+        );
+    }
 }
@@ -2,7 +2,14 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::{convert::TryInto, io::Write};
+use std::{
+    convert::TryInto,
+    io::Write,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc,
+    },
+};
 
 use crate::u3v::{Error, Result};
 
@@ -216,7 +223,7 @@ impl<'a> WriteMem<'a> {
 
         Ok(WriteMemChunks {
             address: self.address,
-            data: &self.data,
+            data: self.data,
             data_idx: 0,
             maximum_data_len,
         })
@@ -285,6 +292,134 @@ impl<'a> WriteMemStacked<'a> {
     }
 }
 
+/// A command whose SCD is given as raw bytes, e.g. a device specific command.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomCommand<'a> {
+    command_id: u16,
+    data: &'a [u8],
+    len: u16,
+    ack_scd_len: u16,
+}
+
+impl<'a> CustomCommand<'a> {
+    /// Constructs a command of `command_id` whose SCD is `data`.
+    ///
+    /// `ack_scd_len` is the maximum SCD length of the corresponding ack. `command_id` must be even
+    /// because the id of the corresponding ack is `command_id + 1`.
+    pub fn new(command_id: u16, data: &'a [u8], ack_scd_len: u16) -> Result<Self> {
+        if command_id & 1 == 1 || command_id == u16::MAX - 1 {
+            let msg = format!("invalid command id {:#06X}", command_id);
+            return Err(Error::InvalidPacket(msg.into()));
+        }
+        let len = into_scd_len(data.len())?;
+
+        Ok(Self {
+            command_id,
+            data,
+            len,
+            ack_scd_len,
+        })
+    }
+
+    #[must_use]
+    pub fn command_id(&self) -> u16 {
+        self.command_id
+    }
+
+    #[must_use]
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// Generates request ids of commands, which wrap around at `u16::MAX`.
+///
+/// The generator can be shared between threads, e.g. by wrapping it with [`Arc`].
+#[derive(Debug, Default)]
+pub struct RequestIdGenerator {
+    next: AtomicU16,
+}
+
+impl RequestIdGenerator {
+    /// Constructs a generator whose first id is `first`.
+    #[must_use]
+    pub fn new(first: u16) -> Self {
+        Self {
+            next: AtomicU16::new(first),
+        }
+    }
+
+    /// Returns a new request id.
+    pub fn next_id(&self) -> u16 {
+        // `fetch_add` wraps around on overflow.
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Returns the id which will be returned from the next call of [`Self::next_id`].
+    #[must_use]
+    pub fn peek(&self) -> u16 {
+        self.next.load(Ordering::Relaxed)
+    }
+}
+
+/// Builds [`CommandPacket`] whose request id is assigned from [`RequestIdGenerator`].
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use cameleon_device::u3v::protocol::cmd::{CommandBuilder, ReadMem, RequestIdGenerator};
+///
+/// let ids = Arc::new(RequestIdGenerator::new(0));
+/// let builder = CommandBuilder::new(1024, ids);
+///
+/// let cmd = builder.build(ReadMem::new(0x0004, 64)).unwrap();
+/// let mut buf = vec![0; cmd.cmd_len()];
+/// cmd.serialize(buf.as_mut_slice()).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct CommandBuilder {
+    maximum_cmd_length: usize,
+    ids: Arc<RequestIdGenerator>,
+}
+
+impl CommandBuilder {
+    /// Constructs a builder.
+    ///
+    /// `maximum_cmd_length` is the maximum command transfer length of the device, which is read
+    /// from `SBRM`.
+    #[must_use]
+    pub fn new(maximum_cmd_length: u32, ids: Arc<RequestIdGenerator>) -> Self {
+        Self {
+            maximum_cmd_length: maximum_cmd_length as usize,
+            ids,
+        }
+    }
+
+    /// Builds the command packet with a new request id.
+    ///
+    /// Returns [`Error::CommandTooLong`] without consuming a request id if the command exceeds
+    /// the maximum command transfer length.
+    pub fn build<T: CommandScd>(&self, scd: T) -> Result<CommandPacket<T>> {
+        let cmd_len = CommandPacket::<T>::header_len() + scd.scd_len() as usize;
+        if cmd_len > self.maximum_cmd_length {
+            return Err(Error::CommandTooLong {
+                len: cmd_len,
+                max: self.maximum_cmd_length,
+            });
+        }
+
+        Ok(scd.finalize(self.ids.next_id()))
+    }
+
+    /// Returns the request id generator shared by the builder.
+    #[must_use]
+    pub fn request_ids(&self) -> &Arc<RequestIdGenerator> {
+        &self.ids
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct CommandCcd {
     flag: CommandFlag,
@@ -365,6 +500,8 @@ pub enum ScdKind {
     WriteMem,
     ReadMemStacked,
     WriteMemStacked,
+    /// A command which isn't defined in `GenCP`, which holds its command id.
+    Custom(u16),
 }
 
 impl ScdKind {
@@ -374,6 +511,7 @@ impl ScdKind {
             Self::WriteMem => 0x0802,
             Self::ReadMemStacked => 0x0806,
             Self::WriteMemStacked => 0x0808,
+            Self::Custom(id) => id,
         };
 
         Ok(buf.write_bytes(kind_id)?)
@@ -447,7 +585,7 @@ impl<'a> CommandScd for WriteMem<'a> {
     }
 }
 
-impl<'a> CommandScd for ReadMemStacked {
+impl CommandScd for ReadMemStacked {
     fn flag(&self) -> CommandFlag {
         CommandFlag::RequestAck
     }
@@ -501,6 +639,29 @@ impl<'a> CommandScd for WriteMemStacked<'a> {
     }
 }
 
+impl<'a> CommandScd for CustomCommand<'a> {
+    fn flag(&self) -> CommandFlag {
+        CommandFlag::RequestAck
+    }
+
+    fn scd_kind(&self) -> ScdKind {
+        ScdKind::Custom(self.command_id)
+    }
+
+    fn scd_len(&self) -> u16 {
+        self.len
+    }
+
+    fn serialize(&self, mut buf: impl Write) -> Result<()> {
+        buf.write_all(self.data)?;
+        Ok(())
+    }
+
+    fn ack_scd_len(&self) -> u16 {
+        self.ack_scd_len
+    }
+}
+
 fn into_scd_len(len: usize) -> Result<u16> {
     len.try_into()
         .map_err(|_| Error::InvalidPacket("scd length must be less than u16::MAX".into()))
@@ -527,7 +688,7 @@ mod tests {
         let command = ReadMem::new(0x0004, 64).finalize(1);
         let scd_len = 12;
 
-        assert_eq!(command.cmd_len(), (HEADER_LEN + scd_len).into());
+        assert_eq!(command.cmd_len(), usize::from(HEADER_LEN + scd_len));
         assert_eq!(command.request_id(), 1);
 
         let mut buf = vec![];
@@ -547,7 +708,7 @@ mod tests {
             .finalize(1);
         let scd_len = 11;
 
-        assert_eq!(command.cmd_len(), (HEADER_LEN + scd_len).into());
+        assert_eq!(command.cmd_len(), usize::from(HEADER_LEN + scd_len));
         assert_eq!(command.request_id(), 1);
 
         let mut buf = vec![];
@@ -565,7 +726,7 @@ mod tests {
         let command = ReadMemStacked::new(read_mems).unwrap().finalize(1);
         let scd_len = 12 * 2;
 
-        assert_eq!(command.cmd_len(), (HEADER_LEN + scd_len).into());
+        assert_eq!(command.cmd_len(), usize::from(HEADER_LEN + scd_len));
         assert_eq!(command.request_id(), 1);
 
         let mut buf = vec![];
@@ -591,7 +752,7 @@ mod tests {
         let command = WriteMemStacked::new(write_mems).unwrap().finalize(1);
         let scd_len = (12 + 4) * 2;
 
-        assert_eq!(command.cmd_len(), (HEADER_LEN + scd_len).into());
+        assert_eq!(command.cmd_len(), usize::from(HEADER_LEN + scd_len));
         assert_eq!(command.request_id(), 1);
 
         let mut buf = vec![];
@@ -650,4 +811,70 @@ mod tests {
         assert_eq!(last_chunk.address, expected_addr);
         assert_eq!(last_chunk.data_len, data.len() as u16 - sent_data_len);
     }
+
+    #[test]
+    fn test_custom_cmd() {
+        let command = CustomCommand::new(0x9000, &[0xaa, 0xbb, 0xcc], 8)
+            .unwrap()
+            .finalize(0x1234);
+        let scd_len = 3;
+
+        assert_eq!(command.cmd_len(), usize::from(HEADER_LEN + scd_len));
+        assert_eq!(command.maximum_ack_len(), usize::from(HEADER_LEN) + 8);
+
+        // Serialize into a fixed size buffer.
+        let mut buf = [0; 15];
+        command.serialize(&mut buf[..]).unwrap();
+        let mut expected = serialize_header([0x00, 0x90], [scd_len, 0x00], [0x34, 0x12]);
+        expected.extend(vec![0xaa, 0xbb, 0xcc]); // Data.
+        assert_eq!(&buf[..], expected.as_slice());
+
+        // Ack ids are odd.
+        assert!(CustomCommand::new(0x9001, &[], 0).is_err());
+        assert!(CustomCommand::new(0xfffe, &[], 0).is_err());
+    }
+
+    #[test]
+    fn test_request_id_generator() {
+        let ids = RequestIdGenerator::new(u16::MAX - 1);
+        assert_eq!(ids.next_id(), u16::MAX - 1);
+        assert_eq!(ids.next_id(), u16::MAX);
+        assert_eq!(ids.peek(), 0);
+        assert_eq!(ids.next_id(), 0);
+        assert_eq!(ids.next_id(), 1);
+    }
+
+    #[test]
+    fn test_command_builder() {
+        let ids = Arc::new(RequestIdGenerator::new(7));
+        let builder = CommandBuilder::new(24, ids.clone());
+        let other = CommandBuilder::new(1024, ids.clone());
+
+        // 12 bytes header + 12 bytes scd fits into the maximum command length.
+        let command = builder.build(ReadMem::new(0x0004, 64)).unwrap();
+        assert_eq!(command.request_id(), 7);
+        let mut buf = vec![];
+        command.serialize(&mut buf).unwrap();
+        let mut expected = serialize_header([0x00, 0x08], [12, 0x00], [0x07, 0x00]);
+        expected.extend(vec![0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]); // Address.
+        expected.extend(vec![0x00, 0x00]); // Reserved.
+        expected.extend(vec![64, 0x00]); // Read length.
+        assert_eq!(buf, expected);
+
+        // 12 bytes header + 13 bytes scd exceeds the maximum command length.
+        let data = [0; 5];
+        match builder.build(WriteMem::new(0x0004, &data).unwrap()) {
+            Err(Error::CommandTooLong { len, max }) => {
+                assert_eq!(len, 25);
+                assert_eq!(max, 24);
+            }
+            res => panic!("unexpected result: {:?}", res),
+        }
+        assert_eq!(ids.peek(), 8);
+
+        // Builders sharing the generator don't reuse request ids.
+        let command = other.build(WriteMem::new(0x0004, &data).unwrap()).unwrap();
+        assert_eq!(command.request_id(), 8);
+        assert_eq!(builder.build(ReadMem::new(0, 4)).unwrap().request_id(), 9);
+    }
 }
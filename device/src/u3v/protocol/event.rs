@@ -16,14 +16,37 @@ pub struct EventPacket<'a> {
 impl<'a> EventPacket<'a> {
     const PREFIX_MAGIC: u32 = 0x4556_3355;
 
+    /// Parses `buf` into a packet which borrows data of events from `buf`.
+    ///
+    /// If the device packs multiple events into the packet, all of them are parsed into
+    /// [`Self::scd`].
+    ///
+    /// Returns [`Error::InvalidPacket`] if the packet doesn't contain any event, or sizes in the
+    /// packet are inconsistent with each other or the length of `buf`.
     pub fn parse(buf: &'a (impl AsRef<[u8]> + ?Sized)) -> Result<Self> {
-        let mut cursor = Cursor::new(buf.as_ref());
+        let buf = buf.as_ref();
+        let mut cursor = Cursor::new(buf);
 
         Self::parse_prefix(&mut cursor)?;
 
         let ccd = EventCcd::parse(&mut cursor)?;
 
-        let scd = EventScd::parse(&mut cursor, &ccd)?;
+        let rest = &buf[cursor.position() as usize..];
+        let scd_len = ccd.scd_len as usize;
+        if rest.len() < scd_len {
+            return Err(Error::InvalidPacket(
+                format!(
+                    "SCD length in CCD is {} bytes, but only {} bytes follow the header",
+                    scd_len,
+                    rest.len()
+                )
+                .into(),
+            ));
+        }
+        let scd = EventScd::parse(&mut Cursor::new(&rest[..scd_len]), &ccd)?;
+        if scd.is_empty() {
+            return Err(Error::InvalidPacket("event packet has no event".into()));
+        }
 
         Ok(Self { ccd, scd })
     }
@@ -33,6 +56,24 @@ impl<'a> EventPacket<'a> {
         self.ccd.request_id
     }
 
+    /// Event id of the first event in the packet.
+    #[must_use]
+    pub fn event_id(&self) -> u16 {
+        self.scd[0].event_id
+    }
+
+    /// Timestamp of the first event in the packet.
+    #[must_use]
+    pub fn timestamp(&self) -> u64 {
+        self.scd[0].timestamp
+    }
+
+    /// Data of the first event in the packet.
+    #[must_use]
+    pub fn data(&self) -> &'a [u8] {
+        self.scd[0].data
+    }
+
     fn parse_prefix(cursor: &mut Cursor<&[u8]>) -> Result<()> {
         let magic: u32 = cursor.read_bytes()?;
         if magic == Self::PREFIX_MAGIC {
@@ -83,25 +124,32 @@ pub struct EventScd<'a> {
 }
 
 impl<'a> EventScd<'a> {
+    /// Length of the event size, event id and timestamp fields.
+    const HEADER_LENGTH: u16 = 12;
+
+    /// `cursor` must contain exactly `ccd.scd_len` bytes.
     fn parse(cursor: &mut Cursor<&'a [u8]>, ccd: &EventCcd) -> Result<Vec<Self>> {
         let mut events = vec![];
         let mut remained = ccd.scd_len;
 
         while remained > 0 {
+            if remained < Self::HEADER_LENGTH {
+                return Err(Error::InvalidPacket(
+                    "SCD length in CCD is inconsistent with SCD".into(),
+                ));
+            }
             let event_size: u16 = cursor.read_bytes()?;
             let event_id = cursor.read_bytes()?;
             let timestamp = cursor.read_bytes()?;
 
             // MultiEvent isn't enabled.
             let data = if event_size == 0 {
-                remained = remained.checked_sub(12).ok_or_else(|| {
-                    Error::InvalidPacket("SCD length in CCD is inconsistent with SCD".into())
-                })?;
+                remained -= Self::HEADER_LENGTH;
                 let data = util::read_bytes(cursor, remained)?;
                 remained = 0;
                 data
             } else {
-                let data_len = event_size.checked_sub(12).ok_or_else(|| {
+                let data_len = event_size.checked_sub(Self::HEADER_LENGTH).ok_or_else(|| {
                     Error::InvalidPacket("event size is smaller than scd header".into())
                 })?;
                 remained = remained.checked_sub(event_size).ok_or_else(|| {
@@ -187,6 +235,54 @@ mod tests {
 
         assert_eq!(event_packet.scd[1].event_id, 0x11);
         assert_eq!(event_packet.scd[1].timestamp, timestamp2);
-        assert_eq!(event_packet.scd[1].data, &[]);
+        assert!(event_packet.scd[1].data.is_empty());
+    }
+
+    #[test]
+    fn test_accessors() {
+        let mut scd = vec![];
+        scd.write_bytes(0_u16).unwrap();
+        scd.write_bytes(0x10_u16).unwrap();
+        scd.write_bytes(0x42_u64).unwrap();
+        scd.extend(&[0xaa]);
+        let mut raw_packet = serialize_header(scd.len() as u16, 3);
+        raw_packet.extend(scd);
+
+        let event_packet = EventPacket::parse(&raw_packet).unwrap();
+        assert_eq!(event_packet.request_id(), 3);
+        assert_eq!(event_packet.event_id(), 0x10);
+        assert_eq!(event_packet.timestamp(), 0x42);
+        assert_eq!(event_packet.data(), &[0xaa]);
+    }
+
+    #[test]
+    fn test_malformed_sizes() {
+        fn assert_invalid(scd: &[u8], scd_len: u16) {
+            let mut raw_packet = serialize_header(scd_len, 1);
+            raw_packet.extend(scd);
+            assert!(matches!(
+                EventPacket::parse(&raw_packet),
+                Err(Error::InvalidPacket(_))
+            ));
+        }
+
+        let mut event = vec![];
+        event.write_bytes(14_u16).unwrap();
+        event.write_bytes(0x10_u16).unwrap();
+        event.write_bytes(0_u64).unwrap();
+        event.extend(&[0x12, 0x34]);
+
+        // SCD length exceeds the packet.
+        assert_invalid(&event, event.len() as u16 + 1);
+        // SCD is smaller than the event header.
+        assert_invalid(&event[..8], 8);
+        // Event size exceeds SCD length.
+        assert_invalid(&event[..13], 13);
+        // Event size is smaller than the event header.
+        let mut small = event.clone();
+        small[0] = 4;
+        assert_invalid(&small, small.len() as u16);
+        // No event.
+        assert_invalid(&[], 0);
     }
 }
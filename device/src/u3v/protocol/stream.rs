@@ -18,10 +18,14 @@ use super::util::ReadBytes;
 
 /// Leader of stream protocol.
 ///
+/// The leader consists of the generic part which is common to all payload types and the
+/// specific part whose layout depends on [`Leader::payload_type`]. The specific part of an
+/// unknown payload type is still accessible with [`Leader::raw_specific_leader`].
+///
 /// # Example
 /// ```no_run
 /// use cameleon_device::u3v::protocol::stream::{Leader, PayloadType, ImageLeader,
-///                                             ImageExtendedChunkLeader, ChunkLeader};
+///                                             ImageExtendedChunkLeader, ChunkLeader, GenDcLeader};
 ///
 /// // Buffer for leader bytes.
 /// let mut buf = Vec::new();
@@ -47,6 +51,16 @@ use super::util::ReadBytes;
 ///         // Try parsing specific part as Image Extended Chunk Leader.
 ///         let image_leader: ChunkLeader = leader.specific_leader_as().unwrap();
 ///     }
+///
+///     PayloadType::GenDc => {
+///         // Try parsing specific part as GenDC Leader.
+///         let gendc_leader: GenDcLeader = leader.specific_leader_as().unwrap();
+///     }
+///
+///     PayloadType::Unknown(_) => {
+///         // Only raw bytes are available for an unknown payload type.
+///         let raw = leader.raw_specific_leader();
+///     }
 /// }
 /// ```
 #[derive(Debug, Clone)]
@@ -62,18 +76,36 @@ pub struct Leader<'a> {
 impl<'a> Leader<'a> {
     const LEADER_MAGIC: u32 = 0x4C56_3355;
 
+    /// Size of the generic part of the leader.
+    pub const GENERIC_LEADER_SIZE: u16 = 20;
+
     /// Parse bytes as Leader.
+    ///
+    /// `leader_size` field is validated against the length of `buf`, and the bytes following
+    /// the leader are ignored.
     pub fn parse(buf: &'a (impl AsRef<[u8]> + ?Sized)) -> Result<Self> {
-        let mut cursor = Cursor::new(buf.as_ref());
+        let buf = buf.as_ref();
+        if buf.len() < Self::GENERIC_LEADER_SIZE as usize {
+            return Err(Error::InvalidPacket(
+                format!(
+                    "leader length {} is smaller than the generic leader size {}",
+                    buf.len(),
+                    Self::GENERIC_LEADER_SIZE
+                )
+                .into(),
+            ));
+        }
+        let mut cursor = Cursor::new(buf);
 
         Self::parse_prefix(&mut cursor)?;
         let _reserved1: u16 = cursor.read_bytes()?;
         let leader_size = cursor.read_bytes()?;
         let block_id = cursor.read_bytes()?;
         let _reserved2: u16 = cursor.read_bytes()?;
-        let payload_type = cursor.read_bytes::<u16>()?.try_into()?;
+        let payload_type = cursor.read_bytes::<u16>()?.into();
 
-        let raw_specfic_leader = &cursor.get_ref()[cursor.position() as usize..];
+        let raw_specfic_leader =
+            specific_part(buf, leader_size, Self::GENERIC_LEADER_SIZE, "leader")?;
 
         Ok(Self {
             leader_size,
@@ -106,12 +138,20 @@ impl<'a> Leader<'a> {
     ///         // Try parsing specific part as Image Extended Chunk Leader.
     ///         let image_leader: ChunkLeader = leader.specific_leader_as().unwrap();
     ///     }
+    ///
+    ///     PayloadType::GenDc | PayloadType::Unknown(_) => {}
     /// }
     /// ```
     pub fn specific_leader_as<T: SpecificLeader>(&self) -> Result<T> {
         T::from_bytes(self.raw_specfic_leader)
     }
 
+    /// Raw bytes of the specific part of the leader.
+    #[must_use]
+    pub fn raw_specific_leader(&self) -> &'a [u8] {
+        self.raw_specfic_leader
+    }
+
     /// Total size of leader, this size contains a specific leader part.
     #[must_use]
     pub fn leader_size(&self) -> u16 {
@@ -159,6 +199,12 @@ pub enum PayloadType {
 
     /// Type representing chunk data.
     Chunk,
+
+    /// Type representing a GenDC container, which describes its components by itself.
+    GenDc,
+
+    /// Type which isn't known to this crate, e.g. a vendor specific payload type.
+    Unknown(u16),
 }
 
 /// Image leader is a specific leader part of stream leader.
@@ -224,10 +270,7 @@ impl SpecificLeader for ImageLeader {
     fn from_bytes(buf: &[u8]) -> Result<Self> {
         let mut cursor = Cursor::new(buf);
         let timestamp = cursor.read_bytes()?;
-        let pixel_format = cursor
-            .read_bytes::<u32>()?
-            .try_into()
-            .map_err(|e: String| Error::InvalidPacket(e.into()))?;
+        let pixel_format = PixelFormat::from_code(cursor.read_bytes()?);
         let width = cursor.read_bytes()?;
         let height = cursor.read_bytes()?;
         let x_offset = cursor.read_bytes()?;
@@ -312,10 +355,7 @@ impl SpecificLeader for ImageExtendedChunkLeader {
     fn from_bytes(buf: &[u8]) -> Result<Self> {
         let mut cursor = Cursor::new(buf);
         let timestamp = cursor.read_bytes()?;
-        let pixel_format = cursor
-            .read_bytes::<u32>()?
-            .try_into()
-            .map_err(|e: String| Error::InvalidPacket(e.into()))?;
+        let pixel_format = PixelFormat::from_code(cursor.read_bytes()?);
         let width = cursor.read_bytes()?;
         let height = cursor.read_bytes()?;
         let x_offset = cursor.read_bytes()?;
@@ -335,17 +375,26 @@ impl SpecificLeader for ImageExtendedChunkLeader {
     }
 }
 
-impl TryFrom<u16> for PayloadType {
-    type Error = Error;
-
-    fn try_from(val: u16) -> Result<Self> {
+impl From<u16> for PayloadType {
+    fn from(val: u16) -> Self {
         match val {
-            0x0001 => Ok(PayloadType::Image),
-            0x4001 => Ok(PayloadType::ImageExtendedChunk),
-            0x4000 => Ok(PayloadType::Chunk),
-            val => Err(Error::InvalidPacket(
-                format!("invalid value for leader payload type: {}", val).into(),
-            )),
+            0x0001 => PayloadType::Image,
+            0x4001 => PayloadType::ImageExtendedChunk,
+            0x4000 => PayloadType::Chunk,
+            0x000B => PayloadType::GenDc,
+            val => PayloadType::Unknown(val),
+        }
+    }
+}
+
+impl From<PayloadType> for u16 {
+    fn from(ty: PayloadType) -> Self {
+        match ty {
+            PayloadType::Image => 0x0001,
+            PayloadType::ImageExtendedChunk => 0x4001,
+            PayloadType::Chunk => 0x4000,
+            PayloadType::GenDc => 0x000B,
+            PayloadType::Unknown(val) => val,
         }
     }
 }
@@ -376,6 +425,33 @@ impl SpecificLeader for ChunkLeader {
     }
 }
 
+/// GenDC leader is a specific leader part of stream leader.
+///
+/// When [`Leader::payload_type`] returns [`PayloadType::GenDc`], then the leader contains
+/// [`GenDcLeader`] in a specific leader part. The trailer has no specific part, and the
+/// components of the payload are described by the GenDC container itself.
+pub struct GenDcLeader {
+    timestamp: u64,
+}
+
+impl GenDcLeader {
+    /// Timestamp when the container is created.
+    /// Timestamp represents duration since the device starts running.
+    #[must_use]
+    pub fn timestamp(&self) -> time::Duration {
+        time::Duration::from_nanos(self.timestamp)
+    }
+}
+
+impl SpecificLeader for GenDcLeader {
+    fn from_bytes(buf: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(buf);
+        let timestamp = cursor.read_bytes()?;
+
+        Ok(Self { timestamp })
+    }
+}
+
 /// Trailer part of stream containing auxiliary information of payload data, which is sent after
 /// the payload data.
 #[derive(Debug, Clone)]
@@ -390,9 +466,26 @@ pub struct Trailer<'a> {
 impl<'a> Trailer<'a> {
     const TRAILER_MAGIC: u32 = 0x5456_3355;
 
-    /// Parse bytes as Leader.
+    /// Size of the generic part of the trailer.
+    pub const GENERIC_TRAILER_SIZE: u16 = 28;
+
+    /// Parse bytes as Trailer.
+    ///
+    /// `trailer_size` field is validated against the length of `buf`, and the bytes following
+    /// the trailer are ignored.
     pub fn parse(buf: &'a (impl AsRef<[u8]> + ?Sized)) -> Result<Self> {
-        let mut cursor = Cursor::new(buf.as_ref());
+        let buf = buf.as_ref();
+        if buf.len() < Self::GENERIC_TRAILER_SIZE as usize {
+            return Err(Error::InvalidPacket(
+                format!(
+                    "trailer length {} is smaller than the generic trailer size {}",
+                    buf.len(),
+                    Self::GENERIC_TRAILER_SIZE
+                )
+                .into(),
+            ));
+        }
+        let mut cursor = Cursor::new(buf);
 
         Self::parse_prefix(&mut cursor)?;
         let _reserved1: u16 = cursor.read_bytes()?;
@@ -402,7 +495,8 @@ impl<'a> Trailer<'a> {
         let _reserved2: u16 = cursor.read_bytes()?;
         let valid_payload_size = cursor.read_bytes()?;
 
-        let raw_specfic_trailer = &cursor.get_ref()[cursor.position() as usize..];
+        let raw_specfic_trailer =
+            specific_part(buf, trailer_size, Self::GENERIC_TRAILER_SIZE, "trailer")?;
 
         Ok(Self {
             trailer_size,
@@ -418,6 +512,12 @@ impl<'a> Trailer<'a> {
         T::from_bytes(self.raw_specfic_trailer)
     }
 
+    /// Raw bytes of the specific part of the trailer.
+    #[must_use]
+    pub fn raw_specific_trailer(&self) -> &'a [u8] {
+        self.raw_specfic_trailer
+    }
+
     /// Total size of trailer, this size contains a specific trailer part.
     #[must_use]
     pub fn trailer_size(&self) -> u16 {
@@ -579,6 +679,36 @@ impl TryFrom<u16> for PayloadStatus {
     }
 }
 
+/// Returns the specific part of a leader or trailer whose total size is `size`.
+fn specific_part<'a>(
+    buf: &'a [u8],
+    size: u16,
+    generic_size: u16,
+    section: &str,
+) -> Result<&'a [u8]> {
+    if size < generic_size {
+        return Err(Error::InvalidPacket(
+            format!(
+                "{} size {} is smaller than the generic {} size {}",
+                section, size, section, generic_size
+            )
+            .into(),
+        ));
+    }
+    buf.get(generic_size as usize..size as usize)
+        .ok_or_else(|| {
+            Error::InvalidPacket(
+                format!(
+                    "{} size {} exceeds the buffer length {}",
+                    section,
+                    size,
+                    buf.len()
+                )
+                .into(),
+            )
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::{super::util::WriteBytes, *};
@@ -587,9 +717,10 @@ mod tests {
     fn generic_leader_bytes(payload_type: PayloadType) -> Vec<u8> {
         let mut buf = vec![];
         let (payload_num, size): (u16, u16) = match payload_type {
-            PayloadType::Image => (0x0001, 50),
-            PayloadType::ImageExtendedChunk => (0x4001, 50),
-            PayloadType::Chunk => (0x4000, 20),
+            PayloadType::Image => (0x0001, 52),
+            PayloadType::ImageExtendedChunk => (0x4001, 52),
+            PayloadType::Chunk | PayloadType::GenDc => (u16::from(payload_type), 28),
+            PayloadType::Unknown(ty) => (ty, 20),
         };
         // Leader magic.
         buf.write_bytes(0x4C56_3355_u32).unwrap();
@@ -612,6 +743,7 @@ mod tests {
         let trailer_size: u16 = match payload_type {
             PayloadType::Image | PayloadType::Chunk => 32,
             PayloadType::ImageExtendedChunk => 36,
+            PayloadType::GenDc | PayloadType::Unknown(_) => 28,
         };
 
         let valid_payload_size: u64 = 4096 * 2160;
@@ -688,6 +820,29 @@ mod tests {
         assert_eq!(image_leader.x_padding(), 0);
     }
 
+    #[test]
+    fn test_parse_unknown_pixel_format() {
+        let mut buf = generic_leader_bytes(PayloadType::Image);
+        // Time stamp.
+        buf.write_bytes(100_u64).unwrap();
+        // Pixel Format, vendor specific.
+        buf.write_bytes(0x8108_0001_u32).unwrap();
+        // Width, Height, X offset, Y offset.
+        for _ in 0..4 {
+            buf.write_bytes(16_u32).unwrap();
+        }
+        // X padding and reserved.
+        buf.write_bytes(0_u32).unwrap();
+
+        let leader = Leader::parse(&buf).unwrap();
+        let image_leader: ImageLeader = leader.specific_leader_as().unwrap();
+        assert_eq!(
+            image_leader.pixel_format(),
+            PixelFormat::Unknown(0x8108_0001)
+        );
+        assert_eq!(u32::from(image_leader.pixel_format()), 0x8108_0001);
+    }
+
     #[test]
     fn test_parse_image_extended_chunk_leader() {
         let mut buf = generic_leader_bytes(PayloadType::ImageExtendedChunk);
@@ -733,6 +888,18 @@ mod tests {
         assert_eq!(image_leader.timestamp(), time::Duration::from_nanos(100));
     }
 
+    #[test]
+    fn test_parse_gendc_leader() {
+        let mut buf = generic_leader_bytes(PayloadType::GenDc);
+        // Time stamp.
+        buf.write_bytes(100_u64).unwrap();
+
+        let leader = Leader::parse(&buf).unwrap();
+        assert_eq!(leader.payload_type(), PayloadType::GenDc);
+        let gendc_leader: GenDcLeader = leader.specific_leader_as().unwrap();
+        assert_eq!(gendc_leader.timestamp(), time::Duration::from_nanos(100));
+    }
+
     #[test]
     fn test_parse_generic_trailer() {
         let mut buf = vec![];
@@ -799,4 +966,119 @@ mod tests {
         let specific_trailer: ChunkTrailer = trailer.specific_trailer_as().unwrap();
         assert_eq!(specific_trailer.chunk_layout_id(), chunk_layout_id);
     }
+
+    #[test]
+    fn test_image_leader_layout() {
+        // Image leader of 640x480 `Mono8` image whose block id is 1.
+        let buf: [u8; 52] = [
+            0x55, 0x33, 0x56, 0x4C, // Magic.
+            0x00, 0x00, // Reserved.
+            0x34, 0x00, // Leader size.
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Block ID.
+            0x00, 0x00, // Reserved.
+            0x01, 0x00, // Payload type.
+            0x00, 0xE4, 0x0B, 0x54, 0x02, 0x00, 0x00, 0x00, // Timestamp.
+            0x01, 0x00, 0x08, 0x01, // Pixel format.
+            0x80, 0x02, 0x00, 0x00, // Width.
+            0xE0, 0x01, 0x00, 0x00, // Height.
+            0x08, 0x00, 0x00, 0x00, // X offset.
+            0x04, 0x00, 0x00, 0x00, // Y offset.
+            0x02, 0x00, // X padding.
+            0x00, 0x00, // Reserved.
+        ];
+
+        let leader = Leader::parse(&buf).unwrap();
+        assert_eq!(leader.leader_size(), 52);
+        assert_eq!(leader.block_id(), 1);
+        assert_eq!(leader.payload_type(), PayloadType::Image);
+        let image_leader: ImageLeader = leader.specific_leader_as().unwrap();
+        assert_eq!(image_leader.timestamp(), time::Duration::from_secs(10));
+        assert_eq!(image_leader.pixel_format(), PixelFormat::Mono8);
+        assert_eq!(image_leader.width(), 640);
+        assert_eq!(image_leader.height(), 480);
+        assert_eq!(image_leader.x_offset(), 8);
+        assert_eq!(image_leader.y_offset(), 4);
+        assert_eq!(image_leader.x_padding(), 2);
+    }
+
+    #[test]
+    fn test_image_extended_chunk_trailer_layout() {
+        // Trailer of a payload whose block id is 1 and a part of it is discarded.
+        let buf: [u8; 36] = [
+            0x55, 0x33, 0x56, 0x54, // Magic.
+            0x00, 0x00, // Reserved.
+            0x24, 0x00, // Trailer size.
+            0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // Block ID.
+            0x00, 0xA1, // Status.
+            0x00, 0x00, // Reserved.
+            0x00, 0xB0, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, // Valid payload size.
+            0xE0, 0x01, 0x00, 0x00, // Actual height.
+            0x07, 0x00, 0x00, 0x00, // Chunk layout ID.
+        ];
+
+        let trailer = Trailer::parse(&buf).unwrap();
+        assert_eq!(trailer.trailer_size(), 36);
+        assert_eq!(trailer.block_id(), 1);
+        assert_eq!(trailer.payload_status(), PayloadStatus::DataDiscarded);
+        assert_eq!(trailer.valid_payload_size(), 640 * 480);
+        let specific_trailer: ImageExtendedChunkTrailer = trailer.specific_trailer_as().unwrap();
+        assert_eq!(specific_trailer.actual_height(), 480);
+        assert_eq!(specific_trailer.chunk_layout_id(), 7);
+    }
+
+    #[test]
+    fn test_parse_unknown_payload_type() {
+        let mut buf = generic_leader_bytes(PayloadType::Unknown(0x8001));
+        // Vendor specific leader.
+        buf.extend_from_slice(&[1, 2, 3, 4]);
+        buf[6..8].copy_from_slice(&24_u16.to_le_bytes());
+        let leader = Leader::parse(&buf).unwrap();
+        assert_eq!(leader.payload_type(), PayloadType::Unknown(0x8001));
+        assert_eq!(u16::from(leader.payload_type()), 0x8001);
+        assert_eq!(leader.raw_specific_leader(), &[1, 2, 3, 4]);
+
+        let mut buf = generic_trailer_bytes(PayloadType::Unknown(0x8001));
+        buf.extend_from_slice(&[5, 6]);
+        buf[6..8].copy_from_slice(&30_u16.to_le_bytes());
+        let trailer = Trailer::parse(&buf).unwrap();
+        assert_eq!(trailer.raw_specific_trailer(), &[5, 6]);
+    }
+
+    #[test]
+    fn test_size_validation() {
+        let mut buf = generic_leader_bytes(PayloadType::Chunk);
+        buf.write_bytes(100_u64).unwrap();
+        // Padding after the leader is ignored.
+        buf.extend_from_slice(&[0xff; 4]);
+        let leader = Leader::parse(&buf).unwrap();
+        assert_eq!(leader.raw_specific_leader().len(), 8);
+
+        // Leader size exceeds the buffer.
+        buf[6..8].copy_from_slice(&64_u16.to_le_bytes());
+        assert!(matches!(Leader::parse(&buf), Err(Error::InvalidPacket(_))));
+        // Leader size is smaller than the generic part.
+        buf[6..8].copy_from_slice(&16_u16.to_le_bytes());
+        assert!(matches!(Leader::parse(&buf), Err(Error::InvalidPacket(_))));
+        // Buffer is smaller than the generic part.
+        assert!(matches!(
+            Leader::parse(&buf[..12]),
+            Err(Error::InvalidPacket(_))
+        ));
+
+        // Specific part is shorter than the layout of the payload type.
+        let mut buf = generic_leader_bytes(PayloadType::Image);
+        buf.extend_from_slice(&[0; 16]);
+        buf[6..8].copy_from_slice(&36_u16.to_le_bytes());
+        let leader = Leader::parse(&buf).unwrap();
+        assert!(leader.specific_leader_as::<ImageLeader>().is_err());
+
+        let mut buf = generic_trailer_bytes(PayloadType::Image);
+        assert!(matches!(Trailer::parse(&buf), Err(Error::InvalidPacket(_))));
+        buf.write_bytes(480_u32).unwrap();
+        assert!(Trailer::parse(&buf).is_ok());
+        assert!(matches!(
+            Trailer::parse(&buf[..20]),
+            Err(Error::InvalidPacket(_))
+        ));
+    }
 }
@@ -2,21 +2,27 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::io::{self, Seek};
+use std::{
+    convert::TryFrom,
+    io::{self, Seek},
+};
 
 use byteorder::{ReadBytesExt, WriteBytesExt, LE};
 
 pub(crate) fn read_bytes<'a>(cursor: &mut io::Cursor<&'a [u8]>, len: u16) -> io::Result<&'a [u8]> {
-    let current_pos = cursor.position() as usize;
+    // The position may exceed the address space of the host, and the end position may overflow.
+    let current_pos = usize::try_from(cursor.position()).ok();
+    let end_pos = current_pos.and_then(|pos| pos.checked_add(len as usize));
 
     let buf = cursor.get_ref();
-    let end_pos = len as usize + current_pos;
-
-    if buf.len() < end_pos {
-        return Err(io::Error::new(
-            io::ErrorKind::UnexpectedEof,
-            "data is smaller than specified length",
-        ));
+    let (current_pos, end_pos) = match (current_pos, end_pos) {
+        (Some(current_pos), Some(end_pos)) if end_pos <= buf.len() => (current_pos, end_pos),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "data is smaller than specified length",
+            ))
+        }
     };
 
     let data = &buf[current_pos..end_pos];
@@ -141,3 +147,22 @@ impl_parse_bytes!(i16, read_i16, write_i16);
 // Below line will be uncommented when linter supports this problem, see `https://github.com/rust-lang/rust-clippy/issues/6064`.
 // impl_parse_bytes!(i32, read_i32, write_i32);
 impl_parse_bytes!(i64, read_i64, write_i64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_bytes_out_of_range() {
+        let buf = [0_u8; 8];
+        let mut cursor = io::Cursor::new(&buf[..]);
+        assert_eq!(read_bytes(&mut cursor, 4).unwrap(), &[0; 4]);
+        assert!(read_bytes(&mut cursor, 5).is_err());
+
+        for pos in &[u64::MAX, u64::MAX - 1, usize::MAX as u64] {
+            cursor.set_position(*pos);
+            let err = read_bytes(&mut cursor, u16::MAX).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+}
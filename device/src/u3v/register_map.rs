@@ -2,6 +2,8 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use cameleon_impl::memory::{AccessRight, MemoryProtection};
+
 /// (Address, Length, Access Right) of registers in Technology Agnostic Boot Register Map (ABRM).
 pub mod abrm {
     pub const GENCP_VERSION: (u64, u16) = (0x0000, 4);
@@ -80,3 +82,177 @@ pub mod manifest_entry {
     pub const FILE_SIZE: (u64, u16) = (0x0010, 8);
     pub const SHA1_HASH: (u64, u16) = (0x0018, 20);
 }
+
+/// Bootstrap register maps whose access rights are listed in [`BOOTSTRAP_ACCESS_RIGHTS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootstrapMap {
+    Abrm,
+    Sbrm,
+    Sirm,
+    Eirm,
+}
+
+/// Access right of a bootstrap register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootstrapAccess {
+    /// Name of the register.
+    pub name: &'static str,
+    /// Register map the register belongs to.
+    pub map: BootstrapMap,
+    /// (Offset, Length) of the register in the map.
+    pub register: (u64, u16),
+    /// Access right defined by the specification.
+    pub access: AccessRight,
+    /// `true` if the register may be unavailable, i.e. `NA`, when the device doesn't support the
+    /// corresponding capability.
+    pub optional: bool,
+}
+
+macro_rules! bootstrap_access_rights {
+    ($($map:ident($module:ident::$reg:ident) => $access:ident $(, $optional:ident)?;)*) => {
+        &[$(
+            BootstrapAccess {
+                name: stringify!($reg),
+                map: BootstrapMap::$map,
+                register: $module::$reg,
+                access: AccessRight::$access,
+                optional: bootstrap_access_rights!(@optional $($optional)?),
+            },
+        )*]
+    };
+
+    (@optional) => { false };
+    (@optional optional) => { true };
+}
+
+/// Access rights of the bootstrap registers defined by the `U3V` specification.
+///
+/// This table is authoritative, register maps which model bootstrap registers, e.g. the
+/// emulator's memory, must follow it. See [`protect_bootstrap_map`].
+pub const BOOTSTRAP_ACCESS_RIGHTS: &[BootstrapAccess] = bootstrap_access_rights! {
+    Abrm(abrm::GENCP_VERSION) => RO;
+    Abrm(abrm::MANUFACTURER_NAME) => RO;
+    Abrm(abrm::MODEL_NAME) => RO;
+    Abrm(abrm::FAMILY_NAME) => RO, optional;
+    Abrm(abrm::DEVICE_VERSION) => RO;
+    Abrm(abrm::MANUFACTURER_INFO) => RO;
+    Abrm(abrm::SERIAL_NUMBER) => RO;
+    Abrm(abrm::USER_DEFINED_NAME) => RW, optional;
+    Abrm(abrm::DEVICE_CAPABILITY) => RO;
+    Abrm(abrm::MAXIMUM_DEVICE_RESPONSE_TIME) => RO;
+    Abrm(abrm::MANIFEST_TABLE_ADDRESS) => RO;
+    Abrm(abrm::SBRM_ADDRESS) => RO;
+    Abrm(abrm::DEVICE_CONFIGURATION) => RW;
+    Abrm(abrm::HEARTBEAT_TIMEOUT) => RW, optional;
+    Abrm(abrm::MESSAGE_CHANNEL_ID) => RO, optional;
+    Abrm(abrm::TIMESTAMP) => RO, optional;
+    Abrm(abrm::TIMESTAMP_LATCH) => WO, optional;
+    Abrm(abrm::TIMESTAMP_INCREMENT) => RO, optional;
+    Abrm(abrm::ACCESS_PRIVILEGE) => RW, optional;
+    Abrm(abrm::PROTOCOL_ENDIANNESS) => RO, optional;
+    Abrm(abrm::IMPLEMENTATION_ENDIANNESS) => RO, optional;
+    Abrm(abrm::DEVICE_SOFTWARE_INTERFACE_VERSION) => RO, optional;
+
+    Sbrm(sbrm::U3V_VERSION) => RO;
+    Sbrm(sbrm::U3VCP_CAPABILITY_REGISTER) => RO;
+    Sbrm(sbrm::U3VCP_CONFIGURATION_REGISTER) => RW;
+    Sbrm(sbrm::MAXIMUM_COMMAND_TRANSFER_LENGTH) => RO;
+    Sbrm(sbrm::MAXIMUM_ACKNOWLEDGE_TRANSFER_LENGTH) => RO;
+    Sbrm(sbrm::NUMBER_OF_STREAM_CHANNELS) => RO;
+    Sbrm(sbrm::SIRM_ADDRESS) => RO, optional;
+    Sbrm(sbrm::SIRM_LENGTH) => RO, optional;
+    Sbrm(sbrm::EIRM_ADDRESS) => RO, optional;
+    Sbrm(sbrm::EIRM_LENGTH) => RO, optional;
+    Sbrm(sbrm::IIDC2_ADDRESS) => RO, optional;
+    Sbrm(sbrm::CURRENT_SPEED) => RO;
+
+    Sirm(sirm::SI_INFO) => RO;
+    Sirm(sirm::SI_CONTROL) => RW;
+    Sirm(sirm::REQUIRED_PAYLOAD_SIZE) => RO;
+    Sirm(sirm::REQUIRED_LEADER_SIZE) => RO;
+    Sirm(sirm::REQUIRED_TRAILER_SIZE) => RO;
+    Sirm(sirm::MAXIMUM_LEADER_SIZE) => RW;
+    Sirm(sirm::PAYLOAD_TRANSFER_SIZE) => RW;
+    Sirm(sirm::PAYLOAD_TRANSFER_COUNT) => RW;
+    Sirm(sirm::PAYLOAD_FINAL_TRANSFER1_SIZE) => RW;
+    Sirm(sirm::PAYLOAD_FINAL_TRANSFER2_SIZE) => RW;
+    Sirm(sirm::MAXIMUM_TRAILER_SIZE) => RW;
+
+    Eirm(eirm::EI_CONTROL) => RW;
+    Eirm(eirm::MAXIMUM_EVENT_TRANSFER_LENGTH) => RO;
+    Eirm(eirm::EVENT_TEST_CONTROL) => RW, optional;
+};
+
+/// Sets access rights of the registers in `map` located at `base` according to
+/// [`BOOTSTRAP_ACCESS_RIGHTS`].
+///
+/// Optional registers are also set, override them with `NA` if the capability isn't supported.
+pub fn protect_bootstrap_map(protection: &mut MemoryProtection, map: BootstrapMap, base: usize) {
+    for entry in BOOTSTRAP_ACCESS_RIGHTS
+        .iter()
+        .filter(|entry| entry.map == map)
+    {
+        let (offset, len) = entry.register;
+        let start = base + offset as usize;
+        protection.set_access_right_with_range(start..start + len as usize, entry.access);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_bootstrap_access_rights_layout() {
+        let mut names = HashSet::new();
+        for map in [
+            BootstrapMap::Abrm,
+            BootstrapMap::Sbrm,
+            BootstrapMap::Sirm,
+            BootstrapMap::Eirm,
+        ] {
+            let mut entries: Vec<_> = BOOTSTRAP_ACCESS_RIGHTS
+                .iter()
+                .filter(|entry| entry.map == map)
+                .collect();
+            entries.sort_by_key(|entry| entry.register.0);
+            for pair in entries.windows(2) {
+                let (offset, len) = pair[0].register;
+                assert!(
+                    offset + u64::from(len) <= pair[1].register.0,
+                    "{} overlaps {}",
+                    pair[0].name,
+                    pair[1].name
+                );
+            }
+            for entry in entries {
+                assert!(names.insert(entry.name), "{} is duplicated", entry.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_protect_bootstrap_map() {
+        let base = 0x100;
+        let mut protection = MemoryProtection::new(0x1000);
+        protect_bootstrap_map(&mut protection, BootstrapMap::Abrm, base);
+
+        for entry in BOOTSTRAP_ACCESS_RIGHTS
+            .iter()
+            .filter(|entry| entry.map == BootstrapMap::Abrm)
+        {
+            let (offset, len) = entry.register;
+            let start = base + offset as usize;
+            let access = protection.access_right_with_range(start..start + len as usize);
+            assert_eq!(access, entry.access, "{}", entry.name);
+        }
+        assert_eq!(
+            protection.access_right(base + 0x0184),
+            AccessRight::RW,
+            "USER_DEFINED_NAME"
+        );
+        assert_eq!(protection.access_right(base - 1), AccessRight::NA);
+    }
+}
@@ -0,0 +1,75 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Smoke test for a control transaction against an emulated device, which must work without the
+//! `libusb` feature.
+
+use std::{sync::Arc, time::Duration};
+
+use cameleon_device::{
+    emulator::{self, ControlChannel, EmulatorBuilder},
+    u3v::{
+        protocol::{
+            ack::{self, AckPacket, ScdKind},
+            cmd::{self, CommandBuilder, CommandPacket, CommandScd, RequestIdGenerator},
+        },
+        register_map::abrm,
+    },
+};
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+fn transact<'a, T: CommandScd>(
+    channel: &ControlChannel,
+    cmd: &CommandPacket<T>,
+    buf: &'a mut [u8],
+) -> AckPacket<'a> {
+    let mut cmd_buf = vec![];
+    cmd.serialize(&mut cmd_buf).unwrap();
+    channel.send(&cmd_buf, TIMEOUT).unwrap();
+    let len = channel.recv(buf, TIMEOUT).unwrap();
+    AckPacket::parse(&buf[..len]).unwrap()
+}
+
+#[test]
+fn test_control_transaction() {
+    EmulatorBuilder::new()
+        .serial_number("EMUSMOK1")
+        .unwrap()
+        .build();
+    let device = emulator::enumerate_devices()
+        .unwrap()
+        .into_iter()
+        .find(|device| device.device_info.serial_number == "EMUSMOK1")
+        .unwrap();
+    let mut channel = device.control_channel().unwrap();
+    channel.open().unwrap();
+    let builder = CommandBuilder::new(1024, Arc::new(RequestIdGenerator::new(0)));
+
+    let (address, _) = abrm::USER_DEFINED_NAME;
+    let data = b"smoke\0";
+    let write = builder
+        .build(cmd::WriteMem::new(address, data).unwrap())
+        .unwrap();
+    let mut buf = vec![0; 1024];
+    let ack = transact(&channel, &write, &mut buf);
+    assert!(ack.status().is_success());
+    assert_eq!(ack.request_id(), write.request_id());
+    assert_eq!(ack.scd_kind(), ScdKind::WriteMem);
+    assert_eq!(
+        ack.scd_as::<ack::WriteMem>().unwrap().length,
+        data.len() as u16
+    );
+
+    let read = builder
+        .build(cmd::ReadMem::new(address, data.len() as u16))
+        .unwrap();
+    let mut buf = vec![0; 1024];
+    let ack = transact(&channel, &read, &mut buf);
+    assert_eq!(ack.request_id(), read.request_id());
+    assert_ne!(read.request_id(), write.request_id());
+    assert_eq!(ack.scd_as::<ack::ReadMem>().unwrap().data, data);
+
+    channel.close().unwrap();
+}
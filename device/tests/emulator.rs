@@ -0,0 +1,568 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Control transactions and streaming against an emulated device, which must work without the
+//! `libusb` feature.
+
+use std::{convert::TryInto, path::Path, sync::Arc, time::Duration};
+
+use cameleon_device::{
+    emulator::{self, ControlChannel, EmulatorBuilder},
+    fixture::{FaultKind, Fixture},
+    u3v::{
+        protocol::{
+            ack::{self, AckPacket, GenCpStatus, StatusKind, UsbSpecificStatus},
+            cmd::{self, CommandBuilder, CommandPacket, CommandScd, RequestIdGenerator},
+            event::EventPacket,
+            stream::{ImageLeader, Leader, Trailer},
+        },
+        register_map::{abrm, eirm, manifest_entry, sbrm, sirm},
+        Error, LibUsbError,
+    },
+    PixelFormat,
+};
+
+const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Builds an emulator whose serial number is `serial` and opens its control channel.
+fn open(serial: &str) -> ControlChannel {
+    EmulatorBuilder::new()
+        .serial_number(serial)
+        .unwrap()
+        .build();
+    open_built(serial)
+}
+
+/// Opens the control channel of the built emulator whose serial number is `serial`.
+fn open_built(serial: &str) -> ControlChannel {
+    let device = emulator::enumerate_devices()
+        .unwrap()
+        .into_iter()
+        .find(|device| device.device_info.serial_number == serial)
+        .unwrap();
+    let mut channel = device.control_channel().unwrap();
+    channel.open().unwrap();
+    channel
+}
+
+fn transact<T: CommandScd>(channel: &ControlChannel, scd: T, request_id: u16) -> Vec<u8> {
+    exchange(channel, &scd.finalize(request_id))
+}
+
+fn exchange<T: CommandScd>(channel: &ControlChannel, cmd: &CommandPacket<T>) -> Vec<u8> {
+    let mut buf = vec![];
+    cmd.serialize(&mut buf).unwrap();
+    channel.send(&buf, TIMEOUT).unwrap();
+
+    let mut ack = vec![0; 1024];
+    let len = channel.recv(&mut ack, TIMEOUT).unwrap();
+    ack.truncate(len);
+    ack
+}
+
+fn read_mem(channel: &ControlChannel, address: u64, len: u16, request_id: u16) -> Vec<u8> {
+    let ack = transact(channel, cmd::ReadMem::new(address, len), request_id);
+    let ack = AckPacket::parse(&ack).unwrap();
+    assert!(ack.status().is_success());
+    ack.scd_as::<ack::ReadMem>().unwrap().data.to_vec()
+}
+
+fn read_u64(channel: &ControlChannel, address: u64, request_id: u16) -> u64 {
+    let data = read_mem(channel, address, 8, request_id);
+    u64::from_le_bytes(data.try_into().unwrap())
+}
+
+fn sirm_address(channel: &ControlChannel) -> u64 {
+    let sbrm_address = read_u64(channel, abrm::SBRM_ADDRESS.0, 100);
+    read_u64(channel, sbrm_address + sbrm::SIRM_ADDRESS.0, 101)
+}
+
+fn eirm_address(channel: &ControlChannel) -> u64 {
+    let sbrm_address = read_u64(channel, abrm::SBRM_ADDRESS.0, 100);
+    read_u64(channel, sbrm_address + sbrm::EIRM_ADDRESS.0, 101)
+}
+
+fn string_of(data: &[u8]) -> &str {
+    let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    std::str::from_utf8(&data[..end]).unwrap()
+}
+
+#[test]
+fn test_read_write_mem() {
+    let channel = open("EMUREAD1");
+
+    let (address, len) = abrm::SERIAL_NUMBER;
+    let ack = transact(&channel, cmd::ReadMem::new(address, len), 1);
+    let ack = AckPacket::parse(&ack).unwrap();
+    assert!(ack.status().is_success());
+    assert_eq!(ack.request_id(), 1);
+    assert_eq!(
+        string_of(ack.scd_as::<ack::ReadMem>().unwrap().data),
+        "EMUREAD1"
+    );
+
+    // Serial number is read only.
+    let ack = transact(&channel, cmd::WriteMem::new(address, b"X").unwrap(), 2);
+    let ack = AckPacket::parse(&ack).unwrap();
+    assert_eq!(
+        ack.status().kind(),
+        &StatusKind::GenCp(GenCpStatus::WriteProtect)
+    );
+}
+
+#[test]
+fn test_stacked_mem() {
+    let channel = open("EMUSTACK");
+    let (name_address, name_len) = abrm::USER_DEFINED_NAME;
+    let (serial_address, serial_len) = abrm::SERIAL_NUMBER;
+
+    let name = b"stacked\0";
+    let entries = vec![
+        cmd::WriteMem::new(name_address, name).unwrap(),
+        cmd::WriteMem::new(name_address + 32, b"\0").unwrap(),
+    ];
+    let ack = transact(&channel, cmd::WriteMemStacked::new(entries).unwrap(), 1);
+    let ack = AckPacket::parse(&ack).unwrap();
+    assert!(ack.status().is_success());
+    let lengths: Vec<u16> = ack
+        .scd_as::<ack::WriteMemStacked>()
+        .unwrap()
+        .entries
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(lengths, vec![name.len() as u16, 1]);
+
+    let entries = vec![
+        cmd::ReadMem::new(serial_address, serial_len),
+        cmd::ReadMem::new(name_address, name_len),
+    ];
+    let ack = transact(&channel, cmd::ReadMemStacked::new(entries).unwrap(), 2);
+    let ack = AckPacket::parse(&ack).unwrap();
+    assert!(ack.status().is_success());
+    let data = ack.scd_as::<ack::ReadMemStacked>().unwrap().data;
+    assert_eq!(data.len(), (serial_len + name_len) as usize);
+    let (serial, name) = data.split_at(serial_len as usize);
+    assert_eq!(string_of(serial), "EMUSTACK");
+    assert_eq!(string_of(name), "stacked");
+
+    // The first failing entry is reported.
+    let entries = vec![
+        cmd::WriteMem::new(name_address, b"x").unwrap(),
+        cmd::WriteMem::new(serial_address, b"x").unwrap(),
+    ];
+    let ack = transact(&channel, cmd::WriteMemStacked::new(entries).unwrap(), 3);
+    let ack = AckPacket::parse(&ack).unwrap();
+    assert_eq!(
+        ack.status().kind(),
+        &StatusKind::GenCp(GenCpStatus::WriteProtect)
+    );
+}
+
+#[test]
+fn test_command_builder() {
+    let channel = open("EMUBLDR1");
+    let sbrm_address = read_u64(&channel, abrm::SBRM_ADDRESS.0, 1);
+    let data = read_mem(
+        &channel,
+        sbrm_address + sbrm::MAXIMUM_COMMAND_TRANSFER_LENGTH.0,
+        4,
+        2,
+    );
+    let maximum_cmd_length = u32::from_le_bytes(data.try_into().unwrap());
+
+    // Two builders sharing a generator never reuse a request id.
+    let ids = Arc::new(RequestIdGenerator::new(u16::MAX));
+    let builder = CommandBuilder::new(maximum_cmd_length, ids.clone());
+    let other = CommandBuilder::new(maximum_cmd_length, ids);
+    let (address, len) = abrm::USER_DEFINED_NAME;
+
+    let write = builder
+        .build(cmd::WriteMem::new(address, b"builder\0").unwrap())
+        .unwrap();
+    let ack = exchange(&channel, &write);
+    let ack = AckPacket::parse(&ack).unwrap();
+    assert!(ack.status().is_success());
+    assert_eq!(ack.request_id(), u16::MAX);
+
+    let read = other.build(cmd::ReadMem::new(address, len)).unwrap();
+    let ack = exchange(&channel, &read);
+    let ack = AckPacket::parse(&ack).unwrap();
+    assert_eq!(ack.request_id(), 0);
+    assert_eq!(
+        string_of(ack.scd_as::<ack::ReadMem>().unwrap().data),
+        "builder"
+    );
+
+    // The emulator doesn't implement any custom command.
+    let custom = builder
+        .build(cmd::CustomCommand::new(0x9000, &[1, 2, 3, 4], 0).unwrap())
+        .unwrap();
+    let ack = exchange(&channel, &custom);
+    let ack = AckPacket::parse(&ack).unwrap();
+    assert_eq!(ack.request_id(), 1);
+    assert_eq!(
+        ack.status().kind(),
+        &StatusKind::GenCp(GenCpStatus::NotImplemented)
+    );
+
+    // A command exceeding the maximum command length isn't sent and doesn't consume an id.
+    let data = vec![0; maximum_cmd_length as usize];
+    assert!(matches!(
+        other.build(cmd::WriteMem::new(address, &data).unwrap()),
+        Err(Error::CommandTooLong { .. })
+    ));
+    assert_eq!(other.request_ids().peek(), 2);
+}
+
+#[test]
+fn test_fixture() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/mono_camera.toml");
+    EmulatorBuilder::from_fixture(&path).unwrap().build();
+    let channel = open_built("MONO0001");
+
+    // Registers of the fixture are served with their default values.
+    assert_eq!(read_mem(&channel, 0x30000, 4, 1), 640_u32.to_le_bytes());
+    assert_eq!(read_mem(&channel, 0x30010, 8, 2), 42.5_f64.to_le_bytes());
+    let ack = transact(
+        &channel,
+        cmd::WriteMem::new(0x30000, &800_u32.to_le_bytes()).unwrap(),
+        3,
+    );
+    assert!(AckPacket::parse(&ack).unwrap().status().is_success());
+    assert_eq!(read_mem(&channel, 0x30000, 4, 4), 800_u32.to_le_bytes());
+
+    let ack = transact(&channel, cmd::WriteMem::new(0x30010, &[0; 8]).unwrap(), 5);
+    assert_eq!(
+        AckPacket::parse(&ack).unwrap().status().kind(),
+        &StatusKind::GenCp(GenCpStatus::WriteProtect)
+    );
+
+    // `GenApi` XML of the fixture is served via the manifest table.
+    let manifest_address = read_u64(&channel, abrm::MANIFEST_TABLE_ADDRESS.0, 6);
+    let entry_address = manifest_address + 8;
+    let xml_address = read_u64(
+        &channel,
+        entry_address + manifest_entry::REGISTER_ADDRESS.0,
+        7,
+    );
+    let xml_size = read_u64(&channel, entry_address + manifest_entry::FILE_SIZE.0, 8);
+    let expected = std::fs::read(path.with_file_name("mono_camera.xml")).unwrap();
+    assert_eq!(xml_size, expected.len() as u64);
+    let xml: Vec<u8> = (0..xml_size)
+        .step_by(512)
+        .zip(10..)
+        .flat_map(|(offset, id)| {
+            let len = (xml_size - offset).min(512) as u16;
+            read_mem(&channel, xml_address + offset, len, id)
+        })
+        .collect();
+    assert_eq!(xml, expected);
+
+    // Required payload size is derived from the stream settings.
+    let required_payload_size = read_u64(
+        &channel,
+        sirm_address(&channel) + sirm::REQUIRED_PAYLOAD_SIZE.0,
+        9,
+    );
+    assert_eq!(required_payload_size, 640 * 480);
+}
+
+#[test]
+fn test_fixture_faults() {
+    let src = r#"
+[device]
+serial_number = "FAULTY01"
+
+[[faults]]
+kind = "timeout"
+after_commands = 0
+
+[[faults]]
+kind = "busy"
+after_commands = 1
+
+[[faults]]
+kind = "reject_payload_size"
+after_commands = 0
+
+[[faults]]
+kind = "stall"
+after_commands = 6
+
+[[faults]]
+kind = "disconnect"
+after_commands = 7
+"#;
+    EmulatorBuilder::with_fixture(Fixture::from_toml(src, "").unwrap())
+        .unwrap()
+        .build();
+    let mut channel = open_built("FAULTY01");
+    let (address, len) = abrm::SERIAL_NUMBER;
+    let mut buf = vec![0; 1024];
+
+    // The first command is never acknowledged.
+    let mut cmd = vec![];
+    cmd::ReadMem::new(address, len)
+        .finalize(0)
+        .serialize(&mut cmd)
+        .unwrap();
+    channel.send(&cmd, TIMEOUT).unwrap();
+    assert!(matches!(
+        channel.recv(&mut buf, Duration::from_millis(100)),
+        Err(Error::LibUsb(LibUsbError::Timeout))
+    ));
+
+    let ack = transact(&channel, cmd::ReadMem::new(address, len), 1);
+    assert_eq!(
+        AckPacket::parse(&ack).unwrap().status().kind(),
+        &StatusKind::GenCp(GenCpStatus::Busy)
+    );
+
+    // Commands 2 and 3 read the address of `SIRM`.
+    let payload_transfer_size = sirm_address(&channel) + sirm::PAYLOAD_TRANSFER_SIZE.0;
+    let write = || cmd::WriteMem::new(payload_transfer_size, &[0, 4, 0, 0]).unwrap();
+    let ack = transact(&channel, write(), 4);
+    assert_eq!(
+        AckPacket::parse(&ack).unwrap().status().kind(),
+        &StatusKind::UsbSpecific(UsbSpecificStatus::PayloadSizeNotAligned)
+    );
+    let ack = transact(&channel, write(), 5);
+    assert!(AckPacket::parse(&ack).unwrap().status().is_success());
+
+    let mut cmd = vec![];
+    cmd::ReadMem::new(address, len)
+        .finalize(6)
+        .serialize(&mut cmd)
+        .unwrap();
+    channel.send(&cmd, TIMEOUT).unwrap();
+    assert!(matches!(
+        channel.recv(&mut buf, TIMEOUT),
+        Err(Error::LibUsb(LibUsbError::Pipe))
+    ));
+    channel.clear_halt().unwrap();
+
+    let mut cmd = vec![];
+    cmd::ReadMem::new(address, len)
+        .finalize(7)
+        .serialize(&mut cmd)
+        .unwrap();
+    channel.send(&cmd, TIMEOUT).unwrap();
+    assert!(channel.recv(&mut buf, TIMEOUT).is_err());
+    assert!(matches!(
+        channel.send(&cmd, TIMEOUT),
+        Err(Error::LibUsb(LibUsbError::NoDevice))
+    ));
+}
+
+#[test]
+fn test_command_queue() {
+    EmulatorBuilder::new()
+        .serial_number("EMUQUEU1")
+        .unwrap()
+        .command_latency(Duration::from_millis(20))
+        .build();
+    let device = emulator::enumerate_devices()
+        .unwrap()
+        .into_iter()
+        .find(|device| device.device_info.serial_number == "EMUQUEU1")
+        .unwrap();
+    let channel = open_built("EMUQUEU1");
+    let (address, len) = abrm::SERIAL_NUMBER;
+
+    // Sends two commands without waiting for the first ack, and returns the statuses of the acks
+    // ordered by request id.
+    let send_two = |first_id: u16| {
+        for request_id in first_id..first_id + 2 {
+            let mut buf = vec![];
+            cmd::ReadMem::new(address, len)
+                .finalize(request_id)
+                .serialize(&mut buf)
+                .unwrap();
+            channel.send(&buf, TIMEOUT).unwrap();
+        }
+        let mut statuses = vec![];
+        for _ in 0..2 {
+            let mut buf = vec![0; 1024];
+            let len = channel.recv(&mut buf, TIMEOUT).unwrap();
+            let ack = AckPacket::parse(&buf[..len]).unwrap();
+            statuses.push((ack.request_id(), *ack.status().kind()));
+        }
+        statuses.sort_by_key(|(request_id, _)| *request_id);
+        statuses
+            .into_iter()
+            .map(|(_, status)| status)
+            .collect::<Vec<_>>()
+    };
+
+    // The device processes one command at a time by default.
+    let success = StatusKind::GenCp(GenCpStatus::Success);
+    let busy = StatusKind::GenCp(GenCpStatus::Busy);
+    assert_eq!(send_two(1), vec![success, busy]);
+
+    device.set_command_queue_depth(2).unwrap();
+    assert_eq!(send_two(3), vec![success, success]);
+}
+
+#[test]
+fn test_inject_fault() {
+    EmulatorBuilder::new()
+        .serial_number("INJECT01")
+        .unwrap()
+        .build();
+    let device = emulator::enumerate_devices()
+        .unwrap()
+        .into_iter()
+        .find(|device| device.device_info.serial_number == "INJECT01")
+        .unwrap();
+    let mut channel = device.control_channel().unwrap();
+    channel.open().unwrap();
+    let (address, len) = abrm::SERIAL_NUMBER;
+
+    device.inject_fault(FaultKind::Busy).unwrap();
+    let ack = transact(&channel, cmd::ReadMem::new(address, len), 1);
+    assert_eq!(
+        AckPacket::parse(&ack).unwrap().status().kind(),
+        &StatusKind::GenCp(GenCpStatus::Busy)
+    );
+
+    // Only the next command is affected.
+    assert_eq!(string_of(&read_mem(&channel, address, len, 2)), "INJECT01");
+}
+
+#[test]
+fn test_stream() {
+    let src = r#"
+[device]
+serial_number = "STREAM01"
+
+[stream]
+width = 8
+height = 4
+pixel_format = "Mono8"
+"#;
+    EmulatorBuilder::with_fixture(Fixture::from_toml(src, "").unwrap())
+        .unwrap()
+        .build();
+    let device = emulator::enumerate_devices()
+        .unwrap()
+        .into_iter()
+        .find(|device| device.device_info.serial_number == "STREAM01")
+        .unwrap();
+    let mut ctrl = device.control_channel().unwrap();
+    ctrl.open().unwrap();
+    let mut strm = device.stream_channel().unwrap().unwrap();
+    strm.open().unwrap();
+
+    // The 32 bytes payload is sent in two transfers.
+    let sirm = sirm_address(&ctrl);
+    let write_u32 = |(offset, _): (u64, u16), value: u32, request_id| {
+        let data = value.to_le_bytes();
+        let scd = cmd::WriteMem::new(sirm + offset, &data).unwrap();
+        let ack = transact(&ctrl, scd, request_id);
+        assert!(AckPacket::parse(&ack).unwrap().status().is_success());
+    };
+    write_u32(sirm::MAXIMUM_LEADER_SIZE, 1024, 1);
+    write_u32(sirm::MAXIMUM_TRAILER_SIZE, 1024, 2);
+    write_u32(sirm::PAYLOAD_TRANSFER_SIZE, 16, 3);
+    write_u32(sirm::PAYLOAD_TRANSFER_COUNT, 2, 4);
+    write_u32(sirm::SI_CONTROL, 1, 5);
+
+    let mut buf = vec![0; 1024];
+    let mut block_ids = vec![];
+    for _ in 0..2 {
+        let len = strm.recv(&mut buf, TIMEOUT).unwrap();
+        let leader = Leader::parse(&buf[..len]).unwrap();
+        let block_id = leader.block_id();
+        let image_leader: ImageLeader = leader.specific_leader_as().unwrap();
+        assert_eq!(image_leader.pixel_format(), PixelFormat::Mono8);
+        assert_eq!((image_leader.width(), image_leader.height()), (8, 4));
+
+        let mut payload = vec![];
+        for _ in 0..2 {
+            let len = strm.recv(&mut buf, TIMEOUT).unwrap();
+            assert_eq!(len, 16);
+            payload.extend_from_slice(&buf[..len]);
+        }
+        let expected: Vec<u8> = (0..32).map(|i| (block_id + i) as u8).collect();
+        assert_eq!(payload, expected);
+
+        let len = strm.recv(&mut buf, TIMEOUT).unwrap();
+        let trailer = Trailer::parse(&buf[..len]).unwrap();
+        assert_eq!(trailer.block_id(), block_id);
+        assert_eq!(trailer.valid_payload_size(), 32);
+        block_ids.push(block_id);
+    }
+    assert_eq!(block_ids[1], block_ids[0] + 1);
+
+    // No frame is sent after the stream is disabled.
+    write_u32(sirm::SI_CONTROL, 0, 6);
+    assert!(matches!(
+        strm.recv(&mut buf, Duration::from_millis(50)),
+        Err(Error::LibUsb(LibUsbError::Timeout))
+    ));
+}
+
+#[test]
+fn test_event() {
+    EmulatorBuilder::new()
+        .serial_number("EVENTS01")
+        .unwrap()
+        .build();
+    let device = emulator::enumerate_devices()
+        .unwrap()
+        .into_iter()
+        .find(|device| device.device_info.serial_number == "EVENTS01")
+        .unwrap();
+    let mut ctrl = device.control_channel().unwrap();
+    ctrl.open().unwrap();
+    let mut event = device.event_channel().unwrap().unwrap();
+    event.open().unwrap();
+
+    let write_u32 = |address: u64, value: u32, request_id| {
+        let data = value.to_le_bytes();
+        let scd = cmd::WriteMem::new(address, &data).unwrap();
+        let ack = transact(&ctrl, scd, request_id);
+        assert!(AckPacket::parse(&ack).unwrap().status().is_success());
+    };
+    let eirm = eirm_address(&ctrl);
+    let max_len = read_mem(&ctrl, eirm + eirm::MAXIMUM_EVENT_TRANSFER_LENGTH.0, 4, 1);
+    let mut buf = vec![0; u32::from_le_bytes(max_len.try_into().unwrap()) as usize];
+
+    // Events carry the latched timestamp.
+    write_u32(abrm::TIMESTAMP_LATCH.0, 1, 2);
+    let timestamp = read_u64(&ctrl, abrm::TIMESTAMP.0, 3);
+
+    // No event is sent before the event interface is enabled.
+    write_u32(eirm + eirm::EVENT_TEST_CONTROL.0, 1, 4);
+    assert!(matches!(
+        event.recv(&mut buf, Duration::from_millis(50)),
+        Err(Error::LibUsb(LibUsbError::Timeout))
+    ));
+
+    write_u32(eirm + eirm::EI_CONTROL.0, 1, 5);
+    for request_id in 0..2 {
+        write_u32(eirm + eirm::EVENT_TEST_CONTROL.0, 1, 6);
+        let len = event.recv(&mut buf, TIMEOUT).unwrap();
+        let packet = EventPacket::parse(&buf[..len]).unwrap();
+        assert_eq!(packet.request_id(), request_id);
+        assert_eq!(packet.event_id(), 0x4FFF);
+        assert_eq!(packet.timestamp(), timestamp);
+        assert!(packet.data().is_empty());
+    }
+
+    // Values other than 0 and 1 are rejected.
+    let scd = cmd::WriteMem::new(eirm + eirm::EI_CONTROL.0, &[2, 0, 0, 0]).unwrap();
+    let ack = transact(&ctrl, scd, 7);
+    assert_eq!(
+        AckPacket::parse(&ack).unwrap().status().kind(),
+        &StatusKind::GenCp(GenCpStatus::GenericError)
+    );
+
+    write_u32(eirm + eirm::EI_CONTROL.0, 0, 8);
+    write_u32(eirm + eirm::EVENT_TEST_CONTROL.0, 1, 9);
+    assert!(matches!(
+        event.recv(&mut buf, Duration::from_millis(50)),
+        Err(Error::LibUsb(LibUsbError::Timeout))
+    ));
+}
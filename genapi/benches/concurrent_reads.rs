@@ -0,0 +1,217 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Compares the duration of concurrent reads on a context serialized on a single lock and on a
+//! context made of [`SharedValueStore`] and [`ShardedCacheStore`].
+//!
+//! Run with `cargo bench -p cameleon-genapi --bench concurrent_reads`.
+
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use cameleon_genapi::{
+    builder::GenApiBuilder,
+    interface::IInteger,
+    store::{
+        CacheStore, DefaultCacheStore, DefaultNodeStore, DefaultValueStore, NodeId, NodeStore,
+        ShardedCacheStore, SharedValueStore,
+    },
+    Device, ValueCtxt,
+};
+
+const NODES: usize = 1000;
+const READERS: usize = 8;
+const PASSES: usize = 3;
+/// Every `INVALIDATION_STRIDE`th node is invalidated.
+const INVALIDATION_STRIDE: usize = 10;
+/// Latency of each read from the device.
+const DEVICE_LATENCY: Duration = Duration::from_micros(200);
+
+/// Device whose registers are counters incremented on each invalidation.
+#[derive(Clone)]
+struct Counters(Arc<Vec<AtomicU64>>);
+
+impl Device for Counters {
+    fn read_mem(&mut self, address: i64, buf: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+        thread::sleep(DEVICE_LATENCY);
+        let value = self.0[address as usize / 4].load(Ordering::Acquire);
+        #[allow(clippy::cast_possible_truncation)]
+        buf.copy_from_slice(&(value as u32).to_le_bytes());
+        Ok(())
+    }
+
+    fn write_mem(&mut self, _: i64, _: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        Err("read only device".into())
+    }
+}
+
+trait SharedCtxt: Sync {
+    fn read(&self, nid: NodeId, device: &mut Counters) -> i64;
+
+    fn invalidate(&self, nid: NodeId);
+}
+
+/// All accesses are serialized on a single lock.
+struct SingleLock {
+    node_store: DefaultNodeStore,
+    value_ctxt: Mutex<ValueCtxt<DefaultValueStore, DefaultCacheStore>>,
+}
+
+impl SharedCtxt for SingleLock {
+    fn read(&self, nid: NodeId, device: &mut Counters) -> i64 {
+        let mut cx = self.value_ctxt.lock().unwrap();
+        let node = nid.expect_iinteger_kind(&self.node_store).unwrap();
+        node.value(device, &self.node_store, &mut *cx).unwrap()
+    }
+
+    fn invalidate(&self, nid: NodeId) {
+        self.value_ctxt.lock().unwrap().invalidate_cache_of(nid);
+    }
+}
+
+struct Sharded {
+    node_store: DefaultNodeStore,
+    value_store: SharedValueStore,
+    cache_store: ShardedCacheStore,
+}
+
+impl SharedCtxt for Sharded {
+    fn read(&self, nid: NodeId, device: &mut Counters) -> i64 {
+        let mut cx = ValueCtxt::new(self.value_store.snapshot(), self.cache_store.handle());
+        let node = nid.expect_iinteger_kind(&self.node_store).unwrap();
+        node.value(device, &self.node_store, &mut cx).unwrap()
+    }
+
+    fn invalidate(&self, nid: NodeId) {
+        self.cache_store.handle().invalidate_of(nid);
+    }
+}
+
+fn build() -> (
+    DefaultNodeStore,
+    ValueCtxt<DefaultValueStore, DefaultCacheStore>,
+) {
+    let mut xml = String::from(
+        r#"<RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="0"
+          SubMinorVersion="0"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210">
+        <Port Name="Device"></Port>"#,
+    );
+    for i in 0..NODES {
+        write!(
+            xml,
+            r#"<IntReg Name="Reg{}">
+              <Address>{}</Address>
+              <Length>4</Length>
+              <pPort>Device</pPort>
+              <Sign>Unsigned</Sign>
+              <Endianess>LittleEndian</Endianess>
+            </IntReg>"#,
+            i,
+            i * 4
+        )
+        .unwrap();
+    }
+    xml.push_str("</RegisterDescription>");
+
+    let (_, node_store, value_ctxt) = GenApiBuilder::default().build(&xml).unwrap();
+    (node_store, value_ctxt)
+}
+
+/// Reads all nodes from `READERS` threads while another thread invalidates every
+/// `INVALIDATION_STRIDE`th node, then returns the duration the readers take.
+///
+/// Panics if a reader observes a value older than the value at the last invalidation.
+fn stress(ctxt: &impl SharedCtxt, nids: &[NodeId]) -> Duration {
+    let device = Counters(Arc::new((0..NODES).map(|_| AtomicU64::new(0)).collect()));
+    // Values of the device at the last invalidation of each node.
+    let invalidated: Vec<_> = (0..NODES).map(|_| AtomicU64::new(0)).collect();
+    let done = AtomicBool::new(false);
+
+    // Warm up the cache.
+    for nid in nids {
+        ctxt.read(*nid, &mut device.clone());
+    }
+
+    let start = Instant::now();
+    thread::scope(|s| {
+        s.spawn(|| {
+            while !done.load(Ordering::Acquire) {
+                for i in (0..NODES).step_by(INVALIDATION_STRIDE) {
+                    let value = device.0[i].fetch_add(1, Ordering::AcqRel) + 1;
+                    ctxt.invalidate(nids[i]);
+                    invalidated[i].store(value, Ordering::Release);
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        let readers: Vec<_> = (0..READERS)
+            .map(|_| {
+                s.spawn(|| {
+                    let mut device = device.clone();
+                    for _ in 0..PASSES {
+                        for (i, nid) in nids.iter().enumerate() {
+                            let expected = invalidated[i].load(Ordering::Acquire);
+                            let value = ctxt.read(*nid, &mut device);
+                            assert!(
+                                value as u64 >= expected,
+                                "stale read of `Reg{}`: {} < {}",
+                                i,
+                                value,
+                                expected
+                            );
+                        }
+                    }
+                })
+            })
+            .collect();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        done.store(true, Ordering::Release);
+    });
+
+    start.elapsed()
+}
+
+fn main() {
+    let (node_store, value_ctxt) = build();
+    let nids: Vec<_> = (0..NODES)
+        .map(|i| node_store.id_by_name(format!("Reg{}", i)).unwrap())
+        .collect();
+
+    let single_lock = SingleLock {
+        node_store,
+        value_ctxt: Mutex::new(value_ctxt),
+    };
+    let single_lock_elapsed = stress(&single_lock, &nids);
+
+    let (node_store, value_ctxt) = build();
+    let sharded = Sharded {
+        node_store,
+        value_store: value_ctxt.value_store.into(),
+        cache_store: value_ctxt.cache_store.into(),
+    };
+    let sharded_elapsed = stress(&sharded, &nids);
+
+    println!("single lock: {:?}", single_lock_elapsed);
+    println!("sharded:     {:?}", sharded_elapsed);
+}
@@ -46,7 +46,7 @@ impl BooleanNode {
 }
 
 impl INode for BooleanNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
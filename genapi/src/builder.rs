@@ -15,6 +15,7 @@ pub struct GenApiBuilder<T = DefaultNodeStore, U = DefaultValueStore, S = Defaul
     node_store: T,
     value_store: U,
     cache_store: S,
+    parser_config: parser::ParserConfig,
 }
 
 impl Default for GenApiBuilder {
@@ -23,21 +24,44 @@ impl Default for GenApiBuilder {
             node_store: DefaultNodeStore::default(),
             value_store: DefaultValueStore::default(),
             cache_store: DefaultCacheStore::default(),
+            parser_config: parser::ParserConfig::default(),
         }
     }
 }
 
 pub type BuildResult<T, U, S> = parser::ParseResult<(RegisterDescription, T, ValueCtxt<U, S>)>;
 
+pub type BuildWithReportResult<T, U, S> = parser::ParseResult<(
+    RegisterDescription,
+    T,
+    ValueCtxt<U, S>,
+    parser::CompatibilityReport,
+)>;
+
 impl<T, U, S> GenApiBuilder<T, U, S> {
-    pub fn build(mut self, xml: &impl AsRef<str>) -> BuildResult<T::Store, U::Store, S::Store>
+    pub fn build(self, xml: &impl AsRef<str>) -> BuildResult<T::Store, U::Store, S::Store>
+    where
+        T: NodeStoreBuilder,
+        U: ValueStoreBuilder,
+        S: CacheStoreBuilder,
+    {
+        let (reg_desc, node_store, value_ctxt, _) = self.build_with_report(xml)?;
+        Ok((reg_desc, node_store, value_ctxt))
+    }
+
+    /// Same as [`Self::build`], but also returns [`parser::CompatibilityReport`] of the XML.
+    pub fn build_with_report(
+        mut self,
+        xml: &impl AsRef<str>,
+    ) -> BuildWithReportResult<T::Store, U::Store, S::Store>
     where
         T: NodeStoreBuilder,
         U: ValueStoreBuilder,
         S: CacheStoreBuilder,
     {
-        let reg_desc = parser::parse(
+        let (reg_desc, report) = parser::parse_with_config(
             xml,
+            &self.parser_config,
             &mut self.node_store,
             &mut self.value_store,
             &mut self.cache_store,
@@ -47,6 +71,7 @@ impl<T, U, S> GenApiBuilder<T, U, S> {
             reg_desc,
             self.node_store.build(),
             ValueCtxt::new(self.value_store.build(), self.cache_store.build()),
+            report,
         ))
     }
 
@@ -55,6 +80,7 @@ impl<T, U, S> GenApiBuilder<T, U, S> {
             node_store: self.node_store,
             value_store: self.value_store,
             cache_store: CacheSink::default(),
+            parser_config: self.parser_config,
         }
     }
 
@@ -63,6 +89,7 @@ impl<T, U, S> GenApiBuilder<T, U, S> {
             node_store,
             value_store: self.value_store,
             cache_store: self.cache_store,
+            parser_config: self.parser_config,
         }
     }
 
@@ -71,6 +98,7 @@ impl<T, U, S> GenApiBuilder<T, U, S> {
             node_store: self.node_store,
             value_store,
             cache_store: self.cache_store,
+            parser_config: self.parser_config,
         }
     }
 
@@ -79,8 +107,14 @@ impl<T, U, S> GenApiBuilder<T, U, S> {
             node_store: self.node_store,
             value_store: self.value_store,
             cache_store,
+            parser_config: self.parser_config,
         }
     }
+
+    pub fn with_parser_config(mut self, parser_config: parser::ParserConfig) -> Self {
+        self.parser_config = parser_config;
+        self
+    }
 }
 
 pub trait NodeStoreBuilder {
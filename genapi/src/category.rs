@@ -24,7 +24,7 @@ impl CategoryNode {
 }
 
 impl INode for CategoryNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
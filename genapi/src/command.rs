@@ -39,7 +39,7 @@ impl CommandNode {
 }
 
 impl INode for CommandNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
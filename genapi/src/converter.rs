@@ -94,7 +94,7 @@ impl ConverterNode {
 }
 
 impl INode for ConverterNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
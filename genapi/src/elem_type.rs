@@ -12,23 +12,26 @@ use super::{
     Device, GenApiResult, ValueCtxt,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum NameSpace {
     Standard,
+    #[default]
     Custom,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Visibility {
+    #[default]
     Beginner,
     Expert,
     Guru,
     Invisible,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum MergePriority {
     High,
+    #[default]
     Mid,
     Low,
 }
@@ -62,11 +65,12 @@ impl<T> ImmOrPNode<T> {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum IntegerRepresentation {
     Linear,
     Logarithmic,
     Boolean,
+    #[default]
     PureNumber,
     HexNumber,
     IpV4Address,
@@ -98,23 +102,26 @@ impl IntegerRepresentation {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum FloatRepresentation {
     Linear,
     Logarithmic,
+    #[default]
     PureNumber,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Slope {
     Increasing,
     Decreasing,
     Varying,
+    #[default]
     Automatic,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum DisplayNotation {
+    #[default]
     Automatic,
     Fixed,
     Scientific,
@@ -129,9 +136,10 @@ pub enum StandardNameSpace {
     USB,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum CachingMode {
     /// Allow to caching on read/write.
+    #[default]
     WriteThrough,
     /// Allow to caching on read.
     WriteAround,
@@ -322,15 +330,17 @@ impl RegPIndex {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Endianness {
+    #[default]
     LE,
     BE,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum Sign {
     Signed,
+    #[default]
     Unsigned,
 }
 
@@ -3,7 +3,7 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 use super::{
-    elem_type::ImmOrPNode,
+    elem_type::{ImmOrPNode, Visibility},
     interface::{IEnumeration, INode, ISelector},
     ivalue::IValue,
     node_base::{NodeAttributeBase, NodeBase, NodeElementBase},
@@ -46,7 +46,7 @@ impl EnumerationNode {
 }
 
 impl INode for EnumerationNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
@@ -197,7 +197,7 @@ impl EnumEntryNode {
     #[must_use]
     #[allow(clippy::cast_precision_loss)]
     pub fn numeric_value(&self) -> f64 {
-        self.numeric_value.unwrap_or_else(|| self.value as f64)
+        self.numeric_value.unwrap_or(self.value as f64)
     }
 
     #[must_use]
@@ -214,6 +214,26 @@ impl EnumEntryNode {
         self.is_self_clearing
     }
 
+    #[must_use]
+    pub fn display_name(&self) -> Option<&str> {
+        self.elem_base.display_name.as_deref()
+    }
+
+    #[must_use]
+    pub fn description(&self) -> Option<&str> {
+        self.elem_base.description.as_deref()
+    }
+
+    #[must_use]
+    pub fn tooltip(&self) -> Option<&str> {
+        self.elem_base.tooltip.as_deref()
+    }
+
+    #[must_use]
+    pub fn visibility(&self) -> Visibility {
+        self.elem_base.visibility
+    }
+
     pub fn is_locked<T: ValueStore, U: CacheStore>(
         &self,
         device: &mut impl Device,
@@ -262,7 +262,7 @@ impl Expr {
     {
         use std::ops::Neg;
 
-        let res = self.eval(&var_env)?;
+        let res = self.eval(var_env)?;
         macro_rules! apply_op {
             ($f:ident) => {
                 match res {
@@ -340,9 +340,30 @@ pub enum UnOpKind {
     Round,
 }
 
-#[must_use]
+/// A specialized `Result` type for formula parsing.
+pub type FormulaResult<T> = std::result::Result<T, FormulaError>;
+
+/// An error type returned when a formula is malformed.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum FormulaError {
+    /// The formula contains a character which isn't a part of any token.
+    #[error("unexpected character `{0}`")]
+    UnexpectedChar(char),
+
+    /// A numeric literal can't be converted, e.g. it overflows.
+    #[error("invalid number `{0}`")]
+    InvalidNumber(String),
+
+    /// A token or the end of the formula appears where it isn't allowed.
+    #[error("expected {expected}, but found {found}")]
+    UnexpectedToken {
+        expected: &'static str,
+        found: String,
+    },
+}
+
 #[tracing::instrument(level = "trace")]
-pub fn parse(s: &str) -> Expr {
+pub fn parse(s: &str) -> FormulaResult<Expr> {
     debug!("start parsing expression in `formula`");
     let lexer = Lexer::new(s);
     Parser { lexer }.expr()
@@ -355,12 +376,12 @@ struct Parser<'a> {
 macro_rules! parse_binop {
     ($self:ident.$f:ident, ($token:expr, $op:expr) $(,($token_rep:expr, $op_rep:expr))*) => {
         {
-        let mut expr = $self.$f();
+        let mut expr = $self.$f()?;
         loop {
-            let (op_kind, rhs) = if $self.eat(&$token) {
-                ($op, $self.$f())
-            } $(else if $self.eat(&$token_rep) {
-                ($op_rep, $self.$f())
+            let (op_kind, rhs) = if $self.eat(&$token)? {
+                ($op, $self.$f()?)
+            } $(else if $self.eat(&$token_rep)? {
+                ($op_rep, $self.$f()?)
             })* else {
                 break;
             };
@@ -370,49 +391,49 @@ macro_rules! parse_binop {
                 rhs: rhs.into(),
             };
         }
-        expr
+        Ok(expr)
         }
     }
 }
 
 impl<'a> Parser<'a> {
-    fn expr(&mut self) -> Expr {
-        let expr = self.logical_or();
-        if self.eat(&Token::Question) {
-            let then = self.expr();
-            self.expect(&Token::Colon);
-            let else_ = self.expr();
-            Expr::If {
+    fn expr(&mut self) -> FormulaResult<Expr> {
+        let expr = self.logical_or()?;
+        if self.eat(&Token::Question)? {
+            let then = self.expr()?;
+            self.expect(&Token::Colon, "`:`")?;
+            let else_ = self.expr()?;
+            Ok(Expr::If {
                 cond: expr.into(),
                 then: then.into(),
                 else_: else_.into(),
-            }
+            })
         } else {
-            expr
+            Ok(expr)
         }
     }
 
-    fn logical_or(&mut self) -> Expr {
+    fn logical_or(&mut self) -> FormulaResult<Expr> {
         parse_binop!(self.logical_and, (Token::DoubleOr, BinOpKind::Or))
     }
 
-    fn logical_and(&mut self) -> Expr {
+    fn logical_and(&mut self) -> FormulaResult<Expr> {
         parse_binop!(self.bitwise_or, (Token::DoubleAnd, BinOpKind::And))
     }
 
-    fn bitwise_or(&mut self) -> Expr {
+    fn bitwise_or(&mut self) -> FormulaResult<Expr> {
         parse_binop!(self.bitwise_xor, (Token::Or, BinOpKind::BitOr))
     }
 
-    fn bitwise_xor(&mut self) -> Expr {
+    fn bitwise_xor(&mut self) -> FormulaResult<Expr> {
         parse_binop!(self.bitwise_and, (Token::Caret, BinOpKind::Xor))
     }
 
-    fn bitwise_and(&mut self) -> Expr {
+    fn bitwise_and(&mut self) -> FormulaResult<Expr> {
         parse_binop!(self.eq, (Token::And, BinOpKind::BitAnd))
     }
 
-    fn eq(&mut self) -> Expr {
+    fn eq(&mut self) -> FormulaResult<Expr> {
         parse_binop!(
             self.rel,
             (Token::Eq, BinOpKind::Eq),
@@ -420,7 +441,7 @@ impl<'a> Parser<'a> {
         )
     }
 
-    fn rel(&mut self) -> Expr {
+    fn rel(&mut self) -> FormulaResult<Expr> {
         parse_binop!(
             self.bit_shift,
             (Token::Lt, BinOpKind::Lt),
@@ -430,7 +451,7 @@ impl<'a> Parser<'a> {
         )
     }
 
-    fn bit_shift(&mut self) -> Expr {
+    fn bit_shift(&mut self) -> FormulaResult<Expr> {
         parse_binop!(
             self.term,
             (Token::Shl, BinOpKind::Shl),
@@ -438,7 +459,7 @@ impl<'a> Parser<'a> {
         )
     }
 
-    fn term(&mut self) -> Expr {
+    fn term(&mut self) -> FormulaResult<Expr> {
         parse_binop!(
             self.factor,
             (Token::Plus, BinOpKind::Add),
@@ -446,7 +467,7 @@ impl<'a> Parser<'a> {
         )
     }
 
-    fn factor(&mut self) -> Expr {
+    fn factor(&mut self) -> FormulaResult<Expr> {
         parse_binop!(
             self.unop,
             (Token::Star, BinOpKind::Mul),
@@ -455,85 +476,86 @@ impl<'a> Parser<'a> {
         )
     }
 
-    fn unop(&mut self) -> Expr {
-        if self.eat(&Token::Tilde) {
-            let expr = self.unop();
-            Expr::UnOp {
+    fn unop(&mut self) -> FormulaResult<Expr> {
+        if self.eat(&Token::Tilde)? {
+            let expr = self.unop()?;
+            Ok(Expr::UnOp {
                 kind: UnOpKind::Not,
                 expr: expr.into(),
-            }
-        } else if self.eat(&Token::Minus) {
-            let expr = self.unop();
-            Expr::UnOp {
+            })
+        } else if self.eat(&Token::Minus)? {
+            let expr = self.unop()?;
+            Ok(Expr::UnOp {
                 kind: UnOpKind::Neg,
                 expr: expr.into(),
-            }
+            })
         } else {
             // Eat unary `+` if exists.
-            self.eat(&Token::Plus);
+            self.eat(&Token::Plus)?;
             self.pow()
         }
     }
 
-    fn pow(&mut self) -> Expr {
-        let expr = self.call();
-        if self.eat(&Token::DoubleStar) {
-            let rhs = self.unop();
-            Expr::BinOp {
+    fn pow(&mut self) -> FormulaResult<Expr> {
+        let expr = self.call()?;
+        if self.eat(&Token::DoubleStar)? {
+            let rhs = self.unop()?;
+            Ok(Expr::BinOp {
                 kind: BinOpKind::Pow,
                 lhs: expr.into(),
                 rhs: rhs.into(),
-            }
+            })
         } else {
-            expr
+            Ok(expr)
         }
     }
 
-    fn call(&mut self) -> Expr {
-        if let Some(op_kind) = self.next_call() {
-            self.expect(&Token::LParen);
-            let expr = self.expr();
-            self.expect(&Token::RParen);
-            Expr::UnOp {
+    fn call(&mut self) -> FormulaResult<Expr> {
+        if let Some(op_kind) = self.next_call()? {
+            self.expect(&Token::LParen, "`(`")?;
+            let expr = self.expr()?;
+            self.expect(&Token::RParen, "`)`")?;
+            Ok(Expr::UnOp {
                 kind: op_kind,
                 expr: expr.into(),
-            }
+            })
         } else {
             self.primary()
         }
     }
 
-    fn primary(&mut self) -> Expr {
-        if self.eat(&Token::LParen) {
-            let expr = self.expr();
-            self.expect(&Token::RParen);
-            expr
-        } else if let Some(i) = self.next_integer() {
-            Expr::Integer(i)
-        } else if let Some(f) = self.next_float() {
-            Expr::Float(f)
+    fn primary(&mut self) -> FormulaResult<Expr> {
+        if self.eat(&Token::LParen)? {
+            let expr = self.expr()?;
+            self.expect(&Token::RParen, "`)`")?;
+            Ok(expr)
+        } else if let Some(i) = self.next_integer()? {
+            Ok(Expr::Integer(i))
+        } else if let Some(f) = self.next_float()? {
+            Ok(Expr::Float(f))
+        } else if let Some(s) = self.next_ident()? {
+            Ok(Expr::Ident(s))
         } else {
-            let s = self.next_ident().unwrap();
-            Expr::Ident(s)
+            Err(self.unexpected("an operand")?)
         }
     }
 
-    fn eat(&mut self, tok: &Token) -> bool {
-        match self.lexer.peek() {
+    fn eat(&mut self, tok: &Token) -> FormulaResult<bool> {
+        match self.lexer.peek()? {
             Some(peek) if peek == tok => {
-                self.lexer.next();
-                true
+                self.lexer.next()?;
+                Ok(true)
             }
-            _ => false,
+            _ => Ok(false),
         }
     }
 
-    fn next_call(&mut self) -> Option<UnOpKind> {
-        let s = match self.lexer.peek() {
+    fn next_call(&mut self) -> FormulaResult<Option<UnOpKind>> {
+        let s = match self.lexer.peek()? {
             Some(Token::Ident(s)) => s,
-            _ => return None,
+            _ => return Ok(None),
         };
-        let op = Some(match s.as_str() {
+        let op = match s.as_str() {
             "NEG" => UnOpKind::Neg,
             "SIN" => UnOpKind::Sin,
             "COS" => UnOpKind::Cos,
@@ -550,51 +572,61 @@ impl<'a> Parser<'a> {
             "FLOOR" => UnOpKind::Floor,
             "CEIL" => UnOpKind::Ceil,
             "ROUND" => UnOpKind::Round,
-            _ => return None,
-        });
+            _ => return Ok(None),
+        };
 
-        self.lexer.next();
-        op
+        self.lexer.next()?;
+        Ok(Some(op))
     }
 
-    fn next_integer(&mut self) -> Option<i64> {
-        if let Some(&Token::Integer(i)) = self.lexer.peek() {
-            self.lexer.next();
-            Some(i)
+    fn next_integer(&mut self) -> FormulaResult<Option<i64>> {
+        if let Some(&Token::Integer(i)) = self.lexer.peek()? {
+            self.lexer.next()?;
+            Ok(Some(i))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn next_float(&mut self) -> Option<f64> {
-        if let Some(&Token::Float(f)) = self.lexer.peek() {
-            self.lexer.next();
-            Some(f)
-        } else if let Some(Token::Ident(s)) = self.lexer.peek() {
-            let f = match s.as_str() {
+    fn next_float(&mut self) -> FormulaResult<Option<f64>> {
+        let f = match self.lexer.peek()? {
+            Some(&Token::Float(f)) => f,
+            Some(Token::Ident(s)) => match s.as_str() {
                 "PI" => std::f64::consts::PI,
                 "E" => std::f64::consts::E,
-                _ => return None,
-            };
-            self.lexer.next();
-            Some(f)
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        self.lexer.next()?;
+        Ok(Some(f))
+    }
+
+    fn next_ident(&mut self) -> FormulaResult<Option<String>> {
+        if let Some(Token::Ident(s)) = self.lexer.peek()? {
+            let s = s.to_string();
+            self.lexer.next()?;
+            Ok(Some(s))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn next_ident(&mut self) -> Option<String> {
-        if let Some(Token::Ident(s)) = self.lexer.peek() {
-            let s = s.to_string();
-            self.lexer.next();
-            Some(s)
+    fn expect(&mut self, tok: &Token, expected: &'static str) -> FormulaResult<()> {
+        if self.eat(tok)? {
+            Ok(())
         } else {
-            None
+            Err(self.unexpected(expected)?)
         }
     }
 
-    fn expect(&mut self, tok: &Token) {
-        assert!(self.eat(tok))
+    /// Returns an error which tells that `expected` is expected instead of the next token.
+    fn unexpected(&mut self, expected: &'static str) -> FormulaResult<FormulaError> {
+        let found = match self.lexer.peek()? {
+            Some(tok) => format!("{:?}", tok),
+            None => "the end of the formula".into(),
+        };
+        Ok(FormulaError::UnexpectedToken { expected, found })
     }
 }
 
@@ -647,19 +679,23 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn next(&mut self) -> Option<Token> {
-        self.peek();
-        self.peek.take()
+    fn next(&mut self) -> FormulaResult<Option<Token>> {
+        self.peek()?;
+        Ok(self.peek.take())
     }
 
-    fn peek(&mut self) -> Option<&Token> {
-        if let Some(ref peek) = self.peek {
-            return Some(peek);
+    fn peek(&mut self) -> FormulaResult<Option<&Token>> {
+        if self.peek.is_some() {
+            return Ok(self.peek.as_ref());
         }
 
         while self.eat_char(|c| c.is_whitespace() || c.is_ascii_control()) {}
 
-        self.peek = Some(match self.next_char()? {
+        let c = match self.next_char() {
+            Some(c) => c,
+            None => return Ok(None),
+        };
+        self.peek = Some(match c {
             '(' => Token::LParen,
             ')' => Token::RParen,
             '+' => Token::Plus,
@@ -716,15 +752,14 @@ impl<'a> Lexer<'a> {
                 let start_pos = self.cur - 1;
                 while self.eat_char(char::is_numeric) {}
                 let end_pos = self.cur;
-                let f = f64::from_str(self.sub_string(start_pos, end_pos)).unwrap();
-                Token::Float(f)
+                Token::Float(self.number(start_pos, end_pos, f64::from_str)?)
             }
 
             c if c.is_alphabetic() => {
                 let start_pos = self.cur - 1;
                 while self.eat_char(|c| c.is_alphanumeric() || c == '.' || c == '_') {}
                 let end_pos = self.cur;
-                Token::Ident(self.sub_string(start_pos, end_pos).into())
+                Token::Ident(self.sub_string(start_pos, end_pos)?.into())
             }
 
             c if c.is_numeric() => {
@@ -732,8 +767,7 @@ impl<'a> Lexer<'a> {
                     let start_pos = self.cur;
                     while self.eat_char(|c| c.is_ascii_hexdigit()) {}
                     let end_pos = self.cur;
-                    let i = i64::from_str_radix(self.sub_string(start_pos, end_pos), 16).unwrap();
-                    Token::Integer(i)
+                    Token::Integer(self.number(start_pos, end_pos, |s| i64::from_str_radix(s, 16))?)
                 } else {
                     let start_pos = self.cur - 1;
                     let mut is_integer = true;
@@ -746,19 +780,29 @@ impl<'a> Lexer<'a> {
                         }
                     }) {}
                     let end_pos = self.cur;
-                    let s = self.sub_string(start_pos, end_pos);
                     if is_integer {
-                        Token::Integer(i64::from_str(s).unwrap())
+                        Token::Integer(self.number(start_pos, end_pos, i64::from_str)?)
                     } else {
-                        Token::Float(f64::from_str(s).unwrap())
+                        Token::Float(self.number(start_pos, end_pos, f64::from_str)?)
                     }
                 }
             }
 
-            c => panic!("unexpected character `{}` in formula", c),
+            c => return Err(FormulaError::UnexpectedChar(c)),
         });
 
-        self.peek.as_ref()
+        Ok(self.peek.as_ref())
+    }
+
+    /// Converts the literal between `start_pos` and `end_pos` with `f`.
+    fn number<T, E>(
+        &self,
+        start_pos: usize,
+        end_pos: usize,
+        f: impl FnOnce(&str) -> Result<T, E>,
+    ) -> FormulaResult<T> {
+        let s = self.sub_string(start_pos, end_pos)?;
+        f(s).map_err(|_| FormulaError::InvalidNumber(s.into()))
     }
 
     fn next_char(&mut self) -> Option<char> {
@@ -818,11 +862,17 @@ impl<'a> Lexer<'a> {
     fn peek_char_raw(&self, c: char, n: usize) -> bool {
         self.src
             .get(self.cur + n)
-            .map_or(false, |next| c == *next as char)
+            .is_some_and(|next| c == *next as char)
     }
 
-    fn sub_string(&self, start_pos: usize, end_pos: usize) -> &str {
-        std::str::from_utf8(&self.src[start_pos..end_pos]).unwrap()
+    fn sub_string(&self, start_pos: usize, end_pos: usize) -> FormulaResult<&str> {
+        // Bytes are lexed as `char`s one by one, so a non ASCII character may be cut in the
+        // middle.
+        std::str::from_utf8(&self.src[start_pos..end_pos]).map_err(|e| {
+            let rest = String::from_utf8_lossy(&self.src[start_pos + e.valid_up_to()..]);
+            let c = rest.chars().next().unwrap_or(char::REPLACEMENT_CHARACTER);
+            FormulaError::UnexpectedChar(c)
+        })
     }
 }
 
@@ -832,44 +882,44 @@ mod tests {
 
     #[test]
     fn test_lexer() {
-        let t = Lexer::new("&amp;").next().unwrap();
+        let t = Lexer::new("&amp;").next().unwrap().unwrap();
         assert_eq!(Token::And, t);
 
-        let t = Lexer::new("&lt;").next().unwrap();
+        let t = Lexer::new("&lt;").next().unwrap().unwrap();
         assert_eq!(Token::Lt, t);
 
-        let t = Lexer::new("&gt;").next().unwrap();
+        let t = Lexer::new("&gt;").next().unwrap().unwrap();
         assert_eq!(Token::Gt, t);
 
-        let t = Lexer::new("Foo1.Max").next().unwrap();
+        let t = Lexer::new("Foo1.Max").next().unwrap().unwrap();
         assert_eq!(Token::Ident("Foo1.Max".into()), t);
 
-        let t = Lexer::new("0xa").next().unwrap();
+        let t = Lexer::new("0xa").next().unwrap().unwrap();
         assert_eq!(Token::Integer(0xa), t);
 
-        let t = Lexer::new("10").next().unwrap();
+        let t = Lexer::new("10").next().unwrap().unwrap();
         assert_eq!(Token::Integer(10), t);
 
-        let t = Lexer::new("0.1").next().unwrap();
+        let t = Lexer::new("0.1").next().unwrap().unwrap();
         assert!(matches!(t, Token::Float(_)));
 
-        let t = Lexer::new(".1").next().unwrap();
+        let t = Lexer::new(".1").next().unwrap().unwrap();
         assert!(matches!(t, Token::Float(_)));
 
-        let t = Lexer::new("  10 ").next().unwrap();
+        let t = Lexer::new("  10 ").next().unwrap().unwrap();
         assert_eq!(Token::Integer(10), t);
 
         let mut lexer = Lexer::new("&&||<>**>><<");
-        assert_eq!(Token::DoubleAnd, lexer.next().unwrap());
-        assert_eq!(Token::DoubleOr, lexer.next().unwrap());
-        assert_eq!(Token::Ne, lexer.next().unwrap());
-        assert_eq!(Token::DoubleStar, lexer.next().unwrap());
-        assert_eq!(Token::Shr, lexer.next().unwrap());
-        assert_eq!(Token::Shl, lexer.next().unwrap());
+        assert_eq!(Token::DoubleAnd, lexer.next().unwrap().unwrap());
+        assert_eq!(Token::DoubleOr, lexer.next().unwrap().unwrap());
+        assert_eq!(Token::Ne, lexer.next().unwrap().unwrap());
+        assert_eq!(Token::DoubleStar, lexer.next().unwrap().unwrap());
+        assert_eq!(Token::Shr, lexer.next().unwrap().unwrap());
+        assert_eq!(Token::Shl, lexer.next().unwrap().unwrap());
     }
 
     fn test_eval_impl(expr: &str, var_env: &HashMap<&str, Expr>) {
-        let expr = parse(expr);
+        let expr = parse(expr).unwrap();
         assert!(matches!(
             expr.eval(var_env).unwrap(),
             EvaluationResult::Integer(1)
@@ -914,4 +964,46 @@ mod tests {
         test_eval_impl("ABS(2 ** -1 ** 2 - 1. / 2.) < EPS", &env);
         test_eval_impl("ABS(VAR1 + 1 / 4 - 1.25) < EPS", &env);
     }
+
+    #[test]
+    fn test_parse_error() {
+        let err = |expr: &str| parse(expr).unwrap_err();
+
+        assert_eq!(err("1 $ 2"), FormulaError::UnexpectedChar('$'));
+        assert_eq!(err("Foo\u{e9}"), FormulaError::UnexpectedChar('\u{e9}'));
+        assert_eq!(err("0x"), FormulaError::InvalidNumber("".into()));
+        assert_eq!(
+            err("99999999999999999999"),
+            FormulaError::InvalidNumber("99999999999999999999".into())
+        );
+        assert_eq!(err("1.2.3"), FormulaError::InvalidNumber("1.2.3".into()));
+        assert!(matches!(
+            err("(1 + 2"),
+            FormulaError::UnexpectedToken {
+                expected: "`)`",
+                ..
+            }
+        ));
+        assert!(matches!(
+            err("SIN 1"),
+            FormulaError::UnexpectedToken {
+                expected: "`(`",
+                ..
+            }
+        ));
+        assert!(matches!(
+            err("VAR ? 1"),
+            FormulaError::UnexpectedToken {
+                expected: "`:`",
+                ..
+            }
+        ));
+        assert_eq!(
+            err("1 +"),
+            FormulaError::UnexpectedToken {
+                expected: "an operand",
+                found: "the end of the formula".into()
+            }
+        );
+    }
 }
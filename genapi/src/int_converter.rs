@@ -76,7 +76,7 @@ impl IntConverterNode {
 }
 
 impl INode for IntConverterNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
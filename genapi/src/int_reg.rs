@@ -56,7 +56,7 @@ impl IntRegNode {
 }
 
 impl INode for IntRegNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         let elem_base = &self.register_base.elem_base;
         NodeBase::new(&self.attr_base, elem_base)
     }
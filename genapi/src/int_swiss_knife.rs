@@ -58,7 +58,7 @@ impl IntSwissKnifeNode {
 }
 
 impl INode for IntSwissKnifeNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
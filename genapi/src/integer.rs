@@ -64,7 +64,7 @@ impl IntegerNode {
 }
 
 impl INode for IntegerNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
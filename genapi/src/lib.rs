@@ -69,6 +69,7 @@ pub use swiss_knife::SwissKnifeNode;
 use std::borrow::Cow;
 
 use auto_impl::auto_impl;
+use cameleon_impl::error_code::ErrorCode;
 use tracing::error;
 
 pub mod prelude {
@@ -83,6 +84,16 @@ pub trait Device {
     fn read_mem(&mut self, address: i64, buf: &mut [u8]) -> Result<(), Box<dyn std::error::Error>>;
 
     fn write_mem(&mut self, address: i64, data: &[u8]) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Returns the data of the chunk whose id is `chunk_id`, which is read through ports having
+    /// `ChunkID`.
+    ///
+    /// Returns `None` by default, reading such ports fails with
+    /// [`GenApiError::ChunkDataMissing`] then.
+    fn chunk_data(&self, chunk_id: u64) -> Option<&[u8]> {
+        let _ = chunk_id;
+        None
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -114,6 +125,19 @@ pub enum GenApiError {
 }
 
 impl GenApiError {
+    /// Returns the stable code of the error.
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Device(..) => ErrorCode::DEVICE_ACCESS,
+            Self::NotWritable => ErrorCode::NOT_WRITABLE,
+            Self::InvalidNode(..) => ErrorCode::INVALID_NODE,
+            Self::InvalidData(..) => ErrorCode::INVALID_VALUE,
+            Self::ChunkDataMissing => ErrorCode::CHUNK_DATA_MISSING,
+            Self::InvalidBuffer(..) => ErrorCode::INVALID_BUFFER,
+        }
+    }
+
     fn device(inner: Box<dyn std::error::Error>) -> Self {
         let err = GenApiError::Device(inner);
         error!("{}", err);
@@ -194,7 +218,12 @@ impl<T, U> ValueCtxt<T, U> {
         self.cache_store.cache(nid, address, length, value);
     }
 
-    pub fn get_cache(&self, nid: store::NodeId, address: i64, length: i64) -> Option<&[u8]>
+    pub fn get_cache(
+        &self,
+        nid: store::NodeId,
+        address: i64,
+        length: i64,
+    ) -> Option<store::CachedData<'_>>
     where
         U: store::CacheStore,
     {
@@ -222,3 +251,24 @@ impl<T, U> ValueCtxt<T, U> {
         self.cache_store.clear()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Codes are part of the public API, never change the table below.
+    #[test]
+    fn test_error_code() {
+        let table = [
+            (GenApiError::Device("".into()), 0x0001_0008),
+            (GenApiError::NotWritable, 0x0003_0004),
+            (GenApiError::InvalidNode("".into()), 0x0003_0005),
+            (GenApiError::InvalidData("".into()), 0x0003_0006),
+            (GenApiError::ChunkDataMissing, 0x0003_0007),
+            (GenApiError::InvalidBuffer("".into()), 0x0003_0008),
+        ];
+        for (err, code) in &table {
+            assert_eq!(err.code().get(), *code, "{:?}", err);
+        }
+    }
+}
@@ -14,7 +14,7 @@ pub struct Node {
 }
 
 impl INode for Node {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
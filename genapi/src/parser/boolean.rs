@@ -12,7 +12,7 @@ use crate::{
 
 use super::{
     elem_name::{BOOLEAN, OFF_VALUE, ON_VALUE, P_SELECTED, STREAMABLE},
-    xml, Parse,
+    xml, Parse, ParseResult,
 };
 
 impl Parse for BooleanNode {
@@ -22,24 +22,25 @@ impl Parse for BooleanNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `BooleanNode`");
         debug_assert_eq!(node.tag_name(), BOOLEAN);
 
-        let attr_base = node.parse(node_builder, value_builder, cache_builder);
-        let elem_base = node.parse(node_builder, value_builder, cache_builder);
+        let attr_base = node.parse(node_builder, value_builder, cache_builder)?;
+        let elem_base = node.parse(node_builder, value_builder, cache_builder)?;
 
         let streamable = node
-            .parse_if(STREAMABLE, node_builder, value_builder, cache_builder)
+            .parse_if(STREAMABLE, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
-        let value: ImmOrPNode<bool> = node.parse(node_builder, value_builder, cache_builder);
+        let value: ImmOrPNode<bool> = node.parse(node_builder, value_builder, cache_builder)?;
         let on_value: i64 = node
-            .parse_if(ON_VALUE, node_builder, value_builder, cache_builder)
+            .parse_if(ON_VALUE, node_builder, value_builder, cache_builder)?
             .unwrap_or(1);
         let off_value: i64 = node
-            .parse_if(OFF_VALUE, node_builder, value_builder, cache_builder)
+            .parse_if(OFF_VALUE, node_builder, value_builder, cache_builder)?
             .unwrap_or(0);
-        let p_selected = node.parse_while(P_SELECTED, node_builder, value_builder, cache_builder);
+        let p_selected =
+            node.parse_while(P_SELECTED, node_builder, value_builder, cache_builder)?;
 
         let value = match value {
             ImmOrPNode::Imm(imm) => {
@@ -50,7 +51,7 @@ impl Parse for BooleanNode {
             ImmOrPNode::PNode(pnode) => ImmOrPNode::PNode(pnode),
         };
 
-        Self {
+        Ok(Self {
             attr_base,
             elem_base,
             streamable,
@@ -58,7 +59,7 @@ impl Parse for BooleanNode {
             on_value,
             off_value,
             p_selected,
-        }
+        })
     }
 }
 
@@ -11,7 +11,7 @@ use crate::{
 
 use super::{
     elem_name::{COMMAND, POLLING_TIME},
-    xml, Parse,
+    xml, Parse, ParseResult,
 };
 
 impl Parse for CommandNode {
@@ -21,24 +21,25 @@ impl Parse for CommandNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `CommandNode`");
         debug_assert_eq!(node.tag_name(), COMMAND);
 
-        let attr_base = node.parse(node_builder, value_builder, cache_builder);
-        let elem_base = node.parse(node_builder, value_builder, cache_builder);
+        let attr_base = node.parse(node_builder, value_builder, cache_builder)?;
+        let elem_base = node.parse(node_builder, value_builder, cache_builder)?;
 
-        let value = node.parse(node_builder, value_builder, cache_builder);
-        let command_value = node.parse(node_builder, value_builder, cache_builder);
-        let polling_time = node.parse_if(POLLING_TIME, node_builder, value_builder, cache_builder);
+        let value = node.parse(node_builder, value_builder, cache_builder)?;
+        let command_value = node.parse(node_builder, value_builder, cache_builder)?;
+        let polling_time =
+            node.parse_if(POLLING_TIME, node_builder, value_builder, cache_builder)?;
 
-        Self {
+        Ok(Self {
             attr_base,
             elem_base,
             value,
             command_value,
             polling_time,
-        }
+        })
     }
 }
 
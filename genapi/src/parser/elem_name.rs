@@ -89,6 +89,18 @@ pub(super) const CHUNK_ID: &str = "ChunkID";
 pub(super) const P_CHUNK_ID: &str = "pChunkID";
 pub(super) const SWAP_ENDIANNESS: &str = "SwapEndianess"; // Schema typos "Endianness" to "Endianess".
 pub(super) const CACHE_CHUNK_DATA: &str = "CacheChunkData";
+pub(super) const LENGTH: &str = "Length";
+pub(super) const P_LENGTH: &str = "pLength";
+pub(super) const P_PORT: &str = "pPort";
+pub(super) const LSB: &str = "LSB";
+pub(super) const MSB: &str = "MSB";
+pub(super) const FORMULA: &str = "Formula";
+pub(super) const FORMULA_TO: &str = "FormulaTo";
+pub(super) const FORMULA_FROM: &str = "FormulaFrom";
+pub(super) const COMMAND_VALUE: &str = "CommandValue";
+pub(super) const P_COMMAND_VALUE: &str = "pCommandValue";
+pub(super) const VALUE_DEFAULT: &str = "ValueDefault";
+pub(super) const P_VALUE_DEFAULT: &str = "pValueDefault";
 
 pub(super) const NAME: &str = "Name";
 pub(super) const NAME_SPACE: &str = "NameSpace";
@@ -111,3 +123,133 @@ pub(super) const VERSION_GUID: &str = "VersionGuid";
 
 pub(super) const OFFSET: &str = "Offset";
 pub(super) const P_OFFSET: &str = "pOffset";
+
+pub(super) const SCHEMA_LOCATION: &str = "schemaLocation";
+
+/// Elements defined in the `GenApi` schema version 1.1.
+pub(super) const KNOWN_ELEMENTS: &[&str] = &[
+    NODE,
+    CATEGORY,
+    INTEGER,
+    INT_REG,
+    MASKED_INT_REG,
+    BOOLEAN,
+    COMMAND,
+    ENUMERATION,
+    ENUM_ENTRY,
+    FLOAT,
+    FLOAT_REG,
+    STRING,
+    STRING_REG,
+    REGISTER,
+    CONVERTER,
+    INT_CONVERTER,
+    SWISS_KNIFE,
+    INT_SWISS_KNIFE,
+    PORT,
+    CONF_ROM,
+    TEXT_DESC,
+    INT_KEY,
+    ADV_FEATURE_LOCK,
+    SMART_FEATURE,
+    STRUCT_REG,
+    STRUCT_ENTRY,
+    GROUP,
+    P_INVALIDATOR,
+    P_SELECTED,
+    P_FEATURE,
+    P_VARIABLE,
+    P_IS_IMPLEMENTED,
+    P_IS_AVAILABLE,
+    P_IS_LOCKED,
+    P_BLOCK_POLLING,
+    P_ERROR,
+    P_ALIAS,
+    P_CAST_ALIAS,
+    STREAMABLE,
+    POLLING_TIME,
+    ON_VALUE,
+    OFF_VALUE,
+    NUMERIC_VALUE,
+    SYMBOLIC,
+    IS_SELF_CLEARING,
+    MIN,
+    P_MIN,
+    MAX,
+    P_MAX,
+    INC,
+    P_INC,
+    CONSTANT,
+    EXPRESSION,
+    SIGN,
+    UNIT,
+    REPRESENTATION,
+    DISPLAY_NOTATION,
+    DISPLAY_PRECISION,
+    ENDIANNESS,
+    EXTENSION,
+    DESCRIPTION,
+    DISPLAY_NAME,
+    VISIBILITY,
+    DOCU_URL,
+    IS_DEPRECATED,
+    EVENT_ID,
+    IMPOSED_ACCESS_MODE,
+    ADDRESS,
+    P_ADDRESS,
+    INDEX,
+    P_INDEX,
+    ACCESS_MODE,
+    CACHEABLE,
+    VALUE,
+    P_VALUE,
+    P_VALUE_COPY,
+    VALUE_INDEXED,
+    P_VALUE_INDEXED,
+    BIT,
+    SLOPE,
+    IS_LINEAR,
+    CHUNK_ID,
+    P_CHUNK_ID,
+    SWAP_ENDIANNESS,
+    CACHE_CHUNK_DATA,
+    LENGTH,
+    P_LENGTH,
+    P_PORT,
+    LSB,
+    MSB,
+    FORMULA,
+    FORMULA_TO,
+    FORMULA_FROM,
+    COMMAND_VALUE,
+    P_COMMAND_VALUE,
+    VALUE_DEFAULT,
+    P_VALUE_DEFAULT,
+    TOOL_TIP,
+    REGISTER_DESCRIPTION,
+];
+
+/// Attributes defined in the `GenApi` schema version 1.1.
+pub(super) const KNOWN_ATTRIBUTES: &[&str] = &[
+    NAME,
+    NAME_SPACE,
+    MERGE_PRIORITY,
+    EXPOSE_STATIC,
+    COMMENT,
+    INDEX,
+    OFFSET,
+    P_OFFSET,
+    MODEL_NAME,
+    VENDOR_NAME,
+    TOOL_TIP,
+    STANDARD_NAME_SPCACE,
+    SCHEMA_MAJOR_VERSION,
+    SCHEMA_MINOR_VERSION,
+    SCHEMA_SUB_MINOR_VERSION,
+    MAJOR_VERSION,
+    MINOR_VERSION,
+    SUB_MINOR_VERSION,
+    PRODUCT_GUID,
+    VERSION_GUID,
+    SCHEMA_LOCATION,
+];
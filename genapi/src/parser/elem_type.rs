@@ -22,7 +22,7 @@ use super::{
         ADDRESS, BIT, INDEX, INT_SWISS_KNIFE, NAME, OFFSET, P_ADDRESS, P_INDEX, P_OFFSET, P_VALUE,
         P_VALUE_COPY, P_VALUE_INDEXED, VALUE, VALUE_INDEXED,
     },
-    xml, Parse,
+    xml, Parse, ParseResult,
 };
 
 macro_rules! match_text_view{
@@ -35,24 +35,16 @@ macro_rules! match_text_view{
         } $(else if $text == $s {
             $var
         })* else {
-            unreachable!()
+            return Err($text.invalid_value());
         }
     }
 }
 
-impl Default for NameSpace {
-    fn default() -> Self {
-        Self::Custom
-    }
-}
-
-impl From<&str> for NameSpace {
-    fn from(value: &str) -> Self {
-        match value {
-            "Standard" => Self::Standard,
-            "Custom" => Self::Custom,
-            _ => unreachable!(),
-        }
+pub(super) fn convert_to_name_space(value: &str) -> Option<NameSpace> {
+    match value {
+        "Standard" => Some(NameSpace::Standard),
+        "Custom" => Some(NameSpace::Custom),
+        _ => None,
     }
 }
 
@@ -62,18 +54,12 @@ impl Parse for NameSpace {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        match_text_view!(text,
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(match_text_view!(text,
             "Standard" => Self::Standard,
             "Custom" => Self::Custom,
-        )
-    }
-}
-
-impl Default for Visibility {
-    fn default() -> Self {
-        Self::Beginner
+        ))
     }
 }
 
@@ -83,31 +69,23 @@ impl Parse for Visibility {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        match_text_view!(text,
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(match_text_view!(text,
             "Beginner" => Self::Beginner,
             "Expert" => Self::Expert,
             "Guru" => Self::Guru,
             "Invisible" => Self::Invisible,
-        )
-    }
-}
-
-impl From<&str> for MergePriority {
-    fn from(value: &str) -> Self {
-        match value {
-            "1" => Self::High,
-            "0" => Self::Mid,
-            "-1" => Self::Low,
-            _ => unreachable!(),
-        }
+        ))
     }
 }
 
-impl Default for MergePriority {
-    fn default() -> Self {
-        Self::Mid
+pub(super) fn convert_to_merge_priority(value: &str) -> Option<MergePriority> {
+    match value {
+        "1" => Some(MergePriority::High),
+        "0" => Some(MergePriority::Mid),
+        "-1" => Some(MergePriority::Low),
+        _ => None,
     }
 }
 
@@ -117,13 +95,13 @@ impl Parse for MergePriority {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        match_text_view!(text,
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(match_text_view!(text,
             "1" => Self::High,
             "0" => Self::Mid,
             "-1" => Self::Low,
-        )
+        ))
     }
 }
 
@@ -133,13 +111,13 @@ impl Parse for AccessMode {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        match_text_view!(text,
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(match_text_view!(text,
             "RO" => Self::RO,
             "WO" => Self::WO,
             "RW" => Self::RW,
-        )
+        ))
     }
 }
 
@@ -149,12 +127,19 @@ impl Parse for ImmOrPNode<i64> {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let peeked_text = node.peek().unwrap().text();
-        if peeked_text.view().chars().next().unwrap().is_alphabetic() {
-            Self::PNode(node.parse(node_builder, value_builder, cache_builder))
+    ) -> ParseResult<Self> {
+        let peeked_text = node.expect_peek()?.text();
+        if peeked_text
+            .view()
+            .chars()
+            .next()
+            .is_some_and(char::is_alphabetic)
+        {
+            node.parse(node_builder, value_builder, cache_builder)
+                .map(Self::PNode)
         } else {
-            Self::Imm(node.parse(node_builder, value_builder, cache_builder))
+            node.parse(node_builder, value_builder, cache_builder)
+                .map(Self::Imm)
         }
     }
 }
@@ -165,17 +150,23 @@ impl Parse for ImmOrPNode<f64> {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let peeked_text = node.peek().unwrap().text();
+    ) -> ParseResult<Self> {
+        let peeked_text = node.expect_peek()?.text();
 
         if peeked_text == "INF"
             || peeked_text == "-INF"
             || peeked_text == "NaN"
-            || !peeked_text.view().chars().next().unwrap().is_alphabetic()
+            || !peeked_text
+                .view()
+                .chars()
+                .next()
+                .is_some_and(char::is_alphabetic)
         {
-            Self::Imm(node.parse(node_builder, value_builder, cache_builder))
+            node.parse(node_builder, value_builder, cache_builder)
+                .map(Self::Imm)
         } else {
-            Self::PNode(node.parse(node_builder, value_builder, cache_builder))
+            node.parse(node_builder, value_builder, cache_builder)
+                .map(Self::PNode)
         }
     }
 }
@@ -186,11 +177,13 @@ impl Parse for ImmOrPNode<bool> {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        if convert_to_bool_opt(&node.peek().unwrap().text().view()).is_some() {
-            Self::Imm(node.parse(node_builder, value_builder, cache_builder))
+    ) -> ParseResult<Self> {
+        if convert_to_bool(&node.expect_peek()?.text().view()).is_some() {
+            node.parse(node_builder, value_builder, cache_builder)
+                .map(Self::Imm)
         } else {
-            Self::PNode(node.parse(node_builder, value_builder, cache_builder))
+            node.parse(node_builder, value_builder, cache_builder)
+                .map(Self::PNode)
         }
     }
 }
@@ -203,15 +196,15 @@ macro_rules! impl_parse_for_imm_or_pnode_id {
                 node_builder: &mut impl NodeStoreBuilder,
                 value_builder: &mut impl ValueStoreBuilder,
                 cache_builder: &mut impl CacheStoreBuilder,
-            ) -> Self {
+            ) -> ParseResult<Self> {
                 let node: ImmOrPNode<$value_ty> =
-                    node.parse(node_builder, value_builder, cache_builder);
+                    node.parse(node_builder, value_builder, cache_builder)?;
                 match node {
                     ImmOrPNode::Imm(i) => {
                         let id = value_builder.store(i);
-                        ImmOrPNode::Imm(id)
+                        Ok(ImmOrPNode::Imm(id))
                     }
-                    ImmOrPNode::PNode(id) => ImmOrPNode::PNode(id),
+                    ImmOrPNode::PNode(id) => Ok(ImmOrPNode::PNode(id)),
                 }
             }
         }
@@ -221,25 +214,19 @@ macro_rules! impl_parse_for_imm_or_pnode_id {
 impl_parse_for_imm_or_pnode_id!(IntegerId, i64);
 impl_parse_for_imm_or_pnode_id!(FloatId, f64);
 
-impl Default for IntegerRepresentation {
-    fn default() -> Self {
-        Self::PureNumber
-    }
-}
-
 impl Parse for IntegerRepresentation {
     fn parse(
         node: &mut xml::Node,
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         use IntegerRepresentation::{
             Boolean, HexNumber, IpV4Address, Linear, Logarithmic, MacAddress, PureNumber,
         };
 
-        let value = node.next_text().unwrap();
-        match_text_view!(value,
+        let value = node.next_text()?;
+        Ok(match_text_view!(value,
             "Linear" => Linear,
             "Logarithmic" => Logarithmic,
             "Boolean" => Boolean,
@@ -247,7 +234,7 @@ impl Parse for IntegerRepresentation {
             "HexNumber" => HexNumber,
             "IPV4Address" => IpV4Address,
             "MACAddress" => MacAddress,
-        )
+        ))
     }
 }
 
@@ -257,19 +244,13 @@ impl Parse for FloatRepresentation {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        match_text_view! {text,
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(match_text_view! {text,
             "Linear" => Self::Linear,
             "Logarithmic" => Self::Logarithmic,
             "PureNumber" => Self::PureNumber,
-        }
-    }
-}
-
-impl Default for FloatRepresentation {
-    fn default() -> Self {
-        Self::PureNumber
+        })
     }
 }
 
@@ -279,26 +260,14 @@ impl Parse for Slope {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        match_text_view! {text,
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(match_text_view! {text,
             "Increasing" => Self::Increasing,
             "Decreasing" => Self::Decreasing,
             "Varying" => Self::Varying,
             "Automatic" => Self::Automatic,
-        }
-    }
-}
-
-impl Default for Slope {
-    fn default() -> Self {
-        Self::Automatic
-    }
-}
-
-impl Default for DisplayNotation {
-    fn default() -> Self {
-        Self::Automatic
+        })
     }
 }
 
@@ -308,32 +277,24 @@ impl Parse for DisplayNotation {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        match_text_view! {text,
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(match_text_view! {text,
             "Automatic" => Self::Automatic,
             "Fixed" => Self::Fixed,
             "Scientific" => Self::Scientific,
-        }
-    }
-}
-
-impl From<&str> for StandardNameSpace {
-    fn from(value: &str) -> Self {
-        match value {
-            "None" => Self::None,
-            "IIDC" => Self::IIDC,
-            "GEV" => Self::GEV,
-            "CL" => Self::CL,
-            "USB" => Self::USB,
-            _ => unreachable!(),
-        }
+        })
     }
 }
 
-impl Default for CachingMode {
-    fn default() -> Self {
-        Self::WriteThrough
+pub(super) fn convert_to_standard_name_space(value: &str) -> Option<StandardNameSpace> {
+    match value {
+        "None" => Some(StandardNameSpace::None),
+        "IIDC" => Some(StandardNameSpace::IIDC),
+        "GEV" => Some(StandardNameSpace::GEV),
+        "CL" => Some(StandardNameSpace::CL),
+        "USB" => Some(StandardNameSpace::USB),
+        _ => None,
     }
 }
 
@@ -343,13 +304,13 @@ impl Parse for CachingMode {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        match_text_view! {text,
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(match_text_view! {text,
             "WriteThrough" => Self::WriteThrough,
             "WriteAround" => Self::WriteAround,
             "NoCache" => Self::NoCache,
-        }
+        })
     }
 }
 
@@ -362,21 +323,14 @@ where
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let name = node.peek().unwrap().attribute_of(NAME).unwrap().into();
-        let value = node.parse(node_builder, value_builder, cache_builder);
-        Self { name, value }
-    }
-}
-
-pub(super) fn convert_to_bool(value: &str) -> bool {
-    match convert_to_bool_opt(value) {
-        Some(b) => b,
-        _ => unreachable!(),
+    ) -> ParseResult<Self> {
+        let name = node.expect_peek()?.required_attribute(NAME)?.into();
+        let value = node.parse(node_builder, value_builder, cache_builder)?;
+        Ok(Self { name, value })
     }
 }
 
-pub(super) fn convert_to_bool_opt(value: &str) -> Option<bool> {
+pub(super) fn convert_to_bool(value: &str) -> Option<bool> {
     match value {
         "Yes" | "true" => Some(true),
         "No" | "false" => Some(false),
@@ -390,25 +344,24 @@ impl Parse for bool {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        convert_to_bool(&text.view())
+    ) -> ParseResult<Self> {
+        node.next_text()?.convert(convert_to_bool)
     }
 }
 
-pub(super) fn convert_to_int(value: &str) -> i64 {
+pub(super) fn convert_to_int(value: &str) -> Option<i64> {
     if value.starts_with("0x") || value.starts_with("0X") {
-        i64::from_str_radix(&value[2..], 16).unwrap()
+        i64::from_str_radix(&value[2..], 16).ok()
     } else {
-        value.parse().unwrap()
+        value.parse().ok()
     }
 }
 
-pub(super) fn convert_to_uint(value: &str) -> u64 {
+pub(super) fn convert_to_uint(value: &str) -> Option<u64> {
     if value.starts_with("0x") || value.starts_with("0X") {
-        u64::from_str_radix(&value[2..], 16).unwrap()
+        u64::from_str_radix(&value[2..], 16).ok()
     } else {
-        value.parse().unwrap()
+        value.parse().ok()
     }
 }
 
@@ -418,9 +371,8 @@ impl Parse for i64 {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let value = node.next_text().unwrap();
-        convert_to_int(&value.view())
+    ) -> ParseResult<Self> {
+        node.next_text()?.convert(convert_to_int)
     }
 }
 
@@ -430,9 +382,8 @@ impl Parse for u64 {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let value = node.next_text().unwrap();
-        convert_to_uint(&value.view())
+    ) -> ParseResult<Self> {
+        node.next_text()?.convert(convert_to_uint)
     }
 }
 
@@ -442,15 +393,16 @@ impl Parse for f64 {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let value = node.next_text().unwrap().view();
-        if value == "INF" {
-            f64::INFINITY
-        } else if value == "-INF" {
-            f64::NEG_INFINITY
-        } else {
-            value.parse().unwrap()
-        }
+    ) -> ParseResult<Self> {
+        node.next_text()?.convert(|value| {
+            if value == "INF" {
+                Some(f64::INFINITY)
+            } else if value == "-INF" {
+                Some(f64::NEG_INFINITY)
+            } else {
+                value.parse().ok()
+            }
+        })
     }
 }
 
@@ -460,8 +412,8 @@ impl Parse for String {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        node.next_text().unwrap().view().into()
+    ) -> ParseResult<Self> {
+        Ok(node.next_text()?.view().into())
     }
 }
 
@@ -471,9 +423,9 @@ impl Parse for NodeId {
         node_builder: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        node_builder.get_or_intern(&text.view())
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(node_builder.get_or_intern(text.view()))
     }
 }
 
@@ -485,10 +437,10 @@ macro_rules! impl_parse_for_value_id {
                 node_builder: &mut impl NodeStoreBuilder,
                 value_builder: &mut impl ValueStoreBuilder,
                 cache_builder: &mut impl CacheStoreBuilder,
-            ) -> Self {
-                let value: $value_ty = node.parse(node_builder, value_builder, cache_builder);
+            ) -> ParseResult<Self> {
+                let value: $value_ty = node.parse(node_builder, value_builder, cache_builder)?;
                 let id = value_builder.store(value);
-                id
+                Ok(id)
             }
         }
     };
@@ -507,19 +459,21 @@ where
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let peek = node.peek().unwrap();
+    ) -> ParseResult<Self> {
+        let peek = node.expect_peek()?;
         match peek.tag_name() {
-            VALUE => ValueKind::Value(node.parse(node_builder, value_builder, cache_builder)),
+            VALUE => node
+                .parse(node_builder, value_builder, cache_builder)
+                .map(ValueKind::Value),
             P_VALUE_COPY | P_VALUE => {
-                let p_value = node.parse(node_builder, value_builder, cache_builder);
-                ValueKind::PValue(p_value)
+                let p_value = node.parse(node_builder, value_builder, cache_builder)?;
+                Ok(ValueKind::PValue(p_value))
             }
             P_INDEX => {
-                let p_index = node.parse(node_builder, value_builder, cache_builder);
-                ValueKind::PIndex(p_index)
+                let p_index = node.parse(node_builder, value_builder, cache_builder)?;
+                Ok(ValueKind::PIndex(p_index))
             }
-            _ => unreachable!(),
+            _ => Err(peek.unexpected_element()),
         }
     }
 }
@@ -530,22 +484,22 @@ impl<T> Parse for PValue<T> {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         // NOTE: The pValue can be sandwiched between two pValueCopy sequence.
         let mut p_value_copies =
-            node.parse_while(P_VALUE_COPY, node_builder, value_builder, cache_builder);
+            node.parse_while(P_VALUE_COPY, node_builder, value_builder, cache_builder)?;
 
-        let p_value = node.parse(node_builder, value_builder, cache_builder);
+        let p_value = node.parse(node_builder, value_builder, cache_builder)?;
 
         let node_ids: Vec<NodeId> =
-            node.parse_while(P_VALUE_COPY, node_builder, value_builder, cache_builder);
+            node.parse_while(P_VALUE_COPY, node_builder, value_builder, cache_builder)?;
         p_value_copies.extend(node_ids);
 
-        Self {
+        Ok(Self {
             p_value,
             p_value_copies,
             phantom: PhantomData,
-        }
+        })
     }
 }
 
@@ -559,24 +513,26 @@ where
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let p_index = node.parse(node_builder, value_builder, cache_builder);
+    ) -> ParseResult<Self> {
+        let p_index = node.parse(node_builder, value_builder, cache_builder)?;
 
         let mut value_indexed = vec![];
-        while let Some(indexed) = node
-            .parse_if(VALUE_INDEXED, node_builder, value_builder, cache_builder)
-            .or_else(|| node.parse_if(P_VALUE_INDEXED, node_builder, value_builder, cache_builder))
-        {
+        while let Some(indexed) = node.parse_if_any(
+            &[VALUE_INDEXED, P_VALUE_INDEXED],
+            node_builder,
+            value_builder,
+            cache_builder,
+        )? {
             value_indexed.push(indexed);
         }
 
-        let value_default = node.parse(node_builder, value_builder, cache_builder);
+        let value_default = node.parse(node_builder, value_builder, cache_builder)?;
 
-        Self {
+        Ok(Self {
             p_index,
             value_indexed,
             value_default,
-        }
+        })
     }
 }
 
@@ -590,10 +546,12 @@ where
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let index = convert_to_int(node.peek().unwrap().attribute_of(INDEX).unwrap());
-        let indexed = node.parse(node_builder, value_builder, cache_builder);
-        Self { index, indexed }
+    ) -> ParseResult<Self> {
+        let index = node
+            .expect_peek()?
+            .convert_required_attribute(INDEX, convert_to_int)?;
+        let indexed = node.parse(node_builder, value_builder, cache_builder)?;
+        Ok(Self { index, indexed })
     }
 }
 
@@ -603,23 +561,24 @@ impl Parse for AddressKind {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let peeked_node = node.peek().unwrap();
+    ) -> ParseResult<Self> {
+        let peeked_node = node.expect_peek()?;
         match peeked_node.tag_name() {
-            ADDRESS | P_ADDRESS => {
-                Self::Address(node.parse(node_builder, value_builder, cache_builder))
-            }
+            ADDRESS | P_ADDRESS => node
+                .parse(node_builder, value_builder, cache_builder)
+                .map(Self::Address),
             INT_SWISS_KNIFE => {
                 let swiss_knife: IntSwissKnifeNode =
-                    node.next()
-                        .unwrap()
-                        .parse(node_builder, value_builder, cache_builder);
+                    node.expect_next()?
+                        .parse(node_builder, value_builder, cache_builder)?;
                 let id = swiss_knife.node_base().id();
                 node_builder.store_node(id, NodeData::IntSwissKnife(swiss_knife.into()));
-                Self::IntSwissKnife(id)
+                Ok(Self::IntSwissKnife(id))
             }
-            P_INDEX => Self::PIndex(node.parse(node_builder, value_builder, cache_builder)),
-            _ => unreachable!(),
+            P_INDEX => node
+                .parse(node_builder, value_builder, cache_builder)
+                .map(Self::PIndex),
+            _ => Err(peeked_node.unexpected_element()),
         }
     }
 }
@@ -630,26 +589,20 @@ impl Parse for RegPIndex {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let next_node = node.peek().unwrap();
+    ) -> ParseResult<Self> {
+        let next_node = node.expect_peek()?;
 
         let imm_offset = next_node
-            .attribute_of(OFFSET)
-            .map(|s| ImmOrPNode::Imm(convert_to_int(s)));
+            .convert_attribute(OFFSET, convert_to_int)?
+            .map(ImmOrPNode::Imm);
         let pnode_offset = next_node
             .attribute_of(P_OFFSET)
             .map(|s| ImmOrPNode::PNode(node_builder.get_or_intern(s)));
         let offset = imm_offset.xor(pnode_offset);
 
-        let p_index = node.parse(node_builder, value_builder, cache_builder);
-
-        Self { offset, p_index }
-    }
-}
+        let p_index = node.parse(node_builder, value_builder, cache_builder)?;
 
-impl Default for Endianness {
-    fn default() -> Self {
-        Self::LE
+        Ok(Self { offset, p_index })
     }
 }
 
@@ -659,18 +612,12 @@ impl Parse for Endianness {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        match_text_view! {text,
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(match_text_view! {text,
             "LittleEndian" => Self::LE,
             "BigEndian" => Self::BE,
-        }
-    }
-}
-
-impl Default for Sign {
-    fn default() -> Self {
-        Self::Unsigned
+        })
     }
 }
 
@@ -680,12 +627,12 @@ impl Parse for Sign {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        let text = node.next_text().unwrap();
-        match_text_view! {text,
+    ) -> ParseResult<Self> {
+        let text = node.next_text()?;
+        Ok(match_text_view! {text,
             "Signed" => Self::Signed,
             "Unsigned" => Self::Unsigned,
-        }
+        })
     }
 }
 
@@ -695,15 +642,13 @@ impl Parse for BitMask {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        node.parse_if(BIT, node_builder, value_builder, cache_builder)
-            .map_or_else(
-                || {
-                    let lsb = node.parse(node_builder, value_builder, cache_builder);
-                    let msb = node.parse(node_builder, value_builder, cache_builder);
-                    Self::Range { lsb, msb }
-                },
-                Self::SingleBit,
-            )
+    ) -> ParseResult<Self> {
+        if let Some(bit) = node.parse_if(BIT, node_builder, value_builder, cache_builder)? {
+            Ok(Self::SingleBit(bit))
+        } else {
+            let lsb = node.parse(node_builder, value_builder, cache_builder)?;
+            let msb = node.parse(node_builder, value_builder, cache_builder)?;
+            Ok(Self::Range { lsb, msb })
+        }
     }
 }
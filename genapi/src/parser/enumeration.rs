@@ -14,7 +14,7 @@ use super::{
         ENUMERATION, ENUM_ENTRY, IS_SELF_CLEARING, NAME, NUMERIC_VALUE, POLLING_TIME, P_SELECTED,
         STREAMABLE, SYMBOLIC,
     },
-    xml, Parse,
+    xml, Parse, ParseResult,
 };
 
 impl Parse for EnumerationNode {
@@ -24,25 +24,27 @@ impl Parse for EnumerationNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `EnumerationNode`");
         debug_assert_eq!(node.tag_name(), ENUMERATION);
 
-        let attr_base = node.parse(node_builder, value_builder, cache_builder);
-        let elem_base = node.parse(node_builder, value_builder, cache_builder);
+        let attr_base = node.parse(node_builder, value_builder, cache_builder)?;
+        let elem_base = node.parse(node_builder, value_builder, cache_builder)?;
 
         let streamable = node
-            .parse_if(STREAMABLE, node_builder, value_builder, cache_builder)
+            .parse_if(STREAMABLE, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
         let mut entries = vec![];
         while let Some(mut ent_node) = node.next_if(ENUM_ENTRY) {
-            entries.push(ent_node.parse(node_builder, value_builder, cache_builder));
+            entries.push(ent_node.parse(node_builder, value_builder, cache_builder)?);
         }
-        let value = node.parse(node_builder, value_builder, cache_builder);
-        let p_selected = node.parse_while(P_SELECTED, node_builder, value_builder, cache_builder);
-        let polling_time = node.parse_if(POLLING_TIME, node_builder, value_builder, cache_builder);
+        let value = node.parse(node_builder, value_builder, cache_builder)?;
+        let p_selected =
+            node.parse_while(P_SELECTED, node_builder, value_builder, cache_builder)?;
+        let polling_time =
+            node.parse_if(POLLING_TIME, node_builder, value_builder, cache_builder)?;
 
-        Self {
+        Ok(Self {
             attr_base,
             elem_base,
             streamable,
@@ -50,7 +52,7 @@ impl Parse for EnumerationNode {
             value,
             p_selected,
             polling_time,
-        }
+        })
     }
 }
 
@@ -61,29 +63,29 @@ impl Parse for EnumEntryNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `EnumEntryNode`");
         debug_assert_eq!(node.tag_name(), ENUM_ENTRY);
 
-        let name = node.attribute_of(NAME).unwrap().to_string();
-        let elem_base = node.parse(node_builder, value_builder, cache_builder);
+        let name = node.required_attribute(NAME)?.to_string();
+        let elem_base = node.parse(node_builder, value_builder, cache_builder)?;
 
-        let value = node.parse(node_builder, value_builder, cache_builder);
+        let value = node.parse(node_builder, value_builder, cache_builder)?;
         let numeric_value =
-            node.parse_if(NUMERIC_VALUE, node_builder, value_builder, cache_builder);
-        let symbolic = node.parse_if(SYMBOLIC, node_builder, value_builder, cache_builder);
+            node.parse_if(NUMERIC_VALUE, node_builder, value_builder, cache_builder)?;
+        let symbolic = node.parse_if(SYMBOLIC, node_builder, value_builder, cache_builder)?;
         let is_self_clearing = node
-            .parse_if(IS_SELF_CLEARING, node_builder, value_builder, cache_builder)
+            .parse_if(IS_SELF_CLEARING, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
 
-        Self {
+        Ok(Self {
             name,
             elem_base,
             value,
             numeric_value,
             symbolic,
             is_self_clearing,
-        }
+        })
     }
 }
 
@@ -91,7 +93,13 @@ impl Parse for EnumEntryNode {
 mod tests {
     use crate::elem_type::ImmOrPNode;
 
-    use super::{super::utils::tests::parse_default, *};
+    use super::{
+        super::{
+            utils::tests::{parse_default, parse_error},
+            ParseError,
+        },
+        *,
+    };
 
     #[test]
     fn test_enumeration() {
@@ -126,12 +134,31 @@ mod tests {
         assert_eq!(entry0.name(), "Entry0");
         assert_eq!(entry0.value(), 0);
         assert!((entry0.numeric_value() - 1.0).abs() < f64::EPSILON);
-        assert_eq!(entry0.is_self_clearing(), true);
+        assert!(entry0.is_self_clearing());
 
         let entry1 = &entries[1];
         assert_eq!(entry1.name(), "Entry1");
         assert_eq!(entry1.value(), 1);
         assert!((entry1.numeric_value() - 10.0).abs() < f64::EPSILON);
-        assert_eq!(entry1.is_self_clearing(), false);
+        assert!(!entry1.is_self_clearing());
+    }
+
+    #[test]
+    fn test_enum_entry_without_name() {
+        let xml = r#"
+            <Enumeration Name="TestNode">
+                <EnumEntry>
+                    <Value>0</Value>
+                </EnumEntry>
+                <pValue>MyNode</pValue>
+            </Enumeration>
+            "#;
+
+        let err = parse_error::<EnumerationNode>(xml);
+        assert!(matches!(
+            err,
+            ParseError::MissingAttribute { element, attribute, position }
+                if element == "EnumEntry" && attribute == "Name" && position.line == 3
+        ));
     }
 }
@@ -2,6 +2,9 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use std::convert::TryFrom;
+
+use cameleon_impl::float;
 use tracing::debug;
 
 use crate::{
@@ -10,10 +13,36 @@ use crate::{
 };
 
 use super::{
-    elem_name::{DISPLAY_NOTATION, DISPLAY_PRECISION, ENDIANNESS, FLOAT_REG, REPRESENTATION, UNIT},
-    xml, Parse,
+    elem_name::{
+        DISPLAY_NOTATION, DISPLAY_PRECISION, ENDIANNESS, FLOAT_REG, LENGTH, NAME, REPRESENTATION,
+        UNIT,
+    },
+    elem_type::convert_to_int,
+    xml, Parse, ParseError, ParseResult,
 };
 
+/// Verifies immediate `Length`s of `FloatReg`s in the node.
+///
+/// A length given by `pLength` is verified when the register is accessed.
+pub(super) fn verify_length(node: &xml::Node) -> ParseResult<()> {
+    for float_reg in node.descendants_of(FLOAT_REG) {
+        let length = match float_reg.child_text_of(LENGTH) {
+            Some(text) => text.convert(convert_to_int)?,
+            None => continue,
+        };
+        match usize::try_from(length) {
+            Ok(len) if float::is_valid_length(len) => {}
+            _ => {
+                return Err(ParseError::InvalidFloatRegLength {
+                    name: float_reg.attribute_of(NAME).unwrap_or_default().into(),
+                    length,
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
 impl Parse for FloatRegNode {
     #[tracing::instrument(level = "trace", skip(node_builder, value_builder, cache_builder))]
     fn parse(
@@ -21,22 +50,22 @@ impl Parse for FloatRegNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `FloatRegNode`");
         debug_assert_eq!(node.tag_name(), FLOAT_REG);
 
-        let attr_base = node.parse(node_builder, value_builder, cache_builder);
-        let register_base = node.parse(node_builder, value_builder, cache_builder);
+        let attr_base = node.parse(node_builder, value_builder, cache_builder)?;
+        let register_base = node.parse(node_builder, value_builder, cache_builder)?;
 
         let endianness = node
-            .parse_if(ENDIANNESS, node_builder, value_builder, cache_builder)
+            .parse_if(ENDIANNESS, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
-        let unit = node.parse_if(UNIT, node_builder, value_builder, cache_builder);
+        let unit = node.parse_if(UNIT, node_builder, value_builder, cache_builder)?;
         let representation = node
-            .parse_if(REPRESENTATION, node_builder, value_builder, cache_builder)
+            .parse_if(REPRESENTATION, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
         let display_notation = node
-            .parse_if(DISPLAY_NOTATION, node_builder, value_builder, cache_builder)
+            .parse_if(DISPLAY_NOTATION, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
         let display_precision = node
             .parse_if(
@@ -44,7 +73,7 @@ impl Parse for FloatRegNode {
                 node_builder,
                 value_builder,
                 cache_builder,
-            )
+            )?
             .unwrap_or(6);
 
         let node = Self {
@@ -58,7 +87,7 @@ impl Parse for FloatRegNode {
         };
         node.register_base
             .store_invalidators(node.attr_base.id, cache_builder);
-        node
+        Ok(node)
     }
 }
 
@@ -91,4 +120,37 @@ mod tests {
         assert_eq!(node.display_notation_elem(), DisplayNotation::Fixed);
         assert_eq!(node.display_precision_elem(), 10);
     }
+
+    #[test]
+    fn test_verify_length() {
+        let float_reg = |length| {
+            format!(
+                r#"
+            <FloatReg Name="TestNode">
+              <Address>0x10000</Address>
+              <Length>{}</Length>
+              <pPort>Device</pPort>
+            </FloatReg>
+            "#,
+                length
+            )
+        };
+
+        for length in &["2", "4", "0x8"] {
+            let xml = float_reg(length);
+            let document = xml::Document::from_str(&xml).unwrap();
+            assert!(verify_length(&document.root_node()).is_ok());
+        }
+
+        for (length, expected) in &[("3", 3), ("16", 16), ("-4", -4)] {
+            let xml = float_reg(length);
+            let document = xml::Document::from_str(&xml).unwrap();
+            let err = verify_length(&document.root_node()).unwrap_err();
+            assert!(matches!(
+                err,
+                ParseError::InvalidFloatRegLength { ref name, length }
+                    if name == "TestNode" && length == *expected
+            ));
+        }
+    }
 }
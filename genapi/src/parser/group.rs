@@ -8,11 +8,12 @@ use crate::builder::{CacheStoreBuilder, NodeStoreBuilder, ValueStoreBuilder};
 
 use super::{
     elem_name::{COMMENT, GROUP},
-    xml, NodeData, Parse,
+    xml, NodeData, Parse, ParseResult,
 };
 
 #[derive(Debug, Clone)]
 pub(super) struct GroupNode {
+    #[allow(dead_code)]
     comment: String,
 
     pub(super) nodes: Vec<NodeData>,
@@ -25,20 +26,21 @@ impl Parse for GroupNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `GroupNode`");
         debug_assert_eq!(node.tag_name(), GROUP);
-        let comment = node.attribute_of(COMMENT).unwrap().into();
+        let comment = node.required_attribute(COMMENT)?.into();
 
         let mut nodes = vec![];
         while let Some(ref mut child) = node.next() {
-            let children: Vec<NodeData> = child.parse(node_builder, value_builder, cache_builder);
+            let children: Vec<NodeData> =
+                child.parse(node_builder, value_builder, cache_builder)?;
             for data in children {
                 nodes.push(data);
             }
         }
 
-        Self { comment, nodes }
+        Ok(Self { comment, nodes })
     }
 }
 
@@ -13,7 +13,7 @@ use super::{
     elem_name::{
         CONSTANT, EXPRESSION, INT_CONVERTER, P_VARIABLE, REPRESENTATION, SLOPE, STREAMABLE, UNIT,
     },
-    xml, Parse,
+    xml, Parse, ParseResult,
 };
 
 impl Parse for IntConverterNode {
@@ -23,31 +23,33 @@ impl Parse for IntConverterNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `IntConverterNode`");
         debug_assert_eq!(node.tag_name(), INT_CONVERTER);
 
-        let attr_base = node.parse(node_builder, value_builder, cache_builder);
-        let elem_base = node.parse(node_builder, value_builder, cache_builder);
+        let attr_base = node.parse(node_builder, value_builder, cache_builder)?;
+        let elem_base = node.parse(node_builder, value_builder, cache_builder)?;
 
         let streamable = node
-            .parse_if(STREAMABLE, node_builder, value_builder, cache_builder)
+            .parse_if(STREAMABLE, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
-        let p_variables = node.parse_while(P_VARIABLE, node_builder, value_builder, cache_builder);
-        let constants = node.parse_while(CONSTANT, node_builder, value_builder, cache_builder);
-        let expressions = node.parse_while(EXPRESSION, node_builder, value_builder, cache_builder);
-        let formula_to = node.parse(node_builder, value_builder, cache_builder);
-        let formula_from = node.parse(node_builder, value_builder, cache_builder);
-        let p_value = node.parse(node_builder, value_builder, cache_builder);
-        let unit = node.parse_if(UNIT, node_builder, value_builder, cache_builder);
+        let p_variables =
+            node.parse_while(P_VARIABLE, node_builder, value_builder, cache_builder)?;
+        let constants = node.parse_while(CONSTANT, node_builder, value_builder, cache_builder)?;
+        let expressions =
+            node.parse_while(EXPRESSION, node_builder, value_builder, cache_builder)?;
+        let formula_to = node.parse(node_builder, value_builder, cache_builder)?;
+        let formula_from = node.parse(node_builder, value_builder, cache_builder)?;
+        let p_value = node.parse(node_builder, value_builder, cache_builder)?;
+        let unit = node.parse_if(UNIT, node_builder, value_builder, cache_builder)?;
         let representation = node
-            .parse_if(REPRESENTATION, node_builder, value_builder, cache_builder)
+            .parse_if(REPRESENTATION, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
         let slope = node
-            .parse_if(SLOPE, node_builder, value_builder, cache_builder)
+            .parse_if(SLOPE, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
 
-        Self {
+        Ok(Self {
             attr_base,
             elem_base,
             streamable,
@@ -60,7 +62,7 @@ impl Parse for IntConverterNode {
             unit,
             representation,
             slope,
-        }
+        })
     }
 }
 
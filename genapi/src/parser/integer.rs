@@ -15,7 +15,7 @@ use super::{
     elem_name::{
         INC, INTEGER, MAX, MIN, P_INC, P_MAX, P_MIN, P_SELECTED, REPRESENTATION, STREAMABLE, UNIT,
     },
-    xml, Parse,
+    xml, Parse, ParseResult,
 };
 
 impl Parse for IntegerNode {
@@ -25,33 +25,28 @@ impl Parse for IntegerNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `IntegerNode`");
         debug_assert_eq!(node.tag_name(), INTEGER);
 
-        let attr_base = node.parse(node_builder, value_builder, cache_builder);
-        let elem_base = node.parse(node_builder, value_builder, cache_builder);
+        let attr_base = node.parse(node_builder, value_builder, cache_builder)?;
+        let elem_base = node.parse(node_builder, value_builder, cache_builder)?;
 
         let streamable = node
-            .parse_if(STREAMABLE, node_builder, value_builder, cache_builder)
+            .parse_if(STREAMABLE, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
-        let value_kind = node.parse(node_builder, value_builder, cache_builder);
-        let min = node
-            .parse_if(MIN, node_builder, value_builder, cache_builder)
-            .or_else(|| node.parse_if(P_MIN, node_builder, value_builder, cache_builder));
-        let max = node
-            .parse_if(MAX, node_builder, value_builder, cache_builder)
-            .or_else(|| node.parse_if(P_MAX, node_builder, value_builder, cache_builder));
+        let value_kind = node.parse(node_builder, value_builder, cache_builder)?;
+        let min = node.parse_if_any(&[MIN, P_MIN], node_builder, value_builder, cache_builder)?;
+        let max = node.parse_if_any(&[MAX, P_MAX], node_builder, value_builder, cache_builder)?;
         let inc = node
-            .parse_if(INC, node_builder, value_builder, cache_builder)
-            .or_else(|| node.parse_if(P_INC, node_builder, value_builder, cache_builder))
+            .parse_if_any(&[INC, P_INC], node_builder, value_builder, cache_builder)?
             .unwrap_or(ImmOrPNode::Imm(10));
-        let unit = node.parse_if(UNIT, node_builder, value_builder, cache_builder);
+        let unit = node.parse_if(UNIT, node_builder, value_builder, cache_builder)?;
         let representation: IntegerRepresentation = node
-            .parse_if(REPRESENTATION, node_builder, value_builder, cache_builder)
+            .parse_if(REPRESENTATION, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
         let p_selected: Vec<NodeId> =
-            node.parse_while(P_SELECTED, node_builder, value_builder, cache_builder);
+            node.parse_while(P_SELECTED, node_builder, value_builder, cache_builder)?;
 
         // Deduce min and max value based on representation if not specified.
         let min = min.unwrap_or_else(|| {
@@ -63,7 +58,7 @@ impl Parse for IntegerNode {
             ImmOrPNode::Imm(id)
         });
 
-        Self {
+        Ok(Self {
             attr_base,
             elem_base,
             streamable,
@@ -74,7 +69,7 @@ impl Parse for IntegerNode {
             unit,
             representation,
             p_selected,
-        }
+        })
     }
 }
 
@@ -82,7 +77,13 @@ impl Parse for IntegerNode {
 mod tests {
     use crate::{elem_type::ValueKind, interface::INode, store::ValueStore};
 
-    use super::{super::utils::tests::parse_default, *};
+    use super::{
+        super::{
+            utils::tests::{parse_default, parse_error},
+            ParseError,
+        },
+        *,
+    };
 
     #[test]
     fn test_integer_node_with_immediate() {
@@ -213,4 +214,44 @@ mod tests {
             ImmOrPNode::PNode(node_builder.get_or_intern("pValueDefaultNode"))
         );
     }
+
+    #[test]
+    fn test_integer_node_with_invalid_value() {
+        let xml = r#"
+            <Integer Name = "TestNode">
+                <Value>0xZZ</Value>
+            </Integer>
+            "#;
+
+        let err = parse_error::<IntegerNode>(xml);
+        assert!(matches!(
+            &err,
+            ParseError::InvalidText { element, value, position }
+                if element == "Value" && value == "0xZZ" && position.line == 3 && position.column == 17
+        ));
+        assert!(err.to_string().contains("line 3, column 17"));
+    }
+
+    #[test]
+    fn test_integer_node_without_value() {
+        let xml = r#"
+            <Integer Name = "TestNode">
+                <Min>0</Min>
+            </Integer>
+            "#;
+
+        let err = parse_error::<IntegerNode>(xml);
+        assert!(matches!(
+            err,
+            ParseError::UnexpectedElement { element, parent, .. }
+                if element == "Min" && parent == "Integer"
+        ));
+
+        let xml = r#"<Integer Name = "TestNode"></Integer>"#;
+        let err = parse_error::<IntegerNode>(xml);
+        assert!(matches!(
+            err,
+            ParseError::MissingElement { parent, .. } if parent == "Integer"
+        ));
+    }
 }
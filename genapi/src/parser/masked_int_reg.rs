@@ -11,7 +11,7 @@ use crate::{
 
 use super::{
     elem_name::{ENDIANNESS, MASKED_INT_REG, P_SELECTED, REPRESENTATION, SIGN, UNIT},
-    xml, Parse,
+    xml, Parse, ParseResult,
 };
 
 impl Parse for MaskedIntRegNode {
@@ -21,24 +21,25 @@ impl Parse for MaskedIntRegNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `MaskedIntRegNode`");
         debug_assert_eq!(node.tag_name(), MASKED_INT_REG);
-        let attr_base = node.parse(node_builder, value_builder, cache_builder);
-        let register_base = node.parse(node_builder, value_builder, cache_builder);
+        let attr_base = node.parse(node_builder, value_builder, cache_builder)?;
+        let register_base = node.parse(node_builder, value_builder, cache_builder)?;
 
-        let bit_mask = node.parse(node_builder, value_builder, cache_builder);
+        let bit_mask = node.parse(node_builder, value_builder, cache_builder)?;
         let sign = node
-            .parse_if(SIGN, node_builder, value_builder, cache_builder)
+            .parse_if(SIGN, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
         let endianness = node
-            .parse_if(ENDIANNESS, node_builder, value_builder, cache_builder)
+            .parse_if(ENDIANNESS, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
-        let unit = node.parse_if(UNIT, node_builder, value_builder, cache_builder);
+        let unit = node.parse_if(UNIT, node_builder, value_builder, cache_builder)?;
         let representation = node
-            .parse_if(REPRESENTATION, node_builder, value_builder, cache_builder)
+            .parse_if(REPRESENTATION, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
-        let p_selected = node.parse_while(P_SELECTED, node_builder, value_builder, cache_builder);
+        let p_selected =
+            node.parse_while(P_SELECTED, node_builder, value_builder, cache_builder)?;
 
         let node = Self {
             attr_base,
@@ -52,7 +53,7 @@ impl Parse for MaskedIntRegNode {
         };
         node.register_base
             .store_invalidators(node.attr_base.id, cache_builder);
-        node
+        Ok(node)
     }
 }
 
@@ -24,6 +24,7 @@ mod port;
 mod register;
 mod register_base;
 mod register_description;
+mod schema;
 mod string;
 mod string_reg;
 mod struct_reg;
@@ -31,20 +32,26 @@ mod swiss_knife;
 mod utils;
 mod xml;
 
+pub use schema::{CompatibilityReport, ParseWarning, ParserConfig, SchemaPolicy, SchemaVersion};
+
+use std::fmt;
+
 use group::GroupNode;
 use struct_reg::StructRegNode;
 use thiserror::Error;
+use tracing::warn;
 
 use crate::{
     builder::{CacheStoreBuilder, NodeStoreBuilder, ValueStoreBuilder},
+    formula::FormulaError,
     store::NodeData,
     RegisterDescription,
 };
 
 use elem_name::{
-    ADV_FEATURE_LOCK, BOOLEAN, CATEGORY, COMMAND, CONF_ROM, CONVERTER, ENUMERATION, FLOAT,
-    FLOAT_REG, GROUP, INTEGER, INT_CONVERTER, INT_KEY, INT_REG, INT_SWISS_KNIFE, MASKED_INT_REG,
-    NODE, PORT, REGISTER, SMART_FEATURE, STRING, STRING_REG, STRUCT_REG, SWISS_KNIFE, TEXT_DESC,
+    BOOLEAN, CATEGORY, COMMAND, CONVERTER, ENUMERATION, FLOAT, FLOAT_REG, GROUP, INTEGER,
+    INT_CONVERTER, INT_REG, INT_SWISS_KNIFE, MASKED_INT_REG, NODE, PORT, REGISTER, STRING,
+    STRING_REG, STRUCT_REG, SWISS_KNIFE,
 };
 
 #[derive(Debug, Error)]
@@ -54,21 +61,172 @@ pub enum ParseError {
 
     #[error("invalid XML syntax: {0}")]
     InvalidSyntax(#[from] roxmltree::Error),
+
+    #[error("schema version is missing in `RegisterDescription`")]
+    MissingSchemaVersion,
+
+    #[error(
+        "schema version {0} is newer than the supported version {}",
+        SchemaVersion::SUPPORTED
+    )]
+    UnsupportedSchema(SchemaVersion),
+
+    #[error("schema version {required} is required, but the XML conforms to {found}")]
+    SchemaMismatch {
+        required: SchemaVersion,
+        found: SchemaVersion,
+    },
+
+    #[error("length of `FloatReg` must be either 2/4/8, but `{name}` has {length}")]
+    InvalidFloatRegLength { name: String, length: i64 },
+
+    #[error("`{element}` is missing the required attribute `{attribute}` at {position}")]
+    MissingAttribute {
+        element: String,
+        attribute: String,
+        position: TextPosition,
+    },
+
+    #[error("`{parent}` is missing a required element at {position}")]
+    MissingElement {
+        parent: String,
+        position: TextPosition,
+    },
+
+    #[error("unexpected element `{element}` in `{parent}` at {position}")]
+    UnexpectedElement {
+        element: String,
+        parent: String,
+        position: TextPosition,
+    },
+
+    #[error("invalid value `{value}` of `{element}` at {position}")]
+    InvalidText {
+        element: String,
+        value: String,
+        position: TextPosition,
+    },
+
+    #[error("invalid value `{value}` of the attribute `{attribute}` of `{element}` at {position}")]
+    InvalidAttribute {
+        element: String,
+        attribute: String,
+        value: String,
+        position: TextPosition,
+    },
+
+    #[error("invalid formula `{formula}` of `{element}` at {position}: {reason}")]
+    InvalidFormula {
+        element: String,
+        formula: String,
+        reason: FormulaError,
+        position: TextPosition,
+    },
+
+    #[error("unknown element `{name}` in `{parent}` at {position}")]
+    UnknownElement {
+        name: String,
+        parent: String,
+        position: TextPosition,
+    },
+
+    #[error("unknown attribute `{name}` of `{element}` at {position}")]
+    UnknownAttribute {
+        name: String,
+        element: String,
+        position: TextPosition,
+    },
 }
 
 pub type ParseResult<T> = std::result::Result<T, ParseError>;
 
+/// Position of an element in the XML.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextPosition {
+    /// Byte offset from the start of the XML.
+    pub offset: usize,
+    /// Line number, starting from 1.
+    pub line: u32,
+    /// Column number, starting from 1.
+    pub column: u32,
+}
+
+impl fmt::Display for TextPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
 pub fn parse(
     xml: &impl AsRef<str>,
     node_builder: &mut impl NodeStoreBuilder,
     value_builder: &mut impl ValueStoreBuilder,
     cache_builder: &mut impl CacheStoreBuilder,
 ) -> ParseResult<RegisterDescription> {
-    let document = xml::Document::from_str(xml.as_ref())?;
-    let mut node = document.root_node();
-    let reg_desc = node.parse(node_builder, value_builder, cache_builder);
+    let (reg_desc, _) = parse_with_config(
+        xml,
+        &ParserConfig::default(),
+        node_builder,
+        value_builder,
+        cache_builder,
+    )?;
+    Ok(reg_desc)
+}
+
+/// Parses `xml` with `config`, and returns [`CompatibilityReport`] in addition to the register
+/// description.
+pub fn parse_with_config(
+    xml: &impl AsRef<str>,
+    config: &ParserConfig,
+    node_builder: &mut impl NodeStoreBuilder,
+    value_builder: &mut impl ValueStoreBuilder,
+    cache_builder: &mut impl CacheStoreBuilder,
+) -> ParseResult<(RegisterDescription, CompatibilityReport)> {
+    let mut document = xml::Document::from_str(xml.as_ref())?;
+    let schema_version = document.schema_version()?;
+    let best_effort = config.check(schema_version)?;
+    if best_effort {
+        warn!(
+            "schema version {} is newer than the supported version {}, parse with best effort",
+            schema_version,
+            SchemaVersion::SUPPORTED
+        );
+    }
+    // Constructs of the newer schema are expected when it's parsed with best effort, so they are
+    // skipped even in the strict mode.
+    document.set_strict(config.is_strict() && !best_effort);
+
+    let reg_desc = parse_document(
+        &mut document.root_node(),
+        node_builder,
+        value_builder,
+        cache_builder,
+    );
+
+    // An unknown construct found in the strict mode precedes errors caused by skipping it.
+    if let Some(err) = document.take_error() {
+        return Err(err);
+    }
+    let reg_desc = reg_desc?;
+    let warnings = document.take_warnings();
+    let report = CompatibilityReport {
+        schema_version,
+        degraded: best_effort || !warnings.is_empty(),
+        warnings,
+    };
+    Ok((reg_desc, report))
+}
+
+fn parse_document(
+    node: &mut xml::Node,
+    node_builder: &mut impl NodeStoreBuilder,
+    value_builder: &mut impl ValueStoreBuilder,
+    cache_builder: &mut impl CacheStoreBuilder,
+) -> ParseResult<RegisterDescription> {
+    let reg_desc = node.parse(node_builder, value_builder, cache_builder)?;
     while let Some(ref mut child) = node.next() {
-        let children: Vec<NodeData> = child.parse(node_builder, value_builder, cache_builder);
+        float_reg::verify_length(child)?;
+        let children: Vec<NodeData> = child.parse(node_builder, value_builder, cache_builder)?;
         for child in children {
             let id = child.node_base().id();
             node_builder.store_node(id, child);
@@ -78,13 +236,13 @@ pub fn parse(
     Ok(reg_desc)
 }
 
-trait Parse {
+trait Parse: Sized {
     fn parse(
         node: &mut xml::Node,
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self;
+    ) -> ParseResult<Self>;
 }
 
 impl Parse for Vec<NodeData> {
@@ -94,112 +252,113 @@ impl Parse for Vec<NodeData> {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
-        match node.tag_name() {
+    ) -> ParseResult<Self> {
+        let nodes = match node.tag_name() {
             NODE => vec![NodeData::Node(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             CATEGORY => vec![NodeData::Category(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             INTEGER => vec![NodeData::Integer(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             INT_REG => vec![NodeData::IntReg(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             MASKED_INT_REG => vec![NodeData::MaskedIntReg(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             BOOLEAN => vec![NodeData::Boolean(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             COMMAND => vec![NodeData::Command(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             ENUMERATION => vec![NodeData::Enumeration(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             FLOAT => vec![NodeData::Float(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             FLOAT_REG => vec![NodeData::FloatReg(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             STRING => vec![NodeData::String(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             STRING_REG => vec![NodeData::StringReg(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             REGISTER => vec![NodeData::Register(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             CONVERTER => vec![NodeData::Converter(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             INT_CONVERTER => vec![NodeData::IntConverter(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             SWISS_KNIFE => vec![NodeData::SwissKnife(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             INT_SWISS_KNIFE => vec![NodeData::IntSwissKnife(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             PORT => vec![NodeData::Port(Box::new(node.parse(
                 node_builder,
                 value_builder,
                 cache_builder,
-            )))],
+            )?))],
             STRUCT_REG => {
-                let node: StructRegNode = node.parse(node_builder, value_builder, cache_builder);
+                let node: StructRegNode = node.parse(node_builder, value_builder, cache_builder)?;
                 node.into_masked_int_regs(cache_builder)
                     .into_iter()
                     .map(|node| NodeData::MaskedIntReg(node.into()))
                     .collect()
             }
             GROUP => {
-                let node: GroupNode = node.parse(node_builder, value_builder, cache_builder);
+                let node: GroupNode = node.parse(node_builder, value_builder, cache_builder)?;
                 node.nodes
             }
-            // TODO: Implement DCAM specific ndoes.
-            CONF_ROM | TEXT_DESC | INT_KEY | ADV_FEATURE_LOCK | SMART_FEATURE => todo!(),
-            _ => unreachable!(),
-        }
+            // TODO: Implement DCAM specific nodes, i.e. `ConfRom`, `TextDesc`, `IntKey`,
+            // `AdvFeatureLock` and `SmartFeature`.
+            _ => return Err(node.unexpected_element()),
+        };
+        Ok(nodes)
     }
 }
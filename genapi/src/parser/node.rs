@@ -10,7 +10,7 @@ use crate::{
     Node,
 };
 
-use super::{elem_name::NODE, xml, Parse};
+use super::{elem_name::NODE, xml, Parse, ParseResult};
 
 impl Parse for Node {
     #[tracing::instrument(level = "trace", skip(node_builder, value_builder, cache_builder))]
@@ -19,17 +19,17 @@ impl Parse for Node {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `Node`");
         debug_assert_eq!(node.tag_name(), NODE);
 
-        let attr_base = NodeAttributeBase::parse(node, node_builder, value_builder, cache_builder);
-        let elem_base = NodeElementBase::parse(node, node_builder, value_builder, cache_builder);
+        let attr_base = NodeAttributeBase::parse(node, node_builder, value_builder, cache_builder)?;
+        let elem_base = NodeElementBase::parse(node, node_builder, value_builder, cache_builder)?;
 
-        Self {
+        Ok(Self {
             attr_base,
             elem_base,
-        }
+        })
     }
 }
 
@@ -40,7 +40,13 @@ mod tests {
         interface::INode,
     };
 
-    use super::{super::utils::tests::parse_default, *};
+    use super::{
+        super::{
+            utils::tests::{parse_default, parse_error},
+            ParseError,
+        },
+        *,
+    };
 
     #[test]
     fn test_all_fields_filled() {
@@ -70,14 +76,14 @@ mod tests {
         assert_eq!(node_base.id(), node_builder.get_or_intern("TestNode"));
         assert_eq!(node_base.name_space(), NameSpace::Standard);
         assert_eq!(node_base.merge_priority(), MergePriority::High);
-        assert_eq!(node_base.expose_static().unwrap(), false);
+        assert!(!node_base.expose_static().unwrap());
 
         assert_eq!(node_base.tooltip().unwrap(), "tooltip");
         assert_eq!(node_base.description().unwrap(), "the description");
         assert_eq!(node_base.display_name(), Some("display name"));
         assert_eq!(node_base.visibility(), Visibility::Guru);
         assert_eq!(node_base.docu_url().unwrap(), "http://FOO.com");
-        assert_eq!(node_base.is_deprecated(), true);
+        assert!(node_base.is_deprecated());
         assert_eq!(node_base.event_id(), Some(0xF1));
         assert_eq!(
             node_base.p_is_implemented().unwrap(),
@@ -133,7 +139,7 @@ mod tests {
         assert_eq!(node_base.display_name(), None);
         assert_eq!(node_base.visibility(), Visibility::Beginner);
         assert!(node_base.docu_url().is_none());
-        assert_eq!(node_base.is_deprecated(), false);
+        assert!(!node_base.is_deprecated());
         assert!(node_base.event_id().is_none());
         assert!(node_base.p_is_implemented().is_none());
         assert!(node_base.p_is_available().is_none());
@@ -144,4 +150,31 @@ mod tests {
         assert!(node_base.p_alias().is_none());
         assert!(node_base.p_cast_alias().is_none());
     }
+
+    #[test]
+    fn test_invalid_fields() {
+        let xml = r#"<Node Name = "TestNode"><Visibility>Master</Visibility></Node>"#;
+        let err = parse_error::<Node>(xml);
+        assert!(matches!(
+            err,
+            ParseError::InvalidText { element, value, .. }
+                if element == "Visibility" && value == "Master"
+        ));
+
+        let xml = r#"<Node Name = "TestNode" NameSpace = "Private"></Node>"#;
+        let err = parse_error::<Node>(xml);
+        assert!(matches!(
+            err,
+            ParseError::InvalidAttribute { element, attribute, value, .. }
+                if element == "Node" && attribute == "NameSpace" && value == "Private"
+        ));
+
+        let xml = r#"<Node NameSpace = "Standard"></Node>"#;
+        let err = parse_error::<Node>(xml);
+        assert!(matches!(
+            err,
+            ParseError::MissingAttribute { element, attribute, .. }
+                if element == "Node" && attribute == "Name"
+        ));
+    }
 }
@@ -12,7 +12,7 @@ use crate::{
 
 use super::{
     elem_name::{CACHE_CHUNK_DATA, CHUNK_ID, PORT, P_CHUNK_ID, SWAP_ENDIANNESS},
-    xml, Parse,
+    xml, Parse, ParseResult,
 };
 
 impl Parse for PortNode {
@@ -22,39 +22,37 @@ impl Parse for PortNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `PortNode`");
         debug_assert_eq!(node.tag_name(), PORT);
 
-        let attr_base = node.parse(node_builder, value_builder, cache_builder);
-        let elem_base = node.parse(node_builder, value_builder, cache_builder);
+        let attr_base = node.parse(node_builder, value_builder, cache_builder)?;
+        let elem_base = node.parse(node_builder, value_builder, cache_builder)?;
 
-        let chunk_id = node.next_if(CHUNK_ID).map_or_else(
-            || {
-                node.next_if(P_CHUNK_ID).map(|next_node| {
-                    ImmOrPNode::PNode(node_builder.get_or_intern(&next_node.text().view()))
-                })
-            },
-            |next_node| {
-                Some(ImmOrPNode::Imm(
-                    u64::from_str_radix(&next_node.text().view(), 16).unwrap(),
-                ))
-            },
-        );
+        let chunk_id = if let Some(next_node) = node.next_if(CHUNK_ID) {
+            let chunk_id = next_node
+                .text()
+                .convert(|s| u64::from_str_radix(s, 16).ok())?;
+            Some(ImmOrPNode::Imm(chunk_id))
+        } else {
+            node.next_if(P_CHUNK_ID).map(|next_node| {
+                ImmOrPNode::PNode(node_builder.get_or_intern(next_node.text().view()))
+            })
+        };
         let swap_endianness = node
-            .parse_if(SWAP_ENDIANNESS, node_builder, value_builder, cache_builder)
+            .parse_if(SWAP_ENDIANNESS, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
         let cache_chunk_data = node
-            .parse_if(CACHE_CHUNK_DATA, node_builder, value_builder, cache_builder)
+            .parse_if(CACHE_CHUNK_DATA, node_builder, value_builder, cache_builder)?
             .unwrap_or_default();
 
-        Self {
+        Ok(Self {
             attr_base,
             elem_base,
             chunk_id,
             swap_endianness,
             cache_chunk_data,
-        }
+        })
     }
 }
 
@@ -73,7 +71,7 @@ mod tests {
 
         let (node, ..): (PortNode, _, _, _) = parse_default(xml);
         assert_eq!(node.chunk_id().unwrap(), &ImmOrPNode::Imm(0x00FD_3219));
-        assert_eq!(node.swap_endianness(), true);
+        assert!(node.swap_endianness());
     }
 
     #[test]
@@ -101,6 +99,6 @@ mod tests {
 
         let (node, ..): (PortNode, _, _, _) = parse_default(xml);
         assert_eq!(node.chunk_id(), None);
-        assert_eq!(node.cache_chunk_data(), true);
+        assert!(node.cache_chunk_data());
     }
 }
@@ -9,7 +9,7 @@ use crate::{
     RegisterNode,
 };
 
-use super::{elem_name::REGISTER, xml, Parse};
+use super::{elem_name::REGISTER, xml, Parse, ParseResult};
 
 impl Parse for RegisterNode {
     #[tracing::instrument(level = "trace", skip(node_builder, value_builder, cache_builder))]
@@ -18,12 +18,12 @@ impl Parse for RegisterNode {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `RegisterNode`");
         debug_assert_eq!(node.tag_name(), REGISTER);
 
-        let attr_base = node.parse(node_builder, value_builder, cache_builder);
-        let register_base = node.parse(node_builder, value_builder, cache_builder);
+        let attr_base = node.parse(node_builder, value_builder, cache_builder)?;
+        let register_base = node.parse(node_builder, value_builder, cache_builder)?;
 
         let node = Self {
             attr_base,
@@ -31,7 +31,7 @@ impl Parse for RegisterNode {
         };
         node.register_base
             .store_invalidators(node.attr_base.id, cache_builder);
-        node
+        Ok(node)
     }
 }
 
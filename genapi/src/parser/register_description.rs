@@ -15,8 +15,8 @@ use super::{
         SCHEMA_MAJOR_VERSION, SCHEMA_MINOR_VERSION, SCHEMA_SUB_MINOR_VERSION, STANDARD_NAME_SPCACE,
         SUB_MINOR_VERSION, TOOL_TIP, VENDOR_NAME, VERSION_GUID,
     },
-    elem_type::convert_to_uint,
-    xml, Parse,
+    elem_type::{convert_to_standard_name_space, convert_to_uint},
+    xml, Parse, ParseResult,
 };
 
 impl Parse for RegisterDescription {
@@ -26,27 +26,27 @@ impl Parse for RegisterDescription {
         _: &mut impl NodeStoreBuilder,
         _: &mut impl ValueStoreBuilder,
         _: &mut impl CacheStoreBuilder,
-    ) -> Self {
+    ) -> ParseResult<Self> {
         debug!("start parsing `RegisterDescription`");
         debug_assert_eq!(node.tag_name(), REGISTER_DESCRIPTION);
 
-        let model_name = node.attribute_of(MODEL_NAME).unwrap().into();
-        let vendor_name = node.attribute_of(VENDOR_NAME).unwrap().into();
+        let version_of = |name| node.convert_required_attribute(name, convert_to_uint);
+
+        let model_name = node.required_attribute(MODEL_NAME)?.into();
+        let vendor_name = node.required_attribute(VENDOR_NAME)?.into();
         let tooltip = node.attribute_of(TOOL_TIP).map(Into::into);
-        let standard_name_space = node.attribute_of(STANDARD_NAME_SPCACE).unwrap().into();
-        let schema_major_version =
-            convert_to_uint(&node.attribute_of(SCHEMA_MAJOR_VERSION).unwrap());
-        let schema_minor_version =
-            convert_to_uint(&node.attribute_of(SCHEMA_MINOR_VERSION).unwrap());
-        let schema_subminor_version =
-            convert_to_uint(&node.attribute_of(SCHEMA_SUB_MINOR_VERSION).unwrap());
-        let major_version = convert_to_uint(&node.attribute_of(MAJOR_VERSION).unwrap());
-        let minor_version = convert_to_uint(&node.attribute_of(MINOR_VERSION).unwrap());
-        let subminor_version = convert_to_uint(&node.attribute_of(SUB_MINOR_VERSION).unwrap());
-        let product_guid = node.attribute_of(PRODUCT_GUID).unwrap().into();
-        let version_guid = node.attribute_of(VERSION_GUID).unwrap().into();
-
-        Self {
+        let standard_name_space =
+            node.convert_required_attribute(STANDARD_NAME_SPCACE, convert_to_standard_name_space)?;
+        let schema_major_version = version_of(SCHEMA_MAJOR_VERSION)?;
+        let schema_minor_version = version_of(SCHEMA_MINOR_VERSION)?;
+        let schema_subminor_version = version_of(SCHEMA_SUB_MINOR_VERSION)?;
+        let major_version = version_of(MAJOR_VERSION)?;
+        let minor_version = version_of(MINOR_VERSION)?;
+        let subminor_version = version_of(SUB_MINOR_VERSION)?;
+        let product_guid = node.required_attribute(PRODUCT_GUID)?.into();
+        let version_guid = node.required_attribute(VERSION_GUID)?.into();
+
+        Ok(Self {
             model_name,
             vendor_name,
             tooltip,
@@ -59,15 +59,21 @@ impl Parse for RegisterDescription {
             subminor_version,
             product_guid,
             version_guid,
-        }
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::elem_type::StandardNameSpace;
+    use crate::{builder::GenApiBuilder, elem_type::StandardNameSpace};
 
-    use super::{super::utils::tests::parse_default, *};
+    use super::{
+        super::{
+            utils::tests::{parse_default, parse_error},
+            ParseError,
+        },
+        *,
+    };
 
     #[test]
     #[allow(clippy::too_many_lines)]
@@ -260,4 +266,140 @@ mod tests {
             "76543210-3210-3210-3210-ba9876543210"
         );
     }
+
+    #[test]
+    fn test_register_description_without_model_name() {
+        let xml = r#"
+        <RegisterDescription
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="2"
+          SubMinorVersion="3"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210">
+        </RegisterDescription>
+        "#;
+
+        let err = parse_error::<RegisterDescription>(xml);
+        assert!(matches!(
+            err,
+            ParseError::MissingAttribute { element, attribute, position }
+                if element == "RegisterDescription" && attribute == "ModelName" && position.line == 2
+        ));
+    }
+
+    #[test]
+    fn test_build_broken_xml() {
+        let xml = r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="2"
+          SubMinorVersion="3"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210">
+
+            <Integer Name="MyInt">
+                <Value>ten</Value>
+            </Integer>
+        </RegisterDescription>
+        "#;
+
+        // The error is returned to the caller of the builder instead of panicking.
+        let err = match GenApiBuilder::default().build(&xml) {
+            Ok(_) => panic!("building from the broken XML must fail"),
+            Err(err) => err,
+        };
+        assert!(matches!(
+            err,
+            ParseError::InvalidText { element, value, position }
+                if element == "Value" && value == "ten" && position.line == 16
+        ));
+    }
+
+    /// Builds a document containing `nodes`, which must fail.
+    fn build_error(nodes: &str) -> ParseError {
+        let xml = format!(
+            r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="2"
+          SubMinorVersion="3"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210">
+            {}
+        </RegisterDescription>
+        "#,
+            nodes
+        );
+        match GenApiBuilder::default().build(&xml) {
+            Ok(_) => panic!("building from the broken XML must fail: {}", nodes),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn test_build_malformed_xml() {
+        let err = build_error(r#"<Integer><Value>1</Value></Integer>"#);
+        assert!(matches!(
+            err,
+            ParseError::MissingAttribute { element, attribute, position }
+                if element == "Integer" && attribute == "Name" && position.line == 14
+        ));
+
+        let err = build_error(r#"<Integer Name="MyInt"><pMin>MinNode</pMin></Integer>"#);
+        assert!(matches!(
+            err,
+            ParseError::UnexpectedElement { element, parent, .. }
+                if element == "pMin" && parent == "Integer"
+        ));
+
+        let err = build_error(r#"<Integer Name="MyInt"></Integer>"#);
+        assert!(matches!(
+            err,
+            ParseError::MissingElement { parent, .. } if parent == "Integer"
+        ));
+
+        let err = build_error(
+            r#"<Integer Name="MyInt"><Visibility>Master</Visibility><Value>1</Value></Integer>"#,
+        );
+        assert!(matches!(
+            err,
+            ParseError::InvalidText { element, value, .. }
+                if element == "Visibility" && value == "Master"
+        ));
+
+        let err =
+            build_error(r#"<Integer Name="MyInt" NameSpace="Private"><Value>1</Value></Integer>"#);
+        assert!(matches!(
+            err,
+            ParseError::InvalidAttribute { element, attribute, value, .. }
+                if element == "Integer" && attribute == "NameSpace" && value == "Private"
+        ));
+
+        let err = build_error(
+            r#"<IntSwissKnife Name="MyKnife"><Formula>(1 + 2</Formula></IntSwissKnife>"#,
+        );
+        assert!(matches!(
+            &err,
+            ParseError::InvalidFormula { element, formula, .. }
+                if element == "Formula" && formula == "(1 + 2"
+        ));
+        assert!(err.to_string().contains("expected `)`"));
+    }
 }
@@ -0,0 +1,507 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::fmt;
+
+use super::{
+    elem_name::{SCHEMA_MAJOR_VERSION, SCHEMA_MINOR_VERSION, SCHEMA_SUB_MINOR_VERSION},
+    elem_type::convert_to_uint,
+    xml, ParseError, ParseResult,
+};
+
+/// Version of the `GenApi` schema which a XML conforms to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SchemaVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub subminor: u64,
+}
+
+impl SchemaVersion {
+    /// The newest schema version the parser supports.
+    pub const SUPPORTED: Self = Self::new(1, 1, 0);
+
+    #[must_use]
+    pub const fn new(major: u64, minor: u64, subminor: u64) -> Self {
+        Self {
+            major,
+            minor,
+            subminor,
+        }
+    }
+
+    /// Returns `true` if the version is newer than [`Self::SUPPORTED`].
+    ///
+    /// Subminor versions don't change the schema, so they are ignored.
+    #[must_use]
+    pub fn is_newer_than_supported(self) -> bool {
+        (self.major, self.minor) > (Self::SUPPORTED.major, Self::SUPPORTED.minor)
+    }
+
+    pub(super) fn from_root(root: &xml::Node) -> ParseResult<Self> {
+        let version_of = |name| {
+            root.convert_attribute(name, convert_to_uint)?
+                .ok_or(ParseError::MissingSchemaVersion)
+        };
+        Ok(Self::new(
+            version_of(SCHEMA_MAJOR_VERSION)?,
+            version_of(SCHEMA_MINOR_VERSION)?,
+            version_of(SCHEMA_SUB_MINOR_VERSION)?,
+        ))
+    }
+}
+
+impl fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.subminor)
+    }
+}
+
+/// Determines how the parser treats a XML which conforms to a schema newer than
+/// [`SchemaVersion::SUPPORTED`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaPolicy {
+    /// Fails with [`ParseError::UnsupportedSchema`].
+    Reject,
+    /// Parses the XML with the rules of the supported schema, and marks the resulting
+    /// [`CompatibilityReport`] as degraded.
+    #[default]
+    BestEffort,
+    /// Fails with [`ParseError::SchemaMismatch`] unless the XML conforms to exactly the version,
+    /// otherwise behaves as [`Self::BestEffort`].
+    Require(SchemaVersion),
+}
+
+/// Configuration of the parser.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParserConfig {
+    schema_policy: SchemaPolicy,
+    strict: bool,
+}
+
+impl ParserConfig {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the policy for a XML which conforms to a newer schema than the supported one.
+    #[must_use]
+    pub fn schema_policy(mut self, policy: SchemaPolicy) -> Self {
+        self.schema_policy = policy;
+        self
+    }
+
+    /// Makes the parser fail with [`ParseError::UnknownElement`] or
+    /// [`ParseError::UnknownAttribute`] on constructs unknown to the supported schema.
+    ///
+    /// By default, unknown elements and attributes, e.g. vendor extensions, are skipped and
+    /// reported as [`ParseWarning`]s. A XML which conforms to a newer schema and is parsed with
+    /// best effort by the schema policy is still parsed in the strict mode, its unknown
+    /// constructs are skipped.
+    #[must_use]
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub(super) fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Checks `version` against the policy and returns `true` if the XML should be parsed with
+    /// best effort.
+    pub(super) fn check(&self, version: SchemaVersion) -> ParseResult<bool> {
+        match self.schema_policy {
+            SchemaPolicy::Reject if version.is_newer_than_supported() => {
+                Err(ParseError::UnsupportedSchema(version))
+            }
+            SchemaPolicy::Require(required) if required != version => {
+                Err(ParseError::SchemaMismatch {
+                    required,
+                    found: version,
+                })
+            }
+            _ => Ok(version.is_newer_than_supported()),
+        }
+    }
+}
+
+/// Describes how well a XML is understood by the parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompatibilityReport {
+    pub(super) schema_version: SchemaVersion,
+    pub(super) degraded: bool,
+    pub(super) warnings: Vec<ParseWarning>,
+}
+
+impl CompatibilityReport {
+    /// The schema version the XML declares.
+    #[must_use]
+    pub fn schema_version(&self) -> SchemaVersion {
+        self.schema_version
+    }
+
+    /// Returns `true` if some content of the XML is skipped, i.e. the XML conforms to a newer
+    /// schema and is parsed with best effort, or unknown elements or attributes are skipped.
+    /// Some features of the device may be missing or behave differently.
+    #[must_use]
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    /// Elements and attributes skipped by the parser.
+    #[must_use]
+    pub fn warnings(&self) -> &[ParseWarning] {
+        &self.warnings
+    }
+}
+
+/// A construct of a XML skipped by the parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarning {
+    /// An element unknown to the supported schema, the whole subtree of the element is skipped.
+    UnknownElement {
+        name: String,
+        parent: String,
+        line: u32,
+    },
+    /// An attribute unknown to the supported schema.
+    UnknownAttribute {
+        name: String,
+        element: String,
+        line: u32,
+    },
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownElement { name, parent, line } => write!(
+                f,
+                "line {}: unknown element `{}` in `{}` is skipped",
+                line, name, parent
+            ),
+            Self::UnknownAttribute {
+                name,
+                element,
+                line,
+            } => write!(
+                f,
+                "line {}: unknown attribute `{}` of `{}` is ignored",
+                line, name, element
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        builder::GenApiBuilder,
+        elem_type::{ImmOrPNode, ValueKind},
+        interface::{IInteger, INode},
+        store::{NodeData, NodeStore, ValueStore},
+        Device,
+    };
+
+    use super::{super::ParseError, *};
+
+    /// A XML which conforms to the schema version 1.2.
+    fn xml_v1_2(minor: u64) -> String {
+        format!(
+            r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="{}"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="0"
+          SubMinorVersion="0"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210"
+          xmlns="http://www.genicam.org/GenApi/Version_1_2"
+          xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"
+          xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_2 GenApiSchema.xsd">
+
+            <Category Name="Root" NameSpace="Standard">
+                <pFeature>Width</pFeature>
+            </Category>
+
+            <Integer Name="Width" NameSpace="Standard" Priority="High">
+                <ToolTip>Width of the image.</ToolTip>
+                <DisplayName>Width</DisplayName>
+                <InputDirection>
+                    <Direction>In</Direction>
+                    <pSource>Source</pSource>
+                </InputDirection>
+                <Value>640</Value>
+                <Min>16</Min>
+                <Max>4096</Max>
+                <Inc>4</Inc>
+            </Integer>
+
+            <Selector Name="NewNodeType">
+                <pValue>Width</pValue>
+            </Selector>
+
+            <Port Name="Device" NameSpace="Standard">
+            </Port>
+        </RegisterDescription>
+        "#,
+            minor
+        )
+    }
+
+    fn parse(xml: &str, policy: SchemaPolicy) -> ParseResult<CompatibilityReport> {
+        let (_, node_store, value_ctxt, report) = GenApiBuilder::default()
+            .with_parser_config(ParserConfig::new().schema_policy(policy))
+            .build_with_report(&xml)?;
+
+        // Siblings of skipped elements are parsed.
+        let width = node_store.id_by_name("Width").unwrap();
+        let width = match node_store.node_opt(width).unwrap() {
+            NodeData::Integer(node) => node,
+            _ => panic!("`Width` must be an `Integer`"),
+        };
+        assert_eq!(width.node_base().display_name(), Some("Width"));
+        let value = match width.value_kind() {
+            ValueKind::Value(id) => *id,
+            _ => panic!("`Width` must have an immediate value"),
+        };
+        let min = match width.min_elem() {
+            ImmOrPNode::Imm(id) => id,
+            ImmOrPNode::PNode(..) => panic!("`Width` must have an immediate minimum"),
+        };
+        assert_eq!(value_ctxt.value_store.integer_value(value), Some(640));
+        assert_eq!(value_ctxt.value_store.integer_value(min), Some(16));
+        assert!(node_store.id_by_name("Device").is_some());
+
+        Ok(report)
+    }
+
+    #[test]
+    fn test_reject() {
+        let err = parse(&xml_v1_2(2), SchemaPolicy::Reject).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::UnsupportedSchema(version) if version == SchemaVersion::new(1, 2, 0)
+        ));
+        assert!(err.to_string().contains("1.2.0"));
+
+        let config = ParserConfig::new().schema_policy(SchemaPolicy::Reject);
+        assert!(!config.check(SchemaVersion::new(1, 1, 3)).unwrap());
+        assert!(!config.check(SchemaVersion::new(1, 0, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_best_effort() {
+        let report = parse(&xml_v1_2(2), SchemaPolicy::BestEffort).unwrap();
+        assert!(report.is_degraded());
+        assert_eq!(report.schema_version(), SchemaVersion::new(1, 2, 0));
+        assert_eq!(
+            report.warnings(),
+            &[
+                ParseWarning::UnknownAttribute {
+                    name: "Priority".into(),
+                    element: "Integer".into(),
+                    line: 22,
+                },
+                ParseWarning::UnknownElement {
+                    name: "InputDirection".into(),
+                    parent: "Integer".into(),
+                    line: 25,
+                },
+                ParseWarning::UnknownElement {
+                    name: "Selector".into(),
+                    parent: "RegisterDescription".into(),
+                    line: 35,
+                },
+            ]
+        );
+
+        // A XML conforming to the supported schema is parsed as before.
+        let config = ParserConfig::new();
+        assert!(!config.check(SchemaVersion::SUPPORTED).unwrap());
+        assert!(config.check(SchemaVersion::new(2, 0, 0)).unwrap());
+    }
+
+    #[test]
+    fn test_require() {
+        let required = SchemaVersion::new(1, 2, 0);
+        let report = parse(&xml_v1_2(2), SchemaPolicy::Require(required)).unwrap();
+        assert!(report.is_degraded());
+        assert_eq!(report.warnings().len(), 3);
+
+        let err = parse(&xml_v1_2(1), SchemaPolicy::Require(required)).unwrap_err();
+        assert!(matches!(
+            err,
+            ParseError::SchemaMismatch { required: r, found } if r == required && found == SchemaVersion::new(1, 1, 0)
+        ));
+    }
+
+    /// A XML which conforms to the supported schema, but contains vendor extensions.
+    const XML_WITH_EXTENSIONS: &str = r#"
+        <RegisterDescription
+          ModelName="CameleonModel"
+          VendorName="CameleonVendor"
+          StandardNameSpace="None"
+          SchemaMajorVersion="1"
+          SchemaMinorVersion="1"
+          SchemaSubMinorVersion="0"
+          MajorVersion="1"
+          MinorVersion="0"
+          SubMinorVersion="0"
+          ProductGuid="01234567-0123-0123-0123-0123456789ab"
+          VersionGuid="76543210-3210-3210-3210-ba9876543210">
+
+            <Category Name="Root" NameSpace="Standard">
+                <pFeature>Width</pFeature>
+                <pVendorFeature>VendorGain</pVendorFeature>
+                <pFeature>Gain</pFeature>
+            </Category>
+
+            <Integer Name="Width" NameSpace="Standard">
+                <Value>640</Value>
+            </Integer>
+
+            <IntReg Name="Gain" NameSpace="Standard" VendorCacheHint="Never">
+                <Address>0x100</Address>
+                <Length>4</Length>
+                <pPort>Device</pPort>
+                <Sign>Unsigned</Sign>
+                <Endianess>LittleEndian</Endianess>
+            </IntReg>
+
+            <Port Name="Device" NameSpace="Standard">
+            </Port>
+        </RegisterDescription>
+        "#;
+
+    /// Device whose registers all hold `42`.
+    struct Registers;
+
+    impl Device for Registers {
+        fn read_mem(&mut self, _: i64, buf: &mut [u8]) -> Result<(), Box<dyn std::error::Error>> {
+            buf.copy_from_slice(&42_u32.to_le_bytes());
+            Ok(())
+        }
+
+        fn write_mem(&mut self, _: i64, _: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+            Err("read only device".into())
+        }
+    }
+
+    #[test]
+    fn test_skip_unknown() {
+        let (_, node_store, mut value_ctxt, report) = GenApiBuilder::default()
+            .build_with_report(&XML_WITH_EXTENSIONS)
+            .unwrap();
+        // The XML conforms to the supported schema, but the skipped content degrades it.
+        assert_eq!(report.schema_version(), SchemaVersion::SUPPORTED);
+        assert!(report.is_degraded());
+        assert_eq!(
+            report.warnings(),
+            &[
+                ParseWarning::UnknownElement {
+                    name: "pVendorFeature".into(),
+                    parent: "Category".into(),
+                    line: 17,
+                },
+                ParseWarning::UnknownAttribute {
+                    name: "VendorCacheHint".into(),
+                    element: "IntReg".into(),
+                    line: 25,
+                },
+            ]
+        );
+
+        // Siblings of the skipped element are kept.
+        let width = node_store.id_by_name("Width").unwrap();
+        let gain = node_store.id_by_name("Gain").unwrap();
+        let root = node_store.id_by_name("Root").unwrap();
+        match node_store.node_opt(root).unwrap() {
+            NodeData::Category(node) => assert_eq!(node.p_features(), &[width, gain]),
+            _ => panic!("`Root` must be a `Category`"),
+        }
+
+        let mut device = Registers;
+        let width = width.expect_iinteger_kind(&node_store).unwrap();
+        assert_eq!(
+            width
+                .value(&mut device, &node_store, &mut value_ctxt)
+                .unwrap(),
+            640
+        );
+        let gain = gain.expect_iinteger_kind(&node_store).unwrap();
+        assert_eq!(
+            gain.value(&mut device, &node_store, &mut value_ctxt)
+                .unwrap(),
+            42
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let err = match GenApiBuilder::default()
+            .with_parser_config(ParserConfig::new().strict(true))
+            .build(&XML_WITH_EXTENSIONS)
+        {
+            Ok(_) => panic!("unknown elements must be rejected in the strict mode"),
+            Err(err) => err,
+        };
+        assert!(matches!(
+            err,
+            ParseError::UnknownElement { name, parent, position }
+                if name == "pVendorFeature" && parent == "Category" && position.line == 17
+        ));
+
+        // A newer schema parsed with best effort skips its unknown constructs in the strict mode.
+        let (.., report) = GenApiBuilder::default()
+            .with_parser_config(
+                ParserConfig::new()
+                    .schema_policy(SchemaPolicy::BestEffort)
+                    .strict(true),
+            )
+            .build_with_report(&xml_v1_2(2))
+            .unwrap();
+        assert!(report.is_degraded());
+        assert_eq!(report.warnings().len(), 3);
+
+        // The newer schema is still rejected by the policy.
+        let err = match GenApiBuilder::default()
+            .with_parser_config(
+                ParserConfig::new()
+                    .schema_policy(SchemaPolicy::Reject)
+                    .strict(true),
+            )
+            .build(&xml_v1_2(2))
+        {
+            Ok(_) => panic!("the newer schema must be rejected"),
+            Err(err) => err,
+        };
+        assert!(matches!(err, ParseError::UnsupportedSchema(..)));
+
+        // The strict mode applies to a XML which conforms to the supported schema.
+        let err = match GenApiBuilder::default()
+            .with_parser_config(
+                ParserConfig::new()
+                    .schema_policy(SchemaPolicy::BestEffort)
+                    .strict(true),
+            )
+            .build(&xml_v1_2(1))
+        {
+            Ok(_) => panic!("unknown elements must be rejected in the strict mode"),
+            Err(err) => err,
+        };
+        assert!(matches!(
+            err,
+            ParseError::UnknownAttribute { name, element, .. }
+                if name == "Priority" && element == "Integer"
+        ));
+    }
+}
@@ -4,7 +4,7 @@
 
 #[cfg(test)]
 pub(super) mod tests {
-    use super::super::{xml, Parse};
+    use super::super::{xml, Parse, ParseError};
     use crate::store::{DefaultCacheStore, DefaultNodeStore, DefaultValueStore};
 
     pub(in super::super) fn parse_default<T: Parse>(
@@ -18,10 +18,25 @@ pub(super) mod tests {
         (
             document
                 .root_node()
-                .parse(&mut node_builder, &mut value_builder, &mut cache_builder),
+                .parse(&mut node_builder, &mut value_builder, &mut cache_builder)
+                .unwrap(),
             node_builder,
             value_builder,
             cache_builder,
         )
     }
+
+    /// Parses `xml` as `T` and returns the error, panics if the parse succeeds.
+    pub(in super::super) fn parse_error<T: Parse>(xml: &str) -> ParseError {
+        let document = xml::Document::from_str(xml).unwrap();
+        let parsed: Result<T, _> = document.root_node().parse(
+            &mut DefaultNodeStore::new(),
+            &mut DefaultValueStore::new(),
+            &mut DefaultCacheStore::new(),
+        );
+        match parsed {
+            Ok(_) => panic!("parsing the broken XML must fail"),
+            Err(err) => err,
+        }
+    }
 }
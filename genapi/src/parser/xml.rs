@@ -2,25 +2,66 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::{fmt, iter::Peekable};
+use std::{cell::RefCell, fmt, iter::Peekable};
 
-use crate::builder::{CacheStoreBuilder, NodeStoreBuilder, ValueStoreBuilder};
+use tracing::warn;
 
-use super::{Parse, ParseResult};
+use crate::{
+    builder::{CacheStoreBuilder, NodeStoreBuilder, ValueStoreBuilder},
+    formula::FormulaError,
+};
+
+use super::{
+    elem_name::{KNOWN_ATTRIBUTES, KNOWN_ELEMENTS},
+    schema::{ParseWarning, SchemaVersion},
+    Parse, ParseError, ParseResult, TextPosition,
+};
 
 pub(super) struct Document<'input> {
     document: roxmltree::Document<'input>,
+    unknown: UnknownSink,
 }
 
 impl<'input> Document<'input> {
     pub(super) fn from_str(s: &'input str) -> ParseResult<Self> {
         let document = roxmltree::Document::parse(s)?;
-        Ok(Self { document })
+        Ok(Self {
+            document,
+            unknown: UnknownSink::default(),
+        })
+    }
+
+    /// Makes nodes of the document fail on elements and attributes unknown to the supported
+    /// schema. Otherwise, such constructs are skipped and collected as warnings.
+    pub(super) fn set_strict(&mut self, strict: bool) {
+        self.unknown.strict = strict;
+    }
+
+    pub(super) fn take_warnings(&mut self) -> Vec<ParseWarning> {
+        self.unknown.warnings.get_mut().drain(..).collect()
+    }
+
+    /// Returns the error of the first unknown construct found in the strict mode.
+    pub(super) fn take_error(&mut self) -> Option<ParseError> {
+        self.unknown.error.get_mut().take()
+    }
+
+    /// Returns the schema version declared by the root element, unknown attributes of the root
+    /// element aren't reported.
+    pub(super) fn schema_version(&self) -> ParseResult<SchemaVersion> {
+        let root = self.document.root_element();
+        SchemaVersion::from_root(&Node::from_xmltree_node(
+            root,
+            self.inner_str(),
+            &self.unknown,
+        ))
     }
 
     pub(super) fn root_node<'a>(&'a self) -> Node<'a, 'input> {
         let root = self.document.root_element();
-        Node::from_xmltree_node(root, self.inner_str())
+        let node = Node::from_xmltree_node(root, self.inner_str(), &self.unknown);
+        node.check_attributes();
+        node
     }
 
     pub(super) fn inner_str(&self) -> &'input str {
@@ -33,6 +74,7 @@ pub(super) struct Node<'a, 'input> {
     children: Peekable<roxmltree::Children<'a, 'input>>,
     attributes: Attributes<'a, 'input>,
     src: &'input str,
+    unknown: &'a UnknownSink,
 }
 
 impl<'a, 'input> Node<'a, 'input> {
@@ -41,7 +83,7 @@ impl<'a, 'input> Node<'a, 'input> {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> T {
+    ) -> ParseResult<T> {
         T::parse(self, node_builder, value_builder, cache_builder)
     }
 
@@ -51,11 +93,23 @@ impl<'a, 'input> Node<'a, 'input> {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Option<T> {
-        if self.peek()?.tag_name() == tag_name {
-            Some(self.parse(node_builder, value_builder, cache_builder))
+    ) -> ParseResult<Option<T>> {
+        self.parse_if_any(&[tag_name], node_builder, value_builder, cache_builder)
+    }
+
+    /// Same as [`Self::parse_if`], but parses the next node if its name is one of `tag_names`.
+    pub(super) fn parse_if_any<T: Parse>(
+        &mut self,
+        tag_names: &[&str],
+        node_builder: &mut impl NodeStoreBuilder,
+        value_builder: &mut impl ValueStoreBuilder,
+        cache_builder: &mut impl CacheStoreBuilder,
+    ) -> ParseResult<Option<T>> {
+        if self.peek_is_any(tag_names) {
+            self.parse(node_builder, value_builder, cache_builder)
+                .map(Some)
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -65,48 +119,70 @@ impl<'a, 'input> Node<'a, 'input> {
         node_builder: &mut impl NodeStoreBuilder,
         value_builder: &mut impl ValueStoreBuilder,
         cache_builder: &mut impl CacheStoreBuilder,
-    ) -> Vec<T> {
+    ) -> ParseResult<Vec<T>> {
         let mut res = vec![];
-        while let Some(parsed) = self.parse_if(tag_name, node_builder, value_builder, cache_builder)
+        while let Some(parsed) =
+            self.parse_if(tag_name, node_builder, value_builder, cache_builder)?
         {
             res.push(parsed);
         }
-        res
+        Ok(res)
     }
 
     pub(super) fn next(&mut self) -> Option<Self> {
         let node = self.peek()?;
         self.children.next();
+        node.check_attributes();
 
         Some(node)
     }
 
+    /// Same as [`Self::next`], but fails if the node has no more children.
+    pub(super) fn expect_next(&mut self) -> ParseResult<Self> {
+        self.next().ok_or_else(|| self.missing_element())
+    }
+
     pub(super) fn next_if(&mut self, tag_name: &str) -> Option<Self> {
-        if self.peek()?.tag_name() == tag_name {
+        if self.peek_is_any(&[tag_name]) {
             self.next()
         } else {
             None
         }
     }
 
-    pub(super) fn next_text(&mut self) -> Option<TextView<'a, 'input>> {
-        Some(self.next()?.text())
+    pub(super) fn next_text(&mut self) -> ParseResult<TextView<'a, 'input>> {
+        Ok(self.expect_next()?.text())
     }
 
     pub(super) fn peek(&mut self) -> Option<Self> {
-        let mut inner;
-        loop {
-            inner = self.children.peek()?;
+        let inner = loop {
+            let inner = *self.children.peek()?;
             if inner.node_type() == roxmltree::NodeType::Element {
-                break;
+                if KNOWN_ELEMENTS.contains(&inner.tag_name().name()) {
+                    break inner;
+                }
+                self.unknown.report(
+                    ParseWarning::UnknownElement {
+                        name: inner.tag_name().name().into(),
+                        parent: self.tag_name().into(),
+                        line: position_of(inner).line,
+                    },
+                    position_of(inner),
+                );
             }
+            // Skipping a child skips its whole subtree.
             self.children.next();
-        }
-        let node = Self::from_xmltree_node(*inner, self.src);
+        };
+        let node = Self::from_xmltree_node(inner, self.src, self.unknown);
 
         Some(node)
     }
 
+    /// Same as [`Self::peek`], but fails if the node has no more children.
+    pub(super) fn expect_peek(&mut self) -> ParseResult<Self> {
+        self.peek().ok_or_else(|| self.missing_element())
+    }
+
     pub(super) fn tag_name(&self) -> &str {
         self.inner.tag_name().name()
     }
@@ -115,11 +191,93 @@ impl<'a, 'input> Node<'a, 'input> {
         self.attributes.attribute_of(name)
     }
 
+    /// Same as [`Self::attribute_of`], but fails if the attribute is missing.
+    pub(super) fn required_attribute(&self, name: &str) -> ParseResult<&str> {
+        self.attribute_of(name)
+            .ok_or_else(|| ParseError::MissingAttribute {
+                element: self.tag_name().into(),
+                attribute: name.into(),
+                position: self.position(),
+            })
+    }
+
+    /// Converts the attribute with `f` if it exists, fails if `f` rejects the value.
+    pub(super) fn convert_attribute<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&str) -> Option<T>,
+    ) -> ParseResult<Option<T>> {
+        self.attribute_of(name)
+            .map(|value| f(value).ok_or_else(|| self.invalid_attribute(name, value)))
+            .transpose()
+    }
+
+    /// Same as [`Self::convert_attribute`], but fails if the attribute is missing.
+    pub(super) fn convert_required_attribute<T>(
+        &self,
+        name: &str,
+        f: impl FnOnce(&str) -> Option<T>,
+    ) -> ParseResult<T> {
+        let value = self.required_attribute(name)?;
+        f(value).ok_or_else(|| self.invalid_attribute(name, value))
+    }
+
+    fn invalid_attribute(&self, name: &str, value: &str) -> ParseError {
+        ParseError::InvalidAttribute {
+            element: self.tag_name().into(),
+            attribute: name.into(),
+            value: value.into(),
+            position: self.position(),
+        }
+    }
+
+    /// Returns an error which tells that the node isn't allowed in its parent.
+    pub(super) fn unexpected_element(&self) -> ParseError {
+        ParseError::UnexpectedElement {
+            element: self.tag_name().into(),
+            parent: self
+                .inner
+                .parent_element()
+                .map(|parent| parent.tag_name().name().into())
+                .unwrap_or_default(),
+            position: self.position(),
+        }
+    }
+
+    pub(super) fn position(&self) -> TextPosition {
+        position_of(self.inner)
+    }
+
     pub(super) fn text(&self) -> TextView<'a, 'input> {
         TextView { inner: self.inner }
     }
 
-    fn from_xmltree_node(node: roxmltree::Node<'a, 'input>, src: &'input str) -> Self {
+    /// Returns the node itself and its descendant elements named `tag_name` without consuming
+    /// the children.
+    pub(super) fn descendants_of<'b>(
+        &'b self,
+        tag_name: &'b str,
+    ) -> impl Iterator<Item = Self> + 'b {
+        self.inner
+            .descendants()
+            .filter(move |node| node.is_element() && node.tag_name().name() == tag_name)
+            .map(move |node| Self::from_xmltree_node(node, self.src, self.unknown))
+    }
+
+    /// Returns the text of the first child element named `tag_name` without consuming the
+    /// children.
+    pub(super) fn child_text_of(&self, tag_name: &str) -> Option<TextView<'a, 'input>> {
+        self.inner
+            .children()
+            .find(|node| node.is_element() && node.tag_name().name() == tag_name)
+            .map(|inner| TextView { inner })
+    }
+
+    fn from_xmltree_node(
+        node: roxmltree::Node<'a, 'input>,
+        src: &'input str,
+        unknown: &'a UnknownSink,
+    ) -> Self {
         debug_assert!(node.node_type() == roxmltree::NodeType::Element);
         let children = node.children().peekable();
         let attributes = Attributes::from_xmltree_attrs(node.attributes());
@@ -129,6 +287,73 @@ impl<'a, 'input> Node<'a, 'input> {
             children,
             attributes,
             src,
+            unknown,
+        }
+    }
+
+    fn peek_is_any(&mut self, tag_names: &[&str]) -> bool {
+        self.peek()
+            .is_some_and(|node| tag_names.contains(&node.tag_name()))
+    }
+
+    fn missing_element(&self) -> ParseError {
+        ParseError::MissingElement {
+            parent: self.tag_name().into(),
+            position: self.position(),
+        }
+    }
+
+    /// Reports attributes of the node unknown to the supported schema.
+    fn check_attributes(&self) {
+        for attr in self.attributes.attrs {
+            if !KNOWN_ATTRIBUTES.contains(&attr.name()) {
+                self.unknown.report(
+                    ParseWarning::UnknownAttribute {
+                        name: attr.name().into(),
+                        element: self.tag_name().into(),
+                        line: position_of(self.inner).line,
+                    },
+                    self.position(),
+                );
+            }
+        }
+    }
+}
+
+/// Collects elements and attributes unknown to the supported schema.
+#[derive(Default)]
+struct UnknownSink {
+    /// `true` if unknown constructs are errors instead of warnings.
+    strict: bool,
+    warnings: RefCell<Vec<ParseWarning>>,
+    /// The first unknown construct found in the strict mode.
+    error: RefCell<Option<ParseError>>,
+}
+
+impl UnknownSink {
+    fn report(&self, warning: ParseWarning, position: TextPosition) {
+        if !self.strict {
+            warn!("{}", warning);
+            self.warnings.borrow_mut().push(warning);
+            return;
+        }
+
+        let mut error = self.error.borrow_mut();
+        if error.is_none() {
+            *error = Some(match warning {
+                ParseWarning::UnknownElement { name, parent, .. } => ParseError::UnknownElement {
+                    name,
+                    parent,
+                    position,
+                },
+                ParseWarning::UnknownAttribute { name, element, .. } => {
+                    ParseError::UnknownAttribute {
+                        name,
+                        element,
+                        position,
+                    }
+                }
+            });
         }
     }
 }
@@ -141,6 +366,16 @@ impl<'a, 'input> fmt::Debug for Node<'a, 'input> {
     }
 }
 
+fn position_of(node: roxmltree::Node) -> TextPosition {
+    let offset = node.range().start;
+    let pos = node.document().text_pos_at(offset);
+    TextPosition {
+        offset,
+        line: pos.row,
+        column: pos.col,
+    }
+}
+
 struct Attributes<'a, 'input> {
     attrs: &'a [roxmltree::Attribute<'input>],
 }
@@ -167,7 +402,10 @@ pub(super) struct TextView<'a, 'input> {
 
 impl<'a, 'input> TextView<'a, 'input> {
     pub(super) fn view(&self) -> std::borrow::Cow<'a, str> {
-        let first_child = self.inner.first_child().unwrap();
+        let first_child = match self.inner.first_child() {
+            Some(child) => child,
+            None => return "".into(),
+        };
         if first_child.has_siblings() {
             let mut s = String::new();
             for child in self.inner.children() {
@@ -180,7 +418,31 @@ impl<'a, 'input> TextView<'a, 'input> {
             }
             s.into()
         } else {
-            first_child.text().unwrap().into()
+            first_child.text().unwrap_or_default().into()
+        }
+    }
+
+    /// Converts the text with `f`, fails if `f` rejects the text.
+    pub(super) fn convert<T>(&self, f: impl FnOnce(&str) -> Option<T>) -> ParseResult<T> {
+        f(&self.view()).ok_or_else(|| self.invalid_value())
+    }
+
+    /// Returns an error which tells that the text isn't a valid value of the element.
+    pub(super) fn invalid_value(&self) -> ParseError {
+        ParseError::InvalidText {
+            element: self.inner.tag_name().name().into(),
+            value: self.view().into(),
+            position: position_of(self.inner),
+        }
+    }
+
+    /// Returns an error which tells that the text isn't a valid formula.
+    pub(super) fn invalid_formula(&self, reason: FormulaError) -> ParseError {
+        ParseError::InvalidFormula {
+            element: self.inner.tag_name().name().into(),
+            formula: self.view().into(),
+            reason,
+            position: position_of(self.inner),
         }
     }
 }
@@ -2,9 +2,12 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use std::convert::TryFrom;
+
 use super::{
     elem_type::ImmOrPNode,
     interface::{INode, IPort},
+    ivalue::IValue,
     node_base::{NodeAttributeBase, NodeBase, NodeElementBase},
     store::{CacheStore, NodeStore, ValueStore},
     Device, GenApiError, GenApiResult, ValueCtxt,
@@ -38,7 +41,7 @@ impl PortNode {
 }
 
 impl INode for PortNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
@@ -48,7 +51,7 @@ impl INode for PortNode {
 }
 
 impl IPort for PortNode {
-    #[tracing::instrument(skip(self, device, store),
+    #[tracing::instrument(skip(self, device, store, cx),
                           level = "trace",
                           fields(node = store.name_by_id(self.node_base().id()).unwrap()))]
     fn read<T: ValueStore, U: CacheStore>(
@@ -57,14 +60,41 @@ impl IPort for PortNode {
         buf: &mut [u8],
         device: &mut impl Device,
         store: &impl NodeStore,
-        _: &mut ValueCtxt<T, U>,
+        cx: &mut ValueCtxt<T, U>,
     ) -> GenApiResult<()> {
-        if self.chunk_id.is_some() {
-            Err(GenApiError::chunk_data_missing())
+        if let Some(chunk_id) = &self.chunk_id {
+            let chunk_id = match chunk_id {
+                ImmOrPNode::Imm(id) => *id,
+                ImmOrPNode::PNode(nid) => {
+                    let id: i64 = nid.value(device, store, cx)?;
+                    id as u64
+                }
+            };
+            let data = device
+                .chunk_data(chunk_id)
+                .ok_or_else(GenApiError::chunk_data_missing)?;
+
+            // `address` is the offset in the chunk data.
+            let range = usize::try_from(address)
+                .ok()
+                .and_then(|start| Some(start..start.checked_add(buf.len())?))
+                .filter(|range| range.end <= data.len())
+                .ok_or_else(|| {
+                    GenApiError::invalid_buffer(
+                        format!(
+                            "{} bytes at {} run past chunk {:#x} of {} bytes",
+                            buf.len(),
+                            address,
+                            chunk_id,
+                            data.len()
+                        )
+                        .into(),
+                    )
+                })?;
+            buf.copy_from_slice(&data[range]);
+            Ok(())
         } else {
-            device
-                .read_mem(address, buf)
-                .map_err(|e| GenApiError::device(e))
+            device.read_mem(address, buf).map_err(GenApiError::device)
         }
     }
 
@@ -85,9 +115,7 @@ impl IPort for PortNode {
             // TODO: Implement chunk parser.
             todo!()
         } else {
-            device
-                .write_mem(address, buf)
-                .map_err(|e| GenApiError::device(e))
+            device.write_mem(address, buf).map_err(GenApiError::device)
         }
     }
 }
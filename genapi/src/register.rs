@@ -24,7 +24,7 @@ impl RegisterNode {
 }
 
 impl INode for RegisterNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         let elem_base = &self.register_base.elem_base;
         NodeBase::new(&self.attr_base, elem_base)
     }
@@ -76,8 +76,8 @@ impl RegisterBase {
     ) -> GenApiResult<R> {
         let length = self.length(device, store, cx)?;
         let address = self.address(device, store, cx)?;
-        if let Some(cache) = cx.get_cache(nid, length, address) {
-            f(cache)
+        if let Some(cache) = cx.get_cache(nid, address, length) {
+            f(&cache)
         } else {
             let mut buf = vec![0; length as usize];
             self.read_and_cache(nid, address, length, &mut buf, device, store, cx)?;
@@ -105,7 +105,7 @@ impl RegisterBase {
             .expect_iport_kind(store)?
             .read(address, buf, device, store, cx)?;
         if self.cacheable != CachingMode::NoCache {
-            cx.cache_data(nid, address, length, &buf);
+            cx.cache_data(nid, address, length, buf);
         }
 
         Ok(())
@@ -133,7 +133,7 @@ impl RegisterBase {
             .write(address, buf, device, store, cx)?;
 
         if self.cacheable == CachingMode::WriteThrough {
-            cx.cache_data(nid, address, length, &buf);
+            cx.cache_data(nid, address, length, buf);
         }
         Ok(())
     }
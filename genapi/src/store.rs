@@ -2,7 +2,15 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::{collections::HashMap, convert::TryFrom};
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
 
 use auto_impl::auto_impl;
 use string_interner::{StringInterner, Symbol};
@@ -115,7 +123,7 @@ pub trait ValueStore {
 pub trait CacheStore {
     fn cache(&mut self, nid: NodeId, address: i64, length: i64, data: &[u8]);
 
-    fn get_cache(&self, nid: NodeId, address: i64, length: i64) -> Option<&[u8]>;
+    fn get_cache(&self, nid: NodeId, address: i64, length: i64) -> Option<CachedData<'_>>;
 
     fn invalidate_by(&mut self, nid: NodeId);
 
@@ -124,6 +132,39 @@ pub trait CacheStore {
     fn clear(&mut self);
 }
 
+/// Data returned by [`CacheStore::get_cache`], the cached bytes are never copied.
+#[derive(Debug, Clone)]
+pub enum CachedData<'a> {
+    /// Data borrowed from the store.
+    Borrowed(&'a [u8]),
+    /// Data shared with the store, returned by stores which can't lend their data, e.g. stores
+    /// guarded by a lock.
+    Shared(Arc<[u8]>),
+}
+
+impl Deref for CachedData<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Borrowed(data) => data,
+            Self::Shared(data) => data,
+        }
+    }
+}
+
+impl<'a> From<&'a [u8]> for CachedData<'a> {
+    fn from(data: &'a [u8]) -> Self {
+        Self::Borrowed(data)
+    }
+}
+
+impl From<Arc<[u8]>> for CachedData<'_> {
+    fn from(data: Arc<[u8]>) -> Self {
+        Self::Shared(data)
+    }
+}
+
 impl Symbol for NodeId {
     fn try_from_usize(index: usize) -> Option<Self> {
         if ((u32::MAX - 1) as usize) < index {
@@ -144,111 +185,111 @@ impl NodeId {
         store.name_by_id(self).unwrap()
     }
 
-    pub fn as_inode_kind(self, store: &impl NodeStore) -> Option<INodeKind> {
+    pub fn as_inode_kind(self, store: &impl NodeStore) -> Option<INodeKind<'_>> {
         INodeKind::maybe_from(self, store)
     }
 
-    pub fn expect_inode_kind(self, store: &impl NodeStore) -> GenApiResult<INodeKind> {
+    pub fn expect_inode_kind(self, store: &impl NodeStore) -> GenApiResult<INodeKind<'_>> {
         self.as_inode_kind(store).ok_or_else(|| {
             GenApiError::invalid_node("the node doesn't implement `IInteger`".into())
         })
     }
 
-    pub fn as_iinteger_kind(self, store: &impl NodeStore) -> Option<IIntegerKind> {
+    pub fn as_iinteger_kind(self, store: &impl NodeStore) -> Option<IIntegerKind<'_>> {
         IIntegerKind::maybe_from(self, store)
     }
 
-    pub fn expect_iinteger_kind(self, store: &impl NodeStore) -> GenApiResult<IIntegerKind> {
+    pub fn expect_iinteger_kind(self, store: &impl NodeStore) -> GenApiResult<IIntegerKind<'_>> {
         self.as_iinteger_kind(store).ok_or_else(|| {
             GenApiError::invalid_node("the node doesn't implement `IInteger`".into())
         })
     }
 
-    pub fn as_ifloat_kind(self, store: &impl NodeStore) -> Option<IFloatKind> {
+    pub fn as_ifloat_kind(self, store: &impl NodeStore) -> Option<IFloatKind<'_>> {
         IFloatKind::maybe_from(self, store)
     }
 
-    pub fn expect_ifloat_kind(self, store: &impl NodeStore) -> GenApiResult<IFloatKind> {
+    pub fn expect_ifloat_kind(self, store: &impl NodeStore) -> GenApiResult<IFloatKind<'_>> {
         self.as_ifloat_kind(store)
             .ok_or_else(|| GenApiError::invalid_node("the node doesn't implement `IFloat`".into()))
     }
 
-    pub fn as_istring_kind(self, store: &impl NodeStore) -> Option<IStringKind> {
+    pub fn as_istring_kind(self, store: &impl NodeStore) -> Option<IStringKind<'_>> {
         IStringKind::maybe_from(self, store)
     }
 
-    pub fn expect_istring_kind(self, store: &impl NodeStore) -> GenApiResult<IStringKind> {
+    pub fn expect_istring_kind(self, store: &impl NodeStore) -> GenApiResult<IStringKind<'_>> {
         self.as_istring_kind(store)
             .ok_or_else(|| GenApiError::invalid_node("the node doesn't implement `IString`".into()))
     }
 
-    pub fn as_icommand_kind(self, store: &impl NodeStore) -> Option<ICommandKind> {
+    pub fn as_icommand_kind(self, store: &impl NodeStore) -> Option<ICommandKind<'_>> {
         ICommandKind::maybe_from(self, store)
     }
 
-    pub fn expect_icommand_kind(self, store: &impl NodeStore) -> GenApiResult<ICommandKind> {
+    pub fn expect_icommand_kind(self, store: &impl NodeStore) -> GenApiResult<ICommandKind<'_>> {
         self.as_icommand_kind(store).ok_or_else(|| {
             GenApiError::invalid_node("the node doesn't implement `ICommand`".into())
         })
     }
 
-    pub fn as_ienumeration_kind(self, store: &impl NodeStore) -> Option<IEnumerationKind> {
+    pub fn as_ienumeration_kind(self, store: &impl NodeStore) -> Option<IEnumerationKind<'_>> {
         IEnumerationKind::maybe_from(self, store)
     }
 
     pub fn expect_ienumeration_kind(
         self,
         store: &impl NodeStore,
-    ) -> GenApiResult<IEnumerationKind> {
+    ) -> GenApiResult<IEnumerationKind<'_>> {
         self.as_ienumeration_kind(store).ok_or_else(|| {
             GenApiError::invalid_node("the node doesn't implement `IEnumeration`".into())
         })
     }
 
-    pub fn as_iboolean_kind(self, store: &impl NodeStore) -> Option<IBooleanKind> {
+    pub fn as_iboolean_kind(self, store: &impl NodeStore) -> Option<IBooleanKind<'_>> {
         IBooleanKind::maybe_from(self, store)
     }
 
-    pub fn expect_iboolean_kind(self, store: &impl NodeStore) -> GenApiResult<IBooleanKind> {
+    pub fn expect_iboolean_kind(self, store: &impl NodeStore) -> GenApiResult<IBooleanKind<'_>> {
         self.as_iboolean_kind(store).ok_or_else(|| {
             GenApiError::invalid_node("the node doesn't implement `IBoolean`".into())
         })
     }
 
-    pub fn as_iregister_kind(self, store: &impl NodeStore) -> Option<IRegisterKind> {
+    pub fn as_iregister_kind(self, store: &impl NodeStore) -> Option<IRegisterKind<'_>> {
         IRegisterKind::maybe_from(self, store)
     }
 
-    pub fn expect_iregister_kind(self, store: &impl NodeStore) -> GenApiResult<IRegisterKind> {
+    pub fn expect_iregister_kind(self, store: &impl NodeStore) -> GenApiResult<IRegisterKind<'_>> {
         self.as_iregister_kind(store).ok_or_else(|| {
             GenApiError::invalid_node("the node doesn't implement `IRegister`".into())
         })
     }
 
-    pub fn as_icategory_kind(self, store: &impl NodeStore) -> Option<ICategoryKind> {
+    pub fn as_icategory_kind(self, store: &impl NodeStore) -> Option<ICategoryKind<'_>> {
         ICategoryKind::maybe_from(self, store)
     }
 
-    pub fn expect_icategory_kind(self, store: &impl NodeStore) -> GenApiResult<ICategoryKind> {
+    pub fn expect_icategory_kind(self, store: &impl NodeStore) -> GenApiResult<ICategoryKind<'_>> {
         self.as_icategory_kind(store).ok_or_else(|| {
             GenApiError::invalid_node("the node doesn't implement `ICategory`".into())
         })
     }
 
-    pub fn as_iport_kind(self, store: &impl NodeStore) -> Option<IPortKind> {
+    pub fn as_iport_kind(self, store: &impl NodeStore) -> Option<IPortKind<'_>> {
         IPortKind::maybe_from(self, store)
     }
 
-    pub fn expect_iport_kind(self, store: &impl NodeStore) -> GenApiResult<IPortKind> {
+    pub fn expect_iport_kind(self, store: &impl NodeStore) -> GenApiResult<IPortKind<'_>> {
         self.as_iport_kind(store)
             .ok_or_else(|| GenApiError::invalid_node("the node doesn't implement `IPort`".into()))
     }
 
-    pub fn as_iselector_kind(self, store: &impl NodeStore) -> Option<ISelectorKind> {
+    pub fn as_iselector_kind(self, store: &impl NodeStore) -> Option<ISelectorKind<'_>> {
         ISelectorKind::maybe_from(self, store)
     }
 
-    pub fn expect_iselector_kind(self, store: &impl NodeStore) -> GenApiResult<ISelectorKind> {
+    pub fn expect_iselector_kind(self, store: &impl NodeStore) -> GenApiResult<ISelectorKind<'_>> {
         self.as_iselector_kind(store).ok_or_else(|| {
             GenApiError::invalid_node("the node doesn't implement `ISelector`".into())
         })
@@ -409,7 +450,7 @@ impl_value_data_conversion!(f64, Self::Float);
 impl_value_data_conversion!(String, Self::Str);
 impl_value_data_conversion!(bool, Self::Boolean);
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct DefaultValueStore(Vec<ValueData>);
 
 impl DefaultValueStore {
@@ -501,8 +542,9 @@ impl CacheStore for DefaultCacheStore {
             });
     }
 
-    fn get_cache(&self, nid: NodeId, address: i64, length: i64) -> Option<&[u8]> {
-        Some(self.store.get(&nid)?.get(&(address, length))?.as_ref())
+    fn get_cache(&self, nid: NodeId, address: i64, length: i64) -> Option<CachedData<'_>> {
+        let data: &[u8] = self.store.get(&nid)?.get(&(address, length))?;
+        Some(data.into())
     }
 
     fn invalidate_by(&mut self, nid: NodeId) {
@@ -552,7 +594,7 @@ impl builder::CacheStoreBuilder for CacheSink {
 impl CacheStore for CacheSink {
     fn cache(&mut self, _: NodeId, _: i64, _: i64, _: &[u8]) {}
 
-    fn get_cache(&self, _: NodeId, _: i64, _: i64) -> Option<&[u8]> {
+    fn get_cache(&self, _: NodeId, _: i64, _: i64) -> Option<CachedData<'_>> {
         None
     }
 
@@ -562,3 +604,343 @@ impl CacheStore for CacheSink {
 
     fn clear(&mut self) {}
 }
+
+/// Value store which can be shared between threads.
+///
+/// Each context works on a [`ValueStoreSnapshot`] obtained by [`Self::snapshot`], so readers never
+/// block each other. Updates made on a snapshot are visible to the snapshot immediately, and
+/// visible to snapshots obtained after [`Self::commit`] of the snapshot.
+///
+/// Values are copied on write per entry. A snapshot keeps its updates aside from the shared
+/// values, and a commit replaces only the updated entries, so the other values are never copied.
+#[derive(Debug, Clone, Default)]
+pub struct SharedValueStore {
+    current: Arc<RwLock<Arc<SharedValues>>>,
+}
+
+type SharedValues = Vec<Arc<ValueData>>;
+
+impl SharedValueStore {
+    /// Returns a snapshot of the latest committed values.
+    #[must_use]
+    pub fn snapshot(&self) -> ValueStoreSnapshot {
+        let store = self
+            .current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        ValueStoreSnapshot {
+            store,
+            updates: HashMap::new(),
+        }
+    }
+
+    /// Applies updates made on `snapshot` to the latest values.
+    ///
+    /// Updates of the same value are applied in the order of commits.
+    pub fn commit(&self, snapshot: ValueStoreSnapshot) {
+        if snapshot.updates.is_empty() {
+            return;
+        }
+
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        // Only pointers to the values are copied when another snapshot shares the values.
+        let values = Arc::make_mut(&mut current);
+        for (id, value) in snapshot.updates {
+            values[id.0 as usize] = Arc::new(value);
+        }
+    }
+}
+
+impl From<DefaultValueStore> for SharedValueStore {
+    fn from(store: DefaultValueStore) -> Self {
+        let values = store.0.into_iter().map(Arc::new).collect();
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(values))),
+        }
+    }
+}
+
+/// A snapshot of [`SharedValueStore`].
+#[derive(Debug)]
+pub struct ValueStoreSnapshot {
+    store: Arc<SharedValues>,
+    /// Values updated on the snapshot, which take precedence over `store`.
+    updates: HashMap<ValueId, ValueData>,
+}
+
+impl ValueStore for ValueStoreSnapshot {
+    fn value_opt<T>(&self, id: T) -> Option<&ValueData>
+    where
+        T: Into<ValueId>,
+    {
+        let id = id.into();
+        self.updates
+            .get(&id)
+            .or_else(|| self.store.get(id.0 as usize).map(AsRef::as_ref))
+    }
+
+    fn update<T, U>(&mut self, id: T, value: U) -> Option<ValueData>
+    where
+        T: Into<ValueId>,
+        U: Into<ValueData>,
+    {
+        let id = id.into();
+        let shared = self.store.get(id.0 as usize)?;
+        let old = self
+            .updates
+            .insert(id, value.into())
+            .unwrap_or_else(|| ValueData::clone(shared));
+        Some(old)
+    }
+}
+
+/// The number of shards of [`ShardedCacheStore`].
+const CACHE_SHARDS: usize = 16;
+
+/// Cache store which can be shared between threads.
+///
+/// Cached data is split into shards by node id, and each shard is guarded by its own
+/// [`RwLock`]. Cache hits only take the read side of a shard, so concurrent readers don't block
+/// each other, and an invalidation only takes the write side of the shards of the affected nodes.
+///
+/// Each context accesses the store through a [`ShardedCacheHandle`] obtained by [`Self::handle`].
+///
+/// # Consistency
+///
+/// * Once an invalidation returns, no handle returns the invalidated data.
+/// * Data cached by a handle is returned to all handles, including the handle itself, as soon as
+///   [`CacheStore::cache`] returns.
+/// * Data is cached by a handle only if the node hasn't been invalidated since the handle is
+///   obtained. So a read from the device which races with an invalidation never leaves stale data
+///   in the cache.
+#[derive(Debug, Clone, Default)]
+pub struct ShardedCacheStore {
+    inner: Arc<ShardedCache>,
+}
+
+impl ShardedCacheStore {
+    /// Returns a handle to access the store.
+    #[must_use]
+    pub fn handle(&self) -> ShardedCacheHandle {
+        ShardedCacheHandle {
+            inner: self.inner.clone(),
+            epoch: self.inner.epoch.load(Ordering::Acquire),
+        }
+    }
+}
+
+impl From<DefaultCacheStore> for ShardedCacheStore {
+    fn from(store: DefaultCacheStore) -> Self {
+        let inner = ShardedCache {
+            invalidators: store.invalidators,
+            ..ShardedCache::default()
+        };
+        for (nid, data) in store.store {
+            inner.write_shard(nid).entries.insert(
+                nid,
+                CacheEntry {
+                    data: data
+                        .into_iter()
+                        .map(|(key, data)| (key, data.into()))
+                        .collect(),
+                    invalidated_at: 0,
+                },
+            );
+        }
+
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+/// A handle of [`ShardedCacheStore`].
+#[derive(Debug)]
+pub struct ShardedCacheHandle {
+    inner: Arc<ShardedCache>,
+    /// The epoch of the store when the handle is obtained.
+    epoch: u64,
+}
+
+impl CacheStore for ShardedCacheHandle {
+    fn cache(&mut self, nid: NodeId, address: i64, length: i64, data: &[u8]) {
+        let mut shard = self.inner.write_shard(nid);
+        // Checked while the shard is locked, so that a concurrent invalidation either sees the
+        // cached data or the data isn't cached at all.
+        if self.inner.cleared_at.load(Ordering::Acquire) > self.epoch {
+            return;
+        }
+        let entry = shard.entries.entry(nid).or_default();
+        if entry.invalidated_at <= self.epoch {
+            entry.data.insert((address, length), data.into());
+        }
+    }
+
+    fn get_cache(&self, nid: NodeId, address: i64, length: i64) -> Option<CachedData<'_>> {
+        let shard = self.inner.read_shard(nid);
+        let data = shard.entries.get(&nid)?.data.get(&(address, length))?;
+        Some(data.clone().into())
+    }
+
+    fn invalidate_by(&mut self, nid: NodeId) {
+        if let Some(target_nodes) = self.inner.invalidators.get(&nid) {
+            for nid in target_nodes {
+                self.inner.invalidate(*nid);
+            }
+        }
+    }
+
+    fn invalidate_of(&mut self, nid: NodeId) {
+        self.inner.invalidate(nid);
+    }
+
+    fn clear(&mut self) {
+        let epoch = self.inner.next_epoch();
+        self.inner.cleared_at.store(epoch, Ordering::Release);
+        for shard in &self.inner.shards {
+            shard
+                .write()
+                .unwrap_or_else(PoisonError::into_inner)
+                .entries
+                .clear();
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ShardedCache {
+    shards: Vec<RwLock<CacheShard>>,
+    invalidators: HashMap<NodeId, Vec<NodeId>>,
+    /// Incremented on each invalidation.
+    epoch: AtomicU64,
+    /// The epoch when the whole cache is cleared last time.
+    cleared_at: AtomicU64,
+}
+
+impl Default for ShardedCache {
+    fn default() -> Self {
+        Self {
+            shards: (0..CACHE_SHARDS)
+                .map(|_| RwLock::new(CacheShard::default()))
+                .collect(),
+            invalidators: HashMap::new(),
+            epoch: AtomicU64::new(0),
+            cleared_at: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ShardedCache {
+    fn invalidate(&self, nid: NodeId) {
+        let epoch = self.next_epoch();
+        let mut shard = self.write_shard(nid);
+        let entry = shard.entries.entry(nid).or_default();
+        entry.data.clear();
+        entry.invalidated_at = epoch;
+    }
+
+    fn next_epoch(&self) -> u64 {
+        self.epoch.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    fn read_shard(&self, nid: NodeId) -> RwLockReadGuard<'_, CacheShard> {
+        // Shards are always consistent, so it's safe to ignore poisoning.
+        self.shards[Self::shard_of(nid)]
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_shard(&self, nid: NodeId) -> RwLockWriteGuard<'_, CacheShard> {
+        self.shards[Self::shard_of(nid)]
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn shard_of(nid: NodeId) -> usize {
+        nid.0 as usize % CACHE_SHARDS
+    }
+}
+
+#[derive(Debug, Default)]
+struct CacheShard {
+    entries: HashMap<NodeId, CacheEntry>,
+}
+
+#[derive(Debug, Default)]
+struct CacheEntry {
+    data: HashMap<(i64, i64), Arc<[u8]>>,
+    /// The epoch when the entry is invalidated last time.
+    invalidated_at: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sharded_cache_consistency() {
+        let store = ShardedCacheStore::default();
+        let nid = NodeId(1);
+
+        // Data read before an invalidation isn't cached after the invalidation.
+        let mut before = store.handle();
+        store.handle().invalidate_of(nid);
+        before.cache(nid, 0, 4, &[1; 4]);
+        assert!(before.get_cache(nid, 0, 4).is_none());
+
+        // Data cached after the invalidation is visible to all handles.
+        let mut after = store.handle();
+        after.cache(nid, 0, 4, &[2; 4]);
+        assert_eq!(after.get_cache(nid, 0, 4).as_deref(), Some(&[2; 4][..]));
+        assert_eq!(before.get_cache(nid, 0, 4).as_deref(), Some(&[2; 4][..]));
+
+        // Clearing the cache applies to nodes which are never cached.
+        let mut before = store.handle();
+        store.handle().clear();
+        assert!(after.get_cache(nid, 0, 4).is_none());
+        before.cache(NodeId(2), 0, 4, &[3; 4]);
+        assert!(before.get_cache(NodeId(2), 0, 4).is_none());
+    }
+
+    #[test]
+    fn test_shared_value_store() {
+        let mut builder = DefaultValueStore::new();
+        let id: IntegerId = builder::ValueStoreBuilder::store(&mut builder, 1);
+        let store = SharedValueStore::from(builder);
+
+        let mut writer = store.snapshot();
+        let reader = store.snapshot();
+        writer.update(id, 2);
+        // Updates are visible to the snapshot itself immediately.
+        assert_eq!(writer.integer_value(id), Some(2));
+        assert_eq!(reader.integer_value(id), Some(1));
+
+        store.commit(writer);
+        assert_eq!(reader.integer_value(id), Some(1));
+        assert_eq!(store.snapshot().integer_value(id), Some(2));
+    }
+
+    #[test]
+    fn test_snapshot_updates_entry() {
+        let mut builder = DefaultValueStore::new();
+        let updated: IntegerId = builder::ValueStoreBuilder::store(&mut builder, 1);
+        let untouched: StringId = builder::ValueStoreBuilder::store(&mut builder, "a".to_string());
+        let store = SharedValueStore::from(builder);
+
+        let reader = store.snapshot();
+        let mut writer = store.snapshot();
+        assert_eq!(writer.update(updated, 2), Some(ValueData::Integer(1)));
+        assert_eq!(writer.update(updated, 3), Some(ValueData::Integer(2)));
+        store.commit(writer);
+
+        // Entries which aren't updated are shared by all snapshots.
+        let latest = store.snapshot();
+        assert!(std::ptr::eq(
+            reader.value(untouched),
+            latest.value(untouched)
+        ));
+        assert_eq!(reader.integer_value(updated), Some(1));
+        assert_eq!(latest.integer_value(updated), Some(3));
+    }
+}
@@ -33,7 +33,7 @@ impl StringNode {
 }
 
 impl INode for StringNode {
-    fn node_base(&self) -> NodeBase {
+    fn node_base(&self) -> NodeBase<'_> {
         NodeBase::new(&self.attr_base, &self.elem_base)
     }
 
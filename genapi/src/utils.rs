@@ -4,6 +4,11 @@
 
 use std::{borrow::Cow, collections::HashMap, convert::TryInto};
 
+use cameleon_impl::{
+    byteorder::{BE, LE},
+    float,
+};
+
 use super::{
     elem_type::{Endianness, NamedValue, Sign},
     formula::EvaluationResult,
@@ -76,15 +81,11 @@ pub(super) fn bytes_from_int(
 }
 
 pub(super) fn float_from_slice(slice: &[u8], endianness: Endianness) -> GenApiResult<f64> {
-    match (slice.len(), endianness) {
-        (8, Endianness::LE) => Ok(f64::from_le_bytes(slice.try_into().unwrap())),
-        (8, Endianness::BE) => Ok(f64::from_be_bytes(slice.try_into().unwrap())),
-        (4, Endianness::LE) => Ok(f64::from(f32::from_le_bytes(slice.try_into().unwrap()))),
-        (4, Endianness::BE) => Ok(f64::from(f32::from_be_bytes(slice.try_into().unwrap()))),
-        _ => Err(GenApiError::invalid_buffer(
-            "buffer length must be either 4/8 to convert to f64".into(),
-        )),
+    match endianness {
+        Endianness::LE => float::read_float::<LE>(slice),
+        Endianness::BE => float::read_float::<BE>(slice),
     }
+    .map_err(float_error)
 }
 
 pub(super) fn bytes_from_float(
@@ -92,18 +93,17 @@ pub(super) fn bytes_from_float(
     buf: &mut [u8],
     endianness: Endianness,
 ) -> GenApiResult<()> {
-    match (buf.len(), endianness) {
-        (8, Endianness::LE) | (4, Endianness::LE) => {
-            buf.copy_from_slice(&value.to_le_bytes());
-            Ok(())
-        }
-        (8, Endianness::BE) | (4, Endianness::BE) => {
-            buf.copy_from_slice(&value.to_be_bytes());
-            Ok(())
-        }
-        _ => Err(GenApiError::invalid_buffer(
-            "buffer length must be either 4/8 to convert from f64".into(),
-        )),
+    match endianness {
+        Endianness::LE => float::write_float::<LE>(value, buf),
+        Endianness::BE => float::write_float::<BE>(value, buf),
+    }
+    .map_err(float_error)
+}
+
+fn float_error(err: float::FloatError) -> GenApiError {
+    match err {
+        float::FloatError::InvalidLength(..) => GenApiError::invalid_buffer(err.to_string().into()),
+        float::FloatError::OutOfRange { .. } => GenApiError::invalid_data(err.to_string().into()),
     }
 }
 
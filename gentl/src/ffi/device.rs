@@ -2,43 +2,63 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::{convert::TryInto, ops::Deref, sync::Mutex};
+use std::{
+    convert::TryInto,
+    ffi::CStr,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use crate::imp::device::{DeviceInfoCmd, DeviceInfoValue};
 
 use super::{
-    copy_info, imp, interface, GenTlError, GenTlResult, ModuleHandle, GC_ERROR, INFO_DATATYPE,
+    copy_info, imp, interface,
+    stream::{DataStreamRef, DS_HANDLE},
+    CopyTo, GenTlError, GenTlResult, ModuleHandle, GC_ERROR, INFO_DATATYPE,
 };
 
 pub(super) type DEV_HANDLE = *mut libc::c_void;
 pub(super) type PORT_HANDLE = *mut libc::c_void;
-pub(super) type DS_HANDLE = *mut libc::c_void;
 
 #[derive(Clone, Copy)]
 pub(super) struct DeviceModuleRef<'a> {
     inner: &'a Mutex<dyn imp::device::Device>,
     parent_if: super::interface::IF_HANDLE,
     remote_handle: PORT_HANDLE,
+    /// Access the device module is opened with by this handle.
+    access_flag: imp::device::DeviceAccessFlag,
 }
 
 impl<'a> DeviceModuleRef<'a> {
     pub(super) fn new(
         inner: &'a Mutex<dyn imp::device::Device>,
         parent_if: interface::IF_HANDLE,
+        access_flag: imp::device::DeviceAccessFlag,
     ) -> GenTlResult<Self> {
         let dev_guard = inner.lock().unwrap();
 
+        let mut remote_port = dev_guard.remote_device()?;
+        if access_flag == imp::device::DeviceAccessFlag::ReadOnly {
+            remote_port = Arc::new(imp::port::ReadOnlyPort::new(remote_port)?);
+        }
         let remote_device = RemoteDeviceRef {
-            inner: dev_guard.remote_device()?,
+            inner: remote_port,
+            parent_if,
         };
 
-        let remote_handle =
-            unsafe { Box::new(ModuleHandle::RemoteDevice(remote_device)).into_raw() };
+        let remote_handle = unsafe { ModuleHandle::RemoteDevice(remote_device).into_raw()? };
 
         Ok(Self {
             inner,
             parent_if,
             remote_handle,
+            access_flag,
         })
     }
+
+    pub(super) fn parent_if(&self) -> interface::IF_HANDLE {
+        self.parent_if
+    }
 }
 
 impl<'a> Deref for DeviceModuleRef<'a> {
@@ -49,16 +69,27 @@ impl<'a> Deref for DeviceModuleRef<'a> {
     }
 }
 
-#[derive(Clone, Copy)]
-pub(super) struct RemoteDeviceRef<'a> {
-    inner: &'a Mutex<dyn imp::port::Port>,
+/// The remote device is shared with the device module, so the handle stays valid even after the
+/// device is closed, though any access to it fails then.
+#[derive(Clone)]
+pub(super) struct RemoteDeviceRef {
+    inner: Arc<dyn imp::port::SharedPort>,
+    /// The remote device is released with the interface because its handle is issued before the
+    /// handle of the device module.
+    parent_if: interface::IF_HANDLE,
 }
 
-impl<'a> Deref for RemoteDeviceRef<'a> {
-    type Target = Mutex<dyn imp::port::Port>;
+impl RemoteDeviceRef {
+    pub(super) fn parent_if(&self) -> interface::IF_HANDLE {
+        self.parent_if
+    }
+}
+
+impl Deref for RemoteDeviceRef {
+    type Target = dyn imp::port::SharedPort;
 
     fn deref(&self) -> &Self::Target {
-        self.inner
+        self.inner.as_ref()
     }
 }
 
@@ -69,46 +100,8 @@ pub(super) fn dev_get_info(
     pBuffer: *mut libc::c_void,
     piSize: *mut libc::size_t,
 ) -> GenTlResult<()> {
-    let dev_guard = dev.lock().unwrap();
-    let info_data_type = match iInfoCmd {
-        DEVICE_INFO_CMD::DEVICE_INFO_ID => copy_info(dev_guard.device_id(), pBuffer, piSize),
-
-        DEVICE_INFO_CMD::DEVICE_INFO_VENDOR => {
-            copy_info(dev_guard.vendor_name()?.as_str(), pBuffer, piSize)
-        }
-
-        DEVICE_INFO_CMD::DEVICE_INFO_MODEL => {
-            copy_info(dev_guard.model_name()?.as_str(), pBuffer, piSize)
-        }
-
-        DEVICE_INFO_CMD::DEVICE_INFO_TLTYPE => copy_info(dev_guard.tl_type(), pBuffer, piSize),
-
-        DEVICE_INFO_CMD::DEVICE_INFO_DISPLAYNAME => {
-            copy_info(dev_guard.display_name()?.as_str(), pBuffer, piSize)
-        }
-
-        DEVICE_INFO_CMD::DEVICE_INFO_ACCESS_STATUS => {
-            copy_info(dev_guard.device_access_status(), pBuffer, piSize)
-        }
-
-        DEVICE_INFO_CMD::DEVICE_INFO_USER_DEFINED_NAME => {
-            copy_info(dev_guard.user_defined_name()?.as_str(), pBuffer, piSize)
-        }
-
-        DEVICE_INFO_CMD::DEVICE_INFO_SERIAL_NUMBER => {
-            copy_info(dev_guard.serial_number()?.as_str(), pBuffer, piSize)
-        }
-
-        DEVICE_INFO_CMD::DEVICE_INFO_VERSION => {
-            copy_info(dev_guard.device_version()?.as_str(), pBuffer, piSize)
-        }
-
-        DEVICE_INFO_CMD::DEVICE_INFO_TIMESTAMP_FREQUENCY => {
-            copy_info(dev_guard.timespamp_frequency()?, pBuffer, piSize)
-        }
-
-        _ => Err(GenTlError::InvalidParameter),
-    }?;
+    let value = dev.lock().unwrap().device_info(iInfoCmd.try_into()?)?;
+    let info_data_type = copy_device_info(value, pBuffer, piSize)?;
 
     unsafe {
         *piType = info_data_type;
@@ -117,6 +110,19 @@ pub(super) fn dev_get_info(
     Ok(())
 }
 
+fn copy_device_info(
+    value: DeviceInfoValue,
+    pBuffer: *mut libc::c_void,
+    piSize: *mut libc::size_t,
+) -> GenTlResult<INFO_DATATYPE> {
+    match value {
+        DeviceInfoValue::String(value) => copy_info(value.as_str(), pBuffer, piSize),
+        DeviceInfoValue::UInt64(value) => copy_info(value, pBuffer, piSize),
+        DeviceInfoValue::TlType(value) => copy_info(value, pBuffer, piSize),
+        DeviceInfoValue::AccessStatus(value) => copy_info(value, pBuffer, piSize),
+    }
+}
+
 newtype_enum! {
     pub enum DEVICE_INFO_CMD {
         DEVICE_INFO_ID = 0,
@@ -130,6 +136,30 @@ newtype_enum! {
         DEVICE_INFO_VERSION = 8,
         DEVICE_INFO_TIMESTAMP_FREQUENCY = 9,
         DEVICE_INFO_CUSTOM_ID = 1000,
+        // Producer specific, link speed of the device in bits per second.
+        DEVICE_INFO_LINK_SPEED = 1001,
+    }
+}
+
+impl TryInto<DeviceInfoCmd> for DEVICE_INFO_CMD {
+    type Error = GenTlError;
+
+    fn try_into(self) -> GenTlResult<DeviceInfoCmd> {
+        match self {
+            Self::DEVICE_INFO_ID => Ok(DeviceInfoCmd::Id),
+            Self::DEVICE_INFO_VENDOR => Ok(DeviceInfoCmd::Vendor),
+            Self::DEVICE_INFO_MODEL => Ok(DeviceInfoCmd::Model),
+            Self::DEVICE_INFO_TLTYPE => Ok(DeviceInfoCmd::TlType),
+            Self::DEVICE_INFO_DISPLAYNAME => Ok(DeviceInfoCmd::DisplayName),
+            Self::DEVICE_INFO_ACCESS_STATUS => Ok(DeviceInfoCmd::AccessStatus),
+            Self::DEVICE_INFO_USER_DEFINED_NAME => Ok(DeviceInfoCmd::UserDefinedName),
+            Self::DEVICE_INFO_SERIAL_NUMBER => Ok(DeviceInfoCmd::SerialNumber),
+            Self::DEVICE_INFO_VERSION => Ok(DeviceInfoCmd::Version),
+            Self::DEVICE_INFO_TIMESTAMP_FREQUENCY => Ok(DeviceInfoCmd::TimestampFrequency),
+            Self::DEVICE_INFO_LINK_SPEED => Ok(DeviceInfoCmd::LinkSpeed),
+            // Commands added in newer GenTL versions aren't known to this producer.
+            _ => Err(GenTlError::NotAvailable),
+        }
     }
 }
 
@@ -160,23 +190,18 @@ impl TryInto<imp::device::DeviceAccessFlag> for DEVICE_ACCESS_FLAGS {
 
 gentl_api! {
     pub fn DevClose(hDevice: DEV_HANDLE) -> GenTlResult<()> {
-        let mut handle = unsafe { ModuleHandle::from_raw_manually_drop(hDevice)? };
+        let handle = ModuleHandle::from_raw(hDevice)?;
         let dev_handle = handle.device()?;
 
         // Close the device module.
-        dev_handle.lock().unwrap().close()?;
+        dev_handle.lock().unwrap().close(dev_handle.access_flag)?;
 
-        // Drop remote device handle.
+        // Release remote device handle.
         // This seems weired but there is no function to close remote device in GenTL API.
-        unsafe {
-                let mut remote_handle = ModuleHandle::from_raw_manually_drop(dev_handle.remote_handle)?;
-                std::mem::ManuallyDrop::drop(&mut remote_handle);
-        }
+        ModuleHandle::release(dev_handle.remote_handle)?;
 
-        // Drop the device handle.
-        unsafe {
-            std::mem::ManuallyDrop::drop(&mut handle)
-        }
+        // Release the device handle, handles of its data streams and events are released too.
+        ModuleHandle::release(hDevice)?;
 
         Ok(())
     }
@@ -190,7 +215,7 @@ gentl_api! {
         pBuffer: *mut libc::c_void,
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hDevice)? };
+        let handle = ModuleHandle::from_raw(hDevice)?;
         let dev_handle = handle.device()?;
 
         dev_get_info(dev_handle, iInfoCmd, piType, pBuffer, piSize)
@@ -204,20 +229,34 @@ gentl_api! {
         sDataStreamID: *mut libc::c_char,
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        todo!()
+        let handle = ModuleHandle::from_raw(hDevice)?;
+        let dev_handle = handle.device()?;
+
+        let dev_guard = dev_handle.lock().unwrap();
+        dev_guard
+            .data_stream_id(iIndex as usize)?
+            .copy_to(sDataStreamID, piSize)
     }
 }
 
 gentl_api! {
     pub fn DevGetNumDataStreams(hDevice: DEV_HANDLE, piNumDataStreams: *mut u32) -> GenTlResult<()>
     {
-        todo!()
+        let handle = ModuleHandle::from_raw(hDevice)?;
+        let dev_handle = handle.device()?;
+
+        let num_data_streams = dev_handle.lock().unwrap().num_data_streams()?;
+        unsafe {
+            *piNumDataStreams = num_data_streams as u32;
+        }
+
+        Ok(())
     }
 }
 
 gentl_api! {
     pub fn DevGetPort(hDevice: DEV_HANDLE, phRemoteDevice: *mut PORT_HANDLE) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hDevice)? };
+        let handle = ModuleHandle::from_raw(hDevice)?;
         let dev_handle = handle.device()?;
 
         unsafe {
@@ -234,13 +273,23 @@ gentl_api! {
         sDataStreamID: *const ::std::os::raw::c_char,
         phDataStream: *mut DS_HANDLE,
     ) -> GenTlResult<()> {
-        todo!()
+        let handle = ModuleHandle::from_raw(hDevice)?;
+        let dev_handle = handle.device()?;
+
+        let mut dev_guard = dev_handle.lock().unwrap();
+        let id = unsafe { CStr::from_ptr(sDataStreamID) }.to_string_lossy();
+        let stream = DataStreamRef::new(dev_guard.open_data_stream(&id)?, hDevice);
+        unsafe {
+            *phDataStream = ModuleHandle::DataStream(stream).into_raw()?;
+        }
+
+        Ok(())
     }
 }
 
 gentl_api! {
     pub fn DevGetParentIF(hDevice: DEV_HANDLE, phIface: *mut interface::IF_HANDLE) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hDevice)? };
+        let handle = ModuleHandle::from_raw(hDevice)?;
         let dev_handle = handle.device()?;
 
         unsafe {
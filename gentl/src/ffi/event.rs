@@ -0,0 +1,500 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Event functions of GenTL.
+//!
+//! Data of an event is delivered to the consumer in the layout below, and
+//! [`EventGetDataInfo`] parses the data in the same layout.
+//!
+//! | Event type                 | Layout                                            |
+//! |----------------------------|---------------------------------------------------|
+//! | `EVENT_ERROR`              | `GC_ERROR`, followed by the null terminated text  |
+//! | `EVENT_NEW_BUFFER`         | [`EVENT_NEW_BUFFER_DATA`]                         |
+//! | `EVENT_FEATURE_INVALIDATE` | Null terminated feature name                      |
+//! | `EVENT_FEATURE_CHANGE`     | Null terminated feature name and value            |
+//! | `EVENT_REMOTE_DEVICE`      | `uint64_t` event ID, followed by the event data   |
+//! | `EVENT_MODULE`             | `uint64_t` event ID, followed by the event data   |
+
+use std::{
+    convert::{TryFrom, TryInto},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::imp::{
+    event::{EventData, EventQueue, EventSource, EventType},
+    stream::{BufferHandle, DataStream},
+};
+
+use super::{
+    copy_info, stream, CopyTo, GenTlError, GenTlResult, ModuleHandle, GC_ERROR, GENTL_INFINITE,
+    INFO_DATATYPE,
+};
+
+pub(super) type EVENTSRC_HANDLE = *mut libc::c_void;
+pub(super) type EVENT_HANDLE = *mut libc::c_void;
+
+#[derive(Clone)]
+pub(super) struct EventRef<'a> {
+    queue: Arc<EventQueue>,
+    source: EVENTSRC_HANDLE,
+    /// Data stream firing the event, buffers of new buffer events are delivered through it.
+    stream: Option<&'a Mutex<dyn DataStream>>,
+}
+
+impl<'a> EventRef<'a> {
+    pub(super) fn source(&self) -> EVENTSRC_HANDLE {
+        self.source
+    }
+}
+
+/// Data of `EVENT_NEW_BUFFER`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct EVENT_NEW_BUFFER_DATA {
+    BufferHandle: stream::BUFFER_HANDLE,
+    pUserPointer: *mut libc::c_void,
+}
+
+newtype_enum! {
+    pub enum EVENT_TYPE {
+        EVENT_ERROR = 0,
+        EVENT_NEW_BUFFER = 1,
+        EVENT_FEATURE_INVALIDATE = 2,
+        EVENT_FEATURE_CHANGE = 3,
+        EVENT_REMOTE_DEVICE = 4,
+        EVENT_MODULE = 5,
+        EVENT_CUSTOM_ID = 1000,
+    }
+}
+
+impl TryInto<EventType> for EVENT_TYPE {
+    type Error = GenTlError;
+
+    fn try_into(self) -> GenTlResult<EventType> {
+        match self {
+            Self::EVENT_ERROR => Ok(EventType::Error),
+            Self::EVENT_NEW_BUFFER => Ok(EventType::NewBuffer),
+            Self::EVENT_FEATURE_INVALIDATE => Ok(EventType::FeatureInvalidate),
+            Self::EVENT_FEATURE_CHANGE => Ok(EventType::FeatureChange),
+            Self::EVENT_REMOTE_DEVICE => Ok(EventType::RemoteDevice),
+            Self::EVENT_MODULE => Ok(EventType::Module),
+            _ => Err(GenTlError::NotImplemented),
+        }
+    }
+}
+
+impl From<EventType> for EVENT_TYPE {
+    fn from(event_type: EventType) -> Self {
+        match event_type {
+            EventType::Error => Self::EVENT_ERROR,
+            EventType::NewBuffer => Self::EVENT_NEW_BUFFER,
+            EventType::FeatureInvalidate => Self::EVENT_FEATURE_INVALIDATE,
+            EventType::FeatureChange => Self::EVENT_FEATURE_CHANGE,
+            EventType::RemoteDevice => Self::EVENT_REMOTE_DEVICE,
+            EventType::Module => Self::EVENT_MODULE,
+        }
+    }
+}
+
+newtype_enum! {
+    pub enum EVENT_INFO_CMD {
+        EVENT_EVENT_TYPE = 0,
+        EVENT_NUM_IN_QUEUE = 1,
+        EVENT_NUM_FIRED = 2,
+        EVENT_SIZE_MAX = 3,
+        EVENT_INFO_DATA_SIZE_MAX = 4,
+        EVENT_INFO_CUSTOM_ID = 1000,
+    }
+}
+
+newtype_enum! {
+    pub enum EVENT_DATA_INFO_CMD {
+        EVENT_DATA_ID = 0,
+        EVENT_DATA_VALUE = 1,
+        EVENT_DATA_NUMID = 2,
+        EVENT_DATA_CUSTOM_ID = 1000,
+    }
+}
+
+fn timeout(iTimeout: u64) -> Option<Duration> {
+    if iTimeout == GENTL_INFINITE {
+        None
+    } else {
+        Some(Duration::from_millis(iTimeout))
+    }
+}
+
+/// Serializes `event` in the layout described in the module document.
+fn event_bytes(event: &EventData) -> Vec<u8> {
+    fn push_str(bytes: &mut Vec<u8>, s: &str) {
+        bytes.extend_from_slice(s.as_bytes());
+        bytes.push(0);
+    }
+
+    let mut bytes = vec![];
+    match event {
+        EventData::Error(err) => {
+            bytes.extend_from_slice(&GC_ERROR::from(err).0.to_ne_bytes());
+            push_str(&mut bytes, &err.to_string());
+        }
+        EventData::NewBuffer { buffer, user_data } => {
+            let data = EVENT_NEW_BUFFER_DATA {
+                BufferHandle: stream::raw_buffer_handle(*buffer),
+                pUserPointer: *user_data as *mut libc::c_void,
+            };
+            // SAFETY: `EVENT_NEW_BUFFER_DATA` consists of pointers, so it has no padding.
+            bytes.extend_from_slice(unsafe {
+                std::slice::from_raw_parts(
+                    (&data as *const EVENT_NEW_BUFFER_DATA).cast::<u8>(),
+                    std::mem::size_of::<EVENT_NEW_BUFFER_DATA>(),
+                )
+            });
+        }
+        EventData::FeatureInvalidate { feature } => push_str(&mut bytes, feature),
+        EventData::FeatureChange { feature, value } => {
+            push_str(&mut bytes, feature);
+            push_str(&mut bytes, value);
+        }
+        EventData::RemoteDevice { event_id, data } | EventData::Module { event_id, data } => {
+            bytes.extend_from_slice(&event_id.to_ne_bytes());
+            bytes.extend_from_slice(data);
+        }
+    }
+    bytes
+}
+
+/// Splits `bytes` at the first null, and returns the string before it and the rest.
+fn split_str(bytes: &[u8]) -> GenTlResult<(&str, &[u8])> {
+    let invalid = || GenTlError::InvalidParameter;
+    let nul = bytes.iter().position(|b| *b == 0).ok_or_else(invalid)?;
+    let s = std::str::from_utf8(&bytes[..nul]).map_err(|_| invalid())?;
+    Ok((s, &bytes[nul + 1..]))
+}
+
+/// Splits `bytes` after `N` bytes.
+fn split_array<const N: usize>(bytes: &[u8]) -> GenTlResult<([u8; N], &[u8])> {
+    let head = bytes.get(..N).ok_or(GenTlError::InvalidParameter)?;
+    Ok((<[u8; N]>::try_from(head).unwrap(), &bytes[N..]))
+}
+
+fn event_data_info(
+    event_type: EventType,
+    data: &[u8],
+    cmd: EVENT_DATA_INFO_CMD,
+    pBuffer: *mut libc::c_void,
+    piSize: *mut libc::size_t,
+) -> GenTlResult<INFO_DATATYPE> {
+    match (event_type, cmd) {
+        (EventType::Error, EVENT_DATA_INFO_CMD::EVENT_DATA_ID) => {
+            let (code, _) = split_array(data)?;
+            copy_info(i32::from_ne_bytes(code), pBuffer, piSize)
+        }
+        (EventType::Error, EVENT_DATA_INFO_CMD::EVENT_DATA_VALUE) => {
+            let (_, text) = split_array::<4>(data)?;
+            copy_info(split_str(text)?.0, pBuffer, piSize)
+        }
+
+        (EventType::NewBuffer, EVENT_DATA_INFO_CMD::EVENT_DATA_ID)
+        | (EventType::NewBuffer, EVENT_DATA_INFO_CMD::EVENT_DATA_VALUE) => {
+            if data.len() < std::mem::size_of::<EVENT_NEW_BUFFER_DATA>() {
+                return Err(GenTlError::InvalidParameter);
+            }
+            // SAFETY: The length is checked above, and any bit pattern is valid for pointers.
+            let data = unsafe {
+                data.as_ptr()
+                    .cast::<EVENT_NEW_BUFFER_DATA>()
+                    .read_unaligned()
+            };
+            if cmd == EVENT_DATA_INFO_CMD::EVENT_DATA_ID {
+                copy_info(data.BufferHandle, pBuffer, piSize)
+            } else {
+                copy_info(data.pUserPointer, pBuffer, piSize)
+            }
+        }
+
+        (EventType::FeatureInvalidate, EVENT_DATA_INFO_CMD::EVENT_DATA_ID)
+        | (EventType::FeatureChange, EVENT_DATA_INFO_CMD::EVENT_DATA_ID) => {
+            copy_info(split_str(data)?.0, pBuffer, piSize)
+        }
+        (EventType::FeatureChange, EVENT_DATA_INFO_CMD::EVENT_DATA_VALUE) => {
+            let (_, rest) = split_str(data)?;
+            copy_info(split_str(rest)?.0, pBuffer, piSize)
+        }
+
+        (EventType::RemoteDevice, EVENT_DATA_INFO_CMD::EVENT_DATA_ID)
+        | (EventType::Module, EVENT_DATA_INFO_CMD::EVENT_DATA_ID) => {
+            let (event_id, _) = split_array(data)?;
+            let event_id = format!("{:X}", u64::from_ne_bytes(event_id));
+            copy_info(event_id.as_str(), pBuffer, piSize)
+        }
+        (EventType::RemoteDevice, EVENT_DATA_INFO_CMD::EVENT_DATA_NUMID)
+        | (EventType::Module, EVENT_DATA_INFO_CMD::EVENT_DATA_NUMID) => {
+            let (event_id, _) = split_array(data)?;
+            copy_info(u64::from_ne_bytes(event_id), pBuffer, piSize)
+        }
+        (EventType::RemoteDevice, EVENT_DATA_INFO_CMD::EVENT_DATA_VALUE)
+        | (EventType::Module, EVENT_DATA_INFO_CMD::EVENT_DATA_VALUE) => {
+            let (_, value) = split_array::<8>(data)?;
+            copy_info(value, pBuffer, piSize)
+        }
+
+        _ => Err(GenTlError::InvalidParameter),
+    }
+}
+
+gentl_api! {
+    pub fn GCRegisterEvent(
+        hEventSrc: EVENTSRC_HANDLE,
+        iEventID: EVENT_TYPE,
+        phEvent: *mut EVENT_HANDLE,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hEventSrc)?;
+        let event_type = iEventID.try_into()?;
+
+        let (queue, stream) = match &handle {
+            ModuleHandle::Interface(iface) => (iface.lock().unwrap().register_event(event_type)?, None),
+            ModuleHandle::Device(dev) => (dev.lock().unwrap().register_event(event_type)?, None),
+            ModuleHandle::DataStream(stream) => (
+                stream.lock().unwrap().register_event(event_type)?,
+                Some(stream.module()),
+            ),
+            ModuleHandle::System(..) => return Err(GenTlError::NotImplemented),
+            _ => return Err(GenTlError::InvalidHandle),
+        };
+
+        let event = EventRef {
+            queue,
+            source: hEventSrc,
+            stream,
+        };
+        unsafe {
+            *phEvent = ModuleHandle::Event(event).into_raw()?;
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn GCUnregisterEvent(hEventSrc: EVENTSRC_HANDLE, iEventID: EVENT_TYPE) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hEventSrc)?;
+        let event_type = iEventID.try_into()?;
+
+        match &handle {
+            ModuleHandle::Interface(iface) => iface.lock().unwrap().unregister_event(event_type)?,
+            ModuleHandle::Device(dev) => dev.lock().unwrap().unregister_event(event_type)?,
+            ModuleHandle::DataStream(stream) => stream.lock().unwrap().unregister_event(event_type)?,
+            ModuleHandle::System(..) => return Err(GenTlError::NotImplemented),
+            _ => return Err(GenTlError::InvalidHandle),
+        }
+
+        ModuleHandle::release_where(&mut |handle| {
+            matches!(
+                handle,
+                ModuleHandle::Event(event)
+                    if event.source == hEventSrc && event.queue.event_type() == event_type
+            )
+        });
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn EventGetData(
+        hEvent: EVENT_HANDLE,
+        pBuffer: *mut libc::c_void,
+        piSize: *mut libc::size_t,
+        iTimeout: u64,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hEvent)?;
+        let event = handle.event()?;
+
+        let data = event.queue.get_data(timeout(iTimeout))?;
+        let bytes = event_bytes(&data);
+        // Keep the event in the queue if the consumer only queries the size or the buffer is too
+        // small.
+        let res = bytes.as_slice().copy_to(pBuffer.cast::<u8>(), piSize);
+        if res.is_err() || pBuffer.is_null() {
+            event.queue.requeue(data);
+            return res;
+        }
+
+        match (data, event.stream) {
+            (EventData::NewBuffer { buffer, .. }, Some(stream)) => {
+                stream.lock().unwrap().deliver_buffer(buffer)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+gentl_api! {
+    pub fn EventGetDataInfo(
+        hEvent: EVENT_HANDLE,
+        pInBuffer: *const libc::c_void,
+        iInSize: libc::size_t,
+        iInfoCmd: EVENT_DATA_INFO_CMD,
+        piType: *mut INFO_DATATYPE,
+        pOutBuffer: *mut libc::c_void,
+        piOutSize: *mut libc::size_t,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hEvent)?;
+        let event = handle.event()?;
+
+        if pInBuffer.is_null() {
+            return Err(GenTlError::InvalidParameter);
+        }
+        let data = unsafe { std::slice::from_raw_parts(pInBuffer.cast::<u8>(), iInSize) };
+        let info_data_type =
+            event_data_info(event.queue.event_type(), data, iInfoCmd, pOutBuffer, piOutSize)?;
+
+        unsafe {
+            *piType = info_data_type;
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn EventGetInfo(
+        hEvent: EVENT_HANDLE,
+        iInfoCmd: EVENT_INFO_CMD,
+        piType: *mut INFO_DATATYPE,
+        pBuffer: *mut libc::c_void,
+        piSize: *mut libc::size_t,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hEvent)?;
+        let event = handle.event()?;
+
+        let queue = &event.queue;
+        let info_data_type = match iInfoCmd {
+            EVENT_INFO_CMD::EVENT_EVENT_TYPE => {
+                copy_info(EVENT_TYPE::from(queue.event_type()).0, pBuffer, piSize)
+            }
+
+            EVENT_INFO_CMD::EVENT_NUM_IN_QUEUE => copy_info(queue.num_in_queue(), pBuffer, piSize),
+
+            EVENT_INFO_CMD::EVENT_NUM_FIRED => copy_info(queue.num_fired(), pBuffer, piSize),
+
+            // Only the data of new buffer events has a fixed size.
+            EVENT_INFO_CMD::EVENT_SIZE_MAX if queue.event_type() == EventType::NewBuffer => {
+                copy_info(std::mem::size_of::<EVENT_NEW_BUFFER_DATA>(), pBuffer, piSize)
+            }
+            EVENT_INFO_CMD::EVENT_SIZE_MAX | EVENT_INFO_CMD::EVENT_INFO_DATA_SIZE_MAX => {
+                Err(GenTlError::NotAvailable)
+            }
+
+            _ => Err(GenTlError::InvalidParameter),
+        }?;
+
+        unsafe {
+            *piType = info_data_type;
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn EventFlush(hEvent: EVENT_HANDLE) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hEvent)?;
+        let event = handle.event()?;
+
+        event.queue.flush();
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn EventKill(hEvent: EVENT_HANDLE) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hEvent)?;
+        let event = handle.event()?;
+
+        event.queue.kill();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_info_string(event_type: EventType, data: &[u8], cmd: EVENT_DATA_INFO_CMD) -> String {
+        let mut buf = [0_u8; 64];
+        let mut size = buf.len();
+        let info_data_type =
+            event_data_info(event_type, data, cmd, buf.as_mut_ptr().cast(), &mut size).unwrap();
+        assert!(info_data_type == INFO_DATATYPE::INFO_DATATYPE_STRING);
+        String::from_utf8(buf[..size - 1].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_event_data_layout() {
+        let data = event_bytes(&EventData::FeatureChange {
+            feature: "Width".into(),
+            value: "128".into(),
+        });
+        let cmd = EVENT_DATA_INFO_CMD::EVENT_DATA_ID;
+        assert_eq!(
+            data_info_string(EventType::FeatureChange, &data, cmd),
+            "Width"
+        );
+        let cmd = EVENT_DATA_INFO_CMD::EVENT_DATA_VALUE;
+        assert_eq!(
+            data_info_string(EventType::FeatureChange, &data, cmd),
+            "128"
+        );
+
+        let data = event_bytes(&EventData::Module {
+            event_id: 0x1f,
+            data: vec![1, 2],
+        });
+        let cmd = EVENT_DATA_INFO_CMD::EVENT_DATA_ID;
+        assert_eq!(data_info_string(EventType::Module, &data, cmd), "1F");
+        let mut value = [0_u8; 2];
+        let mut size = value.len();
+        event_data_info(
+            EventType::Module,
+            &data,
+            EVENT_DATA_INFO_CMD::EVENT_DATA_VALUE,
+            value.as_mut_ptr().cast(),
+            &mut size,
+        )
+        .unwrap();
+        assert_eq!(value, [1, 2]);
+
+        let data = event_bytes(&EventData::NewBuffer {
+            buffer: BufferHandle::from_raw(3),
+            user_data: 7,
+        });
+        assert_eq!(data.len(), std::mem::size_of::<EVENT_NEW_BUFFER_DATA>());
+        let mut user_ptr = std::ptr::null_mut::<libc::c_void>();
+        let mut size = std::mem::size_of_val(&user_ptr);
+        event_data_info(
+            EventType::NewBuffer,
+            &data,
+            EVENT_DATA_INFO_CMD::EVENT_DATA_VALUE,
+            (&mut user_ptr as *mut *mut libc::c_void).cast(),
+            &mut size,
+        )
+        .unwrap();
+        assert_eq!(user_ptr as usize, 7);
+
+        // Truncated data is rejected.
+        let mut size = 0;
+        assert!(matches!(
+            event_data_info(
+                EventType::Module,
+                &[0; 4],
+                EVENT_DATA_INFO_CMD::EVENT_DATA_NUMID,
+                std::ptr::null_mut(),
+                &mut size,
+            ),
+            Err(GenTlError::InvalidParameter)
+        ));
+    }
+}
@@ -0,0 +1,183 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Table of module handles passed to the consumer.
+//!
+//! A raw handle encodes an index into the table and the generation of the slot at the time the
+//! handle was issued. A slot's generation is bumped whenever its handle is released, so a handle
+//! kept by the consumer after the module is closed is detected as stale instead of touching freed
+//! state.
+//!
+//! Handles derived from a module, e.g. data streams of a device, are released together with the
+//! module because they are freed when the module is closed.
+
+use std::sync::Mutex;
+
+use crate::{GenTlError, GenTlResult};
+
+use super::ModuleHandle;
+
+/// Number of low bits of a raw handle used for the slot index.
+const INDEX_BITS: u32 = 16;
+const INDEX_MASK: usize = (1 << INDEX_BITS) - 1;
+const MAX_SLOTS: usize = INDEX_MASK;
+const MAX_GENERATION: usize = usize::MAX >> INDEX_BITS;
+
+lazy_static::lazy_static! {
+    pub(super) static ref HANDLE_TABLE: Mutex<HandleTable> = Mutex::new(HandleTable::new());
+}
+
+struct Slot {
+    generation: usize,
+    handle: Option<ModuleHandle<'static>>,
+}
+
+pub(super) struct HandleTable {
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+}
+
+impl HandleTable {
+    fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Registers `handle` and returns its raw handle.
+    pub(super) fn insert(&mut self, handle: ModuleHandle<'static>) -> GenTlResult<usize> {
+        let index = if let Some(index) = self.free.pop() {
+            index
+        } else if self.slots.len() < MAX_SLOTS {
+            self.slots.push(Slot {
+                generation: 0,
+                handle: None,
+            });
+            self.slots.len() - 1
+        } else {
+            return Err(GenTlError::ResourceExhausted);
+        };
+
+        self.slots[index].handle = Some(handle);
+        Ok(self.raw_of(index))
+    }
+
+    /// Returns the handle registered as `raw`.
+    pub(super) fn get(&self, raw: usize) -> GenTlResult<ModuleHandle<'static>> {
+        let index = self.index_of(raw)?;
+        self.slots[index]
+            .handle
+            .clone()
+            .ok_or(GenTlError::InvalidHandle)
+    }
+
+    /// Unregisters the handle registered as `raw` and the handles derived from it, see
+    /// [`ModuleHandle::parent`]. Their raw handles become stale.
+    pub(super) fn remove(&mut self, raw: usize) -> GenTlResult<ModuleHandle<'static>> {
+        let index = self.index_of(raw)?;
+        let handle = self.slots[index]
+            .handle
+            .take()
+            .ok_or(GenTlError::InvalidHandle)?;
+        self.release_slot(index);
+        self.remove_children(raw);
+        Ok(handle)
+    }
+
+    /// Unregisters the handles satisfying `pred` and the handles derived from them.
+    pub(super) fn remove_where(&mut self, pred: &mut dyn FnMut(&ModuleHandle<'static>) -> bool) {
+        let mut removed = vec![];
+        for index in 0..self.slots.len() {
+            if self.slots[index].handle.as_ref().is_some_and(&mut *pred) {
+                removed.push(self.raw_of(index));
+                self.slots[index].handle = None;
+                self.release_slot(index);
+            }
+        }
+
+        for raw in removed {
+            self.remove_children(raw);
+        }
+    }
+
+    /// Unregisters all handles, all raw handles issued so far become stale.
+    pub(super) fn clear(&mut self) {
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.handle.take().is_some() {
+                Self::retire(slot);
+                self.free.push(index);
+            }
+        }
+    }
+
+    fn remove_children(&mut self, raw: usize) {
+        self.remove_where(&mut |handle| handle.parent().map(|parent| parent as usize) == Some(raw));
+    }
+
+    fn release_slot(&mut self, index: usize) {
+        Self::retire(&mut self.slots[index]);
+        self.free.push(index);
+    }
+
+    fn raw_of(&self, index: usize) -> usize {
+        // Index is offset by one so that a raw handle is never null.
+        self.slots[index].generation << INDEX_BITS | (index + 1)
+    }
+
+    fn index_of(&self, raw: usize) -> GenTlResult<usize> {
+        let index = (raw & INDEX_MASK)
+            .checked_sub(1)
+            .ok_or(GenTlError::InvalidHandle)?;
+        match self.slots.get(index) {
+            Some(slot) if slot.generation == raw >> INDEX_BITS => Ok(index),
+            _ => Err(GenTlError::InvalidHandle),
+        }
+    }
+
+    fn retire(slot: &mut Slot) {
+        slot.generation = if slot.generation == MAX_GENERATION {
+            0
+        } else {
+            slot.generation + 1
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ffi::system::SYSTEM_MODULE;
+
+    fn system_handle() -> ModuleHandle<'static> {
+        ModuleHandle::System(SYSTEM_MODULE.as_ref())
+    }
+
+    #[test]
+    fn test_stale_handle() {
+        let mut table = HandleTable::new();
+        let first = table.insert(system_handle()).unwrap();
+        assert_ne!(first, 0);
+        assert!(table.get(first).unwrap().system().is_ok());
+
+        table.remove(first).unwrap();
+        assert!(matches!(table.get(first), Err(GenTlError::InvalidHandle)));
+        assert!(matches!(
+            table.remove(first),
+            Err(GenTlError::InvalidHandle)
+        ));
+
+        // The slot is reused with a new generation, the old handle is still stale.
+        let second = table.insert(system_handle()).unwrap();
+        assert_eq!(first & INDEX_MASK, second & INDEX_MASK);
+        assert_ne!(first, second);
+        assert!(table.get(second).is_ok());
+        assert!(matches!(table.get(first), Err(GenTlError::InvalidHandle)));
+
+        table.clear();
+        assert!(matches!(table.get(second), Err(GenTlError::InvalidHandle)));
+        assert!(matches!(table.get(0), Err(GenTlError::InvalidHandle)));
+    }
+}
@@ -26,13 +26,17 @@ impl<'a> InterfaceModuleRef<'a> {
     ) -> Self {
         Self { inner, parent_tl }
     }
+
+    pub(super) fn parent_tl(&self) -> super::system::TL_HANDLE {
+        self.parent_tl
+    }
 }
 
 impl<'a> std::ops::Deref for InterfaceModuleRef<'a> {
     type Target = Mutex<dyn imp::interface::Interface>;
 
     fn deref(&self) -> &Self::Target {
-        &self.inner
+        self.inner
     }
 }
 
@@ -82,15 +86,13 @@ newtype_enum! {
 
 gentl_api! {
     pub fn IFClose(hIface: IF_HANDLE) -> GenTlResult<()> {
-        let mut handle = unsafe { ModuleHandle::from_raw_manually_drop(hIface)? };
+        let handle = ModuleHandle::from_raw(hIface)?;
         let iface_handle = handle.interface()?;
 
         // Close the interface module.
         iface_handle.lock().unwrap().close()?;
-        // Drop its handle.
-        unsafe {
-            std::mem::ManuallyDrop::drop(&mut handle);
-        }
+        // Release its handle, handles of devices opened through the interface are released too.
+        ModuleHandle::release(hIface)?;
 
         Ok(())
     }
@@ -104,7 +106,7 @@ gentl_api! {
         pBuffer: *mut libc::c_void,
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hIface)? };
+        let handle = ModuleHandle::from_raw(hIface)?;
         let iface = handle.interface()?;
 
         if_get_info(iface, iInfoCmd, piType, pBuffer, piSize)
@@ -118,7 +120,7 @@ gentl_api! {
         sIDeviceID: *mut libc::c_char,
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hIface)? };
+        let handle = ModuleHandle::from_raw(hIface)?;
         let iface = handle.interface()?;
 
         let iface_guard = iface.lock().unwrap();
@@ -142,7 +144,7 @@ gentl_api! {
         pBuffer: *mut libc::c_void,
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hIface)? };
+        let handle = ModuleHandle::from_raw(hIface)?;
         let iface = handle.interface()?;
 
         let iface_guard = iface.lock().unwrap();
@@ -155,7 +157,7 @@ gentl_api! {
 
 gentl_api! {
     pub fn IFGetNumDevices(hIface: IF_HANDLE, piNumDevices: *mut u32) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hIface)? };
+        let handle = ModuleHandle::from_raw(hIface)?;
         let iface = handle.interface()?;
 
         let device_num = iface.lock().unwrap().devices().len();
@@ -174,18 +176,18 @@ gentl_api! {
         iOpenFlag: device::DEVICE_ACCESS_FLAGS,
         phDevice: *mut device::DEV_HANDLE,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hIface)? };
+        let handle = ModuleHandle::from_raw(hIface)?;
         let iface = handle.interface()?;
 
         let iface_guard = iface.lock().unwrap();
         let id = unsafe { CStr::from_ptr(sDeviceID) }.to_string_lossy();
         let device = iface_guard.device_by_id(&id)?;
 
-        device.lock().unwrap().open(iOpenFlag.try_into()?)?;
-        let device = DeviceModuleRef::new(device, hIface)?;
-        let device_handle = Box::new(ModuleHandle::Device(device));
+        let access_flag = iOpenFlag.try_into()?;
+        device.lock().unwrap().open(access_flag)?;
+        let device = DeviceModuleRef::new(device, hIface, access_flag)?;
         unsafe {
-            *phDevice = device_handle.into_raw();
+            *phDevice = ModuleHandle::Device(device).into_raw()?;
         }
 
         Ok(())
@@ -198,7 +200,7 @@ gentl_api! {
         pbChanged: *mut bool8_t,
         iTimeout: u64,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hIface)? };
+        let handle = ModuleHandle::from_raw(hIface)?;
         let iface = handle.interface()?;
 
         let mut iface_guard = iface.lock().unwrap();
@@ -215,7 +217,7 @@ gentl_api! {
 
 gentl_api! {
     pub fn IFGetParentTL(hIface: IF_HANDLE, phSystem: *mut system::TL_HANDLE) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hIface)? };
+        let handle = ModuleHandle::from_raw(hIface)?;
         let iface = handle.interface()?;
 
         unsafe {
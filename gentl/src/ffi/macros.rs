@@ -15,7 +15,9 @@ macro_rules! gentl_api {
                 $body
             }
 
-            let res = inner($($arg),*);
+            // Unwinding out of an `extern "C"` function aborts the consumer process.
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| inner($($arg),*)))
+                .unwrap_or_else(|panic| Err(crate::ffi::panic_error(&*panic)));
             let code = (&res).into();
             crate::ffi::save_last_error(res);
             code
@@ -33,7 +35,9 @@ macro_rules! gentl_api {
                 $body
             }
 
-            let res = inner($($arg),*);
+            // Unwinding out of an `extern "C"` function aborts the consumer process.
+            let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| inner($($arg),*)))
+                .unwrap_or_else(|panic| Err(crate::ffi::panic_error(&*panic)));
             let code = (&res).into();
             crate::ffi::save_last_error(res);
             code
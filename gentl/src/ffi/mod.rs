@@ -6,18 +6,41 @@
 mod macros;
 
 pub mod device;
+pub mod event;
+mod handle;
 pub mod interface;
 pub mod port;
+pub mod stream;
 pub mod system;
 
-use std::{cell::RefCell, mem::ManuallyDrop, sync::RwLock};
+use std::{any::Any, cell::RefCell, convert::TryFrom, sync::RwLock};
 
 use crate::{imp, GenTlError, GenTlResult};
 
+/// Error code defined in GenTL specification.
+///
+/// Errors without a counterpart in the specification are reported in the custom error range as
+/// `GC_ERR_CUSTOM_ID - code`, where `code` is the native [`cameleon::ErrorCode`]. A code which
+/// doesn't fit in the range is reported as `GC_ERR_ERROR`.
 #[repr(transparent)]
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct GC_ERROR(i32);
 
+impl GC_ERROR {
+    const GC_ERR_ERROR: i32 = -1001;
+    const GC_ERR_CUSTOM_ID: i32 = -10000;
+
+    fn custom(code: cameleon::ErrorCode) -> i32 {
+        i32::try_from(code.get())
+            .ok()
+            .and_then(|code| Self::GC_ERR_CUSTOM_ID.checked_sub(code))
+            .unwrap_or(Self::GC_ERR_ERROR)
+    }
+}
+
+/// Timeout which never expires, corresponds to `GENTL_INFINITE`.
+const GENTL_INFINITE: u64 = u64::MAX;
+
 #[repr(transparent)]
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub struct bool8_t(u8);
@@ -51,13 +74,14 @@ impl From<bool> for bool8_t {
 impl From<&GenTlError> for GC_ERROR {
     fn from(val: &GenTlError) -> Self {
         use GenTlError::{
-            Abort, AccessDenied, Ambiguous, BufferTooSmall, Busy, Error, InvalidAddress,
-            InvalidBuffer, InvalidHandle, InvalidId, InvalidIndex, InvalidParameter, InvalidValue,
-            Io, NoData, NotAvailable, NotImplemented, NotInitialized, OutOfMemory,
-            ParsingChunkData, ResourceExhausted, ResourceInUse, Timeout,
+            Abort, AccessDenied, Ambiguous, BufferTooSmall, Busy, Custom, Disconnected, Error,
+            InvalidAddress, InvalidBuffer, InvalidHandle, InvalidId, InvalidIndex,
+            InvalidParameter, InvalidValue, Io, NoData, NotAvailable, NotImplemented,
+            NotInitialized, OutOfMemory, ParsingChunkData, ResourceExhausted, ResourceInUse,
+            Timeout,
         };
         let code = match val {
-            Error(..) => -1001,
+            Error(..) => GC_ERROR::GC_ERR_ERROR,
             NotInitialized => -1002,
             NotImplemented => -1003,
             ResourceInUse => -1004,
@@ -66,7 +90,7 @@ impl From<&GenTlError> for GC_ERROR {
             InvalidId(..) => -1007,
             NoData => -1008,
             InvalidParameter => -1009,
-            Io(..) => -1010,
+            Io(..) | Disconnected => -1010,
             Timeout => -1011,
             Abort => -1012,
             InvalidBuffer => -1013,
@@ -80,6 +104,7 @@ impl From<&GenTlError> for GC_ERROR {
             OutOfMemory => -1021,
             Busy => -1022,
             Ambiguous => -1023,
+            Custom { code, .. } => GC_ERROR::custom(*code),
         };
         GC_ERROR(code)
     }
@@ -113,11 +138,14 @@ struct LastError {
     err: Option<GenTlError>,
 }
 
+#[derive(Clone)]
 enum ModuleHandle<'a> {
     System(system::SystemModuleRef<'a>),
     Interface(interface::InterfaceModuleRef<'a>),
     Device(device::DeviceModuleRef<'a>),
-    RemoteDevice(device::RemoteDeviceRef<'a>),
+    RemoteDevice(device::RemoteDeviceRef),
+    DataStream(stream::DataStreamRef<'a>),
+    Event(event::EventRef<'a>),
 }
 
 impl<'a> ModuleHandle<'a> {
@@ -142,22 +170,78 @@ impl<'a> ModuleHandle<'a> {
         }
     }
 
-    unsafe fn from_raw_manually_drop(
-        raw_handle: *mut libc::c_void,
-    ) -> GenTlResult<ManuallyDrop<Box<ModuleHandle<'a>>>> {
-        if raw_handle.is_null() {
-            Err(GenTlError::InvalidHandle)
-        } else {
-            let handle = raw_handle.cast::<ModuleHandle>();
-            Ok(ManuallyDrop::new(Box::from_raw(handle)))
+    fn data_stream(&self) -> GenTlResult<stream::DataStreamRef<'a>> {
+        match self {
+            ModuleHandle::DataStream(stream) => Ok(*stream),
+            _ => Err(GenTlError::InvalidHandle),
+        }
+    }
+
+    fn event(&self) -> GenTlResult<event::EventRef<'a>> {
+        match self {
+            ModuleHandle::Event(event) => Ok(event.clone()),
+            _ => Err(GenTlError::InvalidHandle),
+        }
+    }
+
+    /// Raw handle of the module which the handle is derived from.
+    ///
+    /// A handle is released together with its parent, see [`handle::HandleTable::remove`].
+    fn parent(&self) -> Option<*mut libc::c_void> {
+        match self {
+            ModuleHandle::System(..) => None,
+            ModuleHandle::Interface(iface) => Some(iface.parent_tl()),
+            ModuleHandle::Device(dev) => Some(dev.parent_if()),
+            ModuleHandle::RemoteDevice(remote) => Some(remote.parent_if()),
+            ModuleHandle::DataStream(stream) => Some(stream.parent_dev()),
+            ModuleHandle::Event(event) => Some(event.source()),
         }
     }
 
-    unsafe fn into_raw(self: Box<Self>) -> *mut libc::c_void {
-        Box::into_raw(self).cast::<libc::c_void>()
+    /// Registers the handle and returns its raw handle.
+    ///
+    /// # Safety
+    /// Modules referred to by the handle must live until the process ends. This holds for all
+    /// modules owned by the system module even if they are borrowed through their mutex guards.
+    unsafe fn into_raw(self) -> GenTlResult<*mut libc::c_void> {
+        let handle = std::mem::transmute::<ModuleHandle<'a>, ModuleHandle<'static>>(self);
+        let raw = handle::HANDLE_TABLE.lock().unwrap().insert(handle)?;
+        Ok(raw as *mut libc::c_void)
     }
 }
 
+impl ModuleHandle<'static> {
+    /// Returns the handle registered as `raw_handle`.
+    fn from_raw(raw_handle: *mut libc::c_void) -> GenTlResult<ModuleHandle<'static>> {
+        handle::HANDLE_TABLE
+            .lock()
+            .unwrap()
+            .get(raw_handle as usize)
+    }
+
+    /// Unregisters the handle registered as `raw_handle` and the handles derived from it, then
+    /// their raw handles become stale.
+    fn release(raw_handle: *mut libc::c_void) -> GenTlResult<ModuleHandle<'static>> {
+        handle::HANDLE_TABLE
+            .lock()
+            .unwrap()
+            .remove(raw_handle as usize)
+    }
+
+    /// Unregisters the handles satisfying `pred` and the handles derived from them.
+    fn release_where(pred: &mut dyn FnMut(&ModuleHandle<'static>) -> bool) {
+        handle::HANDLE_TABLE.lock().unwrap().remove_where(pred);
+    }
+}
+
+// SAFETY: Handles are moved into `HANDLE_TABLE`, which is shared between threads.
+// - The modules referred to by handles are owned by the system module which lives until the
+//   process ends. Module traits require `Send` and the modules are accessed only through their
+//   mutexes, so the references can be used from any thread.
+// - The raw handles of parent modules are only used as keys of `HANDLE_TABLE` and never
+//   dereferenced.
+unsafe impl Send for ModuleHandle<'_> {}
+
 newtype_enum! {
     pub enum INFO_DATATYPE {
         INFO_DATATYPE_UNKNOWN = 0,
@@ -183,12 +267,12 @@ lazy_static::lazy_static! {
 }
 
 thread_local! {
-    static LAST_ERROR: RefCell<LastError> = {
+    static LAST_ERROR: RefCell<LastError> = const { {
         let last_error = LastError {
             err: None,
         };
         RefCell::new(last_error)
-    }
+    } }
 }
 
 impl crate::imp::CharEncoding {
@@ -210,6 +294,16 @@ fn save_last_error<T>(res: GenTlResult<T>) {
     }
 }
 
+/// Converts a panic caught at the C boundary into [`GenTlError::Error`].
+fn panic_error(panic: &(dyn Any + Send)) -> GenTlError {
+    let msg = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    GenTlError::Error(format!("panicked: {}", msg))
+}
+
 #[allow(clippy::needless_pass_by_value)]
 fn copy_info<T: CopyTo>(
     src: T,
@@ -357,7 +451,7 @@ impl CopyTo for imp::device::DeviceAccessStatus {
     type Destination = i32;
 
     fn copy_to(&self, dst: *mut Self::Destination, dst_size: *mut libc::size_t) -> GenTlResult<()> {
-        let val = *self as Self::Destination;
+        let val = self.as_raw() as Self::Destination;
 
         val.copy_to(dst, dst_size)
     }
@@ -407,6 +501,8 @@ impl_copy_to_for_numeric!(i32, INFO_DATATYPE::INFO_DATATYPE_INT32);
 impl_copy_to_for_numeric!(u32, INFO_DATATYPE::INFO_DATATYPE_UINT32);
 impl_copy_to_for_numeric!(i64, INFO_DATATYPE::INFO_DATATYPE_INT64);
 impl_copy_to_for_numeric!(u64, INFO_DATATYPE::INFO_DATATYPE_UINT64);
+impl_copy_to_for_numeric!(usize, INFO_DATATYPE::INFO_DATATYPE_SIZET);
+impl_copy_to_for_numeric!(*mut libc::c_void, INFO_DATATYPE::INFO_DATATYPE_PTR);
 
 fn assert_lib_initialized() -> GenTlResult<()> {
     if *IS_LIB_INITIALIZED.read().unwrap() {
@@ -429,10 +525,23 @@ gentl_api!(
     }
 );
 
+/// Tears down all modules of the producer and invalidates all outstanding handles.
+///
+/// Handles are invalidated first so that calls racing with the shutdown fail with
+/// [`GenTlError::InvalidHandle`] instead of touching modules being closed. Then modules are closed
+/// from the system module, which closes its interfaces and they close their devices in turn.
+///
+/// Calling this function on the already shut down producer does nothing.
+fn shutdown() {
+    handle::HANDLE_TABLE.lock().unwrap().clear();
+    system::SYSTEM_MODULE.lock().unwrap().shutdown();
+}
+
 gentl_api!(
     pub fn GCCloseLib() -> GenTlResult<()> {
         let mut is_init = IS_LIB_INITIALIZED.write().unwrap();
         if *is_init {
+            shutdown();
             *is_init = false;
             Ok(())
         } else {
@@ -442,13 +551,14 @@ gentl_api!(
 );
 
 gentl_api!(
-    pub fn CGCGetInfo(
-        _iInfoCmd: i32,
-        _piType: i32,
-        _pBuffer: *mut libc::c_void,
-        _piSize: *mut libc::size_t,
+    pub fn GCGetInfo(
+        iInfoCmd: system::TL_INFO_CMD,
+        piType: *mut INFO_DATATYPE,
+        pBuffer: *mut libc::c_void,
+        piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        Err(GenTlError::NotImplemented)
+        let system = system::SYSTEM_MODULE.lock().unwrap();
+        system::tl_get_info(system.system_info(), iInfoCmd, piType, pBuffer, piSize)
     }
 );
 
@@ -476,3 +586,204 @@ gentl_api!(
         Ok(())
     }
 );
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVALID_HANDLE: GC_ERROR = GC_ERROR(-1006);
+
+    /// Serializes the tests using the producer, which is shared by the whole process.
+    static PRODUCER_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    fn lock_producer() -> std::sync::MutexGuard<'static, ()> {
+        PRODUCER_LOCK
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Opens the system module and its first interface.
+    fn open_interface() -> (system::TL_HANDLE, interface::IF_HANDLE) {
+        let mut h_system = std::ptr::null_mut();
+        assert!(system::TLOpen(&mut h_system) == GC_ERROR(0));
+
+        let mut iface_id = [0_i8; 256];
+        let mut size = iface_id.len();
+        assert!(
+            system::TLGetInterfaceID(h_system, 0, iface_id.as_mut_ptr().cast(), &mut size)
+                == GC_ERROR(0)
+        );
+        let mut h_iface = std::ptr::null_mut();
+        assert!(
+            system::TLOpenInterface(h_system, iface_id.as_ptr().cast(), &mut h_iface)
+                == GC_ERROR(0)
+        );
+        (h_system, h_iface)
+    }
+
+    #[test]
+    fn test_shutdown_without_close() {
+        let _lock = lock_producer();
+        assert!(GCInitLib() == GC_ERROR(0));
+
+        let (h_system, h_iface) = open_interface();
+
+        // Close the library without closing the interface and the system.
+        assert!(GCCloseLib() == GC_ERROR(0));
+        assert!(!system::SYSTEM_MODULE.lock().unwrap().is_opened());
+
+        // Handles issued before the shutdown are stale.
+        assert!(GCInitLib() == GC_ERROR(0));
+        let mut num_ifaces = 0;
+        assert!(system::TLGetNumInterfaces(h_system, &mut num_ifaces) == INVALID_HANDLE);
+        assert!(interface::IFClose(h_iface) == INVALID_HANDLE);
+        assert!(system::TLClose(h_system) == INVALID_HANDLE);
+
+        // Shutdown is idempotent.
+        shutdown();
+        shutdown();
+
+        // The producer can be opened again.
+        let mut h_new_system = std::ptr::null_mut();
+        assert!(system::TLOpen(&mut h_new_system) == GC_ERROR(0));
+        assert!(h_new_system != h_system);
+        assert!(system::TLClose(h_new_system) == GC_ERROR(0));
+        assert!(GCCloseLib() == GC_ERROR(0));
+    }
+
+    gentl_api!(
+        no_assert pub fn PanickingFunction() -> GenTlResult<()> {
+            panic!("broken invariant")
+        }
+    );
+
+    #[test]
+    fn test_catch_panic() {
+        let _lock = lock_producer();
+        assert!(GCInitLib() == GC_ERROR(0));
+        assert!(PanickingFunction() == GC_ERROR(-1001));
+
+        let mut code = GC_ERROR(0);
+        let mut text = [0_i8; 256];
+        let mut size = text.len();
+        assert!(GCGetLastError(&mut code, text.as_mut_ptr().cast(), &mut size) == GC_ERROR(0));
+        assert!(code == GC_ERROR(-1001));
+        let text = unsafe { std::ffi::CStr::from_ptr(text.as_ptr().cast()) };
+        assert_eq!(
+            text.to_str().unwrap(),
+            "unspecified runtime error: panicked: broken invariant"
+        );
+        assert!(GCCloseLib() == GC_ERROR(0));
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn test_interface_events() {
+        use cameleon_device::emulator::EmulatorBuilder;
+
+        use event::{EVENT_DATA_INFO_CMD, EVENT_TYPE};
+
+        let _lock = lock_producer();
+        assert!(GCInitLib() == GC_ERROR(0));
+        system::SYSTEM_MODULE
+            .lock()
+            .unwrap()
+            .set_emulation_enabled(true);
+        let (h_system, h_iface) = open_interface();
+
+        let mut h_event = std::ptr::null_mut();
+        assert!(
+            event::GCRegisterEvent(h_iface, EVENT_TYPE::EVENT_MODULE, &mut h_event) == GC_ERROR(0)
+        );
+
+        EmulatorBuilder::new()
+            .serial_number("IFACEFFI1")
+            .unwrap()
+            .build();
+        let mut changed = bool8_t::false_();
+        assert!(interface::IFUpdateDeviceList(h_iface, &mut changed, 0) == GC_ERROR(0));
+        assert!(bool::from(changed));
+
+        // The event carries the ID of the device list changed event.
+        let mut data = [0_u8; 64];
+        let mut data_size = data.len();
+        assert!(
+            event::EventGetData(h_event, data.as_mut_ptr().cast(), &mut data_size, 0)
+                == GC_ERROR(0)
+        );
+        let mut info_type = INFO_DATATYPE::INFO_DATATYPE_UNKNOWN;
+        let mut event_id = 0_u64;
+        let mut size = std::mem::size_of::<u64>();
+        assert!(
+            event::EventGetDataInfo(
+                h_event,
+                data.as_ptr().cast(),
+                data_size,
+                EVENT_DATA_INFO_CMD::EVENT_DATA_NUMID,
+                &mut info_type,
+                (&mut event_id as *mut u64).cast(),
+                &mut size,
+            ) == GC_ERROR(0)
+        );
+        assert!(info_type == INFO_DATATYPE::INFO_DATATYPE_UINT64);
+        assert_eq!(event_id, imp::interface::DEVICE_LIST_CHANGED_EVENT_ID);
+
+        // Unregistering the event releases its handle.
+        assert!(event::GCUnregisterEvent(h_iface, EVENT_TYPE::EVENT_MODULE) == GC_ERROR(0));
+        assert!(
+            event::EventGetData(h_event, data.as_mut_ptr().cast(), &mut data_size, 0)
+                == INVALID_HANDLE
+        );
+
+        assert!(interface::IFClose(h_iface) == GC_ERROR(0));
+        assert!(system::TLClose(h_system) == GC_ERROR(0));
+        system::SYSTEM_MODULE
+            .lock()
+            .unwrap()
+            .set_emulation_enabled(false);
+        assert!(GCCloseLib() == GC_ERROR(0));
+    }
+
+    // Codes are reported to consumers, never change the table below.
+    #[test]
+    fn test_control_error_code() {
+        use cameleon::{limits::Limit, ControlError};
+
+        let table = [
+            (ControlError::Busy, -1004),
+            (ControlError::Disconnected, -1010),
+            (ControlError::NotOpened, -1002),
+            (ControlError::Timeout, -1011),
+            (ControlError::BufferTooSmall, -1016),
+            (ControlError::InvalidAddress { address: 0, len: 0 }, -1015),
+            (ControlError::InvalidDevice("".into()), -10000 - 0x0002_0001),
+            (
+                ControlError::LimitExceeded {
+                    limit: Limit::XmlSize,
+                    claimed: 0,
+                    max: 0,
+                },
+                -10000 - 0x0002_0002,
+            ),
+        ];
+        for (err, code) in table {
+            let err = GenTlError::from(err);
+            assert_eq!(GC_ERROR::from(&err).0, code, "{:?}", err);
+        }
+
+        // Codes out of the custom range don't overflow.
+        let max = (GC_ERROR::GC_ERR_CUSTOM_ID - i32::MIN) as u32;
+        for (raw, code) in [(max, i32::MIN), (max + 1, -1001), (u32::MAX, -1001)] {
+            let err = GenTlError::Custom {
+                code: cameleon::ErrorCode::from_raw(raw),
+                message: String::new(),
+            };
+            assert_eq!(GC_ERROR::from(&err).0, code, "{:#x}", raw);
+        }
+
+        let err = GenTlError::from(ControlError::InvalidDevice("broken".into()));
+        assert!(err
+            .to_string()
+            .ends_with("(error code: INVALID_DEVICE (0x00020001))"));
+    }
+}
@@ -4,7 +4,7 @@
 
 use std::slice;
 
-use imp::port::Port as _;
+use imp::port::{Port as _, SharedPort as _};
 
 use super::{
     bool8_t, copy_info, imp, GenTlError, GenTlResult, ModuleHandle, GC_ERROR, INFO_DATATYPE,
@@ -58,7 +58,7 @@ newtype_enum! {
 
 macro_rules! with_port {
     ($handle:ident, |$port:ident| $body: tt) => {
-        match &*$handle.as_ref() {
+        match &$handle {
             ModuleHandle::System(handle) => {
                 #[allow(unused_mut)]
                 let mut $port = handle.lock().unwrap();
@@ -76,9 +76,14 @@ macro_rules! with_port {
             }
 
             ModuleHandle::RemoteDevice(handle) => {
-                let mut $port = handle.lock().unwrap();
+                // The remote device is accessed without locking, see `imp::port::SharedPort`.
+                let $port = &**handle;
                 $body
             }
+
+            ModuleHandle::DataStream(..) | ModuleHandle::Event(..) => {
+                return Err(GenTlError::InvalidHandle);
+            }
         }
     };
 }
@@ -91,64 +96,6 @@ pub struct PORT_REGISTER_STACK_ENTRY {
     Size: libc::size_t,
 }
 
-fn file_location_to_url(xml_info: &imp::port::XmlInfo, port_info: &imp::port::PortInfo) -> String {
-    use imp::port::XmlLocation;
-    match &xml_info.location {
-        XmlLocation::RegisterMap { address, size } => {
-            // The specificaton requires us to stringfy xml location as below when the
-            // xml is On the register,
-            //
-            // local:{filename}.{extension};{address};{length}[?SchemaVersion={major}.(minor).{subminor}]
-            //
-            // filename: {vendor}_{model}_{file_version}.
-            // extension: "zip" if compressed else "xml".
-            // address: Start address of the xml, must be expressed in hexademical
-            // without prefix.
-            // length: Byte length  of the xml, must be expressed in hexademical
-            // without prefix.
-            let extension = match xml_info.compressed {
-                cameleon::genapi::CompressionType::Uncompressed => "xml",
-                cameleon::genapi::CompressionType::Zip => "zip",
-            };
-            let schema_version = &xml_info.schema_version;
-
-            format!("local:{vendor}_{model}_{file_version}.{extension};{address:X};{size:X}?SchemaVersion={schema_major}.{schema_minor}.{schema_subminor}",
-                            vendor = port_info.vendor,
-                            model = port_info.model,
-                            file_version = xml_info.file_version,
-                            extension = extension,
-                            address = address,
-                            size = size,
-                            schema_major=schema_version.major,
-                            schema_minor = schema_version.minor,
-                            schema_subminor = schema_version.patch,
-                        )
-        }
-        XmlLocation::LocalFile(path) => {
-            // file:{filepath}[?SchemaVersion={major}.{minor}.{subminor}]
-            let schema_version = &xml_info.schema_version;
-            format!(
-                "file:{filepath}?SchemaVersion={schema_major}.{schema_minor}.{schema_subminor}",
-                filepath = path.to_string_lossy(),
-                schema_major = schema_version.major,
-                schema_minor = schema_version.minor,
-                schema_subminor = schema_version.patch,
-            )
-        }
-        XmlLocation::Url(url) => {
-            // {url}[?SchemaVersion={major}.{minor}.{subminor}]
-            let schema_version = &xml_info.schema_version;
-            format!(
-                "{url}?SchemaVersion={schema_major}.{schema_minor}.{schema_subminor}",
-                url = url.as_str(),
-                schema_major = schema_version.major,
-                schema_minor = schema_version.minor,
-                schema_subminor = schema_version.patch,
-            )
-        }
-    }
-}
-
 gentl_api! {
     pub fn GCGetPortInfo(
         hPort: PORT_HANDLE,
@@ -157,7 +104,7 @@ gentl_api! {
         pBuffer: *mut libc::c_void,
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hPort)? };
+        let handle = ModuleHandle::from_raw(hPort)?;
 
         let info_data_type = with_port!(handle, |port| {
             let info = port.port_info()?;
@@ -229,11 +176,11 @@ gentl_api! {
         sURL: *mut libc::c_char,
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hPort)? };
+        let handle = ModuleHandle::from_raw(hPort)?;
         let url = with_port!(handle, |port| {
             // Use first  info.
-            let xml_info = port.xml_infos()?.get(0).ok_or_else(|| GenTlError::Error("no xml information in the device".into()))?;
-            file_location_to_url(xml_info, port.port_info()?)
+            let xml_info = port.xml_infos()?.first().ok_or_else(|| GenTlError::Error("no xml information in the device".into()))?;
+            xml_info.url(port.port_info()?)
         });
 
         copy_info(url.as_str(), sURL.cast::<libc::c_void>(), piSize)?;
@@ -243,7 +190,7 @@ gentl_api! {
 
 gentl_api! {
     pub fn GCGetNumPortURLs(hPort: PORT_HANDLE, piNumURLs: *mut u32) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hPort)? };
+        let handle = ModuleHandle::from_raw(hPort)?;
         let num_port = with_port!{handle, |port| {
             let xml_infos = port.xml_infos()?;
             xml_infos.len()
@@ -266,7 +213,7 @@ gentl_api! {
         pBuffer: *mut libc::c_void,
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hPort)? };
+        let handle = ModuleHandle::from_raw(hPort)?;
         let info_data_type = with_port!(handle, |port| {
             let info = port
                 .xml_infos()?
@@ -274,7 +221,7 @@ gentl_api! {
                 .ok_or(GenTlError::InvalidIndex)?;
             match iInfoCmd {
                 URL_INFO_CMD::URL_INFO_URL => {
-                    let url = file_location_to_url(info, port.port_info()?);
+                    let url = info.url(port.port_info()?);
                     copy_info(url.as_str(), pBuffer, piSize)
                 }
 
@@ -332,7 +279,7 @@ gentl_api! {
                     use imp::port::XmlLocation;
                     let scheme_id = match &info.location {
                         XmlLocation::RegisterMap { .. } => URL_SCHEME_IDS::URL_SCHEME_LOCAL,
-                        XmlLocation::LocalFile(_) => URL_SCHEME_IDS::URL_SCHEME_FILE,
+                        XmlLocation::File(_) => URL_SCHEME_IDS::URL_SCHEME_FILE,
                         XmlLocation::Url(_) => URL_SCHEME_IDS::URL_SCHEME_HTTP,
                     };
 
@@ -340,21 +287,8 @@ gentl_api! {
                 }
 
                 URL_INFO_CMD::URL_INFO_FILENAME => {
-                    use imp::port::XmlLocation;
-                    let file_name = match &info.location {
-                        XmlLocation::LocalFile(path) => {
-                            let err_msg = "local file name is invalid";
-                            let file_name =
-                                path.file_name().ok_or_else(|| GenTlError::Error(err_msg.into()))?;
-                            file_name
-                                .to_str()
-                                .ok_or_else(|| GenTlError::Error(err_msg.into()))?
-                        }
-
-                        _ => return Err(GenTlError::NotAvailable),
-                    };
-
-                    copy_info(file_name, pBuffer, piSize)
+                    let file_name = info.file_name(port.port_info()?);
+                    copy_info(file_name.as_str(), pBuffer, piSize)
                 }
 
                 _ => Err(GenTlError::InvalidParameter),
@@ -377,7 +311,7 @@ gentl_api! {
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
         unsafe {
-            let handle = ModuleHandle::from_raw_manually_drop(hPort)?;
+            let handle = ModuleHandle::from_raw(hPort)?;
             let buffer = std::slice::from_raw_parts_mut(pBuffer.cast::<u8>(), *piSize);
 
             let read_len = with_port!(handle, |port| {
@@ -399,7 +333,7 @@ gentl_api! {
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
         unsafe {
-            let handle = ModuleHandle::from_raw_manually_drop(hPort)?;
+            let handle = ModuleHandle::from_raw(hPort)?;
             let data = std::slice::from_raw_parts(pBuffer.cast::<u8>(), *piSize);
 
             let written_len = with_port!(handle, |port| {
@@ -419,7 +353,7 @@ gentl_api! {
         piNumEntries: *mut libc::size_t,
     ) -> GenTlResult<()> {
         unsafe {
-            let handle = ModuleHandle::from_raw_manually_drop(hPort)?;
+            let handle = ModuleHandle::from_raw(hPort)?;
 
             let mut entries: Vec<_> = (0..*piNumEntries)
                 .map(|i| {
@@ -445,7 +379,7 @@ gentl_api! {
         piNumEntries: *mut libc::size_t,
     ) -> GenTlResult<()> {
         unsafe {
-            let handle = ModuleHandle::from_raw_manually_drop(hPort)?;
+            let handle = ModuleHandle::from_raw(hPort)?;
 
             let entries: Vec<_> = (0..*piNumEntries)
                 .map(|i| {
@@ -0,0 +1,586 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{convert::TryInto, ops::Deref, sync::Mutex};
+
+use cameleon::payload::PayloadType;
+
+use crate::imp::stream::{
+    BufferHandle, BufferInfoCmd, BufferInfoValue, BufferPartInfoCmd, DataStream, FlushMode,
+    StreamInfoCmd, StreamInfoValue,
+};
+
+use super::{
+    bool8_t, copy_info, device, GenTlError, GenTlResult, ModuleHandle, GC_ERROR, GENTL_INFINITE,
+    INFO_DATATYPE,
+};
+
+pub(super) type DS_HANDLE = *mut libc::c_void;
+pub(super) type BUFFER_HANDLE = *mut libc::c_void;
+
+#[derive(Clone, Copy)]
+pub(super) struct DataStreamRef<'a> {
+    inner: &'a Mutex<dyn DataStream>,
+    parent_dev: device::DEV_HANDLE,
+}
+
+impl<'a> DataStreamRef<'a> {
+    pub(super) fn new(inner: &'a Mutex<dyn DataStream>, parent_dev: device::DEV_HANDLE) -> Self {
+        Self { inner, parent_dev }
+    }
+
+    /// Returns the data stream module, which outlives the reference itself.
+    pub(super) fn module(&self) -> &'a Mutex<dyn DataStream> {
+        self.inner
+    }
+
+    pub(super) fn parent_dev(&self) -> device::DEV_HANDLE {
+        self.parent_dev
+    }
+}
+
+impl<'a> Deref for DataStreamRef<'a> {
+    type Target = Mutex<dyn DataStream>;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+/// Converts a buffer handle to the opaque handle passed to the consumer.
+pub(super) fn raw_buffer_handle(handle: BufferHandle) -> BUFFER_HANDLE {
+    handle.as_raw() as usize as BUFFER_HANDLE
+}
+
+fn buffer_handle(raw: BUFFER_HANDLE) -> GenTlResult<BufferHandle> {
+    if raw.is_null() {
+        Err(GenTlError::InvalidHandle)
+    } else {
+        Ok(BufferHandle::from_raw(raw as usize as u64))
+    }
+}
+
+fn copy_buffer_info(
+    value: BufferInfoValue,
+    pBuffer: *mut libc::c_void,
+    piSize: *mut libc::size_t,
+) -> GenTlResult<INFO_DATATYPE> {
+    match value {
+        BufferInfoValue::Ptr(ptr) => copy_info(ptr as *mut libc::c_void, pBuffer, piSize),
+        BufferInfoValue::SizeT(value) => copy_info(value, pBuffer, piSize),
+        BufferInfoValue::UInt64(value) => copy_info(value, pBuffer, piSize),
+        BufferInfoValue::Bool(value) => copy_info(bool8_t::from(value), pBuffer, piSize),
+        BufferInfoValue::PayloadType(payload_type) => {
+            let id = match payload_type {
+                // Chunks following the image are reported by `BUFFER_INFO_CONTAINS_CHUNKDATA`.
+                PayloadType::Image | PayloadType::ImageExtendedChunk => {
+                    PAYLOADTYPE_INFO_IDS::PAYLOAD_TYPE_IMAGE
+                }
+                PayloadType::Chunk => PAYLOADTYPE_INFO_IDS::PAYLOAD_TYPE_CHUNK_ONLY,
+                // Parts of the container are reported by `DSGetBufferPartInfo`.
+                PayloadType::GenDc => PAYLOADTYPE_INFO_IDS::PAYLOAD_TYPE_GENDC,
+            };
+            copy_info(id.0 as usize, pBuffer, piSize)
+        }
+        BufferInfoValue::PixelFormat(format) => {
+            copy_info(u64::from(u32::from(format)), pBuffer, piSize)
+        }
+    }
+}
+
+fn copy_stream_info(
+    value: StreamInfoValue,
+    pBuffer: *mut libc::c_void,
+    piSize: *mut libc::size_t,
+) -> GenTlResult<INFO_DATATYPE> {
+    match value {
+        StreamInfoValue::String(value) => copy_info(value.as_str(), pBuffer, piSize),
+        StreamInfoValue::SizeT(value) => copy_info(value, pBuffer, piSize),
+        StreamInfoValue::UInt64(value) => copy_info(value, pBuffer, piSize),
+        StreamInfoValue::Bool(value) => copy_info(bool8_t::from(value), pBuffer, piSize),
+    }
+}
+
+newtype_enum! {
+    pub enum STREAM_INFO_CMD {
+        STREAM_INFO_ID = 0,
+        STREAM_INFO_NUM_DELIVERED = 1,
+        STREAM_INFO_NUM_UNDERRUN = 2,
+        STREAM_INFO_NUM_ANNOUNCED = 3,
+        STREAM_INFO_NUM_QUEUED = 4,
+        STREAM_INFO_NUM_AWAIT_DELIVERY = 5,
+        STREAM_INFO_NUM_STARTED = 6,
+        STREAM_INFO_PAYLOAD_SIZE = 7,
+        STREAM_INFO_IS_GRABBING = 8,
+        STREAM_INFO_DEFINES_PAYLOADSIZE = 9,
+        STREAM_INFO_TLTYPE = 10,
+        STREAM_INFO_NUM_CHUNKS_MAX = 11,
+        STREAM_INFO_BUF_ANNOUNCE_MIN = 12,
+        STREAM_INFO_BUF_ALIGNMENT = 13,
+        STREAM_INFO_CUSTOM_ID = 1000,
+    }
+}
+
+impl TryInto<StreamInfoCmd> for STREAM_INFO_CMD {
+    type Error = GenTlError;
+
+    fn try_into(self) -> GenTlResult<StreamInfoCmd> {
+        match self {
+            Self::STREAM_INFO_ID => Ok(StreamInfoCmd::Id),
+            Self::STREAM_INFO_NUM_DELIVERED => Ok(StreamInfoCmd::NumDelivered),
+            Self::STREAM_INFO_NUM_UNDERRUN => Ok(StreamInfoCmd::NumUnderrun),
+            Self::STREAM_INFO_NUM_ANNOUNCED => Ok(StreamInfoCmd::NumAnnounced),
+            Self::STREAM_INFO_NUM_QUEUED => Ok(StreamInfoCmd::NumQueued),
+            Self::STREAM_INFO_NUM_AWAIT_DELIVERY => Ok(StreamInfoCmd::NumAwaitDelivery),
+            Self::STREAM_INFO_PAYLOAD_SIZE => Ok(StreamInfoCmd::PayloadSize),
+            Self::STREAM_INFO_IS_GRABBING => Ok(StreamInfoCmd::IsGrabbing),
+            _ => Err(GenTlError::InvalidParameter),
+        }
+    }
+}
+
+newtype_enum! {
+    pub enum BUFFER_INFO_CMD {
+        BUFFER_INFO_BASE = 0,
+        BUFFER_INFO_SIZE = 1,
+        BUFFER_INFO_USER_PTR = 2,
+        BUFFER_INFO_TIMESTAMP = 3,
+        BUFFER_INFO_NEW_DATA = 4,
+        BUFFER_INFO_IS_QUEUED = 5,
+        BUFFER_INFO_IS_ACQUIRING = 6,
+        BUFFER_INFO_IS_INCOMPLETE = 7,
+        BUFFER_INFO_TLTYPE = 8,
+        BUFFER_INFO_SIZE_FILLED = 9,
+        BUFFER_INFO_WIDTH = 10,
+        BUFFER_INFO_HEIGHT = 11,
+        BUFFER_INFO_XOFFSET = 12,
+        BUFFER_INFO_YOFFSET = 13,
+        BUFFER_INFO_XPADDING = 14,
+        BUFFER_INFO_YPADDING = 15,
+        BUFFER_INFO_FRAMEID = 16,
+        BUFFER_INFO_IMAGEPRESENT = 17,
+        BUFFER_INFO_IMAGEOFFSET = 18,
+        BUFFER_INFO_PAYLOADTYPE = 19,
+        BUFFER_INFO_PIXELFORMAT = 20,
+        BUFFER_INFO_PIXELFORMAT_NAMESPACE = 21,
+        BUFFER_INFO_DELIVERED_IMAGEHEIGHT = 22,
+        BUFFER_INFO_DELIVERED_CHUNKPAYLOADSIZE = 23,
+        BUFFER_INFO_CHUNKLAYOUTID = 24,
+        BUFFER_INFO_FILENAME = 25,
+        BUFFER_INFO_PIXEL_ENDIANNESS = 26,
+        BUFFER_INFO_DATA_SIZE = 27,
+        BUFFER_INFO_TIMESTAMP_NS = 28,
+        BUFFER_INFO_DATA_LARGER_THAN_BUFFER = 29,
+        BUFFER_INFO_CONTAINS_CHUNKDATA = 30,
+        BUFFER_INFO_CUSTOM_ID = 1000,
+    }
+}
+
+impl TryInto<BufferInfoCmd> for BUFFER_INFO_CMD {
+    type Error = GenTlError;
+
+    fn try_into(self) -> GenTlResult<BufferInfoCmd> {
+        match self {
+            Self::BUFFER_INFO_BASE => Ok(BufferInfoCmd::Base),
+            Self::BUFFER_INFO_SIZE => Ok(BufferInfoCmd::Size),
+            Self::BUFFER_INFO_USER_PTR => Ok(BufferInfoCmd::UserPtr),
+            // The tick of U3V timestamps is 1 ns.
+            Self::BUFFER_INFO_TIMESTAMP | Self::BUFFER_INFO_TIMESTAMP_NS => {
+                Ok(BufferInfoCmd::Timestamp)
+            }
+            Self::BUFFER_INFO_NEW_DATA => Ok(BufferInfoCmd::NewData),
+            Self::BUFFER_INFO_IS_QUEUED => Ok(BufferInfoCmd::IsQueued),
+            Self::BUFFER_INFO_IS_INCOMPLETE => Ok(BufferInfoCmd::IsIncomplete),
+            Self::BUFFER_INFO_SIZE_FILLED => Ok(BufferInfoCmd::SizeFilled),
+            Self::BUFFER_INFO_WIDTH => Ok(BufferInfoCmd::Width),
+            Self::BUFFER_INFO_HEIGHT => Ok(BufferInfoCmd::Height),
+            Self::BUFFER_INFO_XOFFSET => Ok(BufferInfoCmd::XOffset),
+            Self::BUFFER_INFO_YOFFSET => Ok(BufferInfoCmd::YOffset),
+            Self::BUFFER_INFO_XPADDING => Ok(BufferInfoCmd::XPadding),
+            Self::BUFFER_INFO_FRAMEID => Ok(BufferInfoCmd::FrameId),
+            Self::BUFFER_INFO_IMAGEPRESENT => Ok(BufferInfoCmd::ImagePresent),
+            Self::BUFFER_INFO_PAYLOADTYPE => Ok(BufferInfoCmd::PayloadType),
+            Self::BUFFER_INFO_PIXELFORMAT => Ok(BufferInfoCmd::PixelFormat),
+            Self::BUFFER_INFO_CONTAINS_CHUNKDATA => Ok(BufferInfoCmd::ContainsChunkData),
+            _ => Err(GenTlError::InvalidParameter),
+        }
+    }
+}
+
+newtype_enum! {
+    pub enum BUFFER_PART_INFO_CMD {
+        BUFFER_PART_INFO_BASE = 0,
+        BUFFER_PART_INFO_DATA_SIZE = 1,
+        BUFFER_PART_INFO_DATA_TYPE = 2,
+        BUFFER_PART_INFO_DATA_FORMAT = 3,
+        BUFFER_PART_INFO_DATA_FORMAT_NAMESPACE = 4,
+        BUFFER_PART_INFO_WIDTH = 5,
+        BUFFER_PART_INFO_HEIGHT = 6,
+        BUFFER_PART_INFO_XOFFSET = 7,
+        BUFFER_PART_INFO_YOFFSET = 8,
+        BUFFER_PART_INFO_XPADDING = 9,
+        BUFFER_PART_INFO_SOURCE_ID = 10,
+        BUFFER_PART_INFO_DELIVERED_IMAGEHEIGHT = 11,
+        BUFFER_PART_INFO_CUSTOM_ID = 1000,
+    }
+}
+
+impl TryInto<BufferPartInfoCmd> for BUFFER_PART_INFO_CMD {
+    type Error = GenTlError;
+
+    fn try_into(self) -> GenTlResult<BufferPartInfoCmd> {
+        match self {
+            Self::BUFFER_PART_INFO_BASE => Ok(BufferPartInfoCmd::Base),
+            Self::BUFFER_PART_INFO_DATA_SIZE => Ok(BufferPartInfoCmd::DataSize),
+            Self::BUFFER_PART_INFO_DATA_TYPE => Ok(BufferPartInfoCmd::DataType),
+            Self::BUFFER_PART_INFO_DATA_FORMAT => Ok(BufferPartInfoCmd::DataFormat),
+            Self::BUFFER_PART_INFO_WIDTH => Ok(BufferPartInfoCmd::Width),
+            Self::BUFFER_PART_INFO_HEIGHT => Ok(BufferPartInfoCmd::Height),
+            Self::BUFFER_PART_INFO_XOFFSET => Ok(BufferPartInfoCmd::XOffset),
+            Self::BUFFER_PART_INFO_YOFFSET => Ok(BufferPartInfoCmd::YOffset),
+            Self::BUFFER_PART_INFO_XPADDING => Ok(BufferPartInfoCmd::XPadding),
+            Self::BUFFER_PART_INFO_SOURCE_ID => Ok(BufferPartInfoCmd::SourceId),
+            Self::BUFFER_PART_INFO_DELIVERED_IMAGEHEIGHT => {
+                Ok(BufferPartInfoCmd::DeliveredImageHeight)
+            }
+            _ => Err(GenTlError::InvalidParameter),
+        }
+    }
+}
+
+newtype_enum! {
+    pub enum PAYLOADTYPE_INFO_IDS {
+        PAYLOAD_TYPE_UNKNOWN = 0,
+        PAYLOAD_TYPE_IMAGE = 1,
+        PAYLOAD_TYPE_RAW_DATA = 2,
+        PAYLOAD_TYPE_FILE = 3,
+        PAYLOAD_TYPE_CHUNK_DATA = 4,
+        PAYLOAD_TYPE_JPEG = 5,
+        PAYLOAD_TYPE_JPEG2000 = 6,
+        PAYLOAD_TYPE_H264 = 7,
+        PAYLOAD_TYPE_CHUNK_ONLY = 8,
+        PAYLOAD_TYPE_DEVICE_SPECIFIC = 9,
+        PAYLOAD_TYPE_MULTI_PART = 10,
+        PAYLOAD_TYPE_GENDC = 11,
+        PAYLOAD_TYPE_CUSTOM_ID = 1000,
+    }
+}
+
+newtype_enum! {
+    pub enum ACQ_QUEUE_TYPE {
+        ACQ_QUEUE_INPUT_TO_OUTPUT = 0,
+        ACQ_QUEUE_OUTPUT_DISCARD = 1,
+        ACQ_QUEUE_ALL_TO_INPUT = 2,
+        ACQ_QUEUE_UNQUEUED_TO_INPUT = 3,
+        ACQ_QUEUE_ALL_DISCARD = 4,
+    }
+}
+
+impl TryInto<FlushMode> for ACQ_QUEUE_TYPE {
+    type Error = GenTlError;
+
+    fn try_into(self) -> GenTlResult<FlushMode> {
+        match self {
+            Self::ACQ_QUEUE_INPUT_TO_OUTPUT => Ok(FlushMode::InputToOutput),
+            Self::ACQ_QUEUE_OUTPUT_DISCARD => Ok(FlushMode::OutputDiscard),
+            Self::ACQ_QUEUE_ALL_TO_INPUT => Ok(FlushMode::AllToInput),
+            Self::ACQ_QUEUE_UNQUEUED_TO_INPUT => Ok(FlushMode::UnqueuedToInput),
+            Self::ACQ_QUEUE_ALL_DISCARD => Ok(FlushMode::AllDiscard),
+            _ => Err(GenTlError::InvalidParameter),
+        }
+    }
+}
+
+newtype_enum! {
+    pub enum ACQ_START_FLAGS {
+        ACQ_START_FLAGS_DEFAULT = 0,
+        ACQ_START_FLAGS_CUSTOM_ID = 1000,
+    }
+}
+
+newtype_enum! {
+    pub enum ACQ_STOP_FLAGS {
+        ACQ_STOP_FLAGS_DEFAULT = 0,
+        ACQ_STOP_FLAGS_KILL = 1,
+        ACQ_STOP_FLAGS_CUSTOM_ID = 1000,
+    }
+}
+
+gentl_api! {
+    pub fn DSAnnounceBuffer(
+        hDataStream: DS_HANDLE,
+        pBuffer: *mut libc::c_void,
+        iSize: libc::size_t,
+        pPrivate: *mut libc::c_void,
+        phBuffer: *mut BUFFER_HANDLE,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let mut stream_guard = stream.lock().unwrap();
+        // SAFETY: The consumer guarantees that the buffer is valid until it's revoked.
+        let buffer = unsafe {
+            stream_guard.announce_buffer(pBuffer.cast::<u8>(), iSize, pPrivate as usize)?
+        };
+        unsafe {
+            *phBuffer = raw_buffer_handle(buffer);
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn DSAllocAndAnnounceBuffer(
+        hDataStream: DS_HANDLE,
+        iBufferSize: libc::size_t,
+        pPrivate: *mut libc::c_void,
+        phBuffer: *mut BUFFER_HANDLE,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let buffer = stream
+            .lock()
+            .unwrap()
+            .alloc_and_announce_buffer(iBufferSize, pPrivate as usize)?;
+        unsafe {
+            *phBuffer = raw_buffer_handle(buffer);
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn DSFlushQueue(hDataStream: DS_HANDLE, iOperation: ACQ_QUEUE_TYPE) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let mut stream_guard = stream.lock().unwrap();
+        stream_guard.flush_queue(iOperation.try_into()?)
+    }
+}
+
+gentl_api! {
+    pub fn DSGetBufferID(
+        hDataStream: DS_HANDLE,
+        iIndex: u32,
+        phBuffer: *mut BUFFER_HANDLE,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let buffer = stream.lock().unwrap().buffer_handle(iIndex as usize)?;
+        unsafe {
+            *phBuffer = raw_buffer_handle(buffer);
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn DSGetBufferInfo(
+        hDataStream: DS_HANDLE,
+        hBuffer: BUFFER_HANDLE,
+        iInfoCmd: BUFFER_INFO_CMD,
+        piType: *mut INFO_DATATYPE,
+        pBuffer: *mut libc::c_void,
+        piSize: *mut libc::size_t,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let stream_guard = stream.lock().unwrap();
+        let info = stream_guard.buffer_info(buffer_handle(hBuffer)?)?;
+        let info_data_type = match iInfoCmd {
+            BUFFER_INFO_CMD::BUFFER_INFO_IS_ACQUIRING => {
+                copy_info(bool8_t::from(stream_guard.is_grabbing()), pBuffer, piSize)
+            }
+            cmd => copy_buffer_info(info.query(cmd.try_into()?)?, pBuffer, piSize),
+        }?;
+
+        unsafe {
+            *piType = info_data_type;
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn DSGetNumBufferParts(
+        hDataStream: DS_HANDLE,
+        hBuffer: BUFFER_HANDLE,
+        piNumParts: *mut u32,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let num_parts = stream
+            .lock()
+            .unwrap()
+            .buffer_info(buffer_handle(hBuffer)?)?
+            .num_parts()?;
+        unsafe {
+            *piNumParts = num_parts
+                .try_into()
+                .map_err(|_| GenTlError::InvalidValue("too many buffer parts".into()))?;
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn DSGetBufferPartInfo(
+        hDataStream: DS_HANDLE,
+        hBuffer: BUFFER_HANDLE,
+        iPartIndex: u32,
+        iInfoCmd: BUFFER_PART_INFO_CMD,
+        piType: *mut INFO_DATATYPE,
+        pBuffer: *mut libc::c_void,
+        piSize: *mut libc::size_t,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let info = stream
+            .lock()
+            .unwrap()
+            .buffer_info(buffer_handle(hBuffer)?)?;
+        let value = info.query_part(iPartIndex as usize, iInfoCmd.try_into()?)?;
+        let info_data_type = copy_buffer_info(value, pBuffer, piSize)?;
+
+        unsafe {
+            *piType = info_data_type;
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn DSGetInfo(
+        hDataStream: DS_HANDLE,
+        iInfoCmd: STREAM_INFO_CMD,
+        piType: *mut INFO_DATATYPE,
+        pBuffer: *mut libc::c_void,
+        piSize: *mut libc::size_t,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let value = stream.lock().unwrap().stream_info(iInfoCmd.try_into()?)?;
+        let info_data_type = copy_stream_info(value, pBuffer, piSize)?;
+
+        unsafe {
+            *piType = info_data_type;
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn DSQueueBuffer(hDataStream: DS_HANDLE, hBuffer: BUFFER_HANDLE) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let mut stream_guard = stream.lock().unwrap();
+        stream_guard.queue_buffer(buffer_handle(hBuffer)?)
+    }
+}
+
+gentl_api! {
+    pub fn DSRevokeBuffer(
+        hDataStream: DS_HANDLE,
+        hBuffer: BUFFER_HANDLE,
+        pBuffer: *mut *mut libc::c_void,
+        pPrivate: *mut *mut libc::c_void,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let mut stream_guard = stream.lock().unwrap();
+        let buffer = buffer_handle(hBuffer)?;
+        let info = stream_guard.buffer_info(buffer)?;
+        let user_data = stream_guard.revoke_buffer(buffer)?;
+
+        // Memory allocated by the data stream is freed, so it's never returned to the consumer.
+        let base = if info.is_allocated {
+            std::ptr::null_mut()
+        } else {
+            info.base as *mut libc::c_void
+        };
+        unsafe {
+            if !pBuffer.is_null() {
+                *pBuffer = base;
+            }
+            if !pPrivate.is_null() {
+                *pPrivate = user_data as *mut libc::c_void;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn DSStartAcquisition(
+        hDataStream: DS_HANDLE,
+        _iStartFlags: ACQ_START_FLAGS,
+        iNumToAcquire: u64,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        let num_to_acquire = if iNumToAcquire == GENTL_INFINITE {
+            None
+        } else {
+            Some(iNumToAcquire)
+        };
+        let mut stream_guard = stream.lock().unwrap();
+        stream_guard.start_acquisition(num_to_acquire)
+    }
+}
+
+gentl_api! {
+    pub fn DSStopAcquisition(hDataStream: DS_HANDLE, _iStopFlags: ACQ_STOP_FLAGS) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        // The acquisition is always stopped immediately, so `ACQ_STOP_FLAGS_KILL` makes no
+        // difference.
+        let mut stream_guard = stream.lock().unwrap();
+        stream_guard.stop_acquisition()
+    }
+}
+
+gentl_api! {
+    pub fn DSClose(hDataStream: DS_HANDLE) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        stream.lock().unwrap().close()?;
+        // Release its handle, handles of events registered to the data stream are released too.
+        ModuleHandle::release(hDataStream)?;
+
+        Ok(())
+    }
+}
+
+gentl_api! {
+    pub fn DSGetParentDev(hDataStream: DS_HANDLE, phDevice: *mut device::DEV_HANDLE) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hDataStream)?;
+        let stream = handle.data_stream()?;
+
+        unsafe {
+            *phDevice = stream.parent_dev;
+        }
+
+        Ok(())
+    }
+}
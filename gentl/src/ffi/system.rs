@@ -17,16 +17,15 @@ pub(super) type SystemModuleRef<'a> = &'a Mutex<imp::system::SystemModule>;
 type SystemModule = Mutex<imp::system::SystemModule>;
 
 lazy_static::lazy_static! {
-    static ref SYSTEM_MODULE: Box<SystemModule> = Box::new(Mutex::new(imp::system::SystemModule::new()));
+    pub(super) static ref SYSTEM_MODULE: Box<SystemModule> = Box::new(Mutex::new(imp::system::SystemModule::new()));
 }
 
 gentl_api! {
     pub fn TLOpen(phSystem: *mut TL_HANDLE) -> GenTlResult<()> {
         SYSTEM_MODULE.lock().unwrap().open()?;
 
-        let handle = Box::new(ModuleHandle::System(SYSTEM_MODULE.as_ref()));
         unsafe {
-            *phSystem = handle.into_raw();
+            *phSystem = ModuleHandle::System(SYSTEM_MODULE.as_ref()).into_raw()?;
         }
         Ok(())
     }
@@ -34,78 +33,89 @@ gentl_api! {
 
 gentl_api!(
     pub fn TLClose(hSystem: TL_HANDLE) -> GenTlResult<()> {
-        let mut handle = unsafe { ModuleHandle::from_raw_manually_drop(hSystem)? };
-        let system_handle = handle.system()?;
+        let handle = ModuleHandle::from_raw(hSystem)?;
+        handle.system()?;
 
-        // Close the system module.
-        system_handle.lock().unwrap().close()?;
-        // Drop its handle.
-        unsafe {
-            std::mem::ManuallyDrop::drop(&mut handle);
-        }
+        // Closing the system module closes all modules opened through it, so all handles
+        // including the system handle become stale.
+        super::shutdown();
         Ok(())
     }
 );
 
-gentl_api!(
-    pub fn TLGetInfo(
-        hSystem: TL_HANDLE,
-        iInfoCmd: TL_INFO_CMD,
-        piType: *mut INFO_DATATYPE,
-        pBuffer: *mut libc::c_void,
-        piSize: *mut libc::size_t,
-    ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hSystem)? };
-        let system_handle = handle.system()?;
-        let handle_guard = system_handle.lock().unwrap();
-        let system_info = handle_guard.system_info();
+/// Copies the information of the system module, shared with `GCGetInfo`.
+pub(super) fn tl_get_info(
+    system_info: &imp::system::SystemInfo,
+    iInfoCmd: TL_INFO_CMD,
+    piType: *mut INFO_DATATYPE,
+    pBuffer: *mut libc::c_void,
+    piSize: *mut libc::size_t,
+) -> GenTlResult<()> {
+    let info_data_type = match iInfoCmd {
+        TL_INFO_CMD::TL_INFO_ID => copy_info(system_info.id.as_str(), pBuffer, piSize),
 
-        let info_data_type = match iInfoCmd {
-            TL_INFO_CMD::TL_INFO_ID => copy_info(system_info.id.as_str(), pBuffer, piSize),
+        TL_INFO_CMD::TL_INFO_VENDOR => copy_info(system_info.vendor.as_str(), pBuffer, piSize),
 
-            TL_INFO_CMD::TL_INFO_VENDOR => copy_info(system_info.vendor.as_str(), pBuffer, piSize),
+        TL_INFO_CMD::TL_INFO_MODEL => copy_info(system_info.model.as_str(), pBuffer, piSize),
 
-            TL_INFO_CMD::TL_INFO_MODEL => copy_info(system_info.model.as_str(), pBuffer, piSize),
+        TL_INFO_CMD::TL_INFO_VERSION => copy_info(system_info.version.as_str(), pBuffer, piSize),
 
-            TL_INFO_CMD::TL_INFO_VERSION => {
-                copy_info(system_info.version.as_str(), pBuffer, piSize)
-            }
+        TL_INFO_CMD::TL_INFO_TLTYPE => copy_info(system_info.tl_type.as_str(), pBuffer, piSize),
 
-            TL_INFO_CMD::TL_INFO_TLTYPE => copy_info(system_info.tl_type.as_str(), pBuffer, piSize),
+        TL_INFO_CMD::TL_INFO_NAME => copy_info(
+            &*system_info.full_path.file_name().unwrap().to_string_lossy(),
+            pBuffer,
+            piSize,
+        ),
 
-            TL_INFO_CMD::TL_INFO_NAME => copy_info(
-                &*system_info.full_path.file_name().unwrap().to_string_lossy(),
-                pBuffer,
-                piSize,
-            ),
+        TL_INFO_CMD::TL_INFO_PATHNAME => {
+            copy_info(&*system_info.full_path.to_string_lossy(), pBuffer, piSize)
+        }
 
-            TL_INFO_CMD::TL_INFO_PATHNAME => {
-                copy_info(&*system_info.full_path.to_string_lossy(), pBuffer, piSize)
-            }
+        TL_INFO_CMD::TL_INFO_DISPLAYNAME => {
+            copy_info(system_info.display_name.as_str(), pBuffer, piSize)
+        }
 
-            TL_INFO_CMD::TL_INFO_DISPLAYNAME => {
-                copy_info(system_info.display_name.as_str(), pBuffer, piSize)
-            }
+        TL_INFO_CMD::TL_INFO_CHAR_ENCODING => {
+            copy_info(system_info.encoding.as_raw(), pBuffer, piSize)
+        }
 
-            TL_INFO_CMD::TL_INFO_CHAR_ENCODING => {
-                copy_info(system_info.encoding.as_raw(), pBuffer, piSize)
-            }
+        TL_INFO_CMD::TL_INFO_GENTL_VER_MAJOR => {
+            copy_info(system_info.gentl_version_major, pBuffer, piSize)
+        }
 
-            TL_INFO_CMD::TL_INFO_GENTL_VER_MAJOR => {
-                copy_info(system_info.gentl_version_major, pBuffer, piSize)
-            }
+        TL_INFO_CMD::TL_INFO_GENTL_VER_MINOR => {
+            copy_info(system_info.gentl_version_minor, pBuffer, piSize)
+        }
+        _ => return Err(GenTlError::InvalidParameter),
+    }?;
 
-            TL_INFO_CMD::TL_INFO_GENTL_VER_MINOR => {
-                copy_info(system_info.gentl_version_minor, pBuffer, piSize)
-            }
-            _ => return Err(GenTlError::InvalidParameter),
-        }?;
+    unsafe {
+        *piType = info_data_type;
+    }
 
-        unsafe {
-            *piType = info_data_type;
-        }
+    Ok(())
+}
 
-        Ok(())
+gentl_api!(
+    pub fn TLGetInfo(
+        hSystem: TL_HANDLE,
+        iInfoCmd: TL_INFO_CMD,
+        piType: *mut INFO_DATATYPE,
+        pBuffer: *mut libc::c_void,
+        piSize: *mut libc::size_t,
+    ) -> GenTlResult<()> {
+        let handle = ModuleHandle::from_raw(hSystem)?;
+        let system_handle = handle.system()?;
+        let handle_guard = system_handle.lock().unwrap();
+
+        tl_get_info(
+            handle_guard.system_info(),
+            iInfoCmd,
+            piType,
+            pBuffer,
+            piSize,
+        )
     }
 );
 
@@ -116,7 +126,7 @@ gentl_api! {
         sIfaceID: *mut libc::c_char,
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hSystem)? };
+        let handle = ModuleHandle::from_raw(hSystem)?;
         let system_handle = handle.system()?;
         let handle_guard = system_handle.lock().unwrap();
 
@@ -138,7 +148,7 @@ gentl_api! {
         pBuffer: *mut libc::c_void,
         piSize: *mut libc::size_t,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hSystem)? };
+        let handle = ModuleHandle::from_raw(hSystem)?;
         let system_handle = handle.system()?;
         let handle_guard = system_handle.lock().unwrap();
         let id = unsafe { CStr::from_ptr(sIfaceID) }.to_string_lossy();
@@ -154,7 +164,7 @@ gentl_api! {
 
 gentl_api! {
     pub fn TLGetNumInterfaces(hSystem: TL_HANDLE, piNumIfaces: *mut u32) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hSystem)? };
+        let handle = ModuleHandle::from_raw(hSystem)?;
         let system_handle = handle.system()?;
         let handle_guard = system_handle.lock().unwrap();
 
@@ -172,7 +182,7 @@ gentl_api! {
         sIfaceID: *const libc::c_char,
         phIface: *mut super::interface::IF_HANDLE,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hSystem)? };
+        let handle = ModuleHandle::from_raw(hSystem)?;
         let system_handle = handle.system()?;
         let handle_guard = system_handle.lock().unwrap();
 
@@ -180,10 +190,8 @@ gentl_api! {
         let iface = handle_guard.interface_of(&id).ok_or_else(|| GenTlError::InvalidId(id.into()))?;
         iface.lock().unwrap().open()?;
         let iface = InterfaceModuleRef::new(iface, hSystem);
-        let module_handle = Box::new(ModuleHandle::Interface(iface));
-
         unsafe {
-            *phIface = module_handle.into_raw();
+            *phIface = ModuleHandle::Interface(iface).into_raw()?;
         }
 
         Ok(())
@@ -196,7 +204,7 @@ gentl_api! {
         pbChanged: *mut bool8_t,
         _iTimeout: u64,
     ) -> GenTlResult<()> {
-        let handle = unsafe { ModuleHandle::from_raw_manually_drop(hSystem)? };
+        let handle = ModuleHandle::from_raw(hSystem)?;
         let system_handle = handle.system()?;
         let handle_guard = system_handle.lock().unwrap();
 
@@ -0,0 +1,280 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::convert::TryFrom;
+
+use cameleon::payload::{
+    GenDcComponent, GenDcContainer, GenDcPart, GenDcPartKind, ImageInfo, PixelFormat,
+};
+
+use crate::{GenTlError, GenTlResult};
+
+/// Data type of a buffer part.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PartDataType {
+    /// Unknown data type.
+    Unknown = 0,
+
+    /// Color or monochrome 2D image.
+    Image2D = 1,
+
+    /// Single color plane of a planar 2D image consisting of 2 planes.
+    Plane2DBiplanar = 2,
+
+    /// Single color plane of a planar 2D image consisting of 3 planes.
+    Plane2DTriplanar = 3,
+
+    /// Single color plane of a planar 2D image consisting of 4 planes.
+    Plane2DQuadplanar = 4,
+
+    /// 3D image (pixel coordinates).
+    Image3D = 5,
+
+    /// Single plane of a planar 3D image consisting of 2 planes.
+    Plane3DBiplanar = 6,
+
+    /// Single plane of a planar 3D image consisting of 3 planes.
+    Plane3DTriplanar = 7,
+
+    /// Single plane of a planar 3D image consisting of 4 planes.
+    Plane3DQuadplanar = 8,
+
+    /// Confidence of the individual pixel values.
+    ConfidenceMap = 9,
+
+    /// Chunk data or metadata of the payload.
+    ChunkData = 10,
+
+    /// JPEG compressed image.
+    Jpeg = 11,
+
+    /// JPEG 2000 compressed image.
+    Jpeg2000 = 12,
+}
+
+/// Describes one part of a multi-part buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BufferPart {
+    /// Data type of the part.
+    pub(crate) data_type: PartDataType,
+
+    /// Pixel format of the part. `None` if the part doesn't contain pixel data.
+    pub(crate) pixel_format: Option<PixelFormat>,
+
+    /// Offset of the part data from the beginning of the buffer in bytes.
+    pub(crate) offset: usize,
+
+    /// Size of the part data in bytes.
+    pub(crate) size: usize,
+
+    /// Width of the part in pixels.
+    pub(crate) width: usize,
+
+    /// Height of the part in pixels.
+    pub(crate) height: usize,
+
+    /// X offset of the part in pixels.
+    pub(crate) x_offset: usize,
+
+    /// Y offset of the part in pixels.
+    pub(crate) y_offset: usize,
+
+    /// Number of padding bytes at the end of each row of the part.
+    pub(crate) x_padding: usize,
+
+    /// Source ID of the part.
+    pub(crate) source_id: u64,
+}
+
+impl BufferPart {
+    /// Constructs a part that describes an image of a single image payload.
+    pub(crate) fn from_image_info(image_info: &ImageInfo) -> Self {
+        Self {
+            data_type: PartDataType::Image2D,
+            pixel_format: Some(image_info.pixel_format),
+            offset: 0,
+            size: image_info.image_size,
+            width: image_info.width,
+            height: image_info.height,
+            x_offset: image_info.x_offset,
+            y_offset: image_info.y_offset,
+            x_padding: image_info.x_padding,
+            source_id: 0,
+        }
+    }
+
+    /// Constructs a part that describes `part` of `component` in a GenDC container. The offset of
+    /// the part is the offset in the container, which is copied to the buffer as received.
+    pub(crate) fn from_gendc(component: &GenDcComponent, part: &GenDcPart) -> Self {
+        let to_usize = |value: u64| usize::try_from(value).unwrap_or(usize::MAX);
+        let (data_type, width, height, x_padding) = match part.kind {
+            GenDcPartKind::Image {
+                width,
+                height,
+                x_padding,
+                ..
+            } => (
+                PartDataType::Image2D,
+                width as usize,
+                height as usize,
+                usize::from(x_padding),
+            ),
+            GenDcPartKind::Metadata => (PartDataType::ChunkData, 0, 0, 0),
+            GenDcPartKind::Array | GenDcPartKind::Unknown => (PartDataType::Unknown, 0, 0, 0),
+        };
+
+        Self {
+            data_type,
+            pixel_format: part.pixel_format(),
+            offset: to_usize(part.offset),
+            size: to_usize(part.size),
+            width,
+            height,
+            x_offset: component.region_offset_x as usize,
+            y_offset: component.region_offset_y as usize,
+            x_padding,
+            source_id: component.source_id.into(),
+        }
+    }
+
+    fn is_within(&self, buffer_len: usize) -> bool {
+        matches!(self.offset.checked_add(self.size), Some(end) if end <= buffer_len)
+    }
+}
+
+/// Parts contained in a delivered buffer.
+///
+/// Part unaware consumers keep accessing the whole buffer, so the buffer itself is never split
+/// into parts. `BufferParts` only describes where each part lies in the buffer.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BufferParts {
+    parts: Vec<BufferPart>,
+    is_incomplete: bool,
+}
+
+impl BufferParts {
+    /// Constructs `BufferParts` from parts described by the payload headers.
+    ///
+    /// If any part lies out of the delivered buffer, the buffer is flagged as incomplete.
+    pub(crate) fn new(parts: Vec<BufferPart>, buffer_len: usize) -> Self {
+        let is_incomplete = parts.iter().any(|part| !part.is_within(buffer_len));
+        Self {
+            parts,
+            is_incomplete,
+        }
+    }
+
+    /// Constructs `BufferParts` of a single image payload, the image is reported as one part.
+    pub(crate) fn single_image(image_info: &ImageInfo, buffer_len: usize) -> Self {
+        Self::new(vec![BufferPart::from_image_info(image_info)], buffer_len)
+    }
+
+    /// Constructs `BufferParts` of a GenDC container, each part of the components is reported as
+    /// one part in the order of the container.
+    pub(crate) fn gendc(container: &GenDcContainer<'_>, buffer_len: usize) -> Self {
+        let parts = container
+            .parts()
+            .map(|(component, part)| BufferPart::from_gendc(component, part))
+            .collect();
+        Self::new(parts, buffer_len)
+    }
+
+    /// Number of parts in the buffer.
+    pub(crate) fn part_count(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Returns information of the part specified by `part_index`.
+    pub(crate) fn part_info(&self, part_index: usize) -> GenTlResult<&BufferPart> {
+        self.parts.get(part_index).ok_or(GenTlError::InvalidIndex)
+    }
+
+    /// Returns `true` if any part lies out of the delivered buffer.
+    pub(crate) fn is_incomplete(&self) -> bool {
+        self.is_incomplete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_part() -> BufferPart {
+        BufferPart {
+            data_type: PartDataType::Image2D,
+            pixel_format: Some(PixelFormat::Mono8),
+            offset: 0,
+            size: 64 * 32,
+            width: 64,
+            height: 32,
+            x_offset: 0,
+            y_offset: 0,
+            x_padding: 0,
+            source_id: 0,
+        }
+    }
+
+    fn metadata_part(offset: usize, size: usize) -> BufferPart {
+        BufferPart {
+            data_type: PartDataType::ChunkData,
+            pixel_format: None,
+            offset,
+            size,
+            width: 0,
+            height: 0,
+            x_offset: 0,
+            y_offset: 0,
+            x_padding: 0,
+            source_id: 0,
+        }
+    }
+
+    #[test]
+    fn test_multi_part() {
+        let parts = BufferParts::new(vec![image_part(), metadata_part(64 * 32, 16)], 64 * 32 + 16);
+
+        assert!(!parts.is_incomplete());
+        assert_eq!(parts.part_count(), 2);
+
+        let image = parts.part_info(0).unwrap();
+        assert_eq!(image.data_type, PartDataType::Image2D);
+        assert_eq!(image.pixel_format, Some(PixelFormat::Mono8));
+        assert_eq!(image.width, 64);
+        assert_eq!(image.height, 32);
+
+        let metadata = parts.part_info(1).unwrap();
+        assert_eq!(metadata.data_type, PartDataType::ChunkData);
+        assert_eq!(metadata.offset, 64 * 32);
+        assert_eq!(metadata.size, 16);
+
+        assert!(parts.part_info(2).is_err());
+    }
+
+    #[test]
+    fn test_single_image() {
+        let image_info = ImageInfo {
+            width: 64,
+            height: 32,
+            x_offset: 0,
+            y_offset: 0,
+            pixel_format: PixelFormat::Mono8,
+            image_size: 64 * 32,
+            x_padding: 0,
+        };
+        let parts = BufferParts::single_image(&image_info, 64 * 32);
+
+        assert!(!parts.is_incomplete());
+        assert_eq!(parts.part_count(), 1);
+        assert_eq!(parts.part_info(0).unwrap(), &image_part());
+    }
+
+    #[test]
+    fn test_part_out_of_buffer() {
+        let parts = BufferParts::new(vec![image_part(), metadata_part(64 * 32, 16)], 64 * 32);
+        assert!(parts.is_incomplete());
+
+        let parts = BufferParts::new(vec![metadata_part(usize::MAX, 1)], 64 * 32);
+        assert!(parts.is_incomplete());
+    }
+}
@@ -0,0 +1,345 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Access status of a device and its conversions.
+//!
+//! The status is exposed to the consumer as the raw value of `DeviceAccessStatusReg` in the VM
+//! and `DEVICE_INFO_ACCESS_STATUS`. All conversions go through [`DeviceAccessStatus::as_raw`] and
+//! [`DeviceAccessStatus::from_raw`], and all changes of the status go through the transition
+//! functions, so that adding a variant fails to compile until every mapping handles it.
+
+use cameleon::{ControlError, ControlResult};
+
+use super::DeviceAccessFlag;
+use crate::{GenTlError, GenTlResult};
+
+/// The current accessibility of the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DeviceAccessStatus {
+    /// The current availability of the device is unknown.
+    Unknown,
+
+    /// The device is available to be opened for Read/Write access but it is currently not opened.
+    ReadWrite,
+
+    /// The device is available to be opened for Read access but is currently not opened.
+    ReadOnly,
+
+    /// The device is seen be the producer but is not available for access because it is not reachable.
+    NoAccess,
+
+    /// The device is already owned/opened by another entity.
+    Busy,
+
+    /// The device is already owned/opened by this GenTL Producer with RW access.
+    OpenReadWrite,
+
+    /// The device is already owned/opened by this GenTL Producer with RO access.
+    OpenReadOnly,
+}
+
+impl DeviceAccessStatus {
+    /// All statuses, in the order of their raw values.
+    pub(crate) const ALL: [Self; 7] = [
+        Self::Unknown,
+        Self::ReadWrite,
+        Self::ReadOnly,
+        Self::NoAccess,
+        Self::Busy,
+        Self::OpenReadWrite,
+        Self::OpenReadOnly,
+    ];
+
+    /// Returns the value of `DEVICE_ACCESS_STATUS` defined in GenTL specification.
+    pub(crate) const fn as_raw(self) -> u32 {
+        match self {
+            Self::Unknown => 0,
+            Self::ReadWrite => 1,
+            Self::ReadOnly => 2,
+            Self::NoAccess => 3,
+            Self::Busy => 4,
+            Self::OpenReadWrite => 5,
+            Self::OpenReadOnly => 6,
+        }
+    }
+
+    /// Returns the status whose value of `DEVICE_ACCESS_STATUS` is `raw`.
+    pub(crate) fn from_raw(raw: u32) -> GenTlResult<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|status| status.as_raw() == raw)
+            .ok_or_else(|| GenTlError::InvalidValue("Invalid value for DeviceAccessStatus".into()))
+    }
+
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            Self::Unknown => "Unknown",
+            Self::ReadWrite => "ReadWrite",
+            Self::ReadOnly => "ReadOnly",
+            Self::NoAccess => "NoAccess",
+            Self::Busy => "Busy",
+            Self::OpenReadWrite => "OpenReadWrite",
+            Self::OpenReadOnly => "OpenReadOnly",
+        }
+    }
+
+    pub(crate) fn is_opened(self) -> bool {
+        match self {
+            Self::OpenReadWrite | Self::OpenReadOnly => true,
+            Self::Unknown | Self::ReadWrite | Self::ReadOnly | Self::NoAccess | Self::Busy => false,
+        }
+    }
+
+    /// Status classified from the result of a probe, which opens the device and reads its ABRM
+    /// while it's not opened by the consumer.
+    ///
+    /// A device held by another process or another handle in this process is
+    /// [`DeviceAccessStatus::Busy`], and a device which can't be opened because of the permission
+    /// or doesn't answer within the timeout is [`DeviceAccessStatus::NoAccess`].
+    pub(crate) fn from_probe(result: &ControlResult<()>) -> Self {
+        match result {
+            Ok(()) => Self::ReadWrite,
+            Err(ControlError::Busy) | Err(ControlError::AlreadyOpenInProcess { .. }) => Self::Busy,
+            Err(ControlError::Io(..))
+            | Err(ControlError::Disconnected)
+            | Err(ControlError::Timeout) => Self::NoAccess,
+            Err(_) => Self::Unknown,
+        }
+    }
+
+    /// Status after the consumer opens the device with `flag`.
+    pub(crate) fn on_open(flag: DeviceAccessFlag) -> Self {
+        match flag {
+            DeviceAccessFlag::ReadOnly => Self::OpenReadOnly,
+            DeviceAccessFlag::Control | DeviceAccessFlag::Exclusive => Self::OpenReadWrite,
+        }
+    }
+
+    /// Status after the consumer opens the device with `flag` while it's not opened by the
+    /// consumer.
+    ///
+    /// A device available only for read access can be opened only with
+    /// [`DeviceAccessFlag::ReadOnly`].
+    pub(crate) fn try_open(self, flag: DeviceAccessFlag) -> GenTlResult<Self> {
+        match self {
+            Self::Unknown | Self::ReadWrite => Ok(Self::on_open(flag)),
+            Self::ReadOnly if flag == DeviceAccessFlag::ReadOnly => Ok(Self::on_open(flag)),
+            Self::ReadOnly | Self::NoAccess => Err(GenTlError::AccessDenied),
+            Self::Busy | Self::OpenReadWrite | Self::OpenReadOnly => Err(GenTlError::ResourceInUse),
+        }
+    }
+
+    /// Status after the consumer opens the device with `flag` again while it's opened with
+    /// `opened_with`.
+    ///
+    /// Only a read-only open is allowed while the device is opened with
+    /// [`DeviceAccessFlag::Control`], and the status is kept as is.
+    pub(crate) fn on_reopen(
+        self,
+        opened_with: DeviceAccessFlag,
+        flag: DeviceAccessFlag,
+    ) -> GenTlResult<Self> {
+        match (opened_with, flag) {
+            (DeviceAccessFlag::Control, DeviceAccessFlag::ReadOnly) if self.is_opened() => Ok(self),
+            _ => Err(GenTlError::ResourceInUse),
+        }
+    }
+
+    /// Status after the consumer closes the device. The availability is unknown until the next
+    /// enumeration.
+    pub(crate) fn on_close(self) -> Self {
+        Self::Unknown
+    }
+
+    /// Status after the device isn't found in an enumeration.
+    ///
+    /// An opened device keeps its status until the consumer closes it.
+    pub(crate) fn on_unplug(self) -> Self {
+        match self {
+            Self::OpenReadWrite | Self::OpenReadOnly => self,
+            Self::Unknown | Self::ReadWrite | Self::ReadOnly | Self::NoAccess | Self::Busy => {
+                Self::NoAccess
+            }
+        }
+    }
+
+    /// Status after the opened device is found to be disconnected while it's accessed.
+    ///
+    /// The device is released, and it can't be opened until it's found again in an enumeration.
+    pub(crate) fn on_disconnect(self) -> Self {
+        Self::NoAccess
+    }
+
+    /// Status after the departed device is found again in an enumeration.
+    pub(crate) fn on_replug(self) -> Self {
+        match self {
+            Self::NoAccess => Self::Unknown,
+            Self::Unknown
+            | Self::ReadWrite
+            | Self::ReadOnly
+            | Self::Busy
+            | Self::OpenReadWrite
+            | Self::OpenReadOnly => self,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_round_trip() {
+        for (i, &status) in DeviceAccessStatus::ALL.iter().enumerate() {
+            assert_eq!(status.as_raw(), i as u32);
+            assert_eq!(
+                DeviceAccessStatus::from_raw(status.as_raw()).unwrap(),
+                status
+            );
+        }
+
+        let len = DeviceAccessStatus::ALL.len() as u32;
+        assert!(DeviceAccessStatus::from_raw(len).is_err());
+        // `DEVICE_ACCESS_STATUS_CUSTOM_ID`.
+        assert!(DeviceAccessStatus::from_raw(1000).is_err());
+    }
+
+    #[test]
+    fn test_transitions() {
+        use DeviceAccessStatus::{NoAccess, OpenReadOnly, OpenReadWrite, Unknown};
+
+        assert_eq!(
+            DeviceAccessStatus::on_open(DeviceAccessFlag::ReadOnly),
+            OpenReadOnly
+        );
+        assert_eq!(
+            DeviceAccessStatus::on_open(DeviceAccessFlag::Exclusive),
+            OpenReadWrite
+        );
+
+        assert_eq!(
+            DeviceAccessStatus::on_open(DeviceAccessFlag::Control),
+            OpenReadWrite
+        );
+
+        for &status in &DeviceAccessStatus::ALL {
+            assert_eq!(status.on_close(), Unknown);
+            // An opened device keeps its status while unplugged, and the others become unknown
+            // when found again.
+            let unplugged = status.on_unplug();
+            assert_eq!(unplugged.is_opened(), status.is_opened());
+            if !status.is_opened() {
+                assert_eq!(unplugged, NoAccess);
+                assert_eq!(unplugged.on_replug(), Unknown);
+            }
+            // A device disconnected while it's opened can be opened again once it's found.
+            assert_eq!(status.on_disconnect(), NoAccess);
+            assert_eq!(status.on_disconnect().on_replug(), Unknown);
+        }
+    }
+
+    #[test]
+    fn test_try_open() {
+        use DeviceAccessFlag::{Control, Exclusive, ReadOnly};
+        use DeviceAccessStatus::{Busy, NoAccess, OpenReadOnly, OpenReadWrite, ReadWrite, Unknown};
+
+        for &status in &[Unknown, ReadWrite] {
+            assert_eq!(status.try_open(ReadOnly).unwrap(), OpenReadOnly);
+            assert_eq!(status.try_open(Control).unwrap(), OpenReadWrite);
+            assert_eq!(status.try_open(Exclusive).unwrap(), OpenReadWrite);
+        }
+
+        let read_only = DeviceAccessStatus::ReadOnly;
+        assert_eq!(read_only.try_open(ReadOnly).unwrap(), OpenReadOnly);
+        for &flag in &[Control, Exclusive] {
+            assert!(matches!(
+                read_only.try_open(flag),
+                Err(GenTlError::AccessDenied)
+            ));
+        }
+
+        for &flag in &[ReadOnly, Control, Exclusive] {
+            assert!(matches!(
+                NoAccess.try_open(flag),
+                Err(GenTlError::AccessDenied)
+            ));
+            for &status in &[Busy, OpenReadWrite, OpenReadOnly] {
+                assert!(matches!(
+                    status.try_open(flag),
+                    Err(GenTlError::ResourceInUse)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_probe() {
+        use std::io;
+
+        assert_eq!(
+            DeviceAccessStatus::from_probe(&Ok(())),
+            DeviceAccessStatus::ReadWrite
+        );
+
+        // The device is held by another process or another handle in this process.
+        let busy = [
+            ControlError::Busy,
+            ControlError::AlreadyOpenInProcess {
+                holder_tag: "another-handle".into(),
+            },
+        ];
+        for err in busy {
+            assert_eq!(
+                DeviceAccessStatus::from_probe(&Err(err)),
+                DeviceAccessStatus::Busy
+            );
+        }
+
+        let permission_denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let no_access = [
+            ControlError::Io(permission_denied.into()),
+            ControlError::Disconnected,
+            ControlError::Timeout,
+        ];
+        for err in no_access {
+            assert_eq!(
+                DeviceAccessStatus::from_probe(&Err(err)),
+                DeviceAccessStatus::NoAccess
+            );
+        }
+
+        // The device is reachable but doesn't behave as expected.
+        assert_eq!(
+            DeviceAccessStatus::from_probe(&Err(ControlError::InvalidDevice("bad ABRM".into()))),
+            DeviceAccessStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn test_reopen() {
+        use DeviceAccessFlag::{Control, Exclusive, ReadOnly};
+
+        // A device opened for control can be shared with a read-only open.
+        let status = DeviceAccessStatus::on_open(Control);
+        assert_eq!(status.on_reopen(Control, ReadOnly).unwrap(), status);
+        for &flag in &[Control, Exclusive] {
+            assert!(matches!(
+                status.on_reopen(Control, flag),
+                Err(GenTlError::ResourceInUse)
+            ));
+        }
+
+        // Exclusive and read-only opens are never shared.
+        for &opened_with in &[Exclusive, ReadOnly] {
+            let status = DeviceAccessStatus::on_open(opened_with);
+            for &flag in &[ReadOnly, Control, Exclusive] {
+                assert!(matches!(
+                    status.on_reopen(opened_with, flag),
+                    Err(GenTlError::ResourceInUse)
+                ));
+            }
+        }
+    }
+}
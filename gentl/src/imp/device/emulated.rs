@@ -0,0 +1,1386 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Device module of emulated U3V devices, which lets consumers be tested without cameras.
+//!
+//! Emulators built by [`cameleon_device::emulator::EmulatorBuilder`] are listed in the U3V
+//! interface module alongside real devices, see [`crate::imp::system::SystemModule`] for how to
+//! enable them. The data stream of an emulated device receives the image frames sent by the
+//! emulator.
+
+use std::{
+    convert::TryInto,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use cameleon::{
+    emulator::{disable_stream_channel, enable_stream_channel, EmulatedStream},
+    limits::Limits,
+    payload::{self, PayloadReceiver},
+    u3v::{
+        self,
+        register_map::{Abrm, ManifestTable, Sbrm},
+        Guid,
+    },
+    ControlError, ControlResult, DeviceControl, PayloadStream,
+};
+use cameleon_device::{
+    emulator::{self, ControlChannel},
+    u3v::protocol::{ack, cmd},
+};
+use cameleon_impl::memory::prelude::*;
+
+use crate::{
+    imp::{
+        event::{EventData, EventRegistry, EventSource, EventType},
+        genapi_common,
+        port::{
+            self, Endianness, ModuleType, Port, PortAccess, PortInfo, SharedPort, TlType, XmlInfo,
+            XmlLocation,
+        },
+        stream::{
+            u3v::{StreamCamera, U3VDataStreamModule, STREAM_ID},
+            DataStream,
+        },
+    },
+    GenTlError, GenTlResult,
+};
+
+use super::{
+    opens::{CloseAction, Opens},
+    u3v::{link_speed, remote_port_info, remote_xml_infos, Channel, Connection, TransactionLimits},
+    u3v_genapi as genapi, Device, DeviceAccessFlag, DeviceAccessStatus, DeviceInfoCmd,
+    DeviceInfoValue,
+};
+use genapi::GenApiReg;
+
+/// Prefix of IDs of emulated devices, which distinguishes them from real devices.
+pub(crate) const ID_PREFIX: &str = "emu-";
+
+/// Events fired by the device module.
+const SUPPORTED_EVENTS: &[EventType] = &[EventType::Error, EventType::FeatureInvalidate];
+
+/// Timeout of each transaction, an emulated device responds immediately.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Maximum command and acknowledge packet length used until SBRM of the device is read.
+const INITIAL_MAXIMUM_PACKET_LENGTH: usize = 128;
+
+/// Length of prefix and CCD of a packet.
+const PACKET_HEADER_LENGTH: usize = 12;
+
+/// Length of the address field of `WriteMem` command.
+const WRITE_MEM_ADDRESS_LENGTH: usize = 8;
+
+/// Enumerates emulated devices in the device pool of the emulator.
+pub(crate) fn enumerate_emulated_device() -> GenTlResult<Vec<EmulatedDeviceModule>> {
+    emulator::enumerate_devices()
+        .map_err(ControlError::from)?
+        .into_iter()
+        .map(EmulatedDeviceModule::new)
+        .collect()
+}
+
+pub(crate) struct EmulatedDeviceModule {
+    vm: genapi::Memory,
+    port_info: PortInfo,
+    xml_infos: Vec<XmlInfo>,
+    /// GenTL events registered by the consumer, see [`super::u3v::U3VDeviceModule`].
+    events: Arc<EventRegistry>,
+
+    device: emulator::Device,
+    remote_device: Option<Arc<EmulatedRemoteDevice>>,
+    data_stream: Option<Box<Mutex<U3VDataStreamModule<EmulatedCamera>>>>,
+
+    /// Current status of the device, see [`super::u3v::U3VDeviceModule`] for the difference from
+    /// `DeviceAccessStatusReg` in VM.
+    current_status: DeviceAccessStatus,
+    /// See [`super::u3v::U3VDeviceModule`].
+    opened_with: Option<DeviceAccessFlag>,
+    /// See [`super::u3v::U3VDeviceModule`].
+    opens: Opens,
+}
+
+impl EmulatedDeviceModule {
+    fn new(device: emulator::Device) -> GenTlResult<Self> {
+        let port_info = PortInfo {
+            id: format!("{}{}", ID_PREFIX, device.device_info.guid),
+            vendor: genapi::VENDOR_NAME.into(),
+            model: genapi::MODEL_NAME.into(),
+            tl_type: genapi::DEVICE_TYPE,
+            module_type: ModuleType::Device,
+            endianness: Endianness::LE,
+            access: PortAccess::RW,
+            version: semver::Version::new(
+                genapi::XML_MAJOR_VERSION,
+                genapi::XML_MINOR_VERSION,
+                genapi::XML_SUBMINOR_VERSION,
+            ),
+            port_name: genapi::PORT_NAME.into(),
+        };
+
+        let mut xml_info = XmlInfo {
+            location: XmlLocation::RegisterMap {
+                address: genapi::GENAPI_XML_ADDRESS as u64,
+                size: genapi::GENAPI_XML_LENGTH,
+            },
+            schema_version: semver::Version::new(
+                genapi_common::SCHEME_MAJOR_VERSION,
+                genapi_common::SCHEME_MINOR_VERSION,
+                genapi_common::SCHEME_SUBMINOR_VERSION,
+            ),
+            file_version: semver::Version::new(
+                genapi::XML_MAJOR_VERSION,
+                genapi::XML_MINOR_VERSION,
+                genapi::XML_SUBMINOR_VERSION,
+            ),
+            sha1_hash: None,
+            compressed: cameleon::genapi::CompressionType::Uncompressed,
+        };
+        let mut vm = genapi::Memory::new();
+        genapi_common::zip_module_xml::<genapi::GenApiXml::Xml, _>(
+            &mut vm,
+            &mut xml_info,
+            &port_info,
+        )?;
+
+        let mut dev = Self {
+            vm,
+            port_info,
+            xml_infos: vec![xml_info],
+            events: Arc::new(EventRegistry::new(SUPPORTED_EVENTS)),
+
+            device,
+            remote_device: None,
+            data_stream: None,
+
+            current_status: DeviceAccessStatus::Unknown,
+            opened_with: None,
+            opens: Opens::default(),
+        };
+
+        dev.initialize_vm()?;
+        Ok(dev)
+    }
+
+    pub(crate) fn device_info(&self) -> &u3v::DeviceInfo {
+        &self.device.device_info
+    }
+
+    pub(crate) fn guid(&self) -> Guid {
+        self.device.device_info.guid
+    }
+
+    pub(crate) fn is_opened(&self) -> bool {
+        Device::device_access_status(self).is_opened()
+    }
+
+    fn is_unplugged(&self) -> bool {
+        self.remote_device
+            .as_ref()
+            .is_some_and(|remote_device| remote_device.connection.is_unplugged())
+    }
+
+    /// See [`super::u3v::U3VDeviceModule`] for how the unplugged device is released.
+    fn handle_unplug(&mut self) {
+        if !self.is_unplugged() {
+            return;
+        }
+
+        if let Some(data_stream) = self.data_stream.take() {
+            data_stream.lock().unwrap().close().ok();
+        }
+        if let Some(remote_device) = self.remote_device.take() {
+            remote_device.ctrl.lock().unwrap().close().ok();
+        }
+        self.opened_with = None;
+        self.current_status = self.current_status.on_disconnect();
+    }
+
+    /// See [`super::u3v::U3VDeviceModule::reflect_status`].
+    pub(crate) fn reflect_status(&mut self) {
+        self.handle_unplug();
+        self.vm
+            .write::<GenApiReg::DeviceAccessStatus>(self.current_status.as_raw())
+            .unwrap();
+    }
+
+    /// See [`super::u3v::U3VDeviceModule::access_status`].
+    pub(crate) fn access_status(&self) -> DeviceAccessStatus {
+        let raw_value = self.vm.read::<GenApiReg::DeviceAccessStatus>().unwrap();
+        // Ok to unwrap because DeviceAccessStatus is RO register.
+        DeviceAccessStatus::from_raw(raw_value).unwrap()
+    }
+
+    pub(crate) fn force_access_status(&mut self, status: DeviceAccessStatus) {
+        self.current_status = status;
+        self.reflect_status();
+    }
+
+    /// Probes the availability of the device by claiming its control channel, and writes the
+    /// result into the current status. The channel is released before returning.
+    ///
+    /// The status of the device opened by the consumer is kept as is.
+    pub(crate) fn probe_status(&mut self) -> DeviceAccessStatus {
+        if self.is_opened() {
+            return self.current_status;
+        }
+
+        let result = self.device.control_channel().and_then(|mut channel| {
+            channel.open()?;
+            channel.close()
+        });
+        self.current_status = DeviceAccessStatus::from_probe(&result.map_err(ControlError::from));
+        self.current_status
+    }
+
+    fn assert_open(&self) -> GenTlResult<()> {
+        if self.is_opened() {
+            Ok(())
+        } else {
+            Err(GenTlError::NotInitialized)
+        }
+    }
+
+    fn remote(&self) -> GenTlResult<Arc<EmulatedRemoteDevice>> {
+        self.assert_open()?;
+
+        Ok(self.remote_device.clone().unwrap())
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.opened_with
+            .is_none_or(|opened_with| opened_with == DeviceAccessFlag::ReadOnly)
+    }
+
+    /// See [`super::u3v::U3VDeviceModule`], the control channel of the emulated device has
+    /// nothing to release.
+    fn release_control(&mut self) -> GenTlResult<()> {
+        if self.opened_with.is_none() {
+            return Ok(());
+        }
+
+        if let Some(data_stream) = self.data_stream.take() {
+            data_stream.lock().unwrap().close()?;
+        }
+        self.opened_with = Some(DeviceAccessFlag::ReadOnly);
+        self.current_status = DeviceAccessStatus::on_open(DeviceAccessFlag::ReadOnly);
+        Ok(())
+    }
+
+    /// See [`super::u3v::U3VDeviceModule`].
+    fn close_device(&mut self) -> GenTlResult<()> {
+        self.opens = Opens::default();
+        if self.opened_with.is_none() {
+            // Events of the unplugged device are kept until it's closed.
+            self.events.unregister_all();
+            return Ok(());
+        }
+
+        if let Some(data_stream) = self.data_stream.take() {
+            data_stream.lock().unwrap().close()?;
+        }
+        self.events.unregister_all();
+        self.opened_with = None;
+        self.current_status = self.current_status.on_close();
+        // The consumer may still hold the remote device, so close its control to make it unusable.
+        match self.remote_device.take() {
+            Some(remote_device) => Ok(remote_device.ctrl.lock().unwrap().close()?),
+            None => Ok(()),
+        }
+    }
+
+    /// Writes `DeviceUserID` in VM to the remote device if it's changed.
+    ///
+    /// If the remote device rejects the name, `DeviceUserID` in VM is restored and the consumer is
+    /// notified to invalidate the feature.
+    fn handle_device_user_id_change(&mut self) -> GenTlResult<()> {
+        let name = self.vm.read::<GenApiReg::DeviceUserID>()?;
+        let remote = self.remote()?;
+        if remote.user_defined_name().unwrap_or_default() == name {
+            return Ok(());
+        }
+
+        let result = if !self.is_read_only() {
+            remote.set_user_defined_name(&name)
+        } else {
+            Err(GenTlError::AccessDenied)
+        };
+        if result.is_err() {
+            let name = remote.user_defined_name().unwrap_or_default();
+            self.vm.write::<GenApiReg::DeviceUserID>(name).unwrap();
+            self.events.notify(EventData::FeatureInvalidate {
+                feature: "DeviceUserID".into(),
+            });
+        }
+
+        result
+    }
+
+    /// See [`super::u3v::U3VDeviceModule`] for how the enable flag of `channel` is written to the
+    /// remote device.
+    fn handle_channel_enable_change(&mut self, channel: Channel) -> GenTlResult<()> {
+        let enable = channel.read_reg(&self.vm)?;
+        let is_read_only = self.is_read_only();
+        let (result, is_enabled) = {
+            let remote = self.remote()?;
+            let abrm = remote.abrm.lock().unwrap();
+            let ctrl = &mut *remote.ctrl.lock().unwrap();
+            let result = if !is_read_only {
+                channel
+                    .set_enable(ctrl, &abrm, enable)
+                    .map_err(GenTlError::from)
+            } else {
+                Err(GenTlError::AccessDenied)
+            };
+            let is_enabled = channel.is_enabled(ctrl, &abrm).unwrap_or(false);
+            (result, is_enabled)
+        };
+
+        if let Err(err) = result {
+            channel.write_reg(&mut self.vm, is_enabled);
+            self.events.notify(EventData::FeatureInvalidate {
+                feature: channel.feature().into(),
+            });
+            self.events.notify(EventData::Error(err));
+        }
+        Ok(())
+    }
+
+    fn initialize_vm(&mut self) -> GenTlResult<()> {
+        let device_info = self.device.device_info.clone();
+        self.vm
+            .write::<GenApiReg::DeviceID>(self.port_info.id.clone())?;
+        self.vm
+            .write::<GenApiReg::DeviceVendorName>(device_info.vendor_name)?;
+        self.vm
+            .write::<GenApiReg::DeviceModelName>(device_info.model_name)?;
+        self.vm
+            .write::<GenApiReg::DeviceUserID>(device_info.user_defined_name.unwrap_or_default())?;
+        self.vm.write::<GenApiReg::StreamSelectorMax>(0)?;
+        self.vm.write::<GenApiReg::StreamID>(STREAM_ID.into())?;
+        self.reflect_status();
+        Ok(())
+    }
+}
+
+impl EventSource for EmulatedDeviceModule {
+    fn events(&self) -> &EventRegistry {
+        &self.events
+    }
+}
+
+impl Drop for EmulatedDeviceModule {
+    fn drop(&mut self) {
+        self.force_close().ok();
+    }
+}
+
+impl Port for EmulatedDeviceModule {
+    fn read(&self, address: u64, buf: &mut [u8]) -> GenTlResult<usize> {
+        self.assert_open()?;
+
+        let range = port::memory_range(address, buf.len())?;
+        let len = buf.len();
+
+        let data = self.vm.read_raw(range)?;
+        buf.copy_from_slice(data);
+
+        Ok(len)
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) -> GenTlResult<usize> {
+        self.assert_open()?;
+
+        let range = port::memory_range(address, data.len())?;
+        self.vm.write_raw(range.start, data)?;
+        self.handle_device_user_id_change()?;
+        for &channel in &[Channel::Stream, Channel::Event] {
+            let reg_range = channel.reg_range();
+            if range.start < reg_range.end && reg_range.start < range.end {
+                self.handle_channel_enable_change(channel)?;
+            }
+        }
+
+        Ok(data.len())
+    }
+
+    fn port_info(&self) -> GenTlResult<&PortInfo> {
+        self.assert_open()?;
+
+        Ok(&self.port_info)
+    }
+
+    fn xml_infos(&self) -> GenTlResult<&[XmlInfo]> {
+        self.assert_open()?;
+
+        Ok(&self.xml_infos)
+    }
+}
+
+impl Device for EmulatedDeviceModule {
+    fn open(&mut self, access_flag: DeviceAccessFlag) -> GenTlResult<()> {
+        self.handle_unplug();
+        if let Some(opened_with) = self.opened_with {
+            self.current_status = self.current_status.on_reopen(opened_with, access_flag)?;
+            self.opens.add(access_flag);
+            return Ok(());
+        }
+
+        let status = self.current_status.try_open(access_flag)?;
+        let access = match access_flag {
+            DeviceAccessFlag::ReadOnly => PortAccess::RO,
+            DeviceAccessFlag::Control | DeviceAccessFlag::Exclusive => PortAccess::RW,
+        };
+
+        let channel = self.device.control_channel().map_err(ControlError::from)?;
+        let id = self.port_info.id.clone();
+        let connection = Connection::new(self.events.clone());
+        let remote_device = EmulatedRemoteDevice::new(channel, id, access, connection)?;
+        self.remote_device = Some(Arc::new(remote_device));
+        // Opens left unclosed since the device was unplugged are forgotten.
+        self.opens = Opens::default();
+        self.opens.add(access_flag);
+        self.opened_with = Some(access_flag);
+        self.current_status = status;
+        Ok(())
+    }
+
+    fn close(&mut self, opened_with: DeviceAccessFlag) -> GenTlResult<()> {
+        self.handle_unplug();
+        match self.opens.remove(opened_with) {
+            CloseAction::Keep => Ok(()),
+            CloseAction::ReleaseControl => self.release_control(),
+            CloseAction::Close => self.close_device(),
+        }
+    }
+
+    fn force_close(&mut self) -> GenTlResult<()> {
+        self.handle_unplug();
+        self.close_device()
+    }
+
+    fn device_id(&self) -> &str {
+        &self.port_info.id
+    }
+
+    fn remote_device(&self) -> GenTlResult<Arc<dyn SharedPort>> {
+        Ok(self.remote()?)
+    }
+
+    fn device_info(&self, cmd: DeviceInfoCmd) -> GenTlResult<DeviceInfoValue> {
+        use DeviceInfoValue::{AccessStatus, UInt64};
+
+        let info = self.device_info();
+        Ok(match cmd {
+            DeviceInfoCmd::Id => DeviceInfoValue::String(self.device_id().into()),
+            DeviceInfoCmd::Vendor => DeviceInfoValue::String(info.vendor_name.clone()),
+            DeviceInfoCmd::Model => DeviceInfoValue::String(info.model_name.clone()),
+            DeviceInfoCmd::TlType => DeviceInfoValue::TlType(self.tl_type()),
+            DeviceInfoCmd::DisplayName => DeviceInfoValue::String(format!(
+                "{} {} ({})",
+                info.vendor_name,
+                info.model_name,
+                self.device_id()
+            )),
+            DeviceInfoCmd::AccessStatus => AccessStatus(self.device_access_status()),
+            DeviceInfoCmd::UserDefinedName => {
+                let name = if self.is_opened() {
+                    self.remote()?.user_defined_name()
+                } else {
+                    info.user_defined_name.clone()
+                };
+                DeviceInfoValue::String(name.ok_or(GenTlError::NotAvailable)?)
+            }
+            DeviceInfoCmd::SerialNumber => DeviceInfoValue::String(info.serial_number.clone()),
+            DeviceInfoCmd::Version => DeviceInfoValue::String(info.device_version.clone()),
+            // `TIMESTAMP INCREMENT` is ns/tick of the device internal clock.
+            DeviceInfoCmd::TimestampFrequency => {
+                match self.remote()?.abrm.lock().unwrap().timestamp_increment() {
+                    0 => return Err(GenTlError::NotAvailable),
+                    increment => UInt64(1_000_000_000 / increment),
+                }
+            }
+            DeviceInfoCmd::LinkSpeed => UInt64(link_speed(info.supported_speed)),
+        })
+    }
+
+    fn tl_type(&self) -> TlType {
+        TlType::USB3Vision
+    }
+
+    fn device_access_status(&self) -> DeviceAccessStatus {
+        if self.is_unplugged() {
+            self.current_status.on_disconnect()
+        } else {
+            self.current_status
+        }
+    }
+
+    fn num_data_streams(&self) -> GenTlResult<usize> {
+        self.assert_open()?;
+
+        // Streaming requires write access to the remote device.
+        if self.is_read_only() {
+            Ok(0)
+        } else {
+            Ok(1)
+        }
+    }
+
+    fn data_stream_id(&self, index: usize) -> GenTlResult<&str> {
+        if index < self.num_data_streams()? {
+            Ok(STREAM_ID)
+        } else {
+            Err(GenTlError::InvalidIndex)
+        }
+    }
+
+    fn open_data_stream(&mut self, stream_id: &str) -> GenTlResult<&Mutex<dyn DataStream>> {
+        self.assert_open()?;
+
+        if self.is_read_only() {
+            return Err(GenTlError::AccessDenied);
+        }
+        if stream_id != STREAM_ID {
+            return Err(GenTlError::InvalidId(stream_id.into()));
+        }
+
+        if self.data_stream.is_none() {
+            let camera = EmulatedCamera::new(&self.device, self.remote()?)?;
+            let data_stream = U3VDataStreamModule::new(
+                Arc::new(Mutex::new(camera)),
+                &self.device_info().serial_number,
+            );
+            self.data_stream = Some(Box::new(Mutex::new(data_stream)));
+        }
+        // Ok to unwrap because the data stream has just been inserted if it's absent.
+        let data_stream = self.data_stream.as_mut().unwrap();
+        data_stream.get_mut().unwrap().open()?;
+        Ok(&**data_stream)
+    }
+}
+
+/// Camera over the stream channel of an emulated device, which streams payloads to the data
+/// stream module.
+pub(crate) struct EmulatedCamera {
+    remote: Arc<EmulatedRemoteDevice>,
+    strm: EmulatedStream,
+}
+
+impl EmulatedCamera {
+    fn new(device: &emulator::Device, remote: Arc<EmulatedRemoteDevice>) -> GenTlResult<Self> {
+        let mut strm = EmulatedStream::new(device)?;
+        strm.open()?;
+        Ok(Self { remote, strm })
+    }
+}
+
+impl StreamCamera for EmulatedCamera {
+    fn start_streaming(&mut self, capacity: usize) -> GenTlResult<PayloadReceiver> {
+        let (sender, receiver) = payload::channel(capacity, capacity);
+        let ctrl = &mut *self.remote.ctrl.lock().unwrap();
+        ctrl.enable_streaming()?;
+        if let Err(err) = self.strm.start_streaming_loop(sender, ctrl) {
+            ctrl.disable_streaming().ok();
+            return Err(err.into());
+        }
+        Ok(receiver)
+    }
+
+    fn stop_streaming(&mut self) -> GenTlResult<()> {
+        self.strm.stop_streaming_loop()?;
+        Ok(self.remote.ctrl.lock().unwrap().disable_streaming()?)
+    }
+
+    fn dropped_payloads(&self) -> u64 {
+        self.strm.dropped_payloads()
+    }
+
+    fn payload_size(&self) -> GenTlResult<u64> {
+        let abrm = self.remote.abrm.lock().unwrap();
+        let ctrl = &mut *self.remote.ctrl.lock().unwrap();
+        let sirm = abrm.sbrm(ctrl)?.sirm().ok_or(GenTlError::NotAvailable)?;
+        Ok(sirm.required_payload_size(ctrl)?)
+    }
+}
+
+/// Port of the emulated remote device, which is shared between threads in the same way as
+/// [`super::u3v::U3VRemoteDevice`].
+pub(crate) struct EmulatedRemoteDevice {
+    // `DeviceControl` requires `&mut` access even for reads.
+    // Lock `abrm` first if both are locked.
+    ctrl: Mutex<EmulatedControl>,
+    abrm: Mutex<Abrm>,
+    limits: TransactionLimits,
+    connection: Connection,
+    port_info: PortInfo,
+    xml_infos: Vec<XmlInfo>,
+}
+
+impl EmulatedRemoteDevice {
+    fn new(
+        channel: ControlChannel,
+        id: String,
+        access: PortAccess,
+        connection: Connection,
+    ) -> GenTlResult<Self> {
+        let mut ctrl = EmulatedControl::new(channel);
+        ctrl.open()?;
+        match Self::read_registers(&mut ctrl) {
+            Ok((abrm, sbrm, manifest_table)) => Ok(Self {
+                port_info: remote_port_info(id, &abrm, access),
+                xml_infos: remote_xml_infos(&manifest_table)?,
+                limits: TransactionLimits::new(&sbrm),
+                connection,
+                ctrl: Mutex::new(ctrl),
+                abrm: Mutex::new(abrm),
+            }),
+            Err(err) => {
+                ctrl.close().ok();
+                Err(err.into())
+            }
+        }
+    }
+
+    fn read_registers(ctrl: &mut EmulatedControl) -> ControlResult<(Abrm, Sbrm, ManifestTable)> {
+        let abrm = Abrm::new(ctrl)?;
+        let sbrm = abrm.sbrm(ctrl)?;
+        ctrl.maximum_cmd_length = sbrm.maximum_command_transfer_length() as usize;
+        ctrl.maximum_ack_length = sbrm.maximum_acknowledge_trasfer_length() as usize;
+        let manifest_table = abrm.manifest_table(ctrl, &Limits::default())?;
+        Ok((abrm, sbrm, manifest_table))
+    }
+
+    /// `USER DEFINED NAME` cached in ABRM.
+    fn user_defined_name(&self) -> Option<String> {
+        self.abrm
+            .lock()
+            .unwrap()
+            .user_defined_name()
+            .map(Into::into)
+    }
+
+    fn set_user_defined_name(&self, name: &str) -> GenTlResult<()> {
+        let mut abrm = self.abrm.lock().unwrap();
+        abrm.set_user_defined_name(&mut *self.ctrl.lock().unwrap(), name)?;
+        Ok(())
+    }
+}
+
+impl SharedPort for EmulatedRemoteDevice {
+    fn read(&self, address: u64, buf: &mut [u8]) -> GenTlResult<usize> {
+        self.connection.assert_connected()?;
+
+        self.limits
+            .read_chunked(address, buf, |address, chunk| {
+                self.ctrl.lock().unwrap().read(address, chunk)
+            })
+            .map_err(|err| self.connection.on_error(err))?;
+        Ok(buf.len())
+    }
+
+    fn write(&self, address: u64, data: &[u8]) -> GenTlResult<usize> {
+        self.connection.assert_connected()?;
+        if !self.port_info.access.is_writable() {
+            return Err(GenTlError::AccessDenied);
+        }
+
+        self.limits
+            .write_chunked(address, data, |address, chunk| {
+                self.ctrl.lock().unwrap().write(address, chunk)
+            })
+            .map_err(|err| self.connection.on_error(err))?;
+        Ok(data.len())
+    }
+
+    fn port_info(&self) -> GenTlResult<&PortInfo> {
+        Ok(&self.port_info)
+    }
+
+    fn xml_infos(&self) -> GenTlResult<&[XmlInfo]> {
+        Ok(&self.xml_infos)
+    }
+}
+
+/// [`DeviceControl`] on the control channel of an emulated device.
+struct EmulatedControl {
+    channel: ControlChannel,
+    request_ids: cmd::RequestIdGenerator,
+    /// Buffer for serializing/deserializing a packet.
+    buffer: Vec<u8>,
+    maximum_cmd_length: usize,
+    maximum_ack_length: usize,
+}
+
+impl EmulatedControl {
+    fn new(channel: ControlChannel) -> Self {
+        Self {
+            channel,
+            request_ids: cmd::RequestIdGenerator::new(0),
+            buffer: Vec::new(),
+            maximum_cmd_length: INITIAL_MAXIMUM_PACKET_LENGTH,
+            maximum_ack_length: INITIAL_MAXIMUM_PACKET_LENGTH,
+        }
+    }
+
+    fn assert_open(&self) -> ControlResult<()> {
+        if self.is_opened() {
+            Ok(())
+        } else {
+            Err(ControlError::NotOpened)
+        }
+    }
+
+    /// Sends `scd` and receives its final ack, then returns the ack parsed by `f`.
+    fn transact<T, U>(
+        &mut self,
+        scd: T,
+        f: impl FnOnce(&ack::AckPacket) -> ControlResult<U>,
+    ) -> ControlResult<U>
+    where
+        T: cmd::CommandScd,
+    {
+        let cmd = cmd::CommandPacket::new(scd, self.request_ids.next_id());
+        let len = std::cmp::max(cmd.cmd_len(), cmd.maximum_ack_len());
+        if self.buffer.len() < len {
+            self.buffer.resize(len, 0);
+        }
+        cmd.serialize(self.buffer.as_mut_slice())?;
+        self.channel
+            .send(&self.buffer[..cmd.cmd_len()], TRANSACTION_TIMEOUT)?;
+
+        let mut timeout = TRANSACTION_TIMEOUT;
+        loop {
+            let recv_len = self.channel.recv(&mut self.buffer, timeout)?;
+            let ack = ack::AckPacket::parse(&self.buffer[..recv_len])?;
+            if ack.request_id() != cmd.request_id() {
+                return Err(ControlError::RequestIdMismatch {
+                    expected: cmd.request_id(),
+                    actual: ack.request_id(),
+                });
+            }
+            let status = *ack.status();
+            if !status.is_success() {
+                return Err(ControlError::Io(StatusError(status).into()));
+            }
+
+            // Wait for the final ack with the same request id.
+            if ack.scd_kind() == ack::ScdKind::Pending {
+                let pending: ack::Pending = ack.scd_as()?;
+                timeout = pending.timeout;
+                continue;
+            }
+
+            return f(&ack);
+        }
+    }
+}
+
+impl DeviceControl for EmulatedControl {
+    fn open(&mut self) -> ControlResult<()> {
+        if self.is_opened() {
+            return Ok(());
+        }
+
+        self.channel.open()?;
+        // Clear a halt left by the previous owner of the channel.
+        let result = self
+            .channel
+            .set_halt(TRANSACTION_TIMEOUT)
+            .and_then(|_| self.channel.clear_halt());
+        if let Err(err) = result {
+            self.channel.close().ok();
+            return Err(err.into());
+        }
+        self.maximum_cmd_length = INITIAL_MAXIMUM_PACKET_LENGTH;
+        self.maximum_ack_length = INITIAL_MAXIMUM_PACKET_LENGTH;
+        Ok(())
+    }
+
+    fn close(&mut self) -> ControlResult<()> {
+        Ok(self.channel.close()?)
+    }
+
+    fn is_opened(&self) -> bool {
+        self.channel.is_opened()
+    }
+
+    fn read(&mut self, address: u64, buf: &mut [u8]) -> ControlResult<()> {
+        self.assert_open()?;
+
+        let maximum_read_len = cmd::ReadMem::maximum_read_length(self.maximum_ack_length) as usize;
+        for (i, chunk) in buf.chunks_mut(maximum_read_len).enumerate() {
+            let offset = i * maximum_read_len;
+            let scd = cmd::ReadMem::new(address + offset as u64, chunk.len().try_into()?);
+            let read = self.transact(scd, |ack| {
+                let data = ack.scd_as::<ack::ReadMem>()?.data;
+                if data.len() == chunk.len() {
+                    chunk.copy_from_slice(data);
+                }
+                Ok(data.len())
+            })?;
+            if read != chunk.len() {
+                return Err(ControlError::PartialChunkRead {
+                    offset,
+                    requested: chunk.len(),
+                    read,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) -> ControlResult<()> {
+        self.assert_open()?;
+
+        let maximum_data_len = std::cmp::min(
+            self.maximum_cmd_length - PACKET_HEADER_LENGTH - WRITE_MEM_ADDRESS_LENGTH,
+            u16::MAX as usize - WRITE_MEM_ADDRESS_LENGTH,
+        );
+        for (i, chunk) in data.chunks(maximum_data_len).enumerate() {
+            let offset = i * maximum_data_len;
+            let scd = cmd::WriteMem::new(address + offset as u64, chunk)?;
+            let written =
+                self.transact(
+                    scd,
+                    |ack| Ok(ack.scd_as::<ack::WriteMem>()?.length as usize),
+                )?;
+            if written != chunk.len() {
+                return Err(ControlError::PartialChunkWrite {
+                    offset,
+                    requested: chunk.len(),
+                    written,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn genapi(&mut self) -> ControlResult<String> {
+        Err(ControlError::NotSupported(
+            "GenApi context of an emulated device".into(),
+        ))
+    }
+
+    fn enable_streaming(&mut self) -> ControlResult<()> {
+        enable_stream_channel(self)
+    }
+
+    fn disable_streaming(&mut self) -> ControlResult<()> {
+        disable_stream_channel(self)
+    }
+}
+
+/// An error of an ack whose status isn't success, which is carried in [`ControlError::Io`].
+#[derive(Debug, thiserror::Error)]
+#[error("invalid status: {0}")]
+struct StatusError(ack::Status);
+
+#[cfg(test)]
+mod tests {
+    use cameleon_device::emulator::EmulatorBuilder;
+
+    use super::*;
+    use crate::imp::stream::{BufferInfoValue, BufferPartInfoCmd, StreamInfoCmd, StreamInfoValue};
+
+    /// Builds an emulator with `serial_number` and returns its device module.
+    fn emulated_device(serial_number: &str) -> EmulatedDeviceModule {
+        EmulatorBuilder::new()
+            .serial_number(serial_number)
+            .unwrap()
+            .build();
+        enumerate_emulated_device()
+            .unwrap()
+            .into_iter()
+            .find(|dev| dev.device_info().serial_number == serial_number)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_open() {
+        let mut dev = emulated_device("GENTLEMU1");
+        assert!(dev.device_id().starts_with(ID_PREFIX));
+        assert!(dev.remote_device().is_err());
+
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        assert_eq!(
+            dev.device_access_status(),
+            DeviceAccessStatus::OpenReadWrite
+        );
+        assert_eq!(dev.num_data_streams().unwrap(), 1);
+        assert_eq!(dev.data_stream_id(0).unwrap(), STREAM_ID);
+        {
+            let remote_device = dev.remote_device().unwrap();
+            let port_info = remote_device.port_info().unwrap();
+            assert_eq!(port_info.id, dev.device_id());
+            assert_eq!(port_info.vendor, dev.vendor_name().unwrap());
+            assert!(!remote_device.xml_infos().unwrap().is_empty());
+
+            // Read `GenCP Version` register of ABRM.
+            let mut buf = [0; 4];
+            assert_eq!(remote_device.read(0, &mut buf).unwrap(), 4);
+        }
+
+        dev.close(DeviceAccessFlag::Exclusive).unwrap();
+        assert!(!dev.is_opened());
+    }
+
+    #[test]
+    fn test_data_stream() {
+        let mut dev = emulated_device("GENTLEMU8");
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        let data_stream = dev.open_data_stream(STREAM_ID).unwrap();
+        let mut data_stream = data_stream.lock().unwrap();
+
+        let payload_size = match data_stream.stream_info(StreamInfoCmd::PayloadSize).unwrap() {
+            StreamInfoValue::SizeT(size) => size,
+            value => panic!("unexpected value: {:?}", value),
+        };
+        assert_eq!(payload_size, 640 * 480);
+        for user_data in 0..2 {
+            let handle = data_stream
+                .alloc_and_announce_buffer(payload_size, user_data)
+                .unwrap();
+            data_stream.queue_buffer(handle).unwrap();
+        }
+
+        data_stream.start_acquisition(Some(2)).unwrap();
+        let mut frame_ids = Vec::new();
+        for _ in 0..2 {
+            let handle = data_stream
+                .wait_filled_buffer(Duration::from_secs(1))
+                .unwrap();
+            let info = data_stream.buffer_info(handle).unwrap();
+            let filled = info.filled().unwrap();
+            assert_eq!(filled.size_filled, payload_size);
+            assert!(!filled.is_incomplete);
+            let image_info = filled.image_info.as_ref().unwrap();
+            assert_eq!((image_info.width, image_info.height), (640, 480));
+            frame_ids.push(filled.frame_id);
+        }
+        assert!(frame_ids[0] < frame_ids[1]);
+        data_stream.stop_acquisition().unwrap();
+        drop(data_stream);
+
+        let delivered = cameleon::metrics::snapshot()
+            .into_iter()
+            .find(|metric| {
+                metric.name == "cameleon_gentl_stream_delivered_buffers_total"
+                    && metric.label(cameleon::metrics::DEVICE_SERIAL_LABEL) == Some("GENTLEMU8")
+            })
+            .unwrap();
+        assert_eq!(
+            delivered.label(cameleon::metrics::STREAM_INDEX_LABEL),
+            Some("0")
+        );
+        assert_eq!(delivered.value, cameleon::metrics::MetricValue::Counter(2));
+
+        dev.close(DeviceAccessFlag::Exclusive).unwrap();
+    }
+
+    #[test]
+    fn test_gendc_data_stream() {
+        use cameleon::payload::{PayloadType, PixelFormat};
+        use cameleon_device::fixture::{Fixture, StreamPayload, StreamSettings};
+
+        use crate::imp::buffer::PartDataType;
+
+        let fixture = Fixture {
+            stream: Some(StreamSettings {
+                payload: StreamPayload::GenDc,
+                ..StreamSettings::default()
+            }),
+            ..Fixture::default()
+        };
+        EmulatorBuilder::with_fixture(fixture)
+            .unwrap()
+            .serial_number("GENTLEMU11")
+            .unwrap()
+            .build();
+        let mut dev = enumerate_emulated_device()
+            .unwrap()
+            .into_iter()
+            .find(|dev| dev.device_info().serial_number == "GENTLEMU11")
+            .unwrap();
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        let data_stream = dev.open_data_stream(STREAM_ID).unwrap();
+        let mut data_stream = data_stream.lock().unwrap();
+
+        // The container consists of the descriptor, the image and 16 bytes of metadata.
+        let descriptor_size = 272;
+        let image_size = 640 * 480;
+        let payload_size = match data_stream.stream_info(StreamInfoCmd::PayloadSize).unwrap() {
+            StreamInfoValue::SizeT(size) => size,
+            value => panic!("unexpected value: {:?}", value),
+        };
+        assert_eq!(payload_size, descriptor_size + image_size + 16);
+        let handle = data_stream
+            .alloc_and_announce_buffer(payload_size, 0)
+            .unwrap();
+        data_stream.queue_buffer(handle).unwrap();
+
+        data_stream.start_acquisition(Some(1)).unwrap();
+        let handle = data_stream
+            .wait_filled_buffer(Duration::from_secs(1))
+            .unwrap();
+        let info = data_stream.buffer_info(handle).unwrap();
+        let filled = info.filled().unwrap();
+        assert_eq!(filled.payload_type, Some(PayloadType::GenDc));
+        assert!(!filled.is_incomplete);
+        assert_eq!(info.num_parts().unwrap(), 2);
+
+        let image = filled.parts.part_info(0).unwrap();
+        assert_eq!(image.data_type, PartDataType::Image2D);
+        assert_eq!(image.pixel_format, Some(PixelFormat::Mono8));
+        assert_eq!((image.width, image.height), (640, 480));
+        assert_eq!((image.offset, image.size), (descriptor_size, image_size));
+        assert_eq!(
+            info.query_part(0, BufferPartInfoCmd::Base).unwrap(),
+            BufferInfoValue::Ptr(info.base.wrapping_add(descriptor_size))
+        );
+
+        let metadata = filled.parts.part_info(1).unwrap();
+        assert_eq!(metadata.data_type, PartDataType::ChunkData);
+        assert_eq!(
+            (metadata.offset, metadata.size),
+            (descriptor_size + image_size, 16)
+        );
+        assert!(matches!(
+            info.query_part(1, BufferPartInfoCmd::Width),
+            Err(GenTlError::NotAvailable)
+        ));
+        // The metadata holds the block ID of the frame.
+        let data = unsafe { std::slice::from_raw_parts(info.base, info.size) };
+        assert_eq!(
+            data[metadata.offset..metadata.offset + 8],
+            filled.frame_id.to_le_bytes()
+        );
+        assert!(info.query_part(2, BufferPartInfoCmd::Base).is_err());
+
+        data_stream.stop_acquisition().unwrap();
+        drop(data_stream);
+        dev.close(DeviceAccessFlag::Exclusive).unwrap();
+    }
+
+    #[test]
+    fn test_device_info() {
+        let mut dev = emulated_device("GENTLEMU6");
+        let display_name = format!(
+            "{} {} ({})",
+            dev.vendor_name().unwrap(),
+            dev.model_name().unwrap(),
+            dev.device_id()
+        );
+        assert_eq!(dev.display_name().unwrap(), display_name);
+        assert_eq!(dev.serial_number().unwrap(), "GENTLEMU6");
+        assert_eq!(
+            Device::device_info(&dev, DeviceInfoCmd::TlType).unwrap(),
+            DeviceInfoValue::TlType(TlType::USB3Vision)
+        );
+        assert!(matches!(
+            Device::device_info(&dev, DeviceInfoCmd::LinkSpeed).unwrap(),
+            DeviceInfoValue::UInt64(speed) if speed > 0
+        ));
+        // The timestamp frequency is read from the remote device.
+        assert!(dev.timestamp_frequency().is_err());
+
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        assert_eq!(
+            Device::device_info(&dev, DeviceInfoCmd::AccessStatus).unwrap(),
+            DeviceInfoValue::AccessStatus(DeviceAccessStatus::OpenReadWrite)
+        );
+        // The emulator clock runs at 1MHz.
+        assert_eq!(dev.timestamp_frequency().unwrap(), 1_000_000);
+        dev.close(DeviceAccessFlag::Exclusive).unwrap();
+    }
+
+    #[test]
+    fn test_unplug() {
+        let mut dev = emulated_device("GENTLEMU7");
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        let queue = dev.register_event(EventType::Error).unwrap();
+        let remote_device = dev.remote_device().unwrap();
+        let mut buf = [0; 4];
+
+        dev.device.unplug().unwrap();
+        assert!(matches!(
+            remote_device.read(0, &mut buf),
+            Err(GenTlError::Disconnected)
+        ));
+        assert!(matches!(
+            queue.get_data(Some(Duration::ZERO)).unwrap(),
+            EventData::Error(GenTlError::Disconnected)
+        ));
+        // The following accesses fail without waiting for the timeout.
+        assert!(matches!(
+            remote_device.read(0, &mut buf),
+            Err(GenTlError::InvalidHandle)
+        ));
+        assert!(matches!(
+            remote_device.write(0, &buf),
+            Err(GenTlError::InvalidHandle)
+        ));
+        assert_eq!(dev.device_access_status(), DeviceAccessStatus::NoAccess);
+        assert!(!dev.is_opened());
+
+        // The device is released when the device list is updated.
+        dev.reflect_status();
+        assert_eq!(dev.access_status(), DeviceAccessStatus::NoAccess);
+        assert!(dev.remote_device().is_err());
+        assert!(enumerate_emulated_device()
+            .unwrap()
+            .iter()
+            .all(|found| found.guid() != dev.guid()));
+
+        // The replugged device is found with the same GUID, and can be opened again once the
+        // device list marks it as found.
+        dev.device.replug().unwrap();
+        assert!(enumerate_emulated_device()
+            .unwrap()
+            .iter()
+            .any(|found| found.guid() == dev.guid()));
+        dev.close(DeviceAccessFlag::Exclusive).unwrap();
+        dev.force_access_status(dev.access_status().on_replug());
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        assert_eq!(dev.remote_device().unwrap().read(0, &mut buf).unwrap(), 4);
+        dev.close(DeviceAccessFlag::Exclusive).unwrap();
+    }
+
+    #[test]
+    fn test_unplug_shared_open() {
+        let mut dev = emulated_device("GENTLEMU9");
+        dev.open(DeviceAccessFlag::Control).unwrap();
+        dev.open(DeviceAccessFlag::ReadOnly).unwrap();
+        let queue = dev.register_event(EventType::Error).unwrap();
+        let remote_device = dev.remote_device().unwrap();
+        let mut buf = [0; 4];
+
+        dev.device.unplug().unwrap();
+        assert!(remote_device.read(0, &mut buf).is_err());
+        dev.reflect_status();
+        assert_eq!(dev.access_status(), DeviceAccessStatus::NoAccess);
+        assert!(dev.remote_device().is_err());
+
+        // Events are kept until both opens are closed.
+        dev.close(DeviceAccessFlag::ReadOnly).unwrap();
+        assert!(dev.events().is_registered(EventType::Error));
+        assert!(matches!(
+            queue.get_data(Some(Duration::ZERO)).unwrap(),
+            EventData::Error(GenTlError::Disconnected)
+        ));
+        dev.close(DeviceAccessFlag::Control).unwrap();
+        assert!(!dev.events().is_registered(EventType::Error));
+
+        // The shared open before the unplug doesn't keep the replugged device opened.
+        dev.device.replug().unwrap();
+        dev.force_access_status(dev.access_status().on_replug());
+        dev.open(DeviceAccessFlag::Control).unwrap();
+        assert_eq!(dev.remote_device().unwrap().read(0, &mut buf).unwrap(), 4);
+        dev.close(DeviceAccessFlag::Control).unwrap();
+        assert!(!dev.is_opened());
+        assert!(dev.remote_device().is_err());
+    }
+
+    #[test]
+    fn test_close_owner_before_reader() {
+        let mut dev = emulated_device("GENTLEMU10");
+        dev.open(DeviceAccessFlag::Control).unwrap();
+        dev.open(DeviceAccessFlag::ReadOnly).unwrap();
+        dev.open_data_stream(STREAM_ID).unwrap();
+        let remote_device = dev.remote_device().unwrap();
+        let mut buf = [0; 4];
+
+        // The control is released while the reader keeps the device opened read-only.
+        dev.close(DeviceAccessFlag::Control).unwrap();
+        assert!(dev.is_opened());
+        assert_eq!(dev.device_access_status(), DeviceAccessStatus::OpenReadOnly);
+        assert_eq!(dev.num_data_streams().unwrap(), 0);
+        assert!(dev.open_data_stream(STREAM_ID).is_err());
+        assert_eq!(remote_device.read(0, &mut buf).unwrap(), 4);
+        assert!(matches!(
+            dev.open(DeviceAccessFlag::Control),
+            Err(GenTlError::ResourceInUse)
+        ));
+
+        // The device is closed with the last reader, then it can be opened for control again.
+        dev.close(DeviceAccessFlag::ReadOnly).unwrap();
+        assert!(!dev.is_opened());
+        assert!(remote_device.read(0, &mut buf).is_err());
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        assert_eq!(
+            dev.device_access_status(),
+            DeviceAccessStatus::OpenReadWrite
+        );
+        dev.close(DeviceAccessFlag::Exclusive).unwrap();
+    }
+
+    #[test]
+    fn test_probe_busy_device() {
+        let mut dev = emulated_device("GENTLEMU2");
+        assert_eq!(dev.probe_status(), DeviceAccessStatus::ReadWrite);
+
+        let mut other = enumerate_emulated_device()
+            .unwrap()
+            .into_iter()
+            .find(|other| other.guid() == dev.guid())
+            .unwrap();
+        other.open(DeviceAccessFlag::Exclusive).unwrap();
+        assert_eq!(dev.probe_status(), DeviceAccessStatus::Busy);
+        assert!(matches!(
+            dev.open(DeviceAccessFlag::Exclusive),
+            Err(GenTlError::ResourceInUse)
+        ));
+
+        other.close(DeviceAccessFlag::Exclusive).unwrap();
+        assert_eq!(dev.probe_status(), DeviceAccessStatus::ReadWrite);
+    }
+
+    #[test]
+    fn test_remote_device_read_stacked() {
+        let mut dev = emulated_device("GENTLEMU3");
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        let remote_device = dev.remote_device().unwrap();
+
+        // Read `GenCP Version` and `Manufacturer Name` registers of ABRM.
+        let mut version = [0; 4];
+        let mut manufacturer = [0; 64];
+        let mut invalid = [0; 4];
+        let mut entries: [(u64, &mut [u8]); 3] = [
+            (0, &mut version),
+            (4, &mut manufacturer),
+            (u64::MAX - 1, &mut invalid),
+        ];
+        let mut read_count = 0;
+        assert!(remote_device
+            .read_stacked(&mut entries[..2], &mut read_count)
+            .is_ok());
+        assert_eq!(read_count, 2);
+
+        // Entries before the failed one are reported as read.
+        assert!(remote_device
+            .read_stacked(&mut entries, &mut read_count)
+            .is_err());
+        assert_eq!(read_count, 2);
+        assert_ne!(version, [0; 4]);
+        assert_ne!(manufacturer[0], 0);
+    }
+
+    #[test]
+    fn test_remote_device_concurrent_read() {
+        let mut dev = emulated_device("GENTLEMU5");
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        let remote_device = dev.remote_device().unwrap();
+
+        // One thread reads the device XML, which takes multiple transactions, while the other
+        // reads `Manufacturer Name` register of ABRM.
+        let (xml_address, xml_size) = match remote_device.xml_infos().unwrap()[0].location {
+            XmlLocation::RegisterMap { address, size } => (address, size.min(4096)),
+            _ => unreachable!(),
+        };
+        let accesses = [(xml_address, xml_size), (4, 64)];
+        let handles: Vec<_> = accesses
+            .iter()
+            .map(|&(address, size)| {
+                let remote_device = remote_device.clone();
+                std::thread::spawn(move || {
+                    let mut expected = vec![0; size];
+                    remote_device.read(address, &mut expected).unwrap();
+                    for _ in 0..1000 {
+                        // A request ID mismatch is returned as an error.
+                        let mut buf = vec![0; size];
+                        remote_device.read(address, &mut buf).unwrap();
+                        assert_eq!(buf, expected);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_channel_enable() {
+        let mut dev = emulated_device("GENTLEMU4");
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        let errors = dev.register_event(EventType::Error).unwrap();
+
+        // Configure SIRM so that the emulator accepts the stream enable flag.
+        let sirm = {
+            let remote = dev.remote().unwrap();
+            let abrm = remote.abrm.lock().unwrap();
+            let ctrl = &mut *remote.ctrl.lock().unwrap();
+            let sirm = abrm.sbrm(ctrl).unwrap().sirm().unwrap();
+            let payload_size = sirm.required_payload_size(ctrl).unwrap();
+            sirm.set_maximum_leader_size(ctrl, 1024).unwrap();
+            sirm.set_maximum_trailer_size(ctrl, 1024).unwrap();
+            sirm.set_payload_transfer_size(ctrl, 1024).unwrap();
+            sirm.set_payload_transfer_count(ctrl, (payload_size / 1024 + 1) as u32)
+                .unwrap();
+            sirm
+        };
+        let is_stream_enabled = |dev: &EmulatedDeviceModule| {
+            let remote = dev.remote().unwrap();
+            let mut ctrl = remote.ctrl.lock().unwrap();
+            sirm.is_stream_enable(&mut *ctrl).unwrap()
+        };
+
+        let address = GenApiReg::StreamEnable::ADDRESS as u64;
+        dev.write(address, &1_u32.to_le_bytes()).unwrap();
+        assert!(is_stream_enabled(&dev));
+        dev.write(address, &0_u32.to_le_bytes()).unwrap();
+        assert!(!is_stream_enabled(&dev));
+
+        let eirm = {
+            let remote = dev.remote().unwrap();
+            let abrm = remote.abrm.lock().unwrap();
+            let ctrl = &mut *remote.ctrl.lock().unwrap();
+            abrm.sbrm(ctrl).unwrap().eirm().unwrap()
+        };
+        let is_event_enabled = |dev: &EmulatedDeviceModule| {
+            let remote = dev.remote().unwrap();
+            let mut ctrl = remote.ctrl.lock().unwrap();
+            eirm.is_event_enable(&mut *ctrl).unwrap()
+        };
+
+        let address = GenApiReg::EventEnable::ADDRESS as u64;
+        dev.write(address, &1_u32.to_le_bytes()).unwrap();
+        assert!(is_event_enabled(&dev));
+        dev.write(address, &0_u32.to_le_bytes()).unwrap();
+        assert!(!is_event_enabled(&dev));
+        assert!(errors.get_data(Some(Duration::ZERO)).is_err());
+    }
+
+    #[test]
+    fn test_channel_enable_without_eirm() {
+        EmulatorBuilder::new()
+            .serial_number("GENTLEMU12")
+            .unwrap()
+            .event_interface(false)
+            .build();
+        let mut dev = enumerate_emulated_device()
+            .unwrap()
+            .into_iter()
+            .find(|dev| dev.device_info().serial_number == "GENTLEMU12")
+            .unwrap();
+        dev.open(DeviceAccessFlag::Exclusive).unwrap();
+        let errors = dev.register_event(EventType::Error).unwrap();
+
+        // The device has no EIRM, so the flag is restored and the error is notified instead of
+        // being returned.
+        let address = GenApiReg::EventEnable::ADDRESS as u64;
+        assert_eq!(dev.write(address, &1_u32.to_le_bytes()).unwrap(), 4);
+        let mut buf = [0xff; 4];
+        dev.read(address, &mut buf).unwrap();
+        assert_eq!(buf, [0; 4]);
+        assert!(matches!(
+            errors.get_data(Some(Duration::ZERO)),
+            Ok(EventData::Error(_))
+        ));
+    }
+}
@@ -2,72 +2,30 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::{convert::TryFrom, sync::Mutex};
+use std::{
+    convert::TryFrom,
+    sync::{Arc, Mutex},
+};
 
 use crate::{GenTlError, GenTlResult};
 
 pub(crate) mod u3v;
 
-use crate::imp::port::{Port, TlType};
+#[cfg(feature = "emulator")]
+pub(crate) mod emulated;
 
-mod u3v_genapi;
-
-/// The current accessibility of the device.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub(crate) enum DeviceAccessStatus {
-    /// The current availability of the device is unknown.
-    Unknown = 0,
-
-    /// The device is available to be opened for Read/Write access but it is currently not opened.
-    ReadWrite = 1,
-
-    /// The device is available to be opened for Read access but is currently not opened.
-    ReadOnly = 2,
-
-    /// The device is seen be the producer but is not available for access because it is not reachable.
-    NoAccess = 3,
-
-    /// The device is already owned/opened by another entity.
-    Busy = 4,
-
-    /// The device is already owned/opened by this GenTL Producer with RW access.
-    OpenReadWrite = 5,
+mod access_status;
+mod opens;
 
-    /// The device is already owned/opened by this GenTL Producer with RO access.
-    OpenReadOnly = 6,
-}
+pub(crate) use access_status::DeviceAccessStatus;
 
-impl DeviceAccessStatus {
-    pub(crate) const fn as_str(self) -> &'static str {
-        match self {
-            Self::Unknown => "Unknown",
-            Self::ReadWrite => "ReadWrite",
-            Self::ReadOnly => "ReadOnly",
-            Self::NoAccess => "NoAccess",
-            Self::Busy => "Busy",
-            Self::OpenReadWrite => "OpenReadWrite",
-            Self::OpenReadOnly => "OpenReadOnly",
-        }
-    }
-}
+use crate::imp::{
+    event::EventSource,
+    port::{Port, SharedPort, TlType},
+    stream::DataStream,
+};
 
-impl TryFrom<i32> for DeviceAccessStatus {
-    type Error = GenTlError;
-    fn try_from(value: i32) -> GenTlResult<Self> {
-        match value {
-            _ if value == (Self::Unknown as i32) => Ok(Self::Unknown),
-            _ if value == (Self::ReadWrite as i32) => Ok(Self::ReadWrite),
-            _ if value == (Self::ReadOnly as i32) => Ok(Self::ReadOnly),
-            _ if value == (Self::NoAccess as i32) => Ok(Self::NoAccess),
-            _ if value == (Self::Busy as i32) => Ok(Self::Busy),
-            _ if value == (Self::OpenReadWrite as i32) => Ok(Self::OpenReadWrite),
-            _ if value == (Self::OpenReadOnly as i32) => Ok(Self::OpenReadOnly),
-            _ => Err(GenTlError::InvalidValue(
-                "Invalid value for DeviceAccessStatus".into(),
-            )),
-        }
-    }
-}
+mod u3v_genapi;
 
 /// This enume defines different modes how a device is to be opened with the IFOpenDevice function.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -82,36 +40,50 @@ pub(crate) enum DeviceAccessFlag {
     Exclusive,
 }
 
-impl DeviceAccessStatus {
-    pub(crate) fn is_opened(self) -> bool {
-        use DeviceAccessStatus::{OpenReadOnly, OpenReadWrite};
-
-        matches!(self, OpenReadOnly | OpenReadWrite)
-    }
-}
-
-pub(crate) trait Device: Port {
+pub(crate) trait Device: Port + EventSource + Send {
     /// Open the device and the remote device.
     fn open(&mut self, access_flag: DeviceAccessFlag) -> GenTlResult<()>;
 
-    /// close the device and the remote device.
-    fn close(&mut self) -> GenTlResult<()>;
+    /// Close an open of the device made with `opened_with`.
+    ///
+    /// The device and the remote device are closed when the last open sharing them is closed.
+    /// When the open owning the control of the device is closed while read-only opens share it,
+    /// the control is released and the device stays opened read-only.
+    fn close(&mut self, opened_with: DeviceAccessFlag) -> GenTlResult<()>;
+
+    /// Close the device and the remote device even if other opens share the device, which is
+    /// used when the parent module is closed.
+    fn force_close(&mut self) -> GenTlResult<()>;
 
     /// ID of the device module.
     fn device_id(&self) -> &str;
 
     /// Port of the remote device.
-    fn remote_device(&self) -> GenTlResult<&Mutex<dyn Port>>;
+    ///
+    /// The port can be cloned and used from multiple threads at the same time, e.g. a feature can
+    /// be read while the device XML is being downloaded. The port stays valid after the device is
+    /// closed, but any access to it fails then.
+    fn remote_device(&self) -> GenTlResult<Arc<dyn SharedPort>>;
+
+    /// Returns the value of the device information specified by `cmd`.
+    /// If the information is not available, return [`GenTlError::NotAvailable`].
+    fn device_info(&self, cmd: DeviceInfoCmd) -> GenTlResult<DeviceInfoValue>;
 
     /// Vendor name of the remote device.
-    fn vendor_name(&self) -> GenTlResult<String>;
+    fn vendor_name(&self) -> GenTlResult<String> {
+        self.device_info(DeviceInfoCmd::Vendor)?.into_string()
+    }
 
     /// Model name of the remote device.
-    fn model_name(&self) -> GenTlResult<String>;
+    fn model_name(&self) -> GenTlResult<String> {
+        self.device_info(DeviceInfoCmd::Model)?.into_string()
+    }
 
     /// Display name of the remote device.
     /// If this is not defined in the device this should be “VENDOR MODEL (ID)”
-    fn display_name(&self) -> GenTlResult<String>;
+    fn display_name(&self) -> GenTlResult<String> {
+        self.device_info(DeviceInfoCmd::DisplayName)?.into_string()
+    }
 
     /// Transport layer type of the device.
     fn tl_type(&self) -> TlType;
@@ -121,14 +93,96 @@ pub(crate) trait Device: Port {
 
     /// User defined name of the device.
     /// If the information is not available, return [`GenTlError::NotAvailable`].
-    fn user_defined_name(&self) -> GenTlResult<String>;
+    fn user_defined_name(&self) -> GenTlResult<String> {
+        self.device_info(DeviceInfoCmd::UserDefinedName)?
+            .into_string()
+    }
 
     /// Serial number of the remote device.
-    fn serial_number(&self) -> GenTlResult<String>;
+    fn serial_number(&self) -> GenTlResult<String> {
+        self.device_info(DeviceInfoCmd::SerialNumber)?.into_string()
+    }
 
     /// evice version in string format.
-    fn device_version(&self) -> GenTlResult<String>;
+    fn device_version(&self) -> GenTlResult<String> {
+        self.device_info(DeviceInfoCmd::Version)?.into_string()
+    }
 
     /// Tick frequency of the device’s timestamp counter in ticks per second
-    fn timespamp_frequency(&self) -> GenTlResult<u64>;
+    fn timestamp_frequency(&self) -> GenTlResult<u64> {
+        self.device_info(DeviceInfoCmd::TimestampFrequency)?
+            .into_u64()
+    }
+
+    /// Number of data streams of the device.
+    fn num_data_streams(&self) -> GenTlResult<usize>;
+
+    /// ID of the data stream specified by `index`.
+    fn data_stream_id(&self, index: usize) -> GenTlResult<&str>;
+
+    /// Open the data stream whose ID is `stream_id`.
+    fn open_data_stream(&mut self, stream_id: &str) -> GenTlResult<&Mutex<dyn DataStream>>;
+
+    /// Tick frequency of the device’s timestamp counter in ticks per second
+    #[deprecated(note = "use `timestamp_frequency` instead")]
+    fn timespamp_frequency(&self) -> GenTlResult<u64> {
+        self.timestamp_frequency()
+    }
+}
+
+/// Information of a device queried by [`Device::device_info`], corresponds to `DEVICE_INFO_CMD`
+/// of GenTL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DeviceInfoCmd {
+    /// ID of the device module.
+    Id,
+    /// Vendor name of the remote device.
+    Vendor,
+    /// Model name of the remote device.
+    Model,
+    /// Transport layer type of the device.
+    TlType,
+    /// Display name of the device, “VENDOR MODEL (ID)” if the device doesn't define it.
+    DisplayName,
+    /// Access status of the device.
+    AccessStatus,
+    /// User defined name of the device.
+    UserDefinedName,
+    /// Serial number of the remote device.
+    SerialNumber,
+    /// Device version in string format.
+    Version,
+    /// Tick frequency of the device’s timestamp counter in ticks per second.
+    TimestampFrequency,
+    /// Speed of the link between the host and the device in bits per second.
+    LinkSpeed,
+}
+
+/// Value of [`DeviceInfoCmd`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum DeviceInfoValue {
+    String(String),
+    UInt64(u64),
+    TlType(TlType),
+    AccessStatus(DeviceAccessStatus),
+}
+
+impl DeviceInfoValue {
+    fn into_string(self) -> GenTlResult<String> {
+        match self {
+            Self::String(value) => Ok(value),
+            _ => Err(GenTlError::InvalidValue(
+                "device info isn't a string".into(),
+            )),
+        }
+    }
+
+    fn into_u64(self) -> GenTlResult<u64> {
+        match self {
+            Self::UInt64(value) => Ok(value),
+            _ => Err(GenTlError::InvalidValue(
+                "device info isn't an integer".into(),
+            )),
+        }
+    }
 }
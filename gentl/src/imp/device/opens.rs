@@ -0,0 +1,110 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Bookkeeping of the opens sharing a device module.
+
+use super::DeviceAccessFlag;
+
+/// Opens of a device module by the consumer.
+///
+/// A device is owned by at most one open with [`DeviceAccessFlag::Control`] or
+/// [`DeviceAccessFlag::Exclusive`], and read-only opens may share the device opened for control,
+/// see [`super::DeviceAccessStatus::on_reopen`]. The owner and the readers are tracked separately
+/// so that the control is released as soon as the owner is closed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Opens {
+    owner: Option<DeviceAccessFlag>,
+    num_readers: usize,
+}
+
+/// What the device module does when an open is closed, see [`Opens::remove`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum CloseAction {
+    /// Other opens still share the device with the same access.
+    Keep,
+    /// The owner is closed while read-only opens remain, so the control of the device is released
+    /// and the device is kept opened read-only.
+    ReleaseControl,
+    /// The last open is closed, so the device is closed.
+    Close,
+}
+
+impl Opens {
+    /// Records an open with `flag`.
+    pub(crate) fn add(&mut self, flag: DeviceAccessFlag) {
+        match flag {
+            DeviceAccessFlag::ReadOnly => self.num_readers += 1,
+            DeviceAccessFlag::Control | DeviceAccessFlag::Exclusive => self.owner = Some(flag),
+        }
+    }
+
+    /// Removes an open with `flag`, and returns what the device module should do.
+    ///
+    /// Closing an open which isn't recorded closes the device only if no open is recorded, so
+    /// closing an unopened device stays harmless.
+    pub(crate) fn remove(&mut self, flag: DeviceAccessFlag) -> CloseAction {
+        match flag {
+            DeviceAccessFlag::ReadOnly if self.num_readers > 0 => self.num_readers -= 1,
+            DeviceAccessFlag::Control | DeviceAccessFlag::Exclusive if self.owner == Some(flag) => {
+                self.owner = None;
+                if self.num_readers > 0 {
+                    return CloseAction::ReleaseControl;
+                }
+            }
+            _ => {}
+        }
+
+        if self.is_empty() {
+            CloseAction::Close
+        } else {
+            CloseAction::Keep
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.owner.is_none() && self.num_readers == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use DeviceAccessFlag::{Control, Exclusive, ReadOnly};
+
+    #[test]
+    fn test_close_owner_first() {
+        let mut opens = Opens::default();
+        opens.add(Control);
+        opens.add(ReadOnly);
+        opens.add(ReadOnly);
+
+        assert_eq!(opens.remove(Control), CloseAction::ReleaseControl);
+        assert_eq!(opens.remove(ReadOnly), CloseAction::Keep);
+        assert_eq!(opens.remove(ReadOnly), CloseAction::Close);
+        assert!(opens.is_empty());
+    }
+
+    #[test]
+    fn test_close_readers_first() {
+        let mut opens = Opens::default();
+        opens.add(Control);
+        opens.add(ReadOnly);
+
+        assert_eq!(opens.remove(ReadOnly), CloseAction::Keep);
+        assert_eq!(opens.remove(Control), CloseAction::Close);
+    }
+
+    #[test]
+    fn test_close_unrecorded() {
+        let mut opens = Opens::default();
+        assert_eq!(opens.remove(Exclusive), CloseAction::Close);
+
+        // Closing an open which isn't recorded doesn't close the opens sharing the device.
+        opens.add(Control);
+        assert_eq!(opens.remove(ReadOnly), CloseAction::Keep);
+        assert_eq!(opens.remove(Exclusive), CloseAction::Keep);
+        assert_eq!(opens.remove(Control), CloseAction::Close);
+    }
+}
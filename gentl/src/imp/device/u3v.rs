@@ -2,52 +2,128 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::{convert::TryFrom, sync::Mutex};
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
 use cameleon::{
     genapi::{CompressionType, SharedDefaultGenApiCtxt},
-    u3v::{self, SharedControlHandle, StreamHandle},
+    u3v::{
+        self,
+        register_map::{Abrm, GenICamFileType, ManifestEntry, ManifestTable, Sbrm},
+        DeviceFilter, Guid, OpenOptions, SharedControlHandle, StreamHandle,
+    },
+    ControlError, ControlResult, DeviceControl,
 };
-use cameleon_impl::memory::prelude::*;
+use cameleon_impl::memory::{prelude::*, MemoryObserver};
 
 use crate::{
     imp::{
+        event::{EventData, EventRegistry, EventSource, EventType},
         genapi_common,
-        port::{Endianness, ModuleType, Port, PortAccess, PortInfo, TlType, XmlInfo, XmlLocation},
+        port::{
+            self, Endianness, ModuleType, Port, PortAccess, PortInfo, SharedPort, TlType, XmlInfo,
+            XmlLocation,
+        },
+        stream::{
+            u3v::{U3VDataStreamModule, STREAM_ID},
+            DataStream,
+        },
     },
     GenTlError, GenTlResult,
 };
 
-use super::{u3v_genapi as genapi, Device, DeviceAccessStatus};
+use super::{
+    opens::{CloseAction, Opens},
+    u3v_genapi as genapi, Device, DeviceAccessFlag, DeviceAccessStatus, DeviceInfoCmd,
+    DeviceInfoValue,
+};
 use genapi::GenApiReg;
 
-type Camera = cameleon::Camera<SharedControlHandle, StreamHandle, SharedDefaultGenApiCtxt>;
+pub(crate) type Camera =
+    cameleon::Camera<SharedControlHandle, StreamHandle, SharedDefaultGenApiCtxt>;
+
+/// Events fired by the device module.
+const SUPPORTED_EVENTS: &[EventType] = &[
+    EventType::Error,
+    EventType::FeatureInvalidate,
+    EventType::RemoteDevice,
+];
+
+/// Tag reported when the device is opened by the device module, see
+/// [`SharedControlHandle::set_open_tag`].
+const OPEN_TAG: &str = "gentl-device-module";
+
+/// Timeout of each transaction while probing the device, which is kept short so that a wedged
+/// device doesn't stall the update of the device list.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(200);
 
-pub(crate) fn enumerate_u3v_device() -> GenTlResult<Vec<U3VDeviceModule>> {
-    todo!()
+/// Options the control handle is opened with for the consumer.
+fn open_options() -> OpenOptions {
+    OpenOptions::new().open_tag(OPEN_TAG)
+}
+
+/// Enumerates U3V devices, which are restricted to the ones matching `filter` if it's given.
+pub(crate) fn enumerate_u3v_device(
+    filter: Option<&DeviceFilter>,
+) -> GenTlResult<Vec<U3VDeviceModule>> {
+    let cameras = match filter {
+        Some(filter) => u3v::enumerate_cameras_with(filter)?,
+        None => u3v::enumerate_cameras()?,
+    };
+
+    cameras
+        .into_iter()
+        .map(|camera| U3VDeviceModule::new(camera.convert_into()))
+        .collect()
 }
 
 pub(crate) struct U3VDeviceModule {
     vm: genapi::Memory,
     port_info: PortInfo,
     xml_infos: Vec<XmlInfo>,
+    event_queue: Arc<Mutex<VecDeque<MemoryEvent>>>,
+    /// GenTL events registered by the consumer, which are shared with the remote device to notify
+    /// the unplug of the device.
+    events: Arc<EventRegistry>,
 
-    camera: Camera,
-    remote_device: Option<Box<Mutex<U3VRemoteDevice>>>,
+    /// Shared with the data stream module, which drives the stream channel of the camera.
+    camera: Arc<Mutex<Camera>>,
+    /// Control handle of `camera`, cloned so that the device module can access the device
+    /// without locking `camera`.
+    ctrl: SharedControlHandle,
+    remote_device: Option<Arc<U3VRemoteDevice>>,
+    data_stream: Option<Box<Mutex<U3VDataStreamModule>>>,
+    /// GUID of the device, which identifies the device across enumerations.
+    guid: Guid,
+    /// Information in the class-specific device descriptor, which is available without opening
+    /// the device.
+    device_info: u3v::DeviceInfo,
 
     /// Current status of the device.  
     /// `DeviceAccessStatus` and `DeviceAccessStatusReg` in VM doesn't reflect this value while
     /// [`Interface::UpdateDeviceList`] is called as the GenTL specification describes.
     current_status: super::DeviceAccessStatus,
+    /// Access the handles of the device are opened with, `None` if the device is not opened.
+    opened_with: Option<DeviceAccessFlag>,
+    /// Opens by the consumer sharing the device, which are kept even after the device is released
+    /// by an unplug until they are closed.
+    opens: Opens,
 }
 
-// TODO: Implement methods for stream and event channel.
+// TODO: Implement methods for event channel.
 impl U3VDeviceModule {
     pub(crate) fn new(camera: Camera) -> GenTlResult<Self> {
         let device_info = camera.ctrl.device_info();
 
         let port_info = PortInfo {
-            id: device_info.guid,
+            id: device_info.guid.to_string(),
             vendor: genapi::VENDOR_NAME.into(),
             model: genapi::MODEL_NAME.into(),
             tl_type: genapi::DEVICE_TYPE,
@@ -62,7 +138,7 @@ impl U3VDeviceModule {
             port_name: genapi::PORT_NAME.into(),
         };
 
-        let xml_info = XmlInfo {
+        let mut xml_info = XmlInfo {
             location: XmlLocation::RegisterMap {
                 address: genapi::GENAPI_XML_ADDRESS as u64,
                 size: genapi::GENAPI_XML_LENGTH,
@@ -80,16 +156,30 @@ impl U3VDeviceModule {
             sha1_hash: None,
             compressed: CompressionType::Uncompressed,
         };
+        let mut vm = genapi::Memory::new();
+        genapi_common::zip_module_xml::<genapi::GenApiXml::Xml, _>(
+            &mut vm,
+            &mut xml_info,
+            &port_info,
+        )?;
 
         let mut dev = Self {
-            vm: genapi::Memory::new(),
+            vm,
             port_info,
             xml_infos: vec![xml_info],
+            event_queue: Arc::new(Mutex::new(VecDeque::new())),
+            events: Arc::new(EventRegistry::new(SUPPORTED_EVENTS)),
 
-            camera,
+            guid: device_info.guid,
+            device_info,
+            ctrl: camera.ctrl.clone(),
+            camera: Arc::new(Mutex::new(camera)),
             remote_device: None,
+            data_stream: None,
 
             current_status: super::DeviceAccessStatus::Unknown,
+            opened_with: None,
+            opens: Opens::default(),
         };
 
         dev.initialize_vm()?;
@@ -97,15 +187,16 @@ impl U3VDeviceModule {
     }
 
     pub(crate) fn device_info(&self) -> &u3v::DeviceInfo {
-        todo!()
+        &self.device_info
     }
 
     /// Reflect current_status to `DeviceAccessStatusReg` in VM.
     /// Actual current status of the device isn't visible until this method is called.
     /// See GenTL specification for more details.
     pub(crate) fn reflect_status(&mut self) {
+        self.handle_unplug();
         self.vm
-            .write::<GenApiReg::DeviceAccessStatus>(self.current_status as u32)
+            .write::<GenApiReg::DeviceAccessStatus>(self.current_status.as_raw())
             .unwrap();
     }
 
@@ -114,13 +205,13 @@ impl U3VDeviceModule {
     /// calling [`U3VDeviceModule::access_status`].  
     /// See GenTL specification for more details.
     pub(crate) fn access_status(&self) -> DeviceAccessStatus {
-        let raw_value = self.vm.read::<GenApiReg::DeviceAccessStatus>().unwrap() as i32;
+        let raw_value = self.vm.read::<GenApiReg::DeviceAccessStatus>().unwrap();
         // Ok to unwrap because DeviceAccessStatus is RO register.
-        DeviceAccessStatus::try_from(raw_value).unwrap()
+        DeviceAccessStatus::from_raw(raw_value).unwrap()
     }
 
-    pub(crate) fn device_id(&self) -> &str {
-        &self.device_info().guid
+    pub(crate) fn guid(&self) -> Guid {
+        self.guid
     }
 
     pub(crate) fn force_access_status(&mut self, status: DeviceAccessStatus) {
@@ -128,6 +219,26 @@ impl U3VDeviceModule {
         self.reflect_status();
     }
 
+    /// Probes the availability of the device by opening it, which reads ABRM of the device, and
+    /// writes the result into the current status. The device is closed before returning.
+    ///
+    /// The status of the device opened by the consumer is kept as is.
+    pub(crate) fn probe_status(&mut self) -> DeviceAccessStatus {
+        if self.is_opened() {
+            return self.current_status;
+        }
+
+        // The options of the probe are replaced when the consumer opens the device.
+        let probe_options = open_options()
+            .timeout_duration(PROBE_TIMEOUT)
+            .retry_count(0);
+        let result = self.ctrl.open_with(&probe_options);
+        self.ctrl.close().ok();
+
+        self.current_status = DeviceAccessStatus::from_probe(&result);
+        self.current_status
+    }
+
     fn assert_open(&self) -> GenTlResult<()> {
         if self.is_opened() {
             Ok(())
@@ -136,24 +247,343 @@ impl U3VDeviceModule {
         }
     }
 
-    fn is_opened(&self) -> bool {
-        let current_status: DeviceAccessStatus = self.current_status;
-        current_status.is_opened()
+    pub(crate) fn is_opened(&self) -> bool {
+        Device::device_access_status(self).is_opened()
+    }
+
+    /// Returns `true` if the remote device has found the device unplugged, but the device module
+    /// hasn't released the device yet.
+    fn is_unplugged(&self) -> bool {
+        self.remote_device
+            .as_ref()
+            .is_some_and(|remote_device| remote_device.connection.is_unplugged())
+    }
+
+    /// Releases the device if it's found unplugged, then the device can't be opened until it's
+    /// found again by [`Interface::UpdateDeviceList`].
+    ///
+    /// Events registered by the consumer are kept until every open of the device is closed, so that
+    /// it can receive the error of the unplug.
+    fn handle_unplug(&mut self) {
+        if !self.is_unplugged() {
+            return;
+        }
+
+        if let Some(data_stream) = self.data_stream.take() {
+            data_stream.lock().unwrap().close().ok();
+        }
+        self.remote_device = None;
+        if let Some(opened_with) = self.opened_with.take() {
+            self.close_handles(opened_with).ok();
+        }
+        self.current_status = self.current_status.on_disconnect();
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.opened_with == Some(DeviceAccessFlag::ReadOnly)
+    }
+
+    /// Closes the handles opened by [`Device::open`].
+    fn close_handles(&mut self, opened_with: DeviceAccessFlag) -> GenTlResult<()> {
+        match opened_with {
+            DeviceAccessFlag::ReadOnly => Ok(self.ctrl.close()?),
+            DeviceAccessFlag::Control | DeviceAccessFlag::Exclusive => {
+                Ok(self.camera.lock().unwrap().close()?)
+            }
+        }
+    }
+
+    /// Releases the control of the device when its owner is closed while read-only opens share
+    /// it, then the device stays opened read-only.
+    ///
+    /// The remote device is kept because the read-only opens share it.
+    fn release_control(&mut self) -> GenTlResult<()> {
+        let opened_with = match self.opened_with {
+            Some(opened_with) => opened_with,
+            // The device has been released by an unplug.
+            None => return Ok(()),
+        };
+
+        if let Some(data_stream) = self.data_stream.take() {
+            data_stream.lock().unwrap().close()?;
+        }
+        self.close_handles(opened_with)?;
+        if let Err(err) = self.ctrl.open_with(&open_options()) {
+            self.remote_device = None;
+            self.opened_with = None;
+            self.current_status = self.current_status.on_close();
+            return Err(err.into());
+        }
+        self.opened_with = Some(DeviceAccessFlag::ReadOnly);
+        self.current_status = DeviceAccessStatus::on_open(DeviceAccessFlag::ReadOnly);
+        Ok(())
+    }
+
+    /// Closes the device and the remote device regardless of the opens sharing them.
+    fn close_device(&mut self) -> GenTlResult<()> {
+        self.opens = Opens::default();
+        let opened_with = match self.opened_with {
+            Some(opened_with) => opened_with,
+            None => {
+                // Events of the unplugged device are kept until it's closed.
+                self.events.unregister_all();
+                return Ok(());
+            }
+        };
+
+        if let Some(data_stream) = self.data_stream.take() {
+            data_stream.lock().unwrap().close()?;
+        }
+        self.remote_device = None;
+        self.events.unregister_all();
+        self.opened_with = None;
+        self.current_status = self.current_status.on_close();
+
+        self.close_handles(opened_with)
     }
 
-    #[allow(clippy::unused_self)]
-    fn handle_events(&mut self) {
+    fn handle_events(&mut self) -> GenTlResult<()> {
         // TODO: Handle stream related events.
+        loop {
+            // Drop mutex guard in every iteration to avoid deadlock possibility.
+            let event = self.event_queue.lock().unwrap().pop_front();
+
+            match event {
+                Some(MemoryEvent::DeviceUserID) => self.handle_device_user_id_change()?,
+                Some(MemoryEvent::ChannelEnable(channel)) => {
+                    self.handle_channel_enable_change(channel)
+                }
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes `DeviceUserID` in VM to the remote device.
+    ///
+    /// If the remote device rejects the name, `DeviceUserID` in VM is restored to the last name
+    /// known to the handle so that VM doesn't diverge from the remote device, and the consumer is
+    /// notified to invalidate the feature.
+    fn handle_device_user_id_change(&mut self) -> GenTlResult<()> {
+        let result = if self.is_read_only() {
+            Err(GenTlError::AccessDenied)
+        } else {
+            self.vm
+                .read::<GenApiReg::DeviceUserID>()
+                .map_err(GenTlError::from)
+                .and_then(|name| Ok(self.ctrl.set_user_defined_name(&name)?))
+        };
+
+        if result.is_err() {
+            let name = self.ctrl.device_info().user_defined_name;
+            self.vm
+                .write::<GenApiReg::DeviceUserID>(name.unwrap_or_default())
+                .unwrap();
+            // The restoration has already been handled.
+            self.event_queue.lock().unwrap().clear();
+            self.events.notify(EventData::FeatureInvalidate {
+                feature: "DeviceUserID".into(),
+            });
+        }
+
+        result
+    }
+
+    /// Writes the enable flag of `channel` in VM to the remote device.
+    ///
+    /// Errors of the remote device are notified as [`EventData::Error`] instead of being returned,
+    /// and the flag in VM is restored to the one the remote device reports.
+    fn handle_channel_enable_change(&mut self, channel: Channel) {
+        let mut ctrl = self.ctrl.clone();
+        let result = if self.is_read_only() {
+            Err(GenTlError::AccessDenied)
+        } else {
+            channel.read_reg(&self.vm).and_then(|enable| {
+                let abrm = ctrl.abrm()?;
+                Ok(channel.set_enable(&mut ctrl, &abrm, enable)?)
+            })
+        };
+
+        if let Err(err) = result {
+            let is_enabled = ctrl
+                .abrm()
+                .and_then(|abrm| channel.is_enabled(&mut ctrl, &abrm))
+                .unwrap_or(false);
+            channel.write_reg(&mut self.vm, is_enabled);
+            // Discard the event fired by the restoration.
+            self.event_queue.lock().unwrap().pop_back();
+            self.events.notify(EventData::FeatureInvalidate {
+                feature: channel.feature().into(),
+            });
+            self.events.notify(EventData::Error(err));
+        }
     }
 
     fn initialize_vm(&mut self) -> GenTlResult<()> {
-        todo!()
+        let device_info = self.ctrl.device_info();
+        self.vm
+            .write::<GenApiReg::DeviceID>(self.port_info.id.clone())?;
+        self.vm
+            .write::<GenApiReg::DeviceVendorName>(device_info.vendor_name)?;
+        self.vm
+            .write::<GenApiReg::DeviceModelName>(device_info.model_name)?;
+        self.vm
+            .write::<GenApiReg::DeviceUserID>(device_info.user_defined_name.unwrap_or_default())?;
+        self.vm.write::<GenApiReg::StreamSelectorMax>(0)?;
+        self.vm.write::<GenApiReg::StreamID>(STREAM_ID.into())?;
+        self.reflect_status();
+
+        self.register_observers();
+        Ok(())
+    }
+
+    fn register_observers(&mut self) {
+        let device_user_id_observer = DeviceUserIDRegObserver(self.event_queue.clone());
+        self.vm
+            .register_observer::<GenApiReg::DeviceUserID, _>(device_user_id_observer);
+
+        let stream_enable_observer =
+            ChannelEnableRegObserver(self.event_queue.clone(), Channel::Stream);
+        self.vm
+            .register_observer::<GenApiReg::StreamEnable, _>(stream_enable_observer);
+        let event_enable_observer =
+            ChannelEnableRegObserver(self.event_queue.clone(), Channel::Event);
+        self.vm
+            .register_observer::<GenApiReg::EventEnable, _>(event_enable_observer);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum MemoryEvent {
+    DeviceUserID,
+    ChannelEnable(Channel),
+}
+
+#[derive(Clone)]
+struct DeviceUserIDRegObserver(Arc<Mutex<VecDeque<MemoryEvent>>>);
+impl MemoryObserver for DeviceUserIDRegObserver {
+    fn update(&self) {
+        self.0.lock().unwrap().push_back(MemoryEvent::DeviceUserID)
+    }
+}
+
+#[derive(Clone)]
+struct ChannelEnableRegObserver(Arc<Mutex<VecDeque<MemoryEvent>>>, Channel);
+impl MemoryObserver for ChannelEnableRegObserver {
+    fn update(&self) {
+        self.0
+            .lock()
+            .unwrap()
+            .push_back(MemoryEvent::ChannelEnable(self.1))
+    }
+}
+
+/// Channel of the remote device which is enabled by a register of the device module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Channel {
+    /// Stream channel, which is enabled by SIRM.
+    Stream,
+    /// Event channel, which is enabled by EIRM.
+    Event,
+}
+
+impl Channel {
+    /// Name of the feature which enables the channel.
+    pub(super) fn feature(self) -> &'static str {
+        match self {
+            Self::Stream => "StreamEnable",
+            Self::Event => "EventEnable",
+        }
+    }
+
+    /// Address range of the register which enables the channel in VM.
+    pub(super) fn reg_range(self) -> std::ops::Range<usize> {
+        match self {
+            Self::Stream => GenApiReg::StreamEnable::range(),
+            Self::Event => GenApiReg::EventEnable::range(),
+        }
+    }
+
+    pub(super) fn read_reg(self, vm: &genapi::Memory) -> GenTlResult<bool> {
+        let value = match self {
+            Self::Stream => vm.read::<GenApiReg::StreamEnable>()?,
+            Self::Event => vm.read::<GenApiReg::EventEnable>()?,
+        };
+        Ok(value != 0)
+    }
+
+    pub(super) fn write_reg(self, vm: &mut genapi::Memory, is_enabled: bool) {
+        let value = u32::from(is_enabled);
+        // Ok to unwrap because the register is a RW integer register.
+        match self {
+            Self::Stream => vm.write::<GenApiReg::StreamEnable>(value).unwrap(),
+            Self::Event => vm.write::<GenApiReg::EventEnable>(value).unwrap(),
+        }
+    }
+
+    /// Sets the enable flag of the channel in the remote device, the remote device verifies the
+    /// flag is set.
+    pub(super) fn set_enable<Ctrl: DeviceControl + ?Sized>(
+        self,
+        ctrl: &mut Ctrl,
+        abrm: &Abrm,
+        enable: bool,
+    ) -> ControlResult<()> {
+        let sbrm = abrm.sbrm(ctrl)?;
+        match self {
+            Self::Stream => {
+                let sirm = sbrm.sirm().ok_or_else(|| Self::not_supported("SIRM"))?;
+                if enable {
+                    sirm.enable_stream(ctrl)
+                } else {
+                    sirm.disable_stream(ctrl)
+                }
+            }
+            Self::Event => {
+                let eirm = sbrm.eirm().ok_or_else(|| Self::not_supported("EIRM"))?;
+                if enable {
+                    eirm.enable_event(ctrl)
+                } else {
+                    eirm.disable_event(ctrl)
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the remote device reports the channel is enabled.
+    pub(super) fn is_enabled<Ctrl: DeviceControl + ?Sized>(
+        self,
+        ctrl: &mut Ctrl,
+        abrm: &Abrm,
+    ) -> ControlResult<bool> {
+        let sbrm = abrm.sbrm(ctrl)?;
+        match self {
+            Self::Stream => match sbrm.sirm() {
+                Some(sirm) => sirm.is_stream_enable(ctrl),
+                None => Ok(false),
+            },
+            Self::Event => match sbrm.eirm() {
+                Some(eirm) => eirm.is_event_enable(ctrl),
+                None => Ok(false),
+            },
+        }
+    }
+
+    fn not_supported(register_map: &str) -> ControlError {
+        ControlError::NotSupported(format!("the remote device has no {}", register_map).into())
+    }
+}
+
+impl EventSource for U3VDeviceModule {
+    fn events(&self) -> &EventRegistry {
+        &self.events
     }
 }
 
 impl Drop for U3VDeviceModule {
     fn drop(&mut self) {
-        self.close().ok();
+        self.force_close().ok();
     }
 }
 
@@ -161,10 +591,10 @@ impl Port for U3VDeviceModule {
     fn read(&self, address: u64, buf: &mut [u8]) -> GenTlResult<usize> {
         self.assert_open()?;
 
-        let address = address as usize;
+        let range = port::memory_range(address, buf.len())?;
         let len = buf.len();
 
-        let data = self.vm.read_raw(address..address + len)?;
+        let data = self.vm.read_raw(range)?;
         buf.copy_from_slice(data);
 
         Ok(len)
@@ -173,8 +603,9 @@ impl Port for U3VDeviceModule {
     fn write(&mut self, address: u64, data: &[u8]) -> GenTlResult<usize> {
         self.assert_open()?;
 
-        self.vm.write_raw(address as usize, &data)?;
-        self.handle_events();
+        let range = port::memory_range(address, data.len())?;
+        self.vm.write_raw(range.start, data)?;
+        self.handle_events()?;
 
         Ok(data.len())
     }
@@ -193,37 +624,121 @@ impl Port for U3VDeviceModule {
 }
 
 impl Device for U3VDeviceModule {
-    fn open(&mut self, access_flag: super::DeviceAccessFlag) -> GenTlResult<()> {
-        todo!()
+    fn open(&mut self, access_flag: DeviceAccessFlag) -> GenTlResult<()> {
+        self.handle_unplug();
+        if let Some(opened_with) = self.opened_with {
+            self.current_status = self.current_status.on_reopen(opened_with, access_flag)?;
+            self.opens.add(access_flag);
+            return Ok(());
+        }
+
+        let status = self.current_status.try_open(access_flag)?;
+        // A read-only open leaves the stream channel unclaimed as no data stream is opened.
+        let port_access = match access_flag {
+            DeviceAccessFlag::ReadOnly => {
+                self.ctrl.open_with(&open_options())?;
+                PortAccess::RO
+            }
+            DeviceAccessFlag::Control | DeviceAccessFlag::Exclusive => {
+                // The camera opens the stream channel of the control handle opened here.
+                self.ctrl.open_with(&open_options())?;
+                self.camera.lock().unwrap().open()?;
+                PortAccess::RW
+            }
+        };
+
+        let connection = Connection::new(self.events.clone());
+        let remote_device = match U3VRemoteDevice::new(self.ctrl.clone(), port_access, connection) {
+            Ok(remote_device) => remote_device,
+            Err(err) => {
+                self.close_handles(access_flag).ok();
+                return Err(err);
+            }
+        };
+        self.remote_device = Some(Arc::new(remote_device));
+        // Opens left unclosed since the device was unplugged are forgotten.
+        self.opens = Opens::default();
+        self.opens.add(access_flag);
+        self.opened_with = Some(access_flag);
+        self.current_status = status;
+        Ok(())
+    }
+
+    fn close(&mut self, opened_with: DeviceAccessFlag) -> GenTlResult<()> {
+        self.handle_unplug();
+        // The device is closed when every open is balanced by a close, including opens of the
+        // unplugged device.
+        match self.opens.remove(opened_with) {
+            CloseAction::Keep => Ok(()),
+            CloseAction::ReleaseControl => self.release_control(),
+            CloseAction::Close => self.close_device(),
+        }
     }
 
-    fn close(&mut self) -> GenTlResult<()> {
-        todo!()
+    fn force_close(&mut self) -> GenTlResult<()> {
+        self.handle_unplug();
+        self.close_device()
     }
 
     fn device_id(&self) -> &str {
         &self.port_info.id
     }
 
-    fn remote_device(&self) -> GenTlResult<&Mutex<dyn Port>> {
+    fn remote_device(&self) -> GenTlResult<Arc<dyn SharedPort>> {
         self.assert_open()?;
 
-        Ok(self.remote_device.as_ref().unwrap().as_ref())
+        Ok(self.remote_device.clone().unwrap())
     }
 
-    fn vendor_name(&self) -> GenTlResult<String> {
-        todo!()
-    }
+    fn device_info(&self, cmd: DeviceInfoCmd) -> GenTlResult<DeviceInfoValue> {
+        use DeviceInfoValue::{AccessStatus, UInt64};
 
-    fn model_name(&self) -> GenTlResult<String> {
-        todo!()
-    }
+        // `Abrm` is cached by the handle, so this doesn't communicate with the device. It's not
+        // available until the device is opened once, then the descriptor is used instead.
+        let abrm = self.ctrl.abrm().ok();
+        let info = &self.device_info;
+        let string = |abrm_value: Option<&str>, info_value: &str| {
+            DeviceInfoValue::String(abrm_value.unwrap_or(info_value).into())
+        };
 
-    fn display_name(&self) -> GenTlResult<String> {
-        let vendor = self.vendor_name()?;
-        let model_name = self.model_name()?;
-        let id = self.device_id();
-        Ok(format!("{} {} ({})", vendor, model_name, id))
+        Ok(match cmd {
+            DeviceInfoCmd::Id => DeviceInfoValue::String(self.device_id().into()),
+            DeviceInfoCmd::Vendor => string(
+                abrm.as_ref().map(Abrm::manufacturer_name),
+                &info.vendor_name,
+            ),
+            DeviceInfoCmd::Model => string(abrm.as_ref().map(Abrm::model_name), &info.model_name),
+            DeviceInfoCmd::TlType => DeviceInfoValue::TlType(self.tl_type()),
+            DeviceInfoCmd::DisplayName => DeviceInfoValue::String(format!(
+                "{} {} ({})",
+                self.vendor_name()?,
+                self.model_name()?,
+                self.device_id()
+            )),
+            DeviceInfoCmd::AccessStatus => AccessStatus(self.device_access_status()),
+            DeviceInfoCmd::UserDefinedName => {
+                let name = match &abrm {
+                    Some(abrm) => abrm.user_defined_name(),
+                    None => info.user_defined_name.as_deref(),
+                };
+                DeviceInfoValue::String(name.ok_or(GenTlError::NotAvailable)?.into())
+            }
+            DeviceInfoCmd::SerialNumber => {
+                string(abrm.as_ref().map(Abrm::serial_number), &info.serial_number)
+            }
+            DeviceInfoCmd::Version => string(
+                abrm.as_ref().map(Abrm::device_version),
+                &info.device_version,
+            ),
+            // `TIMESTAMP INCREMENT` is ns/tick of the device internal clock.
+            DeviceInfoCmd::TimestampFrequency => {
+                match abrm.map(|abrm| abrm.timestamp_increment()) {
+                    Some(0) | None => return Err(GenTlError::NotAvailable),
+                    Some(increment) => UInt64(1_000_000_000 / increment),
+                }
+            }
+            DeviceInfoCmd::LinkSpeed => UInt64(link_speed(info.supported_speed)),
+        })
     }
 
     fn tl_type(&self) -> TlType {
@@ -231,56 +746,451 @@ impl Device for U3VDeviceModule {
     }
 
     fn device_access_status(&self) -> DeviceAccessStatus {
-        todo!()
+        // The unplug is reflected to `current_status` when the device is released.
+        if self.is_unplugged() {
+            self.current_status.on_disconnect()
+        } else {
+            self.current_status
+        }
     }
 
-    fn user_defined_name(&self) -> GenTlResult<String> {
-        todo!()
+    fn num_data_streams(&self) -> GenTlResult<usize> {
+        self.assert_open()?;
+
+        // Streaming requires write access to the remote device.
+        if self.is_read_only() {
+            Ok(0)
+        } else {
+            Ok(1)
+        }
     }
 
-    fn serial_number(&self) -> GenTlResult<String> {
-        Ok(self.device_info().serial_number.clone())
+    fn data_stream_id(&self, index: usize) -> GenTlResult<&str> {
+        if index < self.num_data_streams()? {
+            Ok(STREAM_ID)
+        } else {
+            Err(GenTlError::InvalidIndex)
+        }
     }
 
-    fn device_version(&self) -> GenTlResult<String> {
-        todo!()
+    fn open_data_stream(&mut self, stream_id: &str) -> GenTlResult<&Mutex<dyn DataStream>> {
+        self.assert_open()?;
+
+        if self.is_read_only() {
+            return Err(GenTlError::AccessDenied);
+        }
+        if stream_id != STREAM_ID {
+            return Err(GenTlError::InvalidId(stream_id.into()));
+        }
+
+        let camera = self.camera.clone();
+        let serial = self.device_info().serial_number.clone();
+        let data_stream = self
+            .data_stream
+            .get_or_insert_with(|| Box::new(Mutex::new(U3VDataStreamModule::new(camera, &serial))));
+        data_stream.get_mut().unwrap().open()?;
+        Ok(&**data_stream)
     }
+}
 
-    fn timespamp_frequency(&self) -> GenTlResult<u64> {
-        todo!()
+/// Link speed in bits per second of a device supporting `speed`.
+pub(super) fn link_speed(speed: u3v::BusSpeed) -> u64 {
+    match speed {
+        u3v::BusSpeed::LowSpeed => 1_500_000,
+        u3v::BusSpeed::FullSpeed => 12_000_000,
+        u3v::BusSpeed::HighSpeed => 480_000_000,
+        u3v::BusSpeed::SuperSpeed => 5_000_000_000,
+        u3v::BusSpeed::SuperSpeedPlus => 10_000_000_000,
     }
 }
 
-pub(crate) struct U3VRemoteDevice {}
+/// Port of the remote device, which accesses the device registers through the control channel.
+///
+/// The port is shared between threads, so reads and writes are split into chunks each of which
+/// fits into a single transaction, and the control handle is locked only while a chunk is
+/// transferred. Request ID allocation happens under the same lock, so only one command is
+/// outstanding at a time as GenCP requires, while accesses from multiple threads interleave at
+/// transaction granularity, e.g. a feature read isn't blocked until an XML download completes.
+///
+/// A stacked access is sent by commands under a single lock because it is one operation of the
+/// consumer. If an entry doesn't fit into a single transaction, the entries are accessed one by
+/// one in chunks instead, so that a long entry doesn't hold the lock.
+pub(crate) struct U3VRemoteDevice {
+    ctrl: SharedControlHandle,
+    limits: TransactionLimits,
+    connection: Connection,
+    port_info: PortInfo,
+    xml_infos: Vec<XmlInfo>,
+}
 
 impl U3VRemoteDevice {
-    fn new(handle: u3v::ControlHandle) -> GenTlResult<Self> {
-        todo!()
+    fn new(
+        ctrl: SharedControlHandle,
+        access: PortAccess,
+        connection: Connection,
+    ) -> GenTlResult<Self> {
+        let id = ctrl.device_info().guid.to_string();
+        let abrm = ctrl.abrm()?;
+        let limits = TransactionLimits::new(&abrm.sbrm(&mut ctrl.clone())?);
+        let port_info = remote_port_info(id, &abrm, access);
+        let xml_infos = remote_xml_infos(&ctrl.manifest_table()?)?;
+        Ok(Self {
+            ctrl,
+            limits,
+            connection,
+            port_info,
+            xml_infos,
+        })
+    }
+
+    fn is_stacked_commands_supported(&self) -> GenTlResult<bool> {
+        Ok(self
+            .ctrl
+            .abrm()?
+            .device_capability()?
+            .is_stacked_commands_supported())
+    }
+}
+
+/// Connection to the remote device, which detects the unplug of the device.
+///
+/// Once an access to the remote device fails because the device is disconnected, the error is
+/// notified as [`EventData::Error`] of the device module, and the following accesses fail
+/// immediately with [`GenTlError::InvalidHandle`] instead of waiting for the timeout.
+pub(super) struct Connection {
+    is_unplugged: AtomicBool,
+    /// Events of the device module.
+    events: Arc<EventRegistry>,
+}
+
+impl Connection {
+    pub(super) fn new(events: Arc<EventRegistry>) -> Self {
+        Self {
+            is_unplugged: AtomicBool::new(false),
+            events,
+        }
+    }
+
+    pub(super) fn is_unplugged(&self) -> bool {
+        self.is_unplugged.load(Ordering::Acquire)
+    }
+
+    pub(super) fn assert_connected(&self) -> GenTlResult<()> {
+        if self.is_unplugged() {
+            Err(GenTlError::InvalidHandle)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Converts `err` of an access to the remote device, and marks the device as unplugged if the
+    /// device is disconnected.
+    pub(super) fn on_error(&self, err: ControlError) -> GenTlError {
+        // Only the first detection is notified as accesses from other threads may fail together.
+        if matches!(err, ControlError::Disconnected)
+            && !self.is_unplugged.swap(true, Ordering::AcqRel)
+        {
+            self.events
+                .notify(EventData::Error(ControlError::Disconnected.into()));
+        }
+        err.into()
+    }
+}
+
+/// Maximum data lengths of `ReadMem` and `WriteMem` commands which are transferred by a single
+/// transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct TransactionLimits {
+    read_len: usize,
+    write_len: usize,
+}
+
+impl TransactionLimits {
+    /// Alignment of chunk lengths, which is the same as the control handle so that the handle
+    /// doesn't split a chunk further.
+    const ALIGNMENT: usize = 4;
+    const PACKET_HEADER_LENGTH: usize = 12;
+    const WRITE_MEM_ADDRESS_LENGTH: usize = 8;
+
+    /// Limits of the device whose maximum packet lengths are described by `sbrm`.
+    pub(super) fn new(sbrm: &Sbrm) -> Self {
+        Self::from_packet_lengths(
+            sbrm.maximum_command_transfer_length() as usize,
+            sbrm.maximum_acknowledge_trasfer_length() as usize,
+        )
+    }
+
+    fn from_packet_lengths(maximum_cmd_length: usize, maximum_ack_length: usize) -> Self {
+        let align = |len: usize| (len & !(Self::ALIGNMENT - 1)).max(Self::ALIGNMENT);
+        let read_len = maximum_ack_length
+            .saturating_sub(Self::PACKET_HEADER_LENGTH)
+            .min(u16::MAX as usize);
+        let write_len = maximum_cmd_length
+            .saturating_sub(Self::PACKET_HEADER_LENGTH + Self::WRITE_MEM_ADDRESS_LENGTH)
+            .min(u16::MAX as usize - Self::WRITE_MEM_ADDRESS_LENGTH);
+        Self {
+            read_len: align(read_len),
+            write_len: align(write_len),
+        }
+    }
+
+    /// Returns `true` if `len` bytes are read by a single transaction.
+    pub(super) fn is_single_read(self, len: usize) -> bool {
+        len <= self.read_len
+    }
+
+    /// Returns `true` if `len` bytes are written by a single transaction.
+    pub(super) fn is_single_write(self, len: usize) -> bool {
+        len <= self.write_len
     }
 
-    fn port_info(handle: &u3v::ControlHandle) -> GenTlResult<PortInfo> {
-        todo!()
+    /// Reads data at `address` into `buf` by calling `read_chunk` for each chunk.
+    pub(super) fn read_chunked(
+        self,
+        address: u64,
+        buf: &mut [u8],
+        mut read_chunk: impl FnMut(u64, &mut [u8]) -> ControlResult<()>,
+    ) -> ControlResult<()> {
+        for (i, chunk) in buf.chunks_mut(self.read_len).enumerate() {
+            read_chunk(address + (i * self.read_len) as u64, chunk)?;
+        }
+        Ok(())
     }
 
-    fn xml_infos(handle: &u3v::ControlHandle) -> GenTlResult<Vec<XmlInfo>> {
-        todo!()
+    /// Writes `data` to `address` by calling `write_chunk` for each chunk.
+    pub(super) fn write_chunked(
+        self,
+        address: u64,
+        data: &[u8],
+        mut write_chunk: impl FnMut(u64, &[u8]) -> ControlResult<()>,
+    ) -> ControlResult<()> {
+        for (i, chunk) in data.chunks(self.write_len).enumerate() {
+            write_chunk(address + (i * self.write_len) as u64, chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// The number of entries processed by a stacked command before it fails with `err`.
+///
+/// Only partial read/write errors tell which entry failed, so no entry is regarded as processed
+/// for other errors.
+fn stacked_processed_count(err: &ControlError) -> usize {
+    match err {
+        ControlError::PartialRead { index, .. } | ControlError::PartialWrite { index, .. } => {
+            *index
+        }
+        _ => 0,
     }
 }
 
-impl Port for U3VRemoteDevice {
+/// Port information of the remote device described by `abrm`.
+pub(super) fn remote_port_info(id: String, abrm: &Abrm, access: PortAccess) -> PortInfo {
+    PortInfo {
+        id,
+        vendor: abrm.manufacturer_name().into(),
+        model: abrm.model_name().into(),
+        tl_type: TlType::USB3Vision,
+        module_type: ModuleType::RemoteDevice,
+        endianness: Endianness::LE,
+        access,
+        version: abrm.gencp_version(),
+        port_name: "Device".into(),
+    }
+}
+
+/// Device XML files of the remote device listed in `manifest_table`.
+pub(super) fn remote_xml_infos(manifest_table: &ManifestTable) -> GenTlResult<Vec<XmlInfo>> {
+    manifest_table
+        .entries()
+        .iter()
+        .filter_map(|entry| match entry.file_info().file_type() {
+            Ok(GenICamFileType::DeviceXml) => Some(xml_info(entry)),
+            Ok(GenICamFileType::BufferXml) => None,
+            Err(err) => Some(Err(err.into())),
+        })
+        .collect()
+}
+
+fn xml_info(entry: &ManifestEntry) -> GenTlResult<XmlInfo> {
+    let file_info = entry.file_info();
+    Ok(XmlInfo {
+        location: XmlLocation::RegisterMap {
+            address: entry.file_address(),
+            size: usize::try_from(entry.file_size()).map_err(|_| GenTlError::InvalidAddress)?,
+        },
+        schema_version: file_info.schema_version(),
+        file_version: entry.genicam_file_version(),
+        sha1_hash: entry.sha1_hash(),
+        compressed: file_info.compression_type()?,
+    })
+}
+
+impl SharedPort for U3VRemoteDevice {
     fn read(&self, address: u64, buf: &mut [u8]) -> GenTlResult<usize> {
-        todo!()
+        self.connection.assert_connected()?;
+
+        // `SharedControlHandle` is a shared reference to the handle, so cloning it is cheap.
+        let mut ctrl = self.ctrl.clone();
+        self.limits
+            .read_chunked(address, buf, |address, chunk| ctrl.read(address, chunk))
+            .map_err(|err| self.connection.on_error(err))?;
+        Ok(buf.len())
     }
 
-    fn write(&mut self, address: u64, data: &[u8]) -> GenTlResult<usize> {
-        todo!()
+    fn write(&self, address: u64, data: &[u8]) -> GenTlResult<usize> {
+        self.connection.assert_connected()?;
+        if !self.port_info.access.is_writable() {
+            return Err(GenTlError::AccessDenied);
+        }
+
+        let mut ctrl = self.ctrl.clone();
+        self.limits
+            .write_chunked(address, data, |address, chunk| ctrl.write(address, chunk))
+            .map_err(|err| self.connection.on_error(err))?;
+        Ok(data.len())
+    }
+
+    fn read_stacked(
+        &self,
+        entries: &mut [(u64, &mut [u8])],
+        read_count: &mut usize,
+    ) -> GenTlResult<()> {
+        *read_count = 0;
+        self.connection.assert_connected()?;
+        // Each entry must fit into a single transaction, which is also within the length field
+        // of `ReadMemStacked`.
+        let stacked_entries: Option<Vec<_>> = entries
+            .iter()
+            .map(|(address, buf)| {
+                u16::try_from(buf.len())
+                    .ok()
+                    .filter(|_| self.limits.is_single_read(buf.len()))
+                    .map(|len| (*address, len))
+            })
+            .collect();
+        let stacked_entries = match stacked_entries {
+            Some(stacked_entries) if self.is_stacked_commands_supported()? => stacked_entries,
+            _ => {
+                return port::read_each(entries, read_count, |address, buf| self.read(address, buf))
+            }
+        };
+
+        let mut bufs: Vec<&mut [u8]> = entries.iter_mut().map(|(_, buf)| &mut **buf).collect();
+        match self.ctrl.read_mem_stacked(&stacked_entries, &mut bufs) {
+            Ok(()) => {
+                *read_count = entries.len();
+                Ok(())
+            }
+            Err(err) => {
+                *read_count = stacked_processed_count(&err);
+                Err(self.connection.on_error(err))
+            }
+        }
+    }
+
+    fn write_stacked(
+        &self,
+        entries: &[(u64, &[u8])],
+        written_count: &mut usize,
+    ) -> GenTlResult<()> {
+        *written_count = 0;
+        self.connection.assert_connected()?;
+        if !self.port_info.access.is_writable() {
+            return Err(GenTlError::AccessDenied);
+        }
+        // Each entry must fit into a single transaction, which is also within the length field
+        // of `WriteMemStacked`.
+        if entries
+            .iter()
+            .any(|(_, data)| !self.limits.is_single_write(data.len()))
+            || !self.is_stacked_commands_supported()?
+        {
+            return port::write_each(entries, written_count, |address, data| {
+                self.write(address, data)
+            });
+        }
+
+        match self.ctrl.write_mem_stacked(entries) {
+            Ok(_) => {
+                *written_count = entries.len();
+                Ok(())
+            }
+            Err(err) => {
+                *written_count = stacked_processed_count(&err);
+                Err(self.connection.on_error(err))
+            }
+        }
     }
 
     fn port_info(&self) -> GenTlResult<&PortInfo> {
-        todo!()
+        Ok(&self.port_info)
     }
 
     fn xml_infos(&self) -> GenTlResult<&[XmlInfo]> {
-        todo!()
+        Ok(&self.xml_infos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stacked_processed_count() {
+        let partial_read = ControlError::PartialRead {
+            index: 2,
+            requested: 4,
+            read: 0,
+        };
+        assert_eq!(stacked_processed_count(&partial_read), 2);
+
+        let partial_write = ControlError::PartialWrite {
+            index: 1,
+            requested: 4,
+            written: 2,
+        };
+        assert_eq!(stacked_processed_count(&partial_write), 1);
+
+        // It's unknown which entry has failed.
+        assert_eq!(stacked_processed_count(&ControlError::Timeout), 0);
+    }
+
+    #[test]
+    fn test_transaction_limits() {
+        let limits = TransactionLimits::from_packet_lengths(1024, 1026);
+        assert_eq!(
+            limits,
+            TransactionLimits {
+                read_len: 1012,
+                write_len: 1004,
+            }
+        );
+
+        let mut chunks = vec![];
+        let mut buf = vec![0; 2500];
+        limits
+            .read_chunked(0x100, &mut buf, |address, chunk| {
+                chunks.push((address, chunk.len()));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            chunks,
+            [(0x100, 1012), (0x100 + 1012, 1012), (0x100 + 2024, 476)]
+        );
+
+        // Stacked entries are accessed by the stacked commands only if they fit into a single
+        // transaction.
+        assert!(limits.is_single_read(1012));
+        assert!(!limits.is_single_read(1013));
+        assert!(limits.is_single_write(1004));
+        assert!(!limits.is_single_write(1005));
+
+        // Lengths are capped by the length field of the commands.
+        let limits = TransactionLimits::from_packet_lengths(usize::MAX, usize::MAX);
+        assert_eq!(limits.read_len, 0xfffc);
+        assert_eq!(limits.write_len, 0xfff4);
+        assert!(!limits.is_single_read(u16::MAX as usize));
     }
 }
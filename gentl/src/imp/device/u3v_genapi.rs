@@ -6,8 +6,8 @@ use cameleon_impl::memory::{memory, prelude::*, register_map};
 use const_format::formatcp;
 
 use GenApiReg::{
-    DeviceAccessStatus, DeviceID, DeviceModelName, DeviceVendorName, StreamID, StreamSelector,
-    StreamSelectorMax,
+    DeviceAccessStatus, DeviceID, DeviceModelName, DeviceUserID, DeviceVendorName, EventEnable,
+    StreamEnable, StreamID, StreamSelector, StreamSelectorMax,
 };
 
 use crate::imp::{
@@ -35,6 +35,10 @@ pub(super) enum GenApiReg {
     #[register(len = 128, access = RO, ty = String)]
     DeviceModelName,
 
+    /// User defined name of the remote device, writing it updates the remote device.
+    #[register(len = 64, access = RW, ty = String)]
+    DeviceUserID,
+
     /// Gives the device's access status at the moment of the last execution of the DeviceUpdateList command.
     #[register(len = 4, access = RO, ty = u32)]
     DeviceAccessStatus,
@@ -50,9 +54,17 @@ pub(super) enum GenApiReg {
     /// Interface wide unique identifier of the selected stream.
     #[register(len = 64, access = RO, ty = String)]
     StreamID,
+
+    /// Stream enable flag of SIRM of the remote device, writing it updates the remote device.
+    #[register(len = 4, access = RW, ty = u32)]
+    StreamEnable,
+
+    /// Event enable flag of EIRM of the remote device, writing it updates the remote device.
+    #[register(len = 4, access = RW, ty = u32)]
+    EventEnable,
 }
 
-#[register_map(base=0, endianness=LE)]
+#[register_map(base=GENAPI_XML_ADDRESS, endianness=LE)]
 pub(super) enum GenApiXml {
     #[register(len = GENAPI_XML_LENGTH, access = RO, ty = String)]
     Xml = GENAPI_XML,
@@ -99,6 +111,7 @@ xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_1 http://www.genicam
         <Visibility>Beginner</Visibility>
         <pFeature>DeviceInformation</pFeature>
         <pFeature>StreamEnumeration</pFeature>
+        <pFeature>ChannelControl</pFeature>
     </Category>
 
     <Port Name="{PORT_NAME}" NameSpace="Standard">
@@ -113,6 +126,7 @@ xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_1 http://www.genicam
         <pFeature>DeviceID</pFeature>
         <pFeature>DeviceVendorName</pFeature>
         <pFeature>DeviceModelName</pFeature>
+        <pFeature>DeviceUserID</pFeature>
         <pFeature>DeviceType</pFeature>
         <pFeature>DeviceAccessStatus</pFeature>
     </Category>
@@ -144,6 +158,15 @@ xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_1 http://www.genicam
         <pPort>{PORT_NAME}</pPort>
     </StringReg>
 
+    <StringReg Name="DeviceUserID" NameSpace="Standard">
+        <Description>User-programmable device identifier.</Description>
+        <Visibility>Beginner</Visibility>
+        <Address>{device_user_id_addr}</Address>
+        <Length>{device_user_id_len}</Length>
+        <AccessMode>{device_user_id_access}</AccessMode>
+        <pPort>{PORT_NAME}</pPort>
+    </StringReg>
+
     <Enumeration Name="DeviceType" NameSpace="Standard">
         <Description>Transport layer type of the device.</Description>
         <Visibility>Expert</Visibility>
@@ -247,6 +270,50 @@ xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_1 http://www.genicam
         <pPort>{PORT_NAME}</pPort>
     </StringReg>
 
+    <Category Name="ChannelControl" NameSpace="Custom">
+        <Description>Category that contains features which control the channels of the remote device.</Description>
+        <Visibility>Expert</Visibility>
+
+        <pFeature>StreamEnable</pFeature>
+        <pFeature>EventEnable</pFeature>
+    </Category>
+
+    <Boolean Name="StreamEnable" NameSpace="Custom">
+        <Description>Enables the stream channel of the remote device.</Description>
+        <Visibility>Expert</Visibility>
+        <pValue>StreamEnableReg</pValue>
+        <OnValue>1</OnValue>
+        <OffValue>0</OffValue>
+    </Boolean>
+
+    <IntReg Name="StreamEnableReg" NameSpace="Custom">
+        <Visibility>Invisible</Visibility>
+        <Address>{stream_enable_addr}</Address>
+        <Length>{stream_enable_len}</Length>
+        <AccessMode>{stream_enable_access}</AccessMode>
+        <pPort>{PORT_NAME}</pPort>
+        <Cachable>NoCache</Cachable>
+        <Endianess>LittleEndian</Endianess>
+    </IntReg>
+
+    <Boolean Name="EventEnable" NameSpace="Custom">
+        <Description>Enables the event channel of the remote device.</Description>
+        <Visibility>Expert</Visibility>
+        <pValue>EventEnableReg</pValue>
+        <OnValue>1</OnValue>
+        <OffValue>0</OffValue>
+    </Boolean>
+
+    <IntReg Name="EventEnableReg" NameSpace="Custom">
+        <Visibility>Invisible</Visibility>
+        <Address>{event_enable_addr}</Address>
+        <Length>{event_enable_len}</Length>
+        <AccessMode>{event_enable_access}</AccessMode>
+        <pPort>{PORT_NAME}</pPort>
+        <Cachable>NoCache</Cachable>
+        <Endianess>LittleEndian</Endianess>
+    </IntReg>
+
 </RegisterDescription>"#,
     device_id_addr = DeviceID::ADDRESS,
     device_id_len = DeviceID::LENGTH,
@@ -257,21 +324,24 @@ xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_1 http://www.genicam
     device_model_name_addr = DeviceModelName::ADDRESS,
     device_model_name_len = DeviceModelName::LENGTH,
     device_model_name_access = DeviceModelName::ACCESS_RIGHT.as_str(),
+    device_user_id_addr = DeviceUserID::ADDRESS,
+    device_user_id_len = DeviceUserID::LENGTH,
+    device_user_id_access = DeviceUserID::ACCESS_RIGHT.as_str(),
     device_type = DEVICE_TYPE.as_str(),
     device_access_status_unknown_str = super::DeviceAccessStatus::Unknown.as_str(),
-    device_access_status_unknown_int = super::DeviceAccessStatus::Unknown as i32,
+    device_access_status_unknown_int = super::DeviceAccessStatus::Unknown.as_raw(),
     device_access_status_readwrite_str = super::DeviceAccessStatus::ReadWrite.as_str(),
-    device_access_status_readwrite_int = super::DeviceAccessStatus::ReadWrite as i32,
+    device_access_status_readwrite_int = super::DeviceAccessStatus::ReadWrite.as_raw(),
     device_access_status_readonly_str = super::DeviceAccessStatus::ReadOnly.as_str(),
-    device_access_status_readonly_int = super::DeviceAccessStatus::ReadOnly as i32,
+    device_access_status_readonly_int = super::DeviceAccessStatus::ReadOnly.as_raw(),
     device_access_status_noaccess_str = super::DeviceAccessStatus::NoAccess.as_str(),
-    device_access_status_noaccess_int = super::DeviceAccessStatus::NoAccess as i32,
+    device_access_status_noaccess_int = super::DeviceAccessStatus::NoAccess.as_raw(),
     device_access_status_busy_str = super::DeviceAccessStatus::Busy.as_str(),
-    device_access_status_busy_int = super::DeviceAccessStatus::Busy as i32,
+    device_access_status_busy_int = super::DeviceAccessStatus::Busy.as_raw(),
     device_access_status_openrw_str = super::DeviceAccessStatus::OpenReadWrite.as_str(),
-    device_access_status_openrw_int = super::DeviceAccessStatus::OpenReadWrite as i32,
+    device_access_status_openrw_int = super::DeviceAccessStatus::OpenReadWrite.as_raw(),
     device_access_status_openro_str = super::DeviceAccessStatus::OpenReadOnly.as_str(),
-    device_access_status_openro_int = super::DeviceAccessStatus::OpenReadOnly as i32,
+    device_access_status_openro_int = super::DeviceAccessStatus::OpenReadOnly.as_raw(),
     device_access_status_addr = DeviceAccessStatus::ADDRESS,
     device_access_status_len = DeviceAccessStatus::LENGTH,
     device_access_status_access = DeviceAccessStatus::ACCESS_RIGHT.as_str(),
@@ -284,4 +354,10 @@ xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_1 http://www.genicam
     stream_id_addr = StreamID::ADDRESS,
     stream_id_len = StreamID::LENGTH,
     stream_id_access = StreamID::ACCESS_RIGHT.as_str(),
+    stream_enable_addr = StreamEnable::ADDRESS,
+    stream_enable_len = StreamEnable::LENGTH,
+    stream_enable_access = StreamEnable::ACCESS_RIGHT.as_str(),
+    event_enable_addr = EventEnable::ADDRESS,
+    event_enable_len = EventEnable::LENGTH,
+    event_enable_access = EventEnable::ACCESS_RIGHT.as_str(),
 );
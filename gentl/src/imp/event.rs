@@ -0,0 +1,414 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Event objects of GenTL.
+//!
+//! A module owns an [`EventRegistry`] and pushes events into it. Events are queued only while the
+//! consumer registers the event type, and the consumer pops them from [`EventQueue`].
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{imp::stream::BufferHandle, GenTlError, GenTlResult};
+
+/// Type of an event, corresponds to `EVENT_TYPE` of GenTL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EventType {
+    /// Asynchronous error of the module.
+    Error,
+    /// A buffer of a data stream is filled.
+    NewBuffer,
+    /// A feature of the module needs to be invalidated in the node map of the consumer.
+    FeatureInvalidate,
+    /// A feature of the module has changed its value.
+    FeatureChange,
+    /// Event sent by the remote device.
+    RemoteDevice,
+    /// Event of the module defined by the producer.
+    Module,
+}
+
+/// Data delivered with an event.
+#[derive(Debug)]
+pub(crate) enum EventData {
+    /// Error occurred asynchronously in the module.
+    Error(GenTlError),
+
+    /// A buffer is filled and waits to be delivered.
+    NewBuffer {
+        /// Handle of the filled buffer.
+        buffer: BufferHandle,
+        /// User data given when the buffer is announced.
+        user_data: usize,
+    },
+
+    /// A feature needs to be invalidated.
+    FeatureInvalidate {
+        /// Name of the feature.
+        feature: String,
+    },
+
+    /// A feature has changed its value.
+    FeatureChange {
+        /// Name of the feature.
+        feature: String,
+        /// The new value of the feature in string representation.
+        value: String,
+    },
+
+    /// Event sent by the remote device.
+    RemoteDevice {
+        /// ID of the event.
+        event_id: u64,
+        /// Data attached to the event.
+        data: Vec<u8>,
+    },
+
+    /// Event of the module defined by the producer.
+    Module {
+        /// ID of the event.
+        event_id: u64,
+        /// Data attached to the event.
+        data: Vec<u8>,
+    },
+}
+
+impl EventData {
+    pub(crate) fn event_type(&self) -> EventType {
+        match self {
+            Self::Error(..) => EventType::Error,
+            Self::NewBuffer { .. } => EventType::NewBuffer,
+            Self::FeatureInvalidate { .. } => EventType::FeatureInvalidate,
+            Self::FeatureChange { .. } => EventType::FeatureChange,
+            Self::RemoteDevice { .. } => EventType::RemoteDevice,
+            Self::Module { .. } => EventType::Module,
+        }
+    }
+}
+
+/// Queue of events of a type registered by the consumer.
+#[derive(Debug)]
+pub(crate) struct EventQueue {
+    event_type: EventType,
+    state: Mutex<QueueState>,
+    pushed: Condvar,
+}
+
+#[derive(Debug)]
+struct QueueState {
+    events: VecDeque<EventData>,
+    /// The number of events pushed since the registration.
+    num_fired: u64,
+    /// The number of waits to be aborted by [`EventQueue::kill`].
+    num_kills: usize,
+    is_registered: bool,
+}
+
+impl EventQueue {
+    fn new(event_type: EventType) -> Self {
+        Self {
+            event_type,
+            state: Mutex::new(QueueState {
+                events: VecDeque::new(),
+                num_fired: 0,
+                num_kills: 0,
+                is_registered: true,
+            }),
+            pushed: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn event_type(&self) -> EventType {
+        self.event_type
+    }
+
+    /// Pops the oldest event, waiting for an event to be pushed.
+    ///
+    /// Waits forever if `timeout` is `None`. Returns [`GenTlError::Abort`] if the wait is killed
+    /// by [`EventQueue::kill`] or the event is unregistered.
+    pub(crate) fn get_data(&self, timeout: Option<Duration>) -> GenTlResult<EventData> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if state.num_kills > 0 {
+                state.num_kills -= 1;
+                return Err(GenTlError::Abort);
+            }
+            if !state.is_registered {
+                return Err(GenTlError::Abort);
+            }
+            if let Some(event) = state.events.pop_front() {
+                return Ok(event);
+            }
+
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(GenTlError::Timeout);
+                    }
+                    self.pushed.wait_timeout(state, deadline - now).unwrap().0
+                }
+                None => self.pushed.wait(state).unwrap(),
+            };
+        }
+    }
+
+    /// Puts `event` popped by [`EventQueue::get_data`] back to the head of the queue, so that the
+    /// event isn't lost when the consumer fails to receive it.
+    ///
+    /// The event isn't counted as a newly fired one.
+    pub(crate) fn requeue(&self, event: EventData) {
+        debug_assert_eq!(event.event_type(), self.event_type);
+        let mut state = self.state.lock().unwrap();
+        if state.is_registered {
+            state.events.push_front(event);
+            self.pushed.notify_one();
+        }
+    }
+
+    /// Discards all queued events.
+    pub(crate) fn flush(&self) {
+        self.state.lock().unwrap().events.clear();
+    }
+
+    /// Aborts a single wait in [`EventQueue::get_data`].
+    ///
+    /// If no thread is waiting, the next call of [`EventQueue::get_data`] is aborted.
+    pub(crate) fn kill(&self) {
+        self.state.lock().unwrap().num_kills += 1;
+        self.pushed.notify_all();
+    }
+
+    /// The number of events in the queue.
+    pub(crate) fn num_in_queue(&self) -> usize {
+        self.state.lock().unwrap().events.len()
+    }
+
+    /// The number of events fired since the registration, including the ones already popped.
+    pub(crate) fn num_fired(&self) -> u64 {
+        self.state.lock().unwrap().num_fired
+    }
+
+    fn push(&self, event: EventData) {
+        debug_assert_eq!(event.event_type(), self.event_type);
+        let mut state = self.state.lock().unwrap();
+        state.events.push_back(event);
+        state.num_fired += 1;
+        self.pushed.notify_one();
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.is_registered = false;
+        state.events.clear();
+        self.pushed.notify_all();
+    }
+}
+
+/// Event queues registered to a module.
+///
+/// The registry is internally synchronized so that a module can push events from other threads.
+#[derive(Debug)]
+pub(crate) struct EventRegistry {
+    /// Event types the module fires.
+    supported: &'static [EventType],
+    queues: Mutex<Vec<Arc<EventQueue>>>,
+}
+
+impl EventRegistry {
+    pub(crate) fn new(supported: &'static [EventType]) -> Self {
+        Self {
+            supported,
+            queues: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Registers `event_type` and returns the queue which the events are pushed into.
+    pub(crate) fn register(&self, event_type: EventType) -> GenTlResult<Arc<EventQueue>> {
+        if !self.supported.contains(&event_type) {
+            return Err(GenTlError::NotImplemented);
+        }
+
+        let mut queues = self.queues.lock().unwrap();
+        if queues.iter().any(|queue| queue.event_type == event_type) {
+            return Err(GenTlError::ResourceInUse);
+        }
+        let queue = Arc::new(EventQueue::new(event_type));
+        queues.push(queue.clone());
+        Ok(queue)
+    }
+
+    /// Unregisters `event_type`, waits on the queue are aborted.
+    pub(crate) fn unregister(&self, event_type: EventType) -> GenTlResult<()> {
+        let mut queues = self.queues.lock().unwrap();
+        let index = queues
+            .iter()
+            .position(|queue| queue.event_type == event_type)
+            .ok_or(GenTlError::NotInitialized)?;
+        queues.remove(index).close();
+        Ok(())
+    }
+
+    /// Unregisters all event types.
+    pub(crate) fn unregister_all(&self) {
+        for queue in self.queues.lock().unwrap().drain(..) {
+            queue.close();
+        }
+    }
+
+    pub(crate) fn is_registered(&self, event_type: EventType) -> bool {
+        self.queue(event_type).is_some()
+    }
+
+    /// Pushes `event` into its queue, the event is discarded if its type isn't registered.
+    pub(crate) fn notify(&self, event: EventData) {
+        if let Some(queue) = self.queue(event.event_type()) {
+            queue.push(event);
+        }
+    }
+
+    /// Discards queued events of `event_type`.
+    pub(crate) fn flush(&self, event_type: EventType) {
+        if let Some(queue) = self.queue(event_type) {
+            queue.flush();
+        }
+    }
+
+    fn queue(&self, event_type: EventType) -> Option<Arc<EventQueue>> {
+        self.queues
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|queue| queue.event_type == event_type)
+            .cloned()
+    }
+}
+
+/// Module which fires events.
+pub(crate) trait EventSource {
+    /// Event registry of the module.
+    fn events(&self) -> &EventRegistry;
+
+    /// Register an event of `event_type` to the module.
+    fn register_event(&self, event_type: EventType) -> GenTlResult<Arc<EventQueue>> {
+        self.events().register(event_type)
+    }
+
+    /// Unregister an event of `event_type` from the module.
+    fn unregister_event(&self, event_type: EventType) -> GenTlResult<()> {
+        self.events().unregister(event_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const SUPPORTED: &[EventType] = &[EventType::Error, EventType::NewBuffer];
+
+    fn new_buffer(raw: u64) -> EventData {
+        EventData::NewBuffer {
+            buffer: BufferHandle::from_raw(raw),
+            user_data: 0,
+        }
+    }
+
+    #[test]
+    fn test_register() {
+        let registry = EventRegistry::new(SUPPORTED);
+        assert!(matches!(
+            registry.register(EventType::RemoteDevice),
+            Err(GenTlError::NotImplemented)
+        ));
+
+        // Events are discarded while the type isn't registered.
+        registry.notify(new_buffer(1));
+        let queue = registry.register(EventType::NewBuffer).unwrap();
+        assert!(matches!(
+            registry.register(EventType::NewBuffer),
+            Err(GenTlError::ResourceInUse)
+        ));
+        assert_eq!(queue.num_in_queue(), 0);
+
+        registry.notify(new_buffer(2));
+        registry.notify(EventData::Error(GenTlError::Timeout));
+        assert_eq!(queue.num_in_queue(), 1);
+        assert_eq!(queue.num_fired(), 1);
+        match queue.get_data(Some(Duration::ZERO)).unwrap() {
+            EventData::NewBuffer { buffer, .. } => assert_eq!(buffer.as_raw(), 2),
+            event => panic!("unexpected event: {:?}", event),
+        }
+
+        registry.unregister(EventType::NewBuffer).unwrap();
+        assert!(!registry.is_registered(EventType::NewBuffer));
+        assert!(matches!(
+            registry.unregister(EventType::NewBuffer),
+            Err(GenTlError::NotInitialized)
+        ));
+        assert!(matches!(queue.get_data(None), Err(GenTlError::Abort)));
+    }
+
+    #[test]
+    fn test_get_data() {
+        let registry = Arc::new(EventRegistry::new(SUPPORTED));
+        let queue = registry.register(EventType::NewBuffer).unwrap();
+        assert!(matches!(
+            queue.get_data(Some(Duration::from_millis(10))),
+            Err(GenTlError::Timeout)
+        ));
+
+        let notifier = {
+            let registry = registry.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                registry.notify(new_buffer(1));
+            })
+        };
+        assert!(queue.get_data(Some(Duration::from_secs(10))).is_ok());
+        notifier.join().unwrap();
+
+        registry.notify(new_buffer(2));
+        registry.notify(new_buffer(3));
+        let event = queue.get_data(None).unwrap();
+        queue.requeue(event);
+        match queue.get_data(None).unwrap() {
+            EventData::NewBuffer { buffer, .. } => assert_eq!(buffer.as_raw(), 2),
+            event => panic!("unexpected event: {:?}", event),
+        }
+        queue.requeue(new_buffer(2));
+        queue.flush();
+        assert_eq!(queue.num_in_queue(), 0);
+        assert_eq!(queue.num_fired(), 3);
+    }
+
+    #[test]
+    fn test_kill() {
+        let registry = EventRegistry::new(SUPPORTED);
+        let queue = registry.register(EventType::Error).unwrap();
+
+        let waiter = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.get_data(None))
+        };
+        thread::sleep(Duration::from_millis(10));
+        queue.kill();
+        assert!(matches!(waiter.join().unwrap(), Err(GenTlError::Abort)));
+
+        // A kill without waiters aborts the next wait only.
+        queue.kill();
+        registry.notify(EventData::Error(GenTlError::Timeout));
+        assert!(matches!(queue.get_data(None), Err(GenTlError::Abort)));
+        assert!(matches!(
+            queue.get_data(None),
+            Ok(EventData::Error(GenTlError::Timeout))
+        ));
+    }
+}
@@ -2,9 +2,50 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use cameleon::genapi::CompressionType;
+use cameleon_impl::memory::{prelude::*, AccessRight};
+
+use crate::GenTlResult;
+
+use super::port::{self, PortInfo, XmlInfo, XmlLocation};
+
 pub(super) const SCHEME_MAJOR_VERSION: u64 = 1;
 pub(super) const SCHEME_MINOR_VERSION: u64 = 1;
 pub(super) const SCHEME_SUBMINOR_VERSION: u64 = 0;
 
 pub(super) const GENTL_VERSION_MAJOR: u32 = 1;
 pub(super) const GENTL_VERSION_MINOR: u32 = 6;
+
+/// Replaces the module XML stored in `Xml` register of `vm` with its zip archive, and points
+/// `xml_info` to the archive.
+///
+/// The archive is written over the head of the register so that the layout of the register map
+/// is kept. The XML is left uncompressed if the archive isn't smaller than the XML.
+pub(super) fn zip_module_xml<Xml, M>(
+    vm: &mut M,
+    xml_info: &mut XmlInfo,
+    port_info: &PortInfo,
+) -> GenTlResult<()>
+where
+    Xml: Register,
+    M: MemoryRead + MemoryWrite,
+{
+    let file_name = xml_info.file_name(port_info);
+    let archive = port::zip_xml(&file_name, vm.read_raw(Xml::range())?)?;
+    if archive.len() >= Xml::LENGTH {
+        return Ok(());
+    }
+
+    // The register is read only from the consumer.
+    vm.set_access_right::<Xml>(AccessRight::RW);
+    let written = vm.write_raw(Xml::ADDRESS, &archive);
+    vm.set_access_right::<Xml>(AccessRight::RO);
+    written?;
+
+    xml_info.location = XmlLocation::RegisterMap {
+        address: Xml::ADDRESS as u64,
+        size: archive.len(),
+    };
+    xml_info.compressed = CompressionType::Zip;
+    Ok(())
+}
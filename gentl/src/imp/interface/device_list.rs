@@ -0,0 +1,365 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Device list of an interface module.
+//!
+//! Consumers refer to a device by its index between `IFUpdateDeviceList` calls, so an index must
+//! keep referring to the same device. Newly found devices are appended to the list, and departed
+//! devices keep their slots until [`DeviceList::compact`] is called explicitly.
+
+use std::{collections::HashSet, sync::Mutex};
+
+use cameleon::u3v::Guid;
+
+use crate::{imp::device::DeviceAccessStatus, GenTlResult};
+
+/// A device module which can be listed in [`DeviceList`].
+pub(super) trait ListedDevice {
+    /// GUID which identifies the device across enumerations.
+    fn guid(&self) -> Guid;
+
+    /// Returns `true` if the device is opened by the consumer.
+    fn is_opened(&self) -> bool;
+
+    /// Access status of the device exposed to the consumer.
+    fn access_status(&self) -> DeviceAccessStatus;
+
+    fn force_access_status(&mut self, status: DeviceAccessStatus);
+
+    /// Reflects the current status of the device to the status exposed to the consumer.
+    fn reflect_status(&mut self);
+
+    /// Probes the availability of the device which isn't opened by the consumer, and returns the
+    /// current status updated by the probe. The device must not be left opened.
+    fn probe_status(&mut self) -> DeviceAccessStatus;
+
+    fn close(&mut self) -> GenTlResult<()>;
+}
+
+struct Slot<T> {
+    // Boxed so that the address of the module is stable while the consumer holds its handle.
+    device: Box<Mutex<T>>,
+    /// `false` if the device wasn't found in the last enumeration.
+    is_present: bool,
+}
+
+pub(super) struct DeviceList<T> {
+    slots: Vec<Slot<T>>,
+}
+
+impl<T: ListedDevice> DeviceList<T> {
+    pub(super) fn new() -> Self {
+        Self { slots: vec![] }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub(super) fn get(&self, index: usize) -> Option<&Mutex<T>> {
+        self.slots.get(index).map(|slot| slot.device.as_ref())
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = &Mutex<T>> {
+        self.slots.iter().map(|slot| slot.device.as_ref())
+    }
+
+    /// Returns the index of the device whose GUID is `guid`.
+    pub(super) fn position(&self, guid: &Guid) -> Option<usize> {
+        self.iter()
+            .position(|dev| dev.lock().unwrap().guid() == *guid)
+    }
+
+    /// Merges `found_devices` into the list, and returns `true` if the list is changed.
+    ///
+    /// Devices are matched to existing slots by their GUID, and new devices are appended to the
+    /// list. Found devices which aren't opened are probed so that devices held by another entity
+    /// are marked as [`DeviceAccessStatus::Busy`]. Devices which aren't found are marked as
+    /// [`DeviceAccessStatus::NoAccess`] unless they are opened.
+    pub(super) fn update(&mut self, found_devices: Vec<T>) -> bool {
+        // First, reflect current device status.
+        for dev in self.iter() {
+            dev.lock().unwrap().reflect_status();
+        }
+
+        let mut changed = false;
+        let mut found_ids = HashSet::new();
+        for mut found_device in found_devices {
+            found_ids.insert(found_device.guid());
+
+            if let Some(index) = self.position(&found_device.guid()) {
+                // If device has already been found and its current status is NoAccess, then close
+                // it and change its status to Unknown(initial state).
+                let slot = &mut self.slots[index];
+                slot.is_present = true;
+                let mut device = slot.device.lock().unwrap();
+                let status = device.access_status();
+                if status == DeviceAccessStatus::NoAccess {
+                    device.close().ok();
+                    device.force_access_status(status.on_replug());
+                    changed = true;
+                }
+                if !device.is_opened() && device.probe_status() != device.access_status() {
+                    changed = true;
+                }
+            } else {
+                found_device.probe_status();
+                self.slots.push(Slot {
+                    device: Box::new(Mutex::new(found_device)),
+                    is_present: true,
+                });
+                changed = true;
+            }
+        }
+
+        for slot in &mut self.slots {
+            let mut device = slot.device.lock().unwrap();
+            if found_ids.contains(&device.guid()) {
+                continue;
+            }
+            slot.is_present = false;
+            // An opened device keeps its status until the consumer closes it.
+            let status = device.access_status();
+            if !device.is_opened() && status != DeviceAccessStatus::NoAccess {
+                device.force_access_status(status.on_unplug());
+                changed = true;
+            }
+        }
+
+        if changed {
+            for dev in self.iter() {
+                dev.lock().unwrap().reflect_status();
+            }
+        }
+
+        changed
+    }
+
+    /// Removes departed devices which aren't opened, and returns the number of removed devices.
+    ///
+    /// Indices of the remaining devices may change.
+    pub(super) fn compact(&mut self) -> usize {
+        let len = self.slots.len();
+        self.slots
+            .retain(|slot| slot.is_present || slot.device.lock().unwrap().is_opened());
+        len - self.slots.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use cameleon::ControlError;
+
+    use super::*;
+    use crate::imp::device::DeviceAccessFlag;
+
+    /// Devices held open by handles outside of the device list.
+    type Holders = Arc<Mutex<HashSet<Guid>>>;
+
+    struct FakeDevice {
+        guid: Guid,
+        current_status: DeviceAccessStatus,
+        status: DeviceAccessStatus,
+        holders: Holders,
+    }
+
+    impl FakeDevice {
+        fn new(guid: &str) -> Self {
+            Self::with_holders(guid, Holders::default())
+        }
+
+        fn with_holders(guid: &str, holders: Holders) -> Self {
+            Self {
+                guid: guid.parse().unwrap(),
+                current_status: DeviceAccessStatus::Unknown,
+                status: DeviceAccessStatus::Unknown,
+                holders,
+            }
+        }
+
+        fn open(&mut self) {
+            let status = DeviceAccessStatus::on_open(DeviceAccessFlag::Control);
+            self.force_access_status(status);
+        }
+    }
+
+    impl ListedDevice for FakeDevice {
+        fn guid(&self) -> Guid {
+            self.guid
+        }
+
+        fn is_opened(&self) -> bool {
+            self.current_status.is_opened()
+        }
+
+        fn access_status(&self) -> DeviceAccessStatus {
+            self.status
+        }
+
+        fn force_access_status(&mut self, status: DeviceAccessStatus) {
+            self.current_status = status;
+            self.reflect_status();
+        }
+
+        fn reflect_status(&mut self) {
+            self.status = self.current_status;
+        }
+
+        fn probe_status(&mut self) -> DeviceAccessStatus {
+            let result = if self.holders.lock().unwrap().contains(&self.guid) {
+                Err(ControlError::Busy)
+            } else {
+                Ok(())
+            };
+            self.current_status = DeviceAccessStatus::from_probe(&result);
+            self.current_status
+        }
+
+        fn close(&mut self) -> GenTlResult<()> {
+            self.force_access_status(self.current_status.on_close());
+            Ok(())
+        }
+    }
+
+    /// Returns GUID of the device named `name`, e.g. `2a2b-0000000a` for `A`.
+    fn guid_of(name: &str) -> String {
+        format!("2A2B00000{:03X}", u32::from_str_radix(name, 16).unwrap())
+    }
+
+    fn enumerate(names: &[&str]) -> Vec<FakeDevice> {
+        names
+            .iter()
+            .map(|name| FakeDevice::new(&guid_of(name)))
+            .collect()
+    }
+
+    fn position(list: &DeviceList<FakeDevice>, name: &str) -> Option<usize> {
+        list.position(&guid_of(name).parse().unwrap())
+    }
+
+    fn id_at(list: &DeviceList<FakeDevice>, index: usize) -> String {
+        let guid = list.get(index).unwrap().lock().unwrap().guid;
+        format!("{:X}", guid.as_bytes()[5])
+    }
+
+    #[test]
+    fn test_stable_index() {
+        let mut list = DeviceList::new();
+        assert!(list.update(enumerate(&["A", "B"])));
+        list.get(1).unwrap().lock().unwrap().open();
+
+        // A new device is found before the existing ones.
+        assert!(list.update(enumerate(&["C", "A", "B"])));
+        assert_eq!(list.len(), 3);
+        assert_eq!(id_at(&list, 0), "A");
+        assert_eq!(id_at(&list, 1), "B");
+        assert_eq!(id_at(&list, 2), "C");
+        assert!(list.get(1).unwrap().lock().unwrap().is_opened());
+
+        // Nothing is changed.
+        assert!(!list.update(enumerate(&["B", "C", "A"])));
+    }
+
+    #[test]
+    fn test_departed_device() {
+        let mut list = DeviceList::new();
+        list.update(enumerate(&["A", "B", "C"]));
+        list.get(2).unwrap().lock().unwrap().open();
+
+        // `A` and opened `C` are departed.
+        assert!(list.update(enumerate(&["B"])));
+        assert_eq!(list.len(), 3);
+        let status = |i: usize| list.get(i).unwrap().lock().unwrap().access_status();
+        assert_eq!(status(0), DeviceAccessStatus::NoAccess);
+        assert_eq!(status(2), DeviceAccessStatus::OpenReadWrite);
+
+        // `A` comes back to its slot, and is probed again.
+        assert!(list.update(enumerate(&["A", "B"])));
+        assert_eq!(position(&list, "A"), Some(0));
+        assert_eq!(
+            list.get(0).unwrap().lock().unwrap().access_status(),
+            DeviceAccessStatus::ReadWrite
+        );
+    }
+
+    #[test]
+    fn test_compact() {
+        let mut list = DeviceList::new();
+        list.update(enumerate(&["A", "B", "C", "D"]));
+        list.get(2).unwrap().lock().unwrap().open();
+        list.update(enumerate(&["B"]));
+
+        // Opened `C` is kept until it's closed.
+        assert_eq!(list.compact(), 2);
+        assert_eq!(position(&list, "B"), Some(0));
+        assert_eq!(position(&list, "C"), Some(1));
+
+        list.get(1).unwrap().lock().unwrap().close().unwrap();
+        assert_eq!(list.compact(), 1);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.compact(), 0);
+    }
+
+    #[test]
+    fn test_reconnect_in_another_form() {
+        let mut list = DeviceList::new();
+        list.update(vec![FakeDevice::new("2A2B0000000A")]);
+        list.update(vec![]);
+        assert_eq!(
+            list.get(0).unwrap().lock().unwrap().access_status(),
+            DeviceAccessStatus::NoAccess
+        );
+
+        // The reconnected device reports its GUID in another form.
+        assert!(list.update(vec![FakeDevice::new("{2a2b-0000000a}")]));
+        assert_eq!(list.len(), 1);
+        assert_eq!(
+            list.get(0).unwrap().lock().unwrap().access_status(),
+            DeviceAccessStatus::ReadWrite
+        );
+    }
+
+    #[test]
+    fn test_busy_device() {
+        let holders = Holders::default();
+        let enumerate = |names: &[&str]| -> Vec<FakeDevice> {
+            names
+                .iter()
+                .map(|name| FakeDevice::with_holders(&guid_of(name), holders.clone()))
+                .collect()
+        };
+        let status = |list: &DeviceList<FakeDevice>, i: usize| {
+            list.get(i).unwrap().lock().unwrap().access_status()
+        };
+        let guid_a = guid_of("A").parse().unwrap();
+
+        // `A` is held open by another handle before it's found.
+        holders.lock().unwrap().insert(guid_a);
+        let mut list = DeviceList::new();
+        assert!(list.update(enumerate(&["A", "B"])));
+        assert_eq!(status(&list, 0), DeviceAccessStatus::Busy);
+        assert_eq!(status(&list, 1), DeviceAccessStatus::ReadWrite);
+        assert!(!list.update(enumerate(&["A", "B"])));
+
+        // The other handle releases `A`.
+        holders.lock().unwrap().remove(&guid_a);
+        assert!(list.update(enumerate(&["A", "B"])));
+        assert_eq!(status(&list, 0), DeviceAccessStatus::ReadWrite);
+
+        // A device opened by the consumer isn't probed.
+        list.get(1).unwrap().lock().unwrap().open();
+        holders
+            .lock()
+            .unwrap()
+            .insert(guid_of("B").parse().unwrap());
+        assert!(!list.update(enumerate(&["A", "B"])));
+        assert_eq!(status(&list, 1), DeviceAccessStatus::OpenReadWrite);
+    }
+}
@@ -2,45 +2,114 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use std::sync::Mutex;
+use std::{sync::Mutex, time::SystemTime};
+
+use cameleon::u3v::Guid;
 
 use crate::{
     imp::device::Device,
+    imp::event::EventSource,
     imp::port::{Port, TlType},
     GenTlError, GenTlResult,
 };
 
 pub(crate) mod u3v;
 
+mod device_list;
 mod u3v_genapi;
 
-pub(crate) trait Interface: Port {
+/// Event ID of [`crate::imp::event::EventData::Module`] fired when the device list of an
+/// interface is changed by [`Interface::update_device_list`] or [`Interface::compact_device_list`].
+pub(crate) const DEVICE_LIST_CHANGED_EVENT_ID: u64 = 0x1;
+
+/// Interface module, which fires [`crate::imp::event::EventType::Module`] with
+/// [`DEVICE_LIST_CHANGED_EVENT_ID`] when its device list is changed.
+pub(crate) trait Interface: Port + EventSource + Send {
     fn open(&mut self) -> GenTlResult<()>;
 
     fn close(&mut self) -> GenTlResult<()>;
 
+    /// Updates the device list. Indices of the devices already in the list don't change.
     fn update_device_list(&mut self, timeout: std::time::Duration) -> GenTlResult<bool>;
 
-    fn interface_id(&self) -> &str;
+    /// Removes departed devices which aren't opened from the device list, and returns the number
+    /// of removed devices. Indices of the remaining devices may change.
+    fn compact_device_list(&mut self) -> GenTlResult<usize>;
 
-    fn display_name(&self) -> &str;
+    /// Information of the interface.
+    fn interface_info(&self) -> &InterfaceInfo;
 
-    fn tl_type(&self) -> TlType;
+    /// Time when the device list was updated last, `None` if it has never been updated.
+    fn updated_device_list_timestamp(&self) -> Option<SystemTime>;
 
-    fn mac_addr(&self) -> Option<[u8; 6]>;
+    fn interface_id(&self) -> &str {
+        &self.interface_info().id
+    }
 
-    fn ip_addr(&self) -> Option<std::net::Ipv4Addr>;
+    fn display_name(&self) -> &str {
+        &self.interface_info().display_name
+    }
 
-    fn subnet_mask(&self) -> Option<std::net::Ipv4Addr>;
+    fn tl_type(&self) -> TlType {
+        self.interface_info().tl_type
+    }
 
-    fn gateway_addr(&self) -> Option<std::net::Ipv4Addr>;
+    fn mac_addr(&self) -> Option<[u8; 6]> {
+        self.interface_info().mac_addr
+    }
+
+    fn ip_addr(&self) -> Option<std::net::Ipv4Addr> {
+        self.interface_info().ip_addr
+    }
+
+    fn subnet_mask(&self) -> Option<std::net::Ipv4Addr> {
+        self.interface_info().subnet_mask
+    }
+
+    fn gateway_addr(&self) -> Option<std::net::Ipv4Addr> {
+        self.interface_info().gateway_addr
+    }
 
     fn devices(&self) -> Vec<&Mutex<dyn Device>>;
 
+    /// Enables or disables listing emulated devices, which takes effect at the next update of
+    /// the device list.
+    #[cfg(feature = "emulator")]
+    fn set_emulation_enabled(&mut self, enabled: bool);
+
+    /// Returns the device whose id is `id`. GUIDs are compared regardless of their forms.
     fn device_by_id(&self, id: &str) -> GenTlResult<&Mutex<dyn Device>> {
+        let guid = id.parse::<Guid>().ok();
         self.devices()
             .into_iter()
-            .find(|dev| dev.lock().unwrap().device_id() == id)
+            .find(|dev| {
+                let dev = dev.lock().unwrap();
+                let dev_id = dev.device_id();
+                dev_id == id || (guid.is_some() && dev_id.parse().ok() == guid)
+            })
             .ok_or_else(|| GenTlError::InvalidId(id.into()))
     }
 }
+
+pub(crate) struct InterfaceInfo {
+    /// Unique ID of the interface.
+    pub(crate) id: String,
+
+    /// User readable name of the interface.
+    pub(crate) display_name: String,
+
+    /// Transport layer technology that is supported.
+    pub(crate) tl_type: TlType,
+
+    /// MAC address of the interface, only available for GigE Vision interfaces.
+    pub(crate) mac_addr: Option<[u8; 6]>,
+
+    /// IP address of the interface, only available for GigE Vision interfaces.
+    pub(crate) ip_addr: Option<std::net::Ipv4Addr>,
+
+    /// Subnet mask of the interface, only available for GigE Vision interfaces.
+    pub(crate) subnet_mask: Option<std::net::Ipv4Addr>,
+
+    /// Default gateway of the interface, only available for GigE Vision interfaces.
+    pub(crate) gateway_addr: Option<std::net::Ipv4Addr>,
+}
@@ -5,34 +5,63 @@
 use std::{
     collections::VecDeque,
     sync::{Arc, Mutex},
+    time::SystemTime,
 };
 
-use cameleon::genapi::CompressionType;
+use cameleon::{
+    genapi::CompressionType,
+    u3v::{self, Guid},
+};
 use cameleon_impl::memory::{prelude::*, MemoryObserver};
 
 use crate::{
     imp::{
         device::{
             u3v::{enumerate_u3v_device, U3VDeviceModule},
-            Device, DeviceAccessStatus,
+            Device, DeviceAccessFlag, DeviceAccessStatus, DeviceInfoCmd, DeviceInfoValue,
         },
+        event::{EventData, EventRegistry, EventSource, EventType},
         genapi_common,
-        port::{Endianness, ModuleType, Port, PortAccess, PortInfo, TlType, XmlInfo, XmlLocation},
+        port::{
+            self, Endianness, ModuleType, Port, PortAccess, PortInfo, SharedPort, TlType, XmlInfo,
+            XmlLocation,
+        },
+        stream::DataStream,
     },
     GenTlError, GenTlResult,
 };
 
-use super::{u3v_genapi as genapi, Interface};
+#[cfg(feature = "emulator")]
+use crate::imp::device::emulated::{self, EmulatedDeviceModule};
+
+use super::{
+    device_list::{DeviceList, ListedDevice},
+    u3v_genapi as genapi, Interface, InterfaceInfo, DEVICE_LIST_CHANGED_EVENT_ID,
+};
 use genapi::GenApiReg;
 
-#[allow(clippy::vec_box)]
+/// Events fired by the interface module.
+const SUPPORTED_EVENTS: &[EventType] = &[
+    EventType::Error,
+    EventType::FeatureInvalidate,
+    EventType::Module,
+];
+
 pub(crate) struct U3VInterfaceModule {
     vm: genapi::Memory,
     port_info: PortInfo,
     xml_infos: Vec<XmlInfo>,
+    interface_info: InterfaceInfo,
+    /// GenTL events registered by the consumer.
+    events: EventRegistry,
     is_opened: bool,
-    devices: Vec<Box<Mutex<U3VDeviceModule>>>,
+    devices: DeviceList<U3VDevice>,
+    /// Time when the device list was updated last.
+    updated_device_list_timestamp: Option<SystemTime>,
     event_queue: Arc<Mutex<VecDeque<MemoryEvent>>>,
+    /// `true` if emulated devices are listed alongside real devices.
+    #[cfg(feature = "emulator")]
+    emulation_enabled: bool,
 }
 
 impl U3VInterfaceModule {
@@ -53,7 +82,7 @@ impl U3VInterfaceModule {
             port_name: genapi::PORT_NAME.into(),
         };
 
-        let xml_info = XmlInfo {
+        let mut xml_info = XmlInfo {
             location: XmlLocation::RegisterMap {
                 address: genapi::GENAPI_XML_ADDRESS as u64,
                 size: genapi::GENAPI_XML_LENGTH,
@@ -71,15 +100,35 @@ impl U3VInterfaceModule {
             sha1_hash: None,
             compressed: CompressionType::Uncompressed,
         };
+        let mut vm = genapi::Memory::new();
+        genapi_common::zip_module_xml::<genapi::GenApiXml::Xml, _>(
+            &mut vm,
+            &mut xml_info,
+            &port_info,
+        )
+        .unwrap();
 
         let mut module = Self {
-            vm: genapi::Memory::new(),
+            vm,
             port_info,
             xml_infos: vec![xml_info],
+            interface_info: InterfaceInfo {
+                id: genapi::INTERFACE_ID.into(),
+                display_name: "U3V Interface Module".into(),
+                tl_type: genapi::INTERFACE_TYPE,
+                mac_addr: None,
+                ip_addr: None,
+                subnet_mask: None,
+                gateway_addr: None,
+            },
+            events: EventRegistry::new(SUPPORTED_EVENTS),
             is_opened: false,
 
-            devices: vec![],
+            devices: DeviceList::new(),
+            updated_device_list_timestamp: None,
             event_queue: Arc::new(Mutex::new(VecDeque::new())),
+            #[cfg(feature = "emulator")]
+            emulation_enabled: false,
         };
 
         module.initialize_vm();
@@ -89,70 +138,111 @@ impl U3VInterfaceModule {
     fn update_device_list(&mut self) -> GenTlResult<bool> {
         self.assert_open()?;
 
-        // First, reflect current device status.
-        for device in &self.devices {
-            device.lock().unwrap().reflect_status();
-        }
-
         // Enumerate devices connected to the interface.
-        let found_devices = enumerate_u3v_device()?
+        let mut found_devices: Vec<_> = self
+            .enumerate_real_device()?
             .into_iter()
-            .map(|dev| Box::new(Mutex::new(dev)));
-
-        let mut changed = false;
-
-        for found_device in found_devices {
-            let found_device_guard = found_device.lock().unwrap();
-            let id = found_device_guard.device_id();
-
-            if let Some(device) = self.find_device_by_id(id)? {
-                // If device has already been found and its current status is NoAccess, then close
-                // it and change its status to Unknown(initial state).
-                let mut device_guard = device.lock().unwrap();
-                if device_guard.access_status() == DeviceAccessStatus::NoAccess {
-                    device_guard.close().ok();
-                    device_guard.force_access_status(DeviceAccessStatus::Unknown);
-                    changed = true;
-                }
-            } else {
-                // If device hasn't been found, then just add it to device pool.
-                drop(found_device_guard);
-                self.devices.push(found_device);
-                changed = true;
-            }
+            .map(U3VDevice::Real)
+            .collect();
+        found_devices.extend(self.enumerate_emulated_device()?);
+        let changed = self.devices.update(found_devices);
+        self.updated_device_list_timestamp = Some(SystemTime::now());
+        if changed {
+            self.refresh_device_selector()?;
+            self.notify_device_list_changed();
         }
 
-        if changed {
-            for device in &self.devices {
-                let mut device_guard = device.lock().unwrap();
-                device_guard.reflect_status();
-            }
+        Ok(changed)
+    }
 
+    /// Removes departed devices which aren't opened from the device list, and returns the number
+    /// of removed devices.
+    ///
+    /// `DeviceSelector` keeps selecting the same device if it remains in the list.
+    fn compact_device_list(&mut self) -> GenTlResult<usize> {
+        self.assert_open()?;
+
+        let selected = self
+            .devices
+            .get(self.vm.read::<GenApiReg::DeviceSelector>().unwrap() as usize)
+            .map(|dev| ListedDevice::guid(&*dev.lock().unwrap()));
+        let removed = self.devices.compact();
+        if removed > 0 {
+            let index = selected
+                .and_then(|id| self.devices.position(&id))
+                .unwrap_or(0);
             self.vm
-                .write::<GenApiReg::DeviceSelectorMax>(self.devices.len() as u32 - 1)
+                .write::<GenApiReg::DeviceSelector>(index as u32)
                 .unwrap();
-            self.handle_device_selector_change()?;
+            self.refresh_device_selector()?;
+            // Selector change has already been handled.
+            self.event_queue.lock().unwrap().clear();
+            self.notify_device_list_changed();
         }
 
-        Ok(changed)
+        Ok(removed)
     }
 
-    fn assert_open(&self) -> GenTlResult<()> {
-        if self.is_opened {
+    /// Notifies the consumer that the device list is changed so that it re-reads the device
+    /// enumeration features.
+    fn notify_device_list_changed(&self) {
+        self.events.notify(EventData::FeatureInvalidate {
+            feature: "DeviceSelector".into(),
+        });
+        self.events.notify(EventData::Module {
+            event_id: DEVICE_LIST_CHANGED_EVENT_ID,
+            data: vec![],
+        });
+    }
+
+    /// Enumerates devices connected to the host.
+    ///
+    /// While emulation is enabled, a failure of the enumeration is ignored so that emulated
+    /// devices are still listed, e.g. when libusb can't be initialized in a container.
+    fn enumerate_real_device(&self) -> GenTlResult<Vec<U3VDeviceModule>> {
+        let result = enumerate_u3v_device(None);
+        #[cfg(feature = "emulator")]
+        if self.emulation_enabled && result.is_err() {
+            return Ok(vec![]);
+        }
+        result
+    }
+
+    #[cfg(feature = "emulator")]
+    fn enumerate_emulated_device(&self) -> GenTlResult<Vec<U3VDevice>> {
+        if !self.emulation_enabled {
+            return Ok(vec![]);
+        }
+
+        Ok(emulated::enumerate_emulated_device()?
+            .into_iter()
+            .map(U3VDevice::Emulated)
+            .collect())
+    }
+
+    #[cfg(not(feature = "emulator"))]
+    #[allow(clippy::unused_self, clippy::unnecessary_wraps)]
+    fn enumerate_emulated_device(&self) -> GenTlResult<Vec<U3VDevice>> {
+        Ok(vec![])
+    }
+
+    fn refresh_device_selector(&mut self) -> GenTlResult<()> {
+        self.vm
+            .write::<GenApiReg::DeviceSelectorMax>(self.devices.len().saturating_sub(1) as u32)
+            .unwrap();
+        if self.devices.is_empty() {
             Ok(())
         } else {
-            Err(GenTlError::NotInitialized)
+            self.handle_device_selector_change()
         }
     }
 
-    fn find_device_by_id(&self, id: &str) -> GenTlResult<Option<&Mutex<U3VDeviceModule>>> {
-        for dev in &self.devices {
-            if dev.lock().unwrap().port_info()?.id == id {
-                return Ok(Some(dev.as_ref()));
-            }
+    fn assert_open(&self) -> GenTlResult<()> {
+        if self.is_opened {
+            Ok(())
+        } else {
+            Err(GenTlError::NotInitialized)
         }
-
-        Ok(None)
     }
 
     fn initialize_vm(&mut self) {
@@ -192,15 +282,16 @@ impl U3VInterfaceModule {
     fn handle_device_selector_change(&mut self) -> GenTlResult<()> {
         let device_idx = self.vm.read::<GenApiReg::DeviceSelector>().unwrap() as usize;
 
-        if device_idx >= self.devices.len() {
-            return Err(GenTlError::InvalidIndex);
-        }
-
-        let device = self.devices[device_idx].lock().unwrap();
+        let device = self
+            .devices
+            .get(device_idx)
+            .ok_or(GenTlError::InvalidIndex)?
+            .lock()
+            .unwrap();
         let device_info = device.device_info();
 
         self.vm
-            .write::<GenApiReg::DeviceID>(device.port_info()?.id.clone())?;
+            .write::<GenApiReg::DeviceID>(device.device_id().to_string())?;
 
         self.vm
             .write::<GenApiReg::DeviceVendorName>(device_info.vendor_name.clone())
@@ -211,7 +302,7 @@ impl U3VInterfaceModule {
 
         let status: DeviceAccessStatus = device.access_status();
         self.vm
-            .write::<GenApiReg::DeviceAccessStatus>(status as u32)?;
+            .write::<GenApiReg::DeviceAccessStatus>(status.as_raw())?;
 
         Ok(())
     }
@@ -248,16 +339,17 @@ impl MemoryObserver for DeviceSelectorRegObserver {
 impl Port for U3VInterfaceModule {
     fn read(&self, address: u64, buf: &mut [u8]) -> GenTlResult<usize> {
         self.assert_open()?;
-        let address = address as usize;
+        let range = port::memory_range(address, buf.len())?;
         let len = buf.len();
-        let data = self.vm.read_raw(address..address + len)?;
+        let data = self.vm.read_raw(range)?;
         buf.copy_from_slice(data);
         Ok(len)
     }
 
     fn write(&mut self, address: u64, data: &[u8]) -> GenTlResult<usize> {
         self.assert_open()?;
-        self.vm.write_raw(address as usize, &data)?;
+        let range = port::memory_range(address, data.len())?;
+        self.vm.write_raw(range.start, data)?;
 
         self.handle_events()?;
 
@@ -288,55 +380,196 @@ impl Interface for U3VInterfaceModule {
     }
 
     fn close(&mut self) -> GenTlResult<()> {
-        for dev in &self.devices {
-            dev.lock().unwrap().close()?;
+        // Close all devices even if some of them fail, then report the first error.
+        let mut res = Ok(());
+        for dev in self.devices.iter() {
+            let dev_res = dev.lock().unwrap().force_close();
+            res = res.and(dev_res);
         }
 
         self.is_opened = false;
-        Ok(())
+        res
     }
 
-    fn interface_id(&self) -> &str {
-        genapi::INTERFACE_ID
+    fn interface_info(&self) -> &InterfaceInfo {
+        &self.interface_info
     }
 
-    fn display_name(&self) -> &str {
-        "U3V Interface Module"
+    fn updated_device_list_timestamp(&self) -> Option<SystemTime> {
+        self.updated_device_list_timestamp
     }
 
-    fn tl_type(&self) -> TlType {
-        genapi::INTERFACE_TYPE
+    fn devices(&self) -> Vec<&Mutex<dyn Device>> {
+        let mut dyn_devices: Vec<&Mutex<dyn Device>> = Vec::with_capacity(self.devices.len());
+        for dev in self.devices.iter() {
+            dyn_devices.push(dev);
+        }
+        dyn_devices
     }
 
-    fn mac_addr(&self) -> Option<[u8; 6]> {
-        None
+    // NOTE: USB devices are enumerated without waiting for responses, so `timeout` is ignored.
+    fn update_device_list(&mut self, _timeout: std::time::Duration) -> GenTlResult<bool> {
+        self.assert_open()?;
+
+        self.update_device_list()
     }
 
-    fn ip_addr(&self) -> Option<std::net::Ipv4Addr> {
-        None
+    fn compact_device_list(&mut self) -> GenTlResult<usize> {
+        self.compact_device_list()
     }
 
-    fn subnet_mask(&self) -> Option<std::net::Ipv4Addr> {
-        None
+    #[cfg(feature = "emulator")]
+    fn set_emulation_enabled(&mut self, enabled: bool) {
+        self.emulation_enabled = enabled;
     }
+}
 
-    fn gateway_addr(&self) -> Option<std::net::Ipv4Addr> {
-        None
+impl EventSource for U3VInterfaceModule {
+    fn events(&self) -> &EventRegistry {
+        &self.events
     }
+}
 
-    fn devices(&self) -> Vec<&Mutex<dyn Device>> {
-        let mut dyn_devices: Vec<&Mutex<dyn Device>> = Vec::with_capacity(self.devices.len());
-        for dev in &self.devices {
-            dyn_devices.push(dev.as_ref());
+/// Device listed in the U3V interface module.
+///
+/// Emulated devices share the device list with real devices so that the index of a device is
+/// stable regardless of its kind.
+pub(crate) enum U3VDevice {
+    Real(U3VDeviceModule),
+    #[cfg(feature = "emulator")]
+    Emulated(EmulatedDeviceModule),
+}
+
+/// Calls `$body` with `$dev` bound to the device module of `$self`.
+macro_rules! dispatch {
+    ($self:ident, $dev:ident => $body:expr) => {
+        match $self {
+            U3VDevice::Real($dev) => $body,
+            #[cfg(feature = "emulator")]
+            U3VDevice::Emulated($dev) => $body,
         }
-        dyn_devices
+    };
+}
+
+impl U3VDevice {
+    fn device_info(&self) -> &u3v::DeviceInfo {
+        dispatch!(self, dev => dev.device_info())
     }
+}
 
-    // NOTE: We ignore timeout for now.
-    fn update_device_list(&mut self, _timeout: std::time::Duration) -> GenTlResult<bool> {
-        self.assert_open()?;
+impl ListedDevice for U3VDevice {
+    fn guid(&self) -> Guid {
+        dispatch!(self, dev => dev.guid())
+    }
 
-        self.update_device_list()
+    fn is_opened(&self) -> bool {
+        dispatch!(self, dev => dev.is_opened())
+    }
+
+    fn access_status(&self) -> DeviceAccessStatus {
+        dispatch!(self, dev => dev.access_status())
+    }
+
+    fn force_access_status(&mut self, status: DeviceAccessStatus) {
+        dispatch!(self, dev => dev.force_access_status(status));
+    }
+
+    fn reflect_status(&mut self) {
+        dispatch!(self, dev => dev.reflect_status());
+    }
+
+    fn probe_status(&mut self) -> DeviceAccessStatus {
+        dispatch!(self, dev => dev.probe_status())
+    }
+
+    fn close(&mut self) -> GenTlResult<()> {
+        dispatch!(self, dev => dev.force_close())
+    }
+}
+
+impl EventSource for U3VDevice {
+    fn events(&self) -> &EventRegistry {
+        dispatch!(self, dev => dev.events())
+    }
+}
+
+impl Port for U3VDevice {
+    fn read(&self, address: u64, buf: &mut [u8]) -> GenTlResult<usize> {
+        dispatch!(self, dev => dev.read(address, buf))
+    }
+
+    fn write(&mut self, address: u64, data: &[u8]) -> GenTlResult<usize> {
+        dispatch!(self, dev => dev.write(address, data))
+    }
+
+    fn read_stacked(
+        &self,
+        entries: &mut [(u64, &mut [u8])],
+        read_count: &mut usize,
+    ) -> GenTlResult<()> {
+        dispatch!(self, dev => dev.read_stacked(entries, read_count))
+    }
+
+    fn write_stacked(
+        &mut self,
+        entries: &[(u64, &[u8])],
+        written_count: &mut usize,
+    ) -> GenTlResult<()> {
+        dispatch!(self, dev => dev.write_stacked(entries, written_count))
+    }
+
+    fn port_info(&self) -> GenTlResult<&PortInfo> {
+        dispatch!(self, dev => dev.port_info())
+    }
+
+    fn xml_infos(&self) -> GenTlResult<&[XmlInfo]> {
+        dispatch!(self, dev => dev.xml_infos())
+    }
+}
+
+impl Device for U3VDevice {
+    fn open(&mut self, access_flag: DeviceAccessFlag) -> GenTlResult<()> {
+        dispatch!(self, dev => dev.open(access_flag))
+    }
+
+    fn close(&mut self, opened_with: DeviceAccessFlag) -> GenTlResult<()> {
+        dispatch!(self, dev => Device::close(dev, opened_with))
+    }
+
+    fn force_close(&mut self) -> GenTlResult<()> {
+        dispatch!(self, dev => dev.force_close())
+    }
+
+    fn device_id(&self) -> &str {
+        dispatch!(self, dev => dev.device_id())
+    }
+
+    fn remote_device(&self) -> GenTlResult<Arc<dyn SharedPort>> {
+        dispatch!(self, dev => dev.remote_device())
+    }
+
+    fn device_info(&self, cmd: DeviceInfoCmd) -> GenTlResult<DeviceInfoValue> {
+        dispatch!(self, dev => Device::device_info(dev, cmd))
+    }
+
+    fn tl_type(&self) -> TlType {
+        dispatch!(self, dev => dev.tl_type())
+    }
+
+    fn device_access_status(&self) -> DeviceAccessStatus {
+        dispatch!(self, dev => dev.device_access_status())
+    }
+
+    fn num_data_streams(&self) -> GenTlResult<usize> {
+        dispatch!(self, dev => dev.num_data_streams())
+    }
+
+    fn data_stream_id(&self, index: usize) -> GenTlResult<&str> {
+        dispatch!(self, dev => dev.data_stream_id(index))
+    }
+
+    fn open_data_stream(&mut self, stream_id: &str) -> GenTlResult<&Mutex<dyn DataStream>> {
+        dispatch!(self, dev => dev.open_data_stream(stream_id))
     }
 }
 
@@ -345,3 +578,188 @@ impl Default for U3VInterfaceModule {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interface_info() {
+        let mut iface = U3VInterfaceModule::new();
+        iface.open().unwrap();
+        assert_eq!(iface.interface_id(), genapi::INTERFACE_ID);
+        assert_eq!(iface.interface_id(), iface.port_info().unwrap().id);
+        assert!(iface.mac_addr().is_none());
+        assert!(iface.updated_device_list_timestamp().is_none());
+    }
+
+    #[test]
+    fn test_device_list_changed_event() {
+        let mut iface = U3VInterfaceModule::new();
+        iface.open().unwrap();
+        assert!(matches!(
+            iface.register_event(EventType::RemoteDevice),
+            Err(GenTlError::NotImplemented)
+        ));
+        let queue = iface.register_event(EventType::Module).unwrap();
+
+        // Nothing is fired if the device list isn't changed.
+        assert_eq!(iface.compact_device_list().unwrap(), 0);
+        assert_eq!(queue.num_in_queue(), 0);
+
+        iface.notify_device_list_changed();
+        assert!(matches!(
+            queue.get_data(Some(std::time::Duration::ZERO)).unwrap(),
+            EventData::Module {
+                event_id: DEVICE_LIST_CHANGED_EVENT_ID,
+                ..
+            }
+        ));
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn test_emulated_device_index() {
+        use cameleon_device::emulator::{self, EmulatorBuilder};
+
+        fn build(serial_number: &str) -> emulator::Device {
+            EmulatorBuilder::new()
+                .serial_number(serial_number)
+                .unwrap()
+                .build();
+            emulator::enumerate_devices()
+                .unwrap()
+                .into_iter()
+                .find(|dev| dev.device_info.serial_number == serial_number)
+                .unwrap()
+        }
+
+        fn index_of(iface: &U3VInterfaceModule, serial_number: &str) -> usize {
+            (0..iface.devices.len())
+                .find(|i| {
+                    let dev = iface.devices.get(*i).unwrap().lock().unwrap();
+                    dev.device_info().serial_number == serial_number
+                })
+                .unwrap()
+        }
+
+        let mut iface = U3VInterfaceModule::new();
+        iface.open().unwrap();
+        iface.set_emulation_enabled(true);
+        let first = build("IFACEDEV1");
+        build("IFACEDEV2");
+        iface.update_device_list().unwrap();
+
+        // Open the device and select it with `DeviceSelector`.
+        let index = index_of(&iface, "IFACEDEV2");
+        let guid = {
+            let mut dev = iface.devices.get(index).unwrap().lock().unwrap();
+            Device::open(&mut *dev, DeviceAccessFlag::Exclusive).unwrap();
+            ListedDevice::guid(&*dev)
+        };
+        iface
+            .vm
+            .write::<GenApiReg::DeviceSelector>(index as u32)
+            .unwrap();
+        iface.handle_events().unwrap();
+        let device_id = iface.vm.read::<GenApiReg::DeviceID>().unwrap();
+
+        // A new device is appended, and a departed device keeps its slot.
+        build("IFACEDEV3");
+        first.unplug().unwrap();
+        assert!(iface.update_device_list().unwrap());
+        assert!(index_of(&iface, "IFACEDEV3") > index);
+        let departed = index_of(&iface, "IFACEDEV1");
+        assert_eq!(
+            iface
+                .devices
+                .get(departed)
+                .unwrap()
+                .lock()
+                .unwrap()
+                .access_status(),
+            DeviceAccessStatus::NoAccess
+        );
+
+        assert_eq!(index_of(&iface, "IFACEDEV2"), index);
+        {
+            let dev = iface.devices.get(index).unwrap().lock().unwrap();
+            assert_eq!(ListedDevice::guid(&*dev), guid);
+            assert!(ListedDevice::is_opened(&*dev));
+        }
+        assert_eq!(iface.vm.read::<GenApiReg::DeviceID>().unwrap(), device_id);
+
+        // Compaction removes the departed device, and `DeviceSelector` follows the opened one.
+        assert!(iface.compact_device_list().unwrap() >= 1);
+        let index = index_of(&iface, "IFACEDEV2");
+        assert_eq!(
+            iface.vm.read::<GenApiReg::DeviceSelector>().unwrap() as usize,
+            index
+        );
+        assert_eq!(iface.vm.read::<GenApiReg::DeviceID>().unwrap(), device_id);
+
+        Interface::close(&mut iface).unwrap();
+    }
+
+    #[cfg(feature = "emulator")]
+    #[test]
+    fn test_emulated_device_list_events() {
+        use cameleon_device::emulator::{self, EmulatorBuilder};
+
+        use crate::imp::event::EventQueue;
+
+        /// Returns `true` if the device list changed events are fired, the queues are drained since
+        /// other tests may change the device list concurrently.
+        fn fired(module: &EventQueue, invalidate: &EventQueue) -> bool {
+            let drain = |queue: &EventQueue| {
+                std::iter::from_fn(|| queue.get_data(Some(std::time::Duration::ZERO)).ok())
+                    .collect::<Vec<_>>()
+            };
+            let changed = drain(module).iter().any(|event| {
+                matches!(
+                    event,
+                    EventData::Module {
+                        event_id: DEVICE_LIST_CHANGED_EVENT_ID,
+                        ..
+                    }
+                )
+            });
+            let invalidated = drain(invalidate).iter().any(|event| {
+                matches!(event, EventData::FeatureInvalidate { feature } if feature == "DeviceSelector")
+            });
+            changed && invalidated
+        }
+
+        let mut iface = U3VInterfaceModule::new();
+        iface.open().unwrap();
+        iface.set_emulation_enabled(true);
+        let module = iface.register_event(EventType::Module).unwrap();
+        let invalidate = iface.register_event(EventType::FeatureInvalidate).unwrap();
+        iface.update_device_list().unwrap();
+        fired(&module, &invalidate);
+
+        EmulatorBuilder::new()
+            .serial_number("IFACEDEV4")
+            .unwrap()
+            .build();
+        assert!(Interface::update_device_list(&mut iface, std::time::Duration::ZERO).unwrap());
+        assert!(iface.updated_device_list_timestamp().is_some());
+        assert!(fired(&module, &invalidate));
+
+        // The departed device is removed by compaction, which changes the list again.
+        emulator::enumerate_devices()
+            .unwrap()
+            .into_iter()
+            .find(|dev| dev.device_info.serial_number == "IFACEDEV4")
+            .unwrap()
+            .unplug()
+            .unwrap();
+        assert!(iface.update_device_list().unwrap());
+        assert!(fired(&module, &invalidate));
+        assert!(iface.compact_device_list().unwrap() >= 1);
+        assert!(fired(&module, &invalidate));
+
+        iface.unregister_event(EventType::Module).unwrap();
+        Interface::close(&mut iface).unwrap();
+    }
+}
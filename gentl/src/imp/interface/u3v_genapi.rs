@@ -322,19 +322,19 @@ xsi:schemaLocation="http://www.genicam.org/GenApi/Version_1_1 http://www.genicam
     device_model_name_len = DeviceModelName::LENGTH,
     device_model_name_access = DeviceModelName::ACCESS_RIGHT.as_str(),
     device_access_status_unknown_str = device::DeviceAccessStatus::Unknown.as_str(),
-    device_access_status_unknown_int = device::DeviceAccessStatus::Unknown as i32,
+    device_access_status_unknown_int = device::DeviceAccessStatus::Unknown.as_raw(),
     device_access_status_readwrite_str = device::DeviceAccessStatus::ReadWrite.as_str(),
-    device_access_status_readwrite_int = device::DeviceAccessStatus::ReadWrite as i32,
+    device_access_status_readwrite_int = device::DeviceAccessStatus::ReadWrite.as_raw(),
     device_access_status_readonly_str = device::DeviceAccessStatus::ReadOnly.as_str(),
-    device_access_status_readonly_int = device::DeviceAccessStatus::ReadOnly as i32,
+    device_access_status_readonly_int = device::DeviceAccessStatus::ReadOnly.as_raw(),
     device_access_status_noaccess_str = device::DeviceAccessStatus::NoAccess.as_str(),
-    device_access_status_noaccess_int = device::DeviceAccessStatus::NoAccess as i32,
+    device_access_status_noaccess_int = device::DeviceAccessStatus::NoAccess.as_raw(),
     device_access_status_busy_str = device::DeviceAccessStatus::Busy.as_str(),
-    device_access_status_busy_int = device::DeviceAccessStatus::Busy as i32,
+    device_access_status_busy_int = device::DeviceAccessStatus::Busy.as_raw(),
     device_access_status_openrw_str = device::DeviceAccessStatus::OpenReadWrite.as_str(),
-    device_access_status_openrw_int = device::DeviceAccessStatus::OpenReadWrite as i32,
+    device_access_status_openrw_int = device::DeviceAccessStatus::OpenReadWrite.as_raw(),
     device_access_status_openro_str = device::DeviceAccessStatus::OpenReadOnly.as_str(),
-    device_access_status_openro_int = device::DeviceAccessStatus::OpenReadOnly as i32,
+    device_access_status_openro_int = device::DeviceAccessStatus::OpenReadOnly.as_raw(),
     device_access_status_addr = DeviceAccessStatus::ADDRESS,
     device_access_status_len = DeviceAccessStatus::LENGTH,
     device_access_status_access = DeviceAccessStatus::ACCESS_RIGHT.as_str(),
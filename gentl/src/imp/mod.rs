@@ -2,14 +2,17 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+pub(super) mod buffer;
 pub(super) mod device;
+pub(super) mod event;
 pub(super) mod interface;
 pub(super) mod port;
+pub(super) mod stream;
 pub(super) mod system;
 
 mod genapi_common;
 
-use cameleon::ControlError;
+use cameleon::{CameleonError, ControlError, StreamError};
 use cameleon_impl::memory::MemoryError;
 
 use super::GenTlError;
@@ -27,18 +30,58 @@ impl From<MemoryError> for GenTlError {
 impl From<ControlError> for GenTlError {
     fn from(err: ControlError) -> Self {
         use GenTlError::{
-            BufferTooSmall, InvalidValue, Io, NotInitialized, ResourceInUse, Timeout,
+            BufferTooSmall, Disconnected, InvalidAddress, InvalidValue, Io, NotImplemented,
+            NotInitialized, ResourceInUse, Timeout,
         };
 
         match err {
-            ControlError::Busy => ResourceInUse,
-            ControlError::Disconnected | ControlError::Io(..) | ControlError::InvalidDevice(..) => {
-                Io(err.into())
-            }
+            ControlError::Busy | ControlError::AlreadyOpenInProcess { .. } => ResourceInUse,
+            ControlError::Disconnected => Disconnected,
+            ControlError::Io(err) => Io(err.into()),
+            ControlError::InvalidDevice(..)
+            | ControlError::LimitExceeded { .. }
+            | ControlError::PartialRead { .. }
+            | ControlError::PartialWrite { .. }
+            | ControlError::PartialChunkRead { .. }
+            | ControlError::PartialChunkWrite { .. }
+            | ControlError::UnexpectedAck { .. }
+            | ControlError::RequestIdMismatch { .. }
+            | ControlError::CorruptXmlArchive(..)
+            | ControlError::XmlHashMismatch
+            | ControlError::XmlNotUtf8(..)
+            | ControlError::RetriesExhausted { .. } => GenTlError::Custom {
+                code: err.code(),
+                message: err.to_string(),
+            },
             ControlError::NotOpened => NotInitialized,
             ControlError::InvalidData(..) => InvalidValue(format!("{}", err).into()),
             ControlError::Timeout => Timeout,
             ControlError::BufferTooSmall => BufferTooSmall,
+            ControlError::InvalidAddress { .. } => InvalidAddress,
+            ControlError::NotSupported(..) => NotImplemented,
+        }
+    }
+}
+
+impl From<CameleonError> for GenTlError {
+    fn from(err: CameleonError) -> Self {
+        match err {
+            CameleonError::ControlError(err) => err.into(),
+            _ => Self::Error(err.to_string()),
+        }
+    }
+}
+
+impl From<StreamError> for GenTlError {
+    fn from(err: StreamError) -> Self {
+        match err {
+            StreamError::Timeout => Self::Timeout,
+            StreamError::BufferTooSmall => Self::BufferTooSmall,
+            StreamError::InStreaming => Self::ResourceInUse,
+            StreamError::NotSupported(..) => Self::NotImplemented,
+            StreamError::Disconnected => Self::Disconnected,
+            StreamError::Io(..) => Self::Io(err.into()),
+            _ => Self::Error(err.to_string()),
         }
     }
 }
@@ -2,10 +2,27 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use std::{
+    convert::TryFrom,
+    io::{Cursor, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
 use cameleon::genapi::CompressionType;
 use semver::Version;
 
-use crate::GenTlResult;
+use crate::{GenTlError, GenTlResult};
+
+/// Converts the range of `len` bytes at `address` to the range of the host memory.
+///
+/// Returns [`GenTlError::InvalidAddress`] if the range overflows the address space of the host.
+pub(crate) fn memory_range(address: u64, len: usize) -> GenTlResult<Range<usize>> {
+    let start = usize::try_from(address).map_err(|_| GenTlError::InvalidAddress)?;
+    let end = start.checked_add(len).ok_or(GenTlError::InvalidAddress)?;
+    Ok(start..end)
+}
 
 pub(crate) trait Port {
     /// Reads a number of bytes from a given address from the Port. This is the global
@@ -20,34 +37,73 @@ pub(crate) trait Port {
 
     /// Read multiple entries.
     /// See "6.3.6 Port Functions" of GenTl specification for details.
+    ///
+    /// `read_count` is set to the number of entries fully read even if an error occurs, so that
+    /// the consumer can resume from the failed entry. That's why the count is returned through
+    /// the out parameter like `piNumEntries` of `GCReadPortStacked`, unlike [`Self::read`] which
+    /// returns the length in [`GenTlResult`].
     fn read_stacked(
         &self,
         entries: &mut [(u64, &mut [u8])],
         read_count: &mut usize,
     ) -> GenTlResult<()> {
-        *read_count = 0;
-        for ent in entries {
-            self.read(ent.0, ent.1)?;
-            *read_count += 1;
-        }
-        Ok(())
+        read_each(entries, read_count, |address, buf| self.read(address, buf))
     }
 
     /// Write to multiple entries.
     /// See "6.3.6 Port Functions" of GenTl specification for details.
+    ///
+    /// `written_count` is set to the number of entries fully written even if an error occurs,
+    /// so that the consumer can resume from the failed entry. The count is returned through the
+    /// out parameter for the same reason as [`Self::read_stacked`].
     fn write_stacked(
         &mut self,
         entries: &[(u64, &[u8])],
         written_count: &mut usize,
     ) -> GenTlResult<()> {
-        *written_count = 0;
+        write_each(entries, written_count, |address, data| {
+            self.write(address, data)
+        })
+    }
 
-        for ent in entries {
-            self.write(ent.0, ent.1)?;
-            *written_count += 1;
-        }
+    /// Get detailed port information.
+    fn port_info(&self) -> GenTlResult<&PortInfo>;
+
+    /// Get available xml infos of the port.
+    fn xml_infos(&self) -> GenTlResult<&[XmlInfo]>;
+}
+
+/// Port which can be shared between threads and accessed from them at the same time, i.e. the
+/// port of the remote device.
+///
+/// Unlike [`Port`], all methods take `&self`, so the port is handed out as
+/// `Arc<dyn SharedPort>` instead of being guarded by a single mutex. Implementations serialize
+/// accesses to the device by themselves, see [`crate::imp::device::u3v::U3VRemoteDevice`].
+pub(crate) trait SharedPort: Send + Sync {
+    /// Reads a number of bytes from a given address from the Port, see [`Port::read`].
+    fn read(&self, address: u64, buf: &mut [u8]) -> GenTlResult<usize>;
 
-        Ok(())
+    /// Writes a number of bytes at the given address to the Port, see [`Port::write`].
+    fn write(&self, address: u64, data: &[u8]) -> GenTlResult<usize>;
+
+    /// Read multiple entries, see [`Port::read_stacked`].
+    fn read_stacked(
+        &self,
+        entries: &mut [(u64, &mut [u8])],
+        read_count: &mut usize,
+    ) -> GenTlResult<()> {
+        read_each(entries, read_count, |address, buf| self.read(address, buf))
+    }
+
+    /// Write to multiple entries, see [`Port::write_stacked`].
+    fn write_stacked(
+        &self,
+        entries: &[(u64, &[u8])],
+        written_count: &mut usize,
+    ) -> GenTlResult<()> {
+        write_each(entries, written_count, |address, data| {
+            self.write(address, data)
+        })
     }
 
     /// Get detailed port information.
@@ -57,6 +113,83 @@ pub(crate) trait Port {
     fn xml_infos(&self) -> GenTlResult<&[XmlInfo]>;
 }
 
+/// Reads `entries` one by one with `read`.
+pub(crate) fn read_each(
+    entries: &mut [(u64, &mut [u8])],
+    read_count: &mut usize,
+    mut read: impl FnMut(u64, &mut [u8]) -> GenTlResult<usize>,
+) -> GenTlResult<()> {
+    *read_count = 0;
+    for ent in entries {
+        read(ent.0, ent.1)?;
+        *read_count += 1;
+    }
+    Ok(())
+}
+
+/// Writes `entries` one by one with `write`.
+pub(crate) fn write_each(
+    entries: &[(u64, &[u8])],
+    written_count: &mut usize,
+    mut write: impl FnMut(u64, &[u8]) -> GenTlResult<usize>,
+) -> GenTlResult<()> {
+    *written_count = 0;
+    for ent in entries {
+        write(ent.0, ent.1)?;
+        *written_count += 1;
+    }
+    Ok(())
+}
+
+/// [`SharedPort`] which denies writes to the shared `inner` port.
+///
+/// The remote device of a device opened with [`super::device::DeviceAccessFlag::ReadOnly`] is
+/// shared with the open for control, so the read-only open accesses it through this port.
+pub(crate) struct ReadOnlyPort {
+    inner: Arc<dyn SharedPort>,
+    port_info: PortInfo,
+}
+
+impl ReadOnlyPort {
+    pub(crate) fn new(inner: Arc<dyn SharedPort>) -> GenTlResult<Self> {
+        let mut port_info = inner.port_info()?.clone();
+        if port_info.access.is_readable() {
+            port_info.access = PortAccess::RO;
+        } else {
+            port_info.access = PortAccess::NA;
+        }
+        Ok(Self { inner, port_info })
+    }
+}
+
+impl SharedPort for ReadOnlyPort {
+    fn read(&self, address: u64, buf: &mut [u8]) -> GenTlResult<usize> {
+        self.inner.read(address, buf)
+    }
+
+    fn write(&self, _address: u64, _data: &[u8]) -> GenTlResult<usize> {
+        Err(GenTlError::AccessDenied)
+    }
+
+    fn read_stacked(
+        &self,
+        entries: &mut [(u64, &mut [u8])],
+        read_count: &mut usize,
+    ) -> GenTlResult<()> {
+        self.inner.read_stacked(entries, read_count)
+    }
+
+    fn port_info(&self) -> GenTlResult<&PortInfo> {
+        // Fails in the same way as the inner port once the device is closed.
+        self.inner.port_info()?;
+        Ok(&self.port_info)
+    }
+
+    fn xml_infos(&self) -> GenTlResult<&[XmlInfo]> {
+        self.inner.xml_infos()
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct PortInfo {
     /// Unique ID of the module the port reference.
@@ -91,7 +224,7 @@ pub(crate) struct PortInfo {
     pub(crate) port_name: String,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum TlType {
     /// Camera Link.
     CameraLink,
@@ -192,9 +325,262 @@ pub(crate) struct XmlInfo {
     pub(crate) compressed: CompressionType,
 }
 
+impl XmlInfo {
+    /// Returns the URL of the XML in the form which `GCGetPortURLInfo` reports.
+    ///
+    /// See "4.1.2 Module Access" of GenTL specification for details.
+    pub(crate) fn url(&self, port_info: &PortInfo) -> String {
+        let schema_version = &self.schema_version;
+        let query = format!(
+            "SchemaVersion={}.{}.{}",
+            schema_version.major, schema_version.minor, schema_version.patch
+        );
+
+        match &self.location {
+            // local:{filename}.{extension};{address};{length}[?SchemaVersion={major}.{minor}.{subminor}]
+            //
+            // address and length must be expressed in hexadecimal without prefix.
+            XmlLocation::RegisterMap { address, size } => format!(
+                "local:{file_name};{address:X};{size:X}?{query}",
+                file_name = self.file_name(port_info),
+                address = address,
+                size = size,
+                query = query,
+            ),
+
+            // file:{filepath}[?SchemaVersion={major}.{minor}.{subminor}]
+            XmlLocation::File(path) => {
+                format!("file:{}?{}", path.to_string_lossy(), query)
+            }
+
+            // {url}[?SchemaVersion={major}.{minor}.{subminor}]
+            XmlLocation::Url(url) => format!("{}?{}", url.as_str(), query),
+        }
+    }
+
+    /// Returns the file name of the XML.
+    ///
+    /// The name of a file is taken from its path, otherwise the name is
+    /// `{vendor}_{model}_{file_version}.{extension}` where the extension is `zip` if the XML is
+    /// compressed.
+    pub(crate) fn file_name(&self, port_info: &PortInfo) -> String {
+        let file_name = match &self.location {
+            XmlLocation::File(path) => path.file_name().map(|name| name.to_string_lossy()),
+            XmlLocation::Url(url) => url.path_segments().and_then(Iterator::last).map(Into::into),
+            XmlLocation::RegisterMap { .. } => None,
+        };
+        match file_name {
+            Some(file_name) if !file_name.is_empty() => file_name.into_owned(),
+            _ => {
+                let extension = match self.compressed {
+                    CompressionType::Uncompressed => "xml",
+                    CompressionType::Zip => "zip",
+                };
+                format!(
+                    "{}_{}_{}.{}",
+                    port_info.vendor, port_info.model, self.file_version, extension
+                )
+            }
+        }
+    }
+
+    /// Fetches the XML as it's stored, i.e. the XML is still zipped if it's compressed.
+    ///
+    /// The XML on the register map is read through `port`. Only `file` scheme is supported for
+    /// [`XmlLocation::Url`].
+    pub(crate) fn fetch<P: Port + ?Sized>(&self, port: &P) -> GenTlResult<Vec<u8>> {
+        match &self.location {
+            XmlLocation::RegisterMap { address, size } => {
+                let mut buf = vec![0; *size];
+                port.read(*address, &mut buf)?;
+                Ok(buf)
+            }
+            XmlLocation::File(path) => read_file(path),
+            XmlLocation::Url(url) => match url.to_file_path() {
+                Ok(path) if url.scheme() == "file" => read_file(&path),
+                _ => Err(GenTlError::NotAvailable),
+            },
+        }
+    }
+
+    /// Fetches the XML and decompresses it if it's zipped.
+    pub(crate) fn fetch_xml<P: Port + ?Sized>(&self, port: &P) -> GenTlResult<String> {
+        let data = self.fetch(port)?;
+        let xml = match self.compressed {
+            CompressionType::Uncompressed => data,
+            CompressionType::Zip => unzip_xml(&data)?,
+        };
+        String::from_utf8(xml).map_err(|err| GenTlError::InvalidValue(err.to_string().into()))
+    }
+}
+
 #[derive(Clone)]
 pub(crate) enum XmlLocation {
+    /// The XML is on the register map of the module.
     RegisterMap { address: u64, size: usize },
-    LocalFile(std::path::PathBuf),
+    /// The XML is in a file of the local file system.
+    File(PathBuf),
+    /// The XML is at the URL.
     Url(url::Url),
 }
+
+/// Zips `xml` into an archive which contains a single file named `file_name`.
+pub(crate) fn zip_xml(file_name: &str, xml: &[u8]) -> GenTlResult<Vec<u8>> {
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(file_name, options).map_err(zip_err)?;
+    zip.write_all(xml).map_err(zip_err)?;
+    Ok(zip.finish().map_err(zip_err)?.into_inner())
+}
+
+/// Extracts the XML from an archive which contains a single file.
+pub(crate) fn unzip_xml(archive: &[u8]) -> GenTlResult<Vec<u8>> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive)).map_err(zip_err)?;
+    if zip.len() != 1 {
+        return Err(GenTlError::InvalidValue(
+            "more than one files in zipped XML".into(),
+        ));
+    }
+    let mut xml = vec![];
+    zip.by_index(0)
+        .map_err(zip_err)?
+        .read_to_end(&mut xml)
+        .map_err(zip_err)?;
+    Ok(xml)
+}
+
+fn zip_err(err: impl std::fmt::Display) -> GenTlError {
+    GenTlError::InvalidValue(format!("failed to handle zipped XML: {}", err).into())
+}
+
+fn read_file(path: &Path) -> GenTlResult<Vec<u8>> {
+    std::fs::read(path).map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => GenTlError::NotAvailable,
+        _ => GenTlError::Io(err.into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port_info() -> PortInfo {
+        PortInfo {
+            id: "id".into(),
+            vendor: "Vendor".into(),
+            model: "Model".into(),
+            tl_type: TlType::USB3Vision,
+            module_type: ModuleType::System,
+            endianness: Endianness::LE,
+            access: PortAccess::RO,
+            version: Version::new(1, 0, 0),
+            port_name: "Port".into(),
+        }
+    }
+
+    fn xml_info(location: XmlLocation, compressed: CompressionType) -> XmlInfo {
+        XmlInfo {
+            location,
+            schema_version: Version::new(1, 1, 0),
+            file_version: Version::new(2, 3, 4),
+            sha1_hash: None,
+            compressed,
+        }
+    }
+
+    #[test]
+    fn test_local_url() {
+        let location = XmlLocation::RegisterMap {
+            address: 0x1000,
+            size: 0xff,
+        };
+        let info = xml_info(location.clone(), CompressionType::Uncompressed);
+        assert_eq!(
+            info.url(&port_info()),
+            "local:Vendor_Model_2.3.4.xml;1000;FF?SchemaVersion=1.1.0"
+        );
+
+        let info = xml_info(location, CompressionType::Zip);
+        assert_eq!(
+            info.url(&port_info()),
+            "local:Vendor_Model_2.3.4.zip;1000;FF?SchemaVersion=1.1.0"
+        );
+    }
+
+    #[test]
+    fn test_file_url() {
+        let location = XmlLocation::File("/tmp/genicam/device.zip".into());
+        let info = xml_info(location, CompressionType::Zip);
+        assert_eq!(
+            info.url(&port_info()),
+            "file:/tmp/genicam/device.zip?SchemaVersion=1.1.0"
+        );
+        assert_eq!(info.file_name(&port_info()), "device.zip");
+    }
+
+    #[test]
+    fn test_remote_url() {
+        let url = url::Url::parse("http://example.com/xml/device.xml").unwrap();
+        let info = xml_info(XmlLocation::Url(url), CompressionType::Uncompressed);
+        assert_eq!(
+            info.url(&port_info()),
+            "http://example.com/xml/device.xml?SchemaVersion=1.1.0"
+        );
+        assert_eq!(info.file_name(&port_info()), "device.xml");
+        assert!(matches!(info.fetch(&NoPort), Err(GenTlError::NotAvailable)));
+    }
+
+    #[test]
+    fn test_zip_xml() {
+        let xml = b"<RegisterDescription></RegisterDescription>";
+        let archive = zip_xml("module.xml", xml).unwrap();
+        assert_eq!(unzip_xml(&archive).unwrap(), xml);
+        assert!(unzip_xml(xml).is_err());
+    }
+
+    #[test]
+    fn test_fetch_file() {
+        let xml = "<RegisterDescription></RegisterDescription>";
+        let path = std::env::temp_dir().join("cameleon_gentl_test_fetch_file.zip");
+        std::fs::write(&path, zip_xml("module.xml", xml.as_bytes()).unwrap()).unwrap();
+
+        let info = xml_info(XmlLocation::File(path.clone()), CompressionType::Zip);
+        let fetched = info.fetch_xml(&NoPort);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(fetched.unwrap(), xml);
+
+        let info = xml_info(XmlLocation::File(path), CompressionType::Zip);
+        assert!(matches!(info.fetch(&NoPort), Err(GenTlError::NotAvailable)));
+    }
+
+    /// Port which is never accessed because the XML isn't on the register map.
+    struct NoPort;
+
+    impl Port for NoPort {
+        fn read(&self, _address: u64, _buf: &mut [u8]) -> GenTlResult<usize> {
+            unreachable!()
+        }
+
+        fn write(&mut self, _address: u64, _data: &[u8]) -> GenTlResult<usize> {
+            unreachable!()
+        }
+
+        fn port_info(&self) -> GenTlResult<&PortInfo> {
+            unreachable!()
+        }
+
+        fn xml_infos(&self) -> GenTlResult<&[XmlInfo]> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn test_memory_range() {
+        assert_eq!(memory_range(0x10, 4).unwrap(), 0x10..0x14);
+        assert!(matches!(
+            memory_range(usize::MAX as u64 - 1, 4),
+            Err(GenTlError::InvalidAddress)
+        ));
+    }
+}
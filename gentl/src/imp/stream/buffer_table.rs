@@ -0,0 +1,939 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Lifecycle of buffers announced to a data stream.
+//!
+//! A buffer goes through the states below as the GenTL specification describes.
+//!
+//! ```text
+//!             queue            fill             deliver
+//! Announced -------> Queued -------> Filled ------------> Delivered
+//!     ^                |               |                      |
+//!     +---- discard ---+---------------+         queue        |
+//!                      ^--------------------------------------+
+//! ```
+//!
+//! Queued buffers wait in the input pool, and filled buffers wait in the output queue until
+//! they are delivered to the consumer.
+
+use std::{collections::VecDeque, ptr::NonNull, time::Duration};
+
+use cameleon::payload::{ImageInfo, Payload, PayloadType, PixelFormat};
+
+use crate::{
+    imp::buffer::{BufferPart, BufferParts},
+    GenTlError, GenTlResult,
+};
+
+/// Identifies an announced buffer in a data stream.
+///
+/// A handle is never reused within a data stream, and its raw value is never `0` so that it can
+/// be passed to the consumer as an opaque pointer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct BufferHandle(u64);
+
+impl BufferHandle {
+    pub(crate) fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+
+    pub(crate) fn as_raw(self) -> u64 {
+        self.0
+    }
+}
+
+/// State of an announced buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BufferState {
+    /// The buffer is announced, but not queued.
+    Announced,
+    /// The buffer is in the input pool and waits to be filled.
+    Queued,
+    /// The buffer is filled and waits in the output queue to be delivered.
+    Filled,
+    /// The buffer is delivered to the consumer.
+    Delivered,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BufferEvent {
+    Queue,
+    Fill,
+    Deliver,
+    Discard,
+    Revoke,
+}
+
+impl BufferState {
+    /// Returns the state after `event`, or an error if `event` is illegal in the current state.
+    ///
+    /// [`BufferEvent::Revoke`] returns the current state as the buffer is removed anyway.
+    fn on(self, event: BufferEvent) -> GenTlResult<Self> {
+        use BufferEvent::{Deliver, Discard, Fill, Queue, Revoke};
+        use BufferState::{Announced, Delivered, Filled, Queued};
+
+        match (self, event) {
+            (Announced, Queue) | (Delivered, Queue) => Ok(Queued),
+            (Queued, Fill) => Ok(Filled),
+            (Filled, Deliver) => Ok(Delivered),
+            (Queued, Discard) | (Filled, Discard) => Ok(Announced),
+            (Announced, Revoke) | (Delivered, Revoke) => Ok(self),
+            // The buffer is owned by the data stream.
+            (Queued, _) | (Filled, _) => Err(GenTlError::ResourceInUse),
+            _ => Err(GenTlError::InvalidBuffer),
+        }
+    }
+}
+
+/// How [`BufferTable::flush`] moves buffers, corresponds to `ACQ_QUEUE_TYPE` of GenTL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FlushMode {
+    /// Moves the buffers in the input pool to the output queue without filling them.
+    InputToOutput,
+    /// Discards the buffers in the output queue.
+    OutputDiscard,
+    /// Moves all buffers, even the ones in the output queue, to the input pool.
+    AllToInput,
+    /// Moves the buffers which are neither in the input pool nor in the output queue to the
+    /// input pool.
+    UnqueuedToInput,
+    /// Discards the buffers in the input pool and the output queue.
+    AllDiscard,
+}
+
+/// Information about the data filled in a buffer.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FilledInfo {
+    /// The number of bytes filled in the buffer.
+    pub(crate) size_filled: usize,
+    /// Timestamp of the payload.
+    pub(crate) timestamp: Duration,
+    /// Frame id (block id) of the payload.
+    pub(crate) frame_id: u64,
+    /// `None` if the buffer is flushed to the output queue without being filled.
+    pub(crate) payload_type: Option<PayloadType>,
+    /// Image information of the payload, if it contains an image.
+    pub(crate) image_info: Option<ImageInfo>,
+    /// Parts contained in the buffer.
+    pub(crate) parts: BufferParts,
+    /// `true` if the payload is incomplete or truncated to the buffer.
+    pub(crate) is_incomplete: bool,
+}
+
+impl FilledInfo {
+    /// Information of a buffer which is moved to the output queue without being filled.
+    fn unfilled() -> Self {
+        Self {
+            is_incomplete: true,
+            ..Self::default()
+        }
+    }
+
+    /// Constructs `FilledInfo` with the parts derived from `image_info`.
+    pub(crate) fn new(
+        size_filled: usize,
+        timestamp: Duration,
+        frame_id: u64,
+        payload_type: PayloadType,
+        image_info: Option<ImageInfo>,
+        is_incomplete: bool,
+    ) -> Self {
+        let parts = match &image_info {
+            Some(image_info) => BufferParts::single_image(image_info, size_filled),
+            None => BufferParts::new(Vec::new(), size_filled),
+        };
+        let is_incomplete = is_incomplete || parts.is_incomplete();
+
+        Self {
+            size_filled,
+            timestamp,
+            frame_id,
+            payload_type: Some(payload_type),
+            image_info,
+            parts,
+            is_incomplete,
+        }
+    }
+
+    /// Constructs `FilledInfo` of `payload` copied to the buffer as received.
+    ///
+    /// The parts of a GenDC container are taken from its descriptor, and a container whose
+    /// descriptor can't be decoded is flagged as incomplete.
+    pub(crate) fn from_payload(payload: &Payload, size_filled: usize, is_truncated: bool) -> Self {
+        let mut info = Self::new(
+            size_filled,
+            payload.timestamp(),
+            payload.id(),
+            payload.payload_type(),
+            payload.image_info().cloned(),
+            payload.is_incomplete() || is_truncated,
+        );
+        if payload.payload_type() == PayloadType::GenDc {
+            match payload.gendc() {
+                Ok(container) => {
+                    info.parts = BufferParts::gendc(&container, size_filled);
+                    info.is_incomplete |= info.parts.is_incomplete();
+                }
+                Err(_) => info.is_incomplete = true,
+            }
+        }
+        info
+    }
+}
+
+/// Memory of an announced buffer.
+enum BufferMemory {
+    /// Allocated by the data stream.
+    Allocated(Box<[u8]>),
+    /// Allocated by the consumer.
+    User { ptr: NonNull<u8>, len: usize },
+}
+
+// SAFETY: The consumer guarantees that the memory is valid until the buffer is revoked, and the
+// data stream is the only one that writes to it while it's queued.
+unsafe impl Send for BufferMemory {}
+
+impl BufferMemory {
+    fn as_ptr(&self) -> *const u8 {
+        match self {
+            Self::Allocated(buf) => buf.as_ptr(),
+            Self::User { ptr, .. } => ptr.as_ptr(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Allocated(buf) => buf.len(),
+            Self::User { len, .. } => *len,
+        }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        match self {
+            Self::Allocated(buf) => buf,
+            // SAFETY: See `BufferTable::announce`.
+            Self::User { ptr, len } => unsafe {
+                std::slice::from_raw_parts_mut(ptr.as_ptr(), *len)
+            },
+        }
+    }
+}
+
+/// Snapshot of an announced buffer.
+#[derive(Clone, Debug)]
+pub(crate) struct BufferInfo {
+    /// Base address of the buffer.
+    pub(crate) base: *const u8,
+    /// Size of the buffer in bytes.
+    pub(crate) size: usize,
+    /// User data given when the buffer is announced.
+    pub(crate) user_data: usize,
+    /// `true` if the memory is allocated by the data stream rather than the consumer.
+    pub(crate) is_allocated: bool,
+    pub(crate) state: BufferState,
+    /// `None` if the buffer has never been filled.
+    pub(crate) filled: Option<FilledInfo>,
+}
+
+impl BufferInfo {
+    /// Information about the data filled in the buffer.
+    /// Returns [`GenTlError::NotAvailable`] if the buffer has never been filled.
+    pub(crate) fn filled(&self) -> GenTlResult<&FilledInfo> {
+        self.filled.as_ref().ok_or(GenTlError::NotAvailable)
+    }
+
+    /// Returns the value of the buffer information specified by `cmd`.
+    ///
+    /// Values of the filled data are taken from the leader and the trailer of the payload.
+    /// Returns [`GenTlError::NotAvailable`] if the value is absent in the payload, e.g. the
+    /// width of a chunk payload, or if the buffer has never been filled with a payload.
+    pub(crate) fn query(&self, cmd: BufferInfoCmd) -> GenTlResult<BufferInfoValue> {
+        use BufferInfoValue::{Bool, Ptr, SizeT, UInt64};
+
+        // Data filled from a payload, a buffer flushed without being filled has no payload.
+        let payload = || -> GenTlResult<&FilledInfo> {
+            match self.filled()? {
+                filled if filled.payload_type.is_some() => Ok(filled),
+                _ => Err(GenTlError::NotAvailable),
+            }
+        };
+        // Ok to unwrap because the payload type is checked above.
+        let payload_type = || -> GenTlResult<PayloadType> { Ok(payload()?.payload_type.unwrap()) };
+        let image_info = || {
+            payload()?
+                .image_info
+                .as_ref()
+                .ok_or(GenTlError::NotAvailable)
+        };
+
+        Ok(match cmd {
+            BufferInfoCmd::Base => Ptr(self.base),
+            BufferInfoCmd::Size => SizeT(self.size),
+            BufferInfoCmd::UserPtr => Ptr(self.user_data as *const u8),
+            BufferInfoCmd::IsQueued => Bool(self.state == BufferState::Queued),
+            BufferInfoCmd::NewData => Bool(
+                matches!(self.state, BufferState::Filled | BufferState::Delivered)
+                    && payload().is_ok(),
+            ),
+            BufferInfoCmd::IsIncomplete => Bool(self.filled()?.is_incomplete),
+            BufferInfoCmd::SizeFilled => SizeT(self.filled()?.size_filled),
+            BufferInfoCmd::Timestamp => UInt64(payload()?.timestamp.as_nanos() as u64),
+            BufferInfoCmd::FrameId => UInt64(payload()?.frame_id),
+            BufferInfoCmd::PayloadType => BufferInfoValue::PayloadType(payload_type()?),
+            BufferInfoCmd::ImagePresent => Bool(payload()?.image_info.is_some()),
+            BufferInfoCmd::ContainsChunkData => Bool(matches!(
+                payload_type()?,
+                PayloadType::ImageExtendedChunk | PayloadType::Chunk
+            )),
+            BufferInfoCmd::Width => SizeT(image_info()?.width),
+            BufferInfoCmd::Height => SizeT(image_info()?.height),
+            BufferInfoCmd::XOffset => SizeT(image_info()?.x_offset),
+            BufferInfoCmd::YOffset => SizeT(image_info()?.y_offset),
+            BufferInfoCmd::XPadding => SizeT(image_info()?.x_padding),
+            BufferInfoCmd::PixelFormat => BufferInfoValue::PixelFormat(image_info()?.pixel_format),
+        })
+    }
+
+    /// Returns the number of parts in the buffer, which is zero if the buffer is flushed without
+    /// being filled.
+    pub(crate) fn num_parts(&self) -> GenTlResult<usize> {
+        Ok(self.filled()?.parts.part_count())
+    }
+
+    /// Returns the value of the information specified by `cmd` of the part at `part_index`.
+    ///
+    /// Returns [`GenTlError::NotAvailable`] for the image information of a part which doesn't
+    /// contain pixel data, e.g. the width of a metadata part.
+    pub(crate) fn query_part(
+        &self,
+        part_index: usize,
+        cmd: BufferPartInfoCmd,
+    ) -> GenTlResult<BufferInfoValue> {
+        use BufferInfoValue::{Ptr, SizeT, UInt64};
+
+        let part = self.filled()?.parts.part_info(part_index)?;
+        let image = || -> GenTlResult<&BufferPart> {
+            if part.pixel_format.is_some() {
+                Ok(part)
+            } else {
+                Err(GenTlError::NotAvailable)
+            }
+        };
+
+        Ok(match cmd {
+            BufferPartInfoCmd::Base => Ptr(self.base.wrapping_add(part.offset)),
+            BufferPartInfoCmd::DataSize => SizeT(part.size),
+            BufferPartInfoCmd::DataType => SizeT(part.data_type as usize),
+            BufferPartInfoCmd::DataFormat => match part.pixel_format {
+                Some(format) => BufferInfoValue::PixelFormat(format),
+                None => return Err(GenTlError::NotAvailable),
+            },
+            BufferPartInfoCmd::Width => SizeT(image()?.width),
+            BufferPartInfoCmd::Height | BufferPartInfoCmd::DeliveredImageHeight => {
+                SizeT(image()?.height)
+            }
+            BufferPartInfoCmd::XOffset => SizeT(image()?.x_offset),
+            BufferPartInfoCmd::YOffset => SizeT(image()?.y_offset),
+            BufferPartInfoCmd::XPadding => SizeT(image()?.x_padding),
+            BufferPartInfoCmd::SourceId => UInt64(part.source_id),
+        })
+    }
+}
+
+/// Information of a buffer queried by [`BufferInfo::query`], corresponds to `BUFFER_INFO_CMD`
+/// of GenTL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BufferInfoCmd {
+    /// Base address of the buffer.
+    Base,
+    /// Size of the buffer in bytes.
+    Size,
+    /// User data given when the buffer is announced.
+    UserPtr,
+    /// `true` if the buffer is in the input pool.
+    IsQueued,
+    /// `true` if the buffer contains a payload which is filled since it's queued.
+    NewData,
+    /// `true` if the payload is incomplete or truncated to the buffer.
+    IsIncomplete,
+    /// The number of bytes filled in the buffer.
+    SizeFilled,
+    /// Timestamp of the payload in ns.
+    Timestamp,
+    /// Frame id (block id) of the payload.
+    FrameId,
+    /// Payload type of the payload.
+    PayloadType,
+    /// `true` if the payload contains an image.
+    ImagePresent,
+    /// `true` if the payload contains chunk data.
+    ContainsChunkData,
+    /// Width of the image in pixels.
+    Width,
+    /// Height of the image in pixels.
+    Height,
+    /// X offset of the image in pixels.
+    XOffset,
+    /// Y offset of the image in pixels.
+    YOffset,
+    /// Number of padding bytes at the end of each row of the image.
+    XPadding,
+    /// Pixel format of the image.
+    PixelFormat,
+}
+
+/// Information of a buffer part queried by [`BufferInfo::query_part`], corresponds to
+/// `BUFFER_PART_INFO_CMD` of GenTL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BufferPartInfoCmd {
+    /// Base address of the part data.
+    Base,
+    /// Size of the part data in bytes.
+    DataSize,
+    /// Data type of the part, see [`PartDataType`](crate::imp::buffer::PartDataType).
+    DataType,
+    /// Pixel format of the part.
+    DataFormat,
+    /// Width of the part in pixels.
+    Width,
+    /// Height of the part in pixels.
+    Height,
+    /// X offset of the part in pixels.
+    XOffset,
+    /// Y offset of the part in pixels.
+    YOffset,
+    /// Number of padding bytes at the end of each row of the part.
+    XPadding,
+    /// Source ID of the part.
+    SourceId,
+    /// Height of the part delivered in the buffer in pixels.
+    DeliveredImageHeight,
+}
+
+/// Value of [`BufferInfoCmd`], each variant corresponds to `INFO_DATATYPE` of GenTL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BufferInfoValue {
+    Ptr(*const u8),
+    SizeT(usize),
+    UInt64(u64),
+    Bool(bool),
+    PayloadType(PayloadType),
+    PixelFormat(PixelFormat),
+}
+
+/// A buffer announced to a data stream.
+pub(crate) struct AnnouncedBuffer {
+    memory: BufferMemory,
+    user_data: usize,
+    state: BufferState,
+    filled: Option<FilledInfo>,
+}
+
+impl AnnouncedBuffer {
+    /// Base address of the buffer.
+    pub(crate) fn base(&self) -> *const u8 {
+        self.memory.as_ptr()
+    }
+
+    /// Size of the buffer in bytes.
+    pub(crate) fn size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// User data given when the buffer is announced.
+    pub(crate) fn user_data(&self) -> usize {
+        self.user_data
+    }
+
+    pub(crate) fn info(&self) -> BufferInfo {
+        BufferInfo {
+            base: self.base(),
+            size: self.size(),
+            user_data: self.user_data,
+            is_allocated: matches!(self.memory, BufferMemory::Allocated(..)),
+            state: self.state,
+            filled: self.filled.clone(),
+        }
+    }
+
+    fn transit(&mut self, event: BufferEvent) -> GenTlResult<()> {
+        self.state = self.state.on(event)?;
+        Ok(())
+    }
+}
+
+/// Announced buffers of a data stream and their input pool and output queue.
+#[derive(Default)]
+pub(crate) struct BufferTable {
+    /// Announced buffers in the order of announcement.
+    buffers: Vec<(BufferHandle, AnnouncedBuffer)>,
+    input: VecDeque<BufferHandle>,
+    output: VecDeque<BufferHandle>,
+    last_handle: u64,
+}
+
+impl BufferTable {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allocates a zero-initialized buffer of `size` bytes and announces it.
+    pub(crate) fn alloc_and_announce(
+        &mut self,
+        size: usize,
+        user_data: usize,
+    ) -> GenTlResult<BufferHandle> {
+        if size == 0 {
+            return Err(GenTlError::InvalidParameter);
+        }
+        let memory = BufferMemory::Allocated(vec![0; size].into_boxed_slice());
+        Ok(self.insert(memory, user_data))
+    }
+
+    /// Announces a buffer allocated by the consumer.
+    ///
+    /// # Safety
+    /// `ptr` must be valid for writes of `size` bytes until the buffer is revoked.
+    pub(crate) unsafe fn announce(
+        &mut self,
+        ptr: *mut u8,
+        size: usize,
+        user_data: usize,
+    ) -> GenTlResult<BufferHandle> {
+        let ptr = NonNull::new(ptr).ok_or(GenTlError::InvalidParameter)?;
+        if size == 0 {
+            return Err(GenTlError::InvalidParameter);
+        }
+        let memory = BufferMemory::User { ptr, len: size };
+        Ok(self.insert(memory, user_data))
+    }
+
+    /// Revokes the buffer and returns its user data.
+    pub(crate) fn revoke(&mut self, handle: BufferHandle) -> GenTlResult<usize> {
+        let index = self.index(handle)?;
+        self.buffers[index].1.transit(BufferEvent::Revoke)?;
+        let (_, buffer) = self.buffers.remove(index);
+        Ok(buffer.user_data)
+    }
+
+    /// Queues the buffer to the input pool.
+    pub(crate) fn queue(&mut self, handle: BufferHandle) -> GenTlResult<()> {
+        self.get_mut(handle)?.transit(BufferEvent::Queue)?;
+        self.input.push_back(handle);
+        Ok(())
+    }
+
+    /// Fills the first buffer in the input pool with `fill` and moves it to the output queue.
+    ///
+    /// Returns `None` without calling `fill` if the input pool is empty.
+    pub(crate) fn fill_next<F>(&mut self, fill: F) -> Option<BufferHandle>
+    where
+        F: FnOnce(&mut [u8]) -> FilledInfo,
+    {
+        let handle = self.input.pop_front()?;
+        // Ok to unwrap because buffers in the input pool are always announced and queued.
+        let buffer = self.get_mut(handle).unwrap();
+        buffer.transit(BufferEvent::Fill).unwrap();
+        buffer.filled = Some(fill(buffer.memory.as_mut_slice()));
+        self.output.push_back(handle);
+        Some(handle)
+    }
+
+    /// Delivers the buffer in the output queue regardless of its position.
+    pub(crate) fn deliver(&mut self, handle: BufferHandle) -> GenTlResult<()> {
+        self.get_mut(handle)?.transit(BufferEvent::Deliver)?;
+        self.output.retain(|h| *h != handle);
+        Ok(())
+    }
+
+    /// Delivers the first buffer in the output queue.
+    pub(crate) fn deliver_next(&mut self) -> Option<BufferHandle> {
+        let handle = self.output.pop_front()?;
+        // Ok to unwrap because buffers in the output queue are always announced and filled.
+        self.get_mut(handle)
+            .unwrap()
+            .transit(BufferEvent::Deliver)
+            .unwrap();
+        Some(handle)
+    }
+
+    /// Moves buffers according to `mode` and returns the buffers moved to the output queue.
+    pub(crate) fn flush(&mut self, mode: FlushMode) -> Vec<BufferHandle> {
+        let mut moved = vec![];
+        match mode {
+            FlushMode::InputToOutput => {
+                while let Some(handle) = self.input.pop_front() {
+                    let buffer = self.get_mut(handle).unwrap();
+                    buffer.transit(BufferEvent::Fill).unwrap();
+                    buffer.filled = Some(FilledInfo::unfilled());
+                    self.output.push_back(handle);
+                    moved.push(handle);
+                }
+            }
+            FlushMode::OutputDiscard => self.discard_output(),
+            FlushMode::AllToInput => {
+                self.discard_output();
+                self.queue_unqueued();
+            }
+            FlushMode::UnqueuedToInput => self.queue_unqueued(),
+            FlushMode::AllDiscard => {
+                self.discard_output();
+                while let Some(handle) = self.input.pop_front() {
+                    let buffer = self.get_mut(handle).unwrap();
+                    buffer.transit(BufferEvent::Discard).unwrap();
+                }
+            }
+        }
+        moved
+    }
+
+    /// Discards all buffers in the input pool and the output queue, then revokes all buffers.
+    pub(crate) fn clear(&mut self) {
+        *self = Self {
+            last_handle: self.last_handle,
+            ..Self::default()
+        };
+    }
+
+    pub(crate) fn get(&self, handle: BufferHandle) -> GenTlResult<&AnnouncedBuffer> {
+        let index = self.index(handle)?;
+        Ok(&self.buffers[index].1)
+    }
+
+    /// Returns the handle of the `index`th buffer in the order of announcement.
+    pub(crate) fn handle_at(&self, index: usize) -> Option<BufferHandle> {
+        self.buffers.get(index).map(|(handle, _)| *handle)
+    }
+
+    /// The number of announced buffers.
+    pub(crate) fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    /// The number of buffers in the input pool.
+    pub(crate) fn num_queued(&self) -> usize {
+        self.input.len()
+    }
+
+    /// The number of buffers in the output queue.
+    pub(crate) fn num_awaiting_delivery(&self) -> usize {
+        self.output.len()
+    }
+
+    fn insert(&mut self, memory: BufferMemory, user_data: usize) -> BufferHandle {
+        self.last_handle += 1;
+        let handle = BufferHandle(self.last_handle);
+        let buffer = AnnouncedBuffer {
+            memory,
+            user_data,
+            state: BufferState::Announced,
+            filled: None,
+        };
+        self.buffers.push((handle, buffer));
+        handle
+    }
+
+    fn discard_output(&mut self) {
+        while let Some(handle) = self.output.pop_front() {
+            let buffer = self.get_mut(handle).unwrap();
+            buffer.transit(BufferEvent::Discard).unwrap();
+        }
+    }
+
+    fn queue_unqueued(&mut self) {
+        for (handle, buffer) in &mut self.buffers {
+            if buffer.transit(BufferEvent::Queue).is_ok() {
+                self.input.push_back(*handle);
+            }
+        }
+    }
+
+    fn index(&self, handle: BufferHandle) -> GenTlResult<usize> {
+        self.buffers
+            .iter()
+            .position(|(h, _)| *h == handle)
+            .ok_or(GenTlError::InvalidHandle)
+    }
+
+    fn get_mut(&mut self, handle: BufferHandle) -> GenTlResult<&mut AnnouncedBuffer> {
+        let index = self.index(handle)?;
+        Ok(&mut self.buffers[index].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fill_with(value: u8) -> impl FnOnce(&mut [u8]) -> FilledInfo {
+        move |buf| {
+            buf.iter_mut().for_each(|b| *b = value);
+            FilledInfo::new(
+                buf.len(),
+                Duration::from_nanos(value.into()),
+                value.into(),
+                PayloadType::Chunk,
+                None,
+                false,
+            )
+        }
+    }
+
+    fn info(table: &BufferTable, handle: BufferHandle) -> BufferInfo {
+        table.get(handle).unwrap().info()
+    }
+
+    fn state(table: &BufferTable, handle: BufferHandle) -> BufferState {
+        info(table, handle).state
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut table = BufferTable::new();
+        let handle = table.alloc_and_announce(4, 42).unwrap();
+        assert_eq!(state(&table, handle), BufferState::Announced);
+        assert!(info(&table, handle).filled().is_err());
+        assert!(table.fill_next(fill_with(1)).is_none());
+
+        table.queue(handle).unwrap();
+        assert_eq!(state(&table, handle), BufferState::Queued);
+
+        assert_eq!(table.fill_next(fill_with(1)), Some(handle));
+        assert_eq!(state(&table, handle), BufferState::Filled);
+
+        assert_eq!(table.deliver_next(), Some(handle));
+        assert_eq!(table.deliver_next(), None);
+        let buffer = info(&table, handle);
+        assert_eq!(buffer.state, BufferState::Delivered);
+        assert_eq!(buffer.size, 4);
+        assert_eq!(buffer.user_data, 42);
+        let filled = buffer.filled().unwrap();
+        assert_eq!(filled.size_filled, 4);
+        assert_eq!(filled.frame_id, 1);
+        assert!(!filled.is_incomplete);
+        let data = unsafe { std::slice::from_raw_parts(buffer.base, buffer.size) };
+        assert_eq!(data, &[1; 4]);
+
+        // A delivered buffer can be queued again.
+        table.queue(handle).unwrap();
+        assert_eq!(table.fill_next(fill_with(2)), Some(handle));
+        assert_eq!(table.deliver_next(), Some(handle));
+        assert_eq!(info(&table, handle).filled().unwrap().frame_id, 2);
+
+        // A buffer can be delivered out of order, but only once.
+        table.queue(handle).unwrap();
+        table.fill_next(fill_with(3)).unwrap();
+        table.deliver(handle).unwrap();
+        assert_eq!(table.num_awaiting_delivery(), 0);
+        assert!(matches!(
+            table.deliver(handle),
+            Err(GenTlError::InvalidBuffer)
+        ));
+
+        assert_eq!(table.revoke(handle).unwrap(), 42);
+        assert!(table.is_empty());
+        assert!(matches!(table.get(handle), Err(GenTlError::InvalidHandle)));
+    }
+
+    #[test]
+    fn test_query() {
+        let mut table = BufferTable::new();
+        let handle = table.alloc_and_announce(64 * 32, 42).unwrap();
+        let query = |table: &BufferTable, cmd| info(table, handle).query(cmd);
+
+        assert_eq!(
+            query(&table, BufferInfoCmd::Size).unwrap(),
+            BufferInfoValue::SizeT(64 * 32)
+        );
+        assert_eq!(
+            query(&table, BufferInfoCmd::UserPtr).unwrap(),
+            BufferInfoValue::Ptr(42 as *const u8)
+        );
+        assert!(matches!(
+            query(&table, BufferInfoCmd::Timestamp),
+            Err(GenTlError::NotAvailable)
+        ));
+
+        let image_info = ImageInfo {
+            width: 64,
+            height: 32,
+            x_offset: 0,
+            y_offset: 0,
+            pixel_format: PixelFormat::Mono8,
+            image_size: 64 * 32,
+            x_padding: 0,
+        };
+        table.queue(handle).unwrap();
+        assert_eq!(
+            query(&table, BufferInfoCmd::IsQueued).unwrap(),
+            BufferInfoValue::Bool(true)
+        );
+        table
+            .fill_next(|buf| {
+                FilledInfo::new(
+                    buf.len(),
+                    Duration::from_nanos(10),
+                    1,
+                    PayloadType::Image,
+                    Some(image_info),
+                    false,
+                )
+            })
+            .unwrap();
+        table.deliver_next().unwrap();
+
+        let expected = [
+            (BufferInfoCmd::NewData, BufferInfoValue::Bool(true)),
+            (BufferInfoCmd::Timestamp, BufferInfoValue::UInt64(10)),
+            (BufferInfoCmd::Width, BufferInfoValue::SizeT(64)),
+            (BufferInfoCmd::Height, BufferInfoValue::SizeT(32)),
+            (
+                BufferInfoCmd::PixelFormat,
+                BufferInfoValue::PixelFormat(PixelFormat::Mono8),
+            ),
+            (
+                BufferInfoCmd::ContainsChunkData,
+                BufferInfoValue::Bool(false),
+            ),
+        ];
+        for (cmd, value) in expected {
+            assert_eq!(query(&table, cmd).unwrap(), value);
+        }
+
+        // Image information is absent in a chunk payload.
+        table.queue(handle).unwrap();
+        table.fill_next(fill_with(1)).unwrap();
+        assert!(matches!(
+            query(&table, BufferInfoCmd::Width),
+            Err(GenTlError::NotAvailable)
+        ));
+        assert_eq!(
+            query(&table, BufferInfoCmd::ImagePresent).unwrap(),
+            BufferInfoValue::Bool(false)
+        );
+
+        // A flushed buffer has no payload.
+        table.deliver_next().unwrap();
+        table.queue(handle).unwrap();
+        table.flush(FlushMode::InputToOutput);
+        assert_eq!(
+            query(&table, BufferInfoCmd::NewData).unwrap(),
+            BufferInfoValue::Bool(false)
+        );
+        assert!(matches!(
+            query(&table, BufferInfoCmd::FrameId),
+            Err(GenTlError::NotAvailable)
+        ));
+    }
+
+    #[test]
+    fn test_illegal_transition() {
+        let mut table = BufferTable::new();
+        let handle = table.alloc_and_announce(4, 0).unwrap();
+
+        table.queue(handle).unwrap();
+        assert!(matches!(
+            table.queue(handle),
+            Err(GenTlError::ResourceInUse)
+        ));
+        assert!(matches!(
+            table.revoke(handle),
+            Err(GenTlError::ResourceInUse)
+        ));
+
+        table.fill_next(fill_with(1)).unwrap();
+        assert!(matches!(
+            table.queue(handle),
+            Err(GenTlError::ResourceInUse)
+        ));
+        assert!(matches!(
+            table.revoke(handle),
+            Err(GenTlError::ResourceInUse)
+        ));
+        assert_eq!(table.num_queued(), 0);
+        assert_eq!(table.num_awaiting_delivery(), 1);
+
+        assert!(matches!(
+            table.queue(BufferHandle::from_raw(0)),
+            Err(GenTlError::InvalidHandle)
+        ));
+        assert!(matches!(
+            table.alloc_and_announce(0, 0),
+            Err(GenTlError::InvalidParameter)
+        ));
+        assert!(matches!(
+            unsafe { table.announce(std::ptr::null_mut(), 4, 0) },
+            Err(GenTlError::InvalidParameter)
+        ));
+    }
+
+    #[test]
+    fn test_user_buffer() {
+        let mut memory = vec![0_u8; 8];
+        let mut table = BufferTable::new();
+        let handle = unsafe { table.announce(memory.as_mut_ptr(), memory.len(), 7) }.unwrap();
+        assert_eq!(info(&table, handle).base, memory.as_ptr());
+        assert!(!info(&table, handle).is_allocated);
+        assert_eq!(table.handle_at(0), Some(handle));
+        assert_eq!(table.handle_at(1), None);
+
+        table.queue(handle).unwrap();
+        table.fill_next(fill_with(3)).unwrap();
+        table.deliver_next().unwrap();
+        assert_eq!(table.revoke(handle).unwrap(), 7);
+        assert_eq!(memory, vec![3; 8]);
+    }
+
+    #[test]
+    fn test_flush() {
+        let mut table = BufferTable::new();
+        let handles: Vec<_> = (0..4)
+            .map(|_| table.alloc_and_announce(4, 0).unwrap())
+            .collect();
+
+        table.flush(FlushMode::UnqueuedToInput);
+        assert_eq!(table.num_queued(), 4);
+
+        table.fill_next(fill_with(1)).unwrap();
+        table.fill_next(fill_with(2)).unwrap();
+        table.deliver_next().unwrap();
+        // 0: Delivered, 1: Filled, 2 and 3: Queued.
+        assert_eq!(
+            table.flush(FlushMode::InputToOutput),
+            vec![handles[2], handles[3]]
+        );
+        assert_eq!(table.num_queued(), 0);
+        assert_eq!(table.num_awaiting_delivery(), 3);
+        assert_eq!(table.deliver_next(), Some(handles[1]));
+        assert_eq!(table.deliver_next(), Some(handles[2]));
+        let flushed = info(&table, handles[2]).filled.unwrap();
+        assert!(flushed.is_incomplete);
+        assert!(flushed.payload_type.is_none());
+
+        // 0, 1 and 2: Delivered, 3: Filled.
+        table.flush(FlushMode::OutputDiscard);
+        assert_eq!(table.num_awaiting_delivery(), 0);
+        assert_eq!(state(&table, handles[3]), BufferState::Announced);
+
+        table.queue(handles[3]).unwrap();
+        table.fill_next(fill_with(3)).unwrap();
+        table.queue(handles[0]).unwrap();
+        // 0: Queued, 1 and 2: Delivered, 3: Filled.
+        table.flush(FlushMode::AllToInput);
+        assert_eq!(table.num_queued(), 4);
+        assert_eq!(table.num_awaiting_delivery(), 0);
+        assert_eq!(table.fill_next(fill_with(4)), Some(handles[0]));
+
+        table.flush(FlushMode::AllDiscard);
+        assert_eq!(table.num_queued(), 0);
+        assert_eq!(table.num_awaiting_delivery(), 0);
+        for handle in handles {
+            assert_eq!(state(&table, handle), BufferState::Announced);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::time::Duration;
+
+use crate::{imp::event::EventSource, GenTlResult};
+
+pub(crate) mod u3v;
+
+mod buffer_table;
+
+pub(crate) use buffer_table::{
+    BufferHandle, BufferInfo, BufferInfoCmd, BufferInfoValue, BufferPartInfoCmd, BufferTable,
+    FilledInfo, FlushMode,
+};
+
+/// Data stream module, which fires [`crate::imp::event::EventType::NewBuffer`] when a buffer is
+/// filled.
+pub(crate) trait DataStream: EventSource + Send {
+    /// ID of the data stream.
+    fn stream_id(&self) -> &str;
+
+    /// Open the data stream.
+    fn open(&mut self) -> GenTlResult<()>;
+
+    /// Close the data stream.
+    /// Acquisition is stopped and all announced buffers are revoked.
+    fn close(&mut self) -> GenTlResult<()>;
+
+    /// Allocate a buffer of `size` bytes and announce it to the data stream.
+    fn alloc_and_announce_buffer(
+        &mut self,
+        size: usize,
+        user_data: usize,
+    ) -> GenTlResult<BufferHandle>;
+
+    /// Announce a buffer allocated by the consumer to the data stream.
+    ///
+    /// # Safety
+    /// `ptr` must be valid for writes of `size` bytes until the buffer is revoked.
+    unsafe fn announce_buffer(
+        &mut self,
+        ptr: *mut u8,
+        size: usize,
+        user_data: usize,
+    ) -> GenTlResult<BufferHandle>;
+
+    /// Revoke an announced buffer and return its user data.
+    /// Only buffers which are neither queued nor awaiting delivery can be revoked.
+    fn revoke_buffer(&mut self, handle: BufferHandle) -> GenTlResult<usize>;
+
+    /// Queue a buffer to the input pool so that it can be filled.
+    fn queue_buffer(&mut self, handle: BufferHandle) -> GenTlResult<()>;
+
+    /// Move buffers between the input pool and the output queue, see [`FlushMode`].
+    fn flush_queue(&mut self, mode: FlushMode) -> GenTlResult<()>;
+
+    /// Start acquisition.
+    /// The acquisition stops by itself after `num_to_acquire` buffers are filled if it's given.
+    fn start_acquisition(&mut self, num_to_acquire: Option<u64>) -> GenTlResult<()>;
+
+    /// Stop acquisition.
+    fn stop_acquisition(&mut self) -> GenTlResult<()>;
+
+    /// Returns `true` if acquisition is running.
+    fn is_grabbing(&self) -> bool;
+
+    /// Wait until a filled buffer is available and deliver it to the consumer.
+    /// Return [`crate::GenTlError::Timeout`] if no buffer is filled within `timeout`.
+    fn wait_filled_buffer(&mut self, timeout: Duration) -> GenTlResult<BufferHandle>;
+
+    /// Mark a filled buffer as delivered.
+    /// Buffers popped from the new buffer event must be delivered with this method.
+    fn deliver_buffer(&mut self, handle: BufferHandle) -> GenTlResult<()>;
+
+    /// Returns the handle of the `index`th announced buffer in the order of announcement.
+    fn buffer_handle(&self, index: usize) -> GenTlResult<BufferHandle>;
+
+    /// Returns the snapshot of the buffer specified by `handle`.
+    fn buffer_info(&self, handle: BufferHandle) -> GenTlResult<BufferInfo>;
+
+    /// Returns the value of the data stream information specified by `cmd`.
+    fn stream_info(&self, cmd: StreamInfoCmd) -> GenTlResult<StreamInfoValue>;
+}
+
+/// Information of a data stream queried by [`DataStream::stream_info`], corresponds to
+/// `STREAM_INFO_CMD` of GenTL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum StreamInfoCmd {
+    /// ID of the data stream.
+    Id,
+    /// The number of buffers filled since the acquisition is started.
+    NumDelivered,
+    /// The number of payloads lost because no buffer was queued since the stream is opened.
+    NumUnderrun,
+    /// The number of announced buffers.
+    NumAnnounced,
+    /// The number of buffers in the input pool.
+    NumQueued,
+    /// The number of buffers in the output queue.
+    NumAwaitDelivery,
+    /// `true` if acquisition is running.
+    IsGrabbing,
+    /// Size of the payload the device sends in bytes.
+    PayloadSize,
+}
+
+/// Value of [`StreamInfoCmd`], each variant corresponds to `INFO_DATATYPE` of GenTL.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum StreamInfoValue {
+    String(String),
+    SizeT(usize),
+    UInt64(u64),
+    Bool(bool),
+}
@@ -0,0 +1,476 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use cameleon::{
+    metrics::{self, MetricSink, MetricSource},
+    payload::{CopyLayout, FrameQueue, OverflowPolicy, Payload, PayloadReceiver},
+    StreamError,
+};
+
+use crate::{
+    imp::{
+        device::u3v::Camera,
+        event::{EventData, EventRegistry, EventSource, EventType},
+    },
+    GenTlError, GenTlResult,
+};
+
+use super::{
+    BufferHandle, BufferInfo, BufferTable, DataStream, FilledInfo, FlushMode, StreamInfoCmd,
+    StreamInfoValue,
+};
+
+/// ID of the data stream on the stream channel, U3V devices have at most one stream channel.
+pub(crate) const STREAM_ID: &str = "StreamChannel0";
+
+/// Events fired by the data stream module.
+const SUPPORTED_EVENTS: &[EventType] = &[EventType::Error, EventType::NewBuffer];
+
+/// Interval to check whether the fill thread should exit.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Camera which streams payloads to [`U3VDataStreamModule`].
+///
+/// It's implemented by cameras over real devices and, with `emulator` feature, over emulated
+/// devices.
+pub(crate) trait StreamCamera: Send + 'static {
+    /// Starts streaming, the returned receiver holds up to `capacity` payloads.
+    fn start_streaming(&mut self, capacity: usize) -> GenTlResult<PayloadReceiver>;
+
+    /// Stops streaming started by [`StreamCamera::start_streaming`].
+    fn stop_streaming(&mut self) -> GenTlResult<()>;
+
+    /// The number of payloads dropped by the streaming loop since the camera is opened.
+    fn dropped_payloads(&self) -> u64;
+
+    /// Payload size required by the device, which is read from SIRM so that it's available
+    /// before the acquisition is started.
+    fn payload_size(&self) -> GenTlResult<u64>;
+}
+
+impl StreamCamera for Camera {
+    fn start_streaming(&mut self, capacity: usize) -> GenTlResult<PayloadReceiver> {
+        Ok(Camera::start_streaming(self, capacity)?)
+    }
+
+    fn stop_streaming(&mut self) -> GenTlResult<()> {
+        Ok(Camera::stop_streaming(self)?)
+    }
+
+    fn dropped_payloads(&self) -> u64 {
+        self.strm.statistics().dropped_payloads
+    }
+
+    fn payload_size(&self) -> GenTlResult<u64> {
+        let mut ctrl = self.ctrl.clone();
+        let sirm = ctrl
+            .abrm()?
+            .sbrm(&mut ctrl)?
+            .sirm()
+            .ok_or(GenTlError::NotAvailable)?;
+        Ok(sirm.required_payload_size(&mut ctrl)?)
+    }
+}
+
+/// Data stream module which receives payloads from the stream channel of a U3V device.
+///
+/// Payloads received by [`cameleon::payload::PayloadReceiver`] are buffered in [`FrameQueue`]
+/// and copied into the queued buffers by a fill thread, so the streaming loop never waits for
+/// the consumer.
+///
+/// The numbers of delivered buffers and underruns are registered as metrics of the stream, see
+/// [`cameleon::metrics`]. As their stream infos, the counters are reset when the stream is opened
+/// and the delivered buffers also when the acquisition is started.
+pub(crate) struct U3VDataStreamModule<C: StreamCamera = Camera> {
+    camera: Arc<Mutex<C>>,
+    shared: Arc<Shared>,
+    /// `Some` while acquisition is started.
+    acquisition: Option<Acquisition>,
+    is_opened: bool,
+}
+
+/// State shared with the fill thread.
+struct Shared {
+    buffers: Mutex<BufferTable>,
+    /// Notified when a buffer is moved to the output queue or the fill thread exits.
+    filled: Condvar,
+    events: EventRegistry,
+    /// The number of buffers filled since the acquisition is started.
+    num_delivered: AtomicU64,
+    /// The number of payloads lost because no buffer was queued since the stream is opened,
+    /// excluding payloads dropped by [`FrameQueue`] in the running acquisition.
+    num_underrun: AtomicU64,
+}
+
+impl MetricSource for Shared {
+    fn collect(&self, sink: &mut MetricSink<'_>) {
+        sink.counter(
+            "cameleon_gentl_stream_delivered_buffers_total",
+            self.num_delivered.load(Ordering::Relaxed),
+        );
+        sink.counter(
+            "cameleon_gentl_stream_underruns_total",
+            self.num_underrun.load(Ordering::Relaxed),
+        );
+    }
+}
+
+struct Acquisition {
+    /// Set to stop the fill thread, the fill thread also sets it when it exits by itself.
+    stop: Arc<AtomicBool>,
+    fill_thread: JoinHandle<()>,
+    /// [`StreamCamera::dropped_payloads`] when the acquisition is started.
+    dropped_at_start: u64,
+}
+
+impl<C: StreamCamera> U3VDataStreamModule<C> {
+    /// Creates the module on `camera`, whose serial number is `serial`.
+    pub(crate) fn new(camera: Arc<Mutex<C>>, serial: &str) -> Self {
+        let shared = Arc::new(Shared {
+            buffers: Mutex::new(BufferTable::new()),
+            filled: Condvar::new(),
+            events: EventRegistry::new(SUPPORTED_EVENTS),
+            num_delivered: AtomicU64::new(0),
+            num_underrun: AtomicU64::new(0),
+        });
+        metrics::register(&shared, metrics::stream_labels(serial, 0));
+        Self {
+            camera,
+            shared,
+            acquisition: None,
+            is_opened: false,
+        }
+    }
+
+    pub(crate) fn is_opened(&self) -> bool {
+        self.is_opened
+    }
+
+    fn assert_open(&self) -> GenTlResult<()> {
+        if self.is_opened {
+            Ok(())
+        } else {
+            Err(GenTlError::NotInitialized)
+        }
+    }
+
+    fn buffers(&self) -> MutexGuard<'_, BufferTable> {
+        self.shared.buffers.lock().unwrap()
+    }
+
+    /// The number of payloads dropped by [`FrameQueue`] since the running acquisition is started.
+    fn num_queue_dropped(&self) -> u64 {
+        match &self.acquisition {
+            Some(acquisition) => {
+                let dropped_payloads = self.camera.lock().unwrap().dropped_payloads();
+                dropped_payloads.saturating_sub(acquisition.dropped_at_start)
+            }
+            None => 0,
+        }
+    }
+}
+
+impl Shared {
+    /// Copies `payload` into the first queued buffer, the payload is dropped if no buffer is
+    /// queued.
+    fn fill(&self, payload: &Payload) -> Option<BufferHandle> {
+        let mut buffers = self.buffers.lock().unwrap();
+        let handle = buffers.fill_next(|buf| {
+            let (size_filled, is_truncated) = match payload.copy_to(buf, CopyLayout::AsReceived) {
+                Ok(report) => (report.bytes, report.partial),
+                Err(_) => (0, true),
+            };
+            FilledInfo::from_payload(payload, size_filled, is_truncated)
+        })?;
+        self.num_delivered.fetch_add(1, Ordering::Relaxed);
+        self.notify_new_buffer(&buffers, handle);
+        Some(handle)
+    }
+
+    fn notify_new_buffer(&self, buffers: &BufferTable, handle: BufferHandle) {
+        // Ok to unwrap because the buffer has just been moved to the output queue.
+        let user_data = buffers.get(handle).unwrap().user_data();
+        self.events.notify(EventData::NewBuffer {
+            buffer: handle,
+            user_data,
+        });
+        self.filled.notify_all();
+    }
+
+    /// Fills the queued buffers until `stop` is set, the queue is closed, or `num_to_acquire`
+    /// buffers are filled.
+    fn fill_loop(&self, queue: FrameQueue, mut num_to_acquire: Option<u64>, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            match queue.recv_timeout(STOP_CHECK_INTERVAL) {
+                Ok(payload) => {
+                    let is_filled = self.fill(&payload).is_some();
+                    queue.send_back(payload);
+                    if !is_filled {
+                        self.num_underrun.fetch_add(1, Ordering::Relaxed);
+                    }
+                    if let (true, Some(remaining)) = (is_filled, num_to_acquire.as_mut()) {
+                        *remaining -= 1;
+                        if *remaining == 0 {
+                            break;
+                        }
+                    }
+                }
+                Err(StreamError::Timeout) => {}
+                // The queue is closed only when the streaming loop exits.
+                Err(StreamError::ReceiveError(_)) => break,
+                Err(err @ StreamError::Disconnected) => {
+                    self.events.notify(EventData::Error(err.into()));
+                    break;
+                }
+                // Errors of a single payload don't stop the stream.
+                Err(err) => self.events.notify(EventData::Error(err.into())),
+            }
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        // Take the lock so that a waiter never misses the notification between its check of
+        // `stop` and its wait.
+        let _buffers = self.buffers.lock().unwrap();
+        self.filled.notify_all();
+    }
+}
+
+impl<C: StreamCamera> EventSource for U3VDataStreamModule<C> {
+    fn events(&self) -> &EventRegistry {
+        &self.shared.events
+    }
+}
+
+impl<C: StreamCamera> DataStream for U3VDataStreamModule<C> {
+    fn stream_id(&self) -> &str {
+        STREAM_ID
+    }
+
+    fn open(&mut self) -> GenTlResult<()> {
+        if self.is_opened {
+            return Err(GenTlError::ResourceInUse);
+        }
+
+        self.shared.num_underrun.store(0, Ordering::Relaxed);
+        self.is_opened = true;
+        Ok(())
+    }
+
+    fn close(&mut self) -> GenTlResult<()> {
+        if !self.is_opened {
+            return Ok(());
+        }
+
+        if self.acquisition.is_some() {
+            self.stop_acquisition()?;
+        }
+        self.shared.events.unregister_all();
+        self.buffers().clear();
+        self.is_opened = false;
+        Ok(())
+    }
+
+    fn alloc_and_announce_buffer(
+        &mut self,
+        size: usize,
+        user_data: usize,
+    ) -> GenTlResult<BufferHandle> {
+        self.assert_open()?;
+
+        self.buffers().alloc_and_announce(size, user_data)
+    }
+
+    unsafe fn announce_buffer(
+        &mut self,
+        ptr: *mut u8,
+        size: usize,
+        user_data: usize,
+    ) -> GenTlResult<BufferHandle> {
+        self.assert_open()?;
+
+        self.buffers().announce(ptr, size, user_data)
+    }
+
+    fn revoke_buffer(&mut self, handle: BufferHandle) -> GenTlResult<usize> {
+        self.assert_open()?;
+
+        self.buffers().revoke(handle)
+    }
+
+    fn queue_buffer(&mut self, handle: BufferHandle) -> GenTlResult<()> {
+        self.assert_open()?;
+
+        self.buffers().queue(handle)
+    }
+
+    fn flush_queue(&mut self, mode: FlushMode) -> GenTlResult<()> {
+        self.assert_open()?;
+
+        let mut buffers = self.buffers();
+        let moved = buffers.flush(mode);
+        match mode {
+            FlushMode::InputToOutput => {
+                for handle in moved {
+                    self.shared.notify_new_buffer(&buffers, handle);
+                }
+            }
+            FlushMode::OutputDiscard | FlushMode::AllToInput | FlushMode::AllDiscard => {
+                self.shared.events.flush(EventType::NewBuffer);
+            }
+            FlushMode::UnqueuedToInput => {}
+        }
+        Ok(())
+    }
+
+    fn start_acquisition(&mut self, num_to_acquire: Option<u64>) -> GenTlResult<()> {
+        self.assert_open()?;
+
+        if self.is_grabbing() {
+            return Err(GenTlError::ResourceInUse);
+        }
+        let capacity = self.buffers().len();
+        if capacity == 0 || num_to_acquire == Some(0) {
+            return Err(GenTlError::InvalidBuffer);
+        }
+        // Acquisition which has stopped by itself is not stopped on the device yet.
+        if self.acquisition.is_some() {
+            self.stop_acquisition()?;
+        }
+
+        // The queue holds as many payloads as the announced buffers so that every buffer
+        // requeued by the consumer can be filled without waiting for the device.
+        let (receiver, dropped_at_start) = {
+            let mut camera = self.camera.lock().unwrap();
+            let receiver = camera.start_streaming(capacity)?;
+            (receiver, camera.dropped_payloads())
+        };
+        let queue = FrameQueue::new(receiver, capacity, OverflowPolicy::DropOldest);
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let fill_thread = {
+            let shared = self.shared.clone();
+            let stop = stop.clone();
+            thread::Builder::new()
+                .name("cameleon-gentl-stream".into())
+                .spawn(move || shared.fill_loop(queue, num_to_acquire, &stop))
+                .map_err(|err| GenTlError::Error(err.to_string()))?
+        };
+        self.shared.num_delivered.store(0, Ordering::Relaxed);
+        self.acquisition = Some(Acquisition {
+            stop,
+            fill_thread,
+            dropped_at_start,
+        });
+        Ok(())
+    }
+
+    fn stop_acquisition(&mut self) -> GenTlResult<()> {
+        self.assert_open()?;
+
+        let num_queue_dropped = self.num_queue_dropped();
+        let acquisition = self.acquisition.take().ok_or(GenTlError::NotInitialized)?;
+        acquisition.stop.store(true, Ordering::Relaxed);
+        // The fill thread exits within `STOP_CHECK_INTERVAL`.
+        if acquisition.fill_thread.join().is_err() {
+            return Err(GenTlError::Error("fill thread panicked".into()));
+        }
+        self.shared
+            .num_underrun
+            .fetch_add(num_queue_dropped, Ordering::Relaxed);
+        self.camera.lock().unwrap().stop_streaming()?;
+        Ok(())
+    }
+
+    fn is_grabbing(&self) -> bool {
+        match &self.acquisition {
+            Some(acquisition) => !acquisition.stop.load(Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    fn wait_filled_buffer(&mut self, timeout: Duration) -> GenTlResult<BufferHandle> {
+        self.assert_open()?;
+
+        let deadline = Instant::now() + timeout;
+        let mut buffers = self.buffers();
+        loop {
+            if let Some(handle) = buffers.deliver_next() {
+                return Ok(handle);
+            }
+            if !self.is_grabbing() {
+                return Err(GenTlError::NotInitialized);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(GenTlError::Timeout);
+            }
+            buffers = self
+                .shared
+                .filled
+                .wait_timeout(buffers, deadline - now)
+                .unwrap()
+                .0;
+        }
+    }
+
+    fn deliver_buffer(&mut self, handle: BufferHandle) -> GenTlResult<()> {
+        self.assert_open()?;
+
+        self.buffers().deliver(handle)
+    }
+
+    fn buffer_handle(&self, index: usize) -> GenTlResult<BufferHandle> {
+        self.assert_open()?;
+
+        self.buffers()
+            .handle_at(index)
+            .ok_or(GenTlError::InvalidIndex)
+    }
+
+    fn buffer_info(&self, handle: BufferHandle) -> GenTlResult<BufferInfo> {
+        self.assert_open()?;
+
+        self.buffers().get(handle).map(|buffer| buffer.info())
+    }
+
+    fn stream_info(&self, cmd: StreamInfoCmd) -> GenTlResult<StreamInfoValue> {
+        use StreamInfoValue::{Bool, SizeT, UInt64};
+
+        self.assert_open()?;
+
+        Ok(match cmd {
+            StreamInfoCmd::Id => StreamInfoValue::String(STREAM_ID.into()),
+            StreamInfoCmd::NumDelivered => {
+                UInt64(self.shared.num_delivered.load(Ordering::Relaxed))
+            }
+            StreamInfoCmd::NumUnderrun => {
+                let num_underrun = self.shared.num_underrun.load(Ordering::Relaxed);
+                UInt64(num_underrun + self.num_queue_dropped())
+            }
+            StreamInfoCmd::NumAnnounced => SizeT(self.buffers().len()),
+            StreamInfoCmd::NumQueued => SizeT(self.buffers().num_queued()),
+            StreamInfoCmd::NumAwaitDelivery => SizeT(self.buffers().num_awaiting_delivery()),
+            StreamInfoCmd::IsGrabbing => Bool(self.is_grabbing()),
+            StreamInfoCmd::PayloadSize => {
+                SizeT(self.camera.lock().unwrap().payload_size()? as usize)
+            }
+        })
+    }
+}
+
+impl<C: StreamCamera> Drop for U3VDataStreamModule<C> {
+    fn drop(&mut self) {
+        self.close().ok();
+    }
+}
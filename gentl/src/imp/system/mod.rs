@@ -20,7 +20,9 @@ use crate::{
 };
 
 use super::{
-    port::{Endianness, ModuleType, Port, PortAccess, PortInfo, TlType, XmlInfo, XmlLocation},
+    port::{
+        self, Endianness, ModuleType, Port, PortAccess, PortInfo, TlType, XmlInfo, XmlLocation,
+    },
     CharEncoding, GenTlError,
 };
 
@@ -28,6 +30,19 @@ mod genapi;
 
 const NUM_INTERFACE: usize = 1;
 
+/// Environment variable which enables emulated devices when it's set to `1`, see
+/// [`SystemModule::set_emulation_enabled`].
+#[cfg(feature = "emulator")]
+const EMULATION_ENV: &str = "CAMELEON_GENTL_EMULATION";
+
+/// Environment variable listing fixtures of emulators built when emulated devices are enabled by
+/// [`EMULATION_ENV`], the paths are separated in the same way as `PATH`.
+///
+/// Emulators can't be built by consumers because the device pool of the emulator lives in this
+/// library, so they are built from fixtures instead, see [`cameleon_device::fixture`].
+#[cfg(feature = "emulator")]
+const EMULATOR_FIXTURES_ENV: &str = "CAMELEON_GENTL_EMULATOR_FIXTURES";
+
 pub(crate) struct SystemModule {
     vm: genapi::Memory,
     port_info: PortInfo,
@@ -57,7 +72,7 @@ impl SystemModule {
             port_name: genapi::PORT_NAME.into(),
         };
 
-        let xml_info = XmlInfo {
+        let mut xml_info = XmlInfo {
             location: XmlLocation::RegisterMap {
                 address: genapi::GENAPI_XML_ADDRESS as u64,
                 size: genapi::GENAPI_XML_LENGTH,
@@ -76,6 +91,13 @@ impl SystemModule {
             sha1_hash: None,
             compressed: CompressionType::Uncompressed,
         };
+        let mut vm = genapi::Memory::new();
+        genapi_common::zip_module_xml::<genapi::GenApiXml::Xml, _>(
+            &mut vm,
+            &mut xml_info,
+            &port_info,
+        )
+        .unwrap();
 
         let system_info = SystemInfo {
             id: genapi::TLID.into(),
@@ -96,7 +118,7 @@ impl SystemModule {
         };
 
         let mut system_module = Self {
-            vm: genapi::Memory::new(),
+            vm,
             port_info,
             xml_infos: vec![xml_info],
             system_info,
@@ -107,9 +129,28 @@ impl SystemModule {
         };
 
         system_module.initialize_vm().unwrap();
+        #[cfg(feature = "emulator")]
+        {
+            let enabled = std::env::var(EMULATION_ENV).is_ok_and(|var| var == "1");
+            if enabled {
+                build_fixture_emulators();
+            }
+            system_module.set_emulation_enabled(enabled);
+        }
         system_module
     }
 
+    /// Enables or disables listing emulated devices in the interfaces, which takes effect at the
+    /// next update of their device lists.
+    ///
+    /// Emulated devices are disabled by default unless `CAMELEON_GENTL_EMULATION=1` is set.
+    #[cfg(feature = "emulator")]
+    pub(crate) fn set_emulation_enabled(&mut self, enabled: bool) {
+        for iface in self.interfaces() {
+            iface.lock().unwrap().set_emulation_enabled(enabled);
+        }
+    }
+
     pub(crate) fn open(&mut self) -> GenTlResult<()> {
         if self.is_opened {
             Err(GenTlError::ResourceInUse)
@@ -121,13 +162,26 @@ impl SystemModule {
 
     pub(crate) fn close(&mut self) -> GenTlResult<()> {
         self.assert_open()?;
-        for iface in &mut self.interfaces() {
-            let _res = iface.lock().unwrap().close();
-        }
+        self.shutdown();
 
         Ok(())
     }
 
+    /// Closes all interfaces, which close their devices in turn, then closes the system module.
+    ///
+    /// Errors of each interface are ignored so that a failing module doesn't keep the rest open.
+    /// Calling this function on the closed system module does nothing.
+    pub(crate) fn shutdown(&mut self) {
+        if !self.is_opened {
+            return;
+        }
+
+        for iface in self.interfaces() {
+            let _res = iface.lock().unwrap().close();
+        }
+        self.is_opened = false;
+    }
+
     pub(crate) fn is_opened(&self) -> bool {
         self.is_opened
     }
@@ -264,15 +318,16 @@ impl SystemModule {
 
 impl Port for SystemModule {
     fn read(&self, address: u64, buf: &mut [u8]) -> GenTlResult<usize> {
-        let address = address as usize;
+        let range = port::memory_range(address, buf.len())?;
         let len = buf.len();
-        let data = self.vm.read_raw(address..address + len)?;
+        let data = self.vm.read_raw(range)?;
         buf.copy_from_slice(data);
         Ok(len)
     }
 
     fn write(&mut self, address: u64, data: &[u8]) -> GenTlResult<usize> {
-        self.vm.write_raw(address as usize, &data)?;
+        let range = port::memory_range(address, data.len())?;
+        self.vm.write_raw(range.start, data)?;
 
         self.handle_events()?;
 
@@ -348,6 +403,29 @@ impl MemoryObserver for InterfaceSelectorRegObserver {
     }
 }
 
+/// Builds emulators from the fixtures listed in [`EMULATOR_FIXTURES_ENV`].
+///
+/// The variable is read and the emulators are built only once in the process, even if the library
+/// is initialized again.
+/// Fixtures which can't be loaded are skipped, then their devices are just missing from the
+/// device list.
+#[cfg(feature = "emulator")]
+fn build_fixture_emulators() {
+    static BUILD: std::sync::Once = std::sync::Once::new();
+
+    BUILD.call_once(|| {
+        let paths = match std::env::var_os(EMULATOR_FIXTURES_ENV) {
+            Some(paths) => paths,
+            None => return,
+        };
+        for path in std::env::split_paths(&paths) {
+            if let Ok(builder) = cameleon_device::emulator::EmulatorBuilder::from_fixture(path) {
+                builder.build();
+            }
+        }
+    });
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -370,4 +448,58 @@ mod tests {
             u3v_interface.lock().unwrap().interface_id()
         );
     }
+
+    #[test]
+    fn test_port_address_overflow() {
+        let mut system_module = SystemModule::new();
+        let mut buf = [0; 4];
+        assert!(matches!(
+            system_module.read(u64::MAX - 1, &mut buf),
+            Err(GenTlError::InvalidAddress)
+        ));
+        assert!(matches!(
+            system_module.write(u64::MAX - 1, &buf),
+            Err(GenTlError::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    fn test_zipped_xml() {
+        let system_module = SystemModule::new();
+        let xml_info = &system_module.xml_infos().unwrap()[0];
+        assert!(matches!(xml_info.compressed, CompressionType::Zip));
+        assert!(matches!(
+            xml_info.location,
+            XmlLocation::RegisterMap { size, .. } if size < genapi::GENAPI_XML_LENGTH
+        ));
+
+        let xml = xml_info.fetch_xml(&system_module).unwrap();
+        assert!(xml.contains(genapi::MODEL_NAME));
+    }
+
+    #[test]
+    fn test_port_stacked() {
+        let mut system_module = SystemModule::new();
+        let address = GenApiReg::InterfaceSelector::ADDRESS as u64;
+        let data = vec![0; GenApiReg::InterfaceSelector::LENGTH];
+
+        let mut written_count = 0;
+        system_module
+            .write_stacked(&[(address, &data), (address, &data)], &mut written_count)
+            .unwrap();
+        assert_eq!(written_count, 2);
+
+        let mut buf = vec![0xff; data.len()];
+        let mut invalid = [0; 4];
+        let mut entries: [(u64, &mut [u8]); 2] =
+            [(address, &mut buf), (u64::MAX - 1, &mut invalid)];
+        let mut read_count = 0;
+        assert!(matches!(
+            system_module.read_stacked(&mut entries, &mut read_count),
+            Err(GenTlError::InvalidAddress)
+        ));
+        // The entry before the failed one is read.
+        assert_eq!(read_count, 1);
+        assert_eq!(buf, data);
+    }
 }
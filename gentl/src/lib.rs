@@ -19,6 +19,7 @@ pub mod ffi;
 #[allow(unused)]
 mod imp;
 
+use cameleon::ErrorCode;
 use thiserror::Error;
 
 /// Errors defined in GenTL specification.
@@ -26,7 +27,7 @@ use thiserror::Error;
 #[derive(Error, Debug)]
 pub(crate) enum GenTlError {
     /// Unspecified runtime error.
-    #[error("unspecified runtime error")]
+    #[error("unspecified runtime error: {0}")]
     Error(String),
 
     /// Module or resource not initialized.
@@ -66,7 +67,13 @@ pub(crate) enum GenTlError {
 
     /// Communication error or connection lost.
     #[error("communication error or connection lost: {0}")]
-    Io(Box<dyn std::error::Error>),
+    Io(Box<dyn std::error::Error + Send + Sync>),
+
+    /// Connection to the device is lost.
+    ///
+    /// GenTL has no dedicated code for it, so it's reported as `GC_ERR_IO`.
+    #[error("the device is disconnected")]
+    Disconnected,
 
     /// Operation timed out.
     #[error("operation timed out")]
@@ -121,6 +128,17 @@ pub(crate) enum GenTlError {
     /// The required operation cannot be executed unambiguously in given context.
     #[error("the required operation cannot be executed unambiguously in given")]
     Ambiguous,
+
+    /// An error which has no counterpart in GenTL specification.
+    ///
+    /// It's reported in the custom error range, see [`ffi::GC_ERROR`].
+    #[error("{message} (error code: {code})")]
+    Custom {
+        /// The native code of the error.
+        code: ErrorCode,
+        /// The message of the error.
+        message: String,
+    },
 }
 
 pub(crate) type GenTlResult<T> = std::result::Result<T, GenTlError>;
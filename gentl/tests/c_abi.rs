@@ -0,0 +1,718 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Smoke tests which load the built producer library in the same way as GenTL consumers do.
+//!
+//! Streaming is tested against an emulator built from a fixture, so the library is built with
+//! `emulator` feature by [`common::library_path`].
+
+#![allow(non_snake_case)]
+
+mod common;
+
+use std::{ffi::c_void, sync::Mutex};
+
+use libloading::{Library, Symbol};
+
+use common::library_path;
+
+type Handle = *mut c_void;
+
+const GC_ERR_SUCCESS: i32 = 0;
+const GC_ERR_NOT_INITIALIZED: i32 = -1002;
+const GC_ERR_ACCESS_DENIED: i32 = -1005;
+const GC_ERR_INVALID_HANDLE: i32 = -1006;
+const GC_ERR_TIMEOUT: i32 = -1011;
+const GC_ERR_NOT_AVAILABLE: i32 = -1014;
+
+const GENTL_INFINITE: u64 = u64::MAX;
+
+const INTERFACE_INFO_TLTYPE: i32 = 2;
+const DEVICE_INFO_ID: i32 = 0;
+const DEVICE_INFO_TLTYPE: i32 = 3;
+const DEVICE_INFO_ACCESS_STATUS: i32 = 5;
+const DEVICE_INFO_USER_DEFINED_NAME: i32 = 6;
+const DEVICE_INFO_SERIAL_NUMBER: i32 = 7;
+const DEVICE_INFO_TIMESTAMP_FREQUENCY: i32 = 9;
+const DEVICE_INFO_LINK_SPEED: i32 = 1001;
+const DEVICE_ACCESS_READONLY: i32 = 2;
+const DEVICE_ACCESS_CONTROL: i32 = 3;
+const DEVICE_ACCESS_EXCLUSIVE: i32 = 4;
+const DEVICE_ACCESS_STATUS_OPEN_READWRITE: i32 = 5;
+const DEVICE_ACCESS_STATUS_OPEN_READONLY: i32 = 6;
+const STREAM_INFO_NUM_DELIVERED: i32 = 1;
+const STREAM_INFO_PAYLOAD_SIZE: i32 = 7;
+const STREAM_INFO_IS_GRABBING: i32 = 8;
+const BUFFER_INFO_BASE: i32 = 0;
+const BUFFER_INFO_SIZE_FILLED: i32 = 9;
+const BUFFER_INFO_WIDTH: i32 = 10;
+const BUFFER_INFO_HEIGHT: i32 = 11;
+const BUFFER_INFO_FRAMEID: i32 = 16;
+const ACQ_QUEUE_ALL_DISCARD: i32 = 4;
+const EVENT_NEW_BUFFER: i32 = 1;
+const EVENT_MODULE: i32 = 5;
+const EVENT_NUM_IN_QUEUE: i32 = 1;
+const EVENT_NUM_FIRED: i32 = 2;
+const EVENT_SIZE_MAX: i32 = 3;
+
+/// The library is initialized once at a time in the process, so tests are serialized.
+static LIB_LOCK: Mutex<()> = Mutex::new(());
+
+/// Data of `EVENT_NEW_BUFFER`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct NewBufferData {
+    buffer: Handle,
+    user_pointer: *mut c_void,
+}
+
+fn copy_string(mut f: impl FnMut(*mut libc::c_char, *mut usize) -> i32) -> String {
+    let mut size = 0;
+    assert_eq!(f(std::ptr::null_mut(), &mut size), GC_ERR_SUCCESS);
+
+    let mut buf = vec![0_u8; size];
+    assert_eq!(f(buf.as_mut_ptr().cast(), &mut size), GC_ERR_SUCCESS);
+    let nul = buf.iter().position(|b| *b == 0).unwrap();
+    String::from_utf8(buf[..nul].to_vec()).unwrap()
+}
+
+/// Enables emulated devices, the fixtures are read once when the library is initialized first in
+/// the process.
+fn enable_emulation() {
+    std::env::set_var("CAMELEON_GENTL_EMULATION", "1");
+    std::env::set_var(
+        "CAMELEON_GENTL_EMULATOR_FIXTURES",
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../device/tests/fixtures/mono_camera.toml"
+        ),
+    );
+}
+
+/// Returns the value of a fixed size info written by `f`.
+fn info<T: Default>(mut f: impl FnMut(*mut i32, *mut c_void, *mut usize) -> i32) -> T {
+    let mut value = T::default();
+    let mut info_type = 0;
+    let mut size = std::mem::size_of::<T>();
+    assert_eq!(
+        f(&mut info_type, (&mut value as *mut T).cast(), &mut size),
+        GC_ERR_SUCCESS
+    );
+    assert_eq!(size, std::mem::size_of::<T>());
+    value
+}
+
+#[test]
+fn test_enumerate_interface() {
+    let _lock = LIB_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    enable_emulation();
+
+    unsafe {
+        let lib = Library::new(library_path()).unwrap();
+
+        let GCInitLib: Symbol<unsafe extern "C" fn() -> i32> = lib.get(b"GCInitLib").unwrap();
+        let GCCloseLib: Symbol<unsafe extern "C" fn() -> i32> = lib.get(b"GCCloseLib").unwrap();
+        let GCGetLastError: Symbol<
+            unsafe extern "C" fn(*mut i32, *mut libc::c_char, *mut usize) -> i32,
+        > = lib.get(b"GCGetLastError").unwrap();
+        let TLOpen: Symbol<unsafe extern "C" fn(*mut Handle) -> i32> = lib.get(b"TLOpen").unwrap();
+        let TLClose: Symbol<unsafe extern "C" fn(Handle) -> i32> = lib.get(b"TLClose").unwrap();
+        let TLGetNumInterfaces: Symbol<unsafe extern "C" fn(Handle, *mut u32) -> i32> =
+            lib.get(b"TLGetNumInterfaces").unwrap();
+        let TLGetInterfaceID: Symbol<
+            unsafe extern "C" fn(Handle, u32, *mut libc::c_char, *mut usize) -> i32,
+        > = lib.get(b"TLGetInterfaceID").unwrap();
+        let TLOpenInterface: Symbol<
+            unsafe extern "C" fn(Handle, *const libc::c_char, *mut Handle) -> i32,
+        > = lib.get(b"TLOpenInterface").unwrap();
+        let IFClose: Symbol<unsafe extern "C" fn(Handle) -> i32> = lib.get(b"IFClose").unwrap();
+        let IFGetInfo: Symbol<
+            unsafe extern "C" fn(Handle, i32, *mut i32, *mut c_void, *mut usize) -> i32,
+        > = lib.get(b"IFGetInfo").unwrap();
+        let GCRegisterEvent: Symbol<unsafe extern "C" fn(Handle, i32, *mut Handle) -> i32> =
+            lib.get(b"GCRegisterEvent").unwrap();
+        let GCUnregisterEvent: Symbol<unsafe extern "C" fn(Handle, i32) -> i32> =
+            lib.get(b"GCUnregisterEvent").unwrap();
+        let EventKill: Symbol<unsafe extern "C" fn(Handle) -> i32> = lib.get(b"EventKill").unwrap();
+
+        let mut hSystem = std::ptr::null_mut();
+        assert_eq!(TLOpen(&mut hSystem), GC_ERR_NOT_INITIALIZED);
+
+        assert_eq!(GCInitLib(), GC_ERR_SUCCESS);
+        assert_eq!(TLOpen(&mut hSystem), GC_ERR_SUCCESS);
+
+        let mut num_interfaces = 0;
+        assert_eq!(
+            TLGetNumInterfaces(hSystem, &mut num_interfaces),
+            GC_ERR_SUCCESS
+        );
+        assert_eq!(num_interfaces, 1);
+
+        let iface_id = copy_string(|buf, size| TLGetInterfaceID(hSystem, 0, buf, size));
+        let iface_id = std::ffi::CString::new(iface_id).unwrap();
+        let mut hIface = std::ptr::null_mut();
+        assert_eq!(
+            TLOpenInterface(hSystem, iface_id.as_ptr(), &mut hIface),
+            GC_ERR_SUCCESS
+        );
+
+        let mut info_type = 0;
+        let tl_type = copy_string(|buf, size| {
+            IFGetInfo(
+                hIface,
+                INTERFACE_INFO_TLTYPE,
+                &mut info_type,
+                buf.cast(),
+                size,
+            )
+        });
+        assert_eq!(tl_type, "U3V");
+
+        // Unregistering the event kills the event handle.
+        let mut hEvent = std::ptr::null_mut();
+        assert_eq!(
+            GCRegisterEvent(hIface, EVENT_MODULE, &mut hEvent),
+            GC_ERR_SUCCESS
+        );
+        assert_eq!(GCUnregisterEvent(hIface, EVENT_MODULE), GC_ERR_SUCCESS);
+        assert_eq!(EventKill(hEvent), GC_ERR_INVALID_HANDLE);
+
+        // The handle of the closed interface becomes stale.
+        assert_eq!(IFClose(hIface), GC_ERR_SUCCESS);
+        assert_eq!(IFClose(hIface), GC_ERR_INVALID_HANDLE);
+        let mut code = 0;
+        let text = copy_string(|buf, size| GCGetLastError(&mut code, buf, size));
+        assert_eq!(code, GC_ERR_INVALID_HANDLE);
+        assert!(!text.is_empty());
+
+        assert_eq!(TLClose(hSystem), GC_ERR_SUCCESS);
+        assert_eq!(GCCloseLib(), GC_ERR_SUCCESS);
+    }
+}
+
+#[test]
+fn test_stream() {
+    let _lock = LIB_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    enable_emulation();
+    const NUM_BUFFERS: usize = 3;
+    const NUM_FRAMES: usize = 5;
+    // Size of a 640x480 `Mono8` frame of the fixture.
+    const PAYLOAD_SIZE: usize = 640 * 480;
+
+    unsafe {
+        let lib = Library::new(library_path()).unwrap();
+
+        let DevGetNumDataStreams: Symbol<unsafe extern "C" fn(Handle, *mut u32) -> i32> =
+            lib.get(b"DevGetNumDataStreams").unwrap();
+        let DevGetDataStreamID: Symbol<
+            unsafe extern "C" fn(Handle, u32, *mut libc::c_char, *mut usize) -> i32,
+        > = lib.get(b"DevGetDataStreamID").unwrap();
+        let DevOpenDataStream: Symbol<
+            unsafe extern "C" fn(Handle, *const libc::c_char, *mut Handle) -> i32,
+        > = lib.get(b"DevOpenDataStream").unwrap();
+        let DSAllocAndAnnounceBuffer: Symbol<
+            unsafe extern "C" fn(Handle, usize, *mut c_void, *mut Handle) -> i32,
+        > = lib.get(b"DSAllocAndAnnounceBuffer").unwrap();
+        let DSQueueBuffer: Symbol<unsafe extern "C" fn(Handle, Handle) -> i32> =
+            lib.get(b"DSQueueBuffer").unwrap();
+        let DSRevokeBuffer: Symbol<
+            unsafe extern "C" fn(Handle, Handle, *mut *mut c_void, *mut *mut c_void) -> i32,
+        > = lib.get(b"DSRevokeBuffer").unwrap();
+        let DSFlushQueue: Symbol<unsafe extern "C" fn(Handle, i32) -> i32> =
+            lib.get(b"DSFlushQueue").unwrap();
+        let DSStartAcquisition: Symbol<unsafe extern "C" fn(Handle, i32, u64) -> i32> =
+            lib.get(b"DSStartAcquisition").unwrap();
+        let DSStopAcquisition: Symbol<unsafe extern "C" fn(Handle, i32) -> i32> =
+            lib.get(b"DSStopAcquisition").unwrap();
+        let DSGetInfo: Symbol<
+            unsafe extern "C" fn(Handle, i32, *mut i32, *mut c_void, *mut usize) -> i32,
+        > = lib.get(b"DSGetInfo").unwrap();
+        let DSGetBufferInfo: Symbol<
+            unsafe extern "C" fn(Handle, Handle, i32, *mut i32, *mut c_void, *mut usize) -> i32,
+        > = lib.get(b"DSGetBufferInfo").unwrap();
+        let DSClose: Symbol<unsafe extern "C" fn(Handle) -> i32> = lib.get(b"DSClose").unwrap();
+        let GCRegisterEvent: Symbol<unsafe extern "C" fn(Handle, i32, *mut Handle) -> i32> =
+            lib.get(b"GCRegisterEvent").unwrap();
+        let GCUnregisterEvent: Symbol<unsafe extern "C" fn(Handle, i32) -> i32> =
+            lib.get(b"GCUnregisterEvent").unwrap();
+        let EventGetData: Symbol<
+            unsafe extern "C" fn(Handle, *mut c_void, *mut usize, u64) -> i32,
+        > = lib.get(b"EventGetData").unwrap();
+        let EventGetInfo: Symbol<
+            unsafe extern "C" fn(Handle, i32, *mut i32, *mut c_void, *mut usize) -> i32,
+        > = lib.get(b"EventGetInfo").unwrap();
+        let EventFlush: Symbol<unsafe extern "C" fn(Handle) -> i32> =
+            lib.get(b"EventFlush").unwrap();
+        let EventKill: Symbol<unsafe extern "C" fn(Handle) -> i32> = lib.get(b"EventKill").unwrap();
+
+        let (hSystem, hIface, hDevice) = open_emulated_device(&lib, DEVICE_ACCESS_EXCLUSIVE);
+
+        let mut num_streams = 0;
+        assert_eq!(
+            DevGetNumDataStreams(hDevice, &mut num_streams),
+            GC_ERR_SUCCESS
+        );
+        assert_eq!(num_streams, 1);
+        let stream_id = copy_string(|buf, size| DevGetDataStreamID(hDevice, 0, buf, size));
+        let stream_id = std::ffi::CString::new(stream_id).unwrap();
+        let mut hStream = std::ptr::null_mut();
+        assert_eq!(
+            DevOpenDataStream(hDevice, stream_id.as_ptr(), &mut hStream),
+            GC_ERR_SUCCESS
+        );
+        let payload_size: usize =
+            info(|ty, buf, size| DSGetInfo(hStream, STREAM_INFO_PAYLOAD_SIZE, ty, buf, size));
+        assert_eq!(payload_size, PAYLOAD_SIZE);
+
+        let mut buffers = Vec::new();
+        for i in 0..NUM_BUFFERS {
+            let mut hBuffer = std::ptr::null_mut();
+            assert_eq!(
+                DSAllocAndAnnounceBuffer(hStream, payload_size, i as *mut c_void, &mut hBuffer),
+                GC_ERR_SUCCESS
+            );
+            assert_eq!(DSQueueBuffer(hStream, hBuffer), GC_ERR_SUCCESS);
+            buffers.push(hBuffer);
+        }
+
+        let mut hEvent = std::ptr::null_mut();
+        assert_eq!(
+            GCRegisterEvent(hStream, EVENT_NEW_BUFFER, &mut hEvent),
+            GC_ERR_SUCCESS
+        );
+        let size_max: usize =
+            info(|ty, buf, size| EventGetInfo(hEvent, EVENT_SIZE_MAX, ty, buf, size));
+        assert_eq!(size_max, std::mem::size_of::<NewBufferData>());
+
+        // Stream frames, each filled buffer is delivered by the event and queued again.
+        assert_eq!(
+            DSStartAcquisition(hStream, 0, GENTL_INFINITE),
+            GC_ERR_SUCCESS
+        );
+        let mut frame_ids = Vec::new();
+        for _ in 0..NUM_FRAMES {
+            let mut data = NewBufferData {
+                buffer: std::ptr::null_mut(),
+                user_pointer: std::ptr::null_mut(),
+            };
+            let mut size = std::mem::size_of::<NewBufferData>();
+            assert_eq!(
+                EventGetData(
+                    hEvent,
+                    (&mut data as *mut NewBufferData).cast(),
+                    &mut size,
+                    1000
+                ),
+                GC_ERR_SUCCESS
+            );
+            assert_eq!(buffers[data.user_pointer as usize], data.buffer);
+
+            let buffer_info = |cmd| {
+                let DSGetBufferInfo = &DSGetBufferInfo;
+                move |ty, buf, size| DSGetBufferInfo(hStream, data.buffer, cmd, ty, buf, size)
+            };
+            let size_filled: usize = info(buffer_info(BUFFER_INFO_SIZE_FILLED));
+            let width: usize = info(buffer_info(BUFFER_INFO_WIDTH));
+            let height: usize = info(buffer_info(BUFFER_INFO_HEIGHT));
+            let frame_id: u64 = info(buffer_info(BUFFER_INFO_FRAMEID));
+            assert_eq!(size_filled, PAYLOAD_SIZE);
+            assert_eq!((width, height), (640, 480));
+
+            // The emulator fills the `i`th byte of the payload with `block_id + i`.
+            let base: usize = info(buffer_info(BUFFER_INFO_BASE));
+            let image = std::slice::from_raw_parts(base as *const u8, size_filled);
+            assert!(image
+                .iter()
+                .enumerate()
+                .all(|(i, byte)| *byte == (frame_id as u8).wrapping_add(i as u8)));
+            frame_ids.push(frame_id);
+
+            assert_eq!(DSQueueBuffer(hStream, data.buffer), GC_ERR_SUCCESS);
+        }
+        assert!(frame_ids.windows(2).all(|ids| ids[0] < ids[1]));
+        let num_fired: u64 =
+            info(|ty, buf, size| EventGetInfo(hEvent, EVENT_NUM_FIRED, ty, buf, size));
+        assert!(num_fired >= NUM_FRAMES as u64);
+
+        assert_eq!(DSStopAcquisition(hStream, 0), GC_ERR_SUCCESS);
+        let is_grabbing: u8 =
+            info(|ty, buf, size| DSGetInfo(hStream, STREAM_INFO_IS_GRABBING, ty, buf, size));
+        assert_eq!(is_grabbing, 0);
+        let num_delivered: u64 =
+            info(|ty, buf, size| DSGetInfo(hStream, STREAM_INFO_NUM_DELIVERED, ty, buf, size));
+        assert!(num_delivered >= NUM_FRAMES as u64);
+
+        // No buffer is filled once the acquisition is stopped.
+        assert_eq!(EventFlush(hEvent), GC_ERR_SUCCESS);
+        let num_in_queue: usize =
+            info(|ty, buf, size| EventGetInfo(hEvent, EVENT_NUM_IN_QUEUE, ty, buf, size));
+        assert_eq!(num_in_queue, 0);
+        let mut data = [0_u8; std::mem::size_of::<NewBufferData>()];
+        let mut size = data.len();
+        assert_eq!(
+            EventGetData(hEvent, data.as_mut_ptr().cast(), &mut size, 100),
+            GC_ERR_TIMEOUT
+        );
+
+        // Buffers allocated by the data stream are revoked without their memory.
+        assert_eq!(DSFlushQueue(hStream, ACQ_QUEUE_ALL_DISCARD), GC_ERR_SUCCESS);
+        for (i, hBuffer) in buffers.into_iter().enumerate() {
+            let mut base = std::ptr::null_mut();
+            let mut user_pointer = std::ptr::null_mut();
+            assert_eq!(
+                DSRevokeBuffer(hStream, hBuffer, &mut base, &mut user_pointer),
+                GC_ERR_SUCCESS
+            );
+            assert!(base.is_null());
+            assert_eq!(user_pointer as usize, i);
+        }
+
+        assert_eq!(GCUnregisterEvent(hStream, EVENT_NEW_BUFFER), GC_ERR_SUCCESS);
+        assert_eq!(EventKill(hEvent), GC_ERR_INVALID_HANDLE);
+
+        assert_eq!(DSClose(hStream), GC_ERR_SUCCESS);
+        assert_eq!(DSClose(hStream), GC_ERR_INVALID_HANDLE);
+        close_emulated_device(&lib, (hSystem, hIface, hDevice));
+    }
+}
+
+#[test]
+fn test_concurrent_port_read() {
+    let _lock = LIB_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    enable_emulation();
+
+    unsafe {
+        let lib = Library::new(library_path()).unwrap();
+
+        let DevGetPort: Symbol<unsafe extern "C" fn(Handle, *mut Handle) -> i32> =
+            lib.get(b"DevGetPort").unwrap();
+        let GCGetPortURL: Symbol<
+            unsafe extern "C" fn(Handle, *mut libc::c_char, *mut usize) -> i32,
+        > = lib.get(b"GCGetPortURL").unwrap();
+        let GCReadPort: Symbol<unsafe extern "C" fn(Handle, u64, *mut c_void, *mut usize) -> i32> =
+            lib.get(b"GCReadPort").unwrap();
+
+        let (hSystem, hIface, hDevice) = open_emulated_device(&lib, DEVICE_ACCESS_EXCLUSIVE);
+        let mut hPort = std::ptr::null_mut();
+        assert_eq!(DevGetPort(hDevice, &mut hPort), GC_ERR_SUCCESS);
+
+        // `local:{file name};{address};{length}?{query}`, address and length are in hex.
+        let url = copy_string(|buf, size| GCGetPortURL(hPort, buf, size));
+        let location: Vec<_> = url.split('?').next().unwrap().split(';').collect();
+        let xml_address = u64::from_str_radix(location[1], 16).unwrap();
+        let xml_size = usize::from_str_radix(location[2], 16).unwrap().min(4096);
+
+        // One thread reads the device XML, which takes multiple transactions, while the other
+        // reads `Manufacturer Name` register of ABRM. A request ID mismatch is returned as an
+        // error.
+        let read_port = *GCReadPort;
+        let port = hPort as usize;
+        let handles: Vec<_> = [(xml_address, xml_size), (4, 64)]
+            .iter()
+            .map(|&(address, size)| {
+                std::thread::spawn(move || {
+                    let read = || {
+                        let mut buf = vec![0_u8; size];
+                        let mut len = size;
+                        assert_eq!(
+                            read_port(port as Handle, address, buf.as_mut_ptr().cast(), &mut len),
+                            GC_ERR_SUCCESS
+                        );
+                        assert_eq!(len, size);
+                        buf
+                    };
+                    let expected = read();
+                    for _ in 0..1000 {
+                        assert_eq!(read(), expected);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        close_emulated_device(&lib, (hSystem, hIface, hDevice));
+    }
+}
+
+#[test]
+fn test_device_info() {
+    let _lock = LIB_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    enable_emulation();
+
+    unsafe {
+        let lib = Library::new(library_path()).unwrap();
+
+        let IFGetDeviceInfo: Symbol<
+            unsafe extern "C" fn(
+                Handle,
+                *const libc::c_char,
+                i32,
+                *mut i32,
+                *mut c_void,
+                *mut usize,
+            ) -> i32,
+        > = lib.get(b"IFGetDeviceInfo").unwrap();
+        let DevGetInfo: Symbol<
+            unsafe extern "C" fn(Handle, i32, *mut i32, *mut c_void, *mut usize) -> i32,
+        > = lib.get(b"DevGetInfo").unwrap();
+
+        let (hSystem, hIface, hDevice) = open_emulated_device(&lib, DEVICE_ACCESS_EXCLUSIVE);
+        let dev_string =
+            |cmd| copy_string(|buf, size| DevGetInfo(hDevice, cmd, &mut 0, buf.cast(), size));
+
+        // The interface and the device module answer the same queries.
+        let device_id = std::ffi::CString::new(dev_string(DEVICE_INFO_ID)).unwrap();
+        let if_string = |cmd| {
+            copy_string(|buf, size| {
+                IFGetDeviceInfo(hIface, device_id.as_ptr(), cmd, &mut 0, buf.cast(), size)
+            })
+        };
+        for cmd in [
+            DEVICE_INFO_TLTYPE,
+            DEVICE_INFO_SERIAL_NUMBER,
+            DEVICE_INFO_USER_DEFINED_NAME,
+        ] {
+            assert_eq!(if_string(cmd), dev_string(cmd));
+        }
+        assert_eq!(dev_string(DEVICE_INFO_TLTYPE), "U3V");
+        assert_eq!(dev_string(DEVICE_INFO_SERIAL_NUMBER), "MONO0001");
+        assert_eq!(dev_string(DEVICE_INFO_USER_DEFINED_NAME), "Mono camera");
+
+        let status: i32 =
+            info(|ty, buf, size| DevGetInfo(hDevice, DEVICE_INFO_ACCESS_STATUS, ty, buf, size));
+        assert_eq!(status, DEVICE_ACCESS_STATUS_OPEN_READWRITE);
+        // The emulator clock runs at 1MHz.
+        let frequency: u64 = info(|ty, buf, size| {
+            DevGetInfo(hDevice, DEVICE_INFO_TIMESTAMP_FREQUENCY, ty, buf, size)
+        });
+        assert_eq!(frequency, 1_000_000);
+        let link_speed: u64 =
+            info(|ty, buf, size| DevGetInfo(hDevice, DEVICE_INFO_LINK_SPEED, ty, buf, size));
+        assert!(link_speed > 0);
+
+        // Commands unknown to the producer aren't available.
+        let mut buf = [0_u8; 64];
+        let mut size = buf.len();
+        assert_eq!(
+            DevGetInfo(hDevice, 999, &mut 0, buf.as_mut_ptr().cast(), &mut size),
+            GC_ERR_NOT_AVAILABLE
+        );
+
+        close_emulated_device(&lib, (hSystem, hIface, hDevice));
+    }
+}
+
+#[test]
+fn test_close_lib_with_shared_open() {
+    let _lock = LIB_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    enable_emulation();
+
+    unsafe {
+        let lib = Library::new(library_path()).unwrap();
+
+        let GCCloseLib: Symbol<unsafe extern "C" fn() -> i32> = lib.get(b"GCCloseLib").unwrap();
+        let IFOpenDevice: Symbol<
+            unsafe extern "C" fn(Handle, *const libc::c_char, i32, *mut Handle) -> i32,
+        > = lib.get(b"IFOpenDevice").unwrap();
+        let DevGetInfo: Symbol<
+            unsafe extern "C" fn(Handle, i32, *mut i32, *mut c_void, *mut usize) -> i32,
+        > = lib.get(b"DevGetInfo").unwrap();
+        let DevClose: Symbol<unsafe extern "C" fn(Handle) -> i32> = lib.get(b"DevClose").unwrap();
+
+        // Open the device twice, and leave both opens unclosed.
+        let (_, hIface, hDevice) = open_emulated_device(&lib, DEVICE_ACCESS_CONTROL);
+        let device_id =
+            copy_string(|buf, size| DevGetInfo(hDevice, DEVICE_INFO_ID, &mut 0, buf.cast(), size));
+        let device_id = std::ffi::CString::new(device_id).unwrap();
+        let mut hReader = std::ptr::null_mut();
+        assert_eq!(
+            IFOpenDevice(
+                hIface,
+                device_id.as_ptr(),
+                DEVICE_ACCESS_READONLY,
+                &mut hReader
+            ),
+            GC_ERR_SUCCESS
+        );
+        assert_eq!(GCCloseLib(), GC_ERR_SUCCESS);
+        assert_eq!(DevClose(hDevice), GC_ERR_NOT_INITIALIZED);
+
+        // The device is closed regardless of the shared open, so it can be opened exclusively.
+        let handles = open_emulated_device(&lib, DEVICE_ACCESS_EXCLUSIVE);
+        assert_eq!(DevClose(hReader), GC_ERR_INVALID_HANDLE);
+        close_emulated_device(&lib, handles);
+    }
+}
+
+#[test]
+fn test_close_owner_with_shared_open() {
+    let _lock = LIB_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    enable_emulation();
+
+    unsafe {
+        let lib = Library::new(library_path()).unwrap();
+
+        let IFOpenDevice: Symbol<
+            unsafe extern "C" fn(Handle, *const libc::c_char, i32, *mut Handle) -> i32,
+        > = lib.get(b"IFOpenDevice").unwrap();
+        let DevGetInfo: Symbol<
+            unsafe extern "C" fn(Handle, i32, *mut i32, *mut c_void, *mut usize) -> i32,
+        > = lib.get(b"DevGetInfo").unwrap();
+        let DevGetPort: Symbol<unsafe extern "C" fn(Handle, *mut Handle) -> i32> =
+            lib.get(b"DevGetPort").unwrap();
+        let GCReadPort: Symbol<unsafe extern "C" fn(Handle, u64, *mut c_void, *mut usize) -> i32> =
+            lib.get(b"GCReadPort").unwrap();
+        let GCWritePort: Symbol<
+            unsafe extern "C" fn(Handle, u64, *const c_void, *mut usize) -> i32,
+        > = lib.get(b"GCWritePort").unwrap();
+        let DevClose: Symbol<unsafe extern "C" fn(Handle) -> i32> = lib.get(b"DevClose").unwrap();
+
+        let (hSystem, hIface, hDevice) = open_emulated_device(&lib, DEVICE_ACCESS_CONTROL);
+        let device_id =
+            copy_string(|buf, size| DevGetInfo(hDevice, DEVICE_INFO_ID, &mut 0, buf.cast(), size));
+        let device_id = std::ffi::CString::new(device_id).unwrap();
+        let mut hReader = std::ptr::null_mut();
+        assert_eq!(
+            IFOpenDevice(
+                hIface,
+                device_id.as_ptr(),
+                DEVICE_ACCESS_READONLY,
+                &mut hReader
+            ),
+            GC_ERR_SUCCESS
+        );
+        let mut hOwnerPort = std::ptr::null_mut();
+        let mut hReaderPort = std::ptr::null_mut();
+        assert_eq!(DevGetPort(hDevice, &mut hOwnerPort), GC_ERR_SUCCESS);
+        assert_eq!(DevGetPort(hReader, &mut hReaderPort), GC_ERR_SUCCESS);
+
+        // `User Defined Name` register of ABRM is writable only through the owner. The name is
+        // written back as is, because the emulator is shared with the other tests.
+        let mut name = [0_u8; 64];
+        let mut size = name.len();
+        assert_eq!(
+            GCReadPort(hOwnerPort, 0x184, name.as_mut_ptr().cast(), &mut size),
+            GC_ERR_SUCCESS
+        );
+        assert_eq!(
+            GCWritePort(hOwnerPort, 0x184, name.as_ptr().cast(), &mut size),
+            GC_ERR_SUCCESS
+        );
+        assert_eq!(
+            GCWritePort(hReaderPort, 0x184, name.as_ptr().cast(), &mut size),
+            GC_ERR_ACCESS_DENIED
+        );
+
+        // The reader keeps the device opened read-only after the owner is closed.
+        assert_eq!(DevClose(hDevice), GC_ERR_SUCCESS);
+        let access_status: i32 =
+            info(|ty, buf, size| DevGetInfo(hReader, DEVICE_INFO_ACCESS_STATUS, ty, buf, size));
+        assert_eq!(access_status, DEVICE_ACCESS_STATUS_OPEN_READONLY);
+        let mut buf = [0_u8; 64];
+        let mut size = buf.len();
+        assert_eq!(
+            GCReadPort(hReaderPort, 0x184, buf.as_mut_ptr().cast(), &mut size),
+            GC_ERR_SUCCESS
+        );
+        assert_eq!(buf, name);
+
+        // The control is released, so the device can be opened for control by another handle
+        // only after the reader is closed.
+        let mut hOwner = std::ptr::null_mut();
+        assert_ne!(
+            IFOpenDevice(
+                hIface,
+                device_id.as_ptr(),
+                DEVICE_ACCESS_CONTROL,
+                &mut hOwner
+            ),
+            GC_ERR_SUCCESS
+        );
+        assert_eq!(DevClose(hReader), GC_ERR_SUCCESS);
+        assert_eq!(
+            IFOpenDevice(
+                hIface,
+                device_id.as_ptr(),
+                DEVICE_ACCESS_CONTROL,
+                &mut hOwner
+            ),
+            GC_ERR_SUCCESS
+        );
+        close_emulated_device(&lib, (hSystem, hIface, hOwner));
+    }
+}
+
+/// Initializes the library and opens the emulated device built from the fixture, see
+/// [`enable_emulation`].
+///
+/// Returns the handles of the system, the interface and the device.
+unsafe fn open_emulated_device(lib: &Library, access: i32) -> (Handle, Handle, Handle) {
+    let GCInitLib: Symbol<unsafe extern "C" fn() -> i32> = lib.get(b"GCInitLib").unwrap();
+    let TLOpen: Symbol<unsafe extern "C" fn(*mut Handle) -> i32> = lib.get(b"TLOpen").unwrap();
+    let TLGetInterfaceID: Symbol<
+        unsafe extern "C" fn(Handle, u32, *mut libc::c_char, *mut usize) -> i32,
+    > = lib.get(b"TLGetInterfaceID").unwrap();
+    let TLOpenInterface: Symbol<
+        unsafe extern "C" fn(Handle, *const libc::c_char, *mut Handle) -> i32,
+    > = lib.get(b"TLOpenInterface").unwrap();
+    let IFUpdateDeviceList: Symbol<unsafe extern "C" fn(Handle, *mut u8, u64) -> i32> =
+        lib.get(b"IFUpdateDeviceList").unwrap();
+    let IFGetNumDevices: Symbol<unsafe extern "C" fn(Handle, *mut u32) -> i32> =
+        lib.get(b"IFGetNumDevices").unwrap();
+    let IFGetDeviceID: Symbol<
+        unsafe extern "C" fn(Handle, u32, *mut libc::c_char, *mut usize) -> i32,
+    > = lib.get(b"IFGetDeviceID").unwrap();
+    let IFOpenDevice: Symbol<
+        unsafe extern "C" fn(Handle, *const libc::c_char, i32, *mut Handle) -> i32,
+    > = lib.get(b"IFOpenDevice").unwrap();
+
+    assert_eq!(GCInitLib(), GC_ERR_SUCCESS);
+    let mut hSystem = std::ptr::null_mut();
+    assert_eq!(TLOpen(&mut hSystem), GC_ERR_SUCCESS);
+    let iface_id = copy_string(|buf, size| TLGetInterfaceID(hSystem, 0, buf, size));
+    let iface_id = std::ffi::CString::new(iface_id).unwrap();
+    let mut hIface = std::ptr::null_mut();
+    assert_eq!(
+        TLOpenInterface(hSystem, iface_id.as_ptr(), &mut hIface),
+        GC_ERR_SUCCESS
+    );
+
+    let mut changed = 0;
+    assert_eq!(
+        IFUpdateDeviceList(hIface, &mut changed, 1000),
+        GC_ERR_SUCCESS
+    );
+    let mut num_devices = 0;
+    assert_eq!(IFGetNumDevices(hIface, &mut num_devices), GC_ERR_SUCCESS);
+    let device_id = (0..num_devices)
+        .map(|i| copy_string(|buf, size| IFGetDeviceID(hIface, i, buf, size)))
+        .find(|id| id.starts_with("emu-"))
+        .unwrap();
+    let device_id = std::ffi::CString::new(device_id).unwrap();
+    let mut hDevice = std::ptr::null_mut();
+    assert_eq!(
+        IFOpenDevice(hIface, device_id.as_ptr(), access, &mut hDevice),
+        GC_ERR_SUCCESS
+    );
+
+    (hSystem, hIface, hDevice)
+}
+
+/// Closes the handles returned by [`open_emulated_device`] and the library.
+unsafe fn close_emulated_device(
+    lib: &Library,
+    (hSystem, hIface, hDevice): (Handle, Handle, Handle),
+) {
+    let GCCloseLib: Symbol<unsafe extern "C" fn() -> i32> = lib.get(b"GCCloseLib").unwrap();
+    let TLClose: Symbol<unsafe extern "C" fn(Handle) -> i32> = lib.get(b"TLClose").unwrap();
+    let IFClose: Symbol<unsafe extern "C" fn(Handle) -> i32> = lib.get(b"IFClose").unwrap();
+    let DevClose: Symbol<unsafe extern "C" fn(Handle) -> i32> = lib.get(b"DevClose").unwrap();
+
+    assert_eq!(DevClose(hDevice), GC_ERR_SUCCESS);
+    assert_eq!(IFClose(hIface), GC_ERR_SUCCESS);
+    assert_eq!(TLClose(hSystem), GC_ERR_SUCCESS);
+    assert_eq!(GCCloseLib(), GC_ERR_SUCCESS);
+}
@@ -0,0 +1,58 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Once,
+};
+
+/// Returns the path to the producer library, which is built with `emulator` feature on the first
+/// call.
+///
+/// `cargo test` doesn't build the `cdylib` of the package, so the library is built by a nested
+/// `cargo build`. The target directory of the running test is locked by cargo until the tests
+/// finish, so the library is built into its own target directory.
+///
+/// # Panics
+/// Panics if the library can't be built.
+pub fn library_path() -> PathBuf {
+    static BUILD: Once = Once::new();
+
+    let target_dir = target_dir().join("gentl-producer");
+    BUILD.call_once(|| build_library(&target_dir));
+    target_dir
+        .join("debug")
+        .join(libloading::library_filename("cameleon_gentl"))
+}
+
+fn build_library(target_dir: &Path) {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let status = Command::new(cargo)
+        .args(["build", "-p", "cameleon-gentl", "--features", "emulator"])
+        .arg("--manifest-path")
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"))
+        .arg("--target-dir")
+        .arg(target_dir)
+        .status()
+        .unwrap_or_else(|e| panic!("failed to run cargo to build the producer library: {}", e));
+    assert!(
+        status.success(),
+        "failed to build the producer library with \
+         `cargo build -p cameleon-gentl --features emulator`: {}",
+        status
+    );
+}
+
+/// Returns the target directory of the running test.
+fn target_dir() -> PathBuf {
+    // Integration tests are placed in `target/<profile>/deps`.
+    let mut path = std::env::current_exe().unwrap();
+    path.pop();
+    if path.ends_with("deps") {
+        path.pop();
+    }
+    path.pop();
+    path
+}
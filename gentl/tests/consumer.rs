@@ -0,0 +1,54 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! End-to-end test of the `GenTL` consumer mode of `cameleon` running over this producer.
+//!
+//! Same as `c_abi`, the library is built with `emulator` feature by [`common::library_path`].
+
+mod common;
+
+use common::library_path;
+
+#[test]
+fn test_consumer() {
+    const NUM_FRAMES: usize = 5;
+    std::env::set_var("CAMELEON_GENTL_EMULATION", "1");
+    std::env::set_var(
+        "CAMELEON_GENTL_EMULATOR_FIXTURES",
+        concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../device/tests/fixtures/streaming_camera.toml"
+        ),
+    );
+
+    let mut camera = cameleon::gentl::enumerate_cameras(library_path())
+        .unwrap()
+        .into_iter()
+        .find(|camera| camera.info().serial_number == "STRM0001")
+        .unwrap();
+    camera.open().unwrap();
+    camera.load_context().unwrap();
+
+    let payload_rx = camera.start_streaming(3).unwrap();
+    let mut ids = Vec::new();
+    for _ in 0..NUM_FRAMES {
+        let payload = async_std::task::block_on(payload_rx.recv()).unwrap();
+        let image_info = payload.image_info().unwrap();
+        assert_eq!((image_info.width, image_info.height), (640, 480));
+        // The emulator fills the `i`th byte of the payload with `block_id + i`.
+        let image = payload.image().unwrap();
+        assert_eq!(image.len(), 640 * 480);
+        let first = payload.id() as u8;
+        assert!(image
+            .iter()
+            .enumerate()
+            .all(|(i, byte)| *byte == first.wrapping_add(i as u8)));
+        ids.push(payload.id());
+        payload_rx.send_back(payload);
+    }
+    assert!(ids.windows(2).all(|ids| ids[0] < ids[1]));
+
+    camera.stop_streaming().unwrap();
+    camera.close().unwrap();
+}
@@ -70,7 +70,7 @@ impl MemoryStruct {
             #vis struct #ident {
                 raw: Vec<u8>,
                 protection: cameleon_impl::memory::MemoryProtection,
-                observers: std::vec::Vec<(std::ops::Range<usize>, std::boxed::Box<dyn cameleon_impl::memory::MemoryObserver>)>,
+                observers: std::vec::Vec<(std::ops::Range<usize>, std::boxed::Box<dyn cameleon_impl::memory::MemoryObserver>, cameleon_impl::leak_check::Tracked)>,
             }
         }
     }
@@ -87,7 +87,7 @@ impl MemoryStruct {
                 #[doc(hidden)]
                 fn notify_all(&self, written_range: std::ops::Range<usize>) {
 
-                    for (reg_range, observer) in &self.observers {
+                    for (reg_range, observer, _) in &self.observers {
 
                         if written_range.start >= reg_range.end || written_range.end <= reg_range.start {
                             continue;
@@ -119,6 +119,9 @@ impl MemoryStruct {
         quote! {
             impl cameleon_impl::memory::prelude::MemoryRead for #ident {
                 fn read_raw(&self, range: std::ops::Range<usize>) -> cameleon_impl::memory::MemoryResult<&[u8]> {
+                    if range.start > range.end {
+                        return Err(cameleon_impl::memory::MemoryError::InvalidAddress);
+                    }
                     self.protection.verify_address_with_range(range.clone())?;
                     let access_right = self.protection.access_right_with_range(range.clone());
                     if !access_right.is_readable() {
@@ -139,7 +142,8 @@ impl MemoryStruct {
 
             impl cameleon_impl::memory::prelude::MemoryWrite for #ident {
                 fn write_raw(&mut self, addr: usize, buf: &[u8]) -> cameleon_impl::memory::MemoryResult<()> {
-                    let (start, end) = (addr, addr + buf.len());
+                    let end = addr.checked_add(buf.len()).ok_or(cameleon_impl::memory::MemoryError::InvalidAddress)?;
+                    let (start, end) = (addr, end);
                     let range = start..end;
                     self.protection.verify_address_with_range(range.clone())?;
                     let access_right = self.protection.access_right_with_range(range.clone());
@@ -165,6 +169,7 @@ impl MemoryStruct {
                     Ok(())
                 }
 
+                #[track_caller]
                 fn register_observer<T, U>(
                     &mut self,
                     observer: U
@@ -174,7 +179,9 @@ impl MemoryStruct {
                 {
                     let reg_range = T::range();
 
-                    self.observers.push((reg_range, Box::new(observer)));
+                    let tracked = cameleon_impl::leak_check::Tracked::new(cameleon_impl::leak_check::Resource::Observer);
+
+                    self.observers.push((reg_range, Box::new(observer), tracked));
                 }
 
             }
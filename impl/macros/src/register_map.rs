@@ -369,6 +369,17 @@ impl Register {
                 }
             }
 
+            RegisterType::F16 | RegisterType::F32 | RegisterType::F64 => {
+                let narrow = if ty.numerical_bits() == 64 {
+                    quote! {}
+                } else {
+                    quote! {.map(|value| value as f32)}
+                };
+                quote! {
+                    cameleon_impl::float::read_float::<#endianness>(data)#narrow.map_err(|e| MemoryError::InvalidRegisterData(format! {"{}", e}.into()))
+                }
+            }
+
             _ => {
                 let read_integral = format_ident!("read_{}", ty.associated_ty());
                 if ty.numerical_bits() == 8 {
@@ -434,6 +445,19 @@ impl Register {
                 }
             }
 
+            RegisterType::F16 | RegisterType::F32 | RegisterType::F64 => {
+                let widen = if ty.numerical_bits() == 64 {
+                    quote! {}
+                } else {
+                    quote! {let data = f64::from(data);}
+                };
+                quote! {
+                    #widen
+                    let mut result = vec![0; #len];
+                    cameleon_impl::float::write_float::<#endianness>(data, &mut result).map_err(|e| MemoryError::InvalidRegisterData(format! {"{}", e}.into()))?;
+                }
+            }
+
             _ => {
                 let write_integral = format_ident!("write_{}", ty.associated_ty());
                 if ty.numerical_bits() == 8 {
@@ -676,14 +700,16 @@ enum RegisterType {
     I16,
     I32,
     I64,
+    /// Half precision float, which is represented as `f32`.
+    F16,
     F32,
     F64,
 }
 
 impl RegisterType {
     fn is_integral(&self) -> bool {
-        use RegisterType::{BitField, Bytes, Str, F32, F64};
-        !matches!(self, Str | Bytes | BitField(..) | F32 | F64)
+        use RegisterType::{BitField, Bytes, Str, F16, F32, F64};
+        !matches!(self, Str | Bytes | BitField(..) | F16 | F32 | F64)
     }
 
     fn is_signed(&self) -> bool {
@@ -707,10 +733,10 @@ impl RegisterType {
     }
 
     fn numerical_bits(&self) -> usize {
-        use RegisterType::{F32, F64, I16, I32, I64, I8, U16, U32, U64, U8};
+        use RegisterType::{F16, F32, F64, I16, I32, I64, I8, U16, U32, U64, U8};
         match self {
             U8 | I8 => 8,
-            U16 | I16 => 16,
+            U16 | I16 | F16 => 16,
             U32 | I32 | F32 => 32,
             U64 | I64 | F64 => 64,
             _ => panic!(),
@@ -718,7 +744,9 @@ impl RegisterType {
     }
 
     fn associated_ty(&self) -> &str {
-        use RegisterType::{BitField, Bytes, Str, F32, F64, I16, I32, I64, I8, U16, U32, U64, U8};
+        use RegisterType::{
+            BitField, Bytes, Str, F16, F32, F64, I16, I32, I64, I8, U16, U32, U64, U8,
+        };
         match self {
             Str => "std::string::String",
             Bytes => "Vec<u8>",
@@ -731,7 +759,7 @@ impl RegisterType {
             I16 => "i16",
             I32 => "i32",
             I64 => "i64",
-            F32 => "f32",
+            F16 | F32 => "f32",
             F64 => "f64",
         }
     }
@@ -739,7 +767,9 @@ impl RegisterType {
 
 impl syn::parse::Parse for RegisterType {
     fn parse(input: syn::parse::ParseStream) -> Result<Self> {
-        use RegisterType::{BitField, Bytes, Str, F32, F64, I16, I32, I64, I8, U16, U32, U64, U8};
+        use RegisterType::{
+            BitField, Bytes, Str, F16, F32, F64, I16, I32, I64, I8, U16, U32, U64, U8,
+        };
 
         let ident = input.parse::<syn::Ident>()?;
         let err_msg =
@@ -756,6 +786,7 @@ impl syn::parse::Parse for RegisterType {
             _ if ident == "i16" => Ok(I16),
             _ if ident == "i32" => Ok(I32),
             _ if ident == "i64" => Ok(I64),
+            _ if ident == "f16" => Ok(F16),
             _ if ident == "f32" => Ok(F32),
             _ if ident == "f64" => Ok(F64),
             _ if ident == "BitField" => Ok(BitField(input.parse()?)),
@@ -776,7 +807,7 @@ impl BitField {
         let len = self.ty.integral_bits();
         match endianness {
             Endianness::LE => self.lsb.base10_parse().unwrap(),
-            Endianness::BE => (len - self.lsb.base10_parse::<usize>().unwrap() - 1),
+            Endianness::BE => len - self.lsb.base10_parse::<usize>().unwrap() - 1,
         }
     }
 
@@ -784,7 +815,7 @@ impl BitField {
         let len = self.ty.integral_bits();
         match endianness {
             Endianness::LE => self.msb.base10_parse().unwrap(),
-            Endianness::BE => (len - self.msb.base10_parse::<usize>().unwrap() - 1),
+            Endianness::BE => len - self.msb.base10_parse::<usize>().unwrap() - 1,
         }
     }
 
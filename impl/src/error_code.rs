@@ -0,0 +1,210 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Stable numeric codes of the errors exposed by cameleon crates.
+//!
+//! A code is a `u32` whose upper 16 bits encode the [`ErrorCategory`] and lower 16 bits identify
+//! the error in the category. Once a code is assigned, it never changes its meaning. New codes
+//! may be added in any release, so consumers must handle unknown codes.
+
+use std::fmt;
+
+/// A stable numeric code of an error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ErrorCode(u32);
+
+/// A coarse category of an [`ErrorCode`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// Communication with the device failed, e.g. the device is disconnected.
+    Transport,
+    /// The device doesn't follow the protocol specification.
+    Protocol,
+    /// `GenApi` context or its nodes can't serve the request.
+    GenApi,
+    /// The API is used in a wrong way, e.g. the device is not opened.
+    Usage,
+}
+
+impl ErrorCategory {
+    const fn base(self) -> u32 {
+        match self {
+            Self::Transport => 0x0001_0000,
+            Self::Protocol => 0x0002_0000,
+            Self::GenApi => 0x0003_0000,
+            Self::Usage => 0x0004_0000,
+        }
+    }
+}
+
+macro_rules! error_codes {
+    ($($(#[$meta:meta])* $name:ident = ($category:ident, $index:literal),)*) => {
+        impl ErrorCode {
+            $(
+                $(#[$meta])*
+                pub const $name: Self = Self(ErrorCategory::$category.base() | $index);
+            )*
+
+            /// Returns the name of the code, or `None` if the code is unknown to this version.
+            #[must_use]
+            pub fn name(self) -> Option<&'static str> {
+                match self {
+                    $(Self::$name => Some(stringify!($name)),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// Input/output error while communicating with the device.
+    IO = (Transport, 0x0001),
+    /// The device is disconnected from the host.
+    DISCONNECTED = (Transport, 0x0002),
+    /// The operation timed out.
+    TIMEOUT = (Transport, 0x0003),
+    /// The device is busy, may be opened by another application.
+    BUSY = (Transport, 0x0004),
+    /// Failed to receive a payload.
+    RECEIVE_FAILED = (Transport, 0x0005),
+    /// Failed to send a payload.
+    SEND_FAILED = (Transport, 0x0006),
+    /// A panic has occurred in the streaming loop.
+    STREAM_POISONED = (Transport, 0x0007),
+    /// Reading or writing the device memory through a `GenApi` node failed.
+    DEVICE_ACCESS = (Transport, 0x0008),
+    /// The deadline of an operation has passed.
+    DEADLINE_EXCEEDED = (Transport, 0x0009),
+
+    /// The device doesn't follow the specification.
+    INVALID_DEVICE = (Protocol, 0x0001),
+    /// A value claimed by the device exceeds the limit.
+    LIMIT_EXCEEDED = (Protocol, 0x0002),
+    /// The device sent an invalid payload.
+    INVALID_PAYLOAD = (Protocol, 0x0003),
+    /// The device sent a leader or trailer larger than the transfer size.
+    SECTION_OVERFLOW = (Protocol, 0x0004),
+    /// The device returned fewer bytes than requested.
+    PARTIAL_READ = (Protocol, 0x0005),
+    /// The device wrote a length different from the requested one.
+    PARTIAL_WRITE = (Protocol, 0x0006),
+    /// The device answered a command with an ack of another command or request.
+    UNEXPECTED_ACK = (Protocol, 0x0007),
+    /// The zipped `GenApi` xml file retrieved from the device is broken.
+    CORRUPT_XML_ARCHIVE = (Protocol, 0x0008),
+
+    /// `GenApi` xml doesn't meet the specification.
+    INVALID_GENAPI_XML = (GenApi, 0x0001),
+    /// The camera lacks `GenApi` nodes required by the operation.
+    MISSING_CAPABILITY = (GenApi, 0x0002),
+    /// The retrieved `GenApi` xml doesn't match the hash reported by the device.
+    XML_INTEGRITY = (GenApi, 0x0003),
+    /// The node is not writable.
+    NOT_WRITABLE = (GenApi, 0x0004),
+    /// The node is invalid.
+    INVALID_NODE = (GenApi, 0x0005),
+    /// The value is invalid for the node.
+    INVALID_VALUE = (GenApi, 0x0006),
+    /// Chunk data required by the node is missing.
+    CHUNK_DATA_MISSING = (GenApi, 0x0007),
+    /// The buffer passed to the node is invalid.
+    INVALID_BUFFER = (GenApi, 0x0008),
+    /// Features differ from the saved values after loading a user set.
+    USER_SET_MISMATCH = (GenApi, 0x0009),
+
+    /// The device is not opened.
+    NOT_OPENED = (Usage, 0x0001),
+    /// The device is already opened in this process.
+    ALREADY_OPEN = (Usage, 0x0002),
+    /// The buffer is too small to receive data.
+    BUFFER_TOO_SMALL = (Usage, 0x0003),
+    /// The data is invalid for the destination.
+    INVALID_DATA = (Usage, 0x0004),
+    /// The accessed range is out of the address space.
+    INVALID_ADDRESS = (Usage, 0x0005),
+    /// Streaming is already started.
+    IN_STREAMING = (Usage, 0x0006),
+    /// The operation is not supported.
+    NOT_SUPPORTED = (Usage, 0x0007),
+    /// A user defined stage of the payload pipeline failed.
+    PIPELINE_FAILED = (Usage, 0x0008),
+    /// `GenApi` context is not loaded yet.
+    GENAPI_CONTEXT_MISSING = (Usage, 0x0009),
+    /// The operation is cancelled by the user.
+    CANCELLED = (Usage, 0x000a),
+    /// The feature is locked while streaming is active.
+    LOCKED_DURING_ACQUISITION = (Usage, 0x000b),
+}
+
+impl ErrorCode {
+    /// Constructs a code from its raw value.
+    ///
+    /// The value isn't validated, [`Self::name`] returns `None` for unknown codes.
+    #[must_use]
+    pub const fn from_raw(raw: u32) -> Self {
+        Self(raw)
+    }
+
+    /// Returns the raw value of the code.
+    #[must_use]
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    /// Returns the category of the code, or `None` if the category is unknown to this version.
+    #[must_use]
+    pub fn category(self) -> Option<ErrorCategory> {
+        match self.0 & 0xffff_0000 {
+            0x0001_0000 => Some(ErrorCategory::Transport),
+            0x0002_0000 => Some(ErrorCategory::Protocol),
+            0x0003_0000 => Some(ErrorCategory::GenApi),
+            0x0004_0000 => Some(ErrorCategory::Usage),
+            _ => None,
+        }
+    }
+}
+
+impl From<ErrorCode> for u32 {
+    fn from(code: ErrorCode) -> Self {
+        code.0
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{} ({:#010X})", name, self.0),
+            None => write!(f, "{:#010X}", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_category() {
+        assert_eq!(ErrorCode::IO.category(), Some(ErrorCategory::Transport));
+        assert_eq!(
+            ErrorCode::INVALID_DEVICE.category(),
+            Some(ErrorCategory::Protocol)
+        );
+        assert_eq!(
+            ErrorCode::NOT_WRITABLE.category(),
+            Some(ErrorCategory::GenApi)
+        );
+        assert_eq!(ErrorCode::CANCELLED.category(), Some(ErrorCategory::Usage));
+        assert_eq!(ErrorCode::from_raw(0x0010_0001).category(), None);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(ErrorCode::TIMEOUT.to_string(), "TIMEOUT (0x00010003)");
+        assert_eq!(ErrorCode::from_raw(0x0001_ffff).to_string(), "0x0001FFFF");
+        assert_eq!(ErrorCode::from_raw(0x0001_0003), ErrorCode::TIMEOUT);
+    }
+}
@@ -0,0 +1,264 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Codec of floating point registers.
+//!
+//! A register holds an IEEE 754 binary16, binary32 or binary64 value depending on its length,
+//! i.e. 2, 4 or 8 bytes. Values are exchanged as `f64` regardless of the register length.
+
+use byteorder::ByteOrder;
+use thiserror::Error;
+
+pub type FloatResult<T> = std::result::Result<T, FloatError>;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum FloatError {
+    #[error("float register length must be either 2/4/8, but {0}")]
+    InvalidLength(usize),
+
+    #[error("{value} is out of range of {len} bytes float")]
+    OutOfRange { value: f64, len: usize },
+}
+
+/// Returns `true` if `len` is a valid length of a float register.
+#[must_use]
+pub fn is_valid_length(len: usize) -> bool {
+    matches!(len, 2 | 4 | 8)
+}
+
+/// Reads a float from `buf`, the length of `buf` determines the precision of the float.
+pub fn read_float<B: ByteOrder>(buf: &[u8]) -> FloatResult<f64> {
+    match buf.len() {
+        2 => Ok(f16_to_f64(B::read_u16(buf))),
+        4 => Ok(f64::from(B::read_f32(buf))),
+        8 => Ok(B::read_f64(buf)),
+        len => Err(FloatError::InvalidLength(len)),
+    }
+}
+
+/// Writes `value` to `buf`, the length of `buf` determines the precision of the float.
+///
+/// `value` is rounded to the nearest representable value, ties to even.
+/// A finite `value` that is rounded to infinity results in [`FloatError::OutOfRange`].
+pub fn write_float<B: ByteOrder>(value: f64, buf: &mut [u8]) -> FloatResult<()> {
+    match buf.len() {
+        2 => B::write_u16(buf, f64_to_f16(value)?),
+        4 => {
+            #[allow(clippy::cast_possible_truncation)]
+            let narrowed = value as f32;
+            if value.is_finite() && narrowed.is_infinite() {
+                return Err(FloatError::OutOfRange { value, len: 4 });
+            }
+            B::write_f32(buf, narrowed);
+        }
+        8 => B::write_f64(buf, value),
+        len => return Err(FloatError::InvalidLength(len)),
+    }
+    Ok(())
+}
+
+/// Converts bits of a half precision float to `f64`, the conversion is lossless.
+#[must_use]
+pub fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exp = i32::from((bits >> 10) & 0x1f);
+    let frac = f64::from(bits & 0x3ff);
+
+    let abs = match exp {
+        // Zero or subnormal.
+        0 => frac * 2_f64.powi(-24),
+        0x1f if frac == 0.0 => f64::INFINITY,
+        0x1f => return f64::NAN,
+        _ => (frac + 1024.0) * 2_f64.powi(exp - 25),
+    };
+    sign * abs
+}
+
+/// Converts `value` to bits of a half precision float.
+///
+/// `value` is rounded to the nearest representable value, ties to even.
+/// A finite `value` that is rounded to infinity results in [`FloatError::OutOfRange`].
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+pub fn f64_to_f16(value: f64) -> FloatResult<u16> {
+    let sign = if value.is_sign_negative() { 0x8000 } else { 0 };
+    let abs = value.abs();
+
+    if value.is_nan() {
+        return Ok(sign | 0x7e00);
+    } else if abs.is_infinite() {
+        return Ok(sign | 0x7c00);
+    }
+
+    let bits = if abs < 2_f64.powi(-14) {
+        // Subnormals are multiples of 2^-24. Scaling by a power of two is exact, and a carry into
+        // the exponent field results in the smallest normal.
+        round_ties_even(abs * 2_f64.powi(24)) as u16
+    } else {
+        let raw = abs.to_bits();
+        let mut exp = ((raw >> 52) & 0x7ff) as i32 - 1023;
+        let frac = raw & ((1 << 52) - 1);
+
+        let mut rounded = frac >> 42;
+        let rem = frac & ((1 << 42) - 1);
+        let half = 1 << 41;
+        if rem > half || (rem == half && rounded & 1 == 1) {
+            rounded += 1;
+        }
+        if rounded == 0x400 {
+            rounded = 0;
+            exp += 1;
+        }
+        if exp > 15 {
+            return Err(FloatError::OutOfRange { value, len: 2 });
+        }
+
+        (((exp + 15) as u16) << 10) | rounded as u16
+    };
+
+    Ok(sign | bits)
+}
+
+fn round_ties_even(value: f64) -> f64 {
+    let floor = value.floor();
+    let diff = value - floor;
+    if diff > 0.5 || (diff == 0.5 && floor % 2.0 != 0.0) {
+        floor + 1.0
+    } else {
+        floor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{BE, LE};
+
+    use super::*;
+
+    #[test]
+    fn test_f16_to_f64() {
+        assert_eq!(f16_to_f64(0x3c00), 1.0);
+        assert_eq!(f16_to_f64(0xc000), -2.0);
+        assert_eq!(f16_to_f64(0x3555), 0.333_251_953_125);
+        assert_eq!(f16_to_f64(0x7bff), 65504.0);
+        assert_eq!(f16_to_f64(0x0400), 2_f64.powi(-14));
+
+        // Subnormals.
+        assert_eq!(f16_to_f64(0x0001), 2_f64.powi(-24));
+        assert_eq!(f16_to_f64(0x03ff), 1023.0 * 2_f64.powi(-24));
+        assert_eq!(f16_to_f64(0x8001), -(2_f64.powi(-24)));
+
+        // Zeros, infinities and NaN.
+        assert_eq!(f16_to_f64(0x0000).to_bits(), 0_f64.to_bits());
+        assert_eq!(f16_to_f64(0x8000).to_bits(), (-0_f64).to_bits());
+        assert_eq!(f16_to_f64(0x7c00), f64::INFINITY);
+        assert_eq!(f16_to_f64(0xfc00), f64::NEG_INFINITY);
+        assert!(f16_to_f64(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn test_f64_to_f16() {
+        assert_eq!(f64_to_f16(1.0), Ok(0x3c00));
+        assert_eq!(f64_to_f16(-2.0), Ok(0xc000));
+        assert_eq!(f64_to_f16(65504.0), Ok(0x7bff));
+        assert_eq!(f64_to_f16(2_f64.powi(-14)), Ok(0x0400));
+
+        // Subnormals.
+        assert_eq!(f64_to_f16(2_f64.powi(-24)), Ok(0x0001));
+        assert_eq!(f64_to_f16(-(2_f64.powi(-24))), Ok(0x8001));
+        assert_eq!(f64_to_f16(1023.0 * 2_f64.powi(-24)), Ok(0x03ff));
+        // Rounded up to the smallest normal.
+        assert_eq!(f64_to_f16(1023.75 * 2_f64.powi(-24)), Ok(0x0400));
+        // Underflow to zero.
+        assert_eq!(f64_to_f16(2_f64.powi(-26)), Ok(0x0000));
+
+        // Round to nearest, ties to even.
+        assert_eq!(f64_to_f16(1.0 + 2_f64.powi(-11)), Ok(0x3c00));
+        assert_eq!(f64_to_f16(1.0 + 3.0 * 2_f64.powi(-11)), Ok(0x3c02));
+        assert_eq!(
+            f64_to_f16(1.0 + 2_f64.powi(-11) + 2_f64.powi(-20)),
+            Ok(0x3c01)
+        );
+        assert_eq!(f64_to_f16(2.5 * 2_f64.powi(-24)), Ok(0x0002));
+        assert_eq!(f64_to_f16(3.5 * 2_f64.powi(-24)), Ok(0x0004));
+        assert_eq!(f64_to_f16(0.1), Ok(0x2e66));
+
+        // Zeros, infinities and NaN.
+        assert_eq!(f64_to_f16(0.0), Ok(0x0000));
+        assert_eq!(f64_to_f16(-0.0), Ok(0x8000));
+        assert_eq!(f64_to_f16(f64::INFINITY), Ok(0x7c00));
+        assert_eq!(f64_to_f16(f64::NEG_INFINITY), Ok(0xfc00));
+        assert_eq!(f64_to_f16(f64::NAN).map(|bits| bits & 0x7e00), Ok(0x7e00));
+
+        // Out of range.
+        assert_eq!(f64_to_f16(65519.0), Ok(0x7bff));
+        assert_eq!(
+            f64_to_f16(65520.0),
+            Err(FloatError::OutOfRange {
+                value: 65520.0,
+                len: 2
+            })
+        );
+        assert!(f64_to_f16(-1e10).is_err());
+    }
+
+    #[test]
+    fn test_read_float() {
+        assert_eq!(read_float::<LE>(&[0x00, 0x3c]), Ok(1.0));
+        assert_eq!(read_float::<BE>(&[0x3c, 0x00]), Ok(1.0));
+        assert_eq!(read_float::<LE>(&[0x01, 0x00]), Ok(2_f64.powi(-24)));
+        assert_eq!(read_float::<BE>(&[0xfc, 0x00]), Ok(f64::NEG_INFINITY));
+
+        assert_eq!(read_float::<LE>(&[0x00, 0x00, 0x20, 0xc0]), Ok(-2.5));
+        assert_eq!(read_float::<BE>(&[0xc0, 0x20, 0x00, 0x00]), Ok(-2.5));
+        assert_eq!(
+            read_float::<BE>(&[0x00, 0x00, 0x00, 0x01]),
+            Ok(2_f64.powi(-149))
+        );
+
+        let f64_bits = [0x40, 0x09, 0x21, 0xfb, 0x54, 0x44, 0x2d, 0x18];
+        let mut f64_bits_le = f64_bits;
+        f64_bits_le.reverse();
+        assert_eq!(read_float::<BE>(&f64_bits), Ok(std::f64::consts::PI));
+        assert_eq!(read_float::<LE>(&f64_bits_le), Ok(std::f64::consts::PI));
+
+        assert_eq!(read_float::<LE>(&[0; 3]), Err(FloatError::InvalidLength(3)));
+        assert_eq!(read_float::<BE>(&[]), Err(FloatError::InvalidLength(0)));
+    }
+
+    #[test]
+    fn test_write_float() {
+        let mut buf = [0; 2];
+        write_float::<LE>(1.0, &mut buf).unwrap();
+        assert_eq!(buf, [0x00, 0x3c]);
+        write_float::<BE>(f64::INFINITY, &mut buf).unwrap();
+        assert_eq!(buf, [0x7c, 0x00]);
+        write_float::<BE>(-(2_f64.powi(-24)), &mut buf).unwrap();
+        assert_eq!(buf, [0x80, 0x01]);
+        assert!(write_float::<LE>(1e5, &mut buf).is_err());
+
+        let mut buf = [0; 4];
+        write_float::<LE>(-2.5, &mut buf).unwrap();
+        assert_eq!(buf, [0x00, 0x00, 0x20, 0xc0]);
+        write_float::<BE>(-2.5, &mut buf).unwrap();
+        assert_eq!(buf, [0xc0, 0x20, 0x00, 0x00]);
+        assert_eq!(
+            write_float::<BE>(1e39, &mut buf),
+            Err(FloatError::OutOfRange {
+                value: 1e39,
+                len: 4
+            })
+        );
+
+        let mut buf = [0; 8];
+        write_float::<BE>(std::f64::consts::PI, &mut buf).unwrap();
+        assert_eq!(buf, [0x40, 0x09, 0x21, 0xfb, 0x54, 0x44, 0x2d, 0x18]);
+        write_float::<LE>(std::f64::consts::PI, &mut buf).unwrap();
+        assert_eq!(buf, [0x18, 0x2d, 0x44, 0x54, 0xfb, 0x21, 0x09, 0x40]);
+
+        assert_eq!(
+            write_float::<LE>(1.0, &mut [0; 1]),
+            Err(FloatError::InvalidLength(1))
+        );
+    }
+}
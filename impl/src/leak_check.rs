@@ -0,0 +1,338 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Opt-in accounting of resources which must be released when a device is closed.
+//!
+//! The accounting is enabled by `leak-check` feature. A resource holds a [`Tracked`] while it's
+//! alive, and tests call [`assert_clean`] after closing devices to make sure that no transfer,
+//! payload buffer, channel or observer outlives them.
+//!
+//! Each resource is tagged with the location where it's created instead of a backtrace, so that
+//! the accounting is cheap enough to be enabled in whole test suites. When the feature is
+//! disabled, [`Tracked`] is zero sized and all functions are no-ops.
+
+use std::{
+    fmt,
+    panic::Location,
+    thread::{self, ThreadId},
+};
+
+/// Kind of a tracked resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Resource {
+    /// An asynchronous USB transfer.
+    AsyncTransfer,
+    /// A payload buffer checked out from the stream.
+    PoolBuffer,
+    /// A buffer provided by the caller which holds a received payload.
+    ProvidedBuffer,
+    /// An opened control or stream channel.
+    Channel,
+    /// An observer registered to a memory.
+    Observer,
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::AsyncTransfer => "async transfer",
+            Self::PoolBuffer => "pool buffer",
+            Self::ProvidedBuffer => "provided buffer",
+            Self::Channel => "channel",
+            Self::Observer => "observer",
+        };
+        f.write_str(s)
+    }
+}
+
+/// A resource which is still alive.
+#[derive(Clone, Debug)]
+pub struct LiveResource {
+    /// Kind of the resource.
+    pub resource: Resource,
+    /// Location where the resource is created.
+    pub location: &'static Location<'static>,
+    /// Name of the thread which created the resource.
+    pub thread: Option<String>,
+    thread_id: ThreadId,
+}
+
+impl fmt::Display for LiveResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} created at {}", self.resource, self.location)?;
+        if let Some(thread) = &self.thread {
+            write!(f, " on thread `{}`", thread)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns resources which are still alive in the process.
+#[must_use]
+pub fn live() -> Vec<LiveResource> {
+    imp::live()
+}
+
+/// Returns the number of `resource` which are still alive.
+#[must_use]
+pub fn count(resource: Resource) -> usize {
+    live().iter().filter(|r| r.resource == resource).count()
+}
+
+/// Panics if any resource created on the current thread is still alive.
+///
+/// The panic message lists the live resources with their creation sites. Only resources created
+/// on the current thread are checked so that tests running in parallel don't observe each other,
+/// use [`live`] to inspect resources created on other threads, e.g. the streaming loop.
+#[track_caller]
+pub fn assert_clean() {
+    report(&on_current_thread(live()));
+}
+
+/// Panics if any resource created on the current thread or on one of the threads named `threads`
+/// is still alive.
+///
+/// This is used when a test drives threads which create resources on its behalf, e.g. the
+/// streaming loop, and the threads are named after the device under test.
+#[track_caller]
+pub fn assert_clean_on(threads: &[&str]) {
+    report(&on_threads(live(), threads));
+}
+
+fn on_current_thread(live: Vec<LiveResource>) -> Vec<LiveResource> {
+    on_threads(live, &[])
+}
+
+/// Retains resources created on the current thread or on one of the threads named `threads`.
+fn on_threads(mut live: Vec<LiveResource>, threads: &[&str]) -> Vec<LiveResource> {
+    let id = thread::current().id();
+    live.retain(|r| {
+        r.thread_id == id
+            || r.thread
+                .as_deref()
+                .is_some_and(|name| threads.contains(&name))
+    });
+    live
+}
+
+#[track_caller]
+fn report(live: &[LiveResource]) {
+    if live.is_empty() {
+        return;
+    }
+    let mut msg = format!("{} resources are leaked:", live.len());
+    for resource in live {
+        msg.push_str(&format!("\n    {}", resource));
+    }
+    panic!("{}", msg);
+}
+
+pub use imp::Tracked;
+
+#[cfg(any(test, feature = "leak-check"))]
+mod imp {
+    use std::{
+        collections::BTreeMap,
+        fmt,
+        panic::Location,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Mutex, PoisonError,
+        },
+        thread,
+    };
+
+    use super::{LiveResource, Resource};
+
+    static REGISTRY: Registry = Registry::new();
+
+    pub(super) struct Registry {
+        next_id: AtomicU64,
+        entries: Mutex<BTreeMap<u64, LiveResource>>,
+    }
+
+    impl Registry {
+        pub(super) const fn new() -> Self {
+            Self {
+                next_id: AtomicU64::new(0),
+                entries: Mutex::new(BTreeMap::new()),
+            }
+        }
+
+        pub(super) fn track(
+            &'static self,
+            resource: Resource,
+            location: &'static Location<'static>,
+        ) -> Tracked {
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            let current = thread::current();
+            let entry = LiveResource {
+                resource,
+                location,
+                thread: current.name().map(Into::into),
+                thread_id: current.id(),
+            };
+            self.entries().insert(id, entry);
+            Tracked {
+                registry: self,
+                id,
+                resource,
+                location,
+            }
+        }
+
+        pub(super) fn live(&self) -> Vec<LiveResource> {
+            self.entries().values().cloned().collect()
+        }
+
+        fn entries(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, LiveResource>> {
+            // A panic while holding the lock doesn't break the map.
+            self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    pub(super) fn live() -> Vec<LiveResource> {
+        REGISTRY.live()
+    }
+
+    /// A token held by a tracked resource, the resource is accounted as alive until the token
+    /// is dropped.
+    pub struct Tracked {
+        registry: &'static Registry,
+        id: u64,
+        resource: Resource,
+        location: &'static Location<'static>,
+    }
+
+    impl Tracked {
+        /// Starts tracking `resource` created at the caller.
+        #[track_caller]
+        #[must_use]
+        pub fn new(resource: Resource) -> Self {
+            REGISTRY.track(resource, Location::caller())
+        }
+    }
+
+    /// The clone is tracked separately with the creation site of the original.
+    impl Clone for Tracked {
+        fn clone(&self) -> Self {
+            self.registry.track(self.resource, self.location)
+        }
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.registry.entries().remove(&self.id);
+        }
+    }
+
+    impl fmt::Debug for Tracked {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Tracked({} created at {})", self.resource, self.location)
+        }
+    }
+}
+
+#[cfg(not(any(test, feature = "leak-check")))]
+mod imp {
+    use super::{LiveResource, Resource};
+
+    pub(super) fn live() -> Vec<LiveResource> {
+        vec![]
+    }
+
+    /// A token held by a tracked resource, does nothing unless `leak-check` feature is enabled.
+    #[derive(Clone, Debug)]
+    pub struct Tracked;
+
+    impl Tracked {
+        /// Starts tracking `resource` created at the caller.
+        #[inline]
+        #[must_use]
+        pub fn new(_resource: Resource) -> Self {
+            Self
+        }
+    }
+}
+
+/// Tracking state never affects equality of the resource holding it.
+impl PartialEq for Tracked {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for Tracked {}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+
+    use super::{imp::Registry, *};
+
+    #[test]
+    fn test_release() {
+        static REGISTRY: Registry = Registry::new();
+
+        let transfer = REGISTRY.track(Resource::AsyncTransfer, Location::caller());
+        let buffer = REGISTRY.track(Resource::PoolBuffer, Location::caller());
+        let cloned = buffer.clone();
+        assert_eq!(REGISTRY.live().len(), 3);
+
+        drop(transfer);
+        drop(buffer);
+        let live = REGISTRY.live();
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].resource, Resource::PoolBuffer);
+
+        drop(cloned);
+        report(&REGISTRY.live());
+    }
+
+    #[test]
+    fn test_thread_attribution() {
+        static REGISTRY: Registry = Registry::new();
+
+        let other = std::thread::spawn(|| REGISTRY.track(Resource::Channel, Location::caller()))
+            .join()
+            .unwrap();
+        assert_eq!(REGISTRY.live().len(), 1);
+        report(&on_current_thread(REGISTRY.live()));
+        drop(other);
+    }
+
+    #[test]
+    fn test_named_thread_attribution() {
+        static REGISTRY: Registry = Registry::new();
+
+        let spawn = |name: &str| {
+            std::thread::Builder::new()
+                .name(name.into())
+                .spawn(|| REGISTRY.track(Resource::PoolBuffer, Location::caller()))
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+        let _looped = spawn("loop-a");
+        let _other = spawn("loop-b");
+
+        let live = on_threads(REGISTRY.live(), &["loop-a"]);
+        assert_eq!(live.len(), 1);
+        assert_eq!(live[0].thread.as_deref(), Some("loop-a"));
+    }
+
+    #[test]
+    fn test_leak_report() {
+        static REGISTRY: Registry = Registry::new();
+
+        // The guard of a buffer is intentionally kept alive.
+        let location = Location::caller();
+        let _buffer = REGISTRY.track(Resource::PoolBuffer, location);
+
+        let err = panic::catch_unwind(|| report(&REGISTRY.live())).unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.starts_with("1 resources are leaked:"));
+        assert!(msg.contains(&format!("pool buffer created at {}", location)));
+    }
+}
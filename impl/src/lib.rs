@@ -8,6 +8,9 @@
     clippy::missing_errors_doc
 )]
 
+pub mod error_code;
+pub mod float;
+pub mod leak_check;
 pub mod memory;
 
 #[doc(hidden)]
@@ -2,7 +2,7 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
-use cameleon_impl::memory::{memory, prelude::*, register_map, AccessRight};
+use cameleon_impl::memory::{memory, prelude::*, register_map, AccessRight, MemoryError};
 
 const SBRM_ADDRESS: u64 = 0x1000;
 const SIRM_ADDRESS: u64 = 0x2000;
@@ -69,6 +69,9 @@ enum SBRM {
 
     #[register(len = 8, access = RO, ty = f64)]
     TestF64 = 0.27,
+
+    #[register(len = 2, access = RW, ty = f16)]
+    TestF16 = 0.5,
 }
 
 fn main() {
@@ -107,6 +110,11 @@ fn main() {
     memory.write::<SBRM::TestF64>(0.1323).unwrap();
     assert!((memory.read::<SBRM::TestF64>().unwrap() - 0.1323).abs() < f64::EPSILON);
 
+    assert_eq!(memory.read::<SBRM::TestF16>().unwrap(), 0.5);
+    memory.write::<SBRM::TestF16>(-65504.0).unwrap();
+    assert_eq!(memory.read::<SBRM::TestF16>().unwrap(), -65504.0);
+    assert!(memory.write::<SBRM::TestF16>(1e5).is_err());
+
     memory
         .write::<ABRM::ManufacturerName>("New name".into())
         .unwrap();
@@ -118,4 +126,19 @@ fn main() {
     assert_eq!(memory.access_right::<SBRM::EIRMLength>(), AccessRight::NA);
 
     assert!(memory.read_raw(1000..1004).is_err());
+
+    // Ranges near `usize::MAX` and reversed ranges are rejected instead of wrapping around.
+    assert!(matches!(
+        memory.read_raw(usize::MAX - 1..usize::MAX),
+        Err(MemoryError::InvalidAddress)
+    ));
+    let (start, end) = (8, 4);
+    assert!(matches!(
+        memory.read_raw(start..end),
+        Err(MemoryError::InvalidAddress)
+    ));
+    assert!(matches!(
+        memory.write_raw(usize::MAX - 1, &[0; 4]),
+        Err(MemoryError::InvalidAddress)
+    ));
 }